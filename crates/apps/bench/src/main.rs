@@ -0,0 +1,275 @@
+//! WATOS bench - kernel microbenchmarks
+//!
+//! Usage: bench [-n COUNT] [BENCHMARK...]
+//!
+//! Runs the named benchmarks, or all of them:
+//! - syscall: SYS_GETPID round trips, the cost of entering the kernel
+//! - switch: starting `bench nop` and waiting for it to exit. Children run
+//!   inside SYS_EXEC, so this is two process switches plus creating and
+//!   tearing down a process: the nearest thing to a context switch there is
+//! - pipe: 4 KiB blocks written into a pipe and read back out
+//! - read: a 1 MiB scratch file (C:/bench.tmp) read in 4 KiB blocks, from
+//!   the block cache after the first pass
+//!
+//! -n sets how many times each benchmark repeats its operation.
+//!
+//! Each result is a line of JSON so scripts can compare kernels:
+//!
+//! {"bench":"syscall","count":100000,"ns_per_op":310,"tsc_per_op":930,
+//!  "cycles_per_op":925,"instructions_per_op":402}
+//!
+//! ns_per_op is left out when the kernel doesn't know the TSC frequency,
+//! the counter fields when the CPU has no performance counters (see
+//! SYS_PERFCTR), and pipe and read add bytes_per_sec.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, open, perfctr, syscalls};
+
+/// Bytes per write or read
+const BLOCK: usize = 4096;
+
+/// Size of the `read` benchmark's scratch file
+const FILE_SIZE: usize = 1024 * 1024;
+
+const SCRATCH_FILE: &str = "C:/bench.tmp";
+
+/// Performance counters used, if the CPU has them
+const CYCLES: u32 = 0;
+const INSTRUCTIONS: u32 = 1;
+
+struct Benchmark {
+    name: &'static str,
+    /// Repetitions when -n isn't given
+    default_count: u64,
+    /// Bytes moved per repetition, for bytes_per_sec (0 = none)
+    bytes: u64,
+    run: fn(&Env, u64) -> bool,
+}
+
+const BENCHMARKS: [Benchmark; 4] = [
+    Benchmark { name: "syscall", default_count: 100_000, bytes: 0, run: bench_syscall },
+    Benchmark { name: "switch", default_count: 100, bytes: 0, run: bench_switch },
+    Benchmark { name: "pipe", default_count: 2048, bytes: BLOCK as u64, run: bench_pipe },
+    Benchmark { name: "read", default_count: 16, bytes: FILE_SIZE as u64, run: bench_read },
+];
+
+/// What the benchmarks need to know about the machine and themselves
+struct Env<'a> {
+    /// How this program was started, to start it again
+    program: &'a str,
+    tsc_khz: Option<u64>,
+    cycles: bool,
+    instructions: bool,
+}
+
+/// Counter readings at one moment
+#[derive(Clone, Copy)]
+struct Sample {
+    tsc: u64,
+    cycles: u64,
+    instructions: u64,
+}
+
+impl Env<'_> {
+    fn sample(&self) -> Sample {
+        // Counters are only read while running, which `main` made sure of
+        unsafe {
+            Sample {
+                tsc: perfctr::rdtsc(),
+                cycles: if self.cycles { perfctr::rdpmc(CYCLES) } else { 0 },
+                instructions: if self.instructions { perfctr::rdpmc(INSTRUCTIONS) } else { 0 },
+            }
+        }
+    }
+}
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_num(mut n: u64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &digits[i..]);
+}
+
+fn write_field(name: &str, value: u64) {
+    write_str(",\"");
+    write_str(name);
+    write_str("\":");
+    write_num(value);
+}
+
+fn usage() -> ! {
+    write_str("Usage: bench [-n COUNT] [syscall|switch|pipe|read...]\r\n");
+    syscalls::exit(1);
+}
+
+fn bench_syscall(_env: &Env, count: u64) -> bool {
+    for _ in 0..count {
+        core::hint::black_box(syscalls::getpid());
+    }
+    true
+}
+
+fn bench_switch(env: &Env, count: u64) -> bool {
+    (0..count).all(|_| syscalls::execv(&[env.program, "nop"]) == 0)
+}
+
+fn bench_pipe(_env: &Env, count: u64) -> bool {
+    let Some((read_fd, write_fd)) = syscalls::pipe() else { return false };
+    let block = [0x5Au8; BLOCK];
+    let mut back = [0u8; BLOCK];
+    let ok = (0..count).all(|_| {
+        syscalls::write(write_fd, &block) == BLOCK && syscalls::read(read_fd, &mut back) == BLOCK
+    });
+    syscalls::close(read_fd);
+    syscalls::close(write_fd);
+    ok
+}
+
+fn bench_read(_env: &Env, count: u64) -> bool {
+    let mut buf = [0u8; BLOCK];
+    (0..count).all(|_| {
+        let fd = syscalls::open(SCRATCH_FILE, open::O_RDONLY);
+        if fd < 0 {
+            return false;
+        }
+        let mut total = 0;
+        loop {
+            match syscalls::read(fd, &mut buf) {
+                n if n == 0 || n > buf.len() => break,
+                n => total += n,
+            }
+        }
+        syscalls::close(fd);
+        total == FILE_SIZE
+    })
+}
+
+/// Write the `read` benchmark's scratch file
+fn make_scratch_file() -> bool {
+    let fd = syscalls::open(SCRATCH_FILE, open::O_WRONLY | open::O_CREAT | open::O_TRUNC);
+    if fd < 0 {
+        return false;
+    }
+    let mut block = [0u8; BLOCK];
+    for (i, byte) in block.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let ok = (0..FILE_SIZE / BLOCK).all(|_| syscalls::write(fd, &block) == BLOCK);
+    syscalls::close(fd);
+    ok
+}
+
+fn run(env: &Env, bench: &Benchmark, count: u64) {
+    if bench.name == "read" && !make_scratch_file() {
+        write_str("bench: cannot write ");
+        write_str(SCRATCH_FILE);
+        write_str("\r\n");
+        return;
+    }
+    let start = env.sample();
+    let ok = (bench.run)(env, count);
+    let end = env.sample();
+    if bench.name == "read" {
+        syscalls::unlink(SCRATCH_FILE);
+    }
+    if !ok {
+        write_str("bench: ");
+        write_str(bench.name);
+        write_str(" failed\r\n");
+        return;
+    }
+
+    let count = count.max(1);
+    let tsc = end.tsc.wrapping_sub(start.tsc);
+    let ns = env.tsc_khz.map(|khz| (tsc as u128 * 1_000_000 / khz as u128) as u64);
+
+    write_str("{\"bench\":\"");
+    write_str(bench.name);
+    write_str("\"");
+    write_field("count", count);
+    if let Some(ns) = ns {
+        write_field("ns_per_op", ns / count);
+    }
+    write_field("tsc_per_op", tsc / count);
+    if env.cycles {
+        write_field("cycles_per_op", end.cycles.wrapping_sub(start.cycles) / count);
+    }
+    if env.instructions {
+        write_field("instructions_per_op", end.instructions.wrapping_sub(start.instructions) / count);
+    }
+    if let Some(ns) = ns.filter(|&ns| bench.bytes != 0 && ns != 0) {
+        write_field("bytes_per_sec", (bench.bytes as u128 * count as u128 * 1_000_000_000 / ns as u128) as u64);
+    }
+    write_str("}\r\n");
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    use core::ptr::addr_of_mut;
+    static mut ARGV_BUF: [u8; argv::MAX_BYTES] = [0u8; argv::MAX_BYTES];
+
+    let buf = unsafe { &mut *addr_of_mut!(ARGV_BUF) };
+    let len = syscalls::getargv(buf).unwrap_or(0);
+    let mut args = argv::decode(&buf[..len]);
+    let program = args.next().unwrap_or("bench");
+
+    let mut count = None;
+    let mut selected = [false; BENCHMARKS.len()];
+    while let Some(arg) = args.next() {
+        match arg {
+            // The child `switch` starts
+            "nop" => syscalls::exit(0),
+            "-n" => match args.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) if n > 0 => count = Some(n),
+                _ => usage(),
+            },
+            name => match BENCHMARKS.iter().position(|b| b.name == name) {
+                Some(i) => selected[i] = true,
+                None => usage(),
+            },
+        }
+    }
+    if !selected.contains(&true) {
+        selected = [true; BENCHMARKS.len()];
+    }
+
+    let pmu = syscalls::perfctr_info().unwrap_or_default();
+    let env = Env {
+        program,
+        tsc_khz: syscalls::tsc_khz(),
+        cycles: pmu.has(perfctr::EVENT_CYCLES) && syscalls::perfctr_start(CYCLES, perfctr::EVENT_CYCLES),
+        instructions: pmu.has(perfctr::EVENT_INSTRUCTIONS)
+            && syscalls::perfctr_start(INSTRUCTIONS, perfctr::EVENT_INSTRUCTIONS),
+    };
+
+    for (bench, _) in BENCHMARKS.iter().zip(selected).filter(|(_, on)| *on) {
+        run(&env, bench, count.unwrap_or(bench.default_count));
+    }
+
+    if env.cycles {
+        syscalls::perfctr_stop(CYCLES);
+    }
+    if env.instructions {
+        syscalls::perfctr_stop(INSTRUCTIONS);
+    }
+    syscalls::exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1);
+}
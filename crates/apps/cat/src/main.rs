@@ -171,12 +171,18 @@ fn cat_file(path: &[u8], opts: &Options, line_num: &mut u32, prev_blank: &mut bo
         return 1;
     }
 
+    cat_fd(fd as u64, opts, line_num, prev_blank);
+    close(fd as u64);
+    0
+}
+
+fn cat_fd(fd: u64, opts: &Options, line_num: &mut u32, prev_blank: &mut bool) {
     static mut READ_BUF: [u8; 4096] = [0u8; 4096];
     let mut at_line_start = true;
     let mut line_buf = [0u8; 16];
 
     loop {
-        let n = unsafe { read(fd as u64, &mut READ_BUF) };
+        let n = unsafe { read(fd, &mut READ_BUF) };
         if n <= 0 {
             break;
         }
@@ -222,9 +228,6 @@ fn cat_file(path: &[u8], opts: &Options, line_num: &mut u32, prev_blank: &mut bo
             at_line_start = is_newline;
         }
     }
-
-    close(fd as u64);
-    0
 }
 
 #[no_mangle]
@@ -256,7 +259,7 @@ extern "C" fn _start() -> ! {
 
         // Check for "--" (end of options) or "-" (stdin)
         if i >= args.len() || args[i] == b' ' {
-            // Just "-", treat as stdin placeholder (we don't support stdin yet)
+            // Just "-", means standard input
             i = opt_start;
             break;
         }
@@ -299,7 +302,7 @@ extern "C" fn _start() -> ! {
 
         if !path.is_empty() {
             if path == b"-" {
-                write_str("cat: reading from stdin not supported yet\r\n");
+                cat_fd(0, &opts, &mut line_num, &mut prev_blank);
             } else {
                 let result = cat_file(path, &opts, &mut line_num, &mut prev_blank);
                 if result != 0 {
@@ -315,9 +318,8 @@ extern "C" fn _start() -> ! {
     }
 
     if file_count == 0 {
-        write_str("cat: missing operand\r\n");
-        write_str("Usage: cat [OPTIONS] FILE...\r\n");
-        exit(1);
+        // No files: copy standard input (e.g. the read end of a pipe)
+        cat_fd(0, &opts, &mut line_num, &mut prev_blank);
     }
 
     exit(exit_code);
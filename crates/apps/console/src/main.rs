@@ -4,6 +4,8 @@
 //! - VT100/ANSI terminal emulation
 //! - Keyboard input with modifiers (Shift, Ctrl, Alt)
 //! - Framebuffer rendering via syscalls
+//! - Mouse selection: drag with the left button to copy text to the
+//!   clipboard, click the middle button to paste it at the prompt
 //!
 //! This runs as a user-space app, NOT in the kernel.
 
@@ -15,8 +17,13 @@ extern crate alloc;
 use core::panic::PanicInfo;
 use watos_syscall::numbers as syscall;
 use watos_terminal::console::ConsoleManager;
+use watos_terminal::font::BUILTIN_FONT;
 use watos_terminal::framebuffer::{FramebufferInfo, PixelFormat, SimpleFramebuffer};
+use watos_terminal::color::THEMES;
 use watos_terminal::keyboard::KeyCode;
+use watos_terminal::keymap::{Keymap, KEYMAP_DIR};
+use core::sync::atomic::{AtomicBool, Ordering};
+use watos_syscall::syscalls;
 
 // ============================================================================
 // Raw Syscall Wrappers
@@ -80,8 +87,13 @@ unsafe fn syscall3(num: u32, arg1: u64, arg2: u64, arg3: u64) -> u64 {
 // System Call Helpers
 // ============================================================================
 
-fn fb_addr() -> u64 {
-    unsafe { syscall0(syscall::SYS_FB_ADDR) }
+/// Map the framebuffer into this process; 0 if there is none or the
+/// kernel refuses
+fn fb_map() -> u64 {
+    match unsafe { syscall0(syscall::SYS_FB_MAP) } {
+        u64::MAX => 0,
+        addr => addr,
+    }
 }
 
 fn fb_dimensions() -> (u32, u32, u32) {
@@ -94,6 +106,37 @@ fn fb_dimensions() -> (u32, u32, u32) {
     }
 }
 
+/// Clipboard type for selections and pastes
+const TEXT_PLAIN: &str = "text/plain";
+
+/// Set once the mouse has moved; the cursor is not shown until then so a
+/// machine without a mouse never gets a stray arrow
+static MOUSE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Render the console, keeping the mouse cursor on top of the new text
+fn render(console: &mut ConsoleManager, framebuffer: &mut SimpleFramebuffer) {
+    let cursor = MOUSE_ACTIVE.load(Ordering::Relaxed);
+    if cursor {
+        syscalls::cursor_show(false);
+    }
+    console.render(framebuffer);
+    if cursor {
+        syscalls::cursor_show(true);
+    }
+}
+
+/// Redraw the blinking text cursor, keeping the mouse cursor on top
+fn render_cursor(console: &mut ConsoleManager, framebuffer: &mut SimpleFramebuffer) {
+    let cursor = MOUSE_ACTIVE.load(Ordering::Relaxed);
+    if cursor {
+        syscalls::cursor_show(false);
+    }
+    console.render_cursor(framebuffer);
+    if cursor {
+        syscalls::cursor_show(true);
+    }
+}
+
 fn read_scancode() -> u8 {
     unsafe { syscall0(syscall::SYS_READ_SCANCODE) as u8 }
 }
@@ -343,9 +386,9 @@ extern "C" fn _start() -> ! {
     serial_write("[CONSOLE] Starting console app\r\n");
 
     // Get framebuffer info from kernel
-    serial_write("[CONSOLE] Getting FB addr...\r\n");
-    let fb_address = fb_addr();
-    serial_write("[CONSOLE] FB addr done\r\n");
+    serial_write("[CONSOLE] Mapping FB...\r\n");
+    let fb_address = fb_map();
+    serial_write("[CONSOLE] FB map done\r\n");
     if fb_address == 0 {
         serial_write("[CONSOLE] ERROR: No framebuffer\r\n");
         exit(1);
@@ -406,6 +449,12 @@ extern "C" fn _start() -> ! {
     console.init_consoles(1);
     serial_write("[CONSOLE] Consoles initialized\r\n");
 
+    // Draw in the kernel consoles' theme
+    let theme = syscalls::sysctl_get("console.theme").and_then(|theme| THEMES.get(theme as usize));
+    if let (Some(&palette), Some(terminal)) = (theme, console.active_terminal_mut()) {
+        terminal.set_palette(palette);
+    }
+
     // Display welcome message
     console.write_str("\x1b[2J\x1b[H"); // Clear screen, home cursor
     console.write_str("WATOS Console v0.2-DEBUG\r\n");
@@ -434,6 +483,14 @@ extern "C" fn _start() -> ! {
     let mut last_blink_tick = get_ticks();
     const BLINK_INTERVAL: u64 = 9; // ~500ms at 18.2 Hz
 
+    // Mouse selection: cell size for mapping pixels to cells, the button
+    // state last seen and the (start, end) cells being dragged over
+    const MOUSE_LEFT: u8 = 0x01;
+    const MOUSE_MIDDLE: u8 = 0x04;
+    let (cell_w, cell_h) = console.cell_size();
+    let mut prev_buttons = 0u8;
+    let mut selection: Option<((usize, usize), (usize, usize))> = None;
+
     // Main loop
     loop {
         // Check if it's time to blink cursor
@@ -442,100 +499,162 @@ extern "C" fn _start() -> ! {
             last_blink_tick = current_tick;
             if console.tick() {
                 // Only redraw the cursor cell, not the whole screen
-                console.render_cursor(&mut framebuffer);
+                render_cursor(&mut console, &mut framebuffer);
             }
         }
         // Poll for any output from kernel console buffer (from child processes)
         let bytes_read = read_console_output(&mut console_buf);
         if bytes_read > 0 {
+            // New text may scroll, so drop a selection in progress
+            if let Some((start, end)) = selection.take() {
+                console.toggle_highlight(start, end);
+            }
             // Process output through terminal emulator and render
             if let Ok(s) = core::str::from_utf8(&console_buf[..bytes_read]) {
                 console.write_str(s);
             } else {
                 console.write(&console_buf[..bytes_read]);
             }
-            console.render(&mut framebuffer);
+            render(&mut console, &mut framebuffer);
+        }
+
+        // Mouse: left-drag selects and copies, middle click pastes
+        let (mouse_x, mouse_y, buttons) = syscalls::mouse_poll();
+        if !MOUSE_ACTIVE.load(Ordering::Relaxed) && (mouse_x, mouse_y, buttons) != (0, 0, 0) {
+            MOUSE_ACTIVE.store(true, Ordering::Relaxed);
+            syscalls::cursor_show(true);
+        }
+        let (cols, rows) = console.size();
+        let cell = (
+            ((mouse_x.max(0) as u32 / cell_w) as usize).min(cols - 1),
+            ((mouse_y.max(0) as u32 / cell_h) as usize).min(rows - 1),
+        );
+        let pressed = buttons & !prev_buttons;
+        let released = prev_buttons & !buttons;
+        prev_buttons = buttons;
+
+        if pressed & MOUSE_LEFT != 0 {
+            selection = Some((cell, cell));
+            console.toggle_highlight(cell, cell);
+            render(&mut console, &mut framebuffer);
+        } else if let Some((start, end)) = selection {
+            if released & MOUSE_LEFT != 0 {
+                let mut text = [0u8; 8192];
+                let len = console.selection_text(start, end, &mut text);
+                if len > 0 {
+                    syscalls::clipboard_set(TEXT_PLAIN, &text[..len]);
+                }
+                console.toggle_highlight(start, end);
+                selection = None;
+                render(&mut console, &mut framebuffer);
+            } else if cell != end {
+                console.toggle_highlight(start, end);
+                console.toggle_highlight(start, cell);
+                selection = Some((start, cell));
+                render(&mut console, &mut framebuffer);
+            }
+        }
+
+        if pressed & MOUSE_MIDDLE != 0 {
+            // Paste the first line into the command being typed
+            let mut text = [0u8; 256];
+            if let Some(len) = syscalls::clipboard_get(TEXT_PLAIN, &mut text) {
+                for &b in text[..len.min(text.len())].iter().take_while(|&&b| b != b'\n' && b != b'\r') {
+                    if (0x20..0x7F).contains(&b) && cmd_len < cmd_buffer.len() - 1 {
+                        cmd_buffer[cmd_len] = b;
+                        cmd_len += 1;
+                        console.write(&[b]);
+                    }
+                }
+                render(&mut console, &mut framebuffer);
+            }
         }
 
         // Read keyboard
         let scancode = read_scancode();
         if scancode != 0 {
+            if let Some((start, end)) = selection.take() {
+                console.toggle_highlight(start, end);
+            }
             // Debug: minimal - just show we got something
             serial_write("[K]");
             if let Some(event) = console.process_scancode(scancode) {
                 if event.pressed {
                     // Get character from keyboard
-                    if let Some(ch) = console.keyboard().to_char(&event) {
-                        match ch {
-                            '\r' | '\n' => {
-                                // Enter - process command
-                                serial_write("[ENTER] cmd_len=");
-                                // Print cmd_len as digit
-                                if cmd_len < 10 {
-                                    let digit = b'0' + cmd_len as u8;
-                                    unsafe {
-                                        core::arch::asm!(
-                                            "int 0x80",
-                                            in("eax") 1u32,  // SYS_WRITE
-                                            in("rdi") 0u64,
-                                            in("rsi") &digit as *const u8 as u64,
-                                            in("rdx") 1u64,
-                                            options(nostack)
-                                        );
+                    let mut typed = console.keyboard_mut().type_chars(&event).peekable();
+                    if typed.peek().is_some() {
+                        for ch in typed.by_ref() {
+                            match ch {
+                                '\r' | '\n' => {
+                                    // Enter - process command
+                                    serial_write("[ENTER] cmd_len=");
+                                    // Print cmd_len as digit
+                                    if cmd_len < 10 {
+                                        let digit = b'0' + cmd_len as u8;
+                                        unsafe {
+                                            core::arch::asm!(
+                                                "int 0x80",
+                                                in("eax") 1u32,  // SYS_WRITE
+                                                in("rdi") 0u64,
+                                                in("rsi") &digit as *const u8 as u64,
+                                                in("rdx") 1u64,
+                                                options(nostack)
+                                            );
+                                        }
                                     }
-                                }
-                                serial_write("\r\n");
-                                console.write_str("\r\n");
-
-                                if cmd_len > 0 {
-                                    let cmd = core::str::from_utf8(&cmd_buffer[..cmd_len]).unwrap_or("");
-                                    serial_write("[CMD] ");
-                                    serial_write(cmd);
                                     serial_write("\r\n");
-                                    process_command(&mut console, cmd);
-                                    cmd_len = 0;
-
-                                    // Drain any pending output from the command before showing prompt
-                                    loop {
-                                        let bytes = read_console_output(&mut console_buf);
-                                        if bytes == 0 {
-                                            break;
-                                        }
-                                        if let Ok(s) = core::str::from_utf8(&console_buf[..bytes]) {
-                                            console.write_str(s);
-                                        } else {
-                                            console.write(&console_buf[..bytes]);
+                                    console.write_str("\r\n");
+
+                                    if cmd_len > 0 {
+                                        let cmd = core::str::from_utf8(&cmd_buffer[..cmd_len]).unwrap_or("");
+                                        serial_write("[CMD] ");
+                                        serial_write(cmd);
+                                        serial_write("\r\n");
+                                        process_command(&mut console, cmd);
+                                        cmd_len = 0;
+
+                                        // Drain any pending output from the command before showing prompt
+                                        loop {
+                                            let bytes = read_console_output(&mut console_buf);
+                                            if bytes == 0 {
+                                                break;
+                                            }
+                                            if let Ok(s) = core::str::from_utf8(&console_buf[..bytes]) {
+                                                console.write_str(s);
+                                            } else {
+                                                console.write(&console_buf[..bytes]);
+                                            }
                                         }
+                                        render(&mut console, &mut framebuffer);
                                     }
-                                    console.render(&mut framebuffer);
-                                }
 
-                                write_prompt(&mut console);
-                            }
-                            '\x08' => {
-                                // Backspace
-                                if cmd_len > 0 {
-                                    cmd_len -= 1;
-                                    console.write_str("\x08 \x08"); // Move back, space, move back
+                                    write_prompt(&mut console);
                                 }
-                            }
-                            '\x7f' => {
-                                // Delete (treat like backspace)
-                                if cmd_len > 0 {
-                                    cmd_len -= 1;
-                                    console.write_str("\x08 \x08");
+                                '\x08' => {
+                                    // Backspace
+                                    if cmd_len > 0 {
+                                        cmd_len = backspace(&cmd_buffer, cmd_len);
+                                        console.write_str("\x08 \x08"); // Move back, space, move back
+                                    }
                                 }
-                            }
-                            _ => {
-                                // Regular character
-                                if cmd_len < cmd_buffer.len() - 1 {
-                                    cmd_buffer[cmd_len] = ch as u8;
-                                    cmd_len += 1;
-
-                                    // Echo character
+                                '\x7f' => {
+                                    // Delete (treat like backspace)
+                                    if cmd_len > 0 {
+                                        cmd_len = backspace(&cmd_buffer, cmd_len);
+                                        console.write_str("\x08 \x08");
+                                    }
+                                }
+                                _ => {
+                                    // Regular character, stored as UTF-8
                                     let mut buf = [0u8; 4];
                                     let s = ch.encode_utf8(&mut buf);
-                                    console.write_str(s);
+                                    if cmd_len + s.len() < cmd_buffer.len() {
+                                        cmd_buffer[cmd_len..cmd_len + s.len()].copy_from_slice(s.as_bytes());
+                                        cmd_len += s.len();
+
+                                        // Echo character
+                                        console.write_str(s);
+                                    }
                                 }
                             }
                         }
@@ -556,10 +675,26 @@ extern "C" fn _start() -> ! {
                 }
 
                 // Render after input
-                console.render(&mut framebuffer);
+                render(&mut console, &mut framebuffer);
             }
         }
+
+        // Nothing pending: halt until a key, mouse or timer interrupt
+        if bytes_read == 0 && scancode == 0 {
+            syscalls::idle();
+        }
+    }
+}
+
+/// Length of the command line with its last (UTF-8) character removed
+fn backspace(buf: &[u8], mut len: usize) -> usize {
+    while len > 0 {
+        len -= 1;
+        if buf[len] & 0xC0 != 0x80 {
+            break;
+        }
     }
+    len
 }
 
 /// Redirection info parsed from command line
@@ -641,6 +776,64 @@ fn close_fd(fd: i64) {
     }
 }
 
+/// Read a layout from FILE, or /etc/keymaps/NAME.kmap, and type through it
+fn load_keymap(console: &mut ConsoleManager, name: &str) {
+    use core::ptr::addr_of_mut;
+    static mut KEYMAP_BUF: [u8; 4096] = [0u8; 4096];
+    static mut PATH_BUF: [u8; 128] = [0u8; 128];
+
+    let path = if name.contains('/') || name.contains('.') {
+        name
+    } else {
+        let path = unsafe { &mut *addr_of_mut!(PATH_BUF) };
+        let parts = [KEYMAP_DIR.as_bytes(), name.as_bytes(), b".kmap"];
+        let mut len = 0;
+        for part in parts {
+            let n = part.len().min(path.len() - len);
+            path[len..len + n].copy_from_slice(&part[..n]);
+            len += n;
+        }
+        core::str::from_utf8(&path[..len]).unwrap_or("")
+    };
+
+    let fd = open_file_read(path);
+    if fd < 0 {
+        console.write_str("loadkeys: can't open ");
+        console.write_str(path);
+        console.write_str("\r\n");
+        return;
+    }
+    let buf = unsafe { &mut *addr_of_mut!(KEYMAP_BUF) };
+    let mut len = 0;
+    while len < buf.len() {
+        let n = read_fd(fd, &mut buf[len..]);
+        if n <= 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close_fd(fd);
+    if len == buf.len() {
+        console.write_str("loadkeys: layout too large\r\n");
+        return;
+    }
+
+    let Ok(text) = core::str::from_utf8(&buf[..len]) else {
+        console.write_str("loadkeys: layout isn't UTF-8\r\n");
+        return;
+    };
+    match Keymap::parse(text) {
+        Ok(keymap) => console.keyboard_mut().set_keymap(Some(keymap)),
+        Err(err) => {
+            console.write_str("loadkeys: line ");
+            write_num(console, err.line as u32);
+            console.write_str(": ");
+            console.write_str(err.reason);
+            console.write_str("\r\n");
+        }
+    }
+}
+
 /// Change directory via syscall
 fn chdir(path: &[u8]) -> u64 {
     unsafe {
@@ -690,6 +883,9 @@ fn process_command(console: &mut ConsoleManager, cmd: &str) {
             console.write_str("  colors  - Test colors\r\n");
             console.write_str("  ver     - Show version\r\n");
             console.write_str("  set     - Show environment variables\r\n");
+            console.write_str("  fontsize N - Scale the console font (1-4)\r\n");
+            console.write_str("  loadkeys NAME - Keyboard layout (de, us, or a .kmap file)\r\n");
+            console.write_str("  kbdrate [DELAY RATE] - Key repeat delay (ms) and rate (per second)\r\n");
             console.write_str("\r\nDrive navigation:\r\n");
             console.write_str("  C:, D:  - Change to drive\r\n");
             console.write_str("\r\nRedirection:\r\n");
@@ -723,6 +919,43 @@ fn process_command(console: &mut ConsoleManager, cmd: &str) {
             }
             console.write_str("\r\n");
         }
+        "fontsize" => {
+            match _args.trim().parse::<u32>() {
+                Ok(scale) if (1..=4).contains(&scale) => {
+                    let (width, height, _) = fb_dimensions();
+                    console.set_font(&BUILTIN_FONT, scale, width, height);
+                    console.write_str("\x1b[2J\x1b[H");
+                }
+                _ => console.write_str("Usage: fontsize N (1-4)\r\n"),
+            }
+        }
+        "loadkeys" => {
+            let name = _args.trim();
+            if name.is_empty() {
+                console.write_str("Usage: loadkeys NAME|FILE\r\n");
+            } else if name == "us" {
+                console.keyboard_mut().set_keymap(None);
+            } else {
+                load_keymap(console, name);
+            }
+        }
+        "kbdrate" => {
+            let mut args = _args.split_whitespace().map(|a| a.parse::<u32>());
+            match (args.next(), args.next(), args.next()) {
+                (None, _, _) => {}
+                (Some(Ok(delay)), Some(Ok(rate)), None) if syscalls::set_kbd_repeat(delay, rate) => {}
+                _ => {
+                    console.write_str("Usage: kbdrate [DELAY RATE] (100-2000 ms, 0-30 per second)\r\n");
+                    return;
+                }
+            }
+            let (delay, rate) = syscalls::kbd_repeat();
+            console.write_str("Delay ");
+            write_num(console, delay);
+            console.write_str(" ms, rate ");
+            write_num(console, rate);
+            console.write_str(" per second\r\n");
+        }
         "ver" | "version" => {
             console.write_str("WATOS Console v0.1\r\n");
             console.write_str("Terminal: watos-terminal crate\r\n");
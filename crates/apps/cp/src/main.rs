@@ -12,6 +12,7 @@
 #![no_main]
 
 use core::panic::PanicInfo;
+use watos_syscall::copyfile::CopyFile;
 use watos_syscall::numbers as syscall;
 
 #[inline(always)]
@@ -122,6 +123,34 @@ fn write_fd(fd: u64, buf: &[u8]) -> i64 {
     }
 }
 
+/// Bytes asked of each SYS_SENDFILE call
+const SENDFILE_STEP: u64 = 1 << 20;
+
+/// Copy in the kernel; bytes copied, 0 at end of input, None on error
+fn sendfile(out_fd: u64, in_fd: u64, count: u64) -> Option<u64> {
+    let ret = unsafe { syscall3(syscall::SYS_SENDFILE, out_fd, in_fd, count) };
+    (ret != u64::MAX).then_some(ret)
+}
+
+/// Copy `src` to `dest` in the kernel, a SYS_COPYFILE step at a time;
+/// false on error, leaving no copy behind
+fn copyfile(src: &[u8], dest: &[u8]) -> bool {
+    let mut req = CopyFile {
+        src_ptr: src.as_ptr() as u64,
+        src_len: src.len() as u64,
+        dst_ptr: dest.as_ptr() as u64,
+        dst_len: dest.len() as u64,
+        ..CopyFile::default()
+    };
+    loop {
+        match unsafe { syscall2(syscall::SYS_COPYFILE, &mut req as *mut CopyFile as u64, 0) } {
+            0 => return true,
+            1 => continue,
+            _ => return false,
+        }
+    }
+}
+
 fn close(fd: u64) {
     unsafe {
         syscall2(syscall::SYS_CLOSE, fd, 0);
@@ -230,6 +259,27 @@ fn build_dest_path(dest: &[u8], src_basename: &[u8], buf: &mut [u8]) -> usize {
 }
 
 fn copy_file(src: &[u8], dest: &[u8], opts: &Options) -> i32 {
+    // Let the kernel copy path to path, keeping mode and times; fall back
+    // to copying descriptor to descriptor if it can't
+    if !copyfile(src, dest) {
+        let code = copy_data(src, dest);
+        if code != 0 {
+            return code;
+        }
+    }
+
+    if opts.verbose {
+        write_str("'");
+        write_bytes(src);
+        write_str("' -> '");
+        write_bytes(dest);
+        write_str("'\r\n");
+    }
+
+    0
+}
+
+fn copy_data(src: &[u8], dest: &[u8]) -> i32 {
     static mut COPY_BUF: [u8; 4096] = [0u8; 4096];
 
     let src_fd = open(src, O_RDONLY);
@@ -249,7 +299,26 @@ fn copy_file(src: &[u8], dest: &[u8], opts: &Options) -> i32 {
         return 1;
     }
 
-    loop {
+    // Let the kernel move the data; fall back to read/write if it can't
+    let mut sent_any = false;
+    let mut use_sendfile = true;
+    while use_sendfile {
+        match sendfile(dest_fd as u64, src_fd as u64, SENDFILE_STEP) {
+            Some(0) => break,
+            Some(_) => sent_any = true,
+            None if !sent_any => use_sendfile = false,
+            None => {
+                write_str("cp: error writing to '");
+                write_bytes(dest);
+                write_str("'\r\n");
+                close(src_fd as u64);
+                close(dest_fd as u64);
+                return 1;
+            }
+        }
+    }
+
+    while !use_sendfile {
         let n = unsafe { read(src_fd as u64, &mut COPY_BUF) };
         if n <= 0 {
             break;
@@ -267,15 +336,6 @@ fn copy_file(src: &[u8], dest: &[u8], opts: &Options) -> i32 {
 
     close(src_fd as u64);
     close(dest_fd as u64);
-
-    if opts.verbose {
-        write_str("'");
-        write_bytes(src);
-        write_str("' -> '");
-        write_bytes(dest);
-        write_str("'\r\n");
-    }
-
     0
 }
 
@@ -1,12 +1,19 @@
 //! WATOS date command
 //!
-//! Displays the current date and time.
+//! Displays the current date and time (UTC, as the RTC keeps it).
+//!
+//! Usage: date [-I | +%s]
+//!
+//!   -I, --iso-8601   ISO 8601: 2026-10-18T09:30:00Z
+//!   +%s              Seconds since the Unix epoch
 
 #![no_std]
 #![no_main]
 
+use core::fmt::Write;
 use core::panic::PanicInfo;
 use watos_syscall::numbers as syscall;
+use watos_time::DateTime;
 
 // ============================================================================
 // Syscall wrappers
@@ -37,6 +44,20 @@ unsafe fn syscall1(num: u32, arg1: u64) -> u64 {
     ret
 }
 
+#[inline(always)]
+unsafe fn syscall2(num: u32, arg1: u64, arg2: u64) -> u64 {
+    let ret: u64;
+    core::arch::asm!(
+        "int 0x80",
+        in("eax") num,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        lateout("rax") ret,
+        options(nostack)
+    );
+    ret
+}
+
 #[inline(always)]
 unsafe fn syscall3(num: u32, arg1: u64, arg2: u64, arg3: u64) -> u64 {
     let ret: u64;
@@ -66,48 +87,35 @@ fn exit(code: i32) -> ! {
     loop {}
 }
 
-fn get_date() -> (u16, u8, u8) {
-    let packed = unsafe { syscall0(syscall::SYS_GETDATE) } as u32;
-    let year = (packed >> 16) as u16;
-    let month = ((packed >> 8) & 0xFF) as u8;
-    let day = (packed & 0xFF) as u8;
-    (year, month, day)
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe { syscall2(syscall::SYS_GETARGS, buf.as_mut_ptr() as u64, buf.len() as u64) as usize }
 }
 
-fn get_time() -> (u8, u8, u8) {
-    let packed = unsafe { syscall0(syscall::SYS_GETTIME) } as u32;
-    let hours = ((packed >> 16) & 0xFF) as u8;
-    let minutes = ((packed >> 8) & 0xFF) as u8;
-    let seconds = (packed & 0xFF) as u8;
-    (hours, minutes, seconds)
+fn now() -> DateTime {
+    let date = unsafe { syscall0(syscall::SYS_GETDATE) } as u32;
+    let time = unsafe { syscall0(syscall::SYS_GETTIME) } as u32;
+    DateTime::new(
+        (date >> 16) as i32,
+        (date >> 8) as u8,
+        date as u8,
+        (time >> 16) as u8,
+        (time >> 8) as u8,
+        time as u8,
+    )
 }
 
-// ============================================================================
-// Number formatting
-// ============================================================================
-
-fn write_num(n: u32, width: usize) {
-    let mut buf = [b'0'; 10];
-    let mut i = buf.len();
-    let mut val = n;
-
-    loop {
-        i -= 1;
-        buf[i] = b'0' + (val % 10) as u8;
-        val /= 10;
-        if val == 0 {
-            break;
-        }
-    }
+/// Fixed buffer for `core::fmt` output
+struct Line {
+    buf: [u8; 64],
+    len: usize,
+}
 
-    // Pad with zeros to width
-    let digits = buf.len() - i;
-    for _ in 0..(width.saturating_sub(digits)) {
-        write_str("0");
-    }
-
-    if let Ok(s) = core::str::from_utf8(&buf[i..]) {
-        write_str(s);
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
     }
 }
 
@@ -117,25 +125,37 @@ fn write_num(n: u32, width: usize) {
 
 #[no_mangle]
 extern "C" fn _start() -> ! {
-    let (year, month, day) = get_date();
-    let (hours, minutes, seconds) = get_time();
-
-    // Print date in YYYY-MM-DD format
-    write_num(year as u32, 4);
-    write_str("-");
-    write_num(month as u32, 2);
-    write_str("-");
-    write_num(day as u32, 2);
-
-    write_str(" ");
-
-    // Print time in HH:MM:SS format
-    write_num(hours as u32, 2);
-    write_str(":");
-    write_num(minutes as u32, 2);
-    write_str(":");
-    write_num(seconds as u32, 2);
+    static mut ARGS_BUF: [u8; 256] = [0u8; 256];
+
+    let args: &[u8] = unsafe {
+        let buf = &mut *core::ptr::addr_of_mut!(ARGS_BUF);
+        let len = get_args(buf);
+        &buf[..len]
+    };
+    // Skip the program name
+    let option = args.split(|&c| c == b' ').filter(|a| !a.is_empty()).nth(1);
+
+    let now = now();
+    let mut line = Line { buf: [0; 64], len: 0 };
+    let _ = match option {
+        None => write!(line, "{}", now),
+        Some(b"-I") | Some(b"--iso-8601") => write!(line, "{}", now.iso8601()),
+        Some(b"+%s") => match now.to_timestamp() {
+            Some(ts) => write!(line, "{}", ts.secs()),
+            None => {
+                write_str("date: the clock holds an invalid date\r\n");
+                exit(1);
+            }
+        },
+        Some(_) => {
+            write_str("usage: date [-I | +%s]\r\n");
+            exit(1);
+        }
+    };
 
+    if let Ok(s) = core::str::from_utf8(&line.buf[..line.len]) {
+        write_str(s);
+    }
     write_str("\r\n");
 
     exit(0);
@@ -5,6 +5,8 @@
 //!   drives        - List all mounted drives
 //!   drives mount NAME PATH  - Mount a drive
 //!   drives unmount NAME     - Unmount a drive
+//!   drives assign FROM TO   - Move a drive to another letter; volumes
+//!                             with a serial number keep it on later boots
 
 #![no_std]
 #![no_main]
@@ -108,6 +110,19 @@ fn unmount_drive(name: &[u8]) -> u64 {
     unsafe { syscall2(syscall::SYS_UNMOUNT, name.as_ptr() as u64, name.len() as u64) }
 }
 
+/// Label and serial number of a drive, by name
+fn drive_volume(name: &[u8]) -> Option<watos_syscall::volume::VolInfo> {
+    let mut path = [0u8; 34];
+    if name.len() + 2 > path.len() {
+        return None;
+    }
+    path[..name.len()].copy_from_slice(name);
+    path[name.len()] = b':';
+    path[name.len() + 1] = b'\\';
+    let path = core::str::from_utf8(&path[..name.len() + 2]).ok()?;
+    watos_syscall::syscalls::volinfo(path)
+}
+
 /// Parse arguments and find words
 fn parse_args(args: &[u8]) -> (&[u8], &[u8], &[u8], &[u8]) {
     let mut words: [&[u8]; 4] = [&[], &[], &[], &[]];
@@ -168,8 +183,8 @@ extern "C" fn _start() -> ! {
         if len == 0 {
             write_str("No drives mounted.\r\n");
         } else {
-            write_str("Drive  Path           Type\r\n");
-            write_str("-----  ----           ----\r\n");
+            write_str("Drive  Path           Type    Label\r\n");
+            write_str("-----  ----           ----    -----\r\n");
             // Parse and format the list output
             let list = unsafe { &LIST_BUF[..len] };
             let mut line_start = 0;
@@ -220,6 +235,15 @@ extern "C" fn _start() -> ! {
                     }
                     write_str("  ");
                     write_bytes(parts[2]); // FS type
+                    if let Some(volume) = drive_volume(parts[0]) {
+                        if let Some(label) = volume.label() {
+                            for _ in parts[2].len()..6 {
+                                write_str(" ");
+                            }
+                            write_str("  ");
+                            write_str(label);
+                        }
+                    }
                     write_str("\r\n");
 
                     line_start = i + 1;
@@ -276,11 +300,36 @@ extern "C" fn _start() -> ! {
             3 => write_str("Error: Drive not found\r\n"),
             _ => write_str("Error: Unmount failed\r\n"),
         }
+    } else if bytes_eq(subcmd, b"assign") {
+        // drives assign FROM TO
+        let letter = |name: &[u8]| match name {
+            [c] | [c, b':'] => Some(*c as char),
+            _ => None,
+        };
+        let (Some(from), Some(to)) = (letter(arg1), letter(arg2)) else {
+            write_str("Usage: drives assign FROM TO\r\n");
+            write_str("Example: drives assign D E\r\n");
+            exit(1);
+        };
+        match watos_syscall::syscalls::drive_assign(from, to) {
+            0 => {
+                write_bytes(arg1);
+                write_str(": is now ");
+                write_bytes(&[to.to_ascii_uppercase() as u8]);
+                write_str(":\r\n");
+            }
+            1 => write_str("Error: Invalid drive letter (C: can't move)\r\n"),
+            2 => write_str("Error: Drive not found\r\n"),
+            3 => write_str("Error: Drive letter already in use\r\n"),
+            4 => write_str("Error: Only root can reassign drives\r\n"),
+            _ => write_str("Error: Reassign failed\r\n"),
+        }
     } else {
-        write_str("Usage: drives [mount NAME PATH | unmount NAME]\r\n");
+        write_str("Usage: drives [mount NAME PATH | unmount NAME | assign FROM TO]\r\n");
         write_str("       drives         - List mounted drives\r\n");
         write_str("       drives mount   - Mount a drive\r\n");
         write_str("       drives unmount - Unmount a drive\r\n");
+        write_str("       drives assign  - Move a drive to another letter, for good\r\n");
     }
 
     exit(0);
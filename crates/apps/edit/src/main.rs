@@ -0,0 +1,627 @@
+//! WATOS edit - full-screen text editor
+//!
+//! Usage: edit [FILE]
+//!
+//! Keys:
+//!   Arrows, Home/End, PgUp/PgDn   Move the cursor
+//!   Ctrl+S                        Save
+//!   Ctrl+Q                        Quit (press twice to discard changes)
+//!   Ctrl+F                        Search (Enter = next match, Esc = cancel)
+//!   Ctrl+K                        Cut the current line
+//!   Alt+C                         Copy the current line
+//!   Ctrl+U                        Paste the clipboard at the cursor
+//!
+//! Draws with VT100 escape sequences on the console and reads/writes
+//! files through the VFS open/read/write syscalls. Cut, copy and paste go
+//! through the system clipboard as text/plain, so text moves between edit,
+//! the console and other programs.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_readline::{Key, KeyReader};
+use watos_syscall::numbers as syscall;
+use watos_syscall::open::{O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use watos_syscall::syscalls;
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Console helpers
+// ============================================================================
+
+fn write_bytes(b: &[u8]) {
+    syscalls::write(1, b);
+}
+
+fn write_str(s: &str) {
+    write_bytes(s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+/// Console size, defaulting to the VT's 160x50 (overridable via COLUMNS/LINES)
+fn screen_size() -> (usize, usize) {
+    fn env_num(name: &str, default: usize) -> usize {
+        let mut buf = [0u8; 16];
+        let len = unsafe {
+            let ret: u64;
+            core::arch::asm!(
+                "int 0x80",
+                in("eax") syscall::SYS_GETENV,
+                in("rdi") name.as_ptr() as u64,
+                in("rsi") name.len() as u64,
+                in("rdx") buf.as_mut_ptr() as u64,
+                in("r10") buf.len() as u64,
+                lateout("rax") ret,
+                options(nostack)
+            );
+            ret as usize
+        };
+        if len == 0 || len > buf.len() {
+            return default;
+        }
+        core::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .filter(|&n: &usize| n >= 10)
+            .unwrap_or(default)
+    }
+    (env_num("COLUMNS", 160), env_num("LINES", 50))
+}
+
+const TAB_WIDTH: usize = 4;
+
+/// Clipboard type used for cut, copy and paste
+const TEXT_PLAIN: &str = "text/plain";
+
+/// Largest paste; the kernel clipboard holds at most 64 KB
+const MAX_PASTE: usize = 64 * 1024;
+
+// ============================================================================
+// Editor
+// ============================================================================
+
+struct Editor {
+    lines: Vec<Vec<u8>>,
+    /// Cursor position in the buffer (column is a byte index into the line)
+    cx: usize,
+    cy: usize,
+    /// First visible row / column
+    row_off: usize,
+    col_off: usize,
+    screen_cols: usize,
+    /// Rows available for text (screen minus status and message lines)
+    text_rows: usize,
+    filename: Option<String>,
+    dirty: bool,
+    message: String,
+    quit_confirm: bool,
+    last_search: String,
+}
+
+impl Editor {
+    fn new() -> Self {
+        let (cols, rows) = screen_size();
+        Editor {
+            lines: alloc::vec![Vec::new()],
+            cx: 0,
+            cy: 0,
+            row_off: 0,
+            col_off: 0,
+            screen_cols: cols,
+            text_rows: rows - 2,
+            filename: None,
+            dirty: false,
+            message: String::from("Ctrl+S save | Ctrl+Q quit | Ctrl+F find | Ctrl+K cut | Alt+C copy | Ctrl+U paste"),
+            quit_confirm: false,
+            last_search: String::new(),
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // File I/O
+    // ------------------------------------------------------------------------
+
+    fn open(&mut self, path: &str) {
+        self.filename = Some(String::from(path));
+        let fd = syscalls::open(path, O_RDONLY);
+        if fd < 0 {
+            self.message = format!("New file: {}", path);
+            return;
+        }
+
+        let mut data = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = syscalls::read(fd, &mut chunk);
+            if n == 0 || n > chunk.len() {
+                break;
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+        syscalls::close(fd);
+
+        self.lines = data
+            .split(|&b| b == b'\n')
+            .map(|line| line.iter().copied().filter(|&b| b != b'\r').collect())
+            .collect();
+        // A trailing newline doesn't start another line
+        if self.lines.len() > 1 && self.lines.last().map_or(false, |l| l.is_empty()) {
+            self.lines.pop();
+        }
+        self.message = format!("\"{}\" {} lines", path, self.lines.len());
+    }
+
+    fn save(&mut self) {
+        let path = match &self.filename {
+            Some(p) => p.clone(),
+            None => match self.prompt("Save as: ") {
+                Some(p) if !p.is_empty() => {
+                    self.filename = Some(p.clone());
+                    p
+                }
+                _ => {
+                    self.message = String::from("Save cancelled");
+                    return;
+                }
+            },
+        };
+
+        let fd = syscalls::open(&path, O_WRONLY | O_CREAT | O_TRUNC);
+        if fd < 0 {
+            self.message = format!("Cannot write {} (read-only filesystem?)", path);
+            return;
+        }
+
+        let mut total = 0;
+        let mut ok = true;
+        for line in &self.lines {
+            ok &= syscalls::write(fd, line) == line.len() && syscalls::write(fd, b"\n") == 1;
+            total += line.len() + 1;
+        }
+        syscalls::close(fd);
+
+        if ok {
+            self.dirty = false;
+            self.message = format!("Wrote {} bytes to {}", total, path);
+        } else {
+            self.message = format!("Error writing {}", path);
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Editing
+    // ------------------------------------------------------------------------
+
+    fn insert_char(&mut self, c: u8) {
+        self.lines[self.cy].insert(self.cx, c);
+        self.cx += 1;
+        self.dirty = true;
+    }
+
+    fn insert_newline(&mut self) {
+        let rest = self.lines[self.cy].split_off(self.cx);
+        self.lines.insert(self.cy + 1, rest);
+        self.cy += 1;
+        self.cx = 0;
+        self.dirty = true;
+    }
+
+    fn backspace(&mut self) {
+        if self.cx > 0 {
+            self.cx -= 1;
+            self.lines[self.cy].remove(self.cx);
+            self.dirty = true;
+        } else if self.cy > 0 {
+            let line = self.lines.remove(self.cy);
+            self.cy -= 1;
+            self.cx = self.lines[self.cy].len();
+            self.lines[self.cy].extend_from_slice(&line);
+            self.dirty = true;
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cx < self.lines[self.cy].len() {
+            self.lines[self.cy].remove(self.cx);
+            self.dirty = true;
+        } else if self.cy + 1 < self.lines.len() {
+            let next = self.lines.remove(self.cy + 1);
+            self.lines[self.cy].extend_from_slice(&next);
+            self.dirty = true;
+        }
+    }
+
+    /// Put the current line, with its newline, on the clipboard
+    fn copy_line(&mut self) -> bool {
+        let mut text = self.lines[self.cy].clone();
+        text.push(b'\n');
+        if syscalls::clipboard_set(TEXT_PLAIN, &text) {
+            true
+        } else {
+            self.message = String::from("Line too long for the clipboard");
+            false
+        }
+    }
+
+    fn cut_line(&mut self) {
+        if !self.copy_line() {
+            return;
+        }
+        if self.lines.len() > 1 {
+            self.lines.remove(self.cy);
+        } else {
+            self.lines[0].clear();
+        }
+        if self.cy >= self.lines.len() {
+            self.cy = self.lines.len() - 1;
+        }
+        self.cx = 0;
+        self.dirty = true;
+    }
+
+    /// Insert the clipboard text at the cursor
+    fn paste(&mut self) {
+        let mut text = alloc::vec![0u8; MAX_PASTE];
+        let len = match syscalls::clipboard_get(TEXT_PLAIN, &mut text) {
+            Some(len) => len.min(MAX_PASTE),
+            None => {
+                self.message = String::from("Clipboard holds no text");
+                return;
+            }
+        };
+        for &b in &text[..len] {
+            match b {
+                b'\n' => self.insert_newline(),
+                b'\r' => {}
+                _ => self.insert_char(b),
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Movement
+    // ------------------------------------------------------------------------
+
+    fn clamp_cx(&mut self) {
+        let len = self.lines[self.cy].len();
+        if self.cx > len {
+            self.cx = len;
+        }
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        match key {
+            Key::Left => {
+                if self.cx > 0 {
+                    self.cx -= 1;
+                } else if self.cy > 0 {
+                    self.cy -= 1;
+                    self.cx = self.lines[self.cy].len();
+                }
+            }
+            Key::Right => {
+                if self.cx < self.lines[self.cy].len() {
+                    self.cx += 1;
+                } else if self.cy + 1 < self.lines.len() {
+                    self.cy += 1;
+                    self.cx = 0;
+                }
+            }
+            Key::Up => self.cy = self.cy.saturating_sub(1),
+            Key::Down => {
+                if self.cy + 1 < self.lines.len() {
+                    self.cy += 1;
+                }
+            }
+            Key::Home => self.cx = 0,
+            Key::End => self.cx = self.lines[self.cy].len(),
+            Key::PageUp => self.cy = self.cy.saturating_sub(self.text_rows),
+            Key::PageDown => self.cy = (self.cy + self.text_rows).min(self.lines.len() - 1),
+            _ => {}
+        }
+        self.clamp_cx();
+    }
+
+    /// Screen column of the cursor, expanding tabs
+    fn render_cx(&self) -> usize {
+        let mut rx = 0;
+        for &b in &self.lines[self.cy][..self.cx] {
+            if b == b'\t' {
+                rx += TAB_WIDTH - (rx % TAB_WIDTH);
+            } else {
+                rx += 1;
+            }
+        }
+        rx
+    }
+
+    fn scroll(&mut self) {
+        if self.cy < self.row_off {
+            self.row_off = self.cy;
+        }
+        if self.cy >= self.row_off + self.text_rows {
+            self.row_off = self.cy + 1 - self.text_rows;
+        }
+        let rx = self.render_cx();
+        if rx < self.col_off {
+            self.col_off = rx;
+        }
+        if rx >= self.col_off + self.screen_cols {
+            self.col_off = rx + 1 - self.screen_cols;
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Search
+    // ------------------------------------------------------------------------
+
+    /// Find the next occurrence of `pattern` after the cursor, wrapping around
+    fn find_next(&mut self, pattern: &[u8]) -> bool {
+        if pattern.is_empty() {
+            return false;
+        }
+        let total = self.lines.len();
+        for step in 0..=total {
+            let row = (self.cy + step) % total;
+            let line = &self.lines[row];
+            let start = if step == 0 { (self.cx + 1).min(line.len()) } else { 0 };
+            let hit = line[start..]
+                .windows(pattern.len())
+                .position(|w| w == pattern)
+                .map(|p| p + start)
+                // On the wrapped-around first line, allow matches before the cursor
+                .or_else(|| {
+                    if step == total {
+                        line.windows(pattern.len()).position(|w| w == pattern)
+                    } else {
+                        None
+                    }
+                });
+            if let Some(col) = hit {
+                self.cy = row;
+                self.cx = col;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn search(&mut self) {
+        let (saved_cx, saved_cy) = (self.cx, self.cy);
+        let prompt = if self.last_search.is_empty() {
+            String::from("Search: ")
+        } else {
+            format!("Search [{}]: ", self.last_search)
+        };
+        let query = match self.prompt(&prompt) {
+            Some(q) if q.is_empty() => self.last_search.clone(),
+            Some(q) => q,
+            None => {
+                self.message = String::from("Search cancelled");
+                return;
+            }
+        };
+        self.last_search = query.clone();
+
+        if self.find_next(query.as_bytes()) {
+            self.message = format!("Found \"{}\" at line {} (Ctrl+F, Enter for next)", query, self.cy + 1);
+        } else {
+            self.cx = saved_cx;
+            self.cy = saved_cy;
+            self.message = format!("\"{}\" not found", query);
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Screen
+    // ------------------------------------------------------------------------
+
+    fn draw(&mut self) {
+        self.scroll();
+
+        let mut out: Vec<u8> = Vec::with_capacity(self.screen_cols * (self.text_rows + 2));
+        out.extend_from_slice(b"\x1b[?25l\x1b[H");
+
+        for screen_row in 0..self.text_rows {
+            let row = self.row_off + screen_row;
+            if row < self.lines.len() {
+                // Expand tabs then clip to the visible columns
+                let mut col = 0;
+                for &b in &self.lines[row] {
+                    let (ch, width) = if b == b'\t' {
+                        (b' ', TAB_WIDTH - (col % TAB_WIDTH))
+                    } else if (0x20..0x7F).contains(&b) {
+                        (b, 1)
+                    } else {
+                        (b'?', 1)
+                    };
+                    for _ in 0..width {
+                        if col >= self.col_off && col < self.col_off + self.screen_cols {
+                            out.push(ch);
+                        }
+                        col += 1;
+                    }
+                }
+            } else {
+                out.push(b'~');
+            }
+            out.extend_from_slice(b"\x1b[K\r\n");
+        }
+
+        // Status bar (reverse video)
+        let name = self.filename.as_deref().unwrap_or("[No Name]");
+        let left = format!(" {}{} - {} lines", name, if self.dirty { " (modified)" } else { "" }, self.lines.len());
+        let right = format!("Ln {}, Col {} ", self.cy + 1, self.cx + 1);
+        out.extend_from_slice(b"\x1b[7m");
+        let mut status = left.into_bytes();
+        status.truncate(self.screen_cols);
+        while status.len() + right.len() < self.screen_cols {
+            status.push(b' ');
+        }
+        if status.len() + right.len() == self.screen_cols {
+            status.extend_from_slice(right.as_bytes());
+        }
+        out.extend_from_slice(&status);
+        out.extend_from_slice(b"\x1b[0m\r\n");
+
+        // Message line
+        let msg = self.message.as_bytes();
+        out.extend_from_slice(&msg[..msg.len().min(self.screen_cols)]);
+        out.extend_from_slice(b"\x1b[K");
+
+        // Place the cursor
+        let cursor = format!(
+            "\x1b[{};{}H\x1b[?25h",
+            self.cy - self.row_off + 1,
+            self.render_cx() - self.col_off + 1
+        );
+        out.extend_from_slice(cursor.as_bytes());
+        write_bytes(&out);
+    }
+
+    /// Read a line of input on the message line; None if cancelled with Esc
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        let mut input = String::new();
+        loop {
+            self.message = format!("{}{}", prompt, input);
+            self.draw();
+            // Keep the cursor on the message line while typing
+            let col = (prompt.len() + input.len() + 1).min(self.screen_cols);
+            write_str(&format!("\x1b[{};{}H", self.text_rows + 2, col));
+
+            match KeyReader::read_key() {
+                Key::Enter => {
+                    self.message.clear();
+                    return Some(input);
+                }
+                Key::Escape | Key::Ctrl('c') | Key::Ctrl('q') => {
+                    self.message.clear();
+                    return None;
+                }
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Char(c) if c.is_ascii() && !c.is_ascii_control() => input.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Main loop
+    // ------------------------------------------------------------------------
+
+    /// Handle one key; returns false when the editor should exit
+    fn process_key(&mut self, key: Key) -> bool {
+        if key != Key::Ctrl('q') {
+            self.quit_confirm = false;
+        }
+
+        match key {
+            Key::Ctrl('q') => {
+                if self.dirty && !self.quit_confirm {
+                    self.message = String::from("Unsaved changes! Press Ctrl+Q again to quit without saving");
+                    self.quit_confirm = true;
+                    return true;
+                }
+                return false;
+            }
+            Key::Ctrl('s') => self.save(),
+            Key::Ctrl('f') => self.search(),
+            Key::Ctrl('k') => self.cut_line(),
+            Key::Alt('c') if self.copy_line() => self.message = String::from("Line copied"),
+            Key::Ctrl('u') => self.paste(),
+            Key::Enter => self.insert_newline(),
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::Tab => self.insert_char(b'\t'),
+            Key::Up | Key::Down | Key::Left | Key::Right |
+            Key::Home | Key::End | Key::PageUp | Key::PageDown => self.move_cursor(key),
+            Key::Char(c) if c.is_ascii() && !c.is_ascii_control() => self.insert_char(c as u8),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    let mut editor = Editor::new();
+
+    // First word is the program name
+    let mut words = args.split_whitespace().skip(1);
+    match (words.next(), words.next()) {
+        (_, Some(_)) => {
+            write_str("Usage: edit [FILE]\r\n");
+            exit(1);
+        }
+        (Some(path), None) => editor.open(path),
+        (None, None) => {}
+    }
+
+    loop {
+        editor.draw();
+        let key = KeyReader::read_key();
+        if !editor.process_key(key) {
+            break;
+        }
+    }
+
+    // Clear the screen on the way out
+    write_str("\x1b[2J\x1b[H");
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\x1b[0m\r\nedit: internal error\r\n");
+    exit(1);
+}
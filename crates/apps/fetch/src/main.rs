@@ -0,0 +1,428 @@
+//! WATOS fetch - download a URL over HTTP or HTTPS
+//!
+//! Usage: fetch [-o FILE] URL
+//!
+//! Sends an HTTP/1.0 GET and writes the body to stdout, or to FILE with
+//! -o. https URLs go over TLS (watos-tls), with the server checked against
+//! the roots in /etc/ssl/certs.pem. Redirects are not followed: any status
+//! but 2xx fails, showing where the server points if it says.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::{net, open, syscalls};
+use watos_time::{DateTime, Timestamp};
+use watos_tls::{ClientConfig, Error, RootStore, TlsStream, Transport, ROOT_STORE_PATH};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Output
+// ============================================================================
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn fail(message: &str) -> ! {
+    write_str("fetch: ");
+    write_str(message);
+    write_str("\r\n");
+    exit(1);
+}
+
+// ============================================================================
+// Waiting on the network
+// ============================================================================
+
+/// PIT ticks per second (the timer runs at ~18.2 Hz)
+const TICKS_PER_SEC: u64 = 18;
+
+/// How long to wait for a lookup, a connection, or the next data
+const TIMEOUT_TICKS: u64 = 30 * TICKS_PER_SEC;
+
+fn ticks() -> u64 {
+    unsafe { watos_syscall::raw_syscall0(syscall::SYS_GETTICKS) }
+}
+
+/// Call `poll` until it has an answer, idling in between; None on timeout
+fn wait<T>(mut poll: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = ticks() + TIMEOUT_TICKS;
+    loop {
+        if let Some(answer) = poll() {
+            return Some(answer);
+        }
+        if ticks() > deadline {
+            return None;
+        }
+        syscalls::idle();
+    }
+}
+
+fn resolve(host: &str) -> Result<u32, &'static str> {
+    if let Some(addr) = net::parse_ipv4(host) {
+        return Ok(addr);
+    }
+    let query = syscalls::net_resolve(host).ok_or("no network")?;
+    match wait(|| match syscalls::net_resolved(query) {
+        Some(None) => None,
+        answer => Some(answer.flatten()),
+    }) {
+        Some(Some(addr)) => Ok(addr),
+        Some(None) => Err("host not found"),
+        None => Err("lookup timed out"),
+    }
+}
+
+/// A TCP connection. Sockets never block, so reads and writes wait here
+/// until they can make progress, as TLS expects of its transport.
+struct Socket(i32);
+
+impl Socket {
+    fn connect(addr: u32, port: u16) -> Result<Self, &'static str> {
+        let socket = Socket(syscalls::net_connect(addr, port).ok_or("no network")?);
+        match wait(|| syscalls::net_state(socket.0).filter(|&state| state != net::STATE_CONNECTING)) {
+            Some(net::STATE_OPEN) => Ok(socket),
+            Some(_) => Err("connection refused"),
+            None => Err("connection timed out"),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        syscalls::net_state(self.0) == Some(net::STATE_OPEN)
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        syscalls::close(self.0);
+    }
+}
+
+impl Transport for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        wait(|| match syscalls::read(self.0, buf) {
+            n if n > 0 && n <= buf.len() => Some(n),
+            // Closed once the peer is done and everything has been read
+            _ if !self.is_open() => Some(0),
+            _ => None,
+        })
+        .ok_or(Error::Transport)
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            // 0 while the send buffer is full
+            let n = wait(|| match syscalls::write(self.0, buf) {
+                0 if self.is_open() => None,
+                n => Some(n),
+            })
+            .ok_or(Error::Transport)?;
+            if n == 0 || n > buf.len() {
+                return Err(Error::Transport);
+            }
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+}
+
+enum Connection {
+    Plain(Socket),
+    Tls(Box<TlsStream<Socket>>),
+}
+
+impl Connection {
+    /// Ok(0) at the end of the response
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        match self {
+            Connection::Plain(socket) => socket.read(buf),
+            Connection::Tls(tls) => tls.read(buf),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        match self {
+            Connection::Plain(socket) => socket.write_all(data),
+            Connection::Tls(tls) => tls.write(data),
+        }
+    }
+}
+
+// ============================================================================
+// TLS
+// ============================================================================
+
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = syscalls::read(fd, &mut buf);
+        if n == 0 || n > buf.len() {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+    syscalls::close(fd);
+    Some(data)
+}
+
+/// Current time from the RTC, for certificate validity
+fn now() -> Timestamp {
+    let (year, month, day) = syscalls::get_date();
+    let (hour, minute, second) = syscalls::get_time();
+    DateTime::new(year as i32, month, day, hour, minute, second)
+        .to_timestamp()
+        .unwrap_or(Timestamp::UNIX_EPOCH)
+}
+
+fn start_tls(socket: Socket, host: &str) -> TlsStream<Socket> {
+    let mut roots = RootStore::new();
+    if let Some(pem) = read_file(ROOT_STORE_PATH) {
+        roots.add_pem(&pem);
+    }
+    if roots.is_empty() {
+        fail(&format!("no trusted certificates in {}", ROOT_STORE_PATH));
+    }
+    let config = ClientConfig { server_name: host, roots: &roots, now: now() };
+    let mut random = |buf: &mut [u8]| {
+        if !syscalls::getrandom(buf) {
+            fail("no random numbers");
+        }
+    };
+    TlsStream::connect(socket, &config, &mut random)
+        .unwrap_or_else(|e| fail(&format!("TLS handshake with {} failed: {:?}", host, e)))
+}
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+struct Url<'a> {
+    https: bool,
+    /// Host and port as written, for the Host header
+    authority: &'a str,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+fn parse_url(url: &str) -> Option<Url<'_>> {
+    let (https, rest) = match url.strip_prefix("https://") {
+        Some(rest) => (true, rest),
+        None => (false, url.strip_prefix("http://")?),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(at) => rest.split_at(at),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, if https { 443 } else { 80 }),
+    };
+    (!host.is_empty()).then_some(Url { https, authority, host, port, path })
+}
+
+/// What matters in a response head
+struct Head {
+    status: u16,
+    reason: String,
+    location: Option<String>,
+    content_length: Option<usize>,
+}
+
+/// Longest response head accepted
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Read up to the end of the response head; returns it and whatever of
+/// the body came with it
+fn read_head(conn: &mut Connection) -> Result<(Head, Vec<u8>), String> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let end = loop {
+        if let Some(at) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break at;
+        }
+        if data.len() > MAX_HEAD {
+            return Err("response head too long".into());
+        }
+        match conn.read(&mut buf) {
+            Ok(0) => return Err("connection closed before a response".into()),
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(e) => return Err(format!("read failed: {:?}", e)),
+        }
+    };
+
+    let head = core::str::from_utf8(&data[..end]).map_err(|_| String::from("malformed response"))?;
+    let mut lines = head.split("\r\n");
+    let mut status_line = lines.next().unwrap_or("").splitn(3, ' ');
+    let version = status_line.next().unwrap_or("");
+    let status = status_line.next().and_then(|code| code.parse().ok());
+    let (true, Some(status)) = (version.starts_with("HTTP/"), status) else {
+        return Err("malformed response".into());
+    };
+    let mut parsed = Head {
+        status,
+        reason: status_line.next().unwrap_or("").into(),
+        location: None,
+        content_length: None,
+    };
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("location") {
+            parsed.location = Some(value.into());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            parsed.content_length = value.parse().ok();
+        }
+    }
+    let body = data.split_off(end + 4);
+    Ok((parsed, body))
+}
+
+/// Write all of `data` to `fd`
+fn write_out(fd: i32, data: &[u8]) {
+    if syscalls::write(fd, data) != data.len() {
+        fail("write failed");
+    }
+}
+
+fn usage() -> ! {
+    write_str("Usage: fetch [-o FILE] URL\r\n");
+    exit(1);
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    // First word is the program name
+    let mut words = args.split_whitespace().skip(1);
+    let mut output = None;
+    let mut url = None;
+    while let Some(word) = words.next() {
+        match word {
+            "-o" => output = Some(words.next().unwrap_or_else(|| usage())),
+            _ if word.starts_with('-') || url.is_some() => usage(),
+            _ => url = Some(word),
+        }
+    }
+    let url = url.and_then(parse_url).unwrap_or_else(|| usage());
+
+    let addr = resolve(url.host).unwrap_or_else(|e| fail(&format!("{}: {}", url.host, e)));
+    let socket = Socket::connect(addr, url.port).unwrap_or_else(|e| fail(&format!("{}: {}", url.authority, e)));
+    let mut conn = if url.https {
+        Connection::Tls(Box::new(start_tls(socket, url.host)))
+    } else {
+        Connection::Plain(socket)
+    };
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: watos-fetch\r\nConnection: close\r\n\r\n",
+        url.path, url.authority
+    );
+    if let Err(e) = conn.write(request.as_bytes()) {
+        fail(&format!("sending the request failed: {:?}", e));
+    }
+
+    let (head, body) = read_head(&mut conn).unwrap_or_else(|e| fail(&e));
+    if !(200..300).contains(&head.status) {
+        let moved = head.location.map(|to| format!(" -> {}", to)).unwrap_or_default();
+        fail(&format!("server answered {} {}{}", head.status, head.reason, moved));
+    }
+
+    let out = match output {
+        Some(path) => {
+            let fd = syscalls::open(path, open::O_WRONLY | open::O_CREAT | open::O_TRUNC);
+            if fd < 0 {
+                fail(&format!("cannot create {}", path));
+            }
+            fd
+        }
+        None => 1,
+    };
+    let mut received = body.len();
+    write_out(out, &body);
+    let mut buf = [0u8; 4096];
+    loop {
+        match conn.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                write_out(out, &buf[..n]);
+                received += n;
+            }
+            // Plenty of servers hang up without a TLS close_notify; a
+            // Content-Length still shows whether anything was cut off
+            Err(Error::UnexpectedEof) if head.content_length.is_some() => break,
+            Err(e) => fail(&format!("read failed: {:?}", e)),
+        }
+    }
+    if out != 1 {
+        syscalls::close(out);
+    }
+    if head.content_length.is_some_and(|len| received < len) {
+        fail("connection closed before the whole body arrived");
+    }
+    if let Connection::Tls(tls) = conn {
+        let _ = tls.close();
+    }
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\r\nfetch: internal error\r\n");
+    exit(1);
+}
@@ -0,0 +1,669 @@
+//! WATOS fm - two-pane file manager
+//!
+//! Usage: fm [LEFT_DIR] [RIGHT_DIR]
+//!
+//! Keys:
+//!   Up/Down, PgUp/PgDn, Home/End  Move the selection
+//!   Tab                           Switch panes
+//!   Enter                         Open the selected directory
+//!   Backspace                     Go to the parent directory
+//!   c / m                         Copy / move the selection to the other pane
+//!   d, Delete                     Move the selection to the trash (asks first)
+//!   n                             Create a directory
+//!   r                             Refresh both panes
+//!   q, Ctrl+Q                     Quit
+//!
+//! Entries are drawn with the FileColor and FileIcon inferred by the VFS
+//! metadata module from each entry's type and extension.
+//!
+//! Each pane watches its directory (SYS_WATCH) and reloads when another
+//! program changes it; the check runs between key presses.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod ops;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use ops::DirEntry;
+use watos_readline::{Key, KeyReader};
+use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
+use watos_syscall::watch;
+use watos_vfs::{color_from_file, icon_from_extension, FileColor, FileIcon, FileType};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Console helpers
+// ============================================================================
+
+fn write_bytes(b: &[u8]) {
+    syscalls::write(1, b);
+}
+
+fn write_str(s: &str) {
+    write_bytes(s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+/// Console size, defaulting to the VT's 160x50 (overridable via COLUMNS/LINES)
+fn screen_size() -> (usize, usize) {
+    fn env_num(name: &str, default: usize) -> usize {
+        let mut buf = [0u8; 16];
+        let len = unsafe {
+            let ret: u64;
+            core::arch::asm!(
+                "int 0x80",
+                in("eax") syscall::SYS_GETENV,
+                in("rdi") name.as_ptr() as u64,
+                in("rsi") name.len() as u64,
+                in("rdx") buf.as_mut_ptr() as u64,
+                in("r10") buf.len() as u64,
+                lateout("rax") ret,
+                options(nostack)
+            );
+            ret as usize
+        };
+        if len == 0 || len > buf.len() {
+            return default;
+        }
+        core::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .filter(|&n: &usize| n >= 10)
+            .unwrap_or(default)
+    }
+    (env_num("COLUMNS", 160), env_num("LINES", 50))
+}
+
+fn current_dir() -> String {
+    let mut buf = [0u8; 256];
+    let len = syscalls::getcwd(&mut buf);
+    match core::str::from_utf8(&buf[..len.min(buf.len())]) {
+        Ok(s) if !s.is_empty() => String::from(s),
+        _ => String::from("/"),
+    }
+}
+
+/// Human-readable size: bytes up to 9999, then K/M/G
+fn format_size(size: u64) -> String {
+    if size < 10_000 {
+        return format!("{}", size);
+    }
+    let units = ["K", "M", "G", "T"];
+    let mut value = size / 1024;
+    let mut unit = 0;
+    while value >= 10_000 && unit + 1 < units.len() {
+        value /= 1024;
+        unit += 1;
+    }
+    format!("{}{}", value, units[unit])
+}
+
+/// Color and icon for a listing entry, as the VFS metadata module infers them
+fn entry_style(entry: &DirEntry) -> (FileColor, FileIcon) {
+    let file_type = match entry.kind {
+        b'D' => FileType::Directory,
+        b'L' => FileType::Symlink,
+        b'C' => FileType::CharDevice,
+        b'B' => FileType::BlockDevice,
+        b'P' => FileType::Fifo,
+        b'S' => FileType::Socket,
+        _ => FileType::Regular,
+    };
+    let ext = entry
+        .name
+        .rfind('.')
+        .filter(|&pos| pos > 0)
+        .map(|pos| &entry.name[pos + 1..]);
+    let icon = match file_type {
+        FileType::Directory => FileIcon::Folder,
+        FileType::Symlink => FileIcon::Link,
+        _ => icon_from_extension(ext.unwrap_or("")),
+    };
+    let is_exec = icon == FileIcon::Executable || ext.is_none() && file_type == FileType::Regular;
+    (color_from_file(file_type, ext, is_exec), icon)
+}
+
+// ============================================================================
+// Panes
+// ============================================================================
+
+struct Pane {
+    path: String,
+    entries: Vec<DirEntry>,
+    selected: usize,
+    scroll: usize,
+    /// Watch fd for `path`, or -1 if it could not be watched
+    watch: i32,
+    /// Label of the volume holding `path`, shown in the header
+    label: Option<String>,
+}
+
+impl Pane {
+    fn new(path: String) -> Self {
+        let mut pane = Pane {
+            path,
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            watch: -1,
+            label: None,
+        };
+        pane.rewatch();
+        pane.relabel();
+        pane.reload();
+        pane
+    }
+
+    /// Replace the watch with one on the current directory
+    fn rewatch(&mut self) {
+        if self.watch >= 0 {
+            syscalls::close(self.watch);
+        }
+        self.watch = syscalls::watch(&self.path, watch::ALL);
+    }
+
+    /// Look up the label of the volume holding the current directory
+    fn relabel(&mut self) {
+        self.label = syscalls::volinfo(&self.path).and_then(|v| v.label().map(String::from));
+    }
+
+    /// Consume pending watch events; true if there were any
+    fn take_events(&mut self) -> bool {
+        if self.watch < 0 {
+            return false;
+        }
+        let mut buf = [0u8; 256];
+        let mut any = false;
+        loop {
+            let n = syscalls::read(self.watch, &mut buf);
+            if n == 0 || n > buf.len() {
+                return any;
+            }
+            any = true;
+        }
+    }
+
+    fn is_root(&self) -> bool {
+        ops::parent(&self.path) == self.path
+    }
+
+    /// Re-read the directory, keeping the selection on the same name if possible
+    fn reload(&mut self) -> bool {
+        // The listing below already reflects anything reported so far
+        self.take_events();
+        let keep = self.selected_entry().map(|e| e.name.clone());
+        let listed = ops::list_dir(&self.path);
+        let ok = listed.is_some();
+
+        self.entries.clear();
+        if !self.is_root() {
+            self.entries.push(DirEntry {
+                name: String::from(".."),
+                kind: b'D',
+                is_dir: true,
+                size: 0,
+            });
+        }
+        self.entries.extend(listed.unwrap_or_default());
+
+        self.selected = keep
+            .and_then(|name| self.entries.iter().position(|e| e.name == name))
+            .unwrap_or(0);
+        self.scroll = self.scroll.min(self.selected);
+        ok
+    }
+
+    fn selected_entry(&self) -> Option<&DirEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Selected entry unless it is the ".." link
+    fn target(&self) -> Option<&DirEntry> {
+        self.selected_entry().filter(|e| e.name != "..")
+    }
+
+    fn change_dir(&mut self, path: String) -> bool {
+        let previous = core::mem::replace(&mut self.path, path);
+        let from = ops::parent(&previous) == self.path;
+        self.selected = 0;
+        self.scroll = 0;
+        if !self.reload() {
+            self.path = previous;
+            self.reload();
+            return false;
+        }
+        self.rewatch();
+        self.relabel();
+        // Coming back up: land on the directory we just left
+        if from {
+            let name = previous.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+            if let Some(pos) = self.entries.iter().position(|e| e.name == name) {
+                self.selected = pos;
+            }
+        }
+        true
+    }
+
+    /// Open the selected directory; false if it could not be read
+    fn enter(&mut self) -> bool {
+        let entry = match self.selected_entry() {
+            Some(e) if e.is_dir => e.name.clone(),
+            _ => return true,
+        };
+        if entry == ".." {
+            self.up()
+        } else {
+            let path = ops::join(&self.path, &entry);
+            self.change_dir(path)
+        }
+    }
+
+    fn up(&mut self) -> bool {
+        if self.is_root() {
+            return false;
+        }
+        let parent = ops::parent(&self.path);
+        self.change_dir(parent)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() as isize - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    fn scroll_to_selection(&mut self, rows: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+
+    /// Render one pane into `out` starting at screen column `x` (1-based)
+    fn draw(&mut self, out: &mut Vec<u8>, x: usize, width: usize, rows: usize, active: bool) {
+        self.scroll_to_selection(rows);
+
+        // Header: the volume label and directory path, highlighted on the
+        // active pane. Widths count characters, not bytes: names may be any UTF-8
+        let header = match &self.label {
+            Some(label) => format!(" [{}] {}", label, self.path),
+            None => format!(" {}", self.path),
+        };
+        let header: String = header.chars().take(width).collect();
+        out.extend_from_slice(format!("\x1b[1;{}H", x).as_bytes());
+        out.extend_from_slice(if active { b"\x1b[7m" } else { b"\x1b[1m" });
+        out.extend_from_slice(header.as_bytes());
+        out.resize(out.len() + width.saturating_sub(header.chars().count()), b' ');
+        out.extend_from_slice(b"\x1b[0m");
+
+        // Layout: " TAG name ... size "
+        let size_width = 6;
+        let name_width = width.saturating_sub(size_width + 7);
+
+        for row in 0..rows {
+            out.extend_from_slice(format!("\x1b[{};{}H", row + 2, x).as_bytes());
+            let index = self.scroll + row;
+            let entry = match self.entries.get(index) {
+                Some(e) => e,
+                None => {
+                    out.resize(out.len() + width, b' ');
+                    continue;
+                }
+            };
+
+            let (color, icon) = entry_style(entry);
+            let selected = index == self.selected;
+            if selected && active {
+                out.extend_from_slice(b"\x1b[7m");
+            }
+            out.extend_from_slice(color.ansi_code().as_bytes());
+
+            let mut name: String = entry.name.chars().take(name_width).collect();
+            if entry.is_dir && name.chars().count() < name_width {
+                name.push('/');
+            }
+            let size = if entry.is_dir {
+                String::from("<DIR>")
+            } else {
+                format_size(entry.size)
+            };
+            let line = format!(
+                " {:<3} {:<nw$} {:>sw$} ",
+                icon.label(),
+                name,
+                size,
+                nw = name_width,
+                sw = size_width
+            );
+            let line: String = line.chars().take(width).collect();
+            out.extend_from_slice(line.as_bytes());
+            out.extend_from_slice(FileColor::reset().as_bytes());
+        }
+    }
+}
+
+// ============================================================================
+// File manager
+// ============================================================================
+
+struct FileManager {
+    panes: [Pane; 2],
+    active: usize,
+    cols: usize,
+    rows: usize,
+    message: String,
+}
+
+impl FileManager {
+    fn new(left: String, right: String) -> Self {
+        let (cols, rows) = screen_size();
+        FileManager {
+            panes: [Pane::new(left), Pane::new(right)],
+            active: 0,
+            cols,
+            rows,
+            message: String::new(),
+        }
+    }
+
+    /// Rows available for entries (screen minus header, status and message lines)
+    fn list_rows(&self) -> usize {
+        self.rows.saturating_sub(3)
+    }
+
+    fn draw(&mut self) {
+        let rows = self.list_rows();
+        let left_width = self.cols / 2;
+        let right_width = self.cols - left_width - 1;
+
+        let mut out: Vec<u8> = Vec::with_capacity(self.cols * self.rows * 2);
+        out.extend_from_slice(b"\x1b[?25l\x1b[H");
+
+        let active = self.active;
+        self.panes[0].draw(&mut out, 1, left_width, rows, active == 0);
+        self.panes[1].draw(&mut out, left_width + 2, right_width, rows, active == 1);
+
+        // Divider between the panes
+        for row in 1..=rows + 1 {
+            out.extend_from_slice(format!("\x1b[{};{}H|", row, left_width + 1).as_bytes());
+        }
+
+        // Status bar (reverse video) describing the selection
+        let pane = &self.panes[self.active];
+        let status = match pane.selected_entry() {
+            Some(e) => {
+                let (_, icon) = entry_style(e);
+                format!(
+                    " {} - {} {} bytes  [{}/{}]",
+                    e.name,
+                    icon.label(),
+                    e.size,
+                    pane.selected + 1,
+                    pane.entries.len()
+                )
+            }
+            None => String::from(" (empty)"),
+        };
+        let status: String = status.chars().take(self.cols).collect();
+        out.extend_from_slice(format!("\x1b[{};1H\x1b[7m", rows + 2).as_bytes());
+        out.extend_from_slice(status.as_bytes());
+        out.resize(out.len() + self.cols.saturating_sub(status.chars().count()), b' ');
+        out.extend_from_slice(b"\x1b[0m");
+
+        self.draw_message(&mut out);
+        write_bytes(&out);
+    }
+
+    fn draw_message(&self, out: &mut Vec<u8>) {
+        let msg = if self.message.is_empty() {
+            "Tab switch  Enter open  c copy  m move  d delete  n mkdir  r refresh  q quit"
+        } else {
+            self.message.as_str()
+        };
+        out.extend_from_slice(format!("\x1b[{};1H", self.rows).as_bytes());
+        out.extend_from_slice(&msg.as_bytes()[..msg.len().min(self.cols)]);
+        out.extend_from_slice(b"\x1b[K");
+    }
+
+    /// Show a message immediately without redrawing the panes
+    fn show_message(&mut self, msg: String) {
+        self.message = msg;
+        let mut out = Vec::new();
+        self.draw_message(&mut out);
+        write_bytes(&out);
+    }
+
+    /// Read a line of input on the message line; None if cancelled with Esc
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        let mut input = String::new();
+        loop {
+            let text = format!("{}{}", prompt, input);
+            self.show_message(text);
+            let col = (prompt.len() + input.len() + 1).min(self.cols);
+            write_str(&format!("\x1b[{};{}H\x1b[?25h", self.rows, col));
+
+            match KeyReader::read_key() {
+                Key::Enter => {
+                    self.message.clear();
+                    return Some(input);
+                }
+                Key::Escape | Key::Ctrl('c') | Key::Ctrl('q') => {
+                    self.message.clear();
+                    return None;
+                }
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Char(c) if c.is_ascii() && !c.is_ascii_control() => input.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str) -> bool {
+        self.show_message(format!("{} (y/n)", question));
+        let yes = matches!(KeyReader::read_key(), Key::Char('y') | Key::Char('Y'));
+        self.message.clear();
+        yes
+    }
+
+    fn reload_all(&mut self) {
+        self.panes[0].reload();
+        self.panes[1].reload();
+    }
+
+    /// Reload panes whose directory changed since they were last read
+    fn reload_changed(&mut self) {
+        for pane in &mut self.panes {
+            if pane.take_events() {
+                pane.reload();
+            }
+        }
+    }
+
+    /// Copy or move the active selection into the other pane's directory
+    fn transfer(&mut self, is_move: bool) {
+        let entry = match self.panes[self.active].target() {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        let src_dir = self.panes[self.active].path.clone();
+        let dest_dir = self.panes[1 - self.active].path.clone();
+        let verb = if is_move { "Move" } else { "Copy" };
+
+        if !self.confirm(&format!("{} {} to {}?", verb, entry.name, dest_dir)) {
+            return;
+        }
+
+        let cols = self.cols;
+        let rows = self.rows;
+        let mut progress = |file: &str, done: u64, total: u64| {
+            let percent = done * 100 / total.max(1);
+            let line = format!("{} {} ... {}% ({} of {})", verb, file, percent, format_size(done), format_size(total));
+            let line: String = line.chars().take(cols).collect();
+            write_str(&format!("\x1b[{};1H{}\x1b[K", rows, line));
+        };
+
+        let result = if is_move {
+            ops::move_entry(&src_dir, &entry, &dest_dir, &mut progress)
+        } else {
+            ops::copy(&src_dir, &entry, &dest_dir, &mut progress)
+        };
+
+        self.message = match result {
+            Ok(()) => format!("{} {}: done", verb, entry.name),
+            Err(e) => format!("{} failed: {}", verb, e),
+        };
+        self.reload_all();
+    }
+
+    fn delete(&mut self) {
+        let entry = match self.panes[self.active].target() {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        let what = if entry.is_dir { "directory" } else { "file" };
+        if !self.confirm(&format!("Delete {} {}?", what, entry.name)) {
+            return;
+        }
+
+        let dir = self.panes[self.active].path.clone();
+        self.show_message(format!("Deleting {} ...", entry.name));
+        let result = match ops::trash(&dir, &entry) {
+            Ok(true) => Ok(format!("Moved {} to the trash", entry.name)),
+            Ok(false) => ops::delete(&dir, &entry).map(|()| format!("Deleted {}", entry.name)),
+            Err(e) => Err(e),
+        };
+        self.message = result.unwrap_or_else(|e| format!("Delete failed: {}", e));
+        self.reload_all();
+    }
+
+    fn make_dir(&mut self) {
+        let name = match self.prompt("New directory: ") {
+            Some(n) if !n.trim().is_empty() => n,
+            _ => return,
+        };
+        let dir = self.panes[self.active].path.clone();
+        self.message = match ops::make_dir(&dir, name.trim()) {
+            Ok(()) => format!("Created {}", name.trim()),
+            Err(e) => e,
+        };
+        self.reload_all();
+    }
+
+    /// Handle one key; returns false when the file manager should exit
+    fn process_key(&mut self, key: Key) -> bool {
+        self.message.clear();
+        let page = self.list_rows().max(1) as isize;
+        let pane = &mut self.panes[self.active];
+
+        match key {
+            Key::Char('q') | Key::Ctrl('q') => return false,
+            Key::Up => pane.move_selection(-1),
+            Key::Down => pane.move_selection(1),
+            Key::PageUp => pane.move_selection(-page),
+            Key::PageDown => pane.move_selection(page),
+            Key::Home => pane.move_selection(isize::MIN / 2),
+            Key::End => pane.move_selection(isize::MAX / 2),
+            Key::Tab => self.active = 1 - self.active,
+            Key::Enter if !pane.enter() => self.message = String::from("Cannot open directory"),
+            Key::Backspace => {
+                pane.up();
+            }
+            Key::Char('c') => self.transfer(false),
+            Key::Char('m') => self.transfer(true),
+            Key::Char('d') | Key::Delete => self.delete(),
+            Key::Char('n') => self.make_dir(),
+            Key::Char('r') => self.reload_all(),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    // First word is the program name
+    let mut words = args.split_whitespace().skip(1);
+    let cwd = current_dir();
+    let left = words.next().map(String::from).unwrap_or_else(|| cwd.clone());
+    let right = words.next().map(String::from).unwrap_or(cwd);
+    if words.next().is_some() {
+        write_str("Usage: fm [LEFT_DIR] [RIGHT_DIR]\r\n");
+        exit(1);
+    }
+
+    let mut fm = FileManager::new(left, right);
+    write_str("\x1b[2J");
+
+    loop {
+        fm.reload_changed();
+        fm.draw();
+        let key = KeyReader::read_key();
+        if !fm.process_key(key) {
+            break;
+        }
+    }
+
+    // Clear the screen on the way out
+    write_str("\x1b[0m\x1b[2J\x1b[H\x1b[?25h");
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\x1b[0m\r\nfm: internal error\r\n");
+    exit(1);
+}
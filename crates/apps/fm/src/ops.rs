@@ -0,0 +1,157 @@
+//! File operations for the file manager
+//!
+//! Copy uses SYS_COPYFILE, which copies a slice per call so progress can be
+//! shown; move uses SYS_RENAME and falls back to copy + delete across
+//! filesystems.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use watos_syscall::syscalls;
+
+/// Directory entry as reported by SYS_READDIR
+#[derive(Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// Type tag from the listing (D, F, L, C, B, P, S)
+    pub kind: u8,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Join a directory and a name with a single '/'
+pub fn join(dir: &str, name: &str) -> String {
+    let mut path = String::from(dir);
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(name);
+    path
+}
+
+/// Parent directory of `path` ("/" stays "/", "C:/apps" -> "C:/")
+pub fn parent(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(pos) => String::from(&trimmed[..=pos]),
+        None => String::from(path),
+    }
+}
+
+/// List a directory, directories first then in collated name order
+pub fn list_dir(path: &str) -> Option<Vec<DirEntry>> {
+    let mut buf = alloc::vec![0u8; 16384];
+    let len = syscalls::readdir(path, &mut buf);
+    if len > buf.len() {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    // Each line is "TYPE NAME SIZE"; the name may contain spaces
+    for line in buf[..len].split(|&b| b == b'\n') {
+        if line.len() < 3 || line[1] != b' ' {
+            continue;
+        }
+        // Names that aren't valid UTF-8 are still listed, with U+FFFD
+        let rest: String = watos_unicode::chars_lossy(&line[2..]).collect();
+        let (name, size) = match rest.rfind(' ') {
+            Some(pos) => (&rest[..pos], rest[pos + 1..].parse().unwrap_or(0)),
+            None => (rest.as_str(), 0),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            continue;
+        }
+        entries.push(DirEntry {
+            name: String::from(name),
+            kind: line[0],
+            is_dir: line[0] == b'D',
+            size,
+        });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| watos_unicode::collate(&a.name, &b.name)));
+    Some(entries)
+}
+
+/// Progress callback: (file being copied, bytes done, total bytes)
+pub type Progress<'a> = dyn FnMut(&str, u64, u64) + 'a;
+
+/// Copy a single file, reporting progress per chunk
+fn copy_file(src: &str, dest: &str, progress: &mut Progress) -> Result<(), String> {
+    if syscalls::copyfile(src, dest, |done, total| progress(src, done, total)) {
+        Ok(())
+    } else {
+        Err(format!("cannot copy {} to {}", src, dest))
+    }
+}
+
+/// Copy a file or directory tree into `dest_dir`
+pub fn copy(src_dir: &str, entry: &DirEntry, dest_dir: &str, progress: &mut Progress) -> Result<(), String> {
+    let src = join(src_dir, &entry.name);
+    let dest = join(dest_dir, &entry.name);
+    if src == dest {
+        return Err(String::from("source and destination are the same"));
+    }
+
+    if !entry.is_dir {
+        return copy_file(&src, &dest, progress);
+    }
+
+    if syscalls::stat(&dest).is_none() && syscalls::mkdir(&dest) != 0 {
+        return Err(format!("cannot create directory {}", dest));
+    }
+    let children = list_dir(&src).ok_or_else(|| format!("cannot read {}", src))?;
+    for child in &children {
+        copy(&src, child, &dest, progress)?;
+    }
+    Ok(())
+}
+
+/// Delete a file or directory tree
+pub fn delete(dir: &str, entry: &DirEntry) -> Result<(), String> {
+    let path = join(dir, &entry.name);
+    if entry.is_dir {
+        let children = list_dir(&path).ok_or_else(|| format!("cannot read {}", path))?;
+        for child in &children {
+            delete(&path, child)?;
+        }
+        if syscalls::rmdir(&path) != 0 {
+            return Err(format!("cannot remove directory {}", path));
+        }
+    } else if syscalls::unlink(&path) != 0 {
+        return Err(format!("cannot delete {}", path));
+    }
+    Ok(())
+}
+
+/// Move a file or directory to its drive's trash; Ok(false) if there is
+/// none for it, and it has to be deleted instead
+pub fn trash(dir: &str, entry: &DirEntry) -> Result<bool, String> {
+    let path = join(dir, &entry.name);
+    match syscalls::trash(&path) {
+        0 => Ok(true),
+        1 => Ok(false),
+        _ => Err(format!("cannot move {} to the trash", path)),
+    }
+}
+
+/// Move a file or directory into `dest_dir`
+pub fn move_entry(src_dir: &str, entry: &DirEntry, dest_dir: &str, progress: &mut Progress) -> Result<(), String> {
+    let src = join(src_dir, &entry.name);
+    let dest = join(dest_dir, &entry.name);
+    if syscalls::rename(&src, &dest) == 0 {
+        return Ok(());
+    }
+    // Different filesystems: copy then remove the original
+    copy(src_dir, entry, dest_dir, progress)?;
+    delete(src_dir, entry)
+}
+
+/// Create a directory
+pub fn make_dir(dir: &str, name: &str) -> Result<(), String> {
+    let path = join(dir, name);
+    if syscalls::mkdir(&path) != 0 {
+        return Err(format!("cannot create directory {}", path));
+    }
+    Ok(())
+}
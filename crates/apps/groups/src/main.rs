@@ -0,0 +1,110 @@
+//! WATOS groups command - print group memberships
+//!
+//! Usage: groups [USER...]
+//!
+//! Without arguments prints the groups of this process. With USER names,
+//! prints one line per user from the group database:
+//!
+//!   guest : users wheel
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, syscalls};
+
+/// Most groups reported for one user
+const MAX_GROUPS: usize = 32;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_num(n: u32) {
+    let mut buf = [0u8; 10];
+    let mut i = buf.len();
+    let mut n = n;
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &buf[i..]);
+}
+
+/// Print a group's name, or its number if it has no entry
+fn write_group(gid: u32) {
+    let mut buf = [0u8; 1024];
+    match syscalls::getgrgid(gid, &mut buf) {
+        Some(group) => write_str(group.name),
+        None => write_num(gid),
+    }
+}
+
+/// Print the primary group followed by the other groups, space separated
+fn write_groups(gid: u32, groups: &[u32]) {
+    write_group(gid);
+    for &g in groups.iter().filter(|&&g| g != gid) {
+        write_str(" ");
+        write_group(g);
+    }
+    write_str("\r\n");
+}
+
+/// Print the groups of a named user; false if there is no such user
+fn user_groups(user: &str) -> bool {
+    let mut pw_buf = [0u8; 512];
+    let Some(pw) = syscalls::getpwnam(user, &mut pw_buf) else {
+        write_str("groups: '");
+        write_str(user);
+        write_str("': no such user\r\n");
+        return false;
+    };
+
+    let mut gids = [0u32; MAX_GROUPS];
+    let mut count = 0;
+    let mut gr_buf = [0u8; 1024];
+    let mut index = 0;
+    while let Some(group) = syscalls::getgrent(index, &mut gr_buf) {
+        if count < gids.len() && group.members().any(|m| m == pw.name) {
+            gids[count] = group.gid;
+            count += 1;
+        }
+        index += 1;
+    }
+
+    write_str(pw.name);
+    write_str(" : ");
+    write_groups(pw.gid, &gids[..count]);
+    true
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 1024];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+
+    let mut status = 0;
+    let mut any = false;
+    for user in argv::decode(&args_buf[..args_len]).skip(1) {
+        any = true;
+        if !user_groups(user) {
+            status = 1;
+        }
+    }
+
+    if !any {
+        let mut gids = [0u32; MAX_GROUPS];
+        let count = syscalls::getgroups(&mut gids).min(MAX_GROUPS);
+        write_groups(syscalls::getgid(), &gids[..count]);
+    }
+    syscalls::exit(status)
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1)
+}
@@ -3,7 +3,7 @@
 //! Provides file operations for both std (host) and no_std (WATOS) environments.
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::String, format};
+use alloc::{string::String, vec::Vec, format};
 #[cfg(not(feature = "std"))]
 use alloc::collections::BTreeMap as HashMap;
 
@@ -28,12 +28,13 @@ pub enum FileMode {
 }
 
 impl FileMode {
-    pub fn to_watos_mode(&self) -> u8 {
+    /// SYS_OPEN flags (watos_syscall::open values)
+    pub fn to_watos_mode(&self) -> u64 {
         match self {
-            FileMode::Input => 0,
-            FileMode::Output => 1,
-            FileMode::Append => 2,
-            FileMode::Random => 3,
+            FileMode::Input => 0x000,  // O_RDONLY
+            FileMode::Output => 0x241, // O_WRONLY | O_CREAT | O_TRUNC
+            FileMode::Append => 0x441, // O_WRONLY | O_CREAT | O_APPEND
+            FileMode::Random => 0x042, // O_RDWR | O_CREAT
         }
     }
 }
@@ -209,7 +210,7 @@ impl FileManager {
     }
 
     pub fn close_all(&mut self) -> Result<()> {
-        let file_nums: alloc::vec::Vec<i32> = self.handles.keys().copied().collect();
+        let file_nums: Vec<i32> = self.handles.keys().copied().collect();
         for num in file_nums {
             self.close(num)?;
         }
@@ -275,9 +276,12 @@ impl FileManager {
         if let Some(handle) = self.handles.get_mut(&file_num) {
             if let Some(ref mut reader) = handle.reader {
                 let mut line = String::new();
-                reader
+                let bytes_read = reader
                     .read_line(&mut line)
                     .map_err(|e| Error::IoError(format!("Error reading from file: {}", e)))?;
+                if bytes_read == 0 {
+                    return Err(Error::IoError("End of file".into()));
+                }
                 Ok(line.trim_end().to_string())
             } else {
                 Err(Error::RuntimeError(format!(
@@ -414,7 +418,7 @@ impl Default for FileManager {
 // WATOS file syscalls
 #[cfg(not(feature = "std"))]
 extern "C" {
-    fn watos_file_open(path: *const u8, path_len: usize, mode: u8) -> u64;
+    fn watos_file_open(path: *const u8, path_len: usize, mode: u64) -> u64;
     fn watos_file_close(handle: u64);
     fn watos_file_read(handle: u64, buf: *mut u8, len: usize) -> usize;
     fn watos_file_write(handle: u64, buf: *const u8, len: usize) -> usize;
@@ -4,11 +4,13 @@
 //! for both std (host) and no_std (WATOS) environments.
 
 #[cfg(not(feature = "std"))]
-use alloc::{string::String, format, string::ToString};
+use alloc::{string::String, vec::Vec, format, string::ToString};
 
 #[cfg(feature = "std")]
 use std::cell::RefCell;
 
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use crate::error::{Error, Result};
 use crate::value::Value;
 
@@ -153,7 +155,7 @@ pub fn left_fn(s: Value, n: Value) -> Result<Value> {
 pub fn right_fn(s: Value, n: Value) -> Result<Value> {
     let string = s.as_string();
     let count = n.as_integer()? as usize;
-    let chars: alloc::vec::Vec<char> = string.chars().collect();
+    let chars: Vec<char> = string.chars().collect();
     let start = if count > chars.len() { 0 } else { chars.len() - count };
     Ok(Value::String(chars[start..].iter().collect()))
 }
@@ -161,7 +163,7 @@ pub fn right_fn(s: Value, n: Value) -> Result<Value> {
 pub fn mid_fn(s: Value, start: Value, len: Option<Value>) -> Result<Value> {
     let string = s.as_string();
     let start_pos = (start.as_integer()? - 1).max(0) as usize;
-    let chars: alloc::vec::Vec<char> = string.chars().collect();
+    let chars: Vec<char> = string.chars().collect();
 
     if start_pos >= chars.len() {
         return Ok(Value::String(String::new()));
@@ -598,12 +600,58 @@ pub fn ioctl_fn(_file_num: Value) -> Result<Value> {
 }
 
 /// Joystick functions
-pub fn stick_fn(_val: Value) -> Result<Value> {
-    Ok(Value::Integer(0))
+///
+/// Read from gamepad 0: axes 0/1 and buttons 0/1 are stick A, axes 2/3 and
+/// buttons 2/3 stick B. Without a joystick the sticks are centered and the
+/// triggers up.
+fn read_joystick() -> (u32, [i16; 4]) {
+    #[cfg(not(feature = "std"))]
+    {
+        let mut axes = [0i16; 4];
+        let buttons = unsafe { watos_get_joystick(axes.as_mut_ptr()) };
+        (buttons, axes)
+    }
+
+    #[cfg(feature = "std")]
+    (0, [0; 4])
 }
 
-pub fn strig_fn(_val: Value) -> Result<Value> {
-    Ok(Value::Integer(0))
+/// Triggers pressed since STRIG last looked at them
+static STRIG_LATCH: AtomicU32 = AtomicU32::new(0);
+
+/// Axis position (-32767..32767) as STICK reports it, 1..200 with 100 centered
+fn stick_position(axis: i16) -> i32 {
+    (axis as i32 + 32767) * 199 / 65534 + 1
+}
+
+/// STICK(0)/STICK(1): stick A x/y; STICK(2)/STICK(3): stick B x/y
+pub fn stick_fn(val: Value) -> Result<Value> {
+    let n = val.as_integer()?;
+    if !(0..=3).contains(&n) {
+        return Err(Error::RuntimeError("Illegal function call".into()));
+    }
+    let (buttons, axes) = read_joystick();
+    STRIG_LATCH.fetch_or(buttons, Ordering::Relaxed);
+    Ok(Value::Integer(stick_position(axes[n as usize])))
+}
+
+/// STRIG(n): -1 if a trigger is down (odd n) or was pressed since the last
+/// STRIG(n) (even n), else 0. n = 0/1 A1, 2/3 B1, 4/5 A2, 6/7 B2.
+pub fn strig_fn(val: Value) -> Result<Value> {
+    let n = val.as_integer()?;
+    if !(0..=7).contains(&n) {
+        return Err(Error::RuntimeError("Illegal function call".into()));
+    }
+    let (buttons, _) = read_joystick();
+    let bit = 1u32 << [0, 2, 1, 3][n as usize / 2];
+    let latched = STRIG_LATCH.fetch_or(buttons, Ordering::Relaxed) | buttons;
+    let pressed = if n % 2 == 1 {
+        buttons & bit != 0
+    } else {
+        STRIG_LATCH.fetch_and(!bit, Ordering::Relaxed);
+        latched & bit != 0
+    };
+    Ok(Value::Integer(if pressed { -1 } else { 0 }))
 }
 
 /// File I/O functions
@@ -689,6 +737,7 @@ extern "C" {
     fn watos_get_cursor_row() -> u8;
     fn watos_get_cursor_col() -> u8;
     fn watos_get_pixel(x: i32, y: i32) -> u8;
+    fn watos_get_joystick(axes: *mut i16) -> u32;
 }
 
 #[cfg(test)]
@@ -722,6 +771,18 @@ mod tests {
         assert_eq!(ucase_fn(Value::String("hello".into())).unwrap().as_string(), "HELLO");
     }
 
+    #[test]
+    fn test_joystick_functions() {
+        assert_eq!(stick_position(-32767), 1);
+        assert_eq!(stick_position(0), 100);
+        assert_eq!(stick_position(32767), 200);
+        // No joystick on the host: centered, triggers up
+        assert_eq!(stick_fn(Value::Integer(1)).unwrap().as_integer().unwrap(), 100);
+        assert_eq!(strig_fn(Value::Integer(0)).unwrap().as_integer().unwrap(), 0);
+        assert!(stick_fn(Value::Integer(4)).is_err());
+        assert!(strig_fn(Value::Integer(8)).is_err());
+    }
+
     #[test]
     fn test_usr_function() {
         assert_eq!(usr_fn(None, Value::Integer(100)).unwrap().as_integer().unwrap(), 0);
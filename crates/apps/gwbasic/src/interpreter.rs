@@ -11,7 +11,8 @@ use std::collections::HashMap;
 use std::io::{self, Write};
 
 use crate::error::{Error, Result};
-use crate::parser::{AstNode, BinaryOperator, UnaryOperator};
+use crate::lexer::Lexer;
+use crate::parser::{AstNode, BinaryOperator, Parser, UnaryOperator};
 use crate::value::Value;
 use crate::graphics::Screen;
 #[cfg(feature = "host")]
@@ -39,6 +40,9 @@ pub struct Interpreter {
     /// Program lines indexed by line number
     lines: HashMap<u32, Vec<AstNode>>,
 
+    /// Source text of each program line (after the line number), for LIST and SAVE
+    sources: HashMap<u32, String>,
+
     /// Current execution position
     current_line: Option<u32>,
 
@@ -87,6 +91,7 @@ impl Interpreter {
             arrays: HashMap::new(),
             array_dims: HashMap::new(),
             lines: HashMap::new(),
+            sources: HashMap::new(),
             current_line: None,
             call_stack: Vec::new(),
             for_stack: Vec::new(),
@@ -110,6 +115,7 @@ impl Interpreter {
             arrays: HashMap::new(),
             array_dims: HashMap::new(),
             lines: HashMap::new(),
+            sources: HashMap::new(),
             current_line: None,
             call_stack: Vec::new(),
             for_stack: Vec::new(),
@@ -136,6 +142,7 @@ impl Interpreter {
             arrays: HashMap::new(),
             array_dims: HashMap::new(),
             lines: HashMap::new(),
+            sources: HashMap::new(),
             current_line: None,
             call_stack: Vec::new(),
             for_stack: Vec::new(),
@@ -162,6 +169,7 @@ impl Interpreter {
             arrays: HashMap::new(),
             array_dims: HashMap::new(),
             lines: HashMap::new(),
+            sources: HashMap::new(),
             current_line: None,
             call_stack: Vec::new(),
             for_stack: Vec::new(),
@@ -189,8 +197,124 @@ impl Interpreter {
         Ok(())
     }
 
+    /// Handle one line typed in immediate mode
+    ///
+    /// A line starting with a number is stored in (or, if it has no
+    /// statements, deleted from) the program; anything else runs right away.
+    pub fn enter_line(&mut self, input: &str) -> Result<()> {
+        let input = input.trim();
+        if input.starts_with(|c: char| c.is_ascii_digit()) {
+            return self.store_program_line(input);
+        }
+
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize()?;
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse()?;
+        self.execute(ast)
+    }
+
+    /// Parse `NUM statements` and store it as a program line
+    fn store_program_line(&mut self, text: &str) -> Result<()> {
+        let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let line_num: u32 = text[..digits]
+            .parse()
+            .map_err(|_| Error::SyntaxError(format!("Bad line number: {}", &text[..digits])))?;
+        let body = text[digits..].trim();
+
+        if body.is_empty() {
+            self.lines.remove(&line_num);
+            self.sources.remove(&line_num);
+            return Ok(());
+        }
+
+        let mut lexer = Lexer::new(text);
+        let tokens = lexer.tokenize()?;
+        let mut parser = Parser::new(tokens);
+        match parser.parse()? {
+            AstNode::Program(mut nodes) if nodes.len() == 1 => match nodes.pop() {
+                Some(AstNode::Line(num, statements)) => {
+                    self.lines.insert(num, statements);
+                    self.sources.insert(num, body.to_string());
+                    Ok(())
+                }
+                _ => Err(Error::SyntaxError(format!("Bad program line: {}", text))),
+            },
+            _ => Err(Error::SyntaxError(format!("Bad program line: {}", text))),
+        }
+    }
+
+    /// Add every numbered line of a program listing; returns how many were stored
+    pub fn load_source(&mut self, text: &str) -> Result<usize> {
+        let mut count = 0;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            self.store_program_line(line)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Read a program from a file into memory, replacing it unless `merge` is set
+    fn load_program(&mut self, filename: &str, merge: bool) -> Result<usize> {
+        let file_num = 1; // Use temporary file handle
+        self.file_manager.open(file_num, filename, FileMode::Input)?;
+
+        let mut text = String::new();
+        loop {
+            match self.file_manager.read_line(file_num) {
+                Ok(line) => {
+                    // Blank lines are skipped, so an empty read only ends the file at EOF
+                    if line.is_empty() && self.file_manager.eof(file_num).unwrap_or(true) {
+                        break;
+                    }
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+                Err(_) => break,
+            }
+        }
+        let _ = self.file_manager.close(file_num);
+
+        if !merge {
+            self.lines.clear();
+            self.sources.clear();
+        }
+        self.load_source(&text)
+    }
+
+    /// Program listing as `NUM source` lines in line-number order
+    fn program_listing(&self, start: Option<u32>, end: Option<u32>) -> Vec<String> {
+        let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
+        line_nums.sort();
+
+        line_nums
+            .into_iter()
+            .filter(|&n| start.map_or(true, |s| n >= s) && end.map_or(true, |e| n <= e))
+            .map(|n| match self.sources.get(&n) {
+                Some(src) => format!("{} {}", n, src),
+                None => {
+                    // Lines loaded from a whole-file parse have no source text
+                    let mut text = format!("{}", n);
+                    for stmt in &self.lines[&n] {
+                        text.push_str(&format!(" {:?}", stmt));
+                    }
+                    text
+                }
+            })
+            .collect()
+    }
+
     /// Run the stored line-numbered program
     pub fn run_stored_program(&mut self) -> Result<()> {
+        self.run_program_from(None)
+    }
+
+    /// Run the stored program from `start_line` (or the first line)
+    fn run_program_from(&mut self, start_line: Option<u32>) -> Result<()> {
         // Get sorted line numbers
         let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
         if line_nums.is_empty() {
@@ -199,6 +323,13 @@ impl Interpreter {
         }
         line_nums.sort();
 
+        // A fresh run re-reads DATA from the start
+        self.data_items.clear();
+        self.data_pointer = 0;
+        self.for_stack.clear();
+        self.while_stack.clear();
+        self.call_stack.clear();
+
         // Pre-process DATA statements - collect them first to avoid borrow issues
         let mut data_nodes = Vec::new();
         for &line_num in &line_nums {
@@ -217,8 +348,14 @@ impl Interpreter {
             self.data_items.push(val);
         }
 
-        // Start at first line
-        self.current_line = Some(line_nums[0]);
+        // Start at the requested line, or the first one
+        match start_line {
+            Some(line) if !self.lines.contains_key(&line) => {
+                return Err(Error::LineNumberError(format!("Line {} not found", line)));
+            }
+            Some(line) => self.current_line = Some(line),
+            None => self.current_line = Some(line_nums[0]),
+        }
 
         // Execute line by line
         while let Some(current) = self.current_line {
@@ -582,33 +719,14 @@ impl Interpreter {
             
             // Program Control
             AstNode::List(start, end) => {
-                let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
-                line_nums.sort();
-                
-                for line_num in line_nums {
-                    if let Some(start_line) = start {
-                        if line_num < start_line {
-                            continue;
-                        }
-                    }
-                    if let Some(end_line) = end {
-                        if line_num > end_line {
-                            break;
-                        }
-                    }
-                    
-                    if let Some(statements) = self.lines.get(&line_num) {
-                        console_print!("{} ", line_num);
-                        for stmt in statements {
-                            console_print!("{:?} ", stmt);
-                        }
-                        console_println!();
-                    }
+                for line in self.program_listing(start, end) {
+                    console_println!("{}", line);
                 }
                 Ok(())
             }
             AstNode::New => {
                 self.lines.clear();
+                self.sources.clear();
                 self.variables.clear();
                 self.for_stack.clear();
                 self.call_stack.clear();
@@ -616,19 +734,7 @@ impl Interpreter {
                 self.data_pointer = 0;
                 Ok(())
             }
-            AstNode::Run(start_line) => {
-                self.current_line = start_line.or_else(|| {
-                    let mut nums: Vec<u32> = self.lines.keys().copied().collect();
-                    nums.sort();
-                    nums.first().copied()
-                });
-                
-                if let Some(line) = self.current_line {
-                    self.execute_goto(line)
-                } else {
-                    Ok(())
-                }
-            }
+            AstNode::Run(start_line) => self.run_program_from(start_line),
             
             // Error Handling
             AstNode::OnError(_line) => {
@@ -703,55 +809,19 @@ impl Interpreter {
             // Program management
             AstNode::Load(filename) => {
                 // Load program from file
-                // Clear current program
-                self.lines.clear();
-                
-                // Open and read file
-                let file_num = 1; // Use temporary file handle
-                match self.file_manager.open(file_num, &filename, FileMode::Input) {
-                    Ok(_) => {
-                        // Read all lines and parse them
-                        loop {
-                            match self.file_manager.read_line(file_num) {
-                                Ok(line) => {
-                                    if line.is_empty() {
-                                        break; // End of file
-                                    }
-                                    // Parse and add line to program
-                                    // Format: "line_number statements"
-                                    if let Some(first_space) = line.find(' ') {
-                                        if let Ok(line_num) = line[..first_space].parse::<u32>() {
-                                            // Re-parse the line content
-                                            // For simplicity, just store as a dummy REM
-                                            self.lines.insert(line_num, vec![AstNode::Rem(line[first_space+1..].to_string())]);
-                                        }
-                                    }
-                                }
-                                Err(_) => break,
-                            }
-                        }
-                        let _ = self.file_manager.close(file_num);
-                        console_println!("Program loaded from {}", filename);
-                    }
-                    Err(_) => {
-                        console_println!("Error: Could not load {}", filename);
-                    }
+                match self.load_program(&filename, false) {
+                    Ok(count) => console_println!("Loaded {} lines from {}", count, filename),
+                    Err(e) => console_println!("Error: Could not load {}: {}", filename, e),
                 }
                 Ok(())
             }
             AstNode::Save(filename) => {
-                // Save program to file
+                // Save program to file as plain text, one numbered line per row
                 let file_num = 1; // Use temporary file handle
                 match self.file_manager.open(file_num, &filename, FileMode::Output) {
                     Ok(_) => {
-                        // Get sorted line numbers
-                        let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
-                        line_nums.sort();
-                        
-                        // Write each line
-                        for line_num in line_nums {
-                            let line_text = format!("{} REM saved line", line_num);
-                            let _ = self.file_manager.write_line(file_num, &line_text);
+                        for line in self.program_listing(None, None) {
+                            let _ = self.file_manager.write_line(file_num, &line);
                         }
                         let _ = self.file_manager.close(file_num);
                         console_println!("Program saved to {}", filename);
@@ -764,71 +834,21 @@ impl Interpreter {
             }
             AstNode::Merge(filename) => {
                 // Merge program from file - add lines without clearing existing
-                let file_num = 1;
-                match self.file_manager.open(file_num, &filename, FileMode::Input) {
-                    Ok(_) => {
-                        loop {
-                            match self.file_manager.read_line(file_num) {
-                                Ok(line) => {
-                                    if line.is_empty() {
-                                        break;
-                                    }
-                                    // Parse and merge line (simple version)
-                                    if let Some(first_space) = line.find(' ') {
-                                        if let Ok(line_num) = line[..first_space].parse::<u32>() {
-                                            self.lines.insert(line_num, vec![AstNode::Rem(line[first_space+1..].to_string())]);
-                                        }
-                                    }
-                                }
-                                Err(_) => break,
-                            }
-                        }
-                        let _ = self.file_manager.close(file_num);
-                        console_println!("Program merged from {}", filename);
-                    }
-                    Err(_) => {
-                        console_println!("Error: Could not merge {}", filename);
-                    }
+                match self.load_program(&filename, true) {
+                    Ok(count) => console_println!("Merged {} lines from {}", count, filename),
+                    Err(e) => console_println!("Error: Could not merge {}: {}", filename, e),
                 }
                 Ok(())
             }
             AstNode::Chain(filename, start_line) => {
-                // Chain to another program - load and optionally jump to line
-                // First, load the program
-                self.lines.clear();
-                let file_num = 1;
-                match self.file_manager.open(file_num, &filename, FileMode::Input) {
-                    Ok(_) => {
-                        loop {
-                            match self.file_manager.read_line(file_num) {
-                                Ok(line) => {
-                                    if line.is_empty() {
-                                        break;
-                                    }
-                                    if let Some(first_space) = line.find(' ') {
-                                        if let Ok(line_num) = line[..first_space].parse::<u32>() {
-                                            self.lines.insert(line_num, vec![AstNode::Rem(line[first_space+1..].to_string())]);
-                                        }
-                                    }
-                                }
-                                Err(_) => break,
-                            }
-                        }
-                        let _ = self.file_manager.close(file_num);
-                        
-                        // Start execution at specified line or first line
-                        if let Some(line) = start_line {
-                            self.current_line = Some(line);
-                        } else {
-                            self.current_line = self.lines.keys().min().copied();
-                        }
-                        console_println!("Chained to {}", filename);
-                    }
-                    Err(_) => {
-                        console_println!("Error: Could not chain to {}", filename);
+                // Chain to another program - load it and run from the given line
+                match self.load_program(&filename, false) {
+                    Ok(_) => self.run_program_from(start_line),
+                    Err(e) => {
+                        console_println!("Error: Could not chain to {}: {}", filename, e);
+                        Ok(())
                     }
                 }
-                Ok(())
             }
             AstNode::Cont => {
                 // Continue execution from where it stopped
@@ -2275,4 +2295,47 @@ mod tests {
         // X = 1, so should call 100, then return and set Y = 99
         assert_eq!(interp.variables.get("Y").unwrap().as_integer().unwrap(), 99);
     }
+
+    #[test]
+    fn test_enter_line_stores_source() {
+        let mut interp = Interpreter::new();
+        interp.enter_line("20 PRINT \"B\"").unwrap();
+        interp.enter_line("10 LET A = 1").unwrap();
+        assert_eq!(
+            interp.program_listing(None, None),
+            vec!["10 LET A = 1".to_string(), "20 PRINT \"B\"".to_string()]
+        );
+
+        // A bare line number deletes the line
+        interp.enter_line("20").unwrap();
+        assert_eq!(interp.program_listing(None, None), vec!["10 LET A = 1".to_string()]);
+    }
+
+    #[test]
+    fn test_run_statement_runs_program() {
+        let mut interp = Interpreter::new();
+        interp.load_source("10 LET A = 5\n\n20 LET B = A * 2\n").unwrap();
+        interp.enter_line("RUN").unwrap();
+        assert_eq!(interp.variables.get("B").unwrap().as_integer().unwrap(), 10);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir().join("gwbasic_roundtrip_test.bas");
+        let path = path.to_str().unwrap();
+
+        let mut interp = Interpreter::new();
+        interp.enter_line("10 LET X = 3").unwrap();
+        interp.enter_line("20 LET Y = X + 4").unwrap();
+        interp.enter_line(&format!("SAVE \"{}\"", path)).unwrap();
+
+        let mut loaded = Interpreter::new();
+        loaded.enter_line(&format!("LOAD \"{}\"", path)).unwrap();
+        assert_eq!(loaded.program_listing(None, None), interp.program_listing(None, None));
+        loaded.enter_line("RUN").unwrap();
+        assert_eq!(loaded.variables.get("Y").unwrap().as_integer().unwrap(), 7);
+
+        let _ = std::fs::remove_file(path);
+    }
 }
\ No newline at end of file
@@ -53,27 +53,9 @@ fn main() {
             break;
         }
 
-        // Try to tokenize, parse, and execute
-        let mut lexer = Lexer::new(input);
-        let tokens = match lexer.tokenize() {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("Lexer error: {}", e);
-                continue;
-            }
-        };
-
-        let mut parser = Parser::new(tokens);
-        let ast = match parser.parse() {
-            Ok(a) => a,
-            Err(e) => {
-                eprintln!("Parser error: {}", e);
-                continue;
-            }
-        };
-
-        if let Err(e) = interpreter.execute(ast) {
-            eprintln!("Runtime error: {}", e);
+        // Numbered lines are stored in the program, anything else runs now
+        if let Err(e) = interpreter.enter_line(input) {
+            eprintln!("Error: {}", e);
         }
     }
 
@@ -254,6 +254,17 @@ impl Parser {
         Ok(statements)
     }
 
+    /// Quoted filename for LOAD/SAVE/MERGE/CHAIN
+    fn parse_filename(&mut self, statement: &str) -> Result<String> {
+        if let TokenType::String(s) = &self.current_token().token_type {
+            let name = s.clone();
+            self.advance();
+            Ok(name)
+        } else {
+            Err(Error::SyntaxError(format!("Expected filename string after {}", statement)))
+        }
+    }
+
     fn parse_statement(&mut self) -> Result<AstNode> {
         match &self.current_token().token_type {
             // Basic I/O
@@ -310,6 +321,34 @@ impl Parser {
                 self.advance();
                 Ok(AstNode::New)
             }
+            TokenType::Load => {
+                self.advance();
+                Ok(AstNode::Load(self.parse_filename("LOAD")?))
+            }
+            TokenType::Save => {
+                self.advance();
+                Ok(AstNode::Save(self.parse_filename("SAVE")?))
+            }
+            TokenType::Merge => {
+                self.advance();
+                Ok(AstNode::Merge(self.parse_filename("MERGE")?))
+            }
+            TokenType::Chain => {
+                self.advance();
+                let filename = self.parse_filename("CHAIN")?;
+                let start_line = if let TokenType::Comma = self.current_token().token_type {
+                    self.advance();
+                    if let TokenType::Integer(n) = self.current_token().token_type {
+                        self.advance();
+                        Some(n as u32)
+                    } else {
+                        return Err(Error::SyntaxError("Expected line number after CHAIN filename".to_string()));
+                    }
+                } else {
+                    None
+                };
+                Ok(AstNode::Chain(filename, start_line))
+            }
             TokenType::Run => {
                 self.advance();
                 let start_line = if let TokenType::Integer(n) = self.current_token().token_type {
@@ -12,6 +12,7 @@ use alloc::collections::BTreeMap;
 // Use shared WATOS syscall interface  
 #[cfg(feature = "watos")]
 use watos_syscall::{numbers as syscall, raw_syscall0, raw_syscall1, raw_syscall2, raw_syscall3};
+use watos_syscall::open::{O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
 
 // Add console syscall numbers if not in shared interface
 #[cfg(feature = "watos")]
@@ -251,11 +252,11 @@ impl Default for WatosFileSystem {
 impl FileSystem for WatosFileSystem {
     fn open(&mut self, path: &str, mode: FileOpenMode) -> Result<FileHandle, &'static str> {
         let mode_num = match mode {
-            FileOpenMode::Input => 0,
-            FileOpenMode::Output => 1,
-            FileOpenMode::Append => 2,
-            FileOpenMode::Random => 3,
-        };
+            FileOpenMode::Input => O_RDONLY,
+            FileOpenMode::Output => O_WRONLY | O_CREAT | O_TRUNC,
+            FileOpenMode::Append => O_WRONLY | O_CREAT | O_APPEND,
+            FileOpenMode::Random => O_RDWR | O_CREAT,
+        } as u64;
         let bytes = path.as_bytes();
         let watos_handle = unsafe {
             syscall3(syscall::SYS_OPEN, bytes.as_ptr() as u64, bytes.len() as u64, mode_num)
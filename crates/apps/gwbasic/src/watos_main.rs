@@ -11,7 +11,7 @@ extern crate alloc;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::panic::PanicInfo;
-use rust_gwbasic::{Lexer, Parser, Interpreter};
+use rust_gwbasic::Interpreter;
 use rust_gwbasic::platform::{WatosConsole, Console};
 use rust_gwbasic::functions::{WatosDate, WatosTime};
 
@@ -38,6 +38,17 @@ fn main() {
 
     let mut interpreter = Interpreter::new();
 
+    // `gwbasic FILE` loads the program and runs it before dropping to the prompt
+    let mut args_buf = [0u8; 256];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+    if let Some(file) = args.split_whitespace().nth(1) {
+        let command = alloc::format!("LOAD \"{}\"", file);
+        if let Err(e) = interpreter.enter_line(&command).and_then(|_| interpreter.enter_line("RUN")) {
+            console.print(&alloc::format!("Error: {}\n", e));
+        }
+    }
+
     loop {
         console.print("> ");
 
@@ -52,37 +63,31 @@ fn main() {
             break;
         }
 
-        // Try to tokenize, parse, and execute
-        let mut lexer = Lexer::new(input);
-        let tokens = match lexer.tokenize() {
-            Ok(t) => t,
-            Err(e) => {
-                console.print("Lexer error: ");
-                // Note: Display trait not available in no_std, using debug
-                console.print(&alloc::format!("{:?}\n", e));
-                continue;
-            }
-        };
-
-        let mut parser = Parser::new(tokens);
-        let ast = match parser.parse() {
-            Ok(a) => a,
-            Err(e) => {
-                console.print("Parser error: ");
-                console.print(&alloc::format!("{:?}\n", e));
-                continue;
-            }
-        };
-
-        if let Err(e) = interpreter.execute(ast) {
-            console.print("Runtime error: ");
-            console.print(&alloc::format!("{:?}\n", e));
+        // Numbered lines are stored in the program, anything else runs now
+        if let Err(e) = interpreter.enter_line(input) {
+            console.print(&alloc::format!("Error: {}\n", e));
         }
     }
 
     console.print("Goodbye!\n");
 }
 
+/// Get the command line (program name first)
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") 83u32,    // SYS_GETARGS
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
 /// Exit the program
 fn exit(code: i32) -> ! {
     rust_gwbasic::platform::exit(code);
@@ -387,6 +392,15 @@ pub extern "C" fn watos_get_key_no_wait() -> u8 {
     }
 }
 
+/// Read gamepad 0 for STICK/STRIG: fills axes 0-3, returns the buttons
+/// (all zero without a joystick)
+#[no_mangle]
+pub extern "C" fn watos_get_joystick(axes: *mut i16) -> u32 {
+    let info = watos_syscall::syscalls::gamepad_state(0).unwrap_or_default();
+    unsafe { core::ptr::copy_nonoverlapping(info.axes.as_ptr(), axes, 4) };
+    info.buttons
+}
+
 /// Get pixel at position
 #[no_mangle]
 pub extern "C" fn watos_get_pixel(x: i32, y: i32) -> u8 {
@@ -0,0 +1,116 @@
+//! WATOS head command - print the first lines of files
+//!
+//! Usage: head [-n N] [FILE...]
+//!
+//! Prints the first N lines (10 by default) of each FILE, or of standard
+//! input if there is none or FILE is -. With several files each gets a
+//! `==> FILE <==` header. Exits as soon as it has its lines, so a writer
+//! feeding it a pipe, as in `yes | head`, finds the pipe closed and stops.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, open, syscalls};
+
+/// Lines printed when -n isn't given
+const DEFAULT_LINES: usize = 10;
+
+/// Most FILE arguments taken
+const MAX_FILES: usize = 32;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_err(s: &str) {
+    syscalls::write(2, s.as_bytes());
+}
+
+fn usage() -> ! {
+    write_err("Usage: head [-n N] [FILE...]\r\n");
+    syscalls::exit(2)
+}
+
+/// Copy the first `lines` lines of `fd` to stdout
+fn head(fd: i32, lines: usize) {
+    let mut buf = [0u8; 4096];
+    let mut left = lines;
+    while left > 0 {
+        let n = syscalls::read(fd, &mut buf);
+        if n == 0 || n > buf.len() {
+            return;
+        }
+        let mut end = n;
+        for (i, &b) in buf[..n].iter().enumerate() {
+            if b == b'\n' {
+                left -= 1;
+                if left == 0 {
+                    end = i + 1;
+                    break;
+                }
+            }
+        }
+        syscalls::write(1, &buf[..end]);
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; argv::MAX_BYTES];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+
+    let mut lines = DEFAULT_LINES;
+    let mut files: [&str; MAX_FILES] = [""; MAX_FILES];
+    let mut count = 0;
+
+    let mut args = argv::decode(&args_buf[..args_len]).skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg {
+            "-n" => Some(args.next().unwrap_or_else(|| usage())),
+            _ => arg.strip_prefix("-n").filter(|v| !v.is_empty()),
+        };
+        if let Some(value) = value {
+            lines = value.parse().unwrap_or_else(|_| usage());
+        } else if arg.starts_with('-') && arg != "-" {
+            usage();
+        } else if count < MAX_FILES {
+            files[count] = arg;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        files[0] = "-";
+        count = 1;
+    }
+
+    let mut status = 0;
+    for (i, &path) in files[..count].iter().enumerate() {
+        if count > 1 {
+            write_str(if i > 0 { "\r\n==> " } else { "==> " });
+            write_str(if path == "-" { "standard input" } else { path });
+            write_str(" <==\r\n");
+        }
+        if path == "-" {
+            head(0, lines);
+            continue;
+        }
+        let fd = syscalls::open(path, open::O_RDONLY);
+        if fd < 0 {
+            write_err("head: cannot open ");
+            write_err(path);
+            write_err("\r\n");
+            status = 1;
+            continue;
+        }
+        head(fd, lines);
+        syscalls::close(fd);
+    }
+
+    syscalls::exit(status)
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1)
+}
@@ -0,0 +1,400 @@
+//! WATOS hexdump command - display file contents in hexadecimal
+//!
+//! Usage: hexdump [OPTIONS] [FILE...]
+//!
+//! Options:
+//!   -C        Canonical hex+ASCII display (default)
+//!   -x        Two-byte hexadecimal words
+//!   -n LEN    Interpret only LEN bytes of input
+//!   -s OFF    Skip OFF bytes from the beginning of the input
+//!
+//! With no FILE, or when FILE is -, read standard input.
+//! Numbers may be given in decimal or as 0x-prefixed hex.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+
+#[inline(always)]
+unsafe fn syscall2(num: u32, arg1: u64, arg2: u64) -> u64 {
+    let ret: u64;
+    core::arch::asm!(
+        "int 0x80",
+        in("eax") num,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        lateout("rax") ret,
+        options(nostack)
+    );
+    ret
+}
+
+#[inline(always)]
+unsafe fn syscall3(num: u32, arg1: u64, arg2: u64, arg3: u64) -> u64 {
+    let ret: u64;
+    core::arch::asm!(
+        "int 0x80",
+        in("eax") num,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        in("rdx") arg3,
+        lateout("rax") ret,
+        options(nostack)
+    );
+    ret
+}
+
+// Output goes through libc-lite's buffered stdout: two writes per line
+// would otherwise be two syscalls per line
+use watos_libc_lite::{exit, stdio};
+
+fn write_str(s: &str) {
+    stdio::print(s);
+}
+
+fn write_bytes(b: &[u8]) {
+    stdio::write(b);
+}
+
+fn write_err(s: &str) {
+    stdio::eprint(s);
+}
+
+fn write_err_bytes(b: &[u8]) {
+    stdio::write_err(b);
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe { syscall2(syscall::SYS_GETARGS, buf.as_mut_ptr() as u64, buf.len() as u64) as usize }
+}
+
+fn open(path: &[u8], flags: u32) -> i64 {
+    unsafe {
+        syscall3(
+            syscall::SYS_OPEN,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            flags as u64,
+        ) as i64
+    }
+}
+
+fn read(fd: u64, buf: &mut [u8]) -> i64 {
+    unsafe {
+        syscall3(
+            syscall::SYS_READ,
+            fd,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+        ) as i64
+    }
+}
+
+fn close(fd: u64) {
+    unsafe {
+        syscall2(syscall::SYS_CLOSE, fd, 0);
+    }
+}
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Canonical, // -C
+    Words,     // -x
+}
+
+struct Options {
+    format: Format,
+    length: Option<u64>, // -n
+    skip: u64,           // -s
+}
+
+impl Options {
+    fn new() -> Self {
+        Options {
+            format: Format::Canonical,
+            length: None,
+            skip: 0,
+        }
+    }
+}
+
+/// Dump state carried across files so offsets continue like one input stream
+struct Dumper {
+    offset: u64,
+    line: [u8; 16],
+    line_len: usize,
+    skipped: u64,
+    consumed: u64,
+    last_line: [u8; 16],
+    have_last: bool,
+    squeezing: bool,
+}
+
+impl Dumper {
+    fn new() -> Self {
+        Dumper {
+            offset: 0,
+            line: [0; 16],
+            line_len: 0,
+            skipped: 0,
+            consumed: 0,
+            last_line: [0; 16],
+            have_last: false,
+            squeezing: false,
+        }
+    }
+
+    /// Feed input bytes; returns false once the -n limit is reached
+    fn feed(&mut self, data: &[u8], opts: &Options) -> bool {
+        for &b in data {
+            if self.skipped < opts.skip {
+                self.skipped += 1;
+                self.offset += 1;
+                continue;
+            }
+            if let Some(limit) = opts.length {
+                if self.consumed >= limit {
+                    return false;
+                }
+            }
+            self.consumed += 1;
+            self.line[self.line_len] = b;
+            self.line_len += 1;
+            if self.line_len == 16 {
+                self.emit_line(opts);
+            }
+        }
+        match opts.length {
+            Some(limit) => self.consumed < limit,
+            None => true,
+        }
+    }
+
+    /// Print the buffered line, collapsing repeats into a single '*'
+    fn emit_line(&mut self, opts: &Options) {
+        let len = self.line_len;
+        if len == 16 && self.have_last && self.line == self.last_line {
+            if !self.squeezing {
+                write_str("*\r\n");
+                self.squeezing = true;
+            }
+        } else {
+            self.squeezing = false;
+            match opts.format {
+                Format::Canonical => print_canonical(self.offset, &self.line[..len]),
+                Format::Words => print_words(self.offset, &self.line[..len]),
+            }
+        }
+        if len == 16 {
+            self.last_line = self.line;
+            self.have_last = true;
+        }
+        self.offset += len as u64;
+        self.line_len = 0;
+    }
+
+    /// Flush any partial line and print the final offset
+    fn finish(&mut self, opts: &Options) {
+        if self.line_len > 0 {
+            self.emit_line(opts);
+        }
+        if self.consumed > 0 {
+            let mut buf = [0u8; 8];
+            format_hex(self.offset, &mut buf);
+            write_bytes(&buf);
+            write_str("\r\n");
+        }
+    }
+}
+
+fn format_hex(mut n: u64, buf: &mut [u8]) {
+    for i in (0..buf.len()).rev() {
+        buf[i] = HEX[(n & 0xF) as usize];
+        n >>= 4;
+    }
+}
+
+/// `00000010  48 65 6c 6c 6f 20 57 41  54 4f 53 0a              |Hello WATOS.|`
+fn print_canonical(offset: u64, bytes: &[u8]) {
+    let mut out = [b' '; 80];
+    format_hex(offset, &mut out[..8]);
+    let mut pos = 10;
+    for i in 0..16 {
+        if i == 8 {
+            pos += 1;
+        }
+        if i < bytes.len() {
+            out[pos] = HEX[(bytes[i] >> 4) as usize];
+            out[pos + 1] = HEX[(bytes[i] & 0xF) as usize];
+        }
+        pos += 3;
+    }
+    pos += 1;
+    out[pos] = b'|';
+    pos += 1;
+    for &b in bytes {
+        out[pos] = if (0x20..0x7F).contains(&b) { b } else { b'.' };
+        pos += 1;
+    }
+    out[pos] = b'|';
+    pos += 1;
+    write_bytes(&out[..pos]);
+    write_str("\r\n");
+}
+
+/// `0000010    6548    6c6c    206f    4157` (little-endian 16-bit words)
+fn print_words(offset: u64, bytes: &[u8]) {
+    let mut out = [b' '; 80];
+    format_hex(offset, &mut out[..7]);
+    let mut pos = 7;
+    for pair in bytes.chunks(2) {
+        let word = pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8;
+        pos += 4;
+        format_hex(word as u64, &mut out[pos..pos + 4]);
+        pos += 4;
+    }
+    write_bytes(&out[..pos]);
+    write_str("\r\n");
+}
+
+/// Parse a decimal or 0x-prefixed hex number
+fn parse_number(s: &[u8]) -> Option<u64> {
+    let (digits, radix) = if s.len() > 2 && (s.starts_with(b"0x") || s.starts_with(b"0X")) {
+        (&s[2..], 16)
+    } else {
+        (s, 10)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut n: u64 = 0;
+    for &c in digits {
+        let d = match c {
+            b'0'..=b'9' => (c - b'0') as u64,
+            b'a'..=b'f' if radix == 16 => (c - b'a' + 10) as u64,
+            b'A'..=b'F' if radix == 16 => (c - b'A' + 10) as u64,
+            _ => return None,
+        };
+        n = n.checked_mul(radix)?.checked_add(d)?;
+    }
+    Some(n)
+}
+
+/// Dump one input; returns false once the -n limit has been reached
+fn dump_fd(fd: u64, dumper: &mut Dumper, opts: &Options) -> bool {
+    static mut READ_BUF: [u8; 4096] = [0u8; 4096];
+    loop {
+        let n = unsafe { read(fd, &mut READ_BUF) };
+        if n <= 0 || n as usize > 4096 {
+            return true;
+        }
+        if !dumper.feed(unsafe { &READ_BUF[..n as usize] }, opts) {
+            return false;
+        }
+    }
+}
+
+fn usage() -> ! {
+    write_err("Usage: hexdump [-C | -x] [-n LEN] [-s OFFSET] [FILE...]\r\n");
+    exit(1);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    static mut ARGS_BUF: [u8; 1024] = [0u8; 1024];
+    static mut FILES: [(usize, usize); 16] = [(0, 0); 16];
+
+    let args_len = unsafe { get_args(&mut ARGS_BUF) };
+    let args = unsafe { &ARGS_BUF[..args_len] };
+
+    let mut opts = Options::new();
+    let mut file_count = 0;
+    let mut exit_code = 0;
+    let mut options_done = false;
+
+    // Split arguments on spaces, skipping the command name
+    let mut words = args.split(|&c| c == b' ').filter(|w| !w.is_empty()).skip(1);
+    while let Some(word) = words.next() {
+        if !options_done && word.len() > 1 && word[0] == b'-' {
+            match word {
+                b"--" => options_done = true,
+                b"-C" => opts.format = Format::Canonical,
+                b"-x" => opts.format = Format::Words,
+                b"-n" | b"-s" => {
+                    let value = match words.next().and_then(parse_number) {
+                        Some(v) => v,
+                        None => {
+                            write_err("hexdump: option ");
+                            write_err_bytes(word);
+                            write_err(" requires a numeric argument\r\n");
+                            usage();
+                        }
+                    };
+                    if word == b"-n" {
+                        opts.length = Some(value);
+                    } else {
+                        opts.skip = value;
+                    }
+                }
+                _ => {
+                    write_err("hexdump: invalid option '");
+                    write_err_bytes(word);
+                    write_err("'\r\n");
+                    usage();
+                }
+            }
+            continue;
+        }
+
+        if file_count >= 16 {
+            write_err("hexdump: too many files\r\n");
+            exit(1);
+        }
+        // Record the word's position within ARGS_BUF
+        let start = word.as_ptr() as usize - args.as_ptr() as usize;
+        unsafe { FILES[file_count] = (start, word.len()); }
+        file_count += 1;
+    }
+
+    let mut dumper = Dumper::new();
+
+    if file_count == 0 {
+        dump_fd(0, &mut dumper, &opts);
+    } else {
+        for f in 0..file_count {
+            let (start, len) = unsafe { FILES[f] };
+            let path = &args[start..start + len];
+
+            let keep_going = if path == b"-" {
+                dump_fd(0, &mut dumper, &opts)
+            } else {
+                let fd = open(path, 0); // O_RDONLY
+                if fd < 0 {
+                    write_err("hexdump: ");
+                    write_err_bytes(path);
+                    write_err(": No such file or directory\r\n");
+                    exit_code = 1;
+                    continue;
+                }
+                let more = dump_fd(fd as u64, &mut dumper, &opts);
+                close(fd as u64);
+                more
+            };
+            if !keep_going {
+                break;
+            }
+        }
+    }
+
+    dumper.finish(&opts);
+    exit(exit_code);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(1);
+}
@@ -0,0 +1,169 @@
+//! WATOS id command - print user and group IDs
+//!
+//! Usage: id [-u | -g | -G] [-n] [USER]
+//!
+//! Without options prints the user, primary group and supplementary
+//! groups of USER, or of this process:
+//!
+//!   uid=1000(guest) gid=100(users) groups=100(users),10(wheel)
+//!
+//! Options:
+//!   -u    Print only the user ID
+//!   -g    Print only the primary group ID
+//!   -G    Print all group IDs
+//!   -n    Print names instead of numbers (with -u, -g or -G)
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, syscalls};
+
+/// Most groups reported for one user
+const MAX_GROUPS: usize = 32;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_num(n: u32) {
+    let mut buf = [0u8; 10];
+    let mut i = buf.len();
+    let mut n = n;
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &buf[i..]);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Show {
+    All,
+    User,
+    Group,
+    Groups,
+}
+
+/// Print a user or group ID as a number, or its name if asked and known
+fn write_id(id: u32, name: Option<&str>, names: bool) {
+    match name {
+        Some(name) if names => write_str(name),
+        _ => write_num(id),
+    }
+}
+
+/// Print `id(name)`, or just the number if there is no such name
+fn write_id_name(id: u32, name: Option<&str>) {
+    write_num(id);
+    if let Some(name) = name {
+        write_str("(");
+        write_str(name);
+        write_str(")");
+    }
+}
+
+fn group_name(gid: u32, buf: &mut [u8]) -> Option<&str> {
+    syscalls::getgrgid(gid, buf).map(|g| g.name)
+}
+
+/// Supplementary groups of a named user, from the group database
+fn groups_of(user: &str, gids: &mut [u32; MAX_GROUPS]) -> usize {
+    let mut buf = [0u8; 1024];
+    let mut count = 0;
+    let mut index = 0;
+    while let Some(group) = syscalls::getgrent(index, &mut buf) {
+        if count < gids.len() && group.members().any(|m| m == user) {
+            gids[count] = group.gid;
+            count += 1;
+        }
+        index += 1;
+    }
+    count
+}
+
+fn usage() -> ! {
+    write_str("Usage: id [-u | -g | -G] [-n] [USER]\r\n");
+    syscalls::exit(2)
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 1024];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+
+    let mut show = Show::All;
+    let mut names = false;
+    let mut user: Option<&str> = None;
+    for arg in argv::decode(&args_buf[..args_len]).skip(1) {
+        match arg {
+            "-u" => show = Show::User,
+            "-g" => show = Show::Group,
+            "-G" => show = Show::Groups,
+            "-n" => names = true,
+            _ if arg.starts_with('-') || user.is_some() => usage(),
+            _ => user = Some(arg),
+        }
+    }
+
+    // Who: the named user from the database, or this process
+    let mut pw_buf = [0u8; 512];
+    let mut gids = [0u32; MAX_GROUPS];
+    let (uid, gid, name, ngroups) = match user {
+        Some(wanted) => match syscalls::getpwnam(wanted, &mut pw_buf) {
+            Some(pw) => (pw.uid, pw.gid, Some(pw.name), groups_of(pw.name, &mut gids)),
+            None => {
+                write_str("id: '");
+                write_str(wanted);
+                write_str("': no such user\r\n");
+                syscalls::exit(1);
+            }
+        },
+        None => {
+            let uid = syscalls::getuid();
+            let count = syscalls::getgroups(&mut gids).min(MAX_GROUPS);
+            let name = syscalls::getpwuid(uid, &mut pw_buf).map(|pw| pw.name);
+            (uid, syscalls::getgid(), name, count)
+        }
+    };
+    let groups = &gids[..ngroups];
+
+    let mut gr_buf = [0u8; 1024];
+    match show {
+        Show::User => write_id(uid, name, names),
+        Show::Group => write_id(gid, group_name(gid, &mut gr_buf), names),
+        Show::Groups => {
+            write_id(gid, group_name(gid, &mut gr_buf), names);
+            for &g in groups.iter().filter(|&&g| g != gid) {
+                write_str(" ");
+                write_id(g, group_name(g, &mut gr_buf), names);
+            }
+        }
+        Show::All => {
+            if names {
+                usage();
+            }
+            write_str("uid=");
+            write_id_name(uid, name);
+            write_str(" gid=");
+            write_id_name(gid, group_name(gid, &mut gr_buf));
+            write_str(" groups=");
+            write_id_name(gid, group_name(gid, &mut gr_buf));
+            for &g in groups.iter().filter(|&&g| g != gid) {
+                write_str(",");
+                write_id_name(g, group_name(g, &mut gr_buf));
+            }
+        }
+    }
+    write_str("\r\n");
+    syscalls::exit(0)
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1)
+}
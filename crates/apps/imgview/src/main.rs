@@ -0,0 +1,190 @@
+//! WATOS imgview - show a BMP or PNG image on the framebuffer
+//!
+//! Usage: imgview FILE
+//!
+//! The image is centered over a dark background, shrunk to fit the screen
+//! if needed, and alpha-blended so transparent areas show the background.
+//! The mouse cursor is shown over the image. Any key or a left click
+//! returns to the console, whose pixels are restored on exit.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_gfx::{color, Canvas, Rect, Surface};
+use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+/// Largest file read into memory
+const MAX_FILE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Backdrop behind the image
+const BACKGROUND: u32 = 0xFF20_2020;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn fail(path: &str, msg: &str) -> ! {
+    write_str("imgview: ");
+    write_str(path);
+    write_str(": ");
+    write_str(msg);
+    write_str("\r\n");
+    exit(1);
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+/// Read a whole file
+fn read_file(path: &str) -> Result<Vec<u8>, &'static str> {
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return Err("cannot open file");
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        let n = syscalls::read(fd, &mut buf);
+        if n == 0 || n > buf.len() {
+            break Ok(());
+        }
+        if data.len() + n > MAX_FILE_SIZE {
+            break Err("file is too large");
+        }
+        data.extend_from_slice(&buf[..n]);
+    };
+    syscalls::close(fd);
+    result.map(|_| data)
+}
+
+/// Nearest-neighbour downscale so the image fits within max_w x max_h
+fn fit(image: Surface, max_w: u32, max_h: u32) -> Surface {
+    let (w, h) = (image.width(), image.height());
+    if w <= max_w && h <= max_h {
+        return image;
+    }
+    // Scale by the tighter of the two ratios, keeping the aspect ratio
+    let (new_w, new_h) = if w as u64 * max_h as u64 > h as u64 * max_w as u64 {
+        (max_w, ((h as u64 * max_w as u64) / w as u64).max(1) as u32)
+    } else {
+        (((w as u64 * max_h as u64) / h as u64).max(1) as u32, max_h)
+    };
+
+    let src = image.pixels();
+    let mut pixels = Vec::with_capacity(new_w as usize * new_h as usize);
+    for y in 0..new_h {
+        let sy = (y as u64 * h as u64 / new_h as u64) as usize;
+        let row = &src[sy * w as usize..(sy + 1) * w as usize];
+        for x in 0..new_w {
+            pixels.push(row[(x as u64 * w as u64 / new_w as u64) as usize]);
+        }
+    }
+    Surface::from_pixels(pixels, new_w, new_h).unwrap_or(image)
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    // First word is the program name
+    let mut words = args.split_whitespace().skip(1);
+    let path = match (words.next(), words.next()) {
+        (Some(path), None) => path,
+        _ => {
+            write_str("Usage: imgview FILE\r\n");
+            exit(1);
+        }
+    };
+
+    let data = read_file(path).unwrap_or_else(|msg| fail(path, msg));
+    let image = watos_image::decode(&data).unwrap_or_else(|e| fail(path, e.as_str()));
+    drop(data);
+
+    let fb = syscalls::fb_map().unwrap_or_else(|| fail(path, "no framebuffer access"));
+    let (width, height, pitch) = syscalls::fb_dimensions();
+    if width == 0 || height == 0 {
+        fail(path, "no framebuffer");
+    }
+    let (orig_w, orig_h) = (image.width(), image.height());
+    let image = fit(image, width, height);
+
+    let mut canvas = unsafe { Canvas::from_raw(fb as *mut u32, width, height, pitch) };
+
+    // Keep the console's pixels to put back afterwards
+    let mut saved = Surface::new(width, height, color::BLACK);
+    saved.canvas().blit(&canvas.as_image(), canvas.bounds(), 0, 0);
+
+    canvas.clear(BACKGROUND);
+    let x = (width - image.width()) as i32 / 2;
+    let y = (height - image.height()) as i32 / 2;
+    canvas.blit_blend(&image.image(), image.bounds(), x, y);
+    // Thin frame so images with a dark edge stand out from the backdrop
+    canvas.draw_rect(Rect::new(x - 1, y - 1, image.width() + 2, image.height() + 2), 0x80FF_FFFF);
+
+    syscalls::cursor_show(true);
+    loop {
+        let (_, _, buttons) = syscalls::mouse_poll();
+        if buttons & 1 != 0 || syscalls::getkey() != 0 {
+            break;
+        }
+        syscalls::idle();
+    }
+    // Hide it first so its save-under copy does not land on the console
+    syscalls::cursor_show(false);
+
+    canvas.blit(&saved.image(), saved.bounds(), 0, 0);
+
+    write_str(&format!("{}: {}x{}\r\n", path, orig_w, orig_h));
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\r\nimgview: internal error\r\n");
+    exit(1);
+}
@@ -0,0 +1,170 @@
+//! WATOS klog - print the saved kernel log
+//!
+//! Usage: klog [-o]
+//!
+//! Decompresses the records of /var/log/kernel.log (see
+//! watos_syscall::klog) and prints the text. With -o, prints the previous
+//! log, kernel.old, instead. Anything that isn't a record, such as a log
+//! saved before compression, is printed as it is.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::{klog, syscalls};
+use wfs_common::core::compress;
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Output
+// ============================================================================
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+/// Write log text, turning each \n into \r\n for the console
+fn write_text(text: &[u8]) {
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(body) => {
+                syscalls::write(1, body.strip_suffix(b"\r").unwrap_or(body));
+                syscalls::write(1, b"\r\n");
+            }
+            None => {
+                syscalls::write(1, line);
+            }
+        }
+    }
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+// ============================================================================
+// Records
+// ============================================================================
+
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = syscalls::read(fd, &mut buf);
+        if n == 0 || n > buf.len() {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+    syscalls::close(fd);
+    Some(data)
+}
+
+fn read_u32(bytes: &[u8]) -> usize {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+}
+
+/// Print every record in `log`; false if one was damaged
+fn print_log(mut log: &[u8]) -> bool {
+    while !log.is_empty() {
+        if !log.starts_with(&klog::MAGIC) {
+            // Plain text up to the next record
+            let end = log.windows(klog::MAGIC.len()).position(|w| w == klog::MAGIC).unwrap_or(log.len());
+            write_text(&log[..end]);
+            log = &log[end..];
+            continue;
+        }
+
+        let Some(header) = log.get(..klog::HEADER_SIZE) else { return false };
+        let (packed, len) = (read_u32(&header[4..8]), read_u32(&header[8..12]));
+        let Some(end) = klog::HEADER_SIZE.checked_add(packed).filter(|&end| end <= log.len()) else {
+            return false;
+        };
+        match compress::decompress(&log[klog::HEADER_SIZE..end], len) {
+            Some(text) => write_text(&text),
+            None => return false,
+        }
+        log = &log[end..];
+    }
+    true
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 256];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    // First word is the program name
+    let mut path = klog::LOG_PATH;
+    for word in args.split_whitespace().skip(1) {
+        match word {
+            "-o" => path = klog::ROTATED_PATH,
+            _ => {
+                write_str("Usage: klog [-o]\r\n");
+                exit(1);
+            }
+        }
+    }
+
+    let Some(log) = read_file(path) else {
+        write_str("klog: cannot read ");
+        write_str(path);
+        write_str("\r\n");
+        exit(1);
+    };
+    if !print_log(&log) {
+        write_str("\r\nklog: damaged record, stopping\r\n");
+        exit(1);
+    }
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\r\nklog: internal error\r\n");
+    exit(1);
+}
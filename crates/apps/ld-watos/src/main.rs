@@ -0,0 +1,334 @@
+//! WATOS program interpreter - /lib/ld-watos
+//!
+//! SYS_EXEC starts this instead of a program whose PT_INTERP names it,
+//! with rsp pointing at the auxiliary vector. It:
+//!
+//! 1. Relocates itself (it is a static PIE loaded at an arbitrary address)
+//! 2. Loads the program's DT_NEEDED libraries from /lib and /apps/lib
+//! 3. Relocates everything and runs initializers, dependencies first
+//! 4. Jumps to the program's entry point with the stack it was given
+//!
+//! It stays resident afterwards: `dlopen`, `dlsym`, `dlclose` and `dlerror`
+//! are exported from here, and programs link against them like any other
+//! library symbol.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use spin::Mutex;
+use watos_ld::elf::{self, Dyn, Phdr, Rela};
+use watos_ld::{Host, Linker, Object, ERROR_MAX};
+use watos_syscall::auxv;
+use watos_syscall::syscalls;
+
+/// Exit status when the program can't be linked (as for a missing command)
+const LINK_FAILED: i32 = 127;
+
+/// dlopen flag: make the library's symbols visible to later lookups
+const RTLD_GLOBAL: i32 = 0x100;
+
+static LINKER: Mutex<Linker> = Mutex::new(Linker::new());
+
+/// Last error for dlerror, NUL-terminated
+static ERROR: Mutex<[u8; ERROR_MAX + 1]> = Mutex::new([0; ERROR_MAX + 1]);
+
+// ============================================================================
+// ENTRY
+// ============================================================================
+
+// Nothing that needs a relocation may run before relocate_self, so the
+// address of our own dynamic section comes from a RIP-relative lea here
+core::arch::global_asm!(
+    ".globl _start",
+    "_start:",
+    "    mov rdi, rsp",
+    "    lea rsi, [rip + _DYNAMIC]",
+    "    and rsp, -16",
+    "    call ld_main",
+    "    ud2",
+);
+
+#[no_mangle]
+unsafe extern "C" fn ld_main(sp: *const u64, dynamic: *const Dyn) -> ! {
+    let mut phdr = 0;
+    let mut phnum = 0;
+    let mut bias = 0;
+    let mut ld_bias = 0;
+    let mut entry = 0;
+
+    let mut p = sp;
+    loop {
+        let (key, value) = (*p, *p.add(1));
+        match key {
+            auxv::AT_NULL => break,
+            auxv::AT_PHDR => phdr = value,
+            auxv::AT_PHNUM => phnum = value as usize,
+            auxv::AT_BASE => ld_bias = value,
+            auxv::AT_ENTRY => entry = value,
+            auxv::AT_LOAD_BIAS => bias = value,
+            _ => {}
+        }
+        p = p.add(2);
+    }
+
+    relocate_self(ld_bias, dynamic);
+
+    let phdrs = core::slice::from_raw_parts(phdr as *const Phdr, phnum);
+    let program = match Object::from_phdrs(b"", bias, phdrs) {
+        Ok(object) => object,
+        Err(e) => fail(e.as_bytes()),
+    };
+    let loader = match Object::from_dynamic(b"ld-watos", ld_bias, dynamic as u64) {
+        Ok(object) => object,
+        Err(e) => fail(e.as_bytes()),
+    };
+
+    {
+        let mut linker = LINKER.lock();
+        if !linker.start(&mut SysHost::new(), program, loader) {
+            let mut msg = [0u8; ERROR_MAX];
+            let len = copy_error(&mut linker, &mut msg);
+            drop(linker);
+            fail(&msg[..len]);
+        }
+    }
+
+    // The program sees the stack as if it had been entered directly: the
+    // auxv stays above it, and rsp is 8 off 16-alignment as after a call
+    core::arch::asm!(
+        "mov rsp, {sp}",
+        "sub rsp, 8",
+        "jmp {entry}",
+        sp = in(reg) sp,
+        entry = in(reg) entry,
+        options(noreturn),
+    );
+}
+
+/// Apply our own R_X86_64_RELATIVE relocations
+///
+/// Runs before anything that reads an absolute address out of memory: no
+/// statics, no panics, no bounds-checked indexing.
+#[inline(never)]
+unsafe fn relocate_self(bias: u64, dynamic: *const Dyn) {
+    let mut rela = 0;
+    let mut relasz = 0;
+    let mut d = dynamic;
+    while (*d).tag != elf::DT_NULL {
+        match (*d).tag {
+            elf::DT_RELA => rela = (*d).val,
+            elf::DT_RELASZ => relasz = (*d).val,
+            _ => {}
+        }
+        d = d.add(1);
+    }
+
+    let first = (bias + rela) as *const Rela;
+    let count = relasz as usize / core::mem::size_of::<Rela>();
+    for i in 0..count {
+        let r = first.add(i);
+        if (*r).info as u32 == elf::R_X86_64_RELATIVE {
+            *((bias + (*r).offset) as *mut u64) = bias.wrapping_add((*r).addend as u64);
+        }
+    }
+}
+
+/// Report a link failure on stderr and exit
+fn fail(msg: &[u8]) -> ! {
+    syscalls::write(2, b"ld-watos: ");
+    syscalls::write(2, msg);
+    syscalls::write(2, b"\n");
+    syscalls::exit(LINK_FAILED)
+}
+
+fn copy_error(linker: &mut Linker, out: &mut [u8]) -> usize {
+    match linker.take_error() {
+        Some(msg) => {
+            let len = msg.len().min(out.len());
+            out[..len].copy_from_slice(&msg[..len]);
+            len
+        }
+        None => 0,
+    }
+}
+
+// ============================================================================
+// HOST
+// ============================================================================
+
+/// Files and memory through syscalls
+///
+/// A library file is read whole into a SYS_MALLOC buffer, freed on the
+/// next read; its segments are copied into memory that is never freed.
+struct SysHost {
+    buf: *mut u8,
+}
+
+impl SysHost {
+    fn new() -> Self {
+        SysHost { buf: core::ptr::null_mut() }
+    }
+
+    fn release(&mut self) {
+        if !self.buf.is_null() {
+            syscalls::free(self.buf);
+            self.buf = core::ptr::null_mut();
+        }
+    }
+}
+
+impl Drop for SysHost {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Host for SysHost {
+    fn read_file(&mut self, path: &str) -> Option<&[u8]> {
+        self.release();
+        let (kind, size) = syscalls::stat(path)?;
+        if kind != 0 || size == 0 {
+            return None;
+        }
+        let size = size as usize;
+
+        let fd = syscalls::open(path, watos_syscall::open::O_RDONLY);
+        if fd < 0 {
+            return None;
+        }
+        let buf = syscalls::malloc(size);
+        if buf.is_null() {
+            syscalls::close(fd);
+            return None;
+        }
+        self.buf = buf;
+
+        let data = unsafe { core::slice::from_raw_parts_mut(buf, size) };
+        let mut done = 0;
+        while done < size {
+            let n = syscalls::read(fd, &mut data[done..]);
+            if n == 0 || n > size - done {
+                break;
+            }
+            done += n;
+        }
+        syscalls::close(fd);
+
+        if done < size {
+            return None;
+        }
+        Some(data)
+    }
+
+    fn alloc(&mut self, size: usize) -> Option<*mut u8> {
+        let p = syscalls::malloc(size);
+        if p.is_null() {
+            None
+        } else {
+            Some(p)
+        }
+    }
+}
+
+// ============================================================================
+// dlopen / dlsym / dlclose / dlerror
+// ============================================================================
+
+/// Bytes of a NUL-terminated string
+unsafe fn c_str<'a>(s: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(s, len)
+}
+
+/// Keep the linker's error for dlerror
+fn save_error(linker: &mut Linker) {
+    let mut error = ERROR.lock();
+    let len = copy_error(linker, &mut error[..ERROR_MAX]);
+    error[len] = 0;
+}
+
+/// Load a library and its dependencies; null on failure
+///
+/// A null `name` returns the global scope handle, which is also null: use
+/// dlerror to tell the two apart.
+///
+/// # Safety
+/// `name` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dlopen(name: *const u8, flags: i32) -> *mut u8 {
+    if name.is_null() {
+        return core::ptr::null_mut();
+    }
+    let mut linker = LINKER.lock();
+    match linker.dlopen(&mut SysHost::new(), c_str(name), flags & RTLD_GLOBAL != 0) {
+        Some(handle) => handle as *mut u8,
+        None => {
+            save_error(&mut linker);
+            core::ptr::null_mut()
+        }
+    }
+}
+
+/// Address of a symbol; a null handle searches the global scope
+///
+/// # Safety
+/// `name` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dlsym(handle: *mut u8, name: *const u8) -> *mut u8 {
+    if name.is_null() {
+        return core::ptr::null_mut();
+    }
+    let mut linker = LINKER.lock();
+    match linker.dlsym(handle as usize, c_str(name)) {
+        Some(addr) => addr as *mut u8,
+        None => {
+            save_error(&mut linker);
+            core::ptr::null_mut()
+        }
+    }
+}
+
+/// Drop a reference from dlopen: 0 on success
+///
+/// Libraries are never unmapped; the handle just stops being valid once
+/// every dlopen of it has been closed.
+///
+/// # Safety
+/// Code from the library must not run after its last dlclose.
+#[no_mangle]
+pub unsafe extern "C" fn dlclose(handle: *mut u8) -> i32 {
+    let mut linker = LINKER.lock();
+    if linker.dlclose(handle as usize) {
+        0
+    } else {
+        save_error(&mut linker);
+        -1
+    }
+}
+
+/// The last error as a C string, or null; reading it clears it
+///
+/// # Safety
+/// The string is overwritten by the next dlerror call.
+#[no_mangle]
+pub unsafe extern "C" fn dlerror() -> *const u8 {
+    static MESSAGE: Mutex<[u8; ERROR_MAX + 1]> = Mutex::new([0; ERROR_MAX + 1]);
+
+    let mut error = ERROR.lock();
+    if error[0] == 0 {
+        return core::ptr::null();
+    }
+    let mut message = MESSAGE.lock();
+    *message = *error;
+    error[0] = 0;
+    message.as_ptr()
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    fail(b"internal error")
+}
@@ -4,7 +4,7 @@
 //!        ln [OPTIONS] TARGET        (creates link in current directory)
 //!
 //! Options:
-//!   -s    Create symbolic link (default, hard links not supported yet)
+//!   -s    Create symbolic link instead of a hard link
 //!   -f    Force - remove existing destination files
 //!   -v    Verbose - print each link created
 
@@ -13,6 +13,7 @@
 
 use core::panic::PanicInfo;
 use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
 
 #[inline(always)]
 unsafe fn syscall2(num: u32, arg1: u64, arg2: u64) -> u64 {
@@ -85,6 +86,13 @@ fn symlink(target: &[u8], linkpath: &[u8]) -> u64 {
     }
 }
 
+fn link(target: &[u8], linkpath: &[u8]) -> u64 {
+    match (core::str::from_utf8(target), core::str::from_utf8(linkpath)) {
+        (Ok(t), Ok(l)) => syscalls::link(t, l),
+        _ => u64::MAX,
+    }
+}
+
 fn unlink(path: &[u8]) -> u64 {
     unsafe {
         syscall2(syscall::SYS_UNLINK, path.as_ptr() as u64, path.len() as u64)
@@ -100,7 +108,7 @@ struct Options {
 impl Options {
     fn new() -> Self {
         Options {
-            symbolic: true, // Default to symbolic links
+            symbolic: false,
             force: false,
             verbose: false,
         }
@@ -123,7 +131,7 @@ fn print_usage() {
     write_str("Create a link to TARGET with the name LINK_NAME.\r\n");
     write_str("\r\n");
     write_str("Options:\r\n");
-    write_str("  -s    Create symbolic link (default)\r\n");
+    write_str("  -s    Create symbolic link\r\n");
     write_str("  -f    Force - remove existing files\r\n");
     write_str("  -v    Verbose output\r\n");
 }
@@ -211,13 +219,19 @@ extern "C" fn _start() -> ! {
         let _ = unlink(linkpath);
     }
 
-    // Create the symlink
-    let result = symlink(target, linkpath);
+    // Create the link
+    let (result, kind, arrow) = if opts.symbolic {
+        (symlink(target, linkpath), "symbolic link", "' -> '")
+    } else {
+        (link(target, linkpath), "hard link", "' => '")
+    };
 
     if result != 0 {
-        write_str("ln: failed to create symbolic link '");
+        write_str("ln: failed to create ");
+        write_str(kind);
+        write_str(" '");
         write_bytes(linkpath);
-        write_str("' -> '");
+        write_str(arrow);
         write_bytes(target);
         write_str("'\r\n");
         exit(1);
@@ -226,7 +240,7 @@ extern "C" fn _start() -> ! {
     if opts.verbose {
         write_str("'");
         write_bytes(linkpath);
-        write_str("' -> '");
+        write_str(arrow);
         write_bytes(target);
         write_str("'\r\n");
     }
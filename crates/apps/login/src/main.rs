@@ -2,12 +2,22 @@
 //!
 //! Provides user authentication and launches console sessions.
 //! Runs as the initial application instead of going directly to shell.
+//!
+//! Usage: login [-h HOST] [-f USER]
+//!
+//! -h marks a session from HOST over the network (from rshd or telnetd):
+//! the user's shell runs directly on the pty instead of in the terminal
+//! emulator, and three failed logins end the session. WATOS keeps no
+//! login records, so HOST itself is not kept.
+//! -f starts USER's session without asking for a password, for
+//! a daemon that has already checked it; only root may use it.
 
 #![no_std]
 #![no_main]
 
 use core::panic::PanicInfo;
 use watos_syscall::numbers as syscall;
+use watos_syscall::{argv, syscalls};
 
 // ============================================================================
 // Raw Syscall Wrappers
@@ -86,8 +96,8 @@ fn read_line(buf: &mut [u8]) -> usize {
             if k != 0 {
                 break k;
             }
-            // Small delay to avoid busy-waiting
-            for _ in 0..10000 { core::hint::spin_loop(); }
+            // Halt until the next key press or timer tick
+            unsafe { syscall0(syscall::SYS_IDLE); }
         };
 
         if key == b'\n' || key == b'\r' {
@@ -120,8 +130,8 @@ fn read_password(buf: &mut [u8]) -> usize {
             if k != 0 {
                 break k;
             }
-            // Small delay to avoid busy-waiting
-            for _ in 0..10000 { core::hint::spin_loop(); }
+            // Halt until the next key press or timer tick
+            unsafe { syscall0(syscall::SYS_IDLE); }
         };
 
         if key == b'\n' || key == b'\r' {
@@ -143,13 +153,43 @@ fn read_password(buf: &mut [u8]) -> usize {
 }
 
 fn exec_console() {
-    // Execute the shell
-    let cmd = b"shell";
+    // The shell, in the terminal emulator when there is a framebuffer
+    let cmd = b"term";
     unsafe {
         syscall2(syscall::SYS_EXEC, cmd.as_ptr() as u64, cmd.len() as u64);
     }
 }
 
+/// Run the user's own shell for a network session: the pty is already
+/// the terminal, and the framebuffer belongs to whoever is at the console
+fn exec_remote_shell(username: &[u8]) {
+    let mut pw_buf = [0u8; 512];
+    let name = core::str::from_utf8(username).unwrap_or("");
+    let shell = syscalls::getpwnam(name, &mut pw_buf)
+        .map(|pw| pw.shell)
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or("shell");
+    syscalls::exec(shell);
+}
+
+/// Start the session once `uid` is known
+fn start_session(uid: u64, username: &[u8], remote: bool) -> ! {
+    // Set current user context
+    unsafe {
+        syscall1(syscall::SYS_SETUID, uid);
+    }
+
+    // Launch console/shell
+    if remote {
+        exec_remote_shell(username);
+    } else {
+        exec_console();
+    }
+
+    // If exec fails, exit
+    exit(0);
+}
+
 fn exit(code: i32) -> ! {
     unsafe {
         syscall1(syscall::SYS_EXIT, code as u64);
@@ -161,8 +201,42 @@ fn exit(code: i32) -> ! {
 // Main Entry Point
 // ============================================================================
 
+fn usage() -> ! {
+    write_str("Usage: login [-h HOST] [-f USER]\r\n");
+    exit(2);
+}
+
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+    let mut args = argv::decode(&args_buf[..args_len]).skip(1);
+    let mut remote = None;
+    let mut preauthenticated = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "-h" => remote = Some(args.next().unwrap_or_else(|| usage())),
+            "-f" => preauthenticated = Some(args.next().unwrap_or_else(|| usage())),
+            _ => usage(),
+        }
+    }
+    let remote = remote.is_some();
+
+    if let Some(user) = preauthenticated {
+        let mut pw_buf = [0u8; 512];
+        if syscalls::getuid() != 0 {
+            write_str("login: -f: permission denied\r\n");
+            exit(1);
+        }
+        match syscalls::getpwnam(user, &mut pw_buf) {
+            Some(pw) => start_session(pw.uid as u64, user.as_bytes(), remote),
+            None => {
+                write_str("login: unknown user\r\n");
+                exit(1);
+            }
+        }
+    }
+
     write_str("\r\n");
     write_str("===============================================\r\n");
     write_str("     WATOS - Welcome to the Operating System  \r\n");
@@ -212,26 +286,21 @@ pub extern "C" fn _start() -> ! {
                 syscall3(syscall::SYS_WRITE, 1, username.as_ptr() as u64, username_len as u64);
             }
             write_str("\r\n\r\n");
-            
-            // Set current user context
-            unsafe {
-                syscall1(syscall::SYS_SETUID, uid);
-            }
 
-            // Launch console/shell
-            exec_console();
-            
-            // If exec fails, exit
-            exit(0);
+            start_session(uid, username, remote);
         } else {
             // Authentication failed
             attempts += 1;
             write_str("\r\nLogin incorrect\r\n");
             
+            if attempts >= 3 && remote {
+                // Over the network, hang up instead of inviting more guesses
+                exit(1);
+            }
             if attempts >= 3 {
                 write_str("Too many failed attempts. Please try again later.\r\n");
                 // Wait a bit before allowing retry
-                for _ in 0..100_000_000 { core::hint::spin_loop(); }
+                unsafe { syscall1(syscall::SYS_SLEEP, 3000); }
                 attempts = 0;
             }
             write_str("\r\n");
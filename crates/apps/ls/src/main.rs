@@ -121,6 +121,11 @@ fn getcwd(buf: &mut [u8]) -> usize {
     unsafe { syscall2(syscall::SYS_GETCWD, buf.as_mut_ptr() as u64, buf.len() as u64) as usize }
 }
 
+fn volinfo(path: &[u8]) -> Option<watos_syscall::volume::VolInfo> {
+    let path = if path.is_empty() { "." } else { core::str::from_utf8(path).ok()? };
+    watos_syscall::syscalls::volinfo(path)
+}
+
 // ============================================================================
 // ANSI color codes
 // ============================================================================
@@ -417,6 +422,36 @@ fn display_entry_short(entry: &DirEntry, opts: &Options) {
     }
 }
 
+/// DOS-style volume lines above a long listing, for labelled volumes
+fn display_volume(path: &[u8], cwd: &[u8]) {
+    let Some(volume) = volinfo(path) else { return };
+    let Some(label) = volume.label() else { return };
+
+    // The drive is the one named in the path, else the current one
+    let named = if path.len() >= 2 && path[1] == b':' { path } else { cwd };
+    if named.len() >= 2 && named[1] == b':' {
+        write_str(" Volume in drive ");
+        write_bytes(&named[..1]);
+        write_str(" is ");
+    } else {
+        write_str(" Volume is ");
+    }
+    write_str(label);
+    write_str("\r\n");
+
+    if let Some(serial) = volume.serial() {
+        let mut hex = [0u8; 9];
+        for (i, shift) in [28, 24, 20, 16, 12, 8, 4, 0].iter().enumerate() {
+            hex[i + i / 4] = b"0123456789ABCDEF"[(serial >> shift) as usize & 0xF];
+        }
+        hex[4] = b'-';
+        write_str(" Volume Serial Number is ");
+        write_bytes(&hex);
+        write_str("\r\n");
+    }
+    write_str("\r\n");
+}
+
 // ============================================================================
 // Main entry parsing and display
 // ============================================================================
@@ -600,6 +635,12 @@ extern "C" fn _start() -> ! {
         exit(0);
     }
 
+    if opts.long_format {
+        let mut cwd = [0u8; 256];
+        let cwd_len = getcwd(&mut cwd);
+        display_volume(path, &cwd[..cwd_len]);
+    }
+
     let entries = unsafe { &DIR_BUF[..len] };
     let count = parse_and_display_entries(entries, &opts);
 
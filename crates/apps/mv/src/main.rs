@@ -111,6 +111,18 @@ fn rename(old_path: &[u8], new_path: &[u8]) -> i64 {
     }
 }
 
+fn realpath(path: &[u8], buf: &mut [u8]) -> usize {
+    unsafe {
+        syscall4(
+            syscall::SYS_REALPATH,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+        ) as usize
+    }
+}
+
 // File type constants
 const S_IFDIR: u64 = 0o040000;
 const S_IFMT: u64 = 0o170000;
@@ -167,7 +179,58 @@ fn build_dest_path(dest: &[u8], src_basename: &[u8], buf: &mut [u8]) -> usize {
     idx
 }
 
+/// How a destination relates to its source once both are canonicalized
+#[derive(PartialEq)]
+enum Overlap {
+    None,
+    SameFile,
+    InsideSource,
+}
+
+fn overlap(src: &[u8], dest: &[u8]) -> Overlap {
+    let mut src_buf = [0u8; 256];
+    let mut dest_buf = [0u8; 256];
+    let src_len = realpath(src, &mut src_buf);
+    let dest_len = realpath(dest, &mut dest_buf);
+    if src_len == 0 || dest_len == 0 {
+        // Let rename report the problem
+        return Overlap::None;
+    }
+
+    let src_real = &src_buf[..src_len];
+    let dest_real = &dest_buf[..dest_len];
+    if src_real == dest_real {
+        Overlap::SameFile
+    } else if dest_real.starts_with(src_real)
+        && (src_real.last() == Some(&b'/') || dest_real[src_len] == b'/')
+    {
+        Overlap::InsideSource
+    } else {
+        Overlap::None
+    }
+}
+
 fn move_entry(src: &[u8], dest: &[u8], opts: &Options) -> i32 {
+    match overlap(src, dest) {
+        Overlap::SameFile => {
+            write_str("mv: '");
+            write_bytes(src);
+            write_str("' and '");
+            write_bytes(dest);
+            write_str("' are the same file\r\n");
+            return 1;
+        }
+        Overlap::InsideSource => {
+            write_str("mv: cannot move '");
+            write_bytes(src);
+            write_str("' to a subdirectory of itself, '");
+            write_bytes(dest);
+            write_str("'\r\n");
+            return 1;
+        }
+        Overlap::None => {}
+    }
+
     let result = rename(src, dest);
     if result == 0 {
         if opts.verbose {
@@ -7,6 +7,11 @@
 //!   -f        Force removal, ignore nonexistent files
 //!   -d        Remove empty directories
 //!   -v        Verbose mode, explain what is being done
+//!
+//! Without -f, files and directory trees go to their drive's trash
+//! (X:/.trash) where `trash` can restore them. Paths with no trash (not
+//! on a drive, or in the trash already) and everything when `fs.trash` is
+//! off are deleted for good.
 
 #![no_std]
 #![no_main]
@@ -115,6 +120,11 @@ fn readdir(path: &[u8], buf: &mut [u8]) -> i64 {
     }
 }
 
+/// Move to the trash: 0 done, 1 no trash for this path, u64::MAX error
+fn trash(path: &[u8]) -> u64 {
+    unsafe { syscall2(syscall::SYS_TRASH, path.as_ptr() as u64, path.len() as u64) }
+}
+
 struct Options {
     recursive: bool,   // -r, -R
     force: bool,       // -f
@@ -249,6 +259,26 @@ fn remove_recursive(path: &[u8], opts: &Options, path_buf: &mut [u8]) -> i32 {
 fn remove_file(path: &[u8], opts: &Options) -> i32 {
     let is_dir = is_directory(path);
 
+    if !opts.force && (!is_dir || opts.recursive) {
+        match trash(path) {
+            0 => {
+                if opts.verbose {
+                    write_str("trashed '");
+                    write_bytes(path);
+                    write_str("'\r\n");
+                }
+                return 0;
+            }
+            1 => {}
+            _ => {
+                write_str("rm: cannot remove '");
+                write_bytes(path);
+                write_str("'\r\n");
+                return 1;
+            }
+        }
+    }
+
     if is_dir {
         if opts.recursive {
             static mut PATH_BUF: [u8; 512] = [0u8; 512];
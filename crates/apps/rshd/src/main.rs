@@ -0,0 +1,424 @@
+//! WATOS rshd - remote shell daemon
+//!
+//! Usage: rshd [-p PORT]
+//!
+//! Listens for watos-rsh connections (port 2222 by default) and hands each
+//! to a process of its own, started as `rshd -c FD` with the connection
+//! on FD, so a slow client can't hold up the rest. That process runs the
+//! handshake with the host key in /etc/rsh/host_key (made on first start),
+//! checks the password with SYS_AUTHENTICATE, then runs
+//! `login -h ADDR -f USER` on a new pty and copies between the pty and the
+//! connection until the shell exits, sending its exit status last.
+//!
+//! Must run as root: the host key is root's, and only root may start a
+//! session with `login -f`. Ptys have no window size yet, so the client's
+//! terminal size is not passed on.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_rsh::host_key::fingerprint;
+use watos_rsh::{Error, Event, HostKey, Server, Session, Transport, DEFAULT_PORT, HOST_KEY_PATH};
+use watos_syscall::numbers as syscall;
+use watos_syscall::{argv, net, open, signals, syscalls, wait};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Output
+// ============================================================================
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn fail(message: &str) -> ! {
+    write_str("rshd: ");
+    write_str(message);
+    write_str("\r\n");
+    exit(1);
+}
+
+// ============================================================================
+// Connections
+// ============================================================================
+
+/// PIT ticks per second (the timer runs at ~18.2 Hz)
+const TICKS_PER_SEC: u64 = 18;
+
+/// How long a client has to finish the handshake and log in
+const LOGIN_TICKS: u64 = 60 * TICKS_PER_SEC;
+
+/// How long a write may wait for the peer to take more data
+const WRITE_TICKS: u64 = 30 * TICKS_PER_SEC;
+
+/// The kernel's per-process fd limit
+const MAX_FDS: i32 = 64;
+
+/// An accepted TCP connection. Sockets never block: `read` waits until
+/// the login deadline, `try_read` returns at once.
+struct Socket {
+    fd: i32,
+    deadline: u64,
+}
+
+impl Socket {
+    fn is_open(&self) -> bool {
+        syscalls::net_state(self.fd) == Some(net::STATE_OPEN)
+    }
+}
+
+impl Transport for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            if let Some(n) = self.try_read(buf)? {
+                return Ok(n);
+            }
+            if syscalls::get_ticks() > self.deadline {
+                return Err(Error::Transport);
+            }
+            syscalls::idle();
+        }
+    }
+
+    fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        match syscalls::read(self.fd, buf) {
+            n if n > 0 && n <= buf.len() => Ok(Some(n)),
+            // Closed once the peer is done and everything has been read
+            _ if !self.is_open() => Ok(Some(0)),
+            _ => Ok(None),
+        }
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        let deadline = syscalls::get_ticks() + WRITE_TICKS;
+        while !buf.is_empty() {
+            // 0 while the send buffer is full
+            match syscalls::write(self.fd, buf) {
+                0 if self.is_open() && syscalls::get_ticks() <= deadline => syscalls::idle(),
+                n if n > 0 && n <= buf.len() => buf = &buf[n..],
+                _ => return Err(Error::Transport),
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Host key
+// ============================================================================
+
+fn read_full(fd: i32, buf: &mut [u8]) -> usize {
+    let mut got = 0;
+    while got < buf.len() {
+        match syscalls::read(fd, &mut buf[got..]) {
+            n if n > 0 && n <= buf.len() - got => got += n,
+            _ => break,
+        }
+    }
+    got
+}
+
+/// The host key from HOST_KEY_PATH, or a new one saved there (mode 0600)
+fn host_key() -> Result<HostKey, &'static str> {
+    let mut private = [0u8; 32];
+    let fd = syscalls::open(HOST_KEY_PATH, open::O_RDONLY);
+    if fd >= 0 {
+        let got = read_full(fd, &mut private);
+        syscalls::close(fd);
+        return match got {
+            32 => Ok(HostKey::from_private(private)),
+            _ => Err("host key file is damaged"),
+        };
+    }
+
+    if !syscalls::getrandom(&mut private) {
+        return Err("no random numbers");
+    }
+    let key = HostKey::from_private(private);
+    if let Some(dir) = HOST_KEY_PATH.rfind('/').map(|i| &HOST_KEY_PATH[..i]) {
+        // Fails harmlessly when it already exists
+        syscalls::mkdir(dir);
+    }
+    let fd = syscalls::open(HOST_KEY_PATH, open::O_WRONLY | open::O_CREAT | open::O_TRUNC);
+    if fd < 0 {
+        return Err("cannot create the host key file");
+    }
+    let written = syscalls::write(fd, &private);
+    syscalls::close(fd);
+    for byte in private.iter_mut() {
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    let chmod = unsafe {
+        watos_syscall::raw_syscall3(syscall::SYS_CHMOD, HOST_KEY_PATH.as_ptr() as u64, HOST_KEY_PATH.len() as u64, 0o600)
+    };
+    if written != 32 || chmod == u64::MAX {
+        syscalls::unlink(HOST_KEY_PATH);
+        return Err("cannot write the host key file");
+    }
+    Ok(key)
+}
+
+// ============================================================================
+// One session
+// ============================================================================
+
+/// SYS_AUTHENTICATE; the password goes NUL-terminated and must be shorter
+/// than 64 bytes
+fn authenticate(user: &str, password: &str) -> bool {
+    let mut buf = [0u8; 64];
+    if user.is_empty() || password.len() >= buf.len() {
+        return false;
+    }
+    buf[..password.len()].copy_from_slice(password.as_bytes());
+    let uid = unsafe {
+        watos_syscall::raw_syscall3(
+            syscall::SYS_AUTHENTICATE,
+            user.as_ptr() as u64,
+            user.len() as u64,
+            buf.as_ptr() as u64,
+        )
+    };
+    for byte in buf.iter_mut() {
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    uid != u64::MAX
+}
+
+/// Run `program` with a new pty as its standard streams
+fn start_on_pty(program: &[&str]) -> Option<(u32, i32)> {
+    let (master, slave) = syscalls::openpty()?;
+    for fd in 0..3 {
+        syscalls::dup2(slave, fd);
+    }
+    let pid = syscalls::spawn(program);
+    // Our own standard streams go back to the console
+    for fd in 0..3 {
+        syscalls::close(fd);
+    }
+    syscalls::close(slave);
+    if pid == u64::MAX || pid == 0 {
+        syscalls::close(master);
+        return None;
+    }
+    Some((pid as u32, master))
+}
+
+/// Program output as a terminal expects it, turning a lone LF into CR LF
+/// as a pty's line discipline would; `last` is the byte before `data`
+fn crlf(data: &[u8], last: &mut u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        if b == b'\n' && *last != b'\r' {
+            out.push(b'\r');
+        }
+        out.push(b);
+        *last = b;
+    }
+    out
+}
+
+/// Shell-style exit status for the client
+fn exit_status(status: u32) -> u32 {
+    if wait::signaled(status) {
+        128 + wait::term_signal(status)
+    } else {
+        wait::exit_code(status) as u32
+    }
+}
+
+/// Copy between the connection and the pty until the shell exits; its
+/// status, or None if the client went away first
+fn relay(session: &mut Session<Socket>, pid: u32, master: i32) -> Option<u32> {
+    let mut buf = [0u8; 4096];
+    let mut last = 0u8;
+    // Keystrokes the pty had no room for yet
+    let mut input: Vec<u8> = Vec::new();
+    loop {
+        let mut busy = false;
+
+        if !input.is_empty() {
+            match syscalls::write(master, &input) {
+                n if n <= input.len() => {
+                    input.drain(..n);
+                    busy = n > 0;
+                }
+                // Hung up: the shell is gone, and wait() below says how
+                _ => input.clear(),
+            }
+        } else {
+            match session.poll_event() {
+                Ok(Some(Event::Data(data))) => {
+                    input = data;
+                    busy = true;
+                }
+                // Nothing to pass a window size to yet
+                Ok(Some(_)) => busy = true,
+                Ok(None) => {}
+                Err(_) => return None,
+            }
+        }
+
+        let exited = syscalls::wait(pid, wait::WNOHANG);
+        // Output the shell wrote before it exited still goes out
+        loop {
+            match syscalls::read(master, &mut buf) {
+                n if n > 0 && n <= buf.len() => {
+                    if session.send_data(&crlf(&buf[..n], &mut last)).is_err() {
+                        return None;
+                    }
+                    busy = true;
+                }
+                _ => break,
+            }
+        }
+        if let Some((_, status)) = exited {
+            return Some(exit_status(status));
+        }
+
+        if !busy {
+            syscalls::idle();
+        }
+    }
+}
+
+/// Serve the connection on `fd`, in a process of its own
+fn serve(fd: i32) -> ! {
+    // Drop what came from the listening daemon, like the listener itself
+    for other in 3..MAX_FDS {
+        if other != fd {
+            syscalls::close(other);
+        }
+    }
+    let host_key = host_key().unwrap_or_else(|e| fail(e));
+    let addr = syscalls::net_peer(fd).map(|(addr, _)| net::octets(addr)).unwrap_or([0; 4]);
+    let addr = format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3]);
+
+    let socket = Socket { fd, deadline: syscalls::get_ticks() + LOGIN_TICKS };
+    let mut random = |buf: &mut [u8]| {
+        if !syscalls::getrandom(buf) {
+            fail("no random numbers");
+        }
+    };
+    let mut session = Server { host_key: &host_key }
+        .accept(socket, &mut random)
+        .unwrap_or_else(|_| exit(1));
+    let (user, _size) = session.wait_login(&mut authenticate).unwrap_or_else(|_| exit(1));
+
+    let Some((pid, master)) = start_on_pty(&["login", "-h", &addr, "-f", &user]) else {
+        let _ = session.send_data(b"rshd: cannot start login\r\n");
+        let _ = session.exit(1);
+        exit(1);
+    };
+    match relay(&mut session, pid, master) {
+        Some(status) => {
+            let _ = session.exit(status);
+        }
+        // The client is gone: so is the session
+        None => {
+            syscalls::kill(pid as i32, signals::SIGKILL);
+            syscalls::wait(pid, 0);
+        }
+    }
+    syscalls::close(master);
+    exit(0);
+}
+
+// ============================================================================
+// Listening
+// ============================================================================
+
+fn usage() -> ! {
+    write_str("Usage: rshd [-p PORT]\r\n");
+    exit(2);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+    let mut args = argv::decode(&args_buf[..args_len]).skip(1);
+    let mut port = DEFAULT_PORT;
+    let mut connection = None;
+    while let Some(arg) = args.next() {
+        let value = match arg {
+            "-p" | "-c" => args.next().unwrap_or_else(|| usage()),
+            _ => usage(),
+        };
+        match arg {
+            "-p" => port = value.parse().unwrap_or_else(|_| usage()),
+            _ => connection = Some(value.parse().unwrap_or_else(|_| usage())),
+        }
+    }
+
+    if syscalls::getuid() != 0 {
+        fail("must be run as root");
+    }
+    if let Some(fd) = connection {
+        serve(fd);
+    }
+
+    // Make the host key now, so a problem with it shows at once
+    let key = host_key().unwrap_or_else(|e| fail(e));
+    let listener = syscalls::net_listen(port).unwrap_or_else(|| fail("no network"));
+    let mut hex = alloc::string::String::new();
+    for b in fingerprint(key.public()) {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    drop(key);
+    write_str(&format!("rshd: listening on port {}, host key SHA256:{}\r\n", port, hex));
+
+    loop {
+        match syscalls::net_accept(listener) {
+            Some(Some(fd)) => {
+                let fd_arg = format!("{}", fd);
+                let pid = syscalls::spawn(&["rshd", "-c", &fd_arg]);
+                if pid == u64::MAX || pid == 0 {
+                    write_str("rshd: cannot start a session\r\n");
+                }
+                // The session process has its own copy
+                syscalls::close(fd);
+            }
+            Some(None) => {
+                // Collect finished sessions
+                while syscalls::wait(0, wait::WNOHANG).is_some() {}
+                syscalls::idle();
+            }
+            None => fail("lost the listening socket"),
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\r\nrshd: internal error\r\n");
+    exit(1);
+}
@@ -0,0 +1,341 @@
+//! Job control for the shell
+//!
+//! Tracks background and stopped jobs launched via SYS_SPAWN and drives
+//! them with SYS_WAIT, SYS_KILL and the process-group syscalls:
+//! - `cmd &` starts a job in the background
+//! - `jobs` lists the job table
+//! - `fg [%n]` / `bg [%n]` resume a stopped job in the foreground/background
+//! - Ctrl+Z stops the foreground job (reported by SYS_WAIT with WUNTRACED)
+//!
+//! The external stages of a pipeline share one process group, so Ctrl+C
+//! and Ctrl+Z reach all of them, and the pipeline is one job.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use watos_syscall::{signals, syscalls, wait};
+
+use crate::pipeline::Started;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+/// State of a job as last reported by SYS_WAIT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Stopped,
+    Done(i32),
+    Killed(u32),
+}
+
+impl JobStatus {
+    fn label(&self) -> String {
+        match self {
+            JobStatus::Running => String::from("Running"),
+            JobStatus::Stopped => String::from("Stopped"),
+            JobStatus::Done(0) => String::from("Done"),
+            JobStatus::Done(code) => format!("Exit {}", code),
+            JobStatus::Killed(sig) => format!("Killed ({})", sig),
+        }
+    }
+
+    /// Shell exit status ($?) for this state: 128+signal when stopped or killed
+    fn exit_status(&self) -> i32 {
+        match self {
+            JobStatus::Running => 0,
+            JobStatus::Stopped => 128 + signals::SIGTSTP as i32,
+            JobStatus::Done(code) => *code,
+            JobStatus::Killed(sig) => 128 + *sig as i32,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        matches!(self, JobStatus::Done(_) | JobStatus::Killed(_))
+    }
+
+    fn from_wait_status(status: u32) -> Self {
+        if wait::stopped(status) {
+            JobStatus::Stopped
+        } else if wait::exited(status) {
+            JobStatus::Done(wait::exit_code(status))
+        } else {
+            JobStatus::Killed(wait::term_signal(status))
+        }
+    }
+}
+
+/// Print why a foreground child died, e.g. "Segmentation fault (core dumped)"
+/// Interrupts by Ctrl+C are not reported.
+pub fn report_crash(status: u32) {
+    if !wait::signaled(status) || wait::term_signal(status) == signals::SIGINT {
+        return;
+    }
+    let sig = wait::term_signal(status);
+    let message = match sig {
+        signals::SIGILL => String::from("Illegal instruction"),
+        signals::SIGTRAP => String::from("Trace/breakpoint trap"),
+        signals::SIGBUS => String::from("Bus error"),
+        signals::SIGFPE => String::from("Floating point exception"),
+        signals::SIGSEGV => String::from("Segmentation fault"),
+        _ => format!("Killed ({})", sig),
+    };
+    write_str(&message);
+    if wait::core_dumped(status) {
+        write_str(" (core dumped)");
+    }
+    write_str("\r\n");
+}
+
+/// A single entry in the job table
+pub struct Job {
+    pub id: usize,
+    pub pid: u32,
+    pub pgid: u32,
+    pub command: String,
+    pub status: JobStatus,
+}
+
+/// Why a command did not start as a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// SYS_SPAWN found no such program, or couldn't load it
+    NotFound,
+}
+
+/// The process group a pipeline's stages are started into
+#[derive(Debug, Default)]
+pub struct StageGroup {
+    /// 0 until the first stage has started and given the group its id
+    pub pgid: u32,
+    /// The stage just spawned, for the caller to collect
+    spawned: Option<u32>,
+}
+
+impl StageGroup {
+    /// The process started by the last `spawn_stage`, if it started one
+    pub fn take_spawned(&mut self) -> Option<u32> {
+        self.spawned.take()
+    }
+}
+
+/// The shell's job table
+pub struct JobTable {
+    jobs: Vec<Job>,
+    next_id: usize,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        JobTable {
+            jobs: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Spawn a command into process group `pgid`, or its own if 0
+    fn spawn(&mut self, argv: &[String], pgid: u32) -> Result<u32, SpawnError> {
+        let pid = syscalls::spawn(argv);
+        if pid == u64::MAX || pid == 0 {
+            return Err(SpawnError::NotFound);
+        }
+        let pid = pid as u32;
+        syscalls::setpgid(pid, pgid);
+        Ok(pid)
+    }
+
+    fn add(&mut self, pid: u32, pgid: u32, command: &str, status: JobStatus) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id,
+            pid,
+            pgid,
+            command: String::from(command),
+            status,
+        });
+        id
+    }
+
+    /// Run `cmd &`: spawn and return immediately
+    /// `cmdline` is the command as typed, for the job table
+    pub fn launch_background(&mut self, argv: &[String], cmdline: &str) -> Result<usize, SpawnError> {
+        let pid = self.spawn(argv, 0)?;
+        let id = self.add(pid, pid, cmdline, JobStatus::Running);
+        write_str(&format!("[{}] {}\r\n", id, pid));
+        Ok(id)
+    }
+
+    /// Run a command in the foreground and wait for it to exit or stop
+    /// Returns the command's exit status
+    pub fn launch_foreground(&mut self, argv: &[String], cmdline: &str) -> Result<i32, SpawnError> {
+        let pid = self.spawn(argv, 0)?;
+        let status = self.wait_foreground(pid, pid);
+        if status == JobStatus::Stopped {
+            self.add_stopped(pid, pid, cmdline);
+        }
+        Ok(status.exit_status())
+    }
+
+    /// Start a pipeline stage in `group` and leave it running
+    /// (see `wait_pipeline`); the status is 0 for now
+    pub fn spawn_stage(&mut self, argv: &[String], group: &mut StageGroup) -> Result<i32, SpawnError> {
+        let pid = self.spawn(argv, group.pgid)?;
+        if group.pgid == 0 {
+            group.pgid = pid;
+        }
+        group.spawned = Some(pid);
+        Ok(0)
+    }
+
+    /// Wait in the foreground for the running stages of a pipeline started
+    /// into process group `pgid`
+    /// Returns the last stage's exit status
+    pub fn wait_pipeline(&mut self, started: &[Started], pgid: u32, cmdline: &str) -> i32 {
+        let mut last = JobStatus::Done(0);
+        for (i, stage) in started.iter().enumerate() {
+            last = match *stage {
+                Started::Finished(code) => JobStatus::Done(code),
+                Started::Running(pid) => {
+                    let status = self.wait_foreground(pid, pgid);
+                    if status == JobStatus::Stopped {
+                        // Ctrl+Z stopped the whole group; `fg` waits for its last process
+                        let last_pid = started[i..].iter().rev().find_map(|s| match s {
+                            Started::Running(pid) => Some(*pid),
+                            Started::Finished(_) => None,
+                        });
+                        self.add_stopped(last_pid.unwrap_or(pid), pgid, cmdline);
+                        return status.exit_status();
+                    }
+                    status
+                }
+            };
+        }
+        last.exit_status()
+    }
+
+    fn add_stopped(&mut self, pid: u32, pgid: u32, cmdline: &str) {
+        let id = self.add(pid, pgid, cmdline, JobStatus::Stopped);
+        write_str(&format!("\r\n[{}]+  Stopped                 {}\r\n", id, cmdline));
+    }
+
+    /// Hand the console to `pgid`, block until `pid` exits or stops, then take it back
+    fn wait_foreground(&mut self, pid: u32, pgid: u32) -> JobStatus {
+        let shell_pgrp = syscalls::getpgid(0);
+        syscalls::tcsetpgrp(pgid);
+
+        // SYS_WAIT blocks until the job exits or Ctrl+Z stops it
+        let status = match syscalls::wait(pid, wait::WUNTRACED) {
+            Some((_, status)) => {
+                report_crash(status);
+                JobStatus::from_wait_status(status)
+            }
+            // Not our child any more: already collected
+            None => JobStatus::Done(0),
+        };
+
+        syscalls::tcsetpgrp(shell_pgrp);
+        status
+    }
+
+    /// Resolve a job spec ("%2", "2", or "" for the current job) to an index
+    fn find(&self, spec: &str) -> Option<usize> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec == "%" || spec == "%+" {
+            return if self.jobs.is_empty() { None } else { Some(self.jobs.len() - 1) };
+        }
+        let num = spec.strip_prefix('%').unwrap_or(spec);
+        let id: usize = num.parse().ok()?;
+        self.jobs.iter().position(|j| j.id == id)
+    }
+
+    /// `fg [%n]`, returning the job's exit status
+    pub fn foreground(&mut self, spec: &str) -> i32 {
+        let idx = match self.find(spec) {
+            Some(i) => i,
+            None => {
+                write_str("fg: no such job\r\n");
+                return 1;
+            }
+        };
+
+        let (pid, pgid) = (self.jobs[idx].pid, self.jobs[idx].pgid);
+        write_str(&self.jobs[idx].command);
+        write_str("\r\n");
+
+        if self.jobs[idx].status == JobStatus::Stopped {
+            syscalls::kill(-(pgid as i32), signals::SIGCONT);
+        }
+        self.jobs[idx].status = JobStatus::Running;
+
+        let status = self.wait_foreground(pid, pgid);
+        match status {
+            JobStatus::Stopped => {
+                let job = &mut self.jobs[idx];
+                job.status = JobStatus::Stopped;
+                write_str(&format!("\r\n[{}]+  Stopped                 {}\r\n", job.id, job.command));
+            }
+            _ => {
+                self.jobs.remove(idx);
+            }
+        }
+        status.exit_status()
+    }
+
+    /// `bg [%n]`
+    pub fn background(&mut self, spec: &str) {
+        let idx = match self.find(spec) {
+            Some(i) => i,
+            None => {
+                write_str("bg: no such job\r\n");
+                return;
+            }
+        };
+
+        let job = &mut self.jobs[idx];
+        if job.status != JobStatus::Stopped {
+            write_str(&format!("bg: job {} already in background\r\n", job.id));
+            return;
+        }
+        syscalls::kill(-(job.pgid as i32), signals::SIGCONT);
+        job.status = JobStatus::Running;
+        write_str(&format!("[{}]+ {} &\r\n", job.id, job.command));
+    }
+
+    /// `jobs`
+    pub fn list(&mut self) {
+        self.poll();
+        for job in &self.jobs {
+            write_str(&format!("[{}]  {:<22}  {}\r\n", job.id, job.status.label(), job.command));
+        }
+        self.jobs.retain(|j| !j.status.finished());
+    }
+
+    /// Collect state changes from background jobs without blocking
+    fn poll(&mut self) {
+        if self.jobs.is_empty() {
+            return;
+        }
+        while let Some((pid, status)) = syscalls::wait(0, wait::WNOHANG | wait::WUNTRACED) {
+            if let Some(job) = self.jobs.iter_mut().find(|j| j.pid == pid) {
+                job.status = JobStatus::from_wait_status(status);
+            }
+        }
+    }
+
+    /// Report jobs that finished since the last prompt and drop them from the table
+    pub fn notify(&mut self) {
+        self.poll();
+        for job in self.jobs.iter().filter(|j| j.status.finished()) {
+            write_str(&format!("[{}]+  {:<22}  {}\r\n", job.id, job.status.label(), job.command));
+        }
+        self.jobs.retain(|j| !j.status.finished());
+    }
+}
+
+impl Default for JobTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,11 +1,20 @@
 //! WATOS Shell - Simple command interpreter
 
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+// Unit tests build on the host, without the entry point that uses the rest
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 
 extern crate alloc;
 
+mod jobs;
+mod pipeline;
+mod script;
+mod words;
+
 use core::panic::PanicInfo;
+use jobs::{JobTable, StageGroup};
+use pipeline::Started;
 use watos_syscall::numbers as syscall;
 use watos_readline::{Readline, EditMode, ShellCompleter, ReadlineError};
 
@@ -98,6 +107,8 @@ fn exit(code: i32) -> ! {
 
 use core::alloc::{GlobalAlloc, Layout};
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 struct SyscallAllocator;
 
@@ -111,7 +122,7 @@ unsafe impl GlobalAlloc for SyscallAllocator {
     }
 }
 
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 static ALLOCATOR: SyscallAllocator = SyscallAllocator;
 
 /// Expand variables in command line ($VAR, ${VAR}, ~)
@@ -159,9 +170,14 @@ fn expand_variables(cmd: &[u8], output: &mut [u8]) -> usize {
                 i += 1; // Skip {
             }
 
-            // Extract variable name
+            // Extract variable name ($?, $#, $0-$9 are single-character specials)
             let var_start = i;
-            while i < cmd_len {
+            let is_special = i < cmd_len && !is_braced
+                && (cmd[i] == b'?' || cmd[i] == b'#' || cmd[i].is_ascii_digit());
+            if is_special {
+                i += 1;
+            }
+            while i < cmd_len && !is_special {
                 let c = cmd[i];
                 if is_braced {
                     if c == b'}' {
@@ -183,8 +199,13 @@ fn expand_variables(cmd: &[u8], output: &mut [u8]) -> usize {
                 i += 1; // Skip closing }
             }
 
-            // Get variable value
-            if var_name.len() > 0 {
+            // Shell variables and special parameters take precedence over the environment
+            let shell_value = core::str::from_utf8(var_name).ok().and_then(script::lookup);
+            if let Some(value) = shell_value {
+                let copy_len = core::cmp::min(value.len(), output.len() - out_pos);
+                output[out_pos..out_pos + copy_len].copy_from_slice(&value.as_bytes()[..copy_len]);
+                out_pos += copy_len;
+            } else if var_name.len() > 0 {
                 static mut VAR_BUF: [u8; 256] = [0u8; 256];
                 let val_len = unsafe {
                     syscall4(
@@ -217,6 +238,32 @@ fn expand_variables(cmd: &[u8], output: &mut [u8]) -> usize {
     out_pos
 }
 
+/// Location of the persistent history file ($HOME/.history)
+fn history_path() -> String {
+    let mut home_buf = [0u8; 128];
+    let home_len = unsafe {
+        syscall4(
+            syscall::SYS_GETENV,
+            b"HOME".as_ptr() as u64,
+            4,
+            home_buf.as_mut_ptr() as u64,
+            home_buf.len() as u64
+        ) as usize
+    };
+
+    let mut path = if home_len > 0 && home_len < home_buf.len() {
+        String::from(core::str::from_utf8(&home_buf[..home_len]).unwrap_or("/"))
+    } else {
+        String::from("/")
+    };
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(".history");
+    path
+}
+
+#[cfg(not(test))]
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     write_str("\r\n");
@@ -230,7 +277,16 @@ pub extern "C" fn _start() -> ! {
     readline.add_completer(Box::new(ShellCompleter::new()));
     readline.set_mode(EditMode::Emacs); // Default to emacs mode
 
+    // Restore history from previous sessions
+    let history_path = history_path();
+    readline.load_history(&history_path);
+
+    let mut jobs = JobTable::new();
+
     loop {
+        // Report background jobs that finished since the last prompt
+        jobs.notify();
+
         // Read line with full editing support
         let line = match readline.readline("$ ") {
             Ok(line) => line,
@@ -251,153 +307,572 @@ pub extern "C" fn _start() -> ! {
         if trimmed.is_empty() {
             continue;
         }
+        readline.append_history(&history_path, trimmed);
+
+        // Keep reading while an if/while block is still open
+        let mut text = String::from(trimmed);
+        let mut interrupted = false;
+        while script::needs_more(&text) {
+            match readline.readline("> ") {
+                Ok(more) => {
+                    readline.append_history(&history_path, more.trim());
+                    text.push('\n');
+                    text.push_str(&more);
+                }
+                Err(_) => {
+                    interrupted = true;
+                    break;
+                }
+            }
+        }
+        if interrupted {
+            continue;
+        }
 
-        // Expand variables ($VAR, ${VAR}, ~)
-        let cmd_bytes = trimmed.as_bytes();
-        let mut expanded_buf = [0u8; 256];
-        let expanded_len = expand_variables(cmd_bytes, &mut expanded_buf);
-        let cmd = &expanded_buf[..expanded_len];
-
-        // Built-in commands
-        if cmd == b"help" {
-            write_str("Available commands:\r\n");
-            write_str("  help         - Show this help\r\n");
-            write_str("  clear        - Clear screen\r\n");
-            write_str("  exit         - Exit shell\r\n");
-            write_str("  echo         - Echo text\r\n");
-            write_str("  ls           - List files\r\n");
-            write_str("  pwd          - Print working directory\r\n");
-            write_str("  cd           - Change directory\r\n");
-            write_str("  uname        - System information\r\n");
-            write_str("  ps           - Process list\r\n");
-            write_str("  date         - Show date/time\r\n");
-            write_str("  export VAR=VALUE - Set environment variable\r\n");
-            write_str("  unset VAR    - Unset environment variable\r\n");
-            write_str("  env          - List environment variables\r\n");
-            write_str("  set          - List environment variables\r\n");
-            write_str("  set -o vi    - Switch to vi editing mode\r\n");
-            write_str("  set -o emacs - Switch to emacs editing mode\r\n");
-            write_str("\r\n");
-        } else if cmd == b"exit" {
-            write_str("Goodbye!\r\n");
-            exit(0);
-        } else if cmd == b"clear" {
-            // ANSI clear screen
-            write_str("\x1b[2J\x1b[H");
-        } else if cmd.starts_with(b"echo ") || cmd.starts_with(b"echo\t") {
-            // echo - print arguments (already expanded)
-            let text = &cmd[5..]; // Skip "echo "
-            unsafe {
-                syscall3(syscall::SYS_WRITE, 1, text.as_ptr() as u64, text.len() as u64);
+        let nodes = match script::parse(&text) {
+            Ok(nodes) => nodes,
+            Err(err) => {
+                report_parse_error(err);
+                script::set_last_status(2);
+                continue;
+            }
+        };
+        script::execute(&nodes, &mut |line: &str| run_line(line, &mut readline, &mut jobs));
+    }
+}
+
+fn report_parse_error(err: script::ParseError) {
+    write_str("syntax error: ");
+    write_str(match err {
+        script::ParseError::Incomplete => "unexpected end of input",
+        script::ParseError::Syntax(msg) => msg,
+    });
+    write_str("\r\n");
+}
+
+/// Expand and run one statement: a pipeline, a background job or a simple command
+/// Returns the exit status
+fn run_line(line: &str, readline: &mut Readline, jobs: &mut JobTable) -> i32 {
+    // Expand variables ($VAR, ${VAR}, $?, ~)
+    let cmd_bytes = line.trim().as_bytes();
+    let mut expanded_buf = [0u8; 512];
+    let expanded_len = expand_variables(cmd_bytes, &mut expanded_buf);
+    let cmd = &expanded_buf[..expanded_len];
+
+    // Background job: the whole line (including any pipeline) runs as one job
+    if cmd.ends_with(b"&") {
+        let job_cmd = core::str::from_utf8(&cmd[..cmd.len() - 1]).unwrap_or("").trim();
+        if job_cmd.is_empty() {
+            write_str("syntax error near unexpected token '&'\r\n");
+            return 2;
+        }
+        let argv = match words::split(job_cmd) {
+            Ok(argv) => argv,
+            Err(msg) => {
+                write_str("syntax error: ");
+                write_str(msg);
+                write_str("\r\n");
+                return 2;
             }
+        };
+        if jobs.launch_background(&argv, job_cmd).is_err() {
+            write_str("Command not found: ");
+            write_str(&argv[0]);
             write_str("\r\n");
-        } else if cmd.starts_with(b"export ") || cmd.starts_with(b"export\t") {
-            // export VAR=VALUE
-            let var_part = &cmd[7..]; // Skip "export "
-            if let Some(eq_pos) = var_part.iter().position(|&c| c == b'=') {
-                let key = &var_part[..eq_pos];
-                let value = &var_part[eq_pos + 1..];
-
-                let result = unsafe {
-                    syscall4(
-                        syscall::SYS_SETENV,
-                        key.as_ptr() as u64,
-                        key.len() as u64,
-                        value.as_ptr() as u64,
-                        value.len() as u64
-                    )
-                };
+            return 127;
+        }
+        return 0;
+    }
 
-                if result != 0 {
-                    write_str("export: failed to set variable\r\n");
+    // Split into pipeline stages with their redirections
+    let cmd_str = match core::str::from_utf8(cmd) {
+        Ok(s) => s,
+        Err(_) => return 1,
+    };
+    match pipeline::parse(cmd_str) {
+        Ok(stages) if stages.len() == 1 && stages[0].redirects.is_empty() => {
+            run_command(cmd, readline, jobs, None)
+        }
+        Ok(stages) => {
+            let mut group = StageGroup::default();
+            let started = pipeline::run(&stages, |stage| {
+                let status = run_command(stage.as_bytes(), readline, jobs, Some(&mut group));
+                match group.take_spawned() {
+                    Some(pid) => Started::Running(pid),
+                    None => Started::Finished(status),
                 }
-            } else {
-                write_str("export: usage: export VAR=VALUE\r\n");
-            }
-        } else if cmd.starts_with(b"unset ") || cmd.starts_with(b"unset\t") {
-            // unset VAR
-            let var_name = &cmd[6..]; // Skip "unset "
-            let result = unsafe {
-                syscall2(
-                    syscall::SYS_UNSETENV,
-                    var_name.as_ptr() as u64,
-                    var_name.len() as u64
-                )
-            };
+            });
+            jobs.wait_pipeline(&started, group.pgid, cmd_str)
+        }
+        Err(msg) => {
+            write_str("syntax error: ");
+            write_str(msg);
+            write_str("\r\n");
+            2
+        }
+    }
+}
 
-            if result != 0 {
-                write_str("unset: failed to unset variable\r\n");
+/// Run a single command (builtin or external) with the current stdio
+/// An external one in a pipeline is only started, into `group`
+/// Returns the exit status
+fn run_command(cmd: &[u8], readline: &mut Readline, jobs: &mut JobTable, group: Option<&mut StageGroup>) -> i32 {
+    let cmd_str = core::str::from_utf8(cmd).unwrap_or("");
+    let words = match words::split(cmd_str) {
+        Ok(words) => words,
+        Err(msg) => {
+            write_str("syntax error: ");
+            write_str(msg);
+            write_str("\r\n");
+            return 2;
+        }
+    };
+    let args: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    // Built-in commands
+    if cmd == b"help" {
+        write_str("Available commands:\r\n");
+        write_str("  help         - Show this help\r\n");
+        write_str("  clear        - Clear screen\r\n");
+        write_str("  exit [N]     - Exit shell (or script) with status N\r\n");
+        write_str("  echo         - Echo text\r\n");
+        write_str("  ls           - List files\r\n");
+        write_str("  pwd          - Print working directory\r\n");
+        write_str("  cd           - Change directory\r\n");
+        write_str("  uname        - System information\r\n");
+        write_str("  ps           - Process list\r\n");
+        write_str("  date         - Show date/time\r\n");
+        write_str("  VAR=VALUE    - Set shell variable\r\n");
+        write_str("  export VAR=VALUE - Set environment variable\r\n");
+        write_str("  unset VAR    - Unset environment variable\r\n");
+        write_str("  env          - List environment variables\r\n");
+        write_str("  set          - List environment and shell variables\r\n");
+        write_str("  set -o vi    - Switch to vi editing mode\r\n");
+        write_str("  set -o emacs - Switch to emacs editing mode\r\n");
+        write_str("  cmd &        - Run command in the background\r\n");
+        write_str("  jobs         - List background and stopped jobs\r\n");
+        write_str("  fg [%n]      - Bring job to the foreground\r\n");
+        write_str("  bg [%n]      - Resume stopped job in the background\r\n");
+        write_str("  ulimit -c [N|unlimited] - Core file size limit (512-byte blocks)\r\n");
+        write_str("  renice [-n] N PID... - Set scheduling niceness (-20 favoured .. 19)\r\n");
+        write_str("  screenshot [FILE] - Save the screen (.png or .bmp, default screen.bmp)\r\n");
+        write_str("  setfont [-s N] [FILE.psf] - Console font (PSF2) and scale; no file = built-in\r\n");
+        write_str("  vidmode [WxH] - List display modes, or switch resolution\r\n");
+        write_str("  cmd > file   - Redirect output (>> appends, 2> stderr, < input)\r\n");
+        write_str("  cmd1 | cmd2  - Pipe output of cmd1 into cmd2\r\n");
+        write_str("  if/then/elif/else/fi, while/until/do/done - Control flow\r\n");
+        write_str("  test EXPR, [ EXPR ] - Evaluate condition ($? holds the status)\r\n");
+        write_str("  sh FILE [ARGS], source FILE - Run a script\r\n");
+        write_str("\r\n");
+        0
+    } else if args.first() == Some(&"exit") {
+        let code = match args.get(1) {
+            Some(n) => n.parse().unwrap_or(2),
+            None => script::last_status(),
+        };
+        if script::in_script() {
+            // Only end the script, not the shell running it
+            script::request_exit(code);
+            return code;
+        }
+        write_str("Goodbye!\r\n");
+        exit(code);
+    } else if cmd == b"true" || cmd == b":" {
+        0
+    } else if cmd == b"false" {
+        1
+    } else if args.first() == Some(&"test") || args.first() == Some(&"[") {
+        let expr = if args[0] == "[" {
+            if args.last() != Some(&"]") {
+                write_str("[: missing ']'\r\n");
+                return 2;
             }
-        } else if cmd == b"set -o vi" {
-            readline.set_mode(EditMode::Vi);
-            write_str("Switched to vi editing mode\r\n");
-        } else if cmd == b"set -o emacs" {
-            readline.set_mode(EditMode::Emacs);
-            write_str("Switched to emacs editing mode\r\n");
-        } else if cmd == b"env" || cmd == b"set" {
-            // List all environment variables
-            static mut ENV_BUF: [u8; 4096] = [0u8; 4096];
-
-            let num_vars = unsafe {
-                syscall2(
-                    syscall::SYS_LISTENV,
-                    ENV_BUF.as_mut_ptr() as u64,
-                    ENV_BUF.len() as u64
-                )
-            };
+            &args[1..args.len() - 1]
+        } else {
+            &args[1..]
+        };
+        script::test(expr)
+    } else if args.len() == 1 && is_assignment(args[0]) {
+        // NAME=value sets a shell variable
+        let (name, value) = args[0].split_once('=').unwrap_or((args[0], ""));
+        script::set_var(name, value);
+        0
+    } else if cmd == b"clear" {
+        // ANSI clear screen
+        write_str("\x1b[2J\x1b[H");
+        0
+    } else if cmd == b"echo" {
+        write_str("\r\n");
+        0
+    } else if args.first() == Some(&"echo") {
+        // echo - print arguments (already expanded and unquoted)
+        write_str(&args[1..].join(" "));
+        write_str("\r\n");
+        0
+    } else if cmd.starts_with(b"export ") || cmd.starts_with(b"export\t") {
+        // export VAR=VALUE, or export VAR to promote a shell variable
+        let var_part = &cmd[7..]; // Skip "export "
+        let (key, value) = match var_part.iter().position(|&c| c == b'=') {
+            Some(eq_pos) => (&var_part[..eq_pos], String::from_utf8_lossy(&var_part[eq_pos + 1..]).into_owned()),
+            None => {
+                let name = core::str::from_utf8(var_part).unwrap_or("").trim();
+                match script::lookup(name).filter(|_| script::is_identifier(name)) {
+                    Some(v) => (name.as_bytes(), v),
+                    None => {
+                        write_str("export: usage: export VAR=VALUE\r\n");
+                        return 2;
+                    }
+                }
+            }
+        };
 
-            if num_vars > 0 {
-                // Parse null-separated strings
-                let mut offset = 0;
-                unsafe {
-                    for _ in 0..num_vars {
-                        if offset >= ENV_BUF.len() {
-                            break;
-                        }
-
-                        // Find null terminator
-                        let mut len = 0;
-                        while offset + len < ENV_BUF.len() && ENV_BUF[offset + len] != 0 {
-                            len += 1;
-                        }
-
-                        if len > 0 {
-                            syscall3(syscall::SYS_WRITE, 1, ENV_BUF[offset..].as_ptr() as u64, len as u64);
-                            write_str("\r\n");
-                        }
-
-                        offset += len + 1; // Skip string + null terminator
+        let result = unsafe {
+            syscall4(
+                syscall::SYS_SETENV,
+                key.as_ptr() as u64,
+                key.len() as u64,
+                value.as_ptr() as u64,
+                value.len() as u64
+            )
+        };
+
+        if result != 0 {
+            write_str("export: failed to set variable\r\n");
+            return 1;
+        }
+        if let Ok(name) = core::str::from_utf8(key) {
+            script::unset_var(name);
+        }
+        0
+    } else if cmd.starts_with(b"unset ") || cmd.starts_with(b"unset\t") {
+        // unset VAR (shell variable first, then environment)
+        let var_name = &cmd[6..]; // Skip "unset "
+        if core::str::from_utf8(var_name).map(script::unset_var).unwrap_or(false) {
+            return 0;
+        }
+        let result = unsafe {
+            syscall2(
+                syscall::SYS_UNSETENV,
+                var_name.as_ptr() as u64,
+                var_name.len() as u64
+            )
+        };
+
+        if result != 0 {
+            write_str("unset: failed to unset variable\r\n");
+            return 1;
+        }
+        0
+    } else if cmd == b"set -o vi" {
+        readline.set_mode(EditMode::Vi);
+        write_str("Switched to vi editing mode\r\n");
+        0
+    } else if cmd == b"set -o emacs" {
+        readline.set_mode(EditMode::Emacs);
+        write_str("Switched to emacs editing mode\r\n");
+        0
+    } else if cmd == b"env" || cmd == b"set" {
+        // List all environment variables
+        static mut ENV_BUF: [u8; 4096] = [0u8; 4096];
+
+        let num_vars = unsafe {
+            syscall2(
+                syscall::SYS_LISTENV,
+                ENV_BUF.as_mut_ptr() as u64,
+                ENV_BUF.len() as u64
+            )
+        };
+
+        if num_vars > 0 {
+            // Parse null-separated strings
+            let mut offset = 0;
+            unsafe {
+                for _ in 0..num_vars {
+                    if offset >= ENV_BUF.len() {
+                        break;
+                    }
+
+                    // Find null terminator
+                    let mut len = 0;
+                    while offset + len < ENV_BUF.len() && ENV_BUF[offset + len] != 0 {
+                        len += 1;
+                    }
+
+                    if len > 0 {
+                        syscall3(syscall::SYS_WRITE, 1, ENV_BUF[offset..].as_ptr() as u64, len as u64);
+                        write_str("\r\n");
                     }
+
+                    offset += len + 1; // Skip string + null terminator
                 }
             }
-        } else {
-            // Try to execute as external command
-            // Pass the full command line (command + arguments) to exec
-            let full_cmdline = cmd;
-            let cmdline_len = full_cmdline.len();
+        }
+
+        // `set` also shows shell-local variables
+        if cmd == b"set" {
+            script::for_each_var(|name, value| {
+                write_str(name);
+                write_str("=");
+                write_str(value);
+                write_str("\r\n");
+            });
+        }
+        0
+    } else if cmd == b"jobs" {
+        jobs.list();
+        0
+    } else if cmd == b"fg" || cmd.starts_with(b"fg ") {
+        jobs.foreground(core::str::from_utf8(&cmd[2..]).unwrap_or(""))
+    } else if cmd == b"bg" || cmd.starts_with(b"bg ") {
+        jobs.background(core::str::from_utf8(&cmd[2..]).unwrap_or(""));
+        0
+    } else if args.first() == Some(&"screenshot") {
+        let path = script::resolve_path(args.get(1).copied().unwrap_or("screen.bmp"));
+        if watos_syscall::syscalls::screenshot(&path) != 0 {
+            write_str("screenshot: cannot write ");
+            write_str(&path);
+            write_str("\r\n");
+            return 1;
+        }
+        write_str("Saved ");
+        write_str(&path);
+        write_str("\r\n");
+        0
+    } else if args.first() == Some(&"setfont") {
+        setfont(&args[1..])
+    } else if args.first() == Some(&"vidmode") {
+        vidmode(&args[1..])
+    } else if args.first() == Some(&"ulimit") {
+        ulimit(&args[1..])
+    } else if args.first() == Some(&"renice") {
+        renice(&args[1..])
+    } else if matches!(args.first(), Some(&"sh") | Some(&"source") | Some(&".")) {
+        // Run a script file in this shell
+        let path = match args.get(1) {
+            Some(p) => *p,
+            None => {
+                write_str(args[0]);
+                write_str(": usage: ");
+                write_str(args[0]);
+                write_str(" FILE [ARGS]\r\n");
+                return 2;
+            }
+        };
+        let script_args = args[1..].iter().map(|a| String::from(*a)).collect();
+        match script::run_file(path, script_args, &mut |line: &str| run_line(line, readline, jobs)) {
+            Ok(status) => status,
+            Err(msg) => {
+                write_str(path);
+                write_str(": ");
+                write_str(msg);
+                write_str("\r\n");
+                if msg == "cannot open script" { 127 } else { 2 }
+            }
+        }
+    } else if let Some(text) = args.first().and_then(|name| script::load_script(name)) {
+        // `./setup.sh` or a file with a `#!/bin/sh` line
+        let script_args = args.iter().map(|a| String::from(*a)).collect();
+        match script::run_script(&text, script_args, &mut |line: &str| run_line(line, readline, jobs)) {
+            Ok(status) => status,
+            Err(err) => {
+                report_parse_error(err);
+                2
+            }
+        }
+    } else if args.is_empty() {
+        0
+    } else if let Some(group) = group {
+        // A pipeline stage runs alongside the others (see `JobTable::wait_pipeline`)
+        jobs.spawn_stage(&words, group).unwrap_or_else(|_| command_not_found(args[0]))
+    } else if let Ok(status) = jobs.launch_foreground(&words, cmd_str) {
+        // Ran as a foreground job (can be stopped with Ctrl+Z)
+        status
+    } else {
+        command_not_found(args[0])
+    }
+}
 
-            let result = unsafe {
-                syscall2(syscall::SYS_EXEC, full_cmdline.as_ptr() as u64, cmdline_len as u64)
+fn command_not_found(name: &str) -> i32 {
+    write_str("Command not found: ");
+    write_str(name);
+    write_str("\r\n");
+    127
+}
+
+/// `ulimit -c [N|unlimited]`: show or set the core file size limit
+/// Sizes are in 512-byte blocks, as in other shells.
+fn ulimit(args: &[&str]) -> i32 {
+    use watos_syscall::rlimit::{RLIMIT_CORE, RLIM_INFINITY};
+    use watos_syscall::syscalls::{getrlimit, setrlimit};
+
+    let value = match args {
+        ["-c"] => None,
+        ["-c", value] => Some(*value),
+        _ => {
+            write_str("ulimit: usage: ulimit -c [N|unlimited]\r\n");
+            return 2;
+        }
+    };
+
+    match value {
+        None => {
+            match getrlimit(RLIMIT_CORE) {
+                Some(RLIM_INFINITY) => write_str("unlimited\r\n"),
+                Some(bytes) => write_str(&alloc::format!("{}\r\n", bytes / 512)),
+                None => {
+                    write_str("ulimit: cannot read core limit\r\n");
+                    return 1;
+                }
+            }
+            0
+        }
+        Some(value) => {
+            let limit = match value {
+                "unlimited" => RLIM_INFINITY,
+                n => match n.parse::<u64>() {
+                    Ok(blocks) => blocks.saturating_mul(512),
+                    Err(_) => {
+                        write_str("ulimit: invalid limit: ");
+                        write_str(n);
+                        write_str("\r\n");
+                        return 1;
+                    }
+                },
             };
+            if setrlimit(RLIMIT_CORE, limit) != 0 {
+                write_str("ulimit: cannot set core limit\r\n");
+                return 1;
+            }
+            0
+        }
+    }
+}
 
-            if result != 0 {
-                // Command failed to execute
-                // Extract just the command name for error message
-                let cmd_name_end = full_cmdline.iter()
-                    .position(|&c| c == b' ')
-                    .unwrap_or(cmdline_len);
-                let cmd_name = &full_cmdline[..cmd_name_end];
+/// `setfont [-s N] [FILE]`: load a PSF2 console font, or the built-in one
+fn setfont(args: &[&str]) -> i32 {
+    let (scale, rest) = match args {
+        ["-s", n, rest @ ..] => match n.parse::<u32>() {
+            Ok(scale) if (1..=8).contains(&scale) => (scale, rest),
+            _ => {
+                write_str("setfont: scale must be 1-8\r\n");
+                return 2;
+            }
+        },
+        _ => (1, args),
+    };
+    let path = match rest {
+        [] => None,
+        [file] => Some(script::resolve_path(file)),
+        _ => {
+            write_str("setfont: usage: setfont [-s N] [FILE.psf]\r\n");
+            return 2;
+        }
+    };
 
-                write_str("Command not found: ");
-                unsafe {
-                    syscall3(syscall::SYS_WRITE, 1, cmd_name.as_ptr() as u64, cmd_name.len() as u64);
+    match watos_syscall::syscalls::setfont(path.as_deref(), scale) {
+        Some((cols, rows)) => {
+            write_str(&alloc::format!("Console is now {}x{}\r\n", cols, rows));
+            0
+        }
+        None => {
+            write_str("setfont: cannot load ");
+            write_str(path.as_deref().unwrap_or("built-in font"));
+            write_str("\r\n");
+            1
+        }
+    }
+}
+
+/// `vidmode [WxH]`: list the display modes, or switch to one
+fn vidmode(args: &[&str]) -> i32 {
+    use watos_syscall::syscalls;
+
+    match args {
+        [] => {
+            let (width, height, _) = syscalls::fb_dimensions();
+            let mut modes = [(0u32, 0u32); 32];
+            let count = syscalls::fb_modes(&mut modes);
+            for &(w, h) in &modes[..count] {
+                let mark = if (w, h) == (width, height) { " *" } else { "" };
+                write_str(&alloc::format!("{}x{}{}\r\n", w, h, mark));
+            }
+            0
+        }
+        [mode] => {
+            let size = mode.split_once(['x', 'X'])
+                .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)));
+            let Some((width, height)) = size else {
+                write_str("vidmode: usage: vidmode [WIDTHxHEIGHT]\r\n");
+                return 2;
+            };
+            match syscalls::fb_set_mode(width, height) {
+                Some((w, h, _)) => {
+                    write_str(&alloc::format!("Display is now {}x{}\r\n", w, h));
+                    0
                 }
-                write_str("\r\n");
+                None => {
+                    write_str("vidmode: mode not available\r\n");
+                    1
+                }
+            }
+        }
+        _ => {
+            write_str("vidmode: usage: vidmode [WIDTHxHEIGHT]\r\n");
+            2
+        }
+    }
+}
+
+/// `renice [-n] N PID...`: set the niceness of processes
+fn renice(args: &[&str]) -> i32 {
+    use watos_syscall::syscalls;
+
+    let args = match args.first() {
+        Some(&"-n") => &args[1..],
+        _ => args,
+    };
+    let (nice, pids) = match args {
+        [nice, pids @ ..] if !pids.is_empty() => match nice.parse::<i32>() {
+            Ok(nice) => (nice, pids),
+            Err(_) => {
+                write_str("renice: usage: renice [-n] N PID...\r\n");
+                return 2;
             }
+        },
+        _ => {
+            write_str("renice: usage: renice [-n] N PID...\r\n");
+            return 2;
         }
+    };
+
+    let mut status = 0;
+    for pid in pids {
+        let Ok(pid) = pid.parse::<u32>() else {
+            write_str(&alloc::format!("renice: {}: not a pid\r\n", pid));
+            status = 1;
+            continue;
+        };
+        let old = syscalls::getpriority(pid);
+        if !syscalls::setpriority(pid, nice) {
+            write_str(&alloc::format!("renice: {}: no such process or permission denied\r\n", pid));
+            status = 1;
+            continue;
+        }
+        let new = syscalls::getpriority(pid).unwrap_or(nice);
+        write_str(&alloc::format!("{} (process ID) old priority {}, new priority {}\r\n", pid, old.unwrap_or(0), new));
+    }
+    status
+}
+
+/// `NAME=value` with a valid variable name
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => script::is_identifier(name),
+        None => false,
     }
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
@@ -0,0 +1,289 @@
+//! Pipelines and I/O redirection
+//!
+//! Parses `cmd1 | cmd2 > out` style lines and wires up stdio with
+//! SYS_OPEN, SYS_DUP2 and SYS_CLOSE before running each stage:
+//! - `cmd > file` / `cmd >> file` redirect stdout (truncate / append)
+//! - `cmd 2> file` redirects stderr
+//! - `cmd < file` reads stdin from a file
+//! - `a | b` connects stdout of `a` to stdin of `b`
+//!
+//! Stages run at the same time, each stage's stdout the write end of a
+//! SYS_PIPE whose read end is the next stage's stdin, so `yes | head` ends
+//! when head does. The pipes are O_CLOEXEC, so a stage inherits only the
+//! ends dup'ed onto its fd 0 and 1 and the reader sees EOF once its writer
+//! exits. Stages start from the last one back, so that a builtin, which runs
+//! in the shell, has its reader before it writes. Closing a redirected
+//! fd 0-2 restores the console.
+//!
+//! A quoted `|`, `<` or `>` is an ordinary character (see `words`).
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use watos_syscall::{open, syscalls};
+
+use crate::words;
+
+/// SYS_OPEN flags for each kind of redirect
+const OPEN_READ: u32 = open::O_RDONLY;
+const OPEN_WRITE: u32 = open::O_WRONLY | open::O_CREAT | open::O_TRUNC;
+const OPEN_APPEND: u32 = open::O_WRONLY | open::O_CREAT | open::O_APPEND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `< file`
+    Input,
+    /// `> file`
+    Output,
+    /// `>> file`
+    Append,
+    /// `2> file`
+    Error,
+}
+
+impl RedirectKind {
+    fn target_fd(&self) -> i32 {
+        match self {
+            RedirectKind::Input => 0,
+            RedirectKind::Output | RedirectKind::Append => 1,
+            RedirectKind::Error => 2,
+        }
+    }
+
+    fn open_mode(&self) -> u32 {
+        match self {
+            RedirectKind::Input => OPEN_READ,
+            RedirectKind::Output | RedirectKind::Error => OPEN_WRITE,
+            RedirectKind::Append => OPEN_APPEND,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub kind: RedirectKind,
+    pub path: String,
+}
+
+/// One stage of a pipeline: the command text with redirections stripped
+/// out, its words still quoted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub line: String,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Split a line into pipeline stages
+pub fn parse(line: &str) -> Result<Vec<Command>, &'static str> {
+    let mut stages = Vec::new();
+    for part in words::split_unquoted(line, b'|')? {
+        let stage = parse_stage(part)?;
+        if stage.line.is_empty() {
+            return Err("empty command in pipeline");
+        }
+        stages.push(stage);
+    }
+    Ok(stages)
+}
+
+fn parse_stage(text: &str) -> Result<Command, &'static str> {
+    let mut kept: Vec<&str> = Vec::new();
+    let mut redirects = Vec::new();
+    let mut tokens = words::raw_words(text)?.into_iter();
+
+    while let Some(tok) = tokens.next() {
+        let (kind, rest) = if let Some(rest) = tok.strip_prefix("2>") {
+            (RedirectKind::Error, rest)
+        } else if let Some(rest) = tok.strip_prefix(">>") {
+            (RedirectKind::Append, rest)
+        } else if let Some(rest) = tok.strip_prefix('>') {
+            (RedirectKind::Output, rest)
+        } else if let Some(rest) = tok.strip_prefix('<') {
+            (RedirectKind::Input, rest)
+        } else {
+            kept.push(tok);
+            continue;
+        };
+
+        // Accept both `> file` and `>file`
+        let path = if rest.is_empty() {
+            tokens.next().ok_or("missing file name after redirection")?
+        } else {
+            rest
+        };
+        if path.starts_with('>') || path.starts_with('<') {
+            return Err("unexpected redirection token");
+        }
+        redirects.push(Redirect {
+            kind,
+            path: words::unquote(path),
+        });
+    }
+
+    let mut line = String::new();
+    for (i, w) in kept.iter().enumerate() {
+        if i > 0 {
+            line.push(' ');
+        }
+        line.push_str(w);
+    }
+    Ok(Command { line, redirects })
+}
+
+fn write_err(s: &str) {
+    syscalls::write(2, s.as_bytes());
+}
+
+/// Point each redirected fd at its file; returns false if a file could not be opened
+fn apply_redirects(redirects: &[Redirect], redirected: &mut [bool; 3]) -> bool {
+    for r in redirects {
+        let fd = syscalls::open(&r.path, r.kind.open_mode());
+        if fd < 0 {
+            write_err("shell: cannot open ");
+            write_err(&r.path);
+            write_err("\r\n");
+            return false;
+        }
+        let target = r.kind.target_fd();
+        syscalls::dup2(fd, target);
+        syscalls::close(fd);
+        redirected[target as usize] = true;
+    }
+    true
+}
+
+/// Close any redirected standard fds so they fall back to the console
+fn restore_stdio(redirected: &[bool; 3]) {
+    for (fd, &was) in redirected.iter().enumerate() {
+        if was {
+            syscalls::close(fd as i32);
+        }
+    }
+}
+
+/// How a stage got going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Started {
+    /// It ran in the shell and finished with this exit status
+    Finished(i32),
+    /// It is running as this process, to be waited for
+    Running(u32),
+}
+
+/// Start every stage with its stdio wired up, calling `start` with the command text
+/// Returns how each stage started, in pipeline order
+pub fn run<F: FnMut(&str) -> Started>(stages: &[Command], mut start: F) -> Vec<Started> {
+    // pipes[i] carries stage i's output to stage i + 1
+    let mut pipes: Vec<(i32, i32)> = Vec::new();
+    for _ in 1..stages.len() {
+        match syscalls::pipe2(open::O_CLOEXEC) {
+            Some(ends) => pipes.push(ends),
+            None => {
+                for &(read_fd, write_fd) in &pipes {
+                    syscalls::close(read_fd);
+                    syscalls::close(write_fd);
+                }
+                write_err("shell: cannot create pipe\r\n");
+                return vec![Started::Finished(1)];
+            }
+        }
+    }
+
+    let mut started = Vec::with_capacity(stages.len());
+    for (i, stage) in stages.iter().enumerate().rev() {
+        let mut redirected = [false; 3];
+        let input = i.checked_sub(1).map(|prev| pipes[prev].0);
+        let output = pipes.get(i).map(|&(_, write_fd)| write_fd);
+
+        if let Some(read_fd) = input {
+            syscalls::dup2(read_fd, 0);
+            redirected[0] = true;
+        }
+        if let Some(write_fd) = output {
+            syscalls::dup2(write_fd, 1);
+            redirected[1] = true;
+        }
+
+        started.push(if apply_redirects(&stage.redirects, &mut redirected) {
+            start(&stage.line)
+        } else {
+            Started::Finished(1)
+        });
+
+        // The stage has its ends now; the shell's copies would keep a
+        // reader from seeing EOF and a writer from seeing a broken pipe
+        restore_stdio(&redirected);
+        for fd in input.into_iter().chain(output) {
+            syscalls::close(fd);
+        }
+    }
+
+    started.reverse();
+    started
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn redirect(kind: RedirectKind, path: &str) -> Redirect {
+        Redirect { kind, path: path.into() }
+    }
+
+    #[test]
+    fn test_parse_stages() {
+        let stages = parse("cat big.txt | grep x|wc").unwrap();
+        let lines: Vec<&str> = stages.iter().map(|s| s.line.as_str()).collect();
+        assert_eq!(lines, vec!["cat big.txt", "grep x", "wc"]);
+        assert!(stages.iter().all(|s| s.redirects.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_quoted_pipe() {
+        let stages = parse(r#"grep "a|b" 'c | d' | wc"#).unwrap();
+        assert_eq!(stages.len(), 2);
+        // Words stay quoted for the caller to split
+        assert_eq!(stages[0].line, r#"grep "a|b" 'c | d'"#);
+        assert_eq!(stages[1].line, "wc");
+        assert_eq!(parse(r#"echo a\|b"#).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_empty_stage() {
+        assert_eq!(parse("ls |").unwrap_err(), "empty command in pipeline");
+        assert_eq!(parse("| wc").unwrap_err(), "empty command in pipeline");
+        assert_eq!(parse("ls || wc").unwrap_err(), "empty command in pipeline");
+        assert_eq!(parse("ls | > out").unwrap_err(), "empty command in pipeline");
+    }
+
+    #[test]
+    fn test_parse_redirects() {
+        let stages = parse("sort < in.txt > out.txt 2>err.log").unwrap();
+        assert_eq!(stages[0].line, "sort");
+        assert_eq!(
+            stages[0].redirects,
+            vec![
+                redirect(RedirectKind::Input, "in.txt"),
+                redirect(RedirectKind::Output, "out.txt"),
+                redirect(RedirectKind::Error, "err.log"),
+            ]
+        );
+
+        let stages = parse(r#"echo hi >>"my log""#).unwrap();
+        assert_eq!(stages[0].redirects, vec![redirect(RedirectKind::Append, "my log")]);
+    }
+
+    #[test]
+    fn test_parse_quoted_redirect_is_a_word() {
+        let stages = parse(r#"echo ">" '<' a"#).unwrap();
+        assert_eq!(stages[0].line, r#"echo ">" '<' a"#);
+        assert!(stages[0].redirects.is_empty());
+    }
+
+    #[test]
+    fn test_parse_bad_redirects() {
+        assert_eq!(parse("ls >").unwrap_err(), "missing file name after redirection");
+        assert_eq!(parse("ls > > x").unwrap_err(), "unexpected redirection token");
+    }
+}
@@ -0,0 +1,727 @@
+//! Shell scripting
+//!
+//! A small interpreter layered over the command runner:
+//! - shell variables (`NAME=value`) and the specials `$?`, `$#`, `$0`-`$9`
+//! - `if/then/elif/else/fi` and `while|until/do/done`
+//! - `;` and newlines as statement separators, `#` comments
+//! - running script files (`sh file`, `source file`, `./file.sh`, `#!` scripts)
+//! - `test` / `[ ... ]` for conditions
+//!
+//! Statements are expanded and executed one at a time by the caller-supplied
+//! runner, so `$VAR` inside a loop body sees the value from the current iteration.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use watos_syscall::syscalls;
+
+// ============================================================================
+// Variables and exit status
+// ============================================================================
+
+/// Shell-local variables (not exported to the environment)
+static mut SHELL_VARS: Vec<(String, String)> = Vec::new();
+/// Positional parameters of the running script ($0, $1, ...)
+static mut POSITIONAL: Vec<String> = Vec::new();
+/// Exit status of the last command ($?)
+static mut LAST_STATUS: i32 = 0;
+/// Nesting depth of script files being executed
+static mut SCRIPT_DEPTH: u32 = 0;
+/// Set by `exit` inside a script; unwinds to the script's caller
+static mut EXIT_REQUEST: Option<i32> = None;
+
+/// Exit status of the last command
+pub fn last_status() -> i32 {
+    unsafe { LAST_STATUS }
+}
+
+pub fn set_last_status(status: i32) {
+    unsafe { LAST_STATUS = status; }
+}
+
+/// Whether we're currently executing a script file
+pub fn in_script() -> bool {
+    unsafe { SCRIPT_DEPTH > 0 }
+}
+
+/// Ask the running script to stop with `code`
+pub fn request_exit(code: i32) {
+    unsafe { EXIT_REQUEST = Some(code); }
+}
+
+fn take_exit_request() -> Option<i32> {
+    unsafe {
+        let request = EXIT_REQUEST;
+        EXIT_REQUEST = None;
+        request
+    }
+}
+
+/// Check that `name` is a valid variable name
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Set a shell variable
+pub fn set_var(name: &str, value: &str) {
+    unsafe {
+        let vars = &mut *core::ptr::addr_of_mut!(SHELL_VARS);
+        if let Some(entry) = vars.iter_mut().find(|(n, _)| n == name) {
+            entry.1 = String::from(value);
+        } else {
+            vars.push((String::from(name), String::from(value)));
+        }
+    }
+}
+
+/// Remove a shell variable; returns true if it existed
+pub fn unset_var(name: &str) -> bool {
+    unsafe {
+        let vars = &mut *core::ptr::addr_of_mut!(SHELL_VARS);
+        let before = vars.len();
+        vars.retain(|(n, _)| n != name);
+        vars.len() != before
+    }
+}
+
+/// Look up a special parameter or shell variable
+///
+/// Returns None if the name isn't a shell variable, in which case the
+/// caller falls back to the environment.
+pub fn lookup(name: &str) -> Option<String> {
+    unsafe {
+        let positional = &*core::ptr::addr_of!(POSITIONAL);
+        match name {
+            "?" => return Some(itoa(LAST_STATUS as i64)),
+            "#" => return Some(itoa(positional.len().saturating_sub(1) as i64)),
+            _ => {}
+        }
+        if let Ok(n) = name.parse::<usize>() {
+            return Some(positional.get(n).cloned().unwrap_or_default());
+        }
+        let vars = &*core::ptr::addr_of!(SHELL_VARS);
+        vars.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone())
+    }
+}
+
+/// Iterate shell variables as `NAME=value` lines
+pub fn for_each_var<F: FnMut(&str, &str)>(mut f: F) {
+    unsafe {
+        let vars = &*core::ptr::addr_of!(SHELL_VARS);
+        for (n, v) in vars.iter() {
+            f(n, v);
+        }
+    }
+}
+
+fn itoa(mut n: i64) -> String {
+    if n == 0 {
+        return String::from("0");
+    }
+    let neg = n < 0;
+    let mut digits = Vec::new();
+    while n != 0 {
+        digits.push(b'0' + (n % 10).unsigned_abs() as u8);
+        n /= 10;
+    }
+    if neg {
+        digits.push(b'-');
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap_or_default()
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    While,
+    Until,
+    Do,
+    Done,
+}
+
+impl Keyword {
+    fn from_word(word: &str) -> Option<Self> {
+        Some(match word {
+            "if" => Keyword::If,
+            "then" => Keyword::Then,
+            "elif" => Keyword::Elif,
+            "else" => Keyword::Else,
+            "fi" => Keyword::Fi,
+            "while" => Keyword::While,
+            "until" => Keyword::Until,
+            "do" => Keyword::Do,
+            "done" => Keyword::Done,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Keyword(Keyword),
+    Command(String),
+}
+
+/// A parsed script statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Command(String),
+    If {
+        branches: Vec<(Vec<Node>, Vec<Node>)>,
+        else_body: Option<Vec<Node>>,
+    },
+    While {
+        until: bool,
+        cond: Vec<Node>,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Input ended inside an if/while block
+    Incomplete,
+    Syntax(&'static str),
+}
+
+/// Split text into keyword and command tokens
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for line in text.lines() {
+        // Drop comments: '#' at the start of a word
+        let mut end = line.len();
+        let bytes = line.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            if b == b'#' && (i == 0 || bytes[i - 1] == b' ' || bytes[i - 1] == b'\t' || bytes[i - 1] == b';') {
+                end = i;
+                break;
+            }
+        }
+
+        for stmt in line[..end].split(';') {
+            let mut rest = stmt.trim();
+            // A statement may start with several keywords ("else if ...", "then echo")
+            while !rest.is_empty() {
+                let word_end = rest.find(|c: char| c.is_ascii_whitespace()).unwrap_or(rest.len());
+                match Keyword::from_word(&rest[..word_end]) {
+                    Some(kw) => {
+                        tokens.push(Token::Keyword(kw));
+                        rest = rest[word_end..].trim_start();
+                    }
+                    None => {
+                        tokens.push(Token::Command(String::from(rest)));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    tokens
+}
+
+/// Parse statements until one of `stop` (left unconsumed) or end of input
+fn parse_list(tokens: &[Token], pos: &mut usize, stop: &[Keyword]) -> Result<Vec<Node>, ParseError> {
+    let mut nodes = Vec::new();
+    loop {
+        let token = match tokens.get(*pos) {
+            Some(t) => t,
+            None if stop.is_empty() => return Ok(nodes),
+            None => return Err(ParseError::Incomplete),
+        };
+        match token {
+            Token::Command(cmd) => {
+                nodes.push(Node::Command(cmd.clone()));
+                *pos += 1;
+            }
+            Token::Keyword(kw) if stop.contains(kw) => return Ok(nodes),
+            Token::Keyword(Keyword::If) => {
+                *pos += 1;
+                nodes.push(parse_if(tokens, pos)?);
+            }
+            Token::Keyword(kw @ (Keyword::While | Keyword::Until)) => {
+                let until = *kw == Keyword::Until;
+                *pos += 1;
+                let cond = parse_condition(tokens, pos, Keyword::Do)?;
+                let body = parse_list(tokens, pos, &[Keyword::Done])?;
+                *pos += 1;
+                nodes.push(Node::While { until, cond, body });
+            }
+            Token::Keyword(kw) => {
+                return Err(ParseError::Syntax(match kw {
+                    Keyword::Then => "unexpected 'then'",
+                    Keyword::Elif => "unexpected 'elif'",
+                    Keyword::Else => "unexpected 'else'",
+                    Keyword::Fi => "unexpected 'fi'",
+                    Keyword::Do => "unexpected 'do'",
+                    Keyword::Done => "unexpected 'done'",
+                    _ => "unexpected keyword",
+                }));
+            }
+        }
+    }
+}
+
+/// Parse a condition list and the keyword that ends it
+fn parse_condition(tokens: &[Token], pos: &mut usize, end: Keyword) -> Result<Vec<Node>, ParseError> {
+    let cond = parse_list(tokens, pos, &[end])?;
+    if cond.is_empty() {
+        return Err(ParseError::Syntax(match end {
+            Keyword::Then => "missing condition before 'then'",
+            _ => "missing condition before 'do'",
+        }));
+    }
+    *pos += 1;
+    Ok(cond)
+}
+
+/// Parse the rest of an if statement (after `if`)
+fn parse_if(tokens: &[Token], pos: &mut usize) -> Result<Node, ParseError> {
+    let mut branches = Vec::new();
+    let mut else_body = None;
+    loop {
+        let cond = parse_condition(tokens, pos, Keyword::Then)?;
+        let body = parse_list(tokens, pos, &[Keyword::Elif, Keyword::Else, Keyword::Fi])?;
+        branches.push((cond, body));
+
+        let kw = match tokens.get(*pos) {
+            Some(Token::Keyword(kw)) => *kw,
+            _ => return Err(ParseError::Incomplete),
+        };
+        *pos += 1;
+        match kw {
+            Keyword::Elif => continue,
+            Keyword::Else => {
+                else_body = Some(parse_list(tokens, pos, &[Keyword::Fi])?);
+                *pos += 1;
+                break;
+            }
+            _ => break,
+        }
+    }
+    Ok(Node::If { branches, else_body })
+}
+
+/// Parse script text into statements
+pub fn parse(text: &str) -> Result<Vec<Node>, ParseError> {
+    let tokens = tokenize(text);
+    let mut pos = 0;
+    parse_list(&tokens, &mut pos, &[])
+}
+
+/// Whether `text` opens a block that hasn't been closed yet
+pub fn needs_more(text: &str) -> bool {
+    parse(text) == Err(ParseError::Incomplete)
+}
+
+// ============================================================================
+// Execution
+// ============================================================================
+
+/// How a statement list finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Normal(i32),
+    /// `exit` was run inside a script
+    Exit(i32),
+}
+
+/// Runs one simple command line and returns its exit status
+pub type Runner<'a> = dyn FnMut(&str) -> i32 + 'a;
+
+/// Execute parsed statements, returning the status of the last one
+pub fn execute(nodes: &[Node], run: &mut Runner) -> Flow {
+    let mut status = 0;
+    for node in nodes {
+        status = match node {
+            Node::Command(line) => {
+                let status = run(line);
+                set_last_status(status);
+                if let Some(code) = take_exit_request() {
+                    return Flow::Exit(code);
+                }
+                status
+            }
+            Node::If { branches, else_body } => {
+                let mut taken = None;
+                for (cond, body) in branches {
+                    match execute(cond, run) {
+                        Flow::Exit(code) => return Flow::Exit(code),
+                        Flow::Normal(0) => {
+                            taken = Some(body);
+                            break;
+                        }
+                        Flow::Normal(_) => {}
+                    }
+                }
+                match taken.or(else_body.as_ref()) {
+                    Some(body) => match execute(body, run) {
+                        Flow::Exit(code) => return Flow::Exit(code),
+                        Flow::Normal(s) => s,
+                    },
+                    None => 0,
+                }
+            }
+            Node::While { until, cond, body } => {
+                let mut last = 0;
+                loop {
+                    let passed = match execute(cond, run) {
+                        Flow::Exit(code) => return Flow::Exit(code),
+                        Flow::Normal(s) => (s == 0) != *until,
+                    };
+                    if !passed {
+                        break;
+                    }
+                    match execute(body, run) {
+                        Flow::Exit(code) => return Flow::Exit(code),
+                        Flow::Normal(s) => last = s,
+                    }
+                }
+                last
+            }
+        };
+    }
+    set_last_status(status);
+    Flow::Normal(status)
+}
+
+// ============================================================================
+// Script files
+// ============================================================================
+
+/// Read a whole file through the VFS
+fn read_file(path: &str) -> Option<String> {
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 512];
+    loop {
+        let n = syscalls::read(fd, &mut chunk);
+        if n == 0 || n > chunk.len() {
+            break;
+        }
+        data.extend_from_slice(&chunk[..n]);
+    }
+    syscalls::close(fd);
+    Some(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Make a relative path absolute using the current directory
+pub fn resolve_path(path: &str) -> String {
+    if path.starts_with('/') || path.contains(':') {
+        return String::from(path);
+    }
+    let mut buf = [0u8; 256];
+    let len = syscalls::getcwd(&mut buf);
+    let mut full = if len > 0 && len < buf.len() {
+        String::from(core::str::from_utf8(&buf[..len]).unwrap_or("/"))
+    } else {
+        String::from("/")
+    };
+    if !full.ends_with('/') {
+        full.push('/');
+    }
+    full.push_str(path.strip_prefix("./").unwrap_or(path));
+    full
+}
+
+/// Load `path` if it should be run by the shell: a `.sh` file or a `#!` script
+/// whose interpreter is the shell
+pub fn load_script(path: &str) -> Option<String> {
+    let is_sh = path.ends_with(".sh");
+    if !is_sh && !path.contains('/') {
+        return None;
+    }
+    let text = read_file(&resolve_path(path))?;
+    if let Some(shebang) = text.strip_prefix("#!") {
+        let interp = shebang.lines().next().unwrap_or("").trim();
+        let prog = interp.split_whitespace().next().unwrap_or("");
+        let name = prog.rsplit('/').next().unwrap_or(prog);
+        if name == "sh" || name == "shell" {
+            return Some(text);
+        }
+        return None;
+    }
+    if is_sh {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// Run script text with the given positional parameters
+///
+/// `args[0]` becomes `$0`. `exit` inside the script ends only the script.
+pub fn run_script(text: &str, args: Vec<String>, run: &mut Runner) -> Result<i32, ParseError> {
+    let nodes = parse(text)?;
+    unsafe {
+        let saved = core::mem::replace(&mut *core::ptr::addr_of_mut!(POSITIONAL), args);
+        SCRIPT_DEPTH += 1;
+        let flow = execute(&nodes, run);
+        SCRIPT_DEPTH -= 1;
+        *core::ptr::addr_of_mut!(POSITIONAL) = saved;
+        let status = match flow {
+            Flow::Normal(s) | Flow::Exit(s) => s,
+        };
+        set_last_status(status);
+        Ok(status)
+    }
+}
+
+/// Read and run a script file (`sh file args...`)
+pub fn run_file(path: &str, args: Vec<String>, run: &mut Runner) -> Result<i32, &'static str> {
+    let text = read_file(&resolve_path(path)).ok_or("cannot open script")?;
+    run_script(&text, args, run).map_err(|e| match e {
+        ParseError::Incomplete => "unexpected end of file",
+        ParseError::Syntax(msg) => msg,
+    })
+}
+
+// ============================================================================
+// test / [
+// ============================================================================
+
+fn parse_int(s: &str) -> Option<i64> {
+    s.trim().parse().ok()
+}
+
+/// Evaluate `test` arguments; returns 0 for true, 1 for false, 2 on error
+pub fn test(args: &[&str]) -> i32 {
+    fn status(b: bool) -> i32 {
+        if b { 0 } else { 1 }
+    }
+
+    if let Some((&"!", rest)) = args.split_first() {
+        return match test(rest) {
+            2 => 2,
+            s => 1 - s,
+        };
+    }
+
+    match args {
+        [] => 1,
+        [s] => status(!s.is_empty()),
+        [op, s] => match *op {
+            "-z" => status(s.is_empty()),
+            "-n" => status(!s.is_empty()),
+            "-e" => status(syscalls::stat(&resolve_path(s)).is_some()),
+            "-f" => status(matches!(syscalls::stat(&resolve_path(s)), Some((0, _)))),
+            "-d" => status(matches!(syscalls::stat(&resolve_path(s)), Some((1, _)))),
+            _ => 2,
+        },
+        [a, op, b] => match *op {
+            "=" | "==" => status(a == b),
+            "!=" => status(a != b),
+            "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                let (x, y) = match (parse_int(a), parse_int(b)) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => return 2,
+                };
+                status(match *op {
+                    "-eq" => x == y,
+                    "-ne" => x != y,
+                    "-lt" => x < y,
+                    "-le" => x <= y,
+                    "-gt" => x > y,
+                    _ => x >= y,
+                })
+            }
+            _ => 2,
+        },
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn cmd(line: &str) -> Node {
+        Node::Command(String::from(line))
+    }
+
+    /// Run `text`, answering each command with the status `status` picks,
+    /// and return the commands in the order they ran
+    fn trace(text: &str, mut status: impl FnMut(&str) -> i32) -> (Vec<String>, Flow) {
+        let nodes = parse(text).unwrap();
+        let mut ran = Vec::new();
+        let flow = execute(&nodes, &mut |line: &str| {
+            ran.push(String::from(line));
+            status(line)
+        });
+        (ran, flow)
+    }
+
+    #[test]
+    fn test_parse_separators_and_comments() {
+        let nodes = parse("echo a; echo b\n# whole line\necho c # trailing\necho d#e").unwrap();
+        assert_eq!(nodes, vec![cmd("echo a"), cmd("echo b"), cmd("echo c"), cmd("echo d#e")]);
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert_eq!(parse(" ; ;\n").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_parse_if_elif_else() {
+        let nodes = parse("if a; then b; elif c; then d; else e; fi").unwrap();
+        assert_eq!(nodes, vec![Node::If {
+            branches: vec![(vec![cmd("a")], vec![cmd("b")]), (vec![cmd("c")], vec![cmd("d")])],
+            else_body: Some(vec![cmd("e")]),
+        }]);
+
+        // Keywords may share a line with the command after them
+        let nodes = parse("if a\nthen b\nfi").unwrap();
+        assert_eq!(nodes, vec![Node::If {
+            branches: vec![(vec![cmd("a")], vec![cmd("b")])],
+            else_body: None,
+        }]);
+    }
+
+    #[test]
+    fn test_parse_loops() {
+        let nodes = parse("while a; do if b; then c; fi; done; until d; do e; done").unwrap();
+        assert_eq!(nodes, vec![
+            Node::While {
+                until: false,
+                cond: vec![cmd("a")],
+                body: vec![Node::If { branches: vec![(vec![cmd("b")], vec![cmd("c")])], else_body: None }],
+            },
+            Node::While { until: true, cond: vec![cmd("d")], body: vec![cmd("e")] },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("if a; then b"), Err(ParseError::Incomplete));
+        assert_eq!(parse("while a; do b"), Err(ParseError::Incomplete));
+        assert_eq!(parse("if a"), Err(ParseError::Incomplete));
+        assert_eq!(parse("fi"), Err(ParseError::Syntax("unexpected 'fi'")));
+        assert_eq!(parse("done"), Err(ParseError::Syntax("unexpected 'done'")));
+        assert_eq!(parse("if then b; fi"), Err(ParseError::Syntax("missing condition before 'then'")));
+        assert_eq!(parse("while do b; done"), Err(ParseError::Syntax("missing condition before 'do'")));
+        assert_eq!(parse("if a; then b; else c; else d; fi"), Err(ParseError::Syntax("unexpected 'else'")));
+    }
+
+    #[test]
+    fn test_needs_more() {
+        assert!(needs_more("if true; then"));
+        assert!(needs_more("while true; do echo x"));
+        assert!(!needs_more("if true; then echo x; fi"));
+        assert!(!needs_more("echo x"));
+        // A syntax error won't be fixed by more lines
+        assert!(!needs_more("fi"));
+    }
+
+    #[test]
+    fn test_execute_if_takes_first_true_branch() {
+        let (ran, flow) = trace("if a; then b; elif c; then d; else e; fi", |line| match line {
+            "a" => 1,
+            _ => 0,
+        });
+        assert_eq!(ran, vec!["a", "c", "d"]);
+        assert_eq!(flow, Flow::Normal(0));
+
+        let (ran, flow) = trace("if a; then b; fi", |_| 1);
+        assert_eq!(ran, vec!["a"]);
+        assert_eq!(flow, Flow::Normal(0));
+    }
+
+    #[test]
+    fn test_execute_while_and_until() {
+        let mut left = 3;
+        let (ran, _) = trace("while check; do body; done", |line| {
+            if line == "check" {
+                left -= 1;
+                if left >= 0 { 0 } else { 1 }
+            } else {
+                0
+            }
+        });
+        assert_eq!(ran, vec!["check", "body", "check", "body", "check", "body", "check"]);
+
+        let mut runs = 0;
+        let (ran, flow) = trace("until check; do body; done", |line| {
+            if line == "check" {
+                runs += 1;
+                if runs == 2 { 0 } else { 1 }
+            } else {
+                7
+            }
+        });
+        assert_eq!(ran, vec!["check", "body", "check"]);
+        // The status of a loop is that of the last body run
+        assert_eq!(flow, Flow::Normal(7));
+    }
+
+    #[test]
+    fn test_execute_exit_unwinds() {
+        let (ran, flow) = trace("while a; do b; quit; c; done; d", |line| {
+            if line == "quit" {
+                request_exit(3);
+            }
+            0
+        });
+        assert_eq!(ran, vec!["a", "b", "quit"]);
+        assert_eq!(flow, Flow::Exit(3));
+    }
+
+    #[test]
+    fn test_test_strings_and_numbers() {
+        assert_eq!(test(&[]), 1);
+        assert_eq!(test(&["x"]), 0);
+        assert_eq!(test(&[""]), 1);
+        assert_eq!(test(&["-z", ""]), 0);
+        assert_eq!(test(&["-n", ""]), 1);
+        assert_eq!(test(&["a", "=", "a"]), 0);
+        assert_eq!(test(&["a", "!=", "a"]), 1);
+        assert_eq!(test(&["2", "-lt", "10"]), 0);
+        assert_eq!(test(&["-3", "-ge", "-2"]), 1);
+        assert_eq!(test(&["!", "1", "-eq", "1"]), 1);
+        assert_eq!(test(&["!", "x"]), 1);
+    }
+
+    #[test]
+    fn test_test_errors() {
+        assert_eq!(test(&["a", "-eq", "1"]), 2);
+        assert_eq!(test(&["-q", "x"]), 2);
+        assert_eq!(test(&["a", "<>", "b"]), 2);
+        assert_eq!(test(&["a", "b", "c", "d"]), 2);
+        // Negating an error is still an error
+        assert_eq!(test(&["!", "a", "-gt", "b"]), 2);
+    }
+
+    #[test]
+    fn test_identifiers_and_itoa() {
+        assert!(is_identifier("_a1"));
+        assert!(!is_identifier("1a"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("a-b"));
+        assert_eq!(itoa(0), "0");
+        assert_eq!(itoa(-42), "-42");
+        assert_eq!(itoa(1234), "1234");
+    }
+
+    #[test]
+    fn test_variables() {
+        set_var("SCRIPT_TEST_VAR", "one");
+        set_var("SCRIPT_TEST_VAR", "two");
+        assert_eq!(lookup("SCRIPT_TEST_VAR").as_deref(), Some("two"));
+        assert!(unset_var("SCRIPT_TEST_VAR"));
+        assert!(!unset_var("SCRIPT_TEST_VAR"));
+        assert_eq!(lookup("SCRIPT_TEST_VAR"), None);
+    }
+}
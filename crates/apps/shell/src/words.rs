@@ -0,0 +1,172 @@
+//! Word splitting and quote removal
+//!
+//! A command line is split into words at unquoted whitespace:
+//! - `'...'` keeps everything inside literally
+//! - `"..."` keeps everything but `\"`, `\\` and `\$`, which lose the backslash
+//! - `\c` outside quotes keeps `c` literally
+//!
+//! so `grep "two words" 'a|b'` runs grep with two arguments. Splitting
+//! happens after variable expansion, and the words become the argv of
+//! SYS_EXECV instead of one space-joined string.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const UNTERMINATED: &str = "unterminated quote";
+
+/// Walk `text`, calling `visit(index, byte)` for each byte that is neither
+/// quoted nor escaped
+fn scan<F: FnMut(usize, u8)>(text: &str, mut visit: F) -> Result<(), &'static str> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' => {
+                i += 1;
+                while bytes.get(i).ok_or(UNTERMINATED)? != &b'\'' {
+                    i += 1;
+                }
+            }
+            b'"' => {
+                i += 1;
+                loop {
+                    match *bytes.get(i).ok_or(UNTERMINATED)? {
+                        b'"' => break,
+                        b'\\' => i += 2,
+                        _ => i += 1,
+                    }
+                }
+            }
+            b => visit(i, b),
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Split `text` at each `sep` that isn't quoted or escaped
+pub fn split_unquoted(text: &str, sep: u8) -> Result<Vec<&str>, &'static str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    scan(text, |i, b| {
+        if b == sep {
+            parts.push(&text[start..i]);
+            start = i + 1;
+        }
+    })?;
+    parts.push(&text[start..]);
+    Ok(parts)
+}
+
+/// Split `text` into words at unquoted whitespace, keeping the quotes
+pub fn raw_words(text: &str) -> Result<Vec<&str>, &'static str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    scan(text, |i, b| {
+        if b == b' ' || b == b'\t' {
+            if i > start {
+                words.push(&text[start..i]);
+            }
+            start = i + 1;
+        }
+    })?;
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    Ok(words)
+}
+
+/// Remove the quoting from one word
+pub fn unquote(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            '\'' => out.extend(chars.by_ref().take_while(|&c| c != '\'')),
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$')) => out.push(next),
+                            Some(next) => {
+                                out.push('\\');
+                                out.push(next);
+                            }
+                            None => out.push('\\'),
+                        },
+                        c => out.push(c),
+                    }
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Split a command line into its arguments
+pub fn split(text: &str) -> Result<Vec<String>, &'static str> {
+    Ok(raw_words(text)?.into_iter().map(unquote).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_split_plain_words() {
+        assert_eq!(split("ls  -l\t/tmp ").unwrap(), vec!["ls", "-l", "/tmp"]);
+        assert!(split("").unwrap().is_empty());
+        assert!(split(" \t ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_quotes() {
+        assert_eq!(split(r#"grep "two words" 'a|b'"#).unwrap(), vec!["grep", "two words", "a|b"]);
+        // Quotes join with their neighbours into one word
+        assert_eq!(split(r#"a"b c"'d e'f"#).unwrap(), vec!["ab cd ef"]);
+        assert_eq!(split(r#"'' """#).unwrap(), vec!["", ""]);
+        assert_eq!(split(r#"'it"s' "it's""#).unwrap(), vec!["it\"s", "it's"]);
+    }
+
+    #[test]
+    fn test_split_escapes() {
+        assert_eq!(split(r"a\ b c").unwrap(), vec!["a b", "c"]);
+        assert_eq!(split(r"\'x").unwrap(), vec!["'x"]);
+        // Single quotes keep backslashes
+        assert_eq!(split(r"'a\nb'").unwrap(), vec![r"a\nb"]);
+        // Double quotes only unescape ", \ and $
+        assert_eq!(split(r#""\"\\\$\n""#).unwrap(), vec![r#""\$\n"#]);
+        // A trailing backslash escapes nothing
+        assert_eq!(split(r"a\").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_unterminated_quotes() {
+        assert_eq!(split("echo 'abc"), Err("unterminated quote"));
+        assert_eq!(split("echo \"abc"), Err("unterminated quote"));
+        assert_eq!(split(r#"echo "abc\""#), Err("unterminated quote"));
+        assert_eq!(split_unquoted("a | 'b", b'|'), Err("unterminated quote"));
+    }
+
+    #[test]
+    fn test_raw_words_keep_quotes() {
+        assert_eq!(raw_words(r#"echo "a b" c\ d"#).unwrap(), vec!["echo", "\"a b\"", r"c\ d"]);
+    }
+
+    #[test]
+    fn test_split_unquoted() {
+        assert_eq!(split_unquoted("a | b|c", b'|').unwrap(), vec!["a ", " b", "c"]);
+        assert_eq!(split_unquoted(r#"echo "a|b" 'c|d' e\|f"#, b'|').unwrap(), vec![r#"echo "a|b" 'c|d' e\|f"#]);
+        assert_eq!(split_unquoted("|", b'|').unwrap(), vec!["", ""]);
+        assert_eq!(split_unquoted("", b';').unwrap(), vec![""]);
+    }
+}
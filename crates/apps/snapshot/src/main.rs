@@ -0,0 +1,139 @@
+//! WATOS snapshot command - take, list and mount filesystem snapshots
+//!
+//! Usage: snapshot PATH NAME
+//!        snapshot -l [PATH]
+//!        snapshot -m PATH NAME
+//!
+//! The first form takes a snapshot of PATH (a file or directory) named NAME.
+//! -l lists the snapshots on the filesystem holding PATH (default: current
+//! directory), oldest first. -m mounts snapshot NAME of the filesystem
+//! holding PATH read-only on the first free drive letter.
+//!
+//! Only filesystems with copy-on-write trees (WFS) support snapshots.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_bytes(b: &[u8]) {
+    syscalls::write(1, b);
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+fn usage() -> ! {
+    write_str("Usage: snapshot PATH NAME\r\n");
+    write_str("       snapshot -l [PATH]\r\n");
+    write_str("       snapshot -m PATH NAME\r\n");
+    exit(1);
+}
+
+fn fail(what: &str, name: &str) -> ! {
+    write_str("snapshot: ");
+    write_str(what);
+    write_str(" '");
+    write_str(name);
+    write_str("'\r\n");
+    exit(1);
+}
+
+fn list(path: &str) -> ! {
+    let mut buf = [0u8; 4096];
+    let len = syscalls::snapshots(path, &mut buf);
+    if len == u64::MAX {
+        fail("cannot list snapshots on", path);
+    }
+
+    for line in buf[..len as usize].split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+        write_bytes(line);
+        write_str("\r\n");
+    }
+    exit(0);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    use core::ptr::addr_of_mut;
+    static mut ARGS_BUF: [u8; 512] = [0u8; 512];
+
+    let args_len = unsafe {
+        let buf = &mut *addr_of_mut!(ARGS_BUF);
+        get_args(buf)
+    };
+    let args = unsafe { &ARGS_BUF[..args_len] };
+    let args = match core::str::from_utf8(args) {
+        Ok(s) => s,
+        Err(_) => usage(),
+    };
+
+    // Skip command name "snapshot"
+    let mut words = args.split(' ').filter(|w| !w.is_empty()).skip(1);
+    let first = words.next().unwrap_or_else(|| usage());
+
+    match first {
+        "-l" => {
+            let path = words.next().unwrap_or(".");
+            if words.next().is_some() {
+                usage();
+            }
+            list(path);
+        }
+        "-m" => {
+            let (path, name) = match (words.next(), words.next(), words.next()) {
+                (Some(p), Some(n), None) => (p, n),
+                _ => usage(),
+            };
+            match syscalls::mount_snapshot(path, name) {
+                Some(letter) => {
+                    write_str(name);
+                    write_str(" mounted read-only as ");
+                    let drive = [letter as u8, b':'];
+                    write_bytes(&drive);
+                    write_str("\r\n");
+                    exit(0);
+                }
+                None => fail("cannot mount snapshot", name),
+            }
+        }
+        path if !path.starts_with('-') => {
+            let name = match (words.next(), words.next()) {
+                (Some(n), None) => n,
+                _ => usage(),
+            };
+            if syscalls::snapshot(path, name) != 0 {
+                fail("cannot take snapshot", name);
+            }
+            exit(0);
+        }
+        _ => usage(),
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(1);
+}
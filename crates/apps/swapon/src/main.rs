@@ -0,0 +1,156 @@
+//! WATOS swapon command - manage swap space
+//!
+//! Usage: swapon FILE
+//!        swapon -c PAGES FILE
+//!        swapon -s
+//!        swapon -o
+//!
+//! The first form swaps to FILE, a swap area made by this command or by
+//! Linux mkswap. -c first creates FILE as a swap area of PAGES 4 KiB pages.
+//! -s shows the size and use of the swap area. -o switches swap off,
+//! bringing the swapped pages back into memory.
+//!
+//! Only root may switch swap on or off.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_num(mut n: u64) {
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &buf[i..]);
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+fn usage() -> ! {
+    write_str("Usage: swapon FILE\r\n");
+    write_str("       swapon -c PAGES FILE\r\n");
+    write_str("       swapon -s\r\n");
+    write_str("       swapon -o\r\n");
+    exit(1);
+}
+
+fn fail(what: &str, name: &str) -> ! {
+    write_str("swapon: ");
+    write_str(what);
+    write_str(" '");
+    write_str(name);
+    write_str("'\r\n");
+    exit(1);
+}
+
+fn show() -> ! {
+    match syscalls::swapinfo() {
+        Some((total, used)) => {
+            write_str("Size: ");
+            write_num(total / 1024);
+            write_str(" kB  Used: ");
+            write_num(used / 1024);
+            write_str(" kB\r\n");
+        }
+        None => write_str("swap is off\r\n"),
+    }
+    exit(0);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    use core::ptr::addr_of_mut;
+    static mut ARGS_BUF: [u8; 512] = [0u8; 512];
+
+    let args_len = unsafe {
+        let buf = &mut *addr_of_mut!(ARGS_BUF);
+        get_args(buf)
+    };
+    let args = unsafe { &ARGS_BUF[..args_len] };
+    let args = match core::str::from_utf8(args) {
+        Ok(s) => s,
+        Err(_) => usage(),
+    };
+
+    // Skip command name "swapon"
+    let mut words = args.split(' ').filter(|w| !w.is_empty()).skip(1);
+    let first = words.next().unwrap_or_else(|| usage());
+
+    match first {
+        "-s" => {
+            if words.next().is_some() {
+                usage();
+            }
+            show();
+        }
+        "-o" => {
+            if words.next().is_some() {
+                usage();
+            }
+            if !syscalls::swapoff() {
+                write_str("swapon: cannot switch swap off\r\n");
+                exit(1);
+            }
+            exit(0);
+        }
+        "-c" => {
+            let (pages, path) = match (words.next(), words.next(), words.next()) {
+                (Some(p), Some(f), None) => (p, f),
+                _ => usage(),
+            };
+            let pages = match pages.parse::<u32>() {
+                Ok(n) if n >= 2 => n,
+                _ => fail("invalid page count", pages),
+            };
+            if !syscalls::swapon(path, pages) {
+                fail("cannot create swap file", path);
+            }
+            exit(0);
+        }
+        path if !path.starts_with('-') => {
+            if words.next().is_some() {
+                usage();
+            }
+            if !syscalls::swapon(path, 0) {
+                fail("cannot swap to", path);
+            }
+            exit(0);
+        }
+        _ => usage(),
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(1);
+}
@@ -0,0 +1,328 @@
+//! WATOS telnetd - plain-text remote console
+//!
+//! Usage: telnetd
+//!
+//! Listens on the port from /etc/telnetd.conf (23 by default) and closes
+//! any connection from a source the file's `allow` lines don't cover; with
+//! none, every connection is refused. Each accepted connection gets a
+//! process of its own, started as `telnetd -c FD` with the connection on
+//! FD, which runs `login -h ADDR` on a new pty and copies between the pty
+//! and the connection until the shell exits or the client hangs up.
+//!
+//! Nothing is encrypted, passwords included: use it on a trusted LAN, and
+//! rshd anywhere else. Must run as root, for login to switch users.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_syscall::{argv, net, open, signals, syscalls, wait};
+use watos_telnet::{Config, Error, Event, Session, Transport, CONFIG_PATH};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Output
+// ============================================================================
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn fail(message: &str) -> ! {
+    write_str("telnetd: ");
+    write_str(message);
+    write_str("\r\n");
+    exit(1);
+}
+
+// ============================================================================
+// Connections
+// ============================================================================
+
+/// PIT ticks per second (the timer runs at ~18.2 Hz)
+const TICKS_PER_SEC: u64 = 18;
+
+/// How long a write may wait for the peer to take more data
+const WRITE_TICKS: u64 = 30 * TICKS_PER_SEC;
+
+/// The kernel's per-process fd limit
+const MAX_FDS: i32 = 64;
+
+/// An accepted TCP connection. Sockets never block: `read` idles until
+/// something arrives, `try_read` returns at once.
+struct Socket(i32);
+
+impl Socket {
+    fn is_open(&self) -> bool {
+        syscalls::net_state(self.0) == Some(net::STATE_OPEN)
+    }
+}
+
+impl Transport for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            if let Some(n) = self.try_read(buf)? {
+                return Ok(n);
+            }
+            syscalls::idle();
+        }
+    }
+
+    fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        match syscalls::read(self.0, buf) {
+            n if n > 0 && n <= buf.len() => Ok(Some(n)),
+            // Closed once the peer is done and everything has been read
+            _ if !self.is_open() => Ok(Some(0)),
+            _ => Ok(None),
+        }
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        let deadline = syscalls::get_ticks() + WRITE_TICKS;
+        while !buf.is_empty() {
+            // 0 while the send buffer is full
+            match syscalls::write(self.0, buf) {
+                0 if self.is_open() && syscalls::get_ticks() <= deadline => syscalls::idle(),
+                n if n > 0 && n <= buf.len() => buf = &buf[n..],
+                _ => return Err(Error::Transport),
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// CONFIG_PATH parsed; no file gives the default, which refuses all
+fn load_config() -> Result<Config, String> {
+    let fd = syscalls::open(CONFIG_PATH, open::O_RDONLY);
+    if fd < 0 {
+        return Ok(Config::default());
+    }
+    let mut text = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        match syscalls::read(fd, &mut buf) {
+            n if n > 0 && n <= buf.len() => text.extend_from_slice(&buf[..n]),
+            _ => break,
+        }
+    }
+    syscalls::close(fd);
+    let text = core::str::from_utf8(&text).map_err(|_| format!("{} is not text", CONFIG_PATH))?;
+    Config::parse(text).map_err(|line| format!("{}: bad line {}", CONFIG_PATH, line))
+}
+
+// ============================================================================
+// One session
+// ============================================================================
+
+/// Run `program` with a new pty as its standard streams
+fn start_on_pty(program: &[&str]) -> Option<(u32, i32)> {
+    let (master, slave) = syscalls::openpty()?;
+    for fd in 0..3 {
+        syscalls::dup2(slave, fd);
+    }
+    let pid = syscalls::spawn(program);
+    // Our own standard streams go back to the console
+    for fd in 0..3 {
+        syscalls::close(fd);
+    }
+    syscalls::close(slave);
+    if pid == u64::MAX || pid == 0 {
+        syscalls::close(master);
+        return None;
+    }
+    Some((pid as u32, master))
+}
+
+/// Program output as a telnet client expects it, turning a lone LF into
+/// CR LF as a pty's line discipline would; `last` is the byte before `data`
+fn crlf(data: &[u8], last: &mut u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        if b == b'\n' && *last != b'\r' {
+            out.push(b'\r');
+        }
+        out.push(b);
+        *last = b;
+    }
+    out
+}
+
+/// Copy between the connection and the pty until the shell exits; false
+/// if the client went away first
+fn relay(session: &mut Session<Socket>, pid: u32, master: i32) -> bool {
+    let mut buf = [0u8; 4096];
+    let mut last = 0u8;
+    // Keystrokes the pty had no room for yet
+    let mut input: Vec<u8> = Vec::new();
+    loop {
+        let mut busy = false;
+
+        if !input.is_empty() {
+            match syscalls::write(master, &input) {
+                n if n <= input.len() => {
+                    input.drain(..n);
+                    busy = n > 0;
+                }
+                // Hung up: the shell is gone, and wait() below says so
+                _ => input.clear(),
+            }
+        } else {
+            match session.poll_event() {
+                Ok(Some(Event::Data(data))) => {
+                    input = data;
+                    busy = true;
+                }
+                // Nothing to pass a window size to yet
+                Ok(Some(Event::Resize(_))) => busy = true,
+                Ok(None) => {}
+                Err(_) => return false,
+            }
+        }
+
+        let exited = syscalls::wait(pid, wait::WNOHANG);
+        // Output the shell wrote before it exited still goes out
+        loop {
+            match syscalls::read(master, &mut buf) {
+                n if n > 0 && n <= buf.len() => {
+                    if session.send_data(&crlf(&buf[..n], &mut last)).is_err() {
+                        return false;
+                    }
+                    busy = true;
+                }
+                _ => break,
+            }
+        }
+        if exited.is_some() {
+            return true;
+        }
+
+        if !busy {
+            syscalls::idle();
+        }
+    }
+}
+
+/// Serve the connection on `fd`, in a process of its own
+fn serve(fd: i32) -> ! {
+    // Drop what came from the listening daemon, like the listener itself
+    for other in 3..MAX_FDS {
+        if other != fd {
+            syscalls::close(other);
+        }
+    }
+    let addr = syscalls::net_peer(fd).map(|(addr, _)| net::octets(addr)).unwrap_or([0; 4]);
+    let addr = format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3]);
+
+    let mut session = Session::accept(Socket(fd)).unwrap_or_else(|_| exit(1));
+    let Some((pid, master)) = start_on_pty(&["login", "-h", &addr]) else {
+        let _ = session.send_data(b"telnetd: cannot start login\r\n");
+        exit(1);
+    };
+    if !relay(&mut session, pid, master) {
+        // The client is gone: so is the session
+        syscalls::kill(pid as i32, signals::SIGKILL);
+        syscalls::wait(pid, 0);
+    }
+    syscalls::close(master);
+    syscalls::close(fd);
+    exit(0);
+}
+
+// ============================================================================
+// Listening
+// ============================================================================
+
+fn usage() -> ! {
+    write_str("Usage: telnetd\r\n");
+    exit(2);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+    let mut args = argv::decode(&args_buf[..args_len]).skip(1);
+    let connection = match (args.next(), args.next(), args.next()) {
+        (None, _, _) => None,
+        (Some("-c"), Some(fd), None) => Some(fd.parse().unwrap_or_else(|_| usage())),
+        _ => usage(),
+    };
+
+    if syscalls::getuid() != 0 {
+        fail("must be run as root");
+    }
+    if let Some(fd) = connection {
+        serve(fd);
+    }
+
+    let config = load_config().unwrap_or_else(|e| fail(&e));
+    if config.allow.is_empty() {
+        write_str(&format!("telnetd: no allow lines in {}, every connection is refused\r\n", CONFIG_PATH));
+    }
+    let listener = syscalls::net_listen(config.port).unwrap_or_else(|| fail("no network"));
+    write_str(&format!("telnetd: listening on port {}\r\n", config.port));
+
+    loop {
+        match syscalls::net_accept(listener) {
+            Some(Some(fd)) => {
+                let addr = syscalls::net_peer(fd).map(|(addr, _)| net::octets(addr));
+                if addr.is_some_and(|addr| config.permits(addr)) {
+                    let fd_arg = format!("{}", fd);
+                    let pid = syscalls::spawn(&["telnetd", "-c", &fd_arg]);
+                    if pid == u64::MAX || pid == 0 {
+                        write_str("telnetd: cannot start a session\r\n");
+                    }
+                } else if let Some([a, b, c, d]) = addr {
+                    write_str(&format!("telnetd: refused {}.{}.{}.{}\r\n", a, b, c, d));
+                }
+                // The session process has its own copy
+                syscalls::close(fd);
+            }
+            Some(None) => {
+                // Collect finished sessions
+                while syscalls::wait(0, wait::WNOHANG).is_some() {}
+                syscalls::idle();
+            }
+            None => fail("lost the listening socket"),
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\r\ntelnetd: internal error\r\n");
+    exit(1);
+}
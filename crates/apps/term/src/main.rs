@@ -0,0 +1,301 @@
+//! WATOS term - terminal emulator
+//!
+//! Usage: term [PROGRAM [ARGS...]]
+//!
+//! Runs PROGRAM (default `shell`) on the slave end of a pty and is its
+//! screen and keyboard: output read from the pty master goes through the
+//! VT100 emulator in `watos_terminal` onto the framebuffer, and keys typed
+//! are written to the master, as text or as escape sequences for cursor and
+//! function keys. Left-drag copies text to the clipboard and the middle
+//! button pastes it. term exits with the program's status when it ends.
+//!
+//! Without the framebuffer (it is for root and the video group) the
+//! program runs on the console instead.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, syscalls, wait};
+use watos_terminal::color::THEMES;
+use watos_terminal::console::ConsoleManager;
+use watos_terminal::framebuffer::{FramebufferInfo, PixelFormat, SimpleFramebuffer};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Terminal
+// ============================================================================
+
+/// Clipboard type for selections and pastes
+const TEXT_PLAIN: &str = "text/plain";
+
+/// Mouse buttons in `mouse_poll`'s state
+const MOUSE_LEFT: u8 = 0x01;
+const MOUSE_MIDDLE: u8 = 0x04;
+
+/// Cursor blink period in timer ticks (~18.2 Hz, so about 500ms)
+const BLINK_INTERVAL: u64 = 9;
+
+/// Exit status for a program that could not be started
+const NOT_STARTED: i32 = 127;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+/// The program's exit status in the shell's terms
+fn exit_status(status: u32) -> i32 {
+    if wait::signaled(status) {
+        128 + wait::term_signal(status) as i32
+    } else {
+        wait::exit_code(status)
+    }
+}
+
+/// Run the program on the console and wait for it
+fn run_on_console(program: &[&str]) -> ! {
+    let pid = syscalls::spawn(program);
+    if pid == u64::MAX || pid == 0 {
+        write_str("term: cannot start ");
+        write_str(program[0]);
+        write_str("\r\n");
+        syscalls::exit(NOT_STARTED);
+    }
+    let status = syscalls::wait(pid as u32, 0).map_or(0, |(_, status)| exit_status(status));
+    syscalls::exit(status)
+}
+
+/// Start the program with the pty slave as its stdin, stdout and stderr;
+/// returns its pid and the master
+fn start_on_pty(program: &[&str]) -> Option<(u32, i32)> {
+    let (master, slave) = syscalls::openpty()?;
+    for fd in 0..3 {
+        syscalls::dup2(slave, fd);
+    }
+    let pid = syscalls::spawn(program);
+    // Our own standard streams go back to the console
+    for fd in 0..3 {
+        syscalls::close(fd);
+    }
+    syscalls::close(slave);
+    if pid == u64::MAX || pid == 0 {
+        syscalls::close(master);
+        return None;
+    }
+    Some((pid as u32, master))
+}
+
+/// Pass program output to the emulator, turning a lone LF into CR LF as a
+/// pty's line discipline would; `last` is the byte before `data`
+fn feed(console: &mut ConsoleManager, data: &[u8], last: &mut u8) {
+    let mut start = 0;
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\n' && (if i == 0 { *last } else { data[i - 1] }) != b'\r' {
+            console.write(&data[start..i]);
+            console.write(b"\r");
+            start = i;
+        }
+    }
+    console.write(&data[start..]);
+    if let Some(&b) = data.last() {
+        *last = b;
+    }
+}
+
+/// Render, keeping the mouse cursor on top of the new text
+fn render(console: &mut ConsoleManager, framebuffer: &mut SimpleFramebuffer, mouse: bool) {
+    if mouse {
+        syscalls::cursor_show(false);
+    }
+    console.render(framebuffer);
+    if mouse {
+        syscalls::cursor_show(true);
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    use core::ptr::addr_of_mut;
+    static mut ARGV_BUF: [u8; argv::MAX_BYTES] = [0u8; argv::MAX_BYTES];
+
+    let buf = unsafe { &mut *addr_of_mut!(ARGV_BUF) };
+    let len = syscalls::getargv(buf).unwrap_or(0);
+    let mut program: alloc::vec::Vec<&str> = argv::decode(&buf[..len]).skip(1).collect();
+    if program.is_empty() {
+        program.push("shell");
+    }
+
+    let Some(fb_address) = syscalls::fb_map() else { run_on_console(&program) };
+    let (width, height, pitch) = syscalls::fb_dimensions();
+    let mut framebuffer = unsafe {
+        SimpleFramebuffer::new(
+            fb_address as *mut u32,
+            FramebufferInfo {
+                width,
+                height,
+                pitch,
+                bpp: 32,
+                format: PixelFormat::Bgr,
+            },
+        )
+    };
+
+    // One 8x16 cell per character
+    let mut console = ConsoleManager::new((width / 8) as usize, (height / 16) as usize);
+    console.init_consoles(1);
+    let theme = syscalls::sysctl_get("console.theme").and_then(|theme| THEMES.get(theme as usize));
+    if let (Some(&palette), Some(terminal)) = (theme, console.active_terminal_mut()) {
+        terminal.set_palette(palette);
+    }
+    console.write_str("\x1b[2J\x1b[H");
+    console.render(&mut framebuffer);
+
+    let Some((child, master)) = start_on_pty(&program) else {
+        write_str("term: cannot start ");
+        write_str(program[0]);
+        write_str("\r\n");
+        syscalls::exit(NOT_STARTED);
+    };
+
+    let mut output = [0u8; 4096];
+    let mut last_byte = 0u8;
+    let mut last_blink = syscalls::get_ticks();
+    let (cell_w, cell_h) = console.cell_size();
+    let mut mouse = false;
+    let mut prev_buttons = 0u8;
+    let mut selection: Option<((usize, usize), (usize, usize))> = None;
+
+    loop {
+        let mut busy = false;
+
+        // Program output
+        let n = syscalls::read(master, &mut output);
+        if n > 0 && n <= output.len() {
+            busy = true;
+            // New text may scroll, so drop a selection in progress
+            if let Some((start, end)) = selection.take() {
+                console.toggle_highlight(start, end);
+            }
+            feed(&mut console, &output[..n], &mut last_byte);
+            render(&mut console, &mut framebuffer, mouse);
+        } else if let Some((_, status)) = syscalls::wait(child, wait::WNOHANG) {
+            // The pty is drained and the program is gone
+            syscalls::exit(exit_status(status));
+        }
+
+        // Keys, typed into the pty
+        let scancode = syscalls::read_scancode();
+        if scancode != 0 {
+            busy = true;
+            if let Some(event) = console.process_scancode(scancode) {
+                if event.pressed {
+                    if let Some((start, end)) = selection.take() {
+                        console.toggle_highlight(start, end);
+                        render(&mut console, &mut framebuffer, mouse);
+                    }
+                    let mut typed = console.keyboard_mut().type_chars(&event).peekable();
+                    if typed.peek().is_some() {
+                        for ch in typed {
+                            // Enter sends CR, as on a VT100
+                            let ch = if ch == '\n' { '\r' } else { ch };
+                            let mut utf8 = [0u8; 4];
+                            syscalls::write(master, ch.encode_utf8(&mut utf8).as_bytes());
+                        }
+                    } else if let Some(sequence) = console.keyboard().to_escape_sequence(&event) {
+                        syscalls::write(master, sequence);
+                    }
+                }
+            }
+        }
+
+        // Mouse: left-drag selects and copies, middle click pastes
+        let (mouse_x, mouse_y, buttons) = syscalls::mouse_poll();
+        if !mouse && (mouse_x, mouse_y, buttons) != (0, 0, 0) {
+            mouse = true;
+            syscalls::cursor_show(true);
+        }
+        let (cols, rows) = console.size();
+        let cell = (
+            ((mouse_x.max(0) as u32 / cell_w) as usize).min(cols - 1),
+            ((mouse_y.max(0) as u32 / cell_h) as usize).min(rows - 1),
+        );
+        let pressed = buttons & !prev_buttons;
+        let released = prev_buttons & !buttons;
+        prev_buttons = buttons;
+
+        if pressed & MOUSE_LEFT != 0 {
+            selection = Some((cell, cell));
+            console.toggle_highlight(cell, cell);
+            render(&mut console, &mut framebuffer, mouse);
+        } else if let Some((start, end)) = selection {
+            if released & MOUSE_LEFT != 0 {
+                let mut text = [0u8; 8192];
+                let len = console.selection_text(start, end, &mut text);
+                if len > 0 {
+                    syscalls::clipboard_set(TEXT_PLAIN, &text[..len]);
+                }
+                console.toggle_highlight(start, end);
+                selection = None;
+                render(&mut console, &mut framebuffer, mouse);
+            } else if cell != end {
+                console.toggle_highlight(start, end);
+                console.toggle_highlight(start, cell);
+                selection = Some((start, cell));
+                render(&mut console, &mut framebuffer, mouse);
+            }
+        }
+        if pressed & MOUSE_MIDDLE != 0 {
+            let mut text = [0u8; 4096];
+            if let Some(len) = syscalls::clipboard_get(TEXT_PLAIN, &mut text) {
+                syscalls::write(master, &text[..len.min(text.len())]);
+            }
+        }
+
+        // Cursor blink, redrawing just its cell
+        let now = syscalls::get_ticks();
+        if now.wrapping_sub(last_blink) >= BLINK_INTERVAL {
+            last_blink = now;
+            if console.tick() {
+                if mouse {
+                    syscalls::cursor_show(false);
+                }
+                console.render_cursor(&mut framebuffer);
+                if mouse {
+                    syscalls::cursor_show(true);
+                }
+            }
+        }
+
+        if !busy {
+            syscalls::idle();
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("term: internal error\r\n");
+    syscalls::exit(1);
+}
@@ -0,0 +1,423 @@
+//! WATOS top - process monitor
+//!
+//! Usage: top [-d SECS] [-n COUNT]
+//!
+//! Takes the system totals from SYS_SYSINFO and each process from
+//! /proc/<pid>/status, and redraws the table every SECS seconds (default
+//! 1). With -n, exits after COUNT refreshes.
+//!
+//! Keys:
+//!   p / c / m / n   Sort by pid / cpu time / memory / name
+//!   k               Send SIGTERM to a process (asks for the pid)
+//!   space           Refresh now
+//!   q               Quit
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::{signals, sysinfo, syscalls};
+
+// ============================================================================
+// Global Allocator (via syscalls)
+// ============================================================================
+
+use core::alloc::{GlobalAlloc, Layout};
+
+struct SyscallAllocator;
+
+unsafe impl GlobalAlloc for SyscallAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        syscalls::malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        syscalls::free(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: SyscallAllocator = SyscallAllocator;
+
+// ============================================================================
+// Console helpers
+// ============================================================================
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+/// Console size, defaulting to the VT's 160x50 (overridable via COLUMNS/LINES)
+fn screen_size() -> (usize, usize) {
+    fn env_num(name: &str, default: usize) -> usize {
+        let mut buf = [0u8; 16];
+        let len = unsafe {
+            let ret: u64;
+            core::arch::asm!(
+                "int 0x80",
+                in("eax") syscall::SYS_GETENV,
+                in("rdi") name.as_ptr() as u64,
+                in("rsi") name.len() as u64,
+                in("rdx") buf.as_mut_ptr() as u64,
+                in("r10") buf.len() as u64,
+                lateout("rax") ret,
+                options(nostack)
+            );
+            ret as usize
+        };
+        if len == 0 || len > buf.len() {
+            return default;
+        }
+        core::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .filter(|&n: &usize| n >= 10)
+            .unwrap_or(default)
+    }
+    (env_num("COLUMNS", 160), env_num("LINES", 50))
+}
+
+/// PIT ticks per second (the timer runs at ~18.2 Hz)
+const TICKS_PER_SEC_X10: u64 = 182;
+
+// ============================================================================
+// /proc readers
+// ============================================================================
+
+/// Read a whole (small) file into a string
+fn read_file(path: &str) -> Option<String> {
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let n = syscalls::read(fd, &mut buf);
+        if n == 0 || n > buf.len() {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+    syscalls::close(fd);
+    String::from_utf8(data).ok()
+}
+
+/// Value of a "Key: value" line, with any trailing unit dropped
+fn field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim() == key {
+            value.split_whitespace().next()
+        } else {
+            None
+        }
+    })
+}
+
+fn field_num(text: &str, key: &str) -> u64 {
+    field(text, key).and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+/// A load average as "0.42"
+fn format_load(load: u32) -> String {
+    let scale = sysinfo::LOAD_SCALE;
+    format!("{}.{:02}", load / scale, load % scale)
+}
+
+struct ProcEntry {
+    pid: u32,
+    ppid: u32,
+    uid: u32,
+    name: String,
+    state: String,
+    nice: i64,
+    mem_kb: u64,
+    cpu_ms: u64,
+}
+
+/// Numeric entries of /proc
+fn list_pids() -> Vec<u32> {
+    let mut buf = alloc::vec![0u8; 4096];
+    let len = syscalls::readdir("/proc", &mut buf);
+    if len > buf.len() {
+        return Vec::new();
+    }
+    // Each line is "TYPE NAME SIZE"
+    buf[..len]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let line = core::str::from_utf8(line).ok()?;
+            let mut parts = line.split_whitespace();
+            if parts.next()? != "D" {
+                return None;
+            }
+            parts.next()?.parse().ok()
+        })
+        .collect()
+}
+
+fn read_process(pid: u32) -> Option<ProcEntry> {
+    let status = read_file(&format!("/proc/{}/status", pid))?;
+    Some(ProcEntry {
+        pid,
+        ppid: field_num(&status, "PPid") as u32,
+        uid: field_num(&status, "Uid") as u32,
+        name: String::from(field(&status, "Name").unwrap_or("?")),
+        state: String::from(field(&status, "State").unwrap_or("?")),
+        nice: field(&status, "Nice").and_then(|v| v.parse().ok()).unwrap_or(0),
+        mem_kb: field_num(&status, "VmSize"),
+        cpu_ms: field_num(&status, "CpuTime"),
+    })
+}
+
+// ============================================================================
+// Display
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortKey {
+    Pid,
+    Cpu,
+    Mem,
+    Name,
+}
+
+impl SortKey {
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Pid => "pid",
+            SortKey::Cpu => "cpu",
+            SortKey::Mem => "mem",
+            SortKey::Name => "name",
+        }
+    }
+}
+
+/// "HH:MM:SS" (or "Nd HH:MM:SS" past a day)
+fn format_duration(secs: u64) -> String {
+    let (days, rest) = (secs / 86400, secs % 86400);
+    let hms = format!("{:02}:{:02}:{:02}", rest / 3600, rest % 3600 / 60, rest % 60);
+    if days > 0 {
+        format!("{}d {}", days, hms)
+    } else {
+        hms
+    }
+}
+
+struct Top {
+    sort: SortKey,
+    message: String,
+    cols: usize,
+    rows: usize,
+}
+
+impl Top {
+    fn draw(&self) {
+        let info = syscalls::sysinfo().unwrap_or_default();
+        let kb = |bytes: u64| bytes / 1024;
+
+        let mut procs: Vec<ProcEntry> = list_pids().into_iter().filter_map(read_process).collect();
+        match self.sort {
+            SortKey::Pid => procs.sort_by_key(|p| p.pid),
+            SortKey::Cpu => procs.sort_by(|a, b| b.cpu_ms.cmp(&a.cpu_ms).then(a.pid.cmp(&b.pid))),
+            SortKey::Mem => procs.sort_by(|a, b| b.mem_kb.cmp(&a.mem_kb).then(a.pid.cmp(&b.pid))),
+            SortKey::Name => procs.sort_by(|a, b| a.name.cmp(&b.name).then(a.pid.cmp(&b.pid))),
+        }
+
+        let mut out = String::new();
+        out.push_str("\x1b[?25l\x1b[H");
+        out.push_str(&format!(
+            "top - up {}, {} processes, load average: {}, {}, {}, sorted by {}\x1b[K\r\n",
+            format_duration(info.uptime_secs),
+            procs.len(),
+            format_load(info.loads[0]),
+            format_load(info.loads[1]),
+            format_load(info.loads[2]),
+            self.sort.label()
+        ));
+        out.push_str(&format!(
+            "Mem: {} kB total, {} kB used, {} kB free   Heap: {} kB used / {} kB\x1b[K\r\n",
+            kb(info.mem_total),
+            kb(info.mem_total - info.mem_free),
+            kb(info.mem_free),
+            kb(info.heap_used),
+            kb(info.heap_total),
+        ));
+        out.push_str(&format!("{}\x1b[K\r\n", self.message));
+
+        let header = format!(
+            "{:>6} {:>6} {:>5} {:>3} {:<9} {:>9} {:>10}  COMMAND",
+            "PID", "PPID", "UID", "NI", "STATE", "MEM(kB)", "TIME"
+        );
+        out.push_str(&format!("\x1b[7m{:<width$}\x1b[0m\r\n", header, width = self.cols));
+
+        // Four header lines above, one spare line at the bottom
+        let table_rows = self.rows.saturating_sub(5);
+        for (i, p) in procs.iter().enumerate().take(table_rows) {
+            let line = format!(
+                "{:>6} {:>6} {:>5} {:>3} {:<9} {:>9} {:>10}  {}",
+                p.pid,
+                p.ppid,
+                p.uid,
+                p.nice,
+                p.state,
+                p.mem_kb,
+                format_duration(p.cpu_ms / 1000),
+                p.name
+            );
+            let line: String = line.chars().take(self.cols).collect();
+            out.push_str(&line);
+            out.push_str("\x1b[K");
+            if i + 1 < table_rows {
+                out.push_str("\r\n");
+            }
+        }
+        out.push_str("\x1b[J");
+        write_str(&out);
+    }
+
+    /// Ask for a pid on the message line; None if cancelled
+    fn prompt_pid(&self) -> Option<u32> {
+        let mut input = String::new();
+        loop {
+            write_str(&format!("\x1b[3;1HPID to kill: {}\x1b[K\x1b[?25h", input));
+            match syscalls::getkey() {
+                0 => {
+                    syscalls::idle();
+                    continue;
+                }
+                b'\r' | b'\n' => break,
+                0x1b => return None,
+                0x08 | 0x7f => {
+                    input.pop();
+                }
+                c if c.is_ascii_digit() && input.len() < 10 => input.push(c as char),
+                _ => {}
+            }
+        }
+        write_str("\x1b[?25l");
+        input.parse().ok()
+    }
+
+    fn kill(&mut self) {
+        self.message = match self.prompt_pid() {
+            None => String::new(),
+            Some(pid) => {
+                if syscalls::kill(pid as i32, signals::SIGTERM) == u64::MAX {
+                    format!("kill {}: failed", pid)
+                } else {
+                    format!("sent SIGTERM to {}", pid)
+                }
+            }
+        };
+    }
+
+    /// Handle a key; false means quit
+    fn process_key(&mut self, key: u8) -> bool {
+        match key {
+            b'q' | b'Q' => return false,
+            b'p' => self.sort = SortKey::Pid,
+            b'c' => self.sort = SortKey::Cpu,
+            b'm' => self.sort = SortKey::Mem,
+            b'n' => self.sort = SortKey::Name,
+            b'k' => self.kill(),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+fn usage() -> ! {
+    write_str("Usage: top [-d SECS] [-n COUNT]\r\n");
+    exit(1);
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; 512];
+    let args_len = get_args(&mut args_buf).min(args_buf.len());
+    let args = core::str::from_utf8(&args_buf[..args_len]).unwrap_or("");
+
+    let mut delay_secs = 1u64;
+    let mut count: Option<u64> = None;
+
+    // First word is the program name
+    let mut words = args.split_whitespace().skip(1);
+    while let Some(word) = words.next() {
+        let value = words.next().and_then(|v| v.parse::<u64>().ok());
+        match (word, value) {
+            ("-d", Some(secs)) if secs > 0 => delay_secs = secs,
+            ("-n", Some(n)) if n > 0 => count = Some(n),
+            _ => usage(),
+        }
+    }
+
+    let (cols, rows) = screen_size();
+    let mut top = Top {
+        sort: SortKey::Pid,
+        message: String::new(),
+        cols,
+        rows,
+    };
+    let interval = delay_secs * TICKS_PER_SEC_X10 / 10;
+    write_str("\x1b[2J");
+
+    let mut refreshes = 0u64;
+    'outer: loop {
+        top.draw();
+        refreshes += 1;
+        if count.is_some_and(|n| refreshes >= n) {
+            break;
+        }
+
+        // Poll the keyboard until the next refresh is due
+        let start = syscalls::get_ticks();
+        while syscalls::get_ticks().wrapping_sub(start) < interval {
+            match syscalls::getkey() {
+                0 => syscalls::idle(),
+                b' ' => break,
+                key => {
+                    if !top.process_key(key) {
+                        break 'outer;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    write_str("\x1b[0m\r\n\x1b[?25h");
+    exit(0);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("\x1b[0m\r\ntop: internal error\r\n");
+    exit(1);
+}
@@ -0,0 +1,200 @@
+//! WATOS trash command - list, restore and empty a drive's trash
+//!
+//! Usage: trash [DRIVE:]
+//!        trash -r DRIVE: ID
+//!        trash -p DRIVE: [ID]
+//!
+//! The first form lists what `rm` and the file manager have moved to the
+//! trash of DRIVE (default: the current drive), oldest first. -r puts ID
+//! back where it was deleted from. -p deletes ID for good, or empties the
+//! trash when no ID is given.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::numbers as syscall;
+use watos_syscall::syscalls;
+use watos_time::Timestamp;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn exit(code: i32) -> ! {
+    syscalls::exit(code)
+}
+
+fn get_args(buf: &mut [u8]) -> usize {
+    unsafe {
+        let ret: u64;
+        core::arch::asm!(
+            "int 0x80",
+            in("eax") syscall::SYS_GETARGS,
+            in("rdi") buf.as_mut_ptr() as u64,
+            in("rsi") buf.len() as u64,
+            lateout("rax") ret,
+            options(nostack)
+        );
+        ret as usize
+    }
+}
+
+fn usage() -> ! {
+    write_str("Usage: trash [DRIVE:]\r\n");
+    write_str("       trash -r DRIVE: ID\r\n");
+    write_str("       trash -p DRIVE: [ID]\r\n");
+    exit(1);
+}
+
+fn fail(what: &str, name: &str) -> ! {
+    write_str("trash: ");
+    write_str(what);
+    write_str(" '");
+    write_str(name);
+    write_str("'\r\n");
+    exit(1);
+}
+
+/// "D:" or "d" -> 'D'
+fn parse_drive(arg: &str) -> char {
+    let mut chars = arg.trim_end_matches(':').chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+        _ => fail("not a drive", arg),
+    }
+}
+
+fn current_drive() -> char {
+    let mut cwd = [0u8; 256];
+    let len = syscalls::getcwd(&mut cwd);
+    match cwd[..len.min(cwd.len())].first() {
+        Some(&c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase() as char,
+        _ => 'C',
+    }
+}
+
+fn write_num(mut n: u64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &digits[i..]);
+}
+
+fn list(drive: char) -> ! {
+    let mut buf = [0u8; 8192];
+    let len = syscalls::trash_list(drive, &mut buf);
+    if len == u64::MAX {
+        let name = [drive as u8, b':'];
+        fail("cannot read the trash of", core::str::from_utf8(&name).unwrap_or("?"));
+    }
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap_or("");
+
+    let mut count = 0;
+    for line in text.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(id), Some(deleted), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let when = Timestamp::from_secs(deleted.parse().unwrap_or(0)).to_datetime();
+        let mut stamp = Stack::default();
+        let _ = core::fmt::write(&mut stamp, format_args!("{}", when));
+        write_str(stamp.as_str());
+        write_str("  ");
+        write_str(id);
+        write_str("  <- ");
+        write_str(path);
+        write_str("\r\n");
+        count += 1;
+    }
+    if count == 0 {
+        write_str("The trash is empty\r\n");
+    }
+    exit(0);
+}
+
+/// Fixed-size text for formatting without an allocator
+#[derive(Default)]
+struct Stack([u8; 32], usize);
+
+impl Stack {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.0[..self.1]).unwrap_or("")
+    }
+}
+
+impl core::fmt::Write for Stack {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.0.len() - self.1);
+        self.0[self.1..self.1 + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.1 += n;
+        Ok(())
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    use core::ptr::addr_of_mut;
+    static mut ARGS_BUF: [u8; 512] = [0u8; 512];
+
+    let args_len = unsafe {
+        let buf = &mut *addr_of_mut!(ARGS_BUF);
+        get_args(buf)
+    };
+    let args = unsafe { &ARGS_BUF[..args_len] };
+    let args = match core::str::from_utf8(args) {
+        Ok(s) => s,
+        Err(_) => usage(),
+    };
+
+    // Skip command name "trash"
+    let mut words = args.split(' ').filter(|w| !w.is_empty()).skip(1);
+
+    match words.next() {
+        None => list(current_drive()),
+        Some("-r") => {
+            let (drive, id) = match (words.next(), words.next(), words.next()) {
+                (Some(d), Some(id), None) => (parse_drive(d), id),
+                _ => usage(),
+            };
+            match syscalls::trash_restore(drive, id) {
+                0 => exit(0),
+                1 => fail("something is already where it came from:", id),
+                _ => fail("cannot restore", id),
+            }
+        }
+        Some("-p") => {
+            let drive = words.next().map(parse_drive).unwrap_or_else(|| usage());
+            let id = words.next().unwrap_or("");
+            if words.next().is_some() {
+                usage();
+            }
+            let purged = syscalls::trash_purge(drive, id);
+            if purged == u64::MAX {
+                fail("cannot purge", if id.is_empty() { "the trash" } else { id });
+            }
+            write_num(purged);
+            write_str(" purged\r\n");
+            exit(0);
+        }
+        Some(drive) if !drive.starts_with('-') => {
+            if words.next().is_some() {
+                usage();
+            }
+            list(parse_drive(drive))
+        }
+        Some(_) => usage(),
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(1);
+}
@@ -1,12 +1,14 @@
 //! WATOS uptime command
 //!
-//! Show how long the system has been running.
+//! Show how long the system has been running, how many processes there
+//! are, and the load averages over the last 1, 5 and 15 minutes.
 
 #![no_std]
 #![no_main]
 
 use core::panic::PanicInfo;
 use watos_syscall::numbers as syscall;
+use watos_syscall::{sysinfo, syscalls};
 
 #[inline(always)]
 unsafe fn syscall0(num: u32) -> u64 {
@@ -147,6 +149,21 @@ extern "C" fn _start() -> ! {
         write_str(" minutes");
     }
 
+    if let Some(info) = syscalls::sysinfo() {
+        write_str(",  ");
+        write_num(info.procs);
+        write_str(if info.procs == 1 { " process" } else { " processes" });
+        write_str(",  load average: ");
+        for (i, &load) in info.loads.iter().enumerate() {
+            if i > 0 {
+                write_str(", ");
+            }
+            write_num(load / sysinfo::LOAD_SCALE);
+            write_str(".");
+            write_num_padded(load % sysinfo::LOAD_SCALE, 2);
+        }
+    }
+
     write_str("\r\n");
     exit(0);
 }
@@ -0,0 +1,179 @@
+//! WATOS who command - show who is using the system
+//!
+//! Usage: who
+//!
+//! WATOS keeps no login records (there is no utmp), so "logged in" means
+//! owning a running process. Each user with processes is listed once, with
+//! the count and the oldest (lowest pid) of them:
+//!
+//!   root     console  3 processes  (pid 1 init)
+//!   guest    console  1 process    (pid 7 shell)
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::syscalls;
+
+/// Most distinct users listed
+const MAX_USERS: usize = 32;
+
+/// Longest process name kept
+const NAME_LEN: usize = 32;
+
+fn write_str(s: &str) {
+    syscalls::write(1, s.as_bytes());
+}
+
+fn write_num(n: u32) {
+    let mut buf = [0u8; 10];
+    let mut i = buf.len();
+    let mut n = n;
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    syscalls::write(1, &buf[i..]);
+}
+
+/// Print `s` padded with spaces to `width` columns
+fn write_padded(s: &str, width: usize) {
+    write_str(s);
+    for _ in s.len()..width {
+        write_str(" ");
+    }
+}
+
+/// A user with running processes
+#[derive(Clone, Copy)]
+struct Session {
+    uid: u32,
+    processes: u32,
+    /// Oldest process of the user
+    pid: u32,
+    name: [u8; NAME_LEN],
+    name_len: usize,
+}
+
+/// Value of a "Key:\tvalue" line of /proc/<pid>/status
+fn field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim())
+    })
+}
+
+/// Read /proc/<pid>/status into `buf`
+fn read_status(pid: &str, buf: &mut [u8]) -> Option<usize> {
+    let mut path = [0u8; 32];
+    let parts: [&[u8]; 3] = [b"/proc/", pid.as_bytes(), b"/status"];
+    let mut len = 0;
+    for part in parts {
+        path.get_mut(len..len + part.len())?.copy_from_slice(part);
+        len += part.len();
+    }
+    let path = core::str::from_utf8(&path[..len]).ok()?;
+
+    let fd = syscalls::open(path, 0);
+    if fd < 0 {
+        return None;
+    }
+    let mut total = 0;
+    while total < buf.len() {
+        let n = syscalls::read(fd, &mut buf[total..]);
+        if n == 0 || n > buf.len() - total {
+            break;
+        }
+        total += n;
+    }
+    syscalls::close(fd);
+    Some(total)
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    // SYS_READDIR fills up to 4096 bytes
+    let mut dir = [0u8; 4096];
+    let len = syscalls::readdir("/proc", &mut dir);
+    if len > dir.len() {
+        write_str("who: cannot read /proc\r\n");
+        syscalls::exit(1);
+    }
+
+    let mut sessions = [Session { uid: 0, processes: 0, pid: 0, name: [0; NAME_LEN], name_len: 0 }; MAX_USERS];
+    let mut count = 0;
+    let mut status = [0u8; 1024];
+
+    // Each line is "TYPE NAME SIZE"; process directories are numeric
+    for line in dir[..len].split(|&b| b == b'\n') {
+        let Ok(line) = core::str::from_utf8(line) else { continue };
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some("D") {
+            continue;
+        }
+        let Some(pid_str) = parts.next() else { continue };
+        let Ok(pid) = pid_str.parse::<u32>() else { continue };
+        let Some(n) = read_status(pid_str, &mut status) else { continue };
+        let Ok(text) = core::str::from_utf8(&status[..n]) else { continue };
+        let Some(uid) = field(text, "Uid").and_then(|v| v.parse::<u32>().ok()) else { continue };
+        let name = field(text, "Name").unwrap_or("?");
+
+        let index = match sessions[..count].iter().position(|s| s.uid == uid) {
+            Some(i) => i,
+            None if count < MAX_USERS => {
+                sessions[count].uid = uid;
+                sessions[count].pid = u32::MAX;
+                count += 1;
+                count - 1
+            }
+            None => continue,
+        };
+        let session = &mut sessions[index];
+        session.processes += 1;
+        if pid < session.pid {
+            let name = truncate(name, NAME_LEN);
+            session.pid = pid;
+            session.name[..name.len()].copy_from_slice(name.as_bytes());
+            session.name_len = name.len();
+        }
+    }
+
+    sessions[..count].sort_unstable_by_key(|s| s.pid);
+    let mut pw_buf = [0u8; 512];
+    for session in &sessions[..count] {
+        match syscalls::getpwuid(session.uid, &mut pw_buf) {
+            Some(pw) => write_padded(pw.name, 9),
+            None => {
+                write_num(session.uid);
+                write_str(" ");
+            }
+        }
+        write_str("console  ");
+        write_num(session.processes);
+        write_str(if session.processes == 1 { " process    (pid " } else { " processes  (pid " });
+        write_num(session.pid);
+        write_str(" ");
+        write_str(core::str::from_utf8(&session.name[..session.name_len]).unwrap_or("?"));
+        write_str(")\r\n");
+    }
+    syscalls::exit(0)
+}
+
+/// Longest prefix of `s` that fits in `max` bytes without splitting a
+/// character
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1)
+}
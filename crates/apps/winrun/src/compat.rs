@@ -0,0 +1,204 @@
+//! Running 32-bit (PE32) programs
+//!
+//! winrun itself is 64-bit, so a 32-bit program runs in compatibility mode
+//! and comes back to 64-bit mode for every kernel32 call:
+//!
+//! - `call32` saves winrun's registers, pushes the return stub as the
+//!   program's return address and far-returns to its entry point with the
+//!   32-bit code segment
+//! - each import is bound to a thunk in 32-bit code,
+//!   `mov eax, index; jmp far USER_CODE:bridge64`
+//! - `bridge64` calls `dispatch32`, which runs the shim on the arguments
+//!   from the 32-bit stack, then pops them (stdcall) and far-returns to the
+//!   caller
+//! - the return stub, `jmp far USER_CODE:return64`, brings the entry
+//!   point's result back to `call32`'s caller
+//!
+//! The thunks live in SYS_MALLOC memory, which is below 4GB and
+//! executable, as is winrun's own image. Everything runs on winrun's stack.
+
+use watos_syscall::segments::{USER_CODE, USER_CODE32, USER_DATA};
+use watos_syscall::syscalls;
+
+/// A kernel32 function as 32-bit programs call it
+struct Shim {
+    name: &'static [u8],
+    /// Arguments, each a 4-byte stack slot, that the function pops
+    args: usize,
+    call: fn(&[u32]) -> u32,
+}
+
+/// Handles come back as 32 bits; INVALID_HANDLE_VALUE is -1 in either width
+fn handle(value: u32) -> u64 {
+    value as i32 as i64 as u64
+}
+
+const SHIMS: &[Shim] = &[
+    Shim { name: b"GetStdHandle", args: 1, call: |a| crate::get_std_handle(a[0]) as u32 },
+    Shim {
+        name: b"WriteFile",
+        args: 5,
+        call: |a| unsafe { crate::write_handle(handle(a[0]), a[1] as *const u8, a[2], a[3] as *mut u32) } as u32,
+    },
+    Shim {
+        name: b"WriteConsoleA",
+        args: 5,
+        call: |a| unsafe { crate::write_handle(handle(a[0]), a[1] as *const u8, a[2], a[3] as *mut u32) } as u32,
+    },
+    Shim {
+        name: b"ReadFile",
+        args: 5,
+        call: |a| unsafe { crate::read_handle(handle(a[0]), a[1] as *mut u8, a[2], a[3] as *mut u32) } as u32,
+    },
+    Shim { name: b"GetCommandLineA", args: 0, call: |_| crate::get_command_line_a() as u32 },
+    Shim { name: b"ExitProcess", args: 1, call: |a| crate::exit_process(a[0]) },
+];
+
+/// Bytes per thunk: `mov eax, imm32` (5) and `jmp ptr16:32` (7), padded
+const THUNK_SIZE: usize = 16;
+
+/// The thunks, one per shim, then the return stub
+#[derive(Clone, Copy)]
+pub struct Thunks {
+    base: u32,
+}
+
+impl Thunks {
+    /// Build them in fresh memory; None if there is none below 4GB
+    pub fn new() -> Option<Self> {
+        let size = (SHIMS.len() + 1) * THUNK_SIZE;
+        let memory = alloc(size)?;
+        let bridge = u32::try_from(bridge64 as *const () as usize).ok()?;
+        let back = u32::try_from(return64 as *const () as usize).ok()?;
+        let code = unsafe { core::slice::from_raw_parts_mut(memory, size) };
+        code.fill(0xCC);
+        for (i, thunk) in code.chunks_exact_mut(THUNK_SIZE).take(SHIMS.len()).enumerate() {
+            thunk[0] = 0xB8;
+            thunk[1..5].copy_from_slice(&(i as u32).to_le_bytes());
+            far_jump(&mut thunk[5..12], bridge);
+        }
+        far_jump(&mut code[SHIMS.len() * THUNK_SIZE..][..7], back);
+        Some(Thunks { base: memory as u32 })
+    }
+
+    /// Address of the thunk for a kernel32 import
+    pub fn resolve(&self, dll: &[u8], symbol: &[u8]) -> Option<u64> {
+        if !dll.eq_ignore_ascii_case(b"kernel32.dll") {
+            return None;
+        }
+        let index = SHIMS.iter().position(|shim| shim.name == symbol)?;
+        Some((self.base + (index * THUNK_SIZE) as u32) as u64)
+    }
+
+    fn return_stub(&self) -> u32 {
+        self.base + (SHIMS.len() * THUNK_SIZE) as u32
+    }
+}
+
+/// `jmp far USER_CODE:target`, as 32-bit code
+fn far_jump(code: &mut [u8], target: u32) {
+    code[0] = 0xEA;
+    code[1..5].copy_from_slice(&target.to_le_bytes());
+    code[5..7].copy_from_slice(&USER_CODE.to_le_bytes());
+}
+
+/// SYS_MALLOC memory that 32-bit code can reach
+pub fn alloc(size: usize) -> Option<*mut u8> {
+    let p = crate::alloc(size)?;
+    if (p as u64).checked_add(size as u64).is_some_and(|end| end <= 1 << 32) {
+        Some(p)
+    } else {
+        syscalls::free(p);
+        None
+    }
+}
+
+/// Call a 32-bit program's entry point and return what it returns
+///
+/// # Safety
+/// `entry` must be the entry point of a PE32 image loaded below 4GB with
+/// its imports bound through `thunks`.
+pub unsafe fn call(entry: u64, thunks: &Thunks) -> u32 {
+    call32(entry, thunks.return_stub() as u64)
+}
+
+/// winrun's rsp while the program runs, for `return64`
+static mut SAVED_RSP: u64 = 0;
+
+extern "C" {
+    fn call32(entry: u64, stub: u64) -> u32;
+    fn bridge64();
+    fn return64();
+}
+
+core::arch::global_asm!(
+    // call32(entry, stub): enter 32-bit code as if it were called
+    ".globl call32",
+    "call32:",
+    "    push rbx",
+    "    push rbp",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov [rip + {saved_rsp}], rsp",
+    "    mov ax, {user_data}",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    and rsp, -16",
+    "    sub rsp, 4",
+    "    mov dword ptr [rsp], esi",
+    "    push {user_code32}",
+    "    push rdi",
+    "    retfq",
+
+    // The entry point returned through the stub, with its result in eax
+    ".globl return64",
+    "return64:",
+    "    mov rsp, [rip + {saved_rsp}]",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop rbp",
+    "    pop rbx",
+    "    mov eax, eax",
+    "    ret",
+
+    // From a thunk: eax = shim index, [esp] = return address, then the
+    // arguments. ebx, ebp, esi and edi must survive for the caller.
+    ".globl bridge64",
+    "bridge64:",
+    "    mov esp, esp",
+    "    mov r13, rsi",
+    "    mov r14, rdi",
+    "    mov r12, rsp",
+    "    mov edi, eax",
+    "    lea rsi, [rsp + 4]",
+    "    and rsp, -16",
+    "    call {dispatch}",
+    "    mov rsp, r12",
+    "    mov rsi, r13",
+    "    mov rdi, r14",
+    "    mov rdx, rax",
+    "    shr rdx, 32",
+    "    mov ecx, dword ptr [rsp]",
+    "    lea rsp, [rsp + rdx + 4]",
+    "    push {user_code32}",
+    "    push rcx",
+    "    retfq",
+    saved_rsp = sym SAVED_RSP,
+    dispatch = sym dispatch32,
+    user_data = const USER_DATA,
+    user_code32 = const USER_CODE32,
+);
+
+/// Run shim `index` on the arguments at `args`; returns the bytes of
+/// arguments to pop in the high half and the result in the low half
+unsafe extern "C" fn dispatch32(index: u32, args: *const u32) -> u64 {
+    let Some(shim) = SHIMS.get(index as usize) else {
+        crate::fail(b"bad thunk")
+    };
+    let result = (shim.call)(core::slice::from_raw_parts(args, shim.args));
+    ((shim.args as u64 * 4) << 32) | result as u64
+}
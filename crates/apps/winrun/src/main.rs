@@ -0,0 +1,269 @@
+//! WATOS Windows program runner - winrun
+//!
+//! Usage: winrun PROGRAM.EXE [ARGS...]
+//!
+//! SYS_EXEC starts this in place of a Windows (PE) program, with the
+//! program's path and arguments. It loads the program with `watos-pe` and
+//! calls its entry point, binding its imports to a tiny kernel32:
+//!
+//! - `GetStdHandle`, `WriteFile`, `WriteConsoleA`, `ReadFile` over the
+//!   standard file descriptors
+//! - `GetCommandLineA`, the path and arguments joined Windows-style
+//! - `ExitProcess`, and returning from the entry point, to exit
+//!
+//! That is enough for trivially linked console programs (built with
+//! `-nostdlib` against kernel32 only), x86-64 or 32-bit x86; the latter run
+//! in compatibility mode (see `compat`). A program needing anything else is
+//! refused before it starts, naming the first missing import.
+
+#![no_std]
+#![no_main]
+
+mod compat;
+
+use core::panic::PanicInfo;
+use watos_pe::{Arch, Error};
+use watos_syscall::{argv, syscalls};
+
+/// Exit status when the program can't be loaded (as for a missing command)
+const LOAD_FAILED: i32 = 127;
+
+/// Longest command line GetCommandLineA returns
+const COMMAND_LINE_MAX: usize = 1024;
+
+/// GetCommandLineA's string, NUL-terminated
+static mut COMMAND_LINE: [u8; COMMAND_LINE_MAX + 1] = [0; COMMAND_LINE_MAX + 1];
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut buf = [0u8; argv::MAX_BYTES];
+    let len = syscalls::getargv(&mut buf).unwrap_or(0);
+    let mut args = argv::decode(&buf[..len]).skip(1);
+    let Some(path) = args.next() else {
+        fail(b"usage: winrun PROGRAM.EXE [ARGS...]");
+    };
+    build_command_line(argv::decode(&buf[..len]).skip(1));
+
+    let Some(file) = read_file(path) else {
+        fail(b"can't read program");
+    };
+    let arch = watos_pe::arch(file).unwrap_or_else(|err| refuse(err));
+    let thunks = match arch {
+        Arch::X86 => Some(compat::Thunks::new().unwrap_or_else(|| fail(b"out of memory"))),
+        Arch::X64 => None,
+    };
+    let image = unsafe {
+        match thunks {
+            Some(thunks) => watos_pe::load(file, &mut compat::alloc, &mut move |dll, symbol| thunks.resolve(dll, symbol)),
+            None => watos_pe::load(file, &mut alloc, &mut resolve),
+        }
+    };
+    syscalls::free(file.as_ptr() as *mut u8);
+    let image = image.unwrap_or_else(|err| refuse(err));
+
+    let status = match &thunks {
+        Some(thunks) => unsafe { compat::call(image.entry, thunks) },
+        None => {
+            let entry: extern "win64" fn() -> u32 = unsafe { core::mem::transmute(image.entry) };
+            entry()
+        }
+    };
+    syscalls::exit(status as i32)
+}
+
+/// Report why the program can't be loaded and exit
+fn refuse(err: Error) -> ! {
+    match err {
+        Error::Unresolved { dll, symbol } => {
+            syscalls::write(2, b"winrun: unsupported import ");
+            syscalls::write(2, dll.as_bytes());
+            syscalls::write(2, b"!");
+            syscalls::write(2, symbol.as_bytes());
+            syscalls::write(2, b"\n");
+            syscalls::exit(LOAD_FAILED)
+        }
+        Error::Malformed(why) | Error::Unsupported(why) => fail(why.as_bytes()),
+        Error::NoMemory => fail(b"out of memory"),
+    }
+}
+
+/// Report a load failure on stderr and exit
+fn fail(msg: &[u8]) -> ! {
+    syscalls::write(2, b"winrun: ");
+    syscalls::write(2, msg);
+    syscalls::write(2, b"\n");
+    syscalls::exit(LOAD_FAILED)
+}
+
+/// Read a whole file into SYS_MALLOC memory
+fn read_file(path: &str) -> Option<&'static [u8]> {
+    let (kind, size) = syscalls::stat(path)?;
+    if kind != 0 || size == 0 {
+        return None;
+    }
+    let size = size as usize;
+
+    let fd = syscalls::open(path, watos_syscall::open::O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let buf = syscalls::malloc(size);
+    if buf.is_null() {
+        syscalls::close(fd);
+        return None;
+    }
+
+    let data = unsafe { core::slice::from_raw_parts_mut(buf, size) };
+    let mut done = 0;
+    while done < size {
+        let n = syscalls::read(fd, &mut data[done..]);
+        if n == 0 || n > size - done {
+            break;
+        }
+        done += n;
+    }
+    syscalls::close(fd);
+
+    if done < size {
+        syscalls::free(buf);
+        return None;
+    }
+    Some(data)
+}
+
+/// Image memory: SYS_MALLOC memory is executable, and is never freed
+fn alloc(size: usize) -> Option<*mut u8> {
+    let p = syscalls::malloc(size);
+    if p.is_null() {
+        None
+    } else {
+        Some(p)
+    }
+}
+
+/// Join the arguments as Windows programs expect to split them again:
+/// arguments with spaces or tabs, or empty ones, go in double quotes, and
+/// quotes inside them are escaped with a backslash
+fn build_command_line<'a>(args: impl Iterator<Item = &'a str>) {
+    let line = unsafe { &mut *core::ptr::addr_of_mut!(COMMAND_LINE) };
+    let mut len = 0;
+    let mut push = |byte: u8| {
+        if len < COMMAND_LINE_MAX {
+            line[len] = byte;
+            len += 1;
+        }
+    };
+    for (i, arg) in args.enumerate() {
+        if i > 0 {
+            push(b' ');
+        }
+        let quote = arg.is_empty() || arg.contains([' ', '\t']);
+        if quote {
+            push(b'"');
+        }
+        for byte in arg.bytes() {
+            if byte == b'"' {
+                push(b'\\');
+            }
+            push(byte);
+        }
+        if quote {
+            push(b'"');
+        }
+    }
+    line[len] = 0;
+}
+
+// ============================================================================
+// KERNEL32
+// ============================================================================
+
+const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+const INVALID_HANDLE_VALUE: u64 = u64::MAX;
+
+/// Handles are file descriptors plus one, so stdin isn't the null handle
+fn handle_fd(handle: u64) -> Option<i32> {
+    match handle {
+        0 | INVALID_HANDLE_VALUE => None,
+        h => Some((h - 1) as i32),
+    }
+}
+
+fn resolve(dll: &[u8], symbol: &[u8]) -> Option<u64> {
+    if !dll.eq_ignore_ascii_case(b"kernel32.dll") {
+        return None;
+    }
+    let address = match symbol {
+        b"GetStdHandle" => get_std_handle as *const () as usize,
+        b"WriteFile" => write_file as *const () as usize,
+        b"WriteConsoleA" => write_console_a as *const () as usize,
+        b"ReadFile" => read_file_shim as *const () as usize,
+        b"GetCommandLineA" => get_command_line_a as *const () as usize,
+        b"ExitProcess" => exit_process as *const () as usize,
+        _ => return None,
+    };
+    Some(address as u64)
+}
+
+extern "win64" fn get_std_handle(which: u32) -> u64 {
+    match which {
+        STD_INPUT_HANDLE => 1,
+        STD_OUTPUT_HANDLE => 2,
+        STD_ERROR_HANDLE => 3,
+        _ => INVALID_HANDLE_VALUE,
+    }
+}
+
+/// Write through a handle; the count goes to `done` if it isn't null
+unsafe fn write_handle(handle: u64, buf: *const u8, len: u32, done: *mut u32) -> i32 {
+    let Some(fd) = handle_fd(handle) else { return 0 };
+    let n = syscalls::write(fd, core::slice::from_raw_parts(buf, len as usize));
+    if n > len as usize {
+        return 0;
+    }
+    if !done.is_null() {
+        *done = n as u32;
+    }
+    1
+}
+
+extern "win64" fn write_file(handle: u64, buf: *const u8, len: u32, written: *mut u32, _overlapped: *mut u8) -> i32 {
+    unsafe { write_handle(handle, buf, len, written) }
+}
+
+extern "win64" fn write_console_a(handle: u64, buf: *const u8, len: u32, written: *mut u32, _reserved: *mut u8) -> i32 {
+    unsafe { write_handle(handle, buf, len, written) }
+}
+
+/// Read through a handle; the count goes to `done` if it isn't null
+unsafe fn read_handle(handle: u64, buf: *mut u8, len: u32, done: *mut u32) -> i32 {
+    let Some(fd) = handle_fd(handle) else { return 0 };
+    let n = syscalls::read(fd, core::slice::from_raw_parts_mut(buf, len as usize));
+    if n > len as usize {
+        return 0;
+    }
+    if !done.is_null() {
+        *done = n as u32;
+    }
+    1
+}
+
+extern "win64" fn read_file_shim(handle: u64, buf: *mut u8, len: u32, read: *mut u32, _overlapped: *mut u8) -> i32 {
+    unsafe { read_handle(handle, buf, len, read) }
+}
+
+extern "win64" fn get_command_line_a() -> *const u8 {
+    core::ptr::addr_of!(COMMAND_LINE) as *const u8
+}
+
+extern "win64" fn exit_process(code: u32) -> ! {
+    syscalls::exit(code as i32)
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    fail(b"internal error")
+}
@@ -0,0 +1,61 @@
+//! WATOS yes command - print a line over and over
+//!
+//! Usage: yes [STRING...]
+//!
+//! Prints its arguments joined by spaces, or "y", once per line until the
+//! output stops taking it: in `yes | head` that is when head exits and
+//! the pipe has no reader left.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use watos_syscall::{argv, syscalls};
+
+/// Bytes written per SYS_WRITE; the line is repeated to fill it
+const BLOCK: usize = 4096;
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let mut args_buf = [0u8; argv::MAX_BYTES];
+    let args_len = syscalls::getargv(&mut args_buf).unwrap_or(0);
+
+    let mut line = [0u8; BLOCK];
+    let mut len = 0;
+    for (i, arg) in argv::decode(&args_buf[..args_len]).skip(1).enumerate() {
+        let sep = usize::from(i > 0);
+        if len + sep + arg.len() + 2 > BLOCK {
+            break;
+        }
+        if sep == 1 {
+            line[len] = b' ';
+        }
+        line[len + sep..len + sep + arg.len()].copy_from_slice(arg.as_bytes());
+        len += sep + arg.len();
+    }
+    if len == 0 {
+        line[0] = b'y';
+        len = 1;
+    }
+    line[len..len + 2].copy_from_slice(b"\r\n");
+    len += 2;
+
+    let mut block = [0u8; BLOCK];
+    let mut fill = 0;
+    while fill + len <= BLOCK {
+        block[fill..fill + len].copy_from_slice(&line[..len]);
+        fill += len;
+    }
+
+    loop {
+        let written = syscalls::write(1, &block[..fill]);
+        if written == 0 || written > fill {
+            syscalls::exit(0);
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    syscalls::exit(1)
+}
@@ -6,8 +6,10 @@ use uefi::table::boot::{AllocateType, MemoryType};
 use uefi::proto::console::gop::GraphicsOutput;
 use uefi::proto::media::file::{File, FileAttribute, FileMode, FileType};
 use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::loaded_image::LoadedImage;
 use uefi::cstr16;
 use core::fmt::Write;
+use watos_bootcfg::{BootConfig, MAX_CMDLINE_SIZE, MAX_CONFIG_SIZE};
 
 /// Maximum number of preloaded apps
 const MAX_PRELOADED_APPS: usize = 32;
@@ -31,6 +33,17 @@ impl PreloadedApp {
     }
 }
 
+/// Maximum number of GOP modes recorded for the kernel
+const MAX_GOP_MODES: usize = 32;
+
+/// A direct-colour mode the firmware's GOP offers
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GopMode {
+    pub width: u32,
+    pub height: u32,
+}
+
 // Boot info structure passed to kernel at 0x80000
 #[repr(C)]
 pub struct BootInfo {
@@ -46,13 +59,23 @@ pub struct BootInfo {
     pub app_count: u32,            // Number of preloaded apps
     pub _pad: u32,                 // Padding for alignment
     pub apps: [PreloadedApp; MAX_PRELOADED_APPS], // Preloaded app table
+    pub config_len: u32,           // Bytes of /boot/watos.cfg in config
+    pub _pad2: u32,
+    pub config: [u8; MAX_CONFIG_SIZE], // Raw boot configuration text
+    pub cmdline_len: u32,          // Bytes of kernel command line
+    pub _pad3: u32,
+    pub cmdline: [u8; MAX_CMDLINE_SIZE], // Kernel command line (ASCII)
+    pub mode_count: u32,           // Number of GOP modes in modes
+    pub _pad4: u32,
+    pub modes: [GopMode; MAX_GOP_MODES], // Resolutions the kernel may switch to
+    pub rsdp_addr: u64,            // ACPI RSDP from the config table (0 if none)
 }
 
 const BOOT_INFO_ADDR: u64 = 0x80000;
 const BOOT_MAGIC: u32 = 0x5741544F;  // "WATO" in ASCII
 
 #[entry]
-fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+fn main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
 
     // Print boot message
     system_table
@@ -64,7 +87,33 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         .write_str("=====================\n")
         .unwrap();
 
+    // Read /boot/watos.cfg; a missing file leaves every option at its default
+    let mut config = [0u8; MAX_CONFIG_SIZE];
+    let config_len = load_config(&mut system_table, &mut config);
+    if config_len != 0 {
+        writeln!(system_table.stdout(), "Loaded boot config ({} bytes)", config_len).unwrap();
+    }
+    let video_mode = BootConfig::parse(&config[..config_len]).video_mode();
+
+    // Kernel command line: the image's load options (e.g. from the UEFI
+    // shell), else `cmdline = ...` in watos.cfg
+    let mut cmdline = [0u8; MAX_CMDLINE_SIZE];
+    let mut cmdline_len = load_options(&system_table, image_handle, &mut cmdline);
+    if cmdline_len == 0 {
+        if let Some(text) = BootConfig::parse(&config[..config_len]).get("cmdline") {
+            cmdline_len = text.len().min(MAX_CMDLINE_SIZE);
+            cmdline[..cmdline_len].copy_from_slice(&text.as_bytes()[..cmdline_len]);
+        }
+    }
+    if cmdline_len != 0 {
+        let text = core::str::from_utf8(&cmdline[..cmdline_len]).unwrap_or("");
+        writeln!(system_table.stdout(), "Command line: {}", text).unwrap();
+    }
+
     // Get GOP info in a separate scope so we can use system_table later
+    let mut video_mode_set = false;
+    let mut modes = [GopMode { width: 0, height: 0 }; MAX_GOP_MODES];
+    let mut mode_count = 0;
     let (fb_addr, width, height, stride, pf_value) = {
         let gop_handle = system_table
             .boot_services()
@@ -76,6 +125,31 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
             .open_protocol_exclusive::<GraphicsOutput>(gop_handle)
             .expect("Failed to open GOP");
 
+        // Switch to the configured resolution if the firmware offers it
+        if let Some((want_w, want_h)) = video_mode {
+            let mode = gop.modes().find(|m| {
+                m.info().resolution() == (want_w as usize, want_h as usize)
+            });
+            video_mode_set = mode.is_some_and(|mode| gop.set_mode(&mode).is_ok());
+        }
+
+        // Record the 32-bit modes so the kernel can offer them later
+        for mode in gop.modes() {
+            let info = mode.info();
+            if !matches!(info.pixel_format(), uefi::proto::console::gop::PixelFormat::Rgb
+                | uefi::proto::console::gop::PixelFormat::Bgr) {
+                continue;
+            }
+            let (w, h) = info.resolution();
+            let entry = GopMode { width: w as u32, height: h as u32 };
+            let known = modes[..mode_count].iter()
+                .any(|m| m.width == entry.width && m.height == entry.height);
+            if !known && mode_count < MAX_GOP_MODES {
+                modes[mode_count] = entry;
+                mode_count += 1;
+            }
+        }
+
         let mode_info = gop.current_mode_info();
         let (w, h) = mode_info.resolution();
         let s = mode_info.stride();
@@ -91,8 +165,15 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         (addr, w, h, s, pf)
     };
 
-    writeln!(system_table.stdout(), "GOP: {}x{} stride={} fb=0x{:x}",
-             width, height, stride, fb_addr).unwrap();
+    if let Some((want_w, want_h)) = video_mode {
+        if !video_mode_set {
+            writeln!(system_table.stdout(), "Video mode {}x{} unavailable, keeping current",
+                     want_w, want_h).unwrap();
+        }
+    }
+
+    writeln!(system_table.stdout(), "GOP: {}x{} stride={} fb=0x{:x} ({} modes)",
+             width, height, stride, fb_addr, mode_count).unwrap();
 
     // Allocate memory for kernel at a fixed low address
     let kernel_pages = 64; // 64 * 4KB = 256KB for kernel
@@ -141,6 +222,9 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
     writeln!(system_table.stdout(), "Loaded WATOS kernel ({} bytes)", kernel_binary.len())
         .unwrap();
 
+    // ACPI tables, for the kernel's power button and soft-off
+    let rsdp_addr = find_rsdp(&system_table);
+
     // Write boot info structure for kernel
     let boot_info = BootInfo {
         magic: BOOT_MAGIC,
@@ -155,6 +239,16 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         app_count,
         _pad: 0,
         apps,
+        config_len: config_len as u32,
+        _pad2: 0,
+        config,
+        cmdline_len: cmdline_len as u32,
+        _pad3: 0,
+        cmdline,
+        mode_count: mode_count as u32,
+        _pad4: 0,
+        modes,
+        rsdp_addr,
     };
 
     unsafe {
@@ -179,6 +273,99 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
     kernel_entry();
 }
 
+/// Address of the ACPI RSDP, preferring the ACPI 2.0 entry (with the XSDT)
+fn find_rsdp(system_table: &SystemTable<Boot>) -> u64 {
+    use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+    let table = system_table.config_table();
+    table.iter().find(|entry| entry.guid == ACPI2_GUID)
+        .or_else(|| table.iter().find(|entry| entry.guid == ACPI_GUID))
+        .map_or(0, |entry| entry.address as u64)
+}
+
+/// Copy this image's load options into `buf` as ASCII, returning the length
+///
+/// The UEFI shell passes the whole command, so a leading `*.efi` word is
+/// dropped; characters outside printable ASCII become spaces.
+fn load_options(system_table: &SystemTable<Boot>, image_handle: Handle, buf: &mut [u8]) -> usize {
+    let image = match system_table
+        .boot_services()
+        .open_protocol_exclusive::<LoadedImage>(image_handle)
+    {
+        Ok(image) => image,
+        Err(_) => return 0,
+    };
+    let options = match image.load_options_as_cstr16() {
+        Ok(options) => options,
+        Err(_) => return 0,
+    };
+
+    let mut len = 0;
+    for c in options.iter() {
+        if len >= buf.len() {
+            break;
+        }
+        let ch: u16 = (*c).into();
+        buf[len] = if (0x20..0x7F).contains(&ch) { ch as u8 } else { b' ' };
+        len += 1;
+    }
+
+    let text = core::str::from_utf8(&buf[..len]).unwrap_or("").trim();
+    let first = text.split(' ').next().unwrap_or("");
+    let skip = if first.len() >= 4 && first[first.len() - 4..].eq_ignore_ascii_case(".efi") {
+        first.len()
+    } else {
+        0
+    };
+    let args = text[skip..].trim();
+    let (start, args_len) = (args.as_ptr() as usize - buf.as_ptr() as usize, args.len());
+    buf.copy_within(start..start + args_len, 0);
+    args_len
+}
+
+/// Read /boot/watos.cfg into `buf`, returning its length (0 if absent)
+fn load_config(system_table: &mut SystemTable<Boot>, buf: &mut [u8]) -> usize {
+    let fs_handle = match system_table
+        .boot_services()
+        .get_handle_for_protocol::<SimpleFileSystem>()
+    {
+        Ok(h) => h,
+        Err(_) => return 0,
+    };
+
+    let mut fs = match system_table
+        .boot_services()
+        .open_protocol_exclusive::<SimpleFileSystem>(fs_handle)
+    {
+        Ok(fs) => fs,
+        Err(_) => return 0,
+    };
+
+    let mut root = match fs.open_volume() {
+        Ok(r) => r,
+        Err(_) => return 0,
+    };
+
+    let handle = match root.open(cstr16!("boot\\watos.cfg"), FileMode::Read, FileAttribute::empty()) {
+        Ok(h) => h,
+        Err(_) => return 0,
+    };
+
+    let mut file = match handle.into_type() {
+        Ok(FileType::Regular(f)) => f,
+        _ => return 0,
+    };
+
+    // Files larger than the buffer are cut off at MAX_CONFIG_SIZE
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read(&mut buf[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+    len
+}
+
 /// Load system/term from the boot filesystem
 fn load_init_app(system_table: &mut SystemTable<Boot>) -> (u64, u64) {
     // Get the filesystem handle
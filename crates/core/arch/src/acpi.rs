@@ -0,0 +1,285 @@
+//! ACPI fixed hardware: power button, soft-off and reset
+//!
+//! Only what a clean VM shutdown needs, without an AML interpreter: the
+//! FADT gives the PM1 register blocks and the SCI interrupt, and the S5
+//! sleep type is picked out of the DSDT's `_S5_` package by pattern. The
+//! fixed-feature power button raises the SCI when pressed (QEMU's
+//! `system_powerdown`, `virsh shutdown`).
+//!
+//! Tables are read through the identity mapping, so `init` must run with
+//! the kernel's page tables.
+
+use core::ptr::read_unaligned;
+
+use crate::port::{inw, outb, outw};
+
+// PM1 status and enable bits
+const PWRBTN: u16 = 1 << 8;
+// PM1 control bits
+const SCI_EN: u16 = 1 << 0;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_TYP_MASK: u16 = 7 << SLP_TYP_SHIFT;
+const SLP_EN: u16 = 1 << 13;
+
+/// FADT flag: the power button is a control method device, not fixed
+const FADT_PWR_BUTTON: u32 = 1 << 4;
+/// FADT flag: RESET_REG is valid
+const FADT_RESET_REG_SUP: u32 = 1 << 10;
+/// Generic address space ID for system I/O
+const GAS_SYSTEM_IO: u8 = 1;
+
+/// What the kernel keeps from the tables
+#[derive(Debug, Clone, Copy)]
+pub struct Acpi {
+    /// Interrupt the SCI arrives on
+    pub sci_irq: u8,
+    smi_cmd: u16,
+    acpi_enable: u8,
+    pm1a_evt: u16,
+    pm1b_evt: u16,
+    pm1_evt_len: u16,
+    pm1a_cnt: u16,
+    pm1b_cnt: u16,
+    /// SLP_TYPa and SLP_TYPb for S5 (soft off)
+    s5: Option<(u8, u8)>,
+    /// I/O port and value that reset the machine
+    reset: Option<(u16, u8)>,
+    fixed_power_button: bool,
+}
+
+static mut ACPI: Option<Acpi> = None;
+
+unsafe fn read8(addr: u64) -> u8 {
+    read_unaligned(addr as *const u8)
+}
+
+unsafe fn read16(addr: u64) -> u16 {
+    read_unaligned(addr as *const u16)
+}
+
+unsafe fn read32(addr: u64) -> u32 {
+    read_unaligned(addr as *const u32)
+}
+
+unsafe fn read64(addr: u64) -> u64 {
+    read_unaligned(addr as *const u64)
+}
+
+unsafe fn checksum_ok(addr: u64, len: usize) -> bool {
+    (0..len as u64).fold(0u8, |sum, i| sum.wrapping_add(read8(addr + i))) == 0
+}
+
+/// Look for the RSDP where BIOS machines keep it; UEFI passes its address
+/// instead
+pub fn find_rsdp() -> Option<u64> {
+    (0xE0000u64..0x100000).step_by(16).find(|&addr| unsafe {
+        core::slice::from_raw_parts(addr as *const u8, 8) == b"RSD PTR " && checksum_ok(addr, 20)
+    })
+}
+
+/// Address of the table with `signature`, from the XSDT (or RSDT)
+unsafe fn find_table(rsdp: u64, signature: &[u8; 4]) -> Option<u64> {
+    if core::slice::from_raw_parts(rsdp as *const u8, 8) != b"RSD PTR " || !checksum_ok(rsdp, 20) {
+        return None;
+    }
+    let (root, entry_size) = if read8(rsdp + 15) >= 2 && read64(rsdp + 24) != 0 {
+        (read64(rsdp + 24), 8)
+    } else {
+        (read32(rsdp + 16) as u64, 4)
+    };
+    let len = read32(root + 4) as u64;
+    let count = len.saturating_sub(36) / entry_size;
+    (0..count)
+        .map(|i| match entry_size {
+            8 => read64(root + 36 + i * 8),
+            _ => read32(root + 36 + i * 4) as u64,
+        })
+        .find(|&table| {
+            core::slice::from_raw_parts(table as *const u8, 4) == signature
+                && checksum_ok(table, read32(table + 4) as usize)
+        })
+}
+
+/// SLP_TYPa and SLP_TYPb from `Name(_S5_, Package() { a, b, ... })`
+fn parse_s5(aml: &[u8]) -> Option<(u8, u8)> {
+    let at = aml.windows(4).position(|w| w == b"_S5_")?;
+    // NameOp, optionally with a root prefix
+    let named = at >= 1 && (aml[at - 1] == 0x08 || (at >= 2 && aml[at - 2] == 0x08 && aml[at - 1] == b'\\'));
+    let mut p = at + 4;
+    if !named || *aml.get(p)? != 0x12 {
+        return None;
+    }
+    // PackageOp, PkgLength (extra bytes in its top two bits), NumElements
+    p += 1;
+    p += 1 + (*aml.get(p)? >> 6) as usize;
+    p += 1;
+    let mut element = || {
+        let value = match *aml.get(p)? {
+            0x0A => {
+                p += 1;
+                *aml.get(p)?
+            }
+            0x00 => 0,
+            0x01 => 1,
+            _ => return None,
+        };
+        p += 1;
+        Some(value)
+    };
+    let a = element()?;
+    let b = element().unwrap_or(0);
+    Some((a, b))
+}
+
+/// Read the FADT and DSDT, switch the chipset to ACPI mode and enable the
+/// power button event. False if the tables aren't usable.
+pub fn init(rsdp: u64) -> bool {
+    let acpi = match unsafe { read_fadt(rsdp) } {
+        Some(acpi) => acpi,
+        None => return false,
+    };
+    unsafe {
+        // SMM owns the PM registers until asked to let go
+        if inw(acpi.pm1a_cnt) & SCI_EN == 0 && acpi.smi_cmd != 0 && acpi.acpi_enable != 0 {
+            outb(acpi.smi_cmd, acpi.acpi_enable);
+            for _ in 0..1_000_000 {
+                if inw(acpi.pm1a_cnt) & SCI_EN != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        if acpi.fixed_power_button {
+            for evt in [acpi.pm1a_evt, acpi.pm1b_evt] {
+                if evt != 0 {
+                    // Status bits clear when written with 1
+                    outw(evt, PWRBTN);
+                    let enable = evt + acpi.pm1_evt_len / 2;
+                    outw(enable, inw(enable) | PWRBTN);
+                }
+            }
+        }
+        ACPI = Some(acpi);
+    }
+    true
+}
+
+unsafe fn read_fadt(rsdp: u64) -> Option<Acpi> {
+    let fadt = find_table(rsdp, b"FACP")?;
+    let len = read32(fadt + 4);
+    let pm1a_cnt = read32(fadt + 64) as u16;
+    let pm1a_evt = read32(fadt + 56) as u16;
+    if pm1a_cnt == 0 || pm1a_evt == 0 {
+        return None;
+    }
+    let flags = if len >= 116 { read32(fadt + 112) } else { 0 };
+
+    let dsdt = if len >= 148 && read64(fadt + 140) != 0 { read64(fadt + 140) } else { read32(fadt + 40) as u64 };
+    let s5 = if dsdt != 0 && core::slice::from_raw_parts(dsdt as *const u8, 4) == b"DSDT" {
+        let dsdt_len = read32(dsdt + 4) as usize;
+        parse_s5(core::slice::from_raw_parts((dsdt + 36) as *const u8, dsdt_len.saturating_sub(36)))
+    } else {
+        None
+    };
+
+    let reset = if len >= 129 && flags & FADT_RESET_REG_SUP != 0 && read8(fadt + 116) == GAS_SYSTEM_IO {
+        Some((read64(fadt + 120) as u16, read8(fadt + 128)))
+    } else {
+        None
+    };
+
+    Some(Acpi {
+        sci_irq: read16(fadt + 46) as u8,
+        smi_cmd: read32(fadt + 48) as u16,
+        acpi_enable: read8(fadt + 52),
+        pm1a_evt,
+        pm1b_evt: read32(fadt + 60) as u16,
+        pm1_evt_len: read8(fadt + 88) as u16,
+        pm1a_cnt,
+        pm1b_cnt: read32(fadt + 68) as u16,
+        s5,
+        reset,
+        fixed_power_button: flags & FADT_PWR_BUTTON == 0,
+    })
+}
+
+/// What `init` found, if it succeeded
+pub fn info() -> Option<Acpi> {
+    unsafe { ACPI }
+}
+
+impl Acpi {
+    /// Can the machine be switched off through PM1 control?
+    pub fn can_power_off(&self) -> bool {
+        self.s5.is_some()
+    }
+
+    /// Is the power button reported through the SCI?
+    pub fn has_power_button(&self) -> bool {
+        self.fixed_power_button
+    }
+}
+
+/// Was the power button pressed since the last call? Clears the event, so
+/// call from the SCI handler.
+pub fn take_power_button() -> bool {
+    let acpi = match info() {
+        Some(acpi) => acpi,
+        None => return false,
+    };
+    let mut pressed = false;
+    for evt in [acpi.pm1a_evt, acpi.pm1b_evt] {
+        if evt == 0 {
+            continue;
+        }
+        unsafe {
+            let status = inw(evt);
+            if status & PWRBTN != 0 {
+                pressed = true;
+            }
+            // Acknowledge everything pending so a level SCI drops
+            if status != 0 {
+                outw(evt, status);
+            }
+        }
+    }
+    pressed
+}
+
+/// Enter S5. Returns, with interrupts off, only if the machine is still
+/// running.
+pub fn power_off() {
+    let acpi = match info() {
+        Some(acpi) => acpi,
+        None => return,
+    };
+    let (a, b) = match acpi.s5 {
+        Some(s5) => s5,
+        None => return,
+    };
+    unsafe {
+        core::arch::asm!("cli", options(nostack, preserves_flags));
+        let cnt = inw(acpi.pm1a_cnt) & !SLP_TYP_MASK;
+        outw(acpi.pm1a_cnt, cnt | ((a as u16) << SLP_TYP_SHIFT) | SLP_EN);
+        if acpi.pm1b_cnt != 0 {
+            let cnt = inw(acpi.pm1b_cnt) & !SLP_TYP_MASK;
+            outw(acpi.pm1b_cnt, cnt | ((b as u16) << SLP_TYP_SHIFT) | SLP_EN);
+        }
+        for _ in 0..10_000_000 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Reset through the FADT reset register. Returns if there is none or it
+/// did nothing.
+pub fn reset() {
+    if let Some((port, value)) = info().and_then(|acpi| acpi.reset) {
+        unsafe {
+            outb(port, value);
+            for _ in 0..1_000_000 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
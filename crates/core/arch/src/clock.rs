@@ -0,0 +1,178 @@
+//! Monotonic clock
+//!
+//! Nanoseconds since [`init`], from the best source the machine has:
+//!
+//! - kvmclock under KVM: the host publishes a TSC-to-nanoseconds scale in
+//!   a page we share with it, so the clock survives migration and host
+//!   frequency changes
+//! - the TSC, when it is invariant and its frequency is known from CPUID
+//!   or measured against the PIT
+//! - PIT ticks (~18.2 Hz) when nothing better is there
+
+use core::ptr::{addr_of, addr_of_mut, read_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+use crate::cpu::{self, rdtsc};
+use crate::hypervisor::{self, KVM_FEATURE_CLOCKSOURCE2};
+
+/// kvmclock: where the host publishes this CPU's time info
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b56_4d01;
+
+/// Nanoseconds per PIT tick (1193182 Hz / 65536)
+const NS_PER_TICK: u64 = 54_925_439;
+
+/// PIT ticks to measure the TSC over
+const CALIBRATION_TICKS: u64 = 2;
+
+/// Where [`now_ns`] gets the time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    KvmClock,
+    Tsc,
+    Pit,
+}
+
+impl ClockSource {
+    /// Name as Linux's clocksource sysfs has it
+    pub fn name(self) -> &'static str {
+        match self {
+            ClockSource::KvmClock => "kvm-clock",
+            ClockSource::Tsc => "tsc",
+            ClockSource::Pit => "pit",
+        }
+    }
+}
+
+/// pvclock_vcpu_time_info, written by the host
+#[repr(C, align(32))]
+struct PvClock {
+    version: u32,
+    _pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    _pad: [u8; 2],
+}
+
+static mut PVCLOCK: PvClock = PvClock {
+    version: 0,
+    _pad0: 0,
+    tsc_timestamp: 0,
+    system_time: 0,
+    tsc_to_system_mul: 0,
+    tsc_shift: 0,
+    flags: 0,
+    _pad: [0; 2],
+};
+
+static mut SOURCE: ClockSource = ClockSource::Pit;
+static mut TSC_KHZ: u64 = 0;
+/// Raw reading of the source at `init`
+static mut BASE: u64 = 0;
+
+/// Pick the clock source. Interrupts must be on, with the PIT running, in
+/// case the TSC has to be measured.
+pub fn init() -> ClockSource {
+    let hv = hypervisor::detect();
+    unsafe {
+        if hv.is_some_and(|hv| hv.kvm_features() & KVM_FEATURE_CLOCKSOURCE2 != 0) {
+            // The kernel is identity mapped: the address is physical
+            cpu::wrmsr(MSR_KVM_SYSTEM_TIME_NEW, addr_of!(PVCLOCK) as u64 | 1);
+            if read_volatile(addr_of!(PVCLOCK.version)) != 0 {
+                SOURCE = ClockSource::KvmClock;
+                BASE = kvmclock_ns();
+                return SOURCE;
+            }
+        }
+
+        if cpu::invariant_tsc() {
+            let khz = hv.and_then(|hv| hv.tsc_khz())
+                .or_else(cpu::tsc_khz)
+                .unwrap_or_else(calibrate_tsc);
+            if khz != 0 {
+                SOURCE = ClockSource::Tsc;
+                TSC_KHZ = khz;
+                BASE = rdtsc();
+                return SOURCE;
+            }
+        }
+
+        SOURCE = ClockSource::Pit;
+        BASE = crate::idt::get_ticks();
+        SOURCE
+    }
+}
+
+/// The source `init` picked
+pub fn source() -> ClockSource {
+    unsafe { SOURCE }
+}
+
+/// TSC frequency in kHz, if the TSC is the clock source
+pub fn tsc_khz() -> Option<u64> {
+    match source() {
+        ClockSource::Tsc => Some(unsafe { TSC_KHZ }),
+        _ => None,
+    }
+}
+
+/// Nanoseconds since `init`
+pub fn now_ns() -> u64 {
+    unsafe {
+        match SOURCE {
+            ClockSource::KvmClock => kvmclock_ns().wrapping_sub(BASE),
+            ClockSource::Tsc => ((rdtsc().wrapping_sub(BASE) as u128 * 1_000_000) / TSC_KHZ as u128) as u64,
+            ClockSource::Pit => crate::idt::get_ticks().wrapping_sub(BASE) * NS_PER_TICK,
+        }
+    }
+}
+
+/// Milliseconds since `init`
+pub fn now_ms() -> u64 {
+    now_ns() / 1_000_000
+}
+
+/// The host's system time, consistent across an update it may be making
+unsafe fn kvmclock_ns() -> u64 {
+    let clock = addr_of_mut!(PVCLOCK);
+    loop {
+        let version = read_volatile(addr_of!((*clock).version));
+        fence(Ordering::Acquire);
+        let tsc_timestamp = read_volatile(addr_of!((*clock).tsc_timestamp));
+        let system_time = read_volatile(addr_of!((*clock).system_time));
+        let mul = read_volatile(addr_of!((*clock).tsc_to_system_mul));
+        let shift = read_volatile(addr_of!((*clock).tsc_shift));
+        let tsc = rdtsc();
+        fence(Ordering::Acquire);
+        // Odd while the host is writing
+        if version & 1 != 0 || read_volatile(addr_of!((*clock).version)) != version {
+            continue;
+        }
+        let mut delta = tsc.wrapping_sub(tsc_timestamp);
+        if shift < 0 {
+            delta >>= -shift as u32;
+        } else {
+            delta <<= shift as u32;
+        }
+        return system_time.wrapping_add(((delta as u128 * mul as u128) >> 32) as u64);
+    }
+}
+
+/// Count TSC cycles over a few PIT ticks, in kHz
+fn calibrate_tsc() -> u64 {
+    let wait_tick = || {
+        let start = crate::idt::get_ticks();
+        while crate::idt::get_ticks() == start {
+            core::hint::spin_loop();
+        }
+    };
+    wait_tick();
+    let start = rdtsc();
+    for _ in 0..CALIBRATION_TICKS {
+        wait_tick();
+    }
+    let cycles = rdtsc().wrapping_sub(start);
+    (cycles as u128 * 1_000_000 / (CALIBRATION_TICKS * NS_PER_TICK) as u128) as u64
+}
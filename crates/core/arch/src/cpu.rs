@@ -0,0 +1,433 @@
+//! CPU identification
+//!
+//! Reads vendor, brand, family/model, feature flags and core counts with
+//! CPUID, and frequency and temperature from MSRs where CPUID says they
+//! exist. Used for /proc/cpuinfo.
+//!
+//! Also the TSC and RDRAND, as timing and entropy sources, and the CR4
+//! protections that keep the kernel out of user pages.
+
+use core::arch::x86_64::{CpuidResult, __cpuid_count};
+
+/// MSRs
+const IA32_MPERF: u32 = 0xE7;
+const IA32_APERF: u32 = 0xE8;
+const IA32_THERM_STATUS: u32 = 0x19C;
+const MSR_TEMPERATURE_TARGET: u32 = 0x1A2;
+
+/// TjMax when MSR_TEMPERATURE_TARGET cannot be read
+const DEFAULT_TJMAX: u32 = 100;
+
+/// CR4 bits
+const CR4_UMIP: u64 = 1 << 11;
+const CR4_SMEP: u64 = 1 << 20;
+const CR4_SMAP: u64 = 1 << 21;
+
+/// CPUID register a flag lives in
+#[derive(Clone, Copy)]
+enum Reg {
+    /// Leaf 1 EDX
+    Std1Edx,
+    /// Leaf 1 ECX
+    Std1Ecx,
+    /// Leaf 7 EBX
+    Std7Ebx,
+    /// Leaf 0x80000001 EDX
+    Ext1Edx,
+    /// Leaf 0x80000001 ECX
+    Ext1Ecx,
+}
+
+/// Feature flags with their Linux /proc/cpuinfo names
+const FLAGS: &[(Reg, u8, &str)] = &[
+    (Reg::Std1Edx, 0, "fpu"),
+    (Reg::Std1Edx, 3, "pse"),
+    (Reg::Std1Edx, 4, "tsc"),
+    (Reg::Std1Edx, 5, "msr"),
+    (Reg::Std1Edx, 6, "pae"),
+    (Reg::Std1Edx, 8, "cx8"),
+    (Reg::Std1Edx, 9, "apic"),
+    (Reg::Std1Edx, 11, "sep"),
+    (Reg::Std1Edx, 12, "mtrr"),
+    (Reg::Std1Edx, 13, "pge"),
+    (Reg::Std1Edx, 15, "cmov"),
+    (Reg::Std1Edx, 16, "pat"),
+    (Reg::Std1Edx, 19, "clflush"),
+    (Reg::Std1Edx, 22, "acpi"),
+    (Reg::Std1Edx, 23, "mmx"),
+    (Reg::Std1Edx, 24, "fxsr"),
+    (Reg::Std1Edx, 25, "sse"),
+    (Reg::Std1Edx, 26, "sse2"),
+    (Reg::Std1Edx, 28, "ht"),
+    (Reg::Std1Edx, 29, "tm"),
+    (Reg::Ext1Edx, 11, "syscall"),
+    (Reg::Ext1Edx, 20, "nx"),
+    (Reg::Ext1Edx, 26, "pdpe1gb"),
+    (Reg::Ext1Edx, 27, "rdtscp"),
+    (Reg::Ext1Edx, 29, "lm"),
+    (Reg::Std1Ecx, 0, "pni"),
+    (Reg::Std1Ecx, 1, "pclmulqdq"),
+    (Reg::Std1Ecx, 3, "monitor"),
+    (Reg::Std1Ecx, 5, "vmx"),
+    (Reg::Std1Ecx, 7, "est"),
+    (Reg::Std1Ecx, 9, "ssse3"),
+    (Reg::Std1Ecx, 12, "fma"),
+    (Reg::Std1Ecx, 13, "cx16"),
+    (Reg::Std1Ecx, 19, "sse4_1"),
+    (Reg::Std1Ecx, 20, "sse4_2"),
+    (Reg::Std1Ecx, 21, "x2apic"),
+    (Reg::Std1Ecx, 22, "movbe"),
+    (Reg::Std1Ecx, 23, "popcnt"),
+    (Reg::Std1Ecx, 24, "tsc_deadline_timer"),
+    (Reg::Std1Ecx, 25, "aes"),
+    (Reg::Std1Ecx, 26, "xsave"),
+    (Reg::Std1Ecx, 28, "avx"),
+    (Reg::Std1Ecx, 29, "f16c"),
+    (Reg::Std1Ecx, 30, "rdrand"),
+    (Reg::Std1Ecx, 31, "hypervisor"),
+    (Reg::Ext1Ecx, 0, "lahf_lm"),
+    (Reg::Ext1Ecx, 2, "svm"),
+    (Reg::Ext1Ecx, 5, "abm"),
+    (Reg::Ext1Ecx, 6, "sse4a"),
+    (Reg::Std7Ebx, 0, "fsgsbase"),
+    (Reg::Std7Ebx, 3, "bmi1"),
+    (Reg::Std7Ebx, 5, "avx2"),
+    (Reg::Std7Ebx, 7, "smep"),
+    (Reg::Std7Ebx, 8, "bmi2"),
+    (Reg::Std7Ebx, 9, "erms"),
+    (Reg::Std7Ebx, 16, "avx512f"),
+    (Reg::Std7Ebx, 18, "rdseed"),
+    (Reg::Std7Ebx, 19, "adx"),
+    (Reg::Std7Ebx, 20, "smap"),
+    (Reg::Std7Ebx, 29, "sha_ni"),
+];
+
+/// What CPUID and the MSRs say about the boot CPU
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    /// "GenuineIntel", "AuthenticAMD", ...
+    pub vendor: [u8; 12],
+    /// Brand string, NUL padded (empty if the CPU has none)
+    pub brand: [u8; 48],
+    /// Display family and model (extended fields folded in)
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    /// Logical processors per package
+    pub siblings: u32,
+    /// Cores per package
+    pub cores: u32,
+    /// Base and maximum frequency in MHz (0 if unknown)
+    pub base_mhz: u32,
+    pub max_mhz: u32,
+    /// Average running frequency since reset from APERF/MPERF, in MHz
+    pub current_mhz: Option<u32>,
+    /// Package temperature in degrees C from the digital thermal sensor
+    pub temperature: Option<u32>,
+    std1_ecx: u32,
+    std1_edx: u32,
+    std7_ebx: u32,
+    ext1_ecx: u32,
+    ext1_edx: u32,
+}
+
+pub(crate) fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    __cpuid_count(leaf, subleaf)
+}
+
+/// Read a model-specific register
+///
+/// # Safety
+///
+/// Must run at CPL0, and `msr` must be a valid MSR that this CPU supports
+/// (as CPUID reports); anything else raises #GP.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (lo, hi): (u32, u32);
+    core::arch::asm!("rdmsr", in("ecx") msr, out("eax") lo, out("edx") hi,
+                     options(nomem, nostack, preserves_flags));
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Write a model-specific register
+///
+/// # Safety
+///
+/// Must run at CPL0, and `msr` must be a valid MSR that this CPU supports
+/// (as CPUID reports), with reserved bits of `value` clear; anything else
+/// raises #GP. The value must also not break state the running kernel
+/// depends on, such as clearing long mode or NX in EFER, or moving LSTAR or
+/// the GS base out from under code that uses them.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    core::arch::asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32,
+                     options(nostack, preserves_flags));
+}
+
+/// Kernel/user separation features in force, see [`enable_protections`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Protections {
+    /// Supervisor Mode Execution Prevention: the kernel faults on
+    /// executing a user page
+    pub smep: bool,
+    /// User Mode Instruction Prevention: SGDT, SIDT, SLDT, SMSW and STR
+    /// fault in ring 3, so programs cannot find kernel tables
+    pub umip: bool,
+    /// Supervisor Mode Access Prevention: the kernel faults on touching a
+    /// user page outside a `stac`/`clac` window (see
+    /// `watos_mem::user_access`)
+    pub smap: bool,
+}
+
+/// Turn on SMEP, SMAP and UMIP where the CPU has them
+///
+/// SMEP and SMAP require that no kernel page is mapped user-accessible in
+/// any address space the kernel runs in (see `ProcessPageTable`). With
+/// SMAP on, `stac` and `clac` exist and every user access must sit in a
+/// window; without it they raise #UD, so callers check [`Protections::smap`].
+pub fn enable_protections() -> Protections {
+    let max_std = cpuid(0, 0).eax;
+    let (ebx, ecx) = if max_std >= 7 {
+        let r = cpuid(7, 0);
+        (r.ebx, r.ecx)
+    } else {
+        (0, 0)
+    };
+    let found = Protections {
+        smep: ebx & (1 << 7) != 0,
+        umip: ecx & (1 << 2) != 0,
+        smap: ebx & (1 << 20) != 0,
+    };
+    let mut set = 0;
+    if found.smep {
+        set |= CR4_SMEP;
+    }
+    if found.umip {
+        set |= CR4_UMIP;
+    }
+    if found.smap {
+        set |= CR4_SMAP;
+    }
+    if set != 0 {
+        unsafe {
+            let cr4: u64;
+            core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov cr4, {}", in(reg) cr4 | set, options(nostack, preserves_flags));
+        }
+    }
+    found
+}
+
+/// Does the TSC tick at a constant rate, through frequency changes and
+/// sleep states?
+pub fn invariant_tsc() -> bool {
+    cpuid(0x8000_0000, 0).eax >= 0x8000_0007 && cpuid(0x8000_0007, 0).edx & (1 << 8) != 0
+}
+
+/// TSC frequency from CPUID leaf 0x15 (crystal clock and ratio), in kHz
+pub fn tsc_khz() -> Option<u64> {
+    if cpuid(0, 0).eax < 0x15 {
+        return None;
+    }
+    let r = cpuid(0x15, 0);
+    if r.eax == 0 || r.ebx == 0 || r.ecx == 0 {
+        return None;
+    }
+    Some(r.ecx as u64 * r.ebx as u64 / r.eax as u64 / 1000)
+}
+
+/// The number before "GHz" or "MHz" in a brand string, in MHz
+fn brand_mhz(brand: &str) -> u32 {
+    let (number, ghz) = match (brand.find("GHz"), brand.find("MHz")) {
+        (Some(i), _) => (&brand[..i], true),
+        (None, Some(i)) => (&brand[..i], false),
+        _ => return 0,
+    };
+    let start = number.rfind(|c: char| !(c.is_ascii_digit() || c == '.')).map_or(0, |i| i + 1);
+    let (whole, frac) = number[start..].split_once('.').unwrap_or((&number[start..], ""));
+    let whole = whole.parse::<u32>().unwrap_or(0);
+    if !ghz {
+        return whole;
+    }
+    // Up to three decimals of GHz are whole MHz
+    let frac = frac.bytes().chain(core::iter::repeat(b'0')).take(3)
+        .fold(0, |n, d| n * 10 + d.wrapping_sub(b'0').min(9) as u32);
+    whole * 1000 + frac
+}
+
+impl CpuInfo {
+    /// Read the CPU the caller is running on
+    pub fn read() -> Self {
+        let leaf0 = cpuid(0, 0);
+        let max_std = leaf0.eax;
+        let mut vendor = [0u8; 12];
+        vendor[0..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+        vendor[8..12].copy_from_slice(&leaf0.ecx.to_le_bytes());
+        let intel = &vendor == b"GenuineIntel";
+
+        let leaf1 = cpuid(1, 0);
+        let base_family = (leaf1.eax >> 8) & 0xF;
+        let base_model = (leaf1.eax >> 4) & 0xF;
+        let family = if base_family == 0xF { base_family + ((leaf1.eax >> 20) & 0xFF) } else { base_family };
+        let model = if base_family == 0x6 || base_family == 0xF {
+            base_model | ((leaf1.eax >> 12) & 0xF0)
+        } else {
+            base_model
+        };
+        let ht = leaf1.edx & (1 << 28) != 0;
+        let siblings = if ht { ((leaf1.ebx >> 16) & 0xFF).max(1) } else { 1 };
+
+        let std7_ebx = if max_std >= 7 { cpuid(7, 0).ebx } else { 0 };
+
+        let max_ext = cpuid(0x8000_0000, 0).eax;
+        let ext1 = if max_ext >= 0x8000_0001 { cpuid(0x8000_0001, 0) } else { CpuidResult { eax: 0, ebx: 0, ecx: 0, edx: 0 } };
+
+        let mut brand = [0u8; 48];
+        if max_ext >= 0x8000_0004 {
+            for (i, leaf) in (0x8000_0002..=0x8000_0004).enumerate() {
+                let r = cpuid(leaf, 0);
+                for (j, reg) in [r.eax, r.ebx, r.ecx, r.edx].iter().enumerate() {
+                    let at = i * 16 + j * 4;
+                    brand[at..at + 4].copy_from_slice(&reg.to_le_bytes());
+                }
+            }
+        }
+
+        // Cores: Intel leaf 4, AMD leaf 0x80000008
+        let cores = if intel && max_std >= 4 {
+            (cpuid(4, 0).eax >> 26) + 1
+        } else if !intel && max_ext >= 0x8000_0008 {
+            (cpuid(0x8000_0008, 0).ecx & 0xFF) + 1
+        } else {
+            1
+        }
+        .min(siblings.max(1));
+
+        let mut info = CpuInfo {
+            vendor,
+            brand,
+            family,
+            model,
+            stepping: leaf1.eax & 0xF,
+            siblings,
+            cores,
+            base_mhz: 0,
+            max_mhz: 0,
+            current_mhz: None,
+            temperature: None,
+            std1_ecx: leaf1.ecx,
+            std1_edx: leaf1.edx,
+            std7_ebx,
+            ext1_ecx: ext1.ecx,
+            ext1_edx: ext1.edx,
+        };
+
+        if max_std >= 0x16 {
+            let r = cpuid(0x16, 0);
+            info.base_mhz = r.eax & 0xFFFF;
+            info.max_mhz = r.ebx & 0xFFFF;
+        }
+        if info.base_mhz == 0 {
+            info.base_mhz = brand_mhz(info.brand());
+        }
+
+        let leaf6 = if max_std >= 6 { cpuid(6, 0) } else { CpuidResult { eax: 0, ebx: 0, ecx: 0, edx: 0 } };
+        let msr = info.has_flag("msr");
+
+        // APERF/MPERF count only while running: their ratio scales base
+        // frequency to the average actually delivered
+        if msr && leaf6.ecx & 1 != 0 && info.base_mhz != 0 {
+            let (aperf, mperf) = unsafe { (rdmsr(IA32_APERF), rdmsr(IA32_MPERF)) };
+            if mperf != 0 {
+                info.current_mhz = Some((info.base_mhz as u128 * aperf as u128 / mperf as u128) as u32);
+            }
+        }
+
+        // Digital thermal sensor: degrees below TjMax
+        if msr && intel && leaf6.eax & 1 != 0 {
+            let status = unsafe { rdmsr(IA32_THERM_STATUS) };
+            if status & (1 << 31) != 0 {
+                // Nehalem and later report TjMax
+                let tjmax = if family == 6 && model >= 0x1A {
+                    match ((unsafe { rdmsr(MSR_TEMPERATURE_TARGET) } >> 16) & 0xFF) as u32 {
+                        0 => DEFAULT_TJMAX,
+                        t => t,
+                    }
+                } else {
+                    DEFAULT_TJMAX
+                };
+                info.temperature = Some(tjmax.saturating_sub(((status >> 16) & 0x7F) as u32));
+            }
+        }
+
+        info
+    }
+
+    /// Vendor string
+    pub fn vendor(&self) -> &str {
+        core::str::from_utf8(&self.vendor).unwrap_or("unknown")
+    }
+
+    /// Brand string without padding
+    pub fn brand(&self) -> &str {
+        let len = self.brand.iter().position(|&b| b == 0).unwrap_or(48);
+        core::str::from_utf8(&self.brand[..len]).unwrap_or("").trim()
+    }
+
+    fn reg(&self, reg: Reg) -> u32 {
+        match reg {
+            Reg::Std1Edx => self.std1_edx,
+            Reg::Std1Ecx => self.std1_ecx,
+            Reg::Std7Ebx => self.std7_ebx,
+            Reg::Ext1Edx => self.ext1_edx,
+            Reg::Ext1Ecx => self.ext1_ecx,
+        }
+    }
+
+    /// Names of the features this CPU has, in /proc/cpuinfo order
+    pub fn flags(&self) -> impl Iterator<Item = &'static str> + '_ {
+        FLAGS.iter()
+            .filter(|&&(reg, bit, _)| self.reg(reg) & (1 << bit) != 0)
+            .map(|&(_, _, name)| name)
+    }
+
+    /// Does the CPU have the feature with this /proc/cpuinfo name?
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags().any(|f| f == name)
+    }
+}
+
+// ============================================================================
+// Random numbers and timestamps
+// ============================================================================
+
+/// Read the time stamp counter
+pub fn rdtsc() -> u64 {
+    // Safe: RDTSC is available on every x86_64 CPU
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// A random number from RDRAND, or None if the CPU lacks it or its
+/// generator keeps failing
+pub fn rdrand() -> Option<u64> {
+    if cpuid(1, 0).ecx & (1 << 30) == 0 {
+        return None;
+    }
+    // Intel suggests 10 attempts before treating the generator as broken
+    for _ in 0..10 {
+        let value: u64;
+        let ok: u8;
+        unsafe {
+            core::arch::asm!(
+                "rdrand {value}",
+                "setc {ok}",
+                value = out(reg) value,
+                ok = out(reg_byte) ok,
+                options(nomem, nostack),
+            );
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
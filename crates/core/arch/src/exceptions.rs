@@ -2,9 +2,22 @@
 //!
 //! Provides handlers for all x86-64 CPU exceptions (vectors 0-31).
 //! These are CRITICAL for debugging - without them, any exception = triple fault.
+//!
+//! Faults raised by user code (ring 3) for the exceptions a program can
+//! cause itself are handed to the kernel's user fault handler instead of
+//! halting the machine, so only the offending process dies. Page faults
+//! on user addresses first go to the page fault resolver, which may map the
+//! page in (from swap) and restart the instruction.
+//!
+//! Double faults, NMIs and machine checks run on IST stacks of their own
+//! and print a crash report (registers, control registers, and what the
+//! machine says caused it) before halting, so an overflowing kernel stack
+//! is reported instead of triple faulting into a silent reboot.
 
 use core::arch::naked_asm;
 
+use crate::{cpu, port, tss};
+
 /// Exception vector numbers
 pub mod vector {
     pub const DIVIDE_ERROR: u8 = 0;
@@ -68,6 +81,304 @@ pub unsafe extern "C" fn exception_common() {
     );
 }
 
+// ============================================================================
+// User-mode faults
+// ============================================================================
+
+/// Register state of a faulting user process, as saved by `user_fault_entry`
+///
+/// The layout mirrors the stack: general purpose registers pushed by the
+/// entry stub, then the vector and error code, then the CPU's IRETQ frame.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FaultFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// Kernel callback for faults in user code: (frame, cr2), never returns
+static mut USER_FAULT_HANDLER: Option<fn(&FaultFrame, u64) -> !> = None;
+
+/// Install the handler that terminates a process which faulted in ring 3
+pub fn set_user_fault_handler(handler: fn(&FaultFrame, u64) -> !) {
+    unsafe { USER_FAULT_HANDLER = Some(handler); }
+}
+
+/// Kernel callback that may satisfy a page fault: (cr2, error code), true
+/// if the access can be retried
+static mut PAGE_FAULT_RESOLVER: Option<fn(u64, u64) -> bool> = None;
+
+/// Install the handler that maps pages in on demand (swap). It is tried
+/// first for page faults from user code, and from kernel code touching
+/// user addresses; the faulting instruction is restarted if it succeeds.
+pub fn set_page_fault_resolver(resolver: fn(u64, u64) -> bool) {
+    unsafe { PAGE_FAULT_RESOLVER = Some(resolver); }
+}
+
+fn resolve_page_fault(cr2: u64, error_code: u64) -> bool {
+    match unsafe { PAGE_FAULT_RESOLVER } {
+        Some(resolver) => resolver(cr2, error_code),
+        None => false,
+    }
+}
+
+/// Common path for user faults; the prologue has pushed vector and error code
+#[unsafe(naked)]
+unsafe extern "C" fn user_fault_entry() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        // 22 qwords on a 16-byte aligned frame: RSP is aligned for the call
+        "mov rdi, rsp",
+        "mov rsi, cr2",
+        "cld",
+        "call {dispatch}",
+        // Back only if the fault was resolved: retry the instruction
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        "add rsp, 16",
+        "iretq",
+        dispatch = sym user_fault_dispatch,
+        options()
+    );
+}
+
+/// Returns only if a page fault was resolved
+extern "C" fn user_fault_dispatch(frame: &FaultFrame, cr2: u64) {
+    if frame.vector == vector::PAGE_FAULT as u64 && resolve_page_fault(cr2, frame.error_code) {
+        return;
+    }
+    unsafe {
+        if let Some(handler) = USER_FAULT_HANDLER {
+            handler(frame, cr2);
+        }
+        crate::serial_write(b"\r\nUser fault with no handler, vector=");
+        crate::serial_hex(frame.vector);
+        crate::serial_write(b" RIP=");
+        crate::serial_hex(frame.rip);
+        crate::serial_write(b"\r\nHALT\r\n");
+        loop { crate::halt(); }
+    }
+}
+
+// ============================================================================
+// Crash reports
+// ============================================================================
+
+/// Common path for double fault, NMI and machine check; the prologue has
+/// pushed vector and error code. Saves the registers as a `FaultFrame`.
+#[unsafe(naked)]
+unsafe extern "C" fn crash_entry() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp",
+        "mov rsi, cr2",
+        "cld",
+        "call {report}",
+        "2: cli",
+        "hlt",
+        "jmp 2b",
+        report = sym crash_report,
+        options()
+    );
+}
+
+/// Kernel callback naming the process whose kernel stack holds an address
+static mut STACK_OWNER: Option<fn(u64) -> Option<u32>> = None;
+
+/// Install the lookup the crash report uses to name the process whose
+/// kernel stack the CPU was on
+pub fn set_stack_owner_lookup(lookup: fn(u64) -> Option<u32>) {
+    unsafe { STACK_OWNER = Some(lookup); }
+}
+
+/// System control port B: why the chipset raised an NMI
+const NMI_STATUS_PORT: u16 = 0x61;
+const NMI_PARITY_ERROR: u8 = 1 << 7;
+const NMI_IO_CHECK: u8 = 1 << 6;
+
+/// Machine check MSRs
+const IA32_MCG_CAP: u32 = 0x179;
+const IA32_MCG_STATUS: u32 = 0x17A;
+const IA32_MC0_STATUS: u32 = 0x401;
+const MC_STATUS_VALID: u64 = 1 << 63;
+const MC_STATUS_ADDRV: u64 = 1 << 58;
+
+fn report_reg(name: &[u8], value: u64) {
+    unsafe {
+        crate::serial_write(name);
+        crate::serial_write(b"=");
+        crate::serial_hex(value);
+        crate::serial_write(b" ");
+    }
+}
+
+/// Print what is known about a fatal exception, then halt
+extern "C" fn crash_report(frame: &FaultFrame, cr2: u64) -> ! {
+    let name: &[u8] = match frame.vector as u8 {
+        vector::DOUBLE_FAULT => b"Double fault (#DF)",
+        vector::NMI => b"Non-maskable interrupt (NMI)",
+        vector::MACHINE_CHECK => b"Machine check (#MC)",
+        _ => b"Fatal exception",
+    };
+    let cr3: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        crate::set_serial_echo(true);
+        crate::serial_write(b"\r\n\r\n*** KERNEL CRASH: ");
+        crate::serial_write(name);
+        crate::serial_write(if frame.cs & 3 == 3 { b" in user mode ***\r\n" } else { b" in kernel ***\r\n" });
+    }
+
+    report_reg(b"RIP", frame.rip);
+    report_reg(b"CS", frame.cs);
+    report_reg(b"RFLAGS", frame.rflags);
+    report_reg(b"ERR", frame.error_code);
+    unsafe { crate::serial_write(b"\r\n"); }
+    report_reg(b"RSP", frame.rsp);
+    report_reg(b"SS", frame.ss);
+    report_reg(b"RSP0", tss::get_kernel_stack());
+    unsafe { crate::serial_write(b"\r\n"); }
+    report_reg(b"CR2", cr2);
+    report_reg(b"CR3", cr3);
+    unsafe { crate::serial_write(b"\r\n"); }
+    let regs: [(&[u8], u64); 15] = [
+        (b"RAX", frame.rax), (b"RBX", frame.rbx), (b"RCX", frame.rcx), (b"RDX", frame.rdx),
+        (b"RSI", frame.rsi), (b"RDI", frame.rdi), (b"RBP", frame.rbp), (b"R8", frame.r8),
+        (b"R9", frame.r9), (b"R10", frame.r10), (b"R11", frame.r11), (b"R12", frame.r12),
+        (b"R13", frame.r13), (b"R14", frame.r14), (b"R15", frame.r15),
+    ];
+    for (i, (name, value)) in regs.iter().enumerate() {
+        report_reg(name, *value);
+        if i % 4 == 3 {
+            unsafe { crate::serial_write(b"\r\n"); }
+        }
+    }
+    unsafe { crate::serial_write(b"\r\n"); }
+
+    match frame.vector as u8 {
+        // A page fault that could not push its frame: CR2 sits just below
+        // the stack pointer
+        vector::DOUBLE_FAULT if cr2 < frame.rsp && frame.rsp - cr2 <= 4096 => unsafe {
+            crate::serial_write(b"Likely cause: kernel stack overflow (CR2 just below RSP)\r\n");
+        },
+        vector::NMI => {
+            let reason = unsafe { port::inb(NMI_STATUS_PORT) };
+            unsafe {
+                if reason & NMI_PARITY_ERROR != 0 {
+                    crate::serial_write(b"NMI reason: memory parity error\r\n");
+                }
+                if reason & NMI_IO_CHECK != 0 {
+                    crate::serial_write(b"NMI reason: I/O channel check\r\n");
+                }
+            }
+        }
+        vector::MACHINE_CHECK => report_machine_check(),
+        _ => {}
+    }
+    if let Some(pid) = unsafe { STACK_OWNER }.and_then(|owner| owner(frame.rsp)) {
+        unsafe {
+            crate::serial_write(b"On the kernel stack of PID ");
+            crate::serial_hex(pid as u64);
+            crate::serial_write(b"\r\n");
+        }
+    }
+    if tss::on_ist_stack(frame.rsp) {
+        unsafe { crate::serial_write(b"Raised while handling another fatal exception\r\n"); }
+    }
+    unsafe { crate::serial_write(b"System halted\r\n"); }
+    loop {
+        unsafe { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+    }
+}
+
+/// Print the global machine check status and every bank holding an error
+fn report_machine_check() {
+    // MCA in CPUID leaf 1 EDX; without it the MSRs do not exist
+    if cpu::cpuid(1, 0).edx & (1 << 14) == 0 {
+        return;
+    }
+    unsafe {
+        let banks = (cpu::rdmsr(IA32_MCG_CAP) & 0xFF) as u32;
+        report_reg(b"MCG_STATUS", cpu::rdmsr(IA32_MCG_STATUS));
+        crate::serial_write(b"\r\n");
+        for bank in 0..banks {
+            let status = cpu::rdmsr(IA32_MC0_STATUS + 4 * bank);
+            if status & MC_STATUS_VALID == 0 {
+                continue;
+            }
+            crate::serial_write(b"MC bank ");
+            crate::serial_hex_byte(bank as u8);
+            crate::serial_write(b": ");
+            report_reg(b"STATUS", status);
+            if status & MC_STATUS_ADDRV != 0 {
+                report_reg(b"ADDR", cpu::rdmsr(IA32_MC0_STATUS + 4 * bank + 1));
+            }
+            crate::serial_write(b"\r\n");
+        }
+    }
+}
+
 // ============================================================================
 // Exception handlers WITHOUT error code
 // ============================================================================
@@ -76,6 +387,13 @@ pub unsafe extern "C" fn exception_common() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn divide_error() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 0",
+        "jmp {user}",
+        "1:",
         "mov al, 0x44", // 'D'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -87,6 +405,7 @@ pub unsafe extern "C" fn divide_error() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -110,21 +429,14 @@ pub unsafe extern "C" fn debug() {
     );
 }
 
-/// Non-Maskable Interrupt (Vector 2)
+/// Non-Maskable Interrupt (Vector 2) - uses IST2
 #[unsafe(naked)]
 pub unsafe extern "C" fn nmi() {
     naked_asm!(
-        "mov al, 0x4E", // 'N'
-        "mov dx, 0x3F8",
-        "out dx, al",
-        "mov al, 0x4D", // 'M'
-        "out dx, al",
-        "mov al, 0x49", // 'I'
-        "out dx, al",
-        "mov al, 0x32", // '2'
-        "out dx, al",
-        "jmp {common}",
-        common = sym exception_common,
+        "push 0",   // No error code
+        "push 2",
+        "jmp {crash}",
+        crash = sym crash_entry,
         options()
     );
 }
@@ -133,6 +445,13 @@ pub unsafe extern "C" fn nmi() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn breakpoint() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 3",
+        "jmp {user}",
+        "1:",
         "mov al, 0x42", // 'B'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -144,6 +463,7 @@ pub unsafe extern "C" fn breakpoint() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -152,6 +472,13 @@ pub unsafe extern "C" fn breakpoint() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn overflow() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 4",
+        "jmp {user}",
+        "1:",
         "mov al, 0x4F", // 'O'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -163,6 +490,7 @@ pub unsafe extern "C" fn overflow() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -171,6 +499,13 @@ pub unsafe extern "C" fn overflow() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn bound_range() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 5",
+        "jmp {user}",
+        "1:",
         "mov al, 0x42", // 'B'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -182,6 +517,7 @@ pub unsafe extern "C" fn bound_range() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -190,6 +526,13 @@ pub unsafe extern "C" fn bound_range() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn invalid_opcode() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 6",
+        "jmp {user}",
+        "1:",
         "mov dx, 0x3F8",
         "mov al, 0x55", // 'U'
         "out dx, al",
@@ -231,6 +574,7 @@ pub unsafe extern "C" fn invalid_opcode() {
 
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -239,6 +583,13 @@ pub unsafe extern "C" fn invalid_opcode() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn device_not_available() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 7",
+        "jmp {user}",
+        "1:",
         "mov al, 0x44", // 'D'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -250,6 +601,7 @@ pub unsafe extern "C" fn device_not_available() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -262,22 +614,10 @@ pub unsafe extern "C" fn device_not_available() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn double_fault() {
     naked_asm!(
-        // Print "DF8!" immediately
-        "mov al, 0x44", // 'D'
-        "mov dx, 0x3F8",
-        "out dx, al",
-        "mov al, 0x46", // 'F'
-        "out dx, al",
-        "mov al, 0x38", // '8'
-        "out dx, al",
-        "mov al, 0x21", // '!'
-        "out dx, al",
-
-        // Pop error code (always 0 for double fault)
-        "add rsp, 8",
-
-        "jmp {common}",
-        common = sym exception_common,
+        // Error code (always 0) is already on the stack
+        "push 8",
+        "jmp {crash}",
+        crash = sym crash_entry,
         options()
     );
 }
@@ -332,6 +672,12 @@ pub unsafe extern "C" fn segment_not_present() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn stack_segment_fault() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 16], 3",
+        "jz 1f",
+        "push 12",
+        "jmp {user}",
+        "1:",
         "mov al, 0x53", // 'S'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -347,6 +693,7 @@ pub unsafe extern "C" fn stack_segment_fault() {
 
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -355,6 +702,12 @@ pub unsafe extern "C" fn stack_segment_fault() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn general_protection() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 16], 3",
+        "jz 1f",
+        "push 13",
+        "jmp {user}",
+        "1:",
         // Print "GP#D" (GP fault, vector D = 13)
         "mov al, 0x47", // 'G'
         "mov dx, 0x3F8",
@@ -398,6 +751,7 @@ pub unsafe extern "C" fn general_protection() {
 
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -406,6 +760,55 @@ pub unsafe extern "C" fn general_protection() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn page_fault() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 16], 3",
+        "jz 1f",
+        "push 14",
+        "jmp {user}",
+        "1:",
+        // Kernel code touching a user page that can be brought in (the
+        // error code sits below 15 saved registers and the alignment pad)
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "sub rsp, 8",
+        "mov rdi, cr2",
+        "mov rsi, [rsp + 128]",
+        "cld",
+        "call {resolve}",
+        "add rsp, 8",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "test al, al",
+        "pop rax",
+        "jz 9f",
+        "add rsp, 8",
+        "iretq",
+        "9:",
         // Print "PF#E" (Page Fault, vector E = 14)
         "mov al, 0x50", // 'P'
         "mov dx, 0x3F8",
@@ -522,14 +925,28 @@ pub unsafe extern "C" fn page_fault() {
 
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
+        resolve = sym kernel_page_fault_resolve,
         options()
     );
 }
 
+/// Page faults in kernel code: only accesses to user memory can be resolved
+extern "C" fn kernel_page_fault_resolve(cr2: u64, error_code: u64) -> bool {
+    cr2 < 0x0000_8000_0000_0000 && resolve_page_fault(cr2, error_code)
+}
+
 /// x87 FPU Error (Vector 16)
 #[unsafe(naked)]
 pub unsafe extern "C" fn x87_fpu() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 16",
+        "jmp {user}",
+        "1:",
         "mov al, 0x46", // 'F'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -539,6 +956,7 @@ pub unsafe extern "C" fn x87_fpu() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -547,6 +965,12 @@ pub unsafe extern "C" fn x87_fpu() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn alignment_check() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 16], 3",
+        "jz 1f",
+        "push 17",
+        "jmp {user}",
+        "1:",
         "mov al, 0x41", // 'A'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -555,21 +979,19 @@ pub unsafe extern "C" fn alignment_check() {
         "add rsp, 8", // Pop error code
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
 
-/// Machine Check (Vector 18)
+/// Machine Check (Vector 18) - uses IST3
 #[unsafe(naked)]
 pub unsafe extern "C" fn machine_check() {
     naked_asm!(
-        "mov al, 0x4D", // 'M'
-        "mov dx, 0x3F8",
-        "out dx, al",
-        "mov al, 0x43", // 'C'
-        "out dx, al",
-        "jmp {common}",
-        common = sym exception_common,
+        "push 0",   // No error code
+        "push 18",
+        "jmp {crash}",
+        crash = sym crash_entry,
         options()
     );
 }
@@ -578,6 +1000,13 @@ pub unsafe extern "C" fn machine_check() {
 #[unsafe(naked)]
 pub unsafe extern "C" fn simd_fpu() {
     naked_asm!(
+        // From ring 3: kill the process instead of the machine
+        "test byte ptr [rsp + 8], 3",
+        "jz 1f",
+        "push 0",   // No error code
+        "push 19",
+        "jmp {user}",
+        "1:",
         "mov al, 0x53", // 'S'
         "mov dx, 0x3F8",
         "out dx, al",
@@ -589,6 +1018,7 @@ pub unsafe extern "C" fn simd_fpu() {
         "out dx, al",
         "jmp {common}",
         common = sym exception_common,
+        user = sym user_fault_entry,
         options()
     );
 }
@@ -616,13 +1046,13 @@ pub fn handlers() -> [(unsafe extern "C" fn(), bool, u8); 32] {
     [
         (divide_error, false, 0),           // 0
         (debug, false, 0),                  // 1
-        (nmi, false, 0),                    // 2
+        (nmi, false, tss::ist::NMI),        // 2 - Uses IST2
         (breakpoint, false, 0),             // 3
         (overflow, false, 0),               // 4
         (bound_range, false, 0),            // 5
         (invalid_opcode, false, 0),         // 6
         (device_not_available, false, 0),   // 7
-        (double_fault, true, 1),            // 8 - Uses IST1!
+        (double_fault, true, tss::ist::DOUBLE_FAULT), // 8 - Uses IST1!
         (reserved, false, 0),               // 9
         (invalid_tss, true, 0),             // 10
         (segment_not_present, true, 0),     // 11
@@ -632,7 +1062,7 @@ pub fn handlers() -> [(unsafe extern "C" fn(), bool, u8); 32] {
         (reserved, false, 0),               // 15
         (x87_fpu, false, 0),                // 16
         (alignment_check, true, 0),         // 17
-        (machine_check, false, 0),          // 18
+        (machine_check, false, tss::ist::MACHINE_CHECK), // 18 - Uses IST3
         (simd_fpu, false, 0),               // 19
         (reserved, false, 0),               // 20
         (reserved, true, 0),                // 21 - Control Protection
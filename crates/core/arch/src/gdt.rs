@@ -66,6 +66,18 @@ impl GdtEntry {
         }
     }
 
+    /// User code segment (Ring 3, 32-bit), for compatibility mode
+    const fn user_code32() -> Self {
+        GdtEntry {
+            limit_low: 0xFFFF,
+            base_low: 0,
+            base_mid: 0,
+            access: 0xFA, // Present, Ring 3, Code, Executable, Readable
+            granularity: 0xCF, // 32-bit, 4KB pages
+            base_high: 0,
+        }
+    }
+
     /// User data segment (Ring 3)
     const fn user_data() -> Self {
         GdtEntry {
@@ -89,6 +101,7 @@ struct Gdt {
     user_data: GdtEntry,   // 0x20 - User data (Ring 3)
     tss_low: u64,          // 0x28 - TSS descriptor low
     tss_high: u64,         // 0x30 - TSS descriptor high
+    user_code32: GdtEntry, // 0x38 - User code, compatibility mode (Ring 3)
 }
 
 impl Gdt {
@@ -101,6 +114,7 @@ impl Gdt {
             user_data: GdtEntry::user_data(),
             tss_low: 0,
             tss_high: 0,
+            user_code32: GdtEntry::user_code32(),
         }
     }
 }
@@ -122,6 +136,8 @@ pub mod selectors {
     pub const USER_CODE: u16 = 0x18 | 3; // RPL = 3
     pub const USER_DATA: u16 = 0x20 | 3; // RPL = 3
     pub const TSS: u16 = 0x28;
+    /// 32-bit code for ring 3, which a 64-bit program far-jumps to
+    pub const USER_CODE32: u16 = 0x38 | 3; // RPL = 3
 }
 
 /// Initialize and load GDT with TSS
@@ -0,0 +1,99 @@
+//! Hypervisor detection
+//!
+//! Under a hypervisor CPUID leaf 1 sets ECX bit 31, and leaves from
+//! 0x40000000 answer with the hypervisor's signature and features.
+
+use crate::cpu::cpuid;
+
+/// First hypervisor CPUID leaf
+const LEAF_BASE: u32 = 0x4000_0000;
+/// KVM feature bits (EAX)
+const LEAF_KVM_FEATURES: u32 = 0x4000_0001;
+/// TSC and local APIC timer frequencies in kHz (VMware's leaf, which QEMU
+/// and KVM also fill in)
+const LEAF_TIMING: u32 = 0x4000_0010;
+
+/// KVM has the kvmclock MSRs at 0x4b564d00
+pub const KVM_FEATURE_CLOCKSOURCE2: u32 = 1 << 3;
+
+/// Known hypervisors, by CPUID signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hypervisor {
+    Kvm,
+    /// QEMU without KVM (TCG)
+    Qemu,
+    HyperV,
+    VMware,
+    VirtualBox,
+    Xen,
+    Other,
+}
+
+/// The hypervisor the kernel runs under
+#[derive(Debug, Clone, Copy)]
+pub struct HypervisorInfo {
+    pub kind: Hypervisor,
+    /// "KVMKVMKVM\0\0\0", "TCGTCGTCGTCG", ...
+    pub signature: [u8; 12],
+    /// Highest hypervisor leaf
+    pub max_leaf: u32,
+}
+
+/// The hypervisor, or None on bare metal
+pub fn detect() -> Option<HypervisorInfo> {
+    if cpuid(1, 0).ecx & (1 << 31) == 0 {
+        return None;
+    }
+    let r = cpuid(LEAF_BASE, 0);
+    let mut signature = [0u8; 12];
+    signature[0..4].copy_from_slice(&r.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&r.ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&r.edx.to_le_bytes());
+    let kind = match &signature {
+        b"KVMKVMKVM\0\0\0" => Hypervisor::Kvm,
+        b"TCGTCGTCGTCG" => Hypervisor::Qemu,
+        b"Microsoft Hv" => Hypervisor::HyperV,
+        b"VMwareVMware" => Hypervisor::VMware,
+        b"VBoxVBoxVBox" => Hypervisor::VirtualBox,
+        b"XenVMMXenVMM" => Hypervisor::Xen,
+        _ => Hypervisor::Other,
+    };
+    // Old KVM reports 0 here but has the features leaf
+    let max_leaf = if r.eax == 0 && kind == Hypervisor::Kvm { LEAF_KVM_FEATURES } else { r.eax };
+    Some(HypervisorInfo { kind, signature, max_leaf })
+}
+
+impl HypervisorInfo {
+    /// Vendor name, as Linux reports it
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            Hypervisor::Kvm => "KVM",
+            Hypervisor::Qemu => "QEMU",
+            Hypervisor::HyperV => "Microsoft",
+            Hypervisor::VMware => "VMware",
+            Hypervisor::VirtualBox => "VirtualBox",
+            Hypervisor::Xen => "Xen",
+            Hypervisor::Other => "unknown",
+        }
+    }
+
+    /// KVM paravirtual features; 0 under other hypervisors
+    pub fn kvm_features(&self) -> u32 {
+        if self.kind == Hypervisor::Kvm && self.max_leaf >= LEAF_KVM_FEATURES {
+            cpuid(LEAF_KVM_FEATURES, 0).eax
+        } else {
+            0
+        }
+    }
+
+    /// TSC frequency the hypervisor reports, in kHz
+    pub fn tsc_khz(&self) -> Option<u64> {
+        if self.max_leaf < LEAF_TIMING {
+            return None;
+        }
+        match cpuid(LEAF_TIMING, 0).eax {
+            0 => None,
+            khz => Some(khz as u64),
+        }
+    }
+}
@@ -80,11 +80,34 @@ static mut IDT: [IdtEntry; 256] = [IdtEntry::empty(); 256];
 /// Timer tick counter
 pub static mut TIMER_TICKS: u64 = 0;
 
+/// Timer ticks that interrupted ring 3 code (the rest were spent in the kernel)
+pub static mut USER_TICKS: u64 = 0;
+
+/// Sampling profiler ring buffer length (must be a power of two)
+pub const PROFILE_BUF_LEN: usize = 4096;
+
+/// Nonzero while the sampling profiler is recording
+pub static mut PROFILE_ENABLED: u64 = 0;
+
+/// Tag stored with each sample (the running pid, 0 = kernel)
+pub static mut PROFILE_TAG: u64 = 0;
+
+/// Samples taken since the last reset (the ring keeps the newest PROFILE_BUF_LEN)
+pub static mut PROFILE_COUNT: u64 = 0;
+
+/// Profiler samples: [interrupted RIP, tag]
+pub static mut PROFILE_SAMPLES: [[u64; 2]; PROFILE_BUF_LEN] = [[0; 2]; PROFILE_BUF_LEN];
+
 /// Keyboard buffer
 pub static mut KEY_BUFFER: [u8; 32] = [0; 32];
 pub static mut KEY_READ_POS: usize = 0;
 pub static mut KEY_WRITE_POS: usize = 0;
 
+/// Mouse byte buffer (raw PS/2 packet bytes)
+pub static mut MOUSE_BUFFER: [u8; 64] = [0; 64];
+pub static mut MOUSE_READ_POS: usize = 0;
+pub static mut MOUSE_WRITE_POS: usize = 0;
+
 /// Initialize IDT with all handlers
 pub fn init() {
     unsafe {
@@ -101,6 +124,8 @@ pub fn init() {
         // Install hardware interrupt handlers (vectors 32-47)
         IDT[pic::irq::TIMER as usize].set_handler(timer_handler as u64, 0);
         IDT[pic::irq::KEYBOARD as usize].set_handler(keyboard_handler as u64, 0);
+        IDT[pic::irq::MOUSE as usize].set_handler(mouse_handler as u64, 0);
+        IDT[pic::irq::COM1 as usize].set_handler(serial_handler as u64, 0);
 
         // Load IDT
         let idt_ptr = IdtPointer {
@@ -152,28 +177,198 @@ unsafe extern "C" fn timer_handler() {
         "lea rax, [rip + {ticks}]",
         "lock inc qword ptr [rax]",
 
+        // Count the tick as user time if it interrupted ring 3
+        // (CS sits above the saved RIP, past our two pushes)
+        "test byte ptr [rsp + 24], 3",
+        "jz 2f",
+        "lea rax, [rip + {user_ticks}]",
+        "lock inc qword ptr [rax]",
+        "2:",
+
+        // Sampling profiler: record the interrupted RIP and the current tag
+        "lea rax, [rip + {prof_enabled}]",
+        "cmp qword ptr [rax], 0",
+        "je 3f",
+        "push rcx",
+        "lea rax, [rip + {prof_count}]",
+        "mov rcx, qword ptr [rax]",
+        "inc qword ptr [rax]",
+        "and rcx, {prof_mask}",
+        "shl rcx, 4",
+        "lea rax, [rip + {prof_samples}]",
+        "add rax, rcx",
+        "mov rdx, [rsp + 24]",          // RIP, past our three pushes
+        "mov [rax], rdx",
+        "lea rdx, [rip + {prof_tag}]",
+        "mov rdx, [rdx]",
+        "mov [rax + 8], rdx",
+        "pop rcx",
+        "3:",
+
         // Send EOI
         "mov al, 0x20",
         "out 0x20, al",
 
+        // A tick in ring 3 goes to the scheduler with the full register
+        // set, which it may keep and switch to another process; 13 more
+        // pushes leave RSP 16-byte aligned
+        "test byte ptr [rsp + 24], 3",
+        "jz 4f",
+        "push rbx",
+        "push rcx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp",
+        "cld",
+        "call {dispatch}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rcx",
+        "pop rbx",
+        "4:",
+
         "pop rdx",
         "pop rax",
         "iretq",
+        dispatch = sym user_tick_dispatch,
         ticks = sym TIMER_TICKS,
+        user_ticks = sym USER_TICKS,
+        prof_enabled = sym PROFILE_ENABLED,
+        prof_count = sym PROFILE_COUNT,
+        prof_samples = sym PROFILE_SAMPLES,
+        prof_tag = sym PROFILE_TAG,
+        prof_mask = const PROFILE_BUF_LEN - 1,
         options()
     );
 }
 
+/// Registers of the ring 3 code a timer tick interrupted, as the timer
+/// handler pushed them, followed by the interrupt frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UserFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// Kernel callback for timer ticks that interrupted ring 3
+static mut USER_TICK_HANDLER: Option<fn(&UserFrame)> = None;
+
+/// Install the callback for timer ticks that interrupted ring 3. It runs
+/// on the process's kernel stack with interrupts off, after the EOI, and
+/// may switch to another process instead of returning.
+pub fn set_user_tick_handler(handler: fn(&UserFrame)) {
+    unsafe { USER_TICK_HANDLER = Some(handler); }
+}
+
+extern "C" fn user_tick_dispatch(frame: *const UserFrame) {
+    if let Some(handler) = unsafe { USER_TICK_HANDLER } {
+        handler(unsafe { &*frame });
+    }
+}
+
+/// Kernel callback for each scancode; true keeps it out of the buffer
+static mut KEY_FILTER: Option<fn(u8) -> bool> = None;
+
+/// Install a filter that sees every scancode as it arrives, before it is
+/// buffered; it runs in the interrupt, with interrupts off, and returns
+/// true for keys the kernel acts on itself (such as Ctrl+Z)
+pub fn set_key_filter(filter: fn(u8) -> bool) {
+    unsafe { KEY_FILTER = Some(filter); }
+}
+
 /// Keyboard interrupt handler (IRQ1 -> INT 33)
 #[unsafe(naked)]
 unsafe extern "C" fn keyboard_handler() {
+    naked_asm!(
+        // Caller-saved registers; 9 pushes leave RSP 16-byte aligned
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "cld",
+        "call {dispatch}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        "iretq",
+        dispatch = sym keyboard_dispatch,
+        options()
+    );
+}
+
+extern "C" fn keyboard_dispatch() {
+    unsafe {
+        let scancode = crate::port::inb(0x60);
+        if !KEY_FILTER.is_some_and(|filter| filter(scancode)) {
+            // Drop the key if the buffer is full
+            let next = (KEY_WRITE_POS + 1) & 31;
+            if next != KEY_READ_POS {
+                KEY_BUFFER[KEY_WRITE_POS] = scancode;
+                KEY_WRITE_POS = next;
+            }
+        }
+    }
+    pic::send_eoi(1);
+}
+
+/// Mouse interrupt handler (IRQ12 -> INT 44)
+#[unsafe(naked)]
+unsafe extern "C" fn mouse_handler() {
     naked_asm!(
         "push rax",
         "push rbx",
         "push rcx",
         "push rdx",
 
-        // Read scancode
+        // Read packet byte
         "in al, 0x60",
         "mov dl, al",
 
@@ -183,14 +378,14 @@ unsafe extern "C" fn keyboard_handler() {
 
         // Calculate next position
         "lea rcx, [rax + 1]",
-        "and rcx, 31",
+        "and rcx, 63",
 
         // Check if buffer full
         "lea rbx, [rip + {read_pos}]",
         "cmp rcx, [rbx]",
         "je 2f",
 
-        // Store scancode
+        // Store byte
         "lea rbx, [rip + {buffer}]",
         "mov [rbx + rax], dl",
 
@@ -199,8 +394,9 @@ unsafe extern "C" fn keyboard_handler() {
         "mov [rbx], rcx",
 
         "2:",
-        // Send EOI
+        // Send EOI to both PICs (IRQ12 is on the slave)
         "mov al, 0x20",
+        "out 0xA0, al",
         "out 0x20, al",
 
         "pop rdx",
@@ -208,19 +404,138 @@ unsafe extern "C" fn keyboard_handler() {
         "pop rbx",
         "pop rax",
         "iretq",
-        buffer = sym KEY_BUFFER,
-        write_pos = sym KEY_WRITE_POS,
-        read_pos = sym KEY_READ_POS,
+        buffer = sym MOUSE_BUFFER,
+        write_pos = sym MOUSE_WRITE_POS,
+        read_pos = sym MOUSE_READ_POS,
         options()
     );
 }
 
+/// Kernel callback for bytes received on COM1: (byte, interrupted ring 3)
+static mut SERIAL_RX_HANDLER: Option<fn(u8, bool)> = None;
+
+/// Install the callback for received serial bytes. It runs in the
+/// interrupt with interrupts off, and may take as long as it likes or never
+/// return: the EOI has already been sent.
+pub fn set_serial_rx_handler(handler: fn(u8, bool)) {
+    unsafe { SERIAL_RX_HANDLER = Some(handler); }
+}
+
+/// COM1 interrupt handler (IRQ4 -> INT 36)
+#[unsafe(naked)]
+unsafe extern "C" fn serial_handler() {
+    naked_asm!(
+        // Caller-saved registers; 9 pushes leave RSP 16-byte aligned
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "mov rdi, [rsp + 80]",          // Interrupted CS, past our pushes and RIP
+        "and rdi, 3",
+        "cld",
+        "call {dispatch}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        "iretq",
+        dispatch = sym serial_dispatch,
+        options()
+    );
+}
+
+extern "C" fn serial_dispatch(cpl: u64) {
+    pic::send_eoi(4);
+    while let Some(byte) = crate::serial_read() {
+        if let Some(handler) = unsafe { SERIAL_RX_HANDLER } {
+            handler(byte, cpl == 3);
+        }
+    }
+}
+
+static mut SCI_HANDLER: Option<fn()> = None;
+static mut SCI_IRQ: u8 = 9;
+
+/// Route the ACPI SCI on `irq` (a legacy PIC line) to `handler`. It runs in
+/// the interrupt with interrupts off and must clear the event, as the SCI
+/// is level triggered; the EOI follows its return.
+pub fn set_sci_handler(irq: u8, handler: fn()) {
+    if irq >= 16 {
+        return;
+    }
+    unsafe {
+        SCI_HANDLER = Some(handler);
+        SCI_IRQ = irq;
+        IDT[(pic::PIC1_OFFSET + irq) as usize].set_handler(sci_handler as u64, 0);
+    }
+    if irq >= 8 {
+        pic::enable_irq(2);
+    }
+    pic::enable_irq(irq);
+}
+
+/// ACPI SCI handler, on whichever IRQ the FADT names
+#[unsafe(naked)]
+unsafe extern "C" fn sci_handler() {
+    naked_asm!(
+        // Caller-saved registers; 9 pushes leave RSP 16-byte aligned
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "cld",
+        "call {dispatch}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        "iretq",
+        dispatch = sym sci_dispatch,
+        options()
+    );
+}
+
+extern "C" fn sci_dispatch() {
+    unsafe {
+        if let Some(handler) = SCI_HANDLER {
+            handler();
+        }
+        pic::send_eoi(SCI_IRQ);
+    }
+}
+
 // ============================================================================
 // Public API
 // ============================================================================
 
-/// Get a scancode from keyboard buffer
+/// Get a scancode from the keyboard buffer, with key repeat applied
+/// (see [`crate::typematic`])
 pub fn get_scancode() -> Option<u8> {
+    crate::typematic::next(read_key_buffer, crate::clock::now_ms())
+}
+
+/// Take a raw scancode from the keyboard buffer
+fn read_key_buffer() -> Option<u8> {
     unsafe {
         if KEY_READ_POS != KEY_WRITE_POS {
             let scancode = KEY_BUFFER[KEY_READ_POS];
@@ -232,11 +547,72 @@ pub fn get_scancode() -> Option<u8> {
     }
 }
 
+/// Get a raw byte from the mouse buffer
+pub fn get_mouse_byte() -> Option<u8> {
+    unsafe {
+        if MOUSE_READ_POS != MOUSE_WRITE_POS {
+            let byte = MOUSE_BUFFER[MOUSE_READ_POS];
+            MOUSE_READ_POS = (MOUSE_READ_POS + 1) & 63;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+}
+
 /// Get current timer tick count
 pub fn get_ticks() -> u64 {
     unsafe { TIMER_TICKS }
 }
 
+/// Get the number of timer ticks that landed in user mode
+pub fn get_user_ticks() -> u64 {
+    unsafe { USER_TICKS }
+}
+
+/// Start or stop the sampling profiler
+pub fn profile_enable(enabled: bool) {
+    unsafe { PROFILE_ENABLED = enabled as u64; }
+}
+
+/// Is the sampling profiler recording?
+pub fn profile_enabled() -> bool {
+    unsafe { PROFILE_ENABLED != 0 }
+}
+
+/// Set the tag recorded with subsequent samples
+pub fn set_profile_tag(tag: u64) {
+    unsafe { PROFILE_TAG = tag; }
+}
+
+/// Discard all recorded samples
+pub fn profile_reset() {
+    unsafe {
+        core::arch::asm!("cli", options(nostack, preserves_flags));
+        PROFILE_COUNT = 0;
+        core::arch::asm!("sti", options(nostack, preserves_flags));
+    }
+}
+
+/// Number of samples taken since the last reset
+pub fn profile_count() -> u64 {
+    unsafe { PROFILE_COUNT }
+}
+
+/// Get a buffered sample as (rip, tag); index 0 is the oldest still held
+pub fn profile_sample(index: usize) -> Option<(u64, u64)> {
+    unsafe {
+        let count = PROFILE_COUNT;
+        let held = (count as usize).min(PROFILE_BUF_LEN);
+        if index >= held {
+            return None;
+        }
+        let first = count as usize - held;
+        let [rip, tag] = PROFILE_SAMPLES[(first + index) & (PROFILE_BUF_LEN - 1)];
+        Some((rip, tag))
+    }
+}
+
 /// Wait for approximately N milliseconds (18.2 Hz timer = ~55ms/tick)
 pub fn sleep_ms(ms: u32) {
     let ticks_needed = ((ms as u64) / 55).max(1);
@@ -0,0 +1,44 @@
+//! Kernel log ring buffer
+//!
+//! Everything written through `serial_write`, `serial_hex` and
+//! `serial_hex_byte` is also kept here, so the log can be saved once a
+//! writable filesystem is up. Positions are byte offsets since boot; the
+//! ring keeps the most recent `KLOG_SIZE` bytes.
+
+/// Ring capacity in bytes
+pub const KLOG_SIZE: usize = 64 * 1024;
+
+static mut RING: [u8; KLOG_SIZE] = [0; KLOG_SIZE];
+/// Total bytes ever logged
+static mut WRITTEN: u64 = 0;
+
+/// Append bytes to the log
+pub fn push(bytes: &[u8]) {
+    unsafe {
+        for &b in bytes {
+            RING[(WRITTEN % KLOG_SIZE as u64) as usize] = b;
+            WRITTEN += 1;
+        }
+    }
+}
+
+/// Total bytes logged since boot (the position of the next byte)
+pub fn written() -> u64 {
+    unsafe { WRITTEN }
+}
+
+/// Copy logged bytes starting at position `from` into `out`
+///
+/// Returns (bytes copied, position of the first byte copied). Bytes older
+/// than the ring's capacity are gone, so the start may be later than `from`.
+pub fn read(from: u64, out: &mut [u8]) -> (usize, u64) {
+    unsafe {
+        let oldest = WRITTEN.saturating_sub(KLOG_SIZE as u64);
+        let start = from.max(oldest);
+        let len = (WRITTEN.saturating_sub(start) as usize).min(out.len());
+        for (i, slot) in out[..len].iter_mut().enumerate() {
+            *slot = RING[((start + i as u64) % KLOG_SIZE as u64) as usize];
+        }
+        (len, start)
+    }
+}
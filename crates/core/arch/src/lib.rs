@@ -6,6 +6,11 @@
 //! - IDT (Interrupt Descriptor Table) with exception handlers
 //! - PIC (8259 Programmable Interrupt Controller)
 //! - Port I/O primitives
+//! - CPU identification (CPUID, MSRs) and performance counters
+//! - Hypervisor detection and a monotonic clock (kvmclock, TSC or PIT)
+//! - ACPI power button, soft-off and reset
+//! - Kernel log ring buffer fed by the serial debug output
+//! - Software key repeat for the keyboard buffer
 
 #![no_std]
 
@@ -16,13 +21,33 @@ pub mod idt;
 pub mod exceptions;
 pub mod pic;
 pub mod rtc;
+pub mod cpu;
+pub mod pmu;
+pub mod klog;
+pub mod hypervisor;
+pub mod clock;
+pub mod acpi;
+pub mod typematic;
 
 /// Serial port for debug output (COM1)
 pub const SERIAL_PORT: u16 = 0x3F8;
 
+/// Whether debug output reaches the serial port; the kernel log always
+/// records it
+static mut SERIAL_ECHO: bool = true;
+
+/// Turn copying debug output to the serial port on or off
+pub fn set_serial_echo(on: bool) {
+    unsafe { SERIAL_ECHO = on; }
+}
+
 /// Debug output to serial port
 #[inline]
 pub unsafe fn serial_write(s: &[u8]) {
+    klog::push(s);
+    if !SERIAL_ECHO {
+        return;
+    }
     for &byte in s {
         // Simple busy-wait
         for _ in 0..100 {
@@ -36,6 +61,10 @@ pub unsafe fn serial_write(s: &[u8]) {
 #[inline]
 pub unsafe fn serial_hex_byte(val: u8) {
     const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    klog::push(&[HEX[(val >> 4) as usize], HEX[(val & 0xF) as usize]]);
+    if !SERIAL_ECHO {
+        return;
+    }
     port::outb(SERIAL_PORT, HEX[(val >> 4) as usize]);
     port::outb(SERIAL_PORT, HEX[(val & 0xF) as usize]);
 }
@@ -46,10 +75,63 @@ pub unsafe fn serial_hex(val: u64) {
     const HEX: &[u8; 16] = b"0123456789ABCDEF";
     for i in (0..16).rev() {
         let nibble = ((val >> (i * 4)) & 0xF) as usize;
-        port::outb(SERIAL_PORT, HEX[nibble]);
+        klog::push(&HEX[nibble..nibble + 1]);
+        if SERIAL_ECHO {
+            port::outb(SERIAL_PORT, HEX[nibble]);
+        }
+    }
+}
+
+/// Set the serial port speed; false if `baud` is not a divisor of 115200
+pub fn serial_set_baud(baud: u32) -> bool {
+    if baud == 0 || baud > 115200 || !115200u32.is_multiple_of(baud) {
+        return false;
+    }
+    let divisor = (115200 / baud) as u16;
+    unsafe {
+        port::outb(SERIAL_PORT + 3, 0x80); // DLAB on
+        port::outb(SERIAL_PORT, divisor as u8);
+        port::outb(SERIAL_PORT + 1, (divisor >> 8) as u8);
+        port::outb(SERIAL_PORT + 3, 0x03); // 8N1, DLAB off
+    }
+    true
+}
+
+/// Line status register bits
+const SERIAL_LSR_DATA_READY: u8 = 0x01;
+const SERIAL_LSR_THR_EMPTY: u8 = 0x20;
+
+/// Byte waiting in the serial receiver, if any
+pub fn serial_read() -> Option<u8> {
+    unsafe {
+        (port::inb(SERIAL_PORT + 5) & SERIAL_LSR_DATA_READY != 0).then(|| port::inb(SERIAL_PORT))
     }
 }
 
+/// Write straight to the serial port, bypassing the kernel log and the echo
+/// switch. For interactive output such as the kernel monitor.
+pub fn serial_write_raw(s: &[u8]) {
+    for &byte in s {
+        unsafe {
+            while port::inb(SERIAL_PORT + 5) & SERIAL_LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            port::outb(SERIAL_PORT, byte);
+        }
+    }
+}
+
+/// Raise IRQ4 when a byte arrives (see `idt::set_serial_rx_handler`)
+pub fn serial_enable_rx_irq() {
+    unsafe {
+        port::outb(SERIAL_PORT + 1, 0x01); // IER: received data available
+        let mcr = port::inb(SERIAL_PORT + 4);
+        port::outb(SERIAL_PORT + 4, mcr | 0x08); // OUT2 gates the IRQ line
+        while serial_read().is_some() {}
+    }
+    pic::enable_irq(4);
+}
+
 /// Initialize all architecture components
 ///
 /// Call order matters:
@@ -107,3 +189,22 @@ pub fn halt() {
         core::arch::asm!("sti; hlt", options(nostack, preserves_flags));
     }
 }
+
+/// Reset the machine: pulse the reset line through the keyboard controller,
+/// and if that does nothing, triple fault
+pub fn reboot() -> ! {
+    unsafe {
+        core::arch::asm!("cli", options(nostack, preserves_flags));
+        for _ in 0..100_000 {
+            if port::inb(0x64) & 0x02 == 0 {
+                break;
+            }
+        }
+        port::outb(0x64, 0xFE);
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+        let empty = [0u64; 2];
+        core::arch::asm!("lidt [{}]", "int3", in(reg) empty.as_ptr(), options(noreturn));
+    }
+}
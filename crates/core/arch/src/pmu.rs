@@ -0,0 +1,188 @@
+//! Performance monitoring counters
+//!
+//! Intel's architectural performance monitoring (CPUID leaf 0xA): a few
+//! general-purpose counters, each programmed with one of the architectural
+//! events through its IA32_PERFEVTSELx MSR and read with RDPMC. Counters
+//! are system-wide and count in both rings; nothing saves them across
+//! processes, so a measurement should be the only thing running.
+//!
+//! While any counter is running CR4.PCE is set, letting user code read the
+//! counters with RDPMC directly instead of paying for a syscall per read.
+//! CPUs without leaf 0xA (AMD, most emulators) have no counters here.
+
+use crate::cpu::{cpuid, rdmsr, wrmsr};
+
+/// MSRs
+const IA32_PMC0: u32 = 0xC1;
+const IA32_PERFEVTSEL0: u32 = 0x186;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38F;
+
+/// IA32_PERFEVTSELx bits
+const EVTSEL_USR: u64 = 1 << 16;
+const EVTSEL_OS: u64 = 1 << 17;
+const EVTSEL_EN: u64 = 1 << 22;
+
+/// CR4 bit letting ring 3 use RDPMC
+const CR4_PCE: u64 = 1 << 8;
+
+/// Most counters tracked, whatever the CPU has
+pub const MAX_COUNTERS: usize = 8;
+
+/// Architectural events, in CPUID 0xA EBX bit order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Event {
+    /// Core cycles while not halted
+    Cycles = 0,
+    /// Instructions retired
+    Instructions = 1,
+    /// Reference cycles (a constant rate) while not halted
+    RefCycles = 2,
+    LlcReferences = 3,
+    LlcMisses = 4,
+    /// Branch instructions retired
+    Branches = 5,
+    /// Mispredicted branches retired
+    BranchMisses = 6,
+}
+
+impl Event {
+    pub const ALL: [Event; 7] = [
+        Event::Cycles,
+        Event::Instructions,
+        Event::RefCycles,
+        Event::LlcReferences,
+        Event::LlcMisses,
+        Event::Branches,
+        Event::BranchMisses,
+    ];
+
+    pub fn from_index(index: u64) -> Option<Event> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// Event select and unit mask
+    fn code(self) -> u64 {
+        match self {
+            Event::Cycles => 0x003C,
+            Event::Instructions => 0x00C0,
+            Event::RefCycles => 0x013C,
+            Event::LlcReferences => 0x4F2E,
+            Event::LlcMisses => 0x412E,
+            Event::Branches => 0x00C4,
+            Event::BranchMisses => 0x00C5,
+        }
+    }
+}
+
+/// What the CPU's performance monitoring offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmuInfo {
+    /// Architectural performance monitoring version
+    pub version: u8,
+    /// General-purpose counters (at most `MAX_COUNTERS` are used)
+    pub counters: u8,
+    /// Bits in each counter
+    pub width: u8,
+    /// Bit n set if `Event::ALL[n]` can be counted
+    pub events: u8,
+}
+
+impl PmuInfo {
+    /// Pack into a u64 for SYS_PERFCTR: version, counters, width, events
+    /// from the low byte up
+    pub fn to_bits(self) -> u64 {
+        self.version as u64
+            | (self.counters as u64) << 8
+            | (self.width as u64) << 16
+            | (self.events as u64) << 24
+    }
+
+    pub fn has(&self, event: Event) -> bool {
+        self.events & (1 << event as u8) != 0
+    }
+}
+
+/// The CPU's performance monitoring, None if it has none usable
+pub fn info() -> Option<PmuInfo> {
+    if cpuid(0, 0).eax < 0xA {
+        return None;
+    }
+    let r = cpuid(0xA, 0);
+    let version = (r.eax & 0xFF) as u8;
+    let counters = ((r.eax >> 8) & 0xFF).min(MAX_COUNTERS as u32) as u8;
+    if version == 0 || counters == 0 {
+        return None;
+    }
+    // EBX has a bit set for each event that is *not* available, for the
+    // first (EAX[31:24]) events
+    let known = ((r.eax >> 24) & 0xFF).min(Event::ALL.len() as u32);
+    let events = !r.ebx & ((1u32 << known) - 1);
+    Some(PmuInfo {
+        version,
+        counters,
+        width: ((r.eax >> 16) & 0xFF) as u8,
+        events: events as u8,
+    })
+}
+
+/// Counters running, a bit each
+static mut RUNNING: u8 = 0;
+
+fn set_user_rdpmc(on: bool) {
+    unsafe {
+        let cr4: u64;
+        core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        let cr4 = if on { cr4 | CR4_PCE } else { cr4 & !CR4_PCE };
+        core::arch::asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
+    }
+}
+
+/// Zero `counter` and start it counting `event`; false if there is no such
+/// counter or the CPU can't count the event
+pub fn start(counter: u32, event: Event) -> bool {
+    let Some(pmu) = info() else { return false };
+    if counter >= pmu.counters as u32 || !pmu.has(event) {
+        return false;
+    }
+    unsafe {
+        wrmsr(IA32_PERFEVTSEL0 + counter, 0);
+        wrmsr(IA32_PMC0 + counter, 0);
+        wrmsr(IA32_PERFEVTSEL0 + counter, event.code() | EVTSEL_USR | EVTSEL_OS | EVTSEL_EN);
+        // Version 2 added a global enable, on for every counter at reset
+        // but cleared by some firmware
+        if pmu.version >= 2 {
+            let global = rdmsr(IA32_PERF_GLOBAL_CTRL);
+            wrmsr(IA32_PERF_GLOBAL_CTRL, global | 1 << counter);
+        }
+        RUNNING |= 1 << counter;
+    }
+    set_user_rdpmc(true);
+    true
+}
+
+/// Current value of a running `counter`
+pub fn read(counter: u32) -> Option<u64> {
+    if counter as usize >= MAX_COUNTERS || unsafe { RUNNING } & (1 << counter) == 0 {
+        return None;
+    }
+    let (lo, hi): (u32, u32);
+    unsafe {
+        core::arch::asm!("rdpmc", in("ecx") counter, out("eax") lo, out("edx") hi,
+                         options(nomem, nostack, preserves_flags));
+    }
+    Some((hi as u64) << 32 | lo as u64)
+}
+
+/// Stop `counter`, returning its final value
+pub fn stop(counter: u32) -> Option<u64> {
+    let value = read(counter)?;
+    unsafe {
+        wrmsr(IA32_PERFEVTSEL0 + counter, 0);
+        RUNNING &= !(1 << counter);
+        if RUNNING == 0 {
+            set_user_rdpmc(false);
+        }
+    }
+    Some(value)
+}
@@ -1,8 +1,10 @@
 //! Real-Time Clock (RTC) support
 //!
-//! Reads date and time from the CMOS RTC chip.
+//! Reads date and time from the CMOS RTC chip. Decoding (BCD, 12-hour
+//! mode) is `watos_time::RtcRegisters`.
 
 use crate::port::{inb, outb};
+use watos_time::{DateTime, RtcRegisters, Timestamp};
 
 /// CMOS address port
 const CMOS_ADDR: u16 = 0x70;
@@ -35,9 +37,34 @@ fn update_in_progress() -> bool {
     read_cmos(reg::STATUS_A) & 0x80 != 0
 }
 
-/// Convert BCD to binary
-fn bcd_to_bin(bcd: u8) -> u8 {
-    (bcd & 0x0F) + ((bcd >> 4) * 10)
+/// Read every time register in one pass
+fn read_registers() -> RtcRegisters {
+    // Wait for update to complete
+    while update_in_progress() {}
+
+    RtcRegisters {
+        seconds: read_cmos(reg::SECONDS),
+        minutes: read_cmos(reg::MINUTES),
+        hours: read_cmos(reg::HOURS),
+        day: read_cmos(reg::DAY),
+        month: read_cmos(reg::MONTH),
+        year: read_cmos(reg::YEAR),
+        century: 0, // Assume 21st century
+        status_b: read_cmos(reg::STATUS_B),
+    }
+}
+
+/// Read the RTC until two passes agree, so an update between registers
+/// can't produce e.g. 12:59:00 from 12:59:59 -> 13:00:00
+fn read_stable() -> Option<DateTime> {
+    let mut last = read_registers();
+    loop {
+        let now = read_registers();
+        if now == last {
+            return now.to_datetime();
+        }
+        last = now;
+    }
 }
 
 /// Time structure
@@ -56,64 +83,33 @@ pub struct Date {
     pub day: u8,
 }
 
-/// Read current time from RTC
-pub fn read_time() -> Time {
-    // Wait for update to complete
-    while update_in_progress() {}
-
-    let status_b = read_cmos(reg::STATUS_B);
-    let is_bcd = (status_b & 0x04) == 0;
-    let is_24h = (status_b & 0x02) != 0;
-
-    let mut seconds = read_cmos(reg::SECONDS);
-    let mut minutes = read_cmos(reg::MINUTES);
-    let mut hours = read_cmos(reg::HOURS);
-
-    // Convert from BCD if needed
-    if is_bcd {
-        seconds = bcd_to_bin(seconds);
-        minutes = bcd_to_bin(minutes);
-        hours = bcd_to_bin(hours & 0x7F) | (hours & 0x80); // Preserve PM bit
-    }
+/// Current date and time, or the epoch if the RTC holds nonsense
+pub fn read_datetime() -> DateTime {
+    read_stable().unwrap_or(DateTime::new(1970, 1, 1, 0, 0, 0))
+}
 
-    // Convert 12-hour to 24-hour if needed
-    if !is_24h && (hours & 0x80) != 0 {
-        hours = ((hours & 0x7F) + 12) % 24;
-    }
+/// Current time as a timestamp
+pub fn now() -> Timestamp {
+    read_datetime().to_timestamp().unwrap_or(Timestamp::UNIX_EPOCH)
+}
 
+/// Read current time from RTC
+pub fn read_time() -> Time {
+    let dt = read_datetime();
     Time {
-        hours,
-        minutes,
-        seconds,
+        hours: dt.hour,
+        minutes: dt.minute,
+        seconds: dt.second,
     }
 }
 
 /// Read current date from RTC
 pub fn read_date() -> Date {
-    // Wait for update to complete
-    while update_in_progress() {}
-
-    let status_b = read_cmos(reg::STATUS_B);
-    let is_bcd = (status_b & 0x04) == 0;
-
-    let mut day = read_cmos(reg::DAY);
-    let mut month = read_cmos(reg::MONTH);
-    let mut year = read_cmos(reg::YEAR);
-
-    // Convert from BCD if needed
-    if is_bcd {
-        day = bcd_to_bin(day);
-        month = bcd_to_bin(month);
-        year = bcd_to_bin(year);
-    }
-
-    // Assume 21st century
-    let full_year = 2000 + year as u16;
-
+    let dt = read_datetime();
     Date {
-        year: full_year,
-        month,
-        day,
+        year: dt.year as u16,
+        month: dt.month,
+        day: dt.day,
     }
 }
 
@@ -2,14 +2,28 @@
 //!
 //! The TSS holds the kernel stack pointer that the CPU switches to
 //! when transitioning from user mode (Ring 3) to kernel mode (Ring 0).
+//!
+//! It also holds the Interrupt Stack Table: double faults, NMIs and machine
+//! checks switch to stacks of their own, so they can be reported even when
+//! the kernel stack is exhausted or in an unknown state.
 
 use core::mem::size_of;
 
-/// Stack size for double fault handler (separate from main kernel stack)
-const DOUBLE_FAULT_STACK_SIZE: usize = 4096;
+/// IST slots of the exceptions that never run on the interrupted stack
+pub mod ist {
+    pub const DOUBLE_FAULT: u8 = 1;
+    pub const NMI: u8 = 2;
+    pub const MACHINE_CHECK: u8 = 3;
+}
+
+/// Size of each IST stack; the crash report runs Rust code on it
+pub const IST_STACK_SIZE: usize = 16 * 1024;
+
+#[repr(C, align(16))]
+struct IstStack([u8; IST_STACK_SIZE]);
 
-/// Double fault stack - must be separate to handle stack overflow
-static mut DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+/// Stacks for IST1-3 (separate from the kernel stacks to survive overflow)
+static mut IST_STACKS: [IstStack; 3] = [const { IstStack([0; IST_STACK_SIZE]) }; 3];
 
 /// TSS structure for x86-64
 #[repr(C, packed)]
@@ -24,9 +38,11 @@ pub struct TaskStateSegment {
     reserved2: u64,
     /// Interrupt Stack Table entry 1 (for double fault)
     pub ist1: u64,
-    /// IST entries 2-7
+    /// IST entry 2 (for NMI)
     pub ist2: u64,
+    /// IST entry 3 (for machine check)
     pub ist3: u64,
+    /// IST entries 4-7 (unused)
     pub ist4: u64,
     pub ist5: u64,
     pub ist6: u64,
@@ -108,19 +124,35 @@ pub fn init(kernel_stack: u64) {
         // Set kernel stack for Ring 3 -> Ring 0 transitions
         TSS.rsp0 = kernel_stack;
 
-        // Set IST1 for double fault handler (critical!)
-        // This ensures double faults use a known-good stack
-        let df_stack_top = DOUBLE_FAULT_STACK.as_ptr() as u64 + DOUBLE_FAULT_STACK_SIZE as u64;
-        TSS.ist1 = df_stack_top;
+        // Double fault, NMI and machine check get known-good stacks
+        TSS.ist1 = ist_stack_top(ist::DOUBLE_FAULT);
+        TSS.ist2 = ist_stack_top(ist::NMI);
+        TSS.ist3 = ist_stack_top(ist::MACHINE_CHECK);
 
         crate::serial_write(b"[TSS] RSP0=0x");
         crate::serial_hex(kernel_stack);
         crate::serial_write(b" IST1=0x");
-        crate::serial_hex(df_stack_top);
+        crate::serial_hex(TSS.ist1);
+        crate::serial_write(b" IST2=0x");
+        crate::serial_hex(TSS.ist2);
+        crate::serial_write(b" IST3=0x");
+        crate::serial_hex(TSS.ist3);
         crate::serial_write(b"\r\n");
     }
 }
 
+/// Top of the stack for IST slot `index` (1-3)
+fn ist_stack_top(index: u8) -> u64 {
+    let base = core::ptr::addr_of!(IST_STACKS) as u64;
+    base + index as u64 * IST_STACK_SIZE as u64
+}
+
+/// Is `addr` on one of the IST stacks?
+pub fn on_ist_stack(addr: u64) -> bool {
+    let base = core::ptr::addr_of!(IST_STACKS) as u64;
+    (base..base + size_of::<[IstStack; 3]>() as u64).contains(&addr)
+}
+
 /// Get TSS descriptor for GDT
 pub fn descriptor() -> TssDescriptor {
     unsafe { TssDescriptor::new(&TSS) }
@@ -0,0 +1,150 @@
+//! Key repeat
+//!
+//! The keyboard's own typematic repeat resends a held key's make code at
+//! whatever rate the controller was left at, and only the last key pressed
+//! repeats. Rather than trust that, scancodes leaving the keyboard buffer
+//! pass through here: a make code for a key that is already down is the
+//! hardware repeating it and is dropped, and the last key pressed is
+//! repeated in software, first after `delay` ms and then `rate` times a
+//! second, until it is released. Every reader of the buffer (raw scancodes
+//! and SYS_GETKEY alike) sees the same repeats.
+//!
+//! Repeats are generated when the buffer is read, so a reader idling
+//! until the next interrupt gets them no faster than the timer wakes it.
+//! Modifiers and lock keys are never repeated.
+
+/// Defaults, those of a PC keyboard after reset
+pub const DEFAULT_DELAY_MS: u32 = 500;
+pub const DEFAULT_RATE: u32 = 10;
+
+/// Accepted delays, in ms
+pub const DELAY_RANGE: core::ops::RangeInclusive<u32> = 100..=2000;
+/// Most repeats per second; 0 turns repeat off
+pub const MAX_RATE: u32 = 30;
+
+static mut DELAY_MS: u32 = DEFAULT_DELAY_MS;
+static mut RATE: u32 = DEFAULT_RATE;
+
+/// Keys down, by scancode plus 0x80 for those with the E0 prefix
+static mut DOWN: [u64; 4] = [0; 4];
+/// Key being repeated, as (extended, scancode)
+static mut HELD: Option<(bool, u8)> = None;
+/// When `HELD` next repeats, in clock ms
+static mut NEXT_REPEAT: u64 = 0;
+/// An E0 prefix waiting for the byte that says whether it's a repeat
+static mut PREFIX: bool = false;
+/// Bytes of a Pause sequence (E1 ...) still to pass through
+static mut SKIP: u8 = 0;
+/// A byte decided on but not yet handed out
+static mut PENDING: Option<u8> = None;
+
+/// Repeat delay in ms and rate in repeats per second
+pub fn get() -> (u32, u32) {
+    unsafe { (DELAY_MS, RATE) }
+}
+
+/// Set the repeat delay and rate; false if either is out of range
+pub fn set(delay_ms: u32, rate: u32) -> bool {
+    if !DELAY_RANGE.contains(&delay_ms) || rate > MAX_RATE {
+        return false;
+    }
+    unsafe {
+        DELAY_MS = delay_ms;
+        RATE = rate;
+    }
+    true
+}
+
+/// The next scancode: buffered ones from `raw` with hardware repeats
+/// removed, then a software repeat if one is due at `now_ms`
+pub fn next(mut raw: impl FnMut() -> Option<u8>, now_ms: u64) -> Option<u8> {
+    unsafe {
+        if let Some(byte) = PENDING {
+            PENDING = None;
+            return Some(byte);
+        }
+        while let Some(byte) = raw() {
+            if let Some(byte) = filter(byte, now_ms) {
+                return Some(byte);
+            }
+        }
+        repeat(now_ms)
+    }
+}
+
+/// Track one byte from the keyboard; what to hand out now, if anything
+unsafe fn filter(byte: u8, now_ms: u64) -> Option<u8> {
+    if SKIP > 0 {
+        SKIP -= 1;
+        return Some(byte);
+    }
+    match byte {
+        0xE1 => {
+            SKIP = 5;
+            return Some(byte);
+        }
+        0xE0 => {
+            PREFIX = true;
+            return None;
+        }
+        _ => {}
+    }
+
+    let extended = PREFIX;
+    PREFIX = false;
+    let code = byte & 0x7F;
+    // The fake shifts around extended keys in some modes aren't keys
+    if extended && code == 0x2A {
+        return emit(extended, byte);
+    }
+
+    let key = code as usize + if extended { 0x80 } else { 0 };
+    let bit = 1u64 << (key % 64);
+    if byte & 0x80 != 0 {
+        DOWN[key / 64] &= !bit;
+        if HELD == Some((extended, code)) {
+            HELD = None;
+        }
+    } else {
+        if DOWN[key / 64] & bit != 0 {
+            return None;
+        }
+        DOWN[key / 64] |= bit;
+        if !is_modifier(extended, code) {
+            HELD = Some((extended, code));
+            NEXT_REPEAT = now_ms + DELAY_MS as u64;
+        }
+    }
+    emit(extended, byte)
+}
+
+/// Hand out `byte`, after its E0 prefix if it had one
+unsafe fn emit(extended: bool, byte: u8) -> Option<u8> {
+    if extended {
+        PENDING = Some(byte);
+        Some(0xE0)
+    } else {
+        Some(byte)
+    }
+}
+
+/// A repeat of the held key, if one is due
+unsafe fn repeat(now_ms: u64) -> Option<u8> {
+    let (extended, code) = HELD?;
+    if RATE == 0 || now_ms < NEXT_REPEAT {
+        return None;
+    }
+    // Catching up after a slow reader would burst, so count from now
+    NEXT_REPEAT = now_ms + (1000 / RATE) as u64;
+    emit(extended, code)
+}
+
+/// Shift, Ctrl, Alt, GUI and the locks
+fn is_modifier(extended: bool, code: u8) -> bool {
+    match code {
+        0x1D | 0x38 => true,
+        0x2A | 0x36 | 0x3A | 0x45 | 0x46 => !extended,
+        0x5B | 0x5C => extended,
+        _ => false,
+    }
+}
@@ -0,0 +1,90 @@
+//! Kernel command line
+//!
+//! Space-separated words, each a bare flag (`debug`) or a `key=value`
+//! option (`serial=115200`, `root=C:`). Double quotes let a value contain
+//! spaces: `init="shell -l"`. As with the config file, a repeated key keeps
+//! its last value.
+
+/// Largest command line passed to the kernel
+pub const MAX_CMDLINE_SIZE: usize = 512;
+
+/// A parsed command line, borrowing its text
+#[derive(Clone, Copy)]
+pub struct Cmdline<'a> {
+    text: &'a str,
+}
+
+impl<'a> Cmdline<'a> {
+    pub const fn empty() -> Self {
+        Cmdline { text: "" }
+    }
+
+    /// Wrap the raw bytes; anything after invalid UTF-8 is ignored
+    pub fn parse(data: &'a [u8]) -> Self {
+        let text = match core::str::from_utf8(data) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or(""),
+        };
+        Cmdline { text: text.trim_end_matches('\0').trim() }
+    }
+
+    /// The command line as given
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// Words as (key, value); flags have no value. Quotes are removed from
+    /// values.
+    pub fn words(&self) -> Words<'a> {
+        Words { rest: self.text }
+    }
+
+    /// Is the bare flag `name` present?
+    pub fn flag(&self, name: &str) -> bool {
+        self.words().any(|(key, value)| key == name && value.is_none())
+    }
+
+    /// Value of `key=value` (the last one if repeated)
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.words().filter(|&(k, _)| k == key).filter_map(|(_, v)| v).last()
+    }
+}
+
+/// Iterator over command line words
+pub struct Words<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = (&'a str, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        // A word ends at the first blank outside quotes
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c.is_ascii_whitespace() && !quoted
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (word, tail) = rest.split_at(end);
+        self.rest = tail;
+
+        Some(match word.split_once('=') {
+            Some((key, value)) => {
+                let value = value.strip_prefix('"').unwrap_or(value);
+                (key, Some(value.strip_suffix('"').unwrap_or(value)))
+            }
+            None => (word, None),
+        })
+    }
+}
@@ -0,0 +1,289 @@
+//! WATOS Boot Configuration
+//!
+//! Parses `/boot/watos.cfg` from the EFI system partition. The bootloader
+//! reads the file, applies the options it owns (the video mode) and hands
+//! the raw text to the kernel in BootInfo, so both sides share this parser.
+//!
+//! The format is one `key = value` pair per line:
+//!
+//! ```text
+//! # WATOS boot configuration
+//! video = 1280x800     # GOP mode chosen by the bootloader
+//! loglevel = info      # quiet | info | debug
+//! root = ahci2         # AHCI port holding the root FAT filesystem
+//! init = login         # first program started
+//! cmdline = debug      # kernel command line if the firmware passes none
+//! ```
+//!
+//! Whitespace around keys and values is ignored, `#` starts a comment, and
+//! a key given twice keeps its last value. Unknown keys are kept so
+//! subsystems can look up their own options with [`BootConfig::get`].
+//!
+//! The kernel command line ([`cmdline`]) arrives in BootInfo as well. Once
+//! the kernel calls [`install`], any subsystem can read both through
+//! [`config`], [`cmdline()`] and [`option`], where the command line wins.
+
+#![no_std]
+
+pub mod cmdline;
+
+pub use cmdline::{Cmdline, MAX_CMDLINE_SIZE};
+
+/// Location of the file on the EFI system partition
+pub const CONFIG_PATH: &str = "/boot/watos.cfg";
+
+/// Largest configuration file passed to the kernel
+pub const MAX_CONFIG_SIZE: usize = 4096;
+
+/// How much the kernel logs to the serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Keep messages in the kernel log only
+    Quiet,
+    /// Echo messages to the serial port (default)
+    Info,
+    /// Also report the boot configuration and other diagnostics
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "quiet" | "0" => Some(LogLevel::Quiet),
+            "info" | "1" => Some(LogLevel::Info),
+            "debug" | "2" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed configuration file, borrowing its text
+#[derive(Clone, Copy)]
+pub struct BootConfig<'a> {
+    text: &'a str,
+}
+
+impl<'a> BootConfig<'a> {
+    /// An empty configuration: every option at its default
+    pub const fn empty() -> Self {
+        BootConfig { text: "" }
+    }
+
+    /// Wrap the file contents; anything after invalid UTF-8 is ignored
+    pub fn parse(data: &'a [u8]) -> Self {
+        let text = match core::str::from_utf8(data) {
+            Ok(text) => text,
+            // The prefix up to the error is valid by definition
+            Err(e) => core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or(""),
+        };
+        BootConfig { text }
+    }
+
+    /// Non-blank lines with comments stripped
+    fn lines(&self) -> impl Iterator<Item = &'a str> {
+        self.text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+    }
+
+    /// Every `key = value` pair in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.lines().filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            (!key.is_empty()).then_some((key, value.trim()))
+        })
+    }
+
+    /// Lines that are not `key = value` pairs
+    pub fn malformed(&self) -> impl Iterator<Item = &'a str> {
+        self.lines()
+            .filter(|line| line.split_once('=').is_none_or(|(key, _)| key.trim().is_empty()))
+    }
+
+    /// Value of `key` (the last one if repeated)
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.entries().filter(|&(k, _)| k == key).last().map(|(_, v)| v)
+    }
+
+    /// Numeric value of `key`, decimal or 0x-prefixed hex
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        let value = self.get(key)?;
+        match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    }
+
+    /// Boolean value of `key`: yes/no, on/off, true/false or 1/0
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        parse_bool(self.get(key)?)
+    }
+
+    /// `video = WIDTHxHEIGHT`
+    pub fn video_mode(&self) -> Option<(u32, u32)> {
+        let (w, h) = self.get("video")?.split_once(['x', 'X'])?;
+        let (w, h) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+        (w > 0 && h > 0).then_some((w, h))
+    }
+
+    /// `loglevel = quiet | info | debug`, Info if absent or invalid
+    pub fn log_level(&self) -> LogLevel {
+        self.get("loglevel").and_then(LogLevel::parse).unwrap_or(LogLevel::Info)
+    }
+
+    /// `root = ahciN`: the AHCI port to mount as C:, or None to probe
+    pub fn root_port(&self) -> Option<u8> {
+        self.get("root").and_then(ahci_port)
+    }
+
+    /// `init = NAME`: the first program to start
+    pub fn init(&self) -> Option<&'a str> {
+        self.get("init").filter(|name| !name.is_empty())
+    }
+}
+
+/// yes/no, on/off, true/false or 1/0
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "yes" | "on" | "true" => Some(true),
+        "0" | "no" | "off" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Port number of an `ahciN` root device
+fn ahci_port(root: &str) -> Option<u8> {
+    root.strip_prefix("ahci")?.parse().ok()
+}
+
+static mut CONFIG_TEXT: &[u8] = b"";
+static mut CMDLINE_TEXT: &[u8] = b"";
+
+/// Publish the boot configuration and command line to the whole kernel
+pub fn install(config: &'static [u8], cmdline: &'static [u8]) {
+    unsafe {
+        CONFIG_TEXT = config;
+        CMDLINE_TEXT = cmdline;
+    }
+}
+
+/// The installed watos.cfg
+pub fn config() -> BootConfig<'static> {
+    BootConfig::parse(unsafe { CONFIG_TEXT })
+}
+
+/// The installed kernel command line
+pub fn cmdline() -> Cmdline<'static> {
+    Cmdline::parse(unsafe { CMDLINE_TEXT })
+}
+
+/// Value of a boot option: `key=value` on the command line, else `key` in
+/// watos.cfg
+pub fn option(key: &str) -> Option<&'static str> {
+    cmdline().get(key).or_else(|| config().get(key))
+}
+
+/// Boolean boot option, looked up like [`option`]
+pub fn option_bool(key: &str) -> Option<bool> {
+    parse_bool(option(key)?)
+}
+
+/// Effective root device port (`root=ahciN`); None to probe. The root
+/// filesystem is always drive C:, so `root=C:` also means probe.
+pub fn root_port() -> Option<u8> {
+    option("root").and_then(ahci_port)
+}
+
+/// Effective init program
+pub fn init() -> Option<&'static str> {
+    option("init").filter(|name| !name.is_empty())
+}
+
+/// Effective log level: the `debug` or `quiet` flags, `loglevel=` on the
+/// command line, then watos.cfg
+pub fn log_level() -> LogLevel {
+    let cmdline = cmdline();
+    if cmdline.flag("debug") {
+        LogLevel::Debug
+    } else if cmdline.flag("quiet") {
+        LogLevel::Quiet
+    } else {
+        cmdline.get("loglevel").and_then(LogLevel::parse).unwrap_or_else(|| config().log_level())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &[u8] = b"# boot options\n\
+        video = 1024x768\n\
+        loglevel=debug   # noisy\n\
+        \n\
+        root = ahci2\n\
+        init = shell\n\
+        init = login\n\
+        bogus line\n\
+        =novalue\n\
+        klog.max = 0x40000\n";
+
+    #[test]
+    fn test_typed_options() {
+        let cfg = BootConfig::parse(SAMPLE);
+        assert_eq!(cfg.video_mode(), Some((1024, 768)));
+        assert_eq!(cfg.log_level(), LogLevel::Debug);
+        assert_eq!(cfg.root_port(), Some(2));
+        assert_eq!(cfg.init(), Some("login"));
+        assert_eq!(cfg.get_u64("klog.max"), Some(0x40000));
+        assert_eq!(cfg.get("missing"), None);
+    }
+
+    #[test]
+    fn test_entries_and_malformed() {
+        let cfg = BootConfig::parse(SAMPLE);
+        assert_eq!(cfg.entries().count(), 6);
+        let bad: [&str; 2] = ["bogus line", "=novalue"];
+        assert!(cfg.malformed().eq(bad.iter().copied()));
+    }
+
+    #[test]
+    fn test_defaults_and_bad_input() {
+        let cfg = BootConfig::empty();
+        assert_eq!(cfg.log_level(), LogLevel::Info);
+        assert_eq!(cfg.video_mode(), None);
+
+        let cfg = BootConfig::parse(b"video = big\nroot = C:\nloglevel = loud\n");
+        assert_eq!(cfg.video_mode(), None);
+        assert_eq!(cfg.root_port(), None);
+        assert_eq!(cfg.log_level(), LogLevel::Info);
+
+        // Text after invalid UTF-8 is dropped
+        let cfg = BootConfig::parse(b"init = login\n\xFF\ninit = shell\n");
+        assert_eq!(cfg.init(), Some("login"));
+        assert_eq!(cfg.get_bool("debug"), None);
+    }
+
+    #[test]
+    fn test_cmdline_words() {
+        let cmdline = Cmdline::parse(b"  debug serial=115200\troot=C: init=\"shell -l\" root=D:\0");
+        assert!(cmdline.flag("debug"));
+        assert!(!cmdline.flag("serial"));
+        assert_eq!(cmdline.get("serial"), Some("115200"));
+        assert_eq!(cmdline.get("root"), Some("D:"));
+        assert_eq!(cmdline.get("init"), Some("shell -l"));
+        assert_eq!(cmdline.words().count(), 5);
+        assert_eq!(cmdline.as_str(), "debug serial=115200\troot=C: init=\"shell -l\" root=D:");
+    }
+
+    #[test]
+    fn test_cmdline_overrides_config() {
+        install(b"loglevel = quiet\ninit = login\n", b"init=shell");
+        assert_eq!(option("init"), Some("shell"));
+        assert_eq!(option("loglevel"), Some("quiet"));
+        assert_eq!(log_level(), LogLevel::Quiet);
+        install(b"loglevel = quiet\n", b"debug");
+        assert_eq!(log_level(), LogLevel::Debug);
+    }
+}
@@ -0,0 +1,212 @@
+//! Authenticated encryption with associated data
+//!
+//! - ChaCha20-Poly1305 (RFC 8439)
+//! - AES-GCM with 96-bit nonces (NIST SP 800-38D), 128- or 256-bit keys
+//!
+//! Both encrypt in place and return a 16-byte tag, and both check the tag
+//! before decrypting anything.
+
+use crate::aes::Aes;
+use crate::chacha20::{ChaCha20, KEY_LEN, NONCE_LEN};
+use crate::poly1305::Poly1305;
+use crate::{ct_eq, wipe};
+
+/// Tag length in bytes for both ciphers
+pub const TAG_LEN: usize = 16;
+
+/// ChaCha20-Poly1305 under one key
+pub struct ChaCha20Poly1305 {
+    key: [u8; KEY_LEN],
+}
+
+impl ChaCha20Poly1305 {
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        ChaCha20Poly1305 { key: *key }
+    }
+
+    /// Encrypt `data` in place and return its tag
+    pub fn seal(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8]) -> [u8; TAG_LEN] {
+        ChaCha20::new(&self.key, nonce, 1).apply(data);
+        self.tag(nonce, aad, data)
+    }
+
+    /// Check `tag` and decrypt `data` in place; false (data untouched) if
+    /// the tag is wrong
+    pub fn open(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        if !ct_eq(&self.tag(nonce, aad, data), tag) {
+            return false;
+        }
+        ChaCha20::new(&self.key, nonce, 1).apply(data);
+        true
+    }
+
+    fn tag(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        // The one-time Poly1305 key is the first half of keystream block 0
+        let mut otk = [0u8; 32];
+        ChaCha20::new(&self.key, nonce, 0).apply(&mut otk);
+        let mut mac = Poly1305::new(&otk);
+        wipe(&mut otk);
+
+        mac.update(aad);
+        mac.pad16();
+        mac.update(ciphertext);
+        mac.pad16();
+        mac.update(&(aad.len() as u64).to_le_bytes());
+        mac.update(&(ciphertext.len() as u64).to_le_bytes());
+        mac.finish()
+    }
+}
+
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+/// AES-GCM under one key
+pub struct AesGcm {
+    aes: Aes,
+    /// GHASH key: the encryption of the zero block
+    h: u128,
+}
+
+impl AesGcm {
+    /// A 16- or 32-byte key; None for other lengths
+    pub fn new(key: &[u8]) -> Option<Self> {
+        let aes = Aes::new(key)?;
+        let mut zero = [0u8; 16];
+        aes.encrypt_block(&mut zero);
+        Some(AesGcm { aes, h: u128::from_be_bytes(zero) })
+    }
+
+    /// Encrypt `data` in place and return its tag
+    pub fn seal(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8]) -> [u8; TAG_LEN] {
+        self.ctr(nonce, data);
+        self.tag(nonce, aad, data)
+    }
+
+    /// Check `tag` and decrypt `data` in place; false (data untouched) if
+    /// the tag is wrong
+    pub fn open(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        if !ct_eq(&self.tag(nonce, aad, data), tag) {
+            return false;
+        }
+        self.ctr(nonce, data);
+        true
+    }
+
+    /// Counter block `n` for a nonce (block 1 masks the tag, 2.. the data)
+    fn counter(nonce: &[u8; NONCE_LEN], n: u32) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..NONCE_LEN].copy_from_slice(nonce);
+        block[NONCE_LEN..].copy_from_slice(&n.to_be_bytes());
+        block
+    }
+
+    fn ctr(&self, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let mut pad = Self::counter(nonce, i as u32 + 2);
+            self.aes.encrypt_block(&mut pad);
+            for (b, p) in chunk.iter_mut().zip(pad) {
+                *b ^= p;
+            }
+        }
+    }
+
+    fn tag(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        let mut y = 0u128;
+        for part in [aad, ciphertext] {
+            for chunk in part.chunks(16) {
+                let mut block = [0u8; 16];
+                block[..chunk.len()].copy_from_slice(chunk);
+                y = gf_mul(y ^ u128::from_be_bytes(block), self.h);
+            }
+        }
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        y = gf_mul(y ^ lengths, self.h);
+
+        let mut mask = Self::counter(nonce, 1);
+        self.aes.encrypt_block(&mut mask);
+        (y ^ u128::from_be_bytes(mask)).to_be_bytes()
+    }
+}
+
+/// Multiply in GF(2^128) with GCM's bit order
+fn gf_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0u128;
+    let mut v = y;
+    for i in 0..128 {
+        if (x >> (127 - i)) & 1 == 1 {
+            z ^= v;
+        }
+        let carry = v & 1;
+        v >>= 1;
+        if carry == 1 {
+            v ^= 0xe1 << 120;
+        }
+    }
+    z
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{hex, unhex};
+    use std::vec::Vec;
+
+    #[test]
+    fn test_chacha20_poly1305() {
+        // RFC 8439 section 2.8.2
+        let key: [u8; 32] = core::array::from_fn(|i| 0x80 + i as u8);
+        let nonce = [0x07, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+        let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+        let plain: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let aead = ChaCha20Poly1305::new(&key);
+        let mut data = Vec::from(plain);
+        let tag = aead.seal(&nonce, &aad, &mut data);
+        assert_eq!(hex(&data[..16]).as_str(), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(hex(&tag).as_str(), "1ae10b594f09e26a7e902ecbd0600691");
+
+        let mut bad = tag;
+        bad[0] ^= 1;
+        assert!(!aead.open(&nonce, &aad, &mut data, &bad));
+        assert!(aead.open(&nonce, &aad, &mut data, &tag));
+        assert_eq!(&data[..], plain);
+    }
+
+    #[test]
+    fn test_aes_gcm() {
+        // McGrew and Viega, GCM test cases 4 (AES-128) and 16 (AES-256)
+        let plain = unhex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let nonce: [u8; 12] = unhex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let cases = [
+            (
+                "feffe9928665731c6d6a8f9467308308",
+                "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                 21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+                "5bc94fbc3221a5db94fae95ae7121a47",
+            ),
+            (
+                "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+                 8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662",
+                "76fc6ece0f4e1768cddf8853bb2d551b",
+            ),
+        ];
+        for (key, cipher, tag) in cases {
+            let gcm = AesGcm::new(&unhex(key)).unwrap();
+            let mut data = plain.clone();
+            assert_eq!(hex(&gcm.seal(&nonce, &aad, &mut data)).as_str(), tag);
+            assert_eq!(hex(&data).as_str(), cipher);
+            assert!(gcm.open(&nonce, &aad, &mut data, &unhex(tag)));
+            assert_eq!(data, plain);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! AES block encryption (FIPS 197), 128- and 256-bit keys
+//!
+//! Encryption only: GCM never runs the cipher backwards. Table lookups on
+//! the S-box make this leak timing to a co-located attacker.
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// Most round keys (AES-256 has 14 rounds)
+const MAX_ROUND_KEYS: usize = 15;
+
+/// An expanded AES key
+#[derive(Clone)]
+pub struct Aes {
+    round_keys: [[u8; 16]; MAX_ROUND_KEYS],
+    rounds: usize,
+}
+
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+impl Aes {
+    /// Expand a 16- or 32-byte key; None for other lengths
+    pub fn new(key: &[u8]) -> Option<Self> {
+        let nk = match key.len() {
+            16 => 4,
+            32 => 8,
+            _ => return None,
+        };
+        let rounds = nk + 6;
+        let total = 4 * (rounds + 1);
+
+        let mut w = [[0u8; 4]; 4 * MAX_ROUND_KEYS];
+        for (i, word) in key.chunks_exact(4).enumerate() {
+            w[i].copy_from_slice(word);
+        }
+        let mut rcon = 1u8;
+        for i in nk..total {
+            let mut t = w[i - 1];
+            if i % nk == 0 {
+                t = [SBOX[t[1] as usize], SBOX[t[2] as usize], SBOX[t[3] as usize], SBOX[t[0] as usize]];
+                t[0] ^= rcon;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                t = t.map(|b| SBOX[b as usize]);
+            }
+            for j in 0..4 {
+                w[i][j] = w[i - nk][j] ^ t[j];
+            }
+        }
+
+        let mut round_keys = [[0u8; 16]; MAX_ROUND_KEYS];
+        for (r, key) in round_keys.iter_mut().enumerate().take(rounds + 1) {
+            for j in 0..4 {
+                key[4 * j..4 * j + 4].copy_from_slice(&w[4 * r + j]);
+            }
+        }
+        Some(Aes { round_keys, rounds })
+    }
+
+    /// Encrypt one block in place
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        add_round_key(block, &self.round_keys[0]);
+        for round in 1..=self.rounds {
+            for b in block.iter_mut() {
+                *b = SBOX[*b as usize];
+            }
+            shift_rows(block);
+            if round != self.rounds {
+                mix_columns(block);
+            }
+            add_round_key(block, &self.round_keys[round]);
+        }
+    }
+}
+
+impl Drop for Aes {
+    fn drop(&mut self) {
+        for key in &mut self.round_keys {
+            crate::wipe(key);
+        }
+    }
+}
+
+fn add_round_key(block: &mut [u8; 16], key: &[u8; 16]) {
+    for (b, k) in block.iter_mut().zip(key) {
+        *b ^= k;
+    }
+}
+
+/// The state is column-major: byte `4 * c + r` is row r of column c
+fn shift_rows(s: &mut [u8; 16]) {
+    let t = *s;
+    for c in 0..4 {
+        for r in 1..4 {
+            s[4 * c + r] = t[4 * ((c + r) % 4) + r];
+        }
+    }
+}
+
+fn mix_columns(s: &mut [u8; 16]) {
+    for col in s.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        col[0] ^= all ^ xtime(a0 ^ a1);
+        col[1] ^= all ^ xtime(a1 ^ a2);
+        col[2] ^= all ^ xtime(a2 ^ a3);
+        col[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn test_aes() {
+        // FIPS 197 appendix C
+        let plain: [u8; 16] = core::array::from_fn(|i| (i as u8) * 0x11);
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+
+        let mut block = plain;
+        Aes::new(&key[..16]).unwrap().encrypt_block(&mut block);
+        assert_eq!(hex(&block).as_str(), "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let mut block = plain;
+        Aes::new(&key).unwrap().encrypt_block(&mut block);
+        assert_eq!(hex(&block).as_str(), "8ea2b7ca516745bfeafc49904b496089");
+
+        assert!(Aes::new(&key[..24]).is_none());
+    }
+}
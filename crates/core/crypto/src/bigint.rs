@@ -0,0 +1,300 @@
+//! Modular arithmetic on fixed-size unsigned integers
+//!
+//! Just what signature verification needs: numbers of up to [`MAX_BITS`]
+//! bits as little-endian u64 limbs, multiplied in Montgomery form modulo an
+//! odd modulus. Nothing here is constant time; it only handles public
+//! values (keys, signatures, digests).
+
+use core::cmp::Ordering;
+
+/// Largest modulus in bits (RSA-4096)
+pub const MAX_BITS: usize = 4096;
+
+/// Limbs in a [`Limbs`]
+pub const MAX_LIMBS: usize = MAX_BITS / 64;
+
+/// A number, least significant limb first; only a modulus's `len` limbs
+/// are used
+pub type Limbs = [u64; MAX_LIMBS];
+
+/// Parse a big-endian number into `len` limbs; None if it doesn't fit
+pub fn from_be_bytes(bytes: &[u8], len: usize) -> Option<Limbs> {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+    if bytes.len() > len * 8 {
+        return None;
+    }
+    let mut out = [0u64; MAX_LIMBS];
+    for (i, &b) in bytes.iter().rev().enumerate() {
+        out[i / 8] |= (b as u64) << (8 * (i % 8));
+    }
+    Some(out)
+}
+
+/// Write the low `out.len()` bytes of a number big-endian
+pub fn to_be_bytes(a: &Limbs, out: &mut [u8]) {
+    let n = out.len();
+    for (i, b) in out.iter_mut().enumerate() {
+        let k = n - 1 - i;
+        *b = if k / 8 < MAX_LIMBS { (a[k / 8] >> (8 * (k % 8))) as u8 } else { 0 };
+    }
+}
+
+/// Compare the first `len` limbs of two numbers
+pub fn cmp(a: &Limbs, b: &Limbs, len: usize) -> Ordering {
+    for i in (0..len).rev() {
+        match a[i].cmp(&b[i]) {
+            Ordering::Equal => {}
+            other => return other,
+        }
+    }
+    Ordering::Equal
+}
+
+pub fn is_zero(a: &Limbs, len: usize) -> bool {
+    a[..len].iter().all(|&l| l == 0)
+}
+
+/// a -= b over `len` limbs, returning the borrow
+fn sub_in_place(a: &mut Limbs, b: &Limbs, len: usize) -> u64 {
+    let mut borrow = 0u64;
+    for i in 0..len {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (d, b2) = d.overflowing_sub(borrow);
+        a[i] = d;
+        borrow = (b1 | b2) as u64;
+    }
+    borrow
+}
+
+/// a += b over `len` limbs, returning the carry
+fn add_in_place(a: &mut Limbs, b: &Limbs, len: usize) -> u64 {
+    let mut carry = 0u64;
+    for i in 0..len {
+        let (s, c1) = a[i].overflowing_add(b[i]);
+        let (s, c2) = s.overflowing_add(carry);
+        a[i] = s;
+        carry = (c1 | c2) as u64;
+    }
+    carry
+}
+
+/// Number of significant bits
+pub fn bit_len(a: &Limbs, len: usize) -> usize {
+    for i in (0..len).rev() {
+        if a[i] != 0 {
+            return 64 * i + 64 - a[i].leading_zeros() as usize;
+        }
+    }
+    0
+}
+
+/// An odd modulus with its Montgomery constants (R = 2^(64 * len))
+#[derive(Clone)]
+pub struct Modulus {
+    n: Limbs,
+    len: usize,
+    /// -n^-1 mod 2^64
+    n0inv: u64,
+    /// R^2 mod n
+    rr: Limbs,
+}
+
+impl Modulus {
+    /// None if `n` is even, one, or longer than MAX_BITS
+    pub fn new(n_bytes: &[u8]) -> Option<Self> {
+        let len = n_bytes.iter().skip_while(|&&b| b == 0).count().div_ceil(8).max(1);
+        let n = from_be_bytes(n_bytes, len)?;
+        if n[0] & 1 == 0 || (len == 1 && n[0] == 1) {
+            return None;
+        }
+
+        // Newton's iteration for n^-1 mod 2^64
+        let mut inv = 1u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(n[0].wrapping_mul(inv)));
+        }
+
+        // R^2 mod n by doubling 1 (2 * 64 * len) times
+        let mut rr = [0u64; MAX_LIMBS];
+        rr[0] = 1;
+        for _ in 0..2 * 64 * len {
+            let prev = rr;
+            let carry = add_in_place(&mut rr, &prev, len);
+            if carry != 0 || cmp(&rr, &n, len) != Ordering::Less {
+                sub_in_place(&mut rr, &n, len);
+            }
+        }
+        Some(Modulus { n, len, n0inv: inv.wrapping_neg(), rr })
+    }
+
+    /// Limbs in use
+    pub fn limbs(&self) -> usize {
+        self.len
+    }
+
+    pub fn n(&self) -> &Limbs {
+        &self.n
+    }
+
+    /// Bits in the modulus
+    pub fn bits(&self) -> usize {
+        bit_len(&self.n, self.len)
+    }
+
+    /// Is a < n?
+    pub fn contains(&self, a: &Limbs) -> bool {
+        cmp(a, &self.n, self.len) == Ordering::Less
+    }
+
+    /// a mod n for a < 2n
+    pub fn reduce_once(&self, a: &Limbs) -> Limbs {
+        let mut r = *a;
+        if !self.contains(&r) {
+            sub_in_place(&mut r, &self.n, self.len);
+        }
+        r
+    }
+
+    /// a * b / R mod n (CIOS Montgomery multiplication)
+    pub fn mont_mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let len = self.len;
+        let mut t = [0u64; MAX_LIMBS + 2];
+        for &ai in &a[..len] {
+            let mut c = 0u128;
+            for j in 0..len {
+                let v = t[j] as u128 + ai as u128 * b[j] as u128 + c;
+                t[j] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[len] as u128 + c;
+            t[len] = v as u64;
+            t[len + 1] = (v >> 64) as u64;
+
+            let m = t[0].wrapping_mul(self.n0inv);
+            let mut c = (t[0] as u128 + m as u128 * self.n[0] as u128) >> 64;
+            for j in 1..len {
+                let v = t[j] as u128 + m as u128 * self.n[j] as u128 + c;
+                t[j - 1] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[len] as u128 + c;
+            t[len - 1] = v as u64;
+            t[len] = t[len + 1] + (v >> 64) as u64;
+        }
+
+        let mut r = [0u64; MAX_LIMBS];
+        r[..len].copy_from_slice(&t[..len]);
+        if t[len] != 0 || !self.contains(&r) {
+            sub_in_place(&mut r, &self.n, len);
+        }
+        r
+    }
+
+    /// Into Montgomery form (a must be < n)
+    pub fn to_mont(&self, a: &Limbs) -> Limbs {
+        self.mont_mul(a, &self.rr)
+    }
+
+    /// Out of Montgomery form
+    pub fn from_mont(&self, a: &Limbs) -> Limbs {
+        let mut one = [0u64; MAX_LIMBS];
+        one[0] = 1;
+        self.mont_mul(a, &one)
+    }
+
+    /// 1 in Montgomery form
+    pub fn one(&self) -> Limbs {
+        let mut one = [0u64; MAX_LIMBS];
+        one[0] = 1;
+        self.to_mont(&one)
+    }
+
+    /// a + b mod n
+    pub fn add(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let mut r = *a;
+        let carry = add_in_place(&mut r, b, self.len);
+        if carry != 0 || !self.contains(&r) {
+            sub_in_place(&mut r, &self.n, self.len);
+        }
+        r
+    }
+
+    /// a - b mod n
+    pub fn sub(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let mut r = *a;
+        if sub_in_place(&mut r, b, self.len) != 0 {
+            add_in_place(&mut r, &self.n, self.len);
+        }
+        r
+    }
+
+    /// base^exp mod n, base in Montgomery form, result in Montgomery form
+    pub fn pow(&self, base: &Limbs, exp_be: &[u8]) -> Limbs {
+        let mut acc = self.one();
+        for &byte in exp_be {
+            for bit in (0..8).rev() {
+                acc = self.mont_mul(&acc, &acc);
+                if (byte >> bit) & 1 == 1 {
+                    acc = self.mont_mul(&acc, base);
+                }
+            }
+        }
+        acc
+    }
+
+    /// a^-1 for a prime modulus (Fermat), in and out of Montgomery form
+    pub fn invert_prime(&self, a: &Limbs) -> Limbs {
+        let mut two = [0u64; MAX_LIMBS];
+        two[0] = 2;
+        let mut exp = self.n;
+        sub_in_place(&mut exp, &two, self.len);
+        let mut exp_be = [0u8; MAX_BITS / 8];
+        let bytes = &mut exp_be[..self.len * 8];
+        to_be_bytes(&exp, bytes);
+        self.pow(a, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modular_arithmetic() {
+        // 2^127 - 1 is prime
+        let mut p_bytes = [0xffu8; 16];
+        p_bytes[0] = 0x7f;
+        let m = Modulus::new(&p_bytes).unwrap();
+        assert_eq!(m.limbs(), 2);
+        assert_eq!(m.bits(), 127);
+
+        let a = from_be_bytes(&[0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x11], 2).unwrap();
+        let am = m.to_mont(&a);
+        assert_eq!(m.from_mont(&am), a);
+
+        // a * a^-1 = 1
+        let inv = m.invert_prime(&am);
+        let mut one = [0u64; MAX_LIMBS];
+        one[0] = 1;
+        assert_eq!(m.from_mont(&m.mont_mul(&am, &inv)), one);
+
+        // Fermat: a^(p-1) = 1
+        let mut exp = p_bytes;
+        exp[15] = 0xfe;
+        assert_eq!(m.from_mont(&m.pow(&am, &exp)), one);
+
+        // 3^5 mod 7 = 5
+        let seven = Modulus::new(&[7]).unwrap();
+        let three = seven.to_mont(&from_be_bytes(&[3], 1).unwrap());
+        assert_eq!(seven.from_mont(&seven.pow(&three, &[5]))[0], 5);
+        assert_eq!(seven.from_mont(&seven.sub(&seven.one(), &three))[0], 5);
+
+        let mut out = [0u8; 10];
+        to_be_bytes(&a, &mut out);
+        assert_eq!(out, [0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x11]);
+
+        assert!(Modulus::new(&[8]).is_none());
+        assert!(Modulus::new(&[1]).is_none());
+    }
+}
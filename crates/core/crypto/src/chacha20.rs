@@ -0,0 +1,118 @@
+//! ChaCha20 stream cipher (RFC 8439)
+//!
+//! 256-bit key, 96-bit nonce and 32-bit block counter. A key and nonce
+//! pair must never encrypt two different messages.
+
+/// Key length in bytes
+pub const KEY_LEN: usize = 32;
+
+/// Nonce length in bytes
+pub const NONCE_LEN: usize = 12;
+
+const BLOCK_LEN: usize = 64;
+
+/// ChaCha20 keystream, applied with [`ChaCha20::apply`]
+pub struct ChaCha20 {
+    /// Constants, key, counter and nonce
+    state: [u32; 16],
+    /// Current keystream block and how much of it is used
+    block: [u8; BLOCK_LEN],
+    used: usize,
+}
+
+impl ChaCha20 {
+    /// Start the keystream at block `counter`
+    pub fn new(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> Self {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+        for (i, chunk) in key.chunks_exact(4).enumerate() {
+            state[4 + i] = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        state[12] = counter;
+        for (i, chunk) in nonce.chunks_exact(4).enumerate() {
+            state[13 + i] = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        ChaCha20 { state, block: [0; BLOCK_LEN], used: BLOCK_LEN }
+    }
+
+    /// XOR the keystream into `data`; encrypting and decrypting are the same
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            if self.used == BLOCK_LEN {
+                self.block = block(&self.state);
+                self.state[12] = self.state[12].wrapping_add(1);
+                self.used = 0;
+            }
+            *byte ^= self.block[self.used];
+            self.used += 1;
+        }
+    }
+}
+
+impl Drop for ChaCha20 {
+    fn drop(&mut self) {
+        self.state = [0; 16];
+        crate::wipe(&mut self.block);
+    }
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// One 64-byte keystream block
+fn block(state: &[u32; 16]) -> [u8; BLOCK_LEN] {
+    let mut s = *state;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; BLOCK_LEN];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&s[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chacha20() {
+        // RFC 8439 section 2.4.2
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plain: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let expected: [u8; 16] = [
+            0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d, 0x69, 0x81,
+        ];
+
+        let mut data = [0u8; 114];
+        data.copy_from_slice(plain);
+        // Split across calls to cross a block boundary mid-call
+        let mut cipher = ChaCha20::new(&key, &nonce, 1);
+        let (first, rest) = data.split_at_mut(50);
+        cipher.apply(first);
+        cipher.apply(rest);
+        assert_eq!(data[..16], expected);
+        assert_eq!(data[112..], [0x87, 0x4d]);
+
+        ChaCha20::new(&key, &nonce, 1).apply(&mut data);
+        assert_eq!(&data[..], plain);
+    }
+}
@@ -0,0 +1,314 @@
+//! ECDSA signature verification on NIST P-256 and P-384 (FIPS 186-4)
+//!
+//! Points are kept in Jacobian coordinates with field elements in
+//! Montgomery form; the curves' a = -3 lets doubling skip a multiply.
+
+use crate::bigint::{self, Limbs, Modulus, MAX_LIMBS};
+
+/// A supported curve
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    P256,
+    P384,
+}
+
+struct Params {
+    p: &'static [u8],
+    n: &'static [u8],
+    b: &'static [u8],
+    gx: &'static [u8],
+    gy: &'static [u8],
+}
+
+const P256: Params = Params {
+    p: &[
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    ],
+    n: &[
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63, 0x25, 0x51,
+    ],
+    b: &[
+        0x5a, 0xc6, 0x35, 0xd8, 0xaa, 0x3a, 0x93, 0xe7, 0xb3, 0xeb, 0xbd, 0x55, 0x76, 0x98, 0x86, 0xbc,
+        0x65, 0x1d, 0x06, 0xb0, 0xcc, 0x53, 0xb0, 0xf6, 0x3b, 0xce, 0x3c, 0x3e, 0x27, 0xd2, 0x60, 0x4b,
+    ],
+    gx: &[
+        0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40, 0xf2,
+        0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2, 0x96,
+    ],
+    gy: &[
+        0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e, 0x16,
+        0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51, 0xf5,
+    ],
+};
+
+const P384: Params = Params {
+    p: &[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+    ],
+    n: &[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc7, 0x63, 0x4d, 0x81, 0xf4, 0x37, 0x2d, 0xdf,
+        0x58, 0x1a, 0x0d, 0xb2, 0x48, 0xb0, 0xa7, 0x7a, 0xec, 0xec, 0x19, 0x6a, 0xcc, 0xc5, 0x29, 0x73,
+    ],
+    b: &[
+        0xb3, 0x31, 0x2f, 0xa7, 0xe2, 0x3e, 0xe7, 0xe4, 0x98, 0x8e, 0x05, 0x6b, 0xe3, 0xf8, 0x2d, 0x19,
+        0x18, 0x1d, 0x9c, 0x6e, 0xfe, 0x81, 0x41, 0x12, 0x03, 0x14, 0x08, 0x8f, 0x50, 0x13, 0x87, 0x5a,
+        0xc6, 0x56, 0x39, 0x8d, 0x8a, 0x2e, 0xd1, 0x9d, 0x2a, 0x85, 0xc8, 0xed, 0xd3, 0xec, 0x2a, 0xef,
+    ],
+    gx: &[
+        0xaa, 0x87, 0xca, 0x22, 0xbe, 0x8b, 0x05, 0x37, 0x8e, 0xb1, 0xc7, 0x1e, 0xf3, 0x20, 0xad, 0x74,
+        0x6e, 0x1d, 0x3b, 0x62, 0x8b, 0xa7, 0x9b, 0x98, 0x59, 0xf7, 0x41, 0xe0, 0x82, 0x54, 0x2a, 0x38,
+        0x55, 0x02, 0xf2, 0x5d, 0xbf, 0x55, 0x29, 0x6c, 0x3a, 0x54, 0x5e, 0x38, 0x72, 0x76, 0x0a, 0xb7,
+    ],
+    gy: &[
+        0x36, 0x17, 0xde, 0x4a, 0x96, 0x26, 0x2c, 0x6f, 0x5d, 0x9e, 0x98, 0xbf, 0x92, 0x92, 0xdc, 0x29,
+        0xf8, 0xf4, 0x1d, 0xbd, 0x28, 0x9a, 0x14, 0x7c, 0xe9, 0xda, 0x31, 0x13, 0xb5, 0xf0, 0xb8, 0xc0,
+        0x0a, 0x60, 0xb1, 0xce, 0x1d, 0x7e, 0x81, 0x9d, 0x7a, 0x43, 0x1d, 0x7c, 0x90, 0xea, 0x0e, 0x5f,
+    ],
+};
+
+impl Curve {
+    /// Length of a field element or scalar in bytes
+    pub const fn size(self) -> usize {
+        match self {
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+        }
+    }
+
+    fn params(self) -> &'static Params {
+        match self {
+            Curve::P256 => &P256,
+            Curve::P384 => &P384,
+        }
+    }
+}
+
+/// Jacobian point (X/Z^2, Y/Z^3) in Montgomery form; Z = 0 is infinity
+#[derive(Clone, Copy)]
+struct Point {
+    x: Limbs,
+    y: Limbs,
+    z: Limbs,
+}
+
+struct Field {
+    p: Modulus,
+}
+
+impl Field {
+    fn mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        self.p.mont_mul(a, b)
+    }
+
+    fn sqr(&self, a: &Limbs) -> Limbs {
+        self.p.mont_mul(a, a)
+    }
+
+    fn add(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        self.p.add(a, b)
+    }
+
+    fn sub(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        self.p.sub(a, b)
+    }
+
+    fn is_zero(&self, a: &Limbs) -> bool {
+        bigint::is_zero(a, self.p.limbs())
+    }
+
+    fn eq(&self, a: &Limbs, b: &Limbs) -> bool {
+        a[..self.p.limbs()] == b[..self.p.limbs()]
+    }
+
+    fn double(&self, pt: &Point) -> Point {
+        if self.is_zero(&pt.z) || self.is_zero(&pt.y) {
+            return Point { z: [0; MAX_LIMBS], ..*pt };
+        }
+        // dbl-2001-b
+        let delta = self.sqr(&pt.z);
+        let gamma = self.sqr(&pt.y);
+        let beta = self.mul(&pt.x, &gamma);
+        let t = self.mul(&self.sub(&pt.x, &delta), &self.add(&pt.x, &delta));
+        let alpha = self.add(&self.add(&t, &t), &t);
+        let beta2 = self.add(&beta, &beta);
+        let beta4 = self.add(&beta2, &beta2);
+        let beta8 = self.add(&beta4, &beta4);
+        let x = self.sub(&self.sqr(&alpha), &beta8);
+        let yz = self.add(&pt.y, &pt.z);
+        let z = self.sub(&self.sub(&self.sqr(&yz), &gamma), &delta);
+        let gamma2 = self.sqr(&gamma);
+        let gamma2_2 = self.add(&gamma2, &gamma2);
+        let gamma2_4 = self.add(&gamma2_2, &gamma2_2);
+        let gamma2_8 = self.add(&gamma2_4, &gamma2_4);
+        let y = self.sub(&self.mul(&alpha, &self.sub(&beta4, &x)), &gamma2_8);
+        Point { x, y, z }
+    }
+
+    fn add_points(&self, a: &Point, b: &Point) -> Point {
+        if self.is_zero(&a.z) {
+            return *b;
+        }
+        if self.is_zero(&b.z) {
+            return *a;
+        }
+        // add-2007-bl
+        let z1z1 = self.sqr(&a.z);
+        let z2z2 = self.sqr(&b.z);
+        let u1 = self.mul(&a.x, &z2z2);
+        let u2 = self.mul(&b.x, &z1z1);
+        let s1 = self.mul(&self.mul(&a.y, &b.z), &z2z2);
+        let s2 = self.mul(&self.mul(&b.y, &a.z), &z1z1);
+        if self.eq(&u1, &u2) {
+            return if self.eq(&s1, &s2) {
+                self.double(a)
+            } else {
+                Point { z: [0; MAX_LIMBS], ..*a }
+            };
+        }
+        let h = self.sub(&u2, &u1);
+        let h2 = self.add(&h, &h);
+        let i = self.sqr(&h2);
+        let j = self.mul(&h, &i);
+        let ds = self.sub(&s2, &s1);
+        let r = self.add(&ds, &ds);
+        let v = self.mul(&u1, &i);
+        let x = self.sub(&self.sub(&self.sqr(&r), &j), &self.add(&v, &v));
+        let s1j = self.mul(&s1, &j);
+        let y = self.sub(&self.mul(&r, &self.sub(&v, &x)), &self.add(&s1j, &s1j));
+        let zz = self.sqr(&self.add(&a.z, &b.z));
+        let z = self.mul(&self.sub(&self.sub(&zz, &z1z1), &z2z2), &h);
+        Point { x, y, z }
+    }
+
+    /// Affine x coordinate, out of Montgomery form
+    fn affine_x(&self, pt: &Point) -> Limbs {
+        let zinv = self.p.invert_prime(&pt.z);
+        self.p.from_mont(&self.mul(&pt.x, &self.sqr(&zinv)))
+    }
+}
+
+/// Verify a signature (r, s as big-endian integers) over a message digest
+/// with an uncompressed public key (`04 || X || Y`)
+pub fn verify(curve: Curve, public_key: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> bool {
+    let params = curve.params();
+    let size = curve.size();
+    let len = size / 8;
+    let (field, order) = match (Modulus::new(params.p), Modulus::new(params.n)) {
+        (Some(p), Some(n)) => (Field { p }, n),
+        _ => return false,
+    };
+
+    // Public key: on the curve, y^2 = x^3 - 3x + b
+    if public_key.len() != 1 + 2 * size || public_key[0] != 4 {
+        return false;
+    }
+    let (qx, qy) = match (
+        bigint::from_be_bytes(&public_key[1..1 + size], len),
+        bigint::from_be_bytes(&public_key[1 + size..], len),
+    ) {
+        (Some(x), Some(y)) if field.p.contains(&x) && field.p.contains(&y) => (x, y),
+        _ => return false,
+    };
+    let (qx, qy) = (field.p.to_mont(&qx), field.p.to_mont(&qy));
+    let b = match bigint::from_be_bytes(params.b, len) {
+        Some(b) => field.p.to_mont(&b),
+        None => return false,
+    };
+    let x3 = field.mul(&field.sqr(&qx), &qx);
+    let three_x = field.add(&field.add(&qx, &qx), &qx);
+    if !field.eq(&field.sqr(&qy), &field.add(&field.sub(&x3, &three_x), &b)) {
+        return false;
+    }
+
+    // 0 < r, s < n
+    let (r, s) = match (bigint::from_be_bytes(r, len), bigint::from_be_bytes(s, len)) {
+        (Some(r), Some(s)) => (r, s),
+        _ => return false,
+    };
+    for v in [&r, &s] {
+        if bigint::is_zero(v, len) || !order.contains(v) {
+            return false;
+        }
+    }
+
+    // e = leftmost bits of the digest, reduced mod n
+    let mut e_bytes = [0u8; 48];
+    let take = digest.len().min(size);
+    e_bytes[size - take..size].copy_from_slice(&digest[..take]);
+    let e = match bigint::from_be_bytes(&e_bytes[..size], len) {
+        Some(e) => order.reduce_once(&e),
+        None => return false,
+    };
+
+    // u1 = e / s, u2 = r / s; w is in Montgomery form so the products
+    // come out plain
+    let w = order.invert_prime(&order.to_mont(&s));
+    let u1 = order.mont_mul(&w, &e);
+    let u2 = order.mont_mul(&w, &r);
+
+    // u1 G + u2 Q, both scalars at once
+    let (gx, gy) = match (bigint::from_be_bytes(params.gx, len), bigint::from_be_bytes(params.gy, len)) {
+        (Some(x), Some(y)) => (field.p.to_mont(&x), field.p.to_mont(&y)),
+        _ => return false,
+    };
+    let one = field.p.one();
+    let g = Point { x: gx, y: gy, z: one };
+    let q = Point { x: qx, y: qy, z: one };
+    let gq = field.add_points(&g, &q);
+    let mut acc = Point { x: one, y: one, z: [0; MAX_LIMBS] };
+    for bit in (0..64 * len).rev() {
+        acc = field.double(&acc);
+        let b1 = (u1[bit / 64] >> (bit % 64)) & 1 == 1;
+        let b2 = (u2[bit / 64] >> (bit % 64)) & 1 == 1;
+        acc = match (b1, b2) {
+            (true, true) => field.add_points(&acc, &gq),
+            (true, false) => field.add_points(&acc, &g),
+            (false, true) => field.add_points(&acc, &q),
+            (false, false) => acc,
+        };
+    }
+    if field.is_zero(&acc.z) {
+        return false;
+    }
+
+    // x mod n == r (p < 2n for both curves)
+    let x = order.reduce_once(&field.affine_x(&acc));
+    x[..len] == r[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::HashAlg;
+    use crate::unhex;
+
+    #[test]
+    fn test_verify_p256() {
+        let q = unhex("04e999d6efcba0af1d81f1cce4d05c5c2d100e45113d00ad8302344414f71d9befbc66031ee0cdd0a770a4a7066cb1bc0c32e632955c3130966e5fab325e84057b");
+        let r = unhex("3926cda349ffbd6a02aff5cc8408bdaa4b4912f13dd49b7231e9d568058ac92b");
+        let s = unhex("ec1fc28ecb35d54aa51b257e0f7c3ce7a64b6f30bd2149cd7f04076cf0d2e4e3");
+        let digest = HashAlg::Sha256.digest(b"watos");
+        assert!(verify(Curve::P256, &q, &digest, &r, &s));
+        assert!(!verify(Curve::P256, &q, &digest, &s, &r));
+        assert!(!verify(Curve::P256, &q, &HashAlg::Sha256.digest(b"watos!"), &r, &s));
+
+        let mut off_curve = q.clone();
+        off_curve[64] ^= 1;
+        assert!(!verify(Curve::P256, &off_curve, &digest, &r, &s));
+    }
+
+    #[test]
+    fn test_verify_p384() {
+        let q = unhex("04bd1483f9002e74244f7c6ac6c7c8a192259bcaf4ce4a664faf4e5952bb07fcf7ed634db650a4373857207367c90321ee67ba0a7244aea48e6e0a85967845a9294f750eeb4c5af3c87d8223ffa16a6a25e67eee4f19873bab8287483c20f6d8ad");
+        let r = unhex("2724aef0850c491a1ed464cbb09eec2ead1b7a4095b3b0cd24049dd1e7a7fdeba35ed41aa75f6810e02d31f5a35ec512");
+        let s = unhex("fad72d2e960ecf1e43040ca91a38d1443e26517d9061baa51f0a84c2d159b7c4ff0e245c0c233faa518c85acf086498f");
+        let digest = HashAlg::Sha384.digest(b"watos");
+        assert!(verify(Curve::P384, &q, &digest, &r, &s));
+        assert!(!verify(Curve::P384, &q, &HashAlg::Sha384.digest(b"watos?"), &r, &s));
+    }
+}
@@ -0,0 +1,90 @@
+//! Choosing a hash function at run time
+//!
+//! Signature schemes name the hash they use; [`HashAlg`] lets RSA and ECDSA
+//! verification and certificate checks take it as a value.
+
+use crate::sha256::Sha256;
+use crate::sha512::Sha512;
+
+/// Longest digest of any [`HashAlg`]
+pub const MAX_DIGEST_LEN: usize = 64;
+
+/// A supported hash function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlg {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlg {
+    /// Digest length in bytes
+    pub const fn digest_len(self) -> usize {
+        match self {
+            HashAlg::Sha256 => 32,
+            HashAlg::Sha384 => 48,
+            HashAlg::Sha512 => 64,
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlg::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlg::Sha384 => Hasher::Sha512(Sha512::new_384(), 48),
+            HashAlg::Sha512 => Hasher::Sha512(Sha512::new(), 64),
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> Digest {
+        let mut h = self.hasher();
+        h.update(data);
+        h.finish()
+    }
+}
+
+/// Incremental hasher for a [`HashAlg`]
+#[derive(Clone)]
+pub enum Hasher {
+    Sha256(Sha256),
+    /// SHA-512 state and how much of it is the digest
+    Sha512(Sha512, usize),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h, _) => h.update(data),
+        }
+    }
+
+    pub fn finish(self) -> Digest {
+        let mut digest = Digest { bytes: [0; MAX_DIGEST_LEN], len: 0 };
+        match self {
+            Hasher::Sha256(h) => {
+                digest.bytes[..32].copy_from_slice(&h.finish());
+                digest.len = 32;
+            }
+            Hasher::Sha512(h, len) => {
+                digest.bytes = h.finish();
+                digest.len = len;
+            }
+        }
+        digest
+    }
+}
+
+/// A digest of up to [`MAX_DIGEST_LEN`] bytes
+#[derive(Clone, Copy)]
+pub struct Digest {
+    bytes: [u8; MAX_DIGEST_LEN],
+    len: usize,
+}
+
+impl core::ops::Deref for Digest {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
@@ -0,0 +1,91 @@
+//! WATOS Crypto Module
+//!
+//! Primitives for keeping secrets, implemented from their specifications
+//! with no dependencies:
+//!
+//! - SHA-256, SHA-384 and SHA-512 (FIPS 180-4), HMAC-SHA256 (RFC 2104)
+//!   and HKDF-SHA256 (RFC 5869)
+//! - PBKDF2-HMAC-SHA256 (RFC 8018), for deriving keys from passwords
+//! - ChaCha20 (RFC 8439), a stream cipher, and AES-128/256 (FIPS 197)
+//! - ChaCha20-Poly1305 (RFC 8439) and AES-GCM (SP 800-38D) AEADs
+//! - X25519 key agreement (RFC 7748)
+//! - RSA (RFC 8017) and ECDSA P-256/P-384 (FIPS 186-4) signature
+//!   verification, over the modular arithmetic in [`bigint`]
+//!
+//! None of this is hardened against side channels beyond comparing MACs in
+//! constant time with [`ct_eq`]; AES uses table lookups. There is no random
+//! number source here; callers supply keys, salts and nonces.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod aead;
+pub mod aes;
+pub mod bigint;
+pub mod chacha20;
+pub mod ecdsa;
+pub mod hash;
+pub mod poly1305;
+pub mod rsa;
+pub mod sha256;
+pub mod sha512;
+pub mod x25519;
+
+pub use aead::{AesGcm, ChaCha20Poly1305};
+pub use chacha20::ChaCha20;
+pub use hash::HashAlg;
+pub use sha256::{hkdf_expand, hkdf_extract, hmac_sha256, pbkdf2_hmac_sha256, HmacSha256, Sha256};
+pub use sha512::Sha512;
+
+/// Compare two byte strings without stopping at the first difference
+///
+/// Slices of different lengths compare unequal (the lengths themselves are
+/// not secret).
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Overwrite a buffer with zeros in a way the compiler won't drop
+pub fn wipe(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        // Safe: b is a valid, aligned &mut u8
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> std::string::String {
+    bytes.iter().map(|b| std::format!("{:02x}", b)).collect()
+}
+
+/// Decode hex test vectors, ignoring whitespace
+#[cfg(test)]
+fn unhex(s: &str) -> std::vec::Vec<u8> {
+    let digits: std::vec::Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(b"abc", b"abc"));
+        assert!(!ct_eq(b"abc", b"abd"));
+        assert!(!ct_eq(b"abc", b"ab"));
+
+        let mut secret = *b"hunter2";
+        wipe(&mut secret);
+        assert_eq!(secret, [0; 7]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Poly1305 one-time authenticator (RFC 8439)
+//!
+//! 26-bit limbs, after poly1305-donna. A key must authenticate only one
+//! message; ChaCha20-Poly1305 derives a fresh one per nonce.
+
+/// Tag length in bytes
+pub const TAG_LEN: usize = 16;
+
+const MASK: u32 = 0x3ff_ffff;
+
+pub struct Poly1305 {
+    r: [u32; 5],
+    /// r * 5, for the wrap-around terms
+    s: [u32; 4],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes(b[..4].try_into().unwrap())
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let r = [
+            le32(&key[0..]) & 0x3ff_ffff,
+            (le32(&key[3..]) >> 2) & 0x3ff_ff03,
+            (le32(&key[6..]) >> 4) & 0x3ff_c0ff,
+            (le32(&key[9..]) >> 6) & 0x3f0_3fff,
+            (le32(&key[12..]) >> 8) & 0x00f_ffff,
+        ];
+        let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+        let pad = [le32(&key[16..]), le32(&key[20..]), le32(&key[24..]), le32(&key[28..])];
+        Poly1305 { r, s, h: [0; 5], pad, buf: [0; 16], buf_len: 0 }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len > 0 {
+            let take = (16 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 16 {
+                return;
+            }
+            let block = self.buf;
+            self.block(&block, 1 << 24);
+            self.buf_len = 0;
+        }
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            self.block(block.try_into().unwrap(), 1 << 24);
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    /// Feed zeros up to the next 16-byte boundary (as ChaCha20-Poly1305 does
+    /// between its fields)
+    pub fn pad16(&mut self) {
+        if self.buf_len > 0 {
+            let zeros = [0u8; 16];
+            self.update(&zeros[..16 - self.buf_len]);
+        }
+    }
+
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(u64::from);
+        let [s1, s2, s3, s4] = self.s.map(u64::from);
+        let h = &mut self.h;
+
+        h[0] += le32(&m[0..]) & MASK;
+        h[1] += (le32(&m[3..]) >> 2) & MASK;
+        h[2] += (le32(&m[6..]) >> 4) & MASK;
+        h[3] += (le32(&m[9..]) >> 6) & MASK;
+        h[4] += (le32(&m[12..]) >> 8) | hibit;
+        let [h0, h1, h2, h3, h4] = h.map(u64::from);
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        h[0] = d0 as u32 & MASK;
+        d2 += d1 >> 26;
+        h[1] = d1 as u32 & MASK;
+        d3 += d2 >> 26;
+        h[2] = d2 as u32 & MASK;
+        d4 += d3 >> 26;
+        h[3] = d3 as u32 & MASK;
+        let c = (d4 >> 26) as u32;
+        h[4] = d4 as u32 & MASK;
+        h[0] += c * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    pub fn finish(mut self) -> [u8; TAG_LEN] {
+        if self.buf_len > 0 {
+            let mut last = [0u8; 16];
+            last[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            last[self.buf_len] = 1;
+            self.block(&last, 0);
+        }
+
+        // Fully carry h
+        let mut h = self.h;
+        let mut c;
+        c = h[1] >> 26;
+        h[1] &= MASK;
+        h[2] += c;
+        c = h[2] >> 26;
+        h[2] &= MASK;
+        h[3] += c;
+        c = h[3] >> 26;
+        h[3] &= MASK;
+        h[4] += c;
+        c = h[4] >> 26;
+        h[4] &= MASK;
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= MASK;
+        h[1] += c;
+
+        // g = h + 5 - 2^130; use it if it didn't go negative
+        let mut g = [0u32; 5];
+        g[0] = h[0] + 5;
+        c = g[0] >> 26;
+        g[0] &= MASK;
+        for i in 1..4 {
+            g[i] = h[i] + c;
+            c = g[i] >> 26;
+            g[i] &= MASK;
+        }
+        g[4] = (h[4] + c).wrapping_sub(1 << 26);
+        let use_g = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !use_g) | (g[i] & use_g);
+        }
+
+        // h mod 2^128, plus the pad
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0u8; TAG_LEN];
+        let mut carry = 0u64;
+        for i in 0..4 {
+            let f = words[i] as u64 + self.pad[i] as u64 + carry;
+            tag[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+            carry = f >> 32;
+        }
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn test_poly1305() {
+        // RFC 8439 section 2.5.2
+        let key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5, 0x06, 0xa8,
+            0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf, 0x41, 0x49, 0xf5, 0x1b,
+        ];
+        let mut mac = Poly1305::new(&key);
+        mac.update(b"Cryptographic Forum ");
+        mac.update(b"Research Group");
+        assert_eq!(hex(&mac.finish()).as_str(), "a8061dc1305136c6c22b8baf0c0127a9");
+    }
+}
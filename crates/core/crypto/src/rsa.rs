@@ -0,0 +1,185 @@
+//! RSA signature verification (RFC 8017)
+//!
+//! PKCS #1 v1.5 and PSS with MGF1, for checking certificates and TLS
+//! handshake signatures. There is no signing and no encryption.
+
+use crate::bigint::{self, Modulus, MAX_BITS};
+use crate::ct_eq;
+use crate::hash::{HashAlg, MAX_DIGEST_LEN};
+
+/// Smallest modulus accepted, in bits
+pub const MIN_BITS: usize = 1024;
+
+/// A public key: modulus and exponent
+pub struct PublicKey<'a> {
+    n: Modulus,
+    e: &'a [u8],
+}
+
+impl<'a> PublicKey<'a> {
+    /// Build a key from big-endian modulus and exponent (as found in a
+    /// certificate); None if the modulus is out of range
+    pub fn new(n: &[u8], e: &'a [u8]) -> Option<Self> {
+        let n = Modulus::new(n)?;
+        if n.bits() < MIN_BITS || e.iter().all(|&b| b == 0) {
+            return None;
+        }
+        Some(PublicKey { n, e })
+    }
+
+    /// Modulus length in bytes
+    pub fn size(&self) -> usize {
+        self.n.bits().div_ceil(8)
+    }
+
+    /// s^e mod n into `em` (`size()` bytes); false if s isn't a valid
+    /// signature representative
+    fn encrypt(&self, sig: &[u8], em: &mut [u8]) -> bool {
+        if sig.len() != self.size() {
+            return false;
+        }
+        let s = match bigint::from_be_bytes(sig, self.n.limbs()) {
+            Some(s) if self.n.contains(&s) => s,
+            _ => return false,
+        };
+        let m = self.n.from_mont(&self.n.pow(&self.n.to_mont(&s), self.e));
+        bigint::to_be_bytes(&m, em);
+        true
+    }
+
+    /// Verify an RSASSA-PKCS1-v1_5 signature over a message digest
+    pub fn verify_pkcs1(&self, hash: HashAlg, digest: &[u8], sig: &[u8]) -> bool {
+        let mut em_buf = [0u8; MAX_BITS / 8];
+        let k = self.size();
+        let em = &mut em_buf[..k];
+        if !self.encrypt(sig, em) {
+            return false;
+        }
+
+        // EM = 00 01 FF..FF 00 DigestInfo digest
+        let prefix = digest_info_prefix(hash);
+        let t_len = prefix.len() + hash.digest_len();
+        if digest.len() != hash.digest_len() || k < t_len + 11 {
+            return false;
+        }
+        let ps_end = k - t_len - 1;
+        em[0] == 0
+            && em[1] == 1
+            && em[2..ps_end].iter().all(|&b| b == 0xff)
+            && em[ps_end] == 0
+            && ct_eq(&em[ps_end + 1..k - hash.digest_len()], prefix)
+            && ct_eq(&em[k - hash.digest_len()..], digest)
+    }
+
+    /// Verify an RSASSA-PSS signature over a message digest, with MGF1
+    /// using the same hash and a salt as long as the digest (as TLS 1.3
+    /// requires)
+    pub fn verify_pss(&self, hash: HashAlg, digest: &[u8], sig: &[u8]) -> bool {
+        let mut em_buf = [0u8; MAX_BITS / 8];
+        let k = self.size();
+        if !self.encrypt(sig, &mut em_buf[..k]) {
+            return false;
+        }
+
+        // EM is emBits = modBits - 1 bits long, so may be a byte shorter
+        let em_bits = self.n.bits() - 1;
+        let em_len = em_bits.div_ceil(8);
+        if em_len < k && em_buf[0] != 0 {
+            return false;
+        }
+        let em = &mut em_buf[k - em_len..k];
+
+        let h_len = hash.digest_len();
+        let s_len = h_len;
+        if digest.len() != h_len || em_len < h_len + s_len + 2 || em[em_len - 1] != 0xbc {
+            return false;
+        }
+        let db_len = em_len - h_len - 1;
+        let top_mask = 0xffu8 >> (8 * em_len - em_bits);
+        if em[0] & !top_mask != 0 {
+            return false;
+        }
+
+        let mut h = [0u8; MAX_DIGEST_LEN];
+        h[..h_len].copy_from_slice(&em[db_len..db_len + h_len]);
+        let db = &mut em[..db_len];
+        mgf1_xor(hash, &h[..h_len], db);
+        db[0] &= top_mask;
+
+        // DB = 00..00 01 salt
+        let ps_len = db_len - s_len - 1;
+        if db[..ps_len].iter().any(|&b| b != 0) || db[ps_len] != 1 {
+            return false;
+        }
+        let salt = &db[ps_len + 1..];
+
+        let mut hasher = hash.hasher();
+        hasher.update(&[0u8; 8]);
+        hasher.update(digest);
+        hasher.update(salt);
+        ct_eq(&hasher.finish(), &h[..h_len])
+    }
+}
+
+/// DER of DigestInfo up to the digest itself (RFC 8017 section 9.2)
+fn digest_info_prefix(hash: HashAlg) -> &'static [u8] {
+    match hash {
+        HashAlg::Sha256 => &[
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+            0x05, 0x00, 0x04, 0x20,
+        ],
+        HashAlg::Sha384 => &[
+            0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02,
+            0x05, 0x00, 0x04, 0x30,
+        ],
+        HashAlg::Sha512 => &[
+            0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+            0x05, 0x00, 0x04, 0x40,
+        ],
+    }
+}
+
+/// XOR MGF1(seed) into `out`
+fn mgf1_xor(hash: HashAlg, seed: &[u8], out: &mut [u8]) {
+    for (counter, chunk) in out.chunks_mut(hash.digest_len()).enumerate() {
+        let mut hasher = hash.hasher();
+        hasher.update(seed);
+        hasher.update(&(counter as u32).to_be_bytes());
+        for (b, m) in chunk.iter_mut().zip(hasher.finish().iter()) {
+            *b ^= m;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unhex;
+
+    const N: &str = "984a09c8db527628a2d6efa9033f12b9c2db873bf37162c116eee8d4ac8015954b7f03f8756c10c66de77bd8cf81a0bd1e5ed0e1d44fa83ad178cab0711ccc73e5706db0e05a916e5ab9fc6897770dc938dfc1be08c47d242e4ffbb8b850dd24423aafd5a976f116d356a7c0e3a63a3a74924f616f4ef675f0ab6f930a054b91c410d081d560b32a9c69caeede11fea1a160678b201afd5e91a4d75887b766634f54d9da15ae707f54f308f1dc8d3c59c6c2d4b4aae90f23ac84fc0c5130528e084edbe6b291a1143d1bb05841930cd255f239024685e89421a591761a5247d0e063b3746350cca40ea32df77b7cff4cc9cc323fff81159ebec88308b974c8c9";
+
+    #[test]
+    fn test_verify() {
+        let n = unhex(N);
+        let key = PublicKey::new(&n, &[1, 0, 1]).unwrap();
+        assert_eq!(key.size(), 256);
+        let digest = HashAlg::Sha256.digest(b"watos");
+
+        let mut sig = unhex(
+            "9287562a3607e3e25b4a56fb4ec5a88b4cdf07056f2e092b4c6db5442b2c77935818f1dce17960b7d8185d1fd67e450129f2b2479f67fa8c33bea67ec58c2c6c98043128b2ac265d3970795774526152912d63d7acefbc4c45435a02615099685cd17205f1b2d408c8cbdcfc28573ed0462a483b7b3a1d85ac05c5e78640bf2f29296d835f2bbba17574853037910d1dfb89b0cdda8faa2456bad9a308c9fd8f99a39423a58206e3b537788fad923a98dbf593d5a0a86741e40fc47ba33db2ef52c3b962e363d436df1e56dc4c28e1d17b3636f953b14a9d1ee28435388048b655dc4ad74bcae9c0b999b49beae037f225ecda4b5bca97423d142ac4b8980c38",
+        );
+        assert!(key.verify_pkcs1(HashAlg::Sha256, &digest, &sig));
+        assert!(!key.verify_pss(HashAlg::Sha256, &digest, &sig));
+        sig[100] ^= 1;
+        assert!(!key.verify_pkcs1(HashAlg::Sha256, &digest, &sig));
+
+        let sig = unhex(
+            "571ceb6e5803308d5e8ebb437337dc1ddb4e58357fbe1f13513e4c5ecb28c76972cf4d13b8d78758b4ec9707721569e4de75bf89550b379e1428e4b05e55aa33083360f557dd43b9c0166ebefc16bae7effed0cb69df840a6629144751448100aef9c4601feacc8b4ae73bc59d1540bb06b76faa6f2f8aa7c9bf52e90cda76e6071b1de0ea7920431108a5754bfa06392c220c8d736fe67418c4b065138aed160e47e91bb9c9692b46811bbbb29b6db1801c18e6981817b137cb99e67d46ee6ac8c8e2621873e8ca196fec91f0ce9f96c62f4e3e85dc191005850291fa5d30b0d2d619da9e5af1ff363e256fbf80cbb845d4d3ac3fb6b961d6069f405c179b52",
+        );
+        assert!(key.verify_pss(HashAlg::Sha256, &digest, &sig));
+        let other = HashAlg::Sha256.digest(b"watos!");
+        assert!(!key.verify_pss(HashAlg::Sha256, &other, &sig));
+
+        assert!(PublicKey::new(&n[..64], &[3]).is_none());
+    }
+}
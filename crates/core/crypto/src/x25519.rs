@@ -0,0 +1,216 @@
+//! X25519 Diffie-Hellman (RFC 7748)
+//!
+//! Field elements mod 2^255 - 19 as five 51-bit limbs; scalar
+//! multiplication with the constant-time Montgomery ladder.
+
+/// Key length in bytes (public, private and shared)
+pub const KEY_LEN: usize = 32;
+
+/// The base point, u = 9
+pub const BASEPOINT: [u8; KEY_LEN] = {
+    let mut b = [0u8; KEY_LEN];
+    b[0] = 9;
+    b
+};
+
+const MASK51: u64 = (1 << 51) - 1;
+
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let load = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+        Fe([
+            load(0) & MASK51,
+            (load(6) >> 3) & MASK51,
+            (load(12) >> 6) & MASK51,
+            (load(19) >> 1) & MASK51,
+            (load(24) >> 12) & MASK51,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        // Reduce fully: carry, then subtract p if h >= p
+        let mut h = self.carry().carry().0;
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK51;
+        }
+        h[4] &= MASK51;
+
+        let mut out = [0u8; 32];
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12),
+        ];
+        for (chunk, w) in out.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&w.to_le_bytes());
+        }
+        out
+    }
+
+    fn carry(self) -> Fe {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK51;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK51;
+        Fe(h)
+    }
+
+    fn add(self, o: Fe) -> Fe {
+        Fe(core::array::from_fn(|i| self.0[i] + o.0[i])).carry()
+    }
+
+    /// self - o, adding 2p first so limbs never go negative
+    fn sub(self, o: Fe) -> Fe {
+        const TWO_P: [u64; 5] = [0xfffffffffffda, 0xffffffffffffe, 0xffffffffffffe, 0xffffffffffffe, 0xffffffffffffe];
+        Fe(core::array::from_fn(|i| self.0[i] + TWO_P[i] - o.0[i])).carry()
+    }
+
+    fn mul(self, o: Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = o.0.map(u128::from);
+        let b19: [u128; 5] = core::array::from_fn(|i| b[i] * 19);
+
+        let t = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        reduce_wide(t)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn mul_small(self, n: u64) -> Fe {
+        reduce_wide(self.0.map(|l| l as u128 * n as u128))
+    }
+
+    /// self^(p - 2) = 1/self
+    fn invert(self) -> Fe {
+        // p - 2 = 2^255 - 21; walk its bits from the top
+        let mut result = Fe::ONE;
+        for bit in (0..255).rev() {
+            result = result.square();
+            let set = bit >= 5 || (0b01011 >> bit) & 1 == 1;
+            if set {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    /// Swap a and b if `swap` is 1, without branching on it
+    fn cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = 0u64.wrapping_sub(swap);
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+}
+
+fn reduce_wide(t: [u128; 5]) -> Fe {
+    let mut h = [0u64; 5];
+    let mut carry = 0u128;
+    for i in 0..5 {
+        let v = t[i] + carry;
+        h[i] = v as u64 & MASK51;
+        carry = v >> 51;
+    }
+    let mut h0 = h[0] as u128 + carry * 19;
+    h[0] = h0 as u64 & MASK51;
+    h0 >>= 51;
+    h[1] += h0 as u64;
+    Fe(h)
+}
+
+/// Multiply point `u` by `scalar` (clamped as RFC 7748 requires)
+pub fn x25519(scalar: &[u8; KEY_LEN], u: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let mut u_bytes = *u;
+    u_bytes[31] &= 127;
+    let x1 = Fe::from_bytes(&u_bytes);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swap = 0u64;
+
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+
+    crate::wipe(&mut k);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// Public key for a private key
+pub fn public_key(private: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    x25519(private, &BASEPOINT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hex, unhex};
+
+    #[test]
+    fn test_x25519() {
+        // RFC 7748 section 5.2
+        let scalar = unhex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = unhex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        let out = x25519(&scalar.try_into().unwrap(), &u.try_into().unwrap());
+        assert_eq!(hex(&out).as_str(), "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552");
+
+        // RFC 7748 section 6.1
+        let alice = unhex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = unhex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let (alice, bob): ([u8; 32], [u8; 32]) = (alice.try_into().unwrap(), bob.try_into().unwrap());
+        let alice_pub = public_key(&alice);
+        assert_eq!(hex(&alice_pub).as_str(), "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a");
+        let shared = x25519(&bob, &alice_pub);
+        assert_eq!(shared, x25519(&alice, &public_key(&bob)));
+        assert_eq!(hex(&shared).as_str(), "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    }
+}
@@ -0,0 +1,466 @@
+//! Heap Debugging Allocator
+//!
+//! `DebugHeap` wraps a `LockedHeap` and checks every allocation:
+//!
+//! - Each block carries a header (magic, size, sequence number, call site)
+//!   and a tail canary; a bad header on free is reported as a double free or
+//!   invalid free, a damaged canary as a buffer overflow.
+//! - New memory is filled with `ALLOC_POISON` and freed memory with
+//!   `FREE_POISON`, so uninitialized reads and stale pointers stand out.
+//! - Freed blocks sit in a quarantine before going back to the heap; when a
+//!   block leaves the quarantine its poison is verified, catching writes
+//!   through dangling pointers (use-after-free).
+//! - Live blocks are kept on a list so an outstanding-allocation report can
+//!   be produced at any time (`snapshot`).
+//!
+//! Call sites are the first return addresses found by walking the RBP frame
+//! chain, so they are only meaningful when the kernel is built with
+//! `-C force-frame-pointers=yes`.
+//!
+//! Problems are reported through the function set with `set_reporter`. The
+//! allocator lock is held while reporting, so the reporter must not allocate.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::ops::Deref;
+use core::ptr;
+use linked_list_allocator::LockedHeap;
+use spin::Mutex;
+
+/// Number of return addresses recorded per allocation
+pub const BACKTRACE_DEPTH: usize = 4;
+
+/// Freed blocks held back from reuse
+const QUARANTINE_LEN: usize = 64;
+
+/// Fill byte for freshly allocated memory
+pub const ALLOC_POISON: u8 = 0xAA;
+/// Fill byte for freed memory
+pub const FREE_POISON: u8 = 0xDD;
+/// Fill byte for the guard area after each block
+const CANARY: u8 = 0xCB;
+const TAIL_SIZE: usize = 16;
+
+const LIVE_MAGIC: u64 = 0xA110_CA7E_D0C0_FFEE;
+const FREED_MAGIC: u64 = 0xF4EE_D0DE_AD0B_10C5;
+
+/// Only frame pointers inside the identity-mapped kernel region are followed
+const FRAME_MIN: u64 = 0x1000;
+const FRAME_MAX: u64 = 0x80_0000;
+
+/// Bookkeeping placed directly in front of every user block
+#[repr(C)]
+struct Header {
+    magic: u64,
+    size: usize,
+    /// Distance from the start of the underlying block to the user pointer
+    pad: usize,
+    align: usize,
+    seq: u64,
+    prev: *mut Header,
+    next: *mut Header,
+    callers: [u64; BACKTRACE_DEPTH],
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<Header>();
+
+/// One outstanding allocation, as returned by `DebugHeap::snapshot`
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationInfo {
+    /// Allocation sequence number (order of allocation)
+    pub seq: u64,
+    pub addr: usize,
+    pub size: usize,
+    /// Return addresses, innermost first (0 = not recorded)
+    pub callers: [u64; BACKTRACE_DEPTH],
+}
+
+/// Error counters kept by the debug heap
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugStats {
+    pub live_allocations: usize,
+    pub live_bytes: usize,
+    pub double_frees: usize,
+    pub invalid_frees: usize,
+    pub overflows: usize,
+    pub use_after_free: usize,
+}
+
+struct DebugState {
+    /// Most recent live allocation
+    head: *mut Header,
+    next_seq: u64,
+    /// Freed blocks not yet returned to the heap
+    quarantine: [*mut Header; QUARANTINE_LEN],
+    quarantine_next: usize,
+    stats: DebugStats,
+}
+
+// The raw pointers all point into the heap owned by the allocator
+unsafe impl Send for DebugState {}
+
+/// Reporter for heap errors (e.g. the serial port)
+static mut REPORTER: Option<fn(&str)> = None;
+
+/// Set the function that receives heap error messages
+pub fn set_reporter(reporter: fn(&str)) {
+    unsafe { REPORTER = Some(reporter); }
+}
+
+/// Fixed-size formatter, so reports never touch the heap
+struct MessageBuf {
+    buf: [u8; 160],
+    len: usize,
+}
+
+impl Write for MessageBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+fn report(args: fmt::Arguments) {
+    let mut msg = MessageBuf { buf: [0; 160], len: 0 };
+    let _ = msg.write_fmt(args);
+    let _ = msg.write_str("\r\n");
+    if let Some(reporter) = unsafe { REPORTER } {
+        reporter(core::str::from_utf8(&msg.buf[..msg.len]).unwrap_or("[HEAP] ?\r\n"));
+    }
+}
+
+/// Walk the frame-pointer chain and collect return addresses
+#[inline(always)]
+fn backtrace() -> [u64; BACKTRACE_DEPTH] {
+    let mut callers = [0u64; BACKTRACE_DEPTH];
+    let mut frame: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags));
+    }
+    for slot in callers.iter_mut() {
+        if !(FRAME_MIN..FRAME_MAX).contains(&frame) || !frame.is_multiple_of(8) {
+            break;
+        }
+        unsafe {
+            *slot = *((frame + 8) as *const u64);
+            frame = *(frame as *const u64);
+        }
+    }
+    callers
+}
+
+/// Heap allocator with poisoning, canaries, quarantine and leak tracking
+pub struct DebugHeap {
+    heap: LockedHeap,
+    state: Mutex<DebugState>,
+}
+
+impl DebugHeap {
+    /// Create an empty debug heap; initialize it through `lock().init(..)`
+    pub const fn empty() -> Self {
+        DebugHeap {
+            heap: LockedHeap::empty(),
+            state: Mutex::new(DebugState {
+                head: ptr::null_mut(),
+                next_seq: 0,
+                quarantine: [ptr::null_mut(); QUARANTINE_LEN],
+                quarantine_next: 0,
+                stats: DebugStats {
+                    live_allocations: 0,
+                    live_bytes: 0,
+                    double_frees: 0,
+                    invalid_frees: 0,
+                    overflows: 0,
+                    use_after_free: 0,
+                },
+            }),
+        }
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> DebugStats {
+        self.state.lock().stats
+    }
+
+    /// Copy outstanding allocations (newest first) into `out`
+    ///
+    /// Only fills the capacity `out` already has, so the heap is not touched
+    /// while its lock is held; reserve `stats().live_allocations` first.
+    pub fn snapshot(&self, out: &mut alloc::vec::Vec<AllocationInfo>) {
+        let state = self.state.lock();
+        let mut node = state.head;
+        while !node.is_null() && out.len() < out.capacity() {
+            unsafe {
+                out.push(AllocationInfo {
+                    seq: (*node).seq,
+                    addr: node as usize + HEADER_SIZE,
+                    size: (*node).size,
+                    callers: (*node).callers,
+                });
+                node = (*node).next;
+            }
+        }
+    }
+
+    /// Check the header and canary of every live allocation
+    ///
+    /// Returns the number of damaged blocks (each is also reported).
+    pub fn check(&self) -> usize {
+        let mut state = self.state.lock();
+        let mut damaged = 0;
+        let mut node = state.head;
+        while !node.is_null() {
+            unsafe {
+                if (*node).magic != LIVE_MAGIC || !tail_intact(node) {
+                    report(format_args!(
+                        "[HEAP] corrupted block #{} at {:#x} ({} bytes)",
+                        (*node).seq,
+                        node as usize + HEADER_SIZE,
+                        (*node).size
+                    ));
+                    damaged += 1;
+                    if (*node).magic != LIVE_MAGIC {
+                        // The list cannot be trusted past a bad header
+                        break;
+                    }
+                }
+                node = (*node).next;
+            }
+        }
+        state.stats.overflows += damaged;
+        damaged
+    }
+
+    /// Give a quarantined block back to the heap, verifying its poison
+    unsafe fn release(&self, state: &mut DebugState, header: *mut Header) {
+        let user = (header as *mut u8).add(HEADER_SIZE);
+        let size = (*header).size;
+        if let Some(offset) = (0..size).find(|&i| *user.add(i) != FREE_POISON) {
+            state.stats.use_after_free += 1;
+            report(format_args!(
+                "[HEAP] use after free: block #{} at {:#x} written at +{} after free",
+                (*header).seq,
+                user as usize,
+                offset
+            ));
+        }
+        let pad = (*header).pad;
+        let outer = Layout::from_size_align_unchecked(pad + size + TAIL_SIZE, (*header).align);
+        self.heap.dealloc(user.sub(pad), outer);
+    }
+}
+
+impl Deref for DebugHeap {
+    type Target = LockedHeap;
+
+    fn deref(&self) -> &LockedHeap {
+        &self.heap
+    }
+}
+
+/// Is the canary after a block intact?
+unsafe fn tail_intact(header: *mut Header) -> bool {
+    let tail = (header as *mut u8).add(HEADER_SIZE + (*header).size);
+    (0..TAIL_SIZE).all(|i| *tail.add(i) == CANARY)
+}
+
+/// Offset of the user pointer within the underlying block
+fn user_offset(align: usize) -> usize {
+    HEADER_SIZE.div_ceil(align) * align
+}
+
+unsafe impl GlobalAlloc for DebugHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let align = layout.align().max(core::mem::align_of::<Header>());
+        let pad = user_offset(align);
+        let outer = match Layout::from_size_align(pad + layout.size() + TAIL_SIZE, align) {
+            Ok(l) => l,
+            Err(_) => return ptr::null_mut(),
+        };
+        let callers = backtrace();
+
+        let block = self.heap.alloc(outer);
+        if block.is_null() {
+            return block;
+        }
+        let user = block.add(pad);
+        let header = user.sub(HEADER_SIZE) as *mut Header;
+        ptr::write_bytes(user, ALLOC_POISON, layout.size());
+        ptr::write_bytes(user.add(layout.size()), CANARY, TAIL_SIZE);
+
+        let mut state = self.state.lock();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        header.write(Header {
+            magic: LIVE_MAGIC,
+            size: layout.size(),
+            pad,
+            align,
+            seq,
+            prev: ptr::null_mut(),
+            next: state.head,
+            callers,
+        });
+        if !state.head.is_null() {
+            (*state.head).prev = header;
+        }
+        state.head = header;
+        state.stats.live_allocations += 1;
+        state.stats.live_bytes += layout.size();
+        user
+    }
+
+    unsafe fn dealloc(&self, user: *mut u8, layout: Layout) {
+        let header = user.sub(HEADER_SIZE) as *mut Header;
+        let mut state = self.state.lock();
+
+        match (*header).magic {
+            LIVE_MAGIC => {}
+            FREED_MAGIC => {
+                state.stats.double_frees += 1;
+                report(format_args!(
+                    "[HEAP] double free of block #{} at {:#x} (caller {:#x})",
+                    (*header).seq,
+                    user as usize,
+                    backtrace()[0]
+                ));
+                return;
+            }
+            _ => {
+                state.stats.invalid_frees += 1;
+                report(format_args!(
+                    "[HEAP] free of unknown or corrupted block at {:#x} (caller {:#x})",
+                    user as usize,
+                    backtrace()[0]
+                ));
+                return;
+            }
+        }
+
+        let size = (*header).size;
+        if size != layout.size() {
+            report(format_args!(
+                "[HEAP] block #{} at {:#x} freed with size {} but allocated with {}",
+                (*header).seq,
+                user as usize,
+                layout.size(),
+                size
+            ));
+        }
+        if !tail_intact(header) {
+            state.stats.overflows += 1;
+            report(format_args!(
+                "[HEAP] buffer overflow past block #{} at {:#x} ({} bytes)",
+                (*header).seq,
+                user as usize,
+                size
+            ));
+        }
+
+        // Unlink from the live list
+        let (prev, next) = ((*header).prev, (*header).next);
+        if prev.is_null() {
+            state.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        state.stats.live_allocations -= 1;
+        state.stats.live_bytes -= size;
+
+        (*header).magic = FREED_MAGIC;
+        ptr::write_bytes(user, FREE_POISON, size);
+
+        // Quarantine the block, releasing the oldest one
+        let slot = state.quarantine_next;
+        state.quarantine_next = (slot + 1) % QUARANTINE_LEN;
+        let oldest = core::mem::replace(&mut state.quarantine[slot], header);
+        if !oldest.is_null() {
+            self.release(&mut state, oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const ARENA_SIZE: usize = 64 * 1024;
+
+    fn make_heap(arena: &mut Vec<u8>) -> DebugHeap {
+        let heap = DebugHeap::empty();
+        unsafe { heap.lock().init(arena.as_mut_ptr(), arena.len()); }
+        heap
+    }
+
+    #[test]
+    fn test_alloc_poison_and_tracking() {
+        let mut arena = alloc::vec![0u8; ARENA_SIZE];
+        let heap = make_heap(&mut arena);
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        unsafe {
+            let p = heap.alloc(layout);
+            assert!(!p.is_null());
+            assert_eq!(*p, ALLOC_POISON);
+            assert_eq!(heap.stats().live_allocations, 1);
+            assert_eq!(heap.stats().live_bytes, 24);
+
+            let mut live = Vec::with_capacity(4);
+            heap.snapshot(&mut live);
+            assert_eq!(live.len(), 1);
+            assert_eq!(live[0].addr, p as usize);
+
+            heap.dealloc(p, layout);
+            assert_eq!(*p, FREE_POISON);
+            assert_eq!(heap.stats().live_allocations, 0);
+        }
+    }
+
+    #[test]
+    fn test_double_free_detected() {
+        let mut arena = alloc::vec![0u8; ARENA_SIZE];
+        let heap = make_heap(&mut arena);
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        unsafe {
+            let p = heap.alloc(layout);
+            heap.dealloc(p, layout);
+            heap.dealloc(p, layout);
+        }
+        assert_eq!(heap.stats().double_frees, 1);
+    }
+
+    #[test]
+    fn test_overflow_detected() {
+        let mut arena = alloc::vec![0u8; ARENA_SIZE];
+        let heap = make_heap(&mut arena);
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        unsafe {
+            let p = heap.alloc(layout);
+            *p.add(16) = 0;
+            assert_eq!(heap.check(), 1);
+        }
+    }
+
+    #[test]
+    fn test_use_after_free_detected_on_release() {
+        let mut arena = alloc::vec![0u8; ARENA_SIZE];
+        let heap = make_heap(&mut arena);
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            let stale = heap.alloc(layout);
+            heap.dealloc(stale, layout);
+            *stale.add(8) = 1;
+            // Push the stale block out of the quarantine
+            for _ in 0..QUARANTINE_LEN {
+                let p = heap.alloc(layout);
+                heap.dealloc(p, layout);
+            }
+        }
+        assert_eq!(heap.stats().use_after_free, 1);
+    }
+}
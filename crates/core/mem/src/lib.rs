@@ -25,12 +25,15 @@
 extern crate alloc;
 
 pub mod heap;
+pub mod heap_debug;
 pub mod paging;
 pub mod phys;
 pub mod user_access;
 
 // Re-export commonly used items
 pub use heap::{init as init_heap, ALLOCATOR};
-pub use paging::{ProcessPageTable, PageTable, PAGE_SIZE};
+pub use heap_debug::DebugHeap;
+pub use paging::{ProcessPageTable, PageTable, MemRegion, MemUsage, PAGE_SIZE};
 pub use paging::flags as page_flags;
 pub use user_access::{validate_user_ptr, read_user_string, copy_from_user, copy_to_user, UserAccessError};
+pub use user_access::{read_user, write_user, read_user_bytes, UserAccess};
@@ -11,6 +11,10 @@
 //! 0x0000_0000_0000_0000 - 0x0000_7FFF_FFFF_FFFF : User space (lower half)
 //! 0xFFFF_8000_0000_0000 - 0xFFFF_FFFF_FFFF_FFFF : Kernel space (higher half)
 //! ```
+//!
+//! The kernel itself still runs from the identity map of the first 8MB,
+//! kernel-only in every page table; the copy of it at `KERNEL_SPACE_START`
+//! is mapped but nothing is linked there yet.
 
 use alloc::vec::Vec;
 use alloc::boxed::Box;
@@ -27,6 +31,19 @@ pub const KERNEL_SPACE_START: u64 = 0xFFFF_FFFF_8000_0000;
 /// User space maximum (lower 128TB in canonical addressing)
 pub const USER_SPACE_MAX: u64 = 0x0000_7FFF_FFFF_FFFF;
 
+/// Kernel stacks: one per process slot, identity mapped below the heap
+pub const KERNEL_STACKS_START: u64 = 0x200000;
+
+/// Size of each kernel stack, its guard page included
+pub const KERNEL_STACK_SIZE: u64 = 0x10000;
+
+/// Number of kernel stacks (one per process slot)
+pub const KERNEL_STACK_SLOTS: usize = 16;
+
+/// The lowest page of each kernel stack, left unmapped in process page
+/// tables so running off the bottom faults
+pub const KERNEL_STACK_GUARD: u64 = PAGE_SIZE as u64;
+
 /// Page table entry flags
 pub mod flags {
     /// Page is present in memory
@@ -47,11 +64,23 @@ pub mod flags {
     pub const HUGE_PAGE: u64 = 1 << 7;
     /// Page is global (not flushed on CR3 switch)
     pub const GLOBAL: u64 = 1 << 8;
+    /// Not present because it is in swap; the address bits hold the slot
+    /// (available to software, ignored by the CPU)
+    pub const SWAPPED: u64 = 1 << 9;
+    /// Not present because it hasn't been loaded from its executable yet
+    /// (available to software, ignored by the CPU)
+    pub const LAZY: u64 = 1 << 10;
     /// Disable execution (NX bit)
     pub const NO_EXECUTE: u64 = 1 << 63;
 
     /// Mask for extracting physical address from entry
     pub const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+    /// Mask for extracting the physical address of a 2MB page
+    pub const LARGE_ADDR_MASK: u64 = 0x000F_FFFF_FFE0_0000;
+    /// PAT index bit of a 4KB page (shares bit 7 with HUGE_PAGE)
+    pub const PAT: u64 = 1 << 7;
+    /// PAT index bit of a 2MB page
+    pub const LARGE_PAT: u64 = 1 << 12;
 }
 
 /// 4-level page table structure
@@ -109,6 +138,38 @@ impl Default for PageTable {
     }
 }
 
+/// User memory region a page belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemRegion {
+    /// ELF segments (code, data, bss)
+    Code,
+    Heap,
+    Stack,
+}
+
+/// Number of user pages mapped in each region of a process
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemUsage {
+    pub code_pages: usize,
+    pub heap_pages: usize,
+    pub stack_pages: usize,
+}
+
+impl MemUsage {
+    /// Total mapped user pages
+    pub fn total_pages(&self) -> usize {
+        self.code_pages + self.heap_pages + self.stack_pages
+    }
+
+    fn region_mut(&mut self, region: MemRegion) -> &mut usize {
+        match region {
+            MemRegion::Code => &mut self.code_pages,
+            MemRegion::Heap => &mut self.heap_pages,
+            MemRegion::Stack => &mut self.stack_pages,
+        }
+    }
+}
+
 /// Per-process page table manager
 ///
 /// Manages a complete 4-level page table hierarchy for a process.
@@ -121,6 +182,13 @@ pub struct ProcessPageTable {
     /// Physical pages allocated for this process (stack, heap, segments)
     /// These are freed when the process exits
     allocated_phys_pages: Vec<u64>,
+    /// 2MB physical blocks allocated for this process, freed likewise
+    allocated_large_pages: Vec<u64>,
+    /// Mapped user pages per region
+    usage: MemUsage,
+    /// Virtual addresses of the pages mapped with `map_region_page`, the
+    /// candidates for swapping out
+    user_pages: Vec<u64>,
 }
 
 impl ProcessPageTable {
@@ -132,6 +200,9 @@ impl ProcessPageTable {
             pml4: PageTable::new(),
             allocated_tables: Vec::new(),
             allocated_phys_pages: Vec::new(),
+            allocated_large_pages: Vec::new(),
+            usage: MemUsage::default(),
+            user_pages: Vec::new(),
         };
 
         // Map kernel space (required for interrupts/syscalls)
@@ -147,35 +218,54 @@ impl ProcessPageTable {
     /// 2. High canonical addresses for proper kernel space
     ///
     /// Covers:
-    /// - First 8MB: kernel code, heap, bootloader data, kernel stacks
-    /// - 16MB-48MB: physical page allocator region (for kernel access during syscalls)
+    /// - First 2MB: kernel code and data, bootloader data
+    /// - 2MB-3MB: kernel stacks (each above a guard page)
+    /// - 3MB-8MB: kernel heap
     ///
-    /// NOTE: Currently includes USER flag for shared kernel/user memory (like heap).
-    /// This allows user processes to use kernel-allocated memory (SYS_MALLOC).
+    /// All of it is for the kernel only: user memory (SYS_MALLOC included)
+    /// lives in the process's own pages, so SMEP can keep the kernel from
+    /// executing user pages and SMAP from touching them by accident.
     fn map_kernel_space(&mut self) {
-        // Include USER flag so user code can access kernel heap (for SYS_MALLOC)
-        let kernel_flags = flags::PRESENT | flags::WRITABLE | flags::GLOBAL | flags::USER;
         let kernel_only_flags = flags::PRESENT | flags::WRITABLE | flags::GLOBAL;
 
         // Map first 8MB (4 x 2MB pages) using huge pages
-        // This covers kernel code (0x100000), heap, app data, and kernel stacks (0x280000+)
+        // This covers kernel code (0x100000), kernel stacks and heap
         for i in 0..4 {
             let phys_addr = (i as u64) * LARGE_PAGE_SIZE as u64;
 
-            // Identity mapping for kernel and user access
-            self.map_large_page(phys_addr, phys_addr, kernel_flags);
+            // Identity mapping, which the kernel runs at
+            self.map_large_page(phys_addr, phys_addr, kernel_only_flags);
 
             // High canonical mapping for proper kernel space (kernel only)
             let high_virt = KERNEL_SPACE_START + phys_addr;
             self.map_large_page(high_virt, phys_addr, kernel_only_flags);
         }
 
+        self.map_kernel_stacks(kernel_only_flags);
+
         // NOTE: Do NOT map 16MB+ here - that's where user processes are loaded.
         // Kernel accesses to physical pages during syscalls use the KERNEL page table
         // (we switch to it before exec/loading), not the user page table.
     }
 
+    /// Remap the kernel stacks' part of the identity map as 4KB pages for
+    /// the kernel only, leaving out each stack's guard page. Runs before
+    /// the table is ever loaded, so nothing is flushed.
+    fn map_kernel_stacks(&mut self, kernel_only_flags: u64) {
+        self.split_large_page(KERNEL_STACKS_START);
+        let Some(pd_entry) = self.pd_entry(KERNEL_STACKS_START) else { return };
+        let pt = unsafe { &mut *((*pd_entry & flags::ADDR_MASK) as *mut PageTable) };
+        let end = KERNEL_STACKS_START + KERNEL_STACK_SLOTS as u64 * KERNEL_STACK_SIZE;
+        for addr in (KERNEL_STACKS_START..end).step_by(PAGE_SIZE) {
+            let guard = (addr - KERNEL_STACKS_START) % KERNEL_STACK_SIZE < KERNEL_STACK_GUARD;
+            let entry = if guard { 0 } else { addr | kernel_only_flags };
+            pt.set_entry(((addr >> 12) & 0x1FF) as usize, entry);
+        }
+    }
+
     /// Map a 2MB large page
+    ///
+    /// Whatever was mapped in the 2MB range before is replaced.
     pub fn map_large_page(&mut self, virt_addr: u64, phys_addr: u64, flags: u64) {
         let pml4_idx = ((virt_addr >> 39) & 0x1FF) as usize;
         let pdp_idx = ((virt_addr >> 30) & 0x1FF) as usize;
@@ -273,33 +363,19 @@ impl ProcessPageTable {
         let pd_phys = pdp.get_entry(pdp_idx) & flags::ADDR_MASK;
         let pd = unsafe { &mut *(pd_phys as *mut PageTable) };
 
-        // Ensure PT exists - handle huge pages specially
-        let pd_entry = pd.get_entry(pd_idx);
+        // Ensure PT exists - a huge page is split so the rest of it stays
         if !pd.is_present(pd_idx) {
             let pt = self.allocate_table();
             pd.set_entry(pd_idx, pt as u64 | hier_flags);
-        } else if (pd_entry & flags::HUGE_PAGE) != 0 {
-            // Split huge page (2MB) into 512 x 4KB pages
-            // Get the physical base address of the huge page
-            let huge_phys_base = pd_entry & flags::ADDR_MASK;
-            let old_flags = pd_entry & !flags::ADDR_MASK & !flags::HUGE_PAGE;
-
-            // Allocate a new page table
-            let pt = self.allocate_table();
-            let pt_ptr = unsafe { &mut *(pt as *mut PageTable) };
-
-            // Map all 512 4KB pages to match the original huge page
-            for i in 0..512 {
-                let page_phys = huge_phys_base + (i as u64 * PAGE_SIZE as u64);
-                pt_ptr.set_entry(i, page_phys | old_flags);
+        } else {
+            if (pd.get_entry(pd_idx) & flags::HUGE_PAGE) != 0 {
+                self.split_large_page(virt_addr);
             }
-
-            // Replace the huge page entry with the PT pointer
-            pd.set_entry(pd_idx, pt as u64 | hier_flags);
-        } else if is_user_page {
-            let entry = pd.get_entry(pd_idx);
-            if entry & flags::USER == 0 {
-                pd.set_entry(pd_idx, entry | flags::USER);
+            if is_user_page {
+                let entry = pd.get_entry(pd_idx);
+                if entry & flags::USER == 0 {
+                    pd.set_entry(pd_idx, entry | flags::USER);
+                }
             }
         }
 
@@ -310,6 +386,79 @@ impl ProcessPageTable {
         pt.set_entry(pt_idx, phys_addr | flags);
     }
 
+    /// Map `size` bytes at `phys_addr` to `virt_addr`, using 2MB pages
+    /// where both addresses are 2MB aligned and a whole one fits, 4KB pages
+    /// elsewhere. Returns the number of 2MB pages used.
+    pub fn map_range(&mut self, virt_addr: u64, phys_addr: u64, size: u64, flags: u64) -> usize {
+        let large = LARGE_PAGE_SIZE as u64;
+        let end = size.div_ceil(PAGE_SIZE as u64) * PAGE_SIZE as u64;
+        let mut large_pages = 0;
+        let mut offset = 0;
+        while offset < end {
+            let (virt, phys) = (virt_addr + offset, phys_addr + offset);
+            if virt.is_multiple_of(large) && phys.is_multiple_of(large) && end - offset >= large {
+                self.map_large_page(virt, phys, flags);
+                large_pages += 1;
+                offset += large;
+            } else {
+                self.map_4k_page(virt, phys, flags);
+                offset += PAGE_SIZE as u64;
+            }
+        }
+        large_pages
+    }
+
+    /// Split the 2MB page covering `virt_addr` into 512 4KB pages with the
+    /// same flags, so parts of it can be remapped or protected on their
+    /// own. Returns false if no 2MB page covers the address.
+    ///
+    /// The translation does not change, so nothing needs flushing until
+    /// one of the 4KB entries is changed (`set_entry` flushes it, and with
+    /// it the 2MB TLB entry).
+    pub fn split_large_page(&mut self, virt_addr: u64) -> bool {
+        let Some(pd_entry) = self.pd_entry(virt_addr) else { return false };
+        let entry = unsafe { *pd_entry };
+        if entry & flags::PRESENT == 0 || entry & flags::HUGE_PAGE == 0 {
+            return false;
+        }
+        let base = entry & flags::LARGE_ADDR_MASK;
+        let mut page_flags = entry & !(flags::ADDR_MASK | flags::HUGE_PAGE);
+        if entry & flags::LARGE_PAT != 0 {
+            page_flags |= flags::PAT;
+        }
+
+        let pt = self.allocate_table();
+        let pt_ref = unsafe { &mut *pt };
+        for i in 0..512 {
+            pt_ref.set_entry(i, (base + (i * PAGE_SIZE) as u64) | page_flags);
+        }
+        let hier_flags = flags::PRESENT | flags::WRITABLE | (entry & flags::USER);
+        unsafe { *pd_entry = pt as u64 | hier_flags; }
+        true
+    }
+
+    /// Change the flags of a mapped 4KB page. A 2MB page covering it is
+    /// split first, unless the new flags match the whole of it anyway.
+    pub fn set_page_flags(&mut self, virt_addr: u64, page_flags: u64) -> bool {
+        let ignored = flags::ACCESSED | flags::DIRTY;
+        if let Some(pd_entry) = self.pd_entry(virt_addr) {
+            let entry = unsafe { *pd_entry };
+            if entry & flags::PRESENT != 0 && entry & flags::HUGE_PAGE != 0 {
+                let current = entry & !(flags::ADDR_MASK | flags::HUGE_PAGE | ignored);
+                if current == page_flags & !ignored {
+                    return true;
+                }
+                self.split_large_page(virt_addr);
+            }
+        }
+        match self.entry(virt_addr) {
+            Some(entry) if entry & flags::PRESENT != 0 => {
+                self.set_entry(virt_addr, (entry & flags::ADDR_MASK) | page_flags)
+            }
+            _ => false,
+        }
+    }
+
     /// Look up the physical address for a virtual address
     /// Returns None if the page is not mapped
     pub fn lookup(&self, virt_addr: u64) -> Option<u64> {
@@ -378,6 +527,11 @@ impl ProcessPageTable {
             return None;
         }
 
+        // Unmapping part of a 2MB page keeps the rest of it
+        if (pd.get_entry(pd_idx) & flags::HUGE_PAGE) != 0 {
+            self.split_large_page(virt_addr);
+        }
+
         let pt_phys = pd.get_entry(pd_idx) & flags::ADDR_MASK;
         let pt = unsafe { &mut *(pt_phys as *mut PageTable) };
 
@@ -413,6 +567,121 @@ impl ProcessPageTable {
         self.allocated_phys_pages.push(phys_addr);
     }
 
+    /// Stop tracking a physical page, which the caller now owns; false if
+    /// this process did not own it
+    pub fn untrack_phys_page(&mut self, phys_addr: u64) -> bool {
+        match self.allocated_phys_pages.iter().position(|&p| p == phys_addr) {
+            Some(i) => {
+                self.allocated_phys_pages.swap_remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track a 2MB physical block allocated for this process (see
+    /// `phys::alloc_large_page`), freed when the ProcessPageTable is dropped
+    pub fn track_large_page(&mut self, phys_addr: u64) {
+        self.allocated_large_pages.push(phys_addr);
+    }
+
+    /// Number of physical pages owned by this process (stack, heap, segments)
+    pub fn owned_page_count(&self) -> usize {
+        self.allocated_phys_pages.len() + self.allocated_large_pages.len() * (LARGE_PAGE_SIZE / PAGE_SIZE)
+    }
+
+    /// Virtual addresses of the user pages mapped with `map_region_page`
+    pub fn user_pages(&self) -> &[u64] {
+        &self.user_pages
+    }
+
+    /// Page table entry for a 4KB page, present or not; None if there is
+    /// no page table for the address or it is inside a huge page
+    pub fn entry(&self, virt_addr: u64) -> Option<u64> {
+        self.pt_entry(virt_addr).map(|entry| unsafe { *entry })
+    }
+
+    /// Replace the page table entry of a 4KB page that has one (see
+    /// `entry`), flushing the address from the TLB
+    pub fn set_entry(&mut self, virt_addr: u64, entry: u64) -> bool {
+        match self.pt_entry(virt_addr) {
+            Some(slot) => {
+                unsafe { *slot = entry; }
+                invlpg(virt_addr);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Walk to the PT entry for `virt_addr`
+    fn pt_entry(&self, virt_addr: u64) -> Option<*mut u64> {
+        let pd_entry = unsafe { *self.pd_entry(virt_addr)? };
+        if pd_entry & flags::PRESENT == 0 || pd_entry & flags::HUGE_PAGE != 0 {
+            return None;
+        }
+        let table = pd_entry & flags::ADDR_MASK;
+        Some((table + ((virt_addr >> 12) & 0x1FF) * 8) as *mut u64)
+    }
+
+    /// Walk to the PD entry for `virt_addr`, which may be a 2MB page
+    fn pd_entry(&self, virt_addr: u64) -> Option<*mut u64> {
+        let mut table = self.pml4.physical_addr();
+        for shift in [39u64, 30] {
+            let entry = unsafe { *((table + ((virt_addr >> shift) & 0x1FF) * 8) as *const u64) };
+            if entry & flags::PRESENT == 0 || (shift != 39 && entry & flags::HUGE_PAGE != 0) {
+                return None;
+            }
+            table = entry & flags::ADDR_MASK;
+        }
+        Some((table + ((virt_addr >> 21) & 0x1FF) * 8) as *mut u64)
+    }
+
+    /// Map a 2MB user page and count it against a memory region
+    ///
+    /// Both addresses must be 2MB aligned. Large pages are not swapped.
+    pub fn map_region_large_page(&mut self, virt_addr: u64, phys_addr: u64, flags: u64, region: MemRegion) -> Result<(), &'static str> {
+        let align = LARGE_PAGE_SIZE as u64 - 1;
+        if virt_addr & align != 0 || phys_addr & align != 0 {
+            return Err("Large page not 2MB aligned");
+        }
+        if virt_addr + align > USER_SPACE_MAX {
+            return Err("Virtual address outside user space");
+        }
+        self.map_large_page(virt_addr, phys_addr, flags | flags::USER);
+        if (flags & flags::PRESENT) != 0 {
+            *self.usage.region_mut(region) += LARGE_PAGE_SIZE / PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Map a 4KB user page and count it against a memory region
+    ///
+    /// Remapping an address that is already present does not count twice.
+    pub fn map_region_page(&mut self, virt_addr: u64, phys_addr: u64, flags: u64, region: MemRegion) -> Result<(), &'static str> {
+        let was_mapped = self.lookup(virt_addr).is_some();
+        self.map_user_page(virt_addr, phys_addr, flags)?;
+        if !was_mapped && (flags & flags::PRESENT) != 0 {
+            *self.usage.region_mut(region) += 1;
+            self.user_pages.push(virt_addr);
+        }
+        Ok(())
+    }
+
+    /// Unmap a 4KB page that was mapped with `map_region_page`
+    pub fn unmap_region_page(&mut self, virt_addr: u64, region: MemRegion) -> Option<u64> {
+        let phys = self.unmap_4k_page(virt_addr)?;
+        let count = self.usage.region_mut(region);
+        *count = count.saturating_sub(1);
+        self.user_pages.retain(|&page| page != virt_addr);
+        Some(phys)
+    }
+
+    /// Pages currently mapped in each user region
+    pub fn mem_usage(&self) -> MemUsage {
+        self.usage
+    }
+
     /// Get physical address of PML4 (for loading into CR3)
     pub fn pml4_phys_addr(&self) -> u64 {
         self.pml4.physical_addr()
@@ -442,6 +711,9 @@ impl Drop for ProcessPageTable {
         for &phys_addr in &self.allocated_phys_pages {
             crate::phys::free_page(phys_addr);
         }
+        for &phys_addr in &self.allocated_large_pages {
+            crate::phys::free_large_page(phys_addr);
+        }
 
         // Free all allocated sub-tables
         for &table_ptr in &self.allocated_tables {
@@ -480,6 +752,99 @@ pub fn get_cr3() -> u64 {
     cr3
 }
 
+/// Physical address of `virt_addr` under the active page table (CR3), or
+/// None if it is not mapped. Page tables must be identity mapped, as the
+/// kernel's are.
+pub fn translate(virt_addr: u64) -> Option<u64> {
+    let mut table = get_cr3() & flags::ADDR_MASK;
+    // PML4, PDP, PD, PT; (index shift, size of a huge page at this level)
+    for (level, shift) in [39u64, 30, 21, 12].into_iter().enumerate() {
+        let entry = unsafe { *((table + ((virt_addr >> shift) & 0x1FF) * 8) as *const u64) };
+        if entry & flags::PRESENT == 0 {
+            return None;
+        }
+        let page = entry & flags::ADDR_MASK;
+        if shift == 12 || (level > 0 && entry & flags::HUGE_PAGE != 0) {
+            let offset_mask = (1u64 << shift) - 1;
+            return Some((page & !offset_mask) | (virt_addr & offset_mask));
+        }
+        table = page;
+    }
+    None
+}
+
+/// Copy the active page tables, replacing every page table whose 512
+/// entries map one aligned 2MB block with the same flags by a 2MB page
+///
+/// Firmware page tables are left untouched: only the tables on the way to
+/// a promoted block are copied, and the rest are shared. The copies live
+/// on the kernel heap for good. Returns the new PML4, to be loaded into
+/// CR3, and the number of 2MB pages made; None if nothing could be
+/// promoted. Page tables must be identity mapped, as the firmware's are.
+pub fn promote_identity_map() -> Option<(u64, usize)> {
+    let pml4 = unsafe { &*((get_cr3() & flags::ADDR_MASK) as *const PageTable) };
+    let mut promoted = 0;
+    let new_pml4 = copy_promoted(pml4, 3, &mut promoted)?;
+    Some((new_pml4 as u64, promoted))
+}
+
+/// Copy of `table` (PML4 = level 3 ... PD = level 1) with its promotable
+/// page tables replaced, or None if none are below it
+fn copy_promoted(table: &PageTable, level: u32, promoted: &mut usize) -> Option<*mut PageTable> {
+    let mut copy: Option<*mut PageTable> = None;
+    for i in 0..512 {
+        let entry = table.get_entry(i);
+        if entry & flags::PRESENT == 0 || (level < 3 && entry & flags::HUGE_PAGE != 0) {
+            continue;
+        }
+        let child = unsafe { &*((entry & flags::ADDR_MASK) as *const PageTable) };
+        let new_entry = if level == 1 {
+            match large_page_entry(child) {
+                Some(large) => {
+                    *promoted += 1;
+                    large
+                }
+                None => continue,
+            }
+        } else {
+            match copy_promoted(child, level - 1, promoted) {
+                Some(new_child) => new_child as u64 | (entry & !flags::ADDR_MASK),
+                None => continue,
+            }
+        };
+        let copy = *copy.get_or_insert_with(|| {
+            let new_table = Box::into_raw(Box::new(PageTable::new()));
+            for j in 0..512 {
+                unsafe { (*new_table).set_entry(j, table.get_entry(j)); }
+            }
+            new_table
+        });
+        unsafe { (*copy).set_entry(i, new_entry); }
+    }
+    copy
+}
+
+/// The 2MB page entry equivalent to page table `pt`, if it maps one
+/// aligned, contiguous 2MB block with the same flags throughout
+fn large_page_entry(pt: &PageTable) -> Option<u64> {
+    let ignored = flags::ACCESSED | flags::DIRTY;
+    let first = pt.get_entry(0);
+    let base = first & flags::ADDR_MASK;
+    let page_flags = first & !(flags::ADDR_MASK | ignored);
+    if first & flags::PRESENT == 0 || base & (LARGE_PAGE_SIZE as u64 - 1) != 0 {
+        return None;
+    }
+    let uniform = (1..512).all(|i| {
+        let entry = pt.get_entry(i);
+        entry & flags::ADDR_MASK == base + (i * PAGE_SIZE) as u64 && entry & !(flags::ADDR_MASK | ignored) == page_flags
+    });
+    if !uniform {
+        return None;
+    }
+    let pat = if page_flags & flags::PAT != 0 { flags::LARGE_PAT } else { 0 };
+    Some(base | (page_flags & !flags::PAT) | pat | flags::HUGE_PAGE)
+}
+
 /// Check if paging is enabled (CR0.PG bit)
 #[inline]
 pub fn is_enabled() -> bool {
@@ -514,3 +879,68 @@ pub fn invlpg(virt_addr: u64) {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RW_USER: u64 = flags::PRESENT | flags::WRITABLE | flags::USER;
+
+    #[test]
+    fn test_4k_page_splits_large_page() {
+        let mut table = ProcessPageTable::new();
+        table.map_large_page(0x4000_0000, 0x8000_0000, RW_USER);
+        assert_eq!(table.lookup(0x4000_3010), Some(0x8000_3010));
+
+        table.map_4k_page(0x4000_3000, 0x1234_5000, RW_USER);
+        assert_eq!(table.lookup(0x4000_3000), Some(0x1234_5000));
+        assert_eq!(table.lookup(0x4000_2000), Some(0x8000_2000));
+        assert_eq!(table.lookup(0x401F_F000), Some(0x801F_F000));
+        assert!(!table.split_large_page(0x4000_0000));
+    }
+
+    #[test]
+    fn test_map_range_uses_large_pages_when_aligned() {
+        let mut table = ProcessPageTable::new();
+        assert_eq!(table.map_range(0x6000_0000, 0x9000_0000, 0x30_0000, RW_USER), 1);
+        assert_eq!(table.lookup(0x6010_0000), Some(0x9010_0000));
+        assert_eq!(table.lookup(0x6020_0000), Some(0x9020_0000));
+        assert_eq!(table.lookup(0x6030_0000), None);
+        assert_eq!(table.map_range(0x7000_1000, 0xA000_2000, 0x40_0000, RW_USER), 0);
+    }
+
+    #[test]
+    fn test_kernel_stack_guard_pages() {
+        let table = ProcessPageTable::new();
+        let second = KERNEL_STACKS_START + KERNEL_STACK_SIZE;
+        assert_eq!(table.lookup(KERNEL_STACKS_START), None);
+        assert_eq!(table.lookup(second), None);
+        assert_eq!(table.lookup(second - KERNEL_STACK_GUARD), Some(second - KERNEL_STACK_GUARD));
+        assert_eq!(table.entry(second + KERNEL_STACK_GUARD).map(|e| e & flags::USER), Some(0));
+        let end = KERNEL_STACKS_START + KERNEL_STACK_SLOTS as u64 * KERNEL_STACK_SIZE;
+        assert_eq!(table.entry(end).map(|e| e & flags::USER), Some(0));
+    }
+
+    #[test]
+    fn test_kernel_space_is_kernel_only() {
+        let table = ProcessPageTable::new();
+        for addr in (0..0x80_0000).step_by(0x10_0000) {
+            if let Some(entry) = table.entry(addr) {
+                assert_eq!(entry & flags::USER, 0, "{:#x} is user-accessible", addr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_page_entry() {
+        let mut pt = PageTable::new();
+        for i in 0..512 {
+            pt.set_entry(i, (0x20_0000 + i as u64 * 0x1000) | flags::PRESENT | flags::WRITABLE);
+        }
+        pt.set_entry(7, pt.get_entry(7) | flags::ACCESSED);
+        assert_eq!(large_page_entry(&pt), Some(0x20_0000 | flags::PRESENT | flags::WRITABLE | flags::HUGE_PAGE));
+
+        pt.set_entry(9, pt.get_entry(9) & !flags::WRITABLE);
+        assert_eq!(large_page_entry(&pt), None);
+    }
+}
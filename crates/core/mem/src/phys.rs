@@ -3,8 +3,9 @@
 //! Simple bitmap-based physical page allocator.
 //! Tracks which 4KB physical pages are free or in use.
 
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::Mutex;
-use crate::paging::PAGE_SIZE;
+use crate::paging::{LARGE_PAGE_SIZE, PAGE_SIZE};
 
 /// Maximum physical memory supported (1GB)
 const MAX_PHYS_MEMORY: usize = 1024 * 1024 * 1024;
@@ -18,10 +19,22 @@ const BITMAP_SIZE: usize = MAX_PAGES / 64;
 /// Physical page allocator
 static PHYS_ALLOCATOR: Mutex<PhysAllocator> = Mutex::new(PhysAllocator::new());
 
+/// Frees a page when memory runs out (by swapping one out); false if it
+/// could not
+static mut RECLAIMER: Option<fn() -> bool> = None;
+
+/// Set while the reclaimer runs, so its own allocations fail instead of
+/// recursing
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+
 /// Bitmap-based physical page allocator
 struct PhysAllocator {
     /// Bitmap: 1 = free, 0 = used
     bitmap: [u64; BITMAP_SIZE],
+    /// Free pages already reported to the hypervisor (see `take_unreported`)
+    reported: [u64; BITMAP_SIZE],
+    /// Pages set in `reported`
+    reported_pages: usize,
     /// Total pages available
     total_pages: usize,
     /// Free pages remaining
@@ -34,6 +47,8 @@ impl PhysAllocator {
     const fn new() -> Self {
         PhysAllocator {
             bitmap: [0; BITMAP_SIZE],
+            reported: [0; BITMAP_SIZE],
+            reported_pages: 0,
             total_pages: 0,
             free_pages: 0,
             next_free: 0,
@@ -76,6 +91,7 @@ impl PhysAllocator {
                 self.free_pages -= 1;
 
                 let page = idx * 64 + bit;
+                self.unreport(page);
                 self.next_free = page + 1;
 
                 return Some((page * PAGE_SIZE) as u64);
@@ -107,8 +123,9 @@ impl PhysAllocator {
         }
     }
 
-    /// Allocate contiguous physical pages
-    fn alloc_contiguous(&mut self, count: usize) -> Option<u64> {
+    /// Allocate contiguous physical pages, the first at a multiple of
+    /// `align` pages
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<u64> {
         if count == 0 || self.free_pages < count {
             return None;
         }
@@ -124,6 +141,9 @@ impl PhysAllocator {
             if (self.bitmap[word] & (1 << bit)) != 0 {
                 // Page is free
                 if run_length == 0 {
+                    if page % align != 0 {
+                        continue;
+                    }
                     run_start = page;
                 }
                 run_length += 1;
@@ -135,6 +155,7 @@ impl PhysAllocator {
                         let w = p / 64;
                         let b = p % 64;
                         self.bitmap[w] &= !(1 << b);
+                        self.unreport(p);
                     }
                     self.free_pages -= count;
                     return Some((run_start * PAGE_SIZE) as u64);
@@ -147,6 +168,64 @@ impl PhysAllocator {
 
         None
     }
+
+    /// Forget that `page` was reported, as its contents now matter
+    fn unreport(&mut self, page: usize) {
+        let (word, bit) = (page / 64, page % 64);
+        if self.reported[word] & (1 << bit) != 0 {
+            self.reported[word] &= !(1 << bit);
+            self.reported_pages -= 1;
+        }
+    }
+
+    /// Find a run of at least `min` free, unreported pages and mark up to
+    /// `max` of them used
+    fn take_unreported(&mut self, min: usize, max: usize) -> Option<(u64, usize)> {
+        if min == 0 || self.free_pages - self.reported_pages < min {
+            return None;
+        }
+        let mut run_start = 0;
+        let mut run_length = 0;
+        for page in 0..=MAX_PAGES {
+            let candidate = page < MAX_PAGES && {
+                let (word, bit) = (page / 64, page % 64);
+                self.bitmap[word] & !self.reported[word] & (1 << bit) != 0
+            };
+            if candidate && run_length < max {
+                if run_length == 0 {
+                    run_start = page;
+                }
+                run_length += 1;
+                continue;
+            }
+            if run_length >= min {
+                for p in run_start..run_start + run_length {
+                    self.bitmap[p / 64] &= !(1 << (p % 64));
+                }
+                self.free_pages -= run_length;
+                return Some(((run_start * PAGE_SIZE) as u64, run_length));
+            }
+            run_length = 0;
+        }
+        None
+    }
+
+    /// Free pages from `take_unreported`, remembering whether the report
+    /// went through
+    fn finish_report(&mut self, phys_addr: u64, count: usize, reported: bool) {
+        let start = (phys_addr as usize) / PAGE_SIZE;
+        for page in start..(start + count).min(MAX_PAGES) {
+            let (word, bit) = (page / 64, page % 64);
+            if self.bitmap[word] & (1 << bit) == 0 {
+                self.bitmap[word] |= 1 << bit;
+                self.free_pages += 1;
+                if reported {
+                    self.reported[word] |= 1 << bit;
+                    self.reported_pages += 1;
+                }
+            }
+        }
+    }
 }
 
 /// Initialize physical memory allocator
@@ -161,9 +240,31 @@ pub fn init(start: u64, size: u64) {
 
 /// Allocate a single physical page (4KB)
 ///
-/// Returns the physical address of the allocated page, or None if out of memory.
+/// When none is free the reclaimer (see [`set_reclaimer`]) is asked for
+/// one. Returns the physical address of the allocated page, or None if out
+/// of memory.
 pub fn alloc_page() -> Option<u64> {
-    PHYS_ALLOCATOR.lock().alloc_page()
+    loop {
+        let page = PHYS_ALLOCATOR.lock().alloc_page();
+        if page.is_some() {
+            return page;
+        }
+        let reclaim = unsafe { RECLAIMER }?;
+        if RECLAIMING.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let freed = reclaim();
+        RECLAIMING.store(false, Ordering::Release);
+        if !freed {
+            return None;
+        }
+    }
+}
+
+/// Install the function `alloc_page` calls to free a page when memory runs
+/// out
+pub fn set_reclaimer(reclaim: fn() -> bool) {
+    unsafe { RECLAIMER = Some(reclaim); }
 }
 
 /// Free a physical page
@@ -183,7 +284,44 @@ pub fn free_page(phys_addr: u64) {
 ///
 /// Returns the physical address of the first page, or None if not available.
 pub fn alloc_contiguous(count: usize) -> Option<u64> {
-    PHYS_ALLOCATOR.lock().alloc_contiguous(count)
+    PHYS_ALLOCATOR.lock().alloc_contiguous(count, 1)
+}
+
+/// Allocate a 2MB-aligned block of 512 pages for a 2MB page mapping
+///
+/// Returns the physical address of the block, or None if no aligned run
+/// is free.
+pub fn alloc_large_page() -> Option<u64> {
+    let pages = LARGE_PAGE_SIZE / PAGE_SIZE;
+    PHYS_ALLOCATOR.lock().alloc_contiguous(pages, pages)
+}
+
+/// Free a block from [`alloc_large_page`]
+pub fn free_large_page(phys_addr: u64) {
+    let mut allocator = PHYS_ALLOCATOR.lock();
+    for i in 0..LARGE_PAGE_SIZE / PAGE_SIZE {
+        allocator.free_page(phys_addr + (i * PAGE_SIZE) as u64);
+    }
+}
+
+/// Take a run of free pages for free page reporting
+///
+/// Finds at least `min` contiguous free pages that were not reported since
+/// they were last allocated, and marks up to `max` of them used so nothing
+/// allocates them while the hypervisor discards their contents. Hand them
+/// back with [`finish_report`].
+///
+/// Returns the physical address of the first page and the page count.
+pub fn take_unreported(min: usize, max: usize) -> Option<(u64, usize)> {
+    PHYS_ALLOCATOR.lock().take_unreported(min, max)
+}
+
+/// Free pages taken with [`take_unreported`]
+///
+/// `reported` says whether the hypervisor accepted them; reported pages
+/// are skipped by later calls until they are allocated again.
+pub fn finish_report(phys_addr: u64, count: usize, reported: bool) {
+    PHYS_ALLOCATOR.lock().finish_report(phys_addr, count, reported);
 }
 
 /// Get physical memory statistics
@@ -193,6 +331,7 @@ pub fn stats() -> PhysStats {
         total_pages: alloc.total_pages,
         free_pages: alloc.free_pages,
         used_pages: alloc.total_pages - alloc.free_pages,
+        reported_pages: alloc.reported_pages,
     }
 }
 
@@ -205,6 +344,8 @@ pub struct PhysStats {
     pub free_pages: usize,
     /// Pages currently in use
     pub used_pages: usize,
+    /// Free pages the hypervisor has been told it may discard
+    pub reported_pages: usize,
 }
 
 impl PhysStats {
@@ -222,4 +363,9 @@ impl PhysStats {
     pub fn used_bytes(&self) -> usize {
         self.used_pages * PAGE_SIZE
     }
+
+    /// Reported free memory in bytes
+    pub fn reported_bytes(&self) -> usize {
+        self.reported_pages * PAGE_SIZE
+    }
 }
@@ -2,6 +2,13 @@
 //!
 //! Provides safe mechanisms for the kernel to access user space memory.
 //! All syscalls that receive user pointers MUST validate them using these functions.
+//!
+//! With SMAP on (see [`set_smap`]) the kernel faults on touching a user
+//! page, except inside a [`UserAccess`] window, which sets EFLAGS.AC with
+//! `stac` and clears it again with `clac`. The copy functions here open one
+//! for just the copy.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Maximum user space address (canonical form for x86_64)
 /// User space: 0x0000_0000_0000_0000 - 0x0000_7FFF_FFFF_FFFF
@@ -25,6 +32,52 @@ pub enum UserAccessError {
     InvalidUtf8,
 }
 
+/// Whether the CPU enforces SMAP, so `stac`/`clac` exist and matter
+static SMAP: AtomicBool = AtomicBool::new(false);
+/// Open `UserAccess` windows; AC is set while this is non-zero
+static WINDOWS: AtomicUsize = AtomicUsize::new(0);
+
+/// Record that CR4.SMAP is set. Call once, after turning it on and before
+/// any user memory is touched.
+pub fn set_smap(on: bool) {
+    SMAP.store(on, Ordering::SeqCst);
+}
+
+/// Is SMAP enforced?
+pub fn smap() -> bool {
+    SMAP.load(Ordering::Relaxed)
+}
+
+/// A window in which the kernel may touch user pages
+///
+/// Windows nest; AC is cleared when the outermost one is dropped. Keep them
+/// short, and never hold one across a switch to another process.
+pub struct UserAccess(());
+
+impl UserAccess {
+    pub fn open() -> Self {
+        if WINDOWS.fetch_add(1, Ordering::SeqCst) == 0 && smap() {
+            unsafe { core::arch::asm!("stac", options(nomem, nostack)); }
+        }
+        UserAccess(())
+    }
+}
+
+impl Drop for UserAccess {
+    fn drop(&mut self) {
+        if WINDOWS.fetch_sub(1, Ordering::SeqCst) == 1 && smap() {
+            unsafe { core::arch::asm!("clac", options(nomem, nostack)); }
+        }
+    }
+}
+
+/// Forget every open window, for paths that leave the kernel without
+/// unwinding (a process exits or is replaced mid-syscall). AC itself comes
+/// from the RFLAGS the next IRETQ restores.
+pub fn reset_windows() {
+    WINDOWS.store(0, Ordering::SeqCst);
+}
+
 /// Validate that a user pointer range is accessible
 ///
 /// # Arguments
@@ -84,17 +137,21 @@ pub fn read_user_string(ptr: u64, max_len: u64) -> Result<alloc::string::String,
     validate_user_ptr(ptr, max_len)?;
     
     // Safety: We've validated the pointer is in user space
-    let slice = unsafe {
-        core::slice::from_raw_parts(ptr as *const u8, max_len as usize)
+    let bytes = {
+        let _access = UserAccess::open();
+        let slice = unsafe {
+            core::slice::from_raw_parts(ptr as *const u8, max_len as usize)
+        };
+
+        // Find null terminator or use max_len
+        let len = slice.iter()
+            .position(|&b| b == 0)
+            .unwrap_or(max_len as usize);
+        slice[..len].to_vec()
     };
     
-    // Find null terminator or use max_len
-    let len = slice.iter()
-        .position(|&b| b == 0)
-        .unwrap_or(max_len as usize);
-    
     // Convert to string, validating UTF-8
-    String::from_utf8(slice[..len].to_vec())
+    String::from_utf8(bytes)
         .map_err(|_| UserAccessError::InvalidUtf8)
 }
 
@@ -114,6 +171,7 @@ pub fn copy_from_user(user_ptr: u64, kernel_buf: &mut [u8]) -> Result<(), UserAc
     validate_user_ptr(user_ptr, kernel_buf.len() as u64)?;
     
     // Safety: We've validated the user pointer
+    let _access = UserAccess::open();
     let user_slice = unsafe {
         core::slice::from_raw_parts(user_ptr as *const u8, kernel_buf.len())
     };
@@ -138,6 +196,7 @@ pub fn copy_to_user(kernel_buf: &[u8], user_ptr: u64) -> Result<(), UserAccessEr
     validate_user_ptr(user_ptr, kernel_buf.len() as u64)?;
     
     // Safety: We've validated the user pointer
+    let _access = UserAccess::open();
     let user_slice = unsafe {
         core::slice::from_raw_parts_mut(user_ptr as *mut u8, kernel_buf.len())
     };
@@ -146,6 +205,31 @@ pub fn copy_to_user(kernel_buf: &[u8], user_ptr: u64) -> Result<(), UserAccessEr
     Ok(())
 }
 
+/// Read a `T` from user space
+///
+/// `T` must be valid for any bit pattern (plain integers and `repr(C)`
+/// structs of them).
+pub fn read_user<T: Copy>(user_ptr: u64) -> Result<T, UserAccessError> {
+    validate_user_ptr(user_ptr, core::mem::size_of::<T>() as u64)?;
+    let _access = UserAccess::open();
+    Ok(unsafe { core::ptr::read_unaligned(user_ptr as *const T) })
+}
+
+/// Write a `T` to user space
+pub fn write_user<T: Copy>(user_ptr: u64, value: T) -> Result<(), UserAccessError> {
+    validate_user_ptr(user_ptr, core::mem::size_of::<T>() as u64)?;
+    let _access = UserAccess::open();
+    unsafe { core::ptr::write_unaligned(user_ptr as *mut T, value); }
+    Ok(())
+}
+
+/// Copy `len` bytes from user space into a new vector
+pub fn read_user_bytes(user_ptr: u64, len: usize) -> Result<alloc::vec::Vec<u8>, UserAccessError> {
+    let mut buf = alloc::vec![0u8; len];
+    copy_from_user(user_ptr, &mut buf)?;
+    Ok(buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
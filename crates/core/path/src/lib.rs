@@ -17,6 +17,7 @@ extern crate alloc;
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::format;
+use core::cmp::Ordering;
 
 /// Path separator (Unix-style, canonical)
 pub const SEPARATOR: char = '/';
@@ -294,6 +295,82 @@ pub fn is_valid_name(name: &str) -> bool {
         && name.len() <= MAX_FILENAME
 }
 
+/// How path strings are compared
+///
+/// Which one applies depends on the filesystem: FAT ignores case, WFS
+/// does not. The VFS records the right one for each mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathCmp {
+    /// Names must match exactly
+    #[default]
+    CaseSensitive,
+    /// Names match regardless of letter case
+    CaseInsensitive,
+}
+
+impl PathCmp {
+    /// Comparison for a `case_insensitive` flag
+    pub fn new(case_insensitive: bool) -> Self {
+        if case_insensitive {
+            PathCmp::CaseInsensitive
+        } else {
+            PathCmp::CaseSensitive
+        }
+    }
+
+    /// Check if this comparison ignores case
+    pub fn is_case_insensitive(self) -> bool {
+        self == PathCmp::CaseInsensitive
+    }
+
+    fn chars_eq(self, a: char, b: char) -> bool {
+        a == b || (self.is_case_insensitive() && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    /// Compare two strings for equality
+    pub fn eq(self, a: &str, b: &str) -> bool {
+        match self {
+            PathCmp::CaseSensitive => a == b,
+            PathCmp::CaseInsensitive => eq_ignore_case(a, b),
+        }
+    }
+
+    /// Order two strings, e.g. for sorted directory listings
+    pub fn cmp(self, a: &str, b: &str) -> Ordering {
+        match self {
+            PathCmp::CaseSensitive => a.cmp(b),
+            PathCmp::CaseInsensitive => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    /// Check if `path` is `prefix` or lies below it
+    ///
+    /// Whole components only: `/apps` is a prefix of `/apps/ls` but not
+    /// of `/applications`.
+    pub fn starts_with(self, path: &str, prefix: &str) -> bool {
+        let mut rest = path.chars();
+        for p in prefix.chars() {
+            match rest.next() {
+                Some(c) if self.chars_eq(c, p) => {}
+                _ => return false,
+            }
+        }
+        prefix.ends_with(SEPARATOR) || matches!(rest.next(), None | Some(SEPARATOR))
+    }
+}
+
+/// Compare two strings for equality, ignoring letter case
+///
+/// Does not allocate, unlike comparing `to_lowercase()` copies.
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
 /// Compare two paths for equality (case-insensitive on Windows, case-sensitive on Unix)
 pub fn paths_equal(a: &str, b: &str, case_insensitive: bool) -> bool {
     let parsed_a = parse(a);
@@ -304,21 +381,12 @@ pub fn paths_equal(a: &str, b: &str, case_insensitive: bool) -> bool {
         return false;
     }
 
-    // Compare paths
-    if case_insensitive {
-        parsed_a.path.to_lowercase() == parsed_b.path.to_lowercase()
-    } else {
-        parsed_a.path == parsed_b.path
-    }
+    PathCmp::new(case_insensitive).eq(&parsed_a.path, &parsed_b.path)
 }
 
 /// Compare two filenames for equality (case-insensitive option)
 pub fn filenames_equal(a: &str, b: &str, case_insensitive: bool) -> bool {
-    if case_insensitive {
-        a.to_lowercase() == b.to_lowercase()
-    } else {
-        a == b
-    }
+    PathCmp::new(case_insensitive).eq(a, b)
 }
 
 /// Get path components as a vector
@@ -337,3 +405,36 @@ pub fn is_absolute(path: &str) -> bool {
 pub fn is_relative(path: &str) -> bool {
     !is_absolute(path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq_ignore_case() {
+        assert!(eq_ignore_case("README.TXT", "readme.txt"));
+        assert!(eq_ignore_case("Ärger", "äRGER"));
+        assert!(!eq_ignore_case("readme", "readme.txt"));
+        assert!(paths_equal("C:\\Apps\\LS", "c:/apps/ls", true));
+        assert!(!paths_equal("C:\\Apps\\LS", "c:/apps/ls", false));
+    }
+
+    #[test]
+    fn test_path_cmp() {
+        let ci = PathCmp::CaseInsensitive;
+        let cs = PathCmp::CaseSensitive;
+
+        assert!(ci.eq("/Apps", "/apps"));
+        assert!(!cs.eq("/Apps", "/apps"));
+        assert_eq!(ci.cmp("b", "A"), Ordering::Greater);
+        assert_eq!(cs.cmp("b", "A"), Ordering::Greater);
+        assert_eq!(cs.cmp("a", "B"), Ordering::Greater);
+        assert_eq!(ci.cmp("a", "B"), Ordering::Less);
+
+        assert!(ci.starts_with("/APPS/ls", "/apps"));
+        assert!(ci.starts_with("/apps", "/Apps"));
+        assert!(ci.starts_with("/apps", "/"));
+        assert!(!ci.starts_with("/applications", "/apps"));
+        assert!(!cs.starts_with("/APPS/ls", "/apps"));
+    }
+}
@@ -0,0 +1,337 @@
+//! WATOS Kernel Locks
+//!
+//! [`Mutex`] is the kernel's spinlock. Normally it is `spin::Mutex`; with
+//! the `lock-debug` feature it is [`DebugMutex`], which turns the silent
+//! hangs of lock bugs into panics that say what happened:
+//!
+//! - Each lock records its holder (the owner set with `set_owner_lookup`,
+//!   the current PID in the kernel), the call site that took it, and when.
+//! - Taking a lock its own owner already holds panics at once. On one CPU
+//!   that is a recursive lock, or an interrupt handler taking a lock the
+//!   code it interrupted holds; either would spin forever.
+//! - Waiting longer than `DEADLOCK_CYCLES` for any lock panics with the
+//!   holder's call site and how long it has held it.
+//! - Taking lock B while holding lock A records the order A before B; if B
+//!   before A was seen earlier, that is a lock order inversion and panics
+//!   with both sites.
+//!
+//! Locks are identified by address, so a lock that moves while unlocked is
+//! a new lock to the order checks. The tracking tables are fixed size;
+//! orders beyond `MAX_ORDERS` are not recorded.
+//!
+//! `DebugMutex` is always available, so a single lock can be watched
+//! without building the whole kernel with the feature.
+//!
+//! For read-mostly data there is [`Rcu`], whose readers take no lock.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+pub mod rcu;
+
+pub use rcu::Rcu;
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+
+#[cfg(not(feature = "lock-debug"))]
+pub use spin::{Mutex, MutexGuard};
+
+#[cfg(feature = "lock-debug")]
+pub type Mutex<T> = DebugMutex<T>;
+#[cfg(feature = "lock-debug")]
+pub type MutexGuard<'a, T> = DebugMutexGuard<'a, T>;
+
+/// TSC cycles to wait for a lock before calling it a deadlock (a few
+/// seconds at common clock rates)
+pub const DEADLOCK_CYCLES: u64 = 10_000_000_000;
+
+/// Locks one context can hold at once and still be order checked
+pub const MAX_HELD: usize = 16;
+
+/// Lock orders (A taken before B) remembered
+pub const MAX_ORDERS: usize = 256;
+
+/// Kernel callback naming the current owner (0 until set)
+static mut OWNER_LOOKUP: Option<fn() -> u32> = None;
+
+/// Install the function that says who is taking a lock, e.g. the current
+/// PID. Locks taken twice by the same owner are reported as recursive.
+pub fn set_owner_lookup(lookup: fn() -> u32) {
+    unsafe { OWNER_LOOKUP = Some(lookup); }
+}
+
+fn current_owner() -> u32 {
+    match unsafe { OWNER_LOOKUP } {
+        Some(lookup) => lookup(),
+        None => 0,
+    }
+}
+
+fn rdtsc() -> u64 {
+    let (lo, hi): (u32, u32);
+    unsafe {
+        core::arch::asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    }
+    ((hi as u64) << 32) | lo as u64
+}
+
+// ============================================================================
+// Lock order tracking
+// ============================================================================
+
+type Site = &'static Location<'static>;
+
+struct Order {
+    first: usize,
+    second: usize,
+    /// Where `second` was taken while `first` was held
+    site: Site,
+}
+
+struct Tracker {
+    held: [Option<(usize, Site)>; MAX_HELD],
+    orders: [Option<Order>; MAX_ORDERS],
+}
+
+/// Locks held and orders seen, for the whole (single CPU) kernel. Skipped
+/// when busy rather than waited on, so an interrupt cannot deadlock on it.
+static TRACKER: spin::Mutex<Tracker> = spin::Mutex::new(Tracker {
+    held: [None; MAX_HELD],
+    orders: [const { None }; MAX_ORDERS],
+});
+
+/// An inversion found when taking a lock: (held lock's site, earlier
+/// opposite order's site)
+fn check_order(lock: usize, site: Site) -> Option<(Site, Site)> {
+    let mut tracker = TRACKER.try_lock()?;
+    let Tracker { held, orders } = &mut *tracker;
+    for &(first, first_site) in held.iter().flatten() {
+        if first == lock {
+            continue;
+        }
+        let opposite = orders.iter().flatten().find(|o| o.first == lock && o.second == first);
+        if let Some(opposite) = opposite {
+            return Some((first_site, opposite.site));
+        }
+        let known = orders.iter().flatten().any(|o| o.first == first && o.second == lock);
+        if !known {
+            if let Some(slot) = orders.iter_mut().find(|o| o.is_none()) {
+                *slot = Some(Order { first, second: lock, site });
+            }
+        }
+    }
+    None
+}
+
+fn note_held(lock: usize, site: Site) {
+    if let Some(mut tracker) = TRACKER.try_lock() {
+        if let Some(slot) = tracker.held.iter_mut().find(|h| h.is_none()) {
+            *slot = Some((lock, site));
+        }
+    }
+}
+
+fn note_released(lock: usize) {
+    if let Some(mut tracker) = TRACKER.try_lock() {
+        if let Some(slot) = tracker.held.iter_mut().find(|h| h.is_some_and(|(l, _)| l == lock)) {
+            *slot = None;
+        }
+    }
+}
+
+// ============================================================================
+// DebugMutex
+// ============================================================================
+
+/// A spinlock that records who holds it and panics instead of deadlocking
+pub struct DebugMutex<T: ?Sized> {
+    locked: AtomicBool,
+    owner: AtomicU32,
+    site: AtomicPtr<Location<'static>>,
+    acquired_at: AtomicU64,
+    max_hold: AtomicU64,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for DebugMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for DebugMutex<T> {}
+
+/// Access to the data of a locked [`DebugMutex`]; unlocks when dropped
+pub struct DebugMutexGuard<'a, T: ?Sized> {
+    lock: &'a DebugMutex<T>,
+}
+
+impl<T> DebugMutex<T> {
+    pub const fn new(data: T) -> Self {
+        DebugMutex {
+            locked: AtomicBool::new(false),
+            owner: AtomicU32::new(0),
+            site: AtomicPtr::new(core::ptr::null_mut()),
+            acquired_at: AtomicU64::new(0),
+            max_hold: AtomicU64::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> DebugMutex<T> {
+    fn id(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    /// Take the lock, panicking on recursion, order inversion, or a wait
+    /// longer than `DEADLOCK_CYCLES`
+    #[track_caller]
+    pub fn lock(&self) -> DebugMutexGuard<'_, T> {
+        let site = Location::caller();
+        let owner = current_owner();
+        if let Some((held_site, opposite_site)) = check_order(self.id(), site) {
+            panic!(
+                "lock order inversion: lock {:#x} taken at {} while holding a lock taken at {}; \
+                 the opposite order was taken at {}",
+                self.id(), site, held_site, opposite_site
+            );
+        }
+        let start = rdtsc();
+        while !self.try_acquire() {
+            if self.owner.load(Ordering::Relaxed) == owner {
+                panic!(
+                    "recursive lock: lock {:#x} taken at {} is already held by owner {} since {}",
+                    self.id(), site, owner, self.holder_site()
+                );
+            }
+            let now = rdtsc();
+            if now.wrapping_sub(start) > DEADLOCK_CYCLES {
+                panic!(
+                    "deadlock: lock {:#x} wanted at {} by owner {}, held by owner {} since {} for {} cycles",
+                    self.id(), site, owner, self.owner.load(Ordering::Relaxed), self.holder_site(),
+                    now.wrapping_sub(self.acquired_at.load(Ordering::Relaxed))
+                );
+            }
+            core::hint::spin_loop();
+        }
+        self.acquired(owner, site)
+    }
+
+    /// Take the lock if it is free
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<DebugMutexGuard<'_, T>> {
+        let site = Location::caller();
+        self.try_acquire().then(|| self.acquired(current_owner(), site))
+    }
+
+    /// Is the lock held?
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// Longest time the lock has been held, in TSC cycles
+    pub fn max_hold_cycles(&self) -> u64 {
+        self.max_hold.load(Ordering::Relaxed)
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    fn acquired(&self, owner: u32, site: Site) -> DebugMutexGuard<'_, T> {
+        self.owner.store(owner, Ordering::Relaxed);
+        self.site.store(site as *const _ as *mut _, Ordering::Relaxed);
+        self.acquired_at.store(rdtsc(), Ordering::Relaxed);
+        note_held(self.id(), site);
+        DebugMutexGuard { lock: self }
+    }
+
+    fn holder_site(&self) -> &'static Location<'static> {
+        let site = self.site.load(Ordering::Relaxed);
+        if site.is_null() {
+            Location::caller()
+        } else {
+            unsafe { &*site }
+        }
+    }
+}
+
+impl<T: Default> Default for DebugMutex<T> {
+    fn default() -> Self {
+        DebugMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for DebugMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for DebugMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for DebugMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let lock = self.lock;
+        let held = rdtsc().wrapping_sub(lock.acquired_at.load(Ordering::Relaxed));
+        lock.max_hold.fetch_max(held, Ordering::Relaxed);
+        note_released(lock.id());
+        lock.owner.store(0, Ordering::Relaxed);
+        lock.site.store(core::ptr::null_mut(), Ordering::Relaxed);
+        lock.locked.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_lock_records_hold_time() {
+        let lock = DebugMutex::new(5u32);
+        *lock.lock() += 1;
+        assert_eq!(*lock.lock(), 6);
+        assert!(lock.max_hold_cycles() > 0);
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.is_locked() && lock.try_lock().is_none());
+        drop(guard);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn test_recursion_and_inversion_panic() {
+        let a = DebugMutex::new(());
+        let b = DebugMutex::new(());
+
+        let recursive = catch_unwind(AssertUnwindSafe(|| {
+            let _first = a.lock();
+            let _second = a.lock();
+        }));
+        assert!(recursive.is_err());
+        assert!(!a.is_locked());
+
+        {
+            let _a = a.lock();
+            let _b = b.lock();
+        }
+        let inverted = catch_unwind(AssertUnwindSafe(|| {
+            let _b = b.lock();
+            let _a = a.lock();
+        }));
+        assert!(inverted.is_err());
+        assert!(!a.is_locked() && !b.is_locked());
+    }
+}
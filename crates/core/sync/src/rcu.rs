@@ -0,0 +1,122 @@
+//! Read-copy-update cell
+//!
+//! For data that is read on every operation and changed rarely, like the
+//! mount table. Readers take a snapshot (`read`): two atomic counter
+//! updates and a reference count, no lock, and never a wait. Writers copy
+//! the current value, change the copy and publish it; readers holding the
+//! old snapshot keep using it, and it is freed when the last one drops it.
+//!
+//! Publishing waits out a grace period: readers still between loading the
+//! old pointer and taking their reference. That window is a few
+//! instructions, but a writer running in an interrupt handler that cut into
+//! it would wait forever, so writers must not run in interrupt handlers.
+
+use alloc::sync::Arc;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// A value read without locking and replaced by copy and publish
+pub struct Rcu<T> {
+    /// The published value (`Arc::into_raw`), null until the first one
+    current: AtomicPtr<T>,
+    /// Readers between loading `current` and owning a reference
+    readers: AtomicUsize,
+    /// Serializes writers, so no update is lost
+    writer: spin::Mutex<()>,
+}
+
+unsafe impl<T: Send + Sync> Send for Rcu<T> {}
+unsafe impl<T: Send + Sync> Sync for Rcu<T> {}
+
+impl<T> Rcu<T> {
+    /// An empty cell; `read` returns None until a value is published
+    pub const fn new() -> Self {
+        Rcu {
+            current: AtomicPtr::new(ptr::null_mut()),
+            readers: AtomicUsize::new(0),
+            writer: spin::Mutex::new(()),
+        }
+    }
+
+    /// Snapshot of the current value
+    pub fn read(&self) -> Option<Arc<T>> {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        let current = self.current.load(Ordering::SeqCst);
+        let snapshot = (!current.is_null()).then(|| unsafe {
+            Arc::increment_strong_count(current);
+            Arc::from_raw(current)
+        });
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+        snapshot
+    }
+
+    /// Replace the value outright
+    pub fn publish(&self, value: T) {
+        let _writer = self.writer.lock();
+        self.replace(Arc::new(value));
+    }
+
+    /// Change a copy of the value with `f` and publish it if `f` succeeds.
+    /// None if nothing was ever published.
+    pub fn update<R, E>(&self, f: impl FnOnce(&mut T) -> Result<R, E>) -> Option<Result<R, E>>
+    where
+        T: Clone,
+    {
+        let _writer = self.writer.lock();
+        let mut copy = T::clone(&*self.read()?);
+        let result = f(&mut copy);
+        if result.is_ok() {
+            self.replace(Arc::new(copy));
+        }
+        Some(result)
+    }
+
+    fn replace(&self, value: Arc<T>) {
+        let old = self.current.swap(Arc::into_raw(value) as *mut T, Ordering::SeqCst);
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            core::hint::spin_loop();
+        }
+        if !old.is_null() {
+            drop(unsafe { Arc::from_raw(old) });
+        }
+    }
+}
+
+impl<T> Default for Rcu<T> {
+    fn default() -> Self {
+        Rcu::new()
+    }
+}
+
+impl<T> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        if !current.is_null() {
+            drop(unsafe { Arc::from_raw(current) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_snapshots_outlive_updates() {
+        let cell: Rcu<Vec<u32>> = Rcu::new();
+        assert!(cell.read().is_none());
+        assert!(cell.update(|v| { v.push(1); Ok::<_, ()>(()) }).is_none());
+
+        cell.publish(vec![1]);
+        let before = cell.read().unwrap();
+        assert_eq!(cell.update(|v| { v.push(2); Ok::<_, ()>(()) }), Some(Ok(())));
+        assert_eq!(cell.update(|v| { v.push(3); Err::<(), _>("no") }), Some(Err("no")));
+
+        assert_eq!(*before, vec![1]);
+        assert_eq!(*cell.read().unwrap(), vec![1, 2]);
+        drop(before);
+        assert_eq!(Arc::strong_count(&cell.read().unwrap()), 2);
+    }
+}
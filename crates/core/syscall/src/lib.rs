@@ -20,6 +20,7 @@ pub mod numbers {
     pub const SYS_EXIT: u32 = 6;
 
     // System
+    pub const SYS_IDLE: u32 = 10;          // Halt until the next interrupt (timer, key, mouse, NIC)
     pub const SYS_SLEEP: u32 = 11;
     pub const SYS_GETPID: u32 = 12;
     pub const SYS_TIME: u32 = 13;
@@ -67,6 +68,13 @@ pub mod numbers {
     pub const SYS_FB_INFO: u32 = 50;       // Get framebuffer info (returns BootInfo ptr)
     pub const SYS_FB_ADDR: u32 = 51;       // Get framebuffer address
     pub const SYS_FB_DIMENSIONS: u32 = 52; // Get width/height/pitch
+    pub const SYS_SCREENSHOT: u32 = 53;    // Save the screen to a file (path, len); .png = PNG, else BMP
+    pub const SYS_SETFONT: u32 = 54;       // Console font (path, len, scale); len 0 = built-in 8x16
+    pub const SYS_FB_SET_MODE: u32 = 55;   // Switch resolution (width, height) to a GOP mode from boot
+    pub const SYS_MOUSE_POLL: u32 = 56;    // Apply mouse motion to the cursor; (buttons << 32) | (y << 16) | x
+    pub const SYS_CURSOR_SHOW: u32 = 57;   // Show/hide the cursor (visible, refresh)
+    pub const SYS_CURSOR_SPRITE: u32 = 58; // Cursor image (ptr, 32*32, (hot_x << 16) | hot_y)
+    pub const SYS_FB_MAP: u32 = 59;        // Map the framebuffer into this process (root or video group) -> address
 
     // Raw keyboard (PS/2 scancodes)
     pub const SYS_READ_SCANCODE: u32 = 60; // Read raw keyboard scancode (non-blocking)
@@ -87,6 +95,8 @@ pub mod numbers {
     pub const SYS_READLINK: u32 = 87;      // Read symbolic link target
     pub const SYS_MKFIFO: u32 = 88;        // Create named pipe (FIFO)
     pub const SYS_STATFS: u32 = 89;        // Get filesystem statistics
+    pub const SYS_REALPATH: u32 = 93;      // Canonical absolute path (path, len, buf, buf_len)
+    pub const SYS_WATCH: u32 = 94;         // Watch a directory (path, len, mask) -> event fd
 
     // Permission operations
     pub const SYS_CHMOD: u32 = 140;        // Change file mode (path, mode)
@@ -95,9 +105,121 @@ pub mod numbers {
 
     // Process execution
     pub const SYS_EXEC: u32 = 80;          // Execute program (replace current process)
-    pub const SYS_SPAWN: u32 = 81;         // Spawn new process
+    pub const SYS_SPAWN: u32 = 81;         // Spawn new process (argv blocks_ptr, blocks_len)
     pub const SYS_WAIT: u32 = 82;          // Wait for child process
     pub const SYS_GETARGS: u32 = 83;       // Get command line arguments (copies to buffer)
+    pub const SYS_GETRUSAGE: u32 = 84;     // Get CPU usage (pid, buf_ptr -> [user_ms, system_ms]), 0 = self
+    pub const SYS_GETMEMUSAGE: u32 = 155;  // Get process memory usage (pid, buf_ptr -> u64[6]), 0 = self
+    pub const SYS_SETRLIMIT: u32 = 156;    // Set a resource limit of the current process (resource, limit)
+    pub const SYS_GETRLIMIT: u32 = 157;    // Get a resource limit (resource, buf_ptr -> u64)
+    pub const SYS_SETPRIORITY: u32 = 158;  // Set niceness -20..19 (pid, nice), 0 = self
+    pub const SYS_GETPRIORITY: u32 = 159;  // Get 20 - niceness (pid), 0 = self
+
+    // Process groups and job control
+    pub const SYS_SETPGID: u32 = 150;      // Set process group (pid, pgid), 0 = self
+    pub const SYS_GETPGID: u32 = 151;      // Get process group (pid), 0 = self
+    pub const SYS_KILL: u32 = 152;         // Send signal (pid, sig), negative pid = process group
+    pub const SYS_TCSETPGRP: u32 = 153;    // Set foreground process group of the console
+    pub const SYS_TCGETPGRP: u32 = 154;    // Get foreground process group of the console
+
+    // File descriptor operations
+    pub const SYS_PIPE: u32 = 160;         // Create pipe (fds_ptr -> [read_fd, write_fd], flags)
+    pub const SYS_DUP: u32 = 161;          // Duplicate fd onto lowest free fd >= 3
+    pub const SYS_DUP2: u32 = 162;         // Duplicate fd onto a specific fd (old_fd, new_fd)
+    pub const SYS_OPENPTY: u32 = 163;      // Create pty pair (fds_ptr -> [master_fd, slave_fd])
+
+    // Clipboard
+    pub const SYS_CLIPBOARD_SET: u32 = 170; // Replace contents (type_ptr, type_len, data_ptr, data_len)
+    pub const SYS_CLIPBOARD_GET: u32 = 171; // Read contents of a type (type_ptr, type_len, buf_ptr, buf_len) -> full length
+
+    // Audio output (see `audio`)
+    pub const SYS_AUDIO_OPEN: u32 = 180;       // Open a playback stream -> handle
+    pub const SYS_AUDIO_SET_CONFIG: u32 = 181; // Set stream format (handle, sample_rate, channels | format << 8)
+    pub const SYS_AUDIO_WRITE: u32 = 182;      // Queue samples (handle, buf_ptr, len) -> bytes accepted
+    pub const SYS_AUDIO_AVAILABLE: u32 = 183;  // Bytes that can be written without blocking (handle)
+    pub const SYS_AUDIO_CLOSE: u32 = 184;      // Close a playback stream (handle)
+    pub const SYS_BEEP: u32 = 185;             // PC speaker tone (freq_hz, duration_ms); 0 Hz rests
+
+    // Gamepads (see `gamepad`)
+    pub const SYS_GAMEPAD_LIST: u32 = 186;     // Connected pads -> bit n set for pad n
+    pub const SYS_GAMEPAD_STATE: u32 = 187;    // Read a pad (pad, *mut GamepadInfo)
+
+    // Filesystem snapshots
+    pub const SYS_SNAPSHOT: u32 = 188;         // Snapshot a path (path_ptr, path_len, name_ptr, name_len)
+    pub const SYS_SNAPSHOT_LIST: u32 = 189;    // Names on a path's fs, one per line (path_ptr, path_len, buf_ptr, buf_len) -> bytes
+    pub const SYS_SNAPSHOT_MOUNT: u32 = 190;   // Mount read-only (path_ptr, path_len, name_ptr, name_len) -> drive letter
+
+    // Hard links
+    pub const SYS_LINK: u32 = 191;             // Second name for a file (old_ptr, old_len, new_ptr, new_len)
+
+    // Terminals
+    pub const SYS_ISATTY: u32 = 192;           // Is fd the console or a pty? (fd) -> 1 or 0
+
+    // Argument vectors (see `argv`)
+    pub const SYS_EXECV: u32 = 193;            // Run argv[0] with an argv (blocks_ptr, blocks_len)
+    pub const SYS_GETARGV: u32 = 194;          // Own argv as blocks (buf_ptr, buf_len) -> bytes needed
+
+    // Account enumeration (see `pwd`)
+    pub const SYS_GETPWENT: u32 = 195;         // passwd line of the nth user (index, buf_ptr, buf_len) -> len, 0 = end
+    pub const SYS_GETPWNAM: u32 = 196;         // passwd line by name (name_ptr, name_len, buf_ptr, buf_len) -> len, 0 = none
+    pub const SYS_GETGRENT: u32 = 197;         // group line of the nth group (index, buf_ptr, buf_len) -> len, 0 = end
+    pub const SYS_GETGRGID: u32 = 198;         // group line of a GID (gid, buf_ptr, buf_len) -> len, 0 = none
+    pub const SYS_GETGROUPS: u32 = 199;        // Supplementary GIDs (u32 buf_ptr, count) -> how many there are
+
+    // Keyring: per-user secrets, encrypted under the user's password
+    pub const SYS_KEY_ADD: u32 = 200;          // Store a key (name_ptr, name_len, data_ptr, data_len) -> 0
+    pub const SYS_KEY_READ: u32 = 201;         // Read a key (name_ptr, name_len, buf_ptr, buf_len) -> key length
+    pub const SYS_KEY_DELETE: u32 = 202;       // Delete a key (name_ptr, name_len, owner uid or u64::MAX for own) -> 0
+
+    // Swap (root only, except SYS_SWAPINFO)
+    pub const SYS_SWAPON: u32 = 203;           // Swap to a file (path_ptr, path_len, pages to format it with or 0) -> 0
+    pub const SYS_SWAPOFF: u32 = 204;          // Bring swapped pages back and stop swapping -> 0
+    pub const SYS_SWAPINFO: u32 = 205;         // Swap size and use in bytes (u64[2] buf_ptr) -> 0, u64::MAX if off
+
+    // Kernel-side copy between descriptors, no user buffer
+    pub const SYS_SENDFILE: u32 = 206;         // Copy from in_fd's position (out_fd, in_fd, count) -> bytes, u64::MAX on error
+
+    // Kernel tunables, named like /proc/sys paths with dots (kernel.loglevel)
+    pub const SYS_SYSCTL: u32 = 207;           // Read or set (name_ptr, name_len, 0 read / 1 write, value) -> value
+
+    // System statistics in one call (watos_syscall::sysinfo)
+    pub const SYS_SYSINFO: u32 = 208;          // Fill a SysInfo (buf_ptr, buf_len) -> bytes filled, u64::MAX on error
+
+    // Volume label and serial number, alongside SYS_STATFS (watos_syscall::volume)
+    pub const SYS_VOLINFO: u32 = 209;          // Fill a VolInfo (path_ptr, path_len, buf_ptr, buf_len) -> bytes filled, u64::MAX on error
+
+    // Drive letters, kept per volume across boots in C:/etc/drives
+    pub const SYS_DRIVE_ASSIGN: u32 = 210;     // Move a drive (from, to), root only -> 0, 1 bad letter, 2 not mounted, 3 taken, 4 denied
+
+    // Path to path file copy, a slice per call (watos_syscall::copyfile)
+    pub const SYS_COPYFILE: u32 = 211;         // Continue a CopyFile (req_ptr) -> 0 finished, 1 more to do, u64::MAX on error
+
+    // Per-drive recycle bin (X:/.trash)
+    pub const SYS_TRASH: u32 = 212;            // Move to the trash (path_ptr, path_len) -> 0, 1 no trash there (delete instead), u64::MAX on error
+    pub const SYS_TRASH_LIST: u32 = 213;       // List a drive's trash (drive, buf_ptr, buf_len) -> bytes, "ID\tDELETED\tPATH\n" lines
+    pub const SYS_TRASH_RESTORE: u32 = 214;    // Put back (drive, id_ptr, id_len) -> 0, 1 original path taken, u64::MAX on error
+    pub const SYS_TRASH_PURGE: u32 = 215;      // Delete for good (drive, id_ptr, id_len; 0 len = all) -> count, u64::MAX on error
+
+    // Timestamp and performance counters (watos_syscall::perfctr)
+    pub const SYS_PERFCTR: u32 = 216;          // (op, counter, event) -> per op, u64::MAX on error
+
+    // Keyboard
+    pub const SYS_KBD_REPEAT: u32 = 217;       // Key repeat (delay_ms, rate, set) -> delay << 16 | rate, u64::MAX out of range
+
+    // Plain-text copy of console output, for accessibility tools
+    pub const SYS_CONSOLE_MIRROR: u32 = 218;   // Read it (buf_ptr, buf_len) -> bytes, u64::MAX if another process reads it
+
+    // TCP/IP; sockets are fds and never block (watos_syscall::net)
+    pub const SYS_NET_CONNECT: u32 = 219;      // Open a connection (ipv4, port) -> fd, u64::MAX on error
+    pub const SYS_NET_LISTEN: u32 = 220;       // Listen (port) -> fd, u64::MAX if taken
+    pub const SYS_NET_ACCEPT: u32 = 221;       // Take a connection (listen_fd) -> fd, 0 none yet, u64::MAX on error
+    pub const SYS_NET_STATE: u32 = 222;        // Where a socket is (fd) -> net::STATE_*, u64::MAX not a socket
+    pub const SYS_NET_PEER: u32 = 223;         // Other end (fd) -> ipv4 << 16 | port, u64::MAX unknown
+    pub const SYS_NET_RESOLVE: u32 = 224;      // Start a DNS lookup (name_ptr, name_len) -> query, u64::MAX on error
+    pub const SYS_NET_RESOLVED: u32 = 225;     // Its answer (query) -> 1 << 32 | ipv4, 0 pending, u64::MAX failed
+
+    // Random bytes from the kernel pool
+    pub const SYS_GETRANDOM: u32 = 226;        // (buf_ptr, len up to 256) -> len, u64::MAX on error
 
     // Date/Time
     pub const SYS_GETDATE: u32 = 90;       // Get current date (year, month, day)
@@ -117,7 +239,7 @@ pub mod numbers {
     pub const SYS_GETUID: u32 = 122;       // Get current user ID
     pub const SYS_GETGID: u32 = 123;       // Get current group ID
     pub const SYS_SETGID: u32 = 124;       // Set current group ID
-    pub const SYS_GET_USER_INFO: u32 = 125; // Get user information by UID
+    pub const SYS_GET_USER_INFO: u32 = 125; // passwd line of a UID (uid, buf_ptr, buf_len) -> len, 0 = none
     pub const SYS_GETEUID: u32 = 126;      // Get effective user ID
     pub const SYS_GETEGID: u32 = 127;      // Get effective group ID
     pub const SYS_SETEUID: u32 = 128;      // Set effective user ID
@@ -140,6 +262,532 @@ pub mod numbers {
     pub const SYS_LISTENV: u32 = 139;        // List environment variables (buf_ptr, buf_len) -> num_vars
 }
 
+/// Signal numbers delivered by SYS_KILL
+pub mod signals {
+    pub const SIGINT: u32 = 2;    // Interrupt (Ctrl+C)
+    pub const SIGILL: u32 = 4;    // Illegal instruction
+    pub const SIGTRAP: u32 = 5;   // Breakpoint trap
+    pub const SIGBUS: u32 = 7;    // Misaligned memory access
+    pub const SIGFPE: u32 = 8;    // Arithmetic fault (e.g. divide by zero)
+    pub const SIGKILL: u32 = 9;   // Kill (cannot be caught)
+    pub const SIGSEGV: u32 = 11;  // Invalid memory access
+    pub const SIGTERM: u32 = 15;  // Terminate
+    pub const SIGCONT: u32 = 18;  // Continue a stopped process
+    pub const SIGSTOP: u32 = 19;  // Stop (cannot be caught)
+    pub const SIGTSTP: u32 = 20;  // Terminal stop (Ctrl+Z)
+}
+
+/// Options and status decoding for SYS_WAIT
+///
+/// The status word follows the traditional Unix layout:
+/// - exited:   low byte 0x00, exit code in bits 8..16
+/// - stopped:  low byte 0x7F, stop signal in bits 8..16
+/// - signaled: low 7 bits hold the terminating signal, bit 7 is set if a
+///   core file was written
+pub mod wait {
+    pub const WNOHANG: u32 = 1;    // Return immediately if no child has changed state
+    pub const WUNTRACED: u32 = 2;  // Also report stopped children
+    pub const WCOREFLAG: u32 = 0x80; // Status bit: the killed child dumped core
+
+    /// Child exited normally
+    pub fn exited(status: u32) -> bool {
+        status & 0x7F == 0
+    }
+
+    /// Exit code of a child that exited normally
+    pub fn exit_code(status: u32) -> i32 {
+        ((status >> 8) & 0xFF) as i32
+    }
+
+    /// Child is stopped (e.g. by SIGTSTP)
+    pub fn stopped(status: u32) -> bool {
+        status & 0xFF == 0x7F
+    }
+
+    /// Signal that stopped the child
+    pub fn stop_signal(status: u32) -> u32 {
+        (status >> 8) & 0xFF
+    }
+
+    /// Child was terminated by a signal
+    pub fn signaled(status: u32) -> bool {
+        !exited(status) && !stopped(status)
+    }
+
+    /// Signal that terminated the child
+    pub fn term_signal(status: u32) -> u32 {
+        status & 0x7F
+    }
+
+    /// A killed child left a core file behind
+    pub fn core_dumped(status: u32) -> bool {
+        signaled(status) && status & WCOREFLAG != 0
+    }
+}
+
+/// Flags for SYS_OPEN (the Linux values)
+///
+/// The low two bits pick the access mode; the rest are or-ed in. Without
+/// O_CREAT a missing file is an error, and O_EXCL makes O_CREAT fail if
+/// the file exists, so that a file can be created atomically. O_APPEND
+/// moves every write to the current end of the file. O_CLOEXEC is for
+/// SYS_PIPE, and keeps both ends out of SYS_SPAWN and SYS_EXEC children.
+pub mod open {
+    pub const O_RDONLY: u32 = 0x000;  // Read only
+    pub const O_WRONLY: u32 = 0x001;  // Write only
+    pub const O_RDWR: u32 = 0x002;    // Read and write
+    pub const O_ACCMODE: u32 = 0x003; // Mask for the access mode
+    pub const O_CREAT: u32 = 0x040;   // Create if missing
+    pub const O_EXCL: u32 = 0x080;    // With O_CREAT, fail if it exists
+    pub const O_TRUNC: u32 = 0x200;   // Truncate to 0 when opened for writing
+    pub const O_APPEND: u32 = 0x400;  // Write at the end
+    pub const O_CLOEXEC: u32 = 0x80000; // Not inherited by children
+}
+
+/// Auxiliary vector handed to a program interpreter
+///
+/// When a program has a PT_INTERP header, SYS_EXEC loads the interpreter
+/// too and enters it with rsp pointing at (key, value) u64 pairs ending in
+/// AT_NULL.
+pub mod auxv {
+    pub const AT_NULL: u64 = 0;          // End of the vector
+    pub const AT_PHDR: u64 = 3;          // Copy of the program's headers
+    pub const AT_PHENT: u64 = 4;         // Size of one program header
+    pub const AT_PHNUM: u64 = 5;         // Number of program headers
+    pub const AT_BASE: u64 = 7;          // Interpreter load bias
+    pub const AT_ENTRY: u64 = 9;         // Program entry point (relocated)
+    pub const AT_LOAD_BIAS: u64 = 0x1000; // Added to the program's vaddrs (0 unless PIE)
+}
+
+/// Argument vectors for SYS_EXECV, SYS_SPAWN and SYS_GETARGV
+///
+/// An argv travels as one length-prefixed block per argument: a u32
+/// little-endian byte count, then that many bytes of UTF-8. There is no
+/// terminator; the syscall passes the total length. argv[0] is the program
+/// name. Arguments may hold spaces, quotes or be empty, unlike the flat
+/// string from SYS_GETARGS (the arguments joined with spaces).
+pub mod argv {
+    /// Most arguments one argv may hold
+    pub const MAX_ARGS: usize = 64;
+    /// Largest encoded argv, prefixes included
+    pub const MAX_BYTES: usize = 4096;
+
+    const PREFIX: usize = 4;
+
+    /// Bytes `encode` needs for `args`
+    pub fn encoded_len<S: AsRef<str>>(args: &[S]) -> usize {
+        args.iter().map(|a| PREFIX + a.as_ref().len()).sum()
+    }
+
+    /// Encode `args` into `buf`; the encoded length, or None if it won't fit
+    pub fn encode<S: AsRef<str>>(args: &[S], buf: &mut [u8]) -> Option<usize> {
+        if encoded_len(args) > buf.len() {
+            return None;
+        }
+        let mut pos = 0;
+        for arg in args {
+            let bytes = arg.as_ref().as_bytes();
+            buf[pos..pos + PREFIX].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+            pos += PREFIX;
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        }
+        Some(pos)
+    }
+
+    /// The arguments in encoded blocks
+    ///
+    /// Iteration stops at a block that is cut short or isn't UTF-8; check
+    /// with `is_valid` first to tell that apart from the end.
+    pub fn decode(buf: &[u8]) -> Args<'_> {
+        Args { rest: buf }
+    }
+
+    /// Whether `buf` is a well-formed argv within the limits above
+    pub fn is_valid(buf: &[u8]) -> bool {
+        if buf.len() > MAX_BYTES {
+            return false;
+        }
+        let mut args = decode(buf);
+        let count = args.by_ref().take(MAX_ARGS + 1).count();
+        count <= MAX_ARGS && args.rest.is_empty()
+    }
+
+    /// Iterator made by `decode`
+    pub struct Args<'a> {
+        rest: &'a [u8],
+    }
+
+    impl<'a> Iterator for Args<'a> {
+        type Item = &'a str;
+
+        fn next(&mut self) -> Option<&'a str> {
+            if self.rest.len() < PREFIX {
+                return None;
+            }
+            let len = u32::from_le_bytes([self.rest[0], self.rest[1], self.rest[2], self.rest[3]]) as usize;
+            let body = self.rest.get(PREFIX..PREFIX + len)?;
+            let arg = core::str::from_utf8(body).ok()?;
+            self.rest = &self.rest[PREFIX + len..];
+            Some(arg)
+        }
+    }
+}
+
+/// Account entries from SYS_GETPWENT, SYS_GETGRENT and friends
+///
+/// The kernel hands back one line in the /etc/passwd or /etc/group format,
+/// with no newline; these parse it. A line longer than the buffer was cut
+/// short and won't parse.
+pub mod pwd {
+    /// `name:x:uid:gid:gecos:home:shell`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Passwd<'a> {
+        pub name: &'a str,
+        pub uid: u32,
+        pub gid: u32,
+        pub gecos: &'a str,
+        pub home: &'a str,
+        pub shell: &'a str,
+    }
+
+    impl<'a> Passwd<'a> {
+        pub fn parse(line: &'a [u8]) -> Option<Self> {
+            let line = core::str::from_utf8(line).ok()?;
+            let mut fields = line.splitn(7, ':');
+            let name = fields.next()?;
+            let _password = fields.next()?;
+            Some(Passwd {
+                name,
+                uid: fields.next()?.parse().ok()?,
+                gid: fields.next()?.parse().ok()?,
+                gecos: fields.next()?,
+                home: fields.next()?,
+                shell: fields.next()?,
+            })
+        }
+    }
+
+    /// `name:x:gid:member,member,...`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Group<'a> {
+        pub name: &'a str,
+        pub gid: u32,
+        members: &'a str,
+    }
+
+    impl<'a> Group<'a> {
+        pub fn parse(line: &'a [u8]) -> Option<Self> {
+            let line = core::str::from_utf8(line).ok()?;
+            let mut fields = line.splitn(4, ':');
+            let name = fields.next()?;
+            let _password = fields.next()?;
+            Some(Group {
+                name,
+                gid: fields.next()?.parse().ok()?,
+                members: fields.next()?,
+            })
+        }
+
+        /// Users with this as a supplementary group
+        pub fn members(&self) -> impl Iterator<Item = &'a str> {
+            self.members.split(',').filter(|m| !m.is_empty())
+        }
+    }
+}
+
+/// Resources for SYS_SETRLIMIT / SYS_GETRLIMIT
+///
+/// Limits are inherited by child processes.
+pub mod rlimit {
+    pub const RLIMIT_CORE: u32 = 4;          // Largest core file to write, in bytes (0 = none)
+    pub const RLIM_INFINITY: u64 = u64::MAX; // No limit
+}
+
+/// Event masks for SYS_WATCH
+///
+/// Reading the returned fd yields one line per event, "CODE NAME\n", where
+/// NAME is the entry inside the watched directory and CODE is:
+/// - `C` created, `D` deleted
+/// - `F` renamed away (moved from), `T` renamed here (moved to)
+/// - `W` closed after being opened for writing
+/// - `O` (no name) events were dropped because the queue filled up
+///
+/// Reads return 0 when no events are pending.
+pub mod watch {
+    pub const CREATE: u32 = 1;       // Entry created
+    pub const DELETE: u32 = 2;       // Entry deleted
+    pub const MOVED_FROM: u32 = 4;   // Entry renamed away
+    pub const MOVED_TO: u32 = 8;     // Entry renamed into the directory
+    pub const CLOSE_WRITE: u32 = 16; // File closed after being opened for writing
+    pub const ALL: u32 = CREATE | DELETE | MOVED_FROM | MOVED_TO | CLOSE_WRITE;
+}
+
+/// Sample formats for SYS_AUDIO_SET_CONFIG
+///
+/// A stream starts out in the device's current format (usually 44.1 kHz
+/// stereo S16LE). Samples are interleaved by channel. SYS_AUDIO_WRITE may
+/// take fewer bytes than offered when the device buffer is full; wait for
+/// SYS_AUDIO_AVAILABLE to grow and write the rest.
+pub mod audio {
+    pub const FORMAT_U8: u32 = 0;     // 8-bit unsigned
+    pub const FORMAT_S16LE: u32 = 1;  // 16-bit signed little-endian
+    pub const FORMAT_S16BE: u32 = 2;  // 16-bit signed big-endian
+    pub const FORMAT_F32: u32 = 3;    // 32-bit float
+}
+
+/// Gamepad state for SYS_GAMEPAD_STATE
+///
+/// Axes run from -32767 (left/up) to 32767 (right/down). A gameport
+/// joystick's stick A is axes 0/1 and buttons 0/1, stick B axes 2/3 and
+/// buttons 2/3. A USB pad's hat switch shows up as four buttons from
+/// `HAT_BUTTON`: up, right, down, left.
+pub mod gamepad {
+    pub const MAX_PADS: usize = 4;
+    pub const MAX_AXES: usize = 6;
+
+    pub const KIND_GAMEPORT: u8 = 1;
+    pub const KIND_USB: u8 = 2;
+
+    pub const HAT_BUTTON: u8 = 16;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct GamepadInfo {
+        pub kind: u8,
+        pub button_count: u8,
+        pub axis_count: u8,
+        pub reserved: u8,
+        pub buttons: u32,              // Bit n set while button n is down
+        pub axes: [i16; MAX_AXES],
+    }
+}
+
+/// Volume identification for SYS_VOLINFO
+///
+/// What DOS printed above a directory listing: the label (up to
+/// `LABEL_MAX` bytes, longer ones are cut) and the serial number, for
+/// filesystems that have them.
+pub mod volume {
+    pub const LABEL_MAX: usize = 32;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct VolInfo {
+        pub serial: u32,
+        pub has_serial: u8,     // 0 when the filesystem has no serial number
+        pub label_len: u8,      // 0 when the volume is unlabelled
+        pub reserved: [u8; 2],
+        pub label: [u8; LABEL_MAX],
+    }
+
+    impl VolInfo {
+        /// The label, None when there is none
+        pub fn label(&self) -> Option<&str> {
+            let len = (self.label_len as usize).min(LABEL_MAX);
+            match len {
+                0 => None,
+                _ => core::str::from_utf8(&self.label[..len]).ok(),
+            }
+        }
+
+        /// The serial number, None when there is none
+        pub fn serial(&self) -> Option<u32> {
+            (self.has_serial != 0).then_some(self.serial)
+        }
+    }
+}
+
+/// File copy for SYS_COPYFILE
+///
+/// The kernel copies the file a `STEP` at a time, so a caller can show
+/// progress between calls: each call continues from `done`, then updates
+/// `done` and `total`. The first call (`done` 0) replaces the destination;
+/// the last one gives it the source's mode and times. A copy that fails is
+/// removed.
+pub mod copyfile {
+    /// Most bytes one call copies
+    pub const STEP: u64 = 1 << 20;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct CopyFile {
+        pub src_ptr: u64,
+        pub src_len: u64,
+        pub dst_ptr: u64,
+        pub dst_len: u64,
+        pub done: u64,          // Bytes copied so far
+        pub total: u64,         // Size of the source
+    }
+
+    impl CopyFile {
+        pub fn new(src: &str, dst: &str) -> Self {
+            CopyFile {
+                src_ptr: src.as_ptr() as u64,
+                src_len: src.len() as u64,
+                dst_ptr: dst.as_ptr() as u64,
+                dst_len: dst.len() as u64,
+                ..CopyFile::default()
+            }
+        }
+    }
+}
+
+/// Timestamp and performance counters for SYS_PERFCTR
+///
+/// The first argument picks the operation. The counters are the CPU's
+/// architectural performance counters (Intel only): system-wide, counting
+/// in user and kernel mode, zeroed by `OP_START`. While one runs, user code
+/// may read it with [`rdpmc`] instead of `OP_READ`. RDTSC always works in
+/// user mode; `OP_TSC_KHZ` says how fast it ticks.
+pub mod perfctr {
+    pub const OP_INFO: u64 = 0;       // -> PmuInfo::to_bits, 0 without counters
+    pub const OP_TSC_KHZ: u64 = 1;    // -> TSC ticks per millisecond, 0 if unknown
+    pub const OP_START: u64 = 2;      // (counter, event) -> 0
+    pub const OP_READ: u64 = 3;       // (counter) -> value
+    pub const OP_STOP: u64 = 4;       // (counter) -> final value
+
+    // Events, bit numbers in `PmuInfo::events`
+    pub const EVENT_CYCLES: u64 = 0;          // Core cycles while not halted
+    pub const EVENT_INSTRUCTIONS: u64 = 1;    // Instructions retired
+    pub const EVENT_REF_CYCLES: u64 = 2;      // Constant-rate cycles while not halted
+    pub const EVENT_LLC_REFERENCES: u64 = 3;  // Last level cache references
+    pub const EVENT_LLC_MISSES: u64 = 4;      // Last level cache misses
+    pub const EVENT_BRANCHES: u64 = 5;        // Branches retired
+    pub const EVENT_BRANCH_MISSES: u64 = 6;   // Mispredicted branches retired
+
+    /// What OP_INFO reports
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct PmuInfo {
+        pub version: u8,
+        pub counters: u8,     // General-purpose counters, numbered from 0
+        pub width: u8,        // Bits per counter
+        pub events: u8,       // Bit EVENT_* set if it can be counted
+    }
+
+    impl PmuInfo {
+        pub fn from_bits(bits: u64) -> Self {
+            PmuInfo {
+                version: bits as u8,
+                counters: (bits >> 8) as u8,
+                width: (bits >> 16) as u8,
+                events: (bits >> 24) as u8,
+            }
+        }
+
+        pub fn has(&self, event: u64) -> bool {
+            event < 8 && self.events & (1 << event) != 0
+        }
+    }
+
+    /// Read the time stamp counter
+    #[inline]
+    pub fn rdtsc() -> u64 {
+        let (lo, hi): (u32, u32);
+        unsafe {
+            core::arch::asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+        }
+        (hi as u64) << 32 | lo as u64
+    }
+
+    /// Read performance counter `counter` without a syscall
+    ///
+    /// # Safety
+    ///
+    /// The counter must have been started with OP_START and not stopped
+    /// since; otherwise RDPMC faults and the program is killed.
+    #[inline]
+    pub unsafe fn rdpmc(counter: u32) -> u64 {
+        let (lo, hi): (u32, u32);
+        core::arch::asm!("rdpmc", in("ecx") counter, out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+        (hi as u64) << 32 | lo as u64
+    }
+}
+
+/// System statistics for SYS_SYSINFO
+///
+/// Memory is in bytes. The load averages are the share of the last 1, 5
+/// and 15 minutes the CPU was busy, times `LOAD_SCALE`; one process runs at
+/// a time, so `LOAD_SCALE` means it was never idle. Fields are only ever
+/// added at the end, and the kernel fills as much as the caller's buffer
+/// holds.
+pub mod sysinfo {
+    pub const LOAD_SCALE: u32 = 100;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct SysInfo {
+        pub uptime_secs: u64,
+        pub idle_secs: u64,     // Time spent halted with nothing to run
+        pub mem_total: u64,     // Physical memory for processes
+        pub mem_free: u64,
+        pub heap_total: u64,    // Kernel heap
+        pub heap_used: u64,
+        pub swap_total: u64,    // 0 when swap is off
+        pub swap_used: u64,
+        pub procs: u32,         // Live processes
+        pub loads: [u32; 3],    // 1, 5 and 15 minute load averages
+    }
+}
+
+/// TCP/IP sockets
+///
+/// SYS_NET_CONNECT and SYS_NET_LISTEN hand back fds that work with
+/// SYS_READ, SYS_WRITE and SYS_CLOSE, but never block: a read returns 0
+/// when nothing has arrived, so SYS_NET_STATE is how to tell that the
+/// other end has closed. Addresses are a.b.c.d as a << 24 | b << 16 |
+/// c << 8 | d.
+pub mod net {
+    pub const STATE_CONNECTING: u64 = 0;
+    pub const STATE_OPEN: u64 = 1;
+    pub const STATE_CLOSED: u64 = 2;   // Refused, reset, or the peer is done sending
+
+    /// An address as the net syscalls take it
+    pub const fn ipv4(octets: [u8; 4]) -> u32 {
+        u32::from_be_bytes(octets)
+    }
+
+    /// The octets of an address from the net syscalls
+    pub const fn octets(addr: u32) -> [u8; 4] {
+        addr.to_be_bytes()
+    }
+
+    /// Parse a dotted quad
+    pub fn parse_ipv4(s: &str) -> Option<u32> {
+        let mut octets = [0u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        parts.next().is_none().then_some(ipv4(octets))
+    }
+}
+
+/// Saved kernel log format
+///
+/// /var/log/kernel.log is a sequence of records, one per flush: `MAGIC`,
+/// the compressed length and the original length (u32 little-endian), then
+/// the log text as one LZ4 block. Logs from before compression are plain
+/// text; `klog` prints anything that isn't a record as it is.
+pub mod klog {
+    pub const LOG_PATH: &str = "/var/log/kernel.log";
+    pub const ROTATED_PATH: &str = "/var/log/kernel.old"; // Previous log after rotation
+    pub const MAGIC: [u8; 4] = *b"KLZ4";
+    pub const HEADER_SIZE: usize = 12;                     // MAGIC and the two lengths
+}
+
+/// Ring 3 code segments
+///
+/// Programs start in 64-bit mode. A far jump or return to `USER_CODE32`
+/// runs 32-bit code (compatibility mode) in the same address space, below
+/// 4GB; a far jump to `USER_CODE` goes back. Syscalls must be made from
+/// 64-bit mode.
+pub mod segments {
+    pub const USER_CODE: u16 = 0x1B;   // 64-bit
+    pub const USER_CODE32: u16 = 0x3B; // 32-bit, compatibility mode
+    pub const USER_DATA: u16 = 0x23;   // Data and stack, for either
+}
+
 /// Raw syscall interface - performs INT 0x80
 ///
 /// # Safety
@@ -201,9 +849,25 @@ pub unsafe fn raw_syscall3(num: u32, arg1: u64, arg2: u64, arg3: u64) -> u64 {
     ret
 }
 
+#[inline(always)]
+pub unsafe fn raw_syscall4(num: u32, arg1: u64, arg2: u64, arg3: u64, arg4: u64) -> u64 {
+    let ret: u64;
+    core::arch::asm!(
+        "int 0x80",
+        in("eax") num,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        in("rdx") arg3,
+        in("r10") arg4,
+        lateout("rax") ret,
+        options(nostack)
+    );
+    ret
+}
+
 /// High-level syscall wrappers
 pub mod syscalls {
-    use super::{numbers::*, raw_syscall0, raw_syscall1, raw_syscall2, raw_syscall3};
+    use super::{numbers::*, raw_syscall0, raw_syscall1, raw_syscall2, raw_syscall3, raw_syscall4};
 
     /// Exit the current process
     pub fn exit(code: i32) -> ! {
@@ -228,9 +892,11 @@ pub mod syscalls {
     }
 
     /// Open a file
-    pub fn open(path: &str, mode: u32) -> i32 {
+    ///
+    /// `flags` are `open::O_*` values; returns the fd, negative on error.
+    pub fn open(path: &str, flags: u32) -> i32 {
         unsafe {
-            raw_syscall3(SYS_OPEN, path.as_ptr() as u64, path.len() as u64, mode as u64) as i32
+            raw_syscall3(SYS_OPEN, path.as_ptr() as u64, path.len() as u64, flags as u64) as i32
         }
     }
 
@@ -241,6 +907,144 @@ pub mod syscalls {
         }
     }
 
+    /// Is the fd a terminal (the console or a pty)?
+    pub fn isatty(fd: i32) -> bool {
+        unsafe { raw_syscall1(SYS_ISATTY, fd as u64) == 1 }
+    }
+
+    /// Create an anonymous pipe
+    /// Returns Some((read_fd, write_fd)) on success
+    pub fn pipe() -> Option<(i32, i32)> {
+        pipe2(0)
+    }
+
+    /// Create an anonymous pipe with `open::O_CLOEXEC` on both ends, or 0
+    /// Reads block until data arrives or every write end is closed, and
+    /// writes block while the pipe is full
+    pub fn pipe2(flags: u32) -> Option<(i32, i32)> {
+        let mut fds: [i32; 2] = [-1; 2];
+        let result = unsafe { raw_syscall2(SYS_PIPE, fds.as_mut_ptr() as u64, flags as u64) };
+        if result == 0 {
+            Some((fds[0], fds[1]))
+        } else {
+            None
+        }
+    }
+
+    /// Create a pseudo-terminal pair
+    /// Returns Some((master_fd, slave_fd)) on success
+    pub fn openpty() -> Option<(i32, i32)> {
+        let mut fds: [i32; 2] = [-1; 2];
+        let result = unsafe { raw_syscall1(SYS_OPENPTY, fds.as_mut_ptr() as u64) };
+        if result == 0 {
+            Some((fds[0], fds[1]))
+        } else {
+            None
+        }
+    }
+
+    /// Replace the system clipboard with `data` of MIME-style type `kind`
+    /// (e.g. "text/plain"). Returns false for a malformed type or data over
+    /// 64 KB.
+    pub fn clipboard_set(kind: &str, data: &[u8]) -> bool {
+        unsafe {
+            raw_syscall4(
+                SYS_CLIPBOARD_SET,
+                kind.as_ptr() as u64,
+                kind.len() as u64,
+                data.as_ptr() as u64,
+                data.len() as u64,
+            ) == 0
+        }
+    }
+
+    /// Copy the clipboard into `buf` if it holds type `kind` ("text/*" and
+    /// "*/*" match more than one type). Returns the full length of the
+    /// contents, which may exceed `buf`; None if empty or of another type.
+    pub fn clipboard_get(kind: &str, buf: &mut [u8]) -> Option<usize> {
+        let len = unsafe {
+            raw_syscall4(
+                SYS_CLIPBOARD_GET,
+                kind.as_ptr() as u64,
+                kind.len() as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+            )
+        };
+        (len != u64::MAX).then_some(len as usize)
+    }
+
+    /// Open a playback stream on the audio output. Returns its handle, or
+    /// None if there is no sound device.
+    pub fn audio_open() -> Option<u32> {
+        let handle = unsafe { raw_syscall0(SYS_AUDIO_OPEN) };
+        (handle != u64::MAX).then_some(handle as u32)
+    }
+
+    /// Set a stream's sample rate, channel count and format (see `audio`).
+    /// Returns false if the device can't play that format.
+    pub fn audio_set_config(handle: u32, sample_rate: u32, channels: u8, format: u32) -> bool {
+        let packed = channels as u64 | ((format as u64) << 8);
+        unsafe { raw_syscall3(SYS_AUDIO_SET_CONFIG, handle as u64, sample_rate as u64, packed) == 0 }
+    }
+
+    /// Queue samples for playback. Returns how many bytes were taken, which
+    /// is less than `samples.len()` when the device buffer fills up.
+    pub fn audio_write(handle: u32, samples: &[u8]) -> Option<usize> {
+        let n = unsafe {
+            raw_syscall3(SYS_AUDIO_WRITE, handle as u64, samples.as_ptr() as u64, samples.len() as u64)
+        };
+        (n != u64::MAX).then_some(n as usize)
+    }
+
+    /// Bytes that `audio_write` would take right now
+    pub fn audio_available(handle: u32) -> Option<usize> {
+        let n = unsafe { raw_syscall1(SYS_AUDIO_AVAILABLE, handle as u64) };
+        (n != u64::MAX).then_some(n as usize)
+    }
+
+    /// Close a playback stream. Samples already queued still play.
+    pub fn audio_close(handle: u32) -> bool {
+        unsafe { raw_syscall1(SYS_AUDIO_CLOSE, handle as u64) == 0 }
+    }
+
+    /// Sound `freq_hz` on the PC speaker for `duration_ms` (rounded up to
+    /// the ~55 ms timer tick), returning when it ends. 0 Hz is a rest.
+    /// Returns false if the speaker can't make that frequency.
+    pub fn beep(freq_hz: u32, duration_ms: u32) -> bool {
+        unsafe { raw_syscall2(SYS_BEEP, freq_hz as u64, duration_ms as u64) == 0 }
+    }
+
+    /// Connected gamepads: bit n set if pad n is there
+    pub fn gamepads() -> u32 {
+        unsafe { raw_syscall0(SYS_GAMEPAD_LIST) as u32 }
+    }
+
+    /// Current buttons and axes of a pad, or None if it isn't connected
+    pub fn gamepad_state(pad: u32) -> Option<super::gamepad::GamepadInfo> {
+        let mut info = super::gamepad::GamepadInfo::default();
+        let ret = unsafe {
+            raw_syscall2(SYS_GAMEPAD_STATE, pad as u64, &mut info as *mut _ as u64)
+        };
+        (ret == 0).then_some(info)
+    }
+
+    /// Duplicate a file descriptor
+    /// Returns the new fd, or -1 on error
+    pub fn dup(fd: i32) -> i32 {
+        unsafe {
+            raw_syscall1(SYS_DUP, fd as u64) as i32
+        }
+    }
+
+    /// Make new_fd refer to the same file as old_fd
+    /// Returns new_fd, or -1 on error
+    pub fn dup2(old_fd: i32, new_fd: i32) -> i32 {
+        unsafe {
+            raw_syscall2(SYS_DUP2, old_fd as u64, new_fd as u64) as i32
+        }
+    }
+
     /// Get a key without blocking
     pub fn getkey() -> u8 {
         unsafe {
@@ -248,7 +1052,16 @@ pub mod syscalls {
         }
     }
 
-    /// Sleep for milliseconds
+    /// Let another process run, or wait for the next interrupt (timer tick,
+    /// key, mouse, NIC) if none can; call from polling loops that found
+    /// nothing to do
+    pub fn idle() {
+        unsafe {
+            raw_syscall0(SYS_IDLE);
+        }
+    }
+
+    /// Sleep for milliseconds (rounded up to the ~55 ms timer tick)
     pub fn sleep(ms: u32) {
         unsafe {
             raw_syscall1(SYS_SLEEP, ms as u64);
@@ -262,6 +1075,336 @@ pub mod syscalls {
         }
     }
 
+    /// Real user ID of this process
+    pub fn getuid() -> u32 {
+        unsafe { raw_syscall0(SYS_GETUID) as u32 }
+    }
+
+    /// Real group ID of this process
+    pub fn getgid() -> u32 {
+        unsafe { raw_syscall0(SYS_GETGID) as u32 }
+    }
+
+    /// Effective user ID of this process
+    pub fn geteuid() -> u32 {
+        unsafe { raw_syscall0(SYS_GETEUID) as u32 }
+    }
+
+    /// Effective group ID of this process
+    pub fn getegid() -> u32 {
+        unsafe { raw_syscall0(SYS_GETEGID) as u32 }
+    }
+
+    /// Supplementary groups of this process, copied into `gids`
+    /// Returns how many there are, which may be more than fit
+    pub fn getgroups(gids: &mut [u32]) -> usize {
+        unsafe { raw_syscall2(SYS_GETGROUPS, gids.as_mut_ptr() as u64, gids.len() as u64) as usize }
+    }
+
+    /// The `index`th user account, None past the last
+    pub fn getpwent(index: usize, buf: &mut [u8]) -> Option<super::pwd::Passwd<'_>> {
+        let len = unsafe { raw_syscall3(SYS_GETPWENT, index as u64, buf.as_mut_ptr() as u64, buf.len() as u64) };
+        super::pwd::Passwd::parse(buf.get(..len as usize)?).filter(|_| len != 0)
+    }
+
+    /// The account with a user ID
+    pub fn getpwuid(uid: u32, buf: &mut [u8]) -> Option<super::pwd::Passwd<'_>> {
+        let len = unsafe { raw_syscall3(SYS_GET_USER_INFO, uid as u64, buf.as_mut_ptr() as u64, buf.len() as u64) };
+        super::pwd::Passwd::parse(buf.get(..len as usize)?).filter(|_| len != 0)
+    }
+
+    /// The account with a user name
+    pub fn getpwnam<'a>(name: &str, buf: &'a mut [u8]) -> Option<super::pwd::Passwd<'a>> {
+        let len = unsafe {
+            raw_syscall4(
+                SYS_GETPWNAM,
+                name.as_ptr() as u64,
+                name.len() as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+            )
+        };
+        super::pwd::Passwd::parse(buf.get(..len as usize)?).filter(|_| len != 0)
+    }
+
+    /// The `index`th group, None past the last
+    pub fn getgrent(index: usize, buf: &mut [u8]) -> Option<super::pwd::Group<'_>> {
+        let len = unsafe { raw_syscall3(SYS_GETGRENT, index as u64, buf.as_mut_ptr() as u64, buf.len() as u64) };
+        super::pwd::Group::parse(buf.get(..len as usize)?).filter(|_| len != 0)
+    }
+
+    /// The group with a group ID
+    pub fn getgrgid(gid: u32, buf: &mut [u8]) -> Option<super::pwd::Group<'_>> {
+        let len = unsafe { raw_syscall3(SYS_GETGRGID, gid as u64, buf.as_mut_ptr() as u64, buf.len() as u64) };
+        super::pwd::Group::parse(buf.get(..len as usize)?).filter(|_| len != 0)
+    }
+
+    /// Store a secret in this user's keyring, replacing one of the same name
+    ///
+    /// Fails if the user hasn't logged in since boot (the ring is locked).
+    pub fn key_add(name: &str, data: &[u8]) -> bool {
+        unsafe {
+            raw_syscall4(
+                SYS_KEY_ADD,
+                name.as_ptr() as u64,
+                name.len() as u64,
+                data.as_ptr() as u64,
+                data.len() as u64,
+            ) == 0
+        }
+    }
+
+    /// Read a secret from this user's keyring into `buf`
+    /// Returns its length; if that is more than `buf.len()` nothing was copied
+    pub fn key_read(name: &str, buf: &mut [u8]) -> Option<usize> {
+        let len = unsafe {
+            raw_syscall4(
+                SYS_KEY_READ,
+                name.as_ptr() as u64,
+                name.len() as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+            )
+        };
+        (len != u64::MAX).then_some(len as usize)
+    }
+
+    /// Delete a secret from this user's keyring
+    pub fn key_delete(name: &str) -> bool {
+        unsafe { raw_syscall3(SYS_KEY_DELETE, name.as_ptr() as u64, name.len() as u64, u64::MAX) == 0 }
+    }
+
+    /// Delete a secret from another user's keyring (root only)
+    pub fn key_delete_user(uid: u32, name: &str) -> bool {
+        unsafe { raw_syscall3(SYS_KEY_DELETE, name.as_ptr() as u64, name.len() as u64, uid as u64) == 0 }
+    }
+
+    /// Start swapping to a file; with `format_pages` nonzero the file is
+    /// created (or overwritten) as a swap area of that many 4 KiB pages
+    pub fn swapon(path: &str, format_pages: u32) -> bool {
+        unsafe { raw_syscall3(SYS_SWAPON, path.as_ptr() as u64, path.len() as u64, format_pages as u64) == 0 }
+    }
+
+    /// Stop swapping; fails if the swapped pages don't fit in memory
+    pub fn swapoff() -> bool {
+        unsafe { raw_syscall0(SYS_SWAPOFF) == 0 }
+    }
+
+    /// Swap size and bytes in use, None if swap is off
+    pub fn swapinfo() -> Option<(u64, u64)> {
+        let mut info = [0u64; 2];
+        let ret = unsafe { raw_syscall1(SYS_SWAPINFO, info.as_mut_ptr() as u64) };
+        (ret == 0).then_some((info[0], info[1]))
+    }
+
+    /// Copy up to `count` bytes from `in_fd` to `out_fd` inside the kernel.
+    /// Returns the bytes copied (fewer at end of input), None on error.
+    pub fn sendfile(out_fd: u64, in_fd: u64, count: u64) -> Option<u64> {
+        let ret = unsafe { raw_syscall3(SYS_SENDFILE, out_fd, in_fd, count) };
+        (ret != u64::MAX).then_some(ret)
+    }
+
+    /// Copy the file `src` to `dst` inside the kernel, calling `progress`
+    /// with (bytes done, total) after each `copyfile::STEP`
+    ///
+    /// The copy keeps the source's mode and times where the destination's
+    /// filesystem can. Returns false on error, with no copy left behind.
+    pub fn copyfile(src: &str, dst: &str, mut progress: impl FnMut(u64, u64)) -> bool {
+        let mut req = super::copyfile::CopyFile::new(src, dst);
+        loop {
+            match unsafe { raw_syscall1(SYS_COPYFILE, &mut req as *mut _ as u64) } {
+                0 => {
+                    progress(req.done, req.total);
+                    return true;
+                }
+                1 => progress(req.done, req.total),
+                _ => return false,
+            }
+        }
+    }
+
+    /// Move `path` into its drive's trash instead of deleting it
+    ///
+    /// Returns 0 when trashed, 1 when there's no trash for it (not on a
+    /// drive, already in the trash, or trashing is off) and the caller
+    /// should delete it, u64::MAX on error.
+    pub fn trash(path: &str) -> u64 {
+        unsafe { raw_syscall2(SYS_TRASH, path.as_ptr() as u64, path.len() as u64) }
+    }
+
+    /// List the trash of `drive` into `buf`, oldest first, as
+    /// "ID\tDELETED\tPATH" lines (DELETED in seconds since the epoch)
+    /// Returns the bytes written, or u64::MAX on error
+    pub fn trash_list(drive: char, buf: &mut [u8]) -> u64 {
+        unsafe { raw_syscall3(SYS_TRASH_LIST, drive as u64, buf.as_mut_ptr() as u64, buf.len() as u64) }
+    }
+
+    /// Put `id` from the trash of `drive` back where it was deleted from
+    /// Returns 0, 1 if something else is there now, u64::MAX on error
+    pub fn trash_restore(drive: char, id: &str) -> u64 {
+        unsafe { raw_syscall3(SYS_TRASH_RESTORE, drive as u64, id.as_ptr() as u64, id.len() as u64) }
+    }
+
+    /// Delete `id` from the trash of `drive` for good, or everything in
+    /// it when `id` is empty
+    /// Returns how many went, or u64::MAX on error
+    pub fn trash_purge(drive: char, id: &str) -> u64 {
+        unsafe { raw_syscall3(SYS_TRASH_PURGE, drive as u64, id.as_ptr() as u64, id.len() as u64) }
+    }
+
+    /// The CPU's performance counters, None if it has none
+    pub fn perfctr_info() -> Option<super::perfctr::PmuInfo> {
+        let bits = unsafe { raw_syscall1(SYS_PERFCTR, super::perfctr::OP_INFO) };
+        (bits != 0 && bits != u64::MAX).then(|| super::perfctr::PmuInfo::from_bits(bits))
+    }
+
+    /// TSC ticks per millisecond, None if the kernel doesn't know
+    pub fn tsc_khz() -> Option<u64> {
+        let khz = unsafe { raw_syscall1(SYS_PERFCTR, super::perfctr::OP_TSC_KHZ) };
+        (khz != 0 && khz != u64::MAX).then_some(khz)
+    }
+
+    /// Zero `counter` and start it counting `event` (perfctr::EVENT_*);
+    /// false if there's no such counter or event
+    pub fn perfctr_start(counter: u32, event: u64) -> bool {
+        unsafe { raw_syscall3(SYS_PERFCTR, super::perfctr::OP_START, counter as u64, event) == 0 }
+    }
+
+    /// Value of a running counter
+    pub fn perfctr_read(counter: u32) -> Option<u64> {
+        let value = unsafe { raw_syscall2(SYS_PERFCTR, super::perfctr::OP_READ, counter as u64) };
+        (value != u64::MAX).then_some(value)
+    }
+
+    /// Stop a counter, returning its final value
+    pub fn perfctr_stop(counter: u32) -> Option<u64> {
+        let value = unsafe { raw_syscall2(SYS_PERFCTR, super::perfctr::OP_STOP, counter as u64) };
+        (value != u64::MAX).then_some(value)
+    }
+
+    /// Key repeat as (delay in ms, repeats per second)
+    pub fn kbd_repeat() -> (u32, u32) {
+        let value = unsafe { raw_syscall3(SYS_KBD_REPEAT, 0, 0, 0) };
+        ((value >> 16) as u32, (value & 0xFFFF) as u32)
+    }
+
+    /// Set key repeat: `delay_ms` 100-2000, `rate` 0 (off) to 30 per second;
+    /// false if either is out of range
+    pub fn set_kbd_repeat(delay_ms: u32, rate: u32) -> bool {
+        unsafe { raw_syscall3(SYS_KBD_REPEAT, delay_ms as u64, rate as u64, 1) != u64::MAX }
+    }
+
+    /// Console output written since the last call, as plain text with
+    /// `@` annotation lines (see the kernel's watos_vt::mirror). The first
+    /// caller becomes the only reader until it exits; None for anyone else.
+    pub fn console_mirror_read(buf: &mut [u8]) -> Option<usize> {
+        let ret = unsafe { raw_syscall2(SYS_CONSOLE_MIRROR, buf.as_mut_ptr() as u64, buf.len() as u64) };
+        (ret != u64::MAX).then_some(ret as usize)
+    }
+
+    /// Connect to `addr` (see `net::ipv4`); the fd is usable at once, with
+    /// writes queued until the connection is up
+    pub fn net_connect(addr: u32, port: u16) -> Option<i32> {
+        let ret = unsafe { raw_syscall2(SYS_NET_CONNECT, addr as u64, port as u64) };
+        (ret != u64::MAX).then_some(ret as i32)
+    }
+
+    /// Listen on `port`; None if it is taken or there is no network
+    pub fn net_listen(port: u16) -> Option<i32> {
+        let ret = unsafe { raw_syscall1(SYS_NET_LISTEN, port as u64) };
+        (ret != u64::MAX).then_some(ret as i32)
+    }
+
+    /// A new connection on a listening fd: Some(None) if none has come
+    /// in, None if `fd` is not listening
+    pub fn net_accept(fd: i32) -> Option<Option<i32>> {
+        match unsafe { raw_syscall1(SYS_NET_ACCEPT, fd as u64) } {
+            u64::MAX => None,
+            0 => Some(None),
+            ret => Some(Some(ret as i32)),
+        }
+    }
+
+    /// One of `net::STATE_*`, None if `fd` is not a socket
+    pub fn net_state(fd: i32) -> Option<u64> {
+        let ret = unsafe { raw_syscall1(SYS_NET_STATE, fd as u64) };
+        (ret != u64::MAX).then_some(ret)
+    }
+
+    /// Address and port at the other end of a connection
+    pub fn net_peer(fd: i32) -> Option<(u32, u16)> {
+        let ret = unsafe { raw_syscall1(SYS_NET_PEER, fd as u64) };
+        (ret != u64::MAX).then_some(((ret >> 16) as u32, ret as u16))
+    }
+
+    /// Start looking up a host name; poll `net_resolved` with the query
+    pub fn net_resolve(name: &str) -> Option<u64> {
+        let ret = unsafe { raw_syscall2(SYS_NET_RESOLVE, name.as_ptr() as u64, name.len() as u64) };
+        (ret != u64::MAX).then_some(ret)
+    }
+
+    /// Some(None) while the lookup is out, None if it failed
+    pub fn net_resolved(query: u64) -> Option<Option<u32>> {
+        match unsafe { raw_syscall1(SYS_NET_RESOLVED, query) } {
+            u64::MAX => None,
+            0 => Some(None),
+            ret => Some(Some(ret as u32)),
+        }
+    }
+
+    /// Fill `buf` from the kernel's random pool, 256 bytes at a time
+    pub fn getrandom(buf: &mut [u8]) -> bool {
+        buf.chunks_mut(256).all(|chunk| unsafe {
+            raw_syscall2(SYS_GETRANDOM, chunk.as_mut_ptr() as u64, chunk.len() as u64) != u64::MAX
+        })
+    }
+
+    /// Uptime, memory, process count and load in one call
+    pub fn sysinfo() -> Option<super::sysinfo::SysInfo> {
+        let mut info = super::sysinfo::SysInfo::default();
+        let ret = unsafe {
+            raw_syscall2(SYS_SYSINFO, &mut info as *mut _ as u64, core::mem::size_of_val(&info) as u64)
+        };
+        (ret != u64::MAX).then_some(info)
+    }
+
+    /// Label and serial number of the volume holding `path`, None if it
+    /// doesn't exist
+    pub fn volinfo(path: &str) -> Option<super::volume::VolInfo> {
+        let mut info = super::volume::VolInfo::default();
+        let ret = unsafe {
+            raw_syscall4(
+                SYS_VOLINFO,
+                path.as_ptr() as u64,
+                path.len() as u64,
+                &mut info as *mut _ as u64,
+                core::mem::size_of_val(&info) as u64,
+            )
+        };
+        (ret != u64::MAX).then_some(info)
+    }
+
+    /// Move drive `from` to the free letter `to`, and keep it there on
+    /// later boots (root only)
+    ///
+    /// Returns 0, or 1 for a bad letter or C:, 2 if `from` isn't mounted,
+    /// 3 if `to` is taken, 4 if not root.
+    pub fn drive_assign(from: char, to: char) -> u64 {
+        unsafe { raw_syscall2(SYS_DRIVE_ASSIGN, from as u64, to as u64) }
+    }
+
+    /// Current value of the tunable `name`, None if there is none
+    pub fn sysctl_get(name: &str) -> Option<u64> {
+        let ret = unsafe { raw_syscall3(SYS_SYSCTL, name.as_ptr() as u64, name.len() as u64, 0) };
+        (ret != u64::MAX).then_some(ret)
+    }
+
+    /// Set the tunable `name` (root only); false if unknown, out of range
+    /// or refused
+    pub fn sysctl_set(name: &str, value: u64) -> bool {
+        unsafe { raw_syscall4(SYS_SYSCTL, name.as_ptr() as u64, name.len() as u64, 1, value) != u64::MAX }
+    }
+
     /// Get system time (ticks since boot)
     pub fn time() -> u64 {
         unsafe {
@@ -269,14 +1412,15 @@ pub mod syscalls {
         }
     }
 
-    /// Allocate memory
+    /// Allocate `size` bytes, 16-byte aligned, from this process's heap;
+    /// null when it is full
     pub fn malloc(size: usize) -> *mut u8 {
         unsafe {
             raw_syscall1(SYS_MALLOC, size as u64) as *mut u8
         }
     }
 
-    /// Free memory
+    /// Free a block from [`malloc`]; the kernel knows its size
     pub fn free(ptr: *mut u8) {
         unsafe {
             raw_syscall1(SYS_FREE, ptr as u64);
@@ -332,7 +1476,8 @@ pub mod syscalls {
         }
     }
 
-    /// Get framebuffer info pointer (returns pointer to BootInfo struct)
+    /// Get framebuffer info pointer (returns the kernel's address of the
+    /// BootInfo struct, which user code cannot read)
     pub fn fb_info() -> u64 {
         unsafe {
             raw_syscall0(SYS_FB_INFO)
@@ -346,6 +1491,14 @@ pub mod syscalls {
         }
     }
 
+    /// Map the framebuffer into this process and return its address
+    /// (see `fb_dimensions`). Only root and the video group may; others
+    /// get None.
+    pub fn fb_map() -> Option<u64> {
+        let addr = unsafe { raw_syscall0(SYS_FB_MAP) };
+        (addr != 0 && addr != u64::MAX).then_some(addr)
+    }
+
     /// Get framebuffer dimensions: returns (width, height, pitch) packed
     /// Format: high 32 bits = width | mid 16 bits = height | low 16 bits = pitch/4
     pub fn fb_dimensions() -> (u32, u32, u32) {
@@ -358,6 +1511,80 @@ pub mod syscalls {
         }
     }
 
+    /// Save the screen to an image file, PNG if the name ends in .png, else BMP
+    /// Returns 0 on success
+    pub fn screenshot(path: &str) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_SCREENSHOT, path.as_ptr() as u64, path.len() as u64)
+        }
+    }
+
+    /// Switch the console font to a PSF2 file (None = built-in 8x16 font),
+    /// drawn at `scale` times its size. Returns the new (cols, rows).
+    pub fn setfont(path: Option<&str>, scale: u32) -> Option<(u32, u32)> {
+        let (ptr, len) = path.map(|p| (p.as_ptr() as u64, p.len() as u64)).unwrap_or((0, 0));
+        let result = unsafe { raw_syscall3(SYS_SETFONT, ptr, len, scale as u64) };
+        if result == u64::MAX {
+            None
+        } else {
+            Some(((result >> 16) as u32, (result & 0xFFFF) as u32))
+        }
+    }
+
+    /// Switch the display to `width` x `height`, one of the modes listed by
+    /// `fb_modes`. Returns the new (width, height, pitch).
+    pub fn fb_set_mode(width: u32, height: u32) -> Option<(u32, u32, u32)> {
+        let packed = unsafe { raw_syscall2(SYS_FB_SET_MODE, width as u64, height as u64) };
+        if packed == u64::MAX {
+            None
+        } else {
+            Some(((packed >> 32) as u32, ((packed >> 16) & 0xFFFF) as u32, (packed & 0xFFFF) as u32 * 4))
+        }
+    }
+
+    /// Fill `modes` with the available (width, height) display modes;
+    /// returns how many were written
+    pub fn fb_modes(modes: &mut [(u32, u32)]) -> usize {
+        // The kernel writes 4 u32s per mode: width, height, bpp, format
+        let mut raw = [0u32; 4 * 32];
+        let max = modes.len().min(32);
+        let count = unsafe {
+            raw_syscall2(SYS_VGA_ENUMERATE_MODES, raw.as_mut_ptr() as u64, max as u64)
+        } as usize;
+        for (i, mode) in modes.iter_mut().take(count.min(max)).enumerate() {
+            *mode = (raw[i * 4], raw[i * 4 + 1]);
+        }
+        count.min(max)
+    }
+
+    /// Apply pending mouse motion to the cursor and return its hotspot
+    /// (x, y) and buttons (bit 0 left, bit 1 right, bit 2 middle)
+    pub fn mouse_poll() -> (i32, i32, u8) {
+        let packed = unsafe { raw_syscall0(SYS_MOUSE_POLL) };
+        ((packed & 0xFFFF) as i32, ((packed >> 16) & 0xFFFF) as i32, (packed >> 32) as u8)
+    }
+
+    /// Show or hide the mouse cursor. Programs drawing to the framebuffer
+    /// should hide it while drawing under it. Returns false without a
+    /// framebuffer.
+    pub fn cursor_show(visible: bool) -> bool {
+        unsafe { raw_syscall2(SYS_CURSOR_SHOW, visible as u64, 0) != u64::MAX }
+    }
+
+    /// Redraw the cursor after drawing under it without hiding it first
+    pub fn cursor_refresh() {
+        unsafe { raw_syscall2(SYS_CURSOR_SHOW, 1, 1); }
+    }
+
+    /// Replace the cursor with a 32x32 ARGB sprite whose hotspot is at
+    /// (hot_x, hot_y)
+    pub fn cursor_set_sprite(pixels: &[u32; 32 * 32], hot_x: u32, hot_y: u32) -> bool {
+        let hotspot = ((hot_x as u64) << 16) | hot_y as u64;
+        unsafe {
+            raw_syscall3(SYS_CURSOR_SPRITE, pixels.as_ptr() as u64, pixels.len() as u64, hotspot) != u64::MAX
+        }
+    }
+
     /// Read raw keyboard scancode (non-blocking, returns 0 if no key)
     pub fn read_scancode() -> u8 {
         unsafe {
@@ -400,6 +1627,150 @@ pub mod syscalls {
         }
     }
 
+    /// Run a program with an argument vector; argv[0] names the program
+    /// Returns 0 on success, like `exec`; u64::MAX if the argv is too big
+    pub fn execv<S: AsRef<str>>(argv: &[S]) -> u64 {
+        let mut blocks = [0u8; super::argv::MAX_BYTES];
+        match super::argv::encode(argv, &mut blocks) {
+            Some(len) => unsafe { raw_syscall2(SYS_EXECV, blocks.as_ptr() as u64, len as u64) },
+            None => u64::MAX,
+        }
+    }
+
+    /// Spawn a program as a new child process without waiting for it
+    /// argv[0] names the program
+    /// Returns the child PID, or u64::MAX on error
+    pub fn spawn<S: AsRef<str>>(argv: &[S]) -> u64 {
+        let mut blocks = [0u8; super::argv::MAX_BYTES];
+        match super::argv::encode(argv, &mut blocks) {
+            Some(len) => unsafe { raw_syscall2(SYS_SPAWN, blocks.as_ptr() as u64, len as u64) },
+            None => u64::MAX,
+        }
+    }
+
+    /// Copy this process's argv into `buf` as blocks (see `argv`)
+    /// Returns the encoded length, or None if `buf` is too small
+    pub fn getargv(buf: &mut [u8]) -> Option<usize> {
+        let len = unsafe { raw_syscall2(SYS_GETARGV, buf.as_mut_ptr() as u64, buf.len() as u64) } as usize;
+        (len <= buf.len()).then_some(len)
+    }
+
+    /// Wait for a child process to change state
+    /// pid: child to wait for (0 = any child)
+    /// options: combination of wait::WNOHANG / wait::WUNTRACED
+    /// Returns Some((pid, status)) when a child changed state, None otherwise
+    pub fn wait(pid: u32, options: u32) -> Option<(u32, u32)> {
+        let mut status: u32 = 0;
+        let result = unsafe {
+            raw_syscall3(
+                SYS_WAIT,
+                pid as u64,
+                &mut status as *mut u32 as u64,
+                options as u64,
+            )
+        };
+        if result == 0 || result == u64::MAX {
+            None
+        } else {
+            Some((result as u32, status))
+        }
+    }
+
+    /// Get CPU time used by a process (0 = self) as (user_ms, system_ms)
+    pub fn getrusage(pid: u32) -> Option<(u64, u64)> {
+        let mut buf: [u64; 2] = [0; 2];
+        let result = unsafe {
+            raw_syscall2(SYS_GETRUSAGE, pid as u64, buf.as_mut_ptr() as u64)
+        };
+        if result == 0 {
+            Some((buf[0], buf[1]))
+        } else {
+            None
+        }
+    }
+
+    /// Get memory usage of a process (0 = self)
+    /// Returns: [vm_size_kb, rss_kb, code_kb, heap_kb, stack_kb, malloc_bytes]
+    /// Returns 0 on success, u64::MAX if there is no such process
+    pub fn memusage(pid: u32, buf: &mut [u64; 6]) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_GETMEMUSAGE, pid as u64, buf.as_mut_ptr() as u64)
+        }
+    }
+
+    /// Set a resource limit of the calling process (see `rlimit`)
+    /// Returns 0 on success, u64::MAX for an unknown resource
+    pub fn setrlimit(resource: u32, limit: u64) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_SETRLIMIT, resource as u64, limit)
+        }
+    }
+
+    /// Get a resource limit of the calling process (see `rlimit`)
+    pub fn getrlimit(resource: u32) -> Option<u64> {
+        let mut limit: u64 = 0;
+        let result = unsafe {
+            raw_syscall2(SYS_GETRLIMIT, resource as u64, &mut limit as *mut u64 as u64)
+        };
+        if result == 0 {
+            Some(limit)
+        } else {
+            None
+        }
+    }
+
+    /// Set the niceness of a process (0 = self): -20 is the most favoured,
+    /// 19 the least. Only root may lower it or renice other users' processes.
+    pub fn setpriority(pid: u32, nice: i32) -> bool {
+        unsafe {
+            raw_syscall2(SYS_SETPRIORITY, pid as u64, nice as i64 as u64) == 0
+        }
+    }
+
+    /// Get the niceness of a process (0 = self)
+    pub fn getpriority(pid: u32) -> Option<i32> {
+        let raw = unsafe { raw_syscall1(SYS_GETPRIORITY, pid as u64) };
+        (raw != u64::MAX).then(|| 20 - raw as i32)
+    }
+
+    /// Send a signal to a process, or to a process group if pid is negative
+    /// Returns 0 on success
+    pub fn kill(pid: i32, sig: u32) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_KILL, pid as i64 as u64, sig as u64)
+        }
+    }
+
+    /// Set the process group of a process (0 = calling process)
+    /// Returns 0 on success
+    pub fn setpgid(pid: u32, pgid: u32) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_SETPGID, pid as u64, pgid as u64)
+        }
+    }
+
+    /// Get the process group of a process (0 = calling process)
+    pub fn getpgid(pid: u32) -> u32 {
+        unsafe {
+            raw_syscall1(SYS_GETPGID, pid as u64) as u32
+        }
+    }
+
+    /// Make a process group the console's foreground group
+    /// Returns 0 on success
+    pub fn tcsetpgrp(pgid: u32) -> u64 {
+        unsafe {
+            raw_syscall1(SYS_TCSETPGRP, pgid as u64)
+        }
+    }
+
+    /// Get the console's foreground process group
+    pub fn tcgetpgrp() -> u32 {
+        unsafe {
+            raw_syscall0(SYS_TCGETPGRP) as u32
+        }
+    }
+
     /// Mount a drive with a given name
     /// name: Drive name (e.g., "C", "D", "MYDATA")
     /// mount_path: Null-terminated mount path (e.g., "/mnt/c\0")
@@ -482,6 +1853,98 @@ pub mod syscalls {
         }
     }
 
+    /// Delete a file
+    /// Returns 0 on success
+    pub fn unlink(path: &str) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_UNLINK, path.as_ptr() as u64, path.len() as u64)
+        }
+    }
+
+    /// Remove an empty directory
+    /// Returns 0 on success
+    pub fn rmdir(path: &str) -> u64 {
+        unsafe {
+            raw_syscall2(SYS_RMDIR, path.as_ptr() as u64, path.len() as u64)
+        }
+    }
+
+    /// Rename or move a file/directory
+    /// Returns 0 on success
+    pub fn rename(old_path: &str, new_path: &str) -> u64 {
+        unsafe {
+            raw_syscall4(
+                SYS_RENAME,
+                old_path.as_ptr() as u64,
+                old_path.len() as u64,
+                new_path.as_ptr() as u64,
+                new_path.len() as u64,
+            )
+        }
+    }
+
+    /// Give a file a second name (a hard link)
+    /// Returns 0 on success
+    pub fn link(old_path: &str, new_path: &str) -> u64 {
+        unsafe {
+            raw_syscall4(
+                SYS_LINK,
+                old_path.as_ptr() as u64,
+                old_path.len() as u64,
+                new_path.as_ptr() as u64,
+                new_path.len() as u64,
+            )
+        }
+    }
+
+    /// Take a snapshot of a path, named `name`
+    /// Returns 0 on success
+    pub fn snapshot(path: &str, name: &str) -> u64 {
+        unsafe {
+            raw_syscall4(
+                SYS_SNAPSHOT,
+                path.as_ptr() as u64,
+                path.len() as u64,
+                name.as_ptr() as u64,
+                name.len() as u64,
+            )
+        }
+    }
+
+    /// List the snapshots on the filesystem holding a path
+    /// Fills `buf` with one name per line and returns the bytes written,
+    /// or u64::MAX on error
+    pub fn snapshots(path: &str, buf: &mut [u8]) -> u64 {
+        unsafe {
+            raw_syscall4(
+                SYS_SNAPSHOT_LIST,
+                path.as_ptr() as u64,
+                path.len() as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+            )
+        }
+    }
+
+    /// Mount a snapshot of the filesystem holding a path, read-only
+    /// Returns the drive letter it was mounted as
+    pub fn mount_snapshot(path: &str, name: &str) -> Option<char> {
+        let result = unsafe {
+            raw_syscall4(
+                SYS_SNAPSHOT_MOUNT,
+                path.as_ptr() as u64,
+                path.len() as u64,
+                name.as_ptr() as u64,
+                name.len() as u64,
+            )
+        };
+        if result == u64::MAX {
+            None
+        } else {
+            Some(result as u8 as char)
+        }
+    }
+
     /// Get file/directory status
     /// Returns (type, size) where type: 0=file, 1=directory
     pub fn stat(path: &str) -> Option<(u64, u64)> {
@@ -530,6 +1993,28 @@ pub mod syscalls {
         }
     }
 
+    /// Canonicalize a path: CWD joined, `.`/`..` and symlinks resolved
+    /// Returns bytes written to buffer (e.g., "C:/apps/ls"), 0 on error
+    pub fn realpath(path: &str, buf: &mut [u8]) -> usize {
+        unsafe {
+            raw_syscall4(
+                SYS_REALPATH,
+                path.as_ptr() as u64,
+                path.len() as u64,
+                buf.as_mut_ptr() as u64,
+                buf.len() as u64,
+            ) as usize
+        }
+    }
+
+    /// Watch a directory for changes (see the `watch` module)
+    /// Returns an fd to read events from, or -1 on error
+    pub fn watch(path: &str, mask: u32) -> i32 {
+        unsafe {
+            raw_syscall3(SYS_WATCH, path.as_ptr() as u64, path.len() as u64, mask as u64) as i32
+        }
+    }
+
     /// Create a named pipe (FIFO)
     /// Returns 0 on success, error code on failure
     pub fn mkfifo(path: &str) -> u64 {
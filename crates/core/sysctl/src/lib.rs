@@ -0,0 +1,278 @@
+//! WATOS Kernel Tunables
+//!
+//! Subsystems register the settings they can change at runtime as typed
+//! [`Tunable`]s with dotted names (`vm.blockcache.readahead`). Each one
+//! brings a getter and a setter, so the value stays wherever its subsystem
+//! keeps it; the registry only names, types and range-checks it.
+//!
+//! Tunables are read and written by name through [`get`] and [`set`]
+//! (`SYS_SYSCTL`), or as text through [`read`] and [`write`], which is how
+//! `/proc/sys/vm/blockcache/readahead` shows them. Writes go through the
+//! check installed with [`set_write_check`], which the kernel makes
+//! root-only.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// What values a tunable takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A number in `min..=max`
+    Int { min: u64, max: u64 },
+    /// 0 or 1
+    Bool,
+    /// An index into the names, which are what the text form shows
+    Choice(&'static [&'static str]),
+}
+
+impl Kind {
+    /// Is `value` allowed?
+    pub fn accepts(&self, value: u64) -> bool {
+        match *self {
+            Kind::Int { min, max } => (min..=max).contains(&value),
+            Kind::Bool => value <= 1,
+            Kind::Choice(names) => (value as usize) < names.len(),
+        }
+    }
+
+    /// The text form of `value`
+    pub fn format(&self, value: u64) -> String {
+        match *self {
+            Kind::Choice(names) => names.get(value as usize).copied().unwrap_or("?").to_string(),
+            _ => format!("{}", value),
+        }
+    }
+
+    /// A value from its text form; choices also take their index
+    pub fn parse(&self, text: &str) -> Option<u64> {
+        let value = match *self {
+            Kind::Choice(names) => match names.iter().position(|&n| n == text) {
+                Some(index) => index as u64,
+                None => text.parse().ok()?,
+            },
+            Kind::Bool => match text {
+                "on" | "true" | "yes" => 1,
+                "off" | "false" | "no" => 0,
+                _ => text.parse().ok()?,
+            },
+            Kind::Int { .. } => text.parse().ok()?,
+        };
+        self.accepts(value).then_some(value)
+    }
+}
+
+/// A setting a subsystem lets be changed at runtime
+#[derive(Debug, Clone, Copy)]
+pub struct Tunable {
+    /// Dotted path: lowercase words of `a-z`, `0-9` and `_`
+    pub name: &'static str,
+    /// One line for listings
+    pub description: &'static str,
+    pub kind: Kind,
+    /// Current value
+    pub get: fn() -> u64,
+    /// Apply a value `kind` accepts; false if the subsystem refuses it
+    pub set: fn(u64) -> bool,
+}
+
+/// Why a sysctl operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No tunable by that name
+    NotFound,
+    /// The value is out of range or malformed, or the subsystem refused it
+    Invalid,
+    /// The caller may not change tunables
+    Denied,
+    /// Registering a name that is taken, malformed, or a directory of others
+    Exists,
+}
+
+static REGISTRY: Mutex<Vec<Tunable>> = Mutex::new(Vec::new());
+
+/// Kernel callback deciding whether the caller may write (None: anyone)
+static mut WRITE_CHECK: Option<fn() -> bool> = None;
+
+/// Install the check every write must pass, e.g. "the caller is root"
+pub fn set_write_check(check: fn() -> bool) {
+    unsafe { WRITE_CHECK = Some(check); }
+}
+
+fn may_write() -> bool {
+    match unsafe { WRITE_CHECK } {
+        Some(check) => check(),
+        None => true,
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|word| {
+            !word.is_empty() && word.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        })
+}
+
+/// Is `name` inside directory `dir` ("" is the root)?
+fn under<'a>(name: &'a str, dir: &str) -> Option<&'a str> {
+    if dir.is_empty() {
+        Some(name)
+    } else {
+        name.strip_prefix(dir)?.strip_prefix('.')
+    }
+}
+
+/// Add a tunable. Its name may not be taken, nor be the directory of
+/// another tunable or inside a tunable.
+pub fn register(tunable: Tunable) -> Result<(), Error> {
+    if !valid_name(tunable.name) {
+        return Err(Error::Exists);
+    }
+    let mut registry = REGISTRY.lock();
+    let clash = registry.iter().any(|t| {
+        t.name == tunable.name || under(t.name, tunable.name).is_some() || under(tunable.name, t.name).is_some()
+    });
+    if clash {
+        return Err(Error::Exists);
+    }
+    registry.push(tunable);
+    Ok(())
+}
+
+/// The tunable called `name`
+pub fn find(name: &str) -> Option<Tunable> {
+    REGISTRY.lock().iter().find(|t| t.name == name).copied()
+}
+
+/// Current value of `name`
+pub fn get(name: &str) -> Result<u64, Error> {
+    let tunable = find(name).ok_or(Error::NotFound)?;
+    Ok((tunable.get)())
+}
+
+/// Change `name` to `value`
+pub fn set(name: &str, value: u64) -> Result<(), Error> {
+    let tunable = find(name).ok_or(Error::NotFound)?;
+    if !may_write() {
+        return Err(Error::Denied);
+    }
+    if !tunable.kind.accepts(value) || !(tunable.set)(value) {
+        return Err(Error::Invalid);
+    }
+    Ok(())
+}
+
+/// Current value of `name` as a line of text
+pub fn read(name: &str) -> Result<String, Error> {
+    let tunable = find(name).ok_or(Error::NotFound)?;
+    Ok(format!("{}\n", tunable.kind.format((tunable.get)())))
+}
+
+/// Change `name` to the value written as `text`
+pub fn write(name: &str, text: &str) -> Result<(), Error> {
+    let tunable = find(name).ok_or(Error::NotFound)?;
+    let value = tunable.kind.parse(text.trim()).ok_or(Error::Invalid)?;
+    set(name, value)
+}
+
+/// All tunables, sorted by name
+pub fn list() -> Vec<Tunable> {
+    let mut all = REGISTRY.lock().clone();
+    all.sort_by_key(|t| t.name);
+    all
+}
+
+/// Is `dir` ("" for the root) a directory of tunables?
+pub fn is_dir(dir: &str) -> bool {
+    REGISTRY.lock().iter().any(|t| under(t.name, dir).is_some())
+}
+
+/// The entries directly inside directory `dir`, sorted, each with whether
+/// it is a directory itself
+pub fn children(dir: &str) -> Vec<(String, bool)> {
+    let mut entries: Vec<(String, bool)> = Vec::new();
+    for tunable in REGISTRY.lock().iter() {
+        let Some(rest) = under(tunable.name, dir) else { continue };
+        let entry = match rest.split_once('.') {
+            Some((child, _)) => (String::from(child), true),
+            None => (String::from(rest), false),
+        };
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    entries.sort();
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static LEVEL: AtomicU64 = AtomicU64::new(1);
+    static SIZE: AtomicU64 = AtomicU64::new(64);
+
+    #[test]
+    fn test_register_read_write() {
+        register(Tunable {
+            name: "test.log.level",
+            description: "how much to log",
+            kind: Kind::Choice(&["quiet", "info", "debug"]),
+            get: || LEVEL.load(Ordering::Relaxed),
+            set: |v| { LEVEL.store(v, Ordering::Relaxed); true },
+        }).unwrap();
+        register(Tunable {
+            name: "test.cache.size",
+            description: "entries kept",
+            kind: Kind::Int { min: 1, max: 1024 },
+            get: || SIZE.load(Ordering::Relaxed),
+            // Powers of two only
+            set: |v| v.is_power_of_two() && { SIZE.store(v, Ordering::Relaxed); true },
+        }).unwrap();
+
+        assert_eq!(read("test.log.level").unwrap(), "info\n");
+        write("test.log.level", "debug\n").unwrap();
+        assert_eq!(get("test.log.level"), Ok(2));
+        set("test.log.level", 0).unwrap();
+        assert_eq!(read("test.log.level").unwrap(), "quiet\n");
+        assert_eq!(write("test.log.level", "loud"), Err(Error::Invalid));
+        assert_eq!(set("test.log.level", 3), Err(Error::Invalid));
+
+        assert_eq!(write("test.cache.size", "128"), Ok(()));
+        assert_eq!(write("test.cache.size", "100"), Err(Error::Invalid));
+        assert_eq!(set("test.cache.size", 2048), Err(Error::Invalid));
+        assert_eq!(get("test.cache.size"), Ok(128));
+        assert_eq!(get("test.cache.none"), Err(Error::NotFound));
+
+        let test: Vec<(String, bool)> = children("test");
+        assert_eq!(test, [(String::from("cache"), true), (String::from("log"), true)]);
+        assert_eq!(children("test.log"), [(String::from("level"), false)]);
+        assert!(is_dir("test.cache") && !is_dir("test.cache.size"));
+    }
+
+    #[test]
+    fn test_names_checked() {
+        let tunable = |name| Tunable {
+            name,
+            description: "",
+            kind: Kind::Bool,
+            get: || 0,
+            set: |_| true,
+        };
+        register(tunable("names.a.b")).unwrap();
+        assert_eq!(register(tunable("names.a.b")), Err(Error::Exists));
+        assert_eq!(register(tunable("names.a")), Err(Error::Exists));
+        assert_eq!(register(tunable("names.a.b.c")), Err(Error::Exists));
+        assert_eq!(register(tunable("names..c")), Err(Error::Exists));
+        assert_eq!(register(tunable("Names.c")), Err(Error::Exists));
+        register(tunable("names.ab")).unwrap();
+        assert_eq!(Kind::Bool.parse("on"), Some(1));
+        assert_eq!(Kind::Bool.parse("2"), None);
+    }
+}
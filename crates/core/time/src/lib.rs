@@ -0,0 +1,454 @@
+//! WATOS Time Module
+//!
+//! One representation of a point in time, and conversions to and from the
+//! ways the rest of the system stores one:
+//!
+//! - [`Timestamp`]: seconds and nanoseconds since the Unix epoch (UTC)
+//! - [`DateTime`]: the same broken down into a proleptic Gregorian date and
+//!   time of day
+//! - FAT directory entry date/time words
+//! - CMOS RTC registers (BCD or binary, 12 or 24 hour)
+//! - WFS inode times (u64 seconds since the epoch, also `FileStat` times)
+//! - ISO 8601 text (`2026-10-18T09:30:00Z`)
+//!
+//! There are no time zones: the RTC is taken to run on UTC.
+
+#![no_std]
+
+use core::fmt;
+
+/// Seconds in a day
+pub const SECS_PER_DAY: i64 = 86_400;
+
+/// Nanoseconds in a second
+pub const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+// ============================================================================
+// TIMESTAMP
+// ============================================================================
+
+/// A point in time: seconds since 1970-01-01T00:00:00Z plus nanoseconds
+///
+/// Ordering and equality follow the point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp {
+    secs: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// 1970-01-01T00:00:00Z
+    pub const UNIX_EPOCH: Timestamp = Timestamp { secs: 0, nanos: 0 };
+
+    /// `nanos` beyond a second carry into `secs`
+    pub const fn new(secs: i64, nanos: u32) -> Self {
+        Timestamp {
+            secs: secs + (nanos / NANOS_PER_SEC) as i64,
+            nanos: nanos % NANOS_PER_SEC,
+        }
+    }
+
+    pub const fn from_secs(secs: i64) -> Self {
+        Timestamp { secs, nanos: 0 }
+    }
+
+    /// Whole seconds since the epoch (negative before 1970)
+    pub const fn secs(&self) -> i64 {
+        self.secs
+    }
+
+    /// Nanoseconds past `secs`
+    pub const fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Break down into date and time of day
+    pub fn to_datetime(&self) -> DateTime {
+        let days = self.secs.div_euclid(SECS_PER_DAY);
+        let rem = self.secs.rem_euclid(SECS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+            nanos: self.nanos,
+        }
+    }
+
+    /// From a WFS inode time: seconds since the epoch
+    pub const fn from_wfs(secs: u64) -> Self {
+        Timestamp::from_secs(secs as i64)
+    }
+
+    /// To a WFS inode time; times before 1970 become 0
+    pub const fn to_wfs(&self) -> u64 {
+        if self.secs < 0 { 0 } else { self.secs as u64 }
+    }
+
+    /// From a FAT directory entry's date and time words and, for creation
+    /// times, the 10ms count byte
+    ///
+    /// `None` for a zero date (never set) or a malformed one.
+    pub fn from_fat(date: u16, time: u16, centis: u8) -> Option<Self> {
+        if date == 0 {
+            return None;
+        }
+        let centis = if centis < 200 { centis as u32 } else { 0 };
+        let dt = DateTime {
+            year: 1980 + (date >> 9) as i32,
+            month: ((date >> 5) & 0x0F) as u8,
+            day: (date & 0x1F) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3F) as u8,
+            second: ((time & 0x1F) * 2) as u8 + (centis / 100) as u8,
+            nanos: centis % 100 * 10_000_000,
+        };
+        dt.to_timestamp()
+    }
+
+    /// To FAT (date, time, 10ms count) fields
+    ///
+    /// FAT counts seconds in twos; the odd second and hundredths go in the
+    /// count byte, which only creation times have. `None` outside the years
+    /// FAT can store, 1980 to 2107.
+    pub fn to_fat(&self) -> Option<(u16, u16, u8)> {
+        let dt = self.to_datetime();
+        if !(1980..=2107).contains(&dt.year) {
+            return None;
+        }
+        let date = (((dt.year - 1980) as u16) << 9) | ((dt.month as u16) << 5) | dt.day as u16;
+        let time = ((dt.hour as u16) << 11) | ((dt.minute as u16) << 5) | (dt.second as u16 / 2);
+        let centis = (dt.second % 2) * 100 + (dt.nanos / 10_000_000) as u8;
+        Some((date, time, centis))
+    }
+
+    /// ISO 8601 in UTC: `2026-10-18T09:30:00Z`, with `.nnnnnnnnn` fractional
+    /// seconds when there are any
+    pub fn iso8601(&self) -> Iso8601 {
+        Iso8601(self.to_datetime())
+    }
+}
+
+// ============================================================================
+// DATE AND TIME
+// ============================================================================
+
+/// A UTC calendar date and time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i32,
+    /// 1-12
+    pub month: u8,
+    /// 1-31
+    pub day: u8,
+    /// 0-23
+    pub hour: u8,
+    /// 0-59
+    pub minute: u8,
+    /// 0-59
+    pub second: u8,
+    pub nanos: u32,
+}
+
+impl DateTime {
+    pub const fn new(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Self {
+        DateTime { year, month, day, hour, minute, second, nanos: 0 }
+    }
+
+    /// Whether every field is in range (February 29th only in leap years)
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && self.nanos < NANOS_PER_SEC
+    }
+
+    /// `None` if any field is out of range
+    pub fn to_timestamp(&self) -> Option<Timestamp> {
+        if !self.is_valid() {
+            return None;
+        }
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * SECS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        Some(Timestamp::new(secs, self.nanos))
+    }
+
+    /// Day of the week, 0 = Sunday
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        (days_from_civil(self.year, self.month, self.day) + 4).rem_euclid(7) as u8
+    }
+
+    /// ISO 8601, as [`Timestamp::iso8601`]
+    pub fn iso8601(&self) -> Iso8601 {
+        Iso8601(*self)
+    }
+}
+
+/// `2026-10-18 09:30:00`, the form `date` and logs print
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// ISO 8601 formatting of a [`DateTime`], made by `iso8601()`
+#[derive(Debug, Clone, Copy)]
+pub struct Iso8601(DateTime);
+
+impl fmt::Display for Iso8601 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dt = &self.0;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+        )?;
+        if dt.nanos != 0 {
+            write!(f, ".{:09}", dt.nanos)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Days in a month (1-12) of a year; 0 for an invalid month
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Days since 1970-01-01 of a date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Date of a day counted from 1970-01-01
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+    (year, month, day)
+}
+
+// ============================================================================
+// RTC
+// ============================================================================
+
+/// Status register B: hours are 24-hour
+pub const RTC_24_HOUR: u8 = 0x02;
+/// Status register B: values are binary rather than BCD
+pub const RTC_BINARY: u8 = 0x04;
+
+/// Raw CMOS RTC time registers, as read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    /// Bit 7 is PM in 12-hour mode
+    pub hours: u8,
+    pub day: u8,
+    pub month: u8,
+    /// Year within the century
+    pub year: u8,
+    /// Century register, 0 if the machine has none (20xx is assumed)
+    pub century: u8,
+    /// Status register B (`RTC_24_HOUR`, `RTC_BINARY`)
+    pub status_b: u8,
+}
+
+impl RtcRegisters {
+    /// Decode to a date and time; `None` if the values make no sense
+    pub fn to_datetime(&self) -> Option<DateTime> {
+        let binary = self.status_b & RTC_BINARY != 0;
+        let value = |v: u8| if binary { v } else { bcd_to_bin(v) };
+
+        let pm = self.hours & 0x80 != 0;
+        let mut hour = value(self.hours & 0x7F);
+        if self.status_b & RTC_24_HOUR == 0 {
+            // 12-hour clock: 12 AM is midnight, 12 PM is noon
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+
+        let century = match self.century {
+            0 => 20,
+            c => value(c) as i32,
+        };
+        let dt = DateTime::new(
+            century * 100 + value(self.year) as i32,
+            value(self.month),
+            value(self.day),
+            hour,
+            value(self.minutes),
+            value(self.seconds),
+        );
+        dt.is_valid().then_some(dt)
+    }
+
+    /// Encode a date and time in the format `status_b` selects
+    pub fn from_datetime(dt: &DateTime, status_b: u8) -> Self {
+        let binary = status_b & RTC_BINARY != 0;
+        let value = |v: u8| if binary { v } else { bin_to_bcd(v) };
+
+        let hours = if status_b & RTC_24_HOUR != 0 {
+            value(dt.hour)
+        } else {
+            let twelve = match dt.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            value(twelve) | if dt.hour >= 12 { 0x80 } else { 0 }
+        };
+
+        RtcRegisters {
+            seconds: value(dt.second),
+            minutes: value(dt.minute),
+            hours,
+            day: value(dt.day),
+            month: value(dt.month),
+            year: value(dt.year.rem_euclid(100) as u8),
+            century: value(dt.year.div_euclid(100) as u8),
+            status_b,
+        }
+    }
+}
+
+/// Packed BCD byte to binary
+pub const fn bcd_to_bin(bcd: u8) -> u8 {
+    (bcd & 0x0F) + (bcd >> 4) * 10
+}
+
+/// Binary (0-99) to a packed BCD byte
+pub const fn bin_to_bcd(bin: u8) -> u8 {
+    ((bin / 10) << 4) | (bin % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn test_civil_round_trip() {
+        assert_eq!(DateTime::new(1970, 1, 1, 0, 0, 0).to_timestamp(), Some(Timestamp::UNIX_EPOCH));
+        assert_eq!(
+            DateTime::new(2000, 3, 1, 0, 0, 0).to_timestamp().unwrap().secs(),
+            951_868_800
+        );
+        assert_eq!(Timestamp::from_secs(-1).to_datetime(), DateTime::new(1969, 12, 31, 23, 59, 59));
+
+        // Every day across leap and century years maps back to itself
+        let mut secs = DateTime::new(1899, 1, 1, 12, 0, 0).to_timestamp().unwrap().secs();
+        let end = DateTime::new(2101, 1, 1, 0, 0, 0).to_timestamp().unwrap().secs();
+        while secs < end {
+            let dt = Timestamp::from_secs(secs).to_datetime();
+            assert!(dt.is_valid());
+            assert_eq!(dt.to_timestamp().unwrap().secs(), secs);
+            secs += SECS_PER_DAY;
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(DateTime::new(2024, 2, 29, 0, 0, 0).is_valid());
+        assert!(!DateTime::new(2023, 2, 29, 0, 0, 0).is_valid());
+        assert!(!DateTime::new(1900, 2, 29, 0, 0, 0).is_valid());
+        assert!(DateTime::new(2000, 2, 29, 0, 0, 0).is_valid());
+        assert!(!DateTime::new(2024, 13, 1, 0, 0, 0).is_valid());
+        assert!(!DateTime::new(2024, 4, 31, 0, 0, 0).is_valid());
+        assert!(!DateTime::new(2024, 1, 1, 24, 0, 0).is_valid());
+        assert_eq!(DateTime::new(2026, 10, 18, 0, 0, 0).weekday(), 0);
+        assert_eq!(Timestamp::new(5, 2_500_000_000), Timestamp::new(7, 500_000_000));
+    }
+
+    #[test]
+    fn test_fat() {
+        // 2024-02-29 13:45:31.25
+        let ts = Timestamp::new(
+            DateTime::new(2024, 2, 29, 13, 45, 31).to_timestamp().unwrap().secs(),
+            250_000_000,
+        );
+        let (date, time, centis) = ts.to_fat().unwrap();
+        assert_eq!(date, (44 << 9) | (2 << 5) | 29);
+        assert_eq!(time, (13 << 11) | (45 << 5) | 15);
+        assert_eq!(centis, 125);
+        assert_eq!(Timestamp::from_fat(date, time, centis), Some(ts));
+        // Without the count byte, two-second resolution
+        assert_eq!(Timestamp::from_fat(date, time, 0).unwrap().secs(), ts.secs() - 1);
+
+        assert_eq!(Timestamp::from_fat(0, 0, 0), None);
+        assert_eq!(Timestamp::from_fat((1 << 5) | 32, 0, 0), None);
+        assert_eq!(DateTime::new(1979, 12, 31, 0, 0, 0).to_timestamp().unwrap().to_fat(), None);
+        assert_eq!(DateTime::new(2108, 1, 1, 0, 0, 0).to_timestamp().unwrap().to_fat(), None);
+    }
+
+    #[test]
+    fn test_rtc() {
+        assert_eq!(bcd_to_bin(0x59), 59);
+        assert_eq!(bin_to_bcd(59), 0x59);
+
+        let dt = DateTime::new(2026, 10, 18, 0, 30, 5);
+        for status_b in [0, RTC_24_HOUR, RTC_BINARY, RTC_24_HOUR | RTC_BINARY] {
+            for hour in [0, 1, 11, 12, 13, 23] {
+                let dt = DateTime { hour, ..dt };
+                let regs = RtcRegisters::from_datetime(&dt, status_b);
+                assert_eq!(regs.to_datetime(), Some(dt));
+            }
+        }
+
+        // BCD, 12-hour: 12:15 AM is 00:15, 12:15 PM is 12:15; no century register
+        let mut regs = RtcRegisters { hours: 0x12, minutes: 0x15, day: 1, month: 1, year: 0x26, ..Default::default() };
+        assert_eq!(regs.to_datetime(), Some(DateTime::new(2026, 1, 1, 0, 15, 0)));
+        regs.hours = 0x92;
+        assert_eq!(regs.to_datetime().unwrap().hour, 12);
+
+        regs.month = 0x13;
+        assert_eq!(regs.to_datetime(), None);
+    }
+
+    #[test]
+    fn test_wfs_and_iso8601() {
+        let ts = Timestamp::from_wfs(1_792_315_800);
+        assert_eq!(ts.to_wfs(), 1_792_315_800);
+        assert_eq!(Timestamp::from_secs(-5).to_wfs(), 0);
+
+        assert_eq!(format!("{}", ts.iso8601()), "2026-10-18T09:30:00Z");
+        assert_eq!(format!("{}", Timestamp::new(ts.secs(), 5).iso8601()), "2026-10-18T09:30:00.000000005Z");
+        assert_eq!(format!("{}", ts.to_datetime()), "2026-10-18 09:30:00");
+    }
+}
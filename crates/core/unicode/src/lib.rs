@@ -0,0 +1,306 @@
+//! WATOS Unicode Module
+//!
+//! Text handling for file names and listings:
+//!
+//! - UTF-8: validation, lossy decoding, truncating at a character boundary
+//! - UTF-16: decoding (FAT long file names are stored as UTF-16)
+//! - Case folding, for case-insensitive comparison
+//! - Collation: the order directory listings are shown in
+//!
+//! Collation is deliberately simple rather than locale-tailored. Names are
+//! compared in three passes, each only breaking ties left by the previous:
+//!
+//! 1. Case and accents ignored, runs of digits compared as numbers:
+//!    `File2` < `file10`, `Éclair` between `echo` and `edit`
+//! 2. Accents distinguish: `resume` < `résumé`
+//! 3. Exact code points, so distinct names never compare equal
+//!
+//! Accents are only understood for Latin-1 and Latin Extended-A letters;
+//! other scripts sort by folded code point.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::cmp::Ordering;
+
+// ============================================================================
+// UTF-8
+// ============================================================================
+
+/// Whether `bytes` is valid UTF-8
+pub fn is_valid(bytes: &[u8]) -> bool {
+    core::str::from_utf8(bytes).is_ok()
+}
+
+/// Characters of possibly invalid UTF-8, with U+FFFD for each bad sequence
+///
+/// Unlike `String::from_utf8_lossy` this allocates nothing.
+pub fn chars_lossy(bytes: &[u8]) -> CharsLossy<'_> {
+    CharsLossy { rest: bytes, valid: "".chars(), bad: false }
+}
+
+/// Iterator made by [`chars_lossy`]
+pub struct CharsLossy<'a> {
+    /// Bytes not yet decoded
+    rest: &'a [u8],
+    /// Valid characters ahead of `rest`
+    valid: core::str::Chars<'a>,
+    /// An invalid sequence follows `valid`
+    bad: bool,
+}
+
+impl Iterator for CharsLossy<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.valid.next() {
+                return Some(c);
+            }
+            if self.bad {
+                self.bad = false;
+                return Some(char::REPLACEMENT_CHARACTER);
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            let (good, rest) = match core::str::from_utf8(self.rest) {
+                Ok(s) => (s, &[][..]),
+                Err(e) => {
+                    let (good, bad) = self.rest.split_at(e.valid_up_to());
+                    self.bad = true;
+                    // A truncated sequence at the end is one bad sequence
+                    let skip = e.error_len().unwrap_or(bad.len());
+                    // Safe: from_utf8 validated this prefix
+                    (unsafe { core::str::from_utf8_unchecked(good) }, &bad[skip..])
+                }
+            };
+            self.valid = good.chars();
+            self.rest = rest;
+        }
+    }
+}
+
+/// Longest prefix of `s` that fits in `max` bytes without splitting a
+/// character
+pub fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// ============================================================================
+// UTF-16
+// ============================================================================
+
+/// Decode UTF-16 code units, stopping at a NUL and replacing unpaired
+/// surrogates with U+FFFD
+pub fn from_utf16_lossy(units: &[u16]) -> String {
+    let end = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+    char::decode_utf16(units[..end].iter().copied())
+        .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+// ============================================================================
+// CASE FOLDING
+// ============================================================================
+
+/// Case-folded form of a string (Unicode lowercase mapping)
+pub fn fold(s: &str) -> String {
+    s.chars().flat_map(char::to_lowercase).collect()
+}
+
+/// Compare two strings ignoring case, without allocating
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Base letter of an accented Latin lowercase letter (`é` -> `e`);
+/// anything else is returned unchanged
+pub fn base_letter(c: char) -> char {
+    match c as u32 {
+        0xE0..=0xE5 => 'a',
+        0xE7 => 'c',
+        0xE8..=0xEB => 'e',
+        0xEC..=0xEF => 'i',
+        0xF0 => 'd',
+        0xF1 => 'n',
+        0xF2..=0xF6 | 0xF8 => 'o',
+        0xF9..=0xFC => 'u',
+        0xFD | 0xFF => 'y',
+        0xDF => 's',
+        // Latin Extended-A: runs of letters sharing a base, upper and
+        // lower case interleaved
+        0x100..=0x105 => 'a',
+        0x106..=0x10D => 'c',
+        0x10E..=0x111 => 'd',
+        0x112..=0x11B => 'e',
+        0x11C..=0x123 => 'g',
+        0x124..=0x127 => 'h',
+        0x128..=0x133 => 'i',
+        0x134..=0x135 => 'j',
+        0x136..=0x138 => 'k',
+        0x139..=0x142 => 'l',
+        0x143..=0x14B => 'n',
+        0x14C..=0x153 => 'o',
+        0x154..=0x159 => 'r',
+        0x15A..=0x161 | 0x17F => 's',
+        0x162..=0x167 => 't',
+        0x168..=0x173 => 'u',
+        0x174..=0x175 => 'w',
+        0x176..=0x178 => 'y',
+        0x179..=0x17E => 'z',
+        _ => c,
+    }
+}
+
+// ============================================================================
+// COLLATION
+// ============================================================================
+
+/// Order two names for display (see the module docs)
+pub fn collate(a: &str, b: &str) -> Ordering {
+    compare_keys(a, b, true)
+        .then_with(|| compare_keys(a, b, false))
+        .then_with(|| a.cmp(b))
+}
+
+/// Collation element: a run of digits, or one folded character
+#[derive(Clone, Copy)]
+enum Key<'a> {
+    /// Digits with leading zeros removed
+    Number(&'a str),
+    Char(char),
+}
+
+fn compare_keys(a: &str, b: &str, strip_accents: bool) -> Ordering {
+    let mut a = Keys::new(a, strip_accents);
+    let mut b = Keys::new(b, strip_accents);
+    loop {
+        let order = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(Key::Number(x)), Some(Key::Number(y))) => x.len().cmp(&y.len()).then_with(|| x.cmp(y)),
+            (Some(Key::Char(x)), Some(Key::Char(y))) => x.cmp(&y),
+            // A number sorts where its first digit would
+            (Some(Key::Number(_)), Some(Key::Char(y))) => '0'.cmp(&y),
+            (Some(Key::Char(x)), Some(Key::Number(_))) => x.cmp(&'0'),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// Collation elements of a string
+struct Keys<'a> {
+    rest: &'a str,
+    lower: Option<core::char::ToLowercase>,
+    strip_accents: bool,
+}
+
+impl<'a> Keys<'a> {
+    fn new(s: &'a str, strip_accents: bool) -> Self {
+        Keys { rest: s, lower: None, strip_accents }
+    }
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = Key<'a>;
+
+    fn next(&mut self) -> Option<Key<'a>> {
+        if let Some(c) = self.lower.as_mut().and_then(Iterator::next) {
+            return Some(self.letter(c));
+        }
+
+        let digits = self.rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 {
+            let (run, rest) = self.rest.split_at(digits);
+            self.rest = rest;
+            let trimmed = run.trim_start_matches('0');
+            return Some(Key::Number(if trimmed.is_empty() { "0" } else { trimmed }));
+        }
+
+        let c = self.rest.chars().next()?;
+        self.rest = &self.rest[c.len_utf8()..];
+        let mut lower = c.to_lowercase();
+        let first = lower.next().unwrap_or(c);
+        self.lower = Some(lower);
+        Some(self.letter(first))
+    }
+}
+
+impl Keys<'_> {
+    fn letter(&self, c: char) -> Key<'static> {
+        Key::Char(if self.strip_accents { base_letter(c) } else { c })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_utf8() {
+        assert!(is_valid("héllo".as_bytes()));
+        assert!(!is_valid(b"h\xE9llo"));
+
+        let lossy: String = chars_lossy(b"ab\xFFc\xE2\x82").collect();
+        assert_eq!(lossy, "ab\u{FFFD}c\u{FFFD}");
+        let lossy: String = chars_lossy("été".as_bytes()).collect();
+        assert_eq!(lossy, "été");
+
+        assert_eq!(truncate("héllo", 2), "h");
+        assert_eq!(truncate("héllo", 3), "hé");
+        assert_eq!(truncate("abc", 10), "abc");
+    }
+
+    #[test]
+    fn test_utf16() {
+        let units: Vec<u16> = "Résumé 📄.txt".encode_utf16().chain([0, 0xFFFF]).collect();
+        assert_eq!(from_utf16_lossy(&units), "Résumé 📄.txt");
+        assert_eq!(from_utf16_lossy(&[0x61, 0xD800, 0x62]), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn test_case_folding() {
+        assert_eq!(fold("ÉCOLE Straße"), "école straße");
+        assert!(eq_ignore_case("README.TXT", "readme.txt"));
+        assert!(eq_ignore_case("ΣΟΦΙΑ", "σοφια"));
+        assert!(!eq_ignore_case("école", "ecole"));
+    }
+
+    #[test]
+    fn test_collation() {
+        let mut names = [
+            "file10.txt", "File2.txt", "résumé", "Zebra", "eagle", "éclair", "resume",
+            "echo", "_build", "file2.txt", "Ünter", "apple", "file02.txt",
+        ];
+        names.sort_by(|a, b| collate(a, b));
+        assert_eq!(
+            names,
+            [
+                "_build", "apple", "eagle", "echo", "éclair", "File2.txt", "file02.txt", "file2.txt",
+                "file10.txt", "resume", "résumé", "Ünter", "Zebra",
+            ]
+        );
+
+        assert_eq!(collate("a", "a"), Ordering::Equal);
+        assert_eq!(collate("a", "ab"), Ordering::Less);
+        assert_eq!(collate("9", "a"), Ordering::Less);
+        assert_eq!(collate(".hidden", "1st"), Ordering::Less);
+    }
+}
@@ -0,0 +1,73 @@
+//! PC Speaker Driver
+//!
+//! The speaker is driven by PIT channel 2 in square wave mode, gated
+//! through port 0x61. It can only sound one tone at a time, so it
+//! implements [`Beep`] rather than `AudioDevice`; the kernel uses it for
+//! SYS_BEEP and the console bell. Every PC has one, sound card or not.
+
+#![no_std]
+
+use watos_arch::port::{inb, outb};
+use watos_driver_traits::audio::Beep;
+use watos_driver_traits::{DriverError, DriverResult};
+
+/// PIT input clock
+pub const PIT_HZ: u32 = 1_193_182;
+
+/// PIT ports
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+
+/// Channel 2, LSB then MSB, mode 3 (square wave), binary
+const PIT_CH2_SQUARE: u8 = 0xB6;
+
+/// System control port B: bit 0 gates channel 2, bit 1 connects the speaker
+const PORT_B: u16 = 0x61;
+const PORT_B_SPEAKER: u8 = 0x03;
+
+/// PIT reload value for `freq_hz`, or None if the PIT can't make it
+fn divisor(freq_hz: u32) -> Option<u16> {
+    if freq_hz == 0 {
+        return None;
+    }
+    match PIT_HZ / freq_hz {
+        0 => None,
+        d => u16::try_from(d).ok(),
+    }
+}
+
+/// The PC speaker. The hardware is the only state, so any instance will do.
+pub struct PcSpeaker;
+
+impl Beep for PcSpeaker {
+    fn tone(&mut self, freq_hz: u32) -> DriverResult<()> {
+        let div = divisor(freq_hz).ok_or(DriverError::InvalidParameter)?;
+        unsafe {
+            outb(PIT_COMMAND, PIT_CH2_SQUARE);
+            outb(PIT_CHANNEL2, div as u8);
+            outb(PIT_CHANNEL2, (div >> 8) as u8);
+            outb(PORT_B, inb(PORT_B) | PORT_B_SPEAKER);
+        }
+        Ok(())
+    }
+
+    fn silence(&mut self) {
+        unsafe { outb(PORT_B, inb(PORT_B) & !PORT_B_SPEAKER); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divisor() {
+        assert_eq!(divisor(1000), Some(1193));
+        assert_eq!(divisor(PIT_HZ), Some(1));
+        // Below ~18.2 Hz the count doesn't fit in 16 bits
+        assert_eq!(divisor(18), None);
+        assert_eq!(divisor(19), Some(62799));
+        assert_eq!(divisor(0), None);
+        assert_eq!(divisor(PIT_HZ + 1), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Legacy analog gameport (port 0x201)
+//!
+//! Writing the port fires four one-shots, one per axis; each bit reads 1
+//! until its one-shot times out, after a time proportional to the stick's
+//! resistance. The high nibble holds the four buttons, 0 while pressed.
+//! There is no timer to read, so positions are counted in port reads and
+//! scaled against the count seen at probe time, when the sticks are
+//! assumed centered. Reads are made from syscalls, with interrupts off.
+
+use watos_arch::port::{inb, outb};
+
+use crate::MAX_AXES;
+
+const GAMEPORT: u16 = 0x201;
+
+/// Port reads before giving up on an axis. Real sticks finish within a
+/// few thousand.
+const TIMEOUT: u32 = 20_000;
+
+/// Even a stick pushed fully one way holds its bit high for ~24 us. An
+/// axis that drops sooner is a port that reads 0, not a joystick.
+const MIN_COUNT: u32 = 4;
+
+pub struct Gameport {
+    /// Counts for each stick axis at rest; 0 if the axis never answered
+    centers: [u32; 4],
+}
+
+impl Gameport {
+    /// Look for a joystick. With nothing plugged in the axis bits stay high
+    /// (or the port reads 0xFF on machines without one), so an axis counts
+    /// as present once its bit drops before the timeout.
+    pub fn probe() -> Option<Self> {
+        let mut centers = measure();
+        for center in centers.iter_mut() {
+            if *center < MIN_COUNT {
+                *center = 0;
+            }
+        }
+        if centers[0] == 0 || centers[1] == 0 {
+            return None;
+        }
+        Some(Gameport { centers })
+    }
+
+    /// 2 for one stick, 4 when stick B answered too
+    pub fn axis_count(&self) -> u8 {
+        if self.centers[2] != 0 && self.centers[3] != 0 { 4 } else { 2 }
+    }
+
+    /// Current (buttons, axes)
+    pub fn read(&mut self) -> (u32, [i16; MAX_AXES]) {
+        let counts = measure();
+        let raw = unsafe { inb(GAMEPORT) };
+        let mut axes = [0; MAX_AXES];
+        for (axis, value) in axes.iter_mut().take(4).enumerate() {
+            *value = scale(counts[axis], self.centers[axis]);
+        }
+        (buttons(raw), axes)
+    }
+}
+
+/// Fire the one-shots and count reads until each axis bit drops
+fn measure() -> [u32; 4] {
+    let mut counts = [0; 4];
+    let mut pending = 0x0F;
+    unsafe { outb(GAMEPORT, 0xFF) };
+    for count in 1..=TIMEOUT {
+        let bits = unsafe { inb(GAMEPORT) } & pending;
+        let dropped = pending & !bits;
+        for (axis, slot) in counts.iter_mut().enumerate() {
+            if dropped & (1 << axis) != 0 {
+                *slot = count;
+            }
+        }
+        pending = bits;
+        if pending == 0 {
+            break;
+        }
+    }
+    counts
+}
+
+/// Position of an axis: `center` maps to 0, twice it (or more) to 32767
+fn scale(count: u32, center: u32) -> i16 {
+    if center == 0 || count == 0 {
+        return 0;
+    }
+    let offset = (count as i64 - center as i64) * i16::MAX as i64 / center as i64;
+    offset.clamp(-(i16::MAX as i64), i16::MAX as i64) as i16
+}
+
+/// Button bits 4-7 (active low) as buttons 0-3: A1, A2, B1, B2
+fn buttons(raw: u8) -> u32 {
+    ((!raw >> 4) & 0x0F) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_and_buttons() {
+        assert_eq!(scale(500, 500), 0);
+        assert_eq!(scale(1000, 500), 32767);
+        assert_eq!(scale(5000, 500), 32767);
+        assert_eq!(scale(250, 500), -16383);
+        assert_eq!(scale(1, 500), -32701);
+        // Axis missing or timed out
+        assert_eq!(scale(0, 500), 0);
+        assert_eq!(scale(300, 0), 0);
+
+        assert_eq!(buttons(0xFF), 0);
+        assert_eq!(buttons(0xEF), 0b0001);
+        assert_eq!(buttons(0x5F), 0b1010);
+        assert_eq!(buttons(0x0F), 0b1111);
+    }
+}
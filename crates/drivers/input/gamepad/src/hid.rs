@@ -0,0 +1,427 @@
+//! USB HID gamepads
+//!
+//! A HID device describes its input reports in a report descriptor.
+//! [`ReportLayout::parse`] walks it for the fields a pad uses: X, Y, Z, Rx,
+//! Ry and Rz become axes 0-5, buttons 1-16 become buttons 0-15, and a hat
+//! switch becomes four buttons from [`HAT_BUTTON`] (up, right, down, left).
+//! Other fields are skipped. Only variable (not array) input fields are
+//! read, which covers the pads in common use.
+
+use crate::MAX_AXES;
+
+/// First of the four d-pad buttons a hat switch is reported as
+pub const HAT_BUTTON: u8 = 16;
+
+/// Buttons read from the Button usage page
+const MAX_HID_BUTTONS: u32 = 16;
+
+/// Usage pages
+const PAGE_GENERIC_DESKTOP: u16 = 0x01;
+const PAGE_BUTTON: u16 = 0x09;
+
+/// Generic Desktop usages
+const USAGE_X: u16 = 0x30;
+const USAGE_RZ: u16 = 0x35;
+const USAGE_HAT: u16 = 0x39;
+
+/// Input item flags
+const INPUT_CONSTANT: u32 = 0x01;
+const INPUT_VARIABLE: u32 = 0x02;
+
+/// Usages kept from one main item's local items
+const MAX_USAGES: usize = 16;
+
+/// Report IDs tracked while parsing
+const MAX_REPORT_IDS: usize = 8;
+
+/// Where a value sits in the report, and its range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// Offset in bits, after the report ID byte
+    pub bit: u32,
+    pub size: u8,
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Field {
+    /// The raw value, sign-extended if the range goes negative
+    fn read(&self, data: &[u8]) -> Option<i32> {
+        if self.size == 0 || self.size > 32 {
+            return None;
+        }
+        let mut value: u64 = 0;
+        for i in 0..self.size as u32 {
+            let bit = self.bit + i;
+            let byte = *data.get((bit / 8) as usize)?;
+            value |= (((byte >> (bit % 8)) & 1) as u64) << i;
+        }
+        let value = if self.min < 0 && value & (1 << (self.size - 1)) != 0 {
+            (value | (u64::MAX << self.size)) as i64
+        } else {
+            value as i64
+        };
+        Some(value as i32)
+    }
+}
+
+/// The gamepad fields of one input report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportLayout {
+    /// The report's ID byte, if the device numbers its reports
+    pub report_id: Option<u8>,
+    /// Bit offsets of buttons 0-15
+    pub buttons: [Option<u32>; MAX_HID_BUTTONS as usize],
+    pub axes: [Option<Field>; MAX_AXES],
+    pub hat: Option<Field>,
+}
+
+/// Item state that persists between main items
+#[derive(Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+impl ReportLayout {
+    /// Build the layout from a report descriptor. Returns None if it has
+    /// no buttons or axes, or is malformed.
+    pub fn parse(desc: &[u8]) -> Option<Self> {
+        let mut layout = ReportLayout {
+            report_id: None,
+            buttons: [None; MAX_HID_BUTTONS as usize],
+            axes: [None; MAX_AXES],
+            hat: None,
+        };
+        let mut globals = Globals::default();
+        let mut stack = [Globals::default(); 4];
+        let mut depth = 0;
+        let mut usages = [0u32; MAX_USAGES];
+        let mut usage_count = 0;
+        let (mut usage_min, mut usage_max) = (None, None);
+        // Input bits seen so far, per report ID
+        let mut offsets = [(0u8, 0u32); MAX_REPORT_IDS];
+        let mut ids = 1;
+        // Report ID of the first pad field; 0 if reports aren't numbered
+        let mut chosen = None;
+
+        let mut pos = 0;
+        while pos < desc.len() {
+            let prefix = desc[pos];
+            if prefix == 0xFE {
+                // Long item: size, tag, data
+                pos += 3 + *desc.get(pos + 1)? as usize;
+                continue;
+            }
+            let size = match prefix & 0x03 { 3 => 4, n => n as usize };
+            let bytes = desc.get(pos + 1..pos + 1 + size)?;
+            pos += 1 + size;
+            let unsigned = bytes.iter().rev().fold(0u32, |v, &b| v << 8 | b as u32);
+            let signed = match size {
+                1 => bytes[0] as i8 as i32,
+                2 => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+                4 => unsigned as i32,
+                _ => 0,
+            };
+
+            match (prefix >> 2) & 0x03 {
+                // Main
+                0 => {
+                    if prefix >> 4 == 0x8 {
+                        let slot = match offsets[..ids].iter().position(|&(id, _)| id == globals.report_id) {
+                            Some(slot) => slot,
+                            None if ids < MAX_REPORT_IDS => {
+                                offsets[ids] = (globals.report_id, 0);
+                                ids += 1;
+                                ids - 1
+                            }
+                            None => return None,
+                        };
+                        let start = offsets[slot].1;
+                        if unsigned & (INPUT_CONSTANT | INPUT_VARIABLE) == INPUT_VARIABLE {
+                            for i in 0..globals.report_count {
+                                let usage = if usage_count > 0 {
+                                    usages[(i as usize).min(usage_count - 1)]
+                                } else {
+                                    match (usage_min, usage_max) {
+                                        (Some(min), Some(max)) if min + i <= max => min + i,
+                                        _ => continue,
+                                    }
+                                };
+                                let field = Field {
+                                    bit: start.saturating_add(i.saturating_mul(globals.report_size)),
+                                    size: globals.report_size as u8,
+                                    min: globals.logical_min,
+                                    max: globals.logical_max,
+                                };
+                                if layout.add(usage, globals.usage_page, field) {
+                                    match chosen {
+                                        None => chosen = Some(globals.report_id),
+                                        // A second report: keep the first one's fields only
+                                        Some(id) if id != globals.report_id => layout.remove(usage, globals.usage_page),
+                                        _ => {}
+                                    }
+                                }
+                            }
+                        }
+                        offsets[slot].1 = start.saturating_add(globals.report_size.saturating_mul(globals.report_count));
+                    }
+                    usage_count = 0;
+                    usage_min = None;
+                    usage_max = None;
+                }
+                // Global
+                1 => match prefix >> 4 {
+                    0x0 => globals.usage_page = unsigned as u16,
+                    0x1 => globals.logical_min = signed,
+                    0x2 => {
+                        // A maximum that only fits unsigned is positive
+                        globals.logical_max = if globals.logical_min >= 0 { unsigned as i32 } else { signed };
+                    }
+                    0x7 => globals.report_size = unsigned,
+                    0x8 => globals.report_id = unsigned as u8,
+                    0x9 => globals.report_count = unsigned,
+                    0xA if depth < stack.len() => {
+                        stack[depth] = globals;
+                        depth += 1;
+                    }
+                    0xB if depth > 0 => {
+                        depth -= 1;
+                        globals = stack[depth];
+                    }
+                    _ => {}
+                },
+                // Local
+                2 => {
+                    // A 4-byte usage carries its own page in the high half
+                    let usage = if size == 4 { unsigned } else { (globals.usage_page as u32) << 16 | unsigned };
+                    match prefix >> 4 {
+                        0x0 if usage_count < MAX_USAGES => {
+                            usages[usage_count] = usage;
+                            usage_count += 1;
+                        }
+                        0x1 => usage_min = Some(usage),
+                        0x2 => usage_max = Some(usage),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        layout.report_id = chosen.filter(|&id| id != 0);
+        let found = layout.buttons.iter().any(Option::is_some)
+            || layout.axes.iter().any(Option::is_some)
+            || layout.hat.is_some();
+        found.then_some(layout)
+    }
+
+    /// Record a field if it's one a pad uses; returns whether it was
+    fn add(&mut self, usage: u32, page: u16, field: Field) -> bool {
+        let page = match (usage >> 16) as u16 { 0 => page, p => p };
+        let id = usage as u16;
+        match page {
+            PAGE_BUTTON if (1..=MAX_HID_BUTTONS).contains(&(id as u32)) && field.size == 1 => {
+                self.buttons[id as usize - 1] = Some(field.bit);
+                true
+            }
+            PAGE_GENERIC_DESKTOP if (USAGE_X..=USAGE_RZ).contains(&id) => {
+                self.axes[(id - USAGE_X) as usize] = Some(field);
+                true
+            }
+            PAGE_GENERIC_DESKTOP if id == USAGE_HAT => {
+                self.hat = Some(field);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Undo `add`
+    fn remove(&mut self, usage: u32, page: u16) {
+        let page = match (usage >> 16) as u16 { 0 => page, p => p };
+        let id = usage as u16;
+        match page {
+            PAGE_BUTTON => self.buttons[id as usize - 1] = None,
+            PAGE_GENERIC_DESKTOP if id == USAGE_HAT => self.hat = None,
+            _ => self.axes[(id - USAGE_X) as usize] = None,
+        }
+    }
+
+    /// Buttons to connect the pad with
+    pub fn button_count(&self) -> u8 {
+        if self.hat.is_some() {
+            return HAT_BUTTON + 4;
+        }
+        self.buttons.iter().rposition(Option::is_some).map_or(0, |n| n as u8 + 1)
+    }
+
+    /// Axes to connect the pad with
+    pub fn axis_count(&self) -> u8 {
+        self.axes.iter().rposition(Option::is_some).map_or(0, |n| n as u8 + 1)
+    }
+
+    /// (buttons, axes) from an input report, or None if it's another report
+    /// or too short
+    pub fn decode(&self, report: &[u8]) -> Option<(u32, [i16; MAX_AXES])> {
+        let data = match self.report_id {
+            Some(id) => report.strip_prefix(&[id])?,
+            None => report,
+        };
+
+        let mut buttons = 0u32;
+        for (n, bit) in self.buttons.iter().enumerate() {
+            if let Some(bit) = *bit {
+                let byte = *data.get((bit / 8) as usize)?;
+                if byte & (1 << (bit % 8)) != 0 {
+                    buttons |= 1 << n;
+                }
+            }
+        }
+
+        if let Some(hat) = self.hat {
+            // Eight directions clockwise from up; anything else is centered
+            let dir = hat.read(data)? - hat.min;
+            if (0..8).contains(&dir) {
+                let dpad = match dir {
+                    0 => 0b0001,
+                    1 => 0b0011,
+                    2 => 0b0010,
+                    3 => 0b0110,
+                    4 => 0b0100,
+                    5 => 0b1100,
+                    6 => 0b1000,
+                    _ => 0b1001,
+                };
+                buttons |= dpad << HAT_BUTTON;
+            }
+        }
+
+        let mut axes = [0; MAX_AXES];
+        for (axis, field) in axes.iter_mut().zip(&self.axes) {
+            if let Some(field) = field {
+                *axis = normalize(field.read(data)?, field.min, field.max);
+            }
+        }
+        Some((buttons, axes))
+    }
+}
+
+/// Map min..=max onto -32767..=32767
+fn normalize(value: i32, min: i32, max: i32) -> i16 {
+    if max <= min {
+        return 0;
+    }
+    let value = value.clamp(min, max) as i64;
+    let scaled = (value - min as i64) * 65534 / (max as i64 - min as i64) - 32767;
+    scaled as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A typical pad: report ID 1, X/Y/Z/Rz as 0..255, a 4-bit hat with
+    /// 4 bits of padding, then 12 buttons and 4 bits of padding
+    const PAD: &[u8] = &[
+        0x05, 0x01,       // Usage Page (Generic Desktop)
+        0x09, 0x05,       // Usage (Game Pad)
+        0xA1, 0x01,       // Collection (Application)
+        0x85, 0x01,       //   Report ID (1)
+        0x09, 0x30,       //   Usage (X)
+        0x09, 0x31,       //   Usage (Y)
+        0x09, 0x32,       //   Usage (Z)
+        0x09, 0x35,       //   Usage (Rz)
+        0x15, 0x00,       //   Logical Minimum (0)
+        0x26, 0xFF, 0x00, //   Logical Maximum (255)
+        0x75, 0x08,       //   Report Size (8)
+        0x95, 0x04,       //   Report Count (4)
+        0x81, 0x02,       //   Input (Data, Var, Abs)
+        0x09, 0x39,       //   Usage (Hat switch)
+        0x15, 0x00,       //   Logical Minimum (0)
+        0x25, 0x07,       //   Logical Maximum (7)
+        0x75, 0x04,       //   Report Size (4)
+        0x95, 0x01,       //   Report Count (1)
+        0x81, 0x42,       //   Input (Data, Var, Abs, Null)
+        0x81, 0x01,       //   Input (Const) - padding
+        0x05, 0x09,       //   Usage Page (Button)
+        0x19, 0x01,       //   Usage Minimum (1)
+        0x29, 0x0C,       //   Usage Maximum (12)
+        0x15, 0x00,       //   Logical Minimum (0)
+        0x25, 0x01,       //   Logical Maximum (1)
+        0x75, 0x01,       //   Report Size (1)
+        0x95, 0x0C,       //   Report Count (12)
+        0x81, 0x02,       //   Input (Data, Var, Abs)
+        0x75, 0x04,       //   Report Size (4)
+        0x95, 0x01,       //   Report Count (1)
+        0x81, 0x01,       //   Input (Const) - padding
+        0x85, 0x02,       //   Report ID (2)
+        0x05, 0x01,       //   Usage Page (Generic Desktop)
+        0x09, 0x33,       //   Usage (Rx) - in another report, ignored
+        0x75, 0x08,       //   Report Size (8)
+        0x95, 0x01,       //   Report Count (1)
+        0x81, 0x02,       //   Input (Data, Var, Abs)
+        0xC0,             // End Collection
+    ];
+
+    #[test]
+    fn test_parse() {
+        let layout = ReportLayout::parse(PAD).unwrap();
+        assert_eq!(layout.report_id, Some(1));
+        assert_eq!(layout.axes[0], Some(Field { bit: 0, size: 8, min: 0, max: 255 }));
+        assert_eq!(layout.axes[5].map(|f| f.bit), Some(24));
+        assert_eq!(layout.axes[3], None);
+        assert_eq!(layout.hat.map(|f| f.bit), Some(32));
+        assert_eq!(layout.buttons[0], Some(40));
+        assert_eq!(layout.buttons[11], Some(51));
+        assert_eq!(layout.buttons[12], None);
+        assert_eq!(layout.axis_count(), 6);
+        assert_eq!(layout.button_count(), HAT_BUTTON + 4);
+
+        assert_eq!(ReportLayout::parse(&[0x05, 0x01, 0xC0]), None);
+        // Truncated item
+        assert_eq!(ReportLayout::parse(&[0x05, 0x01, 0x26, 0xFF]), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        let layout = ReportLayout::parse(PAD).unwrap();
+        // X left, Y centered-ish, Z right, Rz 0; hat down-left; buttons 1 and 12
+        let report = [0x01, 0x00, 0x80, 0xFF, 0x00, 0x05, 0x01, 0x08];
+        let (buttons, axes) = layout.decode(&report).unwrap();
+        assert_eq!(axes, [-32767, 128, 32767, 0, 0, -32767]);
+        assert_eq!(buttons, 0b1000_0000_0001 | 0b1100 << HAT_BUTTON);
+
+        // Hat null state (8) is centered
+        let report = [0x01, 0x80, 0x80, 0x80, 0x80, 0x08, 0x00, 0x00];
+        let (buttons, _) = layout.decode(&report).unwrap();
+        assert_eq!(buttons, 0);
+
+        // Other report IDs and short reports are skipped
+        assert_eq!(layout.decode(&[0x02, 0x10]), None);
+        assert_eq!(layout.decode(&[0x01, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn test_signed_fields() {
+        // No report ID, X/Y as -127..127
+        let desc = [
+            0x05, 0x01, 0x09, 0x04, 0xA1, 0x01,
+            0x09, 0x30, 0x09, 0x31,
+            0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x02, 0x81, 0x02,
+            0xC0,
+        ];
+        let layout = ReportLayout::parse(&desc).unwrap();
+        assert_eq!(layout.report_id, None);
+        assert_eq!(layout.button_count(), 0);
+        assert_eq!(layout.axis_count(), 2);
+        let (_, axes) = layout.decode(&[0x81, 0x7F]).unwrap();
+        assert_eq!(&axes[..2], &[-32767, 32767]);
+        let (_, axes) = layout.decode(&[0x00, 0xC0]).unwrap();
+        assert_eq!(&axes[..2], &[0, -16513]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Gamepad and Joystick Driver
+//!
+//! Controllers are numbered pads in [`GAMEPADS`], which compares each new
+//! state with the last and queues `GamepadDown`/`GamepadUp`/`GamepadAxis`
+//! [`InputEvent`]s for polling. Programs read the current state with
+//! SYS_GAMEPAD_STATE instead.
+//!
+//! Two sources feed it:
+//!
+//! - [`gameport`]: the legacy analog joystick port at 0x201, probed by
+//!   [`init`] and read by [`poll`].
+//! - [`hid`]: USB HID gamepads. [`hid::ReportLayout`] is built from the
+//!   device's report descriptor and turns input reports into states; the
+//!   USB host controller driver hands reports to [`report`].
+//!
+//! Axes run from -32767 (left/up) to 32767 (right/down). For two-stick
+//! gameport joysticks, axes 0/1 and buttons 0/1 are stick A and axes 2/3
+//! and buttons 2/3 stick B, which is the order BASIC's STICK and STRIG use.
+
+#![no_std]
+
+pub mod gameport;
+pub mod hid;
+
+use spin::Mutex;
+use watos_driver_traits::input::{InputDevice, InputDeviceInfo, InputDeviceType, InputEvent};
+use watos_driver_traits::DriverResult;
+
+/// Pads the registry holds
+pub const MAX_PADS: usize = 4;
+/// Axes reported per pad
+pub const MAX_AXES: usize = 6;
+/// Buttons reported per pad
+pub const MAX_BUTTONS: usize = 32;
+
+/// Axis movement smaller than this isn't reported as an event
+const AXIS_DEADBAND: i16 = 1024;
+
+/// Where a pad is connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GamepadKind {
+    Gameport = 1,
+    UsbHid = 2,
+}
+
+/// A pad's controls. The layout must match watos_syscall::gamepad::GamepadInfo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct GamepadState {
+    /// GamepadKind, or 0 if nothing is connected
+    pub kind: u8,
+    pub button_count: u8,
+    pub axis_count: u8,
+    pub reserved: u8,
+    /// Bit n set while button n is down
+    pub buttons: u32,
+    pub axes: [i16; MAX_AXES],
+}
+
+/// Events decoded but not yet polled
+const QUEUE_LEN: usize = 32;
+
+/// Connected pads and their pending events
+pub struct Gamepads {
+    pads: [Option<GamepadState>; MAX_PADS],
+    queue: [Option<InputEvent>; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl Gamepads {
+    pub const fn new() -> Self {
+        Gamepads { pads: [None; MAX_PADS], queue: [None; QUEUE_LEN], head: 0, len: 0 }
+    }
+
+    /// Add a pad with the given controls; returns its number, or None if
+    /// all slots are taken
+    pub fn connect(&mut self, kind: GamepadKind, button_count: u8, axis_count: u8) -> Option<u8> {
+        let slot = self.pads.iter().position(|p| p.is_none())?;
+        self.pads[slot] = Some(GamepadState {
+            kind: kind as u8,
+            button_count: button_count.min(MAX_BUTTONS as u8),
+            axis_count: axis_count.min(MAX_AXES as u8),
+            ..GamepadState::default()
+        });
+        Some(slot as u8)
+    }
+
+    /// Remove a pad, releasing any buttons it had down
+    pub fn disconnect(&mut self, pad: u8) {
+        if let Some(state) = self.pads.get_mut(pad as usize).and_then(Option::take) {
+            for button in 0..MAX_BUTTONS as u8 {
+                if state.buttons & (1 << button) != 0 {
+                    self.push(InputEvent::GamepadUp(pad, button));
+                }
+            }
+        }
+    }
+
+    /// A pad's current state
+    pub fn state(&self, pad: u8) -> Option<GamepadState> {
+        self.pads.get(pad as usize).copied().flatten()
+    }
+
+    /// Bit n set if pad n is connected
+    pub fn connected(&self) -> u32 {
+        self.pads.iter().enumerate()
+            .filter(|(_, p)| p.is_some())
+            .fold(0, |mask, (n, _)| mask | 1 << n)
+    }
+
+    /// Record a new reading and queue an event per change. Buttons and axes
+    /// beyond the pad's counts are ignored.
+    pub fn update(&mut self, pad: u8, buttons: u32, axes: &[i16; MAX_AXES]) {
+        let Some(old) = self.state(pad) else { return };
+        let buttons = match old.button_count as usize {
+            MAX_BUTTONS => buttons,
+            n => buttons & ((1 << n) - 1),
+        };
+
+        let changed = buttons ^ old.buttons;
+        for button in 0..MAX_BUTTONS as u8 {
+            if changed & (1 << button) != 0 {
+                self.push(if buttons & (1 << button) != 0 {
+                    InputEvent::GamepadDown(pad, button)
+                } else {
+                    InputEvent::GamepadUp(pad, button)
+                });
+            }
+        }
+
+        let mut new = GamepadState { buttons, ..old };
+        for (axis, &value) in axes.iter().enumerate().take(old.axis_count as usize) {
+            if value.abs_diff(old.axes[axis]) >= AXIS_DEADBAND as u16 {
+                self.push(InputEvent::GamepadAxis(pad, axis as u8, value));
+            }
+            new.axes[axis] = value;
+        }
+        self.pads[pad as usize] = Some(new);
+    }
+
+    fn push(&mut self, event: InputEvent) {
+        if self.len < QUEUE_LEN {
+            self.queue[(self.head + self.len) % QUEUE_LEN] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<InputEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.queue[self.head].take();
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        event
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// All pads as one input device
+pub struct GamepadInput {
+    pads: Mutex<Gamepads>,
+}
+
+impl GamepadInput {
+    pub const fn new() -> Self {
+        GamepadInput { pads: Mutex::new(Gamepads::new()) }
+    }
+
+    /// Run `f` on the registry
+    pub fn with<R>(&self, f: impl FnOnce(&mut Gamepads) -> R) -> R {
+        f(&mut self.pads.lock())
+    }
+}
+
+impl Default for GamepadInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for GamepadInput {
+    fn poll_event(&self) -> DriverResult<Option<InputEvent>> {
+        poll();
+        Ok(self.pads.lock().pop())
+    }
+
+    fn has_events(&self) -> bool {
+        self.pads.lock().len != 0
+    }
+
+    fn info(&self) -> InputDeviceInfo {
+        InputDeviceInfo {
+            name: "Gamepads",
+            device_type: InputDeviceType::Gamepad,
+        }
+    }
+}
+
+/// Every connected pad
+pub static GAMEPADS: GamepadInput = GamepadInput::new();
+
+/// The gameport, if [`init`] found a joystick on it, and its pad number
+static GAMEPORT: Mutex<Option<(gameport::Gameport, u8)>> = Mutex::new(None);
+
+/// Probe the gameport. Returns true if a joystick answered.
+pub fn init() -> bool {
+    let Some(port) = gameport::Gameport::probe() else { return false };
+    let Some(pad) = GAMEPADS.with(|g| g.connect(GamepadKind::Gameport, 4, port.axis_count())) else {
+        return false;
+    };
+    *GAMEPORT.lock() = Some((port, pad));
+    true
+}
+
+/// Read the polled devices (the gameport) into the registry
+pub fn poll() {
+    if let Some((port, pad)) = GAMEPORT.lock().as_mut() {
+        let (buttons, axes) = port.read();
+        GAMEPADS.with(|g| g.update(*pad, buttons, &axes));
+    }
+}
+
+/// Feed an input report from a USB HID pad connected as `pad`
+pub fn report(pad: u8, layout: &hid::ReportLayout, data: &[u8]) {
+    if let Some((buttons, axes)) = layout.decode(data) {
+        GAMEPADS.with(|g| g.update(pad, buttons, &axes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_and_events() {
+        let mut pads = Gamepads::new();
+        let a = pads.connect(GamepadKind::Gameport, 4, 4).unwrap();
+        let b = pads.connect(GamepadKind::UsbHid, 12, 6).unwrap();
+        assert_eq!((a, b), (0, 1));
+        assert_eq!(pads.connected(), 0b11);
+
+        // Button 5 is beyond the gameport's four and is dropped
+        let mut axes = [0; MAX_AXES];
+        axes[1] = -32767;
+        axes[4] = 5000;
+        pads.update(a, 0b10_0001, &axes);
+        assert_eq!(pads.pop(), Some(InputEvent::GamepadDown(0, 0)));
+        assert_eq!(pads.pop(), Some(InputEvent::GamepadAxis(0, 1, -32767)));
+        assert_eq!(pads.pop(), None);
+        let state = pads.state(a).unwrap();
+        assert_eq!(state.buttons, 1);
+        assert_eq!(state.axes, [0, -32767, 0, 0, 0, 0]);
+
+        // Jitter inside the deadband is tracked but not reported
+        axes[1] = -32000;
+        pads.update(a, 1, &axes);
+        assert_eq!(pads.pop(), None);
+        assert_eq!(pads.state(a).unwrap().axes[1], -32000);
+
+        pads.disconnect(a);
+        assert_eq!(pads.pop(), Some(InputEvent::GamepadUp(0, 0)));
+        assert_eq!(pads.state(a), None);
+        assert_eq!(pads.connected(), 0b10);
+        assert_eq!(pads.connect(GamepadKind::Gameport, 4, 2), Some(0));
+    }
+}
@@ -2,6 +2,12 @@
 //!
 //! Provides scancode-to-ASCII conversion and keyboard state management.
 //! Supports US keyboard layout with modifier keys (Shift, Ctrl, Alt, Caps Lock).
+//!
+//! Keys added after the original XT keyboard send an 0xE0 prefix before
+//! their scancode: the arrows and the navigation block, right Ctrl and Alt,
+//! keypad Enter and /. Pause sends a six-byte sequence starting with 0xE1.
+//! [`Keyboard`] follows these sequences and reports every key as a
+//! [`KeyEvent`] with a [`KeyCode`], plus the ASCII it types, if any.
 
 #![no_std]
 
@@ -135,8 +141,108 @@ pub mod scancodes {
     pub const NUM_LOCK: u8 = 0x45;
     pub const SCROLL_LOCK: u8 = 0x46;
 
+    pub const F11: u8 = 0x57;
+    pub const F12: u8 = 0x58;
+
     /// Release flag (OR'd with scancode)
     pub const RELEASE: u8 = 0x80;
+
+    /// Prefix of the extended keys
+    pub const EXTENDED: u8 = 0xE0;
+    /// Prefix of the Pause sequence (E1 1D 45 E1 9D C5)
+    pub const PAUSE: u8 = 0xE1;
+
+    /// Scancodes that follow [`EXTENDED`]
+    pub mod extended {
+        pub const KEYPAD_ENTER: u8 = 0x1C;
+        pub const RIGHT_CTRL: u8 = 0x1D;
+        /// Sent around some keys to undo or fake Shift; not a key
+        pub const FAKE_LEFT_SHIFT: u8 = 0x2A;
+        pub const KEYPAD_SLASH: u8 = 0x35;
+        pub const FAKE_RIGHT_SHIFT: u8 = 0x36;
+        pub const PRINT_SCREEN: u8 = 0x37;
+        pub const RIGHT_ALT: u8 = 0x38;
+        pub const HOME: u8 = 0x47;
+        pub const UP: u8 = 0x48;
+        pub const PAGE_UP: u8 = 0x49;
+        pub const LEFT: u8 = 0x4B;
+        pub const RIGHT: u8 = 0x4D;
+        pub const END: u8 = 0x4F;
+        pub const DOWN: u8 = 0x50;
+        pub const PAGE_DOWN: u8 = 0x51;
+        pub const INSERT: u8 = 0x52;
+        pub const DELETE: u8 = 0x53;
+        pub const LEFT_GUI: u8 = 0x5B;
+        pub const RIGHT_GUI: u8 = 0x5C;
+        pub const MENU: u8 = 0x5D;
+    }
+}
+
+/// What a key is, independent of modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    /// A key that types something: the unshifted US character (Enter is
+    /// b'\n', Escape 27, Backspace 8, keypad keys their digit or symbol)
+    Char(u8),
+    /// F1 to F12
+    F(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    LeftShift,
+    RightShift,
+    LeftCtrl,
+    RightCtrl,
+    LeftAlt,
+    RightAlt,
+    LeftGui,
+    RightGui,
+    Menu,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    PrintScreen,
+    Pause,
+    /// A scancode with no meaning here
+    Unknown { extended: bool, code: u8 },
+}
+
+impl KeyCode {
+    /// Shift, Ctrl, Alt, GUI and the locks
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            KeyCode::LeftShift
+                | KeyCode::RightShift
+                | KeyCode::LeftCtrl
+                | KeyCode::RightCtrl
+                | KeyCode::LeftAlt
+                | KeyCode::RightAlt
+                | KeyCode::LeftGui
+                | KeyCode::RightGui
+                | KeyCode::CapsLock
+                | KeyCode::NumLock
+                | KeyCode::ScrollLock
+        )
+    }
+}
+
+/// A key going down or up
+#[derive(Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub pressed: bool,
+    /// What the key types with the current modifiers; None on release
+    pub ascii: Option<u8>,
+    /// Modifiers after the event
+    pub state: KeyboardState,
 }
 
 /// US keyboard layout scancode to ASCII mapping
@@ -179,79 +285,160 @@ static SCANCODE_TO_ASCII_SHIFT: [u8; 128] = [
     0,   0,   0,   0,   0,   0,   0,   0,              // 0x78-0x7F
 ];
 
-/// Global keyboard state
-static KEYBOARD_STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState::new());
+/// Where the decoder is in a multi-byte sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    None,
+    /// Saw 0xE0
+    Extended,
+    /// In the Pause sequence, this many bytes from its end
+    Pause(u8),
+}
 
-/// Process a scancode and update keyboard state
-///
-/// Returns Some(ascii) if the scancode represents a printable character,
-/// or None for modifier keys and special keys.
-pub fn process_scancode(scancode: u8) -> Option<u8> {
-    let mut state = KEYBOARD_STATE.lock();
-    let is_release = (scancode & scancodes::RELEASE) != 0;
-    let key = scancode & !scancodes::RELEASE;
-
-    // Handle modifier keys
-    match key {
-        scancodes::LEFT_SHIFT => {
-            state.left_shift = !is_release;
-            return None;
-        }
-        scancodes::RIGHT_SHIFT => {
-            state.right_shift = !is_release;
-            return None;
-        }
-        scancodes::LEFT_CTRL => {
-            state.left_ctrl = !is_release;
-            return None;
-        }
-        scancodes::LEFT_ALT => {
-            state.left_alt = !is_release;
-            return None;
-        }
-        scancodes::CAPS_LOCK => {
-            if !is_release {
-                state.caps_lock = !state.caps_lock;
+/// Scancode set 1 decoder and modifier tracking
+pub struct Keyboard {
+    state: KeyboardState,
+    prefix: Prefix,
+}
+
+impl Keyboard {
+    pub const fn new() -> Self {
+        Self { state: KeyboardState::new(), prefix: Prefix::None }
+    }
+
+    /// Modifier and lock state
+    pub fn state(&self) -> KeyboardState {
+        self.state
+    }
+
+    /// Take one byte from the keyboard. Returns an event when it completes
+    /// a key; prefixes and the fake shifts around extended keys give None.
+    pub fn process(&mut self, scancode: u8) -> Option<KeyEvent> {
+        match (self.prefix, scancode) {
+            (Prefix::Pause(1), _) => {
+                self.prefix = Prefix::None;
+                return Some(KeyEvent { key: KeyCode::Pause, pressed: true, ascii: None, state: self.state });
             }
-            return None;
-        }
-        scancodes::NUM_LOCK => {
-            if !is_release {
-                state.num_lock = !state.num_lock;
+            (Prefix::Pause(left), _) => {
+                self.prefix = Prefix::Pause(left - 1);
+                return None;
             }
-            return None;
-        }
-        scancodes::SCROLL_LOCK => {
-            if !is_release {
-                state.scroll_lock = !state.scroll_lock;
+            (_, scancodes::EXTENDED) => {
+                self.prefix = Prefix::Extended;
+                return None;
+            }
+            (_, scancodes::PAUSE) => {
+                self.prefix = Prefix::Pause(5);
+                return None;
             }
-            return None;
+            _ => {}
         }
-        _ => {}
+
+        let extended = self.prefix == Prefix::Extended;
+        self.prefix = Prefix::None;
+        let pressed = (scancode & scancodes::RELEASE) == 0;
+        let code = scancode & !scancodes::RELEASE;
+        let key = if extended { decode_extended(code)? } else { decode(code) };
+
+        let state = &mut self.state;
+        match key {
+            KeyCode::LeftShift => state.left_shift = pressed,
+            KeyCode::RightShift => state.right_shift = pressed,
+            KeyCode::LeftCtrl => state.left_ctrl = pressed,
+            KeyCode::RightCtrl => state.right_ctrl = pressed,
+            KeyCode::LeftAlt => state.left_alt = pressed,
+            KeyCode::RightAlt => state.right_alt = pressed,
+            KeyCode::CapsLock if pressed => state.caps_lock = !state.caps_lock,
+            KeyCode::NumLock if pressed => state.num_lock = !state.num_lock,
+            KeyCode::ScrollLock if pressed => state.scroll_lock = !state.scroll_lock,
+            _ => {}
+        }
+
+        let ascii = match key {
+            KeyCode::Char(_) if pressed => to_ascii(&self.state, code, extended),
+            _ => None,
+        };
+        Some(KeyEvent { key, pressed, ascii, state: self.state })
     }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Key for a scancode without a prefix
+fn decode(code: u8) -> KeyCode {
+    match code {
+        scancodes::LEFT_SHIFT => KeyCode::LeftShift,
+        scancodes::RIGHT_SHIFT => KeyCode::RightShift,
+        scancodes::LEFT_CTRL => KeyCode::LeftCtrl,
+        scancodes::LEFT_ALT => KeyCode::LeftAlt,
+        scancodes::CAPS_LOCK => KeyCode::CapsLock,
+        scancodes::NUM_LOCK => KeyCode::NumLock,
+        scancodes::SCROLL_LOCK => KeyCode::ScrollLock,
+        scancodes::F1..=scancodes::F10 => KeyCode::F(code - scancodes::F1 + 1),
+        scancodes::F11 => KeyCode::F(11),
+        scancodes::F12 => KeyCode::F(12),
+        _ => match SCANCODE_TO_ASCII[code as usize] {
+            0 => KeyCode::Unknown { extended: false, code },
+            ascii => KeyCode::Char(ascii),
+        },
+    }
+}
 
-    // Only process key press events (not releases) for ASCII conversion
-    if is_release {
-        return None;
+/// Key for a scancode after 0xE0; None for the fake shifts
+fn decode_extended(code: u8) -> Option<KeyCode> {
+    use scancodes::extended::*;
+    Some(match code {
+        KEYPAD_ENTER => KeyCode::Char(b'\n'),
+        KEYPAD_SLASH => KeyCode::Char(b'/'),
+        RIGHT_CTRL => KeyCode::RightCtrl,
+        RIGHT_ALT => KeyCode::RightAlt,
+        FAKE_LEFT_SHIFT | FAKE_RIGHT_SHIFT => return None,
+        PRINT_SCREEN => KeyCode::PrintScreen,
+        HOME => KeyCode::Home,
+        UP => KeyCode::Up,
+        PAGE_UP => KeyCode::PageUp,
+        LEFT => KeyCode::Left,
+        RIGHT => KeyCode::Right,
+        END => KeyCode::End,
+        DOWN => KeyCode::Down,
+        PAGE_DOWN => KeyCode::PageDown,
+        INSERT => KeyCode::Insert,
+        DELETE => KeyCode::Delete,
+        LEFT_GUI => KeyCode::LeftGui,
+        RIGHT_GUI => KeyCode::RightGui,
+        MENU => KeyCode::Menu,
+        _ => KeyCode::Unknown { extended: true, code },
+    })
+}
+
+/// What a Char key types with the modifiers in `state`
+fn to_ascii(state: &KeyboardState, code: u8, extended: bool) -> Option<u8> {
+    // Keypad Enter and / type the same whatever Shift does
+    if extended {
+        return Some(SCANCODE_TO_ASCII[code as usize]);
     }
 
     // Convert scancode to ASCII
     let ascii = if state.shift() {
-        SCANCODE_TO_ASCII_SHIFT[key as usize]
+        SCANCODE_TO_ASCII_SHIFT[code as usize]
     } else {
-        SCANCODE_TO_ASCII[key as usize]
+        SCANCODE_TO_ASCII[code as usize]
     };
 
     // Apply caps lock for letters
     if ascii != 0 {
-        if state.caps_lock && ascii >= b'a' && ascii <= b'z' {
+        if state.caps_lock && ascii.is_ascii_lowercase() {
             // Caps lock inverts shift for letters
             if state.shift() {
                 Some(ascii) // Shift + caps = lowercase
             } else {
                 Some(ascii - 32) // Just caps = uppercase
             }
-        } else if state.caps_lock && ascii >= b'A' && ascii <= b'Z' {
+        } else if state.caps_lock && ascii.is_ascii_uppercase() {
             // Caps lock inverts shift for letters
             if state.shift() {
                 Some(ascii + 32) // Shift + caps = lowercase
@@ -266,19 +453,38 @@ pub fn process_scancode(scancode: u8) -> Option<u8> {
     }
 }
 
+/// Global keyboard state
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::new());
+
+/// Process a scancode and update keyboard state
+///
+/// Returns the key event once a key's bytes are complete, or None for
+/// prefix bytes.
+pub fn process_key(scancode: u8) -> Option<KeyEvent> {
+    KEYBOARD.lock().process(scancode)
+}
+
+/// Process a scancode and update keyboard state
+///
+/// Returns Some(ascii) if the scancode represents a printable character,
+/// or None for modifier keys and special keys.
+pub fn process_scancode(scancode: u8) -> Option<u8> {
+    process_key(scancode).and_then(|event| event.ascii)
+}
+
 /// Get the current keyboard state
 pub fn get_state() -> KeyboardState {
-    *KEYBOARD_STATE.lock()
+    KEYBOARD.lock().state()
 }
 
 /// Reset keyboard state (useful for testing or initialization)
 pub fn reset_state() {
-    *KEYBOARD_STATE.lock() = KeyboardState::new();
+    *KEYBOARD.lock() = Keyboard::new();
 }
 
 /// Check if a key is currently pressed (for raw scancode queries)
 pub fn is_key_pressed(scancode: u8) -> bool {
-    let state = KEYBOARD_STATE.lock();
+    let state = get_state();
     match scancode {
         scancodes::LEFT_SHIFT => state.left_shift,
         scancodes::RIGHT_SHIFT => state.right_shift,
@@ -287,3 +493,68 @@ pub fn is_key_pressed(scancode: u8) -> bool {
         _ => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(kb: &mut Keyboard, bytes: &[u8]) -> Option<KeyEvent> {
+        bytes.iter().fold(None, |_, &b| kb.process(b))
+    }
+
+    #[test]
+    fn test_extended_keys() {
+        let mut kb = Keyboard::new();
+        assert!(kb.process(0xE0).is_none());
+        let up = kb.process(0x48).unwrap();
+        assert_eq!((up.key, up.pressed, up.ascii), (KeyCode::Up, true, None));
+        let up = feed(&mut kb, &[0xE0, 0xC8]).unwrap();
+        assert_eq!((up.key, up.pressed), (KeyCode::Up, false));
+
+        // Without the prefix the same code is keypad 8
+        assert_eq!(kb.process(0x48).unwrap().ascii, Some(b'8'));
+        assert_eq!(feed(&mut kb, &[0xE0, 0x53]).unwrap().key, KeyCode::Delete);
+        assert_eq!(feed(&mut kb, &[0xE0, 0x1C]).unwrap().ascii, Some(b'\n'));
+        assert_eq!(feed(&mut kb, &[0xE0, 0x35]).unwrap().ascii, Some(b'/'));
+    }
+
+    #[test]
+    fn test_right_modifiers() {
+        let mut kb = Keyboard::new();
+        feed(&mut kb, &[0xE0, 0x1D, 0xE0, 0x38]);
+        assert!(kb.state().right_ctrl && kb.state().right_alt);
+        assert!(!kb.state().left_ctrl && !kb.state().left_alt);
+        feed(&mut kb, &[0xE0, 0x9D, 0xE0, 0xB8]);
+        assert!(!kb.state().ctrl() && !kb.state().alt());
+    }
+
+    #[test]
+    fn test_fake_shift_and_pause() {
+        let mut kb = Keyboard::new();
+        // Print Screen: E0 2A E0 37; the fake shift isn't a key
+        assert!(feed(&mut kb, &[0xE0, 0x2A]).is_none());
+        assert!(!kb.state().shift());
+        assert_eq!(feed(&mut kb, &[0xE0, 0x37]).unwrap().key, KeyCode::PrintScreen);
+
+        // Pause is one event for six bytes, and the 1D inside isn't Ctrl
+        let bytes = [0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5];
+        for &b in &bytes[..5] {
+            assert!(kb.process(b).is_none());
+        }
+        assert_eq!(kb.process(bytes[5]).unwrap().key, KeyCode::Pause);
+        assert!(!kb.state().ctrl() && !kb.state().num_lock);
+        assert_eq!(kb.process(0x1E).unwrap().ascii, Some(b'a'));
+    }
+
+    #[test]
+    fn test_ascii_with_modifiers() {
+        let mut kb = Keyboard::new();
+        kb.process(scancodes::LEFT_SHIFT);
+        assert_eq!(kb.process(scancodes::KEY_1).unwrap().ascii, Some(b'!'));
+        kb.process(scancodes::LEFT_SHIFT | scancodes::RELEASE);
+        kb.process(scancodes::CAPS_LOCK);
+        assert_eq!(kb.process(scancodes::A).unwrap().ascii, Some(b'A'));
+        assert_eq!(kb.process(scancodes::A | scancodes::RELEASE).unwrap().ascii, None);
+        assert_eq!(kb.process(scancodes::F12).unwrap().key, KeyCode::F(12));
+    }
+}
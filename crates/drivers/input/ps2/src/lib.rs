@@ -1,8 +1,371 @@
 //! PS/2 Keyboard and Mouse Driver
 //!
 //! Implements InputDevice trait for PS/2 keyboard and mouse.
+//!
+//! The keyboard is still read by the IRQ1 handler in `watos_arch::idt`.
+//! The mouse sits on the controller's auxiliary port: [`init_mouse`] turns
+//! on its IRQ12 and streaming mode, the IRQ12 handler buffers the raw bytes,
+//! and [`Ps2Mouse`] decodes them into [`InputEvent`]s when polled.
+//!
+//! [`suspend`] and [`resume`] park the controller for a system sleep; the
+//! kernel registers them through [`power_hook`].
 
 #![no_std]
 
-// TODO: Implement PS/2 driver
-// This is a stub - implementation will be added later
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use watos_arch::port::{inb, outb};
+use watos_driver_traits::input::{InputDevice, InputDeviceInfo, InputDeviceType, InputEvent};
+use watos_driver_traits::power::PowerHook;
+use watos_driver_traits::{DriverError, DriverResult};
+
+/// Controller ports
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+/// Status register bits
+const STATUS_OUTPUT_FULL: u8 = 0x01;
+const STATUS_INPUT_FULL: u8 = 0x02;
+
+/// Controller commands
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_AUX: u8 = 0xA7;
+const CMD_ENABLE_AUX: u8 = 0xA8;
+const CMD_DISABLE_KBD: u8 = 0xAD;
+const CMD_ENABLE_KBD: u8 = 0xAE;
+const CMD_WRITE_AUX: u8 = 0xD4;
+
+/// Configuration byte bits
+const CONFIG_AUX_IRQ: u8 = 0x02;
+const CONFIG_AUX_CLOCK_OFF: u8 = 0x20;
+
+/// Mouse commands and replies
+const MOUSE_SET_DEFAULTS: u8 = 0xF6;
+const MOUSE_ENABLE_STREAMING: u8 = 0xF4;
+const MOUSE_ACK: u8 = 0xFA;
+
+/// IRQ lines of the keyboard and auxiliary ports, and the slave PIC cascade
+const KEYBOARD_IRQ: u8 = 1;
+const MOUSE_IRQ: u8 = 12;
+const CASCADE_IRQ: u8 = 2;
+
+/// Polls before a controller wait gives up
+const TIMEOUT: u32 = 100_000;
+
+fn wait_write() -> bool {
+    (0..TIMEOUT).any(|_| unsafe { inb(STATUS_PORT) } & STATUS_INPUT_FULL == 0)
+}
+
+fn wait_read() -> bool {
+    (0..TIMEOUT).any(|_| unsafe { inb(STATUS_PORT) } & STATUS_OUTPUT_FULL != 0)
+}
+
+fn command(cmd: u8) -> bool {
+    wait_write() && {
+        unsafe { outb(COMMAND_PORT, cmd) };
+        true
+    }
+}
+
+fn write_data(value: u8) -> bool {
+    wait_write() && {
+        unsafe { outb(DATA_PORT, value) };
+        true
+    }
+}
+
+fn read_data() -> Option<u8> {
+    wait_read().then(|| unsafe { inb(DATA_PORT) })
+}
+
+/// Discard whatever the controller still holds
+fn drain() {
+    for _ in 0..16 {
+        if unsafe { inb(STATUS_PORT) } & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        unsafe { inb(DATA_PORT) };
+    }
+}
+
+/// Run `f` with interrupts off, restoring the previous state
+fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let rflags: u64;
+    unsafe {
+        core::arch::asm!("pushfq", "pop {}", "cli", out(reg) rflags, options(preserves_flags));
+    }
+    let result = f();
+    if rflags & 0x200 != 0 {
+        unsafe { core::arch::asm!("sti", options(nostack, preserves_flags)) };
+    }
+    result
+}
+
+/// Send a command to the mouse and wait for its ACK
+fn mouse_command(cmd: u8) -> bool {
+    command(CMD_WRITE_AUX) && write_data(cmd) && read_data() == Some(MOUSE_ACK)
+}
+
+/// Enable the auxiliary port, IRQ12 and mouse streaming. Returns false if
+/// no mouse answered.
+pub fn init_mouse() -> bool {
+    // The IRQ handlers would swallow the replies, so run with interrupts off
+    let ok = without_interrupts(|| {
+        command(CMD_ENABLE_AUX);
+        if !command(CMD_READ_CONFIG) {
+            return false;
+        }
+        let config = match read_data() {
+            Some(config) => config,
+            None => return false,
+        };
+        let config = (config | CONFIG_AUX_IRQ) & !CONFIG_AUX_CLOCK_OFF;
+        command(CMD_WRITE_CONFIG) && write_data(config)
+            && mouse_command(MOUSE_SET_DEFAULTS)
+            && mouse_command(MOUSE_ENABLE_STREAMING)
+    });
+
+    if ok {
+        MOUSE_ENABLED.store(true, Ordering::Relaxed);
+        watos_arch::pic::enable_irq(CASCADE_IRQ);
+        watos_arch::pic::enable_irq(MOUSE_IRQ);
+    }
+    ok
+}
+
+/// Set once `init_mouse` found a mouse, so `resume` turns it back on
+static MOUSE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stop both ports and mask their IRQs. Keys pressed while suspended are
+/// lost.
+pub fn suspend() -> DriverResult<()> {
+    watos_arch::pic::disable_irq(KEYBOARD_IRQ);
+    watos_arch::pic::disable_irq(MOUSE_IRQ);
+    without_interrupts(|| {
+        if !(command(CMD_DISABLE_KBD) && command(CMD_DISABLE_AUX)) {
+            return Err(DriverError::Timeout);
+        }
+        drain();
+        Ok(())
+    })
+}
+
+/// Re-enable the ports and IRQs that `suspend` turned off
+pub fn resume() -> DriverResult<()> {
+    let mouse = MOUSE_ENABLED.load(Ordering::Relaxed);
+    let ok = without_interrupts(|| {
+        drain();
+        command(CMD_ENABLE_KBD) && (!mouse || command(CMD_ENABLE_AUX))
+    });
+    watos_arch::pic::enable_irq(KEYBOARD_IRQ);
+    if mouse {
+        watos_arch::pic::enable_irq(MOUSE_IRQ);
+    }
+    if ok { Ok(()) } else { Err(DriverError::Timeout) }
+}
+
+/// Power hook for the controller
+pub fn power_hook() -> PowerHook {
+    PowerHook { name: "ps2", ctx: 0, suspend: |_| suspend(), resume: |_| resume() }
+}
+
+/// A decoded movement packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    /// Horizontal motion, right is positive
+    pub dx: i16,
+    /// Vertical motion in screen direction, down is positive
+    pub dy: i16,
+    /// Button state: bit 0 left, bit 1 right, bit 2 middle
+    pub buttons: u8,
+}
+
+/// Reassembles standard 3-byte packets from the byte stream
+pub struct PacketDecoder {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl PacketDecoder {
+    pub const fn new() -> Self {
+        PacketDecoder { bytes: [0; 3], len: 0 }
+    }
+
+    /// Add a byte; returns a packet once three have arrived
+    pub fn feed(&mut self, byte: u8) -> Option<Packet> {
+        // The first byte always has bit 3 set; skip bytes until in sync
+        if self.len == 0 && byte & 0x08 == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < 3 {
+            return None;
+        }
+        self.len = 0;
+
+        let [flags, x, y] = self.bytes;
+        // X/Y overflow: the motion is meaningless, keep only the buttons
+        let overflow = flags & 0xC0 != 0;
+        let dx = if overflow { 0 } else { x as i16 - (((flags as i16) << 4) & 0x100) };
+        let dy = if overflow { 0 } else { y as i16 - (((flags as i16) << 3) & 0x100) };
+        Some(Packet { dx, dy: -dy, buttons: flags & 0x07 })
+    }
+}
+
+impl Default for PacketDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Events decoded but not yet polled
+const QUEUE_LEN: usize = 8;
+
+struct MouseState {
+    decoder: PacketDecoder,
+    buttons: u8,
+    queue: [Option<InputEvent>; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl MouseState {
+    fn push(&mut self, event: InputEvent) {
+        if self.len < QUEUE_LEN {
+            self.queue[(self.head + self.len) % QUEUE_LEN] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<InputEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.queue[self.head].take();
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        event
+    }
+
+    /// Turn a packet into a move and one event per button change
+    fn queue_packet(&mut self, packet: Packet) {
+        if packet.dx != 0 || packet.dy != 0 {
+            self.push(InputEvent::MouseMove(packet.dx, packet.dy));
+        }
+        let changed = packet.buttons ^ self.buttons;
+        for button in 0..3 {
+            if changed & (1 << button) != 0 {
+                self.push(if packet.buttons & (1 << button) != 0 {
+                    InputEvent::MouseDown(button)
+                } else {
+                    InputEvent::MouseUp(button)
+                });
+            }
+        }
+        self.buttons = packet.buttons;
+    }
+}
+
+/// PS/2 mouse: MouseMove (screen direction), MouseDown and MouseUp events
+pub struct Ps2Mouse {
+    state: Mutex<MouseState>,
+}
+
+impl Ps2Mouse {
+    pub const fn new() -> Self {
+        Ps2Mouse {
+            state: Mutex::new(MouseState {
+                decoder: PacketDecoder::new(),
+                buttons: 0,
+                queue: [None; QUEUE_LEN],
+                head: 0,
+                len: 0,
+            }),
+        }
+    }
+
+    /// Current button state (bit 0 left, bit 1 right, bit 2 middle)
+    pub fn buttons(&self) -> u8 {
+        self.state.lock().buttons
+    }
+}
+
+impl Default for Ps2Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for Ps2Mouse {
+    fn poll_event(&self) -> DriverResult<Option<InputEvent>> {
+        let mut state = self.state.lock();
+        while state.len == 0 {
+            let Some(byte) = watos_arch::idt::get_mouse_byte() else { break };
+            if let Some(packet) = state.decoder.feed(byte) {
+                state.queue_packet(packet);
+            }
+        }
+        Ok(state.pop())
+    }
+
+    fn has_events(&self) -> bool {
+        let state = self.state.lock();
+        state.len != 0 || unsafe { watos_arch::idt::MOUSE_READ_POS != watos_arch::idt::MOUSE_WRITE_POS }
+    }
+
+    fn info(&self) -> InputDeviceInfo {
+        InputDeviceInfo {
+            name: "PS/2 Mouse",
+            device_type: InputDeviceType::Mouse,
+        }
+    }
+}
+
+/// The auxiliary-port mouse
+pub static MOUSE: Ps2Mouse = Ps2Mouse::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_decoding() {
+        let mut decoder = PacketDecoder::new();
+        // Left button, moved right 5 and up 3
+        assert_eq!(decoder.feed(0x09), None);
+        assert_eq!(decoder.feed(5), None);
+        assert_eq!(decoder.feed(3), Some(Packet { dx: 5, dy: -3, buttons: 1 }));
+
+        // Negative X (sign bit 4) and Y (sign bit 5): left 2, down 4
+        decoder.feed(0x38);
+        decoder.feed(0xFE);
+        assert_eq!(decoder.feed(0xFC), Some(Packet { dx: -2, dy: 4, buttons: 0 }));
+
+        // Overflow drops the motion but keeps the buttons
+        decoder.feed(0x4A);
+        decoder.feed(0x10);
+        assert_eq!(decoder.feed(0x10), Some(Packet { dx: 0, dy: 0, buttons: 2 }));
+    }
+
+    #[test]
+    fn test_resync_and_button_events() {
+        let mut decoder = PacketDecoder::new();
+        // A stray byte without bit 3 is skipped
+        assert_eq!(decoder.feed(0x00), None);
+        decoder.feed(0x08);
+        decoder.feed(1);
+        let packet = decoder.feed(0).unwrap();
+
+        let mut state = MouseState { decoder, buttons: 0, queue: [None; QUEUE_LEN], head: 0, len: 0 };
+        state.queue_packet(packet);
+        state.queue_packet(Packet { dx: 0, dy: 0, buttons: 1 });
+        state.queue_packet(Packet { dx: 0, dy: 0, buttons: 0 });
+        assert_eq!(state.pop(), Some(InputEvent::MouseMove(1, 0)));
+        assert_eq!(state.pop(), Some(InputEvent::MouseDown(0)));
+        assert_eq!(state.pop(), Some(InputEvent::MouseUp(0)));
+        assert_eq!(state.pop(), None);
+    }
+}
@@ -1,20 +1,29 @@
 //! WATOS Intel e1000 Network Driver
 //!
-//! Implements the NetworkDevice trait for Intel 82545EM and compatible NICs.
-//! Works on QEMU, VMware, VirtualBox, Hyper-V.
+//! Implements the NicDevice trait for Intel 82540EM/82545EM and compatible
+//! NICs. Works on QEMU, VMware, VirtualBox, Hyper-V.
+//!
+//! The descriptor rings and packet buffers come from the kernel heap, which
+//! is identity-mapped, so heap addresses are the physical addresses the NIC
+//! is given. Frames are polled; the NIC's interrupts stay masked.
 
 #![no_std]
 
-use core::ptr::{read_volatile, write_volatile};
-use watos_driver_traits::{Driver, DriverInfo, DriverState, DriverError};
-use watos_driver_traits::net::{NetworkDevice, MacAddress, LinkStatus, LinkSpeed, NetCapabilities};
-use watos_driver_pci::{PciDriver, PciBar};
+extern crate alloc;
+
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use spin::Mutex;
+use watos_driver_traits::bus::{PciBar, PciBus};
+use watos_driver_traits::nic::{MacAddress, NicDevice, NicDeviceInfo};
+use watos_driver_traits::{Driver, DriverError, DriverInfo, DriverResult, DriverState};
+use watos_driver_pci::PciDriver;
 
 // e1000 Register offsets
 const REG_CTRL: u32 = 0x0000;
 const REG_STATUS: u32 = 0x0008;
 const REG_ICR: u32 = 0x00C0;
-const REG_IMS: u32 = 0x00D0;
 const REG_IMC: u32 = 0x00D8;
 const REG_RCTL: u32 = 0x0100;
 const REG_TCTL: u32 = 0x0400;
@@ -31,12 +40,17 @@ const REG_TDT: u32 = 0x3818;
 const REG_RAL0: u32 = 0x5400;
 const REG_RAH0: u32 = 0x5404;
 
+// Status bits
+const STATUS_LU: u32 = 1 << 1;
+
 // Control bits
 const CTRL_SLU: u32 = 1 << 6;
 const CTRL_RST: u32 = 1 << 26;
 
 // Receive control bits
 const RCTL_EN: u32 = 1 << 1;
+const RCTL_UPE: u32 = 1 << 3;
+const RCTL_MPE: u32 = 1 << 4;
 const RCTL_BAM: u32 = 1 << 15;
 const RCTL_SECRC: u32 = 1 << 26;
 
@@ -56,6 +70,17 @@ const NUM_RX_DESC: usize = 32;
 const NUM_TX_DESC: usize = 32;
 const BUFFER_SIZE: usize = 2048;
 
+/// Spins to wait for a free transmit descriptor before giving up
+const TX_WAIT_LIMIT: u32 = 1_000_000;
+
+// Layout of the ring memory: RX descriptors, TX descriptors, RX buffers,
+// TX buffers
+const RX_DESC_OFFSET: usize = 0;
+const TX_DESC_OFFSET: usize = 0x1000;
+const RX_BUFFER_OFFSET: usize = 0x2000;
+const TX_BUFFER_OFFSET: usize = RX_BUFFER_OFFSET + NUM_RX_DESC * BUFFER_SIZE;
+const RING_MEMORY_SIZE: usize = TX_BUFFER_OFFSET + NUM_TX_DESC * BUFFER_SIZE;
+
 /// RX Descriptor
 #[repr(C, align(16))]
 #[derive(Clone, Copy, Default)]
@@ -81,22 +106,66 @@ struct TxDesc {
     special: u16,
 }
 
+/// Descriptor rings and packet buffers, and where the driver is in them
+struct Rings {
+    memory: *mut u8,
+    rx_cur: usize,
+    tx_cur: usize,
+}
+
+// The ring memory belongs to the driver alone
+unsafe impl Send for Rings {}
+
+impl Rings {
+    fn new() -> Result<Self, DriverError> {
+        let memory = unsafe { alloc_zeroed(Self::layout()) };
+        if memory.is_null() {
+            return Err(DriverError::BufferTooSmall);
+        }
+        Ok(Rings { memory, rx_cur: 0, tx_cur: 0 })
+    }
+
+    fn layout() -> Layout {
+        // Page aligned, which the descriptor rings need (16 bytes at least)
+        Layout::from_size_align(RING_MEMORY_SIZE, 4096).unwrap()
+    }
+
+    fn address(&self, offset: usize) -> u64 {
+        self.memory as u64 + offset as u64
+    }
+
+    fn rx_desc(&self, i: usize) -> *mut RxDesc {
+        unsafe { (self.memory.add(RX_DESC_OFFSET) as *mut RxDesc).add(i) }
+    }
+
+    fn tx_desc(&self, i: usize) -> *mut TxDesc {
+        unsafe { (self.memory.add(TX_DESC_OFFSET) as *mut TxDesc).add(i) }
+    }
+
+    fn rx_buffer(&self, i: usize) -> *mut u8 {
+        unsafe { self.memory.add(RX_BUFFER_OFFSET + i * BUFFER_SIZE) }
+    }
+
+    fn tx_buffer(&self, i: usize) -> *mut u8 {
+        unsafe { self.memory.add(TX_BUFFER_OFFSET + i * BUFFER_SIZE) }
+    }
+}
+
+impl Drop for Rings {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.memory, Self::layout()) };
+    }
+}
+
 /// Intel e1000 Network Driver
 pub struct E1000Driver {
     state: DriverState,
     mmio_base: u64,
     mac_addr: [u8; 6],
-    rx_cur: usize,
-    tx_cur: usize,
+    /// Set up by `init`
+    rings: Mutex<Option<Rings>>,
 }
 
-// Fixed memory for descriptor rings
-const DESC_RING_BASE: u64 = 0x500000; // 5MB
-const RX_DESC_BASE: u64 = DESC_RING_BASE;
-const TX_DESC_BASE: u64 = DESC_RING_BASE + 0x1000;
-const RX_BUFFER_BASE: u64 = DESC_RING_BASE + 0x2000;
-const TX_BUFFER_BASE: u64 = RX_BUFFER_BASE + (NUM_RX_DESC * BUFFER_SIZE) as u64;
-
 impl E1000Driver {
     /// Intel vendor ID
     const VENDOR_INTEL: u16 = 0x8086;
@@ -130,8 +199,7 @@ impl E1000Driver {
                     state: DriverState::Loaded,
                     mmio_base,
                     mac_addr: [0; 6],
-                    rx_cur: 0,
-                    tx_cur: 0,
+                    rings: Mutex::new(None),
                 });
             }
         }
@@ -191,24 +259,19 @@ impl E1000Driver {
         self.write_reg(REG_RAH0, rah);
     }
 
-    fn init_rx(&mut self) {
-        // Clear descriptor memory
+    /// Point the NIC at empty receive buffers and enable the receiver
+    fn init_rx(&self, rings: &mut Rings) {
         unsafe {
-            core::ptr::write_bytes(RX_DESC_BASE as *mut u8, 0, NUM_RX_DESC * 16);
-            core::ptr::write_bytes(RX_BUFFER_BASE as *mut u8, 0, NUM_RX_DESC * BUFFER_SIZE);
-        }
-
-        // Setup descriptors
-        let descs = RX_DESC_BASE as *mut RxDesc;
-        for i in 0..NUM_RX_DESC {
-            unsafe {
-                (*descs.add(i)).addr = RX_BUFFER_BASE + (i * BUFFER_SIZE) as u64;
+            core::ptr::write_bytes(rings.rx_desc(0) as *mut u8, 0, NUM_RX_DESC * 16);
+            for i in 0..NUM_RX_DESC {
+                (*rings.rx_desc(i)).addr = rings.address(RX_BUFFER_OFFSET + i * BUFFER_SIZE);
             }
         }
 
         // Program descriptor ring
-        self.write_reg(REG_RDBAL, RX_DESC_BASE as u32);
-        self.write_reg(REG_RDBAH, (RX_DESC_BASE >> 32) as u32);
+        let base = rings.address(RX_DESC_OFFSET);
+        self.write_reg(REG_RDBAL, base as u32);
+        self.write_reg(REG_RDBAH, (base >> 32) as u32);
         self.write_reg(REG_RDLEN, (NUM_RX_DESC * 16) as u32);
         self.write_reg(REG_RDH, 0);
         self.write_reg(REG_RDT, (NUM_RX_DESC - 1) as u32);
@@ -216,28 +279,23 @@ impl E1000Driver {
         // Enable receiver
         self.write_reg(REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
 
-        self.rx_cur = 0;
+        rings.rx_cur = 0;
     }
 
-    fn init_tx(&mut self) {
-        // Clear descriptor memory
+    /// Mark every transmit descriptor free and enable the transmitter
+    fn init_tx(&self, rings: &mut Rings) {
         unsafe {
-            core::ptr::write_bytes(TX_DESC_BASE as *mut u8, 0, NUM_TX_DESC * 16);
-            core::ptr::write_bytes(TX_BUFFER_BASE as *mut u8, 0, NUM_TX_DESC * BUFFER_SIZE);
-        }
-
-        // Setup descriptors
-        let descs = TX_DESC_BASE as *mut TxDesc;
-        for i in 0..NUM_TX_DESC {
-            unsafe {
-                (*descs.add(i)).addr = TX_BUFFER_BASE + (i * BUFFER_SIZE) as u64;
-                (*descs.add(i)).status = DESC_DD; // Mark as done
+            core::ptr::write_bytes(rings.tx_desc(0) as *mut u8, 0, NUM_TX_DESC * 16);
+            for i in 0..NUM_TX_DESC {
+                (*rings.tx_desc(i)).addr = rings.address(TX_BUFFER_OFFSET + i * BUFFER_SIZE);
+                (*rings.tx_desc(i)).status = DESC_DD; // Mark as done
             }
         }
 
         // Program descriptor ring
-        self.write_reg(REG_TDBAL, TX_DESC_BASE as u32);
-        self.write_reg(REG_TDBAH, (TX_DESC_BASE >> 32) as u32);
+        let base = rings.address(TX_DESC_OFFSET);
+        self.write_reg(REG_TDBAL, base as u32);
+        self.write_reg(REG_TDBAH, (base >> 32) as u32);
         self.write_reg(REG_TDLEN, (NUM_TX_DESC * 16) as u32);
         self.write_reg(REG_TDH, 0);
         self.write_reg(REG_TDT, 0);
@@ -245,10 +303,22 @@ impl E1000Driver {
         // Enable transmitter
         self.write_reg(REG_TCTL, TCTL_EN | TCTL_PSP | (0x10 << 4) | (0x40 << 12));
 
-        self.tx_cur = 0;
+        rings.tx_cur = 0;
+    }
+
+    /// Set up both rings, allocating them the first time
+    fn init_rings(&self) -> Result<(), DriverError> {
+        let mut rings = self.rings.lock();
+        if rings.is_none() {
+            *rings = Some(Rings::new()?);
+        }
+        let rings = rings.as_mut().unwrap();
+        self.init_rx(rings);
+        self.init_tx(rings);
+        Ok(())
     }
 
-    fn link_up(&self) {
+    fn set_link_up(&self) {
         let ctrl = self.read_reg(REG_CTRL);
         self.write_reg(REG_CTRL, ctrl | CTRL_SLU);
     }
@@ -275,9 +345,8 @@ impl Driver for E1000Driver {
 
         self.reset();
         self.read_mac();
-        self.init_rx();
-        self.init_tx();
-        self.link_up();
+        self.init_rings()?;
+        self.set_link_up();
 
         self.state = DriverState::Ready;
         Ok(())
@@ -303,135 +372,159 @@ impl Driver for E1000Driver {
         self.state = DriverState::Ready;
         Ok(())
     }
-}
-
-impl NetworkDevice for E1000Driver {
-    fn mac_address(&self) -> MacAddress {
-        MacAddress(self.mac_addr)
-    }
 
-    fn link_status(&self) -> LinkStatus {
-        let status = self.read_reg(REG_STATUS);
-        if status & (1 << 1) != 0 { // Link up bit
-            LinkStatus::Up
-        } else {
-            LinkStatus::Down
+    fn suspend(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
         }
-    }
 
-    fn link_speed(&self) -> LinkSpeed {
-        let status = self.read_reg(REG_STATUS);
-        match (status >> 6) & 0x3 {
-            0b00 => LinkSpeed::Mbps10,
-            0b01 => LinkSpeed::Mbps100,
-            0b10 | 0b11 => LinkSpeed::Gbps1,
-            _ => LinkSpeed::Unknown,
+        // Let queued frames go out before the transmitter stops
+        for _ in 0..100000 {
+            if self.read_reg(REG_TDH) == self.read_reg(REG_TDT) {
+                break;
+            }
         }
+
+        self.write_reg(REG_RCTL, 0);
+        self.write_reg(REG_TCTL, 0);
+        self.write_reg(REG_IMC, 0xFFFFFFFF);
+        let _ = self.read_reg(REG_ICR);
+
+        self.state = DriverState::Suspended;
+        Ok(())
     }
 
-    fn capabilities(&self) -> NetCapabilities {
-        NetCapabilities {
-            mtu: 1500,
-            checksum_offload: true,
-            scatter_gather: false,
-            promiscuous: true,
+    fn resume(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Suspended {
+            return Err(DriverError::InvalidState);
         }
-    }
 
-    fn has_packet(&self) -> bool {
-        let descs = RX_DESC_BASE as *const RxDesc;
-        let desc = unsafe { &*descs.add(self.rx_cur) };
-        desc.status & DESC_DD != 0
+        // Frames received while asleep are gone; start with empty rings
+        self.program_mac();
+        self.init_rings()?;
+        self.set_link_up();
+
+        self.state = DriverState::Active;
+        Ok(())
     }
+}
 
-    fn set_promiscuous(&mut self, enabled: bool) -> Result<(), DriverError> {
-        let rctl = self.read_reg(REG_RCTL);
-        if enabled {
-            self.write_reg(REG_RCTL, rctl | (1 << 3) | (1 << 4)); // UPE + MPE
-        } else {
-            self.write_reg(REG_RCTL, rctl & !((1 << 3) | (1 << 4)));
+impl Drop for E1000Driver {
+    fn drop(&mut self) {
+        // Stop DMA before the ring memory goes back to the heap
+        if self.rings.lock().is_some() {
+            self.write_reg(REG_RCTL, 0);
+            self.write_reg(REG_TCTL, 0);
         }
-        Ok(())
+    }
+}
+
+impl NicDevice for E1000Driver {
+    fn mac_address(&self) -> MacAddress {
+        self.mac_addr
     }
 
-    fn send(&mut self, packet: &[u8]) -> Result<(), DriverError> {
+    fn send_frame(&self, frame: &[u8]) -> DriverResult<()> {
         if self.state != DriverState::Active {
             return Err(DriverError::InvalidState);
         }
-
-        if packet.len() > BUFFER_SIZE {
-            return Err(DriverError::ResourceError);
+        if frame.len() > BUFFER_SIZE {
+            return Err(DriverError::InvalidParameter);
         }
 
-        let descs = TX_DESC_BASE as *mut TxDesc;
-        let idx = self.tx_cur;
-
-        // Wait for descriptor to be available
-        for _ in 0..1000000 {
-            if unsafe { (*descs.add(idx)).status & DESC_DD != 0 } {
-                break;
+        let mut rings = self.rings.lock();
+        let rings = rings.as_mut().ok_or(DriverError::InvalidState)?;
+        let idx = rings.tx_cur;
+        let desc = rings.tx_desc(idx);
+
+        // Wait for the NIC to be done with the descriptor
+        let mut spins = 0;
+        while unsafe { read_volatile(addr_of!((*desc).status)) } & DESC_DD == 0 {
+            spins += 1;
+            if spins >= TX_WAIT_LIMIT {
+                return Err(DriverError::Timeout);
             }
+            core::hint::spin_loop();
         }
 
-        // Copy packet to buffer
-        let buf_addr = TX_BUFFER_BASE + (idx * BUFFER_SIZE) as u64;
         unsafe {
-            core::ptr::copy_nonoverlapping(packet.as_ptr(), buf_addr as *mut u8, packet.len());
-
-            // Setup descriptor
-            let desc = &mut *descs.add(idx);
-            desc.length = packet.len() as u16;
-            desc.cmd = TDESC_CMD_EOP | TDESC_CMD_IFCS | TDESC_CMD_RS;
-            desc.status = 0;
+            core::ptr::copy_nonoverlapping(frame.as_ptr(), rings.tx_buffer(idx), frame.len());
+            write_volatile(addr_of_mut!((*desc).length), frame.len() as u16);
+            write_volatile(addr_of_mut!((*desc).cmd), TDESC_CMD_EOP | TDESC_CMD_IFCS | TDESC_CMD_RS);
+            write_volatile(addr_of_mut!((*desc).status), 0);
         }
+        fence(Ordering::SeqCst);
 
         // Advance tail
-        self.tx_cur = (self.tx_cur + 1) % NUM_TX_DESC;
-        self.write_reg(REG_TDT, self.tx_cur as u32);
-
+        rings.tx_cur = (idx + 1) % NUM_TX_DESC;
+        self.write_reg(REG_TDT, rings.tx_cur as u32);
         Ok(())
     }
 
-    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, DriverError> {
+    fn receive_frame(&self, buf: &mut [u8]) -> DriverResult<Option<usize>> {
         if self.state != DriverState::Active {
             return Err(DriverError::InvalidState);
         }
 
-        let descs = RX_DESC_BASE as *mut RxDesc;
-        let idx = self.rx_cur;
-
-        // Check if packet available
-        let desc = unsafe { &*descs.add(idx) };
-        if desc.status & DESC_DD == 0 {
-            return Ok(0); // No packet
-        }
+        let mut rings = self.rings.lock();
+        let rings = rings.as_mut().ok_or(DriverError::InvalidState)?;
+        let idx = rings.rx_cur;
+        let desc = rings.rx_desc(idx);
 
-        if desc.status & DESC_EOP == 0 {
-            // Partial packet, skip
-            self.rx_cur = (self.rx_cur + 1) % NUM_RX_DESC;
-            self.write_reg(REG_RDT, idx as u32);
-            return Ok(0);
+        let status = unsafe { read_volatile(addr_of!((*desc).status)) };
+        if status & DESC_DD == 0 {
+            return Ok(None);
         }
+        fence(Ordering::SeqCst);
+
+        // Frames never span buffers (they are no bigger than BUFFER_SIZE),
+        // but one that somehow did, or that won't fit, is dropped
+        let len = unsafe { read_volatile(addr_of!((*desc).length)) } as usize;
+        let result = if status & DESC_EOP == 0 {
+            Ok(None)
+        } else if len > buf.len() {
+            Err(DriverError::BufferTooSmall)
+        } else {
+            unsafe { core::ptr::copy_nonoverlapping(rings.rx_buffer(idx), buf.as_mut_ptr(), len) };
+            Ok(Some(len))
+        };
 
-        let len = desc.length as usize;
-        if len > buffer.len() {
-            return Err(DriverError::ResourceError);
-        }
+        // Hand the descriptor back to the NIC
+        unsafe { write_volatile(addr_of_mut!((*desc).status), 0) };
+        rings.rx_cur = (idx + 1) % NUM_RX_DESC;
+        self.write_reg(REG_RDT, idx as u32);
+        result
+    }
 
-        // Copy packet from buffer
-        let buf_addr = RX_BUFFER_BASE + (idx * BUFFER_SIZE) as u64;
-        unsafe {
-            core::ptr::copy_nonoverlapping(buf_addr as *const u8, buffer.as_mut_ptr(), len);
+    fn link_up(&self) -> bool {
+        self.read_reg(REG_STATUS) & STATUS_LU != 0
+    }
 
-            // Reset descriptor
-            let desc = &mut *descs.add(idx);
-            desc.status = 0;
+    fn link_speed(&self) -> u32 {
+        match (self.read_reg(REG_STATUS) >> 6) & 0x3 {
+            0b00 => 10,
+            0b01 => 100,
+            _ => 1000,
         }
+    }
 
-        // Advance tail
-        self.rx_cur = (self.rx_cur + 1) % NUM_RX_DESC;
-        self.write_reg(REG_RDT, idx as u32);
+    fn info(&self) -> NicDeviceInfo {
+        NicDeviceInfo {
+            name: "e1000",
+            mac: self.mac_addr,
+            mtu: 1500,
+            link_up: self.link_up(),
+            speed_mbps: self.link_speed(),
+        }
+    }
 
-        Ok(len)
+    fn set_promiscuous(&self, enabled: bool) -> DriverResult<()> {
+        let rctl = self.read_reg(REG_RCTL);
+        if enabled {
+            self.write_reg(REG_RCTL, rctl | RCTL_UPE | RCTL_MPE);
+        } else {
+            self.write_reg(REG_RCTL, rctl & !(RCTL_UPE | RCTL_MPE));
+        }
+        Ok(())
     }
 }
@@ -15,6 +15,13 @@
 //! let mut buffer = [0u8; 512];
 //! driver.read_sectors(0, &mut buffer).expect("Read failed");
 //! ```
+//!
+//! # Power management
+//!
+//! `suspend` flushes the drive's write cache and stops the port's command
+//! and FIS engines; `resume` reprograms the port from scratch. Once the
+//! driver has been handed to a filesystem, [`AhciDriver::power_hook`] lets
+//! the kernel's power coordinator do the same without owning it.
 
 #![no_std]
 
@@ -22,6 +29,7 @@ use core::ptr::{read_volatile, write_volatile};
 use watos_driver_traits::{Driver, DriverInfo, DriverState, DriverError};
 use watos_driver_traits::block::{BlockDevice, BlockGeometry};
 use watos_driver_traits::bus::PciAddress;
+use watos_driver_traits::power::PowerHook;
 use watos_driver_pci::PciDriver;
 
 // AHCI HBA Memory Registers
@@ -57,6 +65,7 @@ const FIS_TYPE_REG_H2D: u8 = 0x27;
 const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
 const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
 const ATA_CMD_IDENTIFY: u8 = 0xEC;
+const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
 
 /// AHCI command header
 #[repr(C, packed)]
@@ -217,6 +226,36 @@ impl AhciDriver {
         Self::probe_with_pci(&pci, target_port)
     }
 
+    /// A handle to an already-probed port, for the power hooks. The port's
+    /// memory is at a fixed place so nothing but the address is needed.
+    fn from_power_ctx(ctx: u64, state: DriverState) -> Self {
+        let port = (ctx & 0x1F) as u8;
+        Self {
+            state,
+            mmio_base: ctx & !0x1F,
+            port,
+            cmd_list: Self::port_cmd_list(port),
+            cmd_table: Self::port_cmd_table(port),
+            fis_base: Self::port_fis_base(port),
+            lba_offset: 0,
+            sector_size: 512,
+            total_sectors: 0,
+        }
+    }
+
+    /// Power hook for this port, to register before the driver is handed
+    /// to a filesystem
+    pub fn power_hook(&self) -> PowerHook {
+        fn suspend(ctx: u64) -> Result<(), DriverError> {
+            AhciDriver::from_power_ctx(ctx, DriverState::Active).suspend()
+        }
+        fn resume(ctx: u64) -> Result<(), DriverError> {
+            AhciDriver::from_power_ctx(ctx, DriverState::Suspended).resume()
+        }
+        // ABAR is at least 8K aligned, leaving the low bits for the port
+        PowerHook { name: "ahci", ctx: self.mmio_base | self.port as u64, suspend, resume }
+    }
+
     fn port_base(&self) -> u64 {
         self.mmio_base + 0x100 + (self.port as u64 * 0x80)
     }
@@ -229,17 +268,16 @@ impl AhciDriver {
         unsafe { write_volatile((self.port_base() + offset) as *mut u32, value) }
     }
 
-    fn init_port(&mut self) {
-        // Stop command engine
+    /// Stop the command and FIS engines; false if they did not stop
+    fn stop_engines(&mut self) -> bool {
         let cmd = self.read_port(PORT_CMD);
         self.write_port(PORT_CMD, cmd & !(PORT_CMD_ST | PORT_CMD_FRE));
 
-        // Wait for engine to stop
-        for _ in 0..1000000 {
-            if (self.read_port(PORT_CMD) & (PORT_CMD_CR | PORT_CMD_FR)) == 0 {
-                break;
-            }
-        }
+        (0..1000000).any(|_| self.read_port(PORT_CMD) & (PORT_CMD_CR | PORT_CMD_FR) == 0)
+    }
+
+    fn init_port(&mut self) {
+        self.stop_engines();
 
         // Clear memory
         unsafe {
@@ -283,7 +321,8 @@ impl AhciDriver {
             let flags: u16 = (core::mem::size_of::<FisRegH2D>() / 4) as u16;
             let flags = if write { flags | (1 << 6) } else { flags };
             (*cmd_header).flags = flags;
-            (*cmd_header).prdtl = 1;
+            // Non-data commands (FLUSH) have no PRDT entry
+            (*cmd_header).prdtl = if count == 0 { 0 } else { 1 };
             (*cmd_header).prdbc = 0;
         }
 
@@ -307,8 +346,10 @@ impl AhciDriver {
             (*fis).count_low = count as u8;
             (*fis).count_high = (count >> 8) as u8;
 
-            (*cmd_table).prdt[0].dba = buffer_addr;
-            (*cmd_table).prdt[0].dbc = ((count as u32 * 512) - 1) | (1 << 31);
+            if count != 0 {
+                (*cmd_table).prdt[0].dba = buffer_addr;
+                (*cmd_table).prdt[0].dbc = ((count as u32 * 512) - 1) | (1 << 31);
+            }
         }
 
         self.write_port(PORT_IS, 0xFFFFFFFF);
@@ -421,6 +462,32 @@ impl Driver for AhciDriver {
         self.state = DriverState::Ready;
         Ok(())
     }
+
+    fn suspend(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
+        }
+
+        self.flush()?;
+        if !self.stop_engines() {
+            return Err(DriverError::Timeout);
+        }
+        self.write_port(PORT_IS, 0xFFFFFFFF);
+
+        self.state = DriverState::Suspended;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Suspended {
+            return Err(DriverError::InvalidState);
+        }
+
+        // The drive may have lost power; set the port up from scratch
+        self.init_port();
+        self.state = DriverState::Active;
+        Ok(())
+    }
 }
 
 impl BlockDevice for AhciDriver {
@@ -465,8 +532,11 @@ impl BlockDevice for AhciDriver {
     }
 
     fn flush(&mut self) -> Result<(), DriverError> {
-        // AHCI doesn't require explicit flush for our simple implementation
-        // A full implementation would issue ATA FLUSH CACHE command
-        Ok(())
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
+        }
+
+        // Write the drive's volatile cache out to the media
+        self.issue_command(ATA_CMD_FLUSH_CACHE_EXT, 0, 0, 0, false)
     }
 }
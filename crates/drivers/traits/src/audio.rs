@@ -2,6 +2,13 @@
 //!
 //! Implemented by audio drivers (AC'97, HDA, etc.)
 //! Used by the audio subsystem
+//!
+//! The kernel routes the `SYS_AUDIO_*` syscalls to a single output device
+//! registered with [`register_output`]. A software mixer sharing the card
+//! between streams registers itself here in place of the card.
+
+use alloc::boxed::Box;
+use spin::Mutex;
 
 use crate::DriverResult;
 
@@ -94,6 +101,16 @@ pub struct AudioDeviceInfo {
     pub playing: bool,
 }
 
+/// A device that can only sound a tone, such as the PC speaker. Used for
+/// SYS_BEEP and the console bell, so it works without a sound card.
+pub trait Beep {
+    /// Start a square wave at `freq_hz`, replacing any tone already playing
+    fn tone(&mut self, freq_hz: u32) -> DriverResult<()>;
+
+    /// Stop the tone
+    fn silence(&mut self);
+}
+
 /// Calculate bytes per sample for a format
 pub fn bytes_per_sample(format: SampleFormat) -> usize {
     match format {
@@ -107,3 +124,20 @@ pub fn bytes_per_sample(format: SampleFormat) -> usize {
 pub fn bytes_per_frame(config: &AudioConfig) -> usize {
     bytes_per_sample(config.format) * config.channels as usize
 }
+
+static OUTPUT: Mutex<Option<Box<dyn AudioDevice>>> = Mutex::new(None);
+
+/// Make `device` the system audio output, replacing any earlier one
+pub fn register_output(device: Box<dyn AudioDevice>) {
+    *OUTPUT.lock() = Some(device);
+}
+
+/// Is there an audio output?
+pub fn has_output() -> bool {
+    OUTPUT.lock().is_some()
+}
+
+/// Run `f` on the audio output; None if there isn't one
+pub fn with_output<R>(f: impl FnOnce(&mut dyn AudioDevice) -> R) -> Option<R> {
+    OUTPUT.lock().as_deref_mut().map(|dev| f(dev))
+}
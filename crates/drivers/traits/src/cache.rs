@@ -0,0 +1,623 @@
+//! Block cache with read-ahead and write-behind
+//!
+//! [`CachedDevice`] wraps a [`BlockDevice`] and keeps recently used sectors
+//! in memory:
+//!
+//! - Read-ahead: a read that continues where the previous one ended is a
+//!   sequential stream. When it misses, the device request is stretched to
+//!   cover the next `readahead` sectors too, so a file copy costs one large
+//!   request per window instead of one per cluster. Storage drivers poll
+//!   for completion, so the prefetch is part of the missed request rather
+//!   than running in the background.
+//! - Write-behind: writes land in the cache as dirty sectors. They go to
+//!   the device, with contiguous sectors merged into one request, once the
+//!   oldest has waited `dirty_expire` ticks, when more than `dirty_max` are
+//!   dirty, on eviction, or on `flush`. The expiry is checked on every
+//!   request; the kernel also polls [`writeback_due`] from idle paths and
+//!   syncs the filesystems so nothing waits on the next disk access.
+//!
+//! Tunables are system-wide (the kernel exposes them as /proc/blockcache,
+//! and [`register_sysctls`] as `vm.blockcache.*`). A dirty_expire of 0
+//! makes the cache write-through.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::block::{BlockDevice, BlockGeometry};
+use crate::DriverError;
+
+/// Cache settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheTunables {
+    /// Sectors kept in memory
+    pub capacity: usize,
+    /// Sectors read ahead of a sequential reader; 0 turns read-ahead off
+    pub readahead: u32,
+    /// Timer ticks a dirty sector may wait before it is written
+    pub dirty_expire: u64,
+    /// Dirty sectors allowed before the oldest are written out
+    pub dirty_max: usize,
+}
+
+impl CacheTunables {
+    pub const DEFAULT: CacheTunables = CacheTunables {
+        capacity: 4096,   // 2 MB of 512-byte sectors
+        readahead: 128,   // 64 KB
+        dirty_expire: 91, // ~5 s at 18.2 Hz
+        dirty_max: 1024,
+    };
+
+    /// Change one setting by name; false if the name or value is bad
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        match name {
+            "capacity" if value > 0 => self.capacity = value as usize,
+            "readahead" if value <= u32::MAX as u64 => self.readahead = value as u32,
+            "dirty_expire" => self.dirty_expire = value,
+            "dirty_max" => self.dirty_max = value as usize,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Default for CacheTunables {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static TUNABLES: Mutex<CacheTunables> = Mutex::new(CacheTunables::DEFAULT);
+
+/// Current system-wide settings
+pub fn tunables() -> CacheTunables {
+    *TUNABLES.lock()
+}
+
+/// Replace the system-wide settings
+pub fn set_tunables(tunables: CacheTunables) {
+    *TUNABLES.lock() = tunables;
+}
+
+/// Change one setting of the system-wide tunables
+fn set_tunable(name: &str, value: u64) -> bool {
+    let mut tunables = tunables();
+    let changed = tunables.set(name, value);
+    if changed {
+        set_tunables(tunables);
+    }
+    changed
+}
+
+/// Register the tunables as `vm.blockcache.*` sysctls
+pub fn register_sysctls() {
+    use watos_sysctl::{register, Kind, Tunable};
+
+    let tunables = [
+        Tunable {
+            name: "vm.blockcache.capacity",
+            description: "sectors kept in memory",
+            kind: Kind::Int { min: 1, max: 1 << 20 },
+            get: || tunables().capacity as u64,
+            set: |v| set_tunable("capacity", v),
+        },
+        Tunable {
+            name: "vm.blockcache.readahead",
+            description: "sectors read ahead of a sequential reader",
+            kind: Kind::Int { min: 0, max: 65536 },
+            get: || tunables().readahead as u64,
+            set: |v| set_tunable("readahead", v),
+        },
+        Tunable {
+            name: "vm.blockcache.dirty_expire",
+            description: "timer ticks a dirty sector may wait",
+            kind: Kind::Int { min: 0, max: u32::MAX as u64 },
+            get: || tunables().dirty_expire,
+            set: |v| set_tunable("dirty_expire", v),
+        },
+        Tunable {
+            name: "vm.blockcache.dirty_max",
+            description: "dirty sectors allowed before writeback",
+            kind: Kind::Int { min: 0, max: 1 << 20 },
+            get: || tunables().dirty_max as u64,
+            set: |v| set_tunable("dirty_max", v),
+        },
+    ];
+    for tunable in tunables {
+        let _ = register(tunable);
+    }
+}
+
+/// Counters across all cached devices
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Sectors read from the cache
+    pub hits: u64,
+    /// Sectors the reader asked for that had to come from the device
+    pub misses: u64,
+    /// Sectors fetched ahead of a sequential reader
+    pub readahead: u64,
+    /// Read-ahead sectors that were then read
+    pub readahead_hits: u64,
+    /// Dirty sectors written to the device
+    pub writeback: u64,
+    /// Device write requests made for them
+    pub writeback_requests: u64,
+    /// Sectors dirty right now
+    pub dirty: u64,
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static READAHEAD: AtomicU64 = AtomicU64::new(0);
+static READAHEAD_HITS: AtomicU64 = AtomicU64::new(0);
+static WRITEBACK: AtomicU64 = AtomicU64::new(0);
+static WRITEBACK_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static DIRTY: AtomicU64 = AtomicU64::new(0);
+/// Tick the oldest dirty sector on any device was written, u64::MAX if none
+static OLDEST_DIRTY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+pub fn stats() -> CacheStats {
+    CacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        readahead: READAHEAD.load(Ordering::Relaxed),
+        readahead_hits: READAHEAD_HITS.load(Ordering::Relaxed),
+        writeback: WRITEBACK.load(Ordering::Relaxed),
+        writeback_requests: WRITEBACK_REQUESTS.load(Ordering::Relaxed),
+        dirty: DIRTY.load(Ordering::Relaxed),
+    }
+}
+
+/// Has some dirty sector waited dirty_expire ticks as of `now`?
+pub fn writeback_due(now: u64) -> bool {
+    let oldest = OLDEST_DIRTY.load(Ordering::Relaxed);
+    oldest != u64::MAX && now.wrapping_sub(oldest) >= tunables().dirty_expire
+}
+
+struct Block {
+    data: Box<[u8]>,
+    /// Access counter value at the last use, for LRU eviction
+    used: u64,
+    /// Tick it was first dirtied, None if it matches the device
+    dirty_since: Option<u64>,
+    /// Fetched by read-ahead and not read yet
+    prefetched: bool,
+}
+
+/// A block device with a sector cache in front of it
+pub struct CachedDevice<D: BlockDevice> {
+    inner: D,
+    geometry: BlockGeometry,
+    /// Timer ticks, for dirty expiry
+    clock: fn() -> u64,
+    blocks: BTreeMap<u64, Block>,
+    uses: u64,
+    dirty: usize,
+    /// Sector after the last read, to spot sequential streams
+    next_read: u64,
+    /// Settings for this device instead of the system-wide ones
+    tunables: Option<CacheTunables>,
+}
+
+impl<D: BlockDevice> CachedDevice<D> {
+    /// Cache `inner`, reading the time from `clock` (timer ticks)
+    pub fn new(inner: D, clock: fn() -> u64) -> Self {
+        let geometry = inner.geometry();
+        CachedDevice {
+            inner,
+            geometry,
+            clock,
+            blocks: BTreeMap::new(),
+            uses: 0,
+            dirty: 0,
+            next_read: u64::MAX,
+            tunables: None,
+        }
+    }
+
+    /// Use `tunables` for this device instead of the system-wide settings
+    pub fn set_tunables(&mut self, tunables: CacheTunables) {
+        self.tunables = Some(tunables);
+    }
+
+    fn tunables(&self) -> CacheTunables {
+        self.tunables.unwrap_or_else(tunables)
+    }
+
+    /// The wrapped device. Writes made to it directly bypass the cache.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Number of cached sectors
+    pub fn cached(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Number of sectors not yet written back
+    pub fn dirty(&self) -> usize {
+        self.dirty
+    }
+
+    fn sector_bytes(&self) -> usize {
+        self.geometry.sector_size as usize
+    }
+
+    /// Sector count of a request, or an error if it is malformed
+    fn check(&self, start: u64, len: usize) -> Result<u64, DriverError> {
+        let sector_size = self.sector_bytes();
+        if len == 0 || !len.is_multiple_of(sector_size) {
+            return Err(DriverError::InvalidParameter);
+        }
+        let count = (len / sector_size) as u64;
+        match start.checked_add(count) {
+            Some(end) if end <= self.geometry.total_sectors => Ok(count),
+            _ => Err(DriverError::InvalidParameter),
+        }
+    }
+
+    fn touch(&mut self) -> u64 {
+        self.uses += 1;
+        self.uses
+    }
+
+    /// Write out every dirty sector that `pick` selects, merging runs
+    fn write_back(&mut self, pick: impl Fn(&Block) -> bool) -> Result<(), DriverError> {
+        let lbas: Vec<u64> = self.blocks.iter()
+            .filter(|(_, b)| b.dirty_since.is_some() && pick(b))
+            .map(|(&lba, _)| lba)
+            .collect();
+        let sector_size = self.sector_bytes();
+
+        let mut i = 0;
+        while i < lbas.len() {
+            let mut end = i + 1;
+            while end < lbas.len() && lbas[end] == lbas[end - 1] + 1 {
+                end += 1;
+            }
+            let mut buf = Vec::with_capacity((end - i) * sector_size);
+            for lba in &lbas[i..end] {
+                buf.extend_from_slice(&self.blocks[lba].data);
+            }
+            self.inner.write_sectors(lbas[i], &buf)?;
+            for lba in &lbas[i..end] {
+                if let Some(block) = self.blocks.get_mut(lba) {
+                    block.dirty_since = None;
+                }
+            }
+            self.dirty -= end - i;
+            DIRTY.fetch_sub((end - i) as u64, Ordering::Relaxed);
+            WRITEBACK.fetch_add((end - i) as u64, Ordering::Relaxed);
+            WRITEBACK_REQUESTS.fetch_add(1, Ordering::Relaxed);
+            i = end;
+        }
+        self.note_oldest();
+        Ok(())
+    }
+
+    /// Keep OLDEST_DIRTY in step after dirtying or cleaning sectors. It is
+    /// shared by all devices, so it only ever moves earlier here, and is
+    /// cleared once nothing is dirty.
+    fn note_oldest(&self) {
+        let oldest = self.blocks.values().filter_map(|b| b.dirty_since).min();
+        match oldest {
+            Some(tick) => { OLDEST_DIRTY.fetch_min(tick, Ordering::Relaxed); }
+            None if DIRTY.load(Ordering::Relaxed) == 0 => OLDEST_DIRTY.store(u64::MAX, Ordering::Relaxed),
+            None => {}
+        }
+    }
+
+    /// Write back what has expired or what's over the dirty limit
+    fn write_back_due(&mut self, tunables: &CacheTunables) -> Result<(), DriverError> {
+        if self.dirty == 0 {
+            return Ok(());
+        }
+        let now = (self.clock)();
+        let expire = tunables.dirty_expire;
+        let expired = |b: &Block| b.dirty_since.is_some_and(|t| now.wrapping_sub(t) >= expire);
+        if self.blocks.values().any(expired) {
+            // One expired sector flushes its whole neighbourhood in order
+            self.write_back(|_| true)?;
+        } else if self.dirty > tunables.dirty_max {
+            // Memory pressure: write the oldest half
+            let mut ages: Vec<u64> = self.blocks.values().filter_map(|b| b.dirty_since).collect();
+            ages.sort_unstable();
+            let cutoff = ages[ages.len() / 2];
+            self.write_back(|b| b.dirty_since.is_some_and(|t| t <= cutoff))?;
+        }
+        Ok(())
+    }
+
+    /// Drop least recently used sectors down to 7/8 of capacity, writing
+    /// back any dirty ones among them
+    fn evict(&mut self, capacity: usize) -> Result<(), DriverError> {
+        if self.blocks.len() <= capacity {
+            return Ok(());
+        }
+        let mut lru: Vec<(u64, u64)> = self.blocks.iter().map(|(&lba, b)| (b.used, lba)).collect();
+        lru.sort_unstable();
+        let victims: Vec<u64> = lru.iter()
+            .take(self.blocks.len() - capacity * 7 / 8)
+            .map(|&(_, lba)| lba)
+            .collect();
+        if victims.iter().any(|lba| self.blocks[lba].dirty_since.is_some()) {
+            self.write_back(|_| true)?;
+        }
+        for lba in victims {
+            self.blocks.remove(&lba);
+        }
+        Ok(())
+    }
+
+    /// Store sectors read from the device, keeping any cached copy (which
+    /// may be dirty)
+    fn fill(&mut self, start: u64, data: &[u8], prefetched: bool) {
+        let sector_size = self.sector_bytes();
+        for (i, sector) in data.chunks(sector_size).enumerate() {
+            let used = self.touch();
+            self.blocks.entry(start + i as u64).or_insert_with(|| Block {
+                data: sector.into(),
+                used,
+                dirty_since: None,
+                prefetched,
+            });
+        }
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for CachedDevice<D> {
+    fn geometry(&self) -> BlockGeometry {
+        self.geometry
+    }
+
+    fn read_sectors(&mut self, start: u64, buffer: &mut [u8]) -> Result<usize, DriverError> {
+        let count = self.check(start, buffer.len())?;
+        let tunables = self.tunables();
+        self.write_back_due(&tunables)?;
+        let sector_size = self.sector_bytes();
+        let sequential = start == self.next_read;
+        self.next_read = start + count;
+
+        let mut i = 0;
+        while i < count {
+            let lba = start + i;
+            let used = self.touch();
+            if let Some(block) = self.blocks.get_mut(&lba) {
+                block.used = used;
+                if core::mem::take(&mut block.prefetched) {
+                    READAHEAD_HITS.fetch_add(1, Ordering::Relaxed);
+                }
+                let at = i as usize * sector_size;
+                buffer[at..at + sector_size].copy_from_slice(&block.data);
+                HITS.fetch_add(1, Ordering::Relaxed);
+                i += 1;
+                continue;
+            }
+
+            // A run of missing sectors, stretched past the request when
+            // reading sequentially, up to the next cached sector
+            let mut end = i + 1;
+            while end < count && !self.blocks.contains_key(&(start + end)) {
+                end += 1;
+            }
+            let wanted = end - i;
+            let mut extra = 0;
+            if sequential && end == count && tunables.capacity > 0 {
+                let limit = (tunables.readahead as u64)
+                    .min(self.geometry.total_sectors - (start + count))
+                    .min(tunables.capacity as u64 / 2);
+                while extra < limit && !self.blocks.contains_key(&(start + count + extra)) {
+                    extra += 1;
+                }
+            }
+
+            let mut data = vec![0u8; ((wanted + extra) as usize) * sector_size];
+            self.inner.read_sectors(lba, &mut data)?;
+            let at = i as usize * sector_size;
+            let split = wanted as usize * sector_size;
+            buffer[at..at + split].copy_from_slice(&data[..split]);
+            MISSES.fetch_add(wanted, Ordering::Relaxed);
+            READAHEAD.fetch_add(extra, Ordering::Relaxed);
+            if tunables.capacity > 0 {
+                self.fill(lba, &data[..split], false);
+                self.fill(lba + wanted, &data[split..], true);
+            }
+            i = end;
+        }
+
+        self.evict(tunables.capacity)?;
+        Ok(buffer.len())
+    }
+
+    fn write_sectors(&mut self, start: u64, buffer: &[u8]) -> Result<usize, DriverError> {
+        self.check(start, buffer.len())?;
+        let tunables = self.tunables();
+        let sector_size = self.sector_bytes();
+
+        if tunables.dirty_expire == 0 || tunables.capacity == 0 {
+            // Write-through: keep cached copies in step
+            self.inner.write_sectors(start, buffer)?;
+            for (i, sector) in buffer.chunks(sector_size).enumerate() {
+                if let Some(block) = self.blocks.get_mut(&(start + i as u64)) {
+                    block.data.copy_from_slice(sector);
+                }
+            }
+            return Ok(buffer.len());
+        }
+
+        let now = (self.clock)();
+        let mut newly_dirty = 0;
+        for (i, sector) in buffer.chunks(sector_size).enumerate() {
+            let used = self.touch();
+            let block = self.blocks.entry(start + i as u64).or_insert_with(|| Block {
+                data: vec![0; sector_size].into_boxed_slice(),
+                used,
+                dirty_since: None,
+                prefetched: false,
+            });
+            block.data.copy_from_slice(sector);
+            block.used = used;
+            block.prefetched = false;
+            if block.dirty_since.is_none() {
+                block.dirty_since = Some(now);
+                newly_dirty += 1;
+            }
+        }
+        self.dirty += newly_dirty;
+        DIRTY.fetch_add(newly_dirty as u64, Ordering::Relaxed);
+        OLDEST_DIRTY.fetch_min(now, Ordering::Relaxed);
+
+        self.write_back_due(&tunables)?;
+        self.evict(tunables.capacity)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), DriverError> {
+        self.write_back(|_| true)?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::mem::{IoOp, MemBlockDevice};
+    use core::sync::atomic::AtomicU64;
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+    /// Held by tests that move the clock
+    static CLOCK: Mutex<()> = Mutex::new(());
+
+    fn now() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    fn cached(dev: &MemBlockDevice, tunables: CacheTunables) -> CachedDevice<MemBlockDevice> {
+        let mut cache = CachedDevice::new(dev.clone(), now);
+        cache.set_tunables(tunables);
+        cache
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let dev = MemBlockDevice::new(512, 64);
+        dev.poke(20 * 512, &[7; 512]);
+        let mut cache = cached(&dev, CacheTunables { readahead: 8, ..CacheTunables::DEFAULT });
+
+        // A lone read isn't sequential: no read-ahead
+        let mut buf = [0u8; 1024];
+        cache.read_sectors(10, &mut buf).unwrap();
+        // Continuing from there is, so the miss fetches 8 more
+        cache.read_sectors(12, &mut buf).unwrap();
+        // Served from the read-ahead window
+        for lba in (14..22).step_by(2) {
+            cache.read_sectors(lba, &mut buf).unwrap();
+        }
+        assert_eq!(buf[..512], [7; 512]);
+        // Past the window: the next miss fetches the next one
+        cache.read_sectors(22, &mut buf).unwrap();
+        assert_eq!(
+            dev.trace(),
+            [
+                IoOp::Read { start: 10, count: 2 },
+                IoOp::Read { start: 12, count: 10 },
+                IoOp::Read { start: 22, count: 10 },
+            ]
+        );
+
+        // Read-ahead stops at the end of the device
+        dev.clear_trace();
+        cache.read_sectors(60, &mut buf).unwrap();
+        cache.read_sectors(62, &mut buf).unwrap();
+        assert_eq!(
+            dev.trace(),
+            [IoOp::Read { start: 60, count: 2 }, IoOp::Read { start: 62, count: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_write_behind() {
+        let dev = MemBlockDevice::new(512, 64);
+        let tunables = CacheTunables { dirty_expire: 10, ..CacheTunables::DEFAULT };
+        let _clock = CLOCK.lock();
+        let mut cache = cached(&dev, tunables);
+        NOW.store(100, Ordering::Relaxed);
+
+        cache.write_sectors(4, &[1; 1024]).unwrap();
+        cache.write_sectors(6, &[2; 512]).unwrap();
+        cache.write_sectors(9, &[3; 512]).unwrap();
+        assert_eq!(cache.dirty(), 4);
+        assert!(dev.trace().is_empty());
+
+        // Reads see the cached data
+        let mut buf = [0u8; 512];
+        cache.read_sectors(6, &mut buf).unwrap();
+        assert_eq!(buf, [2; 512]);
+        assert!(dev.trace().is_empty());
+
+        // Once expired, the next request writes everything, 4-6 in one go
+        NOW.store(110, Ordering::Relaxed);
+        cache.read_sectors(6, &mut buf).unwrap();
+        assert_eq!(
+            dev.trace(),
+            [IoOp::Write { start: 4, count: 3 }, IoOp::Write { start: 9, count: 1 }]
+        );
+        assert_eq!(cache.dirty(), 0);
+        assert_eq!(dev.peek(6 * 512, 512), [2; 512]);
+
+        // Flush writes what's left and flushes the device
+        dev.clear_trace();
+        cache.write_sectors(0, &[5; 512]).unwrap();
+        cache.flush().unwrap();
+        assert_eq!(dev.trace(), [IoOp::Write { start: 0, count: 1 }, IoOp::Flush]);
+    }
+
+    #[test]
+    fn test_pressure_and_eviction() {
+        let dev = MemBlockDevice::new(512, 64);
+        let tunables = CacheTunables { capacity: 16, readahead: 0, dirty_expire: 1000, dirty_max: 4 };
+        let _clock = CLOCK.lock();
+        let mut cache = cached(&dev, tunables);
+
+        // More than dirty_max dirty sectors: the oldest half goes out
+        for lba in 0..5 {
+            NOW.store(lba, Ordering::Relaxed);
+            cache.write_sectors(lba, &[lba as u8 + 1; 512]).unwrap();
+        }
+        assert_eq!(dev.trace(), [IoOp::Write { start: 0, count: 3 }]);
+        assert_eq!(cache.dirty(), 2);
+
+        // Over capacity: the least recently used sectors are dropped,
+        // written back first if dirty
+        dev.clear_trace();
+        let mut buf = [0u8; 512];
+        for lba in 20..40 {
+            cache.read_sectors(lba, &mut buf).unwrap();
+        }
+        assert!(cache.cached() <= 16);
+        assert_eq!(cache.dirty(), 0);
+        assert!(dev.trace().contains(&IoOp::Write { start: 3, count: 2 }));
+        assert_eq!(dev.peek(4 * 512, 512), [5; 512]);
+    }
+
+    #[test]
+    fn test_write_through() {
+        let dev = MemBlockDevice::new(512, 8);
+        let mut cache = cached(&dev, CacheTunables { dirty_expire: 0, ..CacheTunables::DEFAULT });
+        let mut buf = [0u8; 512];
+        cache.read_sectors(1, &mut buf).unwrap();
+        cache.write_sectors(1, &[9; 512]).unwrap();
+        assert_eq!(dev.peek(512, 512), [9; 512]);
+        cache.read_sectors(1, &mut buf).unwrap();
+        assert_eq!(buf, [9; 512]);
+        assert_eq!(
+            dev.trace(),
+            [IoOp::Read { start: 1, count: 1 }, IoOp::Write { start: 1, count: 1 }]
+        );
+        assert_eq!(cache.read_sectors(8, &mut buf), Err(DriverError::InvalidParameter));
+    }
+}
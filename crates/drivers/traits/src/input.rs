@@ -1,6 +1,6 @@
 //! Input Device Trait
 //!
-//! Implemented by input drivers (PS/2, USB HID, etc.)
+//! Implemented by input drivers (PS/2, USB HID, gameport, etc.)
 //! Used by the console/input subsystem
 
 use crate::DriverResult;
@@ -23,6 +23,12 @@ pub enum InputEvent {
     MouseUp(u8),
     /// Mouse scroll (delta)
     MouseScroll(i8),
+    /// Gamepad button pressed (pad, button)
+    GamepadDown(u8, u8),
+    /// Gamepad button released (pad, button)
+    GamepadUp(u8, u8),
+    /// Gamepad axis moved (pad, axis, position from -32767 to 32767)
+    GamepadAxis(u8, u8, i16),
 }
 
 /// Input device trait
@@ -19,20 +19,29 @@
 //! - `debug-video`: VideoDevice operations
 //! - `debug-audio`: AudioDevice operations
 //! - `debug-bus`: Bus enumeration
+//!
+//! # Testing
+//!
+//! The `std` feature adds `mem::MemBlockDevice`, a RAM-backed block device
+//! with fault injection and I/O tracing for host-side filesystem tests.
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate alloc;
 use alloc::vec::Vec;
 
 // Re-export all trait modules
 pub mod block;
+pub mod cache;
 pub mod nic;
 pub mod input;
 pub mod video;
 pub mod audio;
 mod debug;
 pub mod bus;
+pub mod power;
+#[cfg(feature = "std")]
+pub mod mem;
 
 pub use block::*;
 pub use nic::*;
@@ -77,6 +86,8 @@ pub enum DriverState {
     Active,
     /// Driver is stopped
     Stopped,
+    /// Device is quiesced for a system sleep; `resume` brings it back
+    Suspended,
     /// Driver encountered an error
     Error,
 }
@@ -106,4 +117,15 @@ pub trait Driver {
 
     /// Stop the driver
     fn stop(&mut self) -> Result<(), DriverError>;
+
+    /// Quiesce the device before a system sleep: flush outstanding work and
+    /// stop DMA and interrupts. Drivers without such state need not override.
+    fn suspend(&mut self) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    /// Restore the device after `suspend`
+    fn resume(&mut self) -> Result<(), DriverError> {
+        Ok(())
+    }
 }
@@ -0,0 +1,286 @@
+//! In-Memory Block Device
+//!
+//! A RAM-backed [`BlockDevice`] for host-side filesystem tests (requires the
+//! `std` feature). Besides plain storage it can:
+//!
+//! - inject faults: fail the Nth read or write, or tear the Nth write so only
+//!   its first few sectors reach the media (simulated power loss)
+//! - record every request in an I/O trace
+//!
+//! Clones share the same media, fault schedule and trace, so a test can hand
+//! one clone to a filesystem and keep another to inspect or corrupt the image.
+
+use std::sync::{Arc, Mutex};
+use std::vec;
+use std::vec::Vec;
+
+use crate::block::{BlockDevice, BlockGeometry};
+use crate::DriverError;
+
+/// A request seen by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoOp {
+    /// Read of `count` sectors starting at `start`
+    Read { start: u64, count: u64 },
+    /// Write of `count` sectors starting at `start`
+    Write { start: u64, count: u64 },
+    /// Cache flush
+    Flush,
+}
+
+/// Fault armed for an upcoming write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteFault {
+    /// Nothing reaches the media
+    Fail,
+    /// Only the first `sectors` sectors reach the media
+    Torn { sectors: u64 },
+}
+
+struct MemState {
+    data: Vec<u8>,
+    reads: u64,
+    writes: u64,
+    /// (read number, counted from 1) that fails
+    read_fault: Option<u64>,
+    /// (write number, counted from 1) and what happens to it
+    write_fault: Option<(u64, WriteFault)>,
+    trace: Vec<IoOp>,
+}
+
+/// RAM-backed block device with fault injection and I/O tracing
+#[derive(Clone)]
+pub struct MemBlockDevice {
+    sector_size: u32,
+    total_sectors: u64,
+    state: Arc<Mutex<MemState>>,
+}
+
+impl MemBlockDevice {
+    /// Create a zero-filled device
+    pub fn new(sector_size: u32, total_sectors: u64) -> Self {
+        Self::from_image(sector_size, vec![0; (sector_size as u64 * total_sectors) as usize])
+    }
+
+    /// Create a device backed by an existing image
+    ///
+    /// The image is zero-padded to a whole number of sectors.
+    pub fn from_image(sector_size: u32, mut image: Vec<u8>) -> Self {
+        assert!(sector_size > 0, "sector size must be non-zero");
+        let total_sectors = (image.len() as u64).div_ceil(sector_size as u64);
+        image.resize((total_sectors * sector_size as u64) as usize, 0);
+
+        MemBlockDevice {
+            sector_size,
+            total_sectors,
+            state: Arc::new(Mutex::new(MemState {
+                data: image,
+                reads: 0,
+                writes: 0,
+                read_fault: None,
+                write_fault: None,
+                trace: Vec::new(),
+            })),
+        }
+    }
+
+    /// Copy of the whole image
+    pub fn image(&self) -> Vec<u8> {
+        self.lock().data.clone()
+    }
+
+    /// Read raw bytes at a byte offset, bypassing faults and tracing
+    pub fn peek(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.lock().data[offset..offset + len].to_vec()
+    }
+
+    /// Overwrite raw bytes at a byte offset, bypassing faults and tracing
+    ///
+    /// Used to corrupt an image behind the filesystem's back.
+    pub fn poke(&self, offset: usize, bytes: &[u8]) {
+        self.lock().data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Make the `n`th read from now fail with `IoError` (1 = the next read)
+    pub fn fail_read(&self, n: u64) {
+        let mut state = self.lock();
+        state.read_fault = Some(state.reads + n);
+    }
+
+    /// Make the `n`th write from now fail with `IoError` without touching the media
+    pub fn fail_write(&self, n: u64) {
+        let mut state = self.lock();
+        state.write_fault = Some((state.writes + n, WriteFault::Fail));
+    }
+
+    /// Tear the `n`th write from now: only its first `sectors` sectors are
+    /// stored, then it fails with `IoError`
+    pub fn tear_write(&self, n: u64, sectors: u64) {
+        let mut state = self.lock();
+        state.write_fault = Some((state.writes + n, WriteFault::Torn { sectors }));
+    }
+
+    /// Disarm any pending faults
+    pub fn clear_faults(&self) {
+        let mut state = self.lock();
+        state.read_fault = None;
+        state.write_fault = None;
+    }
+
+    /// Requests seen so far, oldest first
+    pub fn trace(&self) -> Vec<IoOp> {
+        self.lock().trace.clone()
+    }
+
+    /// Forget the recorded trace
+    pub fn clear_trace(&self) {
+        self.lock().trace.clear();
+    }
+
+    /// Number of write requests seen so far (including failed ones)
+    pub fn write_count(&self) -> u64 {
+        self.lock().writes
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemState> {
+        // A panicking test must not poison the image for other clones
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Byte range covered by a request, or an error if it is malformed
+    fn range(&self, start: u64, len: usize) -> Result<(usize, u64), DriverError> {
+        let sector_size = self.sector_size as usize;
+        if len == 0 || !len.is_multiple_of(sector_size) {
+            return Err(DriverError::InvalidParameter);
+        }
+        let count = (len / sector_size) as u64;
+        match start.checked_add(count) {
+            Some(end) if end <= self.total_sectors => {}
+            _ => return Err(DriverError::InvalidParameter),
+        }
+        Ok(((start * self.sector_size as u64) as usize, count))
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn geometry(&self) -> BlockGeometry {
+        BlockGeometry {
+            sector_size: self.sector_size,
+            total_sectors: self.total_sectors,
+            optimal_transfer: 8,
+        }
+    }
+
+    fn read_sectors(&mut self, start: u64, buffer: &mut [u8]) -> Result<usize, DriverError> {
+        let (offset, count) = self.range(start, buffer.len())?;
+        let mut state = self.lock();
+        state.reads += 1;
+        state.trace.push(IoOp::Read { start, count });
+
+        if state.read_fault == Some(state.reads) {
+            state.read_fault = None;
+            return Err(DriverError::IoError);
+        }
+
+        buffer.copy_from_slice(&state.data[offset..offset + buffer.len()]);
+        Ok(buffer.len())
+    }
+
+    fn write_sectors(&mut self, start: u64, buffer: &[u8]) -> Result<usize, DriverError> {
+        let (offset, count) = self.range(start, buffer.len())?;
+        let mut state = self.lock();
+        state.writes += 1;
+        state.trace.push(IoOp::Write { start, count });
+
+        let fault = match state.write_fault {
+            Some((n, fault)) if n == state.writes => {
+                state.write_fault = None;
+                Some(fault)
+            }
+            _ => None,
+        };
+
+        let stored = match fault {
+            None => buffer.len(),
+            Some(WriteFault::Fail) => 0,
+            Some(WriteFault::Torn { sectors }) => {
+                (sectors.min(count) * self.sector_size as u64) as usize
+            }
+        };
+        state.data[offset..offset + stored].copy_from_slice(&buffer[..stored]);
+
+        match fault {
+            None => Ok(buffer.len()),
+            Some(_) => Err(DriverError::IoError),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), DriverError> {
+        self.lock().trace.push(IoOp::Flush);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write_roundtrip() {
+        let mut dev = MemBlockDevice::new(512, 8);
+        let data = [0x5Au8; 1024];
+        assert_eq!(dev.write_sectors(2, &data), Ok(1024));
+
+        let mut buf = [0u8; 1024];
+        assert_eq!(dev.read_sectors(2, &mut buf), Ok(1024));
+        assert_eq!(buf, data);
+        assert_eq!(
+            dev.trace(),
+            [IoOp::Write { start: 2, count: 2 }, IoOp::Read { start: 2, count: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_requests() {
+        let mut dev = MemBlockDevice::new(4096, 4);
+        let mut small = [0u8; 512];
+        assert_eq!(dev.read_sectors(0, &mut small), Err(DriverError::InvalidParameter));
+        let mut block = [0u8; 4096];
+        assert_eq!(dev.read_sectors(4, &mut block), Err(DriverError::InvalidParameter));
+        assert_eq!(dev.read_sectors(u64::MAX, &mut block), Err(DriverError::InvalidParameter));
+    }
+
+    #[test]
+    fn test_fail_nth_write() {
+        let mut dev = MemBlockDevice::new(512, 4);
+        dev.fail_write(2);
+        assert!(dev.write_sectors(0, &[1u8; 512]).is_ok());
+        assert_eq!(dev.write_sectors(1, &[2u8; 512]), Err(DriverError::IoError));
+        assert!(dev.write_sectors(2, &[3u8; 512]).is_ok());
+        assert_eq!(dev.peek(512, 512), [0u8; 512]);
+        assert_eq!(dev.peek(1024, 1), [3]);
+        assert_eq!(dev.write_count(), 3);
+    }
+
+    #[test]
+    fn test_torn_write_keeps_prefix() {
+        let mut dev = MemBlockDevice::new(512, 4);
+        dev.tear_write(1, 1);
+        assert_eq!(dev.write_sectors(0, &[7u8; 2048]), Err(DriverError::IoError));
+        assert_eq!(dev.peek(0, 512), [7u8; 512]);
+        assert_eq!(dev.peek(512, 1536), [0u8; 1536]);
+    }
+
+    #[test]
+    fn test_clones_share_media() {
+        let mut dev = MemBlockDevice::new(512, 2);
+        let handle = dev.clone();
+        handle.poke(510, &[0x55, 0xAA]);
+        handle.fail_read(1);
+
+        let mut buf = [0u8; 512];
+        assert_eq!(dev.read_sectors(0, &mut buf), Err(DriverError::IoError));
+        assert!(dev.read_sectors(0, &mut buf).is_ok());
+        assert_eq!(&buf[510..], &[0x55, 0xAA]);
+    }
+}
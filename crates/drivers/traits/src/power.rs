@@ -0,0 +1,131 @@
+//! Power management hooks
+//!
+//! Drivers that own hardware state register a [`PowerHook`] once the device
+//! is up. The kernel's power coordinator then calls [`suspend_all`] before
+//! the machine stops (ACPI S3, a VM pause) and [`resume_all`] after it
+//! comes back.
+//!
+//! Devices are suspended in the reverse of their registration order and
+//! resumed in registration order, so a device that was set up on top of
+//! another (a filesystem's disk behind its controller) goes down first and
+//! comes up last. If a suspend fails, the devices already suspended are
+//! resumed again and the system stays awake.
+//!
+//! A hook is a pair of plain functions plus a context word rather than a
+//! boxed driver: most drivers are owned by something else once running
+//! (an AHCI port by the filesystem mounted on it), so the hook rebuilds a
+//! handle to the hardware from `ctx` when it fires.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::DriverResult;
+
+/// A registered device
+#[derive(Debug, Clone, Copy)]
+pub struct PowerHook {
+    /// Device name for logs and /proc/power
+    pub name: &'static str,
+    /// Driver-defined value passed to both functions (a base address, a port)
+    pub ctx: u64,
+    /// Quiesce the device: finish or flush outstanding work, stop DMA and
+    /// interrupts
+    pub suspend: fn(u64) -> DriverResult<()>,
+    /// Bring the device back to where it was before `suspend`
+    pub resume: fn(u64) -> DriverResult<()>,
+}
+
+static HOOKS: Mutex<Vec<PowerHook>> = Mutex::new(Vec::new());
+
+/// Register a device. Registering the same name and context twice replaces
+/// the earlier hook.
+pub fn register(hook: PowerHook) {
+    let mut hooks = HOOKS.lock();
+    match hooks.iter_mut().find(|h| h.name == hook.name && h.ctx == hook.ctx) {
+        Some(existing) => *existing = hook,
+        None => hooks.push(hook),
+    }
+}
+
+/// Forget a device (it was removed or its driver stopped)
+pub fn unregister(name: &str, ctx: u64) {
+    HOOKS.lock().retain(|h| !(h.name == name && h.ctx == ctx));
+}
+
+/// The registered devices, in registration order
+pub fn hooks() -> Vec<PowerHook> {
+    HOOKS.lock().clone()
+}
+
+/// Suspend every device, last registered first. On failure the devices
+/// already suspended are resumed and the failing device's name and error
+/// are returned.
+pub fn suspend_all() -> Result<(), (&'static str, crate::DriverError)> {
+    let hooks = hooks();
+    for (i, hook) in hooks.iter().enumerate().rev() {
+        if let Err(e) = (hook.suspend)(hook.ctx) {
+            for done in &hooks[i + 1..] {
+                let _ = (done.resume)(done.ctx);
+            }
+            return Err((hook.name, e));
+        }
+    }
+    Ok(())
+}
+
+/// Resume every device, first registered first. Every device is tried; the
+/// first failure is returned.
+pub fn resume_all() -> Result<(), (&'static str, crate::DriverError)> {
+    let mut result = Ok(());
+    for hook in hooks() {
+        if let Err(e) = (hook.resume)(hook.ctx) {
+            result = result.and(Err((hook.name, e)));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DriverError;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    // Bit n set while device n is suspended
+    static SUSPENDED: AtomicU64 = AtomicU64::new(0);
+
+    fn suspend(ctx: u64) -> DriverResult<()> {
+        if ctx == 9 {
+            return Err(DriverError::Busy);
+        }
+        SUSPENDED.fetch_or(1 << ctx, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn resume(ctx: u64) -> DriverResult<()> {
+        SUSPENDED.fetch_and(!(1 << ctx), Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[test]
+    fn test_suspend_resume_and_rollback() {
+        for ctx in [1, 2, 3] {
+            register(PowerHook { name: "dev", ctx, suspend, resume });
+        }
+        register(PowerHook { name: "dev", ctx: 2, suspend, resume });
+        assert_eq!(hooks().len(), 3);
+
+        assert_eq!(suspend_all(), Ok(()));
+        assert_eq!(SUSPENDED.load(Ordering::SeqCst), 0b1110);
+        assert_eq!(resume_all(), Ok(()));
+        assert_eq!(SUSPENDED.load(Ordering::SeqCst), 0);
+
+        // A device registered first but refusing to suspend undoes the rest
+        HOOKS.lock().insert(0, PowerHook { name: "stuck", ctx: 9, suspend, resume });
+        assert_eq!(suspend_all(), Err(("stuck", DriverError::Busy)));
+        assert_eq!(SUSPENDED.load(Ordering::SeqCst), 0);
+
+        unregister("stuck", 9);
+        assert_eq!(hooks().len(), 3);
+    }
+}
@@ -0,0 +1,80 @@
+//! Bochs Graphics Adaptor (BGA)
+//!
+//! UEFI GOP cannot be called after ExitBootServices, so the kernel changes
+//! resolution by programming the display adapter itself. QEMU's standard
+//! VGA, bochs-display and VirtualBox expose the Bochs "DISPI" interface,
+//! which is also what the OVMF GOP driver uses underneath: the linear
+//! framebuffer stays where GOP put it, with a pitch of width * 4.
+
+/// DISPI index/data ports
+const INDEX_PORT: u16 = 0x01CE;
+const DATA_PORT: u16 = 0x01CF;
+
+/// DISPI registers
+const REG_ID: u16 = 0;
+const REG_XRES: u16 = 1;
+const REG_YRES: u16 = 2;
+const REG_BPP: u16 = 3;
+const REG_ENABLE: u16 = 4;
+const REG_VIRT_WIDTH: u16 = 6;
+const REG_X_OFFSET: u16 = 8;
+const REG_Y_OFFSET: u16 = 9;
+
+/// Interface versions; 0xB0C4 and later support 32bpp
+const ID_MIN: u16 = 0xB0C4;
+const ID_MAX: u16 = 0xB0CF;
+
+/// REG_ENABLE bits
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+fn outw(port: u16, value: u16) {
+    unsafe {
+        core::arch::asm!("out dx, ax", in("dx") port, in("ax") value, options(nostack, preserves_flags));
+    }
+}
+
+fn inw(port: u16) -> u16 {
+    let value: u16;
+    unsafe {
+        core::arch::asm!("in ax, dx", in("dx") port, out("ax") value, options(nostack, preserves_flags));
+    }
+    value
+}
+
+fn write_reg(reg: u16, value: u16) {
+    outw(INDEX_PORT, reg);
+    outw(DATA_PORT, value);
+}
+
+fn read_reg(reg: u16) -> u16 {
+    outw(INDEX_PORT, reg);
+    inw(DATA_PORT)
+}
+
+/// Is a BGA with 32bpp support present?
+pub fn present() -> bool {
+    (ID_MIN..=ID_MAX).contains(&read_reg(REG_ID))
+}
+
+/// Switch to `width` x `height` at 32bpp; returns the new pitch in bytes
+pub fn set_mode(width: u32, height: u32) -> Option<u32> {
+    if !present() || width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return None;
+    }
+
+    write_reg(REG_ENABLE, 0);
+    write_reg(REG_XRES, width as u16);
+    write_reg(REG_YRES, height as u16);
+    write_reg(REG_BPP, 32);
+    write_reg(REG_VIRT_WIDTH, width as u16);
+    write_reg(REG_X_OFFSET, 0);
+    write_reg(REG_Y_OFFSET, 0);
+    write_reg(REG_ENABLE, ENABLED | LFB_ENABLED);
+
+    // The adapter clamps modes it cannot show
+    if read_reg(REG_XRES) as u32 != width || read_reg(REG_YRES) as u32 != height {
+        return None;
+    }
+    Some(width * 4)
+}
@@ -0,0 +1,241 @@
+//! Mouse cursor overlay
+//!
+//! A 32x32 ARGB sprite drawn straight onto a 32bpp framebuffer, independent
+//! of the display hardware. The pixels under the sprite are saved before it
+//! is drawn and put back when it moves or hides, so a move only touches the
+//! old and new 32x32 squares.
+//!
+//! Anything else drawing under the cursor makes the saved copy stale: hide
+//! the cursor around such drawing, or call [`Cursor::refresh`] afterwards.
+
+/// Sprite width and height in pixels
+pub const CURSOR_SIZE: usize = 32;
+
+const CURSOR_PIXELS: usize = CURSOR_SIZE * CURSOR_SIZE;
+
+/// Default arrow: `X` outline, `.` fill, space transparent
+const ARROW: [&str; 19] = [
+    "X",
+    "XX",
+    "X.X",
+    "X..X",
+    "X...X",
+    "X....X",
+    "X.....X",
+    "X......X",
+    "X.......X",
+    "X........X",
+    "X.....XXXXX",
+    "X..X..X",
+    "X.X X..X",
+    "XX  X..X",
+    "X    X..X",
+    "     X..X",
+    "      X..X",
+    "      X..X",
+    "       XX",
+];
+
+/// A linear 32bpp framebuffer the cursor is drawn on
+#[derive(Clone, Copy)]
+pub struct Screen {
+    pub addr: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per scanline
+    pub pitch: usize,
+    /// Pixels are stored B, G, R, X (0x00RRGGBB), else R, G, B, X
+    pub is_bgr: bool,
+}
+
+impl Screen {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> *mut u32 {
+        (self.addr + y as usize * self.pitch + x as usize * 4) as *mut u32
+    }
+}
+
+/// Source-over blend of an ARGB pixel onto an opaque one
+fn blend(dst: u32, src: u32) -> u32 {
+    let a = src >> 24;
+    match a {
+        0 => dst,
+        255 => src & 0x00FF_FFFF,
+        _ => {
+            let mix = |shift: u32| {
+                let s = (src >> shift) & 0xFF;
+                let d = (dst >> shift) & 0xFF;
+                ((s * a + d * (255 - a)) / 255) << shift
+            };
+            mix(16) | mix(8) | mix(0)
+        }
+    }
+}
+
+/// Swap red and blue for RGBX framebuffers
+fn to_screen(color: u32, is_bgr: bool) -> u32 {
+    if is_bgr {
+        color
+    } else {
+        (color & 0xFF00_FF00) | ((color >> 16) & 0xFF) | ((color & 0xFF) << 16)
+    }
+}
+
+/// Cursor sprite, position and save-under buffer
+pub struct Cursor {
+    sprite: [u32; CURSOR_PIXELS],
+    hot_x: i32,
+    hot_y: i32,
+    /// Hotspot position on screen
+    x: i32,
+    y: i32,
+    visible: bool,
+    /// Top-left corner of the sprite currently on screen
+    drawn: Option<(i32, i32)>,
+    saved: [u32; CURSOR_PIXELS],
+}
+
+impl Cursor {
+    /// The default arrow, hidden, at the top-left corner
+    pub fn new() -> Self {
+        let mut sprite = [0u32; CURSOR_PIXELS];
+        for (y, row) in ARROW.iter().enumerate() {
+            for (x, c) in row.bytes().enumerate() {
+                sprite[y * CURSOR_SIZE + x] = match c {
+                    b'X' => 0xFF00_0000,
+                    b'.' => 0xFFFF_FFFF,
+                    _ => 0,
+                };
+            }
+        }
+        Cursor {
+            sprite,
+            hot_x: 0,
+            hot_y: 0,
+            x: 0,
+            y: 0,
+            visible: false,
+            drawn: None,
+            saved: [0; CURSOR_PIXELS],
+        }
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Show or hide the cursor
+    pub fn set_visible(&mut self, screen: &Screen, visible: bool) {
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+        if visible {
+            self.draw(screen);
+        } else {
+            self.erase(screen);
+        }
+    }
+
+    /// Move the hotspot to (x, y), clamped to the screen
+    pub fn move_to(&mut self, screen: &Screen, x: i32, y: i32) {
+        let x = x.clamp(0, screen.width as i32 - 1);
+        let y = y.clamp(0, screen.height as i32 - 1);
+        if (x, y) == (self.x, self.y) {
+            return;
+        }
+        self.erase(screen);
+        self.x = x;
+        self.y = y;
+        if self.visible {
+            self.draw(screen);
+        }
+    }
+
+    /// Replace the sprite (`CURSOR_SIZE` squared ARGB pixels, row by row)
+    /// and its hotspot; false if `pixels` is the wrong size
+    pub fn set_sprite(&mut self, screen: &Screen, pixels: &[u32], hot_x: u32, hot_y: u32) -> bool {
+        if pixels.len() != CURSOR_PIXELS {
+            return false;
+        }
+        self.erase(screen);
+        self.sprite.copy_from_slice(pixels);
+        self.hot_x = hot_x.min(CURSOR_SIZE as u32 - 1) as i32;
+        self.hot_y = hot_y.min(CURSOR_SIZE as u32 - 1) as i32;
+        if self.visible {
+            self.draw(screen);
+        }
+        true
+    }
+
+    /// Re-save what is under the cursor and redraw it, after something else
+    /// drew over it
+    pub fn refresh(&mut self, screen: &Screen) {
+        if self.visible {
+            self.drawn = None;
+            self.draw(screen);
+        }
+    }
+
+    /// Forget the on-screen sprite without restoring it (the screen was
+    /// replaced, e.g. by a mode change) and redraw on `screen`
+    pub fn reset(&mut self, screen: &Screen) {
+        self.drawn = None;
+        self.x = self.x.clamp(0, screen.width as i32 - 1);
+        self.y = self.y.clamp(0, screen.height as i32 - 1);
+        if self.visible {
+            self.draw(screen);
+        }
+    }
+
+    fn draw(&mut self, screen: &Screen) {
+        let (left, top) = (self.x - self.hot_x, self.y - self.hot_y);
+        for row in 0..CURSOR_SIZE {
+            for col in 0..CURSOR_SIZE {
+                let (sx, sy) = (left + col as i32, top + row as i32);
+                if !screen.contains(sx, sy) {
+                    continue;
+                }
+                let i = row * CURSOR_SIZE + col;
+                let ptr = screen.pixel(sx, sy);
+                unsafe {
+                    let under = core::ptr::read_volatile(ptr);
+                    self.saved[i] = under;
+                    let src = self.sprite[i];
+                    if src >> 24 != 0 {
+                        let over = blend(to_screen(under, screen.is_bgr), src);
+                        core::ptr::write_volatile(ptr, to_screen(over, screen.is_bgr));
+                    }
+                }
+            }
+        }
+        self.drawn = Some((left, top));
+    }
+
+    fn erase(&mut self, screen: &Screen) {
+        let Some((left, top)) = self.drawn.take() else { return };
+        for row in 0..CURSOR_SIZE {
+            for col in 0..CURSOR_SIZE {
+                let (sx, sy) = (left + col as i32, top + row as i32);
+                if screen.contains(sx, sy) {
+                    unsafe {
+                        core::ptr::write_volatile(screen.pixel(sx, sy), self.saved[row * CURSOR_SIZE + col]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,16 +1,22 @@
 //! GOP/Linear Framebuffer Driver
 //!
-//! Works with UEFI GOP (Graphics Output Protocol) framebuffer
+//! Works with UEFI GOP (Graphics Output Protocol) framebuffer. The mode can
+//! only change after boot on adapters with a Bochs DISPI interface (see
+//! [`bga`](crate::bga)), and only to resolutions GOP listed.
 
 use watos_driver_traits::{Driver, DriverResult, DriverError, DriverState};
 use watos_driver_traits::video::{VideoDevice, VideoMode, VideoDeviceInfo, Color, PixelFormat};
 use spin::Mutex;
+use alloc::vec::Vec;
+use crate::bga;
 
 /// Framebuffer driver using linear memory-mapped framebuffer (from UEFI GOP)
 pub struct FramebufferDriver {
     state: DriverState,
     framebuffer_addr: u64,
     current_mode: VideoMode,
+    /// Modes GOP offered at boot (always includes the current one)
+    modes: Vec<VideoMode>,
     pitch: usize,
     palette: [u32; 256], // For indexed color modes
 }
@@ -41,11 +47,20 @@ impl FramebufferDriver {
             state: DriverState::Loaded,
             framebuffer_addr: fb_addr,
             current_mode: mode,
+            modes: alloc::vec![mode],
             pitch: pitch as usize,
             palette,
         }
     }
 
+    /// Record a resolution GOP offered at boot
+    pub fn add_mode(&mut self, width: u32, height: u32) {
+        let known = self.modes.iter().any(|m| m.width == width && m.height == height);
+        if !known && width != 0 && height != 0 {
+            self.modes.push(VideoMode { width, height, ..self.current_mode });
+        }
+    }
+
     /// Get framebuffer as mutable slice
     fn framebuffer_mut(&mut self) -> &mut [u8] {
         let size = self.pitch * self.current_mode.height as usize;
@@ -99,20 +114,26 @@ impl VideoDevice for FramebufferDriver {
     }
 
     fn available_modes(&self) -> &[VideoMode] {
-        // For GOP, we only support the mode set by UEFI
-        core::slice::from_ref(&self.current_mode)
+        &self.modes
     }
 
     fn set_mode(&mut self, mode: VideoMode) -> DriverResult<()> {
-        // GOP framebuffer mode is fixed at boot time
-        // We can only "set" the mode if it matches the current one
-        if mode.width == self.current_mode.width 
+        if mode.width == self.current_mode.width
             && mode.height == self.current_mode.height
             && mode.bpp == self.current_mode.bpp {
-            Ok(())
-        } else {
-            Err(DriverError::NotSupported)
+            return Ok(());
         }
+
+        // Other GOP modes are reachable only through a BGA, at 32bpp
+        let listed = self.modes.iter().any(|m| m.width == mode.width && m.height == mode.height);
+        if !listed || mode.bpp != 32 || self.current_mode.bpp != 32 {
+            return Err(DriverError::NotSupported);
+        }
+        let pitch = bga::set_mode(mode.width, mode.height).ok_or(DriverError::NotSupported)?;
+
+        self.current_mode = VideoMode { width: mode.width, height: mode.height, ..self.current_mode };
+        self.pitch = pitch as usize;
+        Ok(())
     }
 
     fn framebuffer(&self) -> *mut u8 {
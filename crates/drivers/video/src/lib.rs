@@ -7,14 +7,18 @@
 extern crate alloc;
 
 pub mod modes;
+pub mod bga;
 pub mod vga;
 pub mod svga;
 pub mod framebuffer;
 pub mod session;
+pub mod screenshot;
+pub mod cursor;
 
 use spin::Mutex;
 use watos_driver_traits::video::{VideoDevice, VideoMode, Color};
 use session::SessionManager;
+use cursor::{Cursor, Screen};
 
 /// Video driver type
 pub enum VideoDriverType {
@@ -49,6 +53,9 @@ impl VideoDriverType {
 /// Global video driver instance
 static VIDEO_DRIVER: Mutex<Option<VideoDriverType>> = Mutex::new(None);
 
+/// Mouse cursor overlay (created on first use)
+static CURSOR: Mutex<Option<Cursor>> = Mutex::new(None);
+
 /// Global session manager
 static SESSION_MANAGER: Mutex<SessionManager> = Mutex::new(SessionManager::new());
 
@@ -58,6 +65,13 @@ pub fn init_from_boot_info(fb_addr: u64, width: u32, height: u32, pitch: u32, bp
     *VIDEO_DRIVER.lock() = Some(VideoDriverType::Framebuffer(driver));
 }
 
+/// Record a resolution the firmware offered, for mode switching after boot
+pub fn add_boot_mode(width: u32, height: u32) {
+    if let Some(VideoDriverType::Framebuffer(d)) = VIDEO_DRIVER.lock().as_mut() {
+        d.add_mode(width, height);
+    }
+}
+
 /// Initialize VGA driver
 pub fn init_vga() {
     let driver = vga::VgaDriver::new();
@@ -77,12 +91,20 @@ pub fn get_current_mode() -> Option<VideoMode> {
 
 /// Set video mode
 pub fn set_mode(mode: VideoMode) -> Result<(), &'static str> {
-    let mut driver = VIDEO_DRIVER.lock();
-    if let Some(ref mut d) = *driver {
-        d.as_device_mut().set_mode(mode).map_err(|_| "Failed to set video mode")
-    } else {
-        Err("No video driver initialized")
+    let result = {
+        let mut driver = VIDEO_DRIVER.lock();
+        if let Some(ref mut d) = *driver {
+            d.as_device_mut().set_mode(mode).map_err(|_| "Failed to set video mode")
+        } else {
+            Err("No video driver initialized")
+        }
+    };
+    if result.is_ok() {
+        if let (Some(screen), Some(cursor)) = (cursor_screen(), CURSOR.lock().as_mut()) {
+            cursor.reset(&screen);
+        }
     }
+    result
 }
 
 /// Set pixel in physical framebuffer
@@ -149,6 +171,69 @@ pub fn get_available_modes() -> alloc::vec::Vec<VideoMode> {
     }
 }
 
+// ============================================================================
+// Cursor API
+// ============================================================================
+
+/// The physical framebuffer, if the cursor can be drawn on it (32bpp)
+fn cursor_screen() -> Option<Screen> {
+    let driver = VIDEO_DRIVER.lock();
+    let device = driver.as_ref()?.as_device();
+    let mode = device.current_mode();
+    if mode.bpp != 32 || device.framebuffer().is_null() {
+        return None;
+    }
+    Some(Screen {
+        addr: device.framebuffer() as usize,
+        width: mode.width,
+        height: mode.height,
+        pitch: device.pitch(),
+        is_bgr: mode.format == watos_driver_traits::video::PixelFormat::Bgr,
+    })
+}
+
+/// Run `f` on the cursor and the screen; None without a 32bpp framebuffer
+fn with_cursor<T>(f: impl FnOnce(&mut Cursor, &Screen) -> T) -> Option<T> {
+    let screen = cursor_screen()?;
+    let mut cursor = CURSOR.lock();
+    Some(f(cursor.get_or_insert_with(Cursor::new), &screen))
+}
+
+/// Show or hide the mouse cursor; false without a 32bpp framebuffer
+pub fn cursor_show(visible: bool) -> bool {
+    with_cursor(|cursor, screen| cursor.set_visible(screen, visible)).is_some()
+}
+
+/// Move the cursor by a relative amount (from mouse events); returns the
+/// new hotspot position
+pub fn cursor_move_by(dx: i32, dy: i32) -> (i32, i32) {
+    with_cursor(|cursor, screen| {
+        let (x, y) = cursor.position();
+        cursor.move_to(screen, x + dx, y + dy);
+        cursor.position()
+    }).unwrap_or((0, 0))
+}
+
+/// Move the cursor hotspot to (x, y)
+pub fn cursor_move_to(x: i32, y: i32) {
+    with_cursor(|cursor, screen| cursor.move_to(screen, x, y));
+}
+
+/// Current cursor hotspot position
+pub fn cursor_position() -> (i32, i32) {
+    CURSOR.lock().as_ref().map(|c| c.position()).unwrap_or((0, 0))
+}
+
+/// Replace the cursor sprite (32x32 ARGB) and hotspot
+pub fn cursor_set_sprite(pixels: &[u32], hot_x: u32, hot_y: u32) -> bool {
+    with_cursor(|cursor, screen| cursor.set_sprite(screen, pixels, hot_x, hot_y)).unwrap_or(false)
+}
+
+/// Redraw the cursor after something drew over it
+pub fn cursor_refresh() {
+    with_cursor(|cursor, screen| cursor.refresh(screen));
+}
+
 // ============================================================================
 // Session Management API
 // ============================================================================
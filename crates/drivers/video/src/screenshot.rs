@@ -0,0 +1,168 @@
+//! Framebuffer screenshots
+//!
+//! Captures the physical framebuffer as 24-bit RGB and encodes it as BMP or
+//! PNG. The PNG encoder writes stored (uncompressed) deflate blocks: larger
+//! files than a real compressor, but no tables or state to carry around.
+
+use alloc::vec::Vec;
+
+/// A captured frame: `pixels` holds 0xRRGGBB values, rows top to bottom
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+/// Image file formats a screenshot can be saved as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Bmp,
+    Png,
+}
+
+impl ImageFormat {
+    /// Pick the format from a file name: `.png` is PNG, anything else BMP
+    pub fn from_path(path: &str) -> Self {
+        let is_png = path.len() >= 4 && path[path.len() - 4..].eq_ignore_ascii_case(".png");
+        if is_png { ImageFormat::Png } else { ImageFormat::Bmp }
+    }
+}
+
+/// Copy the current contents of the framebuffer
+pub fn capture() -> Option<Screenshot> {
+    let driver = crate::VIDEO_DRIVER.lock();
+    let device = driver.as_ref()?.as_device();
+    let mode = device.current_mode();
+
+    let mut pixels = Vec::with_capacity(mode.width as usize * mode.height as usize);
+    for y in 0..mode.height {
+        for x in 0..mode.width {
+            pixels.push(device.get_pixel(x, y) & 0x00FF_FFFF);
+        }
+    }
+
+    Some(Screenshot { width: mode.width, height: mode.height, pixels })
+}
+
+impl Screenshot {
+    /// Encode in the given format
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Bmp => self.to_bmp(),
+            ImageFormat::Png => self.to_png(),
+        }
+    }
+
+    /// 24-bit bottom-up BMP with a BITMAPINFOHEADER
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let row_size = (self.width as usize * 3 + 3) & !3;
+        let data_size = row_size * self.height as usize;
+        let data_offset = 14 + 40;
+
+        let mut out = Vec::with_capacity(data_offset + data_size);
+        // BITMAPFILEHEADER
+        out.extend_from_slice(b"BM");
+        out.extend_from_slice(&((data_offset + data_size) as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        // BITMAPINFOHEADER
+        out.extend_from_slice(&40u32.to_le_bytes());
+        out.extend_from_slice(&(self.width as i32).to_le_bytes());
+        out.extend_from_slice(&(self.height as i32).to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());   // planes
+        out.extend_from_slice(&24u16.to_le_bytes());  // bits per pixel
+        out.extend_from_slice(&0u32.to_le_bytes());   // BI_RGB
+        out.extend_from_slice(&(data_size as u32).to_le_bytes());
+        out.extend_from_slice(&2835u32.to_le_bytes()); // 72 DPI
+        out.extend_from_slice(&2835u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());   // palette colors
+        out.extend_from_slice(&0u32.to_le_bytes());   // important colors
+
+        for row in self.rows().rev() {
+            let start = out.len();
+            for &p in row {
+                out.extend_from_slice(&[p as u8, (p >> 8) as u8, (p >> 16) as u8]);
+            }
+            out.resize(start + row_size, 0);
+        }
+        out
+    }
+
+    /// 8-bit RGB PNG
+    pub fn to_png(&self) -> Vec<u8> {
+        // Scanlines, each prefixed with filter type 0 (none)
+        let mut raw = Vec::with_capacity((self.width as usize * 3 + 1) * self.height as usize);
+        for row in self.rows() {
+            raw.push(0);
+            for &p in row {
+                raw.extend_from_slice(&[(p >> 16) as u8, (p >> 8) as u8, p as u8]);
+            }
+        }
+
+        // zlib stream of stored deflate blocks
+        let mut zlib = Vec::with_capacity(raw.len() + raw.len() / 65535 * 5 + 11);
+        zlib.extend_from_slice(&[0x78, 0x01]);
+        let mut blocks = raw.chunks(65535).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+        }
+        while let Some(block) = blocks.next() {
+            let last = blocks.peek().is_none();
+            let len = block.len() as u16;
+            zlib.push(last as u8);
+            zlib.extend_from_slice(&len.to_le_bytes());
+            zlib.extend_from_slice(&(!len).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit, truecolor, deflate, no filter, no interlace
+
+        let mut out = Vec::with_capacity(zlib.len() + 64);
+        out.extend_from_slice(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        png_chunk(&mut out, b"IHDR", &ihdr);
+        png_chunk(&mut out, b"IDAT", &zlib);
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    fn rows(&self) -> core::slice::Chunks<'_, u32> {
+        self.pixels.chunks(self.width.max(1) as usize)
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
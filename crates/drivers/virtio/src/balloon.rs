@@ -0,0 +1,297 @@
+//! virtio memory balloon (QEMU `-device virtio-balloon-pci`)
+//!
+//! The host asks for memory back by raising the balloon's target size. The
+//! guest hands pages over by frame number (inflate) and takes them back
+//! when the target drops (deflate). Which pages go in is up to the caller,
+//! which also keeps the list; the driver only talks to the device.
+//!
+//! With free page reporting the guest also tells the host about free
+//! ranges it may discard without giving them up: the host maps them back,
+//! zeroed, when they are next touched. The stats queue answers the host's
+//! `guest-stats` polls.
+
+use alloc::vec::Vec;
+use watos_driver_traits::{Driver, DriverError, DriverInfo, DriverState};
+use watos_driver_pci::PciDriver;
+
+use crate::{Buffer, LegacyDevice, Virtqueue, STATUS_ACKNOWLEDGE, STATUS_DRIVER, STATUS_DRIVER_OK, STATUS_FAILED};
+
+/// Transitional PCI device ID of the memory balloon
+pub const DEVICE_ID: u16 = 0x1002;
+
+/// Balloon pages are 4 KiB, whatever the guest's page size
+pub const PAGE_SHIFT: u32 = 12;
+
+/// Frame numbers per inflate or deflate request
+pub const PFNS_PER_REQUEST: usize = 256;
+
+/// Ranges per free page report
+pub const MAX_REPORT_RANGES: usize = 32;
+
+// Feature bits
+const FEATURE_STATS_VQ: u32 = 1 << 1;
+const FEATURE_FREE_PAGE_HINT: u32 = 1 << 3;
+const FEATURE_REPORTING: u32 = 1 << 5;
+
+// Configuration fields
+const CONFIG_NUM_PAGES: u16 = 0;
+const CONFIG_ACTUAL: u16 = 4;
+
+// Queues; the ones after deflate are numbered by the features present
+const QUEUE_INFLATE: u16 = 0;
+const QUEUE_DEFLATE: u16 = 1;
+
+/// Queues that carry requests the driver waits on
+#[derive(Clone, Copy)]
+enum Queue {
+    Inflate,
+    Deflate,
+    Reporting,
+}
+
+/// Statistic tags (virtio 1.1 §5.5.6.3)
+pub mod stat {
+    pub const SWAP_IN: u16 = 0;
+    pub const SWAP_OUT: u16 = 1;
+    pub const MAJOR_FAULTS: u16 = 2;
+    pub const MINOR_FAULTS: u16 = 3;
+    pub const MEMORY_FREE: u16 = 4;
+    pub const MEMORY_TOTAL: u16 = 5;
+    pub const MEMORY_AVAILABLE: u16 = 6;
+    pub const DISK_CACHES: u16 = 7;
+}
+
+/// Bytes per statistic: a 16-bit tag and a 64-bit value, packed
+const STAT_SIZE: usize = 10;
+
+/// virtio memory balloon
+pub struct VirtioBalloon {
+    state: DriverState,
+    device: LegacyDevice,
+    inflate: Option<Virtqueue>,
+    deflate: Option<Virtqueue>,
+    stats: Option<Virtqueue>,
+    reporting: Option<Virtqueue>,
+    /// Statistics the device holds until the host polls
+    stats_buffer: Vec<u8>,
+    stats_queued: bool,
+}
+
+impl VirtioBalloon {
+    /// Probe for a balloon device (initializes its own PCI driver)
+    pub fn probe() -> Option<Self> {
+        let mut pci = PciDriver::new();
+        pci.init().ok()?;
+        Self::probe_with_pci(&pci)
+    }
+
+    /// Probe for a balloon device using provided PCI driver
+    pub fn probe_with_pci(pci: &PciDriver) -> Option<Self> {
+        let device = LegacyDevice::probe_with_pci(pci, DEVICE_ID)?;
+        Some(VirtioBalloon {
+            state: DriverState::Loaded,
+            device,
+            inflate: None,
+            deflate: None,
+            stats: None,
+            reporting: None,
+            stats_buffer: Vec::new(),
+            stats_queued: false,
+        })
+    }
+
+    /// Pages the host wants in the balloon
+    pub fn target_pages(&self) -> u32 {
+        self.device.config32(CONFIG_NUM_PAGES)
+    }
+
+    /// Tell the host how many pages the balloon holds
+    pub fn set_actual(&self, pages: u32) {
+        self.device.set_config32(CONFIG_ACTUAL, pages);
+    }
+
+    /// Give the host the pages with these frame numbers. The guest must not
+    /// touch them until they are deflated.
+    pub fn inflate(&mut self, pfns: &[u32]) -> Result<(), DriverError> {
+        self.transfer(Queue::Inflate, pfns)
+    }
+
+    /// Take pages back from the host
+    pub fn deflate(&mut self, pfns: &[u32]) -> Result<(), DriverError> {
+        self.transfer(Queue::Deflate, pfns)
+    }
+
+    /// Does the host accept free page reports?
+    pub fn can_report(&self) -> bool {
+        self.reporting.is_some()
+    }
+
+    /// Report free ranges (physical address, bytes) the host may discard.
+    /// The guest must not touch them until this returns.
+    pub fn report(&mut self, ranges: &[(u64, u32)]) -> Result<(), DriverError> {
+        if ranges.is_empty() || ranges.len() > MAX_REPORT_RANGES {
+            return Err(DriverError::InvalidParameter);
+        }
+        let chain: Vec<Buffer> = ranges
+            .iter()
+            .map(|&(addr, len)| Buffer { addr, len, device_writes: true })
+            .collect();
+        self.request(Queue::Reporting, &chain)
+    }
+
+    /// Has the host asked for fresh statistics? Answer with `update_stats`.
+    pub fn stats_requested(&mut self) -> bool {
+        match self.stats.as_mut() {
+            Some(queue) if self.stats_queued => {
+                if queue.poll().is_some() {
+                    self.stats_queued = false;
+                }
+                !self.stats_queued
+            }
+            _ => false,
+        }
+    }
+
+    /// Queue statistics (tag, value) for the host's next poll. Does nothing
+    /// while the previous ones are still waiting.
+    pub fn update_stats(&mut self, stats: &[(u16, u64)]) -> Result<(), DriverError> {
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
+        }
+        if self.stats_queued {
+            return Ok(());
+        }
+        let queue = self.stats.as_mut().ok_or(DriverError::NotSupported)?;
+        self.stats_buffer.clear();
+        for &(tag, value) in stats {
+            self.stats_buffer.extend_from_slice(&tag.to_le_bytes());
+            self.stats_buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let len = u32::try_from(stats.len() * STAT_SIZE).map_err(|_| DriverError::InvalidParameter)?;
+        queue.submit_chain(&[Buffer { addr: self.stats_buffer.as_ptr() as u64, len, device_writes: false }])?;
+        self.device.notify(queue);
+        self.stats_queued = true;
+        Ok(())
+    }
+
+    fn transfer(&mut self, queue: Queue, pfns: &[u32]) -> Result<(), DriverError> {
+        if pfns.is_empty() || pfns.len() > PFNS_PER_REQUEST {
+            return Err(DriverError::InvalidParameter);
+        }
+        let bytes: Vec<u8> = pfns.iter().flat_map(|pfn| pfn.to_le_bytes()).collect();
+        let chain = [Buffer { addr: bytes.as_ptr() as u64, len: bytes.len() as u32, device_writes: false }];
+        self.request(queue, &chain)
+    }
+
+    /// Send one chain on a queue and wait for the device to finish with it
+    fn request(&mut self, queue: Queue, chain: &[Buffer]) -> Result<(), DriverError> {
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
+        }
+        let queue = match queue {
+            Queue::Inflate => self.inflate.as_mut(),
+            Queue::Deflate => self.deflate.as_mut(),
+            Queue::Reporting => self.reporting.as_mut(),
+        }
+        .ok_or(DriverError::NotSupported)?;
+        queue.submit_chain(chain)?;
+        self.device.notify(queue);
+        match queue.wait() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // The device may still read the buffers; stop it first
+                self.device.reset();
+                self.drop_queues();
+                self.state = DriverState::Error;
+                Err(e)
+            }
+        }
+    }
+
+    fn drop_queues(&mut self) {
+        self.inflate = None;
+        self.deflate = None;
+        self.stats = None;
+        self.reporting = None;
+        self.stats_queued = false;
+    }
+}
+
+impl Driver for VirtioBalloon {
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "virtio-balloon",
+            version: "0.1.0",
+            author: "WATOS",
+            description: "virtio memory balloon and free page reporting",
+        }
+    }
+
+    fn state(&self) -> DriverState {
+        self.state
+    }
+
+    fn init(&mut self) -> Result<(), DriverError> {
+        self.device.reset();
+        self.device.add_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let offered = self.device.device_features();
+        self.device.set_guest_features(offered & (FEATURE_STATS_VQ | FEATURE_REPORTING));
+
+        // The device numbers its queues by what it offers, not by what we take
+        let mut next = QUEUE_DEFLATE + 1;
+        let stats_queue = (offered & FEATURE_STATS_VQ != 0).then(|| {
+            next += 1;
+            next - 1
+        });
+        if offered & FEATURE_FREE_PAGE_HINT != 0 {
+            next += 1;
+        }
+        let reporting_queue = (offered & FEATURE_REPORTING != 0).then_some(next);
+
+        let queues = (|| -> Result<_, DriverError> {
+            let inflate = self.device.setup_queue(QUEUE_INFLATE)?;
+            let deflate = self.device.setup_queue(QUEUE_DEFLATE)?;
+            let stats = stats_queue.map(|index| self.device.setup_queue(index)).transpose()?;
+            let reporting = reporting_queue.map(|index| self.device.setup_queue(index)).transpose()?;
+            Ok((inflate, deflate, stats, reporting))
+        })();
+        match queues {
+            Ok((inflate, deflate, stats, reporting)) => {
+                self.inflate = Some(inflate);
+                self.deflate = Some(deflate);
+                self.stats = stats;
+                self.reporting = reporting;
+            }
+            Err(e) => {
+                self.device.add_status(STATUS_FAILED);
+                self.state = DriverState::Error;
+                return Err(e);
+            }
+        }
+        self.state = DriverState::Ready;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Ready {
+            return Err(DriverError::InvalidState);
+        }
+        self.device.add_status(STATUS_DRIVER_OK);
+        self.state = DriverState::Active;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), DriverError> {
+        self.device.reset();
+        self.drop_queues();
+        self.state = DriverState::Stopped;
+        Ok(())
+    }
+}
+
+impl Drop for VirtioBalloon {
+    fn drop(&mut self) {
+        // Stop the device before its queue memory is freed
+        self.device.reset();
+    }
+}
@@ -0,0 +1,189 @@
+//! WATOS virtio Drivers
+//!
+//! Devices that hypervisors such as QEMU provide to guests, reached through
+//! the legacy virtio PCI interface (virtio 1.0 §4.1.4.8): registers in I/O
+//! BAR0 and split virtqueues at page-aligned physical addresses.
+//!
+//! QEMU devices are transitional by default, so the legacy interface is
+//! there unless the device sits behind a PCIe root port or is started with
+//! `disable-legacy=on`.
+//!
+//! # Usage
+//!
+//! ```rust,ignore
+//! use watos_driver_virtio::p9::Virtio9p;
+//! use watos_driver_traits::Driver;
+//!
+//! let mut dev = Virtio9p::probe().expect("No virtio 9P device found");
+//! dev.init().expect("Failed to initialize");
+//! dev.start().expect("Failed to start");
+//!
+//! let len = dev.request(&tversion, &mut reply).expect("Request failed");
+//! ```
+//!
+//! Requests are polled: the driver spins until the device returns the
+//! buffers, with one request in flight at a time.
+
+#![no_std]
+
+extern crate alloc;
+
+use core::arch::asm;
+use watos_driver_traits::bus::{PciAddress, PciBar, PciBus};
+use watos_driver_traits::DriverError;
+use watos_driver_pci::PciDriver;
+
+pub mod balloon;
+pub mod p9;
+pub mod queue;
+
+pub use queue::{Buffer, Virtqueue};
+
+/// PCI vendor ID of all virtio devices
+pub const VIRTIO_VENDOR: u16 = 0x1AF4;
+
+// Legacy register offsets in BAR0
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+const REG_ISR: u16 = 0x13;
+/// Device-specific configuration, without MSI-X
+const REG_CONFIG: u16 = 0x14;
+
+// Device status bits
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FAILED: u8 = 0x80;
+
+/// A virtio device's legacy PCI registers
+pub struct LegacyDevice {
+    address: PciAddress,
+    io_base: u16,
+}
+
+impl LegacyDevice {
+    /// Find a transitional virtio device by its PCI device ID and enable
+    /// its I/O space and bus mastering
+    pub fn probe_with_pci(pci: &PciDriver, device_id: u16) -> Option<Self> {
+        let dev = pci.find_by_id(VIRTIO_VENDOR, device_id)?;
+        let io_base = match dev.bars[0] {
+            PciBar::Io { port, .. } if port != 0 => port as u16,
+            // Modern-only device: no legacy registers
+            _ => return None,
+        };
+        pci.enable_io_space(dev.address);
+        pci.enable_bus_master(dev.address);
+        Some(LegacyDevice { address: dev.address, io_base })
+    }
+
+    /// PCI address of the device
+    pub fn address(&self) -> PciAddress {
+        self.address
+    }
+
+    /// Reset the device, dropping its queues
+    pub fn reset(&self) {
+        self.write8(REG_STATUS, 0);
+    }
+
+    pub fn status(&self) -> u8 {
+        self.read8(REG_STATUS)
+    }
+
+    /// Set bits in the device status
+    pub fn add_status(&self, bits: u8) {
+        self.write8(REG_STATUS, self.status() | bits);
+    }
+
+    /// Feature bits the device offers (the low 32; legacy has no more)
+    pub fn device_features(&self) -> u32 {
+        self.read32(REG_DEVICE_FEATURES)
+    }
+
+    /// Accept a subset of the offered features
+    pub fn set_guest_features(&self, features: u32) {
+        self.write32(REG_GUEST_FEATURES, features);
+    }
+
+    /// Allocate queue `index` at the size the device asks for and give the
+    /// device its address
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue, DriverError> {
+        self.write16(REG_QUEUE_SELECT, index);
+        if self.read32(REG_QUEUE_PFN) != 0 {
+            return Err(DriverError::InvalidState);
+        }
+        let size = self.read16(REG_QUEUE_SIZE);
+        if size == 0 {
+            return Err(DriverError::NotSupported);
+        }
+        let queue = Virtqueue::new(index, size)?;
+        let pfn = queue.physical_address() / queue::QUEUE_ALIGN as u64;
+        self.write32(REG_QUEUE_PFN, u32::try_from(pfn).map_err(|_| DriverError::InvalidState)?);
+        Ok(queue)
+    }
+
+    /// Tell the device a queue has new buffers
+    pub fn notify(&self, queue: &Virtqueue) {
+        self.write16(REG_QUEUE_NOTIFY, queue.index());
+    }
+
+    /// Read and acknowledge the interrupt status
+    pub fn isr(&self) -> u8 {
+        self.read8(REG_ISR)
+    }
+
+    /// Byte of the device-specific configuration
+    pub fn config8(&self, offset: u16) -> u8 {
+        self.read8(REG_CONFIG + offset)
+    }
+
+    /// Little-endian 16-bit field of the device-specific configuration
+    pub fn config16(&self, offset: u16) -> u16 {
+        u16::from_le_bytes([self.config8(offset), self.config8(offset + 1)])
+    }
+
+    /// Little-endian 32-bit field of the device-specific configuration
+    pub fn config32(&self, offset: u16) -> u32 {
+        self.read32(REG_CONFIG + offset)
+    }
+
+    /// Write a 32-bit field of the device-specific configuration
+    pub fn set_config32(&self, offset: u16, value: u32) {
+        self.write32(REG_CONFIG + offset, value);
+    }
+
+    fn read8(&self, reg: u16) -> u8 {
+        let value: u8;
+        unsafe { asm!("in al, dx", in("dx") self.io_base + reg, out("al") value, options(nostack)) };
+        value
+    }
+
+    fn read16(&self, reg: u16) -> u16 {
+        let value: u16;
+        unsafe { asm!("in ax, dx", in("dx") self.io_base + reg, out("ax") value, options(nostack)) };
+        value
+    }
+
+    fn read32(&self, reg: u16) -> u32 {
+        let value: u32;
+        unsafe { asm!("in eax, dx", in("dx") self.io_base + reg, out("eax") value, options(nostack)) };
+        value
+    }
+
+    fn write8(&self, reg: u16, value: u8) {
+        unsafe { asm!("out dx, al", in("dx") self.io_base + reg, in("al") value, options(nostack)) };
+    }
+
+    fn write16(&self, reg: u16, value: u16) {
+        unsafe { asm!("out dx, ax", in("dx") self.io_base + reg, in("ax") value, options(nostack)) };
+    }
+
+    fn write32(&self, reg: u16, value: u32) {
+        unsafe { asm!("out dx, eax", in("dx") self.io_base + reg, in("eax") value, options(nostack)) };
+    }
+}
@@ -0,0 +1,134 @@
+//! virtio 9P transport (QEMU `-virtfs`)
+//!
+//! Carries 9P messages to the host: each request goes out in one buffer and
+//! the reply comes back in another. The messages themselves are built and
+//! parsed by the filesystem (`watos-9p`). The device's mount tag names the
+//! host directory it shares.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use watos_driver_traits::{Driver, DriverError, DriverInfo, DriverState};
+use watos_driver_pci::PciDriver;
+
+use crate::{LegacyDevice, Virtqueue, STATUS_ACKNOWLEDGE, STATUS_DRIVER, STATUS_DRIVER_OK, STATUS_FAILED};
+
+/// Transitional PCI device ID of the 9P transport
+pub const DEVICE_ID: u16 = 0x1009;
+
+/// The device has a mount tag in its configuration
+const FEATURE_MOUNT_TAG: u32 = 1 << 0;
+
+/// virtio 9P device
+pub struct Virtio9p {
+    state: DriverState,
+    device: LegacyDevice,
+    queue: Option<Virtqueue>,
+    tag: String,
+}
+
+impl Virtio9p {
+    /// Probe for a 9P device (initializes its own PCI driver)
+    pub fn probe() -> Option<Self> {
+        let mut pci = PciDriver::new();
+        pci.init().ok()?;
+        Self::probe_with_pci(&pci)
+    }
+
+    /// Probe for a 9P device using provided PCI driver
+    pub fn probe_with_pci(pci: &PciDriver) -> Option<Self> {
+        let device = LegacyDevice::probe_with_pci(pci, DEVICE_ID)?;
+        Some(Virtio9p { state: DriverState::Loaded, device, queue: None, tag: String::new() })
+    }
+
+    /// Mount tag the host gave the share; empty before `init`
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Send one 9P message and receive the reply into `response`, returning
+    /// the reply's length
+    pub fn request(&mut self, request: &[u8], response: &mut [u8]) -> Result<usize, DriverError> {
+        if self.state != DriverState::Active {
+            return Err(DriverError::InvalidState);
+        }
+        let queue = self.queue.as_mut().ok_or(DriverError::InvalidState)?;
+        queue.submit(request, response)?;
+        self.device.notify(queue);
+        match queue.wait() {
+            Ok(len) => Ok(len.min(response.len())),
+            Err(e) => {
+                // The device may still write the buffers; stop it first
+                self.device.reset();
+                self.queue = None;
+                self.state = DriverState::Error;
+                Err(e)
+            }
+        }
+    }
+
+    fn read_tag(&self) -> String {
+        if self.device.device_features() & FEATURE_MOUNT_TAG == 0 {
+            return String::new();
+        }
+        let len = self.device.config16(0);
+        let bytes: Vec<u8> = (0..len).map(|i| self.device.config8(2 + i)).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Driver for Virtio9p {
+    fn info(&self) -> DriverInfo {
+        DriverInfo {
+            name: "virtio-9p",
+            version: "0.1.0",
+            author: "WATOS",
+            description: "virtio 9P host file sharing",
+        }
+    }
+
+    fn state(&self) -> DriverState {
+        self.state
+    }
+
+    fn init(&mut self) -> Result<(), DriverError> {
+        self.device.reset();
+        self.device.add_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let features = self.device.device_features() & FEATURE_MOUNT_TAG;
+        self.device.set_guest_features(features);
+        self.tag = self.read_tag();
+
+        match self.device.setup_queue(0) {
+            Ok(queue) => self.queue = Some(queue),
+            Err(e) => {
+                self.device.add_status(STATUS_FAILED);
+                self.state = DriverState::Error;
+                return Err(e);
+            }
+        }
+        self.state = DriverState::Ready;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), DriverError> {
+        if self.state != DriverState::Ready {
+            return Err(DriverError::InvalidState);
+        }
+        self.device.add_status(STATUS_DRIVER_OK);
+        self.state = DriverState::Active;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), DriverError> {
+        self.device.reset();
+        self.queue = None;
+        self.state = DriverState::Stopped;
+        Ok(())
+    }
+}
+
+impl Drop for Virtio9p {
+    fn drop(&mut self) {
+        // Stop the device before its queue memory is freed
+        self.device.reset();
+    }
+}
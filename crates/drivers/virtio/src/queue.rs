@@ -0,0 +1,160 @@
+//! Split virtqueues in the legacy layout
+//!
+//! The descriptor table and available ring share the first part of the
+//! queue memory; the used ring starts on the next page. The memory comes
+//! from the kernel heap, which is identity-mapped, so heap addresses are
+//! the physical addresses the device needs. Buffers handed to the device
+//! are used the same way.
+
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use watos_driver_traits::DriverError;
+
+/// Alignment of the queue and of its used ring
+pub const QUEUE_ALIGN: usize = 4096;
+
+/// Spins to wait for the device before giving up
+const POLL_LIMIT: u32 = 100_000_000;
+
+// Descriptor flags
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+fn align_up(value: usize) -> usize {
+    (value + QUEUE_ALIGN - 1) & !(QUEUE_ALIGN - 1)
+}
+
+/// One buffer of a chain, by physical address
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: u64,
+    pub len: u32,
+    /// The device writes the buffer rather than reads it
+    pub device_writes: bool,
+}
+
+/// One virtqueue, used for one request at a time
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    memory: *mut u8,
+    layout: Layout,
+    avail_offset: usize,
+    used_offset: usize,
+    /// Used ring index already consumed
+    last_used: u16,
+}
+
+// The queue memory belongs to the Virtqueue alone
+unsafe impl Send for Virtqueue {}
+
+impl Virtqueue {
+    /// Allocate zeroed memory for a queue of `size` entries
+    pub fn new(index: u16, size: u16) -> Result<Self, DriverError> {
+        let n = size as usize;
+        let avail_offset = 16 * n;
+        let used_offset = align_up(avail_offset + 6 + 2 * n);
+        let total = used_offset + align_up(6 + 8 * n);
+        let layout = Layout::from_size_align(total, QUEUE_ALIGN).map_err(|_| DriverError::InvalidParameter)?;
+        let memory = unsafe { alloc_zeroed(layout) };
+        if memory.is_null() {
+            return Err(DriverError::BufferTooSmall);
+        }
+        Ok(Virtqueue { index, size, memory, layout, avail_offset, used_offset, last_used: 0 })
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn physical_address(&self) -> u64 {
+        self.memory as u64
+    }
+
+    /// Queue a chain of one device-readable and one device-writable buffer.
+    /// The device must be notified afterwards.
+    pub fn submit(&mut self, out: &[u8], input: &mut [u8]) -> Result<(), DriverError> {
+        let len = |b: usize| u32::try_from(b).map_err(|_| DriverError::InvalidParameter);
+        self.submit_chain(&[
+            Buffer { addr: out.as_ptr() as u64, len: len(out.len())?, device_writes: false },
+            Buffer { addr: input.as_mut_ptr() as u64, len: len(input.len())?, device_writes: true },
+        ])
+    }
+
+    /// Queue `chain` as one request, device-readable buffers first. The
+    /// buffers must stay put until the device returns them.
+    pub fn submit_chain(&mut self, chain: &[Buffer]) -> Result<(), DriverError> {
+        if chain.is_empty() || chain.len() > self.size as usize {
+            return Err(DriverError::NotSupported);
+        }
+        unsafe {
+            let descs = self.memory as *mut Descriptor;
+            for (i, buffer) in chain.iter().enumerate() {
+                let mut flags = if buffer.device_writes { DESC_F_WRITE } else { 0 };
+                if i + 1 < chain.len() {
+                    flags |= DESC_F_NEXT;
+                }
+                write_volatile(
+                    descs.add(i),
+                    Descriptor { addr: buffer.addr, len: buffer.len, flags, next: (i + 1) as u16 },
+                );
+            }
+
+            let avail = self.memory.add(self.avail_offset) as *mut u16;
+            let idx = read_volatile(avail.add(1));
+            write_volatile(avail.add(2 + (idx % self.size) as usize), 0);
+            // Descriptors before the index that publishes them
+            fence(Ordering::SeqCst);
+            write_volatile(avail.add(1), idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Has the device returned the submitted chain? The number of bytes it
+    /// wrote if so
+    pub fn poll(&mut self) -> Option<usize> {
+        let used = unsafe { self.memory.add(self.used_offset) as *const u16 };
+        let idx = unsafe { read_volatile(used.add(1)) };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used % self.size) as usize;
+        let elem = unsafe { used.add(2) as *const u32 };
+        let written = unsafe { read_volatile(elem.add(slot * 2 + 1)) };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some(written as usize)
+    }
+
+    /// Wait for the device to return the submitted chain; the number of
+    /// bytes it wrote
+    pub fn wait(&mut self) -> Result<usize, DriverError> {
+        for _ in 0..POLL_LIMIT {
+            if let Some(written) = self.poll() {
+                return Ok(written);
+            }
+            core::hint::spin_loop();
+        }
+        Err(DriverError::Timeout)
+    }
+}
+
+impl Drop for Virtqueue {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.memory, self.layout) };
+    }
+}
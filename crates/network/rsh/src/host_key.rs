@@ -0,0 +1,92 @@
+//! The server's long-term X25519 key
+
+use watos_crypto::{wipe, x25519, Sha256};
+use watos_vfs::{FileMode, VfsError};
+
+/// Where the server keeps its private host key (root only)
+pub const HOST_KEY_PATH: &str = "/etc/rsh/host_key";
+
+const HOST_KEY_MODE: u32 = 0o600;
+
+/// A host key pair
+pub struct HostKey {
+    private: [u8; x25519::KEY_LEN],
+    public: [u8; x25519::KEY_LEN],
+}
+
+impl HostKey {
+    pub fn from_private(private: [u8; x25519::KEY_LEN]) -> Self {
+        HostKey { public: x25519::public_key(&private), private }
+    }
+
+    /// A new key from `random`
+    pub fn generate(random: &mut dyn FnMut(&mut [u8])) -> Self {
+        let mut private = [0u8; x25519::KEY_LEN];
+        random(&mut private);
+        Self::from_private(private)
+    }
+
+    /// Read the key at `path`, or create one there (mode 0600) if there is
+    /// none
+    pub fn load_or_create(path: &str, random: &mut dyn FnMut(&mut [u8])) -> Result<Self, VfsError> {
+        let mut private = [0u8; x25519::KEY_LEN];
+        match watos_vfs::open(path, FileMode::READ) {
+            Ok(mut file) => {
+                let mut got = 0;
+                while got < private.len() {
+                    match file.read(&mut private[got..])? {
+                        0 => return Err(VfsError::InvalidArgument),
+                        n => got += n,
+                    }
+                }
+                return Ok(Self::from_private(private));
+            }
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let key = Self::generate(random);
+        if let Some(dir) = path.rfind('/').map(|i| &path[..i]).filter(|d| !d.is_empty()) {
+            match watos_vfs::mkdir(dir) {
+                Ok(()) | Err(VfsError::AlreadyExists) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut file = watos_vfs::open(path, FileMode::WRITE)?;
+        let mut done = 0;
+        while done < key.private.len() {
+            match file.write(&key.private[done..])? {
+                0 => return Err(VfsError::NoSpace),
+                n => done += n,
+            }
+        }
+        file.sync()?;
+        for result in [watos_vfs::chown(path, 0, 0), watos_vfs::chmod(path, HOST_KEY_MODE)] {
+            match result {
+                Ok(()) | Err(VfsError::NotSupported) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(key)
+    }
+
+    pub fn public(&self) -> &[u8; x25519::KEY_LEN] {
+        &self.public
+    }
+
+    /// X25519 with the private key
+    pub(crate) fn agree(&self, peer: &[u8; x25519::KEY_LEN]) -> [u8; x25519::KEY_LEN] {
+        x25519::x25519(&self.private, peer)
+    }
+}
+
+impl Drop for HostKey {
+    fn drop(&mut self) {
+        wipe(&mut self.private);
+    }
+}
+
+/// SHA-256 of a public host key, for showing to users and pinning
+pub fn fingerprint(public: &[u8; x25519::KEY_LEN]) -> [u8; 32] {
+    Sha256::digest(public)
+}
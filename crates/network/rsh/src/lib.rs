@@ -0,0 +1,88 @@
+//! WATOS Remote Shell Protocol
+//!
+//! A small SSH-like protocol for logging in to a WATOS machine over a byte
+//! stream and driving a terminal there. Both ends run over anything that
+//! implements [`Transport`].
+//!
+//! ```text
+//! client                                   server
+//! Hello      version, ephemeral key  -->
+//!            <--  Hello      host key, ephemeral key
+//!            <--  Ready      (encrypted from here on)
+//! Auth       user, password, rows, cols -->
+//!            <--  AuthResult
+//! Data / Resize                     <-->   Data / Exit
+//! ```
+//!
+//! Session keys come from HKDF-SHA256 over two X25519 agreements: client
+//! ephemeral with server ephemeral (forward secrecy) and client ephemeral
+//! with the server's static host key (only the real server can complete
+//! it). The client checks the host key itself, by pinning it on first use
+//! like SSH's known_hosts. Every message after the server's Hello is a
+//! ChaCha20-Poly1305 frame with a per-direction counter nonce.
+//!
+//! The server side only checks credentials through a callback and hands
+//! terminal bytes to the caller; running the login shell on a pty and
+//! copying between it and the connection is up to the daemon (`rshd`).
+//! [`Session::poll_event`] lets it do that from one loop, checking the
+//! connection between reads of the pty.
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(test)]
+extern crate std;
+
+pub mod host_key;
+mod session;
+
+pub use host_key::{HostKey, HOST_KEY_PATH};
+pub use session::{Client, Event, Server, Session, TermSize};
+
+/// Port the daemon listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 2222;
+
+/// Protocol version in Hello messages
+pub const VERSION: u8 = 1;
+
+/// Largest payload in one frame
+pub const MAX_PAYLOAD: usize = 4096;
+
+/// Failed logins allowed on one connection
+pub const MAX_AUTH_ATTEMPTS: usize = 3;
+
+/// A reliable, ordered byte stream (a TCP connection)
+pub trait Transport {
+    /// Read at least one byte into `buf`; `Ok(0)` at end of stream
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Read whatever has arrived into `buf` without waiting: `Ok(None)`
+    /// if nothing has, `Ok(Some(0))` at end of stream. By default this
+    /// waits like `read`.
+    fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        self.read(buf).map(Some)
+    }
+
+    /// Write all of `buf`
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+/// Why a session failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The transport failed
+    Transport,
+    /// The peer closed the connection
+    Closed,
+    /// A message was malformed or out of place
+    Protocol,
+    /// The peer speaks another protocol version
+    Version,
+    /// A frame failed decryption
+    BadFrame,
+    /// The client refused the server's host key
+    HostKeyRejected,
+    /// The server refused the user name and password
+    AuthFailed,
+}
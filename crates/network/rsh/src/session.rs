@@ -0,0 +1,571 @@
+//! Handshake, login and the encrypted message channel
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use watos_crypto::aead::{ChaCha20Poly1305, TAG_LEN};
+use watos_crypto::chacha20::{KEY_LEN, NONCE_LEN};
+use watos_crypto::{hkdf_expand, hkdf_extract, wipe, x25519, Sha256};
+
+use crate::host_key::HostKey;
+use crate::{Error, Transport, MAX_AUTH_ATTEMPTS, MAX_PAYLOAD, VERSION};
+
+const MAGIC: &[u8; 4] = b"WRSH";
+
+// Encrypted message types
+const MSG_READY: u8 = 1;
+const MSG_AUTH: u8 = 2;
+const MSG_AUTH_RESULT: u8 = 3;
+const MSG_DATA: u8 = 4;
+const MSG_RESIZE: u8 = 5;
+const MSG_EXIT: u8 = 6;
+
+/// Terminal dimensions in character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// Something the peer sent once logged in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Terminal bytes: keystrokes to the server, output to the client
+    Data(Vec<u8>),
+    /// The client's terminal changed size
+    Resize(TermSize),
+    /// The remote shell exited with a status
+    Exit(u32),
+}
+
+/// One direction's cipher and message counter
+struct Keys {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Keys {
+    fn new(prk: &[u8], label: &[u8]) -> Self {
+        let mut key = [0u8; KEY_LEN];
+        hkdf_expand(prk, label, &mut key);
+        let cipher = ChaCha20Poly1305::new(&key);
+        wipe(&mut key);
+        Keys { cipher, counter: 0 }
+    }
+
+    fn nonce(&mut self) -> [u8; NONCE_LEN] {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        nonce
+    }
+}
+
+/// Frames over a transport, before and after keys are set
+struct Framer<T: Transport> {
+    transport: T,
+    incoming: Vec<u8>,
+}
+
+impl<T: Transport> Framer<T> {
+    fn write(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut frame = Vec::with_capacity(2 + payload.len());
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(payload);
+        self.transport.write_all(&frame)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        self.fill(2)?;
+        let len = self.frame_len()?;
+        self.fill(2 + len)?;
+        Ok(self.take(len))
+    }
+
+    /// A whole frame if one has arrived, without waiting for more
+    fn try_read(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut buf = [0u8; 1024];
+        loop {
+            if self.incoming.len() >= 2 {
+                let len = self.frame_len()?;
+                if self.incoming.len() >= 2 + len {
+                    return Ok(Some(self.take(len)));
+                }
+            }
+            match self.transport.try_read(&mut buf)? {
+                None => return Ok(None),
+                Some(0) => return Err(Error::Closed),
+                Some(got) => self.incoming.extend_from_slice(&buf[..got]),
+            }
+        }
+    }
+
+    /// Payload length of the frame at the front of `incoming`
+    fn frame_len(&self) -> Result<usize, Error> {
+        let len = u16::from_be_bytes([self.incoming[0], self.incoming[1]]) as usize;
+        if len > 1 + MAX_PAYLOAD + TAG_LEN {
+            return Err(Error::Protocol);
+        }
+        Ok(len)
+    }
+
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        self.incoming.drain(..2 + len).skip(2).collect()
+    }
+
+    fn fill(&mut self, n: usize) -> Result<(), Error> {
+        let mut buf = [0u8; 1024];
+        while self.incoming.len() < n {
+            match self.transport.read(&mut buf)? {
+                0 => return Err(Error::Closed),
+                got => self.incoming.extend_from_slice(&buf[..got]),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hello: magic, version, then keys
+fn hello(keys: &[&[u8; 32]]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(5 + 32 * keys.len());
+    msg.extend_from_slice(MAGIC);
+    msg.push(VERSION);
+    for key in keys {
+        msg.extend_from_slice(*key);
+    }
+    msg
+}
+
+/// The peer's keys from its Hello
+fn parse_hello(msg: &[u8], count: usize) -> Result<[[u8; 32]; 2], Error> {
+    if msg.len() < 5 || &msg[..4] != MAGIC {
+        return Err(Error::Protocol);
+    }
+    if msg[4] != VERSION {
+        return Err(Error::Version);
+    }
+    if msg.len() != 5 + 32 * count {
+        return Err(Error::Protocol);
+    }
+    let mut keys = [[0u8; 32]; 2];
+    for (key, chunk) in keys.iter_mut().zip(msg[5..].chunks(32)) {
+        key.copy_from_slice(chunk);
+    }
+    Ok(keys)
+}
+
+/// Send and receive keys from the two agreements and both Hellos
+fn session_keys(client_hello: &[u8], server_hello: &[u8], ephemeral: &[u8; 32], with_host: &[u8; 32]) -> (Keys, Keys) {
+    let mut transcript = Sha256::new();
+    transcript.update(client_hello);
+    transcript.update(server_hello);
+    let mut ikm = [0u8; 64];
+    ikm[..32].copy_from_slice(ephemeral);
+    ikm[32..].copy_from_slice(with_host);
+    let mut prk = hkdf_extract(&transcript.finish(), &ikm);
+    let keys = (Keys::new(&prk, b"wrsh client to server"), Keys::new(&prk, b"wrsh server to client"));
+    wipe(&mut ikm);
+    wipe(&mut prk);
+    keys
+}
+
+/// An established, encrypted connection
+pub struct Session<T: Transport> {
+    framer: Framer<T>,
+    send: Keys,
+    recv: Keys,
+    server: bool,
+}
+
+/// Connecting to a server
+pub struct Client;
+
+impl Client {
+    /// Run the handshake. `verify_host` sees the server's public host key
+    /// and decides whether to trust it (compare with a pinned copy).
+    pub fn connect<T: Transport>(
+        transport: T,
+        random: &mut dyn FnMut(&mut [u8]),
+        verify_host: &mut dyn FnMut(&[u8; 32]) -> bool,
+    ) -> Result<Session<T>, Error> {
+        let mut framer = Framer { transport, incoming: Vec::new() };
+        let mut private = [0u8; x25519::KEY_LEN];
+        random(&mut private);
+        let client_hello = hello(&[&x25519::public_key(&private)]);
+        framer.write(&client_hello)?;
+
+        let server_hello = framer.read()?;
+        let [host, ephemeral] = parse_hello(&server_hello, 2)?;
+        if !verify_host(&host) {
+            wipe(&mut private);
+            return Err(Error::HostKeyRejected);
+        }
+        let mut dh_ephemeral = x25519::x25519(&private, &ephemeral);
+        let mut dh_host = x25519::x25519(&private, &host);
+        wipe(&mut private);
+        let (send, recv) = session_keys(&client_hello, &server_hello, &dh_ephemeral, &dh_host);
+        wipe(&mut dh_ephemeral);
+        wipe(&mut dh_host);
+
+        // Ready proves the server could derive the keys, so holds the host key
+        let mut session = Session { framer, send, recv, server: false };
+        match session.recv()? {
+            (MSG_READY, body) if body.is_empty() => Ok(session),
+            _ => Err(Error::Protocol),
+        }
+    }
+}
+
+/// Accepting connections with a host key
+pub struct Server<'a> {
+    pub host_key: &'a HostKey,
+}
+
+impl Server<'_> {
+    /// Run the handshake with a connecting client
+    pub fn accept<T: Transport>(&self, transport: T, random: &mut dyn FnMut(&mut [u8])) -> Result<Session<T>, Error> {
+        let mut framer = Framer { transport, incoming: Vec::new() };
+        let client_hello = framer.read()?;
+        let [client_key, _] = parse_hello(&client_hello, 1)?;
+
+        let mut private = [0u8; x25519::KEY_LEN];
+        random(&mut private);
+        let server_hello = hello(&[self.host_key.public(), &x25519::public_key(&private)]);
+        framer.write(&server_hello)?;
+
+        let mut dh_ephemeral = x25519::x25519(&private, &client_key);
+        let mut dh_host = self.host_key.agree(&client_key);
+        wipe(&mut private);
+        let (recv, send) = session_keys(&client_hello, &server_hello, &dh_ephemeral, &dh_host);
+        wipe(&mut dh_ephemeral);
+        wipe(&mut dh_host);
+
+        let mut session = Session { framer, send, recv, server: true };
+        session.send(MSG_READY, &[])?;
+        Ok(session)
+    }
+}
+
+impl<T: Transport> Session<T> {
+    fn send(&mut self, msg_type: u8, body: &[u8]) -> Result<(), Error> {
+        let mut msg = Vec::with_capacity(1 + body.len() + TAG_LEN);
+        msg.push(msg_type);
+        msg.extend_from_slice(body);
+        let len = ((msg.len() + TAG_LEN) as u16).to_be_bytes();
+        let nonce = self.send.nonce();
+        let tag = self.send.cipher.seal(&nonce, &len, &mut msg);
+        msg.extend_from_slice(&tag);
+        let result = self.framer.write(&msg);
+        wipe(&mut msg);
+        result
+    }
+
+    fn recv(&mut self) -> Result<(u8, Vec<u8>), Error> {
+        let frame = self.framer.read()?;
+        self.open(frame)
+    }
+
+    /// Decrypt a frame into its message type and body
+    fn open(&mut self, mut frame: Vec<u8>) -> Result<(u8, Vec<u8>), Error> {
+        if frame.len() < 1 + TAG_LEN {
+            return Err(Error::Protocol);
+        }
+        let len = (frame.len() as u16).to_be_bytes();
+        let data_len = frame.len() - TAG_LEN;
+        let tag: [u8; TAG_LEN] = frame[data_len..].try_into().unwrap();
+        let nonce = self.recv.nonce();
+        if !self.recv.cipher.open(&nonce, &len, &mut frame[..data_len], &tag) {
+            return Err(Error::BadFrame);
+        }
+        frame.truncate(data_len);
+        let msg_type = frame.remove(0);
+        Ok((msg_type, frame))
+    }
+
+    /// Client: log in, with the terminal's size; `Err(AuthFailed)` may be
+    /// retried until the server gives up and closes the connection
+    pub fn login(&mut self, user: &str, password: &str, size: TermSize) -> Result<(), Error> {
+        let (user, password) = (user.as_bytes(), password.as_bytes());
+        if user.len() > 255 || password.len() > 255 {
+            return Err(Error::Protocol);
+        }
+        let mut body = Vec::with_capacity(6 + user.len() + password.len());
+        body.push(user.len() as u8);
+        body.extend_from_slice(user);
+        body.push(password.len() as u8);
+        body.extend_from_slice(password);
+        body.extend_from_slice(&size.rows.to_be_bytes());
+        body.extend_from_slice(&size.cols.to_be_bytes());
+        let sent = self.send(MSG_AUTH, &body);
+        wipe(&mut body);
+        sent?;
+        match self.recv()? {
+            (MSG_AUTH_RESULT, body) if body == [1] => Ok(()),
+            (MSG_AUTH_RESULT, body) if body == [0] => Err(Error::AuthFailed),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    /// Server: wait for a login that `authenticate(user, password)`
+    /// accepts, allowing [`MAX_AUTH_ATTEMPTS`]; returns the user name and
+    /// the client's terminal size
+    pub fn wait_login(&mut self, authenticate: &mut dyn FnMut(&str, &str) -> bool) -> Result<(String, TermSize), Error> {
+        for _ in 0..MAX_AUTH_ATTEMPTS {
+            let (msg_type, mut body) = self.recv()?;
+            if msg_type != MSG_AUTH {
+                return Err(Error::Protocol);
+            }
+            let parsed = parse_auth(&body);
+            let accepted = match &parsed {
+                Some((user, password, _)) => authenticate(user, password),
+                None => false,
+            };
+            let result = parsed.map(|(user, _, size)| (String::from(user), size));
+            wipe(&mut body);
+            self.send(MSG_AUTH_RESULT, &[accepted as u8])?;
+            match result {
+                Some(login) if accepted => return Ok(login),
+                Some(_) => {}
+                None => return Err(Error::Protocol),
+            }
+        }
+        Err(Error::AuthFailed)
+    }
+
+    /// Send terminal bytes
+    pub fn send_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        for chunk in data.chunks(MAX_PAYLOAD) {
+            self.send(MSG_DATA, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Client: tell the server the terminal changed size
+    pub fn resize(&mut self, size: TermSize) -> Result<(), Error> {
+        let mut body = [0u8; 4];
+        body[..2].copy_from_slice(&size.rows.to_be_bytes());
+        body[2..].copy_from_slice(&size.cols.to_be_bytes());
+        self.send(MSG_RESIZE, &body)
+    }
+
+    /// Server: report the shell's exit status and end the session
+    pub fn exit(mut self, status: u32) -> Result<T, Error> {
+        self.send(MSG_EXIT, &status.to_be_bytes())?;
+        Ok(self.framer.transport)
+    }
+
+    /// Next message from the peer
+    pub fn recv_event(&mut self) -> Result<Event, Error> {
+        let (msg_type, body) = self.recv()?;
+        self.event(msg_type, body)
+    }
+
+    /// Next message from the peer if a whole one has arrived, without
+    /// waiting (needs a transport with its own `try_read`)
+    pub fn poll_event(&mut self) -> Result<Option<Event>, Error> {
+        match self.framer.try_read()? {
+            Some(frame) => {
+                let (msg_type, body) = self.open(frame)?;
+                self.event(msg_type, body).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn event(&self, msg_type: u8, body: Vec<u8>) -> Result<Event, Error> {
+        let word = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+        match (msg_type, body.len(), self.server) {
+            (MSG_DATA, _, _) => Ok(Event::Data(body)),
+            (MSG_RESIZE, 4, true) => Ok(Event::Resize(TermSize { rows: word(&body), cols: word(&body[2..]) })),
+            (MSG_EXIT, 4, false) => Ok(Event::Exit(u32::from_be_bytes([body[0], body[1], body[2], body[3]]))),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.framer.transport
+    }
+}
+
+/// User, password and terminal size from an Auth body
+fn parse_auth(body: &[u8]) -> Option<(&str, &str, TermSize)> {
+    let user_len = *body.first()? as usize;
+    let user = body.get(1..1 + user_len)?;
+    let rest = &body[1 + user_len..];
+    let password_len = *rest.first()? as usize;
+    let password = rest.get(1..1 + password_len)?;
+    let size = rest.get(1 + password_len..)?;
+    if size.len() != 4 {
+        return None;
+    }
+    let size = TermSize {
+        rows: u16::from_be_bytes([size[0], size[1]]),
+        cols: u16::from_be_bytes([size[2], size[3]]),
+    };
+    Some((core::str::from_utf8(user).ok()?, core::str::from_utf8(password).ok()?, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+    use std::thread;
+    use std::vec;
+
+    /// One end of an in-memory connection
+    struct Pipe {
+        tx: Sender<Vec<u8>>,
+        rx: Receiver<Vec<u8>>,
+        pending: Vec<u8>,
+    }
+
+    impl Transport for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            if self.pending.is_empty() {
+                match self.rx.recv() {
+                    Ok(data) => self.pending = data,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            Ok(n)
+        }
+
+        fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+            if self.pending.is_empty() {
+                match self.rx.try_recv() {
+                    Ok(data) => self.pending = data,
+                    Err(TryRecvError::Empty) => return Ok(None),
+                    Err(TryRecvError::Disconnected) => return Ok(Some(0)),
+                }
+            }
+            self.read(buf).map(Some)
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.tx.send(buf.to_vec()).map_err(|_| Error::Transport)
+        }
+    }
+
+    fn pipe() -> (Pipe, Pipe) {
+        let (a_tx, b_rx) = channel();
+        let (b_tx, a_rx) = channel();
+        (Pipe { tx: a_tx, rx: a_rx, pending: Vec::new() }, Pipe { tx: b_tx, rx: b_rx, pending: Vec::new() })
+    }
+
+    fn counter_random(seed: u8) -> impl FnMut(&mut [u8]) {
+        let mut n = seed;
+        move |buf: &mut [u8]| {
+            for b in buf {
+                n = n.wrapping_mul(31).wrapping_add(7);
+                *b = n;
+            }
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let (client_end, server_end) = pipe();
+        let host_key = HostKey::from_private([9; 32]);
+        let host_public = *host_key.public();
+
+        let server = thread::spawn(move || {
+            let server = Server { host_key: &host_key };
+            let mut session = server.accept(server_end, &mut counter_random(1)).unwrap();
+            let (user, size) = session.wait_login(&mut |u, p| u == "root" && p == "toor").unwrap();
+            assert_eq!(user, "root");
+            assert_eq!(size, TermSize { rows: 25, cols: 80 });
+            assert_eq!(session.recv_event().unwrap(), Event::Data(b"ls\r".to_vec()));
+            assert_eq!(session.recv_event().unwrap(), Event::Resize(TermSize { rows: 50, cols: 132 }));
+            session.send_data(&vec![b'x'; MAX_PAYLOAD + 10]).unwrap();
+            session.exit(3).unwrap();
+        });
+
+        let mut session = Client::connect(client_end, &mut counter_random(2), &mut |key| *key == host_public).unwrap();
+        let size = TermSize { rows: 25, cols: 80 };
+        assert_eq!(session.login("root", "wrong", size), Err(Error::AuthFailed));
+        session.login("root", "toor", size).unwrap();
+        session.send_data(b"ls\r").unwrap();
+        session.resize(TermSize { rows: 50, cols: 132 }).unwrap();
+        let mut received = 0;
+        loop {
+            match session.recv_event().unwrap() {
+                Event::Data(data) => received += data.len(),
+                Event::Exit(status) => {
+                    assert_eq!(status, 3);
+                    break;
+                }
+                Event::Resize(_) => panic!("resize sent to client"),
+            }
+        }
+        assert_eq!(received, MAX_PAYLOAD + 10);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_poll_event() {
+        let (client_end, server_end) = pipe();
+        let (go_tx, go_rx) = channel();
+        let server = thread::spawn(move || {
+            let host_key = HostKey::from_private([7; 32]);
+            let mut session = Server { host_key: &host_key }.accept(server_end, &mut counter_random(7)).unwrap();
+            session.wait_login(&mut |_, _| true).unwrap();
+            assert_eq!(session.poll_event(), Ok(None));
+            go_tx.send(()).unwrap();
+            let mut events = Vec::new();
+            while events.len() < 2 {
+                match session.poll_event().unwrap() {
+                    Some(event) => events.push(event),
+                    None => thread::yield_now(),
+                }
+            }
+            assert_eq!(events, [Event::Data(b"echo hi\r".to_vec()), Event::Resize(TermSize { rows: 30, cols: 100 })]);
+            // The client hanging up shows as an error, not as no event
+            while let Ok(None) = session.poll_event() {
+                thread::yield_now();
+            }
+        });
+        let mut session = Client::connect(client_end, &mut counter_random(8), &mut |_| true).unwrap();
+        session.login("root", "toor", TermSize { rows: 25, cols: 80 }).unwrap();
+        go_rx.recv().unwrap();
+        session.send_data(b"echo hi\r").unwrap();
+        session.resize(TermSize { rows: 30, cols: 100 }).unwrap();
+        drop(session);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_host_key_rejected() {
+        let (client_end, server_end) = pipe();
+        let server = thread::spawn(move || {
+            let host_key = HostKey::from_private([5; 32]);
+            // The client hangs up instead of logging in
+            let result = Server { host_key: &host_key }
+                .accept(server_end, &mut counter_random(3))
+                .and_then(|mut session| session.wait_login(&mut |_, _| true));
+            assert!(matches!(result, Err(Error::Closed | Error::Transport)));
+        });
+        let result = Client::connect(client_end, &mut counter_random(4), &mut |_| false);
+        assert_eq!(result.err(), Some(Error::HostKeyRejected));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_login_attempts_limited() {
+        let (client_end, server_end) = pipe();
+        let server = thread::spawn(move || {
+            let host_key = HostKey::from_private([6; 32]);
+            let mut session = Server { host_key: &host_key }.accept(server_end, &mut counter_random(5)).unwrap();
+            assert_eq!(session.wait_login(&mut |_, _| false).err(), Some(Error::AuthFailed));
+        });
+        let mut session = Client::connect(client_end, &mut counter_random(6), &mut |_| true).unwrap();
+        let size = TermSize { rows: 25, cols: 80 };
+        for _ in 0..MAX_AUTH_ATTEMPTS {
+            assert_eq!(session.login("guest", "guess", size), Err(Error::AuthFailed));
+        }
+        server.join().unwrap();
+        assert!(session.login("guest", "guess", size).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! smoltcp's view of a NIC
+
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+use watos_driver_traits::nic::NicDevice;
+
+/// Largest Ethernet frame without its FCS
+const MAX_FRAME: usize = 1514;
+
+/// A NIC as a smoltcp device; frames are polled, one per token
+pub struct NicPhy<'a>(pub &'a dyn NicDevice);
+
+pub struct RxToken(Vec<u8>);
+
+pub struct TxToken<'a>(&'a dyn NicDevice);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut frame = vec![0u8; len];
+        let result = f(&mut frame);
+        // A frame the NIC can't take is lost, as on the wire; TCP resends it
+        let _ = self.0.send_frame(&frame);
+        result
+    }
+}
+
+impl Device for NicPhy<'_> {
+    type RxToken<'b> = RxToken where Self: 'b;
+    type TxToken<'b> = TxToken<'b> where Self: 'b;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let mut frame = [0u8; MAX_FRAME];
+        match self.0.receive_frame(&mut frame) {
+            Ok(Some(len)) => Some((RxToken(frame[..len].to_vec()), TxToken(self.0))),
+            _ => None,
+        }
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken(self.0))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = MAX_FRAME;
+        caps
+    }
+}
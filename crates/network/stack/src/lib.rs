@@ -1,17 +1,29 @@
-//! Network Stack - Raw packet handling for ping/ARP
+//! Network Stack
 //!
-//! Simple implementation using smoltcp wire types for packet construction.
+//! - [`socket`]: TCP connections, listeners and DNS lookups on a
+//!   [`NicDevice`](watos_driver_traits::nic::NicDevice), run by smoltcp;
+//!   the kernel's socket syscalls are built on it
+//! - [`NetworkStack`]: raw packet handling for ping/ARP, using smoltcp
+//!   wire types for packet construction
 
 #![no_std]
 
 extern crate alloc;
 
+#[cfg(test)]
+extern crate std;
+
+mod device;
+pub mod socket;
+
+pub use smoltcp::wire::Ipv4Address;
+
 use alloc::vec;
 use smoltcp::wire::{
     ArpOperation, ArpPacket, ArpRepr,
     EthernetAddress, EthernetFrame, EthernetProtocol, EthernetRepr,
     Icmpv4Message, Icmpv4Packet, Icmpv4Repr,
-    IpProtocol, Ipv4Address, Ipv4Packet, Ipv4Repr,
+    IpProtocol, Ipv4Packet, Ipv4Repr,
 };
 use smoltcp::phy::ChecksumCapabilities;
 
@@ -31,6 +43,8 @@ pub struct NetConfig {
     pub ip_addr: Ipv4Address,
     pub netmask: Ipv4Address,
     pub gateway: Ipv4Address,
+    /// Name server for `socket::resolve`
+    pub dns: Ipv4Address,
     pub mac_addr: EthernetAddress,
 }
 
@@ -40,6 +54,7 @@ impl Default for NetConfig {
             ip_addr: Ipv4Address::new(10, 0, 2, 15),
             netmask: Ipv4Address::new(255, 255, 255, 0),
             gateway: Ipv4Address::new(10, 0, 2, 2),
+            dns: Ipv4Address::new(10, 0, 2, 3),
             mac_addr: EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
         }
     }
@@ -57,8 +72,10 @@ impl<D: NetworkDriver> NetworkStack<D> {
     /// Create a new network stack
     pub fn new(driver: D) -> Option<Self> {
         let mac = driver.get_mac_address()?;
-        let mut config = NetConfig::default();
-        config.mac_addr = EthernetAddress(mac);
+        let config = NetConfig {
+            mac_addr: EthernetAddress(mac),
+            ..NetConfig::default()
+        };
 
         Some(Self {
             config,
@@ -0,0 +1,514 @@
+//! TCP connections and name lookups
+//!
+//! One smoltcp interface on the machine's NIC, with a static IPv4
+//! configuration. Connections and listeners are file objects, so the kernel
+//! keeps them in fd tables and the usual read, write and close work on them.
+//! Like pipes, reads and writes never block: 0 bytes means nothing could be
+//! moved yet, and [`state`] tells a connection still opening from one that
+//! has ended.
+//!
+//! ```ignore
+//! let stream = socket::connect(Ipv4Address::new(10, 0, 2, 2), 80)?;
+//! // state(id) goes Connecting -> Open -> Closed
+//! let listener = socket::listen(23)?;
+//! // accept(id) -> Some(stream) once a client has connected
+//! ```
+//!
+//! Sockets report [`SOCKET_DEVICE`] and their id in `stat` (as `dev` and
+//! `inode`), which is how the kernel finds the socket behind an fd.
+//!
+//! Nothing runs in the background: the stack is polled whenever a socket is
+//! used, and from [`poll`], which the kernel calls when a process idles.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::socket::dns::{self, GetQueryResultError, QueryHandle};
+use smoltcp::socket::tcp::{self, State};
+use smoltcp::time::Instant;
+use smoltcp::wire::{DnsQueryType, EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use spin::Mutex;
+use watos_driver_traits::nic::NicDevice;
+use watos_vfs::{FileOperations, FileStat, FileType, SeekFrom, VfsError, VfsResult};
+
+use crate::device::NicPhy;
+use crate::NetConfig;
+
+/// Device ID sockets report in `stat`
+pub const SOCKET_DEVICE: u64 = 138;
+
+/// Receive and send buffer size of each connection
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Connections a listener holds open for `accept`
+const BACKLOG: usize = 4;
+
+/// Local ports for outgoing connections
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+/// Why a socket operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    /// No NIC was found, or `init` hasn't run
+    NoInterface,
+    /// Another listener has the port
+    AddrInUse,
+    /// Bad address, port or name
+    InvalidArgument,
+    /// The id is not a socket of the right kind
+    NotSocket,
+    /// A name lookup found nothing
+    NotFound,
+}
+
+/// Where a connection is, for `SYS_NET_STATE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    /// The handshake hasn't finished
+    Connecting,
+    /// Data may still arrive (or, for a listener, it is listening)
+    Open,
+    /// The peer has finished sending or the connection failed, and all
+    /// it sent has been read
+    Closed,
+}
+
+enum Kind {
+    Stream(SocketHandle),
+    /// Sockets listening on `port`; one that a client connects to is
+    /// handed out by `accept` and replaced
+    Listener { port: u16, backlog: Vec<SocketHandle> },
+}
+
+struct Stack {
+    nic: Box<dyn NicDevice>,
+    iface: Interface,
+    sockets: SocketSet<'static>,
+    dns: SocketHandle,
+    /// Milliseconds since boot
+    clock: fn() -> u64,
+    kinds: BTreeMap<u64, Kind>,
+    next_id: u64,
+    next_port: u16,
+    queries: BTreeMap<u64, QueryHandle>,
+    /// Streams closed by their owners, kept until the FIN exchange is over
+    closing: Vec<SocketHandle>,
+}
+
+static STACK: Mutex<Option<Stack>> = Mutex::new(None);
+
+/// Bring up the interface on `nic`. `seed` varies TCP sequence numbers and
+/// ports between boots; `clock` gives milliseconds since boot.
+pub fn init(nic: Box<dyn NicDevice>, config: &NetConfig, seed: u64, clock: fn() -> u64) {
+    let mut iface_config = Config::new(EthernetAddress(nic.mac_address()).into());
+    iface_config.random_seed = seed;
+    let now = Instant::from_millis(clock() as i64);
+    let mut iface = Interface::new(iface_config, &mut NicPhy(&*nic), now);
+    iface.update_ip_addrs(|addrs| {
+        let _ = addrs.push(IpCidr::new(IpAddress::Ipv4(config.ip_addr), prefix_len(config.netmask)));
+    });
+    let _ = iface.routes_mut().add_default_ipv4_route(config.gateway);
+
+    let mut sockets = SocketSet::new(Vec::new());
+    let dns = sockets.add(dns::Socket::new(&[IpAddress::Ipv4(config.dns)], Vec::new()));
+
+    *STACK.lock() = Some(Stack {
+        nic,
+        iface,
+        sockets,
+        dns,
+        clock,
+        kinds: BTreeMap::new(),
+        next_id: 1,
+        next_port: *EPHEMERAL_PORTS.start() + (seed % 4096) as u16,
+        queries: BTreeMap::new(),
+        closing: Vec::new(),
+    });
+}
+
+/// Has `init` run?
+pub fn is_up() -> bool {
+    STACK.lock().is_some()
+}
+
+/// Move frames between the NIC and the sockets, and run TCP timers
+pub fn poll() {
+    if let Some(stack) = STACK.lock().as_mut() {
+        stack.poll();
+    }
+}
+
+/// Start connecting to `addr:port`; the stream is usable once [`state`]
+/// says it's open
+pub fn connect(addr: Ipv4Address, port: u16) -> Result<SocketFile, NetError> {
+    with_stack(|stack| {
+        let handle = stack.sockets.add(new_tcp_socket());
+        let local_port = stack.ephemeral_port();
+        let socket = stack.sockets.get_mut::<tcp::Socket>(handle);
+        if socket.connect(stack.iface.context(), (IpAddress::Ipv4(addr), port), local_port).is_err() {
+            stack.sockets.remove(handle);
+            return Err(NetError::InvalidArgument);
+        }
+        Ok(stack.register(Kind::Stream(handle)))
+    })
+}
+
+/// Listen for connections on `port`
+pub fn listen(port: u16) -> Result<SocketFile, NetError> {
+    with_stack(|stack| {
+        let taken = stack.kinds.values().any(|kind| matches!(kind, Kind::Listener { port: p, .. } if *p == port));
+        if port == 0 || taken {
+            return Err(if taken { NetError::AddrInUse } else { NetError::InvalidArgument });
+        }
+        let mut backlog = Vec::with_capacity(BACKLOG);
+        for _ in 0..BACKLOG {
+            backlog.push(stack.listening_socket(port)?);
+        }
+        Ok(stack.register(Kind::Listener { port, backlog }))
+    })
+}
+
+/// A connection made to listener `id`, if one has come in
+pub fn accept(id: u64) -> Result<Option<SocketFile>, NetError> {
+    with_stack(|stack| {
+        let (port, backlog) = match stack.kinds.get_mut(&id) {
+            Some(Kind::Listener { port, backlog }) => (*port, backlog),
+            _ => return Err(NetError::NotSocket),
+        };
+        let mut accepted = None;
+        for slot in backlog.iter_mut() {
+            match stack.sockets.get::<tcp::Socket>(*slot).state() {
+                State::Established | State::CloseWait if accepted.is_none() => {
+                    accepted = Some(*slot);
+                    *slot = stack.sockets.add(new_tcp_socket());
+                }
+                // A handshake that was reset: listen again
+                State::Closed => {}
+                _ => continue,
+            }
+            stack.sockets.get_mut::<tcp::Socket>(*slot).listen(port).map_err(|_| NetError::InvalidArgument)?;
+        }
+        Ok(accepted.map(|handle| stack.register(Kind::Stream(handle))))
+    })
+}
+
+/// How far connection `id` has got
+pub fn state(id: u64) -> Result<SocketState, NetError> {
+    with_stack(|stack| match stack.kinds.get(&id) {
+        Some(Kind::Stream(handle)) => {
+            let socket = stack.sockets.get::<tcp::Socket>(*handle);
+            Ok(if socket.may_recv() || socket.can_recv() {
+                SocketState::Open
+            } else if matches!(socket.state(), State::SynSent | State::SynReceived) {
+                SocketState::Connecting
+            } else {
+                SocketState::Closed
+            })
+        }
+        Some(Kind::Listener { .. }) => Ok(SocketState::Open),
+        None => Err(NetError::NotSocket),
+    })
+}
+
+/// The other end of connection `id`
+pub fn peer(id: u64) -> Result<(Ipv4Address, u16), NetError> {
+    with_stack(|stack| match stack.kinds.get(&id) {
+        Some(Kind::Stream(handle)) => match stack.sockets.get::<tcp::Socket>(*handle).remote_endpoint() {
+            Some(IpEndpoint { addr: IpAddress::Ipv4(addr), port }) => Ok((addr, port)),
+            _ => Err(NetError::NotSocket),
+        },
+        _ => Err(NetError::NotSocket),
+    })
+}
+
+/// Start looking up the IPv4 address of `name`; returns a query id for
+/// [`resolved`]
+pub fn resolve(name: &str) -> Result<u64, NetError> {
+    with_stack(|stack| {
+        let dns = stack.sockets.get_mut::<dns::Socket>(stack.dns);
+        let query = dns
+            .start_query(stack.iface.context(), name, DnsQueryType::A)
+            .map_err(|_| NetError::InvalidArgument)?;
+        let id = stack.next_id;
+        stack.next_id += 1;
+        stack.queries.insert(id, query);
+        Ok(id)
+    })
+}
+
+/// The answer to query `id`, None while it is still out. A query is
+/// forgotten once it has answered or failed.
+pub fn resolved(id: u64) -> Result<Option<Ipv4Address>, NetError> {
+    with_stack(|stack| {
+        let query = *stack.queries.get(&id).ok_or(NetError::InvalidArgument)?;
+        let result = stack.sockets.get_mut::<dns::Socket>(stack.dns).get_query_result(query);
+        if !matches!(result, Err(GetQueryResultError::Pending)) {
+            stack.queries.remove(&id);
+        }
+        match result {
+            Ok(addrs) => addrs
+                .iter()
+                .find_map(|addr| match addr {
+                    IpAddress::Ipv4(addr) => Some(Some(*addr)),
+                    #[allow(unreachable_patterns)]
+                    _ => None,
+                })
+                .ok_or(NetError::NotFound),
+            Err(GetQueryResultError::Pending) => Ok(None),
+            Err(GetQueryResultError::Failed) => Err(NetError::NotFound),
+        }
+    })
+}
+
+/// Run `f` on the stack, polling before and after
+fn with_stack<T>(f: impl FnOnce(&mut Stack) -> Result<T, NetError>) -> Result<T, NetError> {
+    let mut stack = STACK.lock();
+    let stack = stack.as_mut().ok_or(NetError::NoInterface)?;
+    stack.poll();
+    let result = f(stack);
+    stack.poll();
+    result
+}
+
+fn new_tcp_socket() -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0u8; BUFFER_SIZE]),
+        tcp::SocketBuffer::new(vec![0u8; BUFFER_SIZE]),
+    )
+}
+
+/// Prefix length of a netmask
+fn prefix_len(netmask: Ipv4Address) -> u8 {
+    netmask.as_bytes().iter().map(|b| b.count_ones() as u8).sum()
+}
+
+impl Stack {
+    fn poll(&mut self) {
+        let now = Instant::from_millis((self.clock)() as i64);
+        self.iface.poll(now, &mut NicPhy(&*self.nic), &mut self.sockets);
+
+        let sockets = &mut self.sockets;
+        self.closing.retain(|&handle| {
+            let done = sockets.get::<tcp::Socket>(handle).state() == State::Closed;
+            if done {
+                sockets.remove(handle);
+            }
+            !done
+        });
+    }
+
+    fn register(&mut self, kind: Kind) -> SocketFile {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.kinds.insert(id, kind);
+        SocketFile { id }
+    }
+
+    fn ephemeral_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = if port == *EPHEMERAL_PORTS.end() { *EPHEMERAL_PORTS.start() } else { port + 1 };
+        port
+    }
+
+    fn listening_socket(&mut self, port: u16) -> Result<SocketHandle, NetError> {
+        let mut socket = new_tcp_socket();
+        socket.listen(port).map_err(|_| NetError::InvalidArgument)?;
+        Ok(self.sockets.add(socket))
+    }
+
+    fn stream(&mut self, id: u64) -> VfsResult<&mut tcp::Socket<'static>> {
+        match self.kinds.get(&id) {
+            Some(Kind::Stream(handle)) => Ok(self.sockets.get_mut::<tcp::Socket>(*handle)),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    /// Drop socket `id`: a stream is closed gracefully, a listener's
+    /// pending connections are reset
+    fn close(&mut self, id: u64) {
+        match self.kinds.remove(&id) {
+            Some(Kind::Stream(handle)) => {
+                self.sockets.get_mut::<tcp::Socket>(handle).close();
+                self.closing.push(handle);
+            }
+            Some(Kind::Listener { backlog, .. }) => {
+                for handle in backlog {
+                    self.sockets.get_mut::<tcp::Socket>(handle).abort();
+                    self.closing.push(handle);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// A connection or listener as a file object
+pub struct SocketFile {
+    id: u64,
+}
+
+impl SocketFile {
+    /// The id `accept`, `state` and `peer` take
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+fn vfs_error(_: NetError) -> VfsError {
+    VfsError::IoError
+}
+
+impl FileOperations for SocketFile {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        with_stack(|stack| Ok(stack.stream(self.id).map(|socket| socket.recv_slice(buf).unwrap_or(0))))
+            .map_err(vfs_error)?
+    }
+
+    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        with_stack(|stack| {
+            Ok(stack.stream(self.id).and_then(|socket| match socket.state() {
+                // Queued until the handshake is done
+                State::SynSent | State::SynReceived => Ok(socket.send_slice(buf).unwrap_or(0)),
+                _ if !socket.may_send() => Err(VfsError::IoError), // Hangup
+                _ => Ok(socket.send_slice(buf).unwrap_or(0)),
+            }))
+        })
+        .map_err(vfs_error)?
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+        Err(VfsError::InvalidArgument) // Sockets are not seekable
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        let pending = with_stack(|stack| Ok(stack.stream(self.id).map_or(0, |socket| socket.recv_queue())))
+            .unwrap_or(0);
+        Ok(FileStat {
+            file_type: FileType::Socket,
+            size: pending as u64,
+            inode: self.id,
+            dev: SOCKET_DEVICE,
+            mode: 0o600,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::InvalidArgument)
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Some(stack) = STACK.lock().as_mut() {
+            stack.close(self.id);
+            stack.poll();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use watos_driver_traits::nic::{MacAddress, NicDeviceInfo};
+    use watos_driver_traits::DriverResult;
+
+    /// A NIC wired to itself: every frame sent comes back in
+    struct LoopbackNic {
+        frames: Mutex<VecDeque<Vec<u8>>>,
+    }
+
+    impl NicDevice for LoopbackNic {
+        fn mac_address(&self) -> MacAddress {
+            [0x52, 0x54, 0x00, 0x12, 0x34, 0x56]
+        }
+
+        fn send_frame(&self, frame: &[u8]) -> DriverResult<()> {
+            self.frames.lock().push_back(frame.to_vec());
+            Ok(())
+        }
+
+        fn receive_frame(&self, buf: &mut [u8]) -> DriverResult<Option<usize>> {
+            Ok(self.frames.lock().pop_front().map(|frame| {
+                buf[..frame.len()].copy_from_slice(&frame);
+                frame.len()
+            }))
+        }
+
+        fn link_up(&self) -> bool {
+            true
+        }
+
+        fn link_speed(&self) -> u32 {
+            1000
+        }
+
+        fn info(&self) -> NicDeviceInfo {
+            NicDeviceInfo { name: "loopback", mac: self.mac_address(), mtu: 1500, link_up: true, speed_mbps: 1000 }
+        }
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn clock() -> u64 {
+        NOW.fetch_add(10, Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_connect_accept_exchange_close() {
+        let config = NetConfig::default();
+        init(Box::new(LoopbackNic { frames: Mutex::new(VecDeque::new()) }), &config, 7, clock);
+        assert!(is_up());
+
+        let mut listener = listen(2323).unwrap();
+        assert_eq!(listen(2323).err(), Some(NetError::AddrInUse));
+        assert_eq!(listener.read(&mut [0u8; 4]).err(), Some(VfsError::InvalidArgument));
+
+        let mut client = connect(config.ip_addr, 2323).unwrap();
+        let mut server = None;
+        for _ in 0..100 {
+            server = accept(listener.id()).unwrap();
+            if server.is_some() {
+                break;
+            }
+        }
+        let mut server = server.expect("no connection to accept");
+        assert_eq!(state(client.id()), Ok(SocketState::Open));
+        assert_eq!(peer(server.id()).map(|(addr, _)| addr), Ok(config.ip_addr));
+        assert_eq!(server.stat().unwrap().dev, SOCKET_DEVICE);
+
+        assert_eq!(client.write(b"hello").unwrap(), 5);
+        let mut buf = [0u8; 16];
+        let mut got = 0;
+        for _ in 0..100 {
+            got += server.read(&mut buf[got..]).unwrap();
+            if got == 5 {
+                break;
+            }
+        }
+        assert_eq!(&buf[..got], b"hello");
+
+        // Closing one end ends the stream at the other once drained
+        drop(client);
+        for _ in 0..100 {
+            if state(server.id()) == Ok(SocketState::Closed) {
+                break;
+            }
+            poll();
+        }
+        assert_eq!(state(server.id()), Ok(SocketState::Closed));
+        drop(listener);
+    }
+}
@@ -0,0 +1,195 @@
+//! WATOS Telnet Console
+//!
+//! A plain-text remote console for testing on a trusted LAN, until remote
+//! logins go through `watos-rsh` everywhere. A client connects with any
+//! telnet program; the daemon attaches the connection to a pty running
+//! `login`, which asks for the user name and password itself.
+//!
+//! Nothing on the wire is encrypted, passwords included. The only
+//! protection is the source address allowlist in [`CONFIG_PATH`]:
+//!
+//! ```text
+//! # /etc/telnetd.conf
+//! port 2323
+//! allow 192.168.1.0/24
+//! allow 10.0.2.2
+//! ```
+//!
+//! With no file, or no `allow` lines, every connection is refused.
+//!
+//! The daemon, `telnetd`, listens on `config.port` and drops connections
+//! whose source [`Config::permits`] refuses. For the rest it calls
+//! [`Session::accept`], runs `login` on a new pty, and copies
+//! [`Event::Data`] to the master and the master's output to
+//! [`Session::send_data`], using [`Session::poll_event`] so one loop can
+//! watch both. Ptys have no window size yet, so [`Event::Resize`] is
+//! dropped.
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(test)]
+extern crate std;
+
+mod session;
+
+pub use session::{Event, Session, TermSize};
+
+use alloc::vec::Vec;
+use watos_vfs::{FileMode, VfsError};
+
+/// Where the daemon reads its port and allowlist
+pub const CONFIG_PATH: &str = "/etc/telnetd.conf";
+
+/// Port used when the configuration names none
+pub const DEFAULT_PORT: u16 = 23;
+
+/// A reliable, ordered byte stream (a TCP connection)
+pub trait Transport {
+    /// Read at least one byte into `buf`; `Ok(0)` at end of stream
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Read whatever has arrived into `buf` without waiting: `Ok(None)`
+    /// if nothing has, `Ok(Some(0))` at end of stream. By default this
+    /// waits like `read`.
+    fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        self.read(buf).map(Some)
+    }
+
+    /// Write all of `buf`
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+/// Why a session failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The transport failed
+    Transport,
+    /// The peer closed the connection
+    Closed,
+}
+
+/// An IPv4 network: address and prefix length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    pub addr: [u8; 4],
+    pub prefix: u8,
+}
+
+impl Network {
+    fn mask(&self) -> u32 {
+        match self.prefix {
+            0 => 0,
+            n => u32::MAX << (32 - n as u32),
+        }
+    }
+
+    /// Is `addr` inside this network?
+    pub fn contains(&self, addr: [u8; 4]) -> bool {
+        let mask = self.mask();
+        u32::from_be_bytes(addr) & mask == u32::from_be_bytes(self.addr) & mask
+    }
+
+    /// `a.b.c.d` or `a.b.c.d/prefix`
+    fn parse(s: &str) -> Option<Network> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, prefix.parse::<u8>().ok().filter(|&p| p <= 32)?),
+            None => (s, 32),
+        };
+        let mut octets = [0u8; 4];
+        let mut parts = addr.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Network { addr: octets, prefix })
+    }
+}
+
+/// The daemon's settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// TCP port to listen on
+    pub port: u16,
+    /// Sources allowed to connect; empty refuses everyone
+    pub allow: Vec<Network>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { port: DEFAULT_PORT, allow: Vec::new() }
+    }
+}
+
+impl Config {
+    /// Parse a configuration file; the error is the 1-based number of the
+    /// first line that isn't `port N`, `allow NETWORK`, blank or a comment
+    pub fn parse(text: &str) -> Result<Config, usize> {
+        let mut config = Config::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let parsed = match (words.next(), words.next(), words.next()) {
+                (None, _, _) => Some(()),
+                (Some("port"), Some(port), None) => port.parse().ok().filter(|&p| p != 0).map(|p| config.port = p),
+                (Some("allow"), Some(net), None) => Network::parse(net).map(|net| config.allow.push(net)),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(index + 1);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read `path`; a missing file gives the default, which refuses all
+    pub fn load(path: &str) -> Result<Config, VfsError> {
+        let mut file = match watos_vfs::open(path, FileMode::READ) {
+            Ok(file) => file,
+            Err(VfsError::NotFound) => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        let mut text = Vec::new();
+        let mut buf = [0u8; 512];
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => text.extend_from_slice(&buf[..n]),
+            }
+        }
+        let text = core::str::from_utf8(&text).map_err(|_| VfsError::InvalidArgument)?;
+        Config::parse(text).map_err(|_| VfsError::InvalidArgument)
+    }
+
+    /// May a connection from `addr` log in?
+    pub fn permits(&self, addr: [u8; 4]) -> bool {
+        self.allow.iter().any(|net| net.contains(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(!Config::default().permits([127, 0, 0, 1]));
+
+        let config = Config::parse("# LAN only\nport 2323\n\nallow 192.168.1.0/24  # office\nallow 10.0.2.2\n").unwrap();
+        assert_eq!(config.port, 2323);
+        assert!(config.permits([192, 168, 1, 77]));
+        assert!(config.permits([10, 0, 2, 2]));
+        assert!(!config.permits([192, 168, 2, 1]));
+        assert!(!config.permits([10, 0, 2, 3]));
+        assert!(Config::parse("allow 0.0.0.0/0").unwrap().permits([8, 8, 8, 8]));
+
+        assert_eq!(Config::parse("port 23\nallow 10.0.0.0/33"), Err(2));
+        assert_eq!(Config::parse("allow 10.0.0"), Err(1));
+        assert_eq!(Config::parse("port 0"), Err(1));
+        assert_eq!(Config::parse("listen 23"), Err(1));
+    }
+}
@@ -0,0 +1,285 @@
+//! Telnet option negotiation and the data stream
+//!
+//! The server offers to echo and to suppress go-ahead, which puts clients
+//! in character-at-a-time mode so the pty's line discipline does the
+//! editing, and asks for window size reports (NAWS, RFC 1073). Anything
+//! else the client asks for is refused. Commands are stripped from the
+//! data; Interrupt Process becomes Ctrl-C, and a client's CR LF or CR NUL
+//! becomes the CR a terminal's Enter key sends.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::{Error, Transport};
+
+// Commands
+const SE: u8 = 240;
+const IP: u8 = 244;
+const SB: u8 = 250;
+const WILL: u8 = 251;
+const WONT: u8 = 252;
+const DO: u8 = 253;
+const DONT: u8 = 254;
+const IAC: u8 = 255;
+
+// Options
+const ECHO: u8 = 1;
+const SGA: u8 = 3;
+const NAWS: u8 = 31;
+
+/// Longest subnegotiation kept; longer ones are dropped
+const MAX_SUBNEGOTIATION: usize = 64;
+
+/// Terminal dimensions in character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// Something the client sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Keystrokes
+    Data(Vec<u8>),
+    /// The client's terminal changed size
+    Resize(TermSize),
+}
+
+/// Where the parser is in the incoming stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Data,
+    /// After a CR in the data
+    Cr,
+    /// After IAC
+    Command,
+    /// After WILL, WONT, DO or DONT
+    Option(u8),
+    /// Inside IAC SB ... IAC SE
+    Sub,
+    /// After IAC inside a subnegotiation
+    SubCommand,
+}
+
+/// A telnet connection, after the server's offers have been sent
+pub struct Session<T: Transport> {
+    transport: T,
+    state: State,
+    subnegotiation: Vec<u8>,
+    events: VecDeque<Event>,
+}
+
+impl<T: Transport> Session<T> {
+    /// Start a session on a new connection
+    pub fn accept(mut transport: T) -> Result<Session<T>, Error> {
+        transport.write_all(&[IAC, WILL, ECHO, IAC, WILL, SGA, IAC, DO, SGA, IAC, DO, NAWS])?;
+        Ok(Session { transport, state: State::Data, subnegotiation: Vec::new(), events: VecDeque::new() })
+    }
+
+    /// Send terminal output, doubling any IAC bytes
+    pub fn send_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        if !data.contains(&IAC) {
+            return self.transport.write_all(data);
+        }
+        let mut escaped = Vec::with_capacity(data.len() + 8);
+        for &byte in data {
+            escaped.push(byte);
+            if byte == IAC {
+                escaped.push(IAC);
+            }
+        }
+        self.transport.write_all(&escaped)
+    }
+
+    /// Next thing the client sent; `Err(Closed)` when it hangs up
+    pub fn recv_event(&mut self) -> Result<Event, Error> {
+        let mut buf = [0u8; 512];
+        while self.events.is_empty() {
+            match self.transport.read(&mut buf)? {
+                0 => return Err(Error::Closed),
+                n => self.receive(&buf[..n])?,
+            }
+        }
+        Ok(self.events.pop_front().unwrap())
+    }
+
+    /// Next thing the client sent if it has arrived, without waiting
+    /// (needs a transport with its own `try_read`)
+    pub fn poll_event(&mut self) -> Result<Option<Event>, Error> {
+        let mut buf = [0u8; 512];
+        while self.events.is_empty() {
+            match self.transport.try_read(&mut buf)? {
+                None => return Ok(None),
+                Some(0) => return Err(Error::Closed),
+                Some(n) => self.receive(&buf[..n])?,
+            }
+        }
+        Ok(self.events.pop_front())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Parse bytes from the client, queueing events and answering options
+    fn receive(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut data = Vec::new();
+        let mut replies = Vec::new();
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (State::Data | State::Cr, IAC) => State::Command,
+                (State::Cr, b'\n' | 0) => State::Data,
+                (State::Data | State::Cr, b'\r') => {
+                    data.push(byte);
+                    State::Cr
+                }
+                (State::Data | State::Cr, _) => {
+                    data.push(byte);
+                    State::Data
+                }
+                (State::Command, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Command, IP) => {
+                    data.push(0x03);
+                    State::Data
+                }
+                (State::Command, WILL | WONT | DO | DONT) => State::Option(byte),
+                (State::Command, SB) => {
+                    self.subnegotiation.clear();
+                    State::Sub
+                }
+                (State::Command, _) => State::Data,
+                (State::Option(verb), option) => {
+                    if let Some(reply) = answer(verb, option) {
+                        replies.extend_from_slice(&[IAC, reply, option]);
+                    }
+                    State::Data
+                }
+                (State::Sub, IAC) => State::SubCommand,
+                (State::Sub, _) | (State::SubCommand, IAC) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION {
+                        self.subnegotiation.push(byte);
+                    }
+                    State::Sub
+                }
+                (State::SubCommand, SE) => {
+                    if !data.is_empty() {
+                        self.events.push_back(Event::Data(core::mem::take(&mut data)));
+                    }
+                    if let [NAWS, c1, c0, r1, r0] = self.subnegotiation[..] {
+                        let size = TermSize { rows: u16::from_be_bytes([r1, r0]), cols: u16::from_be_bytes([c1, c0]) };
+                        self.events.push_back(Event::Resize(size));
+                    }
+                    State::Data
+                }
+                (State::SubCommand, _) => State::Sub,
+            };
+        }
+        if !data.is_empty() {
+            self.events.push_back(Event::Data(data));
+        }
+        if replies.is_empty() {
+            Ok(())
+        } else {
+            self.transport.write_all(&replies)
+        }
+    }
+}
+
+/// The reply to the client's WILL/WONT/DO/DONT `option`, if one is due:
+/// refuse what the server didn't offer or ask for, and stay quiet about
+/// the rest so negotiation can't loop
+fn answer(verb: u8, option: u8) -> Option<u8> {
+    match (verb, option) {
+        (DO, ECHO | SGA) | (WILL, SGA | NAWS) => None,
+        (DO, _) => Some(WONT),
+        (WILL, _) => Some(DONT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    /// Input fed in pieces, output collected
+    struct Script {
+        input: VecDeque<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Transport for Script {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let Some(chunk) = self.input.pop_front() else { return Ok(0) };
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+
+        /// An empty chunk is a moment with nothing to read
+        fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+            match self.input.front() {
+                Some(chunk) if chunk.is_empty() => {
+                    self.input.pop_front();
+                    Ok(None)
+                }
+                _ => self.read(buf).map(Some),
+            }
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.output.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    fn session(input: &[&[u8]]) -> Session<Script> {
+        let script = Script { input: input.iter().map(|c| c.to_vec()).collect(), output: Vec::new() };
+        let mut session = Session::accept(script).unwrap();
+        session.transport.output.clear();
+        session
+    }
+
+    #[test]
+    fn test_commands_stripped_from_data() {
+        // Commands split across reads, CR LF and CR NUL, an escaped 0xFF
+        // and Interrupt Process
+        let mut s = session(&[b"ls\r\n", &[b'a', IAC], &[IAC, b'b', b'\r', 0, IAC], &[IP]]);
+        assert_eq!(s.recv_event(), Ok(Event::Data(b"ls\r".to_vec())));
+        assert_eq!(s.recv_event(), Ok(Event::Data(b"a".to_vec())));
+        assert_eq!(s.recv_event(), Ok(Event::Data(vec![IAC, b'b', b'\r'])));
+        assert_eq!(s.recv_event(), Ok(Event::Data(vec![0x03])));
+        assert_eq!(s.recv_event(), Err(Error::Closed));
+
+        s.send_data(&[b'x', IAC, b'y']).unwrap();
+        assert_eq!(s.transport.output, [b'x', IAC, IAC, b'y']);
+    }
+
+    #[test]
+    fn test_negotiation_and_window_size() {
+        let mut s = session(&[
+            &[IAC, DO, ECHO, IAC, WILL, NAWS, IAC, DO, 24, IAC, WILL, 39],
+            &[IAC, SB, NAWS, 0, 132, 0],
+            &[50, IAC, SE, b'q'],
+        ]);
+        assert_eq!(s.recv_event(), Ok(Event::Resize(TermSize { rows: 50, cols: 132 })));
+        assert_eq!(s.recv_event(), Ok(Event::Data(b"q".to_vec())));
+        // Terminal type and new-environ refused, the rest accepted silently
+        assert_eq!(s.transport.output, [IAC, WONT, 24, IAC, DONT, 39]);
+    }
+
+    #[test]
+    fn test_poll_event() {
+        let mut s = session(&[b"a", b"", &[IAC], b"", &[IP], b"b"]);
+        assert_eq!(s.poll_event(), Ok(Some(Event::Data(b"a".to_vec()))));
+        assert_eq!(s.poll_event(), Ok(None));
+        // A command cut in two waits for its second half
+        assert_eq!(s.poll_event(), Ok(None));
+        assert_eq!(s.poll_event(), Ok(Some(Event::Data(vec![0x03]))));
+        assert_eq!(s.poll_event(), Ok(Some(Event::Data(b"b".to_vec()))));
+        assert_eq!(s.poll_event(), Err(Error::Closed));
+    }
+}
@@ -0,0 +1,630 @@
+//! The client handshake and the application data stream
+
+use alloc::vec::Vec;
+
+use watos_crypto::ecdsa::Curve;
+use watos_crypto::hash::HashAlg;
+use watos_crypto::{ct_eq, wipe, x25519, Sha256};
+use watos_time::Timestamp;
+
+use crate::codec::{put_u16, put_vec, Reader};
+use crate::keys::{self, Secret};
+use crate::record::{self, CipherSuite, RecordKeys, RecordLayer};
+use crate::roots::RootStore;
+use crate::x509::{self, PublicKey, Scheme};
+use crate::{Error, Transport};
+
+// Handshake message types
+const HELLO_REQUEST: u8 = 0;
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const NEW_SESSION_TICKET: u8 = 4;
+const ENCRYPTED_EXTENSIONS: u8 = 8;
+const CERTIFICATE: u8 = 11;
+const SERVER_KEY_EXCHANGE: u8 = 12;
+const CERTIFICATE_REQUEST: u8 = 13;
+const SERVER_HELLO_DONE: u8 = 14;
+const CERTIFICATE_VERIFY: u8 = 15;
+const CLIENT_KEY_EXCHANGE: u8 = 16;
+const FINISHED: u8 = 20;
+const KEY_UPDATE: u8 = 24;
+
+// Extensions
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXT_EXTENDED_MASTER_SECRET: u16 = 23;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_KEY_SHARE: u16 = 51;
+const EXT_RENEGOTIATION_INFO: u16 = 0xff01;
+
+const GROUP_X25519: u16 = 0x001d;
+const CURVE_TYPE_NAMED: u8 = 3;
+const TLS12: u16 = 0x0303;
+const TLS13: u16 = 0x0304;
+
+const ALERT_CLOSE_NOTIFY: u8 = 0;
+const ALERT_NO_RENEGOTIATION: u8 = 100;
+
+/// End of ServerHello.random from a TLS 1.3 server answering with TLS 1.2,
+/// which means someone removed 1.3 from our ClientHello (RFC 8446 4.1.3)
+const DOWNGRADE_TLS12: [u8; 8] = *b"DOWNGRD\x01";
+
+/// Signature schemes offered, in order of preference
+const SIGNATURE_SCHEMES: [u16; 8] = [0x0403, 0x0503, 0x0804, 0x0805, 0x0806, 0x0401, 0x0501, 0x0601];
+
+/// ServerHello.random of a HelloRetryRequest: SHA-256("HelloRetryRequest")
+const HELLO_RETRY_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// What a connection needs to know to check the server
+pub struct ClientConfig<'a> {
+    /// Host name to send (SNI) and to find in the certificate
+    pub server_name: &'a str,
+    pub roots: &'a RootStore,
+    /// Current time, for certificate validity
+    pub now: Timestamp,
+}
+
+/// Reassembles handshake messages from records
+struct Handshake {
+    buf: Vec<u8>,
+}
+
+impl Handshake {
+    /// Next handshake message, header included
+    fn next<T: Transport>(&mut self, records: &mut RecordLayer<T>) -> Result<Vec<u8>, Error> {
+        loop {
+            if self.buf.len() >= 4 {
+                let len = Reader::new(&self.buf[1..4]).u24()?;
+                if self.buf.len() >= 4 + len {
+                    return Ok(self.buf.drain(..4 + len).collect());
+                }
+            }
+            match records.read()? {
+                (record::HANDSHAKE, data) if !data.is_empty() => self.buf.extend_from_slice(&data),
+                (record::ALERT, data) => return Err(alert_error(&data)),
+                _ => return Err(Error::UnexpectedMessage),
+            }
+        }
+    }
+
+    /// Next message, which must be of type `msg_type`
+    fn expect<T: Transport>(&mut self, records: &mut RecordLayer<T>, msg_type: u8) -> Result<Vec<u8>, Error> {
+        let msg = self.next(records)?;
+        if msg[0] != msg_type {
+            return Err(Error::UnexpectedMessage);
+        }
+        Ok(msg)
+    }
+}
+
+fn alert_error(data: &[u8]) -> Error {
+    match data {
+        [_, ALERT_CLOSE_NOTIFY] => Error::Closed,
+        [_, description] => Error::Alert(*description),
+        _ => Error::Decode,
+    }
+}
+
+fn hash_of(transcript: &Sha256) -> Secret {
+    transcript.clone().finish()
+}
+
+/// Wrap a handshake message body in its header
+fn handshake_message(msg_type: u8, body: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut msg = alloc::vec![msg_type];
+    put_vec(&mut msg, 3, body);
+    msg
+}
+
+/// A TLS connection that has completed its handshake
+pub struct TlsStream<T: Transport> {
+    records: RecordLayer<T>,
+    suite: CipherSuite,
+    /// Current read and write traffic secrets, kept for KeyUpdate; None
+    /// under TLS 1.2, which has no KeyUpdate
+    secrets: Option<(Secret, Secret)>,
+    handshake: Handshake,
+    /// Decrypted application data not yet returned by `read`
+    pending: Vec<u8>,
+    /// The server sent close_notify
+    closed: bool,
+}
+
+impl<T: Transport> TlsStream<T> {
+    /// Run the handshake over `transport`. `random` fills buffers with
+    /// unpredictable bytes (the ephemeral key comes from it).
+    pub fn connect(transport: T, config: &ClientConfig, random: &mut dyn FnMut(&mut [u8])) -> Result<Self, Error> {
+        let mut records = RecordLayer::new(transport);
+        match Self::handshake(&mut records, config, random) {
+            Ok((suite, secrets)) => Ok(TlsStream {
+                records,
+                suite,
+                secrets,
+                handshake: Handshake { buf: Vec::new() },
+                pending: Vec::new(),
+                closed: false,
+            }),
+            Err(e) => {
+                if let Some(description) = e.alert() {
+                    let _ = records.write(record::ALERT, &[2, description]);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the cipher suite and, for TLS 1.3, the server and client
+    /// application traffic secrets
+    fn handshake(
+        records: &mut RecordLayer<T>,
+        config: &ClientConfig,
+        random: &mut dyn FnMut(&mut [u8]),
+    ) -> Result<(CipherSuite, Option<(Secret, Secret)>), Error> {
+        let mut private = [0u8; x25519::KEY_LEN];
+        random(&mut private);
+        let mut client_random = [0u8; 32];
+        random(&mut client_random);
+        let mut session_id = [0u8; 32];
+        random(&mut session_id);
+
+        let hello = client_hello(config.server_name, &client_random, &session_id, &x25519::public_key(&private));
+        let mut transcript = Sha256::new();
+        transcript.update(&hello);
+        records.write(record::HANDSHAKE, &hello)?;
+
+        let mut hs = Handshake { buf: Vec::new() };
+        let msg = hs.expect(records, SERVER_HELLO)?;
+        let hello = parse_server_hello(&msg[4..], &session_id)?;
+        transcript.update(&msg);
+        let suite = hello.suite;
+        if suite.is_tls12() {
+            let result = handshake12(records, config, &mut hs, transcript, &private, &client_random, &hello);
+            wipe(&mut private);
+            return result.map(|()| (suite, None));
+        }
+        let server_share = hello.share.ok_or(Error::IllegalParameter)?;
+        if !hs.buf.is_empty() {
+            // Nothing may follow ServerHello under the old keys
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let mut shared = x25519::x25519(&private, &server_share);
+        wipe(&mut private);
+        if shared.iter().all(|&b| b == 0) {
+            return Err(Error::IllegalParameter);
+        }
+        let (client_hs, server_hs, master) = keys::handshake_secrets(&shared, &hash_of(&transcript));
+        wipe(&mut shared);
+        records.read_keys = Some(RecordKeys::new(suite, &server_hs));
+
+        hs.expect(records, ENCRYPTED_EXTENSIONS).map(|m| transcript.update(&m))?;
+
+        // Optional CertificateRequest, then the server's chain
+        let mut msg = hs.next(records)?;
+        let mut cert_request_context = None;
+        if msg[0] == CERTIFICATE_REQUEST {
+            let mut r = Reader::new(&msg[4..]);
+            cert_request_context = Some(r.vec8()?.to_vec());
+            transcript.update(&msg);
+            msg = hs.next(records)?;
+        }
+        if msg[0] != CERTIFICATE {
+            return Err(Error::UnexpectedMessage);
+        }
+        let cert_msg = msg;
+        transcript.update(&cert_msg);
+        let chain = parse_certificate(&cert_msg[4..])?;
+        let leaf = x509::verify_chain(&chain, config.roots, config.server_name, config.now)?;
+
+        // CertificateVerify: signed over the transcript so far
+        let msg = hs.expect(records, CERTIFICATE_VERIFY)?;
+        let mut r = Reader::new(&msg[4..]);
+        let scheme = r.u16()?;
+        let signature = r.vec16()?;
+        r.finish()?;
+        let scheme = verify_scheme(scheme, &leaf.key, false)?;
+        let mut signed = Vec::with_capacity(64 + 34 + 32);
+        signed.extend_from_slice(&[0x20; 64]);
+        signed.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+        signed.extend_from_slice(&hash_of(&transcript));
+        if !leaf.key.verify(scheme, &signed, signature) {
+            return Err(Error::BadSignature);
+        }
+        transcript.update(&msg);
+
+        let msg = hs.expect(records, FINISHED)?;
+        let expected = keys::finished(&server_hs, &hash_of(&transcript));
+        if !ct_eq(&msg[4..], &expected) {
+            return Err(Error::BadFinished);
+        }
+        transcript.update(&msg);
+        if !hs.buf.is_empty() {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let server_hash = hash_of(&transcript);
+        let client_ap = keys::derive_secret(&master, b"c ap traffic", &server_hash);
+        let server_ap = keys::derive_secret(&master, b"s ap traffic", &server_hash);
+
+        // Middlebox compatibility: a dummy ChangeCipherSpec before our
+        // first protected record
+        records.write(record::CHANGE_CIPHER_SPEC, &[1])?;
+        records.write_keys = Some(RecordKeys::new(suite, &client_hs));
+
+        // No client certificate, if one was asked for
+        if let Some(context) = cert_request_context {
+            let msg = handshake_message(CERTIFICATE, |out| {
+                out.push(context.len() as u8);
+                out.extend_from_slice(&context);
+                out.extend_from_slice(&[0, 0, 0]);
+            });
+            transcript.update(&msg);
+            records.write(record::HANDSHAKE, &msg)?;
+        }
+
+        let verify_data = keys::finished(&client_hs, &hash_of(&transcript));
+        records.write(record::HANDSHAKE, &handshake_message(FINISHED, |out| out.extend_from_slice(&verify_data)))?;
+
+        records.read_keys = Some(RecordKeys::new(suite, &server_ap));
+        records.write_keys = Some(RecordKeys::new(suite, &client_ap));
+        Ok((suite, Some((server_ap, client_ap))))
+    }
+
+    /// Read application data into `buf`; `Ok(0)` once the server has
+    /// closed the connection
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.pending.is_empty() {
+            if self.closed {
+                return Ok(0);
+            }
+            match self.records.read() {
+                Ok((record::APPLICATION_DATA, data)) => self.pending = data,
+                Ok((record::HANDSHAKE, data)) => {
+                    self.handshake.buf.extend_from_slice(&data);
+                    self.post_handshake()?;
+                }
+                Ok((record::ALERT, data)) => match alert_error(&data) {
+                    Error::Closed => self.closed = true,
+                    e => return Err(e),
+                },
+                Ok(_) => return Err(Error::UnexpectedMessage),
+                Err(e) => return Err(e),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+
+    /// Send application data
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.records.write(record::APPLICATION_DATA, data)
+    }
+
+    /// Send close_notify and hand back the transport
+    pub fn close(mut self) -> Result<T, Error> {
+        self.records.write(record::ALERT, &[1, ALERT_CLOSE_NOTIFY])?;
+        Ok(self.records.transport)
+    }
+
+    /// Handle whole messages sent after the handshake: session tickets
+    /// (ignored) and key updates, or under TLS 1.2 requests to renegotiate
+    /// (declined)
+    fn post_handshake(&mut self) -> Result<(), Error> {
+        while self.handshake.buf.len() >= 4 {
+            let len = Reader::new(&self.handshake.buf[1..4]).u24()?;
+            if self.handshake.buf.len() < 4 + len {
+                break;
+            }
+            let msg: Vec<u8> = self.handshake.buf.drain(..4 + len).collect();
+            match (msg[0], &msg[4..], &mut self.secrets) {
+                (NEW_SESSION_TICKET, _, Some(_)) => {}
+                (KEY_UPDATE, [requested @ (0 | 1)], Some((read_secret, write_secret))) => {
+                    *read_secret = keys::next_traffic_secret(read_secret);
+                    self.records.read_keys = Some(RecordKeys::new(self.suite, read_secret));
+                    if *requested == 1 {
+                        let update = handshake_message(KEY_UPDATE, |out| out.push(0));
+                        self.records.write(record::HANDSHAKE, &update)?;
+                        *write_secret = keys::next_traffic_secret(write_secret);
+                        self.records.write_keys = Some(RecordKeys::new(self.suite, write_secret));
+                    }
+                }
+                (KEY_UPDATE, _, Some(_)) => return Err(Error::Decode),
+                (HELLO_REQUEST, [], None) => self.records.write(record::ALERT, &[1, ALERT_NO_RENEGOTIATION])?,
+                _ => return Err(Error::UnexpectedMessage),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The TLS 1.2 handshake after ServerHello (RFC 5246 section 7.3, with
+/// ECDHE from RFC 8422): check the chain and the signed key exchange,
+/// send ours, then swap Finished messages. Leaves the record layer
+/// protected both ways.
+fn handshake12<T: Transport>(
+    records: &mut RecordLayer<T>,
+    config: &ClientConfig,
+    hs: &mut Handshake,
+    mut transcript: Sha256,
+    private: &[u8; x25519::KEY_LEN],
+    client_random: &[u8; 32],
+    hello: &ServerHello,
+) -> Result<(), Error> {
+    let suite = hello.suite;
+
+    let msg = hs.expect(records, CERTIFICATE)?;
+    transcript.update(&msg);
+    let chain = parse_certificate12(&msg[4..])?;
+    let leaf = x509::verify_chain(&chain, config.roots, config.server_name, config.now)?;
+    if matches!(leaf.key, PublicKey::Rsa { .. }) != suite.signs_with_rsa() {
+        return Err(Error::IllegalParameter);
+    }
+
+    // ServerKeyExchange: the server's share, signed together with both
+    // hello randoms
+    let msg = hs.expect(records, SERVER_KEY_EXCHANGE)?;
+    transcript.update(&msg);
+    let body = &msg[4..];
+    let mut r = Reader::new(body);
+    if r.u8()? != CURVE_TYPE_NAMED || r.u16()? != GROUP_X25519 {
+        return Err(Error::IllegalParameter);
+    }
+    let server_share = <[u8; 32]>::try_from(r.vec8()?).map_err(|_| Error::IllegalParameter)?;
+    let params = &body[..4 + server_share.len()];
+    let scheme = verify_scheme(r.u16()?, &leaf.key, true)?;
+    let signature = r.vec16()?;
+    r.finish()?;
+    let mut signed = Vec::with_capacity(64 + params.len());
+    signed.extend_from_slice(client_random);
+    signed.extend_from_slice(&hello.random);
+    signed.extend_from_slice(params);
+    if !leaf.key.verify(scheme, &signed, signature) {
+        return Err(Error::BadSignature);
+    }
+
+    // Optional CertificateRequest, then ServerHelloDone
+    let mut msg = hs.next(records)?;
+    let cert_requested = msg[0] == CERTIFICATE_REQUEST;
+    if cert_requested {
+        transcript.update(&msg);
+        msg = hs.next(records)?;
+    }
+    if msg[0] != SERVER_HELLO_DONE || msg.len() != 4 || !hs.buf.is_empty() {
+        return Err(Error::UnexpectedMessage);
+    }
+    transcript.update(&msg);
+
+    let mut shared = x25519::x25519(private, &server_share);
+    if shared.iter().all(|&b| b == 0) {
+        return Err(Error::IllegalParameter);
+    }
+
+    // No client certificate, if one was asked for
+    if cert_requested {
+        let msg = handshake_message(CERTIFICATE, |out| out.extend_from_slice(&[0, 0, 0]));
+        transcript.update(&msg);
+        records.write(record::HANDSHAKE, &msg)?;
+    }
+    let msg = handshake_message(CLIENT_KEY_EXCHANGE, |out| {
+        put_vec(out, 1, |out| out.extend_from_slice(&x25519::public_key(private)))
+    });
+    transcript.update(&msg);
+    records.write(record::HANDSHAKE, &msg)?;
+
+    let session_hash = hello.extended_master_secret.then(|| hash_of(&transcript));
+    let mut master = keys::master_secret12(&shared, session_hash.as_ref(), client_random, &hello.random);
+    wipe(&mut shared);
+
+    // Key block: client key, server key, client IV, server IV
+    let (key_len, iv_len) = (suite.key_len(), suite.fixed_iv_len());
+    let mut block = [0u8; 2 * (32 + 12)];
+    let block = &mut block[..2 * (key_len + iv_len)];
+    keys::prf(&master, b"key expansion", &[&hello.random, client_random], block);
+    let (client_key, rest) = block.split_at(key_len);
+    let (server_key, rest) = rest.split_at(key_len);
+    let (client_iv, server_iv) = rest.split_at(iv_len);
+    records.write(record::CHANGE_CIPHER_SPEC, &[1])?;
+    records.write_keys = Some(RecordKeys::tls12(suite, client_key, client_iv));
+    records.pending_read_keys = Some(RecordKeys::tls12(suite, server_key, server_iv));
+    wipe(block);
+
+    let verify_data = keys::finished12(&master, b"client finished", &hash_of(&transcript));
+    let msg = handshake_message(FINISHED, |out| out.extend_from_slice(&verify_data));
+    transcript.update(&msg);
+    records.write(record::HANDSHAKE, &msg)?;
+
+    let msg = hs.expect(records, FINISHED)?;
+    // It must have come after the server's ChangeCipherSpec, under its keys
+    if records.pending_read_keys.is_some() || !hs.buf.is_empty() {
+        return Err(Error::UnexpectedMessage);
+    }
+    let expected = keys::finished12(&master, b"server finished", &hash_of(&transcript));
+    wipe(&mut master);
+    if !ct_eq(&msg[4..], &expected) {
+        return Err(Error::BadFinished);
+    }
+    Ok(())
+}
+
+fn client_hello(server_name: &str, random: &[u8; 32], session_id: &[u8; 32], share: &[u8; 32]) -> Vec<u8> {
+    handshake_message(CLIENT_HELLO, |out| {
+        put_u16(out, 0x0303);
+        out.extend_from_slice(random);
+        put_vec(out, 1, |out| out.extend_from_slice(session_id));
+        put_vec(out, 2, |out| {
+            for suite in CipherSuite::OFFERED {
+                put_u16(out, suite.id());
+            }
+        });
+        out.extend_from_slice(&[1, 0]);
+
+        put_vec(out, 2, |out| {
+            put_u16(out, EXT_SERVER_NAME);
+            put_vec(out, 2, |out| {
+                put_vec(out, 2, |out| {
+                    out.push(0);
+                    put_vec(out, 2, |out| out.extend_from_slice(server_name.as_bytes()));
+                })
+            });
+
+            put_u16(out, EXT_SUPPORTED_GROUPS);
+            put_vec(out, 2, |out| put_vec(out, 2, |out| put_u16(out, GROUP_X25519)));
+
+            put_u16(out, EXT_SIGNATURE_ALGORITHMS);
+            put_vec(out, 2, |out| {
+                put_vec(out, 2, |out| {
+                    for scheme in SIGNATURE_SCHEMES {
+                        put_u16(out, scheme);
+                    }
+                })
+            });
+
+            put_u16(out, EXT_SUPPORTED_VERSIONS);
+            put_vec(out, 2, |out| {
+                put_vec(out, 1, |out| {
+                    put_u16(out, TLS13);
+                    put_u16(out, TLS12);
+                })
+            });
+
+            // For TLS 1.2 servers: uncompressed points only, the extended
+            // master secret, and secure renegotiation (with nothing to
+            // renegotiate)
+            put_u16(out, EXT_EC_POINT_FORMATS);
+            put_vec(out, 2, |out| put_vec(out, 1, |out| out.push(0)));
+            put_u16(out, EXT_EXTENDED_MASTER_SECRET);
+            put_u16(out, 0);
+            put_u16(out, EXT_RENEGOTIATION_INFO);
+            put_vec(out, 2, |out| out.push(0));
+
+            put_u16(out, EXT_KEY_SHARE);
+            put_vec(out, 2, |out| {
+                put_vec(out, 2, |out| {
+                    put_u16(out, GROUP_X25519);
+                    put_vec(out, 2, |out| out.extend_from_slice(share));
+                })
+            });
+        });
+    })
+}
+
+/// What the server chose
+struct ServerHello {
+    suite: CipherSuite,
+    random: [u8; 32],
+    /// X25519 key share (TLS 1.3)
+    share: Option<[u8; 32]>,
+    /// The server agreed to the extended master secret (TLS 1.2)
+    extended_master_secret: bool,
+}
+
+/// Check a ServerHello answers our ClientHello with TLS 1.3 and X25519,
+/// or with TLS 1.2 and one of its suites
+fn parse_server_hello(body: &[u8], session_id: &[u8]) -> Result<ServerHello, Error> {
+    let mut r = Reader::new(body);
+    if r.u16()? != TLS12 {
+        return Err(Error::Unsupported);
+    }
+    let random = <[u8; 32]>::try_from(r.bytes(32)?).map_err(|_| Error::Decode)?;
+    if random == HELLO_RETRY_RANDOM {
+        // Only sent to ask for a group other than X25519
+        return Err(Error::Unsupported);
+    }
+    let echoed_session_id = r.vec8()?;
+    let suite = CipherSuite::from_id(r.u16()?).ok_or(Error::IllegalParameter)?;
+    if r.u8()? != 0 {
+        return Err(Error::IllegalParameter);
+    }
+
+    let mut version = None;
+    let mut share = None;
+    let mut extended_master_secret = false;
+    let mut exts = Reader::new(r.vec16()?);
+    r.finish()?;
+    while !exts.is_empty() {
+        let ext_type = exts.u16()?;
+        let mut data = Reader::new(exts.vec16()?);
+        match ext_type {
+            EXT_SUPPORTED_VERSIONS => version = Some(data.u16()?),
+            EXT_KEY_SHARE => {
+                if data.u16()? != GROUP_X25519 {
+                    return Err(Error::IllegalParameter);
+                }
+                let key = data.vec16()?;
+                share = Some(<[u8; 32]>::try_from(key).map_err(|_| Error::IllegalParameter)?);
+            }
+            EXT_EXTENDED_MASTER_SECRET => extended_master_secret = true,
+            _ => continue,
+        }
+        data.finish()?;
+    }
+
+    match version {
+        Some(TLS13) => {
+            if suite.is_tls12() || echoed_session_id != session_id || share.is_none() {
+                return Err(Error::IllegalParameter);
+            }
+        }
+        // A TLS 1.2 server sends no supported_versions. Echoing our
+        // session id would mean resuming a session we never had.
+        None => {
+            if !suite.is_tls12() || random[24..] == DOWNGRADE_TLS12 || echoed_session_id == session_id {
+                return Err(Error::IllegalParameter);
+            }
+        }
+        Some(_) => return Err(Error::IllegalParameter),
+    }
+    Ok(ServerHello { suite, random, share, extended_master_secret })
+}
+
+/// The DER certificates in a Certificate message body
+fn parse_certificate(body: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let mut r = Reader::new(body);
+    if !r.vec8()?.is_empty() {
+        return Err(Error::IllegalParameter);
+    }
+    let mut list = Reader::new(r.vec24()?);
+    r.finish()?;
+    let mut chain = Vec::new();
+    while !list.is_empty() {
+        chain.push(list.vec24()?);
+        list.vec16()?;
+    }
+    Ok(chain)
+}
+
+/// The DER certificates in a TLS 1.2 Certificate message body
+fn parse_certificate12(body: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let mut r = Reader::new(body);
+    let mut list = Reader::new(r.vec24()?);
+    r.finish()?;
+    let mut chain = Vec::new();
+    while !list.is_empty() {
+        chain.push(list.vec24()?);
+    }
+    Ok(chain)
+}
+
+/// How to check a CertificateVerify (or TLS 1.2 ServerKeyExchange)
+/// signature scheme with the leaf's key; PKCS #1 v1.5 is only allowed in
+/// TLS 1.2
+fn verify_scheme(scheme: u16, key: &PublicKey, tls12: bool) -> Result<Scheme, Error> {
+    Ok(match (scheme, key) {
+        (0x0401, PublicKey::Rsa { .. }) if tls12 => Scheme::RsaPkcs1(HashAlg::Sha256),
+        (0x0501, PublicKey::Rsa { .. }) if tls12 => Scheme::RsaPkcs1(HashAlg::Sha384),
+        (0x0601, PublicKey::Rsa { .. }) if tls12 => Scheme::RsaPkcs1(HashAlg::Sha512),
+        (0x0403, PublicKey::Ec { curve: Curve::P256, .. }) => Scheme::Ecdsa(HashAlg::Sha256),
+        (0x0503, PublicKey::Ec { curve: Curve::P384, .. }) => Scheme::Ecdsa(HashAlg::Sha384),
+        (0x0804, PublicKey::Rsa { .. }) => Scheme::RsaPss(HashAlg::Sha256),
+        (0x0805, PublicKey::Rsa { .. }) => Scheme::RsaPss(HashAlg::Sha384),
+        (0x0806, PublicKey::Rsa { .. }) => Scheme::RsaPss(HashAlg::Sha512),
+        _ => return Err(Error::IllegalParameter),
+    })
+}
@@ -0,0 +1,85 @@
+//! Reading and writing the TLS presentation language: big-endian
+//! integers and length-prefixed vectors
+
+use alloc::vec::Vec;
+
+use crate::Error;
+
+/// Cursor over a received message
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.data.len() {
+            return Err(Error::Decode);
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u24(&mut self) -> Result<usize, Error> {
+        let b = self.bytes(3)?;
+        Ok((b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    /// A vector with a one-byte length
+    pub fn vec8(&mut self) -> Result<&'a [u8], Error> {
+        let n = self.u8()? as usize;
+        self.bytes(n)
+    }
+
+    /// A vector with a two-byte length
+    pub fn vec16(&mut self) -> Result<&'a [u8], Error> {
+        let n = self.u16()? as usize;
+        self.bytes(n)
+    }
+
+    /// A vector with a three-byte length
+    pub fn vec24(&mut self) -> Result<&'a [u8], Error> {
+        let n = self.u24()?;
+        self.bytes(n)
+    }
+
+    /// Fail unless everything has been read
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Decode)
+        }
+    }
+}
+
+pub fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+/// Append a vector whose length prefix (of `len_bytes` bytes) is filled in
+/// after `body` has written it
+pub fn put_vec(out: &mut Vec<u8>, len_bytes: usize, body: impl FnOnce(&mut Vec<u8>)) {
+    let at = out.len();
+    out.resize(at + len_bytes, 0);
+    body(out);
+    let len = (out.len() - at - len_bytes) as u32;
+    out[at..at + len_bytes].copy_from_slice(&len.to_be_bytes()[4 - len_bytes..]);
+}
@@ -0,0 +1,99 @@
+//! Reading DER (X.690), as much of it as certificates use
+
+use crate::x509::CertError;
+
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OID: u8 = 0x06;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const SEQUENCE: u8 = 0x30;
+
+/// Context-specific constructed tag `[n]`
+pub const fn explicit(n: u8) -> u8 {
+    0xa0 | n
+}
+
+/// Context-specific primitive tag `[n]`
+pub const fn implicit(n: u8) -> u8 {
+    0x80 | n
+}
+
+/// Cursor over a run of DER elements
+#[derive(Clone, Copy)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Next element: its tag, its contents, and the whole encoding
+    pub fn any(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), CertError> {
+        let d = self.data;
+        if d.len() < 2 || d[0] & 0x1f == 0x1f {
+            return Err(CertError::Malformed);
+        }
+        let (len, header) = match d[1] {
+            n if n < 0x80 => (n as usize, 2),
+            0x81 => (*d.get(2).ok_or(CertError::Malformed)? as usize, 3),
+            0x82 if d.len() >= 4 => ((d[2] as usize) << 8 | d[3] as usize, 4),
+            0x83 if d.len() >= 5 => ((d[2] as usize) << 16 | (d[3] as usize) << 8 | d[4] as usize, 5),
+            _ => return Err(CertError::Malformed),
+        };
+        let end = header + len;
+        if end > d.len() {
+            return Err(CertError::Malformed);
+        }
+        self.data = &d[end..];
+        Ok((d[0], &d[header..end], &d[..end]))
+    }
+
+    /// Contents of the next element, which must have this tag
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], CertError> {
+        match self.any()? {
+            (t, contents, _) if t == tag => Ok(contents),
+            _ => Err(CertError::Malformed),
+        }
+    }
+
+    /// Contents of the next element if it has this tag
+    pub fn optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, CertError> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// A positive INTEGER's magnitude, without the sign byte
+    pub fn unsigned(&mut self) -> Result<&'a [u8], CertError> {
+        let int = self.read(INTEGER)?;
+        match int {
+            [] => Err(CertError::Malformed),
+            [b, ..] if b & 0x80 != 0 => Err(CertError::Malformed),
+            [0, rest @ ..] if !rest.is_empty() => Ok(rest),
+            _ => Ok(int),
+        }
+    }
+
+    /// A BIT STRING with no unused bits
+    pub fn bit_string(&mut self) -> Result<&'a [u8], CertError> {
+        match self.read(BIT_STRING)? {
+            [0, bits @ ..] => Ok(bits),
+            _ => Err(CertError::Malformed),
+        }
+    }
+}
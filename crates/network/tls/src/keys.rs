@@ -0,0 +1,148 @@
+//! The TLS 1.3 key schedule (RFC 8446 section 7), and the TLS 1.2 PRF
+//! (RFC 5246 section 5), for SHA-256 suites
+
+use watos_crypto::sha256::DIGEST_LEN;
+use watos_crypto::{hkdf_expand, hkdf_extract, hmac_sha256, HmacSha256, Sha256};
+
+/// A secret or transcript hash
+pub type Secret = [u8; DIGEST_LEN];
+
+/// HKDF-Expand-Label(secret, label, context, out.len())
+pub fn expand_label(secret: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut info = [0u8; 2 + 1 + 6 + 32 + 1 + 64];
+    let mut n = 0;
+    for part in [
+        &(out.len() as u16).to_be_bytes()[..],
+        &[(6 + label.len()) as u8],
+        b"tls13 ",
+        label,
+        &[context.len() as u8],
+        context,
+    ] {
+        info[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    }
+    hkdf_expand(secret, &info[..n], out);
+}
+
+/// Derive-Secret(secret, label, messages) given the messages' hash
+pub fn derive_secret(secret: &Secret, label: &[u8], transcript: &Secret) -> Secret {
+    let mut out = [0u8; DIGEST_LEN];
+    expand_label(secret, label, transcript, &mut out);
+    out
+}
+
+/// Handshake traffic secrets from the (EC)DHE shared secret and the hash
+/// of ClientHello..ServerHello; also returns the master secret
+pub fn handshake_secrets(shared: &[u8], hello_hash: &Secret) -> (Secret, Secret, Secret) {
+    let early = hkdf_extract(&[0u8; DIGEST_LEN], &[0u8; DIGEST_LEN]);
+    let empty = Sha256::digest(b"");
+    let handshake = hkdf_extract(&derive_secret(&early, b"derived", &empty), shared);
+    let client = derive_secret(&handshake, b"c hs traffic", hello_hash);
+    let server = derive_secret(&handshake, b"s hs traffic", hello_hash);
+    let master = hkdf_extract(&derive_secret(&handshake, b"derived", &empty), &[0u8; DIGEST_LEN]);
+    (client, server, master)
+}
+
+/// verify_data of a Finished message sent under `traffic_secret`
+pub fn finished(traffic_secret: &Secret, transcript: &Secret) -> Secret {
+    let mut key = [0u8; DIGEST_LEN];
+    expand_label(traffic_secret, b"finished", b"", &mut key);
+    hmac_sha256(&key, transcript)
+}
+
+/// The next traffic secret after a KeyUpdate
+pub fn next_traffic_secret(secret: &Secret) -> Secret {
+    let mut out = [0u8; DIGEST_LEN];
+    expand_label(secret, b"traffic upd", b"", &mut out);
+    out
+}
+
+/// Length of a TLS 1.2 master secret
+pub const MASTER_SECRET_LEN: usize = 48;
+
+/// Length of TLS 1.2 Finished verify_data
+pub const VERIFY_DATA_LEN: usize = 12;
+
+/// PRF(secret, label, seed) filling `out`: P_SHA256 over label + seed
+pub fn prf(secret: &[u8], label: &[u8], seed: &[&[u8]], out: &mut [u8]) {
+    let hmac = |a: &[u8], with_seed: bool| {
+        let mut mac = HmacSha256::new(secret);
+        mac.update(a);
+        if with_seed {
+            mac.update(label);
+            seed.iter().for_each(|part| mac.update(part));
+        }
+        mac.finish()
+    };
+    // A(1) = HMAC(label + seed), A(i) = HMAC(A(i - 1))
+    let mut a = hmac(&[], true);
+    for chunk in out.chunks_mut(DIGEST_LEN) {
+        let block = hmac(&a, true);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        a = hmac(&a, false);
+    }
+}
+
+/// The TLS 1.2 master secret. With the extended master secret extension
+/// (RFC 7627) it is bound to the hash of the handshake up to
+/// ClientKeyExchange; otherwise to the two hello randoms.
+pub fn master_secret12(
+    pre_master: &[u8],
+    session_hash: Option<&Secret>,
+    client_random: &[u8; 32],
+    server_random: &[u8; 32],
+) -> [u8; MASTER_SECRET_LEN] {
+    let mut master = [0u8; MASTER_SECRET_LEN];
+    match session_hash {
+        Some(hash) => prf(pre_master, b"extended master secret", &[hash], &mut master),
+        None => prf(pre_master, b"master secret", &[client_random, server_random], &mut master),
+    }
+    master
+}
+
+/// verify_data of a TLS 1.2 Finished message; `label` is
+/// "client finished" or "server finished"
+pub fn finished12(master: &[u8; MASTER_SECRET_LEN], label: &[u8], transcript: &Secret) -> [u8; VERIFY_DATA_LEN] {
+    let mut out = [0u8; VERIFY_DATA_LEN];
+    prf(master, label, &[transcript], &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Secret {
+        let mut out = [0u8; DIGEST_LEN];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_handshake_secrets() {
+        // RFC 8448 section 3, "Simple 1-RTT Handshake"
+        let shared = unhex("8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d");
+        let hello_hash = unhex("860c06edc07858ee8e78f0e7428c58edd6b43f2ca3e6e95f02ed063cf0e1cad8");
+        let (client, server, _) = handshake_secrets(&shared, &hello_hash);
+        assert_eq!(client, unhex("b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21"));
+        assert_eq!(server, unhex("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38"));
+    }
+
+    #[test]
+    fn test_prf() {
+        // The widely used TLS 1.2 PRF-SHA256 vector (100 bytes of output)
+        let secret = [
+            0x9b, 0xbe, 0x43, 0x6b, 0xa9, 0x40, 0xf0, 0x17, 0xb1, 0x76, 0x52, 0x84, 0x9a, 0x71, 0xdb, 0x35,
+        ];
+        let seed = [
+            0xa0, 0xba, 0x9f, 0x93, 0x6c, 0xda, 0x31, 0x18, 0x27, 0xa6, 0xf7, 0x96, 0xff, 0xd5, 0x19, 0x8c,
+        ];
+        let mut out = [0u8; 100];
+        prf(&secret, b"test label", &[&seed], &mut out);
+        assert_eq!(out[..32], unhex("e3f229ba727be17b8d122620557cd453c2aab21d07c3d495329b52d4e61edb5a"));
+        assert_eq!(out[96..], [0x87, 0x34, 0x7b, 0x66]);
+    }
+}
@@ -0,0 +1,119 @@
+//! WATOS TLS Client
+//!
+//! A TLS 1.3 (RFC 8446) and TLS 1.2 (RFC 5246) client over any byte stream
+//! that implements [`Transport`]; [`TlsStream`] then reads and writes
+//! application data in the clear:
+//!
+//! ```ignore
+//! let roots = RootStore::load(ROOT_STORE_PATH)?;
+//! let config = ClientConfig { server_name: "example.com", roots: &roots, now };
+//! let mut tls = TlsStream::connect(tcp, &config, &mut rdrand_fill)?;
+//! tls.write(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")?;
+//! ```
+//!
+//! What the client offers:
+//!
+//! - TLS 1.3 cipher suites TLS_CHACHA20_POLY1305_SHA256 and
+//!   TLS_AES_128_GCM_SHA256
+//! - TLS 1.2 suites TLS_ECDHE_{ECDSA,RSA}_WITH_CHACHA20_POLY1305_SHA256 and
+//!   TLS_ECDHE_{ECDSA,RSA}_WITH_AES_128_GCM_SHA256, with the extended
+//!   master secret (RFC 7627) when the server agrees to it
+//! - X25519 key exchange only; a server that answers with a
+//!   HelloRetryRequest for another group is refused
+//! - server signatures: ECDSA P-256/P-384, RSA-PSS, and RSA PKCS #1 v1.5
+//!   in certificates and TLS 1.2 key exchanges
+//!
+//! TLS 1.3 is preferred. A 1.3 server that answers with 1.2 anyway is
+//! refused, since that means the ClientHello was tampered with.
+//!
+//! The server's chain is checked against a [`RootStore`] read from PEM
+//! (normally [`ROOT_STORE_PATH`]): signatures, validity periods, CA basic
+//! constraints on intermediates, and the host name against the leaf's
+//! subjectAltName DNS entries (there is no fallback to the common name and
+//! no IP address matching).
+//!
+//! Not supported: TLS 1.1 and earlier (servers fail with
+//! [`Error::Unsupported`]), TLS 1.2 without ECDHE, renegotiation (refused
+//! with a warning), session resumption and 0-RTT (tickets are ignored),
+//! client certificates (an empty one is sent if asked), ALPN, and
+//! revocation checks. Randomness comes from the caller.
+
+#![no_std]
+
+extern crate alloc;
+
+mod client;
+mod codec;
+pub mod der;
+mod keys;
+mod record;
+pub mod roots;
+pub mod x509;
+
+pub use client::{ClientConfig, TlsStream};
+pub use roots::{RootStore, ROOT_STORE_PATH};
+pub use x509::CertError;
+
+/// A reliable, ordered byte stream to carry TLS records (a TCP connection)
+pub trait Transport {
+    /// Read at least one byte into `buf`; `Ok(0)` at end of stream
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Write all of `buf`
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+/// Why a TLS operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The transport failed
+    Transport,
+    /// The transport ended in the middle of a record or the handshake
+    UnexpectedEof,
+    /// The server sent a fatal alert, with its description code
+    Alert(u8),
+    /// A record or handshake message was malformed
+    Decode,
+    /// A message arrived that wasn't allowed at that point
+    UnexpectedMessage,
+    /// A record failed decryption
+    BadRecordMac,
+    /// The server chose a value the client didn't offer
+    IllegalParameter,
+    /// The server needs something this client lacks (TLS 1.1 or older, a
+    /// key exchange group other than X25519)
+    Unsupported,
+    /// The server's certificate chain was rejected
+    Certificate(CertError),
+    /// The server's CertificateVerify signature was wrong
+    BadSignature,
+    /// The server's Finished message was wrong
+    BadFinished,
+    /// The connection has been closed
+    Closed,
+}
+
+impl Error {
+    /// The alert to send the server when failing with this error
+    fn alert(self) -> Option<u8> {
+        Some(match self {
+            Error::Transport | Error::UnexpectedEof | Error::Alert(_) | Error::Closed => return None,
+            Error::Decode => 50,
+            Error::UnexpectedMessage => 10,
+            Error::BadRecordMac => 20,
+            Error::IllegalParameter => 47,
+            Error::Unsupported => 40,
+            Error::Certificate(CertError::UnknownIssuer) => 48,
+            Error::Certificate(CertError::Expired) => 45,
+            Error::Certificate(CertError::Unsupported) => 43,
+            Error::Certificate(_) => 42,
+            Error::BadSignature | Error::BadFinished => 51,
+        })
+    }
+}
+
+impl From<CertError> for Error {
+    fn from(e: CertError) -> Self {
+        Error::Certificate(e)
+    }
+}
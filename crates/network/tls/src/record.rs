@@ -0,0 +1,398 @@
+//! The record layer: framing, and AEAD protection once keys are set
+//!
+//! TLS 1.3 records hide their real content type inside the ciphertext and
+//! take the nonce from the sequence number alone. TLS 1.2 AEAD records
+//! (RFC 5246 section 6.2.3.3) keep the content type in the header, put the
+//! sequence number in the additional data, and for AES-GCM carry an
+//! explicit nonce (RFC 5288); ChaCha20-Poly1305 builds its nonce the 1.3
+//! way (RFC 7905).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use watos_crypto::aead::{AesGcm, ChaCha20Poly1305, TAG_LEN};
+use watos_crypto::chacha20::NONCE_LEN;
+
+use crate::keys::{expand_label, Secret};
+use crate::{Error, Transport};
+
+pub const CHANGE_CIPHER_SPEC: u8 = 20;
+pub const ALERT: u8 = 21;
+pub const HANDSHAKE: u8 = 22;
+pub const APPLICATION_DATA: u8 = 23;
+
+/// Largest plaintext in one record
+pub const MAX_PLAINTEXT: usize = 1 << 14;
+
+/// Largest protected record body: plaintext, content type, padding and tag
+const MAX_CIPHERTEXT: usize = MAX_PLAINTEXT + 256;
+
+const HEADER_LEN: usize = 5;
+
+/// Length of the explicit nonce in a TLS 1.2 AES-GCM record
+const EXPLICIT_NONCE_LEN: usize = 8;
+
+/// A cipher suite: the TLS 1.3 ones, and TLS 1.2 ECDHE suites with the
+/// same AEADs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128GcmSha256,
+    ChaCha20Poly1305Sha256,
+    EcdheEcdsaAes128GcmSha256,
+    EcdheRsaAes128GcmSha256,
+    EcdheEcdsaChaCha20Poly1305Sha256,
+    EcdheRsaChaCha20Poly1305Sha256,
+}
+
+impl CipherSuite {
+    /// Suites offered, in order of preference
+    pub const OFFERED: [CipherSuite; 6] = [
+        CipherSuite::ChaCha20Poly1305Sha256,
+        CipherSuite::Aes128GcmSha256,
+        CipherSuite::EcdheEcdsaChaCha20Poly1305Sha256,
+        CipherSuite::EcdheRsaChaCha20Poly1305Sha256,
+        CipherSuite::EcdheEcdsaAes128GcmSha256,
+        CipherSuite::EcdheRsaAes128GcmSha256,
+    ];
+
+    pub fn id(self) -> u16 {
+        match self {
+            CipherSuite::Aes128GcmSha256 => 0x1301,
+            CipherSuite::ChaCha20Poly1305Sha256 => 0x1303,
+            CipherSuite::EcdheEcdsaAes128GcmSha256 => 0xc02b,
+            CipherSuite::EcdheRsaAes128GcmSha256 => 0xc02f,
+            CipherSuite::EcdheEcdsaChaCha20Poly1305Sha256 => 0xcca9,
+            CipherSuite::EcdheRsaChaCha20Poly1305Sha256 => 0xcca8,
+        }
+    }
+
+    pub fn from_id(id: u16) -> Option<Self> {
+        Self::OFFERED.into_iter().find(|s| s.id() == id)
+    }
+
+    /// A TLS 1.2 suite
+    pub fn is_tls12(self) -> bool {
+        !matches!(self, CipherSuite::Aes128GcmSha256 | CipherSuite::ChaCha20Poly1305Sha256)
+    }
+
+    /// A TLS 1.2 suite whose key exchange is signed with RSA
+    pub fn signs_with_rsa(self) -> bool {
+        matches!(self, CipherSuite::EcdheRsaAes128GcmSha256 | CipherSuite::EcdheRsaChaCha20Poly1305Sha256)
+    }
+
+    fn is_chacha(self) -> bool {
+        matches!(
+            self,
+            CipherSuite::ChaCha20Poly1305Sha256
+                | CipherSuite::EcdheEcdsaChaCha20Poly1305Sha256
+                | CipherSuite::EcdheRsaChaCha20Poly1305Sha256
+        )
+    }
+
+    pub fn key_len(self) -> usize {
+        if self.is_chacha() {
+            32
+        } else {
+            16
+        }
+    }
+
+    /// Length of the implicit IV a TLS 1.2 key block holds for this suite
+    pub fn fixed_iv_len(self) -> usize {
+        if self.is_chacha() {
+            NONCE_LEN
+        } else {
+            NONCE_LEN - EXPLICIT_NONCE_LEN
+        }
+    }
+}
+
+enum Aead {
+    ChaCha(ChaCha20Poly1305),
+    Gcm(Box<AesGcm>),
+}
+
+impl Aead {
+    fn new(suite: CipherSuite, key: &[u8]) -> Self {
+        if suite.is_chacha() {
+            let mut k = [0u8; 32];
+            k.copy_from_slice(key);
+            let aead = Aead::ChaCha(ChaCha20Poly1305::new(&k));
+            watos_crypto::wipe(&mut k);
+            aead
+        } else {
+            // 16-byte keys are always accepted
+            Aead::Gcm(Box::new(AesGcm::new(key).unwrap()))
+        }
+    }
+
+    fn seal(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8]) -> [u8; TAG_LEN] {
+        match self {
+            Aead::ChaCha(a) => a.seal(nonce, aad, data),
+            Aead::Gcm(a) => a.seal(nonce, aad, data),
+        }
+    }
+
+    fn open(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        match self {
+            Aead::ChaCha(a) => a.open(nonce, aad, data, tag),
+            Aead::Gcm(a) => a.open(nonce, aad, data, tag),
+        }
+    }
+}
+
+/// Keys protecting one direction of the connection
+pub struct RecordKeys {
+    aead: Aead,
+    iv: [u8; NONCE_LEN],
+    seq: u64,
+    tls12: bool,
+}
+
+impl RecordKeys {
+    /// Traffic keys from a TLS 1.3 traffic secret (RFC 8446 section 7.3)
+    pub fn new(suite: CipherSuite, secret: &Secret) -> Self {
+        let mut key = [0u8; 32];
+        let key = &mut key[..suite.key_len()];
+        expand_label(secret, b"key", b"", key);
+        let mut iv = [0u8; NONCE_LEN];
+        expand_label(secret, b"iv", b"", &mut iv);
+        let aead = Aead::new(suite, key);
+        watos_crypto::wipe(key);
+        RecordKeys { aead, iv, seq: 0, tls12: false }
+    }
+
+    /// TLS 1.2 keys from the key block: the write key and the
+    /// `fixed_iv_len` bytes of IV
+    pub fn tls12(suite: CipherSuite, key: &[u8], fixed_iv: &[u8]) -> Self {
+        let mut iv = [0u8; NONCE_LEN];
+        iv[..fixed_iv.len()].copy_from_slice(fixed_iv);
+        RecordKeys { aead: Aead::new(suite, key), iv, seq: 0, tls12: true }
+    }
+
+    /// A TLS 1.2 AES-GCM record, which carries part of its nonce
+    fn explicit_nonce(&self) -> bool {
+        self.tls12 && matches!(self.aead, Aead::Gcm(_))
+    }
+
+    /// Per-record nonce: the IV XORed with the sequence number. A TLS 1.2
+    /// GCM IV is four bytes, so this is that followed by the sequence
+    /// number, which also serves as the explicit part.
+    fn nonce(&mut self) -> [u8; NONCE_LEN] {
+        let mut nonce = self.iv;
+        for (n, s) in nonce[4..].iter_mut().zip(self.seq.to_be_bytes()) {
+            *n ^= s;
+        }
+        self.seq += 1;
+        nonce
+    }
+
+    /// TLS 1.2 additional data: sequence number, then the header with the
+    /// plaintext length. Call before `nonce`.
+    fn tls12_aad(&self, content_type: u8, len: usize) -> [u8; 13] {
+        let mut aad = [0u8; 13];
+        aad[..8].copy_from_slice(&self.seq.to_be_bytes());
+        aad[8..11].copy_from_slice(&[content_type, 3, 3]);
+        aad[11..].copy_from_slice(&(len as u16).to_be_bytes());
+        aad
+    }
+}
+
+/// Records over a transport
+pub struct RecordLayer<T: Transport> {
+    pub transport: T,
+    /// Bytes read from the transport and not yet consumed
+    incoming: Vec<u8>,
+    pub read_keys: Option<RecordKeys>,
+    pub write_keys: Option<RecordKeys>,
+    /// TLS 1.2 read keys, which take over at the server's ChangeCipherSpec
+    pub pending_read_keys: Option<RecordKeys>,
+}
+
+impl<T: Transport> RecordLayer<T> {
+    pub fn new(transport: T) -> Self {
+        RecordLayer { transport, incoming: Vec::new(), read_keys: None, write_keys: None, pending_read_keys: None }
+    }
+
+    /// Make sure `n` bytes are buffered
+    fn fill(&mut self, n: usize) -> Result<(), Error> {
+        let mut buf = [0u8; 2048];
+        while self.incoming.len() < n {
+            let got = self.transport.read(&mut buf)?;
+            if got == 0 {
+                return Err(Error::UnexpectedEof);
+            }
+            self.incoming.extend_from_slice(&buf[..got]);
+        }
+        Ok(())
+    }
+
+    /// Next record's content type and plaintext. ChangeCipherSpec records
+    /// are skipped: under TLS 1.3 they are only for compatibility, under
+    /// TLS 1.2 they switch to the pending read keys.
+    pub fn read(&mut self) -> Result<(u8, Vec<u8>), Error> {
+        loop {
+            self.fill(HEADER_LEN)?;
+            let mut header = [0u8; HEADER_LEN];
+            header.copy_from_slice(&self.incoming[..HEADER_LEN]);
+            let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+            if len > MAX_CIPHERTEXT {
+                return Err(Error::Decode);
+            }
+            self.fill(HEADER_LEN + len)?;
+            let mut body: Vec<u8> = self.incoming.drain(..HEADER_LEN + len).skip(HEADER_LEN).collect();
+
+            let content_type = header[0];
+            if content_type == CHANGE_CIPHER_SPEC {
+                if body != [1] {
+                    return Err(Error::UnexpectedMessage);
+                }
+                if let Some(keys) = self.pending_read_keys.take() {
+                    self.read_keys = Some(keys);
+                }
+                continue;
+            }
+            let keys = match &mut self.read_keys {
+                Some(keys) => keys,
+                None if len > MAX_PLAINTEXT => return Err(Error::Decode),
+                None => return Ok((content_type, body)),
+            };
+            if keys.tls12 {
+                return Self::open12(keys, content_type, body);
+            }
+            if content_type != APPLICATION_DATA || len < TAG_LEN {
+                return Err(Error::UnexpectedMessage);
+            }
+
+            let nonce = keys.nonce();
+            let (data_len, tag) = (len - TAG_LEN, body[len - TAG_LEN..].to_vec());
+            if !keys.aead.open(&nonce, &header, &mut body[..data_len], &tag) {
+                return Err(Error::BadRecordMac);
+            }
+            body.truncate(data_len);
+
+            // TLSInnerPlaintext: content, type, zero padding
+            let type_at = body.iter().rposition(|&b| b != 0).ok_or(Error::UnexpectedMessage)?;
+            let inner_type = body[type_at];
+            body.truncate(type_at);
+            if body.len() > MAX_PLAINTEXT {
+                return Err(Error::Decode);
+            }
+            return Ok((inner_type, body));
+        }
+    }
+
+    /// Decrypt a TLS 1.2 record body: [explicit nonce] ciphertext tag
+    fn open12(keys: &mut RecordKeys, content_type: u8, mut body: Vec<u8>) -> Result<(u8, Vec<u8>), Error> {
+        let explicit_len = if keys.explicit_nonce() { EXPLICIT_NONCE_LEN } else { 0 };
+        let data_len = body.len().checked_sub(explicit_len + TAG_LEN).ok_or(Error::BadRecordMac)?;
+        if data_len > MAX_PLAINTEXT {
+            return Err(Error::Decode);
+        }
+        let aad = keys.tls12_aad(content_type, data_len);
+        let mut nonce = keys.nonce();
+        if explicit_len > 0 {
+            nonce[NONCE_LEN - EXPLICIT_NONCE_LEN..].copy_from_slice(&body[..explicit_len]);
+        }
+        let tag = body[explicit_len + data_len..].to_vec();
+        if !keys.aead.open(&nonce, &aad, &mut body[explicit_len..explicit_len + data_len], &tag) {
+            return Err(Error::BadRecordMac);
+        }
+        body.truncate(explicit_len + data_len);
+        body.drain(..explicit_len);
+        Ok((content_type, body))
+    }
+
+    /// Send data as one or more records of a content type
+    pub fn write(&mut self, content_type: u8, data: &[u8]) -> Result<(), Error> {
+        for chunk in data.chunks(MAX_PLAINTEXT) {
+            let mut record = Vec::with_capacity(HEADER_LEN + chunk.len() + 1 + TAG_LEN);
+            match &mut self.write_keys {
+                None => {
+                    record.extend_from_slice(&[content_type, 3, 3]);
+                    record.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+                    record.extend_from_slice(chunk);
+                }
+                Some(keys) if keys.tls12 => {
+                    let explicit_len = if keys.explicit_nonce() { EXPLICIT_NONCE_LEN } else { 0 };
+                    let len = (explicit_len + chunk.len() + TAG_LEN) as u16;
+                    record.extend_from_slice(&[content_type, 3, 3]);
+                    record.extend_from_slice(&len.to_be_bytes());
+                    let aad = keys.tls12_aad(content_type, chunk.len());
+                    let nonce = keys.nonce();
+                    record.extend_from_slice(&nonce[NONCE_LEN - explicit_len..]);
+                    let start = record.len();
+                    record.extend_from_slice(chunk);
+                    let tag = keys.aead.seal(&nonce, &aad, &mut record[start..]);
+                    record.extend_from_slice(&tag);
+                }
+                Some(keys) => {
+                    let len = (chunk.len() + 1 + TAG_LEN) as u16;
+                    record.extend_from_slice(&[APPLICATION_DATA, 3, 3]);
+                    record.extend_from_slice(&len.to_be_bytes());
+                    record.extend_from_slice(chunk);
+                    record.push(content_type);
+                    let mut header = [0u8; HEADER_LEN];
+                    header.copy_from_slice(&record[..HEADER_LEN]);
+                    let nonce = keys.nonce();
+                    let tag = keys.aead.seal(&nonce, &header, &mut record[HEADER_LEN..]);
+                    record.extend_from_slice(&tag);
+                }
+            }
+            self.transport.write_all(&record)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes written come back out on read
+    struct Pipe(Vec<u8>);
+
+    impl Transport for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let n = buf.len().min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0.drain(..n);
+            Ok(n)
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.0.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tls12_records() {
+        for suite in [CipherSuite::EcdheRsaAes128GcmSha256, CipherSuite::EcdheRsaChaCha20Poly1305Sha256] {
+            let key = [7u8; 32];
+            let iv = [9u8; NONCE_LEN];
+            let keys = || RecordKeys::tls12(suite, &key[..suite.key_len()], &iv[..suite.fixed_iv_len()]);
+            let mut records = RecordLayer::new(Pipe(Vec::new()));
+            records.write_keys = Some(keys());
+            records.write(HANDSHAKE, b"first").unwrap();
+            records.write(APPLICATION_DATA, b"second").unwrap();
+
+            // The type stays in the header; GCM adds an explicit nonce
+            let explicit = if suite.is_chacha() { 0 } else { EXPLICIT_NONCE_LEN };
+            assert_eq!(records.transport.0[0], HANDSHAKE);
+            assert_eq!(records.transport.0.len(), 2 * (HEADER_LEN + explicit + TAG_LEN) + 11);
+
+            // Plaintext until ChangeCipherSpec brings in the pending keys
+            records.incoming = [&[CHANGE_CIPHER_SPEC, 3, 3, 0, 1, 1][..], &records.transport.0].concat();
+            records.transport.0.clear();
+            records.pending_read_keys = Some(keys());
+            assert_eq!(records.read().unwrap(), (HANDSHAKE, b"first".to_vec()));
+            assert_eq!(records.read().unwrap(), (APPLICATION_DATA, b"second".to_vec()));
+
+            // A record replayed out of sequence fails
+            records.write(HANDSHAKE, b"again").unwrap();
+            records.read_keys = Some(keys());
+            assert_eq!(records.read(), Err(Error::BadRecordMac));
+        }
+    }
+}
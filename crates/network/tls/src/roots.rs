@@ -0,0 +1,122 @@
+//! Trusted root certificates
+
+use alloc::vec::Vec;
+
+use watos_vfs::{FileMode, VfsError};
+
+use crate::x509::{CertError, Certificate};
+
+/// The system root store: PEM certificates, concatenated
+pub const ROOT_STORE_PATH: &str = "/etc/ssl/certs.pem";
+
+const PEM_BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+const PEM_END: &[u8] = b"-----END CERTIFICATE-----";
+
+/// Certificates trusted as the ends of chains, kept DER encoded
+#[derive(Default)]
+pub struct RootStore {
+    certs: Vec<Vec<u8>>,
+}
+
+impl RootStore {
+    pub fn new() -> Self {
+        RootStore { certs: Vec::new() }
+    }
+
+    /// Trust a DER certificate
+    pub fn add(&mut self, der: &[u8]) -> Result<(), CertError> {
+        Certificate::parse(der)?;
+        self.certs.push(der.to_vec());
+        Ok(())
+    }
+
+    /// Every CERTIFICATE block in PEM text; blocks that don't decode or
+    /// parse are skipped. Returns how many were added.
+    pub fn add_pem(&mut self, pem: &[u8]) -> usize {
+        let mut added = 0;
+        let mut rest = pem;
+        while let Some(start) = find(rest, PEM_BEGIN) {
+            rest = &rest[start + PEM_BEGIN.len()..];
+            let end = match find(rest, PEM_END) {
+                Some(end) => end,
+                None => break,
+            };
+            if let Some(der) = base64_decode(&rest[..end]) {
+                if self.add(&der).is_ok() {
+                    added += 1;
+                }
+            }
+            rest = &rest[end + PEM_END.len()..];
+        }
+        added
+    }
+
+    /// Read a PEM root store file (normally [`ROOT_STORE_PATH`])
+    pub fn load(path: &str) -> Result<Self, VfsError> {
+        let mut file = watos_vfs::open(path, FileMode::READ)?;
+        let mut pem = Vec::new();
+        let mut buf = [0u8; 512];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            pem.extend_from_slice(&buf[..n]);
+        }
+        let mut store = RootStore::new();
+        store.add_pem(&pem);
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.certs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty()
+    }
+
+    /// The DER certificates
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.certs.iter().map(|c| c.as_slice())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Standard base64, ignoring whitespace
+fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for &c in text.iter().filter(|c| !c.is_ascii_whitespace()) {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            _ => return None,
+        };
+        if padding > 0 {
+            return None;
+        }
+        acc = acc << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if padding > 2 {
+        return None;
+    }
+    Some(out)
+}
@@ -0,0 +1,410 @@
+//! X.509 certificates (RFC 5280): parsing and chain verification
+
+use watos_crypto::ecdsa::{self, Curve};
+use watos_crypto::hash::HashAlg;
+use watos_crypto::rsa;
+use watos_time::{DateTime, Timestamp};
+
+use crate::der::{self, Reader};
+use crate::roots::RootStore;
+
+/// Longest chain from leaf to root (counting both)
+pub const MAX_CHAIN_DEPTH: usize = 8;
+
+/// Why a certificate was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertError {
+    /// Not valid DER, or not shaped like a certificate
+    Malformed,
+    /// Uses a key type, algorithm or critical extension this client
+    /// doesn't know
+    Unsupported,
+    /// Outside its validity period
+    Expired,
+    /// Not issued for the server name that was connected to
+    NameMismatch,
+    /// No path to a trusted root
+    UnknownIssuer,
+    /// The server sent no certificate
+    Missing,
+}
+
+// ============================================================================
+// ALGORITHMS
+// ============================================================================
+
+const OID_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_RSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_RSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_RSA_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ECDSA_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_EXT_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+
+/// A signature scheme: how to check a signature with a [`PublicKey`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    RsaPkcs1(HashAlg),
+    RsaPss(HashAlg),
+    Ecdsa(HashAlg),
+    /// Recognised in a certificate but not verifiable
+    Unsupported,
+}
+
+fn signature_scheme(alg_id: &[u8]) -> Result<Scheme, CertError> {
+    let oid = Reader::new(alg_id).read(der::OID)?;
+    Ok(match oid {
+        OID_RSA_SHA256 => Scheme::RsaPkcs1(HashAlg::Sha256),
+        OID_RSA_SHA384 => Scheme::RsaPkcs1(HashAlg::Sha384),
+        OID_RSA_SHA512 => Scheme::RsaPkcs1(HashAlg::Sha512),
+        OID_ECDSA_SHA256 => Scheme::Ecdsa(HashAlg::Sha256),
+        OID_ECDSA_SHA384 => Scheme::Ecdsa(HashAlg::Sha384),
+        OID_ECDSA_SHA512 => Scheme::Ecdsa(HashAlg::Sha512),
+        _ => Scheme::Unsupported,
+    })
+}
+
+/// A subject public key
+#[derive(Debug, Clone, Copy)]
+pub enum PublicKey<'a> {
+    /// Big-endian modulus and exponent
+    Rsa { n: &'a [u8], e: &'a [u8] },
+    /// Uncompressed point
+    Ec { curve: Curve, point: &'a [u8] },
+    Unsupported,
+}
+
+impl PublicKey<'_> {
+    fn parse(spki: &[u8]) -> Result<PublicKey<'_>, CertError> {
+        let mut r = Reader::new(spki);
+        let mut alg = Reader::new(r.read(der::SEQUENCE)?);
+        let key = r.bit_string()?;
+        Ok(match alg.read(der::OID)? {
+            OID_RSA => {
+                let mut seq = Reader::new(key);
+                let mut fields = Reader::new(seq.read(der::SEQUENCE)?);
+                PublicKey::Rsa { n: fields.unsigned()?, e: fields.unsigned()? }
+            }
+            OID_EC => match alg.read(der::OID) {
+                Ok(OID_P256) => PublicKey::Ec { curve: Curve::P256, point: key },
+                Ok(OID_P384) => PublicKey::Ec { curve: Curve::P384, point: key },
+                _ => PublicKey::Unsupported,
+            },
+            _ => PublicKey::Unsupported,
+        })
+    }
+
+    /// Check a signature over `message`; ECDSA signatures are DER
+    /// `SEQUENCE { r, s }`
+    pub fn verify(&self, scheme: Scheme, message: &[u8], signature: &[u8]) -> bool {
+        match (*self, scheme) {
+            (PublicKey::Rsa { n, e }, Scheme::RsaPkcs1(hash) | Scheme::RsaPss(hash)) => {
+                let key = match rsa::PublicKey::new(n, e) {
+                    Some(key) => key,
+                    None => return false,
+                };
+                let digest = hash.digest(message);
+                match scheme {
+                    Scheme::RsaPss(_) => key.verify_pss(hash, &digest, signature),
+                    _ => key.verify_pkcs1(hash, &digest, signature),
+                }
+            }
+            (PublicKey::Ec { curve, point }, Scheme::Ecdsa(hash)) => {
+                let parse = || -> Result<(&[u8], &[u8]), CertError> {
+                    let mut outer = Reader::new(signature);
+                    let mut seq = Reader::new(outer.read(der::SEQUENCE)?);
+                    let rs = (seq.unsigned()?, seq.unsigned()?);
+                    if !outer.is_empty() || !seq.is_empty() {
+                        return Err(CertError::Malformed);
+                    }
+                    Ok(rs)
+                };
+                match parse() {
+                    Ok((r, s)) => ecdsa::verify(curve, point, &hash.digest(message), r, s),
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+// ============================================================================
+// CERTIFICATES
+// ============================================================================
+
+/// The parts of a certificate that verification looks at
+#[derive(Debug, Clone, Copy)]
+pub struct Certificate<'a> {
+    /// The signed TBSCertificate, as encoded
+    pub tbs: &'a [u8],
+    pub scheme: Scheme,
+    pub signature: &'a [u8],
+    /// Encoded issuer and subject Names, compared byte for byte
+    pub issuer: &'a [u8],
+    pub subject: &'a [u8],
+    pub not_before: Timestamp,
+    pub not_after: Timestamp,
+    pub key: PublicKey<'a>,
+    /// basicConstraints cA
+    pub is_ca: bool,
+    /// Contents of the subjectAltName GeneralNames, if present
+    alt_names: Option<&'a [u8]>,
+}
+
+impl<'a> Certificate<'a> {
+    pub fn parse(encoded: &'a [u8]) -> Result<Self, CertError> {
+        let mut outer = Reader::new(encoded);
+        let mut cert = Reader::new(outer.read(der::SEQUENCE)?);
+        if !outer.is_empty() {
+            return Err(CertError::Malformed);
+        }
+        let (_, tbs_contents, tbs) = cert.any()?;
+        let scheme = signature_scheme(cert.read(der::SEQUENCE)?)?;
+        let signature = cert.bit_string()?;
+
+        let mut t = Reader::new(tbs_contents);
+        t.optional(der::explicit(0))?;
+        t.read(der::INTEGER)?;
+        if signature_scheme(t.read(der::SEQUENCE)?)? != scheme {
+            return Err(CertError::Malformed);
+        }
+        let issuer = t.read(der::SEQUENCE)?;
+        let mut validity = Reader::new(t.read(der::SEQUENCE)?);
+        let not_before = parse_time(&mut validity)?;
+        let not_after = parse_time(&mut validity)?;
+        let subject = t.read(der::SEQUENCE)?;
+        let (_, spki, _) = t.any()?;
+        let key = PublicKey::parse(spki)?;
+        t.optional(der::implicit(1))?;
+        t.optional(der::implicit(2))?;
+
+        let mut is_ca = false;
+        let mut alt_names = None;
+        if let Some(exts) = t.optional(der::explicit(3))? {
+            let mut list = Reader::new(Reader::new(exts).read(der::SEQUENCE)?);
+            while !list.is_empty() {
+                let mut ext = Reader::new(list.read(der::SEQUENCE)?);
+                let oid = ext.read(der::OID)?;
+                let critical = ext.optional(der::BOOLEAN)? == Some(&[0xff]);
+                let value = ext.read(der::OCTET_STRING)?;
+                match oid {
+                    OID_BASIC_CONSTRAINTS => {
+                        let mut bc = Reader::new(Reader::new(value).read(der::SEQUENCE)?);
+                        is_ca = bc.optional(der::BOOLEAN)? == Some(&[0xff]);
+                    }
+                    OID_SUBJECT_ALT_NAME => {
+                        alt_names = Some(Reader::new(value).read(der::SEQUENCE)?);
+                    }
+                    OID_KEY_USAGE | OID_EXT_KEY_USAGE => {}
+                    _ if critical => return Err(CertError::Unsupported),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Certificate {
+            tbs,
+            scheme,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            key,
+            is_ca,
+            alt_names,
+        })
+    }
+
+    /// Whether a subjectAltName DNS entry matches `host`
+    pub fn matches_name(&self, host: &str) -> bool {
+        let mut names = match self.alt_names {
+            Some(names) => Reader::new(names),
+            None => return false,
+        };
+        while let Ok((tag, name, _)) = names.any() {
+            if tag == der::implicit(2) && dns_name_matches(name, host.as_bytes()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn check_time(&self, now: Timestamp) -> Result<(), CertError> {
+        if now < self.not_before || now > self.not_after {
+            return Err(CertError::Expired);
+        }
+        Ok(())
+    }
+
+    /// Whether `issuer` signed this certificate
+    fn issued_by(&self, issuer: &Certificate) -> bool {
+        self.issuer == issuer.subject && issuer.key.verify(self.scheme, self.tbs, self.signature)
+    }
+}
+
+/// UTCTime (two-digit years, 1950-2049) or GeneralizedTime, in UTC
+fn parse_time(r: &mut Reader) -> Result<Timestamp, CertError> {
+    let (tag, text, _) = r.any()?;
+    let digits = match (tag, text) {
+        (der::UTC_TIME, [d @ .., b'Z']) if d.len() == 12 => d,
+        (der::GENERALIZED_TIME, [d @ .., b'Z']) if d.len() == 14 => d,
+        _ => return Err(CertError::Malformed),
+    };
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return Err(CertError::Malformed);
+    }
+    let num = |s: &[u8]| s.iter().fold(0i32, |acc, d| acc * 10 + (d - b'0') as i32);
+    let (year, rest) = if tag == der::UTC_TIME {
+        let yy = num(&digits[..2]);
+        (if yy < 50 { 2000 + yy } else { 1900 + yy }, &digits[2..])
+    } else {
+        (num(&digits[..4]), &digits[4..])
+    };
+    let field = |i: usize| num(&rest[2 * i..2 * i + 2]) as u8;
+    DateTime::new(year, field(0), field(1), field(2), field(3), field(4))
+        .to_timestamp()
+        .ok_or(CertError::Malformed)
+}
+
+/// Case-insensitive DNS name match; `*.` may stand for one whole leftmost
+/// label
+fn dns_name_matches(pattern: &[u8], host: &[u8]) -> bool {
+    let host = host.strip_suffix(b".").unwrap_or(host);
+    match pattern.strip_prefix(b"*.") {
+        Some(suffix) => match host.iter().position(|&b| b == b'.') {
+            Some(dot) => dot > 0 && host[dot + 1..].eq_ignore_ascii_case(suffix),
+            None => false,
+        },
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+/// Verify a server's chain (leaf first, then any intermediates in any
+/// order) for `host` at time `now`, returning the leaf
+pub fn verify_chain<'a>(
+    chain: &[&'a [u8]],
+    roots: &RootStore,
+    host: &str,
+    now: Timestamp,
+) -> Result<Certificate<'a>, CertError> {
+    let leaf = Certificate::parse(chain.first().ok_or(CertError::Missing)?)?;
+    leaf.check_time(now)?;
+    if !leaf.matches_name(host) {
+        return Err(CertError::NameMismatch);
+    }
+
+    let mut current = leaf;
+    for _ in 1..MAX_CHAIN_DEPTH {
+        // Trust anchors are trusted as they are, dates included
+        let anchored = roots
+            .iter()
+            .filter_map(|der| Certificate::parse(der).ok())
+            .any(|root| current.issued_by(&root));
+        if anchored {
+            return Ok(leaf);
+        }
+
+        let next = chain[1..]
+            .iter()
+            .filter_map(|der| Certificate::parse(der).ok())
+            .find(|c| c.is_ca && current.issued_by(c));
+        match next {
+            Some(next) => {
+                next.check_time(now)?;
+                current = next;
+            }
+            None => return Err(CertError::UnknownIssuer),
+        }
+    }
+    Err(CertError::UnknownIssuer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &[u8] = b"\
+-----BEGIN CERTIFICATE-----\n\
+MIIBfjCCASOgAwIBAgIUdl0IV85JnuUtDrQ4FY8dLgraND8wCgYIKoZIzj0EAwIw\n\
+FDESMBAGA1UEAwwJVGVzdC1Sb290MB4XDTI2MDEwMTAwMDAwMFoXDTM2MDEwMTAw\n\
+MDAwMFowFDESMBAGA1UEAwwJVGVzdC1Sb290MFkwEwYHKoZIzj0CAQYIKoZIzj0D\n\
+AQcDQgAEF1OkXY/pjyouoJb9OkKW3IiKQ4wtUyZOMbe4byfVvsa6rMsbWMFc/AWe\n\
+KidyJvFai/PKJnt0ZV6vOMoCoeNg2qNTMFEwHQYDVR0OBBYEFNE6L/BD+oegv4yR\n\
+xYCJJwUtEzUmMB8GA1UdIwQYMBaAFNE6L/BD+oegv4yRxYCJJwUtEzUmMA8GA1Ud\n\
+EwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAPNZcjktb/62Hu/R2qv7Ko7F\n\
+4YJ6czZX332HFMcDLQHZAiEA0mCNZB/RyoaCUwFGi6gonZ97lnJVkQ82hJ/5aiov\n\
+GKY=\n\
+-----END CERTIFICATE-----
+";
+
+    const LEAF: &[u8] = b"\
+-----BEGIN CERTIFICATE-----\n\
+MIIBmjCCAUCgAwIBAgIBAjAKBggqhkjOPQQDAjAUMRIwEAYDVQQDDAlUZXN0LVJv\n\
+b3QwHhcNMjYwMTAxMDAwMDAwWhcNMjcwMTAxMDAwMDAwWjAPMQ0wCwYDVQQDDARs\n\
+ZWFmMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE3wU43prUB9PzezUI+uZkACFP\n\
+JUoUpKR/lkBGoK6LYwOmpTeagwfS1dIv91bTVvNrpxrlNK08z6OwAOBtR49vcaOB\n\
+hzCBhDAJBgNVHRMEAjAAMCIGA1UdEQQbMBmCCWxvY2FsaG9zdIIMKi53YXRvcy50\n\
+ZXN0MBMGA1UdJQQMMAoGCCsGAQUFBwMBMB0GA1UdDgQWBBS7o22uApKnGhkFMDfJ\n\
+kQRQXFuqjjAfBgNVHSMEGDAWgBTROi/wQ/qHoL+MkcWAiScFLRM1JjAKBggqhkjO\n\
+PQQDAgNIADBFAiB4CKgtiG2q58lcOZD+XJLlytaUOfKMwLlnPr67d36eMgIhAMmY\n\
+3dPIonQd7giFh2VkffGJwYurWK5lbVNxBoneTh0F\n\
+-----END CERTIFICATE-----
+";
+
+    fn at(year: i32, month: u8) -> Timestamp {
+        DateTime::new(year, month, 1, 0, 0, 0).to_timestamp().unwrap()
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let mut roots = RootStore::new();
+        assert_eq!(roots.add_pem(ROOT), 1);
+        let mut leaf_store = RootStore::new();
+        assert_eq!(leaf_store.add_pem(LEAF), 1);
+        let leaf_der = leaf_store.iter().next().unwrap();
+        let chain = [leaf_der];
+
+        let leaf = verify_chain(&chain, &roots, "localhost", at(2026, 6)).unwrap();
+        assert!(!leaf.is_ca);
+        assert!(matches!(leaf.key, PublicKey::Ec { curve: Curve::P256, .. }));
+        assert!(verify_chain(&chain, &roots, "www.watos.test", at(2026, 6)).is_ok());
+
+        let err = |host, when| verify_chain(&chain, &roots, host, when).err();
+        assert_eq!(err("a.b.watos.test", at(2026, 6)), Some(CertError::NameMismatch));
+        assert_eq!(err("example.com", at(2026, 6)), Some(CertError::NameMismatch));
+        assert_eq!(err("localhost", at(2027, 6)), Some(CertError::Expired));
+        assert_eq!(err("localhost", at(2025, 6)), Some(CertError::Expired));
+        assert_eq!(
+            verify_chain(&chain, &RootStore::new(), "localhost", at(2026, 6)).err(),
+            Some(CertError::UnknownIssuer)
+        );
+        assert_eq!(verify_chain(&[], &roots, "localhost", at(2026, 6)).err(), Some(CertError::Missing));
+
+        // A corrupted signature leaves no path to the root
+        let mut forged = leaf_der.to_vec();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(verify_chain(&[&forged], &roots, "localhost", at(2026, 6)).is_err());
+    }
+
+    #[test]
+    fn test_dns_name_matches() {
+        assert!(dns_name_matches(b"example.com", b"EXAMPLE.com"));
+        assert!(dns_name_matches(b"example.com", b"example.com."));
+        assert!(dns_name_matches(b"*.example.com", b"www.example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b"example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b".example.com"));
+        assert!(!dns_name_matches(b"*.example.com", b"a.b.example.com"));
+        assert!(!dns_name_matches(b"www.example.com", b"example.com"));
+    }
+}
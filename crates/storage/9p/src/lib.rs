@@ -0,0 +1,910 @@
+//! WATOS 9P Filesystem
+//!
+//! A 9P2000.L client, so a directory on the host can be mounted as a drive
+//! and used read-write without copying files into a disk image. With QEMU:
+//!
+//! ```text
+//! -virtfs local,path=/home/me/work,mount_tag=host,security_model=none
+//! ```
+//!
+//! ```ignore
+//! let fs = NinePFs::mount(Box::new(virtio_9p_device), "")?;
+//! watos_vfs::mount_drive('H', Box::new(fs))?;
+//! ```
+//!
+//! Messages go through [`Transport`], one request at a time; the kernel
+//! provides one over the virtio 9P device. Paths are walked from the
+//! attached root for each operation, and every open file holds a fid of its
+//! own until it is dropped.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod proto;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use proto::*;
+use watos_vfs::{
+    DirEntry, FileMode, FileOperations, FileStat, FileType, Filesystem, FsStats, SeekFrom,
+    VfsError, VfsResult,
+};
+
+/// Protocol version spoken
+pub const VERSION: &str = "9P2000.L";
+
+/// Largest message asked for; the server may lower it
+pub const MSIZE: u32 = 64 * 1024;
+
+/// Mode of created files and directories, before the host's umask
+const CREATE_MODE: u32 = 0o644;
+const MKDIR_MODE: u32 = 0o755;
+
+/// Fid of the attached root
+const ROOT_FID: u32 = 0;
+
+/// Tag of every request after Tversion
+const TAG: u16 = 1;
+
+/// Carries 9P messages to the server
+pub trait Transport: Send {
+    /// Send one T-message and receive the R-message into `reply`,
+    /// returning its length
+    fn request(&mut self, request: &[u8], reply: &mut [u8]) -> VfsResult<usize>;
+}
+
+// ============================================================================
+// CLIENT
+// ============================================================================
+
+/// A connection to the server and its fids
+struct Client {
+    transport: Box<dyn Transport>,
+    msize: u32,
+    next_fid: u32,
+    free_fids: Vec<u32>,
+    reply: Vec<u8>,
+}
+
+impl Client {
+    /// Send a message and return the body of its reply
+    fn rpc(&mut self, message: Message) -> VfsResult<Vec<u8>> {
+        let kind = message.kind();
+        let request = message.finish();
+        if request.len() > self.msize as usize {
+            return Err(VfsError::InvalidArgument);
+        }
+        let len = self.transport.request(&request, &mut self.reply)?;
+        if len < HEADER_SIZE {
+            return Err(VfsError::IoError);
+        }
+        let size = u32::from_le_bytes(self.reply[..4].try_into().unwrap()) as usize;
+        if size < HEADER_SIZE || size > len {
+            return Err(VfsError::IoError);
+        }
+        let body = &self.reply[HEADER_SIZE..size];
+        match self.reply[4] {
+            RLERROR => Err(errno_to_error(Reader::new(body).u32()?)),
+            r if r == kind + 1 => Ok(body.to_vec()),
+            _ => Err(VfsError::IoError),
+        }
+    }
+
+    /// Agree on the protocol and attach to the share's root
+    fn connect(&mut self, aname: &str) -> VfsResult<()> {
+        let body = self.rpc(Message::new(TVERSION, NOTAG).u32(MSIZE).str(VERSION))?;
+        let mut r = Reader::new(&body);
+        let msize = r.u32()?;
+        if r.str()? != VERSION {
+            return Err(VfsError::NotSupported);
+        }
+        if msize < 4096 {
+            return Err(VfsError::IoError);
+        }
+        self.msize = msize.min(MSIZE);
+        self.reply.truncate(self.msize as usize);
+
+        self.rpc(
+            Message::new(TATTACH, TAG).u32(ROOT_FID).u32(NOFID).str("root").str(aname).u32(0),
+        )?;
+        Ok(())
+    }
+
+    /// Largest payload of one Tread or Twrite
+    fn max_io(&self) -> usize {
+        self.msize as usize - IO_HEADER_SIZE
+    }
+
+    fn alloc_fid(&mut self) -> u32 {
+        self.free_fids.pop().unwrap_or_else(|| {
+            self.next_fid += 1;
+            self.next_fid
+        })
+    }
+
+    /// Release a fid on the server and reuse it
+    fn clunk(&mut self, fid: u32) {
+        let _ = self.rpc(Message::new(TCLUNK, TAG).u32(fid));
+        self.free_fids.push(fid);
+    }
+
+    /// A new fid for `names` below `from`
+    fn walk_from(&mut self, from: u32, names: &[&str]) -> VfsResult<u32> {
+        let fid = self.alloc_fid();
+        let mut start = from;
+        let mut chunks = names.chunks(MAX_WALK).peekable();
+        // Even no names walks once, to clone the fid
+        if chunks.peek().is_none() {
+            if let Err(e) = self.rpc(Message::new(TWALK, TAG).u32(from).u32(fid).u16(0)) {
+                self.free_fids.push(fid);
+                return Err(e);
+            }
+            return Ok(fid);
+        }
+        for chunk in chunks {
+            let mut message = Message::new(TWALK, TAG).u32(start).u32(fid).u16(chunk.len() as u16);
+            for name in chunk {
+                message = message.str(name);
+            }
+            let walked = self.rpc(message).and_then(|body| Ok(Reader::new(&body).u16()? as usize));
+            match walked {
+                Ok(n) if n == chunk.len() => start = fid,
+                // A partial walk leaves the new fid unused
+                result => {
+                    if start == fid {
+                        self.clunk(fid);
+                    } else {
+                        self.free_fids.push(fid);
+                    }
+                    return Err(result.err().unwrap_or(VfsError::NotFound));
+                }
+            }
+        }
+        Ok(fid)
+    }
+
+    fn walk(&mut self, path: &str) -> VfsResult<u32> {
+        let names = components(path);
+        self.walk_from(ROOT_FID, &names)
+    }
+
+    /// A fid for the parent of `path` and the last name in it
+    fn walk_parent<'p>(&mut self, path: &'p str) -> VfsResult<(u32, &'p str)> {
+        let mut names = components(path);
+        let name = names.pop().ok_or(VfsError::InvalidPath)?;
+        Ok((self.walk_from(ROOT_FID, &names)?, name))
+    }
+
+    fn getattr(&mut self, fid: u32) -> VfsResult<FileStat> {
+        let body = self.rpc(Message::new(TGETATTR, TAG).u32(fid).u64(GETATTR_BASIC))?;
+        let mut r = Reader::new(&body);
+        let _valid = r.u64()?;
+        let qid = r.qid()?;
+        let mode = r.u32()?;
+        let uid = r.u32()?;
+        let gid = r.u32()?;
+        let nlink = r.u64()?;
+        let _rdev = r.u64()?;
+        let size = r.u64()?;
+        let blksize = r.u64()?;
+        let blocks = r.u64()?;
+        let atime = r.u64()?;
+        let _ = r.u64()?;
+        let mtime = r.u64()?;
+        let _ = r.u64()?;
+        let ctime = r.u64()?;
+        Ok(FileStat {
+            file_type: file_type_from_mode(mode),
+            size,
+            nlink: nlink as u32,
+            inode: qid.path,
+            dev: 0,
+            mode: mode & 0o7777,
+            uid,
+            gid,
+            blksize: blksize as u32,
+            blocks,
+            atime,
+            mtime,
+            ctime,
+        })
+    }
+
+    fn setattr(&mut self, fid: u32, valid: u32, mode: u32, uid: u32, gid: u32, size: u64) -> VfsResult<()> {
+        let message = Message::new(TSETATTR, TAG).u32(fid).u32(valid).u32(mode).u32(uid).u32(gid).u64(size);
+        // atime and mtime, unused without their valid bits
+        self.rpc(message.u64(0).u64(0).u64(0).u64(0))?;
+        Ok(())
+    }
+
+    /// Set atime and mtime (seconds; no nanoseconds are kept)
+    fn set_times(&mut self, fid: u32, atime: u64, mtime: u64) -> VfsResult<()> {
+        let valid = SETATTR_ATIME | SETATTR_ATIME_SET | SETATTR_MTIME | SETATTR_MTIME_SET;
+        let message = Message::new(TSETATTR, TAG).u32(fid).u32(valid).u32(0).u32(0).u32(0).u64(0);
+        self.rpc(message.u64(atime).u64(0).u64(mtime).u64(0))?;
+        Ok(())
+    }
+
+    /// Open a walked fid; returns the server's I/O unit
+    fn lopen(&mut self, fid: u32, flags: u32) -> VfsResult<u32> {
+        let body = self.rpc(Message::new(TLOPEN, TAG).u32(fid).u32(flags))?;
+        let mut r = Reader::new(&body);
+        r.qid()?;
+        r.u32()
+    }
+
+    fn read(&mut self, fid: u32, offset: u64, buffer: &mut [u8]) -> VfsResult<usize> {
+        let count = buffer.len().min(self.max_io()) as u32;
+        let body = self.rpc(Message::new(TREAD, TAG).u32(fid).u64(offset).u32(count))?;
+        let mut r = Reader::new(&body);
+        let n = (r.u32()? as usize).min(buffer.len());
+        buffer[..n].copy_from_slice(r.bytes(n)?);
+        Ok(n)
+    }
+
+    fn write(&mut self, fid: u32, offset: u64, data: &[u8]) -> VfsResult<usize> {
+        let data = &data[..data.len().min(self.max_io())];
+        let message = Message::new(TWRITE, TAG).u32(fid).u64(offset).u32(data.len() as u32).raw(data);
+        let body = self.rpc(message)?;
+        Ok(Reader::new(&body).u32()? as usize)
+    }
+
+    /// Every entry of an open directory fid except `.` and `..`
+    fn readdir(&mut self, fid: u32) -> VfsResult<Vec<(String, FileType, u64)>> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        loop {
+            let count = self.max_io() as u32;
+            let body = self.rpc(Message::new(TREADDIR, TAG).u32(fid).u64(offset).u32(count))?;
+            let mut r = Reader::new(&body);
+            let len = r.u32()? as usize;
+            if len == 0 {
+                return Ok(entries);
+            }
+            let mut r = Reader::new(r.bytes(len)?);
+            while r.remaining() > 0 {
+                let qid = r.qid()?;
+                offset = r.u64()?;
+                let kind = r.u8()?;
+                let name = r.str()?;
+                if name != "." && name != ".." {
+                    entries.push((name, file_type_from_dirent(kind), qid.path));
+                }
+            }
+        }
+    }
+}
+
+/// Names in a path, without empty and `.` components
+fn components(path: &str) -> Vec<&str> {
+    path.split('/').filter(|name| !name.is_empty() && *name != ".").collect()
+}
+
+fn file_type_from_mode(mode: u32) -> FileType {
+    match mode & 0o170000 {
+        0o040000 => FileType::Directory,
+        0o100000 => FileType::Regular,
+        0o120000 => FileType::Symlink,
+        0o060000 => FileType::BlockDevice,
+        0o020000 => FileType::CharDevice,
+        0o010000 => FileType::Fifo,
+        0o140000 => FileType::Socket,
+        _ => FileType::Unknown,
+    }
+}
+
+/// File type from a Linux `DT_*` value in Rreaddir
+fn file_type_from_dirent(kind: u8) -> FileType {
+    match kind {
+        1 => FileType::Fifo,
+        2 => FileType::CharDevice,
+        4 => FileType::Directory,
+        6 => FileType::BlockDevice,
+        8 => FileType::Regular,
+        10 => FileType::Symlink,
+        12 => FileType::Socket,
+        _ => FileType::Unknown,
+    }
+}
+
+// ============================================================================
+// FILESYSTEM
+// ============================================================================
+
+/// A host directory shared over 9P
+pub struct NinePFs {
+    client: Arc<Mutex<Client>>,
+}
+
+impl NinePFs {
+    /// Connect over `transport` and attach to the share `aname` (QEMU
+    /// ignores it: each device shares one directory)
+    pub fn mount(transport: Box<dyn Transport>, aname: &str) -> VfsResult<Self> {
+        let mut client = Client {
+            transport,
+            msize: MSIZE,
+            next_fid: ROOT_FID,
+            free_fids: Vec::new(),
+            reply: vec![0; MSIZE as usize],
+        };
+        client.connect(aname)?;
+        Ok(NinePFs { client: Arc::new(Mutex::new(client)) })
+    }
+
+    /// Run `f` on a fid for `path`, clunking it afterwards
+    fn with_fid<T>(&self, path: &str, f: impl FnOnce(&mut Client, u32) -> VfsResult<T>) -> VfsResult<T> {
+        let mut client = self.client.lock();
+        let fid = client.walk(path)?;
+        let result = f(&mut client, fid);
+        client.clunk(fid);
+        result
+    }
+
+    /// Run `f` on a fid for the parent of `path` and the name in it
+    fn with_parent<T>(&self, path: &str, f: impl FnOnce(&mut Client, u32, &str) -> VfsResult<T>) -> VfsResult<T> {
+        let mut client = self.client.lock();
+        let (fid, name) = client.walk_parent(path)?;
+        let result = f(&mut client, fid, name);
+        client.clunk(fid);
+        result
+    }
+}
+
+fn open_flags(mode: FileMode) -> u32 {
+    let access = match (mode.read, mode.write || mode.append) {
+        (true, true) => O_RDWR,
+        (false, true) => O_WRONLY,
+        _ => O_RDONLY,
+    };
+    if mode.truncate {
+        access | O_TRUNC
+    } else {
+        access
+    }
+}
+
+impl Filesystem for NinePFs {
+    fn name(&self) -> &'static str {
+        "9p"
+    }
+
+    fn open(&self, path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
+        let flags = open_flags(mode);
+        let mut client = self.client.lock();
+        let opened = match client.walk(path) {
+            Ok(fid) if mode.create && mode.exclusive => {
+                client.clunk(fid);
+                Err(VfsError::AlreadyExists)
+            }
+            Ok(fid) => match client.lopen(fid, flags) {
+                Ok(iounit) => Ok((fid, iounit)),
+                Err(e) => {
+                    client.clunk(fid);
+                    Err(e)
+                }
+            },
+            Err(VfsError::NotFound) if mode.create => {
+                // Tlcreate turns the parent's fid into the new file's
+                let (fid, name) = client.walk_parent(path)?;
+                let message = Message::new(TLCREATE, TAG)
+                    .u32(fid)
+                    .str(name)
+                    .u32(flags | O_CREAT | O_EXCL)
+                    .u32(CREATE_MODE)
+                    .u32(0);
+                match client.rpc(message).and_then(|body| {
+                    let mut r = Reader::new(&body);
+                    r.qid()?;
+                    r.u32()
+                }) {
+                    Ok(iounit) => Ok((fid, iounit)),
+                    Err(e) => {
+                        client.clunk(fid);
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let (fid, iounit) = opened?;
+        let max_io = client.max_io();
+        drop(client);
+
+        let io_size = match iounit as usize {
+            0 => max_io,
+            n => n.min(max_io),
+        };
+        Ok(Box::new(NinePFile {
+            client: self.client.clone(),
+            fid,
+            position: 0,
+            io_size,
+            append: mode.append,
+        }))
+    }
+
+    fn stat(&self, path: &str) -> VfsResult<FileStat> {
+        self.with_fid(path, |client, fid| client.getattr(fid))
+    }
+
+    fn mkdir(&self, path: &str) -> VfsResult<()> {
+        self.with_parent(path, |client, fid, name| {
+            client.rpc(Message::new(TMKDIR, TAG).u32(fid).str(name).u32(MKDIR_MODE).u32(0))?;
+            Ok(())
+        })
+    }
+
+    fn unlink(&self, path: &str) -> VfsResult<()> {
+        self.with_parent(path, |client, fid, name| {
+            client.rpc(Message::new(TUNLINKAT, TAG).u32(fid).str(name).u32(0))?;
+            Ok(())
+        })
+    }
+
+    fn rmdir(&self, path: &str) -> VfsResult<()> {
+        self.with_parent(path, |client, fid, name| {
+            client.rpc(Message::new(TUNLINKAT, TAG).u32(fid).str(name).u32(AT_REMOVEDIR))?;
+            Ok(())
+        })
+    }
+
+    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
+        self.with_fid(path, |client, fid| {
+            client.lopen(fid, O_RDONLY | O_DIRECTORY)?;
+            let names = client.readdir(fid)?;
+            let mut entries = Vec::with_capacity(names.len());
+            for (name, file_type, inode) in names {
+                // Sizes aren't in Rreaddir
+                let size = match file_type {
+                    FileType::Regular => client
+                        .walk_from(fid, &[name.as_str()])
+                        .and_then(|child| {
+                            let stat = client.getattr(child);
+                            client.clunk(child);
+                            stat
+                        })
+                        .map(|stat| stat.size)
+                        .unwrap_or(0),
+                    _ => 0,
+                };
+                entries.push(DirEntry { name, file_type, size, inode });
+            }
+            Ok(entries)
+        })
+    }
+
+    fn rename(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
+        let mut client = self.client.lock();
+        let (old_dir, old_name) = client.walk_parent(old_path)?;
+        let result = client.walk_parent(new_path).and_then(|(new_dir, new_name)| {
+            let message = Message::new(TRENAMEAT, TAG).u32(old_dir).str(old_name).u32(new_dir).str(new_name);
+            let result = client.rpc(message);
+            client.clunk(new_dir);
+            result
+        });
+        client.clunk(old_dir);
+        result.map(|_| ())
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        // Writes go straight to the host
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<FsStats> {
+        let body = self.client.lock().rpc(Message::new(TSTATFS, TAG).u32(ROOT_FID))?;
+        let mut r = Reader::new(&body);
+        let _fs_type = r.u32()?;
+        let block_size = r.u32()?;
+        let total_blocks = r.u64()?;
+        let _bfree = r.u64()?;
+        let free_blocks = r.u64()?;
+        let total_inodes = r.u64()?;
+        let free_inodes = r.u64()?;
+        let _fsid = r.u64()?;
+        let max_name_len = r.u32()?;
+        Ok(FsStats { total_blocks, free_blocks, block_size, total_inodes, free_inodes, max_name_len })
+    }
+
+    fn chmod(&self, path: &str, mode: u32) -> VfsResult<()> {
+        self.with_fid(path, |client, fid| client.setattr(fid, SETATTR_MODE, mode & 0o7777, 0, 0, 0))
+    }
+
+    fn chown(&self, path: &str, uid: u32, gid: u32) -> VfsResult<()> {
+        self.with_fid(path, |client, fid| client.setattr(fid, SETATTR_UID | SETATTR_GID, 0, uid, gid, 0))
+    }
+
+    fn set_times(&self, path: &str, atime: u64, mtime: u64) -> VfsResult<()> {
+        self.with_fid(path, |client, fid| client.set_times(fid, atime, mtime))
+    }
+
+    fn readlink(&self, path: &str) -> VfsResult<String> {
+        self.with_fid(path, |client, fid| {
+            let body = client.rpc(Message::new(TREADLINK, TAG).u32(fid))?;
+            Reader::new(&body).str()
+        })
+    }
+
+    fn link(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
+        let mut client = self.client.lock();
+        let target = client.walk(old_path)?;
+        let result = client.walk_parent(new_path).and_then(|(dir, name)| {
+            let result = client.rpc(Message::new(TLINK, TAG).u32(dir).u32(target).str(name));
+            client.clunk(dir);
+            result
+        });
+        client.clunk(target);
+        result.map(|_| ())
+    }
+}
+
+/// An open file: a fid opened on the server
+struct NinePFile {
+    client: Arc<Mutex<Client>>,
+    fid: u32,
+    position: u64,
+    /// Most bytes per Tread or Twrite
+    io_size: usize,
+    append: bool,
+}
+
+impl FileOperations for NinePFile {
+    fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+        let mut client = self.client.lock();
+        let mut total = 0;
+        while total < buffer.len() {
+            let end = buffer.len().min(total + self.io_size);
+            let n = client.read(self.fid, self.position, &mut buffer[total..end])?;
+            if n == 0 {
+                break;
+            }
+            total += n;
+            self.position += n as u64;
+        }
+        Ok(total)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+        let mut client = self.client.lock();
+        if self.append {
+            self.position = client.getattr(self.fid)?.size;
+        }
+        let mut total = 0;
+        while total < buffer.len() {
+            let end = buffer.len().min(total + self.io_size);
+            let n = client.write(self.fid, self.position, &buffer[total..end])?;
+            if n == 0 {
+                return Err(VfsError::NoSpace);
+            }
+            total += n;
+            self.position += n as u64;
+        }
+        Ok(total)
+    }
+
+    fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
+        let base = match whence {
+            SeekFrom::Start => 0,
+            SeekFrom::Current => self.position as i64,
+            SeekFrom::End => self.stat()?.size as i64,
+        };
+        let position = base + offset;
+        if position < 0 {
+            return Err(VfsError::InvalidArgument);
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+
+    fn tell(&self) -> u64 {
+        self.position
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        self.client.lock().rpc(Message::new(TFSYNC, TAG).u32(self.fid).u32(0))?;
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        self.client.lock().getattr(self.fid)
+    }
+
+    fn truncate(&mut self, size: u64) -> VfsResult<()> {
+        self.client.lock().setattr(self.fid, SETATTR_SIZE, 0, 0, 0, size)
+    }
+}
+
+impl Drop for NinePFile {
+    fn drop(&mut self) {
+        self.client.lock().clunk(self.fid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::format;
+
+    const ENOENT: u32 = 2;
+    const EEXIST: u32 = 17;
+    const ENOTEMPTY: u32 = 39;
+    const EOPNOTSUPP: u32 = 95;
+
+    /// A 9P2000.L server over an in-memory tree, with a small msize so
+    /// reads and writes are split
+    #[derive(Default)]
+    struct State {
+        /// Path (without a leading slash) to contents; None for directories
+        nodes: BTreeMap<String, Option<Vec<u8>>>,
+        fids: BTreeMap<u32, String>,
+    }
+
+    #[derive(Clone)]
+    struct FakeServer(Arc<Mutex<State>>);
+
+    impl FakeServer {
+        fn new() -> Self {
+            let mut state = State::default();
+            state.nodes.insert(String::new(), None);
+            state.nodes.insert(String::from("hello.txt"), Some(b"hello, host\n".to_vec()));
+            state.nodes.insert(String::from("big.bin"), Some((0..20000u32).map(|i| i as u8).collect()));
+            state.nodes.insert(String::from("src"), None);
+            FakeServer(Arc::new(Mutex::new(state)))
+        }
+
+        fn open_fids(&self) -> usize {
+            self.0.lock().fids.len()
+        }
+    }
+
+    fn join(dir: &str, name: &str) -> String {
+        if dir.is_empty() {
+            String::from(name)
+        } else {
+            format!("{}/{}", dir, name)
+        }
+    }
+
+    fn parent(path: &str) -> &str {
+        path.rfind('/').map_or("", |i| &path[..i])
+    }
+
+    fn qid(nodes: &BTreeMap<String, Option<Vec<u8>>>, path: &str) -> Qid {
+        let kind = if nodes[path].is_none() { 0x80 } else { 0 };
+        let index = nodes.keys().position(|k| k == path).unwrap();
+        Qid { kind, version: 0, path: index as u64 + 1 }
+    }
+
+    fn put_qid(m: Message, q: Qid) -> Message {
+        m.u8(q.kind).u32(q.version).u64(q.path)
+    }
+
+    impl State {
+        fn handle(&mut self, kind: u8, r: &mut Reader) -> Result<Message, u32> {
+            let reply = Message::new(kind + 1, TAG);
+            match kind {
+                TVERSION => {
+                    r.u32().unwrap();
+                    Ok(Message::new(kind + 1, NOTAG).u32(8192).str(VERSION))
+                }
+                TATTACH => {
+                    self.fids.insert(r.u32().unwrap(), String::new());
+                    Ok(reply)
+                }
+                TWALK => {
+                    let (fid, newfid) = (r.u32().unwrap(), r.u32().unwrap());
+                    let mut path = self.fids[&fid].clone();
+                    let mut qids = Vec::new();
+                    let nwname = r.u16().unwrap() as usize;
+                    for _ in 0..nwname {
+                        let name = r.str().unwrap();
+                        let next = if name == ".." { String::from(parent(&path)) } else { join(&path, &name) };
+                        if !self.nodes.contains_key(&next) {
+                            break;
+                        }
+                        qids.push(qid(&self.nodes, &next));
+                        path = next;
+                    }
+                    let wanted = qids.len() == nwname;
+                    if qids.is_empty() && !wanted {
+                        return Err(ENOENT);
+                    }
+                    let mut reply = reply.u16(qids.len() as u16);
+                    for q in &qids {
+                        reply = put_qid(reply, *q);
+                    }
+                    if wanted {
+                        self.fids.insert(newfid, path);
+                    }
+                    Ok(reply)
+                }
+                TLOPEN => {
+                    let path = self.fids[&r.u32().unwrap()].clone();
+                    if r.u32().unwrap() & O_TRUNC != 0 {
+                        self.nodes.insert(path.clone(), Some(Vec::new()));
+                    }
+                    Ok(put_qid(reply, qid(&self.nodes, &path)).u32(0))
+                }
+                TLCREATE => {
+                    let fid = r.u32().unwrap();
+                    let path = join(&self.fids[&fid], &r.str().unwrap());
+                    if self.nodes.contains_key(&path) {
+                        return Err(EEXIST);
+                    }
+                    self.nodes.insert(path.clone(), Some(Vec::new()));
+                    self.fids.insert(fid, path.clone());
+                    Ok(put_qid(reply, qid(&self.nodes, &path)).u32(0))
+                }
+                TMKDIR => {
+                    let path = join(&self.fids[&r.u32().unwrap()], &r.str().unwrap());
+                    if self.nodes.contains_key(&path) {
+                        return Err(EEXIST);
+                    }
+                    self.nodes.insert(path.clone(), None);
+                    Ok(put_qid(reply, qid(&self.nodes, &path)))
+                }
+                TREAD => {
+                    let path = &self.fids[&r.u32().unwrap()];
+                    let (offset, count) = (r.u64().unwrap() as usize, r.u32().unwrap() as usize);
+                    let data = self.nodes[path].as_ref().unwrap();
+                    let chunk = &data[offset.min(data.len())..(offset + count).min(data.len())];
+                    Ok(reply.u32(chunk.len() as u32).raw(chunk))
+                }
+                TWRITE => {
+                    let path = self.fids[&r.u32().unwrap()].clone();
+                    let (offset, count) = (r.u64().unwrap() as usize, r.u32().unwrap() as usize);
+                    let data = self.nodes.get_mut(&path).unwrap().as_mut().unwrap();
+                    if data.len() < offset + count {
+                        data.resize(offset + count, 0);
+                    }
+                    data[offset..offset + count].copy_from_slice(r.bytes(count).unwrap());
+                    Ok(reply.u32(count as u32))
+                }
+                TGETATTR => {
+                    let path = &self.fids[&r.u32().unwrap()];
+                    let (mode, size) = match &self.nodes[path] {
+                        Some(data) => (0o100644, data.len() as u64),
+                        None => (0o040755, 0),
+                    };
+                    let reply = put_qid(reply.u64(GETATTR_BASIC), qid(&self.nodes, path));
+                    let reply = reply.u32(mode).u32(1000).u32(1000).u64(1).u64(0).u64(size).u64(4096);
+                    // blocks, four times, gen and data version
+                    Ok(reply.u64(size.div_ceil(512)).raw(&[0; 8 * 10]))
+                }
+                TREADDIR => {
+                    let path = self.fids[&r.u32().unwrap()].clone();
+                    let (offset, count) = (r.u64().unwrap() as usize, r.u32().unwrap() as usize);
+                    let mut entries = Message::new(0, 0);
+                    let mut len = 0;
+                    let children = self.nodes.keys().filter(|k| !k.is_empty() && parent(k) == path);
+                    for (i, child) in children.enumerate().skip(offset) {
+                        let name = &child[child.rfind('/').map_or(0, |i| i + 1)..];
+                        let entry_len = Qid::SIZE + 8 + 1 + 2 + name.len();
+                        if len + entry_len > count {
+                            break;
+                        }
+                        let kind = if self.nodes[child].is_none() { 4 } else { 8 };
+                        entries = put_qid(entries, qid(&self.nodes, child)).u64(i as u64 + 1).u8(kind).str(name);
+                        len += entry_len;
+                    }
+                    let entries = entries.finish();
+                    Ok(reply.u32(len as u32).raw(&entries[HEADER_SIZE..]))
+                }
+                TUNLINKAT => {
+                    let path = join(&self.fids[&r.u32().unwrap()], &r.str().unwrap());
+                    if !self.nodes.contains_key(&path) {
+                        return Err(ENOENT);
+                    }
+                    if self.nodes.keys().any(|k| parent(k) == path && !k.is_empty()) {
+                        return Err(ENOTEMPTY);
+                    }
+                    self.nodes.remove(&path);
+                    Ok(reply)
+                }
+                TCLUNK => {
+                    self.fids.remove(&r.u32().unwrap());
+                    Ok(reply)
+                }
+                _ => Err(EOPNOTSUPP),
+            }
+        }
+    }
+
+    impl Transport for FakeServer {
+        fn request(&mut self, request: &[u8], reply: &mut [u8]) -> VfsResult<usize> {
+            let mut r = Reader::new(&request[HEADER_SIZE..]);
+            let message = match self.0.lock().handle(request[4], &mut r) {
+                Ok(message) => message,
+                Err(errno) => Message::new(RLERROR, TAG).u32(errno),
+            };
+            let message = message.finish();
+            reply[..message.len()].copy_from_slice(&message);
+            Ok(message.len())
+        }
+    }
+
+    fn read_all(fs: &NinePFs, path: &str) -> Vec<u8> {
+        let mut file = fs.open(path, FileMode::READ).unwrap();
+        let mut data = vec![0u8; file.stat().unwrap().size as usize + 10];
+        let n = file.read(&mut data).unwrap();
+        data.truncate(n);
+        data
+    }
+
+    #[test]
+    fn reads_and_lists_host_files() {
+        let server = FakeServer::new();
+        let fs = NinePFs::mount(Box::new(server.clone()), "").unwrap();
+
+        let mut entries = fs.readdir("/").unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: Vec<_> = entries.iter().map(|e| (e.name.as_str(), e.file_type, e.size)).collect();
+        assert_eq!(
+            names,
+            [
+                ("big.bin", FileType::Regular, 20000),
+                ("hello.txt", FileType::Regular, 12),
+                ("src", FileType::Directory, 0),
+            ]
+        );
+
+        assert_eq!(read_all(&fs, "/hello.txt"), b"hello, host\n");
+        // Larger than the negotiated msize: several Treads
+        let big = read_all(&fs, "/big.bin");
+        assert_eq!(big.len(), 20000);
+        assert!(big.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        assert_eq!(fs.stat("/src").unwrap().file_type, FileType::Directory);
+        assert!(matches!(fs.stat("/missing"), Err(VfsError::NotFound)));
+        assert!(matches!(fs.stat("/src/missing/deeper"), Err(VfsError::NotFound)));
+        assert!(matches!(fs.rename("/hello.txt", "/bye.txt"), Err(VfsError::NotSupported)));
+
+        // Only the root fid is left
+        assert_eq!(server.open_fids(), 1);
+    }
+
+    #[test]
+    fn creates_writes_and_removes() {
+        let server = FakeServer::new();
+        let fs = NinePFs::mount(Box::new(server.clone()), "").unwrap();
+
+        let data: Vec<u8> = (0..10000u32).map(|i| (i * 7) as u8).collect();
+        let mut file = fs.open("/src/new.rs", FileMode::WRITE).unwrap();
+        assert_eq!(file.write(&data).unwrap(), data.len());
+        drop(file);
+        assert_eq!(read_all(&fs, "/src/new.rs"), data);
+
+        let exclusive = FileMode::CREATE_NEW;
+        assert!(matches!(fs.open("/src/new.rs", exclusive), Err(VfsError::AlreadyExists)));
+
+        let mut file = fs.open("/src/new.rs", FileMode::APPEND).unwrap();
+        file.write(b"tail").unwrap();
+        drop(file);
+        assert_eq!(fs.stat("/src/new.rs").unwrap().size, 10004);
+
+        // Deeper than one Twalk can go
+        let mut path = String::new();
+        for i in 0..20 {
+            path = format!("{}/d{}", path, i);
+            fs.mkdir(&path).unwrap();
+        }
+        let deep = format!("{}/leaf", path);
+        fs.open(&deep, FileMode::WRITE).unwrap();
+        assert_eq!(fs.stat(&deep).unwrap().file_type, FileType::Regular);
+
+        assert!(matches!(fs.rmdir(&path), Err(VfsError::DirectoryNotEmpty)));
+        fs.unlink(&deep).unwrap();
+        fs.rmdir(&path).unwrap();
+        fs.unlink("/src/new.rs").unwrap();
+        assert!(matches!(fs.stat("/src/new.rs"), Err(VfsError::NotFound)));
+
+        assert_eq!(server.open_fids(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+//! 9P2000.L message encoding
+//!
+//! Every message is `size[4] type[1] tag[2]` followed by its fields, all
+//! little-endian; strings are `len[2]` and UTF-8 bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use watos_vfs::{VfsError, VfsResult};
+
+/// Tag of Tversion, which precedes tag bookkeeping
+pub const NOTAG: u16 = 0xFFFF;
+
+/// No fid, for Tattach without authentication
+pub const NOFID: u32 = !0;
+
+/// Size of the message header
+pub const HEADER_SIZE: usize = 7;
+
+/// Header bytes of Rread and Twrite in front of the data
+pub const IO_HEADER_SIZE: usize = 24;
+
+/// Most names in one Twalk
+pub const MAX_WALK: usize = 16;
+
+// Message types; each R-message is its T-message plus one
+pub const RLERROR: u8 = 7;
+pub const TSTATFS: u8 = 8;
+pub const TLOPEN: u8 = 12;
+pub const TLCREATE: u8 = 14;
+pub const TREADLINK: u8 = 22;
+pub const TGETATTR: u8 = 24;
+pub const TSETATTR: u8 = 26;
+pub const TREADDIR: u8 = 40;
+pub const TFSYNC: u8 = 50;
+pub const TLINK: u8 = 70;
+pub const TMKDIR: u8 = 72;
+pub const TRENAMEAT: u8 = 74;
+pub const TUNLINKAT: u8 = 76;
+pub const TVERSION: u8 = 100;
+pub const TATTACH: u8 = 104;
+pub const TWALK: u8 = 110;
+pub const TREAD: u8 = 116;
+pub const TWRITE: u8 = 118;
+pub const TCLUNK: u8 = 120;
+
+// Tgetattr request mask: mode through blocks
+pub const GETATTR_BASIC: u64 = 0x7FF;
+
+// Tsetattr valid bits
+pub const SETATTR_MODE: u32 = 0x01;
+pub const SETATTR_UID: u32 = 0x02;
+pub const SETATTR_GID: u32 = 0x04;
+pub const SETATTR_SIZE: u32 = 0x08;
+pub const SETATTR_ATIME: u32 = 0x10;
+pub const SETATTR_MTIME: u32 = 0x20;
+/// The times given, rather than the server's clock
+pub const SETATTR_ATIME_SET: u32 = 0x80;
+pub const SETATTR_MTIME_SET: u32 = 0x100;
+
+// Linux open flags, as 9P2000.L uses them
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 1;
+pub const O_RDWR: u32 = 2;
+pub const O_CREAT: u32 = 0o100;
+pub const O_EXCL: u32 = 0o200;
+pub const O_TRUNC: u32 = 0o1000;
+pub const O_DIRECTORY: u32 = 0o200000;
+
+/// Tunlinkat flag to remove a directory
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+/// Server's identity for a file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Qid {
+    pub kind: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+impl Qid {
+    pub const SIZE: usize = 13;
+}
+
+/// Builds one T-message
+pub struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(kind: u8, tag: u16) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&[0; 4]);
+        buf.push(kind);
+        buf.extend_from_slice(&tag.to_le_bytes());
+        Message { buf }
+    }
+
+    pub fn kind(&self) -> u8 {
+        self.buf[4]
+    }
+
+    pub fn u8(mut self, value: u8) -> Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn u16(mut self, value: u16) -> Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn str(self, value: &str) -> Self {
+        self.u16(value.len() as u16).raw(value.as_bytes())
+    }
+
+    pub fn raw(mut self, data: &[u8]) -> Self {
+        self.buf.extend_from_slice(data);
+        self
+    }
+
+    /// The message with its size filled in
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&size.to_le_bytes());
+        self.buf
+    }
+}
+
+/// Reads the fields of an R-message body
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> VfsResult<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(VfsError::IoError);
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> VfsResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> VfsResult<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> VfsResult<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> VfsResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn str(&mut self) -> VfsResult<String> {
+        let len = self.u16()? as usize;
+        let bytes = self.bytes(len)?;
+        core::str::from_utf8(bytes).map(String::from).map_err(|_| VfsError::IoError)
+    }
+
+    pub fn qid(&mut self) -> VfsResult<Qid> {
+        Ok(Qid { kind: self.u8()?, version: self.u32()?, path: self.u64()? })
+    }
+}
+
+/// VFS error for a Linux errno from Rlerror
+pub fn errno_to_error(errno: u32) -> VfsError {
+    match errno {
+        1 | 13 => VfsError::PermissionDenied,
+        2 => VfsError::NotFound,
+        16 => VfsError::Busy,
+        17 => VfsError::AlreadyExists,
+        18 => VfsError::CrossDevice,
+        20 => VfsError::NotADirectory,
+        21 => VfsError::IsADirectory,
+        22 => VfsError::InvalidArgument,
+        24 => VfsError::TooManyOpenFiles,
+        28 | 122 => VfsError::NoSpace,
+        30 => VfsError::ReadOnly,
+        36 => VfsError::NameTooLong,
+        39 => VfsError::DirectoryNotEmpty,
+        38 | 95 => VfsError::NotSupported,
+        _ => VfsError::IoError,
+    }
+}
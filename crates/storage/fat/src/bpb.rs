@@ -1,4 +1,4 @@
-//! BIOS Parameter Block parsing
+//! BIOS Parameter Block parsing and serialization
 
 use watos_vfs::{VfsError, VfsResult};
 
@@ -45,6 +45,8 @@ pub struct BiosParameterBlock {
     pub fs_info_sector: u16,
     /// FAT32: Backup boot sector
     pub backup_boot_sector: u16,
+    /// Volume serial number
+    pub volume_id: u32,
     /// Volume label
     pub volume_label: [u8; 11],
     /// Filesystem type string
@@ -87,7 +89,7 @@ impl BiosParameterBlock {
         ]);
 
         // FAT32 extended fields
-        let (fat_size_32, root_cluster, fs_info_sector, backup_boot_sector, volume_label, fs_type) =
+        let (fat_size_32, root_cluster, fs_info_sector, backup_boot_sector, volume_id, volume_label, fs_type) =
             if fat_size_16 == 0 {
                 // FAT32
                 let fat_size_32 = u32::from_le_bytes([
@@ -104,6 +106,12 @@ impl BiosParameterBlock {
                 ]);
                 let fs_info_sector = u16::from_le_bytes([boot_sector[48], boot_sector[49]]);
                 let backup_boot_sector = u16::from_le_bytes([boot_sector[50], boot_sector[51]]);
+                let volume_id = u32::from_le_bytes([
+                    boot_sector[67],
+                    boot_sector[68],
+                    boot_sector[69],
+                    boot_sector[70],
+                ]);
 
                 let mut volume_label = [0u8; 11];
                 volume_label.copy_from_slice(&boot_sector[71..82]);
@@ -116,18 +124,26 @@ impl BiosParameterBlock {
                     root_cluster,
                     fs_info_sector,
                     backup_boot_sector,
+                    volume_id,
                     volume_label,
                     fs_type,
                 )
             } else {
                 // FAT12/16
+                let volume_id = u32::from_le_bytes([
+                    boot_sector[39],
+                    boot_sector[40],
+                    boot_sector[41],
+                    boot_sector[42],
+                ]);
+
                 let mut volume_label = [0u8; 11];
                 volume_label.copy_from_slice(&boot_sector[43..54]);
 
                 let mut fs_type = [0u8; 8];
                 fs_type.copy_from_slice(&boot_sector[54..62]);
 
-                (0, 0, 0, 0, volume_label, fs_type)
+                (0, 0, 0, 0, volume_id, volume_label, fs_type)
             };
 
         // Validate basic fields
@@ -156,38 +172,111 @@ impl BiosParameterBlock {
             root_cluster,
             fs_info_sector,
             backup_boot_sector,
+            volume_id,
             volume_label,
             fs_type,
         })
     }
 
-    /// Determine FAT type based on BPB fields and cluster count
-    pub fn fat_type(&self) -> FatType {
-        // FAT32 is definitively indicated by fat_size_16 == 0
-        // This is more reliable than cluster count alone
-        if self.fat_size_16 == 0 {
-            return FatType::Fat32;
+    /// Serialize into a boot sector (inverse of `parse`)
+    ///
+    /// Writes the jump instruction, OEM name, BPB, extended boot record and
+    /// boot signature. The boot code area is left for the caller; the
+    /// variant layout is chosen the same way `parse` does (FAT32 iff
+    /// `fat_size_16 == 0`).
+    pub fn write(&self, boot_sector: &mut [u8]) -> VfsResult<()> {
+        if boot_sector.len() < 512 {
+            return Err(VfsError::InvalidArgument);
         }
 
-        // For FAT12/16, use cluster count to distinguish
-        let root_dir_sectors = ((self.root_entry_count as u32 * 32)
-            + (self.bytes_per_sector as u32 - 1))
-            / self.bytes_per_sector as u32;
+        let is_fat32 = self.fat_size_16 == 0;
+
+        // Short jump over the BPB to the boot code
+        let jump_target = if is_fat32 { 0x58 } else { 0x3C };
+        boot_sector[0..3].copy_from_slice(&[0xEB, jump_target, 0x90]);
+        boot_sector[3..11].copy_from_slice(b"WATOS   ");
+
+        boot_sector[11..13].copy_from_slice(&self.bytes_per_sector.to_le_bytes());
+        boot_sector[13] = self.sectors_per_cluster;
+        boot_sector[14..16].copy_from_slice(&self.reserved_sector_count.to_le_bytes());
+        boot_sector[16] = self.num_fats;
+        boot_sector[17..19].copy_from_slice(&self.root_entry_count.to_le_bytes());
+        boot_sector[19..21].copy_from_slice(&self.total_sectors_16.to_le_bytes());
+        boot_sector[21] = self.media_type;
+        boot_sector[22..24].copy_from_slice(&self.fat_size_16.to_le_bytes());
+        boot_sector[24..26].copy_from_slice(&self.sectors_per_track.to_le_bytes());
+        boot_sector[26..28].copy_from_slice(&self.num_heads.to_le_bytes());
+        boot_sector[28..32].copy_from_slice(&self.hidden_sectors.to_le_bytes());
+        boot_sector[32..36].copy_from_slice(&self.total_sectors_32.to_le_bytes());
 
-        let fat_size = self.fat_size_16 as u32;
+        // Extended boot record: drive number, reserved, signature, id, label, type
+        let ebr = if is_fat32 {
+            boot_sector[36..40].copy_from_slice(&self.fat_size_32.to_le_bytes());
+            boot_sector[40..44].fill(0); // ext flags (mirrored FATs), version 0.0
+            boot_sector[44..48].copy_from_slice(&self.root_cluster.to_le_bytes());
+            boot_sector[48..50].copy_from_slice(&self.fs_info_sector.to_le_bytes());
+            boot_sector[50..52].copy_from_slice(&self.backup_boot_sector.to_le_bytes());
+            boot_sector[52..64].fill(0);
+            64
+        } else {
+            36
+        };
+        boot_sector[ebr] = 0x80;
+        boot_sector[ebr + 1] = 0;
+        boot_sector[ebr + 2] = 0x29;
+        boot_sector[ebr + 3..ebr + 7].copy_from_slice(&self.volume_id.to_le_bytes());
+        boot_sector[ebr + 7..ebr + 18].copy_from_slice(&self.volume_label);
+        boot_sector[ebr + 18..ebr + 26].copy_from_slice(&self.fs_type);
 
-        let total_sectors = if self.total_sectors_16 != 0 {
+        boot_sector[510] = 0x55;
+        boot_sector[511] = 0xAA;
+        Ok(())
+    }
+
+    /// Sectors occupied by the FAT12/16 root directory (0 on FAT32)
+    pub fn root_dir_sectors(&self) -> u32 {
+        (self.root_entry_count as u32 * 32).div_ceil(self.bytes_per_sector as u32)
+    }
+
+    /// Size of one FAT in sectors
+    pub fn fat_size(&self) -> u32 {
+        if self.fat_size_16 != 0 {
+            self.fat_size_16 as u32
+        } else {
+            self.fat_size_32
+        }
+    }
+
+    /// Total sectors in the volume
+    pub fn total_sectors(&self) -> u32 {
+        if self.total_sectors_16 != 0 {
             self.total_sectors_16 as u32
         } else {
             self.total_sectors_32
-        };
+        }
+    }
 
-        let data_sectors = total_sectors
-            - self.reserved_sector_count as u32
-            - (self.num_fats as u32 * fat_size)
-            - root_dir_sectors;
+    /// Number of data clusters
+    pub fn cluster_count(&self) -> u32 {
+        let data_sectors = self
+            .total_sectors()
+            .saturating_sub(self.reserved_sector_count as u32)
+            .saturating_sub(self.num_fats as u32 * self.fat_size())
+            .saturating_sub(self.root_dir_sectors());
+
+        data_sectors / self.sectors_per_cluster as u32
+    }
 
-        let cluster_count = data_sectors / self.sectors_per_cluster as u32;
+    /// Determine FAT type based on BPB fields and cluster count
+    pub fn fat_type(&self) -> FatType {
+        // FAT32 is definitively indicated by fat_size_16 == 0
+        // This is more reliable than cluster count alone
+        if self.fat_size_16 == 0 {
+            return FatType::Fat32;
+        }
+
+        // For FAT12/16, use cluster count to distinguish
+        let cluster_count = self.cluster_count();
 
         if cluster_count < 4085 {
             FatType::Fat12
@@ -2,6 +2,7 @@
 
 use alloc::string::String;
 use watos_vfs::{DirEntry, FileType};
+use watos_time::Timestamp;
 
 use crate::bpb::{BiosParameterBlock, FatType};
 
@@ -132,6 +133,22 @@ impl FatDirEntry {
         ((self.first_cluster_high as u32) << 16) | (self.first_cluster_low as u32)
     }
 
+    /// Creation time (seconds since epoch), 0 if never set
+    pub fn create_time(&self) -> u64 {
+        Timestamp::from_fat(self.creation_date, self.creation_time, self.creation_time_tenths)
+            .map_or(0, |t| t.to_wfs())
+    }
+
+    /// Last modification time (seconds since epoch), 0 if never set
+    pub fn modify_time(&self) -> u64 {
+        Timestamp::from_fat(self.modification_date, self.modification_time, 0).map_or(0, |t| t.to_wfs())
+    }
+
+    /// Last access time (seconds since epoch); FAT keeps only the date
+    pub fn access_time(&self) -> u64 {
+        Timestamp::from_fat(self.last_access_date, 0, 0).map_or(0, |t| t.to_wfs())
+    }
+
     /// Get the 8.3 filename as a string
     pub fn short_name(&self) -> String {
         let name_part = &self.name[0..8];
@@ -0,0 +1,342 @@
+//! Volume formatting
+//!
+//! Lays out an empty FAT12/16/32 volume on a block device: boot sector
+//! (plus FSInfo and backup copies on FAT32), both FATs, and an empty root
+//! directory holding the volume label. The result is what `FatFilesystem::new`
+//! expects, and what UEFI firmware expects of an EFI System Partition.
+//!
+//! With `FormatOptions::journal` an empty metadata journal goes in the
+//! reserved area, which FAT12/16 grow by `JOURNAL_SECTORS` to make room.
+
+use alloc::string::String;
+use alloc::vec;
+
+use watos_driver_traits::block::BlockDevice;
+use watos_vfs::{VfsError, VfsResult};
+
+use crate::bpb::{BiosParameterBlock, FatType};
+use crate::dir::attrs;
+use crate::fsinfo::FsInfo;
+use crate::journal::{Journal, JOURNAL_SECTORS};
+
+/// Only 512-byte sectors are supported (the driver reads 512-byte sectors)
+const SECTOR_SIZE: usize = 512;
+
+/// Sectors zeroed per write when clearing FATs
+const ZERO_CHUNK: u32 = 64;
+
+/// FAT12 volumes must have fewer clusters than this
+const FAT12_MAX_CLUSTERS: u32 = 4085;
+/// FAT16 volumes must have fewer clusters than this
+const FAT16_MAX_CLUSTERS: u32 = 65525;
+/// Largest valid FAT32 cluster count (28-bit entries, minus reserved values)
+const FAT32_MAX_CLUSTERS: u32 = 0x0FFF_FFF5;
+
+/// FAT32 layout: boot sector, FSInfo, backups at 6/7, 32 reserved sectors
+const FAT32_RESERVED_SECTORS: u16 = 32;
+const FAT32_FS_INFO_SECTOR: u16 = 1;
+const FAT32_BACKUP_BOOT_SECTOR: u16 = 6;
+const FAT32_ROOT_CLUSTER: u32 = 2;
+
+/// Fixed disk media descriptor
+const MEDIA_FIXED: u8 = 0xF8;
+
+/// `int 0x18`: hand control back to the BIOS if this volume is booted as a
+/// legacy boot sector (UEFI loads files from the volume instead)
+const BOOT_CODE: [u8; 2] = [0xCD, 0x18];
+
+/// Options for `format`
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    /// FAT variant; chosen from the volume size if `None`
+    pub fat_type: Option<FatType>,
+    /// Sectors per cluster (power of two, 1-128); chosen from the volume
+    /// size if `None`
+    pub sectors_per_cluster: Option<u8>,
+    /// Volume label, up to 11 characters (stored upper-case)
+    pub volume_label: Option<String>,
+    /// Volume serial number
+    pub volume_id: u32,
+    /// Keep a metadata journal in the reserved sectors
+    pub journal: bool,
+}
+
+/// Format the whole device as an empty FAT volume
+///
+/// Returns the BPB that was written.
+pub fn format<D: BlockDevice>(device: &mut D, options: &FormatOptions) -> VfsResult<BiosParameterBlock> {
+    let geometry = device.geometry();
+    if geometry.sector_size as usize != SECTOR_SIZE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let total_sectors = u32::try_from(geometry.total_sectors).map_err(|_| VfsError::InvalidArgument)?;
+
+    let label = match &options.volume_label {
+        Some(label) => Some(parse_label(label)?),
+        None => None,
+    };
+
+    let fat_type = match options.fat_type {
+        Some(fat_type) => fat_type,
+        None => default_fat_type(total_sectors),
+    };
+    let sectors_per_cluster = match options.sectors_per_cluster {
+        Some(spc) if spc.is_power_of_two() && spc <= 128 => spc,
+        Some(_) => return Err(VfsError::InvalidArgument),
+        None => default_sectors_per_cluster(fat_type, total_sectors)?,
+    };
+
+    let bpb = layout(fat_type, total_sectors, sectors_per_cluster, label, options.volume_id, options.journal)?;
+    write_volume(device, &bpb, fat_type, label, options.journal)?;
+    Ok(bpb)
+}
+
+/// Compute the BPB for a volume
+fn layout(
+    fat_type: FatType,
+    total_sectors: u32,
+    sectors_per_cluster: u8,
+    label: Option<[u8; 11]>,
+    volume_id: u32,
+    journal: bool,
+) -> VfsResult<BiosParameterBlock> {
+    let is_fat32 = fat_type == FatType::Fat32;
+    let (reserved_sector_count, root_entry_count) = match fat_type {
+        FatType::Fat12 => (1, 224),
+        FatType::Fat16 => (1, 512),
+        FatType::Fat32 => (FAT32_RESERVED_SECTORS, 0),
+    };
+    // FAT32's reserved area already has room after the backup sectors
+    let reserved_sector_count = if journal && !is_fat32 {
+        reserved_sector_count + JOURNAL_SECTORS
+    } else {
+        reserved_sector_count
+    };
+
+    let mut bpb = BiosParameterBlock {
+        bytes_per_sector: SECTOR_SIZE as u16,
+        sectors_per_cluster,
+        reserved_sector_count,
+        num_fats: 2,
+        root_entry_count,
+        total_sectors_16: 0,
+        media_type: MEDIA_FIXED,
+        fat_size_16: 0,
+        sectors_per_track: 63,
+        num_heads: 255,
+        hidden_sectors: 0,
+        total_sectors_32: 0,
+        fat_size_32: 0,
+        root_cluster: if is_fat32 { FAT32_ROOT_CLUSTER } else { 0 },
+        fs_info_sector: if is_fat32 { FAT32_FS_INFO_SECTOR } else { 0 },
+        backup_boot_sector: if is_fat32 { FAT32_BACKUP_BOOT_SECTOR } else { 0 },
+        volume_id,
+        volume_label: label.unwrap_or(*b"NO NAME    "),
+        fs_type: match fat_type {
+            FatType::Fat12 => *b"FAT12   ",
+            FatType::Fat16 => *b"FAT16   ",
+            FatType::Fat32 => *b"FAT32   ",
+        },
+    };
+
+    if !is_fat32 && total_sectors < 0x10000 {
+        bpb.total_sectors_16 = total_sectors as u16;
+    } else {
+        bpb.total_sectors_32 = total_sectors;
+    }
+
+    // Grow the FAT until it covers every cluster left over after it; each
+    // step only shrinks the data area, so this settles in a few rounds
+    let mut fat_size = 1u32;
+    loop {
+        set_fat_size(&mut bpb, fat_size)?;
+        let needed = fat_sectors(fat_type, bpb.cluster_count() + 2);
+        if needed <= fat_size {
+            break;
+        }
+        fat_size = needed;
+    }
+
+    let metadata = reserved_sector_count as u32 + 2 * fat_size + bpb.root_dir_sectors();
+    if metadata >= total_sectors {
+        return Err(VfsError::NoSpace);
+    }
+
+    // The driver tells FAT12 from FAT16 by cluster count, so the count must
+    // match the requested type
+    let clusters = bpb.cluster_count();
+    let valid = match fat_type {
+        FatType::Fat12 => (1..FAT12_MAX_CLUSTERS).contains(&clusters),
+        FatType::Fat16 => (FAT12_MAX_CLUSTERS..FAT16_MAX_CLUSTERS).contains(&clusters),
+        FatType::Fat32 => (FAT16_MAX_CLUSTERS..FAT32_MAX_CLUSTERS).contains(&clusters),
+    };
+    if !valid {
+        return Err(VfsError::InvalidArgument);
+    }
+
+    Ok(bpb)
+}
+
+fn set_fat_size(bpb: &mut BiosParameterBlock, fat_size: u32) -> VfsResult<()> {
+    if bpb.root_cluster != 0 {
+        bpb.fat_size_32 = fat_size;
+    } else {
+        bpb.fat_size_16 = u16::try_from(fat_size).map_err(|_| VfsError::InvalidArgument)?;
+    }
+    Ok(())
+}
+
+/// Sectors needed for a FAT with `entries` entries
+fn fat_sectors(fat_type: FatType, entries: u32) -> u32 {
+    let bytes = match fat_type {
+        FatType::Fat12 => (entries * 3).div_ceil(2),
+        FatType::Fat16 => entries * 2,
+        FatType::Fat32 => entries * 4,
+    };
+    bytes.div_ceil(SECTOR_SIZE as u32)
+}
+
+/// FAT variant for a volume size: FAT12 for floppies, FAT32 from 512 MiB
+fn default_fat_type(total_sectors: u32) -> FatType {
+    if total_sectors <= 8400 {
+        FatType::Fat12
+    } else if total_sectors < 1_048_576 {
+        FatType::Fat16
+    } else {
+        FatType::Fat32
+    }
+}
+
+/// Default cluster size
+///
+/// FAT32 follows the Microsoft table (4 KiB clusters up to 8 GiB, doubling
+/// up to 32 KiB). FAT12/16 use the smallest cluster that keeps the count in
+/// range for the type.
+fn default_sectors_per_cluster(fat_type: FatType, total_sectors: u32) -> VfsResult<u8> {
+    let max_clusters = match fat_type {
+        FatType::Fat12 => FAT12_MAX_CLUSTERS,
+        FatType::Fat16 => FAT16_MAX_CLUSTERS,
+        FatType::Fat32 => {
+            return Ok(match total_sectors {
+                0..=532_480 => 1,
+                532_481..=16_777_216 => 8,
+                16_777_217..=33_554_432 => 16,
+                33_554_433..=67_108_864 => 32,
+                _ => 64,
+            });
+        }
+    };
+
+    let mut spc: u32 = 1;
+    while spc <= 128 {
+        if total_sectors / spc < max_clusters {
+            return Ok(spc as u8);
+        }
+        spc *= 2;
+    }
+    Err(VfsError::InvalidArgument)
+}
+
+/// Normalize a volume label to 11 space-padded upper-case bytes
+fn parse_label(label: &str) -> VfsResult<[u8; 11]> {
+    if label.len() > 11 {
+        return Err(VfsError::NameTooLong);
+    }
+
+    let mut out = [b' '; 11];
+    for (slot, &c) in out.iter_mut().zip(label.as_bytes()) {
+        if !(0x20..0x7F).contains(&c) || b"\"*+,./:;<=>?[\\]|".contains(&c) {
+            return Err(VfsError::InvalidArgument);
+        }
+        *slot = c.to_ascii_uppercase();
+    }
+    Ok(out)
+}
+
+/// Write every on-disk structure for an already computed layout
+fn write_volume<D: BlockDevice>(
+    device: &mut D,
+    bpb: &BiosParameterBlock,
+    fat_type: FatType,
+    label: Option<[u8; 11]>,
+    journal: bool,
+) -> VfsResult<()> {
+    let mut boot = [0u8; SECTOR_SIZE];
+    bpb.write(&mut boot)?;
+    let code = if fat_type == FatType::Fat32 { 0x5A } else { 0x3E };
+    boot[code..code + BOOT_CODE.len()].copy_from_slice(&BOOT_CODE);
+
+    // Reserved area: boot sector, and on FAT32 the FSInfo sector and backups
+    zero_sectors(device, 0, bpb.reserved_sector_count as u32)?;
+    write_sector(device, 0, &boot)?;
+    if fat_type == FatType::Fat32 {
+        // Everything but the root directory cluster is free
+        let mut fs_info = [0u8; SECTOR_SIZE];
+        FsInfo { free_count: bpb.cluster_count() - 1, next_free: FAT32_ROOT_CLUSTER + 1 }.write(&mut fs_info);
+        let backup = bpb.backup_boot_sector as u64;
+        write_sector(device, bpb.fs_info_sector as u64, &fs_info)?;
+        write_sector(device, backup, &boot)?;
+        write_sector(device, backup + 1, &fs_info)?;
+    }
+    if journal {
+        Journal::create(device, bpb)?;
+    }
+
+    // FATs: media descriptor and end-of-chain in the two reserved entries,
+    // and on FAT32 end-of-chain for the root directory cluster
+    let mut first = [0u8; SECTOR_SIZE];
+    let reserved: &[u8] = match fat_type {
+        FatType::Fat12 => &[bpb.media_type, 0xFF, 0xFF],
+        FatType::Fat16 => &[bpb.media_type, 0xFF, 0xFF, 0xFF],
+        FatType::Fat32 => &[
+            bpb.media_type, 0xFF, 0xFF, 0x0F,
+            0xFF, 0xFF, 0xFF, 0x0F,
+            0xFF, 0xFF, 0xFF, 0x0F,
+        ],
+    };
+    first[..reserved.len()].copy_from_slice(reserved);
+
+    let fat_size = bpb.fat_size();
+    for copy in 0..bpb.num_fats as u32 {
+        let start = bpb.reserved_sector_count as u32 + copy * fat_size;
+        zero_sectors(device, start, fat_size)?;
+        write_sector(device, start as u64, &first)?;
+    }
+
+    // Root directory: fixed area on FAT12/16, cluster 2 on FAT32
+    let root_start = bpb.reserved_sector_count as u32 + bpb.num_fats as u32 * fat_size;
+    let root_sectors = match fat_type {
+        FatType::Fat32 => bpb.sectors_per_cluster as u32,
+        _ => bpb.root_dir_sectors(),
+    };
+    zero_sectors(device, root_start, root_sectors)?;
+
+    if let Some(label) = label {
+        let mut sector = [0u8; SECTOR_SIZE];
+        sector[0..11].copy_from_slice(&label);
+        sector[11] = attrs::VOLUME_ID;
+        write_sector(device, root_start as u64, &sector)?;
+    }
+
+    device.flush().map_err(|_| VfsError::IoError)
+}
+
+fn write_sector<D: BlockDevice>(device: &mut D, lba: u64, sector: &[u8; SECTOR_SIZE]) -> VfsResult<()> {
+    device
+        .write_sectors(lba, sector)
+        .map(|_| ())
+        .map_err(|_| VfsError::IoError)
+}
+
+fn zero_sectors<D: BlockDevice>(device: &mut D, start: u32, count: u32) -> VfsResult<()> {
+    let zeros = vec![0u8; ZERO_CHUNK as usize * SECTOR_SIZE];
+    let mut done = 0;
+    while done < count {
+        let n = (count - done).min(ZERO_CHUNK);
+        device
+            .write_sectors((start + done) as u64, &zeros[..n as usize * SECTOR_SIZE])
+            .map_err(|_| VfsError::IoError)?;
+        done += n;
+    }
+    Ok(())
+}
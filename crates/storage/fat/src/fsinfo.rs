@@ -0,0 +1,62 @@
+//! FAT32 FSInfo sector
+//!
+//! Caches the free cluster count and an allocation hint so neither needs a
+//! full FAT scan on every mount. Both fields are hints: either may be
+//! `FsInfo::UNKNOWN`, and a count larger than the volume is ignored.
+
+/// "RRaA" at offset 0
+const LEAD_SIGNATURE: u32 = 0x4161_5252;
+/// "rrAa" at offset 484
+const STRUCT_SIGNATURE: u32 = 0x6141_7272;
+/// 0x55 0xAA at the end of the sector
+const TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+
+/// The two hint fields of the FSInfo sector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsInfo {
+    /// Free clusters on the volume, or `FsInfo::UNKNOWN`
+    pub free_count: u32,
+    /// Cluster to start looking for a free one, or `FsInfo::UNKNOWN`
+    pub next_free: u32,
+}
+
+impl FsInfo {
+    /// Value of a field whose contents are not known
+    pub const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+    /// Parse an FSInfo sector, or `None` if its signatures are wrong
+    pub fn parse(sector: &[u8]) -> Option<Self> {
+        if sector.len() < 512 {
+            return None;
+        }
+        let field = |at: usize| u32::from_le_bytes([sector[at], sector[at + 1], sector[at + 2], sector[at + 3]]);
+
+        if field(0) != LEAD_SIGNATURE || field(484) != STRUCT_SIGNATURE || field(508) != TRAIL_SIGNATURE {
+            return None;
+        }
+
+        Some(FsInfo {
+            free_count: field(488),
+            next_free: field(492),
+        })
+    }
+
+    /// Write signatures and hints into a sector, leaving reserved bytes alone
+    pub fn write(&self, sector: &mut [u8]) {
+        sector[0..4].copy_from_slice(&LEAD_SIGNATURE.to_le_bytes());
+        sector[484..488].copy_from_slice(&STRUCT_SIGNATURE.to_le_bytes());
+        sector[488..492].copy_from_slice(&self.free_count.to_le_bytes());
+        sector[492..496].copy_from_slice(&self.next_free.to_le_bytes());
+        sector[508..512].copy_from_slice(&TRAIL_SIGNATURE.to_le_bytes());
+    }
+
+    /// Free count, if known and possible on a volume of `cluster_count`
+    pub fn valid_free_count(&self, cluster_count: u32) -> Option<u32> {
+        (self.free_count <= cluster_count).then_some(self.free_count)
+    }
+
+    /// Next-free hint, if it names a data cluster
+    pub fn valid_next_free(&self, cluster_count: u32) -> Option<u32> {
+        (2..cluster_count + 2).contains(&self.next_free).then_some(self.next_free)
+    }
+}
@@ -0,0 +1,278 @@
+//! Metadata intent journal
+//!
+//! FAT keeps no journal of its own, so a power cut between writing a
+//! directory sector and the FAT sectors it depends on leaves the two
+//! disagreeing. A volume formatted with a journal keeps a small redo log in
+//! the last `JOURNAL_SECTORS` of its reserved area:
+//!
+//! 1. the new images of every sector in a transaction are written to the log
+//! 2. the header is rewritten as committed, listing their home sectors
+//! 3. the sectors are written in place
+//! 4. the header is marked clean
+//!
+//! with a flush after each step. Mounting replays a committed transaction
+//! that was never marked clean, so a transaction lands whole or not at all.
+//! Other systems ignore reserved sectors: to them the volume is plain FAT,
+//! they just don't replay the log.
+//!
+//! Header sector layout (little-endian):
+//!
+//! | Offset | Size   | Field                                       |
+//! |--------|--------|---------------------------------------------|
+//! | 0      | 4      | Magic "WFJL"                                |
+//! | 4      | 4      | State: 0 clean, 1 committed                 |
+//! | 8      | 4      | Sequence number of the last transaction     |
+//! | 12     | 4      | Records in the transaction                  |
+//! | 16     | 4      | CRC32 of the home sectors and record images |
+//! | 32     | 8 * n  | Home sector of each record                  |
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use watos_driver_traits::block::BlockDevice;
+use watos_vfs::{VfsError, VfsResult};
+
+use crate::bpb::{BiosParameterBlock, FatType};
+
+/// Only 512-byte sectors are journaled
+const SECTOR_SIZE: usize = 512;
+
+/// Sectors the journal occupies: a header and its records
+pub const JOURNAL_SECTORS: u16 = 1 + MAX_RECORDS as u16;
+
+/// Most sectors one transaction can change
+pub const MAX_RECORDS: usize = 16;
+
+/// "WFJL"
+const MAGIC: u32 = 0x4C4A_4657;
+
+const STATE_CLEAN: u32 = 0;
+const STATE_COMMITTED: u32 = 1;
+
+/// First byte of the home sector list in the header
+const TARGETS_OFFSET: usize = 32;
+
+/// Sector updates to apply together
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    writes: Vec<(u64, [u8; SECTOR_SIZE])>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the new contents of a sector; a later write to the same sector
+    /// replaces the earlier one
+    pub fn write(&mut self, sector: u64, data: &[u8; SECTOR_SIZE]) {
+        match self.writes.iter_mut().find(|(s, _)| *s == sector) {
+            Some((_, old)) => *old = *data,
+            None => self.writes.push((sector, *data)),
+        }
+    }
+
+    /// Number of distinct sectors changed
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Each changed sector and its new contents, in the order first written
+    pub fn sectors(&self) -> impl Iterator<Item = (u64, &[u8; SECTOR_SIZE])> {
+        self.writes.iter().map(|(sector, data)| (*sector, data))
+    }
+}
+
+/// A volume's journal, found by `Journal::locate`
+#[derive(Debug, Clone, Copy)]
+pub struct Journal {
+    /// Header sector
+    start: u64,
+    /// Sequence number of the last transaction
+    sequence: u32,
+}
+
+impl Journal {
+    /// Where a journal would live on a volume, if its reserved area has room
+    ///
+    /// The log takes the end of the reserved area, after the boot sector
+    /// and, on FAT32, after the FSInfo sector and the backups at 6/7.
+    fn start(bpb: &BiosParameterBlock) -> Option<u64> {
+        let in_use = match bpb.fat_type() {
+            FatType::Fat32 => 1 + (bpb.fs_info_sector.max(bpb.backup_boot_sector + 1)) as u32,
+            _ => 1,
+        };
+        let reserved = bpb.reserved_sector_count as u32;
+        (reserved >= in_use + JOURNAL_SECTORS as u32).then(|| (reserved - JOURNAL_SECTORS as u32) as u64)
+    }
+
+    /// Find the journal of a mounted volume, `None` if it has none
+    pub fn locate<D: BlockDevice>(device: &mut D, bpb: &BiosParameterBlock) -> VfsResult<Option<Self>> {
+        let start = match Self::start(bpb) {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+
+        let header = read_sector(device, start)?;
+        if field(&header, 0) != MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(Journal { start, sequence: field(&header, 8) }))
+    }
+
+    /// Write an empty journal while formatting
+    pub fn create<D: BlockDevice>(device: &mut D, bpb: &BiosParameterBlock) -> VfsResult<Self> {
+        let start = Self::start(bpb).ok_or(VfsError::NoSpace)?;
+        let journal = Journal { start, sequence: 0 };
+        journal.write_header(device, STATE_CLEAN, 0, 0, &[])?;
+        Ok(journal)
+    }
+
+    /// Finish a transaction interrupted after it committed
+    ///
+    /// Returns whether anything was replayed. A header whose checksum doesn't
+    /// match was torn while committing, so its transaction never happened.
+    pub fn replay<D: BlockDevice>(&mut self, device: &mut D) -> VfsResult<bool> {
+        let header = read_sector(device, self.start)?;
+        if field(&header, 4) != STATE_COMMITTED {
+            return Ok(false);
+        }
+
+        let count = field(&header, 12) as usize;
+        let mut replayed = false;
+        if count <= MAX_RECORDS {
+            let targets = targets(&header, count);
+            let records = read_records(device, self.start + 1, count)?;
+            if checksum(&targets, &records) == field(&header, 16) {
+                for (target, data) in targets.iter().zip(records.chunks(SECTOR_SIZE)) {
+                    write_sectors(device, *target, data)?;
+                }
+                flush(device)?;
+                replayed = true;
+            }
+        }
+
+        self.write_header(device, STATE_CLEAN, 0, 0, &[])?;
+        Ok(replayed)
+    }
+
+    /// Apply a transaction so that it survives a crash whole or not at all
+    ///
+    /// Fails with `NoSpace` if it changes more than `MAX_RECORDS` sectors.
+    pub fn commit<D: BlockDevice>(&mut self, device: &mut D, tx: &Transaction) -> VfsResult<()> {
+        if tx.len() > MAX_RECORDS {
+            return Err(VfsError::NoSpace);
+        }
+        if tx.is_empty() {
+            return Ok(());
+        }
+
+        let targets: Vec<u64> = tx.writes.iter().map(|(sector, _)| *sector).collect();
+        let mut records = Vec::with_capacity(tx.len() * SECTOR_SIZE);
+        for (_, data) in &tx.writes {
+            records.extend_from_slice(data);
+        }
+
+        write_sectors(device, self.start + 1, &records)?;
+        flush(device)?;
+
+        self.sequence = self.sequence.wrapping_add(1);
+        let crc = checksum(&targets, &records);
+        self.write_header(device, STATE_COMMITTED, tx.len() as u32, crc, &targets)?;
+
+        for (sector, data) in &tx.writes {
+            write_sectors(device, *sector, data)?;
+        }
+        flush(device)?;
+
+        self.write_header(device, STATE_CLEAN, 0, 0, &[])
+    }
+
+    /// Sequence number of the last committed transaction
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    fn write_header<D: BlockDevice>(
+        &self,
+        device: &mut D,
+        state: u32,
+        count: u32,
+        crc: u32,
+        targets: &[u64],
+    ) -> VfsResult<()> {
+        let mut header = [0u8; SECTOR_SIZE];
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&state.to_le_bytes());
+        header[8..12].copy_from_slice(&self.sequence.to_le_bytes());
+        header[12..16].copy_from_slice(&count.to_le_bytes());
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        for (i, target) in targets.iter().enumerate() {
+            let at = TARGETS_OFFSET + i * 8;
+            header[at..at + 8].copy_from_slice(&target.to_le_bytes());
+        }
+        write_sectors(device, self.start, &header)?;
+        flush(device)
+    }
+}
+
+fn field(sector: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([sector[at], sector[at + 1], sector[at + 2], sector[at + 3]])
+}
+
+fn targets(header: &[u8], count: usize) -> Vec<u64> {
+    (0..count)
+        .map(|i| {
+            let at = TARGETS_OFFSET + i * 8;
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&header[at..at + 8]);
+            u64::from_le_bytes(bytes)
+        })
+        .collect()
+}
+
+/// CRC32 over the home sector numbers and then the record images
+fn checksum(targets: &[u64], records: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for target in targets {
+        crc = crc32_update(crc, &target.to_le_bytes());
+    }
+    !crc32_update(crc, records)
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    const POLY: u32 = 0xEDB8_8320;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn read_sector<D: BlockDevice>(device: &mut D, lba: u64) -> VfsResult<[u8; SECTOR_SIZE]> {
+    let mut buf = [0u8; SECTOR_SIZE];
+    device.read_sectors(lba, &mut buf).map_err(|_| VfsError::IoError)?;
+    Ok(buf)
+}
+
+fn read_records<D: BlockDevice>(device: &mut D, lba: u64, count: usize) -> VfsResult<Vec<u8>> {
+    let mut buf = vec![0u8; count * SECTOR_SIZE];
+    if count > 0 {
+        device.read_sectors(lba, &mut buf).map_err(|_| VfsError::IoError)?;
+    }
+    Ok(buf)
+}
+
+fn write_sectors<D: BlockDevice>(device: &mut D, lba: u64, data: &[u8]) -> VfsResult<()> {
+    device.write_sectors(lba, data).map(|_| ()).map_err(|_| VfsError::IoError)
+}
+
+fn flush<D: BlockDevice>(device: &mut D) -> VfsResult<()> {
+    device.flush().map_err(|_| VfsError::IoError)
+}
@@ -4,6 +4,13 @@
 //! - FAT12 (floppy disks, small volumes)
 //! - FAT16 (small to medium volumes)
 //! - FAT32 (large volumes)
+//!
+//! Volumes can also be formatted (see `format`), which is how the host-side
+//! mkfs tooling builds FAT and EFI System Partition images.
+//!
+//! A volume formatted with `FormatOptions::journal` carries a metadata
+//! journal in its reserved sectors (see `journal`); mounting replays any
+//! transaction a crash interrupted.
 
 #![no_std]
 
@@ -13,21 +20,28 @@ mod bpb;
 mod cluster;
 mod dir;
 mod file;
+mod format;
+mod fsinfo;
+mod journal;
 mod table;
 
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::Mutex;
 
 use watos_vfs::{
     DirEntry, FileMode, FileOperations, FileStat, FileType, Filesystem, FsStats,
-    SeekFrom, VfsError, VfsResult,
+    PathCmp, SeekFrom, VfsError, VfsResult, VolumeInfo,
 };
 use watos_driver_traits::block::BlockDevice;
 
 pub use bpb::{BiosParameterBlock, FatType};
 pub use dir::{FatDirEntry, DirEntryIterator};
+pub use format::{format, FormatOptions};
+pub use fsinfo::FsInfo;
+pub use journal::{Journal, Transaction, JOURNAL_SECTORS, MAX_RECORDS};
 
 /// Shared inner state for FAT filesystem
 /// This is wrapped in Arc<Mutex<>> so both the filesystem and file handles can access it
@@ -44,6 +58,21 @@ struct FatInner<D: BlockDevice> {
     sectors_per_cluster: u32,
     /// Sector size
     sector_size: u32,
+    /// Free cluster accounting, filled in on first use
+    free_space: Option<FreeSpace>,
+    /// Metadata journal, if the volume was formatted with one
+    journal: Option<Journal>,
+}
+
+/// Cached free cluster count and allocation hint
+#[derive(Debug, Clone, Copy)]
+struct FreeSpace {
+    /// Free data clusters
+    free_clusters: u32,
+    /// Where to start looking for a free cluster
+    next_free: u32,
+    /// FAT32: the on-disk FSInfo sector no longer matches
+    fs_info_dirty: bool,
 }
 
 impl<D: BlockDevice> FatInner<D> {
@@ -81,6 +110,81 @@ impl<D: BlockDevice> FatInner<D> {
         self.sectors_per_cluster * self.sector_size
     }
 
+    /// Free cluster count and hint, counting them on first use
+    ///
+    /// FAT32 trusts a valid FSInfo sector; otherwise the FAT is scanned once
+    /// and, on FAT32, FSInfo is rewritten on the next sync.
+    fn free_space(&mut self) -> VfsResult<FreeSpace> {
+        if let Some(free) = self.free_space {
+            return Ok(free);
+        }
+
+        let cluster_count = self.bpb.cluster_count();
+        let fs_info = if self.fat_type == FatType::Fat32 {
+            self.read_fs_info()?
+        } else {
+            None
+        };
+
+        let free = match fs_info.and_then(|info| info.valid_free_count(cluster_count).map(|n| (n, info))) {
+            Some((free_clusters, info)) => FreeSpace {
+                free_clusters,
+                next_free: info.valid_next_free(cluster_count).unwrap_or(2),
+                fs_info_dirty: false,
+            },
+            None => {
+                let (free_clusters, first_free) =
+                    table::scan_free_clusters(&mut self.device, &self.bpb, self.fat_type)?;
+                FreeSpace {
+                    free_clusters,
+                    next_free: first_free.unwrap_or(2),
+                    fs_info_dirty: self.fat_type == FatType::Fat32,
+                }
+            }
+        };
+
+        self.free_space = Some(free);
+        Ok(free)
+    }
+
+    /// Read the FAT32 FSInfo sector, `None` if absent or invalid
+    fn read_fs_info(&mut self) -> VfsResult<Option<FsInfo>> {
+        let sector = self.bpb.fs_info_sector as u64;
+        if sector == 0 || sector == 0xFFFF || sector >= self.bpb.reserved_sector_count as u64 {
+            return Ok(None);
+        }
+
+        let mut buf = [0u8; 512];
+        self.device
+            .read_sectors(sector, &mut buf)
+            .map_err(|_| VfsError::IoError)?;
+        Ok(FsInfo::parse(&buf))
+    }
+
+    /// Bring the FAT32 FSInfo sector up to date with the cached counts
+    fn write_fs_info(&mut self) -> VfsResult<()> {
+        let free = match self.free_space {
+            Some(free) if free.fs_info_dirty => free,
+            _ => return Ok(()),
+        };
+
+        let sector = self.bpb.fs_info_sector as u64;
+        let mut buf = [0u8; 512];
+        self.device
+            .read_sectors(sector, &mut buf)
+            .map_err(|_| VfsError::IoError)?;
+        FsInfo { free_count: free.free_clusters, next_free: free.next_free }.write(&mut buf);
+        self.device
+            .write_sectors(sector, &buf)
+            .map_err(|_| VfsError::IoError)?;
+        self.device.flush().map_err(|_| VfsError::IoError)?;
+
+        if let Some(ref mut cached) = self.free_space {
+            cached.fs_info_dirty = false;
+        }
+        Ok(())
+    }
+
     /// Find a file/directory by path
     fn find_entry(&mut self, path: &str) -> VfsResult<FatDirEntry> {
         let path = path.trim_start_matches('/');
@@ -215,6 +319,51 @@ impl<D: BlockDevice> FatInner<D> {
         Ok(entries)
     }
 
+    /// The root directory's volume label entry, if it has one
+    fn find_label_entry(&mut self) -> VfsResult<Option<FatDirEntry>> {
+        let is_label = |entry: &FatDirEntry| entry.is_volume_label() && !entry.is_long_name();
+
+        if self.fat_type != FatType::Fat32 {
+            let root_dir_sectors = ((self.bpb.root_entry_count as u32 * 32)
+                + (self.bpb.bytes_per_sector as u32 - 1))
+                / self.bpb.bytes_per_sector as u32;
+
+            let root_start = self.bpb.reserved_sector_count as u64
+                + (self.bpb.num_fats as u64 * self.bpb.fat_size_16 as u64);
+
+            let mut sector_buf = [0u8; 512];
+
+            for i in 0..root_dir_sectors {
+                self.device
+                    .read_sectors(root_start + i as u64, &mut sector_buf)
+                    .map_err(|_| VfsError::IoError)?;
+
+                if let Some(entry) = DirEntryIterator::new(&sector_buf).find(is_label) {
+                    return Ok(Some(entry));
+                }
+            }
+            return Ok(None);
+        }
+
+        let mut buffer = alloc::vec![0u8; self.cluster_size() as usize];
+        let mut cluster = self.bpb.root_cluster;
+
+        while cluster >= 2 {
+            self.read_cluster(cluster, &mut buffer)?;
+
+            if let Some(entry) = DirEntryIterator::new(&buffer).find(is_label) {
+                return Ok(Some(entry));
+            }
+
+            match self.next_cluster(cluster)? {
+                Some(next) => cluster = next,
+                None => break,
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Read file data from clusters
     fn read_file_data(
         &mut self,
@@ -316,6 +465,13 @@ impl<D: BlockDevice + Send + Sync + 'static> FatFilesystem<D> {
         let sectors_per_cluster = bpb.sectors_per_cluster as u32;
         let sector_size = bpb.bytes_per_sector as u32;
 
+        // Finish whatever metadata update a crash interrupted before
+        // trusting any of it
+        let mut journal = Journal::locate(&mut device, &bpb)?;
+        if let Some(ref mut journal) = journal {
+            journal.replay(&mut device)?;
+        }
+
         let inner = FatInner {
             device,
             bpb,
@@ -323,6 +479,8 @@ impl<D: BlockDevice + Send + Sync + 'static> FatFilesystem<D> {
             first_data_sector,
             sectors_per_cluster,
             sector_size,
+            free_space: None,
+            journal,
         };
 
         Ok(FatFilesystem {
@@ -334,6 +492,49 @@ impl<D: BlockDevice + Send + Sync + 'static> FatFilesystem<D> {
     pub fn fat_type(&self) -> FatType {
         self.inner.lock().fat_type
     }
+
+    /// Whether metadata updates go through a journal
+    pub fn has_journal(&self) -> bool {
+        self.inner.lock().journal.is_some()
+    }
+
+    /// The volume label, if the volume has one
+    ///
+    /// As DOS does, the root directory's label entry wins over the copy in
+    /// the boot sector, which formatting tools leave as "NO NAME" when the
+    /// volume is unlabelled.
+    pub fn volume_label(&self) -> VfsResult<Option<String>> {
+        let mut inner = self.inner.lock();
+        if let Some(entry) = inner.find_label_entry()? {
+            return Ok(label_string(&entry.name));
+        }
+        Ok(label_string(&inner.bpb.volume_label))
+    }
+
+    /// The volume serial number, set when the volume was formatted
+    pub fn volume_serial(&self) -> u32 {
+        self.inner.lock().bpb.volume_id
+    }
+
+    /// Write a set of metadata sectors (directory and FAT sectors) together
+    ///
+    /// With a journal the update survives a crash whole or not at all;
+    /// without one the sectors are simply written in order.
+    pub fn commit_metadata(&self, tx: &Transaction) -> VfsResult<()> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        match inner.journal {
+            Some(ref mut journal) => journal.commit(&mut inner.device, tx),
+            None => {
+                for (sector, data) in tx.sectors() {
+                    inner.device
+                        .write_sectors(sector, data)
+                        .map_err(|_| VfsError::IoError)?;
+                }
+                inner.device.flush().map_err(|_| VfsError::IoError)
+            }
+        }
+    }
 }
 
 impl<D: BlockDevice + Send + Sync + 'static> Filesystem for FatFilesystem<D> {
@@ -394,9 +595,10 @@ impl<D: BlockDevice + Send + Sync + 'static> Filesystem for FatFilesystem<D> {
             gid: 0,
             blksize: cluster_size,
             blocks: (entry.file_size as u64 + 511) / 512,
-            atime: 0,
-            mtime: 0,
-            ctime: 0,
+            atime: entry.access_time(),
+            mtime: entry.modify_time(),
+            // No status change time on FAT
+            ctime: entry.modify_time(),
         })
     }
 
@@ -432,33 +634,18 @@ impl<D: BlockDevice + Send + Sync + 'static> Filesystem for FatFilesystem<D> {
     }
 
     fn sync(&self) -> VfsResult<()> {
-        Ok(())
+        let mut inner = self.inner.lock();
+        inner.write_fs_info()?;
+        inner.device.flush().map_err(|_| VfsError::IoError)
     }
 
     fn statfs(&self) -> VfsResult<FsStats> {
-        let inner = self.inner.lock();
-
-        let total_sectors = if inner.bpb.total_sectors_16 != 0 {
-            inner.bpb.total_sectors_16 as u64
-        } else {
-            inner.bpb.total_sectors_32 as u64
-        };
-
-        let fat_size = if inner.bpb.fat_size_16 != 0 {
-            inner.bpb.fat_size_16 as u64
-        } else {
-            inner.bpb.fat_size_32 as u64
-        };
-
-        let data_sectors = total_sectors
-            - inner.bpb.reserved_sector_count as u64
-            - (inner.bpb.num_fats as u64 * fat_size);
-
-        let total_clusters = data_sectors / inner.sectors_per_cluster as u64;
+        let mut inner = self.inner.lock();
+        let free = inner.free_space()?;
 
         Ok(FsStats {
-            total_blocks: total_clusters,
-            free_blocks: 0,
+            total_blocks: inner.bpb.cluster_count() as u64,
+            free_blocks: free.free_clusters as u64,
             block_size: inner.sectors_per_cluster * inner.sector_size,
             total_inodes: 0,
             free_inodes: 0,
@@ -475,6 +662,28 @@ impl<D: BlockDevice + Send + Sync + 'static> Filesystem for FatFilesystem<D> {
         // FAT filesystem doesn't support Unix ownership
         Err(VfsError::NotSupported)
     }
+
+    fn path_cmp(&self) -> PathCmp {
+        // Short names are stored in uppercase and looked up ignoring case
+        PathCmp::CaseInsensitive
+    }
+
+    fn volume(&self) -> VolumeInfo {
+        VolumeInfo {
+            label: self.volume_label().ok().flatten(),
+            serial: Some(self.volume_serial()),
+        }
+    }
+}
+
+/// An 11-byte, space-padded label as text; None when blank or "NO NAME"
+fn label_string(raw: &[u8; 11]) -> Option<String> {
+    let end = raw.iter().rposition(|&c| c != b' ' && c != 0)? + 1;
+    let label = &raw[..end];
+    if label == b"NO NAME" {
+        return None;
+    }
+    Some(label.iter().map(|&c| c as char).collect())
 }
 
 /// File handle with shared access to filesystem state
@@ -495,6 +704,10 @@ struct FatFileHandle<D: BlockDevice + Send + Sync + 'static> {
     can_write: bool,
     /// File attributes
     attributes: u8,
+    /// Last access time (seconds since epoch)
+    atime: u64,
+    /// Last modification time (seconds since epoch)
+    mtime: u64,
 }
 
 impl<D: BlockDevice + Send + Sync + 'static> FatFileHandle<D> {
@@ -514,6 +727,8 @@ impl<D: BlockDevice + Send + Sync + 'static> FatFileHandle<D> {
             can_read,
             can_write,
             attributes: entry.attributes,
+            atime: entry.access_time(),
+            mtime: entry.modify_time(),
         }
     }
 }
@@ -579,7 +794,7 @@ impl<D: BlockDevice + Send + Sync + 'static> FileOperations for FatFileHandle<D>
     }
 
     fn sync(&mut self) -> VfsResult<()> {
-        Ok(())
+        self.inner.lock().device.flush().map_err(|_| VfsError::IoError)
     }
 
     fn stat(&self) -> VfsResult<FileStat> {
@@ -598,9 +813,9 @@ impl<D: BlockDevice + Send + Sync + 'static> FileOperations for FatFileHandle<D>
             gid: 0,
             blksize: self.cluster_size,
             blocks: (self.file_size + 511) / 512,
-            atime: 0,
-            mtime: 0,
-            ctime: 0,
+            atime: self.atime,
+            mtime: self.mtime,
+            ctime: self.mtime,
         })
     }
 
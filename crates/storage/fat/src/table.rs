@@ -1,5 +1,7 @@
 //! FAT table operations
 
+use alloc::vec;
+
 use watos_vfs::{VfsError, VfsResult};
 use watos_driver_traits::block::BlockDevice;
 
@@ -18,6 +20,9 @@ pub const BAD_FAT32: u32 = 0x0FFFFFF7;
 /// Free cluster marker
 pub const FREE_CLUSTER: u32 = 0x00000000;
 
+/// Sectors read per step when scanning a FAT16/32 table
+const SCAN_CHUNK: u32 = 8;
+
 /// Read a FAT entry
 pub fn read_fat_entry<D: BlockDevice>(
     device: &mut D,
@@ -147,6 +152,74 @@ fn read_fat32_entry<D: BlockDevice>(
     }
 }
 
+/// Count free clusters by scanning the first FAT
+///
+/// Returns the number of free data clusters and the first free one, if any.
+pub fn scan_free_clusters<D: BlockDevice>(
+    device: &mut D,
+    bpb: &BiosParameterBlock,
+    fat_type: FatType,
+) -> VfsResult<(u32, Option<u32>)> {
+    let sector_size = bpb.bytes_per_sector as usize;
+    let fat_start = bpb.reserved_sector_count as u64;
+    let fat_size = bpb.fat_size();
+    let last_cluster = bpb.cluster_count() + 1;
+
+    // FAT12 entries straddle sectors; that FAT is at most 12 sectors, so
+    // read it whole. FAT16/32 entries never do, so read in chunks.
+    let chunk_sectors = match fat_type {
+        FatType::Fat12 => fat_size,
+        _ => SCAN_CHUNK.min(fat_size),
+    };
+    let entries_per_chunk = match fat_type {
+        FatType::Fat12 => last_cluster + 1,
+        FatType::Fat16 => chunk_sectors * sector_size as u32 / 2,
+        FatType::Fat32 => chunk_sectors * sector_size as u32 / 4,
+    };
+
+    let mut buffer = vec![0u8; chunk_sectors as usize * sector_size];
+    let mut free = 0;
+    let mut first_free = None;
+    let mut cluster = 2;
+
+    while cluster <= last_cluster {
+        let chunk = cluster / entries_per_chunk;
+        let chunk_start = chunk * chunk_sectors;
+        let sectors = chunk_sectors.min(fat_size - chunk_start);
+        for i in 0..sectors {
+            let offset = i as usize * sector_size;
+            device
+                .read_sectors(fat_start + (chunk_start + i) as u64, &mut buffer[offset..offset + sector_size])
+                .map_err(|_| VfsError::IoError)?;
+        }
+
+        let base = chunk * entries_per_chunk;
+        let end = last_cluster.min(base + entries_per_chunk - 1);
+        for c in cluster..=end {
+            let n = (c - base) as usize;
+            let value = match fat_type {
+                FatType::Fat12 => {
+                    let at = n + n / 2;
+                    let pair = u16::from_le_bytes([buffer[at], buffer[at + 1]]);
+                    u32::from(if n & 1 != 0 { pair >> 4 } else { pair & 0x0FFF })
+                }
+                FatType::Fat16 => u16::from_le_bytes([buffer[n * 2], buffer[n * 2 + 1]]).into(),
+                FatType::Fat32 => {
+                    u32::from_le_bytes([buffer[n * 4], buffer[n * 4 + 1], buffer[n * 4 + 2], buffer[n * 4 + 3]])
+                        & 0x0FFFFFFF
+                }
+            };
+            if value == FREE_CLUSTER {
+                free += 1;
+                first_free.get_or_insert(c);
+            }
+        }
+        cluster = end + 1;
+    }
+
+    Ok((free, first_free))
+}
+
 /// Check if a cluster value indicates end of chain
 pub fn is_end_of_chain(fat_type: FatType, cluster: u32) -> bool {
     match fat_type {
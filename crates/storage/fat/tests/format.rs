@@ -0,0 +1,138 @@
+//! FAT formatting tests
+//!
+//! Volumes are formatted on an in-memory block device and then mounted with
+//! the driver, so the layout `format` writes is checked against the layout
+//! the driver reads.
+//!
+//! Run with: cargo test --package watos-fat
+
+use watos_driver_traits::block::BlockDevice;
+use watos_driver_traits::mem::MemBlockDevice;
+use watos_fat::{format, BiosParameterBlock, FatFilesystem, FatType, FormatOptions};
+use watos_vfs::{FileMode, Filesystem, VfsError};
+
+const SECTOR: usize = 512;
+
+fn options(fat_type: Option<FatType>, label: Option<&str>) -> FormatOptions {
+    FormatOptions {
+        fat_type,
+        sectors_per_cluster: None,
+        volume_label: label.map(String::from),
+        volume_id: 0x1234_5678,
+        journal: false,
+    }
+}
+
+fn boot_sector(dev: &MemBlockDevice) -> BiosParameterBlock {
+    BiosParameterBlock::parse(&dev.peek(0, SECTOR)).unwrap()
+}
+
+#[test]
+fn test_format_each_type_mounts_empty() {
+    // 1.44 MB floppy, 32 MiB, 64 MiB ESP
+    for (sectors, fat_type) in [(2880, FatType::Fat12), (65536, FatType::Fat16), (131072, FatType::Fat32)] {
+        let mut dev = MemBlockDevice::new(SECTOR as u32, sectors);
+        let bpb = format(&mut dev, &options(Some(fat_type), Some("watos"))).unwrap();
+        assert_eq!(bpb.fat_type(), fat_type);
+
+        let fs = FatFilesystem::new(dev.clone()).unwrap();
+        assert_eq!(fs.fat_type(), fat_type);
+        // The volume label entry is not listed
+        assert!(fs.readdir("/").unwrap().is_empty());
+        assert_eq!(fs.stat("/missing").err(), Some(VfsError::NotFound));
+
+        let parsed = boot_sector(&dev);
+        assert_eq!(parsed.volume_label_str(), "WATOS");
+        assert_eq!(parsed.volume_id, 0x1234_5678);
+        assert_eq!(fs.volume_label().unwrap().as_deref(), Some("WATOS"));
+        assert_eq!(fs.volume_serial(), 0x1234_5678);
+        assert_eq!(parsed.cluster_count(), bpb.cluster_count());
+    }
+}
+
+#[test]
+fn test_default_type_follows_volume_size() {
+    // FAT32 is the default only from 512 MiB, too large to build in memory here
+    for (sectors, fat_type) in [(2880, FatType::Fat12), (8401, FatType::Fat16), (131072, FatType::Fat16)] {
+        let mut dev = MemBlockDevice::new(SECTOR as u32, sectors);
+        let bpb = format(&mut dev, &options(None, None)).unwrap();
+        assert_eq!(bpb.fat_type(), fat_type);
+        assert_eq!(&bpb.volume_label, b"NO NAME    ");
+        assert_eq!(FatFilesystem::new(dev).unwrap().volume_label().unwrap(), None);
+    }
+}
+
+#[test]
+fn test_fat32_reserved_area() {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, 131072);
+    let bpb = format(&mut dev, &options(Some(FatType::Fat32), None)).unwrap();
+
+    // Backup boot sector is an exact copy
+    assert_eq!(dev.peek(0, SECTOR), dev.peek(6 * SECTOR, SECTOR));
+
+    // FSInfo: signatures, free count excludes the root directory cluster
+    let fs_info = dev.peek(SECTOR, SECTOR);
+    assert_eq!(&fs_info[0..4], b"RRaA");
+    assert_eq!(&fs_info[484..488], b"rrAa");
+    assert_eq!(u32::from_le_bytes(fs_info[488..492].try_into().unwrap()), bpb.cluster_count() - 1);
+    assert_eq!(&fs_info[508..512], &[0x00, 0x00, 0x55, 0xAA]);
+
+    // Both FATs reserve entries 0-1 and end the root directory chain
+    let fat_start = bpb.reserved_sector_count as usize * SECTOR;
+    for copy in 0..2 {
+        let fat = dev.peek(fat_start + copy * bpb.fat_size_32 as usize * SECTOR, 16);
+        assert_eq!(&fat[0..4], &[0xF8, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(&fat[8..12], &[0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(&fat[12..16], &[0; 4]);
+    }
+}
+
+#[test]
+fn test_file_in_formatted_fat32_root_is_readable() {
+    // 128 MiB: two-sector clusters still leave enough clusters for FAT32
+    let mut dev = MemBlockDevice::new(SECTOR as u32, 262144);
+    let spc = 2;
+    let opts = FormatOptions { sectors_per_cluster: Some(spc), ..options(Some(FatType::Fat32), None) };
+    let bpb = format(&mut dev, &opts).unwrap();
+    assert_eq!(bpb.sectors_per_cluster, spc);
+
+    // Put BOOTX64.EFI in cluster 3 by hand, right after the root directory
+    let data_start = bpb.reserved_sector_count as u64 + 2 * bpb.fat_size_32 as u64;
+    let cluster_sector = |c: u64| data_start + (c - 2) * spc as u64;
+    let contents = b"MZ not really a PE image";
+
+    let mut entry = [0u8; 32];
+    entry[0..11].copy_from_slice(b"BOOTX64 EFI");
+    entry[11] = 0x20;
+    entry[26..28].copy_from_slice(&3u16.to_le_bytes());
+    entry[28..32].copy_from_slice(&(contents.len() as u32).to_le_bytes());
+    dev.poke(cluster_sector(2) as usize * SECTOR, &entry);
+    dev.poke(cluster_sector(3) as usize * SECTOR, contents);
+    dev.poke(bpb.reserved_sector_count as usize * SECTOR + 12, &[0xFF, 0xFF, 0xFF, 0x0F]);
+
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    let mut file = fs.open("/BOOTX64.EFI", FileMode::READ).unwrap();
+    let mut buf = [0u8; 64];
+    let n = file.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], contents);
+}
+
+#[test]
+fn test_invalid_requests_are_rejected() {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, 2880);
+    let fat32 = options(Some(FatType::Fat32), None);
+    // Too few clusters for FAT32
+    assert_eq!(format(&mut dev, &fat32).err(), Some(VfsError::InvalidArgument));
+    // Cluster size must be a power of two
+    let odd = FormatOptions { sectors_per_cluster: Some(3), ..options(None, None) };
+    assert_eq!(format(&mut dev, &odd).err(), Some(VfsError::InvalidArgument));
+    // Labels are 8.3-style: at most 11 characters, no separators
+    assert_eq!(format(&mut dev, &options(None, Some("TWELVE CHARS"))).err(), Some(VfsError::NameTooLong));
+    assert_eq!(format(&mut dev, &options(None, Some("A.B"))).err(), Some(VfsError::InvalidArgument));
+
+    // Nothing was written by the rejected requests
+    assert_eq!(dev.write_count(), 0);
+    let mut boot = [0u8; SECTOR];
+    dev.read_sectors(0, &mut boot).unwrap();
+    assert_eq!(boot, [0u8; SECTOR]);
+}
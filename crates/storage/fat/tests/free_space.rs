@@ -0,0 +1,129 @@
+//! Free space accounting tests
+//!
+//! Volumes are formatted in memory, clusters are marked used by editing the
+//! FAT directly, and the counts `statfs` reports are checked against them.
+//!
+//! Run with: cargo test --package watos-fat
+
+use watos_driver_traits::mem::MemBlockDevice;
+use watos_fat::{format, BiosParameterBlock, FatFilesystem, FatType, FormatOptions, FsInfo};
+use watos_vfs::Filesystem;
+
+const SECTOR: usize = 512;
+
+fn formatted(sectors: u64, fat_type: FatType) -> (MemBlockDevice, BiosParameterBlock) {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, sectors);
+    let options = FormatOptions { fat_type: Some(fat_type), ..FormatOptions::default() };
+    let bpb = format(&mut dev, &options).unwrap();
+    (dev, bpb)
+}
+
+/// Set a FAT entry in every FAT copy
+fn set_entry(dev: &MemBlockDevice, bpb: &BiosParameterBlock, cluster: usize, value: u32) {
+    for copy in 0..bpb.num_fats as usize {
+        let fat = (bpb.reserved_sector_count as usize + copy * bpb.fat_size() as usize) * SECTOR;
+        match bpb.fat_type() {
+            FatType::Fat12 => {
+                let at = fat + cluster + cluster / 2;
+                let old = u16::from_le_bytes(dev.peek(at, 2).try_into().unwrap());
+                let new = if cluster.is_multiple_of(2) {
+                    (old & 0xF000) | (value as u16 & 0x0FFF)
+                } else {
+                    (old & 0x000F) | ((value as u16) << 4)
+                };
+                dev.poke(at, &new.to_le_bytes());
+            }
+            FatType::Fat16 => dev.poke(fat + cluster * 2, &(value as u16).to_le_bytes()),
+            FatType::Fat32 => dev.poke(fat + cluster * 4, &value.to_le_bytes()),
+        }
+    }
+}
+
+fn fs_info(dev: &MemBlockDevice, bpb: &BiosParameterBlock) -> FsInfo {
+    FsInfo::parse(&dev.peek(bpb.fs_info_sector as usize * SECTOR, SECTOR)).unwrap()
+}
+
+#[test]
+fn test_empty_volume_is_all_free() {
+    for (sectors, fat_type) in [(2880, FatType::Fat12), (65536, FatType::Fat16), (131072, FatType::Fat32)] {
+        let (dev, bpb) = formatted(sectors, fat_type);
+        let stats = FatFilesystem::new(dev).unwrap().statfs().unwrap();
+
+        assert_eq!(stats.total_blocks, bpb.cluster_count() as u64);
+        // FAT32 keeps its root directory in a cluster
+        let used = if fat_type == FatType::Fat32 { 1 } else { 0 };
+        assert_eq!(stats.free_blocks, (bpb.cluster_count() - used) as u64);
+        assert_eq!(stats.block_size, bpb.sectors_per_cluster as u32 * SECTOR as u32);
+    }
+}
+
+#[test]
+fn test_scan_counts_used_clusters() {
+    for (sectors, fat_type) in [(2880, FatType::Fat12), (65536, FatType::Fat16)] {
+        let (dev, bpb) = formatted(sectors, fat_type);
+        // A three-cluster chain, a lone odd cluster (FAT12 packing), and
+        // the very last cluster of the volume
+        let last = bpb.cluster_count() as usize + 1;
+        set_entry(&dev, &bpb, 2, 3);
+        set_entry(&dev, &bpb, 3, 4);
+        set_entry(&dev, &bpb, 4, 0xFFFF_FFFF);
+        set_entry(&dev, &bpb, 7, 0xFFFF_FFFF);
+        set_entry(&dev, &bpb, last, 0xFFFF_FFFF);
+
+        let stats = FatFilesystem::new(dev).unwrap().statfs().unwrap();
+        assert_eq!(stats.free_blocks, (bpb.cluster_count() - 5) as u64);
+    }
+}
+
+#[test]
+fn test_fat32_trusts_valid_fs_info() {
+    let (dev, bpb) = formatted(131072, FatType::Fat32);
+    // The hint wins over the FAT: no scan happens when FSInfo is usable
+    let mut sector = dev.peek(bpb.fs_info_sector as usize * SECTOR, SECTOR);
+    FsInfo { free_count: 1000, next_free: 50 }.write(&mut sector);
+    dev.poke(bpb.fs_info_sector as usize * SECTOR, &sector);
+
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    assert_eq!(fs.statfs().unwrap().free_blocks, 1000);
+
+    // Nothing to write back
+    let writes = dev.write_count();
+    fs.sync().unwrap();
+    assert_eq!(dev.write_count(), writes);
+}
+
+#[test]
+fn test_fat32_rebuilds_invalid_fs_info_on_sync() {
+    let (dev, bpb) = formatted(131072, FatType::Fat32);
+    set_entry(&dev, &bpb, 3, 0x0FFF_FFFF);
+    set_entry(&dev, &bpb, 4, 0x0FFF_FFFF);
+
+    let mut sector = dev.peek(bpb.fs_info_sector as usize * SECTOR, SECTOR);
+    FsInfo { free_count: FsInfo::UNKNOWN, next_free: FsInfo::UNKNOWN }.write(&mut sector);
+    dev.poke(bpb.fs_info_sector as usize * SECTOR, &sector);
+
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    let free = bpb.cluster_count() - 3;
+    assert_eq!(fs.statfs().unwrap().free_blocks, free as u64);
+
+    fs.sync().unwrap();
+    assert_eq!(fs_info(&dev, &bpb), FsInfo { free_count: free, next_free: 5 });
+
+    // A count larger than the volume is as good as unknown
+    let mut sector = dev.peek(bpb.fs_info_sector as usize * SECTOR, SECTOR);
+    FsInfo { free_count: bpb.cluster_count() + 1, next_free: 5 }.write(&mut sector);
+    dev.poke(bpb.fs_info_sector as usize * SECTOR, &sector);
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    assert_eq!(fs.statfs().unwrap().free_blocks, free as u64);
+}
+
+#[test]
+fn test_free_count_is_cached() {
+    let (dev, bpb) = formatted(65536, FatType::Fat16);
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    let before = fs.statfs().unwrap().free_blocks;
+
+    // Changes behind the driver's back are not seen: the count is cached
+    set_entry(&dev, &bpb, 2, 0xFFFF);
+    assert_eq!(fs.statfs().unwrap().free_blocks, before);
+}
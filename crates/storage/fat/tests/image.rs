@@ -0,0 +1,252 @@
+//! FAT image tests on an in-memory block device
+//!
+//! The driver is read-only, so images are laid out here by hand: a small
+//! FAT16 volume (512-byte sectors, one sector per cluster, two FATs) with
+//! files in the root directory.
+//!
+//! Run with: cargo test --package watos-fat
+
+use watos_driver_traits::block::BlockDevice;
+use watos_driver_traits::mem::{IoOp, MemBlockDevice};
+use watos_driver_traits::DriverError;
+use watos_fat::{FatFilesystem, FatType};
+use watos_vfs::{FileMode, Filesystem, VfsError};
+
+const SECTOR: usize = 512;
+const TOTAL_SECTORS: u16 = 8192;
+const RESERVED: u16 = 1;
+const FAT_SECTORS: u16 = 32;
+const ROOT_ENTRIES: u16 = 512;
+
+const FAT1_START: u64 = RESERVED as u64;
+const FAT2_START: u64 = FAT1_START + FAT_SECTORS as u64;
+const ROOT_START: u64 = FAT2_START + FAT_SECTORS as u64;
+const DATA_START: u64 = ROOT_START + (ROOT_ENTRIES as u64 * 32) / SECTOR as u64;
+
+/// Write an empty FAT16 volume
+fn format(dev: &mut MemBlockDevice) -> Result<(), DriverError> {
+    let mut boot = [0u8; SECTOR];
+    boot[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"WATOS   ");
+    boot[11..13].copy_from_slice(&(SECTOR as u16).to_le_bytes());
+    boot[13] = 1; // sectors per cluster
+    boot[14..16].copy_from_slice(&RESERVED.to_le_bytes());
+    boot[16] = 2; // FAT copies
+    boot[17..19].copy_from_slice(&ROOT_ENTRIES.to_le_bytes());
+    boot[19..21].copy_from_slice(&TOTAL_SECTORS.to_le_bytes());
+    boot[21] = 0xF8;
+    boot[22..24].copy_from_slice(&FAT_SECTORS.to_le_bytes());
+    boot[38] = 0x29;
+    boot[43..54].copy_from_slice(b"TESTVOL    ");
+    boot[54..62].copy_from_slice(b"FAT16   ");
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+    dev.write_sectors(0, &boot)?;
+
+    // Media descriptor and end-of-chain in the two reserved entries
+    let mut fat = vec![0u8; FAT_SECTORS as usize * SECTOR];
+    fat[0..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+    dev.write_sectors(FAT1_START, &fat)?;
+    dev.write_sectors(FAT2_START, &fat)?;
+
+    let root = vec![0u8; (DATA_START - ROOT_START) as usize * SECTOR];
+    dev.write_sectors(ROOT_START, &root)?;
+    Ok(())
+}
+
+/// Store a file in the root directory using consecutive clusters
+///
+/// Writes happen in the order a careful driver would use: data, both FATs,
+/// then the directory entry that makes the file visible.
+fn write_file(
+    dev: &mut MemBlockDevice,
+    slot: usize,
+    name: &[u8; 11],
+    first_cluster: u16,
+    contents: &[u8],
+) -> Result<(), DriverError> {
+    let clusters = contents.len().div_ceil(SECTOR).max(1);
+    let mut data = contents.to_vec();
+    data.resize(clusters * SECTOR, 0);
+    dev.write_sectors(cluster_sector(first_cluster), &data)?;
+
+    for fat_start in [FAT1_START, FAT2_START] {
+        let mut fat = vec![0u8; FAT_SECTORS as usize * SECTOR];
+        dev.read_sectors(fat_start, &mut fat)?;
+        for i in 0..clusters {
+            let cluster = first_cluster as usize + i;
+            let next: u16 = if i + 1 == clusters { 0xFFFF } else { cluster as u16 + 1 };
+            fat[cluster * 2..cluster * 2 + 2].copy_from_slice(&next.to_le_bytes());
+        }
+        dev.write_sectors(fat_start, &fat)?;
+    }
+
+    let sector = ROOT_START + (slot * 32 / SECTOR) as u64;
+    let offset = slot * 32 % SECTOR;
+    let mut dir = [0u8; SECTOR];
+    dev.read_sectors(sector, &mut dir)?;
+    let entry = &mut dir[offset..offset + 32];
+    entry[0..11].copy_from_slice(name);
+    entry[11] = 0x20; // archive
+    entry[26..28].copy_from_slice(&first_cluster.to_le_bytes());
+    entry[28..32].copy_from_slice(&(contents.len() as u32).to_le_bytes());
+    dev.write_sectors(sector, &dir)?;
+    Ok(())
+}
+
+fn cluster_sector(cluster: u16) -> u64 {
+    DATA_START + cluster as u64 - 2
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+fn mount(dev: &MemBlockDevice) -> FatFilesystem<MemBlockDevice> {
+    match FatFilesystem::new(dev.clone()) {
+        Ok(fs) => fs,
+        Err(e) => panic!("mount failed: {:?}", e),
+    }
+}
+
+fn read_all(fs: &FatFilesystem<MemBlockDevice>, path: &str) -> Result<Vec<u8>, VfsError> {
+    let mut file = fs.open(path, FileMode::READ)?;
+    let mut out = Vec::new();
+    let mut buf = [0u8; 300];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(out);
+        }
+        out.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Volume with HELLO.TXT (3 clusters at cluster 2) and NOTES.TXT (cluster 5)
+fn sample_volume() -> MemBlockDevice {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, TOTAL_SECTORS as u64);
+    format(&mut dev).unwrap();
+    write_file(&mut dev, 0, b"HELLO   TXT", 2, &pattern(1300)).unwrap();
+    write_file(&mut dev, 1, b"NOTES   TXT", 5, b"short note").unwrap();
+    dev
+}
+
+#[test]
+fn test_format_write_and_read_back() {
+    let dev = sample_volume();
+    dev.clear_trace();
+
+    let fs = mount(&dev);
+    assert_eq!(fs.fat_type(), FatType::Fat16);
+
+    let names: Vec<String> = fs.readdir("/").unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["HELLO.TXT", "NOTES.TXT"]);
+
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), pattern(1300));
+    assert_eq!(read_all(&fs, "/NOTES.TXT").unwrap(), b"short note");
+    assert_eq!(fs.stat("/HELLO.TXT").unwrap().size, 1300);
+
+    // A read-only mount never writes to the media
+    assert!(dev.trace().iter().all(|op| matches!(op, IoOp::Read { .. })));
+}
+
+#[test]
+fn test_volume_label_and_serial() {
+    let dev = sample_volume();
+    dev.poke(39, &0x1A2B_3C4Du32.to_le_bytes());
+
+    // Only the boot sector names the volume
+    let fs = mount(&dev);
+    assert_eq!(fs.volume_label().unwrap().as_deref(), Some("TESTVOL"));
+    assert_eq!(fs.volume_serial(), 0x1A2B_3C4D);
+    drop(fs);
+
+    // A label entry in the root directory wins, and isn't listed
+    let mut entry = [0u8; 32];
+    entry[0..11].copy_from_slice(b"WATOS_BOOT ");
+    entry[11] = 0x08; // volume label
+    dev.poke(ROOT_START as usize * SECTOR + 2 * 32, &entry);
+    let fs = mount(&dev);
+    let volume = fs.volume();
+    assert_eq!(volume.label.as_deref(), Some("WATOS_BOOT"));
+    assert_eq!(volume.serial, Some(0x1A2B_3C4D));
+    assert_eq!(fs.readdir("/").unwrap().len(), 2);
+}
+
+#[test]
+fn test_corrupt_boot_sector_is_rejected() {
+    let dev = sample_volume();
+    dev.poke(510, &[0, 0]);
+    assert_eq!(FatFilesystem::new(dev.clone()).err(), Some(VfsError::InvalidArgument));
+}
+
+#[test]
+fn test_broken_chain_recovered_from_second_fat() {
+    let dev = sample_volume();
+
+    // Cluster 3 is the middle of HELLO.TXT; mark it free in the first FAT
+    let entry = FAT1_START as usize * SECTOR + 3 * 2;
+    dev.poke(entry, &[0, 0]);
+    let fs = mount(&dev);
+    assert_eq!(read_all(&fs, "/HELLO.TXT").unwrap(), &pattern(1300)[..2 * SECTOR]);
+    drop(fs);
+
+    // Repair the primary FAT from the mirror copy, as fsck would
+    let fat_bytes = FAT_SECTORS as usize * SECTOR;
+    let mirror = dev.peek(FAT2_START as usize * SECTOR, fat_bytes);
+    dev.poke(FAT1_START as usize * SECTOR, &mirror);
+    let fs = mount(&dev);
+    assert_eq!(read_all(&fs, "/HELLO.TXT").unwrap(), pattern(1300));
+}
+
+#[test]
+fn test_failed_fat_update_leaves_file_invisible() {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, TOTAL_SECTORS as u64);
+    format(&mut dev).unwrap();
+
+    // Writes: data, FAT1 (fails) - the directory entry is never written
+    dev.fail_write(2);
+    assert_eq!(
+        write_file(&mut dev, 0, b"LOST    TXT", 2, b"never committed"),
+        Err(DriverError::IoError)
+    );
+
+    let fs = mount(&dev);
+    assert_eq!(fs.stat("/LOST.TXT").err(), Some(VfsError::NotFound));
+    assert!(fs.readdir("/").unwrap().is_empty());
+}
+
+#[test]
+fn test_read_error_is_reported() {
+    let dev = sample_volume();
+    let fs = mount(&dev);
+    let mut file = fs.open("/HELLO.TXT", FileMode::READ).unwrap();
+
+    dev.fail_read(1);
+    let mut buf = [0u8; 64];
+    assert_eq!(file.read(&mut buf), Err(VfsError::IoError));
+    assert_eq!(file.read(&mut buf), Ok(64));
+}
+
+#[test]
+fn test_stat_reports_directory_entry_times() {
+    let dev = sample_volume();
+
+    // NOTES.TXT: modified 2024-02-29 13:45:30, last accessed 2024-03-01
+    let entry = ROOT_START as usize * SECTOR + 32;
+    let time: u16 = (13 << 11) | (45 << 5) | 15;
+    dev.poke(entry + 22, &time.to_le_bytes());
+    dev.poke(entry + 24, &(((2024 - 1980) << 9) | (2 << 5) | 29u16).to_le_bytes());
+    dev.poke(entry + 18, &(((2024 - 1980) << 9) | (3 << 5) | 1u16).to_le_bytes());
+
+    let fs = mount(&dev);
+    let stat = fs.stat("/NOTES.TXT").unwrap();
+    assert_eq!(stat.mtime, 1_709_214_330);
+    assert_eq!(stat.ctime, stat.mtime);
+    assert_eq!(stat.atime, 1_709_251_200);
+    let file = fs.open("/NOTES.TXT", FileMode::READ).unwrap();
+    assert_eq!(file.stat().unwrap().mtime, stat.mtime);
+
+    // Never-set dates read as 0
+    assert_eq!(fs.stat("/HELLO.TXT").unwrap().mtime, 0);
+}
@@ -0,0 +1,151 @@
+//! Metadata journal tests
+//!
+//! A directory entry is added to the root directory of an in-memory volume
+//! through `commit_metadata`, with the device's fault injection standing in
+//! for a power cut at each step; remounting must show the entry whole or
+//! not at all.
+//!
+//! Run with: cargo test --package watos-fat
+
+use watos_driver_traits::mem::MemBlockDevice;
+use watos_fat::{
+    format, BiosParameterBlock, FatFilesystem, FatType, FormatOptions, Transaction, JOURNAL_SECTORS,
+    MAX_RECORDS,
+};
+use watos_vfs::{Filesystem, VfsError};
+
+const SECTOR: usize = 512;
+
+fn formatted(sectors: u64, fat_type: FatType, journal: bool) -> (MemBlockDevice, BiosParameterBlock) {
+    let mut dev = MemBlockDevice::new(SECTOR as u32, sectors);
+    let options = FormatOptions { fat_type: Some(fat_type), journal, ..FormatOptions::default() };
+    let bpb = format(&mut dev, &options).unwrap();
+    (dev, bpb)
+}
+
+/// First sector of the FAT12/16 root directory
+fn root_sector(bpb: &BiosParameterBlock) -> u64 {
+    bpb.reserved_sector_count as u64 + bpb.num_fats as u64 * bpb.fat_size() as u64
+}
+
+/// A transaction adding an empty file HELLO.TXT to the root directory, and
+/// rewriting the first sector of each FAT unchanged
+fn add_file(dev: &MemBlockDevice, bpb: &BiosParameterBlock) -> Transaction {
+    let root = root_sector(bpb);
+    let mut dir: [u8; SECTOR] = dev.peek(root as usize * SECTOR, SECTOR).try_into().unwrap();
+    dir[0..11].copy_from_slice(b"HELLO   TXT");
+    dir[11] = 0x20; // Archive
+
+    let mut tx = Transaction::new();
+    for copy in 0..bpb.num_fats as u64 {
+        let fat = bpb.reserved_sector_count as u64 + copy * bpb.fat_size() as u64;
+        tx.write(fat, &dev.peek(fat as usize * SECTOR, SECTOR).try_into().unwrap());
+    }
+    tx.write(root, &dir);
+    tx
+}
+
+fn names(fs: &FatFilesystem<MemBlockDevice>) -> Vec<String> {
+    fs.readdir("/").unwrap().into_iter().map(|e| e.name).collect()
+}
+
+#[test]
+fn test_format_with_journal() {
+    for (sectors, fat_type) in [(2880, FatType::Fat12), (65536, FatType::Fat16), (131072, FatType::Fat32)] {
+        let (dev, plain) = formatted(sectors, fat_type, false);
+        assert!(!FatFilesystem::new(dev).unwrap().has_journal());
+
+        let (dev, bpb) = formatted(sectors, fat_type, true);
+        let fs = FatFilesystem::new(dev).unwrap();
+        assert!(fs.has_journal());
+        assert!(names(&fs).is_empty());
+        if fat_type == FatType::Fat32 {
+            assert_eq!(bpb.reserved_sector_count, plain.reserved_sector_count);
+        } else {
+            assert_eq!(bpb.reserved_sector_count, plain.reserved_sector_count + JOURNAL_SECTORS);
+        }
+    }
+}
+
+#[test]
+fn test_commit_applies_transaction() {
+    for journal in [false, true] {
+        let (dev, bpb) = formatted(2880, FatType::Fat12, journal);
+        let fs = FatFilesystem::new(dev.clone()).unwrap();
+        fs.commit_metadata(&add_file(&dev, &bpb)).unwrap();
+        assert_eq!(names(&fs), ["HELLO.TXT"]);
+        assert_eq!(names(&FatFilesystem::new(dev).unwrap()), ["HELLO.TXT"]);
+    }
+}
+
+#[test]
+fn test_crash_after_commit_is_replayed() {
+    let (dev, bpb) = formatted(2880, FatType::Fat12, true);
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    let tx = add_file(&dev, &bpb);
+
+    // Writes: records, committed header, then the three sectors in place.
+    // Lose power before the directory sector lands.
+    dev.fail_write(5);
+    assert_eq!(fs.commit_metadata(&tx).err(), Some(VfsError::IoError));
+    dev.clear_faults();
+    drop(fs);
+    assert_eq!(names(&FatFilesystem::new(dev.clone()).unwrap()), ["HELLO.TXT"]);
+
+    // Replay marked the journal clean: mounting again writes nothing
+    let writes = dev.write_count();
+    FatFilesystem::new(dev.clone()).unwrap();
+    assert_eq!(dev.write_count(), writes);
+}
+
+#[test]
+fn test_crash_before_commit_is_discarded() {
+    // Records torn, or the committing header write lost
+    for (n, sectors) in [(1, 1), (2, 0)] {
+        let (dev, bpb) = formatted(2880, FatType::Fat12, true);
+        let fs = FatFilesystem::new(dev.clone()).unwrap();
+
+        dev.tear_write(n, sectors);
+        assert_eq!(fs.commit_metadata(&add_file(&dev, &bpb)).err(), Some(VfsError::IoError));
+        dev.clear_faults();
+        drop(fs);
+
+        assert!(names(&FatFilesystem::new(dev).unwrap()).is_empty());
+    }
+}
+
+#[test]
+fn test_torn_records_fail_checksum() {
+    let (dev, bpb) = formatted(2880, FatType::Fat12, true);
+    let fs = FatFilesystem::new(dev.clone()).unwrap();
+    let tx = add_file(&dev, &bpb);
+
+    // Committed, then the last record is corrupted behind the journal's back
+    dev.fail_write(3);
+    assert!(fs.commit_metadata(&tx).is_err());
+    dev.clear_faults();
+    drop(fs);
+    let journal = bpb.reserved_sector_count as usize - JOURNAL_SECTORS as usize;
+    dev.poke((journal + 3) * SECTOR, b"garbage");
+
+    assert!(names(&FatFilesystem::new(dev).unwrap()).is_empty());
+}
+
+#[test]
+fn test_transaction_limits() {
+    let (dev, _) = formatted(2880, FatType::Fat12, true);
+    let fs = FatFilesystem::new(dev).unwrap();
+
+    // Rewriting a sector replaces its earlier image
+    let mut tx = Transaction::new();
+    tx.write(100, &[1; SECTOR]);
+    tx.write(100, &[2; SECTOR]);
+    assert_eq!(tx.len(), 1);
+
+    let mut big = Transaction::new();
+    for sector in 0..=MAX_RECORDS as u64 {
+        big.write(100 + sector, &[0; SECTOR]);
+    }
+    assert_eq!(fs.commit_metadata(&big).err(), Some(VfsError::NoSpace));
+    assert_eq!(fs.commit_metadata(&Transaction::new()), Ok(()));
+}
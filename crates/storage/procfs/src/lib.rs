@@ -11,11 +11,22 @@
 //! │   ├── status      process status
 //! │   ├── cmdline     command line arguments
 //! │   ├── cwd         current working directory (symlink)
+//! │   ├── io          reads and writes through file descriptors
 //! │   └── fd/         open file descriptors
-//! ├── cpuinfo         CPU information
+//! ├── cpuinfo         CPU identification, flags, frequency, temperature
 //! ├── meminfo         memory information
 //! ├── uptime          system uptime
-//! └── mounts          mounted filesystems
+//! ├── mounts          mounted filesystems
+//! ├── diskstats       reads and writes per mount
+//! ├── cmdline         kernel command line
+//! ├── profile         sampling profiler report (write start/stop/reset)
+//! ├── profile.folded  profiler samples as folded stacks for flamegraphs
+//! ├── ksyms           kernel symbol map in nm format (from C:/kernel.sym)
+//! ├── power           power state and devices (write a command, e.g. test)
+//! ├── blockcache      block cache tunables and counters (write "name value")
+//! ├── heap            outstanding kernel heap allocations (debug builds)
+//! └── sys/            kernel tunables (watos_sysctl): sys/vm/blockcache/readahead
+//!                     is vm.blockcache.readahead; write a value to change it
 //! ```
 //!
 //! # Usage
@@ -32,6 +43,7 @@ extern crate alloc;
 
 use alloc::boxed::Box;
 use alloc::format;
+use alloc::sync::Arc;
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::vec;
@@ -39,7 +51,7 @@ use spin::Mutex;
 
 use watos_vfs::{
     DirEntry, FileMode, FileOperations, FileStat, FileType, Filesystem, FsStats,
-    SeekFrom, VfsError, VfsResult,
+    IoCounts, SeekFrom, VfsError, VfsResult,
 };
 
 /// Process state for procfs
@@ -62,8 +74,23 @@ pub struct ProcessInfo {
     pub cwd: String,
     pub uid: u32,
     pub gid: u32,
+    /// Mapped user memory (VmSize)
     pub memory_kb: u64,
+    /// Physical memory owned by the process (VmRSS)
+    pub rss_kb: u64,
+    pub code_kb: u64,
+    pub heap_kb: u64,
+    pub stack_kb: u64,
+    /// Bytes currently allocated from the kernel heap
+    pub malloc_bytes: u64,
+    /// Total CPU time (user + system)
     pub cpu_time_ms: u64,
+    pub user_time_ms: u64,
+    pub system_time_ms: u64,
+    /// Scheduling niceness, -20 (favoured) to 19
+    pub nice: i32,
+    /// Reads and writes through file descriptors
+    pub io: IoCounts,
 }
 
 /// Trait for providing process information to procfs
@@ -89,8 +116,63 @@ pub trait SystemProvider: Send + Sync {
     /// Get uptime in seconds
     fn uptime_secs(&self) -> u64;
 
+    /// Get the time the CPU has spent halted with nothing to do, in seconds
+    fn idle_secs(&self) -> u64 {
+        0
+    }
+
     /// Get mount info string
     fn mounts_info(&self) -> String;
+
+    /// Get the kernel command line
+    fn cmdline(&self) -> String {
+        String::new()
+    }
+
+    /// Get the sampling profiler report, or folded stacks if `folded`
+    /// (None if there is no profiler)
+    fn profile(&self, _folded: bool) -> Option<String> {
+        None
+    }
+
+    /// Handle a command written to /proc/profile; false if not understood
+    fn profile_control(&self, _command: &str) -> bool {
+        false
+    }
+
+    /// Get the kernel symbol table as "ADDRESS T NAME" lines (None if the
+    /// kernel keeps no symbols)
+    fn ksyms(&self) -> Option<String> {
+        None
+    }
+
+    /// Get the power state and the devices with power hooks (None if the
+    /// kernel has no power management)
+    fn power(&self) -> Option<String> {
+        None
+    }
+
+    /// Handle a command written to /proc/power; false if not understood
+    fn power_control(&self, _command: &str) -> bool {
+        false
+    }
+
+    /// Get the block cache tunables and counters (None if there is no
+    /// block cache)
+    fn block_cache(&self) -> Option<String> {
+        None
+    }
+
+    /// Handle a command written to /proc/blockcache; false if not understood
+    fn block_cache_control(&self, _command: &str) -> bool {
+        false
+    }
+
+    /// Get the kernel heap debugging report (None unless the kernel heap
+    /// tracks allocations)
+    fn heap_report(&self) -> Option<String> {
+        None
+    }
 }
 
 /// Default system provider with stub data
@@ -134,7 +216,7 @@ impl ProcessProvider for DefaultProcessProvider {
 /// ProcFS - Process Filesystem
 pub struct ProcFs {
     process_provider: Mutex<Box<dyn ProcessProvider>>,
-    system_provider: Mutex<Box<dyn SystemProvider>>,
+    system_provider: Arc<Mutex<Box<dyn SystemProvider>>>,
 }
 
 impl ProcFs {
@@ -142,7 +224,7 @@ impl ProcFs {
     pub fn new() -> Self {
         ProcFs {
             process_provider: Mutex::new(Box::new(DefaultProcessProvider)),
-            system_provider: Mutex::new(Box::new(DefaultSystemProvider)),
+            system_provider: Arc::new(Mutex::new(Box::new(DefaultSystemProvider))),
         }
     }
 
@@ -180,13 +262,34 @@ impl ProcFs {
                  PPid:\t{}\n\
                  Uid:\t{}\n\
                  Gid:\t{}\n\
-                 VmSize:\t{} kB\n",
+                 VmSize:\t{} kB\n\
+                 VmRSS:\t{} kB\n\
+                 VmExe:\t{} kB\n\
+                 VmData:\t{} kB\n\
+                 VmStk:\t{} kB\n\
+                 Malloc:\t{} bytes\n\
+                 CpuTime:\t{} ms\n\
+                 UserTime:\t{} ms\n\
+                 SysTime:\t{} ms\n\
+                 Priority:\t{}\n\
+                 Nice:\t{}\n",
                 info.name, info.state, info.pid, info.ppid,
-                info.uid, info.gid, info.memory_kb
+                info.uid, info.gid, info.memory_kb, info.rss_kb,
+                info.code_kb, info.heap_kb, info.stack_kb, info.malloc_bytes,
+                info.cpu_time_ms,
+                info.user_time_ms, info.system_time_ms,
+                20 + info.nice, info.nice
             )),
             "cmdline" => Some(info.cmdline.clone()),
             "comm" => Some(format!("{}\n", info.name)),
             "cwd" => Some(info.cwd.clone()),
+            "io" => Some(format!(
+                "rchar: {}\n\
+                 wchar: {}\n\
+                 syscr: {}\n\
+                 syscw: {}\n",
+                info.io.read_bytes, info.io.write_bytes, info.io.reads, info.io.writes
+            )),
             _ => None,
         }
     }
@@ -198,9 +301,17 @@ impl ProcFs {
         match file {
             "cpuinfo" => Some(provider.cpu_info()),
             "meminfo" => Some(provider.mem_info()),
-            "uptime" => Some(format!("{}.00 0.00\n", provider.uptime_secs())),
+            "uptime" => Some(format!("{}.00 {}.00\n", provider.uptime_secs(), provider.idle_secs())),
             "mounts" => Some(provider.mounts_info()),
+            "diskstats" => Some(disk_stats()),
             "version" => Some(String::from("WATOS version 0.1.0\n")),
+            "cmdline" => Some(format!("{}\n", provider.cmdline())),
+            "profile" => provider.profile(false),
+            "profile.folded" => provider.profile(true),
+            "heap" => provider.heap_report(),
+            "power" => provider.power(),
+            "blockcache" => provider.block_cache(),
+            "ksyms" => provider.ksyms(),
             _ => None,
         }
     }
@@ -240,6 +351,39 @@ impl Filesystem for ProcFs {
             components
         };
 
+        // Kernel tunables at /proc/sys/a/b/c
+        if components[0] == "sys" {
+            let name = components[1..].join(".");
+            if watos_sysctl::find(&name).is_none() {
+                return Err(if components.len() == 1 || watos_sysctl::is_dir(&name) {
+                    VfsError::IsADirectory
+                } else {
+                    VfsError::NotFound
+                });
+            }
+            if _mode.write || _mode.append {
+                return Ok(Box::new(SysctlFile { name }));
+            }
+            let content = watos_sysctl::read(&name).map_err(sysctl_error)?;
+            return Ok(Box::new(ProcFile::new(content)));
+        }
+
+        // Writing /proc/profile, /proc/power or /proc/blockcache sends a command
+        let control = match components[..] {
+            ["profile"] => Some(Control::Profile),
+            ["power"] => Some(Control::Power),
+            ["blockcache"] => Some(Control::BlockCache),
+            _ => None,
+        };
+        if let Some(control) = control {
+            if _mode.write || _mode.append {
+                return Ok(Box::new(ControlFile {
+                    provider: self.system_provider.clone(),
+                    control,
+                }));
+            }
+        }
+
         // System files at /proc/xxx
         if components.len() == 1 {
             if let Some(content) = self.get_system_file_content(components[0]) {
@@ -296,6 +440,32 @@ impl Filesystem for ProcFs {
             });
         }
 
+        // Kernel tunables and their directories
+        if components[0] == "sys" {
+            let name = components[1..].join(".");
+            if components.len() == 1 || watos_sysctl::is_dir(&name) {
+                return Ok(FileStat {
+                    file_type: FileType::Directory,
+                    size: 0,
+                    nlink: 2,
+                    inode: 3,
+                    mode: 0o555,
+                    ..Default::default()
+                });
+            }
+            if watos_sysctl::find(&name).is_some() {
+                return Ok(FileStat {
+                    file_type: FileType::Regular,
+                    size: 0,
+                    nlink: 1,
+                    inode: 112,
+                    mode: 0o644,
+                    ..Default::default()
+                });
+            }
+            return Err(VfsError::NotFound);
+        }
+
         // System files
         if components.len() == 1 {
             if self.get_system_file_content(components[0]).is_some() {
@@ -367,57 +537,102 @@ impl Filesystem for ProcFs {
                     file_type: FileType::Symlink,
                     size: 0,
                     inode: 2,
-                    mode: 0o777,
-                    uid: 0,
-                    gid: 0,
                 },
                 DirEntry {
                     name: String::from("cpuinfo"),
                     file_type: FileType::Regular,
                     size: 0,
                     inode: 100,
-                    mode: 0o444,
-                    uid: 0,
-                    gid: 0,
                 },
                 DirEntry {
                     name: String::from("meminfo"),
                     file_type: FileType::Regular,
                     size: 0,
                     inode: 101,
-                    mode: 0o444,
-                    uid: 0,
-                    gid: 0,
                 },
                 DirEntry {
                     name: String::from("uptime"),
                     file_type: FileType::Regular,
                     size: 0,
                     inode: 102,
-                    mode: 0o444,
-                    uid: 0,
-                    gid: 0,
                 },
                 DirEntry {
                     name: String::from("mounts"),
                     file_type: FileType::Regular,
                     size: 0,
                     inode: 103,
-                    mode: 0o444,
-                    uid: 0,
-                    gid: 0,
+                },
+                DirEntry {
+                    name: String::from("diskstats"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 113,
                 },
                 DirEntry {
                     name: String::from("version"),
                     file_type: FileType::Regular,
                     size: 0,
                     inode: 104,
-                    mode: 0o444,
-                    uid: 0,
-                    gid: 0,
+                },
+                DirEntry {
+                    name: String::from("profile"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 105,
+                },
+                DirEntry {
+                    name: String::from("profile.folded"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 106,
+                },
+                DirEntry {
+                    name: String::from("cmdline"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 108,
+                },
+                DirEntry {
+                    name: String::from("ksyms"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 110,
+                },
+                DirEntry {
+                    name: String::from("sys"),
+                    file_type: FileType::Directory,
+                    size: 0,
+                    inode: 3,
                 },
             ];
 
+            if self.system_provider.lock().power().is_some() {
+                entries.push(DirEntry {
+                    name: String::from("power"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 109,
+                });
+            }
+
+            if self.system_provider.lock().block_cache().is_some() {
+                entries.push(DirEntry {
+                    name: String::from("blockcache"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 111,
+                });
+            }
+
+            if self.system_provider.lock().heap_report().is_some() {
+                entries.push(DirEntry {
+                    name: String::from("heap"),
+                    file_type: FileType::Regular,
+                    size: 0,
+                    inode: 107,
+                });
+            }
+
             // Add process directories
             let provider = self.process_provider.lock();
             for pid in provider.list_pids() {
@@ -426,15 +641,30 @@ impl Filesystem for ProcFs {
                     file_type: FileType::Directory,
                     size: 0,
                     inode: 1000 + pid as u64,
-                    mode: 0o555,
-                    uid: 0,
-                    gid: 0,
                 });
             }
 
             return Ok(entries);
         }
 
+        // Tunables directory listing
+        if components[0] == "sys" {
+            let dir = components[1..].join(".");
+            if components.len() > 1 && !watos_sysctl::is_dir(&dir) {
+                return Err(VfsError::NotADirectory);
+            }
+            let entries = watos_sysctl::children(&dir)
+                .into_iter()
+                .map(|(name, is_dir)| DirEntry {
+                    name,
+                    file_type: if is_dir { FileType::Directory } else { FileType::Regular },
+                    size: 0,
+                    inode: if is_dir { 3 } else { 112 },
+                })
+                .collect();
+            return Ok(entries);
+        }
+
         // Process directory listing
         if let Some(pid) = Self::parse_pid(components[0]) {
             let provider = self.process_provider.lock();
@@ -445,36 +675,30 @@ impl Filesystem for ProcFs {
                         file_type: FileType::Regular,
                         size: 0,
                         inode: 2000 + pid as u64,
-                        mode: 0o444,
-                        uid: 0,
-                        gid: 0,
                     },
                     DirEntry {
                         name: String::from("cmdline"),
                         file_type: FileType::Regular,
                         size: 0,
                         inode: 2001 + pid as u64,
-                        mode: 0o444,
-                        uid: 0,
-                        gid: 0,
                     },
                     DirEntry {
                         name: String::from("comm"),
                         file_type: FileType::Regular,
                         size: 0,
                         inode: 2002 + pid as u64,
-                        mode: 0o444,
-                        uid: 0,
-                        gid: 0,
                     },
                     DirEntry {
                         name: String::from("cwd"),
                         file_type: FileType::Symlink,
                         size: 0,
                         inode: 2003 + pid as u64,
-                        mode: 0o777,
-                        uid: 0,
-                        gid: 0,
+                    },
+                    DirEntry {
+                        name: String::from("io"),
+                        file_type: FileType::Regular,
+                        size: 0,
+                        inode: 2004 + pid as u64,
                     },
                 ]);
             }
@@ -574,3 +798,124 @@ impl FileOperations for ProcFile {
         Err(VfsError::ReadOnly)
     }
 }
+
+/// Which writable file a ControlFile is
+#[derive(Clone, Copy)]
+enum Control {
+    Profile,
+    Power,
+    BlockCache,
+}
+
+/// Write side of /proc/profile, /proc/power and /proc/blockcache: each write
+/// is a command for the profiler, the power coordinator or the block cache
+struct ControlFile {
+    provider: Arc<Mutex<Box<dyn SystemProvider>>>,
+    control: Control,
+}
+
+impl FileOperations for ControlFile {
+    fn read(&mut self, _buffer: &mut [u8]) -> VfsResult<usize> {
+        Ok(0)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+        let command = core::str::from_utf8(buffer).map_err(|_| VfsError::InvalidArgument)?;
+        let provider = self.provider.lock();
+        let done = match self.control {
+            Control::Profile => provider.profile_control(command.trim()),
+            Control::Power => provider.power_control(command.trim()),
+            Control::BlockCache => provider.block_cache_control(command.trim()),
+        };
+        if done {
+            Ok(buffer.len())
+        } else {
+            Err(VfsError::InvalidArgument)
+        }
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+        Ok(0)
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        Ok(FileStat {
+            file_type: FileType::Regular,
+            size: 0,
+            mode: 0o644,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Ok(()) // Opening for write truncates; there is nothing to truncate
+    }
+}
+
+/// VFS error for a failed sysctl read or write
+/// Reads and writes per mount, as /proc/diskstats shows them
+fn disk_stats() -> String {
+    let mut out = String::from("# mount reads read_bytes writes write_bytes\n");
+    for (mount, io) in watos_vfs::io_stats() {
+        out.push_str(&format!("{} {} {} {} {}\n", mount, io.reads, io.read_bytes, io.writes, io.write_bytes));
+    }
+    out
+}
+
+fn sysctl_error(error: watos_sysctl::Error) -> VfsError {
+    match error {
+        watos_sysctl::Error::NotFound => VfsError::NotFound,
+        watos_sysctl::Error::Denied => VfsError::PermissionDenied,
+        watos_sysctl::Error::Invalid | watos_sysctl::Error::Exists => VfsError::InvalidArgument,
+    }
+}
+
+/// Write side of a /proc/sys file: each write is a new value for the tunable
+struct SysctlFile {
+    name: String,
+}
+
+impl FileOperations for SysctlFile {
+    fn read(&mut self, _buffer: &mut [u8]) -> VfsResult<usize> {
+        Ok(0)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buffer).map_err(|_| VfsError::InvalidArgument)?;
+        watos_sysctl::write(&self.name, value).map_err(sysctl_error)?;
+        Ok(buffer.len())
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+        Ok(0)
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        Ok(FileStat {
+            file_type: FileType::Regular,
+            size: 0,
+            mode: 0o644,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Ok(()) // Opening for write truncates; there is nothing to truncate
+    }
+}
@@ -0,0 +1,584 @@
+//! WATOS TFTP Filesystem
+//!
+//! A read-only filesystem whose files come from a TFTP server (RFC 1350),
+//! so development files on the host can be used without rebuilding disk
+//! images:
+//!
+//! ```ignore
+//! let fs = TftpFs::new(Box::new(udp_socket_to_host));
+//! watos_vfs::mount_drive('T', Box::new(fs))?;
+//! ```
+//!
+//! Files are fetched whole when opened, in octet mode, asking for larger
+//! blocks (RFC 2348) and the transfer size (RFC 2349); servers without
+//! option support get plain 512-byte transfers. `stat` asks for the size
+//! and abandons the transfer.
+//!
+//! TFTP can't list directories. A directory is one with a `.dir` file,
+//! one name per line, with a trailing `/` on subdirectories, which the
+//! host can keep up to date with `ls -p > .dir`.
+//!
+//! UDP comes from the caller through [`Datagram`]; the network stack has
+//! no UDP sockets yet.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+use watos_vfs::{
+    DirEntry, FileMode, FileOperations, FileStat, FileType, Filesystem, FsStats, SeekFrom,
+    VfsError, VfsResult,
+};
+
+/// Well-known TFTP server port
+pub const TFTP_PORT: u16 = 69;
+
+/// Block size asked for: fits an Ethernet frame with IP and UDP headers
+pub const BLOCK_SIZE: usize = 1428;
+
+/// Block size when the server ignores options
+const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// How long to wait for each packet before resending, unless tuned
+/// through `net.tftp.timeout_ms`
+pub const TIMEOUT_MS: u32 = 1000;
+
+/// Resends before giving up on a transfer, unless tuned through
+/// `net.tftp.retries`
+pub const RETRIES: u32 = 5;
+
+static TIMEOUT: AtomicU32 = AtomicU32::new(TIMEOUT_MS);
+static MAX_RETRIES: AtomicU32 = AtomicU32::new(RETRIES);
+
+/// Register the timeout and retry count as `net.tftp.*` sysctls; done by
+/// the first `TftpFs::new`, later calls change nothing
+pub fn register_sysctls() {
+    use watos_sysctl::{register, Kind, Tunable};
+
+    let _ = register(Tunable {
+        name: "net.tftp.timeout_ms",
+        description: "milliseconds to wait for a packet before resending",
+        kind: Kind::Int { min: 10, max: 60_000 },
+        get: || TIMEOUT.load(Ordering::Relaxed) as u64,
+        set: |ms| {
+            TIMEOUT.store(ms as u32, Ordering::Relaxed);
+            true
+        },
+    });
+    let _ = register(Tunable {
+        name: "net.tftp.retries",
+        description: "resends before a transfer fails",
+        kind: Kind::Int { min: 0, max: 100 },
+        get: || MAX_RETRIES.load(Ordering::Relaxed) as u64,
+        set: |n| {
+            MAX_RETRIES.store(n as u32, Ordering::Relaxed);
+            true
+        },
+    });
+}
+
+/// Largest file fetched
+pub const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Name of the listing file in each directory
+pub const DIR_LISTING: &str = ".dir";
+
+// Opcodes
+const RRQ: u16 = 1;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+const OACK: u16 = 6;
+
+// Error codes
+const ERR_NOT_FOUND: u16 = 1;
+const ERR_ACCESS: u16 = 2;
+const ERR_OPTION: u16 = 8;
+
+/// UDP to the TFTP server's address
+pub trait Datagram: Send {
+    /// Send a datagram to `port` on the server
+    fn send_to(&mut self, port: u16, data: &[u8]) -> VfsResult<()>;
+
+    /// Receive a datagram from the server within `timeout_ms`, returning
+    /// its length and source port; None on timeout
+    fn recv_from(&mut self, buf: &mut [u8], timeout_ms: u32) -> Option<(usize, u16)>;
+}
+
+// ============================================================================
+// TFTP CLIENT
+// ============================================================================
+
+/// How much of a file a transfer fetches
+#[derive(Clone, Copy, PartialEq)]
+enum Want {
+    /// Only the size, from the server's options
+    Size,
+    /// The whole file
+    Contents,
+}
+
+fn read_request(path: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(path.len() + 40);
+    packet.extend_from_slice(&RRQ.to_be_bytes());
+    for field in [path, "octet", "blksize", &format!("{}", BLOCK_SIZE), "tsize", "0"] {
+        packet.extend_from_slice(field.as_bytes());
+        packet.push(0);
+    }
+    packet
+}
+
+fn ack(block: u16) -> [u8; 4] {
+    let mut packet = [0u8; 4];
+    packet[..2].copy_from_slice(&ACK.to_be_bytes());
+    packet[2..].copy_from_slice(&block.to_be_bytes());
+    packet
+}
+
+/// An ERROR packet, to abandon a transfer
+fn error_packet(code: u16) -> [u8; 5] {
+    let mut packet = [0u8; 5];
+    packet[..2].copy_from_slice(&ERROR.to_be_bytes());
+    packet[2..4].copy_from_slice(&code.to_be_bytes());
+    packet
+}
+
+fn error_from_code(code: u16) -> VfsError {
+    match code {
+        ERR_NOT_FOUND => VfsError::NotFound,
+        ERR_ACCESS => VfsError::PermissionDenied,
+        _ => VfsError::IoError,
+    }
+}
+
+/// Options acknowledged in an OACK: block size and transfer size
+fn parse_oack(body: &[u8]) -> (Option<usize>, Option<u64>) {
+    let mut fields = body.split(|&b| b == 0).map(|f| core::str::from_utf8(f).unwrap_or(""));
+    let (mut blksize, mut tsize) = (None, None);
+    while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+        if name.eq_ignore_ascii_case("blksize") {
+            blksize = value.parse().ok();
+        } else if name.eq_ignore_ascii_case("tsize") {
+            tsize = value.parse().ok();
+        }
+    }
+    (blksize, tsize)
+}
+
+/// Run one read transfer. Returns the file's size if known, and its
+/// contents when `want` is Contents.
+fn transfer(socket: &mut dyn Datagram, path: &str, want: Want) -> VfsResult<(Option<u64>, Vec<u8>)> {
+    let request = read_request(path);
+    socket.send_to(TFTP_PORT, &request)?;
+
+    let mut last_sent: Vec<u8> = request;
+    let mut last_port = TFTP_PORT;
+    let mut server_port = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut data = Vec::new();
+    let mut expected: u16 = 1;
+    let mut retries = 0;
+    let mut buf = alloc::vec![0u8; 4 + BLOCK_SIZE];
+
+    loop {
+        let (len, port) = match socket.recv_from(&mut buf, TIMEOUT.load(Ordering::Relaxed)) {
+            Some(got) => got,
+            None => {
+                retries += 1;
+                if retries > MAX_RETRIES.load(Ordering::Relaxed) {
+                    return Err(VfsError::IoError);
+                }
+                socket.send_to(last_port, &last_sent)?;
+                continue;
+            }
+        };
+        // Everything after the first reply must come from the same port
+        if server_port.is_some_and(|p| p != port) || len < 4 {
+            continue;
+        }
+        let packet = &buf[..len];
+        let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+        let number = u16::from_be_bytes([packet[2], packet[3]]);
+
+        match opcode {
+            ERROR => return Err(error_from_code(number)),
+            OACK if server_port.is_none() => {
+                server_port = Some(port);
+                let (blksize, tsize) = parse_oack(&packet[2..]);
+                if let Some(b) = blksize {
+                    if b == 0 || b > BLOCK_SIZE {
+                        let _ = socket.send_to(port, &error_packet(ERR_OPTION));
+                        return Err(VfsError::IoError);
+                    }
+                    block_size = b;
+                }
+                let size = tsize;
+                if size.is_some_and(|s| s > MAX_FILE_SIZE) || (want == Want::Size && size.is_some()) {
+                    let _ = socket.send_to(port, &error_packet(ERR_OPTION));
+                    return match size {
+                        Some(s) if s > MAX_FILE_SIZE => Err(VfsError::NoSpace),
+                        _ => Ok((size, data)),
+                    };
+                }
+                last_sent = ack(0).to_vec();
+                last_port = port;
+                socket.send_to(port, &last_sent)?;
+                retries = 0;
+            }
+            DATA => {
+                server_port.get_or_insert(port);
+                if number == expected {
+                    let payload = &packet[4..];
+                    if payload.len() > block_size || (data.len() + payload.len()) as u64 > MAX_FILE_SIZE {
+                        let _ = socket.send_to(port, &error_packet(0));
+                        return Err(VfsError::IoError);
+                    }
+                    data.extend_from_slice(payload);
+                    last_sent = ack(number).to_vec();
+                    last_port = port;
+                    socket.send_to(port, &last_sent)?;
+                    retries = 0;
+                    if payload.len() < block_size {
+                        return Ok((Some(data.len() as u64), data));
+                    }
+                    expected = expected.wrapping_add(1);
+                } else if number == expected.wrapping_sub(1) {
+                    // Our ACK was lost; the server resent the block
+                    socket.send_to(port, &last_sent)?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// ============================================================================
+// FILESYSTEM
+// ============================================================================
+
+/// Read-only filesystem over a TFTP server
+pub struct TftpFs {
+    socket: Mutex<Box<dyn Datagram>>,
+}
+
+impl TftpFs {
+    pub fn new(socket: Box<dyn Datagram>) -> Self {
+        register_sysctls();
+        TftpFs { socket: Mutex::new(socket) }
+    }
+
+    /// Server-side name for a path: relative, without a leading slash
+    fn remote(path: &str) -> &str {
+        path.trim_start_matches('/')
+    }
+
+    fn fetch(&self, path: &str) -> VfsResult<Vec<u8>> {
+        let mut socket = self.socket.lock();
+        transfer(&mut **socket, Self::remote(path), Want::Contents).map(|(_, data)| data)
+    }
+
+    fn listing_path(dir: &str) -> String {
+        let dir = Self::remote(dir).trim_end_matches('/');
+        if dir.is_empty() {
+            String::from(DIR_LISTING)
+        } else {
+            format!("{}/{}", dir, DIR_LISTING)
+        }
+    }
+}
+
+fn dir_stat() -> FileStat {
+    FileStat { file_type: FileType::Directory, nlink: 2, mode: 0o555, ..Default::default() }
+}
+
+impl Filesystem for TftpFs {
+    fn name(&self) -> &'static str {
+        "tftp"
+    }
+
+    fn open(&self, path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
+        if mode.write || mode.create || mode.truncate || mode.append {
+            return Err(VfsError::ReadOnly);
+        }
+        let data = self.fetch(path)?;
+        Ok(Box::new(TftpFile { data, position: 0 }))
+    }
+
+    fn stat(&self, path: &str) -> VfsResult<FileStat> {
+        if Self::remote(path).is_empty() {
+            return Ok(dir_stat());
+        }
+        let size = {
+            let mut socket = self.socket.lock();
+            transfer(&mut **socket, Self::remote(path), Want::Size)
+        };
+        match size {
+            Ok((Some(size), _)) => Ok(FileStat {
+                file_type: FileType::Regular,
+                size,
+                nlink: 1,
+                mode: 0o444,
+                blksize: BLOCK_SIZE as u32,
+                blocks: size.div_ceil(512),
+                ..Default::default()
+            }),
+            // No tsize support: fetch the file to learn its size
+            Ok((None, _)) => self.open(path, FileMode::READ)?.stat(),
+            Err(VfsError::NotFound) => {
+                self.fetch(&Self::listing_path(path))?;
+                Ok(dir_stat())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn mkdir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn unlink(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn rmdir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
+        let listing = match self.fetch(&Self::listing_path(path)) {
+            Ok(listing) => listing,
+            Err(VfsError::NotFound) => return Err(VfsError::NotSupported),
+            Err(e) => return Err(e),
+        };
+        let text = core::str::from_utf8(&listing).map_err(|_| VfsError::IoError)?;
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && *line != DIR_LISTING)
+            .enumerate()
+            .map(|(i, line)| {
+                let (name, file_type) = match line.strip_suffix('/') {
+                    Some(dir) => (dir, FileType::Directory),
+                    None => (line, FileType::Regular),
+                };
+                DirEntry { name: String::from(name), file_type, size: 0, inode: i as u64 + 2 }
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn rename(&self, _old_path: &str, _new_path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<FsStats> {
+        Ok(FsStats {
+            total_blocks: 0,
+            free_blocks: 0,
+            block_size: BLOCK_SIZE as u32,
+            total_inodes: 0,
+            free_inodes: 0,
+            max_name_len: 255,
+        })
+    }
+}
+
+/// A fetched file, held in memory
+struct TftpFile {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl FileOperations for TftpFile {
+    fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+        let remaining = &self.data[self.position.min(self.data.len())..];
+        let n = remaining.len().min(buffer.len());
+        buffer[..n].copy_from_slice(&remaining[..n]);
+        self.position += n;
+        Ok(n)
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
+        let base = match whence {
+            SeekFrom::Start => 0,
+            SeekFrom::Current => self.position as i64,
+            SeekFrom::End => self.data.len() as i64,
+        };
+        let position = base + offset;
+        if position < 0 {
+            return Err(VfsError::InvalidArgument);
+        }
+        self.position = position as usize;
+        Ok(self.position as u64)
+    }
+
+    fn tell(&self) -> u64 {
+        self.position as u64
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        Ok(FileStat {
+            file_type: FileType::Regular,
+            size: self.data.len() as u64,
+            nlink: 1,
+            mode: 0o444,
+            blksize: BLOCK_SIZE as u32,
+            blocks: (self.data.len() as u64).div_ceil(512),
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+
+    /// A TFTP server in memory, answering from port 2000
+    struct FakeServer {
+        files: Vec<(&'static str, Vec<u8>)>,
+        options: bool,
+        /// Drop the first copy of this DATA block, as if lost
+        lose_block: Option<u16>,
+        serving: Option<(Vec<u8>, usize)>,
+        outbox: VecDeque<Vec<u8>>,
+    }
+
+    impl FakeServer {
+        fn new(options: bool) -> Self {
+            FakeServer {
+                files: vec![
+                    ("hello.txt", b"hello, world\n".to_vec()),
+                    ("big.bin", (0..5000u32).map(|i| i as u8).collect()),
+                    ("exact.bin", vec![7u8; 2 * BLOCK_SIZE]),
+                    (".dir", b"hello.txt\nbig.bin\nexact.bin\nsub/\n".to_vec()),
+                    ("sub/.dir", b"".to_vec()),
+                ],
+                options,
+                lose_block: None,
+                serving: None,
+                outbox: VecDeque::new(),
+            }
+        }
+
+        fn queue_block(&mut self, block: u16) {
+            let (data, size) = self.serving.as_ref().unwrap();
+            let start = (block as usize - 1) * size;
+            if start > data.len() {
+                return;
+            }
+            let end = (start + size).min(data.len());
+            let mut packet = vec![0, DATA as u8];
+            packet.extend_from_slice(&block.to_be_bytes());
+            packet.extend_from_slice(&data[start..end]);
+            self.outbox.push_back(packet);
+        }
+    }
+
+    impl Datagram for FakeServer {
+        fn send_to(&mut self, port: u16, packet: &[u8]) -> VfsResult<()> {
+            let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+            match (opcode, port) {
+                (RRQ, TFTP_PORT) => {
+                    let name = packet[2..].split(|&b| b == 0).next().unwrap();
+                    let file = self.files.iter().find(|(n, _)| n.as_bytes() == name);
+                    match file.map(|(_, data)| data.clone()) {
+                        None => self.outbox.push_back(vec![0, ERROR as u8, 0, ERR_NOT_FOUND as u8, 0]),
+                        Some(data) if self.options => {
+                            let oack = format!("\0\x06blksize\0{}\0tsize\0{}\0", BLOCK_SIZE, data.len());
+                            self.outbox.push_back(oack.into_bytes());
+                            self.serving = Some((data, BLOCK_SIZE));
+                        }
+                        Some(data) => {
+                            self.serving = Some((data, DEFAULT_BLOCK_SIZE));
+                            self.queue_block(1);
+                        }
+                    }
+                }
+                (ACK, 2000) => {
+                    let block = u16::from_be_bytes([packet[2], packet[3]]);
+                    self.queue_block(block + 1);
+                }
+                (ERROR, 2000) => self.serving = None,
+                _ => panic!("unexpected packet to port {}", port),
+            }
+            Ok(())
+        }
+
+        fn recv_from(&mut self, buf: &mut [u8], _timeout_ms: u32) -> Option<(usize, u16)> {
+            let packet = self.outbox.pop_front()?;
+            let block = u16::from_be_bytes([packet[2], packet[3]]);
+            if packet[1] == DATA as u8 && self.lose_block == Some(block) {
+                self.lose_block = None;
+                return None;
+            }
+            buf[..packet.len()].copy_from_slice(&packet);
+            Some((packet.len(), 2000))
+        }
+    }
+
+    fn read_all(fs: &TftpFs, path: &str) -> VfsResult<Vec<u8>> {
+        let mut file = fs.open(path, FileMode::READ)?;
+        let mut data = Vec::new();
+        let mut buf = [0u8; 700];
+        loop {
+            match file.read(&mut buf)? {
+                0 => return Ok(data),
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_files() {
+        for options in [true, false] {
+            let mut server = FakeServer::new(options);
+            server.lose_block = Some(2);
+            let fs = TftpFs::new(Box::new(server));
+            assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, world\n");
+            let big = read_all(&fs, "/big.bin").unwrap();
+            assert_eq!(big.len(), 5000);
+            assert!(big.iter().enumerate().all(|(i, &b)| b == i as u8));
+            assert_eq!(read_all(&fs, "/exact.bin").unwrap().len(), 2 * BLOCK_SIZE);
+            assert_eq!(read_all(&fs, "/missing").err(), Some(VfsError::NotFound));
+            assert_eq!(fs.stat("/big.bin").unwrap().size, 5000);
+        }
+    }
+
+    #[test]
+    fn test_directories() {
+        let fs = TftpFs::new(Box::new(FakeServer::new(true)));
+        let names: Vec<_> = fs.readdir("/").unwrap().into_iter().map(|e| (e.name, e.file_type)).collect();
+        assert_eq!(names.len(), 4);
+        assert_eq!(names[3], (String::from("sub"), FileType::Directory));
+        assert_eq!(fs.stat("/sub").unwrap().file_type, FileType::Directory);
+        assert!(fs.readdir("/sub").unwrap().is_empty());
+        assert_eq!(fs.stat("/nothing").err(), Some(VfsError::NotFound));
+        assert_eq!(fs.readdir("/nothing").err(), Some(VfsError::NotSupported));
+        assert_eq!(fs.open("/new", FileMode::WRITE).err(), Some(VfsError::ReadOnly));
+    }
+}
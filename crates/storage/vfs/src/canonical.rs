@@ -0,0 +1,322 @@
+//! Path canonicalization
+//!
+//! Turns any path a process can name into the single absolute path the VFS
+//! knows the file by, so two spellings of the same file compare equal:
+//!
+//! - Relative paths are joined to the caller's working directory
+//! - `.` and `..` are resolved; on a drive `..` stops at the drive root
+//! - Symbolic links are followed, at most `MAX_SYMLINK_DEPTH` of them
+//! - Every step is looked up through the mount table, so links may cross
+//!   from one mount into another
+//! - On mounts that ignore case, names are replaced by the spelling stored
+//!   in the directory, so `c:/apps/LS` and `C:/Apps/ls` come out the same
+//!
+//! Results use forward slashes and an uppercase drive letter (`C:/apps/ls`).
+//! The final component does not have to exist, so a destination can be
+//! canonicalized before it is created; every earlier component must be a
+//! directory or a link to one.
+//!
+//! Link targets follow the same rules as paths given by a process, except
+//! that an absolute Unix target inside a drive stays on that drive.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::file::FileType;
+use crate::Filesystem;
+use crate::mount::MountTable;
+use crate::path::{is_drive_letter, join, parent, PathCmp, PathType, SEPARATOR, WIN_SEPARATOR};
+use crate::symlink::SymlinkResolver;
+use crate::{VfsError, VfsResult};
+
+/// Split a path into its root (if absolute) and its components
+fn split_root(path: &str) -> (Option<PathType>, Vec<&str>) {
+    let (root, rest) = match is_drive_letter(path) {
+        Some(letter) => (Some(PathType::Drive(letter)), &path[2..]),
+        None if path.starts_with(SEPARATOR) => (Some(PathType::Unix), path),
+        None => (None, path),
+    };
+    let components = rest
+        .split(SEPARATOR)
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    (root, components)
+}
+
+/// Render resolved components under a root
+fn render(root: PathType, components: &[String]) -> String {
+    let mut out = match root {
+        PathType::Drive(letter) => format!("{}:", letter),
+        PathType::Unix => String::new(),
+    };
+    if components.is_empty() {
+        out.push(SEPARATOR);
+    }
+    for component in components {
+        out.push(SEPARATOR);
+        out.push_str(component);
+    }
+    out
+}
+
+/// The name an existing entry is stored under, if the directory lists it
+fn stored_name(fs: &dyn Filesystem, rel_path: &str, name: &str, cmp: PathCmp) -> Option<String> {
+    let entries = fs.readdir(&parent(rel_path)?).ok()?;
+    entries.into_iter().find(|e| cmp.eq(&e.name, name)).map(|e| e.name)
+}
+
+/// Canonicalize `path` against the working directory `cwd`
+///
+/// `cwd` must be absolute (e.g. `C:\users\root`); it is only used when
+/// `path` is relative.
+pub fn canonicalize(mounts: &MountTable, path: &str, cwd: &str) -> VfsResult<String> {
+    if path.is_empty() {
+        return Err(VfsError::InvalidPath);
+    }
+
+    let joined = join(cwd, path).replace(WIN_SEPARATOR, "/");
+    let (root, components) = split_root(&joined);
+    let mut root = root.ok_or(VfsError::InvalidPath)?;
+
+    // Components still to visit, in reverse so the next one is on top
+    let mut pending: Vec<String> = components.into_iter().rev().map(String::from).collect();
+    let mut resolved: Vec<String> = Vec::new();
+    let mut links = SymlinkResolver::new();
+
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        resolved.push(name);
+        let current = render(root, &resolved);
+        let (fs, rel_path) = mounts.resolve(&current)?;
+
+        let stat = match fs.stat(&rel_path) {
+            Ok(stat) => stat,
+            Err(VfsError::NotFound) if pending.is_empty() => break,
+            Err(e) => return Err(e),
+        };
+
+        if mounts.path_cmp(&current)?.is_case_insensitive() {
+            if let Some(last) = resolved.last_mut() {
+                if let Some(stored) = stored_name(fs, &rel_path, last, PathCmp::CaseInsensitive) {
+                    *last = stored;
+                }
+            }
+        }
+
+        match stat.file_type {
+            FileType::Directory => {}
+            FileType::Symlink => {
+                links.enter()?;
+                let target = fs.readlink(&rel_path)?.replace(WIN_SEPARATOR, "/");
+                resolved.pop();
+
+                let (target_root, target_components) = split_root(&target);
+                match target_root {
+                    Some(PathType::Drive(letter)) => {
+                        root = PathType::Drive(letter);
+                        resolved.clear();
+                    }
+                    Some(PathType::Unix) => resolved.clear(),
+                    None => {}
+                }
+                pending.extend(target_components.into_iter().rev().map(String::from));
+            }
+            _ if !pending.is_empty() => return Err(VfsError::NotADirectory),
+            _ => {}
+        }
+    }
+
+    Ok(render(root, &resolved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use alloc::collections::BTreeMap;
+    use crate::{DirEntry, FileMode, FileOperations, FileStat, Filesystem, FsStats};
+
+    enum Node {
+        Dir,
+        File,
+        Link(&'static str),
+    }
+
+    /// Filesystem made of stat-able names and links, nothing else
+    struct LinkFs {
+        nodes: BTreeMap<&'static str, Node>,
+        cmp: PathCmp,
+    }
+
+    impl LinkFs {
+        fn new(nodes: Vec<(&'static str, Node)>) -> Box<Self> {
+            let mut nodes: BTreeMap<_, _> = nodes.into_iter().collect();
+            nodes.insert("/", Node::Dir);
+            Box::new(LinkFs { nodes, cmp: PathCmp::CaseSensitive })
+        }
+
+        fn ignoring_case(nodes: Vec<(&'static str, Node)>) -> Box<Self> {
+            let mut fs = Self::new(nodes);
+            fs.cmp = PathCmp::CaseInsensitive;
+            fs
+        }
+
+        fn get(&self, path: &str) -> Option<&Node> {
+            self.nodes.iter().find(|(p, _)| self.cmp.eq(p, path)).map(|(_, node)| node)
+        }
+    }
+
+    impl Filesystem for LinkFs {
+        fn name(&self) -> &'static str {
+            "linkfs"
+        }
+
+        fn open(&self, _path: &str, _mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn stat(&self, path: &str) -> VfsResult<FileStat> {
+            let file_type = match self.get(path).ok_or(VfsError::NotFound)? {
+                Node::Dir => FileType::Directory,
+                Node::File => FileType::Regular,
+                Node::Link(_) => FileType::Symlink,
+            };
+            Ok(FileStat { file_type, ..FileStat::default() })
+        }
+
+        fn mkdir(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn unlink(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn rmdir(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
+            let entries = self.nodes.keys()
+                .filter(|p| parent(p).is_some_and(|dir| self.cmp.eq(&dir, path)))
+                .map(|p| DirEntry {
+                    name: String::from(crate::path::filename(p).unwrap()),
+                    file_type: FileType::Unknown,
+                    size: 0,
+                    inode: 0,
+                })
+                .collect();
+            Ok(entries)
+        }
+
+        fn rename(&self, _old_path: &str, _new_path: &str) -> VfsResult<()> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn sync(&self) -> VfsResult<()> {
+            Ok(())
+        }
+
+        fn statfs(&self) -> VfsResult<FsStats> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn path_cmp(&self) -> PathCmp {
+            self.cmp
+        }
+
+        fn readlink(&self, path: &str) -> VfsResult<String> {
+            match self.get(path) {
+                Some(Node::Link(target)) => Ok(String::from(*target)),
+                _ => Err(VfsError::InvalidArgument),
+            }
+        }
+    }
+
+    fn mounts() -> MountTable {
+        let mut mounts = MountTable::new();
+        mounts.mount_drive('C', LinkFs::new(vec![
+            ("/apps", Node::Dir),
+            ("/apps/ls", Node::File),
+            ("/users", Node::Dir),
+            ("/users/root", Node::Dir),
+            ("/bin", Node::Link("apps")),
+            ("/users/root/up", Node::Link("../..")),
+            ("/users/root/abs", Node::Link("/apps")),
+            ("/loop", Node::Link("loop")),
+        ])).unwrap();
+        mounts.mount_drive('D', LinkFs::new(vec![("/data", Node::Dir)])).unwrap();
+        mounts.mount_drive('F', LinkFs::ignoring_case(vec![
+            ("/DOCS", Node::Dir),
+            ("/DOCS/README.TXT", Node::File),
+            ("/Docs2", Node::Link("docs")),
+        ])).unwrap();
+        mounts.mount("/proc", LinkFs::new(vec![
+            ("/self", Node::Link("D:\\data")),
+        ])).unwrap();
+        mounts
+    }
+
+    #[test]
+    fn test_relative_and_dots() {
+        let m = mounts();
+        assert_eq!(canonicalize(&m, "ls", "C:\\apps").unwrap(), "C:/apps/ls");
+        assert_eq!(canonicalize(&m, "./../apps/./ls", "C:\\users").unwrap(), "C:/apps/ls");
+        assert_eq!(canonicalize(&m, "c:/apps//ls", "D:\\").unwrap(), "C:/apps/ls");
+        // `..` never leaves the drive
+        assert_eq!(canonicalize(&m, "../../../apps", "C:\\users").unwrap(), "C:/apps");
+        assert_eq!(canonicalize(&m, "..", "C:\\").unwrap(), "C:/");
+    }
+
+    #[test]
+    fn test_follows_symlinks() {
+        let m = mounts();
+        assert_eq!(canonicalize(&m, "C:/bin/ls", "C:\\").unwrap(), "C:/apps/ls");
+        // `..` applies to where the link led, not to the link's name
+        assert_eq!(canonicalize(&m, "up/apps", "C:\\users\\root").unwrap(), "C:/apps");
+        assert_eq!(canonicalize(&m, "C:/users/root/up/..", "C:\\").unwrap(), "C:/");
+        // Absolute targets stay on the link's drive
+        assert_eq!(canonicalize(&m, "abs/ls", "C:\\users\\root").unwrap(), "C:/apps/ls");
+        assert_eq!(canonicalize(&m, "C:/loop", "C:\\"), Err(VfsError::InvalidPath));
+    }
+
+    #[test]
+    fn test_links_cross_mounts() {
+        let m = mounts();
+        assert_eq!(canonicalize(&m, "/proc/self", "C:\\").unwrap(), "D:/data");
+        assert_eq!(canonicalize(&m, "/proc/self/new", "C:\\").unwrap(), "D:/data/new");
+        assert_eq!(canonicalize(&m, "/proc/self/../x", "C:\\").unwrap(), "D:/x");
+    }
+
+    #[test]
+    fn test_only_last_component_may_be_missing() {
+        let m = mounts();
+        assert_eq!(canonicalize(&m, "bin/new", "C:\\").unwrap(), "C:/apps/new");
+        assert_eq!(canonicalize(&m, "none/new", "C:\\"), Err(VfsError::NotFound));
+        assert_eq!(canonicalize(&m, "apps/ls/x", "C:\\"), Err(VfsError::NotADirectory));
+        assert_eq!(canonicalize(&m, "E:/x", "C:\\"), Err(VfsError::NotMounted));
+        assert_eq!(canonicalize(&m, "x", "relative"), Err(VfsError::InvalidPath));
+    }
+
+    #[test]
+    fn test_case_insensitive_mount() {
+        let m = mounts();
+        assert_eq!(m.path_cmp("f:/docs").unwrap(), PathCmp::CaseInsensitive);
+        assert_eq!(m.path_cmp("C:/apps").unwrap(), PathCmp::CaseSensitive);
+
+        // The stored spelling wins over the one given
+        assert_eq!(canonicalize(&m, "f:/docs/readme.txt", "C:\\").unwrap(), "F:/DOCS/README.TXT");
+        assert_eq!(canonicalize(&m, "DOCS2/Readme.txt", "F:\\").unwrap(), "F:/DOCS/README.TXT");
+        // A name that does not exist yet keeps its spelling
+        assert_eq!(canonicalize(&m, "f:/docs/New.txt", "C:\\").unwrap(), "F:/DOCS/New.txt");
+        // Case-sensitive mounts are left alone
+        assert_eq!(canonicalize(&m, "C:/APPS", "C:\\").unwrap(), "C:/APPS");
+        assert_eq!(canonicalize(&m, "C:/APPS/ls", "C:\\"), Err(VfsError::NotFound));
+    }
+}
@@ -0,0 +1,142 @@
+//! File copying
+//!
+//! `Vfs::copy` copies a regular file in `COPY_CHUNK` pieces, calling back
+//! after each with the bytes done and the total, then gives the copy the
+//! original's mode and timestamps where the destination filesystem keeps
+//! them. `Vfs::copy_part` does the same a slice at a time, for callers that
+//! can't take a callback (SYS_COPYFILE reports progress between calls).
+//!
+//! A copy that fails is removed, so no half-written file is left behind.
+
+use alloc::vec;
+
+use crate::{FileOperations, FileStat, Filesystem, VfsError, VfsResult};
+
+/// Bytes moved per read and write: one WFS extent, and a fraction of the
+/// block cache, so a copy doesn't push everything else out of it
+pub const COPY_CHUNK: usize = 64 * 1024;
+
+/// Progress callback: (bytes done, total bytes)
+pub type CopyProgress<'a> = dyn FnMut(u64, u64) + 'a;
+
+/// How far a copy has got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyState {
+    /// Bytes copied so far
+    pub done: u64,
+    /// Size of the source; `done` once it is finished, should it have grown
+    pub total: u64,
+    /// The whole file is copied and its attributes applied
+    pub finished: bool,
+}
+
+/// Move up to `limit` bytes from `src` to `dst`, from their current
+/// positions, calling `on_chunk` with the running count
+///
+/// Returns the bytes moved, less than `limit` only at the end of `src`.
+pub(crate) fn stream(
+    src: &mut dyn FileOperations,
+    dst: &mut dyn FileOperations,
+    limit: u64,
+    mut on_chunk: impl FnMut(u64),
+) -> VfsResult<u64> {
+    let mut buf = vec![0u8; COPY_CHUNK];
+    let mut moved = 0u64;
+    while moved < limit {
+        let want = (limit - moved).min(COPY_CHUNK as u64) as usize;
+        let n = src.read(&mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        let mut done = 0;
+        while done < n {
+            match dst.write(&buf[done..n])? {
+                0 => return Err(VfsError::NoSpace),
+                written => done += written,
+            }
+        }
+        moved += n as u64;
+        on_chunk(moved);
+    }
+    Ok(moved)
+}
+
+/// Give `path` the mode and timestamps in `stat`, where `fs` keeps them
+pub(crate) fn copy_attributes(fs: &dyn Filesystem, path: &str, stat: &FileStat) {
+    let _ = fs.chmod(path, stat.mode);
+    let _ = fs.set_times(path, stat.atime, stat.mtime);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use crate::SeekFrom;
+
+    /// In-memory file taking at most `max_write` bytes per write
+    struct MemFile {
+        data: Vec<u8>,
+        pos: usize,
+        max_write: usize,
+    }
+
+    impl MemFile {
+        fn new(data: Vec<u8>, max_write: usize) -> Self {
+            MemFile { data, pos: 0, max_write }
+        }
+    }
+
+    impl FileOperations for MemFile {
+        fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+            let n = buffer.len().min(self.data.len() - self.pos);
+            buffer[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+
+        fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+            let n = buffer.len().min(self.max_write);
+            self.data.extend_from_slice(&buffer[..n]);
+            self.pos += n;
+            Ok(n)
+        }
+
+        fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+            Err(VfsError::NotSupported)
+        }
+
+        fn tell(&self) -> u64 {
+            self.pos as u64
+        }
+
+        fn sync(&mut self) -> VfsResult<()> {
+            Ok(())
+        }
+
+        fn stat(&self) -> VfsResult<FileStat> {
+            Ok(FileStat { size: self.data.len() as u64, ..FileStat::default() })
+        }
+
+        fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+            Err(VfsError::NotSupported)
+        }
+    }
+
+    #[test]
+    fn test_stream_in_chunks() {
+        let data: Vec<u8> = (0..COPY_CHUNK * 2 + 100).map(|i| i as u8).collect();
+        let mut src = MemFile::new(data.clone(), 0);
+        let mut dst = MemFile::new(Vec::new(), 1000);
+        let mut reports = Vec::new();
+
+        // A limit stops it part way; short writes are retried
+        assert_eq!(stream(&mut src, &mut dst, COPY_CHUNK as u64 + 10, |n| reports.push(n)).unwrap(), COPY_CHUNK as u64 + 10);
+        assert_eq!(stream(&mut src, &mut dst, u64::MAX, |n| reports.push(n)).unwrap(), COPY_CHUNK as u64 + 90);
+        assert_eq!(dst.data, data);
+        assert_eq!(reports, [COPY_CHUNK as u64, COPY_CHUNK as u64 + 10, COPY_CHUNK as u64, COPY_CHUNK as u64 + 90]);
+
+        // A destination that takes nothing is full
+        let mut src = MemFile::new(data, 0);
+        assert_eq!(stream(&mut src, &mut MemFile::new(Vec::new(), 0), u64::MAX, |_| {}), Err(VfsError::NoSpace));
+    }
+}
@@ -52,6 +52,8 @@ pub enum VfsError {
     NotAFile,
     /// Corrupted data
     Corrupted,
+    /// Operation would block (empty or full pipe)
+    WouldBlock,
     /// Filesystem-specific error
     FsError(i32),
 }
@@ -83,6 +85,7 @@ impl VfsError {
             VfsError::InvalidName => -22,       // EINVAL
             VfsError::NotAFile => -21,          // EISDIR (not a file)
             VfsError::Corrupted => -5,          // EIO (corruption)
+            VfsError::WouldBlock => -11,        // EAGAIN
             VfsError::FsError(e) => *e,
         }
     }
@@ -0,0 +1,107 @@
+//! Per-mount I/O statistics
+//!
+//! Every mount counts the reads and writes made through files opened on
+//! it, and the bytes they moved. Files opened through the VFS carry their
+//! mount's counters, so the counts survive the file being closed and the
+//! mount table being copied for an update; a new mount starts at zero.
+//! Only successful calls are counted.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{FileOperations, FileStat, SeekFrom, VfsResult};
+
+/// A snapshot of a mount's counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounts {
+    /// Read calls
+    pub reads: u64,
+    /// Bytes read
+    pub read_bytes: u64,
+    /// Write calls
+    pub writes: u64,
+    /// Bytes written
+    pub write_bytes: u64,
+}
+
+/// A mount's live counters
+#[derive(Debug, Default)]
+pub struct IoStats {
+    reads: AtomicU64,
+    read_bytes: AtomicU64,
+    writes: AtomicU64,
+    write_bytes: AtomicU64,
+}
+
+impl IoStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a read that returned `bytes`
+    pub fn record_read(&self, bytes: usize) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.read_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a write that took `bytes`
+    pub fn record_write(&self, bytes: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.write_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// The counters as they are now
+    pub fn counts(&self) -> IoCounts {
+        IoCounts {
+            reads: self.reads.load(Ordering::Relaxed),
+            read_bytes: self.read_bytes.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            write_bytes: self.write_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Wrap a file so its reads and writes count towards `stats`
+pub fn counted(file: Box<dyn FileOperations>, stats: Arc<IoStats>) -> Box<dyn FileOperations> {
+    Box::new(CountedFile { file, stats })
+}
+
+struct CountedFile {
+    file: Box<dyn FileOperations>,
+    stats: Arc<IoStats>,
+}
+
+impl FileOperations for CountedFile {
+    fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+        let n = self.file.read(buffer)?;
+        self.stats.record_read(n);
+        Ok(n)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+        let n = self.file.write(buffer)?;
+        self.stats.record_write(n);
+        Ok(n)
+    }
+
+    fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
+        self.file.seek(offset, whence)
+    }
+
+    fn tell(&self) -> u64 {
+        self.file.tell()
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        self.file.sync()
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        self.file.stat()
+    }
+
+    fn truncate(&mut self, size: u64) -> VfsResult<()> {
+        self.file.truncate(size)
+    }
+}
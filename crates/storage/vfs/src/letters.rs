@@ -0,0 +1,192 @@
+//! Drive letter assignment
+//!
+//! Volumes that report a serial number keep their drive letter from boot
+//! to boot. The letters are recorded in a text file, `DRIVE_MAP_PATH`, one
+//! volume per line:
+//!
+//! ```text
+//! # volume    letter
+//! 1A2B-3C4D   E
+//! ```
+//!
+//! A volume is mounted on its recorded letter; failing that (none recorded,
+//! or the letter is taken this boot) on the letter its driver asks for,
+//! and failing that on the first free letter from `FIRST_LETTER`. Letters
+//! recorded for other volumes are avoided, so a disk that is absent today
+//! still finds its letter free tomorrow. A volume's first letter is
+//! recorded; a conflict never overwrites a record, only an explicit
+//! reassignment does.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::VolumeInfo;
+
+/// Where the letters are kept
+pub const DRIVE_MAP_PATH: &str = "C:/etc/drives";
+
+/// First letter handed out automatically (A: and B: were floppies, C: is
+/// the boot volume)
+pub const FIRST_LETTER: char = 'D';
+
+/// The key a volume's letter is recorded under, if it has one
+pub fn volume_key(volume: &VolumeInfo) -> Option<String> {
+    let serial = volume.serial?;
+    Some(alloc::format!("{:04X}-{:04X}", serial >> 16, serial & 0xFFFF))
+}
+
+/// Recorded drive letters, by volume key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriveMap {
+    entries: Vec<(String, char)>,
+    /// Changed since it was loaded or saved
+    dirty: bool,
+}
+
+impl DriveMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a drive map file; lines that don't parse are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut map = DriveMap::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(key), Some(letter), None) = (words.next(), words.next(), words.next()) else {
+                continue;
+            };
+            let mut chars = letter.trim_end_matches(':').chars();
+            if let (Some(letter), None) = (chars.next(), chars.next()) {
+                if letter.is_ascii_alphabetic() {
+                    map.set(key, letter);
+                }
+            }
+        }
+        map.dirty = false;
+        map
+    }
+
+    /// The drive map file's contents
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# volume    letter\n");
+        for (key, letter) in &self.entries {
+            text.push_str(&alloc::format!("{:<11} {}\n", key, letter));
+        }
+        text
+    }
+
+    /// The letter recorded for `key`
+    pub fn letter(&self, key: &str) -> Option<char> {
+        self.entries.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|&(_, l)| l)
+    }
+
+    /// The volume `letter` is recorded for
+    pub fn owner(&self, letter: char) -> Option<&str> {
+        let letter = letter.to_ascii_uppercase();
+        self.entries.iter().find(|&&(_, l)| l == letter).map(|(k, _)| k.as_str())
+    }
+
+    /// Record `letter` for `key`, taking it from any other volume
+    pub fn set(&mut self, key: &str, letter: char) {
+        let letter = letter.to_ascii_uppercase();
+        if self.letter(key) == Some(letter) {
+            return;
+        }
+        self.entries.retain(|(k, l)| !k.eq_ignore_ascii_case(key) && *l != letter);
+        self.entries.push((String::from(key), letter));
+        self.dirty = true;
+    }
+
+    /// Forget `key`'s letter
+    pub fn remove(&mut self, key: &str) {
+        let before = self.entries.len();
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// Every recorded (key, letter)
+    pub fn entries(&self) -> &[(String, char)] {
+        &self.entries
+    }
+
+    /// Changed since it was loaded or last marked saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    /// The letter to mount a volume on, given the letters `in_use`
+    ///
+    /// `key` is the volume's (see `volume_key`), `preferred` the letter its
+    /// driver asks for. None when every letter is taken.
+    pub fn choose(&self, key: Option<&str>, preferred: char, in_use: impl Fn(char) -> bool) -> Option<char> {
+        let preferred = preferred.to_ascii_uppercase();
+        if let Some(letter) = key.and_then(|key| self.letter(key)) {
+            if !in_use(letter) {
+                return Some(letter);
+            }
+        }
+        let free = |letter: char| !in_use(letter) && self.owner(letter).is_none();
+        if preferred.is_ascii_uppercase() && free(preferred) {
+            return Some(preferred);
+        }
+        let letters = || (b'A'..=b'Z').map(|c| c as char);
+        letters()
+            .filter(|&c| c >= FIRST_LETTER)
+            .find(|&c| free(c))
+            .or_else(|| letters().filter(|&c| c >= FIRST_LETTER).find(|&c| !in_use(c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write_back() {
+        let map = DriveMap::parse("# comment\n1A2B-3C4D   E\n\nbad line here\nCAFE-F00D g:\nX 12\n");
+        assert_eq!(map.letter("1a2b-3c4d"), Some('E'));
+        assert_eq!(map.letter("CAFE-F00D"), Some('G'));
+        assert_eq!(map.entries().len(), 2);
+        assert!(!map.is_dirty());
+        assert_eq!(DriveMap::parse(&map.to_text()), map);
+    }
+
+    #[test]
+    fn test_choose() {
+        let mut map = DriveMap::new();
+        map.set("1111-1111", 'E');
+        map.set("2222-2222", 'F');
+        let taken = |letters: &'static str| move |c: char| letters.contains(c);
+
+        // The recorded letter wins over the preferred one
+        assert_eq!(map.choose(Some("1111-1111"), 'D', taken("C")), Some('E'));
+        // Taken: the preferred letter, avoiding letters recorded for others
+        assert_eq!(map.choose(Some("1111-1111"), 'D', taken("CE")), Some('D'));
+        assert_eq!(map.choose(None, 'F', taken("CD")), Some('G'));
+        assert_eq!(map.choose(None, 'H', taken("C")), Some('H'));
+        // Only recorded letters left
+        assert_eq!(map.choose(None, 'D', taken("ABCDGHIJKLMNOPQRSTUVWXYZ")), Some('E'));
+        assert_eq!(map.choose(None, 'D', taken("ABCDEFGHIJKLMNOPQRSTUVWXYZ")), None);
+    }
+
+    #[test]
+    fn test_set_takes_letter() {
+        let mut map = DriveMap::new();
+        map.set("1111-1111", 'E');
+        map.set("2222-2222", 'E');
+        assert_eq!(map.letter("1111-1111"), None);
+        assert_eq!(map.owner('e'), Some("2222-2222"));
+        map.set("2222-2222", 'F');
+        assert_eq!(map.entries().len(), 1);
+        assert_eq!(volume_key(&VolumeInfo { label: None, serial: Some(0x1A2B_3C4D) }).as_deref(), Some("1A2B-3C4D"));
+    }
+}
@@ -30,16 +30,25 @@ use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::vec;
+use alloc::sync::Arc;
 use spin::Mutex;
+use watos_sync::Rcu;
 
 pub mod path;
+pub mod canonical;
 pub mod file;
 pub mod mount;
 pub mod error;
 pub mod pipe;
+pub mod pty;
 pub mod symlink;
 pub mod metadata;
 pub mod permissions;
+pub mod watch;
+pub mod iostats;
+pub mod letters;
+pub mod copy;
+pub mod trash;
 
 // Re-export universal path utilities for new code
 // TODO: Migrate VFS path module to use watos-path completely
@@ -48,8 +57,14 @@ pub use watos_path as core_path;
 pub use error::{VfsError, VfsResult};
 pub use file::{FileHandle, FileMode, FileType, FileStat};
 pub use mount::{MountPoint, MountTable, DriveMount, MAX_DRIVES};
-pub use path::{Path, PathType, ParsedPath, parse as parse_path, is_drive_letter};
+pub use path::{Path, PathType, PathCmp, ParsedPath, parse as parse_path, is_drive_letter};
 pub use pipe::{create_pipe, create_pipe_with_capacity, NamedPipe, PIPE_BUF_SIZE};
+pub use pty::{create_pty, PTY_BUF_SIZE};
+pub use watch::{WatchEvent, WatchList, WATCH_QUEUE_LEN};
+pub use iostats::{IoCounts, IoStats};
+pub use copy::{CopyProgress, CopyState, COPY_CHUNK};
+pub use trash::{TrashEntry, TRASH_DIR};
+pub use letters::{volume_key, DriveMap, DRIVE_MAP_PATH};
 pub use symlink::{SymlinkFilesystem, SymlinkTarget, SymlinkResolver, ResolvedPath, ResolveOptions, MAX_SYMLINK_DEPTH};
 pub use metadata::{ExtendedMetadata, ExtendedMetadataFs, FileColor, FileIcon, icon_from_extension, color_from_file};
 pub use permissions::{
@@ -133,6 +148,70 @@ pub trait Filesystem: Send + Sync {
         Err(VfsError::NotSupported)
     }
 
+    /// How this filesystem compares names
+    ///
+    /// Recorded on the mount when the filesystem is mounted. Default
+    /// implementation is case-sensitive.
+    fn path_cmp(&self) -> PathCmp {
+        PathCmp::CaseSensitive
+    }
+
+    /// Read the target of a symbolic link
+    ///
+    /// Only called for paths whose `stat` reports `FileType::Symlink`.
+    /// Default implementation returns NotSupported.
+    fn readlink(&self, _path: &str) -> VfsResult<String> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Give the file at `old_path` a second name, `new_path` (a hard link)
+    ///
+    /// Both names then refer to the same file, which is only removed with
+    /// its last name. Directories can't be linked. Default implementation
+    /// returns NotSupported.
+    fn link(&self, _old_path: &str, _new_path: &str) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Take a named, read-only point-in-time snapshot of a file or
+    /// directory tree
+    ///
+    /// Default implementation returns NotSupported.
+    fn snapshot(&self, _path: &str, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Names of the snapshots on this filesystem, oldest first
+    ///
+    /// Default implementation returns NotSupported.
+    fn snapshots(&self) -> VfsResult<Vec<String>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Open a snapshot as a read-only filesystem rooted at what it was
+    /// taken of
+    ///
+    /// Default implementation returns NotSupported.
+    fn open_snapshot(&self, _name: &str) -> VfsResult<Box<dyn Filesystem>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Set a file's access and modification times (seconds since the
+    /// epoch)
+    ///
+    /// Default implementation returns NotSupported.
+    fn set_times(&self, _path: &str, _atime: u64, _mtime: u64) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Volume label and serial number, as DOS showed them
+    ///
+    /// Recorded as the drive label when mounted as a drive letter without
+    /// one. Default implementation has neither.
+    fn volume(&self) -> VolumeInfo {
+        VolumeInfo::default()
+    }
+
     // Compatibility methods for legacy code
 
     /// Check if a file exists
@@ -214,12 +293,29 @@ pub struct FsStats {
     pub max_name_len: u32,
 }
 
+/// Volume identification (see `Filesystem::volume`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VolumeInfo {
+    /// Volume label
+    pub label: Option<String>,
+    /// Serial number, set when the volume was formatted
+    pub serial: Option<u32>,
+}
+
 /// Global VFS instance
-static VFS: Mutex<Option<Vfs>> = Mutex::new(None);
+///
+/// Read-copy-update: file operations work on a snapshot and never wait;
+/// mounting and unmounting publish a changed copy (see `update`).
+static VFS: Rcu<Vfs> = Rcu::new();
 
 /// Virtual File System manager
+#[derive(Clone)]
 pub struct Vfs {
     mounts: MountTable,
+    /// Shared by every copy of the mount table
+    watches: Arc<WatchList>,
+    /// Recorded drive letters (see the `letters` module), shared likewise
+    letters: Arc<Mutex<DriveMap>>,
 }
 
 impl Vfs {
@@ -227,6 +323,8 @@ impl Vfs {
     pub fn new() -> Self {
         Vfs {
             mounts: MountTable::new(),
+            watches: Arc::new(WatchList::new()),
+            letters: Arc::new(Mutex::new(DriveMap::new())),
         }
     }
 
@@ -259,6 +357,50 @@ impl Vfs {
         self.mounts.unmount_drive(letter)
     }
 
+    /// Mount a volume on the letter the drive map gives it, recording a
+    /// newly seen volume's letter; returns the letter
+    ///
+    /// `preferred` is used when the map has none for it, if it is free.
+    pub fn mount_volume(&mut self, preferred: char, fs: Box<dyn Filesystem>) -> VfsResult<char> {
+        let key = volume_key(&fs.volume());
+        let mut letters = self.letters.lock();
+        let letter = letters
+            .choose(key.as_deref(), preferred, |c| self.mounts.get_drive(c).is_some())
+            .ok_or(VfsError::NoSpace)?;
+        self.mounts.mount_drive(letter, fs)?;
+        if let Some(key) = key {
+            if letters.letter(&key).is_none() {
+                letters.set(&key, letter);
+            }
+        }
+        Ok(letter)
+    }
+
+    /// Move a drive to another, free, letter and record it there
+    ///
+    /// The letter is taken from any other volume it was recorded for.
+    pub fn reassign_drive(&mut self, from: char, to: char) -> VfsResult<()> {
+        let key = self
+            .get_drive(from)
+            .ok_or(VfsError::NotMounted)
+            .map(|drive| volume_key(&drive.filesystem.volume()))?;
+        self.mounts.move_drive(from, to)?;
+        if let Some(key) = key {
+            self.letters.lock().set(&key, to);
+        }
+        Ok(())
+    }
+
+    /// The drive map, to save or list
+    pub fn drive_map(&self) -> DriveMap {
+        self.letters.lock().clone()
+    }
+
+    /// Replace the drive map, as loaded from `DRIVE_MAP_PATH`
+    pub fn set_drive_map(&self, map: DriveMap) {
+        *self.letters.lock() = map;
+    }
+
     /// Get drive mount info
     pub fn get_drive(&self, letter: char) -> Option<&DriveMount> {
         self.mounts.get_drive(letter)
@@ -276,16 +418,57 @@ impl Vfs {
 
     // ========== Resolution ==========
 
+    /// How names are compared on the mount holding `path`
+    pub fn path_cmp(&self, path: &str) -> VfsResult<PathCmp> {
+        self.mounts.path_cmp(path)
+    }
+
     /// Resolve path to filesystem and relative path
     /// Automatically handles both Unix paths and drive letter paths
     fn resolve(&self, path: &str) -> VfsResult<(&dyn Filesystem, String)> {
         self.mounts.resolve(path)
     }
 
+    /// Canonicalize a path (see the `canonical` module)
+    ///
+    /// Relative paths are joined to `cwd`, which must be absolute.
+    pub fn canonicalize(&self, path: &str, cwd: &str) -> VfsResult<String> {
+        canonical::canonicalize(&self.mounts, path, cwd)
+    }
+
+    /// Watch a directory for changes (see the `watch` module)
+    ///
+    /// `mask` is made of `watos_syscall::watch` bits. Returns the handle
+    /// events are read from.
+    pub fn watch(&self, path: &str, mask: u32) -> VfsResult<Box<dyn FileOperations>> {
+        self.stat(path)?;
+        let cmp = self.path_cmp(path)?;
+        Ok(self.watches.add(path, cmp, mask))
+    }
+
     /// Open a file
+    ///
+    /// Its reads and writes count towards its mount's `IoStats`.
     pub fn open(&self, path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
         let (fs, rel_path) = self.resolve(path)?;
-        fs.open(&rel_path, mode)
+        let io = self.mounts.io_stats(path)?;
+        if !mode.write || !self.watches.is_watched(path) {
+            return Ok(iostats::counted(fs.open(&rel_path, mode)?, io));
+        }
+
+        let created = mode.create && fs.stat(&rel_path).is_err();
+        let file = iostats::counted(fs.open(&rel_path, mode)?, io);
+        if created {
+            self.watches.notify(path, WatchEvent::Create);
+        }
+        Ok(self.watches.track_writes(path, file))
+    }
+
+    /// I/O counters of every mount: drives as `C:`, then path mounts
+    pub fn io_stats(&self) -> Vec<(String, IoCounts)> {
+        let drives = self.list_drives().map(|d| (alloc::format!("{}:", d.letter), d.io.counts()));
+        let mounts = self.list_mounts().iter().map(|m| (m.path.clone(), m.io.counts()));
+        drives.chain(mounts).collect()
     }
 
     /// Get file statistics
@@ -294,22 +477,47 @@ impl Vfs {
         fs.stat(&rel_path)
     }
 
+    /// Statistics of the filesystem holding `path`
+    pub fn statfs(&self, path: &str) -> VfsResult<FsStats> {
+        let (fs, _) = self.resolve(path)?;
+        fs.statfs()
+    }
+
+    /// Label and serial number of the volume holding `path`
+    ///
+    /// A drive's label is the one it was mounted with, which may differ
+    /// from what the filesystem reports.
+    pub fn volume(&self, path: &str) -> VfsResult<VolumeInfo> {
+        let (fs, _) = self.resolve(path)?;
+        let mut volume = fs.volume();
+        if let Some(drive) = is_drive_letter(path).and_then(|letter| self.get_drive(letter)) {
+            volume.label = drive.label.clone();
+        }
+        Ok(volume)
+    }
+
     /// Create a directory
     pub fn mkdir(&self, path: &str) -> VfsResult<()> {
         let (fs, rel_path) = self.resolve(path)?;
-        fs.mkdir(&rel_path)
+        fs.mkdir(&rel_path)?;
+        self.watches.notify(path, WatchEvent::Create);
+        Ok(())
     }
 
     /// Remove a file
     pub fn unlink(&self, path: &str) -> VfsResult<()> {
         let (fs, rel_path) = self.resolve(path)?;
-        fs.unlink(&rel_path)
+        fs.unlink(&rel_path)?;
+        self.watches.notify(path, WatchEvent::Delete);
+        Ok(())
     }
 
     /// Remove a directory
     pub fn rmdir(&self, path: &str) -> VfsResult<()> {
         let (fs, rel_path) = self.resolve(path)?;
-        fs.rmdir(&rel_path)
+        fs.rmdir(&rel_path)?;
+        self.watches.notify(path, WatchEvent::Delete);
+        Ok(())
     }
 
     /// Read directory entries
@@ -318,17 +526,150 @@ impl Vfs {
         fs.readdir(&rel_path)
     }
 
+    /// Read directory entries in display order
+    ///
+    /// Filesystems return entries in on-disk order; this sorts them with
+    /// `watos_unicode::collate`, so case, accents and embedded numbers
+    /// order the way a reader expects rather than by raw bytes.
+    pub fn readdir_sorted(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
+        let mut entries = self.readdir(path)?;
+        entries.sort_by(|a, b| watos_unicode::collate(&a.name, &b.name));
+        Ok(entries)
+    }
+
     /// Rename a file
+    ///
+    /// Within one filesystem this is the filesystem's own rename. Across
+    /// filesystems a regular file is copied and then the original removed,
+    /// which is not atomic: a failed copy is deleted and leaves the
+    /// original alone. Anything else (directories) fails with CrossDevice.
     pub fn rename(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
         let (old_fs, old_rel) = self.resolve(old_path)?;
         let (new_fs, new_rel) = self.resolve(new_path)?;
 
-        // Check same filesystem
+        if core::ptr::eq(old_fs, new_fs) {
+            old_fs.rename(&old_rel, &new_rel)?;
+        } else {
+            move_across(old_fs, &old_rel, new_fs, &new_rel)?;
+        }
+        self.watches.notify(old_path, WatchEvent::MovedFrom);
+        self.watches.notify(new_path, WatchEvent::MovedTo);
+        Ok(())
+    }
+
+    /// Create a hard link to a file
+    pub fn link(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
+        let (old_fs, old_rel) = self.resolve(old_path)?;
+        let (new_fs, new_rel) = self.resolve(new_path)?;
+
+        // Links can't span filesystems
         if !core::ptr::eq(old_fs, new_fs) {
             return Err(VfsError::CrossDevice);
         }
 
-        old_fs.rename(&old_rel, &new_rel)
+        old_fs.link(&old_rel, &new_rel)?;
+        self.watches.notify(new_path, WatchEvent::Create);
+        Ok(())
+    }
+
+    /// Copy a regular file to `dst`, replacing any file there
+    ///
+    /// `progress` is called with (bytes done, total) after each chunk. The
+    /// copy gets the original's mode and times where the destination keeps
+    /// them. Returns the bytes copied; a failed copy is removed.
+    pub fn copy(&self, src: &str, dst: &str, progress: &mut CopyProgress) -> VfsResult<u64> {
+        Ok(self.copy_part(src, dst, 0, u64::MAX, progress)?.done)
+    }
+
+    /// Copy up to `limit` bytes of `src` to `dst`, starting at `offset`
+    ///
+    /// The first part (`offset` 0) creates or truncates `dst`; later parts
+    /// continue it. Once the end of `src` is reached the copy is finished
+    /// and given the original's attributes, as with `copy`.
+    pub fn copy_part(
+        &self,
+        src: &str,
+        dst: &str,
+        offset: u64,
+        limit: u64,
+        progress: &mut CopyProgress,
+    ) -> VfsResult<CopyState> {
+        let (src_fs, src_rel) = self.resolve(src)?;
+        let (dst_fs, dst_rel) = self.resolve(dst)?;
+        let stat = src_fs.stat(&src_rel)?;
+        match stat.file_type {
+            FileType::Regular => {}
+            FileType::Directory => return Err(VfsError::IsADirectory),
+            _ => return Err(VfsError::NotAFile),
+        }
+        if let Ok(existing) = dst_fs.stat(&dst_rel) {
+            if existing.file_type == FileType::Directory {
+                return Err(VfsError::IsADirectory);
+            }
+            // Truncating the destination would empty the source
+            if core::ptr::eq(src_fs, dst_fs) && existing.inode == stat.inode {
+                return Err(VfsError::InvalidArgument);
+            }
+        }
+
+        let mode = if offset == 0 { FileMode::WRITE } else { FileMode { create: false, truncate: false, ..FileMode::WRITE } };
+        let copied = (|| -> VfsResult<u64> {
+            let mut from = self.open(src, FileMode::READ)?;
+            let mut to = self.open(dst, mode)?;
+            from.seek(offset as i64, SeekFrom::Start)?;
+            to.seek(offset as i64, SeekFrom::Start)?;
+            let moved = copy::stream(from.as_mut(), to.as_mut(), limit, |n| {
+                progress(offset + n, stat.size.max(offset + n))
+            })?;
+            if moved < limit {
+                to.sync()?;
+            }
+            Ok(moved)
+        })();
+        let moved = match copied {
+            Ok(moved) => moved,
+            Err(e) => {
+                let _ = self.unlink(dst);
+                return Err(e);
+            }
+        };
+
+        // Short of the limit: the end of the source, so the copy is done
+        let done = offset + moved;
+        let finished = moved < limit;
+        if finished {
+            copy::copy_attributes(dst_fs, &dst_rel, &stat);
+        }
+        Ok(CopyState {
+            done,
+            total: if finished { done } else { stat.size.max(done) },
+            finished,
+        })
+    }
+
+    /// Move `path` into its drive's trash instead of deleting it, as
+    /// deleted at `now` (seconds since the epoch); returns its id there
+    ///
+    /// NotSupported where there is no trash (see the `trash` module); the
+    /// caller then deletes it for good.
+    pub fn trash(&self, path: &str, now: u64) -> VfsResult<String> {
+        trash::trash(self, path, now)
+    }
+
+    /// What is in `drive`'s trash, oldest first
+    pub fn trash_list(&self, drive: char) -> VfsResult<Vec<TrashEntry>> {
+        trash::list(self, drive)
+    }
+
+    /// Put `id` from `drive`'s trash back where it was; returns that path
+    pub fn trash_restore(&self, drive: char, id: &str) -> VfsResult<String> {
+        trash::restore(self, drive, id)
+    }
+
+    /// Delete `id`, or everything, from `drive`'s trash for good; returns
+    /// how many went
+    pub fn trash_purge(&self, drive: char, id: Option<&str>) -> VfsResult<usize> {
+        trash::purge(self, drive, id)
     }
 
     /// Change file mode (permissions)
@@ -342,6 +683,34 @@ impl Vfs {
         let (fs, rel_path) = self.resolve(path)?;
         fs.chown(&rel_path, uid, gid)
     }
+
+    /// Snapshot a file or directory tree (see `Filesystem::snapshot`)
+    pub fn snapshot(&self, path: &str, name: &str) -> VfsResult<()> {
+        let (fs, rel_path) = self.resolve(path)?;
+        fs.snapshot(&rel_path, name)
+    }
+
+    /// Snapshots on the filesystem holding `path`
+    pub fn snapshots(&self, path: &str) -> VfsResult<Vec<String>> {
+        let (fs, _) = self.resolve(path)?;
+        fs.snapshots()
+    }
+
+    /// Mount snapshot `name` of the filesystem holding `path` as a drive,
+    /// labeled with the snapshot's name
+    pub fn mount_snapshot(&mut self, path: &str, name: &str, letter: char) -> VfsResult<()> {
+        let (fs, _) = self.resolve(path)?;
+        let snapshot = fs.open_snapshot(name)?;
+        self.mount_drive_labeled(letter, snapshot, name)
+    }
+
+    /// Sync every mounted filesystem. All are tried; the first error is
+    /// returned.
+    pub fn sync_all(&self) -> VfsResult<()> {
+        let drives = self.list_drives().map(|d| &d.filesystem);
+        let mounts = self.list_mounts().iter().map(|m| &m.filesystem);
+        drives.chain(mounts).fold(Ok(()), |result, fs| result.and(fs.sync()))
+    }
 }
 
 impl Default for Vfs {
@@ -350,94 +719,272 @@ impl Default for Vfs {
     }
 }
 
+/// Move a regular file to another filesystem: copy it, then remove it
+fn move_across(from: &dyn Filesystem, from_path: &str, to: &dyn Filesystem, to_path: &str) -> VfsResult<()> {
+    let stat = from.stat(from_path)?;
+    if stat.file_type != FileType::Regular {
+        return Err(VfsError::CrossDevice);
+    }
+    if let Ok(existing) = to.stat(to_path) {
+        if existing.file_type == FileType::Directory {
+            return Err(VfsError::IsADirectory);
+        }
+    }
+
+    if let Err(e) = copy_across(from, from_path, to, to_path) {
+        let _ = to.unlink(to_path);
+        return Err(e);
+    }
+    // Not every filesystem keeps permissions or times
+    copy::copy_attributes(to, to_path, &stat);
+
+    // Only one copy may survive
+    if let Err(e) = from.unlink(from_path) {
+        let _ = to.unlink(to_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn copy_across(from: &dyn Filesystem, from_path: &str, to: &dyn Filesystem, to_path: &str) -> VfsResult<()> {
+    let mut src = from.open(from_path, FileMode::READ)?;
+    let mut dst = to.open(to_path, FileMode::WRITE)?;
+    copy::stream(src.as_mut(), dst.as_mut(), u64::MAX, |_| {})?;
+    dst.sync()
+}
+
 /// Initialize the global VFS
 pub fn init() {
-    let mut vfs = VFS.lock();
-    *vfs = Some(Vfs::new());
+    VFS.publish(Vfs::new());
 }
 
-/// Get a reference to the global VFS
-pub fn vfs() -> spin::MutexGuard<'static, Option<Vfs>> {
-    VFS.lock()
+/// Snapshot of the global VFS. Mounts made after it is taken are not in
+/// it; ones removed stay usable through it until it is dropped.
+pub fn vfs() -> Option<Arc<Vfs>> {
+    VFS.read()
 }
 
-/// Mount a filesystem at a path
-pub fn mount(path: &str, fs: Box<dyn Filesystem>) -> VfsResult<()> {
-    let mut vfs = VFS.lock();
-    match vfs.as_mut() {
-        Some(v) => v.mount(path, fs),
+/// Run `f` on the current VFS without locking it
+fn with_vfs<R>(f: impl FnOnce(&Vfs) -> VfsResult<R>) -> VfsResult<R> {
+    match VFS.read() {
+        Some(v) => f(&v),
         None => Err(VfsError::NotInitialized),
     }
 }
 
+/// Change the mount table: `f` gets a copy of the VFS, which replaces
+/// the current one if `f` succeeds
+pub fn update<R>(f: impl FnOnce(&mut Vfs) -> VfsResult<R>) -> VfsResult<R> {
+    VFS.update(f).unwrap_or(Err(VfsError::NotInitialized))
+}
+
+/// Mount a filesystem at a path
+pub fn mount(path: &str, fs: Box<dyn Filesystem>) -> VfsResult<()> {
+    update(|v| v.mount(path, fs))
+}
+
 /// Mount a filesystem as a drive letter
 pub fn mount_drive(letter: char, fs: Box<dyn Filesystem>) -> VfsResult<()> {
-    let mut vfs = VFS.lock();
-    match vfs.as_mut() {
-        Some(v) => v.mount_drive(letter, fs),
-        None => Err(VfsError::NotInitialized),
-    }
+    update(|v| v.mount_drive(letter, fs))
 }
 
 /// Mount a filesystem as a drive letter with a label
 pub fn mount_drive_labeled(letter: char, fs: Box<dyn Filesystem>, label: &str) -> VfsResult<()> {
-    let mut vfs = VFS.lock();
-    match vfs.as_mut() {
-        Some(v) => v.mount_drive_labeled(letter, fs, label),
-        None => Err(VfsError::NotInitialized),
-    }
+    update(|v| v.mount_drive_labeled(letter, fs, label))
 }
 
 /// Unmount a drive letter
 pub fn unmount_drive(letter: char) -> VfsResult<()> {
-    let mut vfs = VFS.lock();
-    match vfs.as_mut() {
-        Some(v) => v.unmount_drive(letter),
-        None => Err(VfsError::NotInitialized),
-    }
+    update(|v| v.unmount_drive(letter))
+}
+
+/// Mount a volume on the letter the drive map gives it (see
+/// `Vfs::mount_volume`), saving the map if that recorded a new volume
+pub fn mount_volume(preferred: char, fs: Box<dyn Filesystem>) -> VfsResult<char> {
+    let letter = update(|v| v.mount_volume(preferred, fs))?;
+    let _ = save_drive_map();
+    Ok(letter)
+}
+
+/// Move a drive to another letter (see `Vfs::reassign_drive`) and save
+/// the drive map
+pub fn reassign_drive(from: char, to: char) -> VfsResult<()> {
+    update(|v| v.reassign_drive(from, to))?;
+    let _ = save_drive_map();
+    Ok(())
+}
+
+/// Load the drive map from `DRIVE_MAP_PATH`; returns the volumes in it
+pub fn load_drive_map() -> VfsResult<usize> {
+    let text = with_vfs(|v| {
+        let (fs, rel_path) = v.resolve(DRIVE_MAP_PATH)?;
+        fs.read_file(&rel_path)
+    })?;
+    let map = DriveMap::parse(&String::from_utf8_lossy(&text));
+    let count = map.entries().len();
+    with_vfs(|v| {
+        v.set_drive_map(map);
+        Ok(count)
+    })
+}
+
+/// Write the drive map to `DRIVE_MAP_PATH` if it changed
+pub fn save_drive_map() -> VfsResult<()> {
+    let vfs = vfs().ok_or(VfsError::NotInitialized)?;
+    let map = vfs.drive_map();
+    if !map.is_dirty() {
+        return Ok(());
+    }
+    if let Some(dir) = path::parent(DRIVE_MAP_PATH) {
+        let _ = vfs.mkdir(&dir);
+    }
+    let mut file = vfs.open(DRIVE_MAP_PATH, FileMode::WRITE)?;
+    file.write(map.to_text().as_bytes())?;
+    file.sync()?;
+    // Unless it changed again meanwhile
+    let mut letters = vfs.letters.lock();
+    if *letters == map {
+        letters.mark_saved();
+    }
+    Ok(())
+}
+
+/// The drive map
+pub fn drive_map() -> DriveMap {
+    vfs().map(|v| v.drive_map()).unwrap_or_default()
+}
+
+/// How names are compared on the mount holding a path
+pub fn path_cmp(path: &str) -> VfsResult<PathCmp> {
+    with_vfs(|v| v.path_cmp(path))
+}
+
+/// Canonicalize a path relative to a working directory
+pub fn canonicalize(path: &str, cwd: &str) -> VfsResult<String> {
+    with_vfs(|v| v.canonicalize(path, cwd))
+}
+
+/// Watch a directory for changes
+pub fn watch(path: &str, mask: u32) -> VfsResult<Box<dyn FileOperations>> {
+    with_vfs(|v| v.watch(path, mask))
 }
 
 /// Open a file
 pub fn open(path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
-    let vfs = VFS.lock();
-    match vfs.as_ref() {
-        Some(v) => v.open(path, mode),
-        None => Err(VfsError::NotInitialized),
-    }
+    with_vfs(|v| v.open(path, mode))
+}
+
+/// I/O counters of every mount
+pub fn io_stats() -> Vec<(String, IoCounts)> {
+    vfs().map(|v| v.io_stats()).unwrap_or_default()
 }
 
 /// Get file statistics
 pub fn stat(path: &str) -> VfsResult<FileStat> {
-    let vfs = VFS.lock();
-    match vfs.as_ref() {
-        Some(v) => v.stat(path),
-        None => Err(VfsError::NotInitialized),
-    }
+    with_vfs(|v| v.stat(path))
 }
 
 /// Read directory entries
 pub fn readdir(path: &str) -> VfsResult<Vec<DirEntry>> {
-    let vfs = VFS.lock();
-    match vfs.as_ref() {
-        Some(v) => v.readdir(path),
-        None => Err(VfsError::NotInitialized),
-    }
+    with_vfs(|v| v.readdir(path))
+}
+
+/// Read directory entries in display order
+pub fn readdir_sorted(path: &str) -> VfsResult<Vec<DirEntry>> {
+    with_vfs(|v| v.readdir_sorted(path))
+}
+
+/// Statistics of the filesystem holding a path
+pub fn statfs(path: &str) -> VfsResult<FsStats> {
+    with_vfs(|v| v.statfs(path))
+}
+
+/// Label and serial number of the volume holding a path
+pub fn volume(path: &str) -> VfsResult<VolumeInfo> {
+    with_vfs(|v| v.volume(path))
+}
+
+/// Create a directory
+pub fn mkdir(path: &str) -> VfsResult<()> {
+    with_vfs(|v| v.mkdir(path))
+}
+
+/// Remove a file
+pub fn unlink(path: &str) -> VfsResult<()> {
+    with_vfs(|v| v.unlink(path))
+}
+
+/// Remove an empty directory
+pub fn rmdir(path: &str) -> VfsResult<()> {
+    with_vfs(|v| v.rmdir(path))
+}
+
+/// Copy a regular file, reporting progress (see `Vfs::copy`)
+pub fn copy(src: &str, dst: &str, progress: &mut CopyProgress) -> VfsResult<u64> {
+    with_vfs(|v| v.copy(src, dst, progress))
+}
+
+/// Copy part of a regular file (see `Vfs::copy_part`)
+pub fn copy_part(src: &str, dst: &str, offset: u64, limit: u64, progress: &mut CopyProgress) -> VfsResult<CopyState> {
+    with_vfs(|v| v.copy_part(src, dst, offset, limit, progress))
+}
+
+/// Move a file or directory to the trash (see `Vfs::trash`)
+pub fn trash(path: &str, now: u64) -> VfsResult<String> {
+    with_vfs(|v| v.trash(path, now))
+}
+
+/// A drive's trash (see `Vfs::trash_list`)
+pub fn trash_list(drive: char) -> VfsResult<Vec<TrashEntry>> {
+    with_vfs(|v| v.trash_list(drive))
+}
+
+/// Restore from the trash (see `Vfs::trash_restore`)
+pub fn trash_restore(drive: char, id: &str) -> VfsResult<String> {
+    with_vfs(|v| v.trash_restore(drive, id))
+}
+
+/// Empty the trash, or take one thing from it (see `Vfs::trash_purge`)
+pub fn trash_purge(drive: char, id: Option<&str>) -> VfsResult<usize> {
+    with_vfs(|v| v.trash_purge(drive, id))
+}
+
+/// Rename or move a file (see `Vfs::rename`)
+pub fn rename(old_path: &str, new_path: &str) -> VfsResult<()> {
+    with_vfs(|v| v.rename(old_path, new_path))
+}
+
+/// Create a hard link to a file within one filesystem
+pub fn link(old_path: &str, new_path: &str) -> VfsResult<()> {
+    with_vfs(|v| v.link(old_path, new_path))
 }
 
 /// Change file mode (permissions)
 pub fn chmod(path: &str, mode: u32) -> VfsResult<()> {
-    let vfs = VFS.lock();
-    match vfs.as_ref() {
-        Some(v) => v.chmod(path, mode),
-        None => Err(VfsError::NotInitialized),
-    }
+    with_vfs(|v| v.chmod(path, mode))
 }
 
 /// Change file owner and group
 pub fn chown(path: &str, uid: u32, gid: u32) -> VfsResult<()> {
-    let vfs = VFS.lock();
-    match vfs.as_ref() {
-        Some(v) => v.chown(path, uid, gid),
-        None => Err(VfsError::NotInitialized),
-    }
+    with_vfs(|v| v.chown(path, uid, gid))
+}
+
+/// Snapshot a file or directory tree
+pub fn snapshot(path: &str, name: &str) -> VfsResult<()> {
+    with_vfs(|v| v.snapshot(path, name))
+}
+
+/// List the snapshots on the filesystem holding a path
+pub fn snapshots(path: &str) -> VfsResult<Vec<String>> {
+    with_vfs(|v| v.snapshots(path))
+}
+
+/// Mount a snapshot read-only as a drive letter
+pub fn mount_snapshot(path: &str, name: &str, letter: char) -> VfsResult<()> {
+    update(|v| v.mount_snapshot(path, name, letter))
+}
+
+/// Sync every mounted filesystem
+pub fn sync_all() -> VfsResult<()> {
+    with_vfs(|v| v.sync_all())
 }
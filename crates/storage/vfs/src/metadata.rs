@@ -189,6 +189,34 @@ impl FileIcon {
         }
     }
 
+    /// Get a short ASCII tag (for consoles without icon glyphs)
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileIcon::None => "   ",
+            FileIcon::File => "FIL",
+            FileIcon::Folder => "DIR",
+            FileIcon::Link => "LNK",
+            FileIcon::Executable => "EXE",
+            FileIcon::Text => "TXT",
+            FileIcon::Code => "SRC",
+            FileIcon::Image => "IMG",
+            FileIcon::Audio => "AUD",
+            FileIcon::Video => "VID",
+            FileIcon::Archive => "ARC",
+            FileIcon::Pdf => "PDF",
+            FileIcon::Config => "CFG",
+            FileIcon::Database => "DB ",
+            FileIcon::Binary => "BIN",
+            FileIcon::Lock => "LCK",
+            FileIcon::Git => "GIT",
+            FileIcon::Doc => "DOC",
+            FileIcon::Key => "KEY",
+            FileIcon::Device => "DEV",
+            FileIcon::Pipe => "FIF",
+            FileIcon::Socket => "SCK",
+        }
+    }
+
     /// Get Nerd Font icon (for compatible terminals)
     pub fn nerd_font(&self) -> &'static str {
         match self {
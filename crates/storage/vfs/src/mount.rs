@@ -6,20 +6,37 @@
 //!
 //! Drive mounts are "jailed" - paths using drive letters cannot escape
 //! the mount root via `..`.
+//!
+//! Each mount records whether names on it are compared case-sensitively,
+//! taken from `Filesystem::path_cmp` when it is mounted.
+//!
+//! Filesystems are shared (`Arc`), so copying the table for an update is
+//! cheap, and a filesystem unmounted while an operation still uses it
+//! stays alive until that operation ends.
+//!
+//! Each mount also keeps I/O counters (see the `iostats` module), shared
+//! the same way.
 
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use crate::iostats::IoStats;
 use crate::{Filesystem, VfsError, VfsResult, MAX_MOUNTS};
-use crate::path::{normalize, parse, PathType};
+use crate::path::{normalize, parse, PathCmp, PathType};
 
 /// A mount point in the VFS
+#[derive(Clone)]
 pub struct MountPoint {
     /// Mount path (normalized, for path mounts)
     pub path: String,
     /// Mounted filesystem
-    pub filesystem: Box<dyn Filesystem>,
+    pub filesystem: Arc<dyn Filesystem>,
+    /// How names below the mount point are compared
+    pub path_cmp: PathCmp,
+    /// Reads and writes made through files opened on the mount
+    pub io: Arc<IoStats>,
 }
 
 impl MountPoint {
@@ -27,28 +44,37 @@ impl MountPoint {
     pub fn new(path: &str, filesystem: Box<dyn Filesystem>) -> Self {
         MountPoint {
             path: normalize(path),
-            filesystem,
+            path_cmp: filesystem.path_cmp(),
+            filesystem: Arc::from(filesystem),
+            io: Arc::new(IoStats::new()),
         }
     }
 }
 
 /// A drive letter mount (jailed)
+#[derive(Clone)]
 pub struct DriveMount {
     /// Drive letter (uppercase A-Z)
     pub letter: char,
     /// Mounted filesystem
-    pub filesystem: Box<dyn Filesystem>,
+    pub filesystem: Arc<dyn Filesystem>,
     /// Optional label for the drive
     pub label: Option<String>,
+    /// How names on the drive are compared
+    pub path_cmp: PathCmp,
+    /// Reads and writes made through files opened on the drive
+    pub io: Arc<IoStats>,
 }
 
 impl DriveMount {
-    /// Create a new drive mount
+    /// Create a new drive mount, labelled with the volume's own label
     pub fn new(letter: char, filesystem: Box<dyn Filesystem>) -> Self {
         DriveMount {
             letter: letter.to_ascii_uppercase(),
-            filesystem,
-            label: None,
+            path_cmp: filesystem.path_cmp(),
+            label: filesystem.volume().label,
+            filesystem: Arc::from(filesystem),
+            io: Arc::new(IoStats::new()),
         }
     }
 
@@ -56,8 +82,10 @@ impl DriveMount {
     pub fn with_label(letter: char, filesystem: Box<dyn Filesystem>, label: &str) -> Self {
         DriveMount {
             letter: letter.to_ascii_uppercase(),
-            filesystem,
+            path_cmp: filesystem.path_cmp(),
+            filesystem: Arc::from(filesystem),
             label: Some(String::from(label)),
+            io: Arc::new(IoStats::new()),
         }
     }
 }
@@ -66,6 +94,7 @@ impl DriveMount {
 pub const MAX_DRIVES: usize = 26;
 
 /// Mount table managing all mounted filesystems
+#[derive(Clone)]
 pub struct MountTable {
     /// Path-based mounts (Unix style)
     mounts: Vec<MountPoint>,
@@ -170,6 +199,29 @@ impl MountTable {
         Ok(())
     }
 
+    /// Move a drive to another letter, which must be free
+    pub fn move_drive(&mut self, from: char, to: char) -> VfsResult<()> {
+        let from_idx = drive_index(from).ok_or(VfsError::InvalidArgument)?;
+        let to_idx = drive_index(to).ok_or(VfsError::InvalidArgument)?;
+
+        if self.drives[from_idx].is_none() {
+            return Err(VfsError::NotMounted);
+        }
+        if from_idx == to_idx {
+            return Ok(());
+        }
+        if self.drives[to_idx].is_some() {
+            return Err(VfsError::AlreadyMounted);
+        }
+
+        let mut drive = self.drives[from_idx].take();
+        if let Some(ref mut drive) = drive {
+            drive.letter = to.to_ascii_uppercase();
+        }
+        self.drives[to_idx] = drive;
+        Ok(())
+    }
+
     /// Get a drive mount by letter
     pub fn get_drive(&self, letter: char) -> Option<&DriveMount> {
         let idx = drive_index(letter)?;
@@ -195,6 +247,38 @@ impl MountTable {
         }
     }
 
+    /// How names are compared on the mount holding `path`
+    pub fn path_cmp(&self, path: &str) -> VfsResult<PathCmp> {
+        let parsed = parse(path);
+
+        match parsed.path_type {
+            PathType::Drive(letter) => self
+                .get_drive(letter)
+                .map(|drive| drive.path_cmp)
+                .ok_or(VfsError::NotMounted),
+            PathType::Unix => self
+                .find_mount(&parsed.path)
+                .map(|(mount, _)| mount.path_cmp)
+                .ok_or(VfsError::NotMounted),
+        }
+    }
+
+    /// The I/O counters of the mount holding `path`
+    pub fn io_stats(&self, path: &str) -> VfsResult<Arc<IoStats>> {
+        let parsed = parse(path);
+
+        match parsed.path_type {
+            PathType::Drive(letter) => self
+                .get_drive(letter)
+                .map(|drive| drive.io.clone())
+                .ok_or(VfsError::NotMounted),
+            PathType::Unix => self
+                .find_mount(&parsed.path)
+                .map(|(mount, _)| mount.io.clone())
+                .ok_or(VfsError::NotMounted),
+        }
+    }
+
     /// Resolve a drive letter path
     fn resolve_drive(&self, letter: char, rel_path: &str) -> VfsResult<(&dyn Filesystem, String)> {
         let idx = drive_index(letter).ok_or(VfsError::InvalidArgument)?;
@@ -210,13 +294,20 @@ impl MountTable {
 
     /// Resolve a Unix-style path
     fn resolve_path(&self, path: &str) -> VfsResult<(&dyn Filesystem, String)> {
+        self.find_mount(path)
+            .map(|(mount, rel_path)| (mount.filesystem.as_ref(), rel_path))
+            .ok_or(VfsError::NotMounted)
+    }
+
+    /// Find the mount holding a Unix-style path and the path within it
+    fn find_mount(&self, path: &str) -> Option<(&MountPoint, String)> {
         let normalized = normalize(path);
 
         // Find the longest matching mount point
         for mount in &self.mounts {
             if normalized == mount.path {
                 // Exact match - root of mount
-                return Ok((mount.filesystem.as_ref(), String::from("/")));
+                return Some((mount, String::from("/")));
             } else if normalized.starts_with(&mount.path) {
                 // Check for proper prefix (must be followed by / or be root)
                 let after = &normalized[mount.path.len()..];
@@ -226,12 +317,12 @@ impl MountTable {
                     } else {
                         String::from(after)
                     };
-                    return Ok((mount.filesystem.as_ref(), rel_path));
+                    return Some((mount, rel_path));
                 }
             }
         }
 
-        Err(VfsError::NotMounted)
+        None
     }
 
     // ========== Query Operations ==========
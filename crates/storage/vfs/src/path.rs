@@ -11,6 +11,7 @@ pub use watos_path::{
     MAX_FILENAME,
     PathType,
     ParsedPath,
+    PathCmp,
     is_drive_letter,
     parse,
     normalize,
@@ -21,6 +22,7 @@ pub use watos_path::{
     extension,
     join,
     is_valid_name,
+    eq_ignore_case,
     paths_equal,
     filenames_equal,
     components,
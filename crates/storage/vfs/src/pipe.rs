@@ -2,6 +2,12 @@
 //!
 //! Supports both anonymous pipes (for `cmd1 | cmd2`) and named pipes (FIFOs).
 //!
+//! The ends never sleep themselves: a read of an empty pipe whose writer is
+//! still open, or a write to a full one, fails with `VfsError::WouldBlock`
+//! and the kernel parks the caller until the other end moves. A read after
+//! the last writer has gone returns `Ok(0)` (EOF); a write after the last
+//! reader has gone fails with `VfsError::IoError` (broken pipe).
+//!
 //! # Anonymous Pipes
 //!
 //! ```ignore
@@ -124,10 +130,7 @@ impl FileOperations for PipeReadEnd {
                 // EOF - write end is closed and no more data
                 return Ok(0);
             }
-            // Would block - in a real OS we'd sleep here
-            // For now, return 0 to indicate no data available
-            // The caller should retry
-            return Ok(0);
+            return Err(VfsError::WouldBlock);
         }
 
         // Read available data
@@ -196,9 +199,7 @@ impl FileOperations for PipeWriteEnd {
         }
 
         if pipe.is_full() {
-            // Would block - in a real OS we'd sleep here
-            // Return 0 to indicate buffer full, caller should retry
-            return Ok(0);
+            return Err(VfsError::WouldBlock);
         }
 
         // Write as much as possible
@@ -329,7 +330,7 @@ impl FileOperations for NamedPipeReadEnd {
             if pipe.write_closed {
                 return Ok(0); // EOF
             }
-            return Ok(0); // Would block
+            return Err(VfsError::WouldBlock);
         }
 
         let to_read = buf.len().min(pipe.available_data());
@@ -406,7 +407,7 @@ impl FileOperations for NamedPipeWriteEnd {
         }
 
         if pipe.is_full() {
-            return Ok(0); // Would block
+            return Err(VfsError::WouldBlock);
         }
 
         let to_write = buf.len().min(pipe.available_space());
@@ -457,3 +458,32 @@ impl Drop for NamedPipeWriteEnd {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_pipe_blocks_until_writer_closes() {
+        let (mut read_end, mut write_end) = create_pipe_with_capacity(4);
+        let mut buf = [0u8; 8];
+        assert_eq!(read_end.read(&mut buf), Err(VfsError::WouldBlock));
+
+        assert_eq!(write_end.write(b"hi"), Ok(2));
+        assert_eq!(read_end.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"hi");
+
+        drop(write_end);
+        assert_eq!(read_end.read(&mut buf), Ok(0));
+    }
+
+    #[test]
+    fn test_full_pipe_blocks_until_reader_closes() {
+        let (read_end, mut write_end) = create_pipe_with_capacity(4);
+        assert_eq!(write_end.write(b"abcdef"), Ok(4));
+        assert_eq!(write_end.write(b"g"), Err(VfsError::WouldBlock));
+
+        drop(read_end);
+        assert_eq!(write_end.write(b"g"), Err(VfsError::IoError));
+    }
+}
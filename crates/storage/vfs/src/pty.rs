@@ -0,0 +1,136 @@
+//! Pseudo-terminal pairs
+//!
+//! A pty is two byte queues joined back to back: whatever the master end
+//! writes shows up as input on the slave end, and whatever a program writes
+//! to the slave shows up on the master. A terminal emulator holds the master
+//! and feeds it keystrokes; the program it hosts uses the slave as its
+//! stdin/stdout/stderr.
+//!
+//! ```ignore
+//! let (master, slave) = create_pty();
+//! // master.write(b"ls\r");       -> slave.read() returns "ls\r"
+//! // slave.write(b"file.txt\r\n"); -> master.read() returns "file.txt\r\n"
+//! ```
+//!
+//! Like pipes, reads on an empty queue return 0 instead of blocking.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use crate::{FileOperations, FileStat, FileType, SeekFrom, VfsError, VfsResult};
+
+/// Per-direction buffer size
+pub const PTY_BUF_SIZE: usize = 4096;
+
+/// Device ID both ends report in `stat`, which tells a pty from the console
+pub const PTY_DEVICE: u64 = 136;
+
+/// State shared by both ends of a pty
+struct PtyState {
+    /// Master -> slave (keyboard input for the hosted program)
+    input: VecDeque<u8>,
+    /// Slave -> master (program output for the terminal to render)
+    output: VecDeque<u8>,
+    master_open: bool,
+    slave_open: bool,
+}
+
+type SharedPty = Arc<Mutex<PtyState>>;
+
+/// Create a pty pair
+///
+/// Returns (master, slave) file operations
+pub fn create_pty() -> (Box<dyn FileOperations>, Box<dyn FileOperations>) {
+    let state = Arc::new(Mutex::new(PtyState {
+        input: VecDeque::with_capacity(PTY_BUF_SIZE),
+        output: VecDeque::with_capacity(PTY_BUF_SIZE),
+        master_open: true,
+        slave_open: true,
+    }));
+
+    let master = Box::new(PtyEnd {
+        state: state.clone(),
+        is_master: true,
+    });
+    let slave = Box::new(PtyEnd {
+        state,
+        is_master: false,
+    });
+
+    (master, slave)
+}
+
+/// One end of a pty pair
+pub struct PtyEnd {
+    state: SharedPty,
+    is_master: bool,
+}
+
+impl FileOperations for PtyEnd {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut pty = self.state.lock();
+        let queue = if self.is_master { &mut pty.output } else { &mut pty.input };
+
+        // An empty queue reads as 0 bytes, whether or not the peer is still open
+        let count = buf.len().min(queue.len());
+        for slot in buf.iter_mut().take(count) {
+            *slot = queue.pop_front().unwrap();
+        }
+        Ok(count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        let mut pty = self.state.lock();
+        let peer_open = if self.is_master { pty.slave_open } else { pty.master_open };
+        if !peer_open {
+            // Hangup - nobody on the other side
+            return Err(VfsError::IoError);
+        }
+
+        let queue = if self.is_master { &mut pty.input } else { &mut pty.output };
+        let count = buf.len().min(PTY_BUF_SIZE.saturating_sub(queue.len()));
+        queue.extend(&buf[..count]);
+        Ok(count)
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+        Err(VfsError::InvalidArgument) // Terminals are not seekable
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        let pty = self.state.lock();
+        let pending = if self.is_master { pty.output.len() } else { pty.input.len() };
+        Ok(FileStat {
+            file_type: FileType::CharDevice,
+            size: pending as u64,
+            dev: PTY_DEVICE,
+            mode: 0o620,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::InvalidArgument)
+    }
+}
+
+impl Drop for PtyEnd {
+    fn drop(&mut self) {
+        let mut pty = self.state.lock();
+        if self.is_master {
+            pty.master_open = false;
+        } else {
+            pty.slave_open = false;
+        }
+    }
+}
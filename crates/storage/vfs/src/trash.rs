@@ -0,0 +1,259 @@
+//! Recycle bin
+//!
+//! Files deleted interactively (`rm` without `-f`, the file manager) are
+//! moved into their drive's trash instead of being unlinked, so a slip can
+//! be undone. Each drive keeps its own, so trashing is a rename and never a
+//! copy:
+//!
+//! ```text
+//! D:/.trash/files/report.txt      the file (or directory tree) itself
+//! D:/.trash/info/report.txt       where it came from and when
+//! ```
+//!
+//! The info file is a few `Key=value` lines:
+//!
+//! ```text
+//! [Trash Info]
+//! Path=D:/work/report.txt
+//! Deleted=1792312200
+//! ```
+//!
+//! A second `report.txt` is stored as `report.txt.2`, and so on. Paths that
+//! aren't on a drive, and things already in a trash, have no trash: the
+//! caller deletes them for good. Trashing can be turned off as a whole
+//! (`fs.trash`), in which case every delete is final.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::path::{self, is_drive_letter};
+use crate::{FileMode, FileType, Vfs, VfsError, VfsResult};
+
+/// Directory at the root of each drive holding its trash
+pub const TRASH_DIR: &str = ".trash";
+
+/// First line of an info file
+const INFO_HEADER: &str = "[Trash Info]";
+
+/// Trashing is on until turned off (`fs.trash`)
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Is deleting to the trash on?
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turn deleting to the trash on or off
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+/// A trashed file or directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    /// Name in the trash, unique per drive
+    pub id: String,
+    /// Where it was deleted from
+    pub path: String,
+    /// When, in seconds since the epoch
+    pub deleted: u64,
+}
+
+impl TrashEntry {
+    /// Read an info file; None if it isn't one
+    pub fn parse(id: &str, text: &str) -> Option<Self> {
+        let mut lines = text.lines().map(str::trim);
+        if lines.next()? != INFO_HEADER {
+            return None;
+        }
+        let (mut path, mut deleted) = (None, 0);
+        for line in lines {
+            match line.split_once('=') {
+                Some(("Path", value)) => path = Some(String::from(value)),
+                Some(("Deleted", value)) => deleted = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        Some(TrashEntry { id: String::from(id), path: path?, deleted })
+    }
+
+    /// The info file's contents
+    pub fn to_text(&self) -> String {
+        format!("{}\nPath={}\nDeleted={}\n", INFO_HEADER, self.path, self.deleted)
+    }
+}
+
+/// The trash directory of `drive`
+pub fn trash_dir(drive: char) -> String {
+    format!("{}:/{}", drive.to_ascii_uppercase(), TRASH_DIR)
+}
+
+fn files_dir(drive: char) -> String {
+    format!("{}/files", trash_dir(drive))
+}
+
+fn info_dir(drive: char) -> String {
+    format!("{}/info", trash_dir(drive))
+}
+
+/// The drive whose trash `path` (canonical) would go to, None if it has
+/// none: not on a drive, the drive's root, or in the trash already
+fn trash_drive(path: &str) -> Option<char> {
+    let drive = is_drive_letter(path)?;
+    let mut components = path[2..].split(['/', '\\']).filter(|c| !c.is_empty());
+    match components.next() {
+        None => None,
+        Some(first) if first.eq_ignore_ascii_case(TRASH_DIR) => None,
+        Some(_) => Some(drive.to_ascii_uppercase()),
+    }
+}
+
+/// `name`, or `name.2`, `name.3`... whichever `taken` says is free
+fn unique_id(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut id = String::from(name);
+    let mut n = 2;
+    while taken(&id) {
+        id = format!("{}.{}", name, n);
+        n += 1;
+    }
+    id
+}
+
+/// Move `path` (canonical) into its drive's trash, recording it as deleted
+/// at `now`; returns its id
+///
+/// NotSupported if there's no trash for it (see the module docs) or
+/// trashing is off.
+pub(crate) fn trash(vfs: &Vfs, path: &str, now: u64) -> VfsResult<String> {
+    let drive = trash_drive(path).filter(|_| enabled()).ok_or(VfsError::NotSupported)?;
+    vfs.stat(path)?;
+    let name = path::filename(path).ok_or(VfsError::InvalidArgument)?;
+
+    for dir in [trash_dir(drive), files_dir(drive), info_dir(drive)] {
+        match vfs.mkdir(&dir) {
+            Ok(()) | Err(VfsError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let (files, info) = (files_dir(drive), info_dir(drive));
+    let id = unique_id(name, |id| {
+        vfs.stat(&path::join(&files, id)).is_ok() || vfs.stat(&path::join(&info, id)).is_ok()
+    });
+
+    // The record first: a file in the trash without one couldn't go back
+    let entry = TrashEntry { id: id.clone(), path: String::from(path), deleted: now };
+    let info_path = path::join(&info, &id);
+    write_info(vfs, &info_path, &entry)?;
+    if let Err(e) = vfs.rename(path, &path::join(&files, &id)) {
+        let _ = vfs.unlink(&info_path);
+        return Err(e);
+    }
+    Ok(id)
+}
+
+fn write_info(vfs: &Vfs, path: &str, entry: &TrashEntry) -> VfsResult<()> {
+    let text = entry.to_text();
+    let mut file = vfs.open(path, FileMode::WRITE)?;
+    if file.write(text.as_bytes())? != text.len() {
+        return Err(VfsError::NoSpace);
+    }
+    file.sync()
+}
+
+/// Everything in `drive`'s trash, oldest first
+pub(crate) fn list(vfs: &Vfs, drive: char) -> VfsResult<Vec<TrashEntry>> {
+    let info = info_dir(drive);
+    let names = match vfs.readdir(&info) {
+        Ok(entries) => entries,
+        Err(VfsError::NotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries: Vec<TrashEntry> = names
+        .iter()
+        .filter(|e| e.file_type == FileType::Regular)
+        .filter_map(|e| {
+            let (fs, rel_path) = vfs.resolve(&path::join(&info, &e.name)).ok()?;
+            let text = fs.read_file(&rel_path).ok()?;
+            TrashEntry::parse(&e.name, &String::from_utf8_lossy(&text))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.deleted.cmp(&b.deleted).then_with(|| a.id.cmp(&b.id)));
+    Ok(entries)
+}
+
+/// Put trashed `id` back where it was deleted from; returns that path
+///
+/// AlreadyExists if something has taken its place since.
+pub(crate) fn restore(vfs: &Vfs, drive: char, id: &str) -> VfsResult<String> {
+    let entry = list(vfs, drive)?.into_iter().find(|e| e.id == id).ok_or(VfsError::NotFound)?;
+    if vfs.stat(&entry.path).is_ok() {
+        return Err(VfsError::AlreadyExists);
+    }
+    vfs.rename(&path::join(&files_dir(drive), id), &entry.path)?;
+    let _ = vfs.unlink(&path::join(&info_dir(drive), id));
+    Ok(entry.path)
+}
+
+/// Delete trashed `id` for good, or everything in the trash when `id` is
+/// None; returns how many went
+pub(crate) fn purge(vfs: &Vfs, drive: char, id: Option<&str>) -> VfsResult<usize> {
+    let entries = list(vfs, drive)?;
+    let mut purged = 0;
+    for entry in entries.iter().filter(|e| id.is_none_or(|id| e.id == id)) {
+        let file = path::join(&files_dir(drive), &entry.id);
+        match remove_tree(vfs, &file) {
+            Ok(()) | Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        vfs.unlink(&path::join(&info_dir(drive), &entry.id))?;
+        purged += 1;
+    }
+    if purged == 0 && id.is_some() {
+        return Err(VfsError::NotFound);
+    }
+    Ok(purged)
+}
+
+/// Remove `path` and, if it's a directory, everything in it
+fn remove_tree(vfs: &Vfs, path: &str) -> VfsResult<()> {
+    if vfs.stat(path)?.file_type != FileType::Directory {
+        return vfs.unlink(path);
+    }
+    for entry in vfs.readdir(path)? {
+        if entry.name != "." && entry.name != ".." {
+            remove_tree(vfs, &path::join(path, &entry.name))?;
+        }
+    }
+    vfs.rmdir(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_round_trip() {
+        let entry = TrashEntry { id: String::from("a.txt.2"), path: String::from("D:/work/a.txt"), deleted: 1792312200 };
+        assert_eq!(TrashEntry::parse("a.txt.2", &entry.to_text()), Some(entry));
+        assert_eq!(TrashEntry::parse("x", "Path=D:/x\n"), None);
+        assert_eq!(TrashEntry::parse("x", "[Trash Info]\nDeleted=5\n"), None);
+    }
+
+    #[test]
+    fn test_which_paths_have_a_trash() {
+        assert_eq!(trash_drive("D:/work/a.txt"), Some('D'));
+        assert_eq!(trash_drive("c:\\a"), Some('C'));
+        assert_eq!(trash_drive("D:/"), None);
+        assert_eq!(trash_drive("D:/.trash/files/a.txt"), None);
+        assert_eq!(trash_drive("D:/.TRASH"), None);
+        assert_eq!(trash_drive("/proc/1"), None);
+    }
+
+    #[test]
+    fn test_unique_id() {
+        assert_eq!(unique_id("a.txt", |_| false), "a.txt");
+        assert_eq!(unique_id("a.txt", |id| id == "a.txt" || id == "a.txt.2"), "a.txt.3");
+    }
+}
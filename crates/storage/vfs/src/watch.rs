@@ -0,0 +1,405 @@
+//! Directory watches (inotify-lite)
+//!
+//! A watch names a directory and a mask of `watos_syscall::watch` events.
+//! It returns a handle that reads as a stream of event lines, one for each
+//! change made through the VFS to an entry of that directory:
+//!
+//! ```text
+//! C notes.txt     created
+//! D old.log       deleted
+//! F draft         renamed away
+//! T final         renamed here
+//! W notes.txt     closed after being opened for writing
+//! O               events were dropped, rescan the directory
+//! ```
+//!
+//! Watching a file instead reports the file's own events. Reading an empty
+//! watch returns 0, like an empty pipe; dropping the handle removes the
+//! watch. Changes that bypass the VFS are not seen.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use watos_syscall::watch::{CLOSE_WRITE, CREATE, DELETE, MOVED_FROM, MOVED_TO};
+
+use crate::path::{filename, parent, parse, ParsedPath, PathCmp};
+use crate::{FileOperations, FileStat, FileType, SeekFrom, VfsError, VfsResult};
+
+/// Events queued per watch before further ones are dropped
+pub const WATCH_QUEUE_LEN: usize = 64;
+
+/// Kind of change reported by a watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// Entry created
+    Create,
+    /// Entry deleted
+    Delete,
+    /// Entry renamed away
+    MovedFrom,
+    /// Entry renamed into the directory
+    MovedTo,
+    /// File closed after being opened for writing
+    CloseWrite,
+}
+
+impl WatchEvent {
+    /// The `watos_syscall::watch` mask bit selecting this event
+    pub fn mask(self) -> u32 {
+        match self {
+            WatchEvent::Create => CREATE,
+            WatchEvent::Delete => DELETE,
+            WatchEvent::MovedFrom => MOVED_FROM,
+            WatchEvent::MovedTo => MOVED_TO,
+            WatchEvent::CloseWrite => CLOSE_WRITE,
+        }
+    }
+
+    /// Code at the start of the event's line
+    fn code(self) -> u8 {
+        match self {
+            WatchEvent::Create => b'C',
+            WatchEvent::Delete => b'D',
+            WatchEvent::MovedFrom => b'F',
+            WatchEvent::MovedTo => b'T',
+            WatchEvent::CloseWrite => b'W',
+        }
+    }
+}
+
+/// Events waiting to be read from one watch
+struct WatchQueue {
+    events: VecDeque<(WatchEvent, String)>,
+    /// Set when an event was dropped; reported after the queued ones
+    overflowed: bool,
+    /// Rest of a line a short read did not take
+    partial: VecDeque<u8>,
+}
+
+impl WatchQueue {
+    fn push(&mut self, event: WatchEvent, name: &str) {
+        if self.overflowed || self.events.len() >= WATCH_QUEUE_LEN {
+            self.overflowed = true;
+        } else {
+            self.events.push_back((event, String::from(name)));
+        }
+    }
+
+    /// Move the next event line into `partial`; false if there is none
+    fn next_line(&mut self) -> bool {
+        if let Some((event, name)) = self.events.pop_front() {
+            self.partial.push_back(event.code());
+            self.partial.push_back(b' ');
+            self.partial.extend(name.bytes());
+            self.partial.push_back(b'\n');
+            true
+        } else if self.overflowed {
+            self.overflowed = false;
+            self.partial.extend(b"O\n");
+            true
+        } else {
+            false
+        }
+    }
+}
+
+type SharedQueue = Arc<Mutex<WatchQueue>>;
+
+struct Watch {
+    path: ParsedPath,
+    cmp: PathCmp,
+    mask: u32,
+    queue: Weak<Mutex<WatchQueue>>,
+}
+
+impl Watch {
+    /// Name to report for an event on `path`, if the watch covers it
+    fn name_for<'a>(&self, path: &'a ParsedPath) -> Option<&'a str> {
+        if path.path_type != self.path.path_type {
+            return None;
+        }
+        let name = filename(&path.path).unwrap_or("");
+        if self.cmp.eq(&path.path, &self.path.path) {
+            return Some(name);
+        }
+        let dir = parent(&path.path)?;
+        self.cmp.eq(&dir, &self.path.path).then_some(name)
+    }
+}
+
+/// All watches registered with a VFS
+pub struct WatchList {
+    watches: Mutex<Vec<Watch>>,
+}
+
+impl WatchList {
+    /// Create an empty watch list
+    pub const fn new() -> Self {
+        WatchList {
+            watches: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Watch `path` for the events in `mask`, comparing names with `cmp`
+    ///
+    /// The caller checks that the path exists.
+    pub fn add(&self, path: &str, cmp: PathCmp, mask: u32) -> Box<dyn FileOperations> {
+        let queue = Arc::new(Mutex::new(WatchQueue {
+            events: VecDeque::new(),
+            overflowed: false,
+            partial: VecDeque::new(),
+        }));
+
+        let mut watches = self.watches.lock();
+        // Good time to forget watches whose handles were closed
+        watches.retain(|w| w.queue.strong_count() > 0);
+        watches.push(Watch {
+            path: parse(path),
+            cmp,
+            mask,
+            queue: Arc::downgrade(&queue),
+        });
+
+        Box::new(WatchHandle { queue })
+    }
+
+    /// Check if any watch would see events on `path`
+    pub fn is_watched(&self, path: &str) -> bool {
+        let watches = self.watches.lock();
+        if watches.is_empty() {
+            return false;
+        }
+        let parsed = parse(path);
+        watches.iter().any(|w| w.queue.strong_count() > 0 && w.name_for(&parsed).is_some())
+    }
+
+    /// Queues interested in `event` on `path`, with the name to report
+    fn interested(&self, path: &str, event: WatchEvent) -> Vec<(SharedQueue, String)> {
+        let watches = self.watches.lock();
+        if watches.is_empty() {
+            return Vec::new();
+        }
+        let parsed = parse(path);
+        watches
+            .iter()
+            .filter(|w| w.mask & event.mask() != 0)
+            .filter_map(|w| {
+                let name = w.name_for(&parsed)?;
+                Some((w.queue.upgrade()?, String::from(name)))
+            })
+            .collect()
+    }
+
+    /// Report `event` on `path` to every watch covering it
+    pub fn notify(&self, path: &str, event: WatchEvent) {
+        for (queue, name) in self.interested(path, event) {
+            queue.lock().push(event, &name);
+        }
+    }
+
+    /// Wrap a file opened for writing so closing it reports `CloseWrite`
+    pub fn track_writes(&self, path: &str, file: Box<dyn FileOperations>) -> Box<dyn FileOperations> {
+        let watchers: Vec<_> = self
+            .interested(path, WatchEvent::CloseWrite)
+            .into_iter()
+            .map(|(queue, name)| (Arc::downgrade(&queue), name))
+            .collect();
+
+        if watchers.is_empty() {
+            file
+        } else {
+            Box::new(WatchedFile { file, watchers })
+        }
+    }
+}
+
+impl Default for WatchList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Readable end of a watch
+struct WatchHandle {
+    queue: SharedQueue,
+}
+
+impl FileOperations for WatchHandle {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut queue = self.queue.lock();
+        let mut done = 0;
+
+        while done < buf.len() {
+            if queue.partial.is_empty() && !queue.next_line() {
+                break;
+            }
+            let take = (buf.len() - done).min(queue.partial.len());
+            for (slot, byte) in buf[done..done + take].iter_mut().zip(queue.partial.drain(..take)) {
+                *slot = byte;
+            }
+            done += take;
+        }
+
+        Ok(done)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: SeekFrom) -> VfsResult<u64> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        let queue = self.queue.lock();
+        Ok(FileStat {
+            file_type: FileType::Unknown,
+            // Pending events, not bytes
+            size: queue.events.len() as u64,
+            mode: 0o444,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::InvalidArgument)
+    }
+}
+
+/// File opened for writing in a watched directory
+struct WatchedFile {
+    file: Box<dyn FileOperations>,
+    watchers: Vec<(Weak<Mutex<WatchQueue>>, String)>,
+}
+
+impl FileOperations for WatchedFile {
+    fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+        self.file.read(buffer)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+        self.file.write(buffer)
+    }
+
+    fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
+        self.file.seek(offset, whence)
+    }
+
+    fn tell(&self) -> u64 {
+        self.file.tell()
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        self.file.sync()
+    }
+
+    fn stat(&self) -> VfsResult<FileStat> {
+        self.file.stat()
+    }
+
+    fn truncate(&mut self, size: u64) -> VfsResult<()> {
+        self.file.truncate(size)
+    }
+}
+
+impl Drop for WatchedFile {
+    fn drop(&mut self) {
+        for (queue, name) in &self.watchers {
+            if let Some(queue) = queue.upgrade() {
+                queue.lock().push(WatchEvent::CloseWrite, name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use watos_syscall::watch::ALL;
+
+    fn read_all(handle: &mut Box<dyn FileOperations>) -> String {
+        let mut out = Vec::new();
+        let mut buf = [0u8; 5];
+        loop {
+            let n = handle.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_events_for_directory_entries() {
+        let list = WatchList::new();
+        let mut handle = list.add("C:/docs", PathCmp::CaseSensitive, ALL);
+
+        list.notify("C:/docs/a.txt", WatchEvent::Create);
+        list.notify("C:/docs/sub/b.txt", WatchEvent::Create); // not a direct entry
+        list.notify("D:/docs/c.txt", WatchEvent::Create); // other drive
+        list.notify("C:/docs/old", WatchEvent::MovedFrom);
+        list.notify("C:/docs/new", WatchEvent::MovedTo);
+        list.notify("C:/docs", WatchEvent::Delete);
+
+        assert_eq!(read_all(&mut handle), "C a.txt\nF old\nT new\nD docs\n");
+        assert_eq!(read_all(&mut handle), "");
+    }
+
+    #[test]
+    fn test_mask_and_case() {
+        let list = WatchList::new();
+        let mut handle = list.add("F:/DOCS", PathCmp::CaseInsensitive, DELETE);
+
+        list.notify("F:/docs/x", WatchEvent::Create);
+        list.notify("f:/Docs/x", WatchEvent::Delete);
+        assert_eq!(read_all(&mut handle), "D x\n");
+    }
+
+    #[test]
+    fn test_close_write_and_overflow() {
+        struct Null;
+        impl FileOperations for Null {
+            fn read(&mut self, _: &mut [u8]) -> VfsResult<usize> { Ok(0) }
+            fn write(&mut self, buf: &[u8]) -> VfsResult<usize> { Ok(buf.len()) }
+            fn seek(&mut self, _: i64, _: SeekFrom) -> VfsResult<u64> { Ok(0) }
+            fn tell(&self) -> u64 { 0 }
+            fn sync(&mut self) -> VfsResult<()> { Ok(()) }
+            fn stat(&self) -> VfsResult<FileStat> { Ok(FileStat::default()) }
+            fn truncate(&mut self, _: u64) -> VfsResult<()> { Ok(()) }
+        }
+
+        let list = WatchList::new();
+        let mut handle = list.add("/tmp", PathCmp::CaseSensitive, ALL);
+
+        let mut file = list.track_writes("/tmp/log", Box::new(Null));
+        file.write(b"hello").unwrap();
+        assert_eq!(read_all(&mut handle), "");
+        drop(file);
+        assert_eq!(read_all(&mut handle), "W log\n");
+
+        for _ in 0..WATCH_QUEUE_LEN + 5 {
+            list.notify("/tmp/f", WatchEvent::Create);
+        }
+        let text = read_all(&mut handle);
+        assert_eq!(text.lines().count(), WATCH_QUEUE_LEN + 1);
+        assert!(text.ends_with("C f\nO\n"));
+
+        // Closed watches stop being tracked
+        drop(handle);
+        assert!(!list.is_watched("/tmp/f"));
+    }
+}
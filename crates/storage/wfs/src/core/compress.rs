@@ -0,0 +1,202 @@
+//! Data compression
+//!
+//! Compressed extents hold up to one chunk of file data in the LZ4 block
+//! format. The stored data starts with a header giving the compressed and
+//! original sizes, so it can be checked and decoded on its own; the rest of
+//! the last block is padding.
+//!
+//! The compressor is the plain greedy LZ4 one: a hash table of 4-byte
+//! sequences, no lazy matching. It is fast and small, which matters more
+//! here than ratio.
+
+#[allow(unused_imports)]
+use crate::prelude::*;
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Header before the compressed data: compressed size, original size (u32 LE)
+pub const HEADER_SIZE: usize = 8;
+
+/// Shortest match LZ4 encodes
+const MIN_MATCH: usize = 4;
+
+/// The last bytes of a block are always literals
+const LAST_LITERALS: usize = 5;
+
+/// A match must start at least this far from the end
+const MF_LIMIT: usize = 12;
+
+/// Longest back reference
+const MAX_OFFSET: usize = 65535;
+
+/// log2 of the match finder's hash table size
+const HASH_BITS: u32 = 12;
+
+// ============================================================================
+// STORED FORMAT
+// ============================================================================
+
+/// Compress data and put the header in front
+pub fn pack(data: &[u8]) -> Vec<u8> {
+    let block = compress(data);
+    let mut out = Vec::with_capacity(HEADER_SIZE + block.len());
+    out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&block);
+    out
+}
+
+/// Decode stored data that should hold `len` bytes
+///
+/// Trailing padding is ignored. Returns None if the header disagrees with
+/// `len` or the data is malformed.
+pub fn unpack(stored: &[u8], len: usize) -> Option<Vec<u8>> {
+    let header = stored.get(..HEADER_SIZE)?;
+    let packed = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let original = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if original != len {
+        return None;
+    }
+    decompress(stored.get(HEADER_SIZE..HEADER_SIZE + packed)?, len)
+}
+
+// ============================================================================
+// LZ4 BLOCK FORMAT
+// ============================================================================
+
+/// Compress into a single LZ4 block
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = [u32::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+
+    // Shorter inputs are all literals
+    if input.len() > MF_LIMIT {
+        let limit = input.len() - MF_LIMIT;
+        while i < limit {
+            let seq = read_u32(input, i);
+            let slot = hash(seq);
+            let candidate = table[slot] as usize;
+            table[slot] = i as u32;
+
+            if table_hit(candidate, i) && read_u32(input, candidate) == seq {
+                let max = input.len() - LAST_LITERALS - i;
+                let mut len = MIN_MATCH;
+                while len < max && input[candidate + len] == input[i + len] {
+                    len += 1;
+                }
+
+                emit_sequence(&mut out, &input[anchor..i], i - candidate, len);
+                i += len;
+                anchor = i;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // The final sequence is literals only
+    emit_literals(&mut out, &input[anchor..], 0);
+    out
+}
+
+/// Decompress an LZ4 block that should hold exactly `len` bytes
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+
+    loop {
+        let token = *input.get(i)?;
+        i += 1;
+
+        let literals = read_length(input, &mut i, (token >> 4) as usize)?;
+        out.extend_from_slice(input.get(i..i.checked_add(literals)?)?);
+        i += literals;
+        if out.len() > len {
+            return None;
+        }
+
+        // Only the last sequence ends without a match
+        if i == input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*input.get(i)?, *input.get(i + 1)?]) as usize;
+        i += 2;
+        if offset == 0 || offset > out.len() {
+            return None;
+        }
+
+        let matched = read_length(input, &mut i, (token & 0x0F) as usize)? + MIN_MATCH;
+        if out.len() + matched > len {
+            return None;
+        }
+
+        // Byte by byte: a match may overlap the bytes it produces
+        let from = out.len() - offset;
+        for k in 0..matched {
+            let byte = out[from + k];
+            out.push(byte);
+        }
+    }
+
+    (out.len() == len).then_some(out)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// A hash table entry is usable: set, and close enough to reference
+fn table_hit(candidate: usize, at: usize) -> bool {
+    candidate != u32::MAX as usize && at - candidate <= MAX_OFFSET
+}
+
+/// Finish a length whose token nibble was 15 with the bytes after it
+fn read_length(input: &[u8], i: &mut usize, nibble: usize) -> Option<usize> {
+    let mut len = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *input.get(*i)?;
+            *i += 1;
+            len = len.checked_add(byte as usize)?;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+/// Write the bytes after a token for a length that didn't fit its nibble
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len >= 15 {
+        let mut rest = len - 15;
+        while rest >= 255 {
+            out.push(255);
+            rest -= 255;
+        }
+        out.push(rest as u8);
+    }
+}
+
+/// Token and literals; `match_nibble` is the low half of the token
+fn emit_literals(out: &mut Vec<u8>, literals: &[u8], match_nibble: u8) {
+    out.push((literals.len().min(15) as u8) << 4 | match_nibble);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+}
+
+fn emit_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, len: usize) {
+    let extra = len - MIN_MATCH;
+    emit_literals(out, literals, extra.min(15) as u8);
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+    write_length(out, extra);
+}
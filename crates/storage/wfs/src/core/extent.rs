@@ -25,7 +25,7 @@ pub const EXTENTS_PER_NODE: usize = super::node::NODE_DATA_SIZE / EXTENT_SIZE;
 /// Extent data is shared (CoW - don't modify in place)
 pub const EXTENT_SHARED: u32 = 0x0001;
 
-/// Extent data is compressed (future)
+/// Extent data is compressed (see `compress`)
 pub const EXTENT_COMPRESSED: u32 = 0x0002;
 
 /// Extent data is encrypted (future)
@@ -37,6 +37,13 @@ pub const EXTENT_HOLE: u32 = 0x0008;
 /// Extent is preallocated but unwritten
 pub const EXTENT_UNWRITTEN: u32 = 0x0010;
 
+/// `crc32` covers the extent's stored blocks
+pub const EXTENT_CRC: u32 = 0x0020;
+
+/// Compressed extents keep their disk block count in the top half of flags,
+/// as `length` is the uncompressed size
+const EXTENT_STORED_SHIFT: u32 = 16;
+
 // ============================================================================
 // EXTENT STRUCTURE
 // ============================================================================
@@ -102,8 +109,34 @@ impl Extent {
         self.refcount > 1 || (self.flags & EXTENT_SHARED != 0)
     }
 
+    /// Check if the data is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.flags & EXTENT_COMPRESSED != 0
+    }
+
+    /// Mark as compressed: the blocks allocated for it hold `length` bytes
+    /// of file data, compressed
+    pub fn set_compressed(&mut self, length: u64) {
+        self.flags |= EXTENT_COMPRESSED | (self.block_count() as u32) << EXTENT_STORED_SHIFT;
+        self.length = length;
+    }
+
+    /// Check if the data has a checksum
+    pub fn has_crc(&self) -> bool {
+        self.flags & EXTENT_CRC != 0
+    }
+
+    /// Record the checksum of the stored blocks
+    pub fn set_crc(&mut self, crc: u32) {
+        self.flags |= EXTENT_CRC;
+        self.crc32 = crc;
+    }
+
     /// Number of disk blocks this extent covers
     pub fn block_count(&self) -> u64 {
+        if self.is_compressed() {
+            return (self.flags >> EXTENT_STORED_SHIFT) as u64;
+        }
         (self.length + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64
     }
 
@@ -132,8 +165,8 @@ impl Extent {
         if !self.contains_offset(file_off) {
             return None;
         }
-        if self.is_hole() {
-            return None; // Holes don't have disk blocks
+        if self.is_hole() || self.is_compressed() {
+            return None; // Holes don't have disk blocks; compressed data is read whole
         }
         let offset_in_extent = file_off - self.file_offset;
         let block_in_extent = offset_in_extent / BLOCK_SIZE as u64;
@@ -148,6 +181,9 @@ impl Extent {
         if split_offset <= self.file_offset || split_offset >= self.file_end() {
             return None; // Split point outside extent
         }
+        if self.is_compressed() {
+            return None; // Must be decompressed first
+        }
 
         let left_length = split_offset - self.file_offset;
         let right_length = self.length - left_length;
@@ -157,7 +193,7 @@ impl Extent {
             file_offset: self.file_offset,
             disk_block: self.disk_block,
             length: left_length,
-            flags: self.flags,
+            flags: self.flags & !EXTENT_CRC,
             refcount: self.refcount,
             crc32: 0, // CRC needs recalc
             generation: self.generation,
@@ -167,7 +203,7 @@ impl Extent {
             file_offset: split_offset,
             disk_block: if self.is_hole() { 0 } else { self.disk_block + left_blocks },
             length: right_length,
-            flags: self.flags,
+            flags: self.flags & !EXTENT_CRC,
             refcount: self.refcount,
             crc32: 0, // CRC needs recalc
             generation: self.generation,
@@ -199,8 +235,8 @@ impl Extent {
         if self.is_hole() != other.is_hole() {
             return false;
         }
-        // Can't merge shared extents
-        if self.is_shared() || other.is_shared() {
+        // Can't merge shared or compressed extents
+        if self.is_shared() || other.is_shared() || self.is_compressed() || other.is_compressed() {
             return false;
         }
         // Check if adjacent in file
@@ -223,7 +259,7 @@ impl Extent {
             file_offset: self.file_offset,
             disk_block: self.disk_block,
             length: self.length + other.length,
-            flags: self.flags,
+            flags: self.flags & !EXTENT_CRC,
             refcount: 1, // Merged extent is exclusive
             crc32: 0,
             generation: self.generation.max(other.generation),
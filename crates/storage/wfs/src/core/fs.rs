@@ -6,13 +6,14 @@
 
 #[allow(unused_imports)]
 use crate::prelude::*;
-use super::structures::{Superblock, ROOT_INODE, WFS_MAGIC};
+use super::structures::{Superblock, BLOCK_SIZE, DEFAULT_CHUNK_SIZE, ROOT_INODE, WFS_MAGIC};
 use super::node::{TreeNode, NodeType};
-use super::inode::{Inode, INODE_SIZE};
-use super::dir::DirEntry;
+use super::inode::{Inode, INODE_SIZE, INODE_COMPRESS, INODE_INLINE, INODE_INLINE_SIZE};
+use super::dir::{dotdot_entry, DirEntry, EntryType};
 use super::extent::{Extent, EXTENT_SIZE};
 use super::tree::{BPlusTree, BlockDevice, BlockAllocator, TreeOps, TreeError, TreeValue};
 use super::transaction::{Transaction, TransactionError};
+use super::compress;
 
 // ============================================================================
 // FILESYSTEM STATE
@@ -31,6 +32,12 @@ pub struct FilesystemState {
     pub transaction: Option<Transaction>,
     /// Next transaction ID
     pub next_txn_id: u64,
+    /// Compress all file data written, not only files flagged
+    /// INODE_COMPRESS (a mount option; not stored on disk)
+    pub compress: bool,
+    /// Check file data against extent checksums when reading (a mount
+    /// option; on by default)
+    pub verify_data: bool,
 }
 
 impl FilesystemState {
@@ -53,6 +60,8 @@ impl FilesystemState {
             free_tree,
             transaction: None,
             next_txn_id: 1,
+            compress: false,
+            verify_data: true,
         }
     }
 
@@ -61,6 +70,23 @@ impl FilesystemState {
         self.transaction.as_ref().map_or(false, |t| t.is_active())
     }
 
+    /// Generation that blocks written now belong to
+    pub fn write_generation(&self) -> u64 {
+        match self.transaction {
+            Some(ref txn) => txn.generation,
+            None => self.superblock.root_generation,
+        }
+    }
+
+    /// Whether a snapshot may still point at an extent's blocks
+    ///
+    /// Anything written up to the newest snapshot's generation is kept when
+    /// the live file lets go of it.
+    pub fn snapshot_holds(&self, extent: &Extent) -> bool {
+        self.superblock.snapshot_tree_block != 0
+            && extent.generation as u64 <= self.superblock.snapshot_generation
+    }
+
     /// Get the current root block
     pub fn root_block(&self) -> u64 {
         if let Some(ref txn) = self.transaction {
@@ -365,6 +391,185 @@ impl DirOps {
         let result = ops.delete::<u64, DirEntryValue>(dir_tree, &hash)?;
         Ok(result.0)
     }
+
+    /// Give an existing inode another name in a directory (a hard link)
+    ///
+    /// Directories can't be linked. Both inodes are updated in the inode
+    /// tree; `target.nlink` counts the new name.
+    pub fn link<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &mut FilesystemState,
+        dir: &mut Inode,
+        name: &str,
+        target: &mut Inode,
+    ) -> Result<(), TreeError> {
+        if target.is_directory() || !dir.is_directory() {
+            return Err(TreeError::InvalidOperation);
+        }
+
+        let mut tree = BPlusTree::new(dir.extent_root, NodeType::Directory, state.superblock.root_generation);
+        if Self::lookup(ops, &tree, name)?.is_some() {
+            return Err(TreeError::DuplicateKey);
+        }
+
+        let entry_type = if target.is_symlink() { EntryType::Symlink } else { EntryType::File };
+        let entry = DirEntry::new(name, target.inode_num, entry_type).ok_or(TreeError::InvalidOperation)?;
+        Self::insert(ops, &mut tree, entry)?;
+        dir.extent_root = tree.root_block;
+        dir.update_crc();
+        InodeOps::insert(ops, state, *dir)?;
+
+        target.nlink += 1;
+        target.update_crc();
+        InodeOps::insert(ops, state, *target)
+    }
+
+    /// A directory holds nothing but "." and ".."
+    pub fn is_empty<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &FilesystemState,
+        dir: &Inode,
+    ) -> Result<bool, TreeError> {
+        let tree = BPlusTree::new(dir.extent_root, NodeType::Directory, state.superblock.root_generation);
+        Ok(ops.entries::<u64, DirEntryValue>(&tree)?
+            .iter()
+            .all(|(_, e)| matches!(e.0.name_str(), "." | "..")))
+    }
+
+    /// Move entry `old_name` of directory `old_dir` to `new_name` in
+    /// `new_dir`
+    ///
+    /// An existing `new_name` is replaced: a file loses that name (and goes
+    /// if it was its last), an empty directory is deleted. Replacing a
+    /// directory with a non-directory or the other way round, or a
+    /// directory that isn't empty, is an InvalidOperation. Moving a
+    /// directory into itself is the caller's to prevent; nothing here knows
+    /// a directory's ancestors.
+    ///
+    /// The inode tree only changes through CoW, so on error the caller can
+    /// restore a copy of `state` taken beforehand and nothing has happened.
+    pub fn rename<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &mut FilesystemState,
+        old_dir: u64,
+        old_name: &str,
+        new_dir: u64,
+        new_name: &str,
+    ) -> Result<(), TreeError> {
+        let dir = Self::directory(ops, state, old_dir)?;
+        let tree = BPlusTree::new(dir.extent_root, NodeType::Directory, state.superblock.root_generation);
+        let entry = Self::lookup(ops, &tree, old_name)?.ok_or(TreeError::KeyNotFound)?;
+        let moves_dir = entry.is_directory() && old_dir != new_dir;
+
+        // Drop whatever the new name refers to now
+        let mut dst = Self::directory(ops, state, new_dir)?;
+        let dst_tree = BPlusTree::new(dst.extent_root, NodeType::Directory, state.superblock.root_generation);
+        if let Some(existing) = Self::lookup(ops, &dst_tree, new_name)? {
+            if existing.inode_num == entry.inode_num {
+                return Ok(());
+            }
+            if existing.is_directory() != entry.is_directory() {
+                return Err(TreeError::InvalidOperation);
+            }
+
+            if existing.is_directory() {
+                let victim = InodeOps::lookup(ops, state, existing.inode_num)?.ok_or(TreeError::KeyNotFound)?;
+                if !Self::is_empty(ops, state, &victim)? {
+                    return Err(TreeError::InvalidOperation);
+                }
+                let mut dst_tree = dst_tree;
+                Self::delete(ops, &mut dst_tree, new_name)?;
+                dst.extent_root = dst_tree.root_block;
+                dst.nlink = dst.nlink.saturating_sub(1);
+                dst.update_crc();
+                InodeOps::insert(ops, state, dst)?;
+                InodeOps::delete(ops, state, victim.inode_num)?;
+            } else {
+                Self::unlink(ops, state, &mut dst, new_name)?;
+            }
+        }
+
+        // Re-read both directories between steps: they may be the same inode
+        let mut src = Self::directory(ops, state, old_dir)?;
+        let mut tree = BPlusTree::new(src.extent_root, NodeType::Directory, state.superblock.root_generation);
+        Self::delete(ops, &mut tree, old_name)?;
+        src.extent_root = tree.root_block;
+        if moves_dir {
+            src.nlink = src.nlink.saturating_sub(1);
+        }
+        src.update_crc();
+        InodeOps::insert(ops, state, src)?;
+
+        let mut dst = Self::directory(ops, state, new_dir)?;
+        let mut tree = BPlusTree::new(dst.extent_root, NodeType::Directory, state.superblock.root_generation);
+        let moved = DirEntry::new(new_name, entry.inode_num, entry.entry_type()).ok_or(TreeError::InvalidOperation)?;
+        Self::insert(ops, &mut tree, moved)?;
+        dst.extent_root = tree.root_block;
+        if moves_dir {
+            dst.nlink += 1;
+        }
+        dst.update_crc();
+        InodeOps::insert(ops, state, dst)?;
+
+        // A moved directory's ".." (if it has one on disk) follows it
+        if moves_dir {
+            let mut child = InodeOps::lookup(ops, state, entry.inode_num)?.ok_or(TreeError::KeyNotFound)?;
+            let mut tree = BPlusTree::new(child.extent_root, NodeType::Directory, state.superblock.root_generation);
+            if Self::lookup(ops, &tree, "..")?.is_some() {
+                Self::delete(ops, &mut tree, "..")?;
+                Self::insert(ops, &mut tree, dotdot_entry(new_dir))?;
+                child.extent_root = tree.root_block;
+                child.update_crc();
+                InodeOps::insert(ops, state, child)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Look up an inode that must be a directory
+    fn directory<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &FilesystemState,
+        inode_num: u64,
+    ) -> Result<Inode, TreeError> {
+        match InodeOps::lookup(ops, state, inode_num)? {
+            Some(inode) if inode.is_directory() => Ok(inode),
+            Some(_) => Err(TreeError::InvalidOperation),
+            None => Err(TreeError::KeyNotFound),
+        }
+    }
+
+    /// Remove a non-directory's name from a directory
+    ///
+    /// The inode goes with its last name. Returns it with its remaining
+    /// link count, which is 0 if it was deleted.
+    pub fn unlink<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &mut FilesystemState,
+        dir: &mut Inode,
+        name: &str,
+    ) -> Result<Inode, TreeError> {
+        let mut tree = BPlusTree::new(dir.extent_root, NodeType::Directory, state.superblock.root_generation);
+        let entry = Self::lookup(ops, &tree, name)?.ok_or(TreeError::KeyNotFound)?;
+        let mut target = InodeOps::lookup(ops, state, entry.inode_num)?.ok_or(TreeError::KeyNotFound)?;
+        if target.is_directory() {
+            return Err(TreeError::InvalidOperation);
+        }
+
+        Self::delete(ops, &mut tree, name)?;
+        dir.extent_root = tree.root_block;
+        dir.update_crc();
+        InodeOps::insert(ops, state, *dir)?;
+
+        target.nlink = target.nlink.saturating_sub(1);
+        if target.nlink == 0 {
+            InodeOps::delete(ops, state, target.inode_num)?;
+        } else {
+            target.update_crc();
+            InodeOps::insert(ops, state, target)?;
+        }
+        Ok(target)
+    }
 }
 
 // ============================================================================
@@ -446,6 +651,15 @@ impl TreeValue for ExtentValue {
     }
 }
 
+/// What backs a file offset
+#[derive(Clone, Copy, Debug)]
+pub enum ExtentMapping {
+    /// Offset lies in an extent with disk blocks behind it
+    Data(Extent),
+    /// Offset lies in a hole that runs up to `end` (or to EOF when `None`)
+    Hole { end: Option<u64> },
+}
+
 impl ExtentOps {
     /// Lookup an extent containing a file offset
     pub fn lookup<D: BlockDevice, A: BlockAllocator>(
@@ -453,20 +667,53 @@ impl ExtentOps {
         extent_tree: &BPlusTree,
         file_offset: u64,
     ) -> Result<Option<Extent>, TreeError> {
-        // Search for extent by file offset
-        let (result, _) = ops.search::<u64, ExtentValue>(extent_tree, &file_offset)?;
+        let entries = ops.leaf_entries::<u64, ExtentValue>(extent_tree, &file_offset)?;
+        Ok(entries
+            .into_iter()
+            .map(|(_, ev)| ev.0)
+            .find(|ext| ext.contains_offset(file_offset)))
+    }
 
-        match result.value() {
-            Some(ev) => {
-                // Check if offset is within this extent
-                if ev.0.contains_offset(file_offset) {
-                    Ok(Some(ev.0))
-                } else {
-                    Ok(None)
+    /// Map a file offset to its extent, or to the hole around it
+    ///
+    /// Regions with no extent and extents flagged EXTENT_HOLE are both
+    /// holes; they read as zeros and own no disk blocks.
+    pub fn map<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        extent_tree: &BPlusTree,
+        file_offset: u64,
+    ) -> Result<ExtentMapping, TreeError> {
+        let entries = ops.leaf_entries::<u64, ExtentValue>(extent_tree, &file_offset)?;
+
+        let mut next = None;
+        for (_, ExtentValue(ext)) in entries {
+            if ext.contains_offset(file_offset) {
+                if ext.is_hole() {
+                    return Ok(ExtentMapping::Hole { end: Some(ext.file_end()) });
                 }
+                return Ok(ExtentMapping::Data(ext));
+            }
+            if ext.file_offset > file_offset {
+                next = Some(next.map_or(ext.file_offset, |n: u64| n.min(ext.file_offset)));
             }
-            None => Ok(None),
         }
+
+        Ok(ExtentMapping::Hole { end: next })
+    }
+
+    /// Get all extents overlapping the byte range [start, end)
+    pub fn overlapping<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        extent_tree: &BPlusTree,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<Extent>, TreeError> {
+        let entries = ops.leaf_entries::<u64, ExtentValue>(extent_tree, &start)?;
+        Ok(entries
+            .into_iter()
+            .map(|(_, ev)| ev.0)
+            .filter(|ext| ext.file_offset < end && ext.file_end() > start)
+            .collect())
     }
 
     /// Insert an extent
@@ -520,7 +767,9 @@ impl ExtentOps {
             }
         }
 
-        Ok(Extent::new(file_offset, start_block, length))
+        let mut extent = Extent::new(file_offset, start_block, length);
+        extent.generation = state.write_generation() as u32;
+        Ok(extent)
     }
 }
 
@@ -529,15 +778,23 @@ impl ExtentOps {
 // ============================================================================
 
 /// File read/write operations
+///
+/// Files are sparse: ranges that were never written have no extent and read
+/// back as zeros, and `inode.blocks` counts only the blocks actually
+/// allocated, so it can be far smaller than `inode.size` suggests. Extent
+/// trees are kept to a single leaf, as the inode does not record a height.
 pub struct FileOps;
 
 impl FileOps {
     /// Read file data at an offset
     ///
-    /// Returns the data read and actual bytes read.
+    /// Returns the number of bytes read. Holes read as zeros. Unless
+    /// `state.verify_data` is off, each extent read from is checked against
+    /// its checksum first, failing with DataCrcError.
     pub fn read<D: BlockDevice, A: BlockAllocator>(
         ops: &mut TreeOps<D, A>,
         device: &D,
+        state: &FilesystemState,
         inode: &Inode,
         offset: u64,
         buf: &mut [u8],
@@ -552,68 +809,51 @@ impl FileOps {
         // Check for inline data
         if inode.is_inline() {
             let inline_data = inode.get_inline_data();
-            if offset < inline_data.len() as u64 {
-                let start = offset as usize;
+            let start = offset as usize;
+            buf[..read_len].fill(0);
+            if start < inline_data.len() {
                 let copy_len = core::cmp::min(read_len, inline_data.len() - start);
                 buf[..copy_len].copy_from_slice(&inline_data[start..start + copy_len]);
-                return Ok(copy_len);
             }
-            return Ok(0);
+            return Ok(read_len);
         }
 
-        // For extents, we need to traverse the extent tree
-        let extent_tree = BPlusTree::from_root(
-            inode.extent_root,
-            0, // height unknown, but search works
-            NodeType::Extent,
-            0,
-        );
-
+        let extent_tree = Self::extent_tree(inode, 0);
         let mut bytes_read = 0;
-        let mut current_offset = offset;
 
         while bytes_read < read_len {
-            // Find extent for current offset
-            let extent = ExtentOps::lookup(ops, &extent_tree, current_offset)?;
-
-            match extent {
-                Some(ext) if !ext.is_hole() => {
-                    // Calculate read position within extent
-                    let offset_in_extent = current_offset - ext.file_offset;
-                    let extent_remaining = ext.length - offset_in_extent;
-                    let to_read = core::cmp::min(
-                        (read_len - bytes_read) as u64,
-                        extent_remaining,
-                    ) as usize;
-
-                    // Calculate disk block
+            let current_offset = offset + bytes_read as u64;
+            let remaining = (read_len - bytes_read) as u64;
+
+            match ExtentOps::map(ops, &extent_tree, current_offset)? {
+                ExtentMapping::Data(ext) if ext.is_compressed() || (state.verify_data && ext.has_crc()) => {
+                    let chunk = Self::load(device, state, &ext)?;
+                    let at = (current_offset - ext.file_offset) as usize;
+                    let copy_len = remaining.min(ext.file_end() - current_offset) as usize;
+
+                    buf[bytes_read..bytes_read + copy_len].copy_from_slice(&chunk[at..at + copy_len]);
+                    bytes_read += copy_len;
+                }
+                ExtentMapping::Data(ext) => {
                     let block = ext.offset_to_block(current_offset)
                         .ok_or(TreeError::InvalidNode)?;
-
-                    // Read block from disk
                     let node = device.read_node(block)?;
 
-                    // Copy data from block
-                    let block_offset = (offset_in_extent % super::structures::BLOCK_SIZE as u64) as usize;
-                    let copy_len = core::cmp::min(
-                        to_read,
-                        super::structures::BLOCK_SIZE as usize - block_offset,
-                    );
-                    buf[bytes_read..bytes_read + copy_len]
-                        .copy_from_slice(&node.data[block_offset..block_offset + copy_len]);
+                    let block_offset = (current_offset - ext.file_offset) % BLOCK_SIZE as u64;
+                    let copy_len = remaining
+                        .min(BLOCK_SIZE as u64 - block_offset)
+                        .min(ext.file_end() - current_offset) as usize;
+                    let block_offset = block_offset as usize;
 
+                    buf[bytes_read..bytes_read + copy_len]
+                        .copy_from_slice(&data_bytes(&node)[block_offset..block_offset + copy_len]);
                     bytes_read += copy_len;
-                    current_offset += copy_len as u64;
                 }
-                Some(_) | None => {
-                    // Hole or no extent - return zeros
-                    let remaining = read_len - bytes_read;
-                    let to_zero = core::cmp::min(remaining, super::structures::BLOCK_SIZE as usize);
-                    for i in 0..to_zero {
-                        buf[bytes_read + i] = 0;
-                    }
-                    bytes_read += to_zero;
-                    current_offset += to_zero as u64;
+                ExtentMapping::Hole { end } => {
+                    let zero_len = end
+                        .map_or(remaining, |end| remaining.min(end - current_offset)) as usize;
+                    buf[bytes_read..bytes_read + zero_len].fill(0);
+                    bytes_read += zero_len;
                 }
             }
         }
@@ -623,7 +863,9 @@ impl FileOps {
 
     /// Write file data at an offset
     ///
-    /// Handles CoW for shared extents.
+    /// Writing past EOF leaves a hole between the old end and `offset`.
+    /// Rewritten blocks move to new disk blocks (CoW); the old ones are
+    /// freed when the transaction commits.
     pub fn write<D: BlockDevice + BlockAllocator, A: BlockAllocator>(
         ops: &mut TreeOps<D, A>,
         device: &mut D,
@@ -632,65 +874,315 @@ impl FileOps {
         offset: u64,
         data: &[u8],
     ) -> Result<usize, TreeError> {
-        let write_len = data.len();
+        if data.is_empty() {
+            return Ok(0);
+        }
 
-        // Check for inline data
-        if Inode::can_inline(offset as usize + write_len) && offset == 0 {
-            // Can write inline
-            inode.set_inline_data(data);
+        let end = offset + data.len() as u64;
+
+        // Small files stay inline; a gap before offset is stored as zeros
+        if inode.extent_root == 0 && inode.size.max(end) <= INODE_INLINE_SIZE as u64 {
+            let mut contents = [0u8; INODE_INLINE_SIZE];
+            let old = inode.get_inline_data();
+            contents[..old.len()].copy_from_slice(old);
+            contents[offset as usize..end as usize].copy_from_slice(data);
+
+            inode.set_inline_data(&contents[..inode.size.max(end) as usize]);
             inode.update_crc();
             InodeOps::insert(ops, state, *inode)?;
-            return Ok(write_len);
+            return Ok(data.len());
+        }
+
+        Self::move_inline_data(ops, device, state, inode)?;
+        Self::write_blocks(ops, device, state, inode, offset, data)?;
+
+        inode.size = inode.size.max(end);
+        inode.update_crc();
+        InodeOps::insert(ops, state, *inode)?;
+
+        Ok(data.len())
+    }
+
+    /// Set the file size
+    ///
+    /// Growing only moves EOF, leaving a hole. Shrinking drops the blocks
+    /// past the new end and zeroes the rest of the last block, so a later
+    /// extension reads zeros rather than the old bytes.
+    pub fn truncate<D: BlockDevice + BlockAllocator, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        device: &mut D,
+        state: &mut FilesystemState,
+        inode: &mut Inode,
+        size: u64,
+    ) -> Result<(), TreeError> {
+        if inode.extent_root == 0 && size <= INODE_INLINE_SIZE as u64 {
+            let mut contents = [0u8; INODE_INLINE_SIZE];
+            let keep = core::cmp::min(inode.inline_size as usize, size as usize);
+            contents[..keep].copy_from_slice(&inode.inline_data[..keep]);
+            inode.set_inline_data(&contents[..size as usize]);
+        } else {
+            Self::move_inline_data(ops, device, state, inode)?;
+
+            if size < inode.size {
+                let bs = BLOCK_SIZE as u64;
+                let cut = size.div_ceil(bs) * bs;
+
+                let mut tree = Self::extent_tree(inode, state.superblock.root_generation);
+                Self::punch(ops, device, state, inode, &mut tree, cut, u64::MAX)?;
+                inode.extent_root = tree.root_block;
+
+                if cut > size {
+                    if let ExtentMapping::Data(_) = ExtentOps::map(ops, &tree, size)? {
+                        let zeros = vec![0u8; (cut - size) as usize];
+                        Self::write_blocks(ops, device, state, inode, size, &zeros)?;
+                    }
+                }
+            }
+            inode.size = size;
         }
 
-        // Need to use extents
-        // For simplicity, allocate a new extent for this write
-        let extent = ExtentOps::allocate_for_write(device, state, offset, write_len as u64)?;
+        inode.update_crc();
+        InodeOps::insert(ops, state, *inode)?;
+        Ok(())
+    }
 
-        // Write data to disk blocks
-        let mut bytes_written = 0;
-        let mut current_block = extent.disk_block;
+    /// Move inline data out to the first data block
+    fn move_inline_data<D: BlockDevice + BlockAllocator, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        device: &mut D,
+        state: &mut FilesystemState,
+        inode: &mut Inode,
+    ) -> Result<(), TreeError> {
+        if !inode.is_inline() {
+            return Ok(());
+        }
 
-        while bytes_written < write_len {
-            let remaining = write_len - bytes_written;
-            let to_write = core::cmp::min(remaining, super::structures::BLOCK_SIZE as usize);
+        let len = inode.inline_size as usize;
+        let contents = inode.inline_data;
+        inode.inline_size = 0;
+        inode.inline_data = [0; INODE_INLINE_SIZE];
+        inode.flags &= !INODE_INLINE;
 
-            // Create a node with the data
-            let mut node = TreeNode::new_leaf(NodeType::Extent, state.superblock.root_generation);
-            node.data[..to_write].copy_from_slice(&data[bytes_written..bytes_written + to_write]);
+        if len > 0 {
+            Self::write_blocks(ops, device, state, inode, 0, &contents[..len])?;
+        }
+        Ok(())
+    }
 
-            // Write to disk
-            device.write_node(current_block, &node)?;
+    /// Write data into new blocks covering the block-aligned range around it
+    ///
+    /// Whatever extents covered that range are replaced. The bytes around
+    /// `data` in that range are read back first so they survive.
+    ///
+    /// Extents are at most a chunk long, so a read never has to check more
+    /// than a chunk against its checksum. Compressed data is written a whole
+    /// chunk at a time (or up to EOF), so each chunk can be read back on its
+    /// own; one that doesn't compress into fewer blocks is stored as is.
+    fn write_blocks<D: BlockDevice + BlockAllocator, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        device: &mut D,
+        state: &mut FilesystemState,
+        inode: &mut Inode,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), TreeError> {
+        let bs = BLOCK_SIZE as u64;
+        let data_end = offset + data.len() as u64;
+        let compress = state.compress || inode.flags & INODE_COMPRESS != 0;
+
+        let (start, end) = if compress {
+            let chunk = DEFAULT_CHUNK_SIZE as u64;
+            let eof = inode.size.max(data_end).div_ceil(bs) * bs;
+            (offset / chunk * chunk, (data_end.div_ceil(chunk) * chunk).min(eof))
+        } else {
+            (offset / bs * bs, data_end.div_ceil(bs) * bs)
+        };
 
-            bytes_written += to_write;
-            current_block += 1;
+        let mut buf = vec![0u8; (end - start) as usize];
+        let at = (offset - start) as usize;
+        let tail = at + data.len();
+        Self::read(ops, device, state, inode, start, &mut buf[..at])?;
+        Self::read(ops, device, state, inode, data_end, &mut buf[tail..])?;
+        buf[at..tail].copy_from_slice(data);
+
+        let mut tree = Self::extent_tree(inode, state.superblock.root_generation);
+        Self::punch(ops, device, state, inode, &mut tree, start, end)?;
+
+        let pieces = DEFAULT_CHUNK_SIZE as usize;
+        for (i, piece) in buf.chunks(pieces).enumerate() {
+            let extent = Self::store(device, state, start + (i * pieces) as u64, piece, compress)?;
+            ExtentOps::insert(ops, &mut tree, extent)?;
+            inode.blocks += extent.block_count();
         }
 
-        // Insert extent into tree
-        let mut extent_tree = BPlusTree::from_root(
-            inode.extent_root,
-            0,
-            NodeType::Extent,
-            state.superblock.root_generation,
-        );
+        inode.extent_root = tree.root_block;
+        Ok(())
+    }
+
+    /// Write file data to new blocks, compressed if asked and worthwhile,
+    /// and checksum what was stored
+    fn store<D: BlockDevice + BlockAllocator>(
+        device: &mut D,
+        state: &mut FilesystemState,
+        file_offset: u64,
+        data: &[u8],
+        compress: bool,
+    ) -> Result<Extent, TreeError> {
+        let bs = BLOCK_SIZE as usize;
+        let packed = if compress { Some(compress::pack(data)) } else { None }
+            .filter(|packed| packed.len().div_ceil(bs) < data.len().div_ceil(bs));
+        let stored = packed.as_deref().unwrap_or(data);
+
+        // Whole blocks, as the checksum covers the padding too
+        let mut blocks = vec![0u8; stored.len().div_ceil(bs) * bs];
+        blocks[..stored.len()].copy_from_slice(stored);
+
+        let mut extent = ExtentOps::allocate_for_write(device, state, file_offset, stored.len() as u64)?;
+        for (i, chunk) in blocks.chunks(bs).enumerate() {
+            let mut node = TreeNode::default();
+            data_bytes_mut(&mut node).copy_from_slice(chunk);
+            device.write_node(extent.disk_block + i as u64, &node)?;
+        }
 
-        if inode.extent_root == 0 {
-            // No existing extent tree - this becomes the first extent
+        extent.set_crc(crate::crc32(&blocks));
+        if packed.is_some() {
+            extent.set_compressed(data.len() as u64);
         }
+        Ok(extent)
+    }
 
-        ExtentOps::insert(ops, &mut extent_tree, extent)?;
+    /// Read a whole extent's data, checked against its checksum and
+    /// decompressed
+    ///
+    /// Plain extents come back as whole blocks, padding included.
+    fn load<D: BlockDevice>(device: &D, state: &FilesystemState, ext: &Extent) -> Result<Vec<u8>, TreeError> {
+        let bs = BLOCK_SIZE as usize;
+        let mut stored = vec![0u8; ext.block_count() as usize * bs];
+        for (i, block) in stored.chunks_mut(bs).enumerate() {
+            block.copy_from_slice(data_bytes(&device.read_node(ext.disk_block + i as u64)?));
+        }
 
-        // Update inode
-        inode.extent_root = extent_tree.root_block;
-        if offset + write_len as u64 > inode.size {
-            inode.size = offset + write_len as u64;
+        if state.verify_data && ext.has_crc() && crate::crc32(&stored) != ext.crc32 {
+            return Err(TreeError::DataCrcError);
         }
-        inode.blocks = (inode.size + super::structures::BLOCK_SIZE as u64 - 1)
-            / super::structures::BLOCK_SIZE as u64;
-        inode.update_crc();
-        InodeOps::insert(ops, state, *inode)?;
+        if !ext.is_compressed() {
+            return Ok(stored);
+        }
+        compress::unpack(&stored, ext.length as usize).ok_or(TreeError::InvalidNode)
+    }
 
-        Ok(write_len)
+    /// A piece split off a checksummed extent, with its own checksum worked
+    /// out from the whole extent's `data`
+    fn rechecksum(piece: Extent, whole: &Extent, data: Option<&[u8]>) -> Extent {
+        let Some(data) = data else {
+            return piece;
+        };
+        let bs = BLOCK_SIZE as usize;
+        let from = (piece.disk_block - whole.disk_block) as usize * bs;
+        let mut piece = piece;
+        piece.set_crc(crate::crc32(&data[from..from + piece.block_count() as usize * bs]));
+        piece
+    }
+
+    /// Remove the extents covering [start, end), keeping any parts outside it
+    fn punch<D: BlockDevice + BlockAllocator, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        device: &mut D,
+        state: &mut FilesystemState,
+        inode: &mut Inode,
+        tree: &mut BPlusTree,
+        start: u64,
+        end: u64,
+    ) -> Result<(), TreeError> {
+        for ext in ExtentOps::overlapping(ops, tree, start, end)? {
+            ExtentOps::delete(ops, tree, ext.file_offset)?;
+
+            let mut dropped = ext;
+
+            let partial = ext.file_offset < start || ext.file_end() > end;
+
+            // Keeping part of a compressed extent means storing it plainly
+            if ext.is_compressed() && partial {
+                let data = Self::load(device, state, &ext)?;
+                dropped = Self::store(device, state, ext.file_offset, &data[..ext.length as usize], false)?;
+                inode.blocks += dropped.block_count();
+                Self::release(device, state, inode, &ext)?;
+            }
+
+            // The pieces kept need checksums of their own
+            let whole = dropped;
+            let data = if whole.has_crc() && partial {
+                Some(Self::load(device, state, &whole)?)
+            } else {
+                None
+            };
+
+            if let Some((left, right)) = dropped.split_at(start) {
+                ExtentOps::insert(ops, tree, Self::rechecksum(left, &whole, data.as_deref()))?;
+                dropped = right;
+            }
+            if let Some((middle, right)) = dropped.split_at(end) {
+                ExtentOps::insert(ops, tree, Self::rechecksum(right, &whole, data.as_deref()))?;
+                dropped = middle;
+            }
+
+            Self::release(device, state, inode, &dropped)?;
+        }
+        Ok(())
+    }
+
+    /// Give up the blocks of an extent removed from the file
+    fn release<D: BlockDevice + BlockAllocator>(
+        device: &mut D,
+        state: &mut FilesystemState,
+        inode: &mut Inode,
+        dropped: &Extent,
+    ) -> Result<(), TreeError> {
+        if dropped.is_hole() {
+            return Ok(());
+        }
+
+        let count = dropped.block_count();
+        inode.blocks = inode.blocks.saturating_sub(count);
+
+        // A snapshot still reads these blocks
+        if state.snapshot_holds(dropped) {
+            return Ok(());
+        }
+
+        // The committed tree may still point at these blocks
+        if let Some(ref mut txn) = state.transaction {
+            txn.schedule_free(dropped.disk_block, count);
+        } else {
+            for block in dropped.disk_block..dropped.disk_end() {
+                device.free_block(block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The inode's extent tree
+    fn extent_tree(inode: &Inode, generation: u64) -> BPlusTree {
+        BPlusTree::from_root(inode.extent_root, 0, NodeType::Extent, generation)
+    }
+}
+
+/// Raw bytes of a file data block
+///
+/// Data blocks have no node header; TreeNode is only the BLOCK_SIZE buffer
+/// the block device moves them in.
+fn data_bytes(node: &TreeNode) -> &[u8] {
+    // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+    unsafe {
+        core::slice::from_raw_parts(node as *const TreeNode as *const u8, BLOCK_SIZE as usize)
+    }
+}
+
+/// Mutable raw bytes of a file data block
+fn data_bytes_mut(node: &mut TreeNode) -> &mut [u8] {
+    // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+    unsafe {
+        core::slice::from_raw_parts_mut(node as *mut TreeNode as *mut u8, BLOCK_SIZE as usize)
     }
 }
 
@@ -806,8 +1298,19 @@ pub fn resolve_path<D: BlockDevice, A: BlockAllocator>(
     state: &FilesystemState,
     path: &str,
 ) -> Result<Option<Inode>, TreeError> {
-    // Start at root inode
-    let mut current_inode = match InodeOps::lookup(ops, state, ROOT_INODE)? {
+    resolve_path_from(ops, state, ROOT_INODE, path)
+}
+
+/// Resolve a path relative to the directory (or file) `start`
+///
+/// Snapshot views resolve from the inode the snapshot was taken of.
+pub fn resolve_path_from<D: BlockDevice, A: BlockAllocator>(
+    ops: &mut TreeOps<D, A>,
+    state: &FilesystemState,
+    start: u64,
+    path: &str,
+) -> Result<Option<Inode>, TreeError> {
+    let mut current_inode = match InodeOps::lookup(ops, state, start)? {
         Some(inode) => inode,
         None => return Err(TreeError::NodeNotFound),
     };
@@ -40,6 +40,9 @@ pub const INODE_INLINE: u32 = 0x0010;
 /// Inode is deleted (pending garbage collection)
 pub const INODE_DELETED: u32 = 0x0020;
 
+/// Compress data written to the file
+pub const INODE_COMPRESS: u32 = 0x0040;
+
 // ============================================================================
 // INODE STRUCTURE
 // ============================================================================
@@ -19,6 +19,8 @@ pub mod freespace;
 pub mod tree;
 pub mod transaction;
 pub mod fs;
+pub mod snapshot;
+pub mod compress;
 
 #[cfg(test)]
 mod tests;
@@ -26,10 +28,11 @@ mod tests;
 // Re-export commonly used items
 pub use structures::*;
 pub use node::{TreeNode, NodeType};
-pub use inode::{Inode, INODE_IMMUTABLE, INODE_APPEND, INODE_NOATIME, INODE_SHARED, INODE_INLINE, INODE_DELETED};
+pub use inode::{Inode, INODE_IMMUTABLE, INODE_APPEND, INODE_NOATIME, INODE_SHARED, INODE_INLINE, INODE_DELETED, INODE_COMPRESS};
 pub use dir::DirEntry;
 pub use extent::Extent;
 pub use freespace::FreeRange;
 pub use tree::{BPlusTree, BlockDevice, BlockAllocator, TreeOps, TreeError, SearchResult, TreePath, TreeKey, TreeValue};
 pub use transaction::{Transaction, TransactionState, TransactionManager, TransactionError};
-pub use fs::{FilesystemState, FilesystemOps, InodeOps, DirOps, ExtentOps, FileOps, FreeSpaceOps, init_filesystem, resolve_path};
+pub use fs::{FilesystemState, FilesystemOps, InodeOps, DirOps, ExtentOps, ExtentMapping, FileOps, FreeSpaceOps, init_filesystem, resolve_path, resolve_path_from};
+pub use snapshot::{Snapshot, SnapshotOps};
@@ -23,6 +23,7 @@ pub const NODE_MAGIC_INODE: u32 = 0x494E4F44;     // "INOD"
 pub const NODE_MAGIC_DIR: u32 = 0x44495245;       // "DIRE"
 pub const NODE_MAGIC_EXTENT: u32 = 0x45585445;    // "EXTE"
 pub const NODE_MAGIC_FREE: u32 = 0x46524545;      // "FREE"
+pub const NODE_MAGIC_SNAP: u32 = 0x534E4150;      // "SNAP"
 
 // ============================================================================
 // NODE TYPE
@@ -40,6 +41,8 @@ pub enum NodeType {
     Extent = NODE_MAGIC_EXTENT,
     /// Free space tree node (maps block_num -> range_length)
     FreeSpace = NODE_MAGIC_FREE,
+    /// Snapshot tree node (maps name hash -> Snapshot)
+    Snapshot = NODE_MAGIC_SNAP,
 }
 
 impl NodeType {
@@ -49,6 +52,7 @@ impl NodeType {
             NODE_MAGIC_DIR => Some(NodeType::Directory),
             NODE_MAGIC_EXTENT => Some(NodeType::Extent),
             NODE_MAGIC_FREE => Some(NodeType::FreeSpace),
+            NODE_MAGIC_SNAP => Some(NodeType::Snapshot),
             _ => None,
         }
     }
@@ -0,0 +1,195 @@
+//! Snapshots
+//!
+//! A snapshot is a named, frozen view of a file or directory tree. Tree
+//! updates never overwrite a node, so the inode tree root at the time of the
+//! snapshot keeps describing the filesystem as it was; the snapshot records
+//! that root and the inode it was taken of. Taking one copies nothing.
+//!
+//! Data blocks are the one thing the live filesystem frees. Extents carry
+//! the generation they were written in, and once a snapshot exists the
+//! superblock's `snapshot_generation` keeps anything written at or before
+//! it: rewriting or truncating a file then leaves the old blocks for the
+//! snapshot rather than freeing them.
+//!
+//! Snapshots live in their own B+tree, keyed by name hash like directory
+//! entries, whose root is in the superblock.
+
+#[allow(unused_imports)]
+use crate::prelude::*;
+use super::fs::{resolve_path, FilesystemState};
+use super::node::NodeType;
+use super::tree::{BPlusTree, BlockAllocator, BlockDevice, TreeError, TreeOps, TreeValue};
+use super::dir::DirEntry;
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Snapshot record size on disk
+pub const SNAPSHOT_SIZE: usize = 128;
+
+/// Longest snapshot name
+pub const SNAPSHOT_NAME_MAX: usize = 80;
+
+// ============================================================================
+// SNAPSHOT RECORD
+// ============================================================================
+
+/// Snapshot - 128 bytes in the snapshot tree
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    // Key (8 bytes)
+    pub name_hash: u64,                // Hash of name for tree key
+
+    // View (24 bytes)
+    pub root_tree_block: u64,          // Inode tree root when taken
+    pub inode_num: u64,                // Inode the snapshot was taken of
+    pub generation: u64,               // Generation when taken
+
+    // Metadata (16 bytes)
+    pub name_len: u8,                  // Length of name
+    pub _pad: [u8; 15],
+
+    // Name (80 bytes)
+    pub name: [u8; SNAPSHOT_NAME_MAX],
+}
+
+impl Snapshot {
+    /// Create a snapshot record
+    pub fn new(name: &str, root_tree_block: u64, inode_num: u64, generation: u64) -> Option<Self> {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() > SNAPSHOT_NAME_MAX || name.contains('/') {
+            return None;
+        }
+
+        let mut snapshot = Self {
+            name_hash: DirEntry::hash_name(name),
+            root_tree_block,
+            inode_num,
+            generation,
+            name_len: bytes.len() as u8,
+            _pad: [0; 15],
+            name: [0; SNAPSHOT_NAME_MAX],
+        };
+        snapshot.name[..bytes.len()].copy_from_slice(bytes);
+        Some(snapshot)
+    }
+
+    /// Get the name as a string slice
+    pub fn name_str(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("")
+    }
+
+    /// Filesystem state that sees the inode tree as it was when taken
+    ///
+    /// Resolve paths in it from `inode_num`, and only read through it.
+    pub fn view(&self, state: &FilesystemState) -> FilesystemState {
+        let mut superblock = state.superblock;
+        superblock.root_tree_block = self.root_tree_block;
+        superblock.root_generation = self.generation;
+        FilesystemState::new(superblock)
+    }
+}
+
+/// Wrapper for Snapshot to implement TreeValue
+#[derive(Clone, Copy)]
+pub struct SnapshotValue(pub Snapshot);
+
+impl TreeValue for SnapshotValue {
+    fn serialized_size(&self) -> usize {
+        SNAPSHOT_SIZE
+    }
+
+    fn serialize(&self, buf: &mut [u8]) -> usize {
+        if buf.len() >= SNAPSHOT_SIZE {
+            let bytes = unsafe {
+                core::slice::from_raw_parts(&self.0 as *const _ as *const u8, SNAPSHOT_SIZE)
+            };
+            buf[..SNAPSHOT_SIZE].copy_from_slice(bytes);
+            SNAPSHOT_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<(Self, usize)> {
+        if buf.len() >= SNAPSHOT_SIZE {
+            let snapshot = unsafe {
+                core::ptr::read_unaligned(buf.as_ptr() as *const Snapshot)
+            };
+            Some((SnapshotValue(snapshot), SNAPSHOT_SIZE))
+        } else {
+            None
+        }
+    }
+}
+
+// ============================================================================
+// SNAPSHOT OPERATIONS
+// ============================================================================
+
+/// Snapshot operations
+pub struct SnapshotOps;
+
+impl SnapshotOps {
+    /// Take a snapshot of `path` named `name`
+    ///
+    /// Records the current inode tree, including changes not yet committed;
+    /// the snapshot itself becomes durable with the next commit.
+    pub fn create<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &mut FilesystemState,
+        path: &str,
+        name: &str,
+    ) -> Result<Snapshot, TreeError> {
+        let inode = resolve_path(ops, state, path)?.ok_or(TreeError::KeyNotFound)?;
+        let generation = state.write_generation();
+        let snapshot = Snapshot::new(name, state.inode_tree.root_block, inode.inode_num, generation)
+            .ok_or(TreeError::InvalidOperation)?;
+
+        let mut tree = Self::tree(state);
+        if ops.search::<u64, SnapshotValue>(&tree, &snapshot.name_hash)?.0.is_found() {
+            return Err(TreeError::DuplicateKey);
+        }
+        ops.insert(&mut tree, snapshot.name_hash, SnapshotValue(snapshot))?;
+
+        state.superblock.snapshot_tree_block = tree.root_block;
+        state.superblock.snapshot_generation = generation;
+        Ok(snapshot)
+    }
+
+    /// Find a snapshot by name
+    pub fn lookup<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &FilesystemState,
+        name: &str,
+    ) -> Result<Option<Snapshot>, TreeError> {
+        let (result, _) = ops.search::<u64, SnapshotValue>(&Self::tree(state), &DirEntry::hash_name(name))?;
+        Ok(result.value().map(|sv| sv.0).filter(|s| s.name_str() == name))
+    }
+
+    /// Every snapshot, oldest first (by generation, then name)
+    pub fn list<D: BlockDevice, A: BlockAllocator>(
+        ops: &mut TreeOps<D, A>,
+        state: &FilesystemState,
+    ) -> Result<Vec<Snapshot>, TreeError> {
+        let mut snapshots: Vec<Snapshot> = ops.entries::<u64, SnapshotValue>(&Self::tree(state))?
+            .into_iter()
+            .map(|(_, sv)| sv.0)
+            .collect();
+        snapshots.sort_by(|a, b| (a.generation, a.name_str()).cmp(&(b.generation, b.name_str())));
+        Ok(snapshots)
+    }
+
+    /// The snapshot tree
+    fn tree(state: &FilesystemState) -> BPlusTree {
+        BPlusTree::new(state.superblock.snapshot_tree_block, NodeType::Snapshot, state.superblock.root_generation)
+    }
+}
+
+// ============================================================================
+// COMPILE-TIME CHECKS
+// ============================================================================
+
+const _: () = assert!(core::mem::size_of::<Snapshot>() == SNAPSHOT_SIZE);
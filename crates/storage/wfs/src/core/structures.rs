@@ -83,8 +83,12 @@ pub struct Superblock {
     pub next_inode: u64,               // Next inode number to allocate
     pub data_start_block: u64,         // First block for data/trees
 
-    // Reserved for future use (104 bytes)
-    pub reserved: [u8; 104],
+    // Snapshots (16 bytes)
+    pub snapshot_tree_block: u64,      // Snapshot tree root (0 if none)
+    pub snapshot_generation: u64,      // Generation of the newest snapshot
+
+    // Reserved for future use (88 bytes)
+    pub reserved: [u8; 88],
 
     // Integrity (8 bytes)
     pub crc32: u32,                    // Checksum
@@ -112,7 +116,9 @@ impl Superblock {
             inode_count: 0,
             next_inode: ROOT_INODE,
             data_start_block: 2, // After primary and backup superblocks
-            reserved: [0; 104],
+            snapshot_tree_block: 0,
+            snapshot_generation: 0,
+            reserved: [0; 88],
             crc32: 0,
             _pad: 0,
         }
@@ -13,6 +13,7 @@ use std::collections::HashMap;
 struct MockBlockDevice {
     blocks: HashMap<u64, TreeNode>,
     next_block: u64,
+    freed: Vec<u64>,
 }
 
 impl MockBlockDevice {
@@ -20,6 +21,7 @@ impl MockBlockDevice {
         Self {
             blocks: HashMap::new(),
             next_block: 10, // Start after reserved blocks
+            freed: Vec::new(),
         }
     }
 
@@ -50,7 +52,8 @@ impl BlockAllocator for MockBlockDevice {
         Ok(block)
     }
 
-    fn free_block(&mut self, _block: u64) -> Result<(), TreeError> {
+    fn free_block(&mut self, block: u64) -> Result<(), TreeError> {
+        self.freed.push(block);
         Ok(())
     }
 }
@@ -776,6 +779,567 @@ fn test_tree_path() {
     assert_eq!(path.len(), 2);
 }
 
+// ============================================================================
+// SPARSE FILE TESTS
+// ============================================================================
+
+/// Tree nodes and file data on separate mock devices, so the data blocks a
+/// file owns can be counted on their own
+struct FileHarness {
+    tree_dev: MockBlockDevice,
+    tree_alloc: MockBlockDevice,
+    data: MockBlockDevice,
+    state: FilesystemState,
+    inode: Inode,
+}
+
+impl FileHarness {
+    fn new() -> Self {
+        Self {
+            tree_dev: MockBlockDevice::new(),
+            tree_alloc: MockBlockDevice::new(),
+            data: MockBlockDevice::new(),
+            state: FilesystemState::new(Superblock::new(1000)),
+            inode: Inode::new_file(5),
+        }
+    }
+
+    fn write(&mut self, offset: u64, bytes: &[u8]) {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        FileOps::write(&mut ops, &mut self.data, &mut self.state, &mut self.inode, offset, bytes).unwrap();
+    }
+
+    fn truncate(&mut self, size: u64) {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        FileOps::truncate(&mut ops, &mut self.data, &mut self.state, &mut self.inode, size).unwrap();
+    }
+
+    fn read(&mut self, offset: u64, len: usize) -> Vec<u8> {
+        // Pre-fill so zeros have to come from the read itself
+        let mut buf = vec![0xFF; len];
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        let n = FileOps::read(&mut ops, &self.data, &self.state, &self.inode, offset, &mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+}
+
+#[test]
+fn test_write_past_eof_leaves_hole() {
+    let mut f = FileHarness::new();
+    let bs = BLOCK_SIZE as u64;
+
+    f.write(0, b"head");
+    assert!(f.inode.is_inline());
+    assert_eq!(f.inode.blocks, 0);
+
+    f.write(3 * bs + 10, b"tail");
+    assert!(!f.inode.is_inline());
+    assert_eq!(f.inode.size, 3 * bs + 14);
+    // Block 0 (moved inline data) and block 3; blocks 1-2 are a hole
+    assert_eq!(f.inode.blocks, 2);
+    assert_eq!(f.data.block_count(), 2);
+
+    assert_eq!(f.read(0, 4), b"head");
+    assert_eq!(f.read(3 * bs + 10, 100), b"tail");
+    assert!(f.read(4, 3 * bs as usize + 6).iter().all(|&b| b == 0));
+    assert_eq!(f.data.block_count(), 2);
+}
+
+#[test]
+fn test_small_write_gap_stays_inline() {
+    let mut f = FileHarness::new();
+
+    f.write(100, b"xyz");
+    assert!(f.inode.is_inline());
+    assert_eq!(f.inode.size, 103);
+
+    let data = f.read(0, 200);
+    assert_eq!(data.len(), 103);
+    assert!(data[..100].iter().all(|&b| b == 0));
+    assert_eq!(&data[100..], b"xyz");
+}
+
+#[test]
+fn test_truncate_grow_allocates_nothing() {
+    let mut f = FileHarness::new();
+
+    f.truncate(1 << 20);
+    assert_eq!(f.inode.size, 1 << 20);
+    assert_eq!(f.inode.blocks, 0);
+
+    let data = f.read(12345, 8192);
+    assert_eq!(data.len(), 8192);
+    assert!(data.iter().all(|&b| b == 0));
+    assert_eq!(f.data.block_count(), 0);
+}
+
+#[test]
+fn test_overwrite_and_shrink_track_blocks() {
+    let mut f = FileHarness::new();
+    let bs = BLOCK_SIZE as usize;
+
+    f.write(0, &vec![0xAA; 2 * bs]);
+    assert_eq!(f.inode.blocks, 2);
+
+    // Rewriting part of a block replaces it rather than adding one
+    f.write(10, b"new");
+    assert_eq!(f.inode.blocks, 2);
+    assert_eq!(f.read(8, 7), [0xAA, 0xAA, b'n', b'e', b'w', 0xAA, 0xAA]);
+
+    f.truncate(100);
+    assert_eq!(f.inode.blocks, 1);
+
+    // Bytes past the old cut must not reappear when the file grows again
+    f.truncate(2 * bs as u64);
+    assert_eq!(f.inode.blocks, 1);
+    let data = f.read(0, 2 * bs);
+    assert_eq!(data[99], 0xAA);
+    assert!(data[100..].iter().all(|&b| b == 0));
+}
+
+// ============================================================================
+// SNAPSHOT TESTS
+// ============================================================================
+
+impl FileHarness {
+    /// Give the harness a root directory so paths resolve
+    fn with_root() -> Self {
+        let mut f = Self::new();
+        let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+        InodeOps::insert(&mut ops, &mut f.state, Inode::new_directory(ROOT_INODE)).unwrap();
+        f
+    }
+
+    fn snapshot(&mut self, name: &str) -> Result<Snapshot, TreeError> {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        SnapshotOps::create(&mut ops, &mut self.state, "/", name)
+    }
+}
+
+#[test]
+fn test_snapshot_create_and_lookup() {
+    let mut f = FileHarness::with_root();
+    assert_eq!(f.state.superblock.snapshot_tree_block, 0);
+
+    let snap = f.snapshot("before").unwrap();
+    assert_eq!(snap.name_str(), "before");
+    assert_eq!(snap.inode_num, ROOT_INODE);
+    assert_eq!(snap.root_tree_block, f.state.inode_tree.root_block);
+    assert_ne!(f.state.superblock.snapshot_tree_block, 0);
+
+    assert_eq!(f.snapshot("before").err(), Some(TreeError::DuplicateKey));
+    assert_eq!(f.snapshot("a/b").err(), Some(TreeError::InvalidOperation));
+    assert_eq!(f.snapshot("").err(), Some(TreeError::InvalidOperation));
+    f.snapshot("after").unwrap();
+
+    let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+    let found = SnapshotOps::lookup(&mut ops, &f.state, "before").unwrap().unwrap();
+    assert_eq!(found.root_tree_block, snap.root_tree_block);
+    assert!(SnapshotOps::lookup(&mut ops, &f.state, "missing").unwrap().is_none());
+
+    let names: Vec<String> = SnapshotOps::list(&mut ops, &f.state)
+        .unwrap()
+        .iter()
+        .map(|s| s.name_str().to_string())
+        .collect();
+    assert_eq!(names, ["after", "before"]);
+}
+
+#[test]
+fn test_snapshot_view_sees_old_tree() {
+    let mut f = FileHarness::with_root();
+    let snap = f.snapshot("empty").unwrap();
+
+    let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+    InodeOps::insert(&mut ops, &mut f.state, Inode::new_file(42)).unwrap();
+    assert!(InodeOps::lookup(&mut ops, &f.state, 42).unwrap().is_some());
+
+    // Inserting copied the path, so the snapshot's root never saw inode 42
+    let view = snap.view(&f.state);
+    assert!(InodeOps::lookup(&mut ops, &view, 42).unwrap().is_none());
+    assert!(InodeOps::lookup(&mut ops, &view, ROOT_INODE).unwrap().is_some());
+}
+
+#[test]
+fn test_snapshot_keeps_replaced_blocks() {
+    let mut f = FileHarness::with_root();
+    let bs = BLOCK_SIZE as usize;
+
+    f.write(0, &vec![0xAA; 2 * bs]);
+    f.write(0, &vec![0xBB; bs]);
+    assert_eq!(f.data.freed.len(), 1);
+
+    f.snapshot("keep").unwrap();
+    f.write(bs as u64, &vec![0xCC; bs]);
+    f.truncate(0);
+    assert_eq!(f.inode.blocks, 0);
+    assert_eq!(f.data.freed.len(), 1);
+
+    // Blocks written in a later generation are the live file's alone
+    f.state.superblock.root_generation += 1;
+    f.write(0, &vec![0xDD; bs]);
+    f.truncate(0);
+    assert_eq!(f.data.freed.len(), 2);
+}
+
+// ============================================================================
+// COMPRESSION TESTS
+// ============================================================================
+
+/// Text-like data: repetitive, but not a single repeated byte
+fn sample_text(len: usize) -> Vec<u8> {
+    let line = b"The quick brown fox jumps over the lazy dog. 0123456789\n";
+    (0..len).map(|i| line[i % line.len()] ^ (i / 4096) as u8).collect()
+}
+
+/// Bytes from an LCG, which no LZ compressor can shrink
+fn sample_noise(len: usize) -> Vec<u8> {
+    let mut x = 0x1234_5678u32;
+    (0..len)
+        .map(|_| {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            (x >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_compress_round_trip() {
+    let inputs = [
+        Vec::new(),
+        b"short".to_vec(),
+        vec![0u8; 70000],
+        sample_text(65536),
+        sample_noise(5000),
+        [sample_noise(300), sample_text(3000), sample_noise(300)].concat(),
+    ];
+    for input in &inputs {
+        let packed = compress::pack(input);
+        assert_eq!(compress::unpack(&packed, input.len()).as_deref(), Some(&input[..]));
+
+        // Padding after the data is ignored
+        let mut padded = packed.clone();
+        padded.resize(packed.len() + 100, 0xEE);
+        assert_eq!(compress::unpack(&padded, input.len()).as_deref(), Some(&input[..]));
+    }
+
+    assert!(compress::pack(&sample_text(65536)).len() < 4096);
+    assert!(compress::pack(&vec![0u8; 65536]).len() < 512);
+}
+
+#[test]
+fn test_decompress_rejects_bad_input() {
+    let input = sample_text(10000);
+    let packed = compress::pack(&input);
+
+    // Wrong size, cut short, and a match reaching back before the start
+    assert!(compress::unpack(&packed, input.len() - 1).is_none());
+    assert!(compress::unpack(&packed[..packed.len() / 2], input.len()).is_none());
+    assert!(compress::decompress(&[0x04, 0xFF, 0xFF], 8).is_none());
+    assert!(compress::decompress(&[], 0).is_none());
+}
+
+#[test]
+fn test_compressed_file_round_trip() {
+    let mut f = FileHarness::new();
+    f.inode.flags |= INODE_COMPRESS;
+    let chunk = DEFAULT_CHUNK_SIZE as usize;
+    let data = sample_text(3 * chunk + 5000);
+
+    f.write(0, &data);
+    assert_eq!(f.read(0, data.len()), data);
+    // Four chunks, each well under a block
+    assert!(f.inode.blocks <= 4);
+    assert_eq!(f.data.block_count() as u64, f.inode.blocks);
+
+    // Overwriting inside a chunk recompresses that chunk
+    f.write(chunk as u64 + 100, b"patched");
+    let mut expected = data.clone();
+    expected[chunk + 100..chunk + 107].copy_from_slice(b"patched");
+    assert_eq!(f.read(0, expected.len()), expected);
+    assert!(f.inode.blocks <= 4);
+
+    // Cutting a chunk in two keeps its head and reads zeros past it later
+    f.truncate(chunk as u64 + 50);
+    f.truncate(2 * chunk as u64);
+    let data = f.read(0, 2 * chunk);
+    assert_eq!(data[..chunk + 50], expected[..chunk + 50]);
+    assert!(data[chunk + 50..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_incompressible_chunk_stored_plain() {
+    let mut f = FileHarness::new();
+    f.state.compress = true;
+    let noise = sample_noise(3 * BLOCK_SIZE as usize);
+
+    f.write(0, &noise);
+    assert_eq!(f.inode.blocks, 3);
+    assert_eq!(f.read(0, noise.len()), noise);
+
+    // The mount option compresses files without the flag
+    let text = sample_text(3 * BLOCK_SIZE as usize);
+    f.write(0, &text);
+    assert_eq!(f.inode.blocks, 1);
+    assert_eq!(f.read(0, text.len()), text);
+}
+
+// ============================================================================
+// DATA CHECKSUM TESTS
+// ============================================================================
+
+impl FileHarness {
+    /// Flip a byte in the data block holding a file offset
+    fn corrupt(&mut self, offset: u64) {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        let tree = BPlusTree::from_root(self.inode.extent_root, 0, NodeType::Extent, 0);
+        let ext = ExtentOps::lookup(&mut ops, &tree, offset).unwrap().unwrap();
+        let block = ext.offset_to_block(offset).unwrap_or(ext.disk_block);
+        let node = self.data.blocks.get_mut(&block).unwrap();
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(node as *mut TreeNode as *mut u8, BLOCK_SIZE as usize)
+        };
+        bytes[(offset % BLOCK_SIZE as u64) as usize] ^= 0xFF;
+    }
+
+    fn try_read(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, TreeError> {
+        let mut buf = vec![0; len];
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        let n = FileOps::read(&mut ops, &self.data, &self.state, &self.inode, offset, &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+}
+
+#[test]
+fn test_corrupt_data_fails_read() {
+    let mut f = FileHarness::new();
+    let bs = BLOCK_SIZE as usize;
+    f.write(0, &vec![0x5A; 4 * bs]);
+
+    f.corrupt(2 * bs as u64 + 7);
+    // Any read from the extent is checked, not only the damaged block
+    assert_eq!(f.try_read(0, 10), Err(TreeError::DataCrcError));
+
+    // Without verification the damage reads back as is
+    f.state.verify_data = false;
+    let data = f.try_read(0, 4 * bs).unwrap();
+    assert_eq!(data[2 * bs + 7], 0xA5);
+    assert_eq!(data[2 * bs + 8], 0x5A);
+}
+
+#[test]
+fn test_split_extents_keep_checksums() {
+    let mut f = FileHarness::new();
+    let bs = BLOCK_SIZE as usize;
+    f.write(0, &vec![0x11; 6 * bs]);
+
+    // Overwriting the middle leaves two pieces of the old extent
+    f.write(2 * bs as u64, &vec![0x22; bs]);
+    f.truncate(5 * bs as u64);
+    let mut expected = vec![0x11; 5 * bs];
+    expected[2 * bs..3 * bs].fill(0x22);
+    assert_eq!(f.read(0, 5 * bs), expected);
+
+    f.corrupt(4 * bs as u64);
+    assert_eq!(f.try_read(3 * bs as u64, 10), Err(TreeError::DataCrcError));
+    assert_eq!(f.try_read(0, 10).unwrap(), vec![0x11; 10]);
+}
+
+#[test]
+fn test_corrupt_compressed_data_fails_read() {
+    let mut f = FileHarness::new();
+    f.inode.flags |= INODE_COMPRESS;
+    f.write(0, &sample_text(DEFAULT_CHUNK_SIZE as usize));
+
+    f.corrupt(0);
+    assert_eq!(f.try_read(100, 10), Err(TreeError::DataCrcError));
+}
+
+// ============================================================================
+// HARD LINK TESTS
+// ============================================================================
+
+impl FileHarness {
+    fn root(&mut self) -> Inode {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        InodeOps::lookup(&mut ops, &self.state, ROOT_INODE).unwrap().unwrap()
+    }
+
+    fn link(&mut self, name: &str, target: &mut Inode) -> Result<(), TreeError> {
+        let mut root = self.root();
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        DirOps::link(&mut ops, &mut self.state, &mut root, name, target)
+    }
+
+    fn unlink(&mut self, name: &str) -> Result<Inode, TreeError> {
+        let mut root = self.root();
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        DirOps::unlink(&mut ops, &mut self.state, &mut root, name)
+    }
+
+    fn resolve(&mut self, path: &str) -> Option<Inode> {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        resolve_path(&mut ops, &self.state, path).unwrap()
+    }
+}
+
+#[test]
+fn test_hard_links_share_inode() {
+    let mut f = FileHarness::with_root();
+    let mut file = Inode::new_file(42);
+    file.nlink = 0;
+
+    f.link("a", &mut file).unwrap();
+    f.link("b", &mut file).unwrap();
+    assert_eq!(file.nlink, 2);
+
+    let a = f.resolve("/a").unwrap();
+    let b = f.resolve("/b").unwrap();
+    assert_eq!(a.inode_num, 42);
+    assert_eq!(b.inode_num, 42);
+    assert_eq!(b.nlink, 2);
+
+    // The inode outlives all but its last name
+    assert_eq!(f.unlink("a").unwrap().nlink, 1);
+    assert!(f.resolve("/a").is_none());
+    assert_eq!(f.resolve("/b").unwrap().nlink, 1);
+
+    assert_eq!(f.unlink("b").unwrap().nlink, 0);
+    let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+    assert!(InodeOps::lookup(&mut ops, &f.state, 42).unwrap().is_none());
+}
+
+#[test]
+fn test_link_errors() {
+    let mut f = FileHarness::with_root();
+    let mut file = Inode::new_file(42);
+    file.nlink = 0;
+    f.link("a", &mut file).unwrap();
+
+    assert_eq!(f.link("a", &mut file), Err(TreeError::DuplicateKey));
+    assert_eq!(f.link("", &mut file), Err(TreeError::InvalidOperation));
+
+    let mut dir = Inode::new_directory(43);
+    assert_eq!(f.link("d", &mut dir), Err(TreeError::InvalidOperation));
+    assert_eq!(f.unlink("missing").err(), Some(TreeError::KeyNotFound));
+}
+
+// ============================================================================
+// RENAME TESTS
+// ============================================================================
+
+impl FileHarness {
+    /// Put a new inode in directory `dir` as `name`
+    fn add(&mut self, dir: u64, name: &str, inode: Inode) {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        InodeOps::insert(&mut ops, &mut self.state, inode).unwrap();
+
+        let mut parent = InodeOps::lookup(&mut ops, &self.state, dir).unwrap().unwrap();
+        let mut tree = BPlusTree::new(parent.extent_root, NodeType::Directory, 0);
+        let entry_type = if inode.is_directory() { dir::EntryType::Directory } else { dir::EntryType::File };
+        DirOps::insert(&mut ops, &mut tree, DirEntry::new(name, inode.inode_num, entry_type).unwrap()).unwrap();
+        parent.extent_root = tree.root_block;
+        if inode.is_directory() {
+            parent.nlink += 1;
+        }
+        InodeOps::insert(&mut ops, &mut self.state, parent).unwrap();
+    }
+
+    fn rename(&mut self, old_dir: u64, old_name: &str, new_dir: u64, new_name: &str) -> Result<(), TreeError> {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        DirOps::rename(&mut ops, &mut self.state, old_dir, old_name, new_dir, new_name)
+    }
+
+    fn inode(&mut self, inode_num: u64) -> Option<Inode> {
+        let mut ops = TreeOps::new(&mut self.tree_dev, &mut self.tree_alloc);
+        InodeOps::lookup(&mut ops, &self.state, inode_num).unwrap()
+    }
+}
+
+#[test]
+fn test_rename_moves_between_directories() {
+    let mut f = FileHarness::with_root();
+    f.add(ROOT_INODE, "src", Inode::new_directory(10));
+    f.add(ROOT_INODE, "dst", Inode::new_directory(11));
+    f.add(10, "file", Inode::new_file(20));
+
+    f.rename(10, "file", 11, "moved").unwrap();
+    assert!(f.resolve("/src/file").is_none());
+    assert_eq!(f.resolve("/dst/moved").unwrap().inode_num, 20);
+
+    // Moving a directory moves its parent link, and its ".." if it has one
+    let mut sub = Inode::new_directory(12);
+    sub.nlink = 2;
+    f.add(10, "sub", sub);
+    {
+        let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+        let mut sub = InodeOps::lookup(&mut ops, &f.state, 12).unwrap().unwrap();
+        let mut tree = BPlusTree::new(sub.extent_root, NodeType::Directory, 0);
+        DirOps::insert(&mut ops, &mut tree, dir::dotdot_entry(10)).unwrap();
+        sub.extent_root = tree.root_block;
+        InodeOps::insert(&mut ops, &mut f.state, sub).unwrap();
+    }
+    let (src_links, dst_links) = (f.inode(10).unwrap().nlink, f.inode(11).unwrap().nlink);
+
+    f.rename(10, "sub", 11, "sub").unwrap();
+    assert_eq!(f.inode(10).unwrap().nlink, src_links - 1);
+    assert_eq!(f.inode(11).unwrap().nlink, dst_links + 1);
+    let moved = f.resolve("/dst/sub").unwrap();
+    let mut ops = TreeOps::new(&mut f.tree_dev, &mut f.tree_alloc);
+    let tree = BPlusTree::new(moved.extent_root, NodeType::Directory, 0);
+    assert_eq!(DirOps::lookup(&mut ops, &tree, "..").unwrap().unwrap().inode_num, 11);
+}
+
+#[test]
+fn test_rename_replaces_target() {
+    let mut f = FileHarness::with_root();
+    f.add(ROOT_INODE, "a", Inode::new_file(20));
+    f.add(ROOT_INODE, "b", Inode::new_file(21));
+
+    f.rename(ROOT_INODE, "a", ROOT_INODE, "b").unwrap();
+    assert!(f.resolve("/a").is_none());
+    assert_eq!(f.resolve("/b").unwrap().inode_num, 20);
+    assert!(f.inode(21).is_none());
+
+    // A replaced file with another name only loses this one
+    let mut shared = Inode::new_file(22);
+    shared.nlink = 0;
+    f.link("c", &mut shared).unwrap();
+    f.link("d", &mut shared).unwrap();
+    f.rename(ROOT_INODE, "b", ROOT_INODE, "c").unwrap();
+    assert_eq!(f.inode(22).unwrap().nlink, 1);
+
+    // Renaming onto another name for the same file changes nothing
+    let mut same = f.inode(20).unwrap();
+    f.link("e", &mut same).unwrap();
+    f.rename(ROOT_INODE, "c", ROOT_INODE, "e").unwrap();
+    assert_eq!(f.resolve("/c").unwrap().inode_num, 20);
+}
+
+#[test]
+fn test_rename_refuses_bad_replacements() {
+    let mut f = FileHarness::with_root();
+    f.add(ROOT_INODE, "file", Inode::new_file(20));
+    f.add(ROOT_INODE, "empty", Inode::new_directory(10));
+    f.add(ROOT_INODE, "full", Inode::new_directory(11));
+    f.add(11, "inside", Inode::new_file(21));
+    f.add(ROOT_INODE, "dir", Inode::new_directory(12));
+
+    assert_eq!(f.rename(ROOT_INODE, "file", ROOT_INODE, "empty"), Err(TreeError::InvalidOperation));
+    assert_eq!(f.rename(ROOT_INODE, "dir", ROOT_INODE, "file"), Err(TreeError::InvalidOperation));
+    assert_eq!(f.rename(ROOT_INODE, "dir", ROOT_INODE, "full"), Err(TreeError::InvalidOperation));
+    assert_eq!(f.rename(ROOT_INODE, "missing", ROOT_INODE, "x"), Err(TreeError::KeyNotFound));
+
+    let root_links = f.inode(ROOT_INODE).unwrap().nlink;
+    f.rename(ROOT_INODE, "dir", ROOT_INODE, "empty").unwrap();
+    assert!(f.inode(10).is_none());
+    assert_eq!(f.resolve("/empty").unwrap().inode_num, 12);
+    assert_eq!(f.inode(ROOT_INODE).unwrap().nlink, root_links - 1);
+}
+
 // ============================================================================
 // STRESS TESTS
 // ============================================================================
@@ -159,6 +159,8 @@ pub enum TreeError {
     EmptyTree,
     /// Invalid operation
     InvalidOperation,
+    /// File data doesn't match its extent checksum
+    DataCrcError,
 }
 
 // ============================================================================
@@ -646,6 +648,65 @@ impl<'a, D: BlockDevice, A: BlockAllocator> TreeOps<'a, D, A> {
         Err(TreeError::InvalidOperation)
     }
 
+    /// Get every entry of the leaf a key would live in
+    ///
+    /// For range lookups (the extent covering a file offset) that need the
+    /// neighbours of a key rather than an exact match.
+    pub fn leaf_entries<K: TreeKey, V: TreeValue>(
+        &self,
+        tree: &BPlusTree,
+        key: &K,
+    ) -> Result<Vec<(K, V)>, TreeError> {
+        let (_, path) = self.search::<K, V>(tree, key)?;
+        let block = match path.leaf_block() {
+            Some(block) => block,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut node = self.device.read_node(block)?;
+        let count = node.item_count as usize;
+        let leaf = LeafNode::<K, V>::new(&mut node);
+        Ok((0..count).filter_map(|i| leaf.get_entry(i)).collect())
+    }
+
+    /// Get every entry in the tree, in key order
+    ///
+    /// Walks the nodes by their own levels rather than `tree.height`, so it
+    /// works on trees opened from just a root block.
+    pub fn entries<K: TreeKey, V: TreeValue>(&self, tree: &BPlusTree) -> Result<Vec<(K, V)>, TreeError> {
+        let mut entries = Vec::new();
+        if !tree.is_empty() {
+            self.collect_entries(tree.root_block, &mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    fn collect_entries<K: TreeKey, V: TreeValue>(
+        &self,
+        block: u64,
+        entries: &mut Vec<(K, V)>,
+    ) -> Result<(), TreeError> {
+        let mut node = self.device.read_node(block)?;
+        if !node.is_valid() {
+            return Err(TreeError::CrcError);
+        }
+
+        let count = node.item_count as usize;
+        if node.level == 0 {
+            let leaf = LeafNode::<K, V>::new(&mut node);
+            entries.extend((0..count).filter_map(|i| leaf.get_entry(i)));
+        } else {
+            let children: Vec<u64> = {
+                let internal = InternalNode::new(&mut node);
+                (0..count).filter_map(|i| internal.get_child(i)).collect()
+            };
+            for child in children {
+                self.collect_entries(child, entries)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Insert a key-value pair into the tree
     ///
     /// Uses CoW: allocates new blocks for all modified nodes.
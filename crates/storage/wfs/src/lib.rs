@@ -8,8 +8,8 @@
 //! - **B+Tree Indexing**: O(log n) directory lookups, supports millions of files
 //! - **Extent-Based Storage**: Efficient large file storage
 //! - **Checksums**: CRC32 on all metadata and data blocks
-//! - **Snapshots**: Foundation for snapshot support
-//! - **Compression/Encryption**: Ready for future extensions
+//! - **Snapshots**: Named read-only views that share unchanged blocks
+//! - **Compression**: Optional LZ4 compression of file data, per file or per mount
 //!
 //! ## Architecture
 //!
@@ -111,7 +111,7 @@ pub mod core;
 pub mod vfs_adapter;
 
 #[cfg(feature = "vfs")]
-pub use vfs_adapter::WfsFilesystem;
+pub use vfs_adapter::{WfsFilesystem, WfsOptions, set_reporter};
 
 // Re-export commonly used types from core
 pub use core::{
@@ -125,13 +125,16 @@ pub use core::{
     FilesystemState, BlockDevice, BlockAllocator,
     InodeOps, DirOps, ExtentOps,
 
+    // Snapshots
+    Snapshot, SnapshotOps,
+
     // Constants
     WFS_MAGIC, WFS_VERSION, BLOCK_SIZE, ROOT_INODE,
     WFS_SIGNATURE, MAX_FILENAME, INLINE_DATA_MAX,
 
     // Flags
     S_IFREG, S_IFDIR, S_IFLNK, S_IFMT,
-    INODE_IMMUTABLE, INODE_APPEND, INODE_NOATIME,
+    INODE_IMMUTABLE, INODE_APPEND, INODE_NOATIME, INODE_COMPRESS,
 };
 
 /// CRC32 calculation (same algorithm as used throughout WFS)
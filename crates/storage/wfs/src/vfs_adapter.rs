@@ -23,10 +23,45 @@ use crate::core::{
     Superblock, Inode, TreeNode, TreeOps, TreeError, FilesystemState, BlockDevice, BlockAllocator,
     FilesystemOps, InodeOps, DirOps, DirEntry, ExtentOps, FileOps, resolve_path, init_filesystem,
     WFS_MAGIC, BLOCK_SIZE, ROOT_INODE, S_IFDIR, S_IFREG, S_IFLNK, S_IFMT, BPlusTree, NodeType,
+    SnapshotOps, resolve_path_from,
 };
 
 use crate::core::dir::EntryType;
-use crate::core::inode::INODE_INLINE_SIZE;
+
+/// 512-byte units per WFS block, for FileStat::blocks
+///
+/// Inodes count allocated WFS blocks, so a sparse file reports only the
+/// space it really uses, not its size.
+const BLOCKS_512: u64 = BLOCK_SIZE as u64 / 512;
+
+/// Mount options
+#[derive(Clone, Copy, Debug)]
+pub struct WfsOptions {
+    /// Compress everything written, not only files flagged INODE_COMPRESS
+    pub compress: bool,
+    /// Check file data against extent checksums on every read
+    pub verify_data: bool,
+}
+
+impl Default for WfsOptions {
+    fn default() -> Self {
+        Self { compress: false, verify_data: true }
+    }
+}
+
+/// Reporter for data errors (e.g. the kernel log)
+static mut REPORTER: Option<fn(&str)> = None;
+
+/// Set the function that receives data error messages
+pub fn set_reporter(reporter: fn(&str)) {
+    unsafe { REPORTER = Some(reporter); }
+}
+
+fn report(msg: &str) {
+    if let Some(reporter) = unsafe { REPORTER } {
+        reporter(msg);
+    }
+}
 
 /// WFS Filesystem VFS adapter
 pub struct WfsFilesystem<D: VfsBlockDevice + Send + Sync + 'static> {
@@ -41,8 +76,15 @@ struct WfsInner<D: BlockDevice + BlockAllocator> {
 impl<D: VfsBlockDevice + Send + Sync + 'static> WfsFilesystem<D> {
     /// Create a new WFS filesystem from a block device
     pub fn new(device: D) -> VfsResult<Self> {
+        Self::with_options(device, WfsOptions::default())
+    }
+
+    /// Create a new WFS filesystem from a block device with mount options
+    pub fn with_options(device: D, options: WfsOptions) -> VfsResult<Self> {
         let adapter = WfsBlockDeviceAdapter::new(device);
-        let inner = WfsInner::new(adapter)?;
+        let mut inner = WfsInner::new(adapter)?;
+        inner.state.compress = options.compress;
+        inner.state.verify_data = options.verify_data;
 
         Ok(Self {
             inner: Arc::new(Mutex::new(inner)),
@@ -52,8 +94,10 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> WfsFilesystem<D> {
 
 impl<D: BlockDevice + BlockAllocator + FilesystemOps> WfsInner<D> {
     fn new(device: D) -> VfsResult<Self> {
-        // Read superblock from block 0
+        // Read superblock from block 0, falling back to the backup in block 1
+        // if the primary is unreadable or fails its CRC (e.g. torn write)
         let superblock = device.read_superblock(0)
+            .or_else(|_| device.read_superblock(1))
             .map_err(|_| VfsError::IoError)?;
 
         // Verify magic
@@ -78,6 +122,49 @@ impl<D: BlockDevice + BlockAllocator + FilesystemOps> WfsInner<D> {
             .ok_or(VfsError::NotFound)
     }
 
+    /// Create an empty regular file
+    fn create_file(&mut self, path: &str) -> VfsResult<Inode> {
+        let dev_ptr = &mut self.device as *mut D;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        let (parent_path, name) = split_path(path)?;
+        let mut parent = resolve_path(&mut ops, &self.state, parent_path)
+            .map_err(tree_error_to_vfs)?
+            .ok_or(VfsError::NotFound)?;
+        if !parent.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+
+        // Linking it in gives the inode its one name
+        let mut inode = Inode::new(InodeOps::allocate_inode_num(&mut self.state), S_IFREG | 0o644);
+        inode.nlink = 0;
+        inode.set_inline_data(&[]);
+
+        let saved = self.state.clone();
+        match DirOps::link(&mut ops, &mut self.state, &mut parent, name, &mut inode) {
+            Ok(()) => Ok(inode),
+            Err(e) => {
+                self.state = saved;
+                Err(match e {
+                    TreeError::InvalidOperation => VfsError::InvalidName,
+                    e => tree_error_to_vfs(e),
+                })
+            }
+        }
+    }
+
+    /// Resolve a path in a snapshot view, from the inode it was taken of
+    fn resolve_snapshot_inode(&mut self, view: &FilesystemState, root: u64, path: &str) -> VfsResult<Inode> {
+        let dev_ptr = &mut self.device as *mut D;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        resolve_path_from(&mut ops, view, root, path)
+            .map_err(tree_error_to_vfs)?
+            .ok_or(VfsError::NotFound)
+    }
+
     fn inode_to_stat(&self, inode: &Inode) -> FileStat {
         let file_type = match inode.mode & S_IFMT {
             S_IFDIR => FileType::Directory,
@@ -96,7 +183,7 @@ impl<D: BlockDevice + BlockAllocator + FilesystemOps> WfsInner<D> {
             uid: inode.uid,
             gid: inode.gid,
             blksize: BLOCK_SIZE,
-            blocks: inode.blocks,
+            blocks: inode.blocks * BLOCKS_512,
             atime: inode.atime,
             mtime: inode.mtime,
             ctime: inode.ctime,
@@ -111,19 +198,30 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsFilesystem<D>
 
     fn open(&self, path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
         let mut inner = self.inner.lock();
-        let inode = inner.resolve_inode(path)?;
+        let inode = match inner.resolve_inode(path) {
+            Ok(_) if mode.create && mode.exclusive => return Err(VfsError::AlreadyExists),
+            Ok(inode) => inode,
+            // Lookup and creation happen under one lock, so O_EXCL is atomic
+            Err(VfsError::NotFound) if mode.create => inner.create_file(path)?,
+            Err(e) => return Err(e),
+        };
+        drop(inner);
 
         // Check if it's a regular file
         if (inode.mode & S_IFMT) != S_IFREG {
             return Err(VfsError::IsADirectory);
         }
 
-        Ok(Box::new(WfsFile {
+        let mut file = WfsFile {
             fs: self.inner.clone(),
             inode,
             position: 0,
             mode,
-        }))
+        };
+        if mode.write && mode.truncate && inode.size != 0 {
+            file.truncate(0)?;
+        }
+        Ok(Box::new(file))
     }
 
     fn stat(&self, path: &str) -> VfsResult<FileStat> {
@@ -213,31 +311,16 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsFilesystem<D>
             .map_err(tree_error_to_vfs)?
             .ok_or(VfsError::NotFound)?;
 
-        // Check it's a regular file
-        if (file_inode.mode & S_IFMT) != S_IFREG {
+        // Directories go through rmdir
+        if file_inode.is_directory() {
             return Err(VfsError::IsADirectory);
         }
 
-        // Remove from parent directory tree
-        let mut parent_dir_tree = BPlusTree::new(
-            parent_inode.extent_root,
-            NodeType::Directory,
-            inner.state.superblock.root_generation,
-        );
-
-        DirOps::delete(&mut ops, &mut parent_dir_tree, name)
-            .map_err(tree_error_to_vfs)?;
-
-        // Update parent inode
-        parent_inode.extent_root = parent_dir_tree.root_block;
-        InodeOps::insert(&mut ops, &mut inner.state, parent_inode)
-            .map_err(tree_error_to_vfs)?;
-
-        // Delete file inode
-        InodeOps::delete(&mut ops, &mut inner.state, file_inode.inode_num)
+        // Remove the name; the inode goes with its last link
+        DirOps::unlink(&mut ops, &mut inner.state, &mut parent_inode, name)
             .map_err(tree_error_to_vfs)?;
 
-        // TODO: Free data blocks (extent tree cleanup)
+        // TODO: Free data blocks (extent tree cleanup) once nlink is 0
 
         Ok(())
     }
@@ -315,49 +398,90 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsFilesystem<D>
         // Parse paths
         let (old_parent_path, old_name) = split_path(old_path)?;
         let (new_parent_path, new_name) = split_path(new_path)?;
+        if old_name.is_empty() || new_name.is_empty() {
+            return Err(VfsError::InvalidArgument);
+        }
 
         // Resolve file and parents
-        let file_inode = inner.resolve_inode(old_path)?;
-        let mut old_parent = resolve_path(&mut ops, &inner.state, old_parent_path)
+        let source = inner.resolve_inode(old_path)?;
+        let old_parent = resolve_path(&mut ops, &inner.state, old_parent_path)
             .map_err(tree_error_to_vfs)?
             .ok_or(VfsError::NotFound)?;
-        let mut new_parent = resolve_path(&mut ops, &inner.state, new_parent_path)
+        let new_parent = resolve_path(&mut ops, &inner.state, new_parent_path)
             .map_err(tree_error_to_vfs)?
             .ok_or(VfsError::NotFound)?;
+        if !new_parent.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
 
-        // Remove from old parent
-        let mut old_parent_tree = BPlusTree::new(
-            old_parent.extent_root,
-            NodeType::Directory,
-            inner.state.superblock.root_generation,
-        );
+        // A directory can't move below itself
+        if source.is_directory() && is_below(new_path, old_path) {
+            return Err(VfsError::InvalidArgument);
+        }
 
-        let old_entry = DirOps::delete(&mut ops, &mut old_parent_tree, old_name)
-            .map_err(tree_error_to_vfs)?;
+        // Check the name being replaced, as rename(2) does
+        if let Ok(Some(existing)) = resolve_path(&mut ops, &inner.state, new_path) {
+            if existing.inode_num == source.inode_num {
+                return Ok(());
+            }
+            match (source.is_directory(), existing.is_directory()) {
+                (true, false) => return Err(VfsError::NotADirectory),
+                (false, true) => return Err(VfsError::IsADirectory),
+                (true, true) => {
+                    if !DirOps::is_empty(&mut ops, &inner.state, &existing).map_err(tree_error_to_vfs)? {
+                        return Err(VfsError::DirectoryNotEmpty);
+                    }
+                }
+                (false, false) => {}
+            }
+        }
 
-        old_parent.extent_root = old_parent_tree.root_block;
-        InodeOps::insert(&mut ops, &mut inner.state, old_parent)
-            .map_err(tree_error_to_vfs)?;
+        // The old trees are untouched until the new roots are in the state,
+        // so putting the old state back undoes a partial move
+        let saved = inner.state.clone();
+        let result = DirOps::rename(
+            &mut ops,
+            &mut inner.state,
+            old_parent.inode_num,
+            old_name,
+            new_parent.inode_num,
+            new_name,
+        );
+        if result.is_err() {
+            inner.state = saved;
+        }
+        result.map_err(tree_error_to_vfs)
+    }
 
-        // Create new entry with new name
-        let new_entry = DirEntry::new(new_name, file_inode.inode_num, old_entry.entry_type())
-            .ok_or(VfsError::InvalidArgument)?;
+    fn link(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
+        let mut inner = self.inner.lock();
 
-        // Add to new parent
-        let mut new_parent_tree = BPlusTree::new(
-            new_parent.extent_root,
-            NodeType::Directory,
-            inner.state.superblock.root_generation,
-        );
+        // Get mutable references for TreeOps
+        let dev_ptr = &mut inner.device as *mut _;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
 
-        DirOps::insert(&mut ops, &mut new_parent_tree, new_entry)
-            .map_err(tree_error_to_vfs)?;
+        let (new_parent_path, new_name) = split_path(new_path)?;
 
-        new_parent.extent_root = new_parent_tree.root_block;
-        InodeOps::insert(&mut ops, &mut inner.state, new_parent)
-            .map_err(tree_error_to_vfs)?;
+        // Resolve target and new parent
+        let mut target = inner.resolve_inode(old_path)?;
+        let mut new_parent = resolve_path(&mut ops, &inner.state, new_parent_path)
+            .map_err(tree_error_to_vfs)?
+            .ok_or(VfsError::NotFound)?;
 
-        Ok(())
+        if target.is_directory() {
+            return Err(VfsError::IsADirectory);
+        }
+        if !new_parent.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+
+        match DirOps::link(&mut ops, &mut inner.state, &mut new_parent, new_name, &mut target) {
+            Ok(()) => Ok(()),
+            Err(TreeError::DuplicateKey) => Err(VfsError::AlreadyExists),
+            Err(TreeError::InvalidOperation) => Err(VfsError::InvalidName),
+            Err(e) => Err(tree_error_to_vfs(e)),
+        }
     }
 
     fn sync(&self) -> VfsResult<()> {
@@ -368,16 +492,54 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsFilesystem<D>
 
     fn statfs(&self) -> VfsResult<FsStats> {
         let inner = self.inner.lock();
-        let sb = &inner.state.superblock;
-
-        Ok(FsStats {
-            block_size: BLOCK_SIZE,
-            total_blocks: sb.total_blocks,
-            free_blocks: sb.free_blocks,
-            total_inodes: sb.inode_count,
-            free_inodes: u64::MAX, // Unlimited in B+tree
-            max_name_len: 255,     // Standard max filename length
-        })
+        Ok(fs_stats(&inner.state.superblock))
+    }
+
+    fn snapshot(&self, path: &str, name: &str) -> VfsResult<()> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        let dev_ptr = &mut inner.device as *mut _;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        match SnapshotOps::create(&mut ops, &mut inner.state, path, name) {
+            Ok(_) => Ok(()),
+            Err(TreeError::DuplicateKey) => Err(VfsError::AlreadyExists),
+            Err(TreeError::InvalidOperation) => Err(VfsError::InvalidArgument),
+            Err(e) => Err(tree_error_to_vfs(e)),
+        }
+    }
+
+    fn snapshots(&self) -> VfsResult<Vec<String>> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        let dev_ptr = &mut inner.device as *mut _;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        let snapshots = SnapshotOps::list(&mut ops, &inner.state).map_err(tree_error_to_vfs)?;
+        Ok(snapshots.iter().map(|s| s.name_str().to_string()).collect())
+    }
+
+    fn open_snapshot(&self, name: &str) -> VfsResult<Box<dyn Filesystem>> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        let dev_ptr = &mut inner.device as *mut _;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        let snapshot = SnapshotOps::lookup(&mut ops, &inner.state, name)
+            .map_err(tree_error_to_vfs)?
+            .ok_or(VfsError::NotFound)?;
+
+        Ok(Box::new(WfsSnapshot {
+            fs: self.inner.clone(),
+            view: snapshot.view(&inner.state),
+            root: snapshot.inode_num,
+        }))
     }
 
     fn chmod(&self, path: &str, mode: u32) -> VfsResult<()> {
@@ -419,6 +581,116 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsFilesystem<D>
 
         Ok(())
     }
+
+    fn set_times(&self, path: &str, atime: u64, mtime: u64) -> VfsResult<()> {
+        let mut inner = self.inner.lock();
+
+        // Get mutable references for TreeOps
+        let dev_ptr = &mut inner.device as *mut _;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        let mut inode = inner.resolve_inode(path)?;
+
+        inode.atime = atime;
+        inode.mtime = mtime;
+
+        InodeOps::insert(&mut ops, &mut inner.state, inode)
+            .map_err(tree_error_to_vfs)?;
+
+        Ok(())
+    }
+}
+
+/// A snapshot mounted read-only
+///
+/// Shares the device with the live filesystem but resolves paths in the
+/// inode tree the snapshot recorded, starting from the inode it was taken
+/// of. Nothing can be changed through it.
+struct WfsSnapshot<D: VfsBlockDevice + Send + Sync + 'static> {
+    fs: Arc<Mutex<WfsInner<WfsBlockDeviceAdapter<D>>>>,
+    view: FilesystemState,
+    root: u64,
+}
+
+impl<D: VfsBlockDevice + Send + Sync + 'static> Filesystem for WfsSnapshot<D> {
+    fn name(&self) -> &'static str {
+        "wfs"
+    }
+
+    fn open(&self, path: &str, mode: FileMode) -> VfsResult<Box<dyn FileOperations>> {
+        if mode.write || mode.append || mode.create || mode.truncate {
+            return Err(VfsError::ReadOnly);
+        }
+
+        let inode = self.fs.lock().resolve_snapshot_inode(&self.view, self.root, path)?;
+        if (inode.mode & S_IFMT) != S_IFREG {
+            return Err(VfsError::IsADirectory);
+        }
+
+        Ok(Box::new(WfsFile {
+            fs: self.fs.clone(),
+            inode,
+            position: 0,
+            mode,
+        }))
+    }
+
+    fn stat(&self, path: &str) -> VfsResult<FileStat> {
+        let mut inner = self.fs.lock();
+        let inode = inner.resolve_snapshot_inode(&self.view, self.root, path)?;
+        Ok(inner.inode_to_stat(&inode))
+    }
+
+    fn mkdir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn unlink(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn rmdir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn readdir(&self, path: &str) -> VfsResult<Vec<VfsDirEntry>> {
+        let inode = self.fs.lock().resolve_snapshot_inode(&self.view, self.root, path)?;
+        if (inode.mode & S_IFMT) != S_IFDIR {
+            return Err(VfsError::NotADirectory);
+        }
+
+        // Same as the live filesystem until B+tree iteration lands there
+        Ok(Vec::new())
+    }
+
+    fn rename(&self, _old_path: &str, _new_path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn link(&self, _old_path: &str, _new_path: &str) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<FsStats> {
+        Ok(fs_stats(&self.view.superblock))
+    }
+
+    fn chmod(&self, _path: &str, _mode: u32) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn chown(&self, _path: &str, _uid: u32, _gid: u32) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn set_times(&self, _path: &str, _atime: u64, _mtime: u64) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
 }
 
 /// WFS file handle
@@ -435,27 +707,26 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> FileOperations for WfsFile<D> {
             return Err(VfsError::PermissionDenied);
         }
 
-        // For now, only support inline data
-        if self.inode.inline_size > 0 {
-            let inline_len = self.inode.inline_size as u64;
-            if self.position >= inline_len {
-                return Ok(0); // EOF
-            }
-
-            let available = (inline_len - self.position) as usize;
-            let to_read = buffer.len().min(available);
+        let mut fs = self.fs.lock();
+        let dev_ptr = &mut fs.device as *mut WfsBlockDeviceAdapter<D>;
+        // FileOps::read only reads through these references
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
 
-            buffer[..to_read].copy_from_slice(
-                &self.inode.inline_data[self.position as usize..self.position as usize + to_read]
-            );
+        // Holes in sparse files come back as zeros
+        let read = FileOps::read(&mut ops, unsafe { &*dev_ptr }, &fs.state, &self.inode, self.position, buffer)
+            .map_err(|e| {
+                if e == TreeError::DataCrcError {
+                    report(&alloc::format!(
+                        "[WFS] Data checksum mismatch: inode {} near offset {}\r\n",
+                        self.inode.inode_num, self.position
+                    ));
+                }
+                tree_error_to_vfs(e)
+            })?;
 
-            self.position += to_read as u64;
-            Ok(to_read)
-        } else {
-            // TODO: Implement extent-based file read
-            // This requires reading from extent tree and data blocks
-            Err(VfsError::NotSupported)
-        }
+        self.position += read as u64;
+        Ok(read)
     }
 
     fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
@@ -463,32 +734,28 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> FileOperations for WfsFile<D> {
             return Err(VfsError::PermissionDenied);
         }
 
-        // For now, only support inline data
-        let max_inline = INODE_INLINE_SIZE as u64;
-        if self.position + buffer.len() as u64 <= max_inline {
-            // Write to inline data
-            let end_pos = self.position as usize + buffer.len();
-
-            // Update inode inline data
-            let mut fs = self.fs.lock();
-            self.inode.inline_data[self.position as usize..end_pos].copy_from_slice(buffer);
-            self.inode.inline_size = end_pos as u16;
-            self.inode.size = end_pos as u64;
-
-            // Update inode on disk
-            let dev_ptr = &mut fs.device as *mut _;
-            let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
-            let mut ops = TreeOps::new(dev_ref, alloc_ref);
-
-            InodeOps::insert(&mut ops, &mut fs.state, self.inode)
-                .map_err(tree_error_to_vfs)?;
-
-            self.position += buffer.len() as u64;
-            Ok(buffer.len())
-        } else {
-            // TODO: Implement extent-based file write
-            Err(VfsError::NotSupported)
+        let mut fs = self.fs.lock();
+        let fs = &mut *fs;
+        let dev_ptr = &mut fs.device as *mut WfsBlockDeviceAdapter<D>;
+        let (dev_ref, alloc_ref, data_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        // Appends go to the end as it is now, whoever else wrote there
+        if self.mode.append {
+            if let Some(current) = InodeOps::lookup(&mut ops, &fs.state, self.inode.inode_num)
+                .map_err(tree_error_to_vfs)?
+            {
+                self.inode = current;
+            }
+            self.position = self.inode.size;
         }
+
+        // Writing past EOF leaves a hole rather than allocating zero blocks
+        FileOps::write(&mut ops, data_ref, &mut fs.state, &mut self.inode, self.position, buffer)
+            .map_err(tree_error_to_vfs)?;
+
+        self.position += buffer.len() as u64;
+        Ok(buffer.len())
     }
 
     fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
@@ -529,7 +796,7 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> FileOperations for WfsFile<D> {
             uid: self.inode.uid,
             gid: self.inode.gid,
             blksize: BLOCK_SIZE,
-            blocks: self.inode.blocks,
+            blocks: self.inode.blocks * BLOCKS_512,
             atime: self.inode.atime,
             mtime: self.inode.mtime,
             ctime: self.inode.ctime,
@@ -537,9 +804,18 @@ impl<D: VfsBlockDevice + Send + Sync + 'static> FileOperations for WfsFile<D> {
     }
 
     fn truncate(&mut self, size: u64) -> VfsResult<()> {
-        // TODO: Implement truncate
-        self.inode.size = size;
-        Ok(())
+        if !self.mode.write {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let mut fs = self.fs.lock();
+        let fs = &mut *fs;
+        let dev_ptr = &mut fs.device as *mut WfsBlockDeviceAdapter<D>;
+        let (dev_ref, alloc_ref, data_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+        FileOps::truncate(&mut ops, data_ref, &mut fs.state, &mut self.inode, size)
+            .map_err(tree_error_to_vfs)
     }
 }
 
@@ -632,6 +908,18 @@ impl<D: VfsBlockDevice> FilesystemOps for WfsBlockDeviceAdapter<D> {
     }
 }
 
+/// statfs figures from a superblock
+fn fs_stats(sb: &Superblock) -> FsStats {
+    FsStats {
+        block_size: BLOCK_SIZE,
+        total_blocks: sb.total_blocks,
+        free_blocks: sb.free_blocks,
+        total_inodes: sb.inode_count,
+        free_inodes: u64::MAX, // Unlimited in B+tree
+        max_name_len: 255,     // Standard max filename length
+    }
+}
+
 /// Split path into (parent, name)
 fn split_path(path: &str) -> VfsResult<(&str, &str)> {
     let path = path.trim_end_matches('/');
@@ -645,6 +933,13 @@ fn split_path(path: &str) -> VfsResult<(&str, &str)> {
     }
 }
 
+/// `path` is strictly below `dir`, comparing components
+fn is_below(path: &str, dir: &str) -> bool {
+    let mut path = path.split('/').filter(|c| !c.is_empty());
+    let dir = dir.split('/').filter(|c| !c.is_empty());
+    dir.into_iter().all(|c| path.next() == Some(c)) && path.next().is_some()
+}
+
 /// Convert TreeError to VfsError
 fn tree_error_to_vfs(err: TreeError) -> VfsError {
     match err {
@@ -653,6 +948,7 @@ fn tree_error_to_vfs(err: TreeError) -> VfsError {
         TreeError::CrcError => VfsError::Corrupted,
         TreeError::InvalidNode => VfsError::Corrupted,
         TreeError::NodeFull => VfsError::NoSpace,
+        TreeError::DataCrcError => VfsError::IoError,
         _ => VfsError::IoError,
     }
 }
@@ -0,0 +1,328 @@
+//! WFS image tests on an in-memory block device
+//!
+//! Images are formatted and populated through the core tree operations (the
+//! same path mkfs.wfs uses), then mounted through the VFS adapter. Faults are
+//! injected with `MemBlockDevice` to check that a failed or torn write never
+//! leaves an unmountable image.
+//!
+//! Run with: cargo test --package wfs-common --features std,vfs
+
+use watos_driver_traits::block::BlockDevice as SectorDevice;
+use watos_driver_traits::mem::{IoOp, MemBlockDevice};
+use watos_vfs::{FileMode, Filesystem, VfsError};
+
+use wfs_common::core::dir::EntryType;
+use wfs_common::core::transaction::TransactionError;
+use wfs_common::core::{init_filesystem, resolve_path, FileOps, FilesystemOps, TreeError};
+use wfs_common::{
+    BPlusTree, BlockAllocator, BlockDevice, DirEntry, DirOps, ExtentOps, FilesystemState,
+    Inode, InodeOps, NodeType, SnapshotOps, TreeNode, TreeOps, WfsFilesystem, BLOCK_SIZE, INODE_COMPRESS,
+    ROOT_INODE, S_IFREG, WfsOptions,
+};
+
+const TOTAL_BLOCKS: u64 = 64;
+const SECTORS_PER_BLOCK: u64 = BLOCK_SIZE as u64 / 512;
+
+/// Formatting side of the device: core block I/O plus a bump allocator
+struct Mkfs {
+    dev: MemBlockDevice,
+    next_block: u64,
+}
+
+impl BlockDevice for Mkfs {
+    fn read_node(&self, block: u64) -> Result<TreeNode, TreeError> {
+        let mut node = TreeNode::default();
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(&mut node as *mut TreeNode as *mut u8, BLOCK_SIZE as usize)
+        };
+        // Clones share the media, so reading through one needs no &mut self
+        self.dev
+            .clone()
+            .read_sectors(block * SECTORS_PER_BLOCK, bytes)
+            .map_err(|_| TreeError::IoError)?;
+        Ok(node)
+    }
+
+    fn write_node(&mut self, block: u64, node: &TreeNode) -> Result<(), TreeError> {
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let bytes = unsafe {
+            std::slice::from_raw_parts(node as *const TreeNode as *const u8, BLOCK_SIZE as usize)
+        };
+        self.dev
+            .write_sectors(block * SECTORS_PER_BLOCK, bytes)
+            .map_err(|_| TreeError::IoError)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), TreeError> {
+        self.dev.flush().map_err(|_| TreeError::IoError)
+    }
+}
+
+impl BlockAllocator for Mkfs {
+    fn allocate_block(&mut self) -> Result<u64, TreeError> {
+        if self.next_block >= TOTAL_BLOCKS {
+            return Err(TreeError::NodeFull);
+        }
+        let block = self.next_block;
+        self.next_block += 1;
+        Ok(block)
+    }
+
+    fn free_block(&mut self, _block: u64) -> Result<(), TreeError> {
+        Ok(())
+    }
+}
+
+impl FilesystemOps for Mkfs {
+    fn allocate_blocks(&mut self, _state: &mut FilesystemState, _count: u64) -> Result<u64, TransactionError> {
+        Err(TransactionError::IoError)
+    }
+
+    fn free_blocks(&mut self, _state: &mut FilesystemState, _start: u64, _count: u64) -> Result<(), TransactionError> {
+        Ok(())
+    }
+}
+
+/// Add a small (inline) file to the root directory
+fn create_file(mkfs: &mut Mkfs, state: &mut FilesystemState, name: &str, data: &[u8]) -> Result<(), TreeError> {
+    let dev_ptr = mkfs as *mut Mkfs;
+    let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+    let mut ops = TreeOps::new(dev_ref, alloc_ref);
+
+    let mut root = InodeOps::lookup(&mut ops, state, ROOT_INODE)?.ok_or(TreeError::KeyNotFound)?;
+
+    let inode_num = InodeOps::allocate_inode_num(state);
+    let mut inode = Inode::new(inode_num, S_IFREG | 0o644);
+    inode.size = data.len() as u64;
+    inode.inline_size = data.len() as u16;
+    inode.inline_data[..data.len()].copy_from_slice(data);
+    inode.nlink = 1;
+    InodeOps::insert(&mut ops, state, inode)?;
+
+    let entry = DirEntry::new(name, inode_num, EntryType::File).ok_or(TreeError::InvalidNode)?;
+    let mut dir_tree = BPlusTree::new(root.extent_root, NodeType::Directory, state.superblock.root_generation);
+    DirOps::insert(&mut ops, &mut dir_tree, entry)?;
+
+    root.extent_root = dir_tree.root_block;
+    InodeOps::insert(&mut ops, state, root)
+}
+
+/// Format a fresh image and commit `hello.txt` in the first transaction
+fn format() -> (Mkfs, FilesystemState) {
+    let mut mkfs = Mkfs {
+        dev: MemBlockDevice::new(512, TOTAL_BLOCKS * SECTORS_PER_BLOCK),
+        next_block: 0,
+    };
+    let mut state = init_filesystem(&mut mkfs, TOTAL_BLOCKS).unwrap();
+    mkfs.next_block = state.superblock.data_start_block;
+
+    mkfs.begin_transaction(&mut state).unwrap();
+    create_file(&mut mkfs, &mut state, "hello.txt", b"hello, wfs").unwrap();
+    mkfs.commit_transaction(&mut state).unwrap();
+    (mkfs, state)
+}
+
+fn mount(dev: &MemBlockDevice) -> WfsFilesystem<MemBlockDevice> {
+    match WfsFilesystem::new(dev.clone()) {
+        Ok(fs) => fs,
+        Err(e) => panic!("mount failed: {:?}", e),
+    }
+}
+
+fn read_all(fs: &dyn Filesystem, path: &str) -> Result<Vec<u8>, VfsError> {
+    let mut file = fs.open(path, FileMode::READ)?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[test]
+fn test_format_write_and_mount() {
+    let (mkfs, _) = format();
+    mkfs.dev.clear_trace();
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, wfs");
+    assert_eq!(fs.stat("/hello.txt").unwrap().size, 10);
+    assert_eq!(fs.stat("/missing").err(), Some(VfsError::NotFound));
+
+    // Mounting and reading never write to the media
+    assert!(mkfs.dev.trace().iter().all(|op| matches!(op, IoOp::Read { .. })));
+}
+
+#[test]
+fn test_commit_writes_primary_before_backup() {
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+    create_file(&mut mkfs, &mut state, "second.txt", b"two").unwrap();
+
+    mkfs.dev.clear_trace();
+    mkfs.commit_transaction(&mut state).unwrap();
+    let block = |b: u64| IoOp::Write { start: b * SECTORS_PER_BLOCK, count: SECTORS_PER_BLOCK };
+    assert_eq!(mkfs.dev.trace(), [block(0), IoOp::Flush, block(1)]);
+}
+
+#[test]
+fn test_failed_commit_keeps_previous_generation() {
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+    create_file(&mut mkfs, &mut state, "second.txt", b"two").unwrap();
+
+    // The primary superblock write is the commit point
+    mkfs.dev.fail_write(1);
+    assert!(mkfs.commit_transaction(&mut state).is_err());
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, wfs");
+    assert_eq!(fs.stat("/second.txt").err(), Some(VfsError::NotFound));
+}
+
+#[test]
+fn test_torn_node_write_is_harmless() {
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+
+    // CoW writes go to fresh blocks, so tearing one cannot damage the
+    // committed tree
+    mkfs.dev.tear_write(1, 3);
+    assert_eq!(
+        create_file(&mut mkfs, &mut state, "second.txt", b"two"),
+        Err(TreeError::IoError)
+    );
+    mkfs.abort_transaction(&mut state).unwrap();
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, wfs");
+}
+
+#[test]
+fn test_corrupt_primary_superblock_uses_backup() {
+    let (mkfs, _) = format();
+    mkfs.dev.poke(0, &[0xFF; 512]);
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, wfs");
+    drop(fs);
+
+    // With both copies gone there is nothing left to mount
+    mkfs.dev.poke(BLOCK_SIZE as usize, &[0xFF; 512]);
+    assert!(WfsFilesystem::new(mkfs.dev.clone()).is_err());
+}
+
+#[test]
+fn test_corrupt_tree_node_is_detected() {
+    let (mkfs, state) = format();
+    let root = state.superblock.root_tree_block as usize * BLOCK_SIZE as usize;
+    let byte = mkfs.dev.peek(root + 200, 1)[0];
+    mkfs.dev.poke(root + 200, &[!byte]);
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(fs.stat("/hello.txt").err(), Some(VfsError::Corrupted));
+}
+
+#[test]
+fn test_snapshot_mounts_read_only() {
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+    {
+        let dev_ptr = &mut mkfs as *mut Mkfs;
+        let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+        SnapshotOps::create(&mut ops, &mut state, "/", "before").unwrap();
+    }
+    create_file(&mut mkfs, &mut state, "second.txt", b"two").unwrap();
+    mkfs.commit_transaction(&mut state).unwrap();
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(fs.snapshots().unwrap(), ["before"]);
+    assert_eq!(fs.snapshot("/", "before").err(), Some(VfsError::AlreadyExists));
+    assert_eq!(read_all(&fs, "/second.txt").unwrap(), b"two");
+
+    let snap = fs.open_snapshot("before").unwrap();
+    assert_eq!(read_all(&*snap, "/hello.txt").unwrap(), b"hello, wfs");
+    assert_eq!(snap.stat("/second.txt").err(), Some(VfsError::NotFound));
+    assert_eq!(snap.open("/hello.txt", FileMode::WRITE).err(), Some(VfsError::ReadOnly));
+    assert_eq!(snap.unlink("/hello.txt").err(), Some(VfsError::ReadOnly));
+
+    assert!(fs.open_snapshot("missing").is_err());
+}
+
+#[test]
+fn test_compressed_file_reads_through_vfs() {
+    // More than the whole image holds uncompressed
+    let text: Vec<u8> = b"WATOS compresses this line. ".iter().cycle().take(300_000).copied().collect();
+
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+    create_file(&mut mkfs, &mut state, "big.txt", b"").unwrap();
+    {
+        let dev_ptr = &mut mkfs as *mut Mkfs;
+        let (dev_ref, alloc_ref, data_dev) = unsafe { (&mut *dev_ptr, &mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+        let mut inode = resolve_path(&mut ops, &state, "/big.txt").unwrap().unwrap();
+        inode.flags |= INODE_COMPRESS;
+        FileOps::write(&mut ops, data_dev, &mut state, &mut inode, 0, &text).unwrap();
+        assert!(inode.blocks < 10);
+    }
+    mkfs.commit_transaction(&mut state).unwrap();
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(fs.stat("/big.txt").unwrap().size, text.len() as u64);
+    assert_eq!(read_all(&fs, "/big.txt").unwrap(), text);
+}
+
+#[test]
+fn test_corrupt_file_data_fails_read() {
+    let text = vec![0x42u8; 3 * BLOCK_SIZE as usize];
+
+    let (mut mkfs, mut state) = format();
+    mkfs.begin_transaction(&mut state).unwrap();
+    create_file(&mut mkfs, &mut state, "data.bin", b"").unwrap();
+    let block = {
+        let dev_ptr = &mut mkfs as *mut Mkfs;
+        let (dev_ref, alloc_ref, data_dev) = unsafe { (&mut *dev_ptr, &mut *dev_ptr, &mut *dev_ptr) };
+        let mut ops = TreeOps::new(dev_ref, alloc_ref);
+        let mut inode = resolve_path(&mut ops, &state, "/data.bin").unwrap().unwrap();
+        FileOps::write(&mut ops, data_dev, &mut state, &mut inode, 0, &text).unwrap();
+        let tree = BPlusTree::from_root(inode.extent_root, 0, NodeType::Extent, 0);
+        ExtentOps::lookup(&mut ops, &tree, BLOCK_SIZE as u64).unwrap().unwrap().disk_block
+    };
+    mkfs.commit_transaction(&mut state).unwrap();
+
+    mkfs.dev.poke(block as usize * BLOCK_SIZE as usize + 10, &[0x00]);
+
+    let fs = mount(&mkfs.dev);
+    assert_eq!(read_all(&fs, "/data.bin").err(), Some(VfsError::IoError));
+    drop(fs);
+
+    // Turning verification off reads the damaged data as is
+    let options = WfsOptions { verify_data: false, ..WfsOptions::default() };
+    let fs = WfsFilesystem::with_options(mkfs.dev.clone(), options).unwrap();
+    let data = read_all(&fs, "/data.bin").unwrap();
+    assert_eq!(data.len(), text.len());
+    assert_eq!(data[10], 0x00);
+}
+
+#[test]
+fn test_open_flags() {
+    let (mkfs, _) = format();
+    let fs = mount(&mkfs.dev);
+
+    // O_CREAT | O_EXCL only succeeds for a new name
+    assert_eq!(fs.open("/hello.txt", FileMode::CREATE_NEW).err(), Some(VfsError::AlreadyExists));
+    // Without O_CREAT a missing file stays missing
+    assert_eq!(fs.open("/new.txt", FileMode::READ_WRITE).err(), Some(VfsError::NotFound));
+
+    // The adapter can't allocate blocks yet, so creating fails, cleanly
+    assert!(fs.open("/new.txt", FileMode::CREATE_NEW).is_err());
+    assert_eq!(fs.stat("/new.txt").err(), Some(VfsError::NotFound));
+    assert_eq!(read_all(&fs, "/hello.txt").unwrap(), b"hello, wfs");
+}
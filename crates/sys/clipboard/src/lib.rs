@@ -0,0 +1,161 @@
+//! WATOS Clipboard
+//!
+//! The system-wide clipboard behind `SYS_CLIPBOARD_SET` and
+//! `SYS_CLIPBOARD_GET`. Its contents carry a MIME-style type tag:
+//!
+//! ```text
+//! text/plain                plain UTF-8 text (what the terminal and edit use)
+//! text/plain;charset=utf-8  parameters are kept but ignored when matching
+//! image/bmp                 any other "type/subtype"
+//! ```
+//!
+//! A reader names the type it understands and gets nothing if the clipboard
+//! holds something else. `text/*` accepts any text type and `*/*` (or an
+//! empty type) accepts anything. Every set bumps a serial number so a
+//! program can tell whether the clipboard changed since it last looked.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Largest clipboard contents in bytes
+pub const MAX_CLIPBOARD_SIZE: usize = 64 * 1024;
+
+/// Longest type tag
+pub const MAX_TYPE_LEN: usize = 64;
+
+/// Plain text, the type every program should offer and accept
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// Why a set was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardError {
+    /// More than MAX_CLIPBOARD_SIZE bytes
+    TooLarge,
+    /// Not a "type/subtype" tag
+    BadType,
+}
+
+/// `type/subtype` with the parameters removed
+fn essence(kind: &str) -> &str {
+    kind.split(';').next().unwrap_or("").trim()
+}
+
+/// A valid tag: printable ASCII, no spaces, one '/' with text on both sides
+fn valid_type(kind: &str) -> bool {
+    let bytes = kind.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_TYPE_LEN || !bytes.iter().all(|b| b.is_ascii_graphic()) {
+        return false;
+    }
+    matches!(essence(kind).split_once('/'), Some((t, s)) if !t.is_empty() && !s.is_empty() && !s.contains('/'))
+}
+
+/// Does contents of type `have` satisfy a request for `want`?
+pub fn type_matches(want: &str, have: &str) -> bool {
+    let (want, have) = (essence(want), essence(have));
+    if want.is_empty() || want == "*/*" {
+        return true;
+    }
+    match (want.split_once('/'), have.split_once('/')) {
+        (Some((wt, "*")), Some((ht, _))) => wt.eq_ignore_ascii_case(ht),
+        _ => want.eq_ignore_ascii_case(have),
+    }
+}
+
+/// Clipboard contents
+pub struct Clipboard {
+    kind: String,
+    data: Vec<u8>,
+    serial: u64,
+}
+
+impl Clipboard {
+    /// An empty clipboard
+    pub const fn new() -> Self {
+        Clipboard { kind: String::new(), data: Vec::new(), serial: 0 }
+    }
+
+    /// Replace the contents
+    pub fn set(&mut self, kind: &str, data: &[u8]) -> Result<(), ClipboardError> {
+        if !valid_type(kind) {
+            return Err(ClipboardError::BadType);
+        }
+        if data.len() > MAX_CLIPBOARD_SIZE {
+            return Err(ClipboardError::TooLarge);
+        }
+        self.kind = String::from(kind);
+        self.data = Vec::from(data);
+        self.serial += 1;
+        Ok(())
+    }
+
+    /// The contents, if there are any and they match `kind`
+    pub fn get(&self, kind: &str) -> Option<&[u8]> {
+        (!self.kind.is_empty() && type_matches(kind, &self.kind)).then_some(&self.data[..])
+    }
+
+    /// Type of the contents ("" when empty)
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Number of sets so far
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Empty the clipboard
+    pub fn clear(&mut self) {
+        self.kind.clear();
+        self.data = Vec::new();
+        self.serial += 1;
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let mut clip = Clipboard::new();
+        assert_eq!(clip.get(TEXT_PLAIN), None);
+        assert_eq!(clip.get(""), None);
+
+        clip.set("text/plain;charset=utf-8", b"hello").unwrap();
+        assert_eq!(clip.get(TEXT_PLAIN), Some(&b"hello"[..]));
+        assert_eq!(clip.get("TEXT/*"), Some(&b"hello"[..]));
+        assert_eq!(clip.get("*/*"), Some(&b"hello"[..]));
+        assert_eq!(clip.get("image/bmp"), None);
+        assert_eq!(clip.get("image/*"), None);
+        assert_eq!(clip.serial(), 1);
+
+        clip.clear();
+        assert_eq!(clip.get(""), None);
+        assert_eq!(clip.kind(), "");
+        assert_eq!(clip.serial(), 2);
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let mut clip = Clipboard::new();
+        clip.set(TEXT_PLAIN, b"keep").unwrap();
+        for kind in ["", "text", "text/", "/plain", "a/b/c", "text/plain x", "text/pl\u{e9}in"] {
+            assert_eq!(clip.set(kind, b"x"), Err(ClipboardError::BadType), "{:?}", kind);
+        }
+        let big = alloc::vec![0u8; MAX_CLIPBOARD_SIZE + 1];
+        assert_eq!(clip.set(TEXT_PLAIN, &big), Err(ClipboardError::TooLarge));
+        // Failed sets leave the contents alone
+        assert_eq!(clip.get(TEXT_PLAIN), Some(&b"keep"[..]));
+        assert_eq!(clip.serial(), 1);
+    }
+}
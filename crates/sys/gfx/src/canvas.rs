@@ -0,0 +1,334 @@
+//! Drawing targets and sources
+//!
+//! A [`Canvas`] is a mutable view of ARGB pixels with a row stride, so it
+//! can wrap a framebuffer whose pitch is wider than its visible width. Every
+//! drawing call is clipped to the canvas' clip rectangle. An [`Image`] is
+//! the read-only counterpart used as a blit source.
+
+use crate::color;
+use crate::rect::Rect;
+
+/// Read-only ARGB pixels
+#[derive(Clone, Copy)]
+pub struct Image<'a> {
+    pixels: &'a [u32],
+    width: u32,
+    height: u32,
+    stride: usize,
+}
+
+impl<'a> Image<'a> {
+    /// Wrap a pixel slice; None if it is too short for the given size
+    pub fn new(pixels: &'a [u32], width: u32, height: u32, stride: usize) -> Option<Self> {
+        if stride < width as usize || pixels.len() < required_len(width, height, stride) {
+            return None;
+        }
+        Some(Image { pixels, width, height, stride })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Pixel at (x, y), if inside the image
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if !self.bounds().contains(x, y) {
+            return None;
+        }
+        Some(self.pixels[y as usize * self.stride + x as usize])
+    }
+
+    /// Pixels of row `y` from column `x`, `len` long (caller clips)
+    fn span(&self, x: u32, y: u32, len: u32) -> &'a [u32] {
+        let start = y as usize * self.stride + x as usize;
+        &self.pixels[start..start + len as usize]
+    }
+}
+
+/// Mutable ARGB pixels with a clip rectangle
+pub struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: u32,
+    height: u32,
+    stride: usize,
+    clip: Rect,
+}
+
+fn required_len(width: u32, height: u32, stride: usize) -> usize {
+    if width == 0 || height == 0 {
+        0
+    } else {
+        (height as usize - 1) * stride + width as usize
+    }
+}
+
+/// How a blit combines source and destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlitMode {
+    Copy,
+    Blend,
+    Faded(u8),
+}
+
+impl<'a> Canvas<'a> {
+    /// Wrap a pixel slice; None if it is too short for the given size
+    pub fn new(pixels: &'a mut [u32], width: u32, height: u32, stride: usize) -> Option<Self> {
+        if stride < width as usize || pixels.len() < required_len(width, height, stride) {
+            return None;
+        }
+        Some(Canvas { pixels, width, height, stride, clip: Rect::new(0, 0, width, height) })
+    }
+
+    /// Wrap raw pixel memory such as a mapped framebuffer
+    ///
+    /// `pitch` is the row length in bytes.
+    ///
+    /// # Safety
+    /// `addr` must point to `pitch * height` writable bytes that nothing else
+    /// accesses while the canvas lives.
+    pub unsafe fn from_raw(addr: *mut u32, width: u32, height: u32, pitch: u32) -> Self {
+        let stride = (pitch / 4) as usize;
+        let pixels = core::slice::from_raw_parts_mut(addr, required_len(width, height, stride));
+        Canvas { pixels, width, height, stride, clip: Rect::new(0, 0, width, height) }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Current clip rectangle
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Restrict drawing to `rect` (intersected with the canvas bounds)
+    pub fn set_clip(&mut self, rect: Rect) {
+        self.clip = rect.intersect(&self.bounds());
+    }
+
+    /// Allow drawing anywhere on the canvas again
+    pub fn reset_clip(&mut self) {
+        self.clip = self.bounds();
+    }
+
+    /// Read-only view of the whole canvas
+    pub fn as_image(&self) -> Image<'_> {
+        Image { pixels: self.pixels, width: self.width, height: self.height, stride: self.stride }
+    }
+
+    /// Pixel at (x, y), if inside the canvas (the clip does not apply)
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        self.as_image().get_pixel(x, y)
+    }
+
+    /// Write a pixel, ignoring its alpha
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.clip.contains(x, y) {
+            self.pixels[y as usize * self.stride + x as usize] = color;
+        }
+    }
+
+    /// Blend a pixel onto the canvas
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.clip.contains(x, y) {
+            let p = &mut self.pixels[y as usize * self.stride + x as usize];
+            *p = color::blend(*p, color);
+        }
+    }
+
+    /// Row `y` from column `x`, `len` long (caller clips)
+    fn span_mut(&mut self, x: u32, y: u32, len: u32) -> &mut [u32] {
+        let start = y as usize * self.stride + x as usize;
+        &mut self.pixels[start..start + len as usize]
+    }
+
+    /// Fill the clip rectangle with one color (no blending)
+    pub fn clear(&mut self, color: u32) {
+        let clip = self.clip;
+        for y in clip.y..clip.bottom() {
+            self.span_mut(clip.x as u32, y as u32, clip.width).fill(color);
+        }
+    }
+
+    /// Fill a rectangle, blending if the color is translucent
+    pub fn fill_rect(&mut self, rect: Rect, color: u32) {
+        let area = rect.intersect(&self.clip);
+        if area.is_empty() {
+            return;
+        }
+        for y in area.y..area.bottom() {
+            color::blend_fill(self.span_mut(area.x as u32, y as u32, area.width), color);
+        }
+    }
+
+    /// Outline a rectangle with 1-pixel edges
+    pub fn draw_rect(&mut self, rect: Rect, color: u32) {
+        if rect.is_empty() {
+            return;
+        }
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        if rect.height > 1 {
+            self.fill_rect(Rect::new(rect.x, rect.bottom() - 1, rect.width, 1), color);
+        }
+        if rect.height > 2 {
+            let side = Rect::new(rect.x, rect.y + 1, 1, rect.height - 2);
+            self.fill_rect(side, color);
+            if rect.width > 1 {
+                self.fill_rect(side.offset(rect.width as i32 - 1, 0), color);
+            }
+        }
+    }
+
+    /// Horizontal line from x0 to x1 inclusive
+    pub fn hline(&mut self, x0: i32, x1: i32, y: i32, color: u32) {
+        self.fill_rect(Rect::from_corners(x0.min(x1), y, x0.max(x1) + 1, y + 1), color);
+    }
+
+    /// Vertical line from y0 to y1 inclusive
+    pub fn vline(&mut self, x: i32, y0: i32, y1: i32, color: u32) {
+        self.fill_rect(Rect::from_corners(x, y0.min(y1), x + 1, y0.max(y1) + 1), color);
+    }
+
+    /// Line between two points inclusive (Bresenham)
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        if y0 == y1 {
+            return self.hline(x0, x1, y0, color);
+        }
+        if x0 == x1 {
+            return self.vline(x0, y0, y1, color);
+        }
+
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.blend_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Circle outline (midpoint algorithm)
+    pub fn circle(&mut self, cx: i32, cy: i32, radius: i32, color: u32) {
+        if radius <= 0 {
+            return self.blend_pixel(cx, cy, color);
+        }
+        let (mut x, mut y, mut err) = (radius, 0, 1 - radius);
+        while x >= y {
+            // Each octant point once, even where octants meet
+            let mut points = [
+                (cx + x, cy + y), (cx + y, cy + x), (cx - y, cy + x), (cx - x, cy + y),
+                (cx - x, cy - y), (cx - y, cy - x), (cx + y, cy - x), (cx + x, cy - y),
+            ];
+            points.sort_unstable();
+            let mut last = None;
+            for p in points {
+                if last != Some(p) {
+                    self.blend_pixel(p.0, p.1, color);
+                    last = Some(p);
+                }
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Filled circle
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: i32, color: u32) {
+        if radius < 0 {
+            return;
+        }
+        // One span per row, so translucent fills blend each pixel once
+        let r2 = radius * radius + radius;
+        let mut half = radius;
+        for dy in 0..=radius {
+            while half > 0 && half * half + dy * dy > r2 {
+                half -= 1;
+            }
+            self.hline(cx - half, cx + half, cy + dy, color);
+            if dy != 0 {
+                self.hline(cx - half, cx + half, cy - dy, color);
+            }
+        }
+    }
+
+    /// Copy `src_rect` of `src` to (dx, dy), ignoring alpha
+    pub fn blit(&mut self, src: &Image, src_rect: Rect, dx: i32, dy: i32) {
+        self.blit_with(src, src_rect, dx, dy, BlitMode::Copy);
+    }
+
+    /// Blend `src_rect` of `src` onto (dx, dy) using the source alpha
+    pub fn blit_blend(&mut self, src: &Image, src_rect: Rect, dx: i32, dy: i32) {
+        self.blit_with(src, src_rect, dx, dy, BlitMode::Blend);
+    }
+
+    /// Blend with the source alpha further scaled by `opacity`
+    pub fn blit_faded(&mut self, src: &Image, src_rect: Rect, dx: i32, dy: i32, opacity: u8) {
+        match opacity {
+            0 => {}
+            255 => self.blit_with(src, src_rect, dx, dy, BlitMode::Blend),
+            _ => self.blit_with(src, src_rect, dx, dy, BlitMode::Faded(opacity)),
+        }
+    }
+
+    fn blit_with(&mut self, src: &Image, src_rect: Rect, dx: i32, dy: i32, mode: BlitMode) {
+        // Clip to the source, shift the destination by what was cut off the
+        // top/left, then clip to the canvas
+        let clipped = src_rect.intersect(&src.bounds());
+        let placed = Rect::new(
+            dx + (clipped.x - src_rect.x),
+            dy + (clipped.y - src_rect.y),
+            clipped.width,
+            clipped.height,
+        );
+        let dest = placed.intersect(&self.clip);
+        if dest.is_empty() {
+            return;
+        }
+        let sx = (clipped.x + (dest.x - placed.x)) as u32;
+        let sy = (clipped.y + (dest.y - placed.y)) as u32;
+
+        for row in 0..dest.height {
+            let from = src.span(sx, sy + row, dest.width);
+            let to = self.span_mut(dest.x as u32, dest.y as u32 + row, dest.width);
+            match mode {
+                BlitMode::Copy => to.copy_from_slice(from),
+                BlitMode::Blend => color::blend_row(to, from),
+                BlitMode::Faded(opacity) => color::blend_row_faded(to, from, opacity),
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! ARGB colors and blending
+//!
+//! Colors are plain `u32`s in `0xAARRGGBB` order. Alpha is straight (not
+//! premultiplied): 255 is opaque, 0 fully transparent.
+
+pub const TRANSPARENT: u32 = 0x0000_0000;
+pub const BLACK: u32 = 0xFF00_0000;
+pub const WHITE: u32 = 0xFFFF_FFFF;
+
+/// Pack ARGB components
+#[inline]
+pub const fn argb(a: u8, r: u8, g: u8, b: u8) -> u32 {
+    ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+}
+
+/// Pack an opaque RGB color
+#[inline]
+pub const fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    argb(255, r, g, b)
+}
+
+/// Alpha component
+#[inline]
+pub const fn alpha(color: u32) -> u8 {
+    (color >> 24) as u8
+}
+
+/// Replace the alpha component
+#[inline]
+pub const fn with_alpha(color: u32, a: u8) -> u32 {
+    (color & 0x00FF_FFFF) | ((a as u32) << 24)
+}
+
+/// Scale a color's alpha by `opacity` (255 leaves it unchanged)
+#[inline]
+pub const fn fade(color: u32, opacity: u8) -> u32 {
+    with_alpha(color, div255(alpha(color) as u32 * opacity as u32) as u8)
+}
+
+/// `x / 255`, rounded, for `x <= 255 * 255`
+#[inline]
+pub const fn div255(x: u32) -> u32 {
+    let x = x + 128;
+    (x + (x >> 8)) >> 8
+}
+
+/// `div255` on two 16-bit lanes at once (bits 0-15 and 16-31)
+#[inline]
+const fn div255_lanes(x: u32) -> u32 {
+    let x = x + 0x0080_0080;
+    ((x + ((x >> 8) & 0x00FF_00FF)) >> 8) & 0x00FF_00FF
+}
+
+/// Source-over blend of `src` onto `dst`
+///
+/// Red and blue are blended together in one multiply, then green; the result
+/// alpha is `sa + da * (1 - sa)`.
+#[inline]
+pub const fn blend(dst: u32, src: u32) -> u32 {
+    let a = src >> 24;
+    if a == 255 {
+        return src;
+    }
+    if a == 0 {
+        return dst;
+    }
+    blend_partial(dst, src, a)
+}
+
+/// The arithmetic part of `blend`, valid for any alpha
+#[inline(always)]
+const fn blend_partial(dst: u32, src: u32, a: u32) -> u32 {
+    let inv = 255 - a;
+    let rb = div255_lanes((src & 0x00FF_00FF) * a + (dst & 0x00FF_00FF) * inv);
+    let g = div255_lanes(((src >> 8) & 0xFF) * a + ((dst >> 8) & 0xFF) * inv);
+    let out_a = a + div255((dst >> 24) * inv);
+    (out_a << 24) | (g << 8) | rb
+}
+
+/// Blend a row of source pixels onto a row of destination pixels
+///
+/// Groups of four that are all opaque are copied and all-transparent groups
+/// skipped; mixed groups take the branch-free blend on every lane.
+pub fn blend_row(dst: &mut [u32], src: &[u32]) {
+    let len = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..len], &src[..len]);
+
+    let mut d4 = dst.chunks_exact_mut(4);
+    let mut s4 = src.chunks_exact(4);
+    for (d, s) in (&mut d4).zip(&mut s4) {
+        let all = s[0] & s[1] & s[2] & s[3];
+        let any = s[0] | s[1] | s[2] | s[3];
+        if all >> 24 == 0xFF {
+            d.copy_from_slice(s);
+        } else if any >> 24 != 0 {
+            for i in 0..4 {
+                d[i] = blend_partial(d[i], s[i], s[i] >> 24);
+            }
+        }
+    }
+    for (d, &s) in d4.into_remainder().iter_mut().zip(s4.remainder()) {
+        *d = blend(*d, s);
+    }
+}
+
+/// Blend a row onto itself with every source alpha scaled by `opacity`
+pub fn blend_row_faded(dst: &mut [u32], src: &[u32], opacity: u8) {
+    for (d, &s) in dst.iter_mut().zip(src) {
+        *d = blend(*d, fade(s, opacity));
+    }
+}
+
+/// Blend one color over a whole row
+pub fn blend_fill(dst: &mut [u32], color: u32) {
+    let a = color >> 24;
+    if a == 255 {
+        dst.fill(color);
+        return;
+    }
+    if a == 0 {
+        return;
+    }
+    // The source terms are the same for every pixel
+    let inv = 255 - a;
+    let src_rb = (color & 0x00FF_00FF) * a;
+    let src_g = ((color >> 8) & 0xFF) * a;
+    for d in dst.iter_mut() {
+        let rb = div255_lanes(src_rb + (*d & 0x00FF_00FF) * inv);
+        let g = div255_lanes(src_g + ((*d >> 8) & 0xFF) * inv);
+        let out_a = a + div255((*d >> 24) * inv);
+        *d = (out_a << 24) | (g << 8) | rb;
+    }
+}
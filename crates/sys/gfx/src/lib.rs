@@ -0,0 +1,141 @@
+//! WATOS 2D Graphics
+//!
+//! Software rendering on 32-bit ARGB pixels (`0xAARRGGBB`), the layout of a
+//! GOP framebuffer in BGRX mode, so a [`Canvas`] can point straight at video
+//! memory or at an off-screen [`Surface`].
+//!
+//! - [`color`]: packing helpers and source-over alpha blending
+//! - [`Rect`]: integer rectangles used for clipping
+//! - [`Canvas`]: a mutable pixel view with a clip rectangle; fills, lines,
+//!   circles, and opaque or alpha-blended blits from an [`Image`]
+//! - [`Surface`] (feature `alloc`): an owned pixel buffer
+//!
+//! Row loops work on whole slices, four pixels at a time, so they compile to
+//! straight-line code the optimizer can vectorize; no floating point is used.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod color;
+pub mod rect;
+pub mod canvas;
+#[cfg(feature = "alloc")]
+pub mod surface;
+
+pub use canvas::{Canvas, Image};
+pub use rect::Rect;
+#[cfg(feature = "alloc")]
+pub use surface::Surface;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::{argb, blend, blend_row, rgb};
+
+    /// Straightforward per-channel source-over, rounded
+    fn reference_blend(dst: u32, src: u32) -> u32 {
+        let a = src >> 24;
+        let mix = |shift: u32| {
+            let s = (src >> shift) & 0xFF;
+            let d = (dst >> shift) & 0xFF;
+            ((s * a + d * (255 - a)) as f32 / 255.0).round() as u32
+        };
+        let out_a = a + (((dst >> 24) * (255 - a)) as f32 / 255.0).round() as u32;
+        (out_a << 24) | (mix(16) << 16) | (mix(8) << 8) | mix(0)
+    }
+
+    #[test]
+    fn test_blend_matches_reference() {
+        for a in [0u8, 1, 64, 127, 128, 200, 254, 255] {
+            for (s, d) in [(0x00, 0xFF), (0xFF, 0x00), (0x12, 0xEE), (0x80, 0x80), (0xAB, 0x01)] {
+                let src = argb(a, s, d, s ^ 0x5A);
+                let dst = argb(0xFF - a / 2, d, s, d ^ 0xA5);
+                assert_eq!(blend(dst, src), reference_blend(dst, src), "a={} s={} d={}", a, s, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blend_row_matches_blend() {
+        let src: [u32; 11] = [
+            0xFF102030, 0xFF405060, 0xFF708090, 0xFFA0B0C0, // opaque group
+            0x00FFFFFF, 0x00000000, 0x00123456, 0x00ABCDEF, // transparent group
+            0x80FF0000, 0xFF00FF00, 0x400000FF,             // mixed remainder
+        ];
+        let mut row = [rgb(10, 20, 30); 11];
+        blend_row(&mut row, &src);
+        for (i, &s) in src.iter().enumerate() {
+            assert_eq!(row[i], blend(rgb(10, 20, 30), s));
+        }
+    }
+
+    #[test]
+    fn test_fill_rect_is_clipped() {
+        let mut surface = Surface::new(8, 8, color::BLACK);
+        let mut canvas = surface.canvas();
+        canvas.set_clip(Rect::new(2, 2, 4, 4));
+        canvas.fill_rect(Rect::new(-5, -5, 100, 100), color::WHITE);
+
+        let image = surface.image();
+        assert_eq!(image.get_pixel(1, 1), Some(color::BLACK));
+        assert_eq!(image.get_pixel(2, 2), Some(color::WHITE));
+        assert_eq!(image.get_pixel(5, 5), Some(color::WHITE));
+        assert_eq!(image.get_pixel(6, 5), Some(color::BLACK));
+        assert_eq!(surface.pixels().iter().filter(|&&p| p == color::WHITE).count(), 16);
+    }
+
+    #[test]
+    fn test_blit_clips_source_and_destination() {
+        let mut src = Surface::new(4, 4, 0);
+        for (i, p) in src.pixels_mut().iter_mut().enumerate() {
+            *p = 0xFF000000 | i as u32;
+        }
+        let mut dst = Surface::new(6, 6, 0);
+        // Source rect hangs off the top-left of the source, destination off
+        // the right edge
+        dst.canvas().blit(&src.image(), Rect::new(-1, -1, 4, 4), 3, 2);
+
+        let image = dst.image();
+        // Source (0,0) lands one pixel in from (3,2)
+        assert_eq!(image.get_pixel(4, 3), Some(0xFF000000));
+        assert_eq!(image.get_pixel(5, 3), Some(0xFF000001));
+        assert_eq!(image.get_pixel(4, 5), Some(0xFF000008));
+        assert_eq!(image.get_pixel(3, 3), Some(0));
+        assert_eq!(image.get_pixel(4, 2), Some(0));
+    }
+
+    #[test]
+    fn test_canvas_respects_stride() {
+        // 3x2 visible inside rows of 5
+        let mut pixels = [0u32; 8];
+        let mut canvas = Canvas::new(&mut pixels, 3, 2, 5).unwrap();
+        canvas.clear(1);
+        assert_eq!(pixels, [1, 1, 1, 0, 0, 1, 1, 1]);
+        assert!(Canvas::new(&mut [0u32; 7], 3, 2, 5).is_none());
+    }
+
+    #[test]
+    fn test_line_and_circle_endpoints() {
+        let mut surface = Surface::new(16, 16, 0);
+        let mut canvas = surface.canvas();
+        canvas.line(1, 1, 10, 4, color::WHITE);
+        canvas.circle(8, 8, 5, 0xFF00FF00);
+        let image = surface.image();
+        assert_eq!(image.get_pixel(1, 1), Some(color::WHITE));
+        assert_eq!(image.get_pixel(10, 4), Some(color::WHITE));
+        assert_eq!(image.get_pixel(13, 8), Some(0xFF00FF00));
+        assert_eq!(image.get_pixel(8, 3), Some(0xFF00FF00));
+        assert_eq!(image.get_pixel(8, 8), Some(0));
+    }
+
+    #[test]
+    fn test_rect_ops() {
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(5, -5, 10, 10);
+        assert_eq!(a.intersect(&b), Rect::new(5, 0, 5, 5));
+        assert!(a.intersect(&Rect::new(20, 20, 1, 1)).is_empty());
+        assert_eq!(a.union(&b), Rect::new(0, -5, 15, 15));
+    }
+}
@@ -0,0 +1,74 @@
+//! Integer rectangles
+
+/// A rectangle: origin may be negative, size is never negative
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    /// Rectangle from two corners (exclusive of the second)
+    pub fn from_corners(x0: i32, y0: i32, x1: i32, y1: i32) -> Self {
+        let (left, right) = (x0.min(x1), x0.max(x1));
+        let (top, bottom) = (y0.min(y1), y0.max(y1));
+        Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// One past the rightmost column
+    pub const fn right(&self) -> i32 {
+        self.x.saturating_add(self.width as i32)
+    }
+
+    /// One past the bottom row
+    pub const fn bottom(&self) -> i32 {
+        self.y.saturating_add(self.height as i32)
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub const fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Overlap of two rectangles (empty if they do not touch)
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= left || bottom <= top {
+            return Rect::new(left, top, 0, 0);
+        }
+        Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// Smallest rectangle covering both (an empty side is ignored)
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Rect::from_corners(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        )
+    }
+
+    /// The same rectangle moved by (dx, dy)
+    pub const fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(self.x + dx, self.y + dy, self.width, self.height)
+    }
+}
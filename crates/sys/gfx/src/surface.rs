@@ -0,0 +1,61 @@
+//! Owned off-screen surfaces
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::canvas::{Canvas, Image};
+use crate::rect::Rect;
+
+/// A heap-allocated ARGB pixel buffer, rows packed without padding
+pub struct Surface {
+    pixels: Vec<u32>,
+    width: u32,
+    height: u32,
+}
+
+impl Surface {
+    /// A surface filled with one color
+    pub fn new(width: u32, height: u32, fill: u32) -> Self {
+        Surface { pixels: vec![fill; width as usize * height as usize], width, height }
+    }
+
+    /// Take ownership of existing pixels; None if the length is wrong
+    pub fn from_pixels(pixels: Vec<u32>, width: u32, height: u32) -> Option<Self> {
+        if pixels.len() != width as usize * height as usize {
+            return None;
+        }
+        Some(Surface { pixels, width, height })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    /// Draw on the surface
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        Canvas::new(&mut self.pixels, self.width, self.height, self.width as usize)
+            .expect("surface buffer matches its size")
+    }
+
+    /// Use the surface as a blit source
+    pub fn image(&self) -> Image<'_> {
+        Image::new(&self.pixels, self.width, self.height, self.width as usize)
+            .expect("surface buffer matches its size")
+    }
+}
@@ -0,0 +1,168 @@
+//! Windows BMP decoding
+//!
+//! Handles the formats screenshot tools and paint programs actually write:
+//! BITMAPINFOHEADER or later (V4/V5), uncompressed 1/4/8-bit palettized and
+//! 16/24/32-bit direct color, and BI_BITFIELDS masks for 16 and 32 bits.
+//! RLE compression and OS/2 core headers are rejected.
+
+use alloc::vec::Vec;
+
+use watos_gfx::Surface;
+
+use crate::{check_size, ImageError};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    let b = data.get(offset..offset + 2).ok_or(ImageError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    let b = data.get(offset..offset + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// A channel mask: extract the bits and widen them to 8
+#[derive(Clone, Copy)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    fn from_mask(mask: u32) -> Result<Self, ImageError> {
+        if mask == 0 {
+            return Ok(Channel { shift: 0, bits: 0 });
+        }
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).trailing_ones();
+        // Masks must be contiguous
+        if (mask >> shift) >> bits != 0 {
+            return Err(ImageError::Corrupt);
+        }
+        Ok(Channel { shift, bits: bits.min(8) })
+    }
+
+    fn extract(&self, value: u32, default: u8) -> u8 {
+        if self.bits == 0 {
+            return default;
+        }
+        let v = (value >> self.shift) & ((1 << self.bits) - 1);
+        // Replicate the top bits so full scale maps to 255
+        let mut out = v << (8 - self.bits);
+        let mut filled = self.bits;
+        while filled < 8 {
+            out |= out >> filled;
+            filled *= 2;
+        }
+        out as u8
+    }
+}
+
+/// Decode a BMP file (starting with "BM")
+pub fn decode(data: &[u8]) -> Result<Surface, ImageError> {
+    if data.get(0..2) != Some(b"BM") {
+        return Err(ImageError::UnknownFormat);
+    }
+    let pixel_offset = u32_at(data, 10)? as usize;
+    let header_size = u32_at(data, 14)? as usize;
+    if header_size < 40 {
+        return Err(ImageError::Unsupported);
+    }
+
+    let width = u32_at(data, 18)? as i32;
+    let raw_height = u32_at(data, 22)? as i32;
+    let bpp = u16_at(data, 28)?;
+    let compression = u32_at(data, 30)?;
+    let colors_used = u32_at(data, 46)?;
+
+    if width <= 0 || raw_height == 0 || raw_height == i32::MIN {
+        return Err(ImageError::Corrupt);
+    }
+    let top_down = raw_height < 0;
+    let (width, height) = (width as u32, raw_height.unsigned_abs());
+    check_size(width, height)?;
+
+    // Masks for direct color; BITFIELDS masks follow a 40-byte header, or
+    // live inside V4/V5 headers at the same offset
+    let (red, green, blue, alpha) = match (compression, bpp) {
+        (BI_RGB, 16) => (0x7C00, 0x03E0, 0x001F, 0),
+        (BI_RGB, 24) | (BI_RGB, 32) => (0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0),
+        (BI_RGB, 1) | (BI_RGB, 4) | (BI_RGB, 8) => (0, 0, 0, 0),
+        (BI_BITFIELDS, 16) | (BI_BITFIELDS, 32) | (BI_ALPHABITFIELDS, 16) | (BI_ALPHABITFIELDS, 32) => {
+            let alpha = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                u32_at(data, 66)?
+            } else {
+                0
+            };
+            (u32_at(data, 54)?, u32_at(data, 58)?, u32_at(data, 62)?, alpha)
+        }
+        _ => return Err(ImageError::Unsupported),
+    };
+    let channels = [
+        Channel::from_mask(red)?,
+        Channel::from_mask(green)?,
+        Channel::from_mask(blue)?,
+        Channel::from_mask(alpha)?,
+    ];
+
+    let palette = if bpp <= 8 {
+        let count = match colors_used {
+            0 => 1usize << bpp,
+            n => (n as usize).min(1 << bpp),
+        };
+        let start = 14 + header_size;
+        let table = data.get(start..start + count * 4).ok_or(ImageError::Truncated)?;
+        table
+            .chunks_exact(4)
+            .map(|c| 0xFF00_0000 | (c[2] as u32) << 16 | (c[1] as u32) << 8 | c[0] as u32)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Rows are padded to 4 bytes
+    let row_bytes = (width as usize * bpp as usize).div_ceil(32) * 4;
+    let pixels_end = pixel_offset + row_bytes * height as usize;
+    let pixel_data = data.get(pixel_offset..pixels_end).ok_or(ImageError::Truncated)?;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as usize {
+        let src_row = if top_down { y } else { height as usize - 1 - y };
+        let row = &pixel_data[src_row * row_bytes..(src_row + 1) * row_bytes];
+        for x in 0..width as usize {
+            let color = match bpp {
+                1 | 4 | 8 => {
+                    let bit = x * bpp as usize;
+                    let shift = 8 - bpp as usize - bit % 8;
+                    let index = (row[bit / 8] >> shift) as usize & ((1 << bpp) - 1);
+                    // Out-of-range indices draw black rather than failing
+                    palette.get(index).copied().unwrap_or(0xFF00_0000)
+                }
+                16 => direct(u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32, &channels),
+                24 => {
+                    let p = &row[x * 3..x * 3 + 3];
+                    0xFF00_0000 | (p[2] as u32) << 16 | (p[1] as u32) << 8 | p[0] as u32
+                }
+                _ => {
+                    let p = &row[x * 4..x * 4 + 4];
+                    direct(u32::from_le_bytes([p[0], p[1], p[2], p[3]]), &channels)
+                }
+            };
+            pixels.push(color);
+        }
+    }
+
+    Surface::from_pixels(pixels, width, height).ok_or(ImageError::Corrupt)
+}
+
+fn direct(value: u32, channels: &[Channel; 4]) -> u32 {
+    let [r, g, b, a] = channels;
+    (a.extract(value, 0xFF) as u32) << 24
+        | (r.extract(value, 0) as u32) << 16
+        | (g.extract(value, 0) as u32) << 8
+        | b.extract(value, 0) as u32
+}
@@ -0,0 +1,285 @@
+//! DEFLATE decompression (RFC 1951) and the zlib wrapper (RFC 1950)
+//!
+//! A straightforward decoder: canonical Huffman tables are decoded by
+//! walking code lengths one bit at a time, which is slow compared to table
+//! lookup but small, and fast enough for icons and wallpapers.
+
+use alloc::vec::Vec;
+
+use crate::ImageError;
+
+/// Bit reader over a byte slice, least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits { data, pos: 0, bit: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, ImageError> {
+        let byte = *self.data.get(self.pos).ok_or(ImageError::Truncated)?;
+        let value = (byte as u32 >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, ImageError> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code: symbol counts per length and symbols by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ImageError> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject over-subscribed codes (incomplete ones are legal)
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(ImageError::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = alloc::vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, ImageError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bit()? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(ImageError::Corrupt)
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order code length code lengths are stored in
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompress a raw DEFLATE stream, refusing to produce more than `limit`
+/// bytes
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, ImageError> {
+    let mut bits = Bits::new(data);
+    let mut out = Vec::new();
+
+    loop {
+        let last = bits.bit()? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, &mut out, limit)?,
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                codes(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut bits)?;
+                codes(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            _ => return Err(ImageError::Corrupt),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompress a zlib stream (2-byte header, DEFLATE data, Adler-32)
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, ImageError> {
+    if data.len() < 6 {
+        return Err(ImageError::Truncated);
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0F != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) || flg & 0x20 != 0 {
+        return Err(ImageError::Corrupt);
+    }
+    let out = inflate(&data[2..], limit)?;
+
+    let tail = &data[data.len() - 4..];
+    let expected = u32::from_be_bytes([tail[0], tail[1], tail[2], tail[3]]);
+    if adler32(&out) != expected {
+        return Err(ImageError::Corrupt);
+    }
+    Ok(out)
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>, limit: usize) -> Result<(), ImageError> {
+    bits.align();
+    let header = bits.data.get(bits.pos..bits.pos + 4).ok_or(ImageError::Truncated)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(ImageError::Corrupt);
+    }
+    bits.pos += 4;
+    let block = bits.data.get(bits.pos..bits.pos + len as usize).ok_or(ImageError::Truncated)?;
+    if out.len() + block.len() > limit {
+        return Err(ImageError::TooLarge);
+    }
+    out.extend_from_slice(block);
+    bits.pos += len as usize;
+    Ok(())
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), ImageError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_tables(bits: &mut Bits) -> Result<(Huffman, Huffman), ImageError> {
+    let hlit = bits.bits(5)? as usize + 257;
+    let hdist = bits.bits(5)? as usize + 1;
+    let hclen = bits.bits(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(ImageError::Corrupt);
+    }
+
+    let mut clen = [0u8; 19];
+    for &index in &CLEN_ORDER[..hclen] {
+        clen[index] = bits.bits(3)? as u8;
+    }
+    let clen_code = Huffman::new(&clen)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let symbol = clen_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let prev = *lengths[..i].last().ok_or(ImageError::Corrupt)?;
+                (prev, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if i + repeat > hlit + hdist {
+            return Err(ImageError::Corrupt);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        // No end-of-block code
+        return Err(ImageError::Corrupt);
+    }
+
+    Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..hlit + hdist])?))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, limit: usize) -> Result<(), ImageError> {
+    loop {
+        let symbol = lit.decode(bits)? as usize;
+        if symbol < 256 {
+            if out.len() >= limit {
+                return Err(ImageError::TooLarge);
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(ImageError::Corrupt);
+        }
+        let len = LENGTH_BASE[index] as usize + bits.bits(LENGTH_EXTRA[index] as u32)? as usize;
+        let dsym = dist.decode(bits)? as usize;
+        if dsym >= DIST_BASE.len() {
+            return Err(ImageError::Corrupt);
+        }
+        let distance = DIST_BASE[dsym] as usize + bits.bits(DIST_EXTRA[dsym] as u32)? as usize;
+        if distance > out.len() {
+            return Err(ImageError::Corrupt);
+        }
+        if out.len() + len > limit {
+            return Err(ImageError::TooLarge);
+        }
+        // Byte by byte: the copy may overlap the bytes it produces
+        let start = out.len() - distance;
+        for i in 0..len {
+            let byte = out[start + i];
+            out.push(byte);
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,197 @@
+//! WATOS Image Decoding
+//!
+//! Turns BMP and PNG files into [`watos_gfx::Surface`]s of ARGB pixels, ready
+//! to blit onto a framebuffer canvas for wallpapers, icons and app assets.
+//!
+//! - [`bmp`]: uncompressed BMP, palettized and direct color
+//! - [`png`]: non-interlaced PNG of any color type and bit depth
+//! - [`inflate`]: the zlib/DEFLATE decompressor PNG needs
+//!
+//! [`decode`] sniffs the format from the file's first bytes.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod bmp;
+pub mod inflate;
+pub mod png;
+
+use watos_gfx::Surface;
+
+/// Largest image accepted, in pixels (64 MB of ARGB)
+pub const MAX_PIXELS: u64 = 16 * 1024 * 1024;
+
+/// Image decoding errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// Not a BMP or PNG file
+    UnknownFormat,
+    /// The file ends early
+    Truncated,
+    /// Valid, but uses a feature this decoder lacks
+    Unsupported,
+    /// Malformed headers or image data
+    Corrupt,
+    /// Larger than [`MAX_PIXELS`]
+    TooLarge,
+}
+
+impl ImageError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageError::UnknownFormat => "unknown image format",
+            ImageError::Truncated => "file is truncated",
+            ImageError::Unsupported => "unsupported image variant",
+            ImageError::Corrupt => "corrupt image data",
+            ImageError::TooLarge => "image is too large",
+        }
+    }
+}
+
+/// Image container formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bmp,
+    Png,
+}
+
+/// Identify a file by its magic bytes
+pub fn sniff(data: &[u8]) -> Option<Format> {
+    if data.starts_with(&png::SIGNATURE) {
+        Some(Format::Png)
+    } else if data.starts_with(b"BM") {
+        Some(Format::Bmp)
+    } else {
+        None
+    }
+}
+
+/// Decode a BMP or PNG file
+pub fn decode(data: &[u8]) -> Result<Surface, ImageError> {
+    match sniff(data) {
+        Some(Format::Png) => png::decode(data),
+        Some(Format::Bmp) => bmp::decode(data),
+        None => Err(ImageError::UnknownFormat),
+    }
+}
+
+pub(crate) fn check_size(width: u32, height: u32) -> Result<(), ImageError> {
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(ImageError::TooLarge);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// 2x2 RGBA; row 0 unfiltered, row 1 Sub-filtered
+    const PNG_RGBA: [u8; 80] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06, 0x00, 0x00, 0x00, 0x72, 0xb6, 0x0d,
+        0x24, 0x00, 0x00, 0x00, 0x17, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0xf8, 0xcf, 0xc0, 0xf0,
+        0x1f, 0x08, 0x1b, 0x18, 0x81, 0xf4, 0x7f, 0x2e, 0x2e, 0x2e, 0x06, 0x00, 0x3b, 0x3f, 0x05, 0x9b,
+        0x4e, 0xf9, 0x31, 0x77, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// 3x1, 1-bit palette (black, white) with black made transparent
+    const PNG_PALETTE: [u8; 98] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+        0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x01, 0x03, 0x00, 0x00, 0x00, 0x21, 0x2e, 0x86,
+        0xf7, 0x00, 0x00, 0x00, 0x06, 0x50, 0x4c, 0x54, 0x45, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xa5,
+        0xd9, 0x9f, 0xdd, 0x00, 0x00, 0x00, 0x01, 0x74, 0x52, 0x4e, 0x53, 0x00, 0x40, 0xe6, 0xd8, 0x66,
+        0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x58, 0x00, 0x00, 0x00, 0xa2,
+        0x00, 0xa1, 0x71, 0x05, 0xcb, 0x41, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42,
+        0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_png_rgba_with_filters() {
+        let surface = decode(&PNG_RGBA).unwrap();
+        assert_eq!((surface.width(), surface.height()), (2, 2));
+        // Row 1 is Sub-filtered: (0,0,255,255) then deltas (10,10,10,0)
+        assert_eq!(surface.pixels(), &[0xFFFF0000, 0x8000FF00, 0xFF0000FF, 0xFF0A0A09]);
+    }
+
+    #[test]
+    fn test_png_palette_transparency() {
+        let surface = decode(&PNG_PALETTE).unwrap();
+        assert_eq!(surface.pixels(), &[0xFFFFFFFF, 0x00000000, 0xFFFFFFFF]);
+    }
+
+    #[test]
+    fn test_png_rejects_bad_data() {
+        assert_eq!(decode(&PNG_RGBA[..60]).err(), Some(ImageError::Truncated));
+        let mut corrupt = PNG_RGBA;
+        corrupt[50] ^= 0x40;
+        assert!(decode(&corrupt).is_err());
+        assert_eq!(decode(b"GIF89a").err(), Some(ImageError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_inflate_block_types() {
+        // Stored block
+        let stored = [0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(inflate::inflate(&stored, 16).unwrap(), b"abc");
+        assert_eq!(inflate::inflate(&stored, 2).err(), Some(ImageError::TooLarge));
+
+        // Dynamic Huffman block from zlib level 9
+        let dynamic = [
+            0x78, 0xda, 0x15, 0x8a, 0xc1, 0x0d, 0x00, 0x30, 0x10, 0x82, 0x56, 0xb9, 0xd5, 0xd0, 0xba, 0xff,
+            0x0a, 0x55, 0x3f, 0x24, 0x08, 0x87, 0x6d, 0x0c, 0x2f, 0xa5, 0x08, 0x07, 0x91, 0x50, 0xe7, 0x72,
+            0x1f, 0xf2, 0x64, 0xdb, 0xf9, 0x65, 0xd7, 0x24, 0x09, 0x1f, 0xfa, 0xf7, 0x17, 0x87,
+        ];
+        let out = inflate::zlib_decompress(&dynamic, 1024).unwrap();
+        assert_eq!(&out[..], b"a acccacaadeccabaea aaebbabbbbcbbaacaaabcaaeb acbabbcaba ebbeeea");
+    }
+
+    /// Minimal BITMAPINFOHEADER file around the given pixel rows
+    fn bmp(width: i32, height: i32, bpp: u16, palette: &[u8], rows: &[u8]) -> Vec<u8> {
+        let offset = 54 + palette.len() as u32;
+        let mut file = vec![b'B', b'M'];
+        file.extend_from_slice(&(offset + rows.len() as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&offset.to_le_bytes());
+        file.extend_from_slice(&40u32.to_le_bytes());
+        file.extend_from_slice(&width.to_le_bytes());
+        file.extend_from_slice(&height.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&bpp.to_le_bytes());
+        file.extend_from_slice(&[0; 24]);
+        file.extend_from_slice(palette);
+        file.extend_from_slice(rows);
+        file
+    }
+
+    #[test]
+    fn test_bmp_24bit_bottom_up() {
+        // 2x2, rows padded to 8 bytes, stored bottom row first
+        let rows = [
+            0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0, 0, // bottom: red, green
+            0xFF, 0x00, 0x00, 0x10, 0x20, 0x30, 0, 0, // top: blue, (0x30,0x20,0x10)
+        ];
+        let surface = decode(&bmp(2, 2, 24, &[], &rows)).unwrap();
+        assert_eq!(surface.pixels(), &[0xFF0000FF, 0xFF302010, 0xFFFF0000, 0xFF00FF00]);
+    }
+
+    #[test]
+    fn test_bmp_palettized_top_down() {
+        // colors_used is 0, so the table holds all 16 entries
+        let mut palette = vec![0u8; 64];
+        palette[4..8].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0]);
+        palette[8..12].copy_from_slice(&[0, 0, 0xFF, 0]);
+        // 3x1 at 4 bits: indices 1, 2, 0
+        let surface = decode(&bmp(3, -1, 4, &palette, &[0x12, 0x00, 0, 0])).unwrap();
+        assert_eq!(surface.pixels(), &[0xFFFFFFFF, 0xFFFF0000, 0xFF000000]);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let huge = bmp(1 << 15, 1 << 15, 24, &[], &[]);
+        assert_eq!(decode(&huge).err(), Some(ImageError::TooLarge));
+    }
+}
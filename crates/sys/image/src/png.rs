@@ -0,0 +1,247 @@
+//! PNG decoding
+//!
+//! Supports every non-interlaced color type and bit depth from the spec:
+//! grayscale, RGB, palette, grayscale+alpha and RGBA at 1 to 16 bits, with
+//! tRNS transparency. 16-bit samples keep their high byte. Ancillary chunks
+//! other than tRNS are skipped and CRCs are not checked; the zlib Adler-32
+//! still catches corrupt image data.
+
+use alloc::vec::Vec;
+
+use watos_gfx::Surface;
+
+use crate::inflate::zlib_decompress;
+use crate::{check_size, ImageError};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+struct Header {
+    width: u32,
+    height: u32,
+    depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            RGB => 3,
+            GRAY_ALPHA => 2,
+            RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes per complete pixel, at least 1 (the filter unit)
+    fn pixel_bytes(&self) -> usize {
+        (self.channels() * self.depth as usize).div_ceil(8)
+    }
+
+    /// Bytes per scanline, excluding the filter byte
+    fn row_bytes(&self) -> usize {
+        (self.width as usize * self.channels() * self.depth as usize).div_ceil(8)
+    }
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() != 13 {
+        return Err(ImageError::Corrupt);
+    }
+    let header = Header { width: be32(&data[0..]), height: be32(&data[4..]), depth: data[8], color_type: data[9] };
+    let valid_depth = match header.color_type {
+        GRAY => matches!(header.depth, 1 | 2 | 4 | 8 | 16),
+        PALETTE => matches!(header.depth, 1 | 2 | 4 | 8),
+        RGB | GRAY_ALPHA | RGBA => matches!(header.depth, 8 | 16),
+        _ => false,
+    };
+    if !valid_depth || data[10] != 0 || data[11] != 0 {
+        return Err(ImageError::Corrupt);
+    }
+    if data[12] != 0 {
+        // Adam7 interlacing
+        return Err(ImageError::Unsupported);
+    }
+    if header.width == 0 || header.height == 0 {
+        return Err(ImageError::Corrupt);
+    }
+    check_size(header.width, header.height)?;
+    Ok(header)
+}
+
+/// Decode a PNG file (starting with the 8-byte signature)
+pub fn decode(data: &[u8]) -> Result<Surface, ImageError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(ImageError::UnknownFormat);
+    }
+
+    let mut header = None;
+    let mut palette: Vec<u32> = Vec::new();
+    let mut trns: &[u8] = &[];
+    let mut idat = Vec::new();
+
+    let mut pos = SIGNATURE.len();
+    loop {
+        let chunk = data.get(pos..pos + 8).ok_or(ImageError::Truncated)?;
+        let len = be32(chunk) as usize;
+        let kind = &chunk[4..8];
+        let body = data.get(pos + 8..pos + 8 + len).ok_or(ImageError::Truncated)?;
+        pos += 12 + len;
+
+        match kind {
+            b"IHDR" => header = Some(parse_header(body)?),
+            b"PLTE" => {
+                if !len.is_multiple_of(3) || len > 256 * 3 {
+                    return Err(ImageError::Corrupt);
+                }
+                palette = body
+                    .chunks_exact(3)
+                    .map(|c| 0xFF00_0000 | (c[0] as u32) << 16 | (c[1] as u32) << 8 | c[2] as u32)
+                    .collect();
+            }
+            b"tRNS" => trns = body,
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ if kind[0] & 0x20 == 0 => {
+                // Unknown critical chunk
+                return Err(ImageError::Unsupported);
+            }
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(ImageError::Corrupt)?;
+    if header.color_type == PALETTE {
+        if palette.is_empty() {
+            return Err(ImageError::Corrupt);
+        }
+        for (entry, &alpha) in palette.iter_mut().zip(trns) {
+            *entry = (*entry & 0x00FF_FFFF) | (alpha as u32) << 24;
+        }
+    }
+
+    let row_bytes = header.row_bytes();
+    let expected = (row_bytes + 1) * header.height as usize;
+    let mut raw = zlib_decompress(&idat, expected)?;
+    if raw.len() != expected {
+        return Err(ImageError::Truncated);
+    }
+    unfilter(&mut raw, row_bytes, header.pixel_bytes(), header.height as usize)?;
+
+    let mut pixels = Vec::with_capacity(header.width as usize * header.height as usize);
+    for row in raw.chunks_exact(row_bytes + 1) {
+        convert_row(&header, &row[1..], &palette, trns, &mut pixels);
+    }
+    Surface::from_pixels(pixels, header.width, header.height).ok_or(ImageError::Corrupt)
+}
+
+/// Undo the per-row filters in place; each row keeps its filter byte
+fn unfilter(raw: &mut [u8], row_bytes: usize, bpp: usize, height: usize) -> Result<(), ImageError> {
+    let stride = row_bytes + 1;
+    for y in 0..height {
+        let (before, rest) = raw.split_at_mut(y * stride);
+        let prev = if y == 0 { None } else { Some(&before[before.len() - row_bytes..]) };
+        let filter = rest[0];
+        let row = &mut rest[1..stride];
+
+        for x in 0..row_bytes {
+            let a = if x >= bpp { row[x - bpp] } else { 0 };
+            let b = prev.map_or(0, |p| p[x]);
+            let c = if x >= bpp { prev.map_or(0, |p| p[x - bpp]) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Corrupt),
+            };
+            row[x] = row[x].wrapping_add(predicted);
+        }
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Sample `index` of a row, as read at the image's bit depth (unscaled)
+fn sample(row: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+/// Scale a sample to 8 bits
+fn to_u8(value: u16, depth: u8) -> u8 {
+    match depth {
+        1 => (value * 0xFF) as u8,
+        2 => (value * 0x55) as u8,
+        4 => (value * 0x11) as u8,
+        8 => value as u8,
+        _ => (value >> 8) as u8,
+    }
+}
+
+fn convert_row(header: &Header, row: &[u8], palette: &[u32], trns: &[u8], out: &mut Vec<u32>) {
+    let depth = header.depth;
+    // Color key from tRNS for gray and RGB images, at the image's bit depth
+    let key = |i: usize| trns.get(i * 2..i * 2 + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    for x in 0..header.width as usize {
+        let pixel = match header.color_type {
+            GRAY => {
+                let v = sample(row, x, depth);
+                let g = to_u8(v, depth) as u32;
+                let a = if key(0) == Some(v) { 0 } else { 0xFF };
+                a << 24 | g << 16 | g << 8 | g
+            }
+            RGB => {
+                let (r, g, b) = (sample(row, x * 3, depth), sample(row, x * 3 + 1, depth), sample(row, x * 3 + 2, depth));
+                let keyed = trns.len() >= 6 && key(0) == Some(r) && key(1) == Some(g) && key(2) == Some(b);
+                let a = if keyed { 0 } else { 0xFF };
+                a << 24 | (to_u8(r, depth) as u32) << 16 | (to_u8(g, depth) as u32) << 8 | to_u8(b, depth) as u32
+            }
+            PALETTE => {
+                let index = sample(row, x, depth) as usize;
+                palette.get(index).copied().unwrap_or(0xFF00_0000)
+            }
+            GRAY_ALPHA => {
+                let g = to_u8(sample(row, x * 2, depth), depth) as u32;
+                let a = to_u8(sample(row, x * 2 + 1, depth), depth) as u32;
+                a << 24 | g << 16 | g << 8 | g
+            }
+            _ => {
+                let c = |i| to_u8(sample(row, x * 4 + i, depth), depth) as u32;
+                c(3) << 24 | c(0) << 16 | c(1) << 8 | c(2)
+            }
+        };
+        out.push(pixel);
+    }
+}
@@ -0,0 +1,515 @@
+//! WATOS Keyring
+//!
+//! Per-user secrets (API tokens, wifi keys) behind `SYS_KEY_ADD`,
+//! `SYS_KEY_READ` and `SYS_KEY_DELETE`, so services need not keep
+//! credentials in plaintext files.
+//!
+//! Each user has a ring of named keys. A ring is encrypted under keys
+//! derived from the user's password with PBKDF2-HMAC-SHA256, and is kept
+//! in `/etc/keyring/<uid>` in that form:
+//!
+//! ```text
+//! "WKR1"  salt[16]  iterations:u32  verifier[32]  count:u32
+//! then per key:  name_len:u16  name  nonce[12]  len:u32  ciphertext  tag[32]
+//! ```
+//!
+//! Values are encrypted with ChaCha20 and authenticated with HMAC-SHA256
+//! over the nonce, name and ciphertext. The verifier is an HMAC of a fixed
+//! string, so a wrong password is refused instead of yielding garbage.
+//!
+//! A ring is unlocked when its user logs in (SYS_AUTHENTICATE) and stays
+//! unlocked until [`lock`] or reboot; the derived keys only live in kernel
+//! memory. Only the owner can add or read keys; root may also delete them
+//! but, without the password, not read them. Changing a password leaves
+//! the old ring unreadable.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use watos_crypto::chacha20::NONCE_LEN;
+use watos_crypto::{ct_eq, hmac_sha256, pbkdf2_hmac_sha256, wipe, ChaCha20, HmacSha256, Sha256};
+use watos_vfs::{FileMode, VfsError};
+
+/// Longest key name in bytes
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Largest key value in bytes
+pub const MAX_KEY_SIZE: usize = 4096;
+
+/// Most keys in one ring
+pub const MAX_KEYS: usize = 64;
+
+/// Directory holding one ring file per UID
+pub const KEYRING_DIR: &str = "/etc/keyring";
+
+/// PBKDF2 iterations for new rings (existing rings keep theirs)
+pub const KDF_ITERATIONS: u32 = 20_000;
+
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+const MAGIC: &[u8; 4] = b"WKR1";
+
+/// Why a keyring operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyError {
+    /// The ring's user hasn't logged in since boot (or it was locked)
+    Locked,
+    /// The password doesn't match the ring
+    WrongPassword,
+    /// No key by that name
+    NotFound,
+    /// Caller may not touch this ring
+    PermissionDenied,
+    /// Empty or longer than MAX_NAME_LEN
+    BadName,
+    /// Value longer than MAX_KEY_SIZE, or the ring is full
+    TooLarge,
+    /// A ring file or key failed its checks
+    Corrupted,
+    /// Reading or writing the ring file failed
+    Io(VfsError),
+}
+
+// ============================================================================
+// Ring
+// ============================================================================
+
+/// Encryption and MAC keys derived from a password
+struct Secret {
+    enc: [u8; 32],
+    mac: [u8; 32],
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        wipe(&mut self.enc);
+        wipe(&mut self.mac);
+    }
+}
+
+/// One encrypted key
+#[derive(Clone)]
+struct Entry {
+    name: String,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+    tag: [u8; TAG_LEN],
+}
+
+/// One user's keys
+pub struct Ring {
+    uid: u32,
+    salt: [u8; SALT_LEN],
+    iterations: u32,
+    /// Set by the first unlock of a new ring
+    verifier: Option<[u8; 32]>,
+    secret: Option<Secret>,
+    entries: Vec<Entry>,
+}
+
+impl Ring {
+    /// An empty ring; the first [`Ring::unlock`] sets its password
+    pub fn new(uid: u32, salt: [u8; SALT_LEN]) -> Self {
+        Ring { uid, salt, iterations: KDF_ITERATIONS, verifier: None, secret: None, entries: Vec::new() }
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Derive the ring's keys from `password`
+    pub fn unlock(&mut self, password: &[u8]) -> Result<(), KeyError> {
+        let mut master = [0u8; 32];
+        pbkdf2_hmac_sha256(password, &self.salt, self.iterations, &mut master);
+        let verifier = hmac_sha256(&master, b"watos-keyring verify");
+        match self.verifier {
+            Some(expected) if !ct_eq(&expected, &verifier) => {
+                wipe(&mut master);
+                return Err(KeyError::WrongPassword);
+            }
+            Some(_) => {}
+            None => self.verifier = Some(verifier),
+        }
+        self.secret = Some(Secret {
+            enc: hmac_sha256(&master, b"watos-keyring enc"),
+            mac: hmac_sha256(&master, b"watos-keyring mac"),
+        });
+        wipe(&mut master);
+        Ok(())
+    }
+
+    /// Forget the derived keys
+    pub fn lock(&mut self) {
+        self.secret = None;
+    }
+
+    /// Names of the keys, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.name.as_str())
+    }
+
+    /// Store `data` under `name`, replacing any key of that name
+    ///
+    /// `nonce` must never have been used with this ring before.
+    pub fn add(&mut self, name: &str, data: &[u8], nonce: [u8; NONCE_LEN]) -> Result<(), KeyError> {
+        check_name(name)?;
+        if data.len() > MAX_KEY_SIZE {
+            return Err(KeyError::TooLarge);
+        }
+        let secret = self.secret.as_ref().ok_or(KeyError::Locked)?;
+        let existing = self.entries.iter().position(|e| e.name == name);
+        if existing.is_none() && self.entries.len() >= MAX_KEYS {
+            return Err(KeyError::TooLarge);
+        }
+
+        let mut ciphertext = Vec::from(data);
+        ChaCha20::new(&secret.enc, &nonce, 1).apply(&mut ciphertext);
+        let tag = entry_tag(secret, name, &nonce, &ciphertext);
+        let entry = Entry { name: String::from(name), nonce, ciphertext, tag };
+        match existing {
+            Some(i) => self.entries[i] = entry,
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    /// Decrypt the key `name` into `out`
+    ///
+    /// Returns the key's length; if `out` is too short nothing is copied.
+    pub fn read(&self, name: &str, out: &mut [u8]) -> Result<usize, KeyError> {
+        let secret = self.secret.as_ref().ok_or(KeyError::Locked)?;
+        let entry = self.entries.iter().find(|e| e.name == name).ok_or(KeyError::NotFound)?;
+        if !ct_eq(&entry.tag, &entry_tag(secret, name, &entry.nonce, &entry.ciphertext)) {
+            return Err(KeyError::Corrupted);
+        }
+        let len = entry.ciphertext.len();
+        if let Some(out) = out.get_mut(..len) {
+            out.copy_from_slice(&entry.ciphertext);
+            ChaCha20::new(&secret.enc, &entry.nonce, 1).apply(out);
+        }
+        Ok(len)
+    }
+
+    /// Remove the key `name`; works while locked
+    pub fn delete(&mut self, name: &str) -> Result<(), KeyError> {
+        let i = self.entries.iter().position(|e| e.name == name).ok_or(KeyError::NotFound)?;
+        self.entries.remove(i);
+        Ok(())
+    }
+
+    /// The ring in its file format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&self.iterations.to_le_bytes());
+        out.extend_from_slice(&self.verifier.unwrap_or([0; 32]));
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for e in &self.entries {
+            out.extend_from_slice(&(e.name.len() as u16).to_le_bytes());
+            out.extend_from_slice(e.name.as_bytes());
+            out.extend_from_slice(&e.nonce);
+            out.extend_from_slice(&(e.ciphertext.len() as u32).to_le_bytes());
+            out.extend_from_slice(&e.ciphertext);
+            out.extend_from_slice(&e.tag);
+        }
+        out
+    }
+
+    /// Read a ring file; it stays locked
+    pub fn from_bytes(uid: u32, bytes: &[u8]) -> Result<Self, KeyError> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            return Err(KeyError::Corrupted);
+        }
+        let salt = r.array()?;
+        let iterations = u32::from_le_bytes(r.array()?);
+        let verifier: [u8; 32] = r.array()?;
+        let count = u32::from_le_bytes(r.array()?) as usize;
+        if iterations == 0 || count > MAX_KEYS {
+            return Err(KeyError::Corrupted);
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name_len = u16::from_le_bytes(r.array()?) as usize;
+            let name = core::str::from_utf8(r.take(name_len)?).map_err(|_| KeyError::Corrupted)?;
+            check_name(name).map_err(|_| KeyError::Corrupted)?;
+            let nonce = r.array()?;
+            let len = u32::from_le_bytes(r.array()?) as usize;
+            if len > MAX_KEY_SIZE {
+                return Err(KeyError::Corrupted);
+            }
+            let ciphertext = Vec::from(r.take(len)?);
+            let tag = r.array()?;
+            entries.push(Entry { name: String::from(name), nonce, ciphertext, tag });
+        }
+        if !r.0.is_empty() {
+            return Err(KeyError::Corrupted);
+        }
+
+        let verifier = (verifier != [0; 32]).then_some(verifier);
+        Ok(Ring { uid, salt, iterations, verifier, secret: None, entries })
+    }
+}
+
+fn check_name(name: &str) -> Result<(), KeyError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(KeyError::BadName);
+    }
+    Ok(())
+}
+
+fn entry_tag(secret: &Secret, name: &str, nonce: &[u8; NONCE_LEN], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+    let mut mac = HmacSha256::new(&secret.mac);
+    mac.update(nonce);
+    mac.update(&(name.len() as u16).to_le_bytes());
+    mac.update(name.as_bytes());
+    mac.update(ciphertext);
+    mac.finish()
+}
+
+/// Cursor over a ring file
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], KeyError> {
+        if self.0.len() < n {
+            return Err(KeyError::Corrupted);
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], KeyError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+// ============================================================================
+// Global keyring
+// ============================================================================
+
+/// Rings loaded since boot
+static RINGS: Mutex<Vec<Ring>> = Mutex::new(Vec::new());
+
+/// Pool the random generator hashes forward
+static POOL: Mutex<[u8; 32]> = Mutex::new([0; 32]);
+
+/// Fill `out` with random bytes
+///
+/// A SHA-256 pool stirred with RDRAND when the CPU has it, and the TSC
+/// always. Without RDRAND the output is only as unpredictable as the
+/// timing of calls. Also behind SYS_GETRANDOM.
+pub fn random_bytes(out: &mut [u8]) {
+    let mut pool = POOL.lock();
+    for chunk in out.chunks_mut(32) {
+        let mut h = Sha256::new();
+        h.update(&*pool);
+        h.update(&watos_arch::cpu::rdtsc().to_le_bytes());
+        if let Some(r) = watos_arch::cpu::rdrand() {
+            h.update(&r.to_le_bytes());
+        }
+        *pool = h.finish();
+        let block = hmac_sha256(&*pool, b"watos-keyring random");
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
+
+fn ring_path(uid: u32) -> String {
+    format!("{}/{}", KEYRING_DIR, uid)
+}
+
+/// Index of `uid`'s ring, loading it from disk or creating it as needed
+fn ring_index(rings: &mut Vec<Ring>, uid: u32) -> Result<usize, KeyError> {
+    if let Some(i) = rings.iter().position(|r| r.uid == uid) {
+        return Ok(i);
+    }
+    let ring = match read_file(&ring_path(uid)) {
+        Ok(bytes) => Ring::from_bytes(uid, &bytes)?,
+        Err(VfsError::NotFound) => {
+            let mut salt = [0u8; SALT_LEN];
+            random_bytes(&mut salt);
+            Ring::new(uid, salt)
+        }
+        Err(e) => return Err(KeyError::Io(e)),
+    };
+    rings.push(ring);
+    Ok(rings.len() - 1)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, VfsError> {
+    let mut file = watos_vfs::open(path, FileMode::READ)?;
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Write a ring to its file, readable by root only
+fn save(ring: &Ring) -> Result<(), KeyError> {
+    let io = KeyError::Io;
+    match watos_vfs::mkdir(KEYRING_DIR) {
+        Ok(()) => restrict(KEYRING_DIR, 0o700)?,
+        Err(VfsError::AlreadyExists) => {}
+        Err(e) => return Err(io(e)),
+    }
+
+    let path = ring_path(ring.uid);
+    let bytes = ring.to_bytes();
+    let mut file = watos_vfs::open(&path, FileMode::WRITE).map_err(io)?;
+    let mut done = 0;
+    while done < bytes.len() {
+        match file.write(&bytes[done..]).map_err(io)? {
+            0 => return Err(io(VfsError::NoSpace)),
+            n => done += n,
+        }
+    }
+    file.sync().map_err(io)?;
+    restrict(&path, 0o600)
+}
+
+/// Give `path` to root with `mode`, where the filesystem has permissions
+fn restrict(path: &str, mode: u32) -> Result<(), KeyError> {
+    for result in [watos_vfs::chown(path, 0, 0), watos_vfs::chmod(path, mode)] {
+        match result {
+            Ok(()) | Err(VfsError::NotSupported) => {}
+            Err(e) => return Err(KeyError::Io(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Unlock `uid`'s ring with their password, creating it if they have none
+pub fn unlock(uid: u32, password: &[u8]) -> Result<(), KeyError> {
+    let mut rings = RINGS.lock();
+    let i = ring_index(&mut rings, uid)?;
+    let ring = &mut rings[i];
+    let new = ring.verifier.is_none();
+    ring.unlock(password)?;
+    if new {
+        save(ring)?;
+    }
+    Ok(())
+}
+
+/// Lock `uid`'s ring until they next log in
+pub fn lock(uid: u32) {
+    if let Some(ring) = RINGS.lock().iter_mut().find(|r| r.uid == uid) {
+        ring.lock();
+    }
+}
+
+/// Store a key in the caller's own ring
+pub fn add(caller: u32, name: &str, data: &[u8]) -> Result<(), KeyError> {
+    let mut rings = RINGS.lock();
+    let i = ring_index(&mut rings, caller)?;
+    let ring = &mut rings[i];
+    let old = ring.entries.clone();
+
+    let mut nonce = [0u8; NONCE_LEN];
+    random_bytes(&mut nonce);
+    ring.add(name, data, nonce)?;
+    // Keep memory and disk the same: undo the add if it can't be saved
+    save(ring).inspect_err(|_| ring.entries = old)
+}
+
+/// Read a key from the caller's own ring (see [`Ring::read`])
+pub fn read(caller: u32, name: &str, out: &mut [u8]) -> Result<usize, KeyError> {
+    let mut rings = RINGS.lock();
+    let i = ring_index(&mut rings, caller)?;
+    rings[i].read(name, out)
+}
+
+/// Delete a key from `owner`'s ring; root may delete anyone's keys
+pub fn delete(caller: u32, owner: u32, name: &str) -> Result<(), KeyError> {
+    if caller != owner && caller != 0 {
+        return Err(KeyError::PermissionDenied);
+    }
+    let mut rings = RINGS.lock();
+    let i = ring_index(&mut rings, owner)?;
+    let ring = &mut rings[i];
+    let old = ring.entries.clone();
+    ring.delete(name)?;
+    save(ring).inspect_err(|_| ring.entries = old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlocked(password: &[u8]) -> Ring {
+        let mut ring = Ring::new(1000, [7; SALT_LEN]);
+        ring.iterations = 2;
+        ring.unlock(password).unwrap();
+        ring
+    }
+
+    #[test]
+    fn test_add_read_delete() {
+        let mut ring = unlocked(b"guest");
+        ring.add("wifi/home", b"correct horse", [1; NONCE_LEN]).unwrap();
+        ring.add("api/token", b"abc123", [2; NONCE_LEN]).unwrap();
+        ring.add("wifi/home", b"battery staple", [3; NONCE_LEN]).unwrap();
+        assert_eq!(ring.names().collect::<Vec<_>>(), ["wifi/home", "api/token"]);
+
+        let mut buf = [0u8; 32];
+        let n = ring.read("wifi/home", &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"battery staple");
+        assert_eq!(ring.read("wifi/home", &mut [0u8; 4]), Ok(14));
+        assert_eq!(ring.read("missing", &mut buf), Err(KeyError::NotFound));
+
+        // Values are not stored in the clear
+        let bytes = ring.to_bytes();
+        assert!(!bytes.windows(6).any(|w| w == b"abc123"));
+
+        ring.lock();
+        assert_eq!(ring.read("api/token", &mut buf), Err(KeyError::Locked));
+        assert_eq!(ring.add("x", b"y", [4; NONCE_LEN]), Err(KeyError::Locked));
+        ring.delete("api/token").unwrap();
+        assert_eq!(ring.delete("api/token"), Err(KeyError::NotFound));
+
+        assert_eq!(ring.add("", b"y", [4; NONCE_LEN]), Err(KeyError::BadName));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let mut ring = unlocked(b"guest");
+        ring.add("api/token", b"abc123", [2; NONCE_LEN]).unwrap();
+        let bytes = ring.to_bytes();
+
+        let mut loaded = Ring::from_bytes(1000, &bytes).unwrap();
+        assert!(!loaded.is_unlocked());
+        assert_eq!(loaded.unlock(b"wrong"), Err(KeyError::WrongPassword));
+        loaded.unlock(b"guest").unwrap();
+        let mut buf = [0u8; 16];
+        let n = loaded.read("api/token", &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"abc123");
+
+        // Tampering with a value is caught
+        let mut bad = bytes.clone();
+        let last = bad.len() - TAG_LEN - 1;
+        bad[last] ^= 1;
+        let mut loaded = Ring::from_bytes(1000, &bad).unwrap();
+        loaded.unlock(b"guest").unwrap();
+        assert_eq!(loaded.read("api/token", &mut buf), Err(KeyError::Corrupted));
+
+        assert!(Ring::from_bytes(1000, &bytes[..bytes.len() - 1]).is_err());
+        assert!(Ring::from_bytes(1000, b"nope").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! ELF64 dynamic linking structures
+//!
+//! Only what the loader reads: headers to map a shared object, and the
+//! dynamic section, symbol table and relocations to link it.
+
+// ============================================================================
+// HEADERS
+// ============================================================================
+
+/// ELF64 file header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ehdr {
+    pub ident: [u8; 16],
+    pub etype: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub flags: u32,
+    pub ehsize: u16,
+    pub phentsize: u16,
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+/// ELF64 program header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Phdr {
+    pub ptype: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+/// Dynamic section entry
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Dyn {
+    pub tag: i64,
+    pub val: u64,
+}
+
+/// Symbol table entry
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Sym {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub shndx: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Sym {
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    pub fn is_defined(&self) -> bool {
+        self.shndx != SHN_UNDEF
+    }
+}
+
+/// Relocation with addend
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rela {
+    pub offset: u64,
+    pub info: u64,
+    pub addend: i64,
+}
+
+impl Rela {
+    pub fn rtype(&self) -> u32 {
+        self.info as u32
+    }
+
+    pub fn sym(&self) -> usize {
+        (self.info >> 32) as usize
+    }
+}
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+pub const ELFCLASS64: u8 = 2;
+pub const ELFDATA2LSB: u8 = 1;
+pub const EM_X86_64: u16 = 0x3E;
+pub const ET_DYN: u16 = 3;
+
+// Program header types
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_TLS: u32 = 7;
+
+// Dynamic tags
+pub const DT_NULL: i64 = 0;
+pub const DT_NEEDED: i64 = 1;
+pub const DT_PLTRELSZ: i64 = 2;
+pub const DT_HASH: i64 = 4;
+pub const DT_STRTAB: i64 = 5;
+pub const DT_SYMTAB: i64 = 6;
+pub const DT_RELA: i64 = 7;
+pub const DT_RELASZ: i64 = 8;
+pub const DT_STRSZ: i64 = 10;
+pub const DT_INIT: i64 = 12;
+pub const DT_SONAME: i64 = 14;
+pub const DT_REL: i64 = 17;
+pub const DT_JMPREL: i64 = 23;
+pub const DT_INIT_ARRAY: i64 = 25;
+pub const DT_INIT_ARRAYSZ: i64 = 27;
+pub const DT_GNU_HASH: i64 = 0x6FFF_FEF5;
+
+// Symbol bindings
+pub const STB_LOCAL: u8 = 0;
+pub const STB_WEAK: u8 = 2;
+
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_ABS: u16 = 0xFFF1;  // Absolute value, not moved by the load bias
+
+// x86-64 relocation types
+pub const R_X86_64_NONE: u32 = 0;
+pub const R_X86_64_64: u32 = 1;
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_COPY: u32 = 5;
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+pub const R_X86_64_RELATIVE: u32 = 8;
+pub const R_X86_64_IRELATIVE: u32 = 37;
+
+// ============================================================================
+// HASH FUNCTIONS
+// ============================================================================
+
+/// SysV ELF hash (DT_HASH)
+pub fn elf_hash(name: &[u8]) -> u32 {
+    let mut h: u32 = 0;
+    for &c in name {
+        h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xF000_0000;
+        if g != 0 {
+            h ^= g >> 24;
+        }
+        h &= !g;
+    }
+    h
+}
+
+/// GNU hash (DT_GNU_HASH)
+pub fn gnu_hash(name: &[u8]) -> u32 {
+    let mut h: u32 = 5381;
+    for &c in name {
+        h = h.wrapping_mul(33).wrapping_add(c as u32);
+    }
+    h
+}
+
+// ============================================================================
+// COMPILE-TIME CHECKS
+// ============================================================================
+
+const _: () = assert!(core::mem::size_of::<Ehdr>() == 64);
+const _: () = assert!(core::mem::size_of::<Phdr>() == 56);
+const _: () = assert!(core::mem::size_of::<Sym>() == 24);
+const _: () = assert!(core::mem::size_of::<Rela>() == 24);
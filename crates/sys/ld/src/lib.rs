@@ -0,0 +1,40 @@
+//! WATOS Dynamic Linking
+//!
+//! The linking half of `ld-watos`, the program interpreter: everything
+//! that doesn't touch the kernel, so it can be tested on the host.
+//!
+//! - Mapping a shared object file's segments into allocated memory
+//! - Reading dynamic sections, and symbol lookup by DT_GNU_HASH or DT_HASH
+//! - x86-64 RELA relocations: RELATIVE, 64, GLOB_DAT, JUMP_SLOT, PC32,
+//!   COPY and IRELATIVE, all bound eagerly
+//! - DT_INIT / DT_INIT_ARRAY, dependencies before the objects needing them
+//! - dlopen / dlsym / dlclose / dlerror over a process-wide [`Linker`]
+//!
+//! Not supported: REL relocations, TLS, symbol versioning, unloading.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use watos_ld::{Linker, Host, Object};
+//!
+//! let mut linker = Linker::new();
+//! unsafe {
+//!     let program = Object::from_phdrs(b"", bias, phdrs)?;
+//!     let loader = Object::from_dynamic(b"ld-watos", ld_bias, ld_dynamic)?;
+//!     if linker.start(&mut host, program, loader) {
+//!         // jump to the program's entry point
+//!     }
+//! }
+//! ```
+
+#![no_std]
+
+pub mod elf;
+mod linker;
+mod object;
+
+#[cfg(test)]
+mod tests;
+
+pub use linker::{Host, Linker, ERROR_MAX, MAX_OBJECTS, SEARCH_PATH};
+pub use object::{map, Mapping, Object, MAX_NEEDED, NAME_MAX};
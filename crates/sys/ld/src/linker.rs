@@ -0,0 +1,428 @@
+//! The link map
+//!
+//! A [`Linker`] holds every object in the process in load order: the
+//! program first, then the loader, then libraries breadth-first by
+//! DT_NEEDED. Objects loaded at startup, and dlopen'd ones with `global`,
+//! form the global scope that symbol references are resolved against, in
+//! that order. A dlopen'd object and the libraries it pulled in form a
+//! group that also sees itself.
+//!
+//! Objects are never unloaded: `dlclose` only drops a reference, as if
+//! everything were opened RTLD_NODELETE.
+
+use crate::elf::{self, Rela, Sym};
+use crate::object::{self, Object};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Most objects in one process
+pub const MAX_OBJECTS: usize = 32;
+
+/// Directories searched for a library named without a '/'
+pub const SEARCH_PATH: &[&str] = &["/lib", "/apps/lib"];
+
+/// Longest dlerror message
+pub const ERROR_MAX: usize = 128;
+
+/// Group of the objects loaded at startup
+const STARTUP_GROUP: u32 = 0;
+
+// ============================================================================
+// HOST
+// ============================================================================
+
+/// What the linker needs from its environment
+pub trait Host {
+    /// Read a whole file into memory that stays valid until the next
+    /// read_file; None if it can't be read
+    fn read_file(&mut self, path: &str) -> Option<&[u8]>;
+
+    /// Writable, executable memory for a library's segments
+    fn alloc(&mut self, size: usize) -> Option<*mut u8>;
+}
+
+// ============================================================================
+// LINKER
+// ============================================================================
+
+#[derive(Clone, Copy)]
+struct Entry {
+    object: Object,
+    group: u32,
+    global: bool,
+    relocated: bool,
+    initialized: bool,
+    refs: u32,
+}
+
+/// Every object in the process and the last error
+pub struct Linker {
+    entries: [Option<Entry>; MAX_OBJECTS],
+    count: usize,
+    next_group: u32,
+    error: [u8; ERROR_MAX],
+    error_len: usize,
+}
+
+impl Linker {
+    pub const fn new() -> Self {
+        Linker {
+            entries: [None; MAX_OBJECTS],
+            count: 0,
+            next_group: STARTUP_GROUP + 1,
+            error: [0; ERROR_MAX],
+            error_len: 0,
+        }
+    }
+
+    /// Objects in load order
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.entries[..self.count].iter().flatten().map(|e| &e.object)
+    }
+
+    /// Link a program at startup
+    ///
+    /// `program` is the dynamically linked program; `loader` is the
+    /// already-relocated loader, which libraries may link against (it
+    /// provides dlopen and friends). Loads every needed library, relocates
+    /// them and the program, and runs initializers, libraries first.
+    ///
+    /// # Safety
+    /// Both objects must describe mapped images; initializers run.
+    pub unsafe fn start<H: Host>(&mut self, host: &mut H, program: Object, loader: Object) -> bool {
+        self.add(program, STARTUP_GROUP, true);
+        let loader_index = self.add(loader, STARTUP_GROUP, true);
+        if let Some(Some(entry)) = loader_index.map(|i| &mut self.entries[i]) {
+            entry.relocated = true;
+            entry.initialized = true;
+        }
+
+        self.load_needed(host, 0) && self.relocate(0) && {
+            self.run_init(0);
+            true
+        }
+    }
+
+    /// Open a library by name or path, loading it and its dependencies if
+    /// needed; returns a handle for `dlsym`
+    ///
+    /// With `global`, its symbols join the global scope.
+    ///
+    /// # Safety
+    /// Runs the library's initializers.
+    pub unsafe fn dlopen<H: Host>(&mut self, host: &mut H, name: &[u8], global: bool) -> Option<usize> {
+        if let Some(index) = self.find(name) {
+            let entry = self.entries[index].as_mut()?;
+            entry.refs += 1;
+            entry.global |= global;
+            return Some(index + 1);
+        }
+
+        let first = self.count;
+        let group = self.next_group;
+        let object = self.open(host, name)?;
+        self.add(object, group, global)?;
+        self.next_group += 1;
+
+        if !(self.load_needed(host, first) && self.relocate(first)) {
+            // Forget the half-linked objects; their memory is not reclaimed
+            for slot in &mut self.entries[first..self.count] {
+                *slot = None;
+            }
+            self.count = first;
+            return None;
+        }
+        self.run_init(first);
+        Some(first + 1)
+    }
+
+    /// Address of `name`, searched for in the object behind `handle` and
+    /// its dependencies, or in the global scope if `handle` is 0
+    ///
+    /// # Safety
+    /// The objects must still be mapped.
+    pub unsafe fn dlsym(&mut self, handle: usize, name: &[u8]) -> Option<u64> {
+        let found = if handle == 0 {
+            self.resolve(None, name)
+        } else {
+            match self.entries.get(handle - 1).copied().flatten() {
+                Some(entry) => {
+                    let own = entry.object.lookup(name).map(|sym| (entry.object, sym));
+                    own.or_else(|| self.search(name, |e| e.group == entry.group))
+                }
+                None => {
+                    self.fail(b"invalid handle", b"");
+                    return None;
+                }
+            }
+        };
+        match found {
+            Some((object, sym)) => Some(object.address(&sym)),
+            None => {
+                self.fail(b"undefined symbol", name);
+                None
+            }
+        }
+    }
+
+    /// Drop a reference taken by `dlopen`; false for a bad handle
+    pub fn dlclose(&mut self, handle: usize) -> bool {
+        match handle.checked_sub(1).and_then(|i| self.entries.get_mut(i)) {
+            Some(Some(entry)) => {
+                entry.refs = entry.refs.saturating_sub(1);
+                true
+            }
+            _ => {
+                self.fail(b"invalid handle", b"");
+                false
+            }
+        }
+    }
+
+    /// The last error, cleared by reading it
+    pub fn take_error(&mut self) -> Option<&[u8]> {
+        let len = core::mem::take(&mut self.error_len);
+        (len > 0).then(|| &self.error[..len])
+    }
+
+    // ========================================================================
+    // LOADING
+    // ========================================================================
+
+    fn add(&mut self, object: Object, group: u32, global: bool) -> Option<usize> {
+        if self.count == MAX_OBJECTS {
+            self.fail(b"too many objects", object.name());
+            return None;
+        }
+        self.entries[self.count] = Some(Entry {
+            object,
+            group,
+            global,
+            relocated: false,
+            initialized: false,
+            refs: 1,
+        });
+        self.count += 1;
+        Some(self.count - 1)
+    }
+
+    /// Index of a loaded object by name, soname or path
+    unsafe fn find(&self, name: &[u8]) -> Option<usize> {
+        let base = name.rsplit(|&c| c == b'/').next().unwrap_or(name);
+        (0..self.count).find(|&i| {
+            self.entries[i].as_ref().is_some_and(|e| {
+                e.object.answers_to(name) || e.object.answers_to(base)
+            })
+        })
+    }
+
+    /// Load the DT_NEEDED libraries of objects from `first` on, and theirs
+    unsafe fn load_needed<H: Host>(&mut self, host: &mut H, first: usize) -> bool {
+        let mut index = first;
+        while index < self.count {
+            let Some(entry) = self.entries[index] else {
+                index += 1;
+                continue;
+            };
+            let mut i = 0;
+            while let Some(name) = entry.object.needed(i) {
+                i += 1;
+                if let Some(found) = self.find(name) {
+                    // Already loaded privately by a dlopen: a global load makes it global
+                    if let Some(existing) = self.entries[found].as_mut() {
+                        existing.global |= entry.global;
+                    }
+                    continue;
+                }
+                let Some(object) = self.open(host, name) else {
+                    return false;
+                };
+                if self.add(object, entry.group, entry.global).is_none() {
+                    return false;
+                }
+            }
+            index += 1;
+        }
+        true
+    }
+
+    /// Map a library found by name (searching SEARCH_PATH) or path
+    unsafe fn open<H: Host>(&mut self, host: &mut H, name: &[u8]) -> Option<Object> {
+        self.error_len = 0;
+        let mut path = [0u8; 256];
+        let found = if name.contains(&b'/') {
+            self.try_open(host, name, name)
+        } else {
+            SEARCH_PATH.iter().find_map(|dir| {
+                let len = dir.len() + 1 + name.len();
+                if len > path.len() {
+                    return None;
+                }
+                path[..dir.len()].copy_from_slice(dir.as_bytes());
+                path[dir.len()] = b'/';
+                path[dir.len() + 1..len].copy_from_slice(name);
+                self.try_open(host, name, &path[..len])
+            })
+        };
+        if found.is_none() && self.error_len == 0 {
+            self.fail(b"cannot find library", name);
+        }
+        found
+    }
+
+    unsafe fn try_open<H: Host>(&mut self, host: &mut H, name: &[u8], path: &[u8]) -> Option<Object> {
+        let path = core::str::from_utf8(path).ok()?;
+        let file = host.read_file(path)?;
+        // Valid until the next read_file, so it may outlive the borrow of host
+        let file = core::slice::from_raw_parts(file.as_ptr(), file.len());
+
+        let base = name.rsplit(|&c| c == b'/').next().unwrap_or(name);
+        let result = object::map(file, &mut |size| host.alloc(size))
+            .and_then(|m| Object::from_dynamic(base, m.bias, m.dynamic));
+        match result {
+            Ok(object) => Some(object),
+            Err(why) => {
+                self.fail(why.as_bytes(), name);
+                None
+            }
+        }
+    }
+
+    // ========================================================================
+    // RELOCATION
+    // ========================================================================
+
+    /// Relocate objects from `first` on
+    unsafe fn relocate(&mut self, first: usize) -> bool {
+        for index in first..self.count {
+            let Some(entry) = self.entries[index] else { continue };
+            if entry.relocated {
+                continue;
+            }
+            for rela in entry.object.relocations() {
+                if !self.apply(&entry, &rela) {
+                    return false;
+                }
+            }
+            if let Some(e) = self.entries[index].as_mut() {
+                e.relocated = true;
+            }
+        }
+        true
+    }
+
+    unsafe fn apply(&mut self, entry: &Entry, rela: &Rela) -> bool {
+        let object = &entry.object;
+        let place = object.bias.wrapping_add(rela.offset);
+        let addend = rela.addend as u64;
+
+        let rtype = rela.rtype();
+        match rtype {
+            elf::R_X86_64_NONE => return true,
+            elf::R_X86_64_RELATIVE => {
+                write_u64(place, object.bias.wrapping_add(addend));
+                return true;
+            }
+            elf::R_X86_64_IRELATIVE => {
+                let resolver: extern "C" fn() -> u64 = core::mem::transmute(object.bias.wrapping_add(addend));
+                write_u64(place, resolver());
+                return true;
+            }
+            _ => {}
+        }
+
+        // The rest refer to a symbol
+        let sym = object.sym(rela.sym());
+        let name = object.string(sym.name);
+        let target = if rela.sym() == 0 {
+            None
+        } else if sym.binding() == elf::STB_LOCAL {
+            Some((*object, sym))
+        } else if rtype == elf::R_X86_64_COPY {
+            // The program's own copy is the destination, not the source
+            self.search(name, |e| e.global && e.object.bias != object.bias)
+                .or_else(|| self.search(name, |e| e.group == entry.group && e.object.bias != object.bias))
+        } else {
+            self.resolve(Some(entry), name)
+        };
+
+        let value = match target {
+            Some((def, def_sym)) => def.address(&def_sym),
+            None if rela.sym() == 0 || sym.binding() == elf::STB_WEAK => 0,
+            None => {
+                self.fail(b"undefined symbol", name);
+                return false;
+            }
+        };
+
+        match rtype {
+            elf::R_X86_64_64 => write_u64(place, value.wrapping_add(addend)),
+            elf::R_X86_64_GLOB_DAT | elf::R_X86_64_JUMP_SLOT => write_u64(place, value),
+            elf::R_X86_64_PC32 => {
+                let rel = value.wrapping_add(addend).wrapping_sub(place);
+                core::ptr::write_unaligned(place as *mut u32, rel as u32);
+            }
+            elf::R_X86_64_COPY => {
+                let size = target.map_or(sym.size, |(_, def_sym)| def_sym.size.min(sym.size));
+                core::ptr::copy_nonoverlapping(value as *const u8, place as *mut u8, size as usize);
+            }
+            _ => {
+                self.fail(b"unsupported relocation type", name);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Definition of `name` seen from `entry`: the global scope, then the
+    /// entry's own group
+    unsafe fn resolve(&self, entry: Option<&Entry>, name: &[u8]) -> Option<(Object, Sym)> {
+        self.search(name, |e| e.global).or_else(|| {
+            let entry = entry.filter(|e| !e.global)?;
+            self.search(name, |e| e.group == entry.group)
+        })
+    }
+
+    /// First definition of `name` among the objects `filter` accepts, in load order
+    unsafe fn search(&self, name: &[u8], filter: impl Fn(&Entry) -> bool) -> Option<(Object, Sym)> {
+        self.entries[..self.count].iter()
+            .flatten()
+            .filter(|e| filter(e))
+            .find_map(|e| e.object.lookup(name).map(|sym| (e.object, sym)))
+    }
+
+    /// Run initializers of objects from `first` on, dependencies first
+    unsafe fn run_init(&mut self, first: usize) {
+        for index in (first..self.count).rev() {
+            let Some(entry) = self.entries[index].as_mut() else { continue };
+            if !entry.initialized {
+                entry.initialized = true;
+                let object = entry.object;
+                object.run_init();
+            }
+        }
+    }
+
+    /// Record an error for dlerror: "what: detail"
+    fn fail(&mut self, what: &[u8], detail: &[u8]) {
+        let mut len = 0;
+        for part in [what, if detail.is_empty() { b"" } else { b": " }, detail] {
+            let n = part.len().min(ERROR_MAX - len);
+            self.error[len..len + n].copy_from_slice(&part[..n]);
+            len += n;
+        }
+        self.error_len = len;
+    }
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe fn write_u64(place: u64, value: u64) {
+    core::ptr::write_unaligned(place as *mut u64, value);
+}
@@ -0,0 +1,385 @@
+//! Loaded objects
+//!
+//! An [`Object`] is an ELF image in memory - the program, the loader
+//! itself, or a shared library - described by its load bias (what to add to
+//! a vaddr to get an address) and its dynamic section. Everything here
+//! reads the image in place, so the methods are unsafe: the caller promises
+//! the bias and dynamic section describe mapped memory.
+
+use crate::elf::{self, Dyn, Ehdr, Phdr, Rela, Sym};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+/// Longest object name kept
+pub const NAME_MAX: usize = 64;
+
+/// Most DT_NEEDED entries per object
+pub const MAX_NEEDED: usize = 16;
+
+/// Alignment of a mapped library (its pages keep their in-page offsets)
+pub const PAGE_SIZE: u64 = 4096;
+
+// ============================================================================
+// OBJECT
+// ============================================================================
+
+/// A mapped ELF image and what its dynamic section says about it
+#[derive(Clone, Copy)]
+pub struct Object {
+    name: [u8; NAME_MAX],
+    name_len: usize,
+    pub bias: u64,
+    symtab: u64,
+    strtab: u64,
+    strsz: u64,
+    hash: u64,
+    gnu_hash: u64,
+    rela: u64,
+    relasz: u64,
+    jmprel: u64,
+    pltrelsz: u64,
+    init: u64,
+    init_array: u64,
+    init_arraysz: u64,
+    soname: Option<u32>,
+    needed: [u32; MAX_NEEDED],
+    needed_count: usize,
+    has_rel: bool,
+}
+
+impl Object {
+    /// Describe an image from its bias and dynamic section address
+    ///
+    /// # Safety
+    /// `dynamic` must point at a DT_NULL-terminated dynamic section of an
+    /// image mapped at `bias`.
+    pub unsafe fn from_dynamic(name: &[u8], bias: u64, dynamic: u64) -> Result<Self, &'static str> {
+        let mut object = Object {
+            name: [0; NAME_MAX],
+            name_len: name.len().min(NAME_MAX),
+            bias,
+            symtab: 0,
+            strtab: 0,
+            strsz: 0,
+            hash: 0,
+            gnu_hash: 0,
+            rela: 0,
+            relasz: 0,
+            jmprel: 0,
+            pltrelsz: 0,
+            init: 0,
+            init_array: 0,
+            init_arraysz: 0,
+            soname: None,
+            needed: [0; MAX_NEEDED],
+            needed_count: 0,
+            has_rel: false,
+        };
+        object.name[..object.name_len].copy_from_slice(&name[..object.name_len]);
+
+        let addr = |val: u64| bias.wrapping_add(val);
+        let mut entry = dynamic as *const Dyn;
+        loop {
+            let d = core::ptr::read_unaligned(entry);
+            match d.tag {
+                elf::DT_NULL => break,
+                elf::DT_NEEDED => {
+                    if object.needed_count == MAX_NEEDED {
+                        return Err("too many needed libraries");
+                    }
+                    object.needed[object.needed_count] = d.val as u32;
+                    object.needed_count += 1;
+                }
+                elf::DT_PLTRELSZ => object.pltrelsz = d.val,
+                elf::DT_HASH => object.hash = addr(d.val),
+                elf::DT_GNU_HASH => object.gnu_hash = addr(d.val),
+                elf::DT_STRTAB => object.strtab = addr(d.val),
+                elf::DT_SYMTAB => object.symtab = addr(d.val),
+                elf::DT_RELA => object.rela = addr(d.val),
+                elf::DT_RELASZ => object.relasz = d.val,
+                elf::DT_STRSZ => object.strsz = d.val,
+                elf::DT_INIT => object.init = addr(d.val),
+                elf::DT_SONAME => object.soname = Some(d.val as u32),
+                elf::DT_REL => object.has_rel = true,
+                elf::DT_JMPREL => object.jmprel = addr(d.val),
+                elf::DT_INIT_ARRAY => object.init_array = addr(d.val),
+                elf::DT_INIT_ARRAYSZ => object.init_arraysz = d.val,
+                _ => {}
+            }
+            entry = entry.add(1);
+        }
+
+        if object.has_rel {
+            return Err("REL relocations are not supported");
+        }
+        if object.symtab == 0 || object.strtab == 0 {
+            return Err("no dynamic symbol table");
+        }
+        Ok(object)
+    }
+
+    /// Describe a program from its program headers (AT_PHDR) and bias
+    ///
+    /// # Safety
+    /// The headers must be those of the image mapped at `bias`.
+    pub unsafe fn from_phdrs(name: &[u8], bias: u64, phdrs: &[Phdr]) -> Result<Self, &'static str> {
+        let dynamic = phdrs.iter()
+            .find(|p| p.ptype == elf::PT_DYNAMIC)
+            .ok_or("not dynamically linked")?;
+        Self::from_dynamic(name, bias, bias.wrapping_add(dynamic.vaddr))
+    }
+
+    /// Name it was loaded by
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    /// Does `name` (a DT_NEEDED entry or dlopen argument) refer to this object?
+    ///
+    /// # Safety
+    /// The object's image must still be mapped.
+    pub unsafe fn answers_to(&self, name: &[u8]) -> bool {
+        self.name() == name || self.soname.is_some_and(|off| self.string(off) == name)
+    }
+
+    /// NUL-terminated string at `offset` in the string table
+    ///
+    /// # Safety
+    /// The object's image must still be mapped.
+    pub unsafe fn string(&self, offset: u32) -> &'static [u8] {
+        if offset as u64 >= self.strsz {
+            return &[];
+        }
+        let start = (self.strtab + offset as u64) as *const u8;
+        let max = (self.strsz - offset as u64) as usize;
+        let mut len = 0;
+        while len < max && *start.add(len) != 0 {
+            len += 1;
+        }
+        core::slice::from_raw_parts(start, len)
+    }
+
+    /// The i-th DT_NEEDED name
+    ///
+    /// # Safety
+    /// The object's image must still be mapped.
+    pub unsafe fn needed(&self, i: usize) -> Option<&'static [u8]> {
+        (i < self.needed_count).then(|| self.string(self.needed[i]))
+    }
+
+    /// Symbol table entry `index`
+    ///
+    /// # Safety
+    /// `index` must be inside the symbol table.
+    pub unsafe fn sym(&self, index: usize) -> Sym {
+        core::ptr::read_unaligned((self.symtab as *const Sym).add(index))
+    }
+
+    /// Find an exported (defined, non-local) symbol by name
+    ///
+    /// Uses DT_GNU_HASH if present, otherwise DT_HASH; an object with
+    /// neither exports nothing.
+    ///
+    /// # Safety
+    /// The object's image must still be mapped.
+    pub unsafe fn lookup(&self, name: &[u8]) -> Option<Sym> {
+        let index = if self.gnu_hash != 0 {
+            self.gnu_lookup(name)?
+        } else if self.hash != 0 {
+            self.sysv_lookup(name)?
+        } else {
+            return None;
+        };
+        let sym = self.sym(index);
+        (sym.is_defined() && sym.binding() != elf::STB_LOCAL).then_some(sym)
+    }
+
+    unsafe fn sysv_lookup(&self, name: &[u8]) -> Option<usize> {
+        let table = self.hash as *const u32;
+        let nbucket = *table as usize;
+        if nbucket == 0 {
+            return None;
+        }
+        let buckets = table.add(2);
+        let chains = buckets.add(nbucket);
+
+        let mut index = *buckets.add(elf::elf_hash(name) as usize % nbucket) as usize;
+        while index != 0 {
+            if self.string(self.sym(index).name) == name {
+                return Some(index);
+            }
+            index = *chains.add(index) as usize;
+        }
+        None
+    }
+
+    unsafe fn gnu_lookup(&self, name: &[u8]) -> Option<usize> {
+        let table = self.gnu_hash as *const u32;
+        let nbuckets = *table as usize;
+        let symoffset = *table.add(1) as usize;
+        let bloom_size = *table.add(2) as usize;
+        let bloom_shift = *table.add(3);
+        if nbuckets == 0 || bloom_size == 0 {
+            return None;
+        }
+        let bloom = table.add(4) as *const u64;
+        let buckets = bloom.add(bloom_size) as *const u32;
+        let chains = buckets.add(nbuckets);
+
+        let hash = elf::gnu_hash(name);
+        let word = core::ptr::read_unaligned(bloom.add((hash as usize / 64) % bloom_size));
+        let mask = (1u64 << (hash % 64)) | (1u64 << ((hash >> bloom_shift) % 64));
+        if word & mask != mask {
+            return None;
+        }
+
+        let mut index = *buckets.add(hash as usize % nbuckets) as usize;
+        if index < symoffset {
+            return None;
+        }
+        loop {
+            let chain_hash = *chains.add(index - symoffset);
+            if chain_hash | 1 == hash | 1 && self.string(self.sym(index).name) == name {
+                return Some(index);
+            }
+            if chain_hash & 1 != 0 {
+                return None;
+            }
+            index += 1;
+        }
+    }
+
+    /// Address of one of this object's symbols
+    pub fn address(&self, sym: &Sym) -> u64 {
+        if sym.shndx == elf::SHN_ABS {
+            sym.value
+        } else {
+            self.bias.wrapping_add(sym.value)
+        }
+    }
+
+    /// DT_RELA then DT_JMPREL relocations
+    ///
+    /// # Safety
+    /// The object's image must still be mapped.
+    pub unsafe fn relocations(&self) -> impl Iterator<Item = Rela> {
+        let table = |addr: u64, size: u64| {
+            let count = if addr == 0 { 0 } else { size as usize / core::mem::size_of::<Rela>() };
+            (0..count).map(move |i| core::ptr::read_unaligned((addr as *const Rela).add(i)))
+        };
+        table(self.rela, self.relasz).chain(table(self.jmprel, self.pltrelsz))
+    }
+
+    /// Run DT_INIT, then each DT_INIT_ARRAY function
+    ///
+    /// # Safety
+    /// The object must be fully relocated.
+    pub unsafe fn run_init(&self) {
+        if self.init != 0 {
+            let init: extern "C" fn() = core::mem::transmute(self.init);
+            init();
+        }
+        let count = self.init_arraysz as usize / 8;
+        for i in 0..count {
+            let f = core::ptr::read_unaligned((self.init_array as *const u64).add(i));
+            // 0 and -1 are placeholders some linkers leave
+            if f != 0 && f != u64::MAX {
+                let f: extern "C" fn() = core::mem::transmute(f);
+                f();
+            }
+        }
+    }
+}
+
+// ============================================================================
+// MAPPING
+// ============================================================================
+
+/// Where a shared object ended up
+pub struct Mapping {
+    pub bias: u64,
+    pub dynamic: u64,
+    pub size: usize,
+}
+
+/// Copy a shared object file's PT_LOAD segments into memory
+///
+/// `alloc` is asked for `size` writable, executable bytes; the segments are
+/// laid out in it as their vaddrs say, page-aligned, with the rest zeroed.
+///
+/// # Safety
+/// `alloc` must return memory valid for the requested size.
+pub unsafe fn map(
+    file: &[u8],
+    alloc: &mut dyn FnMut(usize) -> Option<*mut u8>,
+) -> Result<Mapping, &'static str> {
+    if file.len() < core::mem::size_of::<Ehdr>() {
+        return Err("file too small for ELF header");
+    }
+    let ehdr = core::ptr::read_unaligned(file.as_ptr() as *const Ehdr);
+    if ehdr.ident[..4] != elf::ELF_MAGIC
+        || ehdr.ident[4] != elf::ELFCLASS64
+        || ehdr.ident[5] != elf::ELFDATA2LSB
+        || ehdr.machine != elf::EM_X86_64
+    {
+        return Err("not an x86-64 ELF64 file");
+    }
+    if ehdr.etype != elf::ET_DYN {
+        return Err("not a shared object");
+    }
+
+    let phoff = ehdr.phoff as usize;
+    let phnum = ehdr.phnum as usize;
+    if ehdr.phentsize as usize != core::mem::size_of::<Phdr>()
+        || phoff.checked_add(phnum * core::mem::size_of::<Phdr>()).is_none_or(|end| end > file.len())
+    {
+        return Err("program headers outside file");
+    }
+    let phdr = |i: usize| core::ptr::read_unaligned((file.as_ptr().add(phoff) as *const Phdr).add(i));
+
+    let mut low = u64::MAX;
+    let mut high = 0;
+    let mut dynamic = None;
+    for i in 0..phnum {
+        let p = phdr(i);
+        match p.ptype {
+            elf::PT_LOAD => {
+                low = low.min(p.vaddr & !(PAGE_SIZE - 1));
+                high = high.max(p.vaddr.checked_add(p.memsz).ok_or("segment overflows")?);
+                let end = p.offset.checked_add(p.filesz).ok_or("segment overflows")?;
+                if end > file.len() as u64 || p.filesz > p.memsz {
+                    return Err("segment outside file");
+                }
+            }
+            elf::PT_DYNAMIC => dynamic = Some(p.vaddr),
+            elf::PT_TLS => return Err("thread-local storage is not supported"),
+            _ => {}
+        }
+    }
+    if low == u64::MAX {
+        return Err("no loadable segments");
+    }
+    let dynamic = dynamic.ok_or("no dynamic section")?;
+
+    // Room to round the start up to a page
+    let size = (high - low) as usize;
+    let memory = alloc(size + PAGE_SIZE as usize).ok_or("out of memory")? as u64;
+    let base = (memory + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    core::ptr::write_bytes(base as *mut u8, 0, size);
+
+    let bias = base.wrapping_sub(low);
+    for i in 0..phnum {
+        let p = phdr(i);
+        if p.ptype == elf::PT_LOAD {
+            core::ptr::copy_nonoverlapping(
+                file.as_ptr().add(p.offset as usize),
+                bias.wrapping_add(p.vaddr) as *mut u8,
+                p.filesz as usize,
+            );
+        }
+    }
+
+    Ok(Mapping { bias, dynamic: bias.wrapping_add(dynamic), size })
+}
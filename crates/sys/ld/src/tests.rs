@@ -0,0 +1,457 @@
+//! Linker tests
+//!
+//! Shared objects are built in memory by [`Lib`]: one PT_LOAD segment
+//! holding the dynamic string and symbol tables, a hash table, relocations,
+//! a data area of u64 slots that symbols and relocations point into, and
+//! the dynamic section.
+
+extern crate std;
+
+use std::boxed::Box;
+use std::string::String;
+use std::vec;
+use std::vec::Vec;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::elf::{self, Dyn, Ehdr, Phdr, Rela, Sym};
+use crate::{map, Host, Linker, Object};
+
+// ============================================================================
+// BUILDER
+// ============================================================================
+
+#[derive(Clone, Copy)]
+enum Def {
+    Slot(usize), // Defined at a data slot
+    Abs(u64),    // SHN_ABS value
+    Undef,
+    Weak,        // Weak undefined
+}
+
+#[derive(Clone, Copy)]
+enum Addend {
+    Value(i64),
+    Slot(usize), // vaddr of a data slot
+}
+
+struct Lib {
+    soname: Option<&'static str>,
+    needed: Vec<&'static str>,
+    symbols: Vec<(&'static str, Def)>,
+    relocs: Vec<(usize, u32, Option<&'static str>, Addend)>,
+    data: Vec<u64>,
+    init_array: Option<(usize, usize)>, // first slot, count
+    gnu_hash: bool,
+}
+
+fn align(n: usize) -> usize {
+    (n + 7) & !7
+}
+
+impl Lib {
+    fn new(slots: usize) -> Self {
+        Lib {
+            soname: None,
+            needed: Vec::new(),
+            symbols: Vec::new(),
+            relocs: Vec::new(),
+            data: vec![0; slots],
+            init_array: None,
+            gnu_hash: true,
+        }
+    }
+
+    fn needs(mut self, name: &'static str) -> Self {
+        self.needed.push(name);
+        self
+    }
+
+    fn symbol(mut self, name: &'static str, def: Def) -> Self {
+        self.symbols.push((name, def));
+        self
+    }
+
+    fn reloc(mut self, slot: usize, rtype: u32, sym: Option<&'static str>, addend: Addend) -> Self {
+        self.relocs.push((slot, rtype, sym, addend));
+        self
+    }
+
+    fn build(&self) -> Vec<u8> {
+        // Undefined symbols first: GNU hash only covers the defined tail
+        let mut symbols = self.symbols.clone();
+        symbols.sort_by_key(|(_, def)| matches!(def, Def::Slot(_) | Def::Abs(_)));
+        let undefined = symbols.iter().filter(|(_, d)| matches!(d, Def::Undef | Def::Weak)).count();
+        let sym_index = |name: &str| 1 + symbols.iter().position(|(n, _)| *n == name).unwrap();
+
+        let mut strtab = vec![0u8];
+        let mut add_str = |s: &str| {
+            let off = strtab.len() as u64;
+            strtab.extend_from_slice(s.as_bytes());
+            strtab.push(0);
+            off
+        };
+        let soname = self.soname.map(&mut add_str);
+        let needed: Vec<u64> = self.needed.iter().map(|n| add_str(n)).collect();
+        let sym_names: Vec<u64> = symbols.iter().map(|(n, _)| add_str(n)).collect();
+
+        let nsyms = symbols.len() + 1;
+        let hash_words = if self.gnu_hash {
+            4 + 2 + 1 + (nsyms - 1 - undefined) // header, 1 bloom word, 1 bucket, chains
+        } else {
+            2 + 1 + nsyms // header, 1 bucket, chains
+        };
+        let (plt, other): (Vec<_>, Vec<_>) = self.relocs.iter()
+            .partition(|r| r.1 == elf::R_X86_64_JUMP_SLOT);
+
+        let phdrs = 64;
+        let strtab_off = phdrs + 2 * 56;
+        let symtab_off = align(strtab_off + strtab.len());
+        let hash_off = align(symtab_off + nsyms * 24);
+        let rela_off = align(hash_off + hash_words * 4);
+        let jmprel_off = rela_off + other.len() * 24;
+        let data_off = jmprel_off + plt.len() * 24;
+        let dynamic_off = data_off + self.data.len() * 8;
+        let slot = |i: usize| (data_off + i * 8) as u64;
+
+        let mut dynamic = Vec::new();
+        for off in &needed {
+            dynamic.push((elf::DT_NEEDED, *off));
+        }
+        if let Some(off) = soname {
+            dynamic.push((elf::DT_SONAME, off));
+        }
+        dynamic.push((elf::DT_STRTAB, strtab_off as u64));
+        dynamic.push((elf::DT_STRSZ, strtab.len() as u64));
+        dynamic.push((elf::DT_SYMTAB, symtab_off as u64));
+        dynamic.push((if self.gnu_hash { elf::DT_GNU_HASH } else { elf::DT_HASH }, hash_off as u64));
+        dynamic.push((elf::DT_RELA, rela_off as u64));
+        dynamic.push((elf::DT_RELASZ, (other.len() * 24) as u64));
+        dynamic.push((elf::DT_JMPREL, jmprel_off as u64));
+        dynamic.push((elf::DT_PLTRELSZ, (plt.len() * 24) as u64));
+        if let Some((first, count)) = self.init_array {
+            dynamic.push((elf::DT_INIT_ARRAY, slot(first)));
+            dynamic.push((elf::DT_INIT_ARRAYSZ, (count * 8) as u64));
+        }
+        dynamic.push((elf::DT_NULL, 0));
+        let size = dynamic_off + dynamic.len() * 16;
+
+        let mut image = vec![0u8; size];
+        let put = |image: &mut Vec<u8>, at: usize, bytes: &[u8]| image[at..at + bytes.len()].copy_from_slice(bytes);
+        fn raw<T>(v: &T) -> &[u8] {
+            unsafe { core::slice::from_raw_parts(v as *const T as *const u8, core::mem::size_of::<T>()) }
+        }
+
+        let mut ident = [0u8; 16];
+        ident[..4].copy_from_slice(&elf::ELF_MAGIC);
+        ident[4] = elf::ELFCLASS64;
+        ident[5] = elf::ELFDATA2LSB;
+        let ehdr = Ehdr {
+            ident, etype: elf::ET_DYN, machine: elf::EM_X86_64, version: 1, entry: 0,
+            phoff: phdrs as u64, shoff: 0, flags: 0, ehsize: 64, phentsize: 56, phnum: 2,
+            shentsize: 0, shnum: 0, shstrndx: 0,
+        };
+        put(&mut image, 0, raw(&ehdr));
+        let load = Phdr {
+            ptype: elf::PT_LOAD, flags: 6, offset: 0, vaddr: 0, paddr: 0,
+            filesz: size as u64, memsz: size as u64 + 64, align: 4096,
+        };
+        let dynamic_phdr = Phdr {
+            ptype: elf::PT_DYNAMIC, flags: 6, offset: dynamic_off as u64, vaddr: dynamic_off as u64,
+            paddr: 0, filesz: (dynamic.len() * 16) as u64, memsz: (dynamic.len() * 16) as u64, align: 8,
+        };
+        put(&mut image, phdrs, raw(&load));
+        put(&mut image, phdrs + 56, raw(&dynamic_phdr));
+        put(&mut image, strtab_off, &strtab);
+
+        for (i, ((_, def), name)) in symbols.iter().zip(&sym_names).enumerate() {
+            let (info, shndx, value) = match *def {
+                Def::Slot(s) => (0x11, 1, slot(s)),
+                Def::Abs(v) => (0x11, elf::SHN_ABS, v),
+                Def::Undef => (0x10, elf::SHN_UNDEF, 0),
+                Def::Weak => (0x20, elf::SHN_UNDEF, 0),
+            };
+            let sym = Sym { name: *name as u32, info, other: 0, shndx, value, size: 8 };
+            put(&mut image, symtab_off + (i + 1) * 24, raw(&sym));
+        }
+
+        let mut hash: Vec<u32> = Vec::new();
+        if self.gnu_hash {
+            // One bucket holding every defined symbol, a bloom word that passes everything
+            let first = (1 + undefined) as u32;
+            hash.extend_from_slice(&[1, first, 1, 6]);
+            hash.extend_from_slice(&[u32::MAX, u32::MAX]);
+            hash.push(if nsyms > first as usize { first } else { 0 });
+            for (i, (name, _)) in symbols.iter().enumerate().skip(undefined) {
+                let last = i + 1 == symbols.len();
+                let h = elf::gnu_hash(name.as_bytes()) & !1;
+                hash.push(if last { h | 1 } else { h });
+            }
+        } else {
+            hash.extend_from_slice(&[1, nsyms as u32]);
+            hash.push(if nsyms > 1 { 1 } else { 0 });
+            hash.push(0);
+            for i in 1..nsyms {
+                hash.push(if i + 1 < nsyms { i as u32 + 1 } else { 0 });
+            }
+        }
+        for (i, word) in hash.iter().enumerate() {
+            put(&mut image, hash_off + i * 4, &word.to_le_bytes());
+        }
+
+        for (table, relocs) in [(rela_off, &other), (jmprel_off, &plt)] {
+            for (i, (s, rtype, sym, addend)) in relocs.iter().enumerate() {
+                let index = sym.map_or(0, &sym_index) as u64;
+                let addend = match *addend {
+                    Addend::Value(v) => v,
+                    Addend::Slot(s) => slot(s) as i64,
+                };
+                let rela = Rela { offset: slot(*s), info: index << 32 | *rtype as u64, addend };
+                put(&mut image, table + i * 24, raw(&rela));
+            }
+        }
+
+        for (i, value) in self.data.iter().enumerate() {
+            put(&mut image, data_off + i * 8, &value.to_le_bytes());
+        }
+        for (i, (tag, val)) in dynamic.iter().enumerate() {
+            put(&mut image, dynamic_off + i * 16, raw(&Dyn { tag: *tag, val: *val }));
+        }
+        image
+    }
+}
+
+// ============================================================================
+// HOST
+// ============================================================================
+
+#[derive(Default)]
+struct TestHost {
+    files: Vec<(String, Vec<u8>)>,
+    reads: usize,
+}
+
+impl TestHost {
+    fn with(mut self, path: &str, lib: &Lib) -> Self {
+        self.files.push((String::from(path), lib.build()));
+        self
+    }
+}
+
+impl Host for TestHost {
+    fn read_file(&mut self, path: &str) -> Option<&[u8]> {
+        self.reads += 1;
+        self.files.iter().find(|(p, _)| p == path).map(|(_, data)| data.as_slice())
+    }
+
+    fn alloc(&mut self, size: usize) -> Option<*mut u8> {
+        Some(Box::leak(vec![0xCCu8; size].into_boxed_slice()).as_mut_ptr())
+    }
+}
+
+/// Map a built library directly, as the kernel would the program
+fn load(lib: &Lib, name: &[u8]) -> Object {
+    let image = Box::leak(lib.build().into_boxed_slice());
+    let mut host = TestHost::default();
+    unsafe {
+        let m = map(image, &mut |size| host.alloc(size)).unwrap();
+        Object::from_dynamic(name, m.bias, m.dynamic).unwrap()
+    }
+}
+
+fn slots(linker: &mut Linker, handle: usize, name: &str, count: usize) -> Vec<u64> {
+    let addr = unsafe { linker.dlsym(handle, name.as_bytes()) }.expect("symbol") as *const u64;
+    (0..count).map(|i| unsafe { *addr.add(i) }).collect()
+}
+
+fn error(linker: &mut Linker) -> String {
+    String::from_utf8(linker.take_error().unwrap_or_default().to_vec()).unwrap()
+}
+
+/// libbase.so: `value` = 42, `other` = 7
+fn base() -> Lib {
+    let mut lib = Lib::new(2)
+        .symbol("value", Def::Slot(0))
+        .symbol("other", Def::Slot(1));
+    lib.data = vec![42, 7];
+    lib
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+
+#[test]
+fn test_relocations() {
+    let user = Lib::new(5)
+        .needs("libbase.so")
+        .symbol("slots", Def::Slot(0))
+        .symbol("value", Def::Undef)
+        .symbol("other", Def::Undef)
+        .reloc(0, elf::R_X86_64_64, Some("value"), Addend::Value(8))
+        .reloc(1, elf::R_X86_64_GLOB_DAT, Some("value"), Addend::Value(0))
+        .reloc(2, elf::R_X86_64_RELATIVE, None, Addend::Slot(4))
+        .reloc(3, elf::R_X86_64_JUMP_SLOT, Some("other"), Addend::Value(0));
+    let mut host = TestHost::default()
+        .with("/lib/libbase.so", &base())
+        .with("/apps/lib/libuser.so", &user);
+    let mut linker = Linker::new();
+
+    let handle = unsafe { linker.dlopen(&mut host, b"libuser.so", false) }.expect("dlopen");
+    let value = unsafe { linker.dlsym(handle, b"value") }.unwrap();
+    let other = unsafe { linker.dlsym(handle, b"other") }.unwrap();
+    let own = unsafe { linker.dlsym(handle, b"slots") }.unwrap();
+
+    let got = slots(&mut linker, handle, "slots", 4);
+    assert_eq!(got, [value + 8, value, own + 32, other]);
+    assert_eq!(unsafe { *(value as *const u64) }, 42);
+    assert_eq!(linker.objects().count(), 2);
+}
+
+#[test]
+fn test_symbol_lookup_with_both_hash_styles() {
+    for gnu_hash in [true, false] {
+        let mut lib = Lib::new(3)
+            .symbol("alpha", Def::Slot(0))
+            .symbol("beta", Def::Slot(1))
+            .symbol("gamma", Def::Slot(2))
+            .symbol("imported", Def::Weak);
+        lib.gnu_hash = gnu_hash;
+        let object = load(&lib, b"libabc.so");
+
+        unsafe {
+            let alpha = object.lookup(b"alpha").expect("alpha");
+            let gamma = object.lookup(b"gamma").expect("gamma");
+            assert_eq!(gamma.value - alpha.value, 16);
+            assert!(object.lookup(b"beta").is_some());
+            assert!(object.lookup(b"imported").is_none(), "undefined symbols aren't exported");
+            assert!(object.lookup(b"delta").is_none());
+        }
+    }
+}
+
+#[test]
+fn test_undefined_symbols() {
+    let strong = Lib::new(1)
+        .symbol("missing", Def::Undef)
+        .reloc(0, elf::R_X86_64_GLOB_DAT, Some("missing"), Addend::Value(0));
+    let weak = Lib::new(1)
+        .symbol("slot", Def::Slot(0))
+        .symbol("maybe", Def::Weak)
+        .reloc(0, elf::R_X86_64_GLOB_DAT, Some("maybe"), Addend::Value(0));
+    let mut host = TestHost::default()
+        .with("/lib/libstrong.so", &strong)
+        .with("/lib/libweak.so", &weak);
+    let mut linker = Linker::new();
+
+    assert!(unsafe { linker.dlopen(&mut host, b"libstrong.so", false) }.is_none());
+    assert_eq!(error(&mut linker), "undefined symbol: missing");
+    assert_eq!(linker.objects().count(), 0, "a failed dlopen leaves nothing behind");
+
+    let handle = unsafe { linker.dlopen(&mut host, b"libweak.so", false) }.expect("weak");
+    assert_eq!(slots(&mut linker, handle, "slot", 1), [0]);
+
+    assert!(unsafe { linker.dlsym(handle, b"nope") }.is_none());
+    assert_eq!(error(&mut linker), "undefined symbol: nope");
+    assert!(linker.take_error().is_none(), "reading the error clears it");
+
+    assert!(unsafe { linker.dlopen(&mut host, b"libnone.so", false) }.is_none());
+    assert_eq!(error(&mut linker), "cannot find library: libnone.so");
+}
+
+static INIT_ORDER: AtomicUsize = AtomicUsize::new(0);
+static INIT_BASE: AtomicUsize = AtomicUsize::new(0);
+static INIT_USER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn init_base() {
+    INIT_BASE.store(INIT_ORDER.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+}
+
+extern "C" fn init_user() {
+    INIT_USER.store(INIT_ORDER.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+}
+
+#[test]
+fn test_initializers_run_dependencies_first() {
+    let host_lib = Lib::new(0)
+        .symbol("init_base", Def::Abs(init_base as *const () as u64))
+        .symbol("init_user", Def::Abs(init_user as *const () as u64));
+    let mut base = Lib::new(1)
+        .needs("libhost.so")
+        .symbol("init_base", Def::Undef)
+        .reloc(0, elf::R_X86_64_64, Some("init_base"), Addend::Value(0));
+    base.init_array = Some((0, 1));
+    let mut user = Lib::new(2)
+        .needs("libbase.so")
+        .symbol("init_user", Def::Undef)
+        .reloc(1, elf::R_X86_64_64, Some("init_user"), Addend::Value(0));
+    user.init_array = Some((0, 2)); // slot 0 stays 0 and is skipped
+    let mut host = TestHost::default()
+        .with("/lib/libhost.so", &host_lib)
+        .with("/lib/libbase.so", &base)
+        .with("/lib/libuser.so", &user);
+    let mut linker = Linker::new();
+
+    unsafe { linker.dlopen(&mut host, b"libuser.so", false) }.expect("dlopen");
+    assert_eq!(INIT_BASE.load(Ordering::SeqCst), 1);
+    assert_eq!(INIT_USER.load(Ordering::SeqCst), 2);
+
+    // Opening it again runs nothing
+    unsafe { linker.dlopen(&mut host, b"libuser.so", false) }.expect("dlopen");
+    assert_eq!(INIT_ORDER.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_startup_links_program() {
+    let mut loader = Lib::new(0).symbol("dlopen", Def::Abs(0x1234_5678));
+    loader.soname = Some("ld-watos.so.1");
+    let mut program = Lib::new(3)
+        .needs("libbase.so")
+        .symbol("dlopen", Def::Undef)
+        .symbol("value", Def::Slot(2))
+        .symbol("table", Def::Slot(0))
+        .reloc(0, elf::R_X86_64_JUMP_SLOT, Some("dlopen"), Addend::Value(0))
+        .reloc(1, elf::R_X86_64_GLOB_DAT, Some("other"), Addend::Value(0))
+        .reloc(2, elf::R_X86_64_COPY, Some("value"), Addend::Value(0))
+        .symbol("other", Def::Undef);
+    program.data[2] = 0xDEAD;
+    let mut host = TestHost::default().with("/lib/libbase.so", &base());
+    let mut linker = Linker::new();
+
+    let program = load(&program, b"");
+    let loader = load(&loader, b"ld-watos");
+    assert!(unsafe { linker.start(&mut host, program, loader) }, "{}", error(&mut linker));
+
+    let table = slots(&mut linker, 0, "table", 3);
+    assert_eq!(table[0], 0x1234_5678, "resolved against the loader");
+    assert_eq!(table[2], 42, "COPY took the library's initial value");
+    // The program's copy of `value` comes first in the global scope
+    assert_eq!(unsafe { linker.dlsym(0, b"value") }, Some(program.bias + program_slot(&program, "value")));
+    assert_eq!(table[1], unsafe { linker.dlsym(0, b"other") }.unwrap());
+
+    // dlopen of something already loaded finds it, by name, soname or path
+    assert_eq!(unsafe { linker.dlopen(&mut host, b"ld-watos", false) }, Some(2));
+    assert_eq!(unsafe { linker.dlopen(&mut host, b"ld-watos.so.1", false) }, Some(2));
+    assert_eq!(unsafe { linker.dlopen(&mut host, b"/lib/libbase.so", false) }, Some(3));
+    assert!(linker.dlclose(3));
+    assert!(!linker.dlclose(99));
+}
+
+fn program_slot(program: &Object, name: &str) -> u64 {
+    unsafe { program.lookup(name.as_bytes()).unwrap().value }
+}
+
+#[test]
+fn test_map_rejects_bad_files() {
+    let mut alloc = |size: usize| Some(Box::leak(vec![0u8; size].into_boxed_slice()).as_mut_ptr());
+    unsafe {
+        assert_eq!(map(b"not an elf", &mut alloc).err(), Some("file too small for ELF header"));
+        assert_eq!(map(&[0u8; 64], &mut alloc).err(), Some("not an x86-64 ELF64 file"));
+
+        let mut image = base().build();
+        image[16] = 2; // ET_EXEC
+        assert_eq!(map(&image, &mut alloc).err(), Some("not a shared object"));
+
+        let image = base().build();
+        assert_eq!(map(&image[..image.len() - 8], &mut alloc).err(), Some("segment outside file"));
+    }
+}
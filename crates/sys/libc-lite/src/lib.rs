@@ -0,0 +1,89 @@
+//! WATOS libc-lite
+//!
+//! A small userland runtime for programs that print a lot:
+//! - Buffered stdout and stderr, so a line costs one SYS_WRITE instead of
+//!   one per string
+//! - printf-style formatting that needs no allocator
+//! - `exit`, which flushes both streams before leaving
+//!
+//! stdout is line-buffered when it is a terminal and block-buffered when it
+//! is redirected to a file or pipe; stderr is always line-buffered. Reading
+//! stdin through [`stdio::read`] flushes stdout first, so prompts show up.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use watos_libc_lite::{printf, println};
+//!
+//! println!("{} files", count);
+//! printf!("%-12s %8u\n", name, size);
+//! watos_libc_lite::exit(0);
+//! ```
+//!
+//! Anything still buffered is lost if a program leaves through
+//! `syscalls::exit` directly; use [`exit`] (or call [`stdio::flush_all`]).
+
+#![no_std]
+
+pub mod printf;
+pub mod stdio;
+
+pub use printf::Arg;
+pub use stdio::BufMode;
+
+/// Flush stdout and stderr, then exit with `code`
+pub fn exit(code: i32) -> ! {
+    stdio::flush_all();
+    watos_syscall::syscalls::exit(code)
+}
+
+/// Print to stdout with `core::fmt` formatting
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::stdio::print_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Print to stdout with a trailing newline
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::stdio::write(b"\n")
+    };
+    ($($arg:tt)*) => {{
+        $crate::stdio::print_fmt(format_args!($($arg)*));
+        $crate::stdio::write(b"\n");
+    }};
+}
+
+/// Print to stderr with `core::fmt` formatting
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::stdio::eprint_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Print to stderr with a trailing newline
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::stdio::write_err(b"\n")
+    };
+    ($($arg:tt)*) => {{
+        $crate::stdio::eprint_fmt(format_args!($($arg)*));
+        $crate::stdio::write_err(b"\n");
+    }};
+}
+
+/// C-style printf to stdout: `printf!("%5d %s\n", n, name)`
+///
+/// Each argument is converted with `Arg::from`, so integers, `&str`, `char`
+/// and `bool` can be passed directly.
+#[macro_export]
+macro_rules! printf {
+    ($fmt:expr $(, $arg:expr)* $(,)?) => {
+        $crate::stdio::printf($fmt, &[$($crate::Arg::from($arg)),*])
+    };
+}
@@ -0,0 +1,418 @@
+//! printf-style formatting
+//!
+//! Formats C-style conversion specs into any `core::fmt::Write` without
+//! allocating. Supported:
+//!
+//! ```text
+//! %[flags][width][.precision][length]conversion
+//!
+//! flags       -  left-justify      0  pad with zeros
+//!             +  always sign       (space)  space before positives
+//!             #  0x / 0 prefix for x, X, o
+//! width       digits
+//! precision   digits: minimum digits for integers, maximum chars for %s
+//! length      h, hh, l, ll, z, j, t (accepted and ignored)
+//! conversion  d i u x X o c s p %
+//! ```
+//!
+//! Arguments are typed [`Arg`] values rather than varargs. A conversion that
+//! doesn't match its argument converts it (a `%d` given a char prints the
+//! code point, a `%s` given a number prints it in decimal). Unknown
+//! conversions, and conversions with no argument left, are printed as
+//! written.
+
+use core::fmt::{self, Write};
+
+// ============================================================================
+// ARGUMENTS
+// ============================================================================
+
+/// One printf argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg<'a> {
+    Int(i64),
+    Uint(u64),
+    Str(&'a str),
+    Char(char),
+}
+
+impl Arg<'_> {
+    fn as_i64(&self) -> i64 {
+        match *self {
+            Arg::Int(n) => n,
+            Arg::Uint(n) => n as i64,
+            Arg::Char(c) => c as i64,
+            Arg::Str(_) => 0,
+        }
+    }
+
+    fn as_u64(&self) -> u64 {
+        match *self {
+            Arg::Int(n) => n as u64,
+            Arg::Uint(n) => n,
+            Arg::Char(c) => c as u64,
+            Arg::Str(_) => 0,
+        }
+    }
+}
+
+macro_rules! arg_from {
+    ($variant:ident, $as:ty: $($t:ty),*) => {
+        $(impl From<$t> for Arg<'_> {
+            fn from(n: $t) -> Self {
+                Arg::$variant(n as $as)
+            }
+        })*
+    };
+}
+
+arg_from!(Int, i64: i8, i16, i32, i64, isize);
+arg_from!(Uint, u64: u8, u16, u32, u64, usize);
+
+impl<'a> From<&'a str> for Arg<'a> {
+    fn from(s: &'a str) -> Self {
+        Arg::Str(s)
+    }
+}
+
+impl From<char> for Arg<'_> {
+    fn from(c: char) -> Self {
+        Arg::Char(c)
+    }
+}
+
+impl From<bool> for Arg<'_> {
+    fn from(b: bool) -> Self {
+        Arg::Uint(b as u64)
+    }
+}
+
+// ============================================================================
+// FORMATTING
+// ============================================================================
+
+/// A parsed conversion spec
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Format `fmt` with `args` into `out`
+pub fn format<W: Write>(out: &mut W, fmt: &str, args: &[Arg]) -> fmt::Result {
+    let bytes = fmt.as_bytes();
+    let mut args = args.iter();
+    let mut literal = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        out.write_str(&fmt[literal..i])?;
+        let start = i;
+        i += 1;
+
+        let mut spec = Spec::default();
+        while let Some(&c) = bytes.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                b'0' => spec.zero = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        spec.width = digits(bytes, &mut i);
+        if bytes.get(i) == Some(&b'.') {
+            i += 1;
+            spec.precision = Some(digits(bytes, &mut i));
+        }
+        while matches!(bytes.get(i), Some(b'h' | b'l' | b'z' | b'j' | b't')) {
+            i += 1;
+        }
+
+        let conversion = match bytes.get(i) {
+            Some(&c) => c,
+            // A lone '%' at the end stays as it is
+            None => {
+                literal = start;
+                break;
+            }
+        };
+        i += 1;
+        literal = i;
+
+        if conversion == b'%' {
+            out.write_char('%')?;
+            continue;
+        }
+        // Unknown conversions, and any with no argument left, print as written
+        let arg = match args.clone().next() {
+            Some(arg) if b"diuxXopcs".contains(&conversion) => arg,
+            _ => {
+                out.write_str(&fmt[start..i])?;
+                continue;
+            }
+        };
+        args.next();
+
+        match conversion {
+            b'd' | b'i' => {
+                let n = arg.as_i64();
+                let sign = if n < 0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else if spec.space {
+                    " "
+                } else {
+                    ""
+                };
+                integer(out, &spec, sign, n.unsigned_abs(), 10, false)?;
+            }
+            b'u' => integer(out, &spec, "", arg.as_u64(), 10, false)?,
+            b'x' | b'X' => {
+                let n = arg.as_u64();
+                let upper = conversion == b'X';
+                let prefix = match (spec.alt && n != 0, upper) {
+                    (false, _) => "",
+                    (true, false) => "0x",
+                    (true, true) => "0X",
+                };
+                integer(out, &spec, prefix, n, 16, upper)?;
+            }
+            b'o' => {
+                let prefix = if spec.alt { "0" } else { "" };
+                integer(out, &spec, prefix, arg.as_u64(), 8, false)?;
+            }
+            b'p' => integer(out, &spec, "0x", arg.as_u64(), 16, false)?,
+            b'c' => {
+                let c = match *arg {
+                    Arg::Char(c) => c,
+                    other => char::from_u32(other.as_u64() as u32).unwrap_or('?'),
+                };
+                let mut buf = [0u8; 4];
+                pad(out, &spec, "", c.encode_utf8(&mut buf))?;
+            }
+            b's' => match *arg {
+                Arg::Str(s) => {
+                    let s = match spec.precision {
+                        Some(max) => truncate(s, max),
+                        None => s,
+                    };
+                    pad(out, &spec, "", s)?;
+                }
+                Arg::Char(c) => {
+                    let mut buf = [0u8; 4];
+                    pad(out, &spec, "", c.encode_utf8(&mut buf))?;
+                }
+                Arg::Int(n) => {
+                    let sign = if n < 0 { "-" } else { "" };
+                    integer(out, &Spec { precision: None, ..spec }, sign, n.unsigned_abs(), 10, false)?;
+                }
+                Arg::Uint(n) => integer(out, &Spec { precision: None, ..spec }, "", n, 10, false)?,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    out.write_str(&fmt[literal..])
+}
+
+/// Format into a byte buffer, C `snprintf` style
+///
+/// Writes as much as fits and returns the full formatted length, which is
+/// larger than `buf` if the output was cut short. The output isn't
+/// NUL-terminated.
+pub fn snprintf(buf: &mut [u8], fmt: &str, args: &[Arg]) -> usize {
+    let mut out = SliceWriter { buf, len: 0 };
+    let _ = format(&mut out, fmt, args);
+    out.len
+}
+
+/// Formatter target that fills a slice and counts what didn't fit
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        if self.len < self.buf.len() {
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        }
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+/// Parse a run of decimal digits
+fn digits(bytes: &[u8], i: &mut usize) -> usize {
+    let mut n: usize = 0;
+    while let Some(&c) = bytes.get(*i) {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        n = n.saturating_mul(10).saturating_add((c - b'0') as usize);
+        *i += 1;
+    }
+    n
+}
+
+/// Longest prefix of `s` with at most `max` chars
+fn truncate(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((at, _)) => &s[..at],
+        None => s,
+    }
+}
+
+/// Format an integer with its prefix (sign or 0x), precision and padding
+fn integer<W: Write>(
+    out: &mut W,
+    spec: &Spec,
+    prefix: &str,
+    mut n: u64,
+    radix: u64,
+    upper: bool,
+) -> fmt::Result {
+    const LOWER: &[u8; 16] = b"0123456789abcdef";
+    const UPPER: &[u8; 16] = b"0123456789ABCDEF";
+    let table = if upper { UPPER } else { LOWER };
+
+    // Enough for u64 in octal
+    let mut buf = [0u8; 22];
+    let mut pos = buf.len();
+    loop {
+        pos -= 1;
+        buf[pos] = table[(n % radix) as usize];
+        n /= radix;
+        if n == 0 {
+            break;
+        }
+    }
+    // "%.0d" of zero prints no digits
+    if spec.precision == Some(0) && buf[pos..] == [b'0'] {
+        pos = buf.len();
+    }
+    let digits = core::str::from_utf8(&buf[pos..]).unwrap_or("");
+
+    let zeros = spec.precision.map_or(0, |p| p.saturating_sub(digits.len()));
+    let len = prefix.len() + zeros + digits.len();
+
+    // The 0 flag is ignored with '-' or an explicit precision
+    if spec.zero && !spec.left && spec.precision.is_none() {
+        out.write_str(prefix)?;
+        repeat(out, '0', spec.width.saturating_sub(len))?;
+        return out.write_str(digits);
+    }
+
+    if !spec.left {
+        repeat(out, ' ', spec.width.saturating_sub(len))?;
+    }
+    out.write_str(prefix)?;
+    repeat(out, '0', zeros)?;
+    out.write_str(digits)?;
+    if spec.left {
+        repeat(out, ' ', spec.width.saturating_sub(len))?;
+    }
+    Ok(())
+}
+
+/// Write `prefix` and `s` space-padded to the spec's width
+fn pad<W: Write>(out: &mut W, spec: &Spec, prefix: &str, s: &str) -> fmt::Result {
+    let len = prefix.len() + s.chars().count();
+    if !spec.left {
+        repeat(out, ' ', spec.width.saturating_sub(len))?;
+    }
+    out.write_str(prefix)?;
+    out.write_str(s)?;
+    if spec.left {
+        repeat(out, ' ', spec.width.saturating_sub(len))?;
+    }
+    Ok(())
+}
+
+fn repeat<W: Write>(out: &mut W, c: char, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        out.write_char(c)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(f: &str, args: &[Arg]) -> ([u8; 128], usize) {
+        let mut buf = [0u8; 128];
+        let len = snprintf(&mut buf, f, args);
+        (buf, len)
+    }
+
+    fn check(f: &str, args: &[Arg], expected: &str) {
+        let (buf, len) = fmt(f, args);
+        assert_eq!(core::str::from_utf8(&buf[..len]).unwrap(), expected, "format {:?}", f);
+    }
+
+    #[test]
+    fn test_integers() {
+        check("%d", &[Arg::from(-42)], "-42");
+        check("%i|%u", &[Arg::from(7), Arg::from(7u32)], "7|7");
+        check("%5d|%-5d|%05d", &[Arg::from(-3), Arg::from(3), Arg::from(-3)], "   -3|3    |-0003");
+        check("%+d % d", &[Arg::from(5), Arg::from(5)], "+5  5");
+        check("%.3d|%6.3d", &[Arg::from(7), Arg::from(-7)], "007|  -007");
+        check("%.0d|", &[Arg::from(0)], "|");
+        check("%ld %llu %zu", &[Arg::from(1i64), Arg::from(2u64), Arg::from(3usize)], "1 2 3");
+        check("%d", &[Arg::from(i64::MIN)], "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_hex_and_octal() {
+        check("%x %X", &[Arg::from(0xbeefu32), Arg::from(0xbeefu32)], "beef BEEF");
+        check("%#x %#X %#x", &[Arg::from(255), Arg::from(255), Arg::from(0)], "0xff 0XFF 0");
+        check("%08x|%#08x", &[Arg::from(0x1234), Arg::from(0x1234)], "00001234|0x001234");
+        check("%o %#o", &[Arg::from(8), Arg::from(8)], "10 010");
+        check("%o", &[Arg::from(u64::MAX)], "1777777777777777777777");
+        check("%p", &[Arg::from(0x1000usize)], "0x1000");
+    }
+
+    #[test]
+    fn test_strings_and_chars() {
+        check("[%s]", &[Arg::from("hi")], "[hi]");
+        check("[%5s|%-5s]", &[Arg::from("hi"), Arg::from("hi")], "[   hi|hi   ]");
+        check("[%.2s]", &[Arg::from("hello")], "[he]");
+        check("[%.2s]", &[Arg::from("héllo")], "[hé]");
+        check("%c%c%3c", &[Arg::from('a'), Arg::from(98), Arg::from('é')], "ab  é");
+        check("%s %s", &[Arg::from(-12), Arg::from('x')], "-12 x");
+        check("%d", &[Arg::from(true)], "1");
+    }
+
+    #[test]
+    fn test_odd_specs() {
+        check("100%%", &[], "100%");
+        check("%d and %d", &[Arg::from(1)], "1 and %d");
+        check("%q %d", &[Arg::from(1)], "%q 1");
+        check("tail %", &[], "tail %");
+        check("no specs", &[Arg::from(1)], "no specs");
+    }
+
+    #[test]
+    fn test_snprintf_truncates() {
+        let mut buf = [0u8; 4];
+        let len = snprintf(&mut buf, "%s-%d", &[Arg::from("abc"), Arg::from(12)]);
+        assert_eq!(len, 6);
+        assert_eq!(&buf, b"abc-");
+    }
+}
@@ -0,0 +1,275 @@
+//! Buffered standard streams
+//!
+//! Each stream collects output in a fixed buffer and hands it to SYS_WRITE
+//! in as few calls as it can:
+//!
+//! ```text
+//! Unbuffered  every write goes straight out
+//! Line        flushed when a write contains '\n', or the buffer fills
+//! Block       flushed only when the buffer fills
+//! ```
+//!
+//! stdout picks its mode on first use: Line if fd 1 is a terminal
+//! (SYS_ISATTY), Block if it's a file or pipe. stderr is Line. A write too
+//! big for the buffer skips it after flushing what's pending.
+
+use core::fmt::{self, Write};
+use spin::Mutex;
+use watos_syscall::syscalls;
+
+use crate::printf::{self, Arg};
+
+/// Stream buffer size
+pub const BUF_SIZE: usize = 1024;
+
+/// When a stream flushes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufMode {
+    Unbuffered,
+    Line,
+    Block,
+}
+
+// ============================================================================
+// BUFFER
+// ============================================================================
+
+/// Pending output, flushed through a sink the caller supplies
+pub struct Buffer {
+    data: [u8; BUF_SIZE],
+    len: usize,
+}
+
+impl Buffer {
+    pub const fn new() -> Self {
+        Buffer { data: [0; BUF_SIZE], len: 0 }
+    }
+
+    /// Bytes waiting to be flushed
+    pub fn pending(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Add `bytes`, passing whatever has to go out now to `sink`
+    pub fn write(&mut self, bytes: &[u8], mode: BufMode, sink: &mut impl FnMut(&[u8])) {
+        if mode == BufMode::Unbuffered || bytes.len() >= BUF_SIZE {
+            self.flush(sink);
+            sink(bytes);
+            return;
+        }
+
+        if self.len + bytes.len() > BUF_SIZE {
+            self.flush(sink);
+        }
+        self.data[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+
+        if self.len == BUF_SIZE || (mode == BufMode::Line && bytes.contains(&b'\n')) {
+            self.flush(sink);
+        }
+    }
+
+    /// Pass everything pending to `sink`
+    pub fn flush(&mut self, sink: &mut impl FnMut(&[u8])) {
+        if self.len > 0 {
+            sink(&self.data[..self.len]);
+            self.len = 0;
+        }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// STREAMS
+// ============================================================================
+
+/// A buffered output fd
+pub struct Stream {
+    fd: i32,
+    mode: Option<BufMode>, // None until first use
+    buffer: Buffer,
+}
+
+impl Stream {
+    const fn new(fd: i32, mode: Option<BufMode>) -> Self {
+        Stream { fd, mode, buffer: Buffer::new() }
+    }
+
+    /// Current mode, asking the kernel whether the fd is a terminal the first time
+    pub fn mode(&mut self) -> BufMode {
+        let fd = self.fd;
+        *self.mode.get_or_insert_with(|| {
+            if syscalls::isatty(fd) { BufMode::Line } else { BufMode::Block }
+        })
+    }
+
+    /// Flush, then switch to `mode`
+    pub fn set_mode(&mut self, mode: BufMode) {
+        self.flush();
+        self.mode = Some(mode);
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        let mode = self.mode();
+        let fd = self.fd;
+        self.buffer.write(bytes, mode, &mut |out| write_all(fd, out));
+    }
+
+    pub fn flush(&mut self) {
+        let fd = self.fd;
+        self.buffer.flush(&mut |out| write_all(fd, out));
+    }
+}
+
+impl Write for Stream {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Write all of `bytes`, stopping early if the fd refuses more
+fn write_all(fd: i32, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        let n = syscalls::write(fd, bytes);
+        if n == 0 || n > bytes.len() {
+            return;
+        }
+        bytes = &bytes[n..];
+    }
+}
+
+static STDOUT: Mutex<Stream> = Mutex::new(Stream::new(1, None));
+static STDERR: Mutex<Stream> = Mutex::new(Stream::new(2, Some(BufMode::Line)));
+
+/// Lock stdout, e.g. to hold it across several writes
+pub fn stdout() -> spin::MutexGuard<'static, Stream> {
+    STDOUT.lock()
+}
+
+/// Lock stderr
+pub fn stderr() -> spin::MutexGuard<'static, Stream> {
+    STDERR.lock()
+}
+
+// ============================================================================
+// CONVENIENCE FUNCTIONS
+// ============================================================================
+
+/// Write bytes to stdout
+pub fn write(bytes: &[u8]) {
+    STDOUT.lock().write(bytes);
+}
+
+/// Write bytes to stderr
+pub fn write_err(bytes: &[u8]) {
+    STDERR.lock().write(bytes);
+}
+
+/// Write a string to stdout
+pub fn print(s: &str) {
+    write(s.as_bytes());
+}
+
+/// Write a string to stderr
+pub fn eprint(s: &str) {
+    write_err(s.as_bytes());
+}
+
+/// Format to stdout (behind `print!`)
+pub fn print_fmt(args: fmt::Arguments) {
+    let _ = STDOUT.lock().write_fmt(args);
+}
+
+/// Format to stderr (behind `eprint!`)
+pub fn eprint_fmt(args: fmt::Arguments) {
+    let _ = STDERR.lock().write_fmt(args);
+}
+
+/// C-style printf to stdout
+pub fn printf(fmt: &str, args: &[Arg]) {
+    let _ = printf::format(&mut *STDOUT.lock(), fmt, args);
+}
+
+/// C-style printf to stderr
+pub fn eprintf(fmt: &str, args: &[Arg]) {
+    let _ = printf::format(&mut *STDERR.lock(), fmt, args);
+}
+
+/// Set stdout's buffering mode
+pub fn set_mode(mode: BufMode) {
+    STDOUT.lock().set_mode(mode);
+}
+
+/// Flush stdout
+pub fn flush() {
+    STDOUT.lock().flush();
+}
+
+/// Flush stdout and stderr
+pub fn flush_all() {
+    STDOUT.lock().flush();
+    STDERR.lock().flush();
+}
+
+/// Read from stdin, flushing stdout first so a pending prompt is visible
+pub fn read(buf: &mut [u8]) -> usize {
+    flush();
+    syscalls::read(0, buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run writes through a buffer, recording each flush
+    fn flushes(mode: BufMode, writes: &[&[u8]]) -> ([usize; 8], usize, usize) {
+        let mut buffer = Buffer::new();
+        let mut sizes = [0usize; 8];
+        let mut count = 0;
+        for bytes in writes {
+            buffer.write(bytes, mode, &mut |out| {
+                sizes[count] = out.len();
+                count += 1;
+            });
+        }
+        (sizes, count, buffer.pending().len())
+    }
+
+    #[test]
+    fn test_line_mode_flushes_on_newline() {
+        let (sizes, count, pending) = flushes(BufMode::Line, &[b"ab", b"cd\n", b"ef"]);
+        assert_eq!(&sizes[..count], &[5]);
+        assert_eq!(pending, 2);
+    }
+
+    #[test]
+    fn test_block_mode_waits_for_full_buffer() {
+        let chunk = [b'x'; 600];
+        let (sizes, count, pending) = flushes(BufMode::Block, &[b"a\n", &chunk, &chunk]);
+        assert_eq!(&sizes[..count], &[602]);
+        assert_eq!(pending, 600);
+
+        let exact = [b'x'; BUF_SIZE - 2];
+        let (sizes, count, pending) = flushes(BufMode::Block, &[b"ab", &exact]);
+        assert_eq!(&sizes[..count], &[BUF_SIZE]);
+        assert_eq!(pending, 0);
+    }
+
+    #[test]
+    fn test_large_and_unbuffered_writes_go_straight_out() {
+        let big = [b'x'; BUF_SIZE + 10];
+        let (sizes, count, pending) = flushes(BufMode::Block, &[b"ab", &big]);
+        assert_eq!(&sizes[..count], &[2, BUF_SIZE + 10]);
+        assert_eq!(pending, 0);
+
+        let (sizes, count, pending) = flushes(BufMode::Unbuffered, &[b"ab", b"c"]);
+        assert_eq!(&sizes[..count], &[2, 1]);
+        assert_eq!(pending, 0);
+    }
+}
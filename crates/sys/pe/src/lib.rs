@@ -0,0 +1,413 @@
+//! WATOS PE Loading
+//!
+//! The loading half of `winrun`, which runs Windows console programs:
+//! everything that doesn't touch the kernel, so it can be tested on the
+//! host.
+//!
+//! - Checking a file is an x86 (PE32) or x86-64 (PE32+) console program
+//! - Copying its headers and sections into allocated memory
+//! - Rebasing it with its HIGHLOW or DIR64 base relocations, since the
+//!   memory rarely lands at the preferred image base
+//! - Binding its imports by name through a caller's resolver
+//!
+//! Running a 32-bit program is the caller's part: its memory and every
+//! address the resolver returns must be below 4GB, and its code runs in
+//! compatibility mode (see `watos_syscall::segments`).
+//!
+//! Not supported: GUI programs; DLLs; imports by ordinal; thread-local
+//! storage; resources, exception tables and everything else the Windows
+//! loader does.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let image = unsafe { watos_pe::load(file, &mut alloc, &mut resolve)? };
+//! let entry: extern "win64" fn() -> u32 = unsafe { core::mem::transmute(image.entry) };
+//! let status = entry();
+//! ```
+
+#![no_std]
+
+pub mod pe;
+
+#[cfg(test)]
+mod tests;
+
+use pe::{
+    BaseRelocation, DataDirectory, FileHeader, ImportDescriptor, OptionalHeader32, OptionalHeader64,
+    SectionHeader,
+};
+
+// ============================================================================
+// ERRORS
+// ============================================================================
+
+/// Longest DLL or symbol name kept in an error
+pub const NAME_MAX: usize = 48;
+
+/// A DLL or symbol name, cut to `NAME_MAX` bytes
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Name {
+    bytes: [u8; NAME_MAX],
+    len: u8,
+}
+
+impl Name {
+    fn new(name: &[u8]) -> Self {
+        let len = name.len().min(NAME_MAX);
+        let mut bytes = [0; NAME_MAX];
+        bytes[..len].copy_from_slice(&name[..len]);
+        Name { bytes, len: len as u8 }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl core::fmt::Debug for Name {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", core::str::from_utf8(self.as_bytes()).unwrap_or("?"))
+    }
+}
+
+/// Why a program couldn't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Not a well-formed PE file
+    Malformed(&'static str),
+    /// A PE file this loader can't run
+    Unsupported(&'static str),
+    /// The allocator refused the image's memory
+    NoMemory,
+    /// The resolver knows no such import
+    Unresolved { dll: Name, symbol: Name },
+}
+
+// ============================================================================
+// LOADING
+// ============================================================================
+
+/// What a program's code is built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// i386 (PE32): 32-bit code, with 32-bit import address slots
+    X86,
+    /// x86-64 (PE32+)
+    X64,
+}
+
+/// A loaded program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Image {
+    /// Where the image starts
+    pub base: u64,
+    /// Bytes from `base` it occupies
+    pub size: usize,
+    /// Entry point address
+    pub entry: u64,
+    /// Which mode `entry` must be called in
+    pub arch: Arch,
+}
+
+/// Import lookup: (DLL name, symbol name) to the symbol's address
+pub type Resolver = dyn FnMut(&[u8], &[u8]) -> Option<u64>;
+
+/// The optional header fields `load` uses, from either kind
+struct Optional {
+    image_base: u64,
+    address_of_entry_point: u32,
+    size_of_image: u32,
+    size_of_headers: u32,
+    subsystem: u16,
+    number_of_rva_and_sizes: u32,
+}
+
+impl From<OptionalHeader32> for Optional {
+    fn from(o: OptionalHeader32) -> Self {
+        Optional {
+            image_base: o.image_base as u64,
+            address_of_entry_point: o.address_of_entry_point,
+            size_of_image: o.size_of_image,
+            size_of_headers: o.size_of_headers,
+            subsystem: o.subsystem,
+            number_of_rva_and_sizes: o.number_of_rva_and_sizes,
+        }
+    }
+}
+
+impl From<OptionalHeader64> for Optional {
+    fn from(o: OptionalHeader64) -> Self {
+        Optional {
+            image_base: o.image_base,
+            address_of_entry_point: o.address_of_entry_point,
+            size_of_image: o.size_of_image,
+            size_of_headers: o.size_of_headers,
+            subsystem: o.subsystem,
+            number_of_rva_and_sizes: o.number_of_rva_and_sizes,
+        }
+    }
+}
+
+/// The headers `load` works from
+struct Headers {
+    arch: Arch,
+    optional: Optional,
+    directories: [DataDirectory; 16],
+    sections: usize,
+    section_count: usize,
+}
+
+/// Copy a `T` out of `bytes` at `offset`
+fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(core::mem::size_of::<T>())?;
+    if end > bytes.len() {
+        return None;
+    }
+    Some(unsafe { core::ptr::read_unaligned(bytes.as_ptr().add(offset) as *const T) })
+}
+
+/// File offset of the PE header, if `file` has one
+fn pe_offset(file: &[u8]) -> Option<usize> {
+    if file.get(..2)? != pe::DOS_MAGIC {
+        return None;
+    }
+    let offset = read::<u32>(file, pe::LFANEW_OFFSET)? as usize;
+    (file.get(offset..offset.checked_add(4)?)? == pe::PE_MAGIC).then_some(offset)
+}
+
+/// Is `file` a PE file (of any machine or kind), rather than plain DOS?
+pub fn is_pe(file: &[u8]) -> bool {
+    pe_offset(file).is_some()
+}
+
+fn headers(file: &[u8]) -> Result<Headers, Error> {
+    let offset = pe_offset(file).ok_or(Error::Malformed("no PE header"))?;
+    let header: FileHeader = read(file, offset + 4).ok_or(Error::Malformed("file header outside file"))?;
+    let arch = match header.machine {
+        pe::IMAGE_FILE_MACHINE_AMD64 => Arch::X64,
+        pe::IMAGE_FILE_MACHINE_I386 => Arch::X86,
+        _ => return Err(Error::Unsupported("not an x86 program")),
+    };
+    if header.characteristics & pe::IMAGE_FILE_DLL != 0 {
+        return Err(Error::Unsupported("a DLL, not a program"));
+    }
+
+    let optional_offset = offset + 4 + core::mem::size_of::<FileHeader>();
+    let outside = Error::Malformed("optional header outside file");
+    let magic: u16 = read(file, optional_offset).ok_or(outside)?;
+    let (optional, optional_size) = match (arch, magic) {
+        (Arch::X86, pe::PE32_MAGIC) => (
+            Optional::from(read::<OptionalHeader32>(file, optional_offset).ok_or(outside)?),
+            core::mem::size_of::<OptionalHeader32>(),
+        ),
+        (Arch::X64, pe::PE32PLUS_MAGIC) => (
+            Optional::from(read::<OptionalHeader64>(file, optional_offset).ok_or(outside)?),
+            core::mem::size_of::<OptionalHeader64>(),
+        ),
+        _ => return Err(Error::Malformed("optional header doesn't match the machine")),
+    };
+    match optional.subsystem {
+        pe::IMAGE_SUBSYSTEM_WINDOWS_CUI => {}
+        pe::IMAGE_SUBSYSTEM_WINDOWS_GUI => return Err(Error::Unsupported("GUI program")),
+        _ => return Err(Error::Unsupported("not a console program")),
+    }
+
+    // Directories the header doesn't count stay empty
+    let mut directories = [DataDirectory::default(); 16];
+    let directories_offset = optional_offset + optional_size;
+    let count = (optional.number_of_rva_and_sizes as usize).min(directories.len());
+    for (i, directory) in directories.iter_mut().enumerate().take(count) {
+        *directory = read(file, directories_offset + i * core::mem::size_of::<DataDirectory>())
+            .ok_or(Error::Malformed("data directories outside file"))?;
+    }
+
+    Ok(Headers {
+        arch,
+        optional,
+        directories,
+        sections: optional_offset + header.size_of_optional_header as usize,
+        section_count: header.number_of_sections as usize,
+    })
+}
+
+/// What `file` is built for, or why it can't be loaded at all
+///
+/// Callers need this before [`load`] to know what memory and import
+/// addresses to hand it.
+pub fn arch(file: &[u8]) -> Result<Arch, Error> {
+    headers(file).map(|headers| headers.arch)
+}
+
+/// Load a Windows console program
+///
+/// `alloc` is asked for the image's size in writable, executable bytes,
+/// which are zeroed and filled with the headers and sections. `resolve` is
+/// given each import's DLL and symbol name and returns its address; a
+/// 32-bit program's must fit in 32 bits.
+///
+/// # Safety
+/// `alloc` must return memory valid for the requested size, below 4GB for
+/// a 32-bit program.
+pub unsafe fn load(
+    file: &[u8],
+    alloc: &mut dyn FnMut(usize) -> Option<*mut u8>,
+    resolve: &mut Resolver,
+) -> Result<Image, Error> {
+    let headers = headers(file)?;
+    let optional = &headers.optional;
+    if headers.directories[pe::DIRECTORY_TLS].size != 0 {
+        return Err(Error::Unsupported("thread-local storage"));
+    }
+
+    let size = optional.size_of_image as usize;
+    let header_size = optional.size_of_headers as usize;
+    if header_size > size || header_size > file.len() {
+        return Err(Error::Malformed("headers larger than image"));
+    }
+    let base = alloc(size).ok_or(Error::NoMemory)?;
+    core::ptr::write_bytes(base, 0, size);
+    let image = core::slice::from_raw_parts_mut(base, size);
+    image[..header_size].copy_from_slice(&file[..header_size]);
+
+    for i in 0..headers.section_count {
+        let section: SectionHeader = read(file, headers.sections + i * core::mem::size_of::<SectionHeader>())
+            .ok_or(Error::Malformed("section headers outside file"))?;
+        // Raw data beyond the virtual size is file alignment padding
+        let len = match section.virtual_size {
+            0 => section.size_of_raw_data,
+            virtual_size => section.size_of_raw_data.min(virtual_size),
+        } as usize;
+        let from = section.pointer_to_raw_data as usize;
+        let to = section.virtual_address as usize;
+        let data = file.get(from..from + len).ok_or(Error::Malformed("section outside file"))?;
+        let span = section.virtual_size.max(section.size_of_raw_data) as usize;
+        if to.checked_add(span).is_none_or(|end| end > size) {
+            return Err(Error::Malformed("section outside image"));
+        }
+        image[to..to + len].copy_from_slice(data);
+    }
+
+    let delta = (base as u64).wrapping_sub(optional.image_base);
+    if delta != 0 {
+        let relocs = headers.directories[pe::DIRECTORY_BASERELOC];
+        if relocs.size == 0 {
+            return Err(Error::Unsupported("fixed-address image (no relocations)"));
+        }
+        rebase(image, relocs, delta)?;
+    }
+    bind(image, headers.directories[pe::DIRECTORY_IMPORT], headers.arch, resolve)?;
+
+    if optional.address_of_entry_point as usize >= size {
+        return Err(Error::Malformed("entry point outside image"));
+    }
+    Ok(Image {
+        base: base as u64,
+        size,
+        entry: base as u64 + optional.address_of_entry_point as u64,
+        arch: headers.arch,
+    })
+}
+
+/// Add `delta` to every address the base relocations name
+fn rebase(image: &mut [u8], relocs: DataDirectory, delta: u64) -> Result<(), Error> {
+    let outside = Error::Malformed("relocation outside image");
+    let mut block = relocs.virtual_address as usize;
+    let end = block.checked_add(relocs.size as usize).ok_or(outside)?;
+    while block < end {
+        let header: BaseRelocation = read(image, block).ok_or(outside)?;
+        let block_size = header.size_of_block as usize;
+        if block_size < core::mem::size_of::<BaseRelocation>() {
+            return Err(Error::Malformed("relocation block too small"));
+        }
+        let entries = (block_size - core::mem::size_of::<BaseRelocation>()) / 2;
+        for i in 0..entries {
+            let entry: u16 = read(image, block + core::mem::size_of::<BaseRelocation>() + i * 2).ok_or(outside)?;
+            let at = header.virtual_address as usize + (entry & 0xFFF) as usize;
+            match entry >> 12 {
+                pe::IMAGE_REL_BASED_ABSOLUTE => {}
+                pe::IMAGE_REL_BASED_DIR64 => {
+                    let value: u64 = read(image, at).ok_or(outside)?;
+                    image[at..at + 8].copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+                }
+                pe::IMAGE_REL_BASED_HIGHLOW => {
+                    let value: u32 = read(image, at).ok_or(outside)?;
+                    image[at..at + 4].copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+                }
+                _ => return Err(Error::Unsupported("relocation type")),
+            }
+        }
+        block += block_size;
+    }
+    Ok(())
+}
+
+/// The NUL-terminated string at `rva`
+fn c_str(image: &[u8], rva: usize) -> Result<&[u8], Error> {
+    let rest = image.get(rva..).ok_or(Error::Malformed("name outside image"))?;
+    let len = rest.iter().position(|&b| b == 0).ok_or(Error::Malformed("unterminated name"))?;
+    Ok(&rest[..len])
+}
+
+/// Fill every import address table slot through `resolve`; slots are
+/// 4 bytes in a 32-bit program and 8 in a 64-bit one
+fn bind(
+    image: &mut [u8],
+    imports: DataDirectory,
+    arch: Arch,
+    resolve: &mut Resolver,
+) -> Result<(), Error> {
+    if imports.size == 0 {
+        return Ok(());
+    }
+    let outside = Error::Malformed("import outside image");
+    let mut at = imports.virtual_address as usize;
+    loop {
+        let descriptor: ImportDescriptor = read(image, at).ok_or(outside)?;
+        if descriptor.name == 0 && descriptor.first_thunk == 0 {
+            return Ok(());
+        }
+        let lookup = match descriptor.original_first_thunk {
+            0 => descriptor.first_thunk,
+            rva => rva,
+        } as usize;
+        let dll = Name::new(c_str(image, descriptor.name as usize)?);
+
+        let width = match arch {
+            Arch::X86 => 4,
+            Arch::X64 => 8,
+        };
+        for i in 0.. {
+            let (thunk, by_ordinal) = match arch {
+                Arch::X86 => {
+                    let thunk: u32 = read(image, lookup + i * width).ok_or(outside)?;
+                    (thunk as u64, thunk & pe::IMAGE_ORDINAL_FLAG32 != 0)
+                }
+                Arch::X64 => {
+                    let thunk: u64 = read(image, lookup + i * width).ok_or(outside)?;
+                    (thunk, thunk & pe::IMAGE_ORDINAL_FLAG64 != 0)
+                }
+            };
+            if thunk == 0 {
+                break;
+            }
+            if by_ordinal {
+                return Err(Error::Unsupported("import by ordinal"));
+            }
+            // A hint (u16) precedes the name
+            let symbol = c_str(image, (thunk as u32 as usize).checked_add(2).ok_or(outside)?)?;
+            let address = resolve(dll.as_bytes(), symbol)
+                .ok_or(Error::Unresolved { dll, symbol: Name::new(symbol) })?;
+            let slot = descriptor.first_thunk as usize + i * width;
+            let slot = image.get_mut(slot..slot + width).ok_or(outside)?;
+            match arch {
+                Arch::X86 => {
+                    let address = u32::try_from(address).map_err(|_| Error::Unsupported("import above 4GB"))?;
+                    slot.copy_from_slice(&address.to_le_bytes());
+                }
+                Arch::X64 => slot.copy_from_slice(&address.to_le_bytes()),
+            }
+        }
+        at += core::mem::size_of::<ImportDescriptor>();
+    }
+}
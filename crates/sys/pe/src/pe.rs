@@ -0,0 +1,181 @@
+//! PE/COFF structures
+//!
+//! Only what the loader reads: the headers that say how to lay a PE32 or
+//! PE32+ image out in memory, its base relocations and its import table.
+
+// ============================================================================
+// HEADERS
+// ============================================================================
+
+/// "MZ", at the start of the DOS stub every PE file begins with
+pub const DOS_MAGIC: [u8; 2] = *b"MZ";
+
+/// Offset of the DOS header field holding the PE header's file offset
+pub const LFANEW_OFFSET: usize = 0x3C;
+
+/// "PE\0\0", at the start of the PE header
+pub const PE_MAGIC: [u8; 4] = *b"PE\0\0";
+
+/// COFF file header, after the PE signature
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FileHeader {
+    pub machine: u16,
+    pub number_of_sections: u16,
+    pub time_date_stamp: u32,
+    pub pointer_to_symbol_table: u32,
+    pub number_of_symbols: u32,
+    pub size_of_optional_header: u16,
+    pub characteristics: u16,
+}
+
+/// PE32 optional header, without its data directories
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OptionalHeader32 {
+    pub magic: u16,
+    pub major_linker_version: u8,
+    pub minor_linker_version: u8,
+    pub size_of_code: u32,
+    pub size_of_initialized_data: u32,
+    pub size_of_uninitialized_data: u32,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    pub base_of_data: u32,
+    pub image_base: u32,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub major_os_version: u16,
+    pub minor_os_version: u16,
+    pub major_image_version: u16,
+    pub minor_image_version: u16,
+    pub major_subsystem_version: u16,
+    pub minor_subsystem_version: u16,
+    pub win32_version_value: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub checksum: u32,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub size_of_stack_reserve: u32,
+    pub size_of_stack_commit: u32,
+    pub size_of_heap_reserve: u32,
+    pub size_of_heap_commit: u32,
+    pub loader_flags: u32,
+    pub number_of_rva_and_sizes: u32,
+}
+
+/// PE32+ optional header, without its data directories
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OptionalHeader64 {
+    pub magic: u16,
+    pub major_linker_version: u8,
+    pub minor_linker_version: u8,
+    pub size_of_code: u32,
+    pub size_of_initialized_data: u32,
+    pub size_of_uninitialized_data: u32,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub major_os_version: u16,
+    pub minor_os_version: u16,
+    pub major_image_version: u16,
+    pub minor_image_version: u16,
+    pub major_subsystem_version: u16,
+    pub minor_subsystem_version: u16,
+    pub win32_version_value: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub checksum: u32,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub size_of_stack_reserve: u64,
+    pub size_of_stack_commit: u64,
+    pub size_of_heap_reserve: u64,
+    pub size_of_heap_commit: u64,
+    pub loader_flags: u32,
+    pub number_of_rva_and_sizes: u32,
+}
+
+/// Where a table lives in the image
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+/// Section header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SectionHeader {
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_line_numbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_line_numbers: u16,
+    pub characteristics: u32,
+}
+
+/// Import directory entry, one per DLL
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImportDescriptor {
+    /// Import lookup table; 0 in some old linkers' output, which then use
+    /// the address table for lookup too
+    pub original_first_thunk: u32,
+    pub time_date_stamp: u32,
+    pub forwarder_chain: u32,
+    /// DLL name
+    pub name: u32,
+    /// Import address table, overwritten with the resolved addresses
+    pub first_thunk: u32,
+}
+
+/// Base relocation block header; u16 entries follow
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BaseRelocation {
+    pub virtual_address: u32,
+    pub size_of_block: u32,
+}
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+// Machines
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x014C;
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+// File characteristics
+pub const IMAGE_FILE_DLL: u16 = 0x2000;
+
+// Optional header magic
+pub const PE32_MAGIC: u16 = 0x10B;
+pub const PE32PLUS_MAGIC: u16 = 0x20B;
+
+// Subsystems
+pub const IMAGE_SUBSYSTEM_WINDOWS_GUI: u16 = 2;
+pub const IMAGE_SUBSYSTEM_WINDOWS_CUI: u16 = 3;
+
+// Data directory indices
+pub const DIRECTORY_IMPORT: usize = 1;
+pub const DIRECTORY_BASERELOC: usize = 5;
+pub const DIRECTORY_TLS: usize = 9;
+
+// Base relocation types (top 4 bits of an entry)
+pub const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+pub const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+pub const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+/// Import lookup entry flags: import by ordinal rather than by name
+pub const IMAGE_ORDINAL_FLAG32: u32 = 1 << 31;
+pub const IMAGE_ORDINAL_FLAG64: u64 = 1 << 63;
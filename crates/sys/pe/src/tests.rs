@@ -0,0 +1,202 @@
+//! Loader tests
+//!
+//! Programs are built in memory by [`program`]: headers, then one section
+//! holding the entry point, a pointer the base relocations fix up, and an
+//! import table naming kernel32.dll symbols. They are PE32+ for x86-64 and
+//! PE32 for i386.
+
+extern crate std;
+
+use std::boxed::Box;
+use std::vec;
+use std::vec::Vec;
+
+use crate::pe;
+use crate::{load, Arch, Error, Name};
+
+const IMAGE_BASE: u64 = 0x1_4000_0000;
+const IMAGE_BASE32: u64 = 0x40_0000;
+const SECTION_RVA: usize = 0x1000;
+const SECTION_FILE: usize = 0x200;
+const SECTION_SIZE: usize = 0x400;
+const IMAGE_SIZE: usize = 0x2000;
+
+// Within the section, as RVAs
+const ENTRY: usize = 0x1000;
+const POINTER: usize = 0x1010;
+const IMPORTS: usize = 0x1020;
+const LOOKUP: usize = 0x1100;
+const ADDRESSES: usize = 0x1140;
+const NAMES: usize = 0x1180;
+const DLL: usize = 0x1200;
+const RELOCS: usize = 0x1220;
+
+fn put(file: &mut [u8], at: usize, bytes: &[u8]) {
+    file[at..at + bytes.len()].copy_from_slice(bytes);
+}
+
+/// File offset of an RVA in the section
+fn offset(rva: usize) -> usize {
+    rva - SECTION_RVA + SECTION_FILE
+}
+
+/// A console program for `machine` importing `symbols` from kernel32.dll
+fn program(machine: u16, symbols: &[&str]) -> Vec<u8> {
+    let mut file = vec![0u8; SECTION_FILE + SECTION_SIZE];
+    put(&mut file, 0, b"MZ");
+    put(&mut file, pe::LFANEW_OFFSET, &0x80u32.to_le_bytes());
+    put(&mut file, 0x80, b"PE\0\0");
+
+    let wide = machine == pe::IMAGE_FILE_MACHINE_AMD64;
+    let (fixed_size, slot) = if wide {
+        (core::mem::size_of::<pe::OptionalHeader64>(), 8)
+    } else {
+        (core::mem::size_of::<pe::OptionalHeader32>(), 4)
+    };
+
+    // File header: one section, a full optional header
+    let optional_size = fixed_size + 16 * 8;
+    put(&mut file, 0x84, &machine.to_le_bytes());
+    put(&mut file, 0x86, &1u16.to_le_bytes());
+    put(&mut file, 0x94, &(optional_size as u16).to_le_bytes());
+
+    // Optional header
+    let o = 0x98;
+    if wide {
+        put(&mut file, o, &pe::PE32PLUS_MAGIC.to_le_bytes());
+        put(&mut file, o + 24, &IMAGE_BASE.to_le_bytes());
+    } else {
+        put(&mut file, o, &pe::PE32_MAGIC.to_le_bytes());
+        put(&mut file, o + 28, &(IMAGE_BASE32 as u32).to_le_bytes());
+    }
+    put(&mut file, o + 16, &(ENTRY as u32).to_le_bytes());
+    put(&mut file, o + 56, &(IMAGE_SIZE as u32).to_le_bytes());
+    put(&mut file, o + 60, &(SECTION_FILE as u32).to_le_bytes());
+    put(&mut file, o + 68, &pe::IMAGE_SUBSYSTEM_WINDOWS_CUI.to_le_bytes());
+    put(&mut file, o + fixed_size - 4, &16u32.to_le_bytes());
+    let directory = |index: usize| o + fixed_size + index * 8;
+    put(&mut file, directory(pe::DIRECTORY_IMPORT), &(IMPORTS as u32).to_le_bytes());
+    put(&mut file, directory(pe::DIRECTORY_IMPORT) + 4, &40u32.to_le_bytes());
+    put(&mut file, directory(pe::DIRECTORY_BASERELOC), &(RELOCS as u32).to_le_bytes());
+    put(&mut file, directory(pe::DIRECTORY_BASERELOC) + 4, &12u32.to_le_bytes());
+
+    // Section header
+    let s = o + optional_size;
+    put(&mut file, s, b".text\0\0\0");
+    put(&mut file, s + 8, &0x300u32.to_le_bytes());
+    put(&mut file, s + 12, &(SECTION_RVA as u32).to_le_bytes());
+    put(&mut file, s + 16, &(SECTION_SIZE as u32).to_le_bytes());
+    put(&mut file, s + 20, &(SECTION_FILE as u32).to_le_bytes());
+
+    // Section contents
+    put(&mut file, offset(ENTRY), &[0xC3]);
+    if wide {
+        put(&mut file, offset(POINTER), &(IMAGE_BASE + ENTRY as u64).to_le_bytes());
+    } else {
+        put(&mut file, offset(POINTER), &((IMAGE_BASE32 + ENTRY as u64) as u32).to_le_bytes());
+    }
+    put(&mut file, offset(IMPORTS), &(LOOKUP as u32).to_le_bytes());
+    put(&mut file, offset(IMPORTS) + 12, &(DLL as u32).to_le_bytes());
+    put(&mut file, offset(IMPORTS) + 16, &(ADDRESSES as u32).to_le_bytes());
+    for (i, symbol) in symbols.iter().enumerate() {
+        let name = NAMES + i * 0x20;
+        put(&mut file, offset(LOOKUP) + i * slot, &(name as u64).to_le_bytes()[..slot]);
+        put(&mut file, offset(ADDRESSES) + i * slot, &(name as u64).to_le_bytes()[..slot]);
+        put(&mut file, offset(name) + 2, symbol.as_bytes());
+    }
+    put(&mut file, offset(DLL), b"KERNEL32.dll");
+    put(&mut file, offset(RELOCS), &(SECTION_RVA as u32).to_le_bytes());
+    put(&mut file, offset(RELOCS) + 4, &12u32.to_le_bytes());
+    let kind = if wide { pe::IMAGE_REL_BASED_DIR64 } else { pe::IMAGE_REL_BASED_HIGHLOW };
+    let reloc = (kind << 12) | (POINTER - SECTION_RVA) as u16;
+    put(&mut file, offset(RELOCS) + 8, &reloc.to_le_bytes());
+    file
+}
+
+/// Memory that lives for the rest of the test run
+fn alloc(size: usize) -> Option<*mut u8> {
+    let memory: &mut [u64] = Box::leak(vec![0xAAAA_AAAA_AAAA_AAAAu64; size.div_ceil(8)].into_boxed_slice());
+    Some(memory.as_mut_ptr() as *mut u8)
+}
+
+fn kernel32(dll: &[u8], symbol: &[u8]) -> Option<u64> {
+    if !dll.eq_ignore_ascii_case(b"kernel32.dll") {
+        return None;
+    }
+    match symbol {
+        b"WriteFile" => Some(0x1111),
+        b"ExitProcess" => Some(0x2222),
+        _ => None,
+    }
+}
+
+fn word(base: u64, rva: usize) -> u64 {
+    unsafe { core::ptr::read_unaligned((base as usize + rva) as *const u64) }
+}
+
+fn dword(base: u64, rva: usize) -> u32 {
+    unsafe { core::ptr::read_unaligned((base as usize + rva) as *const u32) }
+}
+
+#[test]
+fn test_load_rebases_and_binds() {
+    let file = program(pe::IMAGE_FILE_MACHINE_AMD64, &["WriteFile", "ExitProcess"]);
+    let image = unsafe { load(&file, &mut alloc, &mut kernel32) }.unwrap();
+
+    assert_eq!(image.arch, Arch::X64);
+    assert_eq!(image.size, IMAGE_SIZE);
+    assert_eq!(image.entry, image.base + ENTRY as u64);
+    assert_eq!(word(image.base, ENTRY) as u8, 0xC3);
+    // Rebased to where the image landed
+    assert_eq!(word(image.base, POINTER), image.base + ENTRY as u64);
+    // Bound, with the lookup table left alone
+    assert_eq!(word(image.base, ADDRESSES), 0x1111);
+    assert_eq!(word(image.base, ADDRESSES + 8), 0x2222);
+    assert_eq!(word(image.base, ADDRESSES + 16), 0);
+    assert_eq!(word(image.base, LOOKUP), NAMES as u64);
+    // Past the section's data, zeroed rather than left as allocated
+    assert_eq!(word(image.base, 0x1800), 0);
+}
+
+#[test]
+fn test_load_refuses() {
+    let file = program(pe::IMAGE_FILE_MACHINE_AMD64, &["WriteFile", "CreateFileA"]);
+    let err = unsafe { load(&file, &mut alloc, &mut kernel32) }.unwrap_err();
+    assert_eq!(err, Error::Unresolved { dll: Name::new(b"KERNEL32.dll"), symbol: Name::new(b"CreateFileA") });
+
+    // 32-bit import slots can't hold a 64-bit address
+    let file = program(pe::IMAGE_FILE_MACHINE_I386, &["WriteFile"]);
+    assert_eq!(
+        unsafe { load(&file, &mut alloc, &mut |_: &[u8], _: &[u8]| Some(1 << 32)) },
+        Err(Error::Unsupported("import above 4GB"))
+    );
+
+    // The optional header must be the machine's kind
+    let mut file = program(pe::IMAGE_FILE_MACHINE_I386, &[]);
+    put(&mut file, 0x84, &pe::IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+    assert_eq!(crate::arch(&file), Err(Error::Malformed("optional header doesn't match the machine")));
+
+    // A DOS program has no PE header
+    let mut file = program(pe::IMAGE_FILE_MACHINE_AMD64, &[]);
+    assert!(crate::is_pe(&file));
+    put(&mut file, pe::LFANEW_OFFSET, &0u32.to_le_bytes());
+    assert!(!crate::is_pe(&file));
+    assert_eq!(unsafe { load(&file, &mut alloc, &mut kernel32) }, Err(Error::Malformed("no PE header")));
+}
+
+#[test]
+fn test_load_32bit() {
+    let file = program(pe::IMAGE_FILE_MACHINE_I386, &["WriteFile", "ExitProcess"]);
+    assert_eq!(crate::arch(&file), Ok(Arch::X86));
+    let image = unsafe { load(&file, &mut alloc, &mut kernel32) }.unwrap();
+
+    assert_eq!(image.arch, Arch::X86);
+    assert_eq!(image.entry, image.base + ENTRY as u64);
+    // HIGHLOW relocation, against the low 32 bits of where it landed
+    assert_eq!(dword(image.base, POINTER), (image.base + ENTRY as u64) as u32);
+    // 4-byte import slots
+    assert_eq!(dword(image.base, ADDRESSES), 0x1111);
+    assert_eq!(dword(image.base, ADDRESSES + 4), 0x2222);
+    assert_eq!(dword(image.base, ADDRESSES + 8), 0);
+    assert_eq!(dword(image.base, LOOKUP), NAMES as u32);
+}
@@ -0,0 +1,205 @@
+//! Core dumps of crashed processes
+//!
+//! A core is an ELF64 `ET_CORE` file in the layout gdb and readelf expect:
+//! a PT_NOTE segment holding NT_PRSTATUS (signal and registers) and
+//! NT_PRPSINFO (name and arguments), followed by one PT_LOAD per run of
+//! mapped pages from the program image, the heap and the used part of the
+//! stack.
+//!
+//! Must be called with the kernel page table loaded: pages are read through
+//! the identity mapping of physical memory.
+
+use alloc::vec::Vec;
+use watos_arch::exceptions::FaultFrame;
+use watos_mem::paging::PAGE_SIZE;
+
+use crate::elf::{EM_X86_64, PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE};
+use crate::{Process, PROCESS_STACK_SIZE};
+
+const ET_CORE: u16 = 4;
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const PRSTATUS_SIZE: usize = 336;
+const PRPSINFO_SIZE: usize = 136;
+/// Offset of pr_reg (struct user_regs_struct) inside prstatus
+const PRSTATUS_REGS: usize = 112;
+
+/// A run of contiguous mapped user pages
+struct Segment {
+    vaddr: u64,
+    pages: Vec<u64>, // physical address of each page
+    flags: u32,
+}
+
+/// Build a core image for `proc`, which died from `signal` with registers `frame`
+///
+/// Returns None if nothing is mapped or the core would be larger than
+/// `limit` bytes; a truncated core is of no use to a debugger.
+pub fn build_core(proc: &Process, frame: &FaultFrame, signal: u32, limit: u64) -> Option<Vec<u8>> {
+    let mut segments = Vec::new();
+    collect(proc, proc.image_start, proc.image_end, PF_R | PF_W | PF_X, &mut segments);
+    collect(proc, proc.heap_base, proc.heap_base + proc.heap_size as u64, PF_R | PF_W, &mut segments);
+    // Only the part of the stack in use: from the faulting RSP to the top
+    let stack_base = proc.stack_top - PROCESS_STACK_SIZE;
+    let stack_low = (frame.rsp & !(PAGE_SIZE as u64 - 1)).clamp(stack_base, proc.stack_top);
+    collect(proc, stack_low, proc.stack_top, PF_R | PF_W, &mut segments);
+    if segments.is_empty() {
+        return None;
+    }
+
+    let notes = notes(proc, frame, signal);
+    let phnum = 1 + segments.len();
+    let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let data_offset = align_up(notes_offset + notes.len(), PAGE_SIZE);
+    let data_size: usize = segments.iter().map(|s| s.pages.len() * PAGE_SIZE).sum();
+    let total = data_offset + data_size;
+    if total as u64 > limit {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(total);
+
+    // ELF header
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    put16(&mut out, ET_CORE);
+    put16(&mut out, EM_X86_64);
+    put32(&mut out, 1);                   // e_version
+    put64(&mut out, 0);                   // e_entry
+    put64(&mut out, EHDR_SIZE as u64);    // e_phoff
+    put64(&mut out, 0);                   // e_shoff
+    put32(&mut out, 0);                   // e_flags
+    put16(&mut out, EHDR_SIZE as u16);
+    put16(&mut out, PHDR_SIZE as u16);
+    put16(&mut out, phnum as u16);
+    put16(&mut out, 0);                   // e_shentsize
+    put16(&mut out, 0);                   // e_shnum
+    put16(&mut out, 0);                   // e_shstrndx
+
+    // Program headers
+    phdr(&mut out, PT_NOTE, 0, notes_offset as u64, 0, notes.len() as u64, 0, 1);
+    let mut offset = data_offset as u64;
+    for seg in &segments {
+        let size = (seg.pages.len() * PAGE_SIZE) as u64;
+        phdr(&mut out, PT_LOAD, seg.flags, offset, seg.vaddr, size, size, PAGE_SIZE as u64);
+        offset += size;
+    }
+
+    out.extend_from_slice(&notes);
+    out.resize(data_offset, 0);
+
+    // Memory contents, read through the identity mapping
+    for seg in &segments {
+        for &phys in &seg.pages {
+            let page = unsafe { core::slice::from_raw_parts(phys as *const u8, PAGE_SIZE) };
+            out.extend_from_slice(page);
+        }
+    }
+
+    Some(out)
+}
+
+/// Append the mapped pages of [start, end) as segments, split at holes
+fn collect(proc: &Process, start: u64, end: u64, flags: u32, segments: &mut Vec<Segment>) {
+    let mut current: Option<Segment> = None;
+    let mut addr = start & !(PAGE_SIZE as u64 - 1);
+    while addr < end {
+        match proc.page_table.lookup(addr) {
+            Some(phys) => {
+                current.get_or_insert_with(|| Segment { vaddr: addr, pages: Vec::new(), flags })
+                    .pages.push(phys & !(PAGE_SIZE as u64 - 1));
+            }
+            None => segments.extend(current.take()),
+        }
+        addr += PAGE_SIZE as u64;
+    }
+    segments.extend(current);
+}
+
+/// NT_PRSTATUS and NT_PRPSINFO notes
+fn notes(proc: &Process, frame: &FaultFrame, signal: u32) -> Vec<u8> {
+    let mut status = [0u8; PRSTATUS_SIZE];
+    status[0..4].copy_from_slice(&signal.to_le_bytes());            // si_signo
+    status[12..14].copy_from_slice(&(signal as u16).to_le_bytes()); // pr_cursig
+    status[32..36].copy_from_slice(&proc.id.to_le_bytes());         // pr_pid
+    status[36..40].copy_from_slice(&proc.ppid.to_le_bytes());       // pr_ppid
+    status[40..44].copy_from_slice(&proc.pgid.to_le_bytes());       // pr_pgrp
+    status[44..48].copy_from_slice(&proc.pgid.to_le_bytes());       // pr_sid
+    let (user_ms, system_ms) = (crate::ticks_to_ms(proc.user_ticks), crate::ticks_to_ms(proc.kernel_ticks));
+    timeval(&mut status[48..64], user_ms);                          // pr_utime
+    timeval(&mut status[64..80], system_ms);                        // pr_stime
+
+    // struct user_regs_struct
+    let regs = [
+        frame.r15, frame.r14, frame.r13, frame.r12, frame.rbp, frame.rbx,
+        frame.r11, frame.r10, frame.r9, frame.r8, frame.rax, frame.rcx,
+        frame.rdx, frame.rsi, frame.rdi, u64::MAX /* orig_rax */,
+        frame.rip, frame.cs, frame.rflags, frame.rsp, frame.ss,
+        0, 0, frame.ss, frame.ss, frame.ss, frame.ss, // fs_base, gs_base, ds, es, fs, gs
+    ];
+    for (i, reg) in regs.iter().enumerate() {
+        let at = PRSTATUS_REGS + i * 8;
+        status[at..at + 8].copy_from_slice(&reg.to_le_bytes());
+    }
+
+    let mut info = [0u8; PRPSINFO_SIZE];
+    info[1] = b'R';                                                 // pr_sname
+    info[16..20].copy_from_slice(&proc.uid.to_le_bytes());          // pr_uid
+    info[20..24].copy_from_slice(&proc.gid.to_le_bytes());          // pr_gid
+    info[24..28].copy_from_slice(&proc.id.to_le_bytes());           // pr_pid
+    info[28..32].copy_from_slice(&proc.ppid.to_le_bytes());         // pr_ppid
+    info[32..36].copy_from_slice(&proc.pgid.to_le_bytes());         // pr_pgrp
+    info[36..40].copy_from_slice(&proc.pgid.to_le_bytes());         // pr_sid
+    copy_cstr(&mut info[40..56], proc.name.as_bytes());             // pr_fname
+    copy_cstr(&mut info[56..136], proc.command_line().as_bytes());  // pr_psargs
+
+    let mut out = Vec::new();
+    note(&mut out, NT_PRSTATUS, &status);
+    note(&mut out, NT_PRPSINFO, &info);
+    out
+}
+
+fn note(out: &mut Vec<u8>, kind: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    put32(out, NAME.len() as u32);
+    put32(out, desc.len() as u32);
+    put32(out, kind);
+    out.extend_from_slice(NAME);
+    out.resize(align_up(out.len(), 4), 0);
+    out.extend_from_slice(desc);
+    out.resize(align_up(out.len(), 4), 0);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn phdr(out: &mut Vec<u8>, kind: u32, flags: u32, offset: u64, vaddr: u64, filesz: u64, memsz: u64, align: u64) {
+    put32(out, kind);
+    put32(out, flags);
+    put64(out, offset);
+    put64(out, vaddr);
+    put64(out, 0); // p_paddr
+    put64(out, filesz);
+    put64(out, memsz);
+    put64(out, align);
+}
+
+fn timeval(out: &mut [u8], ms: u64) {
+    out[0..8].copy_from_slice(&(ms / 1000).to_le_bytes());
+    out[8..16].copy_from_slice(&((ms % 1000) * 1000).to_le_bytes());
+}
+
+/// Copy a string, always leaving a terminating NUL
+fn copy_cstr(out: &mut [u8], s: &[u8]) {
+    let len = s.len().min(out.len() - 1);
+    out[..len].copy_from_slice(&s[..len]);
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn put16(out: &mut Vec<u8>, v: u16) { out.extend_from_slice(&v.to_le_bytes()); }
+fn put32(out: &mut Vec<u8>, v: u32) { out.extend_from_slice(&v.to_le_bytes()); }
+fn put64(out: &mut Vec<u8>, v: u64) { out.extend_from_slice(&v.to_le_bytes()); }
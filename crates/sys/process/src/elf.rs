@@ -2,6 +2,11 @@
 //!
 //! Parses ELF64 executables and loads them into memory.
 
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::lazy::{self, FileMapping, PageSource};
+
 /// ELF64 header
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -61,6 +66,16 @@ pub const EM_X86_64: u16 = 0x3E;
 pub const ET_EXEC: u16 = 2;  // Executable
 pub const ET_DYN: u16 = 3;   // Shared object (PIE)
 
+// Auxiliary vector entries passed to a program interpreter - must match
+// watos_syscall::auxv
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;         // Copy of the program's headers
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_BASE: u64 = 7;         // Interpreter load bias
+pub const AT_ENTRY: u64 = 9;        // Program entry point (relocated)
+pub const AT_LOAD_BIAS: u64 = 0x1000; // Program vaddr -> address offset (WATOS)
+
 /// Parsed ELF64 information
 pub struct Elf64 {
     pub entry: u64,
@@ -130,6 +145,24 @@ impl Elf64 {
         })
     }
 
+    /// Path of the program interpreter (PT_INTERP) of a dynamically linked program
+    pub fn interp<'a>(&self, data: &'a [u8]) -> Option<&'a str> {
+        let phdr = self.phdrs.iter().find(|p| p.ptype == PT_INTERP)?;
+        let start = phdr.offset as usize;
+        let bytes = data.get(start..start.checked_add(phdr.filesz as usize)?)?;
+        let path = bytes.split(|&b| b == 0).next()?;
+        core::str::from_utf8(path).ok().filter(|p| !p.is_empty())
+    }
+
+    /// Lowest PT_LOAD virtual address
+    pub fn min_vaddr(&self) -> u64 {
+        self.phdrs.iter()
+            .filter(|p| p.ptype == PT_LOAD)
+            .map(|p| p.vaddr)
+            .min()
+            .unwrap_or(0)
+    }
+
     /// Load program segments into memory
     pub fn load_segments(&self, data: &[u8], load_base: u64) -> Result<(), &'static str> {
         // Find the lowest vaddr to calculate offset
@@ -242,207 +275,38 @@ impl Elf64 {
         Ok(())
     }
 
-    /// Load ELF segments with memory protection via page tables
+    /// Map the PT_LOAD segments into a process, to be loaded from `source`
+    /// page by page as the process touches them (see `lazy`)
     ///
-    /// Allocates fresh physical pages for each segment to ensure process isolation.
-    pub fn load_segments_protected(&self, data: &[u8], load_base: u64, page_table: &mut watos_mem::paging::ProcessPageTable) -> Result<(), &'static str> {
-        use watos_mem::paging::{flags as page_flags, PAGE_SIZE};
-        use super::{debug_serial, debug_hex};
-
-        unsafe {
-            debug_serial(b"load_segments: phdrs ptr=0x");
-            debug_hex(self.phdrs.as_ptr() as u64);
-            debug_serial(b" len=");
-            debug_hex(self.phdrs.len() as u64);
-            debug_serial(b"\r\n");
-        }
-
-        let min_vaddr = self.phdrs.iter()
-            .filter(|p| p.ptype == PT_LOAD)
-            .map(|p| p.vaddr)
-            .min()
-            .unwrap_or(0);
-
-        unsafe {
-            debug_serial(b"load_segments: min_vaddr=0x");
-            debug_hex(min_vaddr);
-            debug_serial(b"\r\n");
-        }
-
-        for phdr in self.phdrs {
-            if phdr.ptype != PT_LOAD {
-                continue;
-            }
-
-            let src_offset = phdr.offset as usize;
-            let file_size = phdr.filesz as usize;
-            let mem_size = phdr.memsz as usize;
-
+    /// Each page gets a fresh physical page when loaded, so processes
+    /// running the same program share nothing.
+    pub fn map_segments(
+        &self,
+        source: &Arc<dyn PageSource>,
+        load_base: u64,
+        page_table: &mut watos_mem::paging::ProcessPageTable,
+        mappings: &mut Vec<FileMapping>,
+    ) -> Result<(), &'static str> {
+        let min_vaddr = self.min_vaddr();
+
+        for phdr in self.phdrs.iter().filter(|p| p.ptype == PT_LOAD) {
             // Calculate virtual address:
             // - For PIE: relocate relative to load_base
             // - For non-PIE: use original vaddr (code has absolute addresses)
-            let virt_addr = if self.is_pie {
+            let vaddr = if self.is_pie {
                 load_base + (phdr.vaddr - min_vaddr)
             } else {
                 phdr.vaddr
             };
 
-            unsafe {
-                debug_serial(b"  segment: vaddr=0x");
-                debug_hex(phdr.vaddr);
-                debug_serial(b" dest=0x");
-                debug_hex(virt_addr);
-                debug_serial(b" offset=0x");
-                debug_hex(phdr.offset);
-                debug_serial(b" filesz=0x");
-                debug_hex(phdr.filesz);
-                debug_serial(b"\r\n");
-            }
-
-            // Validate source
-            if src_offset + file_size > data.len() {
-                return Err("Segment outside file");
-            }
-
-            // Calculate pages needed
-            let page_start = virt_addr & !(PAGE_SIZE as u64 - 1);
-            let page_end = (virt_addr + mem_size as u64 + PAGE_SIZE as u64 - 1) & !(PAGE_SIZE as u64 - 1);
-            let num_pages = ((page_end - page_start) / PAGE_SIZE as u64) as usize;
-
-            // Determine page flags from ELF flags
-            let mut flags = page_flags::PRESENT;
-            if phdr.flags & PF_W != 0 {
-                flags |= page_flags::WRITABLE;
-            }
-
-            unsafe {
-                debug_serial(b"  copying from 0x");
-                debug_hex(data.as_ptr() as u64 + src_offset as u64);
-                debug_serial(b" to 0x");
-                debug_hex(virt_addr);
-                debug_serial(b"\r\n  copying ");
-                debug_hex(file_size as u64);
-                debug_serial(b" bytes...\r\n");
-            }
-
-            // For each page needed:
-            // 1. Check if page is already mapped (from previous segment)
-            // 2. Allocate a fresh physical page
-            // 3. Copy existing page contents if overlapping (preserve previous segment data)
-            // 4. Copy new segment data to it
-            // 5. Map virtual address to physical page
-            for i in 0..num_pages {
-                let page_virt = page_start + (i as u64 * PAGE_SIZE as u64);
-
-                // Check if this page is already mapped from a previous segment
-                let existing_phys = page_table.lookup(page_virt);
-
-                // Allocate physical page
-                let phys_page = watos_mem::phys::alloc_page()
-                    .ok_or("Out of physical memory")?;
-
-                // Track the physical page for cleanup when process exits
-                page_table.track_phys_page(phys_page);
-
-                if i == 0 {
-                    unsafe {
-                        debug_serial(b"  phys page[0]=0x");
-                        debug_hex(phys_page);
-                        debug_serial(b" -> virt=0x");
-                        debug_hex(page_virt);
-                        if existing_phys.is_some() {
-                            debug_serial(b" (overlap from 0x");
-                            debug_hex(existing_phys.unwrap());
-                            debug_serial(b")");
-                        }
-                        debug_serial(b"\r\n");
-                    }
-                }
-
-                // Calculate what part of the segment goes in this page
-                let page_offset = if page_virt < virt_addr { 0 } else { page_virt - virt_addr };
-                let dest_in_page = if page_virt < virt_addr { (virt_addr - page_virt) as usize } else { 0 };
-
-                // If this page was already mapped, copy existing contents first
-                // Otherwise, zero the page
-                unsafe {
-                    if let Some(old_phys) = existing_phys {
-                        // Copy existing page contents to preserve data from previous segment
-                        core::ptr::copy_nonoverlapping(
-                            old_phys as *const u8,
-                            phys_page as *mut u8,
-                            PAGE_SIZE
-                        );
-                    } else {
-                        // Zero the new page
-                        core::ptr::write_bytes(phys_page as *mut u8, 0, PAGE_SIZE);
-                    }
-                }
-
-                // Copy file data if this page contains any
-                if page_offset < file_size as u64 {
-                    let copy_start = page_offset as usize;
-                    let copy_end = ((page_offset as usize + PAGE_SIZE - dest_in_page).min(file_size)).min(copy_start + PAGE_SIZE - dest_in_page);
-                    let copy_len = copy_end.saturating_sub(copy_start);
-
-                    if copy_len > 0 {
-                        unsafe {
-                            let src = data.as_ptr().add(src_offset + copy_start);
-                            let dest = (phys_page as *mut u8).add(dest_in_page);
-
-                            // Debug: if this is the .got segment (small size), print source value
-                            if file_size < 0x20 && i == 0 {
-                                debug_serial(b"  GOT debug: src=0x");
-                                debug_hex(src as u64);
-                                debug_serial(b" val=0x");
-                                debug_hex(*(src as *const u64));
-                                debug_serial(b" dest=0x");
-                                debug_hex(dest as u64);
-                                debug_serial(b"\r\n");
-                            }
-
-                            core::ptr::copy_nonoverlapping(src, dest, copy_len);
-
-                            // Debug: verify the copy worked for .got segment
-                            if file_size < 0x20 && i == 0 {
-                                debug_serial(b"  GOT after: val=0x");
-                                debug_hex(*(dest as *const u64));
-                                debug_serial(b"\r\n");
-                            }
-                        }
-                    }
-                }
-
-                // Zero BSS portion: memory beyond file_size up to mem_size
-                // This handles the case where memsz > filesz (.bss section)
-                if mem_size > file_size {
-                    // Calculate what BSS portion falls in this page
-                    let bss_start_in_seg = file_size as u64;
-                    let bss_end_in_seg = mem_size as u64;
-
-                    // This page covers segment offsets from page_offset to page_offset + PAGE_SIZE
-                    let page_end_offset = page_offset + (PAGE_SIZE - dest_in_page) as u64;
-
-                    // BSS in this page: overlap of [bss_start_in_seg, bss_end_in_seg) and [page_offset, page_end_offset)
-                    let bss_in_page_start = bss_start_in_seg.max(page_offset);
-                    let bss_in_page_end = bss_end_in_seg.min(page_end_offset);
-
-                    if bss_in_page_end > bss_in_page_start {
-                        let zero_offset_in_page = dest_in_page + (bss_in_page_start - page_offset) as usize;
-                        let zero_len = (bss_in_page_end - bss_in_page_start) as usize;
-                        unsafe {
-                            core::ptr::write_bytes(
-                                (phys_page as *mut u8).add(zero_offset_in_page),
-                                0,
-                                zero_len
-                            );
-                        }
-                    }
-                }
-
-                // Map virtual page to physical page in process's page table
-                page_table.map_user_page(page_virt, phys_page, flags)?;
-            }
+            lazy::map(page_table, mappings, FileMapping {
+                vaddr,
+                memsz: phdr.memsz,
+                offset: phdr.offset,
+                filesz: phdr.filesz,
+                writable: phdr.flags & PF_W != 0,
+                source: source.clone(),
+            })?;
         }
 
         Ok(())
@@ -0,0 +1,157 @@
+//! Cache of recently executed binaries
+//!
+//! Starting a program used to mean reading all of it through the VFS, every
+//! time; the shell runs the same few commands over and over. The cache keeps
+//! the images of the last few binaries executed, keyed by path and by the
+//! file's identity (device, inode, size and modification time), so a binary
+//! that was replaced or rewritten is read again rather than served stale.
+//!
+//! Images are shared (`Arc`), so a hit costs no copy until `exec` loads the
+//! segments into the new process. The cache holds at most
+//! `vm.exec_cache.max_bytes` of images, dropping the least recently run
+//! first; 0 turns it off.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr::addr_of_mut;
+
+/// Most images kept, however small
+const MAX_ENTRIES: usize = 16;
+
+/// Default `vm.exec_cache.max_bytes`
+pub const DEFAULT_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Which file an image was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecKey {
+    pub path: String,
+    pub dev: u64,
+    pub inode: u64,
+    pub size: u64,
+    pub mtime: u64,
+}
+
+struct Entry {
+    key: ExecKey,
+    image: Arc<[u8]>,
+    /// `ExecCache::clock` when last run
+    used: u64,
+}
+
+struct ExecCache {
+    entries: Vec<Entry>,
+    max_bytes: u64,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+static mut CACHE: ExecCache = ExecCache {
+    entries: Vec::new(),
+    max_bytes: DEFAULT_MAX_BYTES,
+    clock: 0,
+    hits: 0,
+    misses: 0,
+};
+
+fn cache() -> &'static mut ExecCache {
+    // Only touched from syscalls, which run one at a time
+    unsafe { &mut *addr_of_mut!(CACHE) }
+}
+
+impl ExecCache {
+    fn bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.image.len() as u64).sum()
+    }
+
+    /// Drop least recently run images until `incoming` more bytes fit in
+    /// at most `max_entries`
+    fn trim(&mut self, incoming: u64, max_entries: usize) {
+        while !self.entries.is_empty()
+            && (self.bytes() + incoming > self.max_bytes || self.entries.len() > max_entries)
+        {
+            let oldest = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            self.entries.swap_remove(oldest);
+        }
+    }
+}
+
+/// The cached image of `key`'s file, if it is still the same file
+pub fn lookup(key: &ExecKey) -> Option<Arc<[u8]>> {
+    let cache = cache();
+    cache.clock += 1;
+    let clock = cache.clock;
+    match cache.entries.iter_mut().find(|e| e.key == *key) {
+        Some(entry) => {
+            entry.used = clock;
+            cache.hits += 1;
+            Some(entry.image.clone())
+        }
+        None => {
+            cache.misses += 1;
+            None
+        }
+    }
+}
+
+/// Remember the image just read for `key`, replacing any older image of
+/// the same path
+pub fn insert(key: ExecKey, image: Arc<[u8]>) {
+    forget(&key.path);
+    let cache = cache();
+    let size = image.len() as u64;
+    if size > cache.max_bytes {
+        return;
+    }
+    cache.trim(size, MAX_ENTRIES - 1);
+    cache.clock += 1;
+    let used = cache.clock;
+    cache.entries.push(Entry { key, image, used });
+}
+
+/// Drop the image of `path`, which is being written to: a rewrite within
+/// the same second at the same size would otherwise look unchanged
+pub fn forget(path: &str) {
+    cache().entries.retain(|e| e.key.path != path);
+}
+
+/// Cache statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecCacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub fn stats() -> ExecCacheStats {
+    let cache = cache();
+    ExecCacheStats {
+        entries: cache.entries.len(),
+        bytes: cache.bytes(),
+        hits: cache.hits,
+        misses: cache.misses,
+    }
+}
+
+pub(crate) fn register_sysctls() {
+    let _ = watos_sysctl::register(watos_sysctl::Tunable {
+        name: "vm.exec_cache.max_bytes",
+        description: "bytes of recently run binaries kept in memory (0 = off)",
+        kind: watos_sysctl::Kind::Int { min: 0, max: 256 * 1024 * 1024 },
+        get: || cache().max_bytes,
+        set: |bytes| {
+            let cache = cache();
+            cache.max_bytes = bytes;
+            cache.trim(0, MAX_ENTRIES);
+            true
+        },
+    });
+}
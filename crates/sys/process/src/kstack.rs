@@ -0,0 +1,60 @@
+//! Kernel stacks
+//!
+//! Interrupts and syscalls from a process run on its kernel stack: process
+//! slot `i` owns the `KERNEL_STACK_SIZE` bytes at `KERNEL_STACKS_START + i *
+//! KERNEL_STACK_SIZE`. The lowest page of each is a guard page, missing from
+//! every process page table, so a stack that runs off its bottom faults
+//! (and double faults onto IST1) instead of overwriting the stack below.
+//!
+//! The word just above the guard page holds a canary, checked on every
+//! syscall exit. It catches overflows while the kernel page table is
+//! loaded, which maps the guard pages like any other memory.
+
+use watos_mem::paging::{KERNEL_STACKS_START, KERNEL_STACK_GUARD, KERNEL_STACK_SIZE, KERNEL_STACK_SLOTS};
+
+use crate::{CURRENT_PROCESS, MAX_PROCESSES, PROCESSES};
+
+const _: () = assert!(MAX_PROCESSES <= KERNEL_STACK_SLOTS);
+
+/// Value of an intact canary
+const CANARY: u64 = 0x5741_544F_535F_4B53; // "WATOS_KS"
+
+fn base(slot: usize) -> u64 {
+    KERNEL_STACKS_START + slot as u64 * KERNEL_STACK_SIZE
+}
+
+fn canary(slot: usize) -> *mut u64 {
+    (base(slot) + KERNEL_STACK_GUARD) as *mut u64
+}
+
+fn slot_of(pid: u32) -> Option<usize> {
+    unsafe { (0..MAX_PROCESSES).find(|&i| PROCESSES[i].as_ref().is_some_and(|p| p.id == pid)) }
+}
+
+/// Top of the kernel stack of process `pid`, with its canary set
+pub(crate) fn prepare(pid: u32) -> Option<u64> {
+    let slot = slot_of(pid)?;
+    unsafe { canary(slot).write_volatile(CANARY); }
+    Some(base(slot) + KERNEL_STACK_SIZE)
+}
+
+/// Check the canary of the current process's kernel stack; the process's
+/// pid if it was overwritten
+pub fn check() -> Result<(), u32> {
+    let Some(pid) = (unsafe { CURRENT_PROCESS }) else { return Ok(()) };
+    match slot_of(pid) {
+        Some(slot) if unsafe { canary(slot).read_volatile() } != CANARY => Err(pid),
+        _ => Ok(()),
+    }
+}
+
+/// The process whose kernel stack, guard page included, holds `addr`.
+/// Installed as the crash report's stack owner lookup.
+pub fn owner(addr: u64) -> Option<u32> {
+    let end = base(MAX_PROCESSES);
+    if !(KERNEL_STACKS_START..end).contains(&addr) {
+        return None;
+    }
+    let slot = ((addr - KERNEL_STACKS_START) / KERNEL_STACK_SIZE) as usize;
+    unsafe { PROCESSES[slot].as_ref().map(|p| p.id) }
+}
@@ -0,0 +1,158 @@
+//! Executable pages loaded on first touch
+//!
+//! exec doesn't copy a program's segments into memory. It records which
+//! bytes of the file belong at which addresses ([`FileMapping`], one per
+//! PT_LOAD segment) and leaves the pages not present with `LAZY` set. The
+//! first touch faults and [`fault_in`] fills a fresh page from the file;
+//! pages a program never touches - most of a large binary, often - are
+//! never read or allocated.
+//!
+//! The bytes come from a [`PageSource`]: an image already in memory (one
+//! from the exec cache, say), or the executable file itself, read through
+//! the VFS and so the block cache. Once loaded a page is an ordinary
+//! private page of the process, and can be swapped like any other.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use watos_mem::paging::{flags as page_flags, MemRegion, ProcessPageTable, PAGE_SIZE};
+
+/// Where the pages of an executable are read from
+pub trait PageSource: Send + Sync {
+    /// Length of the file
+    fn size(&self) -> u64;
+
+    /// Fill `buf` with the bytes at `offset`; false if they can't be read
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> bool;
+}
+
+/// An executable read into memory whole
+impl PageSource for Arc<[u8]> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> bool {
+        let start = offset as usize;
+        match self.get(start..start + buf.len()) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A program file to exec
+#[derive(Clone)]
+pub struct Executable {
+    /// The start of the file, holding at least the ELF and program headers
+    /// (the whole file if it is in memory)
+    pub head: Arc<[u8]>,
+    /// Where the segments are read from
+    pub source: Arc<dyn PageSource>,
+}
+
+impl Executable {
+    /// A program read into memory whole
+    pub fn from_image(image: Arc<[u8]>) -> Self {
+        Executable { head: image.clone(), source: Arc::new(image) }
+    }
+}
+
+/// A segment of an executable mapped into a process
+#[derive(Clone)]
+pub struct FileMapping {
+    /// Where the segment starts in memory (not necessarily page aligned)
+    pub vaddr: u64,
+    /// Size in memory; beyond `filesz` it is zeroed (.bss)
+    pub memsz: u64,
+    /// Where the segment starts in the file
+    pub offset: u64,
+    /// Size in the file
+    pub filesz: u64,
+    pub writable: bool,
+    pub source: Arc<dyn PageSource>,
+}
+
+impl FileMapping {
+    /// Page-aligned addresses of the pages the segment touches
+    pub fn pages(&self) -> impl Iterator<Item = u64> {
+        let page = PAGE_SIZE as u64;
+        let start = self.vaddr & !(page - 1);
+        let end = (self.vaddr + self.memsz).div_ceil(page) * page;
+        (start..end).step_by(PAGE_SIZE)
+    }
+
+    /// Does the segment touch the page at `page_virt`?
+    fn touches(&self, page_virt: u64) -> bool {
+        page_virt < self.vaddr + self.memsz && page_virt + PAGE_SIZE as u64 > self.vaddr
+    }
+
+    /// Write the segment's part of the page at `page_virt` into `page`,
+    /// over whatever an earlier segment left there; false on a read error
+    fn fill(&self, page_virt: u64, page: &mut [u8]) -> bool {
+        let start = page_virt.max(self.vaddr);
+        let end = (page_virt + PAGE_SIZE as u64).min(self.vaddr + self.memsz);
+        if start >= end {
+            return true;
+        }
+        let in_page = |addr: u64| (addr - page_virt) as usize;
+        page[in_page(start)..in_page(end)].fill(0);
+        let file_end = end.min(self.vaddr + self.filesz);
+        if start < file_end {
+            let bytes = &mut page[in_page(start)..in_page(file_end)];
+            return self.source.read_at(self.offset + (start - self.vaddr), bytes);
+        }
+        true
+    }
+}
+
+/// Leave the pages of `mapping` to be loaded on first touch, and remember
+/// it in `mappings`
+pub(crate) fn map(
+    page_table: &mut ProcessPageTable,
+    mappings: &mut Vec<FileMapping>,
+    mapping: FileMapping,
+) -> Result<(), &'static str> {
+    if mapping.filesz > mapping.memsz {
+        return Err("Segment larger in file than in memory");
+    }
+    let file_end = mapping.offset.checked_add(mapping.filesz).ok_or("Segment outside file")?;
+    if file_end > mapping.source.size() {
+        return Err("Segment outside file");
+    }
+    for page_virt in mapping.pages() {
+        page_table.map_user_page(page_virt, 0, page_flags::LAZY)?;
+    }
+    mappings.push(mapping);
+    Ok(())
+}
+
+/// Load the page at `virt` (page aligned) if it is waiting to be: every
+/// segment touching it is copied in, in program header order, so a page
+/// shared by two segments ends up as if they had been copied one after the
+/// other. False if the page isn't lazy, or its bytes couldn't be read.
+pub(crate) fn fault_in(table: &mut ProcessPageTable, mappings: &[FileMapping], virt: u64) -> bool {
+    let Some(entry) = table.entry(virt) else { return false };
+    if entry & page_flags::PRESENT != 0 || entry & page_flags::LAZY == 0 {
+        return false;
+    }
+    let Some(phys) = watos_mem::phys::alloc_page() else { return false };
+    let page = unsafe { core::slice::from_raw_parts_mut(phys as *mut u8, PAGE_SIZE) };
+    page.fill(0);
+
+    let mut flags = page_flags::PRESENT;
+    for mapping in mappings.iter().filter(|m| m.touches(virt)) {
+        if !mapping.fill(virt, page) {
+            watos_mem::phys::free_page(phys);
+            return false;
+        }
+        if mapping.writable {
+            flags |= page_flags::WRITABLE;
+        }
+    }
+    table.track_phys_page(phys);
+    table.map_region_page(virt, phys, flags, MemRegion::Code).is_ok()
+}
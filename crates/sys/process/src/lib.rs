@@ -5,11 +5,23 @@
 #![no_std]
 
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
-use watos_mem::paging::{ProcessPageTable, flags as page_flags, PAGE_SIZE};
+use watos_mem::paging::{ProcessPageTable, MemRegion, flags as page_flags, LARGE_PAGE_SIZE, PAGE_SIZE};
 
 pub mod elf;
+mod coredump;
+pub mod swap;
+pub mod kstack;
+pub mod exec_cache;
+pub mod lazy;
+mod registry;
+pub mod sched;
+pub mod uheap;
+
+pub use lazy::{Executable, PageSource};
 
 /// Boot info passed from bootloader at 0x80000
 #[repr(C)]
@@ -83,11 +95,21 @@ pub struct FileObject {
     pub fs_id: u32,
 }
 
+/// Audio playback stream kernel object. The format is in SYS_AUDIO_SET_CONFIG
+/// terms, so it can be applied to the output device before each write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioObject {
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub format: u8,
+}
+
 /// Kernel objects that can be stored in handle tables
 #[derive(Debug)]
 pub enum KernelObject {
     File(FileObject),
     Console(ConsoleObject),
+    Audio(AudioObject),
 }
 
 /// Per-process handle table
@@ -128,6 +150,12 @@ impl HandleTable {
         handle
     }
 
+    pub fn open_audio(&mut self, stream: AudioObject) -> Handle {
+        let handle = self.allocate_handle();
+        self.objects.insert(handle, KernelObject::Audio(stream));
+        handle
+    }
+
     pub fn close(&mut self, handle: Handle) -> bool {
         self.objects.remove(&handle).is_some()
     }
@@ -160,6 +188,13 @@ impl HandleTable {
             _ => None,
         }
     }
+
+    pub fn get_audio_mut(&mut self, handle: Handle) -> Option<&mut AudioObject> {
+        match self.objects.get_mut(&handle) {
+            Some(KernelObject::Audio(a)) => Some(a),
+            _ => None,
+        }
+    }
 }
 
 impl Default for HandleTable {
@@ -173,62 +208,108 @@ impl Default for HandleTable {
 pub enum ProcessState {
     Ready,
     Running,
+    /// In SYS_EXEC until this child exits, or (0) in SYS_WAIT until any
+    /// child changes state
+    Waiting(u32),
+    /// In SYS_SLEEP until the clock reaches this many milliseconds
+    Sleeping(u64),
     Terminated(i32),
 }
 
 /// Process control block
 pub struct Process {
     pub id: u32,
+    pub ppid: u32,     // Parent process ID (0 = started by the kernel)
     pub name: String,
-    pub args: String,  // Command line arguments
+    pub argv: Vec<String>, // Arguments, argv[0] being the program name
     pub state: ProcessState,
     pub entry_point: u64,
     pub stack_top: u64,
+    pub initial_sp: u64, // rsp on entry: below the auxiliary vector, if any
     pub heap_base: u64,
-    pub heap_size: usize,
+    pub heap_size: usize, // Bytes mapped for the heap so far
+    pub heap: uheap::UserHeap, // SYS_MALLOC blocks
     pub page_table: ProcessPageTable,
     pub handle_table: HandleTable,
     pub uid: u32,  // User ID
     pub gid: u32,  // Group ID
+    pub pgid: u32, // Process group ID (job control)
     pub environment: BTreeMap<String, String>,  // Environment variables
+    pub user_ticks: u64,   // Timer ticks spent in ring 3
+    pub kernel_ticks: u64, // Timer ticks spent in the kernel on its behalf
+    pub malloc_bytes: u64, // Bytes currently allocated through SYS_MALLOC
+    pub io: IoAccount,     // Reads and writes through file descriptors
+    pub image_start: u64,  // Page range the ELF image was loaded into
+    pub image_end: u64,
+    pub mappings: Vec<lazy::FileMapping>, // Segments still loaded on first touch
+    pub core_limit: u64,   // RLIMIT_CORE: largest core file to write (0 = none)
+    pub nice: i32,         // Scheduling niceness, NICE_MIN (favoured) to NICE_MAX
+    pub stopped: bool,     // Stopped by SIGSTOP/SIGTSTP until SIGCONT
+    pub slice: u64,        // Timer ticks left of its time slice
+    pub context: SavedContext, // Registers to resume with while not running
+    pub fpu: sched::FpuState,  // x87/SSE state while not running
+}
+
+impl Process {
+    /// The arguments joined with spaces, as SYS_GETARGS and ps show them
+    pub fn command_line(&self) -> String {
+        self.argv.join(" ")
+    }
 }
 
 const MAX_PROCESSES: usize = 16;
-static mut PROCESSES: [Option<Process>; MAX_PROCESSES] = [
+/// Live processes, each boxed so `registry` can retire one while a reader
+/// still looks at it
+static mut PROCESSES: [Option<Box<Process>>; MAX_PROCESSES] = [
     None, None, None, None, None, None, None, None,
     None, None, None, None, None, None, None, None,
 ];
 static mut NEXT_PID: u32 = 1;
 static mut CURRENT_PROCESS: Option<u32> = None;
 static mut KERNEL_PML4: u64 = 0;
+/// Foreground process group of the console (0 = none)
+static mut FOREGROUND_PGRP: u32 = 0;
+/// Group that handed the console to the foreground group (see
+/// `console_signals`)
+static mut CONSOLE_OWNER_PGRP: u32 = 0;
+/// The process the kernel started, which like init can't be stopped or killed
+static mut INIT_PID: u32 = 0;
+/// Timer counters (total, user) at the last CPU accounting sample
+static mut ACCOUNTED_TICKS: (u64, u64) = (0, 0);
+/// Timer ticks spent halted in `idle`
+static mut IDLE_TICKS: u64 = 0;
+/// Bytes of framebuffer mapped into each process: enough for the largest
+/// mode the display may switch to, so a mode change needs no remapping
+static mut FRAMEBUFFER_MAP_SIZE: u64 = 0;
 
 // Process memory layout (per process):
 //   base + 0x000000: Code/data (up to 1MB)
-//   base + 0x100000: Heap (1MB)
-//   base + 0x1F0000: Stack (64KB, grows down from base + 0x200000)
-// Total: 2MB per process, with 1.5MB gap before next process's code
+//   base + 0x100000: Program interpreter, if dynamically linked (up to 2MB)
+//   base + 0x300000: Stack (1MB, grows down from base + 0x400000)
+// and, the same in every process:
+//   uheap::USER_HEAP_BASE: Heap (256KB, growing to uheap::USER_HEAP_MAX)
+//   USER_FB_BASE: The framebuffer, for processes that map it (SYS_FB_MAP)
 const PROCESS_MEM_BASE: u64 = 0x1000000;
 const PROCESS_MEM_SIZE: u64 = 0x400000;  // 4MB spacing between processes
 const PROCESS_STACK_SIZE: u64 = 0x100000; // 1MB stack
+const INTERP_OFFSET: u64 = 0x100000;
+/// Where SYS_FB_MAP puts the framebuffer, plus its offset into a 2MB page.
+/// The kernel's identity mapping of it stays kernel-only.
+const USER_FB_BASE: u64 = 0x20_0000_0000;
 
-static mut KERNEL_RSP: u64 = 0;
-static mut KERNEL_RBP: u64 = 0;
-
-/// Saved parent context for exec-and-wait semantics
-/// Includes the full interrupt frame from the syscall
+/// User registers of a process that isn't running: where it resumes, and
+/// with what in each register
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct SavedContext {
-    pub valid: bool,
-    pub parent_pid: u32,
-    pub parent_pml4: u64,
     // Interrupt frame values (what IRETQ needs)
-    pub rip: u64,    // Return address
+    pub rip: u64,
     pub cs: u64,
     pub rflags: u64,
-    pub rsp: u64,    // User stack pointer at time of syscall
+    pub rsp: u64,
     pub ss: u64,
-    // General purpose registers that must be preserved across exec
+    // General purpose registers
+    pub rax: u64,
     pub rbx: u64,
     pub rcx: u64,
     pub rdx: u64,
@@ -245,283 +326,173 @@ pub struct SavedContext {
     pub r15: u64,
 }
 
-static mut PARENT_CONTEXT: SavedContext = SavedContext {
-    valid: false,
-    parent_pid: 0,
-    parent_pml4: 0,
-    rip: 0,
-    cs: 0,
-    rflags: 0,
-    rsp: 0,
-    ss: 0,
-    rbx: 0,
-    rcx: 0,
-    rdx: 0,
-    rsi: 0,
-    rdi: 0,
-    rbp: 0,
-    r8: 0,
-    r9: 0,
-    r10: 0,
-    r11: 0,
-    r12: 0,
-    r13: 0,
-    r14: 0,
-    r15: 0,
-};
-
-/// Save the current process context before spawning a child
-/// The return_* parameters are from the interrupt frame on the kernel stack
-/// Register parameters are the values that were on the stack when syscall was invoked
-pub fn save_parent_context_with_frame(
-    return_rip: u64,
-    return_rsp: u64,
-    rbx: u64,
-    rcx: u64,
-    rdx: u64,
-    rsi: u64,
-    rdi: u64,
-    rbp: u64,
-    r8: u64,
-    r9: u64,
-    r10: u64,
-    r11: u64,
-    r12: u64,
-    r13: u64,
-    r14: u64,
-    r15: u64,
-) {
-    unsafe {
-        if let Some(pid) = CURRENT_PROCESS {
-            if let Some(proc) = PROCESSES.iter().find_map(|p| p.as_ref().filter(|p| p.id == pid)) {
-                let user_cs = watos_arch::gdt::selectors::USER_CODE as u64;
-                let user_ss = watos_arch::gdt::selectors::USER_DATA as u64;
-                let parent_pml4 = proc.page_table.pml4_phys_addr();
-
-                PARENT_CONTEXT = SavedContext {
-                    valid: true,
-                    parent_pid: pid,
-                    parent_pml4,
-                    rip: return_rip,
-                    cs: user_cs,
-                    rflags: 0x202, // IF set
-                    rsp: return_rsp,
-                    ss: user_ss,
-                    rbx,
-                    rcx,
-                    rdx,
-                    rsi,
-                    rdi,
-                    rbp,
-                    r8,
-                    r9,
-                    r10,
-                    r11,
-                    r12,
-                    r13,
-                    r14,
-                    r15,
-                };
-
-                debug_serial(b"[PROCESS] Saved parent context, PID=");
-                debug_hex(pid as u64);
-                debug_serial(b" RIP=");
-                debug_hex(return_rip);
-                debug_serial(b" RSP=");
-                debug_hex(return_rsp);
-                debug_serial(b" PML4=");
-                debug_hex(parent_pml4);
-                debug_serial(b"\r\n");
-            }
+impl SavedContext {
+    /// Ring 3 at `rip` with stack `rsp`, interrupts on and every other
+    /// register zero
+    pub const fn new(rip: u64, rsp: u64) -> Self {
+        SavedContext {
+            rip,
+            cs: watos_arch::gdt::selectors::USER_CODE as u64,
+            rflags: 0x202, // IF set
+            rsp,
+            ss: watos_arch::gdt::selectors::USER_DATA as u64,
+            rax: 0,
+            rbx: 0,
+            rcx: 0,
+            rdx: 0,
+            rsi: 0,
+            rdi: 0,
+            rbp: 0,
+            r8: 0,
+            r9: 0,
+            r10: 0,
+            r11: 0,
+            r12: 0,
+            r13: 0,
+            r14: 0,
+            r15: 0,
         }
     }
-}
 
-/// Legacy function - uses entry point as return address (restarts parent)
-pub fn save_parent_context() {
-    unsafe {
-        if let Some(pid) = CURRENT_PROCESS {
-            if let Some(proc) = PROCESSES.iter().find_map(|p| p.as_ref().filter(|p| p.id == pid)) {
-                let user_cs = watos_arch::gdt::selectors::USER_CODE as u64;
-                let user_ss = watos_arch::gdt::selectors::USER_DATA as u64;
-
-                PARENT_CONTEXT = SavedContext {
-                    valid: true,
-                    parent_pid: pid,
-                    parent_pml4: proc.page_table.pml4_phys_addr(),
-                    rip: proc.entry_point,
-                    cs: user_cs,
-                    rflags: 0x202,
-                    rsp: proc.stack_top - 8,
-                    ss: user_ss,
-                    // No saved registers for restart mode (parent will be fresh)
-                    rbx: 0,
-                    rcx: 0,
-                    rdx: 0,
-                    rsi: 0,
-                    rdi: 0,
-                    rbp: 0,
-                    r8: 0,
-                    r9: 0,
-                    r10: 0,
-                    r11: 0,
-                    r12: 0,
-                    r13: 0,
-                    r14: 0,
-                    r15: 0,
-                };
-                debug_serial(b"[PROCESS] Saved parent context (restart mode), PID=");
-                debug_hex(pid as u64);
-                debug_serial(b"\r\n");
-            }
+    /// The registers of the ring 3 code a timer tick interrupted
+    pub fn from_frame(frame: &watos_arch::idt::UserFrame) -> Self {
+        SavedContext {
+            rip: frame.rip,
+            cs: frame.cs,
+            rflags: frame.rflags | 0x200,
+            rsp: frame.rsp,
+            ss: frame.ss,
+            rax: frame.rax,
+            rbx: frame.rbx,
+            rcx: frame.rcx,
+            rdx: frame.rdx,
+            rsi: frame.rsi,
+            rdi: frame.rdi,
+            rbp: frame.rbp,
+            r8: frame.r8,
+            r9: frame.r9,
+            r10: frame.r10,
+            r11: frame.r11,
+            r12: frame.r12,
+            r13: frame.r13,
+            r14: frame.r14,
+            r15: frame.r15,
         }
     }
 }
 
-/// Check if there's a parent to return to
-pub fn has_parent_context() -> bool {
-    unsafe { PARENT_CONTEXT.valid }
-}
-
-/// Free the current process slot (called when child process exits)
+/// Free the current process slot (called when it exits or is killed)
 pub fn free_current_process() {
+    account_cpu();
     unsafe {
         if let Some(pid) = CURRENT_PROCESS {
             // Find and clear this process from the table
-            for proc_slot in PROCESSES.iter_mut() {
-                if let Some(proc) = proc_slot {
-                    if proc.id == pid {
-                        debug_serial(b"[PROCESS] Freeing PID=");
-                        debug_hex(pid as u64);
-                        debug_serial(b"\r\n");
-                        *proc_slot = None;
-                        break;
-                    }
-                }
+            if let Some(slot) = (0..MAX_PROCESSES).find(|&i| PROCESSES[i].as_ref().is_some_and(|p| p.id == pid)) {
+                debug_serial(b"[PROCESS] Freeing PID=");
+                debug_hex(pid as u64);
+                debug_serial(b"\r\n");
+                release(slot);
             }
         }
     }
 }
 
-/// Resume the parent process after child exits
-pub fn resume_parent() -> ! {
+/// Take a process out of the table for good: its children become orphans
+/// and the state changes they reported to it are dropped
+fn release(slot: usize) {
+    let Some(pid) = (unsafe { (*core::ptr::addr_of!(PROCESSES))[slot].as_ref().map(|p| p.id) }) else { return };
     unsafe {
-        if !PARENT_CONTEXT.valid {
-            debug_serial(b"[PROCESS] ERROR: No parent to resume, halting\r\n");
-            loop { core::arch::asm!("hlt"); }
+        for p in (*core::ptr::addr_of_mut!(PROCESSES)).iter_mut().flatten() {
+            if p.ppid == pid {
+                p.ppid = 0;
+            }
         }
+    }
+    sched::forget_children(pid);
+    registry::remove(slot);
+    if let Some(hook) = unsafe { RELEASE_HOOK } {
+        hook(pid);
+    }
+}
 
-        // Extract values we need before switching CR3
-        let parent_pid = PARENT_CONTEXT.parent_pid;
-        let parent_pml4 = PARENT_CONTEXT.parent_pml4;
-
-        debug_serial(b"[PROCESS] Resuming parent PID=");
-        debug_hex(parent_pid as u64);
-        debug_serial(b" RIP=");
-        debug_hex(PARENT_CONTEXT.rip);
-        debug_serial(b" RSP=");
-        debug_hex(PARENT_CONTEXT.rsp);
-        debug_serial(b" PML4=");
-        debug_hex(parent_pml4);
-        debug_serial(b"\r\n");
+/// Called with each process's pid as it is freed
+static mut RELEASE_HOOK: Option<fn(u32)> = None;
 
-        PARENT_CONTEXT.valid = false;
-        CURRENT_PROCESS = Some(parent_pid);
-
-        // Calculate parent's kernel stack
-        const KERNEL_STACK_SIZE: usize = 0x10000;
-        let kernel_stack_base = 0x280000 + (parent_pid as usize * KERNEL_STACK_SIZE);
-        let kernel_stack_top = kernel_stack_base + KERNEL_STACK_SIZE;
-        watos_arch::tss::set_kernel_stack(kernel_stack_top as u64);
-
-        let user_ds = watos_arch::gdt::selectors::USER_DATA as u64;
-
-        // CRITICAL: Switch to parent's kernel stack AND page table, THEN build IRETQ frame
-        // We must do this atomically to avoid stack/page table mismatch
-        core::arch::asm!(
-            // Load ctx pointer from global (mapped in all page tables)
-            "lea r15, [rip + {parent_ctx}]",
-
-            // Switch to parent's kernel stack BEFORE changing CR3
-            "mov rsp, {kernel_stack}",
-
-            // NOW switch to parent's page table
-            "mov cr3, {parent_pml4}",
-
-            // Set up segments
-            "mov ax, {ds:x}",
-            "mov ds, ax",
-            "mov es, ax",
-            "mov fs, ax",
-            "mov gs, ax",
-
-            // Build IRETQ frame on parent's kernel stack
-            "push qword ptr [r15 + {off_ss}]",      // SS
-            "push qword ptr [r15 + {off_rsp}]",     // RSP
-            "push qword ptr [r15 + {off_rflags}]",  // RFLAGS
-            "push qword ptr [r15 + {off_cs}]",      // CS
-            "push qword ptr [r15 + {off_rip}]",     // RIP
-
-            // Restore general purpose registers from saved context
-            "mov rbx, [r15 + {off_rbx}]",
-            "mov rcx, [r15 + {off_rcx}]",
-            "mov rdx, [r15 + {off_rdx}]",
-            "mov rsi, [r15 + {off_rsi}]",
-            "mov rdi, [r15 + {off_rdi}]",
-            "mov rbp, [r15 + {off_rbp}]",
-            "mov r8,  [r15 + {off_r8}]",
-            "mov r9,  [r15 + {off_r9}]",
-            "mov r10, [r15 + {off_r10}]",
-            "mov r11, [r15 + {off_r11}]",
-            "mov r12, [r15 + {off_r12}]",
-            "mov r13, [r15 + {off_r13}]",
-            "mov r14, [r15 + {off_r14}]",
-            "mov r15, [r15 + {off_r15}]",  // Restore R15 last
-
-            // Set RAX to 0 (exec success)
-            "xor rax, rax",
-
-            // Return to user mode
-            "iretq",
-
-            parent_ctx = sym PARENT_CONTEXT,
-            kernel_stack = in(reg) kernel_stack_top,
-            parent_pml4 = in(reg) parent_pml4,
-            ds = in(reg) user_ds as u16,
-            off_ss = const core::mem::offset_of!(SavedContext, ss),
-            off_rsp = const core::mem::offset_of!(SavedContext, rsp),
-            off_rflags = const core::mem::offset_of!(SavedContext, rflags),
-            off_cs = const core::mem::offset_of!(SavedContext, cs),
-            off_rip = const core::mem::offset_of!(SavedContext, rip),
-            off_rbx = const core::mem::offset_of!(SavedContext, rbx),
-            off_rcx = const core::mem::offset_of!(SavedContext, rcx),
-            off_rdx = const core::mem::offset_of!(SavedContext, rdx),
-            off_rsi = const core::mem::offset_of!(SavedContext, rsi),
-            off_rdi = const core::mem::offset_of!(SavedContext, rdi),
-            off_rbp = const core::mem::offset_of!(SavedContext, rbp),
-            off_r8 = const core::mem::offset_of!(SavedContext, r8),
-            off_r9 = const core::mem::offset_of!(SavedContext, r9),
-            off_r10 = const core::mem::offset_of!(SavedContext, r10),
-            off_r11 = const core::mem::offset_of!(SavedContext, r11),
-            off_r12 = const core::mem::offset_of!(SavedContext, r12),
-            off_r13 = const core::mem::offset_of!(SavedContext, r13),
-            off_r14 = const core::mem::offset_of!(SavedContext, r14),
-            off_r15 = const core::mem::offset_of!(SavedContext, r15),
-            options(noreturn)
-        );
+/// Have `hook` run for every process that is freed, so the kernel can drop
+/// what it keeps for the process outside this crate
+pub fn set_release_hook(hook: fn(u32)) {
+    unsafe {
+        RELEASE_HOOK = Some(hook);
     }
 }
 
-fn allocate_process_memory(pid: u32) -> (u64, u64, u64) {
+fn allocate_process_memory(pid: u32) -> (u64, u64) {
     let base = PROCESS_MEM_BASE + (pid as u64 - 1) * PROCESS_MEM_SIZE;
     let stack_top = base + PROCESS_MEM_SIZE;
-    let heap_base = base + 0x80000;
-    (base, stack_top, heap_base)
+    (base, stack_top)
+}
+
+/// Map `pages` zeroed heap pages at `heap_base`
+///
+/// Stretches that are 2MB aligned and at least 2MB long get 2MB pages
+/// when the physical allocator has an aligned block, which saves page
+/// tables and TLB entries; they are not swapped. The rest gets 4KB pages.
+fn map_heap(page_table: &mut ProcessPageTable, heap_base: u64, pages: u64) -> Result<(), &'static str> {
+    let large = LARGE_PAGE_SIZE as u64;
+    let end = heap_base + pages * PAGE_SIZE as u64;
+    let mut virt_addr = heap_base;
+    while virt_addr < end {
+        if virt_addr.is_multiple_of(large) && end - virt_addr >= large {
+            if let Some(phys_addr) = watos_mem::phys::alloc_large_page() {
+                unsafe { core::ptr::write_bytes(phys_addr as *mut u8, 0, LARGE_PAGE_SIZE); }
+                page_table.track_large_page(phys_addr);
+                page_table.map_region_large_page(virt_addr, phys_addr,
+                    page_flags::PRESENT | page_flags::WRITABLE, MemRegion::Heap)?;
+                virt_addr += large;
+                continue;
+            }
+        }
+        let phys_addr = watos_mem::phys::alloc_page()
+            .ok_or("Out of physical memory for heap")? as u64;
+        unsafe { core::ptr::write_bytes(phys_addr as *mut u8, 0, PAGE_SIZE); }
+        page_table.track_phys_page(phys_addr);
+        page_table.map_region_page(virt_addr, phys_addr,
+            page_flags::PRESENT | page_flags::WRITABLE, MemRegion::Heap)?;
+        virt_addr += PAGE_SIZE as u64;
+    }
+    Ok(())
+}
+
+/// Load an ELF64 binary and run it in place of the kernel, as the first
+/// process; returns only if it couldn't be loaded
+/// argv is the argument vector, starting with the program name
+pub fn exec(name: &str, exe: &Executable, argv: &[&str]) -> Result<u32, &'static str> {
+    let pid = spawn(name, exe, None, argv)?;
+    sched::switch_to(pid)
+}
+
+/// Load an ELF64 binary as a new child of the current process, ready to
+/// run; returns its pid
+///
+/// A dynamically linked binary comes with `interp`, the file named by its
+/// PT_INTERP. It must be position-independent; it is loaded INTERP_OFFSET
+/// into the process's memory and entered with rsp pointing at an auxiliary
+/// vector (AT_* pairs, see `elf`) that tells it where the program is.
+pub fn spawn(name: &str, exe: &Executable, interp: Option<&Executable>, argv: &[&str]) -> Result<u32, &'static str> {
+    exec_image(name, exe, interp, argv)
 }
 
-/// Load and execute an ELF64 binary
-/// args is the full command line (program name + arguments)
-pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
+/// Run the new child `pid` now and put the caller to sleep until it exits
+/// (SYS_EXEC); the caller then resumes with `context`
+pub fn run_child(pid: u32, context: SavedContext) -> ! {
+    sched::save_context(context);
+    if let Some(parent) = current_process() {
+        parent.state = ProcessState::Waiting(pid);
+    }
+    sched::switch_to(pid)
+}
+
+fn exec_image(name: &str, exe: &Executable, interp: Option<&Executable>, argv: &[&str]) -> Result<u32, &'static str> {
     unsafe {
         debug_serial(b"[EXEC] start, heap used=");
         let stats = watos_mem::heap::stats();
@@ -536,7 +507,7 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
     unsafe { debug_serial(b"[EXEC] copying name\r\n"); }
     let name_copy = String::from(name);
     unsafe { debug_serial(b"[EXEC] copying args\r\n"); }
-    let args_copy = String::from(args);
+    let argv_copy: Vec<String> = argv.iter().map(|a| String::from(*a)).collect();
 
     // CRITICAL: Switch to kernel page table before loading
     // When called from user process, CR3 points to that process's page table
@@ -563,13 +534,15 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
     }
 
     unsafe {
-        debug_serial(b"[EXEC] parsing ELF, data ptr=0x");
-        debug_hex(data.as_ptr() as u64);
+        debug_serial(b"[EXEC] parsing ELF, head ptr=0x");
+        debug_hex(exe.head.as_ptr() as u64);
         debug_serial(b" len=");
-        debug_hex(data.len() as u64);
+        debug_hex(exe.head.len() as u64);
+        debug_serial(b" file len=");
+        debug_hex(exe.source.size());
         debug_serial(b"\r\n");
     }
-    let elf = elf::Elf64::parse(data)?;
+    let elf = elf::Elf64::parse(&exe.head)?;
 
     let pid = unsafe {
         let p = NEXT_PID;
@@ -577,10 +550,13 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
         p
     };
 
-    let (load_base, stack_top, heap_base) = allocate_process_memory(pid);
+    let (load_base, stack_top) = allocate_process_memory(pid);
+    let heap_base = uheap::USER_HEAP_BASE;
 
     let mut page_table = ProcessPageTable::new();
-    elf.load_segments_protected(data, load_base, &mut page_table)?;
+    map_kernel_framebuffer(&mut page_table);
+    let mut mappings = Vec::new();
+    elf.map_segments(&exe.source, load_base, &mut page_table, &mut mappings)?;
 
     // Map stack with guard pages for overflow detection
     // Stack layout: [GUARD PAGE] [STACK PAGES...] [TOP]
@@ -588,15 +564,19 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
     let stack_pages = PROCESS_STACK_SIZE / PAGE_SIZE as u64;
     let stack_base = stack_top - PROCESS_STACK_SIZE;
     let guard_page = stack_base - PAGE_SIZE as u64;
+    let mut stack_top_page = 0;
 
     for i in 0..stack_pages {
         let virt_addr = stack_top - (i as u64 + 1) * PAGE_SIZE as u64;
         let phys_addr = watos_mem::phys::alloc_page()
             .ok_or("Out of physical memory for stack")? as u64;
         unsafe { core::ptr::write_bytes(phys_addr as *mut u8, 0, PAGE_SIZE); }
+        if i == 0 {
+            stack_top_page = phys_addr;
+        }
         page_table.track_phys_page(phys_addr);
-        page_table.map_user_page(virt_addr, phys_addr,
-            page_flags::PRESENT | page_flags::WRITABLE)?;
+        page_table.map_region_page(virt_addr, phys_addr,
+            page_flags::PRESENT | page_flags::WRITABLE, MemRegion::Stack)?;
     }
 
     // Map guard page as NOT PRESENT - will trigger page fault on stack overflow
@@ -604,21 +584,18 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
 
     // Allocate a reasonable initial heap (256KB)
     let heap_pages = 64u64;
-    for i in 0..heap_pages {
-        let virt_addr = heap_base + i * PAGE_SIZE as u64;
-        let phys_addr = watos_mem::phys::alloc_page()
-            .ok_or("Out of physical memory for heap")? as u64;
-        unsafe { core::ptr::write_bytes(phys_addr as *mut u8, 0, PAGE_SIZE); }
-        page_table.track_phys_page(phys_addr);
-        page_table.map_user_page(virt_addr, phys_addr,
-            page_flags::PRESENT | page_flags::WRITABLE)?;
-    }
+    map_heap(&mut page_table, heap_base, heap_pages)?;
+
+    let min_vaddr = elf.min_vaddr();
 
-    let min_vaddr = elf.phdrs.iter()
+    // Where the segments ended up, for core dumps
+    let load_addr = |vaddr: u64| if elf.is_pie { load_base + (vaddr - min_vaddr) } else { vaddr };
+    let image_start = load_addr(min_vaddr) & !(PAGE_SIZE as u64 - 1);
+    let image_end = elf.phdrs.iter()
         .filter(|p| p.ptype == elf::PT_LOAD)
-        .map(|p| p.vaddr)
-        .min()
-        .unwrap_or(0);
+        .map(|p| load_addr(p.vaddr) + p.memsz)
+        .max()
+        .unwrap_or(image_start);
 
     // For non-PIE: use ELF entry directly (absolute address)
     // For PIE: relocate entry relative to load_base
@@ -628,21 +605,30 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
         elf.entry
     };
 
-    // Map framebuffer for user access (from boot info at 0x80000)
-    unsafe {
-        let boot_info = &*(0x80000 as *const BootInfo);
-        if boot_info.magic == 0x5741544F && boot_info.framebuffer_addr != 0 {
-            let fb_addr = boot_info.framebuffer_addr;
-            let fb_size = (boot_info.framebuffer_pitch * boot_info.framebuffer_height) as u64;
-            let fb_pages = (fb_size + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64;
-
-            for i in 0..fb_pages {
-                let addr = fb_addr + i * PAGE_SIZE as u64;
-                page_table.map_4k_page(addr, addr,
-                    page_flags::PRESENT | page_flags::WRITABLE | page_flags::USER);
+    // A dynamically linked program starts in its interpreter, which finds
+    // the program through the auxiliary vector at the top of the stack
+    let (entry, initial_sp) = match interp {
+        None => (entry, stack_top - 8),
+        Some(interp) => {
+            let ld = elf::Elf64::parse(&interp.head)?;
+            if !ld.is_pie {
+                return Err("Interpreter is not position-independent");
             }
+            let ld_base = load_base + INTERP_OFFSET;
+            ld.map_segments(&interp.source, ld_base, &mut page_table, &mut mappings)?;
+
+            let bias = if elf.is_pie { load_base.wrapping_sub(min_vaddr) } else { 0 };
+            let ld_bias = ld_base.wrapping_sub(ld.min_vaddr());
+            let sp = write_auxv(stack_top_page, stack_top, elf.phdrs, &[
+                (elf::AT_PHENT, core::mem::size_of::<elf::Elf64Phdr>() as u64),
+                (elf::AT_PHNUM, elf.phdrs.len() as u64),
+                (elf::AT_BASE, ld_bias),
+                (elf::AT_ENTRY, entry),
+                (elf::AT_LOAD_BIAS, bias),
+            ])?;
+            (ld_bias.wrapping_add(ld.entry), sp)
         }
-    }
+    };
 
     // Inherit environment from parent process
     let inherited_env = unsafe {
@@ -660,342 +646,135 @@ pub fn exec(name: &str, data: &[u8], args: &str) -> Result<u32, &'static str> {
         }
     };
 
+    // Children start in their parent's process group; the first process
+    // leads its own group
+    let pgid = current_pid()
+        .and_then(get_pgid)
+        .unwrap_or(pid);
+
     let process = Process {
         id: pid,
+        ppid: current_pid().unwrap_or(0),
         name: name_copy,
-        args: args_copy.clone(),
+        argv: argv_copy,
         state: ProcessState::Ready,
         entry_point: entry,
         stack_top,
+        initial_sp,
         heap_base,
         heap_size: (heap_pages as usize) * PAGE_SIZE,
+        heap: uheap::UserHeap::new(heap_base, heap_pages * PAGE_SIZE as u64, uheap::USER_HEAP_MAX),
         page_table,
         handle_table: HandleTable::new(),
         uid: get_current_uid(),  // Inherit from current process
         gid: get_current_gid(),  // Inherit from current process
+        pgid,
         environment: inherited_env,  // Inherit environment from parent
+        user_ticks: 0,
+        kernel_ticks: 0,
+        malloc_bytes: 0,
+        io: IoAccount::default(),
+        image_start,
+        image_end,
+        mappings,
+        core_limit: core_limit(),  // Inherit from current process
+        nice: current_pid().and_then(get_nice).unwrap_or(0),  // Inherit from current process
+        stopped: false,
+        slice: 0,
+        context: SavedContext::new(entry, initial_sp),
+        fpu: sched::FpuState::new(),
     };
 
     // Debug: show what args are being stored
     unsafe {
         debug_serial(b"[PROCESS] exec: storing args='");
-        debug_serial(args_copy.as_bytes());
-        debug_serial(b"' len=");
-        debug_hex(args_copy.len() as u64);
+        debug_serial(process.command_line().as_bytes());
+        debug_serial(b"' argc=");
+        debug_hex(process.argv.len() as u64);
         debug_serial(b"\r\n");
     }
 
+    if !registry::insert(process) {
+        return Err("Process table full");
+    }
     unsafe {
-        for slot in PROCESSES.iter_mut() {
-            if slot.is_none() {
-                *slot = Some(process);
-                break;
-            }
+        if INIT_PID == 0 {
+            INIT_PID = pid;
         }
     }
 
-    run_process(pid)?;
-
     Ok(pid)
 }
 
-fn run_process(pid: u32) -> Result<(), &'static str> {
-    let (entry, stack_top, pml4_addr) = unsafe {
-        let proc = PROCESSES.iter()
-            .find_map(|p| p.as_ref().filter(|p| p.id == pid))
-            .ok_or("Process not found")?;
-
-        CURRENT_PROCESS = Some(pid);
-        (proc.entry_point, proc.stack_top, proc.page_table.pml4_phys_addr())
-    };
-
-    unsafe {
-        debug_serial(b"[PROCESS] Starting PID=");
-        debug_hex(pid as u64);
-        debug_serial(b" in Ring 3 user mode\r\n");
-        debug_serial(b"[PROCESS] Entry=0x");
-        debug_hex(entry);
-        debug_serial(b" Stack=0x");
-        debug_hex(stack_top);
-        debug_serial(b" PML4=0x");
-        debug_hex(pml4_addr);
-        debug_serial(b"\r\n");
-    }
-
-    unsafe {
-        // Save the current kernel stack so we can return on process exit
-        let mut saved_rsp: u64 = 0;
-        let mut saved_rbp: u64 = 0;
-        core::arch::asm!(
-            "mov {}, rsp",
-            "mov {}, rbp",
-            out(reg) saved_rsp,
-            out(reg) saved_rbp,
-            options(nostack, preserves_flags)
-        );
-        KERNEL_RSP = saved_rsp;
-        KERNEL_RBP = saved_rbp;
-
-        const KERNEL_STACK_SIZE: usize = 0x10000;
-        let kernel_stack_base = 0x280000 + (pid as usize * KERNEL_STACK_SIZE);
-        let kernel_stack_top = kernel_stack_base + KERNEL_STACK_SIZE;
-
-        debug_serial(b"[PROCESS] Kernel stack for interrupts: 0x");
-        debug_hex(kernel_stack_top as u64);
-        debug_serial(b"\r\n");
-
-        watos_arch::tss::set_kernel_stack(kernel_stack_top as u64);
+/// Resolve a page fault on `addr` in the current process: bring a swapped
+/// page back, or load a page of its executable for the first time.
+/// Installed as the page fault resolver; false if the fault is a real one,
+/// or the page couldn't be read.
+pub fn resolve_page_fault(addr: u64, error_code: u64) -> bool {
+    // Protection faults have the present bit set; nothing to bring in
+    if error_code & 1 != 0 {
+        return false;
     }
-
     unsafe {
-        debug_serial(b"[PROCESS] Dumping PML4 entry 0...\r\n");
-
-        // Get actual PML4 pointer and dump first entry
-        let pml4_ptr = pml4_addr as *const u64;
-        let pml4_0 = *pml4_ptr;
-        debug_serial(b"  PML4[0]=0x");
-        debug_hex(pml4_0);
-        debug_serial(b"\r\n");
-
-        // Follow to PDP
-        if pml4_0 & 1 != 0 {
-            let pdp_addr = pml4_0 & 0x000F_FFFF_FFFF_F000;
-            let pdp_ptr = pdp_addr as *const u64;
-            let pdp_0 = *pdp_ptr;
-            debug_serial(b"  PDP[0]=0x");
-            debug_hex(pdp_0);
-            debug_serial(b"\r\n");
-
-            // Follow to PD
-            if pdp_0 & 1 != 0 {
-                let pd_addr = pdp_0 & 0x000F_FFFF_FFFF_F000;
-                let pd_ptr = pd_addr as *const u64;
-                let pd_0 = *pd_ptr;
-                debug_serial(b"  PD[0]=0x");
-                debug_hex(pd_0);
-                debug_serial(b" (huge=");
-                if pd_0 & 0x80 != 0 { debug_serial(b"Y"); } else { debug_serial(b"N"); }
-                debug_serial(b")\r\n");
-
-                // Also dump PD[8] (for code at 0x1000000) and PD[9] (for stack at 0x13FF000)
-                let pd_8 = *pd_ptr.add(8);
-                debug_serial(b"  PD[8]=0x");
-                debug_hex(pd_8);
-                if pd_8 & 1 != 0 {
-                    // Follow to PT
-                    let pt_addr = pd_8 & 0x000F_FFFF_FFFF_F000;
-                    let pt_ptr = pt_addr as *const u64;
-                    let pt_0 = *pt_ptr;
-                    debug_serial(b" -> PT[0]=0x");
-                    debug_hex(pt_0);
-                    // PT[43] is for virtual 0x102B000 (GOT page)
-                    let pt_43 = *pt_ptr.add(43);
-                    debug_serial(b" PT[43]=0x");
-                    debug_hex(pt_43);
-                }
-                debug_serial(b"\r\n");
-
-                let pd_9 = *pd_ptr.add(9);
-                debug_serial(b"  PD[9]=0x");
-                debug_hex(pd_9);
-                if pd_9 & 1 != 0 {
-                    // Follow to PT for stack page
-                    let pt_addr = pd_9 & 0x000F_FFFF_FFFF_F000;
-                    let pt_ptr = pt_addr as *const u64;
-                    // Stack at 0x13FF000 -> PT index 511
-                    let pt_511 = *pt_ptr.add(511);
-                    debug_serial(b" -> PT[511]=0x");
-                    debug_hex(pt_511);
-                }
-                debug_serial(b"\r\n");
-            }
+        let Some(pid) = CURRENT_PROCESS else { return false };
+        let Some(process) = (0..MAX_PROCESSES)
+            .filter_map(|i| PROCESSES[i].as_mut())
+            .find(|p| p.id == pid)
+        else {
+            return false;
+        };
+        // A kernel fault under another page table is not this process's
+        if watos_mem::paging::get_cr3() != process.page_table.pml4_phys_addr() {
+            return false;
         }
-
-        debug_serial(b"[PROCESS] Switching to user page table...\r\n");
-
-        // Switch to a kernel stack within the mapped 8MB region
-        // This is necessary because CR3 switch will make our current stack inaccessible
-        let mapped_kernel_stack: u64 = 0x5FF000;  // Within first 8MB, mapped in process page table
-        debug_serial(b"[DEBUG] Switching to mapped kernel stack at 0x5FF000\r\n");
-        core::arch::asm!(
-            "mov rsp, {stack}",
-            "mov rbp, {stack}",
-            stack = in(reg) mapped_kernel_stack,
-            options(nostack)
-        );
-
-        // Switch to user page table before IRETQ
-        debug_serial(b"[DEBUG] Switching to user page table (CR3)\r\n");
-        watos_mem::paging::load_cr3(pml4_addr);
-
-        // Print OK and RSP in pure asm - no Rust stack usage at all
-        core::arch::asm!(
-            "mov dx, 0x3F8",
-            "mov al, 0x4F", "out dx, al",  // 'O'
-            "mov al, 0x4B", "out dx, al",  // 'K'
-            "mov al, 0x20", "out dx, al",  // space
-            "mov al, 0x52", "out dx, al",  // 'R'
-            "mov al, 0x53", "out dx, al",  // 'S'
-            "mov al, 0x50", "out dx, al",  // 'P'
-            "mov al, 0x3D", "out dx, al",  // '='
-
-            // Print RSP as 16 hex digits
-            "mov rsi, rsp",        // save RSP value
-            "mov rcx, 60",         // start shift at 60 (15*4)
-            "2:",
-            "mov rax, rsi",
-            "shr rax, cl",
-            "and al, 0x0F",
-            "add al, 0x30",        // '0'
-            "cmp al, 0x3A",
-            "jl 3f",
-            "add al, 7",           // 'A'-'0'-10
-            "3:",
-            "out dx, al",
-            "sub rcx, 4",
-            "jns 2b",              // loop while shift >= 0
-
-            "mov al, 0x0D", "out dx, al",  // CR
-            "mov al, 0x0A", "out dx, al",  // LF
-            options(nostack, nomem, preserves_flags)
-        );
+        let virt = addr & !(PAGE_SIZE as u64 - 1);
+        let process = &mut **process;
+        swap::in_kernel_space(|| {
+            swap::swap_in(&mut process.page_table, virt)
+                || lazy::fault_in(&mut process.page_table, &process.mappings, virt)
+        })
     }
+}
 
-    unsafe {
-        // Print verification message using pure asm to avoid any stack issues
-        debug_serial(b"[PROCESS] Verifying user code...\r\n");
-
-        // Read first byte from entry point to verify it's accessible
-        let first_byte = *(entry as *const u8);
-        debug_serial(b"[PROCESS] First byte: 0x");
-        let hex = b"0123456789ABCDEF";
-        core::arch::asm!("out dx, al", in("dx") 0x3F8_u16, in("al") hex[(first_byte >> 4) as usize], options(nostack));
-        core::arch::asm!("out dx, al", in("dx") 0x3F8_u16, in("al") hex[(first_byte & 0xF) as usize], options(nostack));
-        debug_serial(b" OK\r\n");
-
-        // Skip stack test after CR3 switch - calling debug_hex uses the Rust stack
-        // which may cause issues after we switched stacks
-        debug_serial(b"[PROCESS] Skipping stack test after CR3 switch\r\n");
+/// Put the program headers and auxiliary vector at the top of a new stack
+///
+/// `top_page` is the physical page behind the stack's highest virtual page.
+/// The headers go at the very top and AT_PHDR points at that copy, since
+/// the program's own headers needn't be inside a loaded segment. Returns
+/// the (16-byte aligned) address of the vector, the interpreter's rsp.
+fn write_auxv(
+    top_page: u64,
+    stack_top: u64,
+    phdrs: &[elf::Elf64Phdr],
+    entries: &[(u64, u64)],
+) -> Result<u64, &'static str> {
+    let phdr_size = core::mem::size_of_val(phdrs) as u64;
+    let phdr_addr = (stack_top - phdr_size) & !15;
+    // Entries, AT_PHDR and AT_NULL, two words each
+    let auxv_addr = (phdr_addr - (entries.len() as u64 + 2) * 16) & !15;
+    let page_virt = stack_top - PAGE_SIZE as u64;
+    if top_page == 0 || auxv_addr < page_virt {
+        return Err("Too many program headers");
     }
 
+    let phys = |virt: u64| (top_page + (virt - page_virt)) as *mut u64;
     unsafe {
-        debug_serial(b"[PROCESS] Transitioning to Ring 3...\r\n");
-
-        // Prepare IRETQ values - use constants to avoid stack-heavy operations
-        let user_stack = stack_top - 8;
-        let user_cs = watos_arch::gdt::selectors::USER_CODE as u64;
-        let user_ds = watos_arch::gdt::selectors::USER_DATA as u64;
-        let rflags: u64 = 0x202;
-
-        debug_serial(b"[PROCESS] Executing IRETQ to Ring 3...\r\n");
-
-        // Build the IRETQ frame using PUSH instructions on current stack
-        // This is the traditional and most reliable approach
-        // Stack grows down, so push in reverse order: SS, RSP, RFLAGS, CS, RIP
-        core::arch::asm!(
-            // Disable interrupts
-            "cli",
-
-            // Push IRETQ frame in reverse order (stack grows down)
-            "push {ss}",      // SS
-            "push {rsp_val}", // RSP
-            "push {rflags}",  // RFLAGS
-            "push {cs}",      // CS
-            "push {rip}",     // RIP
-
-            // Debug: Print the RSP value before IRETQ
-            "mov dx, 0x3F8",
-            "mov al, 0x49", // 'I'
-            "out dx, al",
-            "mov al, 0x52", // 'R'
-            "out dx, al",
-            "mov al, 0x45", // 'E'
-            "out dx, al",
-            "mov al, 0x54", // 'T'
-            "out dx, al",
-            "mov al, 0x51", // 'Q'
-            "out dx, al",
-            "mov al, 0x0D",
-            "out dx, al",
-            "mov al, 0x0A",
-            "out dx, al",
-
-            // Zero GPRs (except RSP which points to the frame)
-            "xor rax, rax",
-            "xor rbx, rbx",
-            "xor rcx, rcx",
-            "xor rdx, rdx",
-            "xor rsi, rsi",
-            "xor rdi, rdi",
-            "xor rbp, rbp",
-            "xor r8, r8",
-            "xor r9, r9",
-            "xor r10, r10",
-            "xor r11, r11",
-            "xor r12, r12",
-            "xor r13, r13",
-            "xor r14, r14",
-            "xor r15, r15",
-
-            // IRETQ - reads frame from [RSP]
-            "iretq",
-            ss = in(reg) user_ds,
-            rsp_val = in(reg) user_stack,
-            rflags = in(reg) rflags,
-            cs = in(reg) user_cs,
-            rip = in(reg) entry,
-            options(noreturn)
+        core::ptr::copy_nonoverlapping(
+            phdrs.as_ptr() as *const u8,
+            phys(phdr_addr) as *mut u8,
+            phdr_size as usize,
         );
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn process_exit_to_kernel(code: i32) -> ! {
-    unsafe {
-        if let Some(pid) = CURRENT_PROCESS {
-            for slot in PROCESSES.iter_mut() {
-                if let Some(ref mut p) = slot {
-                    if p.id == pid {
-                        p.state = ProcessState::Terminated(code);
-                        break;
-                    }
-                }
-            }
-            // Drop the process slot now that it has exited to release resources
-            for slot in PROCESSES.iter_mut() {
-                if let Some(ref p) = slot {
-                    if p.id == pid {
-                        *slot = None;
-                        break;
-                    }
-                }
-            }
-            CURRENT_PROCESS = None;
-        }
-
-        // Always restore kernel paging before returning to the kernel stack
-        restore_kernel_paging();
-
-        // Clear the saved parent context to avoid stale resumes
-        PARENT_CONTEXT.valid = false;
-
-        if KERNEL_RSP != 0 {
-            core::arch::asm!(
-                "mov rsp, {rsp}",
-                "mov rbp, {rbp}",
-                "ret",
-                rsp = in(reg) KERNEL_RSP,
-                rbp = in(reg) KERNEL_RBP,
-                options(noreturn)
-            );
-        }
-
-        debug_serial(b"ERROR: process_exit_to_kernel failed\r\n");
-        CURRENT_PROCESS = None;
-        loop {
-            core::arch::asm!("hlt", options(nostack, nomem));
+        let auxv = phys(auxv_addr);
+        let mut i = 0;
+        for &(key, value) in [(elf::AT_PHDR, phdr_addr)].iter().chain(entries) {
+            *auxv.add(i) = key;
+            *auxv.add(i + 1) = value;
+            i += 2;
         }
+        *auxv.add(i) = elf::AT_NULL;
+        *auxv.add(i + 1) = 0;
     }
+    Ok(auxv_addr)
 }
 
 pub fn exit_current(code: i32) {
@@ -1017,6 +796,17 @@ pub fn current_pid() -> Option<u32> {
     unsafe { CURRENT_PROCESS }
 }
 
+/// The running process
+pub(crate) fn current_process() -> Option<&'static mut Process> {
+    let pid = current_pid()?;
+    unsafe { (*core::ptr::addr_of_mut!(PROCESSES)).iter_mut().flatten().find(|p| p.id == pid).map(|p| &mut **p) }
+}
+
+/// The process the kernel started first; it can't be stopped or killed
+pub fn init_pid() -> u32 {
+    unsafe { INIT_PID }
+}
+
 pub fn current_handle_table() -> Option<&'static mut HandleTable> {
     unsafe {
         if let Some(pid) = CURRENT_PROCESS {
@@ -1032,7 +822,7 @@ pub fn current_handle_table() -> Option<&'static mut HandleTable> {
     }
 }
 
-/// Get the arguments for the current process
+/// Get the arguments for the current process, joined with spaces
 /// Returns the number of bytes copied into the buffer
 pub fn get_current_args(buf: &mut [u8]) -> usize {
     unsafe {
@@ -1043,7 +833,8 @@ pub fn get_current_args(buf: &mut [u8]) -> usize {
             for slot in PROCESSES.iter() {
                 if let Some(ref p) = slot {
                     if p.id == pid {
-                        let args = p.args.as_bytes();
+                        let line = p.command_line();
+                        let args = line.as_bytes();
                         debug_serial(b"[PROCESS] Found process args='");
                         debug_serial(args);
                         debug_serial(b"' len=");
@@ -1063,6 +854,27 @@ pub fn get_current_args(buf: &mut [u8]) -> usize {
     }
 }
 
+/// Get the current process's argv as length-prefixed blocks
+/// (watos_syscall::argv)
+///
+/// Returns the encoded length. Nothing is copied unless it all fits, so a
+/// result larger than `buf` tells the caller how much to allocate.
+pub fn get_current_argv(buf: &mut [u8]) -> usize {
+    unsafe {
+        let pid = match CURRENT_PROCESS {
+            Some(pid) => pid,
+            None => return 0,
+        };
+        for p in PROCESSES.iter().flatten() {
+            if p.id == pid {
+                return watos_syscall::argv::encode(&p.argv, buf)
+                    .unwrap_or_else(|| watos_syscall::argv::encoded_len(&p.argv));
+            }
+        }
+        0
+    }
+}
+
 pub fn init() {
     unsafe {
         KERNEL_PML4 = watos_mem::paging::get_cr3();
@@ -1079,6 +891,56 @@ unsafe fn restore_kernel_paging() {
     }
 }
 
+/// Map at least `bytes` of framebuffer into processes that ask for it
+pub fn set_framebuffer_map_size(bytes: u64) {
+    unsafe { FRAMEBUFFER_MAP_SIZE = bytes; }
+}
+
+/// The framebuffer's physical address and the bytes to map of it, from
+/// boot info at 0x80000
+fn framebuffer() -> Option<(u64, u64)> {
+    unsafe {
+        let boot_info = &*(0x80000 as *const BootInfo);
+        if boot_info.magic != 0x5741544F || boot_info.framebuffer_addr == 0 {
+            return None;
+        }
+        let fb_size = ((boot_info.framebuffer_pitch * boot_info.framebuffer_height) as u64)
+            .max(FRAMEBUFFER_MAP_SIZE);
+        Some((boot_info.framebuffer_addr, fb_size))
+    }
+}
+
+/// Identity map the framebuffer for the kernel only, so the console can
+/// draw whichever page table is loaded
+fn map_kernel_framebuffer(page_table: &mut ProcessPageTable) {
+    if let Some((fb_addr, fb_size)) = framebuffer() {
+        page_table.map_range(fb_addr, fb_addr, fb_size, page_flags::PRESENT | page_flags::WRITABLE);
+    }
+}
+
+/// Map the framebuffer into the current process at `USER_FB_BASE`, and
+/// return the address it is at
+///
+/// Processes start without it; the kernel decides who may call this
+/// (SYS_FB_MAP). Mapping it again is harmless.
+pub fn map_framebuffer() -> Option<u64> {
+    let (fb_addr, fb_size) = framebuffer()?;
+    // Same offset into a 2MB page, so an aligned framebuffer gets 2MB pages
+    let user_addr = USER_FB_BASE + fb_addr % LARGE_PAGE_SIZE as u64;
+    unsafe {
+        let pid = CURRENT_PROCESS?;
+        let process = (0..MAX_PROCESSES).filter_map(|i| PROCESSES[i].as_mut()).find(|p| p.id == pid)?;
+        if process.page_table.lookup(user_addr) != Some(fb_addr) {
+            let page_table = &mut process.page_table;
+            swap::in_kernel_space(|| {
+                page_table.map_range(user_addr, fb_addr, fb_size,
+                    page_flags::PRESENT | page_flags::WRITABLE | page_flags::USER);
+            });
+        }
+    }
+    Some(user_addr)
+}
+
 /// Get the kernel's PML4 address for CR3 switching during syscalls
 /// Returns 0 if not initialized
 pub fn get_kernel_pml4() -> u64 {
@@ -1089,14 +951,13 @@ pub fn cleanup() {
     unsafe {
         restore_kernel_paging();
 
-        for slot in PROCESSES.iter_mut() {
-            if let Some(ref p) = slot {
-                if matches!(p.state, ProcessState::Terminated(_)) {
-                    *slot = None;
-                }
+        for slot in 0..MAX_PROCESSES {
+            if PROCESSES[slot].as_ref().is_some_and(|p| matches!(p.state, ProcessState::Terminated(_))) {
+                registry::remove(slot);
             }
         }
     }
+    registry::reclaim();
 }
 
 /// Get current process UID (returns 0 if no process)
@@ -1165,6 +1026,477 @@ pub fn set_current_gid(gid: u32) -> bool {
     }
 }
 
+// ============================================================================
+// Process Listing
+// ============================================================================
+
+/// Snapshot of one process, for /proc and process monitors
+#[derive(Debug, Clone)]
+pub struct ProcessSummary {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    pub args: String,
+    pub state: ProcessState,
+    /// Stopped by SIGSTOP/SIGTSTP, whatever its state
+    pub stopped: bool,
+    pub uid: u32,
+    pub gid: u32,
+    pub pgid: u32,
+    /// Physical memory owned by the process (resident set)
+    pub memory_kb: u64,
+    /// Mapped user memory: total and per region
+    pub vm_size_kb: u64,
+    pub code_kb: u64,
+    pub heap_kb: u64,
+    pub stack_kb: u64,
+    /// Bytes currently allocated through SYS_MALLOC
+    pub malloc_bytes: u64,
+    /// Reads and writes through file descriptors
+    pub io: IoAccount,
+    /// CPU time consumed in user mode
+    pub user_time_ms: u64,
+    /// CPU time consumed in the kernel on the process's behalf
+    pub system_time_ms: u64,
+    /// Scheduling niceness (see `set_nice`)
+    pub nice: i32,
+}
+
+fn summarize(p: &Process) -> ProcessSummary {
+    let usage = p.page_table.mem_usage();
+    let kb = |pages: usize| (pages * PAGE_SIZE / 1024) as u64;
+    ProcessSummary {
+        pid: p.id,
+        ppid: p.ppid,
+        name: p.name.clone(),
+        args: p.command_line(),
+        state: p.state,
+        stopped: p.stopped,
+        uid: p.uid,
+        gid: p.gid,
+        pgid: p.pgid,
+        memory_kb: kb(p.page_table.owned_page_count()),
+        vm_size_kb: kb(usage.total_pages()),
+        code_kb: kb(usage.code_pages),
+        heap_kb: kb(usage.heap_pages),
+        stack_kb: kb(usage.stack_pages),
+        malloc_bytes: p.malloc_bytes,
+        io: p.io,
+        user_time_ms: ticks_to_ms(p.user_ticks),
+        system_time_ms: ticks_to_ms(p.kernel_ticks),
+        nice: p.nice,
+    }
+}
+
+/// List all live processes in pid order
+pub fn list_processes() -> alloc::vec::Vec<ProcessSummary> {
+    account_cpu();
+    let mut list: alloc::vec::Vec<ProcessSummary> = registry::read(|processes| processes.map(summarize).collect());
+    list.sort_by_key(|p| p.pid);
+    list
+}
+
+/// Number of live processes
+pub fn process_count() -> usize {
+    registry::read(|processes| processes.count())
+}
+
+/// Get a snapshot of one process
+pub fn process_summary(pid: u32) -> Option<ProcessSummary> {
+    account_cpu();
+    registry::read(|mut processes| processes.find(|p| p.id == pid).map(summarize))
+}
+
+// ============================================================================
+// CPU Accounting
+// ============================================================================
+
+/// Convert PIT ticks (~18.2 Hz) to milliseconds
+fn ticks_to_ms(ticks: u64) -> u64 {
+    ticks * 10_000 / 182
+}
+
+/// Charge the timer ticks since the last sample to the current process
+///
+/// The timer interrupt counts every tick and, separately, the ticks that
+/// interrupted ring 3. Called whenever the current process changes and
+/// before usage is reported; ticks with no current process are idle time
+/// and go unaccounted.
+pub fn account_cpu() {
+    let total = watos_arch::idt::get_ticks();
+    let user = watos_arch::idt::get_user_ticks();
+    unsafe {
+        let (last_total, last_user) = ACCOUNTED_TICKS;
+        ACCOUNTED_TICKS = (total, user);
+
+        let user_delta = user.wrapping_sub(last_user);
+        let kernel_delta = total.wrapping_sub(last_total).saturating_sub(user_delta);
+        if let Some(pid) = CURRENT_PROCESS {
+            if let Some(p) = PROCESSES.iter_mut().flatten().find(|p| p.id == pid) {
+                p.user_ticks += user_delta;
+                p.kernel_ticks += kernel_delta;
+            }
+        }
+    }
+}
+
+/// Switch the current process, charging the outgoing one for its CPU time
+/// and retagging profiler samples
+fn set_current(pid: Option<u32>) {
+    account_cpu();
+    unsafe { CURRENT_PROCESS = pid; }
+    watos_arch::idt::set_profile_tag(pid.unwrap_or(0) as u64);
+}
+
+/// Halt the CPU until the next interrupt (timer tick, key, mouse, NIC) on
+/// behalf of a process with nothing to do
+///
+/// The ticks spent halted count as idle time rather than being charged to
+/// the current process. Interrupts are enabled for the wait and disabled
+/// again on return.
+pub fn idle() {
+    account_cpu();
+    watos_arch::halt();
+    watos_arch::disable_interrupts();
+    let total = watos_arch::idt::get_ticks();
+    unsafe {
+        IDLE_TICKS += total.wrapping_sub(ACCOUNTED_TICKS.0);
+        ACCOUNTED_TICKS = (total, watos_arch::idt::get_user_ticks());
+    }
+}
+
+/// Time the CPU has spent idle since boot, in milliseconds
+pub fn idle_ms() -> u64 {
+    ticks_to_ms(unsafe { IDLE_TICKS })
+}
+
+/// Timer ticks per load average sample, ~5 s at 18.2 Hz
+const LOAD_INTERVAL: u64 = 91;
+/// Fixed point: 1.0 is `1 << LOAD_SHIFT`
+const LOAD_SHIFT: u32 = 11;
+/// Per-sample decay for 1, 5 and 15 minute averages: e^(-5s/period)
+const LOAD_DECAY: [u64; 3] = [1884, 2014, 2037];
+/// Samples caught up at most in one go; by then the 15 minute average
+/// has long forgotten the old value
+const LOAD_MAX_CATCH_UP: u64 = 1024;
+
+/// (tick, idle ticks) at the last sample, and the averages
+static mut LOAD: (u64, u64, [u64; 3]) = (0, 0, [0; 3]);
+
+/// Busy share of the last 1, 5 and 15 minutes, in hundredths
+///
+/// Since one process runs at a time, the load is how much of the time the
+/// CPU was not idle. The averages are brought up to date when asked for,
+/// with the busy share since the last call standing in for each 5 second
+/// sample missed.
+pub fn load_average() -> [u32; 3] {
+    let now = watos_arch::idt::get_ticks();
+    unsafe {
+        let (last, last_idle, mut averages) = LOAD;
+        let samples = now.wrapping_sub(last) / LOAD_INTERVAL;
+        if samples > 0 {
+            let span = now.wrapping_sub(last);
+            let idle = IDLE_TICKS.wrapping_sub(last_idle).min(span);
+            let busy = ((span - idle) << LOAD_SHIFT) / span;
+            for _ in 0..samples.min(LOAD_MAX_CATCH_UP) {
+                for (average, decay) in averages.iter_mut().zip(LOAD_DECAY) {
+                    *average = (*average * decay + busy * ((1 << LOAD_SHIFT) - decay)) >> LOAD_SHIFT;
+                }
+            }
+            LOAD = (now, IDLE_TICKS, averages);
+        }
+        averages.map(|average| ((average * 100 + (1 << (LOAD_SHIFT - 1))) >> LOAD_SHIFT) as u32)
+    }
+}
+
+/// Get (user_ms, system_ms) CPU time of a process
+pub fn cpu_usage(pid: u32) -> Option<(u64, u64)> {
+    account_cpu();
+    unsafe {
+        PROCESSES.iter()
+            .find_map(|p| p.as_ref().filter(|p| p.id == pid))
+            .map(|p| (ticks_to_ms(p.user_ticks), ticks_to_ms(p.kernel_ticks)))
+    }
+}
+
+// ============================================================================
+// Memory Accounting
+// ============================================================================
+
+/// SYS_MALLOC: `size` bytes from the current process's heap, mapping
+/// more of it as needed; 0 when the heap or physical memory runs out
+pub fn user_malloc(size: u64) -> u64 {
+    unsafe {
+        let Some(pid) = CURRENT_PROCESS else { return 0 };
+        let Some(p) = (*core::ptr::addr_of_mut!(PROCESSES)).iter_mut().flatten().find(|p| p.id == pid) else { return 0 };
+        let p = &mut **p;
+        let page_table = &mut p.page_table;
+        let addr = p.heap.alloc(size, |start, bytes| {
+            // Page tables and fresh pages are written through the identity map
+            swap::in_kernel_space(|| map_heap(page_table, start, bytes / PAGE_SIZE as u64)).is_ok()
+        });
+        p.heap_size = p.heap.mapped() as usize;
+        match addr {
+            Some(addr) => {
+                p.malloc_bytes += size.div_ceil(uheap::ALIGN) * uheap::ALIGN;
+                addr
+            }
+            None => 0,
+        }
+    }
+}
+
+/// SYS_FREE: give back a block from `user_malloc`; false if `addr` isn't
+/// one. The pages stay mapped for the next allocations.
+pub fn user_free(addr: u64) -> bool {
+    unsafe {
+        let Some(pid) = CURRENT_PROCESS else { return false };
+        let Some(p) = (*core::ptr::addr_of_mut!(PROCESSES)).iter_mut().flatten().find(|p| p.id == pid) else { return false };
+        match p.heap.free(addr) {
+            Some(size) => {
+                p.malloc_bytes = p.malloc_bytes.saturating_sub(size);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// ============================================================================
+// I/O Accounting
+// ============================================================================
+
+/// A process's file descriptor I/O: successful calls and the bytes they moved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoAccount {
+    pub reads: u64,
+    pub read_bytes: u64,
+    pub writes: u64,
+    pub write_bytes: u64,
+}
+
+/// Record a read (or, with `write`, a write) of `bytes` by the current process
+pub fn account_io(write: bool, bytes: usize) {
+    unsafe {
+        if let Some(pid) = CURRENT_PROCESS {
+            if let Some(p) = PROCESSES.iter_mut().flatten().find(|p| p.id == pid) {
+                if write {
+                    p.io.writes += 1;
+                    p.io.write_bytes += bytes as u64;
+                } else {
+                    p.io.reads += 1;
+                    p.io.read_bytes += bytes as u64;
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Process Groups
+// ============================================================================
+
+/// Get the process group of a process
+pub fn get_pgid(pid: u32) -> Option<u32> {
+    unsafe {
+        PROCESSES.iter()
+            .find_map(|p| p.as_ref().filter(|p| p.id == pid))
+            .map(|p| p.pgid)
+    }
+}
+
+/// Move a process into a process group
+/// A pgid of 0 makes the process the leader of a new group
+pub fn set_pgid(pid: u32, pgid: u32) -> bool {
+    unsafe {
+        for slot in PROCESSES.iter_mut() {
+            if let Some(ref mut p) = slot {
+                if p.id == pid {
+                    p.pgid = if pgid == 0 { pid } else { pgid };
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Get the foreground process group of the console (0 = none)
+pub fn foreground_pgrp() -> u32 {
+    unsafe { FOREGROUND_PGRP }
+}
+
+/// Set the foreground process group of the console, on behalf of the
+/// current process
+pub fn set_foreground_pgrp(pgid: u32) {
+    let owner = current_pid().and_then(get_pgid).unwrap_or(0);
+    unsafe {
+        FOREGROUND_PGRP = pgid;
+        CONSOLE_OWNER_PGRP = owner;
+    }
+}
+
+/// Whether Ctrl+Z signals the foreground group: only while a shell has
+/// handed the console to another group. The shell's own group never gets
+/// it, much as Unix shells ignore SIGTSTP.
+pub fn console_signals() -> bool {
+    unsafe { FOREGROUND_PGRP != 0 && FOREGROUND_PGRP != CONSOLE_OWNER_PGRP }
+}
+
+/// Whether the current process may read the keyboard: it is in the
+/// foreground group, or no group has been made foreground
+pub fn owns_console() -> bool {
+    let foreground = foreground_pgrp();
+    foreground == 0 || current_pid().and_then(get_pgid) == Some(foreground)
+}
+
+// ============================================================================
+// Priorities
+// ============================================================================
+
+/// Most favoured niceness
+pub const NICE_MIN: i32 = -20;
+/// Least favoured niceness
+pub const NICE_MAX: i32 = 19;
+
+/// Time slice at nice 0 unless changed through `sched.time_slice`
+pub const DEFAULT_TIME_SLICE: u64 = 5;
+
+/// Time slice at nice 0, in timer ticks
+static mut TIME_SLICE: u64 = DEFAULT_TIME_SLICE;
+
+/// Timer ticks a process may run before yielding the CPU: the base slice
+/// (5 ticks, ~275 ms, unless tuned) at the default nice 0, weighted from
+/// 1/5 of it at NICE_MAX up to twice it at NICE_MIN, and at least 1 tick.
+/// A preemptive scheduler hands these out as weighted time slices, so an
+/// interactive program at a low nice value gets the CPU back sooner than a
+/// niced background job.
+pub fn time_slice_ticks(nice: i32) -> u64 {
+    let weight = ((NICE_MAX - nice.clamp(NICE_MIN, NICE_MAX)) / 4 + 1) as u64;
+    (weight * unsafe { TIME_SLICE } / DEFAULT_TIME_SLICE).max(1)
+}
+
+/// Register the scheduler's tunables (`sched.*`) as sysctls
+pub fn register_sysctls() {
+    let _ = watos_sysctl::register(watos_sysctl::Tunable {
+        name: "sched.time_slice",
+        description: "timer ticks a process at nice 0 runs before yielding",
+        kind: watos_sysctl::Kind::Int { min: 1, max: 1000 },
+        get: || unsafe { TIME_SLICE },
+        set: |ticks| {
+            unsafe { TIME_SLICE = ticks; }
+            true
+        },
+    });
+    exec_cache::register_sysctls();
+}
+
+/// Get the niceness of a process
+pub fn get_nice(pid: u32) -> Option<i32> {
+    unsafe {
+        PROCESSES.iter()
+            .find_map(|p| p.as_ref().filter(|p| p.id == pid))
+            .map(|p| p.nice)
+    }
+}
+
+/// Set the niceness of a process, clamped to NICE_MIN..=NICE_MAX
+///
+/// Like Unix, anyone may lower the priority of their own processes, but
+/// only root may raise it or touch another user's processes.
+pub fn set_nice(pid: u32, nice: i32) -> bool {
+    let nice = nice.clamp(NICE_MIN, NICE_MAX);
+    let caller = get_current_uid();
+    unsafe {
+        match PROCESSES.iter_mut().flatten().find(|p| p.id == pid) {
+            Some(p) if caller == 0 || (p.uid == caller && nice >= p.nice) => {
+                p.nice = nice;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// ============================================================================
+// Exit Status
+// ============================================================================
+
+/// Record the exit code of the current process for its parent to collect
+pub fn record_exit(code: i32) {
+    // Unix layout: exit code in bits 8-15
+    record_status(((code as u32) & 0xFF) << 8);
+}
+
+/// Record that the current process was killed by a signal
+pub fn record_killed(signal: u32, core_dumped: bool) {
+    record_status((signal & 0x7F) | if core_dumped { 0x80 } else { 0 });
+}
+
+fn record_status(status: u32) {
+    if let Some(p) = current_process() {
+        sched::report(p.id, p.ppid, status);
+    }
+}
+
+/// Collect a state change of a child of the current process: its (pid,
+/// wait status). pid 0 matches any child; stops are reported only when
+/// `untraced` (WUNTRACED), and each change only once.
+pub fn take_child_event(pid: u32, untraced: bool) -> Option<(u32, u32)> {
+    sched::take_child_event(current_pid()?, pid, untraced)
+}
+
+/// Whether the current process has a child `pid` (0 = any) to wait for:
+/// one still alive, or one whose exit hasn't been collected
+pub fn has_child(pid: u32) -> bool {
+    let Some(parent) = current_pid() else { return false };
+    let alive = registry::read(|mut processes| processes.any(|p| p.ppid == parent && (pid == 0 || p.id == pid)));
+    alive || sched::child_event_pending(parent, pid)
+}
+
+// ============================================================================
+// Resource Limits and Core Dumps
+// ============================================================================
+
+/// RLIMIT_CORE of the current process (unlimited for the kernel itself)
+pub fn core_limit() -> u64 {
+    unsafe {
+        CURRENT_PROCESS
+            .and_then(|pid| PROCESSES.iter().flatten().find(|p| p.id == pid))
+            .map(|p| p.core_limit)
+            .unwrap_or(u64::MAX)
+    }
+}
+
+/// Set RLIMIT_CORE of the current process
+pub fn set_core_limit(limit: u64) -> bool {
+    unsafe {
+        match CURRENT_PROCESS.and_then(|pid| PROCESSES.iter_mut().flatten().find(|p| p.id == pid)) {
+            Some(p) => {
+                p.core_limit = limit;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Build an ELF core file of the current process, which died from `signal`
+///
+/// Returns None when the process's RLIMIT_CORE forbids it. The kernel page
+/// table must be loaded.
+pub fn dump_core(frame: &watos_arch::exceptions::FaultFrame, signal: u32) -> Option<alloc::vec::Vec<u8>> {
+    account_cpu();
+    unsafe {
+        let pid = CURRENT_PROCESS?;
+        let proc = PROCESSES.iter().flatten().find(|p| p.id == pid)?;
+        if proc.core_limit == 0 {
+            return None;
+        }
+        coredump::build_core(proc, frame, signal, proc.core_limit)
+    }
+}
+
 // ============================================================================
 // Environment Variables
 // ============================================================================
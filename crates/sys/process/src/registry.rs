@@ -0,0 +1,87 @@
+//! Process registry
+//!
+//! The process table holds each process in its own allocation, so removing
+//! one from its slot is a single pointer store. Readers that walk the table
+//! without the current process's cooperation (ps, top, the kernel monitor)
+//! go through `read`, which counts them in; a process removed while any are
+//! in flight is retired rather than freed, and the next removal or
+//! `cleanup` frees it once the readers are gone. A reader therefore never
+//! sees a process's page table or strings freed under it, even when the
+//! removal runs in an interrupt that cut into the walk.
+//!
+//! Freeing happens only on the removing side, never as a reader leaves: a
+//! reader may be an interrupt handler, and freeing there could wait on a
+//! heap lock held by the code it interrupted.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{swap, Process, MAX_PROCESSES, PROCESSES};
+
+/// Readers currently walking the table
+static READERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Processes removed while readers were in flight, not yet freed
+static mut RETIRED: Vec<Box<Process>> = Vec::new();
+
+/// The live processes, as seen by a reader
+pub(crate) struct Processes<'a> {
+    slot: usize,
+    _reader: PhantomData<&'a Process>,
+}
+
+impl<'a> Iterator for Processes<'a> {
+    type Item = &'a Process;
+
+    fn next(&mut self) -> Option<&'a Process> {
+        while self.slot < MAX_PROCESSES {
+            self.slot += 1;
+            if let Some(process) = unsafe { (*addr_of!(PROCESSES))[self.slot - 1].as_deref() } {
+                return Some(process);
+            }
+        }
+        None
+    }
+}
+
+/// Run `f` over the live processes, which stay allocated until it returns
+pub(crate) fn read<R>(f: impl FnOnce(Processes<'_>) -> R) -> R {
+    READERS.fetch_add(1, Ordering::SeqCst);
+    let result = f(Processes { slot: 0, _reader: PhantomData });
+    READERS.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+/// Put a new process in a free slot; false if the table is full
+pub(crate) fn insert(process: Process) -> bool {
+    let process = Box::new(process);
+    unsafe {
+        match (0..MAX_PROCESSES).find(|&i| PROCESSES[i].is_none()) {
+            Some(i) => {
+                PROCESSES[i] = Some(process);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Take process slot `slot` out of the table and release its swap space;
+/// the process itself is freed now if no reader can see it, or later
+pub(crate) fn remove(slot: usize) {
+    let Some(process) = (unsafe { PROCESSES[slot].take() }) else { return };
+    swap::release(&process.page_table);
+    unsafe { (*addr_of_mut!(RETIRED)).push(process); }
+    reclaim();
+}
+
+/// Free the retired processes if no reader is walking the table
+pub(crate) fn reclaim() {
+    if READERS.load(Ordering::SeqCst) == 0 {
+        let retired = unsafe { core::mem::take(&mut *addr_of_mut!(RETIRED)) };
+        drop(retired);
+    }
+}
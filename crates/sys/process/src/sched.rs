@@ -0,0 +1,483 @@
+//! Scheduling
+//!
+//! Every process keeps the user registers it resumes with (`SavedContext`)
+//! and its x87/SSE state. The CPU changes processes only where the outgoing
+//! one holds nothing in the kernel:
+//! - a timer tick that interrupted ring 3 ends its time slice
+//!   (`time_slice_ticks`) while another process can run
+//! - a syscall blocks it: SYS_EXEC until the child exits, SYS_WAIT until a
+//!   child changes state, SYS_SLEEP until its deadline, SYS_IDLE for a turn,
+//!   SYS_READ and SYS_WRITE on an empty or full pipe for a turn
+//! - it exits, crashes, is killed or is stopped
+//!
+//! A switch abandons the outgoing kernel stack; the incoming process goes
+//! back to ring 3 with IRETQ from the top of its own. Processes take turns
+//! round-robin by slot, and the CPU halts while none can run.
+//!
+//! A blocked SYS_WAIT or pipe read or write is restarted rather than
+//! resumed: its saved RIP points back at the `int 0x80` and RAX holds the
+//! syscall number again.
+//!
+//! Signals (SYS_KILL, and Ctrl+Z from the console) have their default
+//! actions only: SIGSTOP and SIGTSTP stop, SIGCONT continues, 0 probes and
+//! anything else terminates. A process that isn't running is always at a
+//! switch point, so a signal acts on it at once; the running process is
+//! acted on with the registers it would resume with.
+
+use alloc::vec::Vec;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use watos_arch::idt::UserFrame;
+use watos_syscall::signals;
+
+use crate::{
+    debug_serial, kstack, set_current, time_slice_ticks, Process, ProcessState, SavedContext,
+    MAX_PROCESSES, PROCESSES,
+};
+
+/// FXSAVE image of a process's x87/SSE registers
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// The state after FNINIT, with every SSE exception masked
+    pub const fn new() -> Self {
+        let mut image = [0u8; 512];
+        image[0] = 0x7F; // FCW 0x037F
+        image[1] = 0x03;
+        image[24] = 0x80; // MXCSR 0x1F80
+        image[25] = 0x1F;
+        FpuState(image)
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Slot of the process that ran last, where the round-robin resumes
+static mut LAST_SLOT: usize = 0;
+
+/// The incoming process's registers, which the switch reads after it has
+/// left the outgoing kernel stack and page table
+static mut SWITCH_CONTEXT: SavedContext = SavedContext::new(0, 0);
+
+fn slot_of(pid: u32) -> Option<usize> {
+    unsafe { (*addr_of!(PROCESSES)).iter().position(|p| p.as_ref().is_some_and(|p| p.id == pid)) }
+}
+
+fn process(pid: u32) -> Option<&'static mut Process> {
+    unsafe { (*addr_of_mut!(PROCESSES)).iter_mut().flatten().find(|p| p.id == pid).map(|p| &mut **p) }
+}
+
+/// Keep the running process's registers, and its FPU state as it is now,
+/// for when it next runs
+pub fn save_context(context: SavedContext) {
+    if let Some(p) = crate::current_process() {
+        p.context = context;
+        unsafe {
+            core::arch::asm!("fxsave64 [{}]", in(reg) p.fpu.0.as_mut_ptr(), options(nostack, preserves_flags));
+        }
+    }
+}
+
+/// Enter process `pid` in ring 3 with its saved registers
+pub fn switch_to(pid: u32) -> ! {
+    // A fault inside a user-access window can end a process mid-syscall
+    watos_mem::user_access::reset_windows();
+    let (Some(slot), Some(kernel_stack_top)) = (slot_of(pid), kstack::prepare(pid)) else {
+        unsafe { debug_serial(b"[PROCESS] ERROR: No process to switch to, halting\r\n"); }
+        loop { watos_arch::halt(); }
+    };
+
+    let pml4 = unsafe {
+        let Some(p) = (*addr_of_mut!(PROCESSES))[slot].as_deref_mut() else { unreachable!() };
+        p.state = ProcessState::Running;
+        p.slice = time_slice_ticks(p.nice);
+        SWITCH_CONTEXT = p.context;
+        core::arch::asm!("fxrstor64 [{}]", in(reg) p.fpu.0.as_ptr(), options(nostack, readonly, preserves_flags));
+        LAST_SLOT = slot;
+        p.page_table.pml4_phys_addr()
+    };
+    set_current(Some(pid));
+    watos_arch::tss::set_kernel_stack(kernel_stack_top);
+
+    let user_ds = watos_arch::gdt::selectors::USER_DATA as u64;
+    unsafe {
+        // Move to the incoming kernel stack before its page table, then
+        // build the IRETQ frame there
+        core::arch::asm!(
+            "cli",
+            "lea r15, [rip + {ctx}]",
+            "mov rsp, {kernel_stack}",
+            "mov cr3, {pml4}",
+
+            "mov ax, {ds:x}",
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov gs, ax",
+
+            "push qword ptr [r15 + {off_ss}]",
+            "push qword ptr [r15 + {off_rsp}]",
+            "push qword ptr [r15 + {off_rflags}]",
+            "push qword ptr [r15 + {off_cs}]",
+            "push qword ptr [r15 + {off_rip}]",
+
+            "mov rax, [r15 + {off_rax}]",
+            "mov rbx, [r15 + {off_rbx}]",
+            "mov rcx, [r15 + {off_rcx}]",
+            "mov rdx, [r15 + {off_rdx}]",
+            "mov rsi, [r15 + {off_rsi}]",
+            "mov rdi, [r15 + {off_rdi}]",
+            "mov rbp, [r15 + {off_rbp}]",
+            "mov r8,  [r15 + {off_r8}]",
+            "mov r9,  [r15 + {off_r9}]",
+            "mov r10, [r15 + {off_r10}]",
+            "mov r11, [r15 + {off_r11}]",
+            "mov r12, [r15 + {off_r12}]",
+            "mov r13, [r15 + {off_r13}]",
+            "mov r14, [r15 + {off_r14}]",
+            "mov r15, [r15 + {off_r15}]",  // R15 last
+            "iretq",
+
+            ctx = sym SWITCH_CONTEXT,
+            kernel_stack = in(reg) kernel_stack_top,
+            pml4 = in(reg) pml4,
+            ds = in(reg) user_ds as u16,
+            off_ss = const core::mem::offset_of!(SavedContext, ss),
+            off_rsp = const core::mem::offset_of!(SavedContext, rsp),
+            off_rflags = const core::mem::offset_of!(SavedContext, rflags),
+            off_cs = const core::mem::offset_of!(SavedContext, cs),
+            off_rip = const core::mem::offset_of!(SavedContext, rip),
+            off_rax = const core::mem::offset_of!(SavedContext, rax),
+            off_rbx = const core::mem::offset_of!(SavedContext, rbx),
+            off_rcx = const core::mem::offset_of!(SavedContext, rcx),
+            off_rdx = const core::mem::offset_of!(SavedContext, rdx),
+            off_rsi = const core::mem::offset_of!(SavedContext, rsi),
+            off_rdi = const core::mem::offset_of!(SavedContext, rdi),
+            off_rbp = const core::mem::offset_of!(SavedContext, rbp),
+            off_r8 = const core::mem::offset_of!(SavedContext, r8),
+            off_r9 = const core::mem::offset_of!(SavedContext, r9),
+            off_r10 = const core::mem::offset_of!(SavedContext, r10),
+            off_r11 = const core::mem::offset_of!(SavedContext, r11),
+            off_r12 = const core::mem::offset_of!(SavedContext, r12),
+            off_r13 = const core::mem::offset_of!(SavedContext, r13),
+            off_r14 = const core::mem::offset_of!(SavedContext, r14),
+            off_r15 = const core::mem::offset_of!(SavedContext, r15),
+            options(noreturn)
+        );
+    }
+}
+
+fn runnable(p: &Process, now_ms: u64) -> bool {
+    !p.stopped && match p.state {
+        ProcessState::Ready => true,
+        ProcessState::Sleeping(deadline) => now_ms >= deadline,
+        _ => false,
+    }
+}
+
+/// The next process to run after the last one, waking it if it slept
+fn pick() -> Option<u32> {
+    let now = watos_arch::clock::now_ms();
+    unsafe {
+        let processes = &mut *addr_of_mut!(PROCESSES);
+        for i in 1..=MAX_PROCESSES {
+            let slot = (LAST_SLOT + i) % MAX_PROCESSES;
+            if let Some(p) = processes[slot].as_deref_mut() {
+                if runnable(p, now) {
+                    p.state = ProcessState::Ready;
+                    return Some(p.id);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether a process other than the running one could run now
+pub fn others_runnable() -> bool {
+    let now = watos_arch::clock::now_ms();
+    let current = crate::current_pid();
+    crate::registry::read(|mut processes| processes.any(|p| Some(p.id) != current && runnable(p, now)))
+}
+
+/// Run the next process that can, idling until there is one. The running
+/// process, if any, must already be saved with its new state.
+pub fn schedule() -> ! {
+    set_current(None);
+    unsafe { crate::restore_kernel_paging(); }
+    loop {
+        deliver_console_signal(None);
+        if let Some(pid) = pick() {
+            switch_to(pid);
+        }
+        if crate::process_count() == 0 {
+            unsafe { debug_serial(b"[PROCESS] No processes left, halting\r\n"); }
+            loop { watos_arch::halt(); }
+        }
+        crate::idle();
+    }
+}
+
+/// Save the running process to resume with `context`, put it in `state`
+/// and run something else
+pub fn block(context: SavedContext, state: ProcessState) -> ! {
+    save_context(context);
+    if let Some(p) = crate::current_process() {
+        p.state = state;
+    }
+    schedule()
+}
+
+/// Timer tick in ring 3: act on a signal from the console, and end the
+/// running process's time slice if another process can run
+pub fn user_tick(frame: &UserFrame) {
+    let context = SavedContext::from_frame(frame);
+    deliver_console_signal(Some(&context));
+    let Some(p) = crate::current_process() else { return };
+    p.slice = p.slice.saturating_sub(1);
+    if p.stopped || (p.slice == 0 && others_runnable()) {
+        block(context, ProcessState::Ready);
+    }
+}
+
+// ============================================================================
+// Signals
+// ============================================================================
+
+/// Signal for the console's foreground group, posted by the keyboard
+/// interrupt and delivered at the next switch point (0 = none)
+static CONSOLE_SIGNAL: AtomicU32 = AtomicU32::new(0);
+
+/// Signal the console's foreground group, from an interrupt
+pub fn post_console_signal(signal: u32) {
+    CONSOLE_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Whether a console signal awaits delivery
+pub fn console_signal_pending() -> bool {
+    CONSOLE_SIGNAL.load(Ordering::SeqCst) != 0
+}
+
+/// Deliver a posted console signal. `context` is the running process's
+/// registers, for when the signal stops or ends it.
+pub fn deliver_console_signal(context: Option<&SavedContext>) {
+    let signal = CONSOLE_SIGNAL.swap(0, Ordering::SeqCst);
+    let pgid = crate::foreground_pgrp();
+    if signal != 0 && pgid != 0 {
+        send(-(pgid as i64), signal, 0, context);
+    }
+}
+
+/// Send `signal` to process `target`, or to process group -`target`, on
+/// behalf of user `uid` (0 may signal anyone, others only their own
+/// processes). The running process, if it is a target, is acted on last
+/// with `context` as the registers it resumes with, and may never return
+/// here. False if no process was signalled.
+pub fn send(target: i64, signal: u32, uid: u32, context: Option<&SavedContext>) -> bool {
+    let current = crate::current_pid();
+    let init = crate::init_pid();
+    let mut sent = false;
+    let mut to_current = false;
+    for slot in 0..MAX_PROCESSES {
+        let Some((pid, pgid, owner)) = (unsafe { (*addr_of!(PROCESSES))[slot].as_ref().map(|p| (p.id, p.pgid, p.uid)) }) else {
+            continue;
+        };
+        let selected = if target < 0 { pgid as i64 == -target } else { pid as i64 == target };
+        let protected = pid == init && signal != 0 && signal != signals::SIGCONT;
+        if !selected || protected || (uid != 0 && uid != owner) {
+            continue;
+        }
+        sent = true;
+        if Some(pid) == current {
+            to_current = true;
+        } else {
+            act(slot, signal);
+        }
+    }
+    if to_current {
+        act_on_current(signal, context);
+    }
+    sent
+}
+
+fn stop_status(signal: u32) -> u32 {
+    0x7F | (signal & 0xFF) << 8
+}
+
+fn is_stop(status: u32) -> bool {
+    status & 0xFF == 0x7F
+}
+
+/// Act on a process that isn't running
+fn act(slot: usize, signal: u32) {
+    let Some(p) = (unsafe { (*addr_of_mut!(PROCESSES))[slot].as_deref_mut() }) else { return };
+    match signal {
+        0 => {}
+        signals::SIGCONT => p.stopped = false,
+        signals::SIGSTOP | signals::SIGTSTP => {
+            if !p.stopped {
+                p.stopped = true;
+                report(p.id, p.ppid, stop_status(signal));
+            }
+        }
+        _ => {
+            report(p.id, p.ppid, signal & 0x7F);
+            crate::release(slot);
+        }
+    }
+}
+
+fn act_on_current(signal: u32, context: Option<&SavedContext>) {
+    let Some(p) = crate::current_process() else { return };
+    match signal {
+        0 | signals::SIGCONT => {}
+        signals::SIGSTOP | signals::SIGTSTP => {
+            if !p.stopped {
+                p.stopped = true;
+                report(p.id, p.ppid, stop_status(signal));
+            }
+            // Without registers to resume with, the next tick stops it
+            if let Some(context) = context {
+                block(*context, ProcessState::Ready);
+            }
+        }
+        _ => {
+            unsafe { crate::restore_kernel_paging(); }
+            crate::record_killed(signal, false);
+            crate::free_current_process();
+            schedule();
+        }
+    }
+}
+
+// ============================================================================
+// Child State Changes
+// ============================================================================
+
+/// Uncollected state changes each parent may hold; older ones are dropped
+const MAX_EVENTS_PER_PARENT: usize = 16;
+
+/// A child's state change its parent hasn't collected with SYS_WAIT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChildEvent {
+    parent: u32,
+    child: u32,
+    status: u32,
+}
+
+/// State changes waiting for SYS_WAIT, oldest first
+struct ChildEvents(Vec<ChildEvent>);
+
+impl ChildEvents {
+    const fn new() -> Self {
+        ChildEvents(Vec::new())
+    }
+
+    fn push(&mut self, parent: u32, child: u32, status: u32) {
+        if self.0.iter().filter(|e| e.parent == parent).count() >= MAX_EVENTS_PER_PARENT {
+            if let Some(oldest) = self.0.iter().position(|e| e.parent == parent) {
+                self.0.remove(oldest);
+            }
+        }
+        self.0.push(ChildEvent { parent, child, status });
+    }
+
+    fn matches(e: &ChildEvent, parent: u32, child: u32) -> bool {
+        e.parent == parent && (child == 0 || e.child == child)
+    }
+
+    fn take(&mut self, parent: u32, child: u32, untraced: bool) -> Option<(u32, u32)> {
+        let i = self.0.iter().position(|e| Self::matches(e, parent, child) && (untraced || !is_stop(e.status)))?;
+        let e = self.0.remove(i);
+        Some((e.child, e.status))
+    }
+
+    fn pending(&self, parent: u32, child: u32) -> bool {
+        self.0.iter().any(|e| Self::matches(e, parent, child))
+    }
+
+    fn forget(&mut self, parent: u32) {
+        self.0.retain(|e| e.parent != parent);
+    }
+}
+
+static mut CHILD_EVENTS: ChildEvents = ChildEvents::new();
+
+/// Tell `parent` that `child` changed state, waking it if it waits for that
+pub(crate) fn report(child: u32, parent: u32, status: u32) {
+    let Some(p) = process(parent) else { return };
+    unsafe { (*addr_of_mut!(CHILD_EVENTS)).push(parent, child, status); }
+    match p.state {
+        ProcessState::Waiting(0) => p.state = ProcessState::Ready,
+        ProcessState::Waiting(pid) if pid == child && !is_stop(status) => p.state = ProcessState::Ready,
+        _ => {}
+    }
+}
+
+pub(crate) fn take_child_event(parent: u32, child: u32, untraced: bool) -> Option<(u32, u32)> {
+    unsafe { (*addr_of_mut!(CHILD_EVENTS)).take(parent, child, untraced) }
+}
+
+pub(crate) fn child_event_pending(parent: u32, child: u32) -> bool {
+    unsafe { (*addr_of!(CHILD_EVENTS)).pending(parent, child) }
+}
+
+/// Drop what `parent`'s children reported, as it is gone
+pub(crate) fn forget_children(parent: u32) {
+    unsafe { (*addr_of_mut!(CHILD_EVENTS)).forget(parent); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_matches_pid_or_any() {
+        let mut events = ChildEvents::new();
+        events.push(1, 5, 0x100);
+        events.push(1, 6, 0x200);
+        events.push(2, 7, 0);
+        assert_eq!(events.take(1, 6, false), Some((6, 0x200)));
+        assert_eq!(events.take(1, 6, false), None);
+        assert_eq!(events.take(1, 0, false), Some((5, 0x100)));
+        assert_eq!(events.take(1, 0, false), None);
+        assert!(events.pending(2, 7));
+    }
+
+    #[test]
+    fn test_stops_need_untraced() {
+        let mut events = ChildEvents::new();
+        events.push(1, 5, stop_status(signals::SIGTSTP));
+        assert_eq!(events.take(1, 5, false), None);
+        assert!(events.pending(1, 5));
+        assert_eq!(events.take(1, 5, true), Some((5, 0x147F)));
+    }
+
+    #[test]
+    fn test_oldest_dropped_per_parent() {
+        let mut events = ChildEvents::new();
+        events.push(2, 99, 0);
+        for child in 0..MAX_EVENTS_PER_PARENT as u32 + 1 {
+            events.push(1, 10 + child, 0);
+        }
+        assert_eq!(events.take(1, 10, false), None);
+        assert_eq!(events.take(1, 0, false), Some((11, 0)));
+        assert!(events.pending(2, 99));
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut events = ChildEvents::new();
+        events.push(1, 5, 0);
+        events.push(2, 6, 0);
+        events.forget(1);
+        assert!(!events.pending(1, 0));
+        assert!(events.pending(2, 6));
+    }
+}
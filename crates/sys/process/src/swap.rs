@@ -0,0 +1,154 @@
+//! Paging user memory out to swap
+//!
+//! When the physical allocator runs dry it asks [`page_out`] for a page.
+//! A clock hand sweeps the user pages of all processes: a page the CPU has
+//! marked accessed since the last sweep gets another round, any other is
+//! written to the swap area and its page table entry left not present, with
+//! the swap slot in the address bits and `SWAPPED` set. Touching it again
+//! faults, and [`crate::resolve_page_fault`] reads it back.
+//!
+//! Page tables and physical pages are only identity mapped in the kernel's
+//! address space, so the work is done there and CR3 restored afterwards.
+
+use watos_mem::paging::{self, flags as page_flags, ProcessPageTable, PAGE_SIZE};
+
+use crate::{KERNEL_PML4, MAX_PROCESSES, PROCESSES};
+
+/// Clock hand: process slot and index into its user pages
+static mut HAND: (usize, usize) = (0, 0);
+
+/// Run `f` with the kernel's page table loaded
+pub(crate) fn in_kernel_space<T>(f: impl FnOnce() -> T) -> T {
+    let saved = paging::get_cr3();
+    let kernel = unsafe { KERNEL_PML4 };
+    let switch = kernel != 0 && saved != kernel;
+    if switch {
+        unsafe { paging::load_cr3(kernel); }
+    }
+    let result = f();
+    if switch {
+        unsafe { paging::load_cr3(saved); }
+    }
+    result
+}
+
+fn slot_of(entry: u64) -> Option<u32> {
+    let swapped = entry & page_flags::PRESENT == 0 && entry & page_flags::SWAPPED != 0;
+    swapped.then_some(((entry & page_flags::ADDR_MASK) >> 12) as u32)
+}
+
+/// Write one user page to swap and free its physical page. Installed as
+/// the physical allocator's reclaimer; false if nothing could be freed.
+pub fn page_out() -> bool {
+    if !watos_swap::active() {
+        return false;
+    }
+    in_kernel_space(|| unsafe {
+        // Two full turns: the first may only clear accessed bits
+        let mut budget = 2 * (0..MAX_PROCESSES)
+            .filter_map(|i| PROCESSES[i].as_ref())
+            .map(|p| p.page_table.user_pages().len())
+            .sum::<usize>();
+        while budget > 0 {
+            let (slot, index) = HAND;
+            let table = match PROCESSES[slot].as_mut() {
+                Some(p) if index < p.page_table.user_pages().len() => &mut p.page_table,
+                _ => {
+                    HAND = ((slot + 1) % MAX_PROCESSES, 0);
+                    continue;
+                }
+            };
+            HAND.1 += 1;
+            budget -= 1;
+            let virt = table.user_pages()[index];
+            if evict(table, virt) {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Clock step for one page: true if it went to swap
+unsafe fn evict(table: &mut ProcessPageTable, virt: u64) -> bool {
+    let Some(entry) = table.entry(virt) else { return false };
+    if entry & page_flags::PRESENT == 0 {
+        return false;
+    }
+    if entry & page_flags::ACCESSED != 0 {
+        table.set_entry(virt, entry & !page_flags::ACCESSED);
+        return false;
+    }
+    let phys = entry & page_flags::ADDR_MASK;
+    // Only pages the process owns; not ones it shares or borrows
+    if !table.untrack_phys_page(phys) {
+        return false;
+    }
+    let contents = core::slice::from_raw_parts(phys as *const u8, PAGE_SIZE);
+    match watos_swap::store(contents) {
+        Ok(slot) => {
+            let kept = entry & !(page_flags::ADDR_MASK | page_flags::PRESENT | page_flags::ACCESSED | page_flags::DIRTY);
+            table.set_entry(virt, ((slot as u64) << 12) | kept | page_flags::SWAPPED);
+            watos_mem::phys::free_page(phys);
+            true
+        }
+        Err(_) => {
+            table.track_phys_page(phys);
+            false
+        }
+    }
+}
+
+/// Read a swapped page back into a fresh physical page and map it
+pub(crate) fn swap_in(table: &mut ProcessPageTable, virt: u64) -> bool {
+    let Some(entry) = table.entry(virt) else { return false };
+    let Some(slot) = slot_of(entry) else { return false };
+    let Some(phys) = watos_mem::phys::alloc_page() else { return false };
+    let contents = unsafe { core::slice::from_raw_parts_mut(phys as *mut u8, PAGE_SIZE) };
+    if watos_swap::load(slot, contents).is_err() {
+        watos_mem::phys::free_page(phys);
+        return false;
+    }
+    let _ = watos_swap::release(slot);
+    let kept = entry & !(page_flags::ADDR_MASK | page_flags::SWAPPED);
+    table.set_entry(virt, phys | kept | page_flags::PRESENT);
+    table.track_phys_page(phys);
+    true
+}
+
+/// Free the swap slots of a process that is going away
+pub(crate) fn release(table: &ProcessPageTable) {
+    if !watos_swap::active() {
+        return;
+    }
+    in_kernel_space(|| {
+        for &virt in table.user_pages() {
+            if let Some(slot) = table.entry(virt).and_then(slot_of) {
+                let _ = watos_swap::release(slot);
+            }
+        }
+    });
+}
+
+/// Bring every swapped page back into memory and stop swapping
+pub fn swapoff() -> watos_swap::SwapResult<()> {
+    watos_swap::set_draining(true)?;
+    let brought_in = in_kernel_space(|| unsafe {
+        for process in (0..MAX_PROCESSES).filter_map(|i| PROCESSES[i].as_mut()) {
+            let table = &mut process.page_table;
+            for index in 0..table.user_pages().len() {
+                let virt = table.user_pages()[index];
+                let swapped = table.entry(virt).and_then(slot_of).is_some();
+                if swapped && !swap_in(table, virt) {
+                    return false;
+                }
+            }
+        }
+        true
+    });
+    let result = if brought_in { watos_swap::swapoff().map(drop) } else { Err(watos_swap::SwapError::NoMemory) };
+    if result.is_err() {
+        let _ = watos_swap::set_draining(false);
+    }
+    result
+}
@@ -0,0 +1,183 @@
+//! The SYS_MALLOC heap of a process
+//!
+//! Each process gets its memory from its own address space: a region at
+//! [`USER_HEAP_BASE`] that starts with the pages exec maps and grows, a
+//! stretch at a time, up to [`USER_HEAP_MAX`]. The kernel heap is never
+//! handed out, so no kernel page has to be user-accessible. The region
+//! stays below 4GB, where 32-bit code (winrun's PE32 programs) can reach it.
+//!
+//! [`UserHeap`] only does the bookkeeping: first fit over a sorted free
+//! list, neighbours merged on free. It remembers each block's size, so
+//! SYS_FREE needs just the pointer, and it refuses pointers it didn't hand
+//! out. Mapping the pages is up to the caller.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Where every process's heap starts
+pub const USER_HEAP_BASE: u64 = 0x4000_0000;
+/// Most the heap may grow to
+pub const USER_HEAP_MAX: u64 = 256 * 1024 * 1024;
+/// Alignment of every block
+pub const ALIGN: u64 = 16;
+/// Least the heap grows by at a time
+pub const GROW_MIN: u64 = 256 * 1024;
+
+pub struct UserHeap {
+    base: u64,
+    /// End of the mapped part
+    top: u64,
+    limit: u64,
+    /// Free blocks (start, length), sorted and never adjacent
+    free: Vec<(u64, u64)>,
+    /// Blocks in use, by start
+    used: BTreeMap<u64, u64>,
+}
+
+impl UserHeap {
+    /// A heap over `mapped` bytes already mapped at `base`, which may grow
+    /// to `limit` bytes
+    pub fn new(base: u64, mapped: u64, limit: u64) -> Self {
+        let mut free = Vec::new();
+        if mapped > 0 {
+            free.push((base, mapped));
+        }
+        UserHeap { base, top: base + mapped, limit, free, used: BTreeMap::new() }
+    }
+
+    /// Bytes mapped so far
+    pub fn mapped(&self) -> u64 {
+        self.top - self.base
+    }
+
+    /// Allocate `size` bytes. When no free block fits, `grow(start, bytes)`
+    /// is asked to map `bytes` more at the top, and returns whether it did.
+    pub fn alloc(&mut self, size: u64, grow: impl FnOnce(u64, u64) -> bool) -> Option<u64> {
+        if size == 0 || size > self.limit {
+            return None;
+        }
+        let size = size.div_ceil(ALIGN) * ALIGN;
+        if let Some(addr) = self.take(size) {
+            return Some(addr);
+        }
+
+        // Grow by what the last free block, if it ends at the top, lacks
+        let tail = match self.free.last() {
+            Some(&(start, len)) if start + len == self.top => len,
+            _ => 0,
+        };
+        let page = crate::PAGE_SIZE as u64;
+        let bytes = ((size - tail).div_ceil(page) * page).max(GROW_MIN);
+        let bytes = bytes.min(self.base + self.limit - self.top);
+        if bytes + tail < size || !grow(self.top, bytes) {
+            return None;
+        }
+        self.release(self.top, bytes);
+        self.top += bytes;
+        self.take(size)
+    }
+
+    /// Free the block at `addr`; returns its size, or None if `addr` isn't
+    /// the start of a block in use
+    pub fn free(&mut self, addr: u64) -> Option<u64> {
+        let size = self.used.remove(&addr)?;
+        self.release(addr, size);
+        Some(size)
+    }
+
+    /// First fit: carve `size` bytes from the front of a free block
+    fn take(&mut self, size: u64) -> Option<u64> {
+        let i = self.free.iter().position(|&(_, len)| len >= size)?;
+        let (start, len) = self.free[i];
+        if len == size {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (start + size, len - size);
+        }
+        self.used.insert(start, size);
+        Some(start)
+    }
+
+    /// Return a range to the free list, merging it with its neighbours
+    fn release(&mut self, start: u64, len: u64) {
+        let i = self.free.partition_point(|&(s, _)| s < start);
+        let mut block = (start, len);
+        if i < self.free.len() && start + len == self.free[i].0 {
+            block.1 += self.free[i].1;
+            self.free.remove(i);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == start {
+            self.free[i - 1].1 += block.1;
+        } else {
+            self.free.insert(i, block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0x1000_0000;
+
+    #[test]
+    fn test_first_fit_and_alignment() {
+        let mut heap = UserHeap::new(BASE, 4096, 1 << 20);
+        let a = heap.alloc(10, |_, _| false).unwrap();
+        let b = heap.alloc(20, |_, _| false).unwrap();
+        assert_eq!(a, BASE);
+        assert_eq!(b, BASE + 16);
+        assert_eq!(heap.free(a), Some(16));
+        // The hole is reused
+        assert_eq!(heap.alloc(16, |_, _| false), Some(BASE));
+    }
+
+    #[test]
+    fn test_free_merges_neighbours() {
+        let mut heap = UserHeap::new(BASE, 4096, 1 << 20);
+        let blocks: Vec<u64> = (0..4).map(|_| heap.alloc(1024, |_, _| false).unwrap()).collect();
+        assert_eq!(heap.alloc(1, |_, _| false), None);
+        heap.free(blocks[0]);
+        heap.free(blocks[2]);
+        heap.free(blocks[1]);
+        heap.free(blocks[3]);
+        assert_eq!(heap.free, alloc::vec![(BASE, 4096)]);
+        assert_eq!(heap.alloc(4096, |_, _| false), Some(BASE));
+    }
+
+    #[test]
+    fn test_free_rejects_unknown_pointers() {
+        let mut heap = UserHeap::new(BASE, 4096, 1 << 20);
+        let a = heap.alloc(64, |_, _| false).unwrap();
+        assert_eq!(heap.free(a + 16), None);
+        assert_eq!(heap.free(a), Some(64));
+        assert_eq!(heap.free(a), None);
+    }
+
+    #[test]
+    fn test_grows_from_the_free_tail() {
+        let mut heap = UserHeap::new(BASE, 4096, 1 << 20);
+        heap.alloc(4000, |_, _| false).unwrap();
+        let mut asked = None;
+        let a = heap.alloc(8192, |start, bytes| {
+            asked = Some((start, bytes));
+            true
+        });
+        // The 96 free bytes at the top count towards the block
+        assert_eq!(a, Some(BASE + 4000));
+        assert_eq!(asked, Some((BASE + 4096, GROW_MIN)));
+        assert_eq!(heap.mapped(), 4096 + GROW_MIN);
+    }
+
+    #[test]
+    fn test_growth_stops_at_the_limit() {
+        let mut heap = UserHeap::new(BASE, 0, GROW_MIN);
+        assert!(heap.alloc(GROW_MIN, |_, _| true).is_some());
+        assert_eq!(heap.alloc(16, |_, _| panic!("grew past the limit")), None);
+        assert_eq!(heap.alloc(GROW_MIN + 1, |_, _| true), None);
+
+        let mut heap = UserHeap::new(BASE, 0, 1 << 20);
+        assert_eq!(heap.alloc(64, |_, _| false), None);
+        assert_eq!(heap.mapped(), 0);
+    }
+}
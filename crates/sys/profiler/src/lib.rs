@@ -0,0 +1,259 @@
+//! WATOS Sampling Profiler
+//!
+//! The timer interrupt records the interrupted RIP and the running pid into a
+//! ring buffer (see `watos_arch::idt`). This crate turns those raw samples
+//! into something readable:
+//!
+//! - [`SymbolTable`] parses the kernel symbol map produced at build time
+//!   (`nm -n --demangle` output, installed as `kernel.sym` next to
+//!   `kernel.bin`) and resolves addresses to function names. The kernel
+//!   keeps one loaded for panic backtraces and `/proc/ksyms` as well.
+//! - [`Histogram`] counts samples per (process, function) and renders either
+//!   a sorted report or folded stacks for host flamegraph tooling:
+//!
+//! ```text
+//! shell;watos::handle_syscall 12
+//! kernel;watos_arch::halt 230
+//! gwbasic;0x1004a30 3
+//! ```
+//!
+//! Addresses outside the kernel symbol map (user code) are shown in hex.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Largest size assumed for the last symbol in the map
+const MAX_LAST_SYMBOL_SIZE: u64 = 0x10000;
+
+/// Kernel function symbols, sorted by address
+pub struct SymbolTable {
+    symbols: Vec<(u64, String)>,
+}
+
+impl SymbolTable {
+    /// Create an empty table (every lookup fails)
+    pub const fn new() -> Self {
+        SymbolTable { symbols: Vec::new() }
+    }
+
+    /// Parse `nm` output ("ADDRESS TYPE NAME" per line)
+    ///
+    /// Only text symbols (t/T/w/W) are kept; other lines are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Vec::new();
+        for line in text.lines() {
+            let mut parts = line.splitn(3, ' ');
+            let (addr, kind, name) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(a), Some(k), Some(n)) => (a, k, n.trim()),
+                _ => continue,
+            };
+            if !matches!(kind, "t" | "T" | "w" | "W") || name.is_empty() {
+                continue;
+            }
+            if let Ok(addr) = u64::from_str_radix(addr, 16) {
+                symbols.push((addr, String::from(name)));
+            }
+        }
+        symbols.sort_by_key(|s| s.0);
+        SymbolTable { symbols }
+    }
+
+    /// Number of symbols loaded
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Find the function containing `addr`
+    pub fn lookup(&self, addr: u64) -> Option<&str> {
+        self.resolve(addr).map(|(name, _)| name)
+    }
+
+    /// Find the function containing `addr` and how far into it `addr` is
+    pub fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        // Index of the first symbol above addr
+        let next = self.symbols.partition_point(|s| s.0 <= addr);
+        if next == 0 {
+            return None;
+        }
+        let (start, name) = &self.symbols[next - 1];
+        if next == self.symbols.len() && addr - start >= MAX_LAST_SYMBOL_SIZE {
+            return None;
+        }
+        Some((name, addr - start))
+    }
+
+    /// "function+0xoffset", or the address in hex if it is not in the map
+    pub fn symbolize(&self, addr: u64) -> String {
+        match self.resolve(addr) {
+            Some((name, 0)) => String::from(name),
+            Some((name, offset)) => format!("{}+{:#x}", name, offset),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// All symbols as (address, name), in address order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.symbols.iter().map(|(addr, name)| (*addr, name.as_str()))
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sample counts per (process, function)
+pub struct Histogram {
+    total: u64,
+    counts: BTreeMap<(String, String), u64>,
+}
+
+impl Histogram {
+    /// Aggregate (rip, pid) samples
+    ///
+    /// `process_name` maps a pid to the name shown for it (pid 0 is the kernel).
+    pub fn aggregate<I, F>(samples: I, symbols: &SymbolTable, process_name: F) -> Self
+    where
+        I: Iterator<Item = (u64, u64)>,
+        F: Fn(u64) -> String,
+    {
+        let mut names: BTreeMap<u64, String> = BTreeMap::new();
+        let mut counts = BTreeMap::new();
+        let mut total = 0;
+
+        for (rip, pid) in samples {
+            let process = names.entry(pid).or_insert_with(|| process_name(pid)).clone();
+            let function = match symbols.lookup(rip) {
+                Some(name) => String::from(name),
+                None => format!("{:#x}", rip),
+            };
+            *counts.entry((process, function)).or_insert(0) += 1;
+            total += 1;
+        }
+
+        Histogram { total, counts }
+    }
+
+    /// Total number of samples aggregated
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Entries sorted by descending count
+    fn sorted(&self) -> Vec<(&(String, String), u64)> {
+        let mut entries: Vec<_> = self.counts.iter().map(|(k, &v)| (k, v)).collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        entries
+    }
+
+    /// Human-readable table, hottest functions first
+    pub fn report(&self) -> String {
+        let mut out = String::from("  COUNT      %  PROCESS          FUNCTION\n");
+        for ((process, function), count) in self.sorted() {
+            let tenths = count * 1000 / self.total.max(1);
+            out.push_str(&format!(
+                "{:>7} {:>4}.{}  {:<16} {}\n",
+                count,
+                tenths / 10,
+                tenths % 10,
+                process,
+                function
+            ));
+        }
+        out
+    }
+
+    /// Folded stacks ("process;function count"), as read by flamegraph.pl
+    /// and similar tools
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for ((process, function), count) in self.counts.iter() {
+            // ';' separates frames, so it cannot appear inside one
+            out.push_str(&format!(
+                "{};{} {}\n",
+                process.replace(';', ":"),
+                function.replace(';', ":"),
+                count
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+0000000000100000 T _start
+0000000000100040 T kernel_main
+0000000000100200 t watos::handle_syscall
+0000000000100200 r some_rodata
+0000000000100500 T watos_arch::halt
+";
+
+    fn name(pid: u64) -> String {
+        match pid {
+            0 => String::from("kernel"),
+            _ => format!("pid{}", pid),
+        }
+    }
+
+    #[test]
+    fn test_parse_keeps_text_symbols() {
+        let table = SymbolTable::parse(MAP);
+        assert_eq!(table.len(), 4);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table = SymbolTable::parse(MAP);
+        assert_eq!(table.lookup(0x100000), Some("_start"));
+        assert_eq!(table.lookup(0x100100), Some("kernel_main"));
+        assert_eq!(table.lookup(0x100250), Some("watos::handle_syscall"));
+        assert_eq!(table.lookup(0x100510), Some("watos_arch::halt"));
+        assert_eq!(table.lookup(0xfffff), None);
+        // User code is far above the kernel
+        assert_eq!(table.lookup(0x1000000), None);
+
+        assert_eq!(table.resolve(0x100250), Some(("watos::handle_syscall", 0x50)));
+        assert_eq!(table.symbolize(0x100040), "kernel_main");
+        assert_eq!(table.symbolize(0x100041), "kernel_main+0x1");
+        assert_eq!(table.symbolize(0x10), "0x10");
+        assert_eq!(table.iter().next(), Some((0x100000, "_start")));
+    }
+
+    #[test]
+    fn test_histogram_report_and_folded() {
+        let table = SymbolTable::parse(MAP);
+        let samples = [
+            (0x100510, 0),
+            (0x100510, 0),
+            (0x100210, 3),
+            (0x1000123, 3),
+        ];
+        let hist = Histogram::aggregate(samples.iter().copied(), &table, name);
+        assert_eq!(hist.total(), 4);
+
+        let folded = hist.folded();
+        assert!(folded.contains("kernel;watos_arch::halt 2\n"));
+        assert!(folded.contains("pid3;watos::handle_syscall 1\n"));
+        assert!(folded.contains("pid3;0x1000123 1\n"));
+
+        let report = hist.report();
+        let first = report.lines().nth(1).unwrap();
+        assert!(first.contains("50.0"));
+        assert!(first.ends_with("watos_arch::halt"));
+    }
+}
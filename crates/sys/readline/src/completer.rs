@@ -108,6 +108,26 @@ impl PathCompleter {
         }
     }
 
+    /// Parse one SYS_READDIR line ("D name 0", "F name 1234")
+    ///
+    /// The name may itself contain spaces, so the size is taken from the last field.
+    fn parse_entry(line: &[u8]) -> Option<(&str, bool)> {
+        if line.len() < 3 || !(line[1] == b' ' || line[1] == b'\t') {
+            return None;
+        }
+        let is_dir = line[0] == b'D';
+        let rest = core::str::from_utf8(&line[2..]).ok()?;
+        let name = match rest.rfind(' ') {
+            Some(pos) if rest[pos + 1..].bytes().all(|b| b.is_ascii_digit()) => &rest[..pos],
+            _ => rest,
+        };
+        if name.is_empty() {
+            None
+        } else {
+            Some((name, is_dir))
+        }
+    }
+
     /// List directory contents
     fn list_directory(path: &str) -> Vec<(String, bool)> {
         let mut results = Vec::new();
@@ -127,28 +147,12 @@ impl PathCompleter {
             ret as usize
         };
 
-        if len > 0 && len < buf.len() {
-            // Parse response: "TYPE\tNAME SIZE\n" format
-            let data = &buf[..len];
-            for line in data.split(|&b| b == b'\n') {
-                if line.is_empty() {
-                    continue;
-                }
-                // Format: "D\tname" or "F\tname SIZE"
-                if line.len() >= 3 && line[1] == b'\t' {
-                    let is_dir = line[0] == b'D';
-                    // Find the name (up to space or end)
-                    let name_start = 2;
-                    let name_end = line[name_start..]
-                        .iter()
-                        .position(|&b| b == b' ')
-                        .map(|p| name_start + p)
-                        .unwrap_or(line.len());
-
-                    if let Ok(name) = core::str::from_utf8(&line[name_start..name_end]) {
-                        if !name.is_empty() && name != "." && name != ".." {
-                            results.push((String::from(name), is_dir));
-                        }
+        if len > 0 && len <= buf.len() {
+            // Parse response: "TYPE NAME SIZE\n" per entry
+            for line in buf[..len].split(|&b| b == b'\n') {
+                if let Some((name, is_dir)) = Self::parse_entry(line) {
+                    if name != "." && name != ".." {
+                        results.push((String::from(name), is_dir));
                     }
                 }
             }
@@ -299,9 +303,9 @@ impl CommandCompleter {
 
         for dir in Self::get_path_dirs() {
             let entries = PathCompleter::list_directory(&dir);
-            for (name, _is_dir) in entries {
+            for (name, is_dir) in entries {
                 // Skip directories, only complete executables
-                if name.starts_with(partial) && !seen.contains(&name) {
+                if !is_dir && name.starts_with(partial) && !seen.contains(&name) {
                     seen.push(name.clone());
                     completions.push(Completion::with_suffix(name, ' '));
                 }
@@ -360,10 +364,9 @@ impl Completer for ShellCompleter {
             return PathCompleter::new().complete(line, cursor);
         }
 
-        // If at the start of line or after certain characters, complete commands
-        let before = &line[..start];
+        // If at the start of line or after a command separator, complete commands
+        let before = line[..start].trim_end();
         let is_command_position = before.is_empty()
-            || before.ends_with(' ')
             || before.ends_with('|')
             || before.ends_with('&')
             || before.ends_with(';');
@@ -379,3 +382,17 @@ impl Completer for ShellCompleter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_readdir_entry() {
+        assert_eq!(PathCompleter::parse_entry(b"D apps 0"), Some(("apps", true)));
+        assert_eq!(PathCompleter::parse_entry(b"F readme.txt 1234"), Some(("readme.txt", false)));
+        assert_eq!(PathCompleter::parse_entry(b"F my notes.txt 12"), Some(("my notes.txt", false)));
+        assert_eq!(PathCompleter::parse_entry(b"D\tbin"), Some(("bin", true)));
+        assert_eq!(PathCompleter::parse_entry(b""), None);
+    }
+}
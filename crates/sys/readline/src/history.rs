@@ -212,6 +212,41 @@ impl History {
     pub fn iter(&self) -> impl Iterator<Item = &String> {
         self.entries.iter().rev()
     }
+
+    /// Load entries from newline-separated text (oldest first, as written by `to_text`)
+    ///
+    /// Returns the number of lines read. Entries beyond `max_size` push out the oldest.
+    pub fn load_text(&mut self, text: &str) -> usize {
+        let mut count = 0;
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if !line.trim().is_empty() {
+                self.add(line);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Maximum number of entries kept
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Whether a history file of `lines` lines holds more than `max_size` entries
+    pub fn needs_trim(&self, lines: usize) -> bool {
+        lines > self.max_size
+    }
+
+    /// Serialize entries as newline-separated text (oldest first)
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for entry in self.entries.iter() {
+            text.push_str(entry);
+            text.push('\n');
+        }
+        text
+    }
 }
 
 #[cfg(test)]
@@ -261,4 +296,42 @@ mod tests {
         assert_eq!(hist.search_backward("echo", ""), Some("echo world"));
         assert_eq!(hist.search_backward("echo", ""), Some("echo hello"));
     }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut hist = History::new(10);
+        hist.add("ls /");
+        hist.add("cat file");
+        let text = hist.to_text();
+        assert_eq!(text, "ls /\ncat file\n");
+
+        let mut loaded = History::new(10);
+        assert_eq!(loaded.load_text("ls /\r\n\ncat file\n"), 2);
+        assert_eq!(loaded.get(0), Some("cat file"));
+        assert_eq!(loaded.get(1), Some("ls /"));
+    }
+
+    #[test]
+    fn test_load_respects_max_size() {
+        let mut hist = History::new(2);
+        hist.load_text("one\ntwo\nthree\n");
+        assert_eq!(hist.len(), 2);
+        assert_eq!(hist.get(1), Some("two"));
+    }
+
+    #[test]
+    fn test_trimmed_text_keeps_newest() {
+        let mut hist = History::new(3);
+        let read = hist.load_text("a\nb\nc\nd\ne\n");
+        assert_eq!(read, 5);
+        assert!(hist.needs_trim(read));
+        assert_eq!(hist.to_text(), "c\nd\ne\n");
+
+        // Rewritten text loads back without needing another trim
+        let mut again = History::new(3);
+        let read = again.load_text(&hist.to_text());
+        assert!(!again.needs_trim(read));
+        assert!(again.needs_trim(read + 1));
+        assert_eq!(again.max_size(), 3);
+    }
 }
@@ -58,10 +58,8 @@ impl KeyReader {
         loop {
             let ch = Self::read_char();
             if ch == 0 {
-                // No key available, spin briefly
-                for _ in 0..1000 {
-                    core::hint::spin_loop();
-                }
+                // No key available, halt until the next interrupt
+                Self::idle();
                 continue;
             }
 
@@ -136,6 +134,18 @@ impl KeyReader {
         }
     }
 
+    /// Wait for the next interrupt (a key press or timer tick)
+    fn idle() {
+        unsafe {
+            core::arch::asm!(
+                "int 0x80",
+                in("eax") syscall::SYS_IDLE,
+                lateout("rax") _,
+                options(nostack)
+            );
+        }
+    }
+
     /// Parse CSI (Control Sequence Introducer) escape sequence
     /// Format: ESC [ <params> <final>
     fn parse_csi_sequence() -> Key {
@@ -2,7 +2,7 @@
 //!
 //! Provides full readline functionality for the WATOS shell including:
 //! - Line editing with cursor movement
-//! - Command history with up/down navigation
+//! - Command history with up/down navigation, optionally persisted to a file
 //! - Tab completion for files/directories and commands
 //! - Vi and Emacs editing modes
 //!
@@ -59,6 +59,13 @@ pub use vi::{ViMode, ViState};
 
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
+use watos_syscall::{open, syscalls};
+
+/// SYS_OPEN flags used for the history file
+const OPEN_READ: u32 = open::O_RDONLY;
+const OPEN_WRITE: u32 = open::O_WRONLY | open::O_CREAT | open::O_TRUNC;
+const OPEN_APPEND: u32 = open::O_WRONLY | open::O_CREAT | open::O_APPEND;
 
 /// Readline error types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -92,6 +99,8 @@ pub struct Readline {
     editor: LineEditor,
     /// Command history
     history: History,
+    /// Lines in the history file, as loaded plus appended
+    file_lines: usize,
     /// Current editing mode
     mode: EditMode,
 }
@@ -102,6 +111,7 @@ impl Readline {
         Readline {
             editor: LineEditor::new(),
             history: History::new(1000),
+            file_lines: 0,
             mode: EditMode::default(),
         }
     }
@@ -111,6 +121,7 @@ impl Readline {
         Readline {
             editor: LineEditor::new(),
             history: History::new(size),
+            file_lines: 0,
             mode: EditMode::default(),
         }
     }
@@ -150,6 +161,73 @@ impl Readline {
         self.history.len()
     }
 
+    /// Load history from a file (one entry per line, oldest first)
+    ///
+    /// A file longer than the history size is rewritten with just the
+    /// entries kept. Returns the number of entries loaded, or 0 if the
+    /// file can't be read.
+    pub fn load_history(&mut self, path: &str) -> usize {
+        let fd = syscalls::open(path, OPEN_READ);
+        if fd < 0 {
+            return 0;
+        }
+
+        let mut data = Vec::new();
+        let mut chunk = [0u8; 512];
+        loop {
+            let n = syscalls::read(fd, &mut chunk);
+            if n == 0 || n > chunk.len() {
+                break;
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+        syscalls::close(fd);
+
+        let text = String::from_utf8_lossy(&data);
+        let read = self.history.load_text(&text);
+        self.file_lines = read;
+        if self.history.needs_trim(read) {
+            self.save_history(path);
+        }
+        self.history.len()
+    }
+
+    /// Write the whole history to a file, replacing its contents
+    pub fn save_history(&mut self, path: &str) -> bool {
+        let fd = syscalls::open(path, OPEN_WRITE);
+        if fd < 0 {
+            return false;
+        }
+        let text = self.history.to_text();
+        let written = syscalls::write(fd, text.as_bytes());
+        syscalls::close(fd);
+        self.file_lines = self.history.len();
+        written == text.len()
+    }
+
+    /// Append a single entry to a history file
+    ///
+    /// Once the file would outgrow the history size it's rewritten from the
+    /// in-memory history (which already holds `line`) instead.
+    pub fn append_history(&mut self, path: &str, line: &str) -> bool {
+        let line = line.trim();
+        if line.is_empty() {
+            return false;
+        }
+        if self.history.needs_trim(self.file_lines + 1) {
+            return self.save_history(path);
+        }
+        let fd = syscalls::open(path, OPEN_APPEND);
+        if fd < 0 {
+            return false;
+        }
+        let ok = syscalls::write(fd, line.as_bytes()) == line.len()
+            && syscalls::write(fd, b"\n") == 1;
+        syscalls::close(fd);
+        self.file_lines += 1;
+        ok
+    }
+
     /// Read a line with full editing support
     ///
     /// Displays the prompt and allows the user to edit the line using
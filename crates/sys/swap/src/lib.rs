@@ -0,0 +1,496 @@
+//! WATOS Swap
+//!
+//! Swap space for user pages, kept in a file. The file has the layout
+//! Linux's `mkswap` writes (version 1), so either side can prepare it:
+//!
+//! ```text
+//! page 0:  boot block[1024]  version:u32  last_page:u32  nr_badpages:u32
+//!          uuid[16]  label[16]  padding  badpages:u32[] (from 1536)
+//!          ... "SWAPSPACE2" in the last 10 bytes
+//! page n:  slot n, one 4 KiB page (1 <= n <= last_page)
+//! ```
+//!
+//! Fields are little-endian. Slots are handed out from a bitmap; page 0
+//! and bad pages are never used. Which slot holds which page is up to the
+//! caller (the process page tables keep it, see `watos-process`).
+//!
+//! One swap area is active at a time, set with [`swapon`]. The functions
+//! that use it only try its lock, as they may run in a page fault that
+//! interrupted the kernel.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+use watos_vfs::{FileOperations, SeekFrom, VfsError};
+
+/// Size of a page and of a slot
+pub const PAGE_SIZE: usize = 4096;
+
+/// Header signature, in the last bytes of page 0
+pub const SIGNATURE: &[u8; 10] = b"SWAPSPACE2";
+
+/// Smallest usable swap file: the header and one slot
+pub const MIN_PAGES: u32 = 2;
+
+const VERSION: u32 = 1;
+
+// Header field offsets in page 0
+const OFF_VERSION: usize = 1024;
+const OFF_LAST_PAGE: usize = 1028;
+const OFF_NR_BADPAGES: usize = 1032;
+const OFF_UUID: usize = 1036;
+const OFF_LABEL: usize = 1052;
+const OFF_BADPAGES: usize = 1536;
+const MAX_BADPAGES: usize = (PAGE_SIZE - SIGNATURE.len() - OFF_BADPAGES) / 4;
+
+/// Swap errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+    /// No swap signature
+    NotSwap,
+    /// Header version other than 1
+    Unsupported,
+    /// File shorter than its header says, or too small to hold a slot
+    TooSmall,
+    /// No free slot
+    Full,
+    /// Not enough free memory to bring swapped pages back
+    NoMemory,
+    /// Slot outside the area, or not in use
+    InvalidSlot,
+    /// A swap area is already active
+    Active,
+    /// No swap area is active
+    NotActive,
+    /// The area is in use by an interrupted operation
+    Busy,
+    /// Reading or writing the file failed
+    Io(VfsError),
+}
+
+impl From<VfsError> for SwapError {
+    fn from(e: VfsError) -> Self {
+        SwapError::Io(e)
+    }
+}
+
+pub type SwapResult<T> = Result<T, SwapError>;
+
+/// Swap header fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Index of the last page in the area
+    pub last_page: u32,
+    pub uuid: [u8; 16],
+    /// Label, NUL padded
+    pub label: [u8; 16],
+    /// Pages that must not be used
+    pub bad_pages: Vec<u32>,
+}
+
+impl Header {
+    /// Header for a new area of `pages` pages, header included
+    pub fn new(pages: u32, label: &str) -> SwapResult<Self> {
+        if pages < MIN_PAGES {
+            return Err(SwapError::TooSmall);
+        }
+        let mut header = Header { last_page: pages - 1, uuid: [0; 16], label: [0; 16], bad_pages: Vec::new() };
+        let len = label.len().min(header.label.len());
+        header.label[..len].copy_from_slice(&label.as_bytes()[..len]);
+        Ok(header)
+    }
+
+    /// Parse page 0 of a swap area
+    pub fn parse(page: &[u8]) -> SwapResult<Self> {
+        if page.len() < PAGE_SIZE || &page[PAGE_SIZE - SIGNATURE.len()..PAGE_SIZE] != SIGNATURE {
+            return Err(SwapError::NotSwap);
+        }
+        let u32_at = |off: usize| u32::from_le_bytes([page[off], page[off + 1], page[off + 2], page[off + 3]]);
+        if u32_at(OFF_VERSION) != VERSION {
+            return Err(SwapError::Unsupported);
+        }
+        let last_page = u32_at(OFF_LAST_PAGE);
+        if last_page < MIN_PAGES - 1 {
+            return Err(SwapError::TooSmall);
+        }
+        let bad = (u32_at(OFF_NR_BADPAGES) as usize).min(MAX_BADPAGES);
+        let mut header = Header {
+            last_page,
+            uuid: [0; 16],
+            label: [0; 16],
+            bad_pages: (0..bad).map(|i| u32_at(OFF_BADPAGES + i * 4)).collect(),
+        };
+        header.uuid.copy_from_slice(&page[OFF_UUID..OFF_UUID + 16]);
+        header.label.copy_from_slice(&page[OFF_LABEL..OFF_LABEL + 16]);
+        Ok(header)
+    }
+
+    /// Page 0 holding this header
+    pub fn to_page(&self) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        let bad = self.bad_pages.len().min(MAX_BADPAGES);
+        page[OFF_VERSION..OFF_VERSION + 4].copy_from_slice(&VERSION.to_le_bytes());
+        page[OFF_LAST_PAGE..OFF_LAST_PAGE + 4].copy_from_slice(&self.last_page.to_le_bytes());
+        page[OFF_NR_BADPAGES..OFF_NR_BADPAGES + 4].copy_from_slice(&(bad as u32).to_le_bytes());
+        page[OFF_UUID..OFF_UUID + 16].copy_from_slice(&self.uuid);
+        page[OFF_LABEL..OFF_LABEL + 16].copy_from_slice(&self.label);
+        for (i, &p) in self.bad_pages[..bad].iter().enumerate() {
+            page[OFF_BADPAGES + i * 4..OFF_BADPAGES + i * 4 + 4].copy_from_slice(&p.to_le_bytes());
+        }
+        page[PAGE_SIZE - SIGNATURE.len()..].copy_from_slice(SIGNATURE);
+        page
+    }
+}
+
+/// Turn `file` into a swap area of `pages` pages (like `mkswap`). Every
+/// page is written, so the filesystem allocates the whole file up front.
+pub fn format(file: &mut dyn FileOperations, pages: u32, label: &str) -> SwapResult<()> {
+    let header = Header::new(pages, label)?;
+    file.seek(0, SeekFrom::Start)?;
+    write_all(file, &header.to_page())?;
+    let zeros = vec![0u8; PAGE_SIZE];
+    for _ in 1..pages {
+        write_all(file, &zeros)?;
+    }
+    file.sync()?;
+    Ok(())
+}
+
+fn write_all(file: &mut dyn FileOperations, mut data: &[u8]) -> SwapResult<()> {
+    while !data.is_empty() {
+        match file.write(data)? {
+            0 => return Err(SwapError::Io(VfsError::NoSpace)),
+            n => data = &data[n..],
+        }
+    }
+    Ok(())
+}
+
+fn read_exact(file: &mut dyn FileOperations, mut buf: &mut [u8]) -> SwapResult<()> {
+    while !buf.is_empty() {
+        match file.read(buf)? {
+            0 => return Err(SwapError::TooSmall),
+            n => buf = &mut buf[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Slot usage of a swap area, in pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStats {
+    pub total: usize,
+    pub used: usize,
+}
+
+/// An open swap file
+pub struct SwapArea {
+    file: Box<dyn FileOperations>,
+    header: Header,
+    /// Bit set: slot in use (or unusable)
+    slots: Vec<u64>,
+    total: usize,
+    used: usize,
+    /// Where the search for a free slot starts
+    next: usize,
+    /// Refuse new pages while swapoff brings the old ones back
+    draining: bool,
+}
+
+impl SwapArea {
+    /// Check the header of a swap file and get ready to use it
+    pub fn open(mut file: Box<dyn FileOperations>) -> SwapResult<Self> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        file.seek(0, SeekFrom::Start)?;
+        read_exact(file.as_mut(), &mut page)?;
+        let header = Header::parse(&page)?;
+        let pages = header.last_page as usize + 1;
+        if file.stat()?.size < (pages * PAGE_SIZE) as u64 {
+            return Err(SwapError::TooSmall);
+        }
+
+        let mut slots = vec![0u64; pages.div_ceil(64)];
+        // Bits past the last page stay set so they are never handed out
+        for slot in pages..slots.len() * 64 {
+            slots[slot / 64] |= 1 << (slot % 64);
+        }
+        let mut total = pages;
+        for slot in core::iter::once(0).chain(header.bad_pages.iter().map(|&p| p as usize)) {
+            if slot < pages && slots[slot / 64] & (1 << (slot % 64)) == 0 {
+                slots[slot / 64] |= 1 << (slot % 64);
+                total -= 1;
+            }
+        }
+        if total == 0 {
+            return Err(SwapError::TooSmall);
+        }
+        Ok(SwapArea { file, header, slots, total, used: 0, next: 1, draining: false })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn stats(&self) -> SwapStats {
+        SwapStats { total: self.total, used: self.used }
+    }
+
+    /// Write a page to a free slot and return the slot
+    pub fn store(&mut self, page: &[u8]) -> SwapResult<u32> {
+        if page.len() != PAGE_SIZE {
+            return Err(SwapError::InvalidSlot);
+        }
+        if self.draining {
+            return Err(SwapError::Full);
+        }
+        let slot = self.alloc_slot().ok_or(SwapError::Full)?;
+        let written = self
+            .file
+            .seek((slot * PAGE_SIZE) as i64, SeekFrom::Start)
+            .map_err(SwapError::from)
+            .and_then(|_| write_all(self.file.as_mut(), page));
+        if let Err(e) = written {
+            self.free_slot(slot);
+            return Err(e);
+        }
+        Ok(slot as u32)
+    }
+
+    /// Read the page in `slot`; the slot stays in use
+    pub fn load(&mut self, slot: u32, page: &mut [u8]) -> SwapResult<()> {
+        if page.len() != PAGE_SIZE || !self.in_use(slot as usize) {
+            return Err(SwapError::InvalidSlot);
+        }
+        self.file.seek(slot as i64 * PAGE_SIZE as i64, SeekFrom::Start)?;
+        read_exact(self.file.as_mut(), page)
+    }
+
+    /// Give `slot` back
+    pub fn release(&mut self, slot: u32) -> SwapResult<()> {
+        if !self.in_use(slot as usize) {
+            return Err(SwapError::InvalidSlot);
+        }
+        self.free_slot(slot as usize);
+        Ok(())
+    }
+
+    fn in_use(&self, slot: usize) -> bool {
+        slot != 0
+            && slot <= self.header.last_page as usize
+            && !self.header.bad_pages.contains(&(slot as u32))
+            && self.slots[slot / 64] & (1 << (slot % 64)) != 0
+    }
+
+    fn alloc_slot(&mut self) -> Option<usize> {
+        if self.used == self.total {
+            return None;
+        }
+        let words = self.slots.len();
+        for i in 0..=words {
+            let word = (self.next / 64 + i) % words;
+            let free = !self.slots[word];
+            if free != 0 {
+                let slot = word * 64 + free.trailing_zeros() as usize;
+                self.slots[word] |= 1 << (slot % 64);
+                self.used += 1;
+                self.next = slot + 1;
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        self.slots[slot / 64] &= !(1 << (slot % 64));
+        self.used -= 1;
+        self.next = self.next.min(slot);
+    }
+}
+
+// ============================================================================
+// Active swap area
+// ============================================================================
+
+static SWAP: Mutex<Option<SwapArea>> = Mutex::new(None);
+
+/// Start swapping to `area`
+pub fn swapon(area: SwapArea) -> SwapResult<()> {
+    let mut swap = SWAP.lock();
+    if swap.is_some() {
+        return Err(SwapError::Active);
+    }
+    *swap = Some(area);
+    Ok(())
+}
+
+/// Stop taking new pages, ahead of bringing the stored ones back for
+/// swapoff; `false` undoes it
+pub fn set_draining(draining: bool) -> SwapResult<()> {
+    with_area(|area| {
+        area.draining = draining;
+        Ok(())
+    })
+}
+
+/// Stop swapping. Fails while slots are still in use.
+pub fn swapoff() -> SwapResult<SwapArea> {
+    let mut swap = SWAP.lock();
+    match swap.as_ref() {
+        None => Err(SwapError::NotActive),
+        Some(area) if area.used != 0 => Err(SwapError::Busy),
+        Some(_) => Ok(swap.take().unwrap()),
+    }
+}
+
+/// Is a swap area active?
+pub fn active() -> bool {
+    SWAP.try_lock().is_some_and(|swap| swap.is_some())
+}
+
+/// Slot usage of the active area
+pub fn stats() -> Option<SwapStats> {
+    SWAP.try_lock().and_then(|swap| swap.as_ref().map(SwapArea::stats))
+}
+
+/// Write a page to the active area, see [`SwapArea::store`]
+pub fn store(page: &[u8]) -> SwapResult<u32> {
+    with_area(|area| area.store(page))
+}
+
+/// Read a page from the active area, see [`SwapArea::load`]
+pub fn load(slot: u32, page: &mut [u8]) -> SwapResult<()> {
+    with_area(|area| area.load(slot, page))
+}
+
+/// Free a slot of the active area
+pub fn release(slot: u32) -> SwapResult<()> {
+    with_area(|area| area.release(slot))
+}
+
+fn with_area<T>(f: impl FnOnce(&mut SwapArea) -> SwapResult<T>) -> SwapResult<T> {
+    let mut swap = SWAP.try_lock().ok_or(SwapError::Busy)?;
+    f(swap.as_mut().ok_or(SwapError::NotActive)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::sync::Arc;
+    use watos_vfs::{FileStat, FileType, VfsResult};
+
+    /// A file in memory, shared so tests can look at it afterwards
+    struct MemFile {
+        data: Arc<Mutex<Vec<u8>>>,
+        pos: usize,
+    }
+
+    impl FileOperations for MemFile {
+        fn read(&mut self, buffer: &mut [u8]) -> VfsResult<usize> {
+            let data = self.data.lock();
+            let n = buffer.len().min(data.len().saturating_sub(self.pos));
+            buffer[..n].copy_from_slice(&data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+
+        fn write(&mut self, buffer: &[u8]) -> VfsResult<usize> {
+            let mut data = self.data.lock();
+            if data.len() < self.pos + buffer.len() {
+                data.resize(self.pos + buffer.len(), 0);
+            }
+            data[self.pos..self.pos + buffer.len()].copy_from_slice(buffer);
+            self.pos += buffer.len();
+            Ok(buffer.len())
+        }
+
+        fn seek(&mut self, offset: i64, whence: SeekFrom) -> VfsResult<u64> {
+            self.pos = match whence {
+                SeekFrom::Start => offset as usize,
+                SeekFrom::Current => (self.pos as i64 + offset) as usize,
+                SeekFrom::End => (self.data.lock().len() as i64 + offset) as usize,
+            };
+            Ok(self.pos as u64)
+        }
+
+        fn tell(&self) -> u64 {
+            self.pos as u64
+        }
+
+        fn sync(&mut self) -> VfsResult<()> {
+            Ok(())
+        }
+
+        fn stat(&self) -> VfsResult<FileStat> {
+            Ok(FileStat { file_type: FileType::Regular, size: self.data.lock().len() as u64, ..FileStat::default() })
+        }
+
+        fn truncate(&mut self, size: u64) -> VfsResult<()> {
+            self.data.lock().resize(size as usize, 0);
+            Ok(())
+        }
+    }
+
+    fn mem_file() -> (MemFile, Arc<Mutex<Vec<u8>>>) {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        (MemFile { data: data.clone(), pos: 0 }, data)
+    }
+
+    #[test]
+    fn test_format_matches_mkswap_layout() {
+        let (mut file, data) = mem_file();
+        format(&mut file, 8, "watos").unwrap();
+
+        let data = data.lock();
+        assert_eq!(data.len(), 8 * PAGE_SIZE);
+        assert_eq!(&data[PAGE_SIZE - 10..PAGE_SIZE], b"SWAPSPACE2");
+        assert_eq!(&data[1024..1032], &[1, 0, 0, 0, 7, 0, 0, 0]);
+        assert_eq!(&data[1052..1057], b"watos");
+
+        let header = Header::parse(&data[..PAGE_SIZE]).unwrap();
+        assert_eq!(header.last_page, 7);
+        assert!(header.bad_pages.is_empty());
+        assert_eq!(Header::parse(&[0u8; PAGE_SIZE]), Err(SwapError::NotSwap));
+    }
+
+    #[test]
+    fn test_store_load_release() {
+        let (mut file, _) = mem_file();
+        let mut header = Header::new(4, "").unwrap();
+        header.bad_pages.push(2);
+        file.write(&header.to_page()).unwrap();
+        file.write(&[0u8; 3 * PAGE_SIZE]).unwrap();
+
+        let mut area = SwapArea::open(Box::new(file)).unwrap();
+        assert_eq!(area.stats(), SwapStats { total: 2, used: 0 });
+
+        let a = area.store(&[0xAA; PAGE_SIZE]).unwrap();
+        let b = area.store(&[0xBB; PAGE_SIZE]).unwrap();
+        // Neither the header nor the bad page
+        assert_eq!((a, b), (1, 3));
+        assert_eq!(area.store(&[0xCC; PAGE_SIZE]), Err(SwapError::Full));
+
+        let mut page = [0u8; PAGE_SIZE];
+        area.load(b, &mut page).unwrap();
+        assert!(page.iter().all(|&x| x == 0xBB));
+        area.release(a).unwrap();
+        assert_eq!(area.release(a), Err(SwapError::InvalidSlot));
+        assert_eq!(area.load(2, &mut page), Err(SwapError::InvalidSlot));
+        assert_eq!(area.store(&[0xDD; PAGE_SIZE]), Ok(1));
+
+        area.draining = true;
+        area.release(1).unwrap();
+        assert_eq!(area.store(&[0xEE; PAGE_SIZE]), Err(SwapError::Full));
+    }
+
+    #[test]
+    fn test_open_rejects_short_file() {
+        let (mut file, _) = mem_file();
+        file.write(&Header::new(16, "").unwrap().to_page()).unwrap();
+        assert!(matches!(SwapArea::open(Box::new(file)), Err(SwapError::TooSmall)));
+    }
+}
@@ -127,6 +127,108 @@ pub const ANSI_COLORS: [Color; 16] = [
     Color::rgb(255, 255, 255), // 15: Bright White
 ];
 
+/// The colors a terminal draws with: default foreground and background,
+/// and what the 16 ANSI colors (SGR 30-37, 90-97 and their backgrounds)
+/// look like
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub fg: Color,
+    pub bg: Color,
+    pub ansi: [Color; 16],
+}
+
+/// Names of the built-in themes, in the order of [`THEMES`]
+pub const THEME_NAMES: [&str; 5] = ["vga", "xterm", "solarized", "high-contrast", "light"];
+
+/// Built-in themes
+pub const THEMES: [Palette; 5] = [
+    Palette::DEFAULT,
+    Palette::from_rgb(0xE5E5E5, 0x000000, [
+        0x000000, 0xCD0000, 0x00CD00, 0xCDCD00, 0x0000EE, 0xCD00CD, 0x00CDCD, 0xE5E5E5,
+        0x7F7F7F, 0xFF0000, 0x00FF00, 0xFFFF00, 0x5C5CFF, 0xFF00FF, 0x00FFFF, 0xFFFFFF,
+    ]),
+    Palette::from_rgb(0x839496, 0x002B36, [
+        0x073642, 0xDC322F, 0x859900, 0xB58900, 0x268BD2, 0xD33682, 0x2AA198, 0xEEE8D5,
+        0x002B36, 0xCB4B16, 0x586E75, 0x657B83, 0x839496, 0x6C71C4, 0x93A1A1, 0xFDF6E3,
+    ]),
+    // Nothing dim, and blue light enough to read on black
+    Palette::from_rgb(0xFFFFFF, 0x000000, [
+        0x000000, 0xFF5555, 0x55FF55, 0xFFFF55, 0x5599FF, 0xFF55FF, 0x55FFFF, 0xFFFFFF,
+        0xAAAAAA, 0xFF8080, 0x80FF80, 0xFFFF80, 0x80B0FF, 0xFF80FF, 0x80FFFF, 0xFFFFFF,
+    ]),
+    // Dark text on white; "white" is dark too so it stays readable
+    Palette::from_rgb(0x000000, 0xFFFFFF, [
+        0x000000, 0xB00000, 0x007000, 0x805000, 0x0000B0, 0x900090, 0x007070, 0x606060,
+        0x404040, 0xE00000, 0x009000, 0xA07000, 0x2040E0, 0xC000C0, 0x009090, 0x202020,
+    ]),
+];
+
+impl Palette {
+    /// White on black with the VGA colors
+    pub const DEFAULT: Self = Self { fg: Color::WHITE, bg: Color::BLACK, ansi: ANSI_COLORS };
+
+    /// A palette from 0xRRGGBB values
+    pub const fn from_rgb(fg: u32, bg: u32, ansi: [u32; 16]) -> Self {
+        let mut colors = [Color::BLACK; 16];
+        let mut i = 0;
+        while i < 16 {
+            colors[i] = Color(0xFF00_0000 | ansi[i]);
+            i += 1;
+        }
+        Self { fg: Color(0xFF00_0000 | fg), bg: Color(0xFF00_0000 | bg), ansi: colors }
+    }
+
+    /// A built-in theme by name
+    pub fn theme(name: &str) -> Option<Self> {
+        THEME_NAMES.iter().position(|&n| n == name).map(|i| THEMES[i])
+    }
+
+    /// Entry `index` of the 256-color palette, the first 16 from this one
+    pub fn color(&self, index: u8) -> Color {
+        match self.ansi.get(index as usize) {
+            Some(&color) => color,
+            None => color_256(index),
+        }
+    }
+
+    /// Replace ANSI colors from a comma-separated list of RRGGBB values,
+    /// starting at color 0; an empty entry leaves that color alone. False,
+    /// with nothing changed, if an entry isn't a color or there are more
+    /// than 16.
+    pub fn set_ansi(&mut self, list: &str) -> bool {
+        let mut ansi = self.ansi;
+        for (i, entry) in list.split(',').map(str::trim).enumerate() {
+            if i == ansi.len() {
+                return false;
+            }
+            if entry.is_empty() {
+                continue;
+            }
+            match parse_rgb(entry) {
+                Some(color) => ansi[i] = color,
+                None => return false,
+            }
+        }
+        self.ansi = ansi;
+        true
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// An opaque color from `RRGGBB` or `#RRGGBB`
+pub fn parse_rgb(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(|rgb| Color(0xFF00_0000 | rgb))
+}
+
 /// Get color from 256-color palette
 pub fn color_256(index: u8) -> Color {
     match index {
@@ -149,3 +251,58 @@ pub fn color_256(index: u8) -> Color {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rgb() {
+        assert_eq!(parse_rgb("FF8000"), Some(Color::rgb(255, 128, 0)));
+        assert_eq!(parse_rgb("#0a0B0c"), Some(Color::rgb(10, 11, 12)));
+        assert_eq!(parse_rgb(""), None);
+        assert_eq!(parse_rgb("#FFF"), None);
+        assert_eq!(parse_rgb("FF80001"), None);
+        assert_eq!(parse_rgb("GG0000"), None);
+        assert_eq!(parse_rgb("##FF0000"), None);
+        assert_eq!(parse_rgb("+FFFFF"), None);
+    }
+
+    #[test]
+    fn test_themes() {
+        assert_eq!(Palette::theme("vga"), Some(Palette::DEFAULT));
+        assert_eq!(Palette::theme("light").unwrap().bg, Color::rgb(255, 255, 255));
+        assert_eq!(Palette::theme("Light"), None);
+        assert_eq!(Palette::theme(""), None);
+        for (i, name) in THEME_NAMES.iter().enumerate() {
+            assert_eq!(Palette::theme(name), Some(THEMES[i]));
+        }
+    }
+
+    #[test]
+    fn test_palette_color() {
+        let palette = Palette::theme("solarized").unwrap();
+        assert_eq!(palette.color(1), Color::rgb(0xDC, 0x32, 0x2F));
+        // Past the first 16 the palette is the standard 256
+        assert_eq!(palette.color(16), color_256(16));
+        assert_eq!(palette.color(255), color_256(255));
+    }
+
+    #[test]
+    fn test_set_ansi() {
+        let mut palette = Palette::DEFAULT;
+        assert!(palette.set_ansi("102030, ,#405060"));
+        assert_eq!(palette.ansi[0], Color::rgb(0x10, 0x20, 0x30));
+        assert_eq!(palette.ansi[1], ANSI_COLORS[1]);
+        assert_eq!(palette.ansi[2], Color::rgb(0x40, 0x50, 0x60));
+        assert_eq!(palette.ansi[3..], ANSI_COLORS[3..]);
+
+        // Bad entries change nothing
+        let before = palette;
+        assert!(!palette.set_ansi("000000,red"));
+        assert!(!palette.set_ansi("000000,,,,,,,,,,,,,,,,FFFFFF"));
+        assert_eq!(palette, before);
+        // Exactly 16 is fine
+        assert!(palette.set_ansi(",,,,,,,,,,,,,,,FFFFFF"));
+    }
+}
@@ -3,7 +3,9 @@
 //! Manages multiple virtual terminals (like Linux tty1-tty12)
 //! with Alt+Fn switching.
 
-use crate::cell::Cell;
+use crate::cell::{Cell, CellFlags};
+use crate::font::Font;
+use crate::grid::{MAX_COLS, MAX_ROWS};
 use crate::framebuffer::Framebuffer;
 use crate::keyboard::{Keyboard, KeyEvent, KeyCode, Modifiers};
 use crate::renderer::Renderer;
@@ -185,6 +187,16 @@ impl ConsoleManager {
         }
     }
 
+    /// Switch the console font and scale, resizing the consoles to fit a
+    /// framebuffer of the given pixel size
+    pub fn set_font(&mut self, font: &'static dyn Font, scale: u32, fb_width: u32, fb_height: u32) {
+        self.renderer.set_font(font, scale);
+        let cols = ((fb_width / self.renderer.cell_width()).max(1) as usize).min(MAX_COLS);
+        let rows = ((fb_height / self.renderer.cell_height()).max(1) as usize).min(MAX_ROWS);
+        self.resize(cols, rows);
+        self.invalidate();
+    }
+
     /// Get keyboard handler reference (for converting events to chars)
     pub fn keyboard(&self) -> &Keyboard {
         &self.keyboard
@@ -200,6 +212,64 @@ impl ConsoleManager {
         (self.cols, self.rows)
     }
 
+    /// Pixel size of one character cell
+    pub fn cell_size(&self) -> (u32, u32) {
+        (self.renderer.cell_width(), self.renderer.cell_height())
+    }
+
+    /// Toggle reverse video over the cells from `start` to `end` (inclusive
+    /// (col, row) pairs, in either order); calling it again with the same
+    /// range removes the highlight
+    pub fn toggle_highlight(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let (cols, rows) = (self.cols, self.rows);
+        if let Some(term) = &mut self.terminals[self.active] {
+            for (col, row) in selection_cells(cols, rows, start, end) {
+                if let Some(cell) = term.grid.get_mut(col, row) {
+                    cell.flags.toggle(CellFlags::REVERSE);
+                }
+            }
+        }
+    }
+
+    /// Copy the text of a selection into `buf` as UTF-8, dropping trailing
+    /// blanks from each row and joining rows with '\n'. Returns the length
+    /// written; text that does not fit is cut off.
+    pub fn selection_text(&self, start: (usize, usize), end: (usize, usize), buf: &mut [u8]) -> usize {
+        let Some(term) = self.active_terminal() else { return 0 };
+        let mut len = 0;
+        // End of the text before this row's trailing blanks
+        let mut trimmed = 0;
+        let mut row = None;
+        for (col, r) in selection_cells(self.cols, self.rows, start, end) {
+            if row.is_some_and(|prev| prev != r) {
+                len = trimmed;
+                if len == buf.len() {
+                    return len;
+                }
+                buf[len] = b'\n';
+                len += 1;
+                trimmed = len;
+            }
+            row = Some(r);
+            let Some(cell) = term.grid.get(col, r) else { continue };
+            if cell.flags.contains(CellFlags::WIDE_SPACER) {
+                continue;
+            }
+            let ch = if cell.ch == '\0' { ' ' } else { cell.ch };
+            let mut utf8 = [0u8; 4];
+            let bytes = ch.encode_utf8(&mut utf8).as_bytes();
+            if len + bytes.len() > buf.len() {
+                return trimmed;
+            }
+            buf[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+            if ch != ' ' {
+                trimmed = len;
+            }
+        }
+        trimmed
+    }
+
     /// Get a row's content as chars for testing
     /// Callback receives (row_index, &[Cell]) for each row
     pub fn for_each_row<F: FnMut(usize, &[Cell])>(&self, mut f: F) {
@@ -213,6 +283,18 @@ impl ConsoleManager {
     }
 }
 
+/// Cells from `start` to `end` (inclusive (col, row) pairs, in either
+/// order) in reading order, clipped to a `cols` x `rows` screen
+fn selection_cells(cols: usize, rows: usize, start: (usize, usize), end: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    let (first, last) = if (start.1, start.0) <= (end.1, end.0) { (start, end) } else { (end, start) };
+    let last_col = cols.saturating_sub(1);
+    (first.1..=last.1.min(rows.saturating_sub(1))).flat_map(move |row| {
+        let from = if row == first.1 { first.0 } else { 0 };
+        let to = if row == last.1 { last.0.min(last_col) } else { last_col };
+        (from..=to).map(move |col| (col, row))
+    })
+}
+
 impl Default for ConsoleManager {
     fn default() -> Self {
         Self::new(80, 25)
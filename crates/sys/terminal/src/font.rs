@@ -0,0 +1,677 @@
+//! Bitmap fonts
+//!
+//! The renderer draws glyphs through the [`Font`] trait. Two sources exist:
+//! - [`BuiltinFont`]: the 8x16 ASCII table plus Latin-1, box drawing and
+//!   block elements, all generated at compile time
+//! - [`Psf2Font`] (with `alloc`): a PC Screen Font 2 file, e.g. loaded from
+//!   the VFS, in whatever cell size the file uses
+//!
+//! Size selection is an integer scale applied by the renderer on top of the
+//! font's own cell size.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::renderer::FONT_8X16;
+
+/// Glyph drawn in place of characters a font does not cover
+pub const REPLACEMENT_CHAR: char = '\u{FFFD}';
+
+/// A single glyph bitmap: rows top to bottom, MSB is the leftmost pixel,
+/// each row padded to a whole number of bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [u8],
+}
+
+impl Glyph<'_> {
+    /// Bytes per glyph row
+    pub fn stride(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    /// Is the pixel at (x, y) set?
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let byte = self.data.get(y as usize * self.stride() + x as usize / 8).copied().unwrap_or(0);
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// A monospaced bitmap font
+pub trait Font {
+    /// Cell width in pixels
+    fn width(&self) -> u32;
+    /// Cell height in pixels
+    fn height(&self) -> u32;
+    /// Glyph for `ch`, or None if the font does not cover it
+    fn glyph(&self, ch: char) -> Option<Glyph<'_>>;
+}
+
+/// Errors from parsing a font file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// Not a PSF2 file
+    BadMagic,
+    /// File shorter than its header claims
+    Truncated,
+    /// Header values we cannot render (zero or oversized cells)
+    Unsupported,
+}
+
+// ============================================================================
+// Built-in font
+// ============================================================================
+
+/// The compiled-in 8x16 font
+///
+/// Covers ASCII, Latin-1 (U+00A0-U+00FF), box drawing (U+2500-U+257F),
+/// block elements (U+2580-U+259F) and U+FFFD.
+pub struct BuiltinFont;
+
+/// Shared instance for renderers that hold a `&'static dyn Font`
+pub static BUILTIN_FONT: BuiltinFont = BuiltinFont;
+
+impl Font for BuiltinFont {
+    fn width(&self) -> u32 {
+        8
+    }
+
+    fn height(&self) -> u32 {
+        16
+    }
+
+    fn glyph(&self, ch: char) -> Option<Glyph<'_>> {
+        let code = ch as u32;
+        let rows: &[u8; 16] = match code {
+            0x20..=0x7E => &FONT_8X16[code as usize],
+            0xA0..=0xFF => &LATIN1[(code - 0xA0) as usize],
+            0x2500..=0x259F => &BOX_DRAWING[(code - 0x2500) as usize],
+            0xFFFD => &REPLACEMENT,
+            _ => return None,
+        };
+        Some(Glyph { width: 8, height: 16, data: rows })
+    }
+}
+
+// Accent marks, three rows each, drawn above a base letter (capitals are
+// shifted down a row to make room) or, for the cedilla, below it
+const GRAVE: [u8; 3] = [0x30, 0x18, 0x00];
+const ACUTE: [u8; 3] = [0x0C, 0x18, 0x00];
+const CIRCUMFLEX: [u8; 3] = [0x18, 0x66, 0x00];
+const TILDE: [u8; 3] = [0x76, 0xDC, 0x00];
+const DIAERESIS: [u8; 3] = [0x00, 0x66, 0x00];
+const RING: [u8; 3] = [0x18, 0x24, 0x18];
+const CEDILLA: [u8; 3] = [0x18, 0x0C, 0x38];
+const NO_ACCENT: [u8; 3] = [0; 3];
+
+/// Base letter and accent for U+00C0-U+00DF; lowercase is the same +0x20.
+/// A zero base marks a hand-drawn glyph.
+const LATIN1_COMPOSED: [(u8, [u8; 3]); 32] = [
+    (b'A', GRAVE), (b'A', ACUTE), (b'A', CIRCUMFLEX), (b'A', TILDE),
+    (b'A', DIAERESIS), (b'A', RING), (0, NO_ACCENT), (b'C', CEDILLA),
+    (b'E', GRAVE), (b'E', ACUTE), (b'E', CIRCUMFLEX), (b'E', DIAERESIS),
+    (b'I', GRAVE), (b'I', ACUTE), (b'I', CIRCUMFLEX), (b'I', DIAERESIS),
+    (0, NO_ACCENT), (b'N', TILDE), (b'O', GRAVE), (b'O', ACUTE),
+    (b'O', CIRCUMFLEX), (b'O', TILDE), (b'O', DIAERESIS), (0, NO_ACCENT),
+    (0, NO_ACCENT), (b'U', GRAVE), (b'U', ACUTE), (b'U', CIRCUMFLEX),
+    (b'U', DIAERESIS), (b'Y', ACUTE), (0, NO_ACCENT), (0, NO_ACCENT),
+];
+
+/// Hand-drawn Latin-1 glyphs as (code point, rows)
+const LATIN1_DRAWN: [(u32, [u8; 16]); 32] = [
+    (0xA2, [0x00, 0x00, 0x00, 0x18, 0x18, 0x3C, 0x66, 0x60, 0x60, 0x66, 0x3C, 0x18, 0x18, 0x00, 0x00, 0x00]),
+    (0xA3, [0x00, 0x00, 0x38, 0x6C, 0x64, 0x60, 0xF0, 0x60, 0x60, 0x60, 0xE6, 0xFC, 0x00, 0x00, 0x00, 0x00]),
+    (0xA4, [0x00, 0x00, 0x00, 0x00, 0x66, 0x3C, 0x66, 0x66, 0x3C, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xA5, [0x00, 0x00, 0x66, 0x66, 0x3C, 0x18, 0x7E, 0x18, 0x7E, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00]),
+    (0xA6, [0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00]),
+    (0xA7, [0x00, 0x3C, 0x66, 0x30, 0x18, 0x3C, 0x66, 0x66, 0x3C, 0x18, 0x0C, 0x66, 0x3C, 0x00, 0x00, 0x00]),
+    (0xA8, [0x00, 0x00, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xA9, [0x00, 0x00, 0x3C, 0x42, 0x99, 0xA5, 0xA1, 0xA1, 0xA5, 0x99, 0x42, 0x3C, 0x00, 0x00, 0x00, 0x00]),
+    (0xAA, [0x00, 0x00, 0x3C, 0x06, 0x3E, 0x66, 0x3E, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xAB, [0x00, 0x00, 0x00, 0x00, 0x00, 0x1B, 0x36, 0x6C, 0xD8, 0x6C, 0x36, 0x1B, 0x00, 0x00, 0x00, 0x00]),
+    (0xAC, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x06, 0x06, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xAE, [0x00, 0x00, 0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA9, 0xA5, 0x42, 0x3C, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xAF, [0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB0, [0x00, 0x38, 0x6C, 0x6C, 0x38, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB1, [0x00, 0x00, 0x00, 0x18, 0x18, 0x7E, 0x18, 0x18, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB2, [0x00, 0x70, 0xD8, 0x30, 0x60, 0xC8, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB3, [0x00, 0x70, 0xD8, 0x30, 0x18, 0xD8, 0x70, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB4, [0x00, 0x00, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB5, [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0xC0, 0x00, 0x00]),
+    (0xB6, [0x00, 0x00, 0x7F, 0xDB, 0xDB, 0xDB, 0x7B, 0x1B, 0x1B, 0x1B, 0x1B, 0x1B, 0x00, 0x00, 0x00, 0x00]),
+    (0xB7, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xB9, [0x00, 0x30, 0x70, 0x30, 0x30, 0x30, 0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xBA, [0x00, 0x00, 0x3C, 0x66, 0x66, 0x3C, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xBB, [0x00, 0x00, 0x00, 0x00, 0x00, 0xD8, 0x6C, 0x36, 0x1B, 0x36, 0x6C, 0xD8, 0x00, 0x00, 0x00, 0x00]),
+    (0xBC, [0x00, 0x40, 0xC2, 0x44, 0x48, 0x10, 0x24, 0x4C, 0x94, 0x3E, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xBD, [0x00, 0x40, 0xC2, 0x44, 0x48, 0x10, 0x2C, 0x52, 0x84, 0x08, 0x1E, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xBE, [0x00, 0xE0, 0x22, 0xE4, 0x28, 0xF0, 0x24, 0x4C, 0x94, 0x3E, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xC6, [0x00, 0x00, 0x3F, 0x6C, 0xCC, 0xCC, 0xFE, 0xCC, 0xCC, 0xCC, 0xCC, 0xCF, 0x00, 0x00, 0x00, 0x00]),
+    (0xD0, [0x00, 0x00, 0x78, 0x6C, 0x66, 0x66, 0xF6, 0x66, 0x66, 0x66, 0x6C, 0x78, 0x00, 0x00, 0x00, 0x00]),
+    (0xD7, [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x3C, 0x18, 0x3C, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xD8, [0x00, 0x00, 0x3C, 0x66, 0x66, 0x6E, 0x6E, 0x76, 0x76, 0x66, 0x66, 0x3C, 0x40, 0x00, 0x00, 0x00]),
+    (0xDE, [0x00, 0x00, 0x60, 0x60, 0x7C, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00]),
+];
+
+/// Hand-drawn lowercase glyphs (U+00DF and up)
+const LATIN1_DRAWN_LOWER: [(u32, [u8; 16]); 6] = [
+    (0xDF, [0x00, 0x00, 0x3C, 0x66, 0x66, 0x66, 0x6C, 0x66, 0x66, 0x66, 0x66, 0x6C, 0x00, 0x00, 0x00, 0x00]),
+    (0xE6, [0x00, 0x00, 0x00, 0x00, 0x00, 0x6C, 0x1A, 0x7E, 0xD8, 0xD8, 0xDA, 0x6C, 0x00, 0x00, 0x00, 0x00]),
+    (0xF0, [0x00, 0x00, 0x6C, 0x38, 0x6C, 0x06, 0x3E, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00]),
+    (0xF7, [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x7E, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (0xF8, [0x00, 0x00, 0x00, 0x00, 0x02, 0x3C, 0x6E, 0x6E, 0x76, 0x76, 0x66, 0x3C, 0x40, 0x00, 0x00, 0x00]),
+    (0xFE, [0x00, 0x00, 0x60, 0x60, 0x60, 0x7C, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0x00, 0x00]),
+];
+
+/// Hang a cedilla under a base glyph
+const fn add_cedilla(base: &[u8; 16]) -> [u8; 16] {
+    let mut out = *base;
+    out[12] |= CEDILLA[0];
+    out[13] |= CEDILLA[1];
+    out[14] |= CEDILLA[2];
+    out
+}
+
+/// Put an accent above a base glyph
+const fn compose(base: &[u8; 16], accent: &[u8; 3], capital: bool, dotless: bool) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    // Capitals move down a row; lowercase keeps its x-height at row 5
+    let (shift, top) = if capital { (1, 0) } else { (0, 2) };
+    let mut row = 0;
+    while row + shift < 16 {
+        out[row + shift] = base[row];
+        row += 1;
+    }
+    if dotless {
+        let mut row = 0;
+        while row < 5 {
+            out[row] = 0;
+            row += 1;
+        }
+    }
+    out[top] |= accent[0];
+    out[top + 1] |= accent[1];
+    out[top + 2] |= accent[2];
+    out
+}
+
+/// Mirror a glyph top to bottom and left to right (for ¡ and ¿)
+const fn rotate_half(glyph: &[u8; 16]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    let mut row = 0;
+    // The ASCII glyphs sit in rows 2..=11, so flip within that band
+    while row < 10 {
+        out[2 + row] = glyph[11 - row].reverse_bits();
+        row += 1;
+    }
+    out
+}
+
+const fn build_latin1() -> [[u8; 16]; 96] {
+    let mut table = [[0u8; 16]; 96];
+
+    table[0xA1 - 0xA0] = rotate_half(&FONT_8X16[b'!' as usize]);
+    table[0xAD - 0xA0] = FONT_8X16[b'-' as usize];
+    table[0xBF - 0xA0] = rotate_half(&FONT_8X16[b'?' as usize]);
+    table[0xB8 - 0xA0] = add_cedilla(&[0; 16]);
+
+    let mut i = 0;
+    while i < LATIN1_DRAWN.len() {
+        table[LATIN1_DRAWN[i].0 as usize - 0xA0] = LATIN1_DRAWN[i].1;
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < 32 {
+        let (base, accent) = LATIN1_COMPOSED[i];
+        let lower = base + 0x20;
+        if base == b'C' {
+            table[0xC0 - 0xA0 + i] = add_cedilla(&FONT_8X16[base as usize]);
+            table[0xE0 - 0xA0 + i] = add_cedilla(&FONT_8X16[lower as usize]);
+        } else if base != 0 {
+            table[0xC0 - 0xA0 + i] = compose(&FONT_8X16[base as usize], &accent, true, false);
+            table[0xE0 - 0xA0 + i] = compose(&FONT_8X16[lower as usize], &accent, false, lower == b'i');
+        }
+        i += 1;
+    }
+    table[0xFF - 0xA0] = compose(&FONT_8X16[b'y' as usize], &DIAERESIS, false, false);
+
+    let mut i = 0;
+    while i < LATIN1_DRAWN_LOWER.len() {
+        table[LATIN1_DRAWN_LOWER[i].0 as usize - 0xA0] = LATIN1_DRAWN_LOWER[i].1;
+        i += 1;
+    }
+
+    table
+}
+
+/// U+00A0-U+00FF
+static LATIN1: [[u8; 16]; 96] = build_latin1();
+
+// Box drawing line weights; 1 is light, 0 no arm
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+/// Pack the weights of the four arms of a box drawing character
+const fn arms(left: u8, right: u8, up: u8, down: u8) -> u8 {
+    left | (right << 2) | (up << 4) | (down << 6)
+}
+
+/// Arms of U+2500-U+257F in code point order. Dashed lines are drawn solid
+/// here and broken up afterwards; the diagonals (U+2571-2573) are zero.
+const BOX_ARMS: [u8; 128] = [
+    // 2500
+    arms(1, 1, 0, 0), arms(2, 2, 0, 0), arms(0, 0, 1, 1), arms(0, 0, 2, 2),
+    arms(1, 1, 0, 0), arms(2, 2, 0, 0), arms(0, 0, 1, 1), arms(0, 0, 2, 2),
+    arms(1, 1, 0, 0), arms(2, 2, 0, 0), arms(0, 0, 1, 1), arms(0, 0, 2, 2),
+    arms(0, 1, 0, 1), arms(0, 2, 0, 1), arms(0, 1, 0, 2), arms(0, 2, 0, 2),
+    // 2510
+    arms(1, 0, 0, 1), arms(2, 0, 0, 1), arms(1, 0, 0, 2), arms(2, 0, 0, 2),
+    arms(0, 1, 1, 0), arms(0, 2, 1, 0), arms(0, 1, 2, 0), arms(0, 2, 2, 0),
+    arms(1, 0, 1, 0), arms(2, 0, 1, 0), arms(1, 0, 2, 0), arms(2, 0, 2, 0),
+    arms(0, 1, 1, 1), arms(0, 2, 1, 1), arms(0, 1, 2, 1), arms(0, 1, 1, 2),
+    // 2520
+    arms(0, 1, 2, 2), arms(0, 2, 2, 1), arms(0, 2, 1, 2), arms(0, 2, 2, 2),
+    arms(1, 0, 1, 1), arms(2, 0, 1, 1), arms(1, 0, 2, 1), arms(1, 0, 1, 2),
+    arms(1, 0, 2, 2), arms(2, 0, 2, 1), arms(2, 0, 1, 2), arms(2, 0, 2, 2),
+    arms(1, 1, 0, 1), arms(2, 1, 0, 1), arms(1, 2, 0, 1), arms(2, 2, 0, 1),
+    // 2530
+    arms(1, 1, 0, 2), arms(2, 1, 0, 2), arms(1, 2, 0, 2), arms(2, 2, 0, 2),
+    arms(1, 1, 1, 0), arms(2, 1, 1, 0), arms(1, 2, 1, 0), arms(2, 2, 1, 0),
+    arms(1, 1, 2, 0), arms(2, 1, 2, 0), arms(1, 2, 2, 0), arms(2, 2, 2, 0),
+    arms(1, 1, 1, 1), arms(2, 1, 1, 1), arms(1, 2, 1, 1), arms(2, 2, 1, 1),
+    // 2540
+    arms(1, 1, 2, 1), arms(1, 1, 1, 2), arms(1, 1, 2, 2), arms(2, 1, 2, 1),
+    arms(1, 2, 2, 1), arms(2, 1, 1, 2), arms(1, 2, 1, 2), arms(2, 2, 2, 1),
+    arms(2, 2, 1, 2), arms(2, 1, 2, 2), arms(1, 2, 2, 2), arms(2, 2, 2, 2),
+    arms(1, 1, 0, 0), arms(2, 2, 0, 0), arms(0, 0, 1, 1), arms(0, 0, 2, 2),
+    // 2550
+    arms(3, 3, 0, 0), arms(0, 0, 3, 3), arms(0, 3, 0, 1), arms(0, 1, 0, 3),
+    arms(0, 3, 0, 3), arms(3, 0, 0, 1), arms(1, 0, 0, 3), arms(3, 0, 0, 3),
+    arms(0, 3, 1, 0), arms(0, 1, 3, 0), arms(0, 3, 3, 0), arms(3, 0, 1, 0),
+    arms(1, 0, 3, 0), arms(3, 0, 3, 0), arms(0, 3, 1, 1), arms(0, 1, 3, 3),
+    // 2560
+    arms(0, 3, 3, 3), arms(3, 0, 1, 1), arms(1, 0, 3, 3), arms(3, 0, 3, 3),
+    arms(3, 3, 0, 1), arms(1, 1, 0, 3), arms(3, 3, 0, 3), arms(3, 3, 1, 0),
+    arms(1, 1, 3, 0), arms(3, 3, 3, 0), arms(3, 3, 1, 1), arms(1, 1, 3, 3),
+    arms(3, 3, 3, 3), arms(0, 1, 0, 1), arms(1, 0, 0, 1), arms(1, 0, 1, 0),
+    // 2570
+    arms(0, 1, 1, 0), 0, 0, 0,
+    arms(1, 0, 0, 0), arms(0, 0, 1, 0), arms(0, 1, 0, 0), arms(0, 0, 0, 1),
+    arms(2, 0, 0, 0), arms(0, 0, 2, 0), arms(0, 2, 0, 0), arms(0, 0, 0, 2),
+    arms(1, 2, 0, 0), arms(0, 0, 1, 2), arms(2, 1, 0, 0), arms(0, 0, 2, 1),
+];
+
+/// Rows a horizontal stroke of the given weight occupies
+const fn stroke_rows(weight: u8) -> (usize, usize) {
+    match weight {
+        HEAVY => (7, 8),
+        DOUBLE => (6, 9),
+        _ => (7, 7),
+    }
+}
+
+/// Columns a vertical stroke of the given weight occupies
+const fn stroke_cols(weight: u8) -> (usize, usize) {
+    match weight {
+        HEAVY => (3, 4),
+        DOUBLE => (2, 5),
+        _ => (3, 3),
+    }
+}
+
+const fn hline(g: &mut [u8; 16], row: usize, from: usize, to: usize) {
+    let mut x = from;
+    while x <= to {
+        g[row] |= 0x80 >> x;
+        x += 1;
+    }
+}
+
+const fn vline(g: &mut [u8; 16], col: usize, from: usize, to: usize) {
+    let mut y = from;
+    while y <= to {
+        g[y] |= 0x80 >> col;
+        y += 1;
+    }
+}
+
+/// Draw a horizontal arm: a double stroke is two lines, others are a band
+const fn h_arm(g: &mut [u8; 16], weight: u8, from: usize, to: usize) {
+    let (a, b) = stroke_rows(weight);
+    if weight == DOUBLE {
+        hline(g, a, from, to);
+        hline(g, b, from, to);
+    } else {
+        let mut row = a;
+        while row <= b {
+            hline(g, row, from, to);
+            row += 1;
+        }
+    }
+}
+
+const fn v_arm(g: &mut [u8; 16], weight: u8, from: usize, to: usize) {
+    let (a, b) = stroke_cols(weight);
+    if weight == DOUBLE {
+        vline(g, a, from, to);
+        vline(g, b, from, to);
+    } else {
+        let mut col = a;
+        while col <= b {
+            vline(g, col, from, to);
+            col += 1;
+        }
+    }
+}
+
+const fn max(a: u8, b: u8) -> u8 {
+    if a > b { a } else { b }
+}
+
+const fn box_glyph(code: u32) -> [u8; 16] {
+    let mut g = [0u8; 16];
+
+    if code >= 0x2571 && code <= 0x2573 {
+        let mut y = 0;
+        while y < 16 {
+            if code != 0x2572 {
+                g[y] |= 0x80 >> (7 - y / 2);
+            }
+            if code != 0x2571 {
+                g[y] |= 0x80 >> (y / 2);
+            }
+            y += 1;
+        }
+        return g;
+    }
+
+    let packed = BOX_ARMS[(code - 0x2500) as usize];
+    let (l, r, u, d) = (packed & 3, (packed >> 2) & 3, (packed >> 4) & 3, (packed >> 6) & 3);
+    let vdouble = u == DOUBLE || d == DOUBLE;
+    let hdouble = l == DOUBLE || r == DOUBLE;
+    let (vmin, vmax) = stroke_cols(max(u, d));
+    let (hmin, hmax) = stroke_rows(max(l, r));
+
+    // A single stroke meeting a double one stops at its near line if the
+    // double passes straight through, otherwise it runs to the far line
+    if l != 0 {
+        let end = if l != DOUBLE && vdouble { if u != 0 && d != 0 { 2 } else { 5 } } else { vmax };
+        h_arm(&mut g, l, 0, end);
+    }
+    if r != 0 {
+        let start = if r != DOUBLE && vdouble { if u != 0 && d != 0 { 5 } else { 2 } } else { vmin };
+        h_arm(&mut g, r, start, 7);
+    }
+    if u != 0 {
+        let end = if u != DOUBLE && hdouble { if l != 0 && r != 0 { 6 } else { 9 } } else { hmax };
+        v_arm(&mut g, u, 0, end);
+    }
+    if d != 0 {
+        let start = if d != DOUBLE && hdouble { if l != 0 && r != 0 { 9 } else { 6 } } else { hmin };
+        v_arm(&mut g, d, start, 15);
+    }
+
+    // Double meets double: the lines cross in a 4x4 box around the center.
+    // Open each side of the box that an arm leaves through.
+    if vdouble && hdouble {
+        if u != 0 {
+            g[6] &= !0x18;
+        }
+        if d != 0 {
+            g[9] &= !0x18;
+        }
+        if l != 0 {
+            g[7] &= !0x20;
+            g[8] &= !0x20;
+        }
+        if r != 0 {
+            g[7] &= !0x04;
+            g[8] &= !0x04;
+        }
+    }
+
+    // Dashed lines
+    let col_gaps: u8 = match code {
+        0x2504 | 0x2505 => 0x24,
+        0x2508 | 0x2509 => 0x55,
+        0x254C | 0x254D => 0x11,
+        _ => 0,
+    };
+    let row_gaps: u16 = match code {
+        0x2506 | 0x2507 => 0x0C30,
+        0x250A | 0x250B => 0x8888,
+        0x254E | 0x254F => 0xC0C0,
+        _ => 0,
+    };
+    let mut y = 0;
+    while y < 16 {
+        g[y] &= !col_gaps;
+        if row_gaps & (1 << y) != 0 {
+            g[y] = 0;
+        }
+        y += 1;
+    }
+
+    g
+}
+
+/// U+2580-U+259F
+const fn block_glyph(code: u32) -> [u8; 16] {
+    let mut g = [0u8; 16];
+    let n = code - 0x2580;
+    let mut y = 0;
+    while y < 16 {
+        let (top, left) = (y < 8, 0xF0u8);
+        g[y] = match n {
+            0x00 => if top { 0xFF } else { 0 },
+            0x01..=0x07 => if y >= 16 - 2 * n as usize { 0xFF } else { 0 },
+            0x08 => 0xFF,
+            0x09..=0x0F => 0xFFu8 << (n - 0x08),
+            0x10 => !left,
+            0x11 => if y % 2 == 0 { 0x22 } else { 0x88 },
+            0x12 => if y % 2 == 0 { 0x55 } else { 0xAA },
+            0x13 => if y % 2 == 0 { 0xDD } else { 0x77 },
+            0x14 => if y < 2 { 0xFF } else { 0 },
+            0x15 => 0x01,
+            // Quadrants
+            0x16 => if top { 0 } else { left },
+            0x17 => if top { 0 } else { !left },
+            0x18 => if top { left } else { 0 },
+            0x19 => if top { left } else { 0xFF },
+            0x1A => if top { left } else { !left },
+            0x1B => if top { 0xFF } else { left },
+            0x1C => if top { 0xFF } else { !left },
+            0x1D => if top { !left } else { 0 },
+            0x1E => if top { !left } else { left },
+            _ => if top { !left } else { 0xFF },
+        };
+        y += 1;
+    }
+    g
+}
+
+const fn build_box_drawing() -> [[u8; 16]; 160] {
+    let mut table = [[0u8; 16]; 160];
+    let mut i = 0;
+    while i < 160 {
+        let code = 0x2500 + i as u32;
+        table[i] = if code < 0x2580 { box_glyph(code) } else { block_glyph(code) };
+        i += 1;
+    }
+    table
+}
+
+/// U+2500-U+259F
+static BOX_DRAWING: [[u8; 16]; 160] = build_box_drawing();
+
+/// U+FFFD: an inverted question mark block
+static REPLACEMENT: [u8; 16] = {
+    let q = FONT_8X16[b'?' as usize];
+    let mut g = [0u8; 16];
+    let mut row = 1;
+    while row <= 12 {
+        g[row] = !q[row];
+        row += 1;
+    }
+    g
+};
+
+// ============================================================================
+// PSF2 fonts
+// ============================================================================
+
+#[cfg(feature = "alloc")]
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+#[cfg(feature = "alloc")]
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+#[cfg(feature = "alloc")]
+const PSF2_SEPARATOR: u8 = 0xFF;
+#[cfg(feature = "alloc")]
+const PSF2_START_SEQ: u8 = 0xFE;
+
+/// Largest glyph cell accepted from a file
+pub const MAX_GLYPH_SIZE: u32 = 64;
+
+/// A font parsed from a PSF2 file
+#[cfg(feature = "alloc")]
+pub struct Psf2Font {
+    data: Vec<u8>,
+    glyphs_offset: usize,
+    glyph_count: usize,
+    glyph_size: usize,
+    width: u32,
+    height: u32,
+    /// (code point, glyph index), sorted by code point; empty when the file
+    /// has no Unicode table and glyph indices are code points
+    unicode: Vec<(u32, u32)>,
+}
+
+#[cfg(feature = "alloc")]
+impl Psf2Font {
+    /// Parse a PSF2 file
+    pub fn parse(data: Vec<u8>) -> Result<Self, FontError> {
+        if data.len() < 32 {
+            return Err(FontError::Truncated);
+        }
+        if data[0..4] != PSF2_MAGIC {
+            return Err(FontError::BadMagic);
+        }
+        let field = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let header_size = field(8) as usize;
+        let flags = field(12);
+        let glyph_count = field(16) as usize;
+        let glyph_size = field(20) as usize;
+        let height = field(24);
+        let width = field(28);
+
+        if width == 0 || height == 0 || width > MAX_GLYPH_SIZE || height > MAX_GLYPH_SIZE
+            || glyph_count == 0 || glyph_size < (width as usize).div_ceil(8) * height as usize
+        {
+            return Err(FontError::Unsupported);
+        }
+        let glyphs_end = glyph_count
+            .checked_mul(glyph_size)
+            .and_then(|n| n.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        if glyphs_end > data.len() {
+            return Err(FontError::Truncated);
+        }
+
+        let mut unicode = Vec::new();
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            // One entry per glyph: UTF-8 code points, then optional 0xFE
+            // prefixed sequences (combining forms, skipped), then 0xFF
+            let mut pos = glyphs_end;
+            let mut glyph = 0u32;
+            let mut in_sequence = false;
+            while pos < data.len() && (glyph as usize) < glyph_count {
+                let b = data[pos];
+                if b == PSF2_SEPARATOR {
+                    glyph += 1;
+                    in_sequence = false;
+                    pos += 1;
+                } else if b == PSF2_START_SEQ {
+                    in_sequence = true;
+                    pos += 1;
+                } else {
+                    let len = utf8_len(b).max(1);
+                    let end = (pos + len).min(data.len());
+                    if !in_sequence {
+                        if let Some(ch) = core::str::from_utf8(&data[pos..end]).ok().and_then(|s| s.chars().next()) {
+                            unicode.push((ch as u32, glyph));
+                        }
+                    }
+                    pos = end;
+                }
+            }
+            // Keep the first glyph listed for each code point
+            unicode.sort_by_key(|&(cp, _)| cp);
+            unicode.dedup_by_key(|&mut (cp, _)| cp);
+        }
+
+        Ok(Psf2Font {
+            data,
+            glyphs_offset: header_size,
+            glyph_count,
+            glyph_size,
+            width,
+            height,
+            unicode,
+        })
+    }
+
+    /// Number of glyphs in the file
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    fn glyph_index(&self, ch: char) -> Option<usize> {
+        let cp = ch as u32;
+        if self.unicode.is_empty() {
+            return Some(cp as usize).filter(|&i| i < self.glyph_count);
+        }
+        self.unicode
+            .binary_search_by_key(&cp, |&(c, _)| c)
+            .ok()
+            .map(|i| self.unicode[i].1 as usize)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Font for Psf2Font {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn glyph(&self, ch: char) -> Option<Glyph<'_>> {
+        let index = self.glyph_index(ch)?;
+        let start = self.glyphs_offset + index * self.glyph_size;
+        Some(Glyph {
+            width: self.width,
+            height: self.height,
+            data: &self.data[start..start + self.glyph_size],
+        })
+    }
+}
+
+/// Length of a UTF-8 sequence from its lead byte (0 for a continuation or
+/// invalid byte)
+pub const fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 0,
+    }
+}
@@ -264,6 +264,19 @@ impl Grid {
         self.default_bg = bg;
     }
 
+    /// Recolor every cell, e.g. for a new palette
+    pub fn map_colors(&mut self, fg: impl Fn(Color) -> Color, bg: impl Fn(Color) -> Color) {
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.fg = fg(cell.fg);
+                cell.bg = bg(cell.bg);
+            }
+        }
+        self.default_fg = fg(self.default_fg);
+        self.default_bg = bg(self.default_bg);
+        self.full_redraw = true;
+    }
+
     /// Get default foreground color
     pub fn default_fg(&self) -> Color {
         self.default_fg
@@ -1,9 +1,12 @@
 //! Keyboard input handling
 //!
-//! Converts raw PS/2 scancodes to key events with modifier tracking.
+//! Converts raw PS/2 scancodes to key events with modifier tracking, and
+//! key events to characters through the US layout or a loaded [`Keymap`].
 
 use bitflags::bitflags;
 
+use crate::keymap::{Keymap, Sym};
+
 bitflags! {
     /// Keyboard modifier flags
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -13,6 +16,8 @@ bitflags! {
         const ALT     = 0b0000_0100;
         const CAPSLOCK = 0b0000_1000;
         const NUMLOCK  = 0b0001_0000;
+        /// Right Alt, which also sets ALT
+        const ALTGR    = 0b0010_0000;
     }
 }
 
@@ -61,6 +66,42 @@ pub struct KeyEvent {
     pub pressed: bool,
     /// Current modifier state
     pub modifiers: Modifiers,
+    /// Scancode without the release bit
+    pub scancode: u8,
+    /// Whether the scancode had the 0xE0 prefix
+    pub extended: bool,
+}
+
+/// Characters a key press typed: none, one, or two when a dead key didn't
+/// combine with what followed it
+#[derive(Debug, Clone, Copy)]
+pub struct Typed {
+    chars: [char; 2],
+    len: u8,
+    pos: u8,
+}
+
+impl Typed {
+    fn new(first: Option<char>, second: Option<char>) -> Self {
+        let mut typed = Typed { chars: ['\0'; 2], len: 0, pos: 0 };
+        for c in [first, second].into_iter().flatten() {
+            typed.chars[typed.len as usize] = c;
+            typed.len += 1;
+        }
+        typed
+    }
+}
+
+impl Iterator for Typed {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.pos == self.len {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.chars[self.pos as usize - 1])
+    }
 }
 
 /// Keyboard state machine for PS/2 scancode set 1
@@ -69,6 +110,10 @@ pub struct Keyboard {
     modifiers: Modifiers,
     /// Extended scancode flag (0xE0 prefix)
     extended: bool,
+    /// Layout in place of US, if one is loaded
+    keymap: Option<Keymap>,
+    /// Accent of a dead key waiting for the next character
+    dead: Option<char>,
 }
 
 impl Keyboard {
@@ -77,14 +122,23 @@ impl Keyboard {
         Self {
             modifiers: Modifiers::empty(),
             extended: false,
+            keymap: None,
+            dead: None,
         }
     }
 
+    /// Type through `keymap`, or the US layout for None
+    pub fn set_keymap(&mut self, keymap: Option<Keymap>) {
+        self.keymap = keymap;
+        self.dead = None;
+    }
+
     /// Reset keyboard state (clears modifiers and extended flag)
     /// Call this after returning from a child process to prevent state corruption
     pub fn reset(&mut self) {
         self.modifiers = Modifiers::empty();
         self.extended = false;
+        self.dead = None;
     }
 
     /// Process a raw PS/2 scancode, returning a key event if applicable
@@ -99,7 +153,8 @@ impl Keyboard {
         let pressed = scancode & 0x80 == 0;
         let code = scancode & 0x7F;
 
-        let key = if self.extended {
+        let extended = self.extended;
+        let key = if extended {
             self.extended = false;
             self.decode_extended(code)
         } else {
@@ -118,6 +173,8 @@ impl Keyboard {
             key,
             pressed,
             modifiers: self.modifiers,
+            scancode: code,
+            extended,
         })
     }
 
@@ -244,7 +301,8 @@ impl Keyboard {
         let modifier = match key {
             KeyCode::LeftShift | KeyCode::RightShift => Some(Modifiers::SHIFT),
             KeyCode::LeftCtrl | KeyCode::RightCtrl => Some(Modifiers::CTRL),
-            KeyCode::LeftAlt | KeyCode::RightAlt => Some(Modifiers::ALT),
+            KeyCode::LeftAlt => Some(Modifiers::ALT),
+            KeyCode::RightAlt => Some(Modifiers::ALT | Modifiers::ALTGR),
             KeyCode::CapsLock if pressed => {
                 // Toggle on press only
                 self.modifiers ^= Modifiers::CAPSLOCK;
@@ -348,6 +406,55 @@ impl Keyboard {
         }
     }
 
+    /// The characters a key event types, through the loaded layout and any
+    /// pending dead key. Without a layout this is [`Keyboard::to_char`].
+    pub fn type_chars(&mut self, event: &KeyEvent) -> Typed {
+        let Some(keymap) = &self.keymap else { return Typed::new(self.to_char(event), None) };
+        if !event.pressed {
+            return Typed::new(None, None);
+        }
+
+        let mods = event.modifiers;
+        let sym = if event.extended {
+            Sym::None
+        } else {
+            let shift = mods.contains(Modifiers::SHIFT);
+            let caps = mods.contains(Modifiers::CAPSLOCK);
+            keymap.lookup(event.scancode, shift, caps, mods.contains(Modifiers::ALTGR))
+        };
+        let ch = match sym {
+            // Ctrl+letter is the control code of the letter the layout puts there
+            Sym::Char(c) if mods.contains(Modifiers::CTRL) && !mods.contains(Modifiers::ALTGR) => {
+                let lower = c.to_ascii_lowercase();
+                lower.is_ascii_lowercase().then(|| (lower as u8 - b'a' + 1) as char)
+            }
+            Sym::Char(c) => Some(c),
+            Sym::Dead(accent) => {
+                return match self.dead.replace(accent) {
+                    // The same dead key twice types the accent
+                    Some(prev) if prev == accent => {
+                        self.dead = None;
+                        Typed::new(Some(accent), None)
+                    }
+                    prev => Typed::new(prev, None),
+                };
+            }
+            Sym::None => self.to_char(event),
+        };
+
+        match (self.dead, ch) {
+            (Some(accent), Some(c)) => {
+                self.dead = None;
+                match self.keymap.as_ref().and_then(|k| k.compose(accent, c)) {
+                    Some(composed) => Typed::new(Some(composed), None),
+                    None if c == ' ' => Typed::new(Some(accent), None),
+                    None => Typed::new(Some(accent), Some(c)),
+                }
+            }
+            _ => Typed::new(ch, None),
+        }
+    }
+
     /// Convert a key event to an escape sequence for terminal
     pub fn to_escape_sequence(&self, event: &KeyEvent) -> Option<&'static [u8]> {
         if !event.pressed {
@@ -0,0 +1,323 @@
+//! Keyboard layouts
+//!
+//! The keyboard decodes scancodes to US keys; a [`Keymap`] says what each
+//! key types instead, on three levels: plain, with Shift, and with AltGr
+//! (right Alt). Keys it leaves out keep their US meaning, so a layout only
+//! lists the keys that differ. A layout is text, usually a file under
+//! [`KEYMAP_DIR`]:
+//!
+//! ```text
+//! # German (QWERTZ)
+//! # scancode  plain  shift  altgr
+//! 0x15  z  Z
+//! 0x2C  y  Y
+//! 0x0C  ß  ?  \
+//! 0x0D  dead_acute  dead_grave
+//! compose  dead_acute  c  ć
+//! ```
+//!
+//! A symbol is a single character, `U+XXXX`, `space`, `-` to keep the US
+//! meaning, or a dead key: `dead_acute`, `dead_grave`, `dead_circumflex`,
+//! `dead_diaeresis`, `dead_tilde`, `dead_cedilla`. A dead key types nothing
+//! but combines with the next character, é from dead_acute then e; space
+//! or the same dead key again types the accent itself, and anything else
+//! types the accent followed by the character. The common Latin-1
+//! combinations are built in, and `compose` lines add more. Lines starting
+//! with `#` are comments.
+
+/// Where `loadkeys NAME` looks for NAME.kmap
+pub const KEYMAP_DIR: &str = "/etc/keymaps/";
+
+/// Keys a layout can remap: every set 1 scancode without a prefix
+pub const KEYS: usize = 128;
+
+/// Most `compose` lines a layout can add
+pub const MAX_COMPOSE: usize = 64;
+
+/// Shift levels
+const PLAIN: usize = 0;
+const SHIFT: usize = 1;
+const ALTGR: usize = 2;
+
+/// What a key types on one level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sym {
+    /// Not in the layout: the US meaning applies
+    None,
+    Char(char),
+    /// A dead key, by the spacing form of its accent
+    Dead(char),
+}
+
+/// Dead key names and the spacing accents that stand for them
+const DEAD_KEYS: [(&str, char); 6] = [
+    ("dead_acute", '´'),
+    ("dead_grave", '`'),
+    ("dead_circumflex", '^'),
+    ("dead_diaeresis", '¨'),
+    ("dead_tilde", '~'),
+    ("dead_cedilla", '¸'),
+];
+
+/// Built-in combinations: for each accent, base and result in turn
+const BUILTIN_COMPOSE: [(char, &str); 6] = [
+    ('´', "aáeéiíoóuúyýAÁEÉIÍOÓUÚYÝ"),
+    ('`', "aàeèiìoòuùAÀEÈIÌOÒUÙ"),
+    ('^', "aâeêiîoôuûAÂEÊIÎOÔUÛ"),
+    ('¨', "aäeëiïoöuüyÿAÄEËIÏOÖUÜ"),
+    ('~', "aãnñoõAÃNÑOÕ"),
+    ('¸', "cçCÇ"),
+];
+
+/// Why a layout didn't parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeymapError {
+    /// 1-based line number
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+/// A keyboard layout
+#[derive(Clone)]
+pub struct Keymap {
+    keys: [[Sym; 3]; KEYS],
+    compose: [(char, char, char); MAX_COMPOSE],
+    compose_len: usize,
+}
+
+impl Keymap {
+    /// A layout that changes nothing
+    pub const fn new() -> Self {
+        Self {
+            keys: [[Sym::None; 3]; KEYS],
+            compose: [('\0', '\0', '\0'); MAX_COMPOSE],
+            compose_len: 0,
+        }
+    }
+
+    /// Parse a layout in the format above
+    pub fn parse(text: &str) -> Result<Self, KeymapError> {
+        let mut map = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let err = |reason| KeymapError { line: i + 1, reason };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap_or("");
+
+            if first == "compose" {
+                let (Some(Sym::Dead(accent)), Some(Sym::Char(base)), Some(Sym::Char(result))) = (
+                    words.next().and_then(parse_sym),
+                    words.next().and_then(parse_sym),
+                    words.next().and_then(parse_sym),
+                ) else {
+                    return Err(err("expected compose DEAD BASE RESULT"));
+                };
+                if map.compose_len == MAX_COMPOSE {
+                    return Err(err("too many compose lines"));
+                }
+                map.compose[map.compose_len] = (accent, base, result);
+                map.compose_len += 1;
+                continue;
+            }
+
+            let code = match first.strip_prefix("0x").or_else(|| first.strip_prefix("0X")) {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => first.parse::<u8>(),
+            };
+            let code = match code {
+                Ok(code) if (code as usize) < KEYS => code as usize,
+                _ => return Err(err("bad scancode")),
+            };
+            for (level, word) in words.enumerate() {
+                if level > ALTGR {
+                    return Err(err("more than three levels"));
+                }
+                map.keys[code][level] = parse_sym(word).ok_or(err("bad symbol"))?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// What `scancode` types with the given modifiers. Caps Lock acts as
+    /// Shift on keys whose plain symbol is a letter.
+    pub fn lookup(&self, scancode: u8, shift: bool, caps: bool, altgr: bool) -> Sym {
+        let Some(levels) = self.keys.get(scancode as usize) else { return Sym::None };
+        if altgr {
+            return levels[ALTGR];
+        }
+        let letter = matches!(levels[PLAIN], Sym::Char(c) if c.is_alphabetic());
+        if shift ^ (caps && letter) { levels[SHIFT] } else { levels[PLAIN] }
+    }
+
+    /// The character `accent` and `base` make, if they combine
+    pub fn compose(&self, accent: char, base: char) -> Option<char> {
+        let extra = self.compose[..self.compose_len].iter().find(|c| c.0 == accent && c.1 == base);
+        if let Some(&(_, _, result)) = extra {
+            return Some(result);
+        }
+        let (_, pairs) = BUILTIN_COMPOSE.iter().find(|c| c.0 == accent)?;
+        let mut chars = pairs.chars();
+        while let (Some(b), Some(result)) = (chars.next(), chars.next()) {
+            if b == base {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_sym(word: &str) -> Option<Sym> {
+    if let Some(&(_, accent)) = DEAD_KEYS.iter().find(|d| d.0 == word) {
+        return Some(Sym::Dead(accent));
+    }
+    match word {
+        "-" => return Some(Sym::None),
+        "space" => return Some(Sym::Char(' ')),
+        _ => {}
+    }
+    if let Some(hex) = word.strip_prefix("U+") {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(Sym::Char);
+    }
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Sym::Char(c)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::Keyboard;
+
+    const GERMAN: &str = "# German (QWERTZ)\n\
+        # scancode  plain  shift  altgr\n\
+        0x15  z  Z\n\
+        0x2C  y  Y\n\
+        0x0C  ß  ?  \\\n\
+        0x0D  dead_acute  dead_grave\n\
+        \n\
+        compose  dead_acute  c  ć\n";
+
+    /// Press and release a key, returning up to two characters it typed
+    fn press(kb: &mut Keyboard, scancode: u8) -> (Option<char>, Option<char>) {
+        let event = kb.process_scancode(scancode).unwrap();
+        let mut typed = kb.type_chars(&event);
+        let result = (typed.next(), typed.next());
+        kb.process_scancode(scancode | 0x80);
+        result
+    }
+
+    fn german_keyboard() -> Keyboard {
+        let mut kb = Keyboard::new();
+        kb.set_keymap(Some(Keymap::parse(GERMAN).unwrap()));
+        kb
+    }
+
+    #[test]
+    fn test_parse_levels() {
+        let map = Keymap::parse(GERMAN).unwrap();
+        assert_eq!(map.lookup(0x15, false, false, false), Sym::Char('z'));
+        assert_eq!(map.lookup(0x15, true, false, false), Sym::Char('Z'));
+        // Caps Lock shifts letters only
+        assert_eq!(map.lookup(0x15, false, true, false), Sym::Char('Z'));
+        let digits = Keymap::parse("0x02 1 !").unwrap();
+        assert_eq!(digits.lookup(0x02, false, true, false), Sym::Char('1'));
+        assert_eq!(map.lookup(0x0C, false, false, true), Sym::Char('\\'));
+        // Keys and levels the layout leaves out keep their US meaning
+        assert_eq!(map.lookup(0x15, false, false, true), Sym::None);
+        assert_eq!(map.lookup(0x10, false, false, false), Sym::None);
+    }
+
+    #[test]
+    fn test_parse_symbols() {
+        let map = Keymap::parse("16 U+00E9 -\n0x39 space\n0X1E a A").unwrap();
+        assert_eq!(map.lookup(16, false, false, false), Sym::Char('é'));
+        assert_eq!(map.lookup(16, true, false, false), Sym::None);
+        assert_eq!(map.lookup(0x39, false, false, false), Sym::Char(' '));
+        assert_eq!(map.lookup(0x1E, true, false, false), Sym::Char('A'));
+    }
+
+    #[test]
+    fn test_dead_keys() {
+        let map = Keymap::parse(GERMAN).unwrap();
+        assert_eq!(map.lookup(0x0D, false, false, false), Sym::Dead('´'));
+        assert_eq!(map.lookup(0x0D, true, false, false), Sym::Dead('`'));
+
+        let mut kb = german_keyboard();
+        // dead_acute then e
+        assert_eq!(press(&mut kb, 0x0D), (None, None));
+        assert_eq!(press(&mut kb, 0x12), (Some('é'), None));
+        // then space: the accent itself
+        press(&mut kb, 0x0D);
+        assert_eq!(press(&mut kb, 0x39), (Some('´'), None));
+        // twice: the accent itself
+        press(&mut kb, 0x0D);
+        assert_eq!(press(&mut kb, 0x0D), (Some('´'), None));
+        // then something it doesn't combine with: both
+        press(&mut kb, 0x0D);
+        assert_eq!(press(&mut kb, 0x2D), (Some('´'), Some('x')));
+        // a different dead key types the first accent and waits again
+        press(&mut kb, 0x0D);
+        kb.process_scancode(0x2A);
+        assert_eq!(press(&mut kb, 0x0D), (Some('´'), None));
+        kb.process_scancode(0xAA);
+        assert_eq!(press(&mut kb, 0x12), (Some('è'), None));
+    }
+
+    #[test]
+    fn test_compose() {
+        let map = Keymap::parse(GERMAN).unwrap();
+        assert_eq!(map.compose('´', 'c'), Some('ć'));
+        assert_eq!(map.compose('´', 'E'), Some('É'));
+        assert_eq!(map.compose('¸', 'c'), Some('ç'));
+        assert_eq!(map.compose('´', 'x'), None);
+        assert_eq!(map.compose('x', 'a'), None);
+        assert_eq!(Keymap::new().compose('´', 'c'), None);
+
+        // Layout lines win over the built-in combinations
+        let map = Keymap::parse("compose dead_acute e ė").unwrap();
+        assert_eq!(map.compose('´', 'e'), Some('ė'));
+
+        let mut kb = german_keyboard();
+        press(&mut kb, 0x0D);
+        assert_eq!(press(&mut kb, 0x2E), (Some('ć'), None));
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        let err = |text, line, reason| {
+            assert_eq!(Keymap::parse(text).err(), Some(KeymapError { line, reason }), "{text:?}");
+        };
+        err("0x15 z\nzz y", 2, "bad scancode");
+        err("0x80 a", 1, "bad scancode");
+        err("0xZZ a", 1, "bad scancode");
+        err("-1 a", 1, "bad scancode");
+        err("0x15 ab", 1, "bad symbol");
+        err("0x15 U+D800", 1, "bad symbol");
+        err("0x15 dead_bogus", 1, "bad symbol");
+        err("0x15 a A b c", 1, "more than three levels");
+        err("compose dead_acute c", 1, "expected compose DEAD BASE RESULT");
+        err("compose a c ć", 1, "expected compose DEAD BASE RESULT");
+        err("compose dead_acute dead_grave ć", 1, "expected compose DEAD BASE RESULT");
+
+        let mut text = [0u8; 24 * (MAX_COMPOSE + 1)];
+        let mut len = 0;
+        for _ in 0..=MAX_COMPOSE {
+            let line = b"compose dead_tilde x y\n";
+            text[len..len + line.len()].copy_from_slice(line);
+            len += line.len();
+        }
+        let text = core::str::from_utf8(&text[..len]).unwrap();
+        err(text, MAX_COMPOSE + 1, "too many compose lines");
+    }
+}
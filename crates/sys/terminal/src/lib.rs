@@ -15,7 +15,7 @@
 //! │  - ANSI parser, Grid, Cursor state                          │
 //! ├─────────────────────────────────────────────────────────────┤
 //! │  Renderer                                                    │
-//! │  - Font rendering, dirty tracking                           │
+//! │  - Font rendering (builtin / PSF2), dirty tracking          │
 //! ├─────────────────────────────────────────────────────────────┤
 //! │  Framebuffer (trait)                                         │
 //! │  - Resolution, double buffering, mode switching             │
@@ -33,18 +33,22 @@ pub mod framebuffer;
 pub mod grid;
 pub mod state;
 pub mod parser;
+pub mod font;
 pub mod renderer;
 pub mod keyboard;
+pub mod keymap;
 pub mod terminal;
 pub mod console;
 
-pub use color::Color;
+pub use color::{Color, Palette};
 pub use cell::{Cell, CellFlags};
 pub use framebuffer::{Framebuffer, PixelFormat};
 pub use grid::Grid;
 pub use state::TerminalState;
 pub use parser::{Parser, Event};
+pub use font::{Font, BuiltinFont};
 pub use renderer::Renderer;
 pub use keyboard::{KeyEvent, KeyCode, Modifiers};
+pub use keymap::Keymap;
 pub use terminal::Terminal;
 pub use console::ConsoleManager;
@@ -67,6 +67,14 @@ pub struct Parser {
     charset_slot: u8,
     osc_cmd: u8,
     osc_len: usize,
+    /// Code point bits collected so far from a UTF-8 sequence
+    utf8_acc: u32,
+    /// Continuation bytes still expected
+    utf8_remaining: u8,
+    /// Lead byte of the sequence in progress (for the Latin-1 fallback)
+    utf8_lead: u8,
+    /// Second event produced by a single byte
+    pending: Option<Event>,
 }
 
 impl Parser {
@@ -82,6 +90,10 @@ impl Parser {
             charset_slot: 0,
             osc_cmd: 0,
             osc_len: 0,
+            utf8_acc: 0,
+            utf8_remaining: 0,
+            utf8_lead: 0,
+            pending: None,
         }
     }
 
@@ -93,6 +105,8 @@ impl Parser {
         self.current_param = 0;
         self.intermediate = 0;
         self.private = false;
+        self.utf8_remaining = 0;
+        self.pending = None;
     }
 
     /// Process a single byte, returning an event if one is produced
@@ -108,7 +122,35 @@ impl Parser {
         }
     }
 
+    /// Take the extra event left by the last `advance`, if any
+    ///
+    /// A byte that interrupts a UTF-8 sequence produces two events: the
+    /// abandoned lead byte and the byte's own.
+    pub fn take_pending(&mut self) -> Option<Event> {
+        self.pending.take()
+    }
+
     fn ground(&mut self, byte: u8) -> Option<Event> {
+        if self.utf8_remaining > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.utf8_acc = (self.utf8_acc << 6) | (byte & 0x3F) as u32;
+                self.utf8_remaining -= 1;
+                if self.utf8_remaining > 0 {
+                    return None;
+                }
+                // Overlong forms and surrogates fail here and become U+FFFD
+                let ch = char::from_u32(self.utf8_acc)
+                    .filter(|&c| c as u32 >= min_code_point(self.utf8_lead))
+                    .unwrap_or(crate::font::REPLACEMENT_CHAR);
+                return Some(Event::Print(ch));
+            }
+            // Sequence cut short: the lead byte was probably Latin-1 text.
+            // Print it as such; this byte's own event waits in `pending`.
+            self.utf8_remaining = 0;
+            self.pending = self.ground(byte);
+            return Some(Event::Print(self.utf8_lead as char));
+        }
+
         match byte {
             // C0 control characters
             0x00..=0x1F => {
@@ -142,13 +184,16 @@ impl Parser {
                     _ => Some(Event::Execute(byte)),
                 }
             }
-            // UTF-8 continuation or start bytes - treat as printable for now
-            // TODO: Full UTF-8 decoding
-            0xA0..=0xFF => {
-                // For now, just show the byte as a character
-                // This works for Latin-1 supplement
-                Some(Event::Print(byte as char))
+            // UTF-8 lead bytes start a multi-byte sequence
+            0xC2..=0xF4 => {
+                let len = crate::font::utf8_len(byte);
+                self.utf8_lead = byte;
+                self.utf8_acc = (byte & (0x7F >> len)) as u32;
+                self.utf8_remaining = len as u8 - 1;
+                None
             }
+            // Stray continuation and invalid bytes: treat as Latin-1
+            0xA0..=0xC1 | 0xF5..=0xFF => Some(Event::Print(byte as char)),
         }
     }
 
@@ -391,6 +436,15 @@ impl Default for Parser {
     }
 }
 
+/// Smallest code point a sequence with this lead byte may encode
+fn min_code_point(lead: u8) -> u32 {
+    match lead {
+        0xE0..=0xEF => 0x800,
+        0xF0..=0xF4 => 0x10000,
+        _ => 0x80,
+    }
+}
+
 // Helper to get a CSI parameter with default value
 pub fn csi_param(params: &[i32; MAX_PARAMS], count: usize, index: usize, default: i32) -> i32 {
     if index < count && params[index] != 0 {
@@ -1,16 +1,17 @@
 //! Terminal renderer
 //!
 //! Renders the terminal grid to a framebuffer with:
-//! - Bitmap font rendering (8x16)
+//! - Bitmap font rendering through [`Font`], with integer scaling
 //! - Dirty cell tracking for efficient updates
 //! - Cursor rendering
 
 use crate::cell::{Cell, CellFlags};
 use crate::color::Color;
+use crate::font::{Font, BUILTIN_FONT, REPLACEMENT_CHAR};
 use crate::framebuffer::Framebuffer;
 use crate::grid::Grid;
 
-/// Font dimensions
+/// Built-in font dimensions
 pub const FONT_WIDTH: u32 = 8;
 pub const FONT_HEIGHT: u32 = 16;
 
@@ -24,6 +25,10 @@ pub struct Renderer {
     cursor_blink_rate: u32,
     /// Last rendered cursor position (col, row) - for clearing old cursor
     last_cursor_pos: Option<(usize, usize)>,
+    /// Font used for glyphs
+    font: &'static dyn Font,
+    /// Integer scale applied to the font (1 = native size)
+    scale: u32,
 }
 
 impl Renderer {
@@ -34,9 +39,28 @@ impl Renderer {
             cursor_blink_count: 0,
             cursor_blink_rate: 30,
             last_cursor_pos: None,
+            font: &BUILTIN_FONT,
+            scale: 1,
         }
     }
 
+    /// Switch font and scale; the caller resizes the grid to match
+    pub fn set_font(&mut self, font: &'static dyn Font, scale: u32) {
+        self.font = font;
+        self.scale = scale.max(1);
+        self.last_cursor_pos = None;
+    }
+
+    /// Cell width in pixels
+    pub fn cell_width(&self) -> u32 {
+        self.font.width() * self.scale
+    }
+
+    /// Cell height in pixels
+    pub fn cell_height(&self) -> u32 {
+        self.font.height() * self.scale
+    }
+
     /// Render the entire grid to the framebuffer
     pub fn render_full<F: Framebuffer>(&mut self, fb: &mut F, grid: &Grid, cursor: Option<(usize, usize)>) {
         // Clear with background color
@@ -106,15 +130,16 @@ impl Renderer {
 
     /// Render a single cell
     pub fn render_cell<F: Framebuffer>(&self, fb: &mut F, col: usize, row: usize, cell: &Cell) {
-        let x = col as u32 * FONT_WIDTH;
-        let y = row as u32 * FONT_HEIGHT;
+        let (cw, ch) = (self.cell_width(), self.cell_height());
+        let x = col as u32 * cw;
+        let y = row as u32 * ch;
 
         // Get effective colors
         let fg = cell.effective_fg();
         let bg = cell.effective_bg();
 
         // Fill background
-        fb.fill_rect(x, y, FONT_WIDTH, FONT_HEIGHT, bg);
+        fb.fill_rect(x, y, cw, ch, bg);
 
         // Draw character glyph
         if cell.ch != ' ' && !cell.is_wide_spacer() {
@@ -123,37 +148,38 @@ impl Renderer {
 
         // Draw underline if set
         if cell.flags.contains(CellFlags::UNDERLINE) {
-            fb.fill_rect(x, y + FONT_HEIGHT - 1, FONT_WIDTH, 1, fg);
+            fb.fill_rect(x, y + ch - self.scale, cw, self.scale, fg);
         }
 
         // Draw strikethrough if set
         if cell.flags.contains(CellFlags::STRIKETHROUGH) {
-            fb.fill_rect(x, y + FONT_HEIGHT / 2, FONT_WIDTH, 1, fg);
+            fb.fill_rect(x, y + ch / 2, cw, self.scale, fg);
         }
     }
 
     /// Draw a character glyph
     fn draw_glyph<F: Framebuffer>(&self, fb: &mut F, x: u32, y: u32, ch: char, fg: Color, flags: CellFlags) {
-        let glyph_idx = if (ch as u32) < 256 {
-            ch as usize
-        } else {
-            // Fallback for non-ASCII
-            b'?' as usize
+        let glyph = match self.font.glyph(ch)
+            .or_else(|| self.font.glyph(REPLACEMENT_CHAR))
+            .or_else(|| self.font.glyph('?'))
+        {
+            Some(glyph) => glyph,
+            None => return,
         };
+        let bold = flags.contains(CellFlags::BOLD);
+        let s = self.scale;
 
-        let glyph = &FONT_8X16[glyph_idx];
-
-        for (row, &bits) in glyph.iter().enumerate() {
-            let bits = if flags.contains(CellFlags::BOLD) {
+        for row in 0..glyph.height {
+            for col in 0..glyph.width {
                 // Bold: OR each pixel with its left neighbor
-                bits | (bits >> 1)
-            } else {
-                bits
-            };
-
-            for col in 0..8 {
-                if (bits >> (7 - col)) & 1 != 0 {
-                    fb.set_pixel(x + col, y + row as u32, fg);
+                let on = glyph.pixel(col, row) || (bold && col > 0 && glyph.pixel(col - 1, row));
+                if !on {
+                    continue;
+                }
+                if s == 1 {
+                    fb.set_pixel(x + col, y + row, fg);
+                } else {
+                    fb.fill_rect(x + col * s, y + row * s, s, s, fg);
                 }
             }
         }
@@ -161,8 +187,9 @@ impl Renderer {
 
     /// Render cursor at position
     fn render_cursor<F: Framebuffer>(&self, fb: &mut F, col: usize, row: usize, grid: &Grid) {
-        let x = col as u32 * FONT_WIDTH;
-        let y = row as u32 * FONT_HEIGHT;
+        let (cw, ch) = (self.cell_width(), self.cell_height());
+        let x = col as u32 * cw;
+        let y = row as u32 * ch;
 
         // Get cell at cursor position for color
         let fg = grid.get(col, row)
@@ -170,7 +197,7 @@ impl Renderer {
             .unwrap_or(Color::WHITE);
 
         // Draw block cursor (inverted)
-        fb.fill_rect(x, y + FONT_HEIGHT - 2, FONT_WIDTH, 2, fg);
+        fb.fill_rect(x, y + ch - 2 * self.scale, cw, 2 * self.scale, fg);
     }
 
     /// Toggle cursor visibility (call this at blink interval)
@@ -226,6 +226,16 @@ impl TerminalState {
         self.clamp_cursor();
     }
 
+    /// Recolor the current, default and saved colors, e.g. for a new palette
+    pub fn map_colors(&mut self, fg: impl Fn(Color) -> Color, bg: impl Fn(Color) -> Color) {
+        self.fg = fg(self.fg);
+        self.bg = bg(self.bg);
+        self.default_fg = fg(self.default_fg);
+        self.default_bg = bg(self.default_bg);
+        self.saved_fg = fg(self.saved_fg);
+        self.saved_bg = bg(self.saved_bg);
+    }
+
     // === Scroll Region ===
 
     pub fn set_scroll_region(&mut self, top: i32, bottom: i32) {
@@ -4,7 +4,7 @@
 //! and updates the screen buffer.
 
 use crate::cell::Cell;
-use crate::color::{Color, color_256, ANSI_COLORS};
+use crate::color::{Color, Palette};
 use crate::grid::Grid;
 use crate::parser::{Parser, Event, csi_param};
 use crate::state::TerminalState;
@@ -17,27 +17,63 @@ pub struct Terminal {
     pub state: TerminalState,
     /// ANSI parser
     parser: Parser,
+    /// A BEL was written and nobody has rung it yet
+    bell: bool,
+    /// Colors SGR codes pick from
+    palette: Palette,
 }
 
 impl Terminal {
     /// Create a new terminal with given dimensions
     pub fn new(cols: usize, rows: usize) -> Self {
-        let fg = Color::WHITE;
-        let bg = Color::BLACK;
+        let palette = Palette::DEFAULT;
+        let (fg, bg) = (palette.fg, palette.bg);
 
         Self {
             grid: Grid::new(cols, rows, fg, bg),
             state: TerminalState::new(cols, rows, fg, bg),
             parser: Parser::new(),
+            bell: false,
+            palette,
         }
     }
 
+    /// The colors in use
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Switch palettes. Text already on screen in a palette color, or the
+    /// default colors, changes to the new palette's; other colors stay.
+    pub fn set_palette(&mut self, palette: Palette) {
+        let old = core::mem::replace(&mut self.palette, palette);
+        // Default colors first, so black text doesn't become the background
+        let remap = |color: Color, old_default: Color, new_default: Color| {
+            if color == old_default {
+                return new_default;
+            }
+            match old.ansi.iter().position(|&c| c == color) {
+                Some(i) => palette.ansi[i],
+                None => color,
+            }
+        };
+        let fg = |c| remap(c, old.fg, palette.fg);
+        let bg = |c| remap(c, old.bg, palette.bg);
+        self.grid.map_colors(fg, bg);
+        self.state.map_colors(fg, bg);
+    }
+
     /// Resize the terminal
     pub fn resize(&mut self, cols: usize, rows: usize) {
         self.grid.resize(cols, rows);
         self.state.resize(cols, rows);
     }
 
+    /// Whether a BEL arrived since the last call
+    pub fn take_bell(&mut self) -> bool {
+        core::mem::take(&mut self.bell)
+    }
+
     /// Get current dimensions
     pub fn size(&self) -> (usize, usize) {
         (self.grid.cols(), self.grid.rows())
@@ -58,6 +94,9 @@ impl Terminal {
         if let Some(event) = self.parser.advance(byte) {
             self.handle_event(event);
         }
+        if let Some(event) = self.parser.take_pending() {
+            self.handle_event(event);
+        }
     }
 
     /// Process a slice of bytes
@@ -67,6 +106,21 @@ impl Terminal {
         }
     }
 
+    /// Process bytes like [`Terminal::process_bytes`], showing `observe`
+    /// each event once it has taken effect
+    pub fn process_bytes_observed(&mut self, bytes: &[u8], mut observe: impl FnMut(&Event, &Self)) {
+        for &byte in bytes {
+            if let Some(event) = self.parser.advance(byte) {
+                self.handle_event(event.clone());
+                observe(&event, self);
+            }
+            if let Some(event) = self.parser.take_pending() {
+                self.handle_event(event.clone());
+                observe(&event, self);
+            }
+        }
+    }
+
     /// Process a string
     pub fn write_str(&mut self, s: &str) {
         self.process_bytes(s.as_bytes());
@@ -115,7 +169,8 @@ impl Terminal {
     fn execute(&mut self, byte: u8) {
         match byte {
             0x07 => {
-                // BEL - bell (ignore or beep)
+                // BEL - bell, sounded by whoever calls take_bell
+                self.bell = true;
             }
             0x08 => {
                 // BS - backspace
@@ -387,7 +442,7 @@ impl Terminal {
 
                 // Standard foreground colors
                 30..=37 => {
-                    self.state.fg = ANSI_COLORS[(p - 30) as usize];
+                    self.state.fg = self.palette.ansi[(p - 30) as usize];
                 }
                 38 => {
                     // Extended foreground color
@@ -395,7 +450,7 @@ impl Terminal {
                         match params[i + 1] {
                             5 if i + 2 < count => {
                                 // 256-color mode
-                                self.state.fg = color_256(params[i + 2] as u8);
+                                self.state.fg = self.palette.color(params[i + 2] as u8);
                                 i += 2;
                             }
                             2 if i + 4 < count => {
@@ -418,7 +473,7 @@ impl Terminal {
 
                 // Standard background colors
                 40..=47 => {
-                    self.state.bg = ANSI_COLORS[(p - 40) as usize];
+                    self.state.bg = self.palette.ansi[(p - 40) as usize];
                 }
                 48 => {
                     // Extended background color
@@ -426,7 +481,7 @@ impl Terminal {
                         match params[i + 1] {
                             5 if i + 2 < count => {
                                 // 256-color mode
-                                self.state.bg = color_256(params[i + 2] as u8);
+                                self.state.bg = self.palette.color(params[i + 2] as u8);
                                 i += 2;
                             }
                             2 if i + 4 < count => {
@@ -449,11 +504,11 @@ impl Terminal {
 
                 // Bright foreground colors
                 90..=97 => {
-                    self.state.fg = ANSI_COLORS[(p - 90 + 8) as usize];
+                    self.state.fg = self.palette.ansi[(p - 90 + 8) as usize];
                 }
                 // Bright background colors
                 100..=107 => {
-                    self.state.bg = ANSI_COLORS[(p - 100 + 8) as usize];
+                    self.state.bg = self.palette.ansi[(p - 100 + 8) as usize];
                 }
 
                 _ => {}
@@ -5,7 +5,11 @@
 
 #![no_std]
 
+extern crate alloc;
+
+use alloc::format;
 use spin::Mutex;
+use watos_vfs::{FileType, VfsError, VfsResult};
 
 /// Maximum number of users in the system
 pub const MAX_USERS: usize = 32;
@@ -22,6 +26,9 @@ pub const MAX_PASSWORD_LEN: usize = 64;
 /// Maximum group name length
 pub const MAX_GROUPNAME_LEN: usize = 32;
 
+/// Maximum supplementary groups per user (matching VFS NGROUPS_MAX)
+pub const NGROUPS_MAX: usize = 16;
+
 /// User ID type
 pub type Uid = u32;
 
@@ -39,6 +46,9 @@ pub const UID_GUEST: Uid = 1000;
 pub const GID_ROOT: Gid = 0;
 pub const GID_USERS: Gid = 100;
 
+/// Group allowed to map the framebuffer (SYS_FB_MAP)
+pub const GID_VIDEO: Gid = 44;
+
 /// Maximum GECOS field length (full name/comment)
 pub const MAX_GECOS_LEN: usize = 128;
 
@@ -72,8 +82,8 @@ pub struct User {
     /// Login shell path
     pub shell: [u8; MAX_SHELL_LEN],
     pub shell_len: usize,
-    /// Supplementary groups (up to 8)
-    pub groups: [Gid; 8],
+    /// Supplementary groups (up to NGROUPS_MAX)
+    pub groups: [Gid; NGROUPS_MAX],
     pub group_count: usize,
     /// Is this entry active?
     pub active: bool,
@@ -94,7 +104,7 @@ impl User {
             home_len: 0,
             shell: [0; MAX_SHELL_LEN],
             shell_len: 0,
-            groups: [0; 8],
+            groups: [0; NGROUPS_MAX],
             group_count: 0,
             active: false,
         }
@@ -153,6 +163,35 @@ impl User {
         }
         false
     }
+
+    /// Supplementary groups as a slice
+    pub fn supplementary_groups(&self) -> &[Gid] {
+        &self.groups[..self.group_count]
+    }
+
+    /// Add a supplementary group
+    ///
+    /// Returns false if the user already has NGROUPS_MAX groups. Adding a
+    /// group the user is already in succeeds without a duplicate.
+    pub fn add_group(&mut self, gid: Gid) -> bool {
+        if self.supplementary_groups().contains(&gid) {
+            return true;
+        }
+        if self.group_count >= NGROUPS_MAX {
+            return false;
+        }
+        self.groups[self.group_count] = gid;
+        self.group_count += 1;
+        true
+    }
+
+    /// Remove a supplementary group, if present
+    pub fn remove_group(&mut self, gid: Gid) {
+        if let Some(i) = self.supplementary_groups().iter().position(|&g| g == gid) {
+            self.groups.copy_within(i + 1..self.group_count, i);
+            self.group_count -= 1;
+        }
+    }
 }
 
 /// Group entry in the group database
@@ -212,6 +251,9 @@ impl UserDatabase {
         // Create wheel group (admin users)
         self.add_group_with_id(10, b"wheel");
 
+        // Create video group (direct framebuffer access)
+        self.add_group_with_id(GID_VIDEO, b"video");
+
         // Create root user (password: "root")
         self.add_user_full(
             UID_ROOT,
@@ -233,6 +275,10 @@ impl UserDatabase {
             b"/home/guest",
             b"C:/apps/system/shell",
         );
+        // The console user gets the screen
+        if let Some(guest) = self.users.iter_mut().find(|u| u.active && u.uid == UID_GUEST) {
+            guest.add_group(GID_VIDEO);
+        }
     }
 
     /// Add a group with a specific GID
@@ -254,11 +300,6 @@ impl UserDatabase {
         false
     }
 
-    /// Add a user with a specific UID (minimal info)
-    fn add_user_with_id(&mut self, uid: Uid, username: &[u8], password: &[u8], gid: Gid, guid: Guid) -> bool {
-        self.add_user_full_internal(uid, username, password, gid, guid, b"", b"/", b"/apps/system/shell")
-    }
-
     /// Add a user with full /etc/passwd-compatible information
     pub fn add_user_full(
         &mut self,
@@ -325,11 +366,30 @@ impl UserDatabase {
     }
 
     /// Add a new user (assigns next available UID)
+    ///
+    /// The home directory is recorded as `/home/<username>`; the global
+    /// [`add_user`] also creates it. Fails if the name is taken or can't
+    /// be used as a path component.
     pub fn add_user(&mut self, username: &[u8], password: &[u8], gid: Gid) -> Option<Uid> {
+        if !is_valid_username(username) || self.get_user_by_name(username).is_some() {
+            return None;
+        }
+
+        let mut home = [0u8; MAX_HOME_LEN];
+        let home_len = HOME_BASE.len() + 1 + username.len();
+        home[..HOME_BASE.len()].copy_from_slice(HOME_BASE.as_bytes());
+        home[HOME_BASE.len()] = b'/';
+        home[HOME_BASE.len() + 1..home_len].copy_from_slice(username);
+
+        // Skip UIDs already taken, e.g. by entries loaded from /etc/passwd
+        while self.get_user(self.next_uid).is_some() {
+            self.next_uid += 1;
+        }
         let uid = self.next_uid;
         let guid = generate_guid();
-        
-        if self.add_user_with_id(uid, username, password, gid, guid) {
+        let shell = b"C:/apps/system/shell";
+
+        if self.add_user_full_internal(uid, username, password, gid, guid, b"", &home[..home_len], shell) {
             self.next_uid += 1;
             Some(uid)
         } else {
@@ -337,6 +397,17 @@ impl UserDatabase {
         }
     }
 
+    /// Remove a user from the table (their files are left alone)
+    pub fn remove_user(&mut self, uid: Uid) -> bool {
+        match self.users.iter_mut().find(|u| u.active && u.uid == uid) {
+            Some(user) => {
+                *user = User::empty();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Authenticate a user by username and password
     pub fn authenticate(&self, username: &[u8], password: &[u8]) -> Option<Uid> {
         let password_hash = hash_password(password);
@@ -371,6 +442,16 @@ impl UserDatabase {
     pub fn get_group(&self, gid: Gid) -> Option<&Group> {
         self.groups.iter().find(|g| g.active && g.gid == gid)
     }
+
+    /// Users in a group: those with it as their primary group, then those
+    /// with it as a supplementary group
+    pub fn group_members(&self, gid: Gid) -> impl Iterator<Item = &User> {
+        let primary = self.users.iter().filter(move |u| u.active && u.gid == gid);
+        let supplementary = self.users.iter().filter(move |u| {
+            u.active && u.gid != gid && u.supplementary_groups().contains(&gid)
+        });
+        primary.chain(supplementary)
+    }
 }
 
 /// Simple password hashing (XOR-based - NOT secure, replace with proper crypto)
@@ -416,9 +497,23 @@ pub fn get_user_by_name(username: &[u8]) -> Option<User> {
     USER_DB.lock().get_user_by_name(username).copied()
 }
 
-/// Add a new user
+/// Add a new user and create their home directory
+///
+/// If the home directory can't be created the user is removed again, so a
+/// returned UID always has somewhere to write.
 pub fn add_user(username: &[u8], password: &[u8], gid: Gid) -> Option<Uid> {
-    USER_DB.lock().add_user(username, password, gid)
+    let user = {
+        let mut db = USER_DB.lock();
+        let uid = db.add_user(username, password, gid)?;
+        *db.get_user(uid)?
+    };
+
+    // USER_DB is not held across VFS calls
+    if create_home(&user).is_err() {
+        USER_DB.lock().remove_user(user.uid);
+        return None;
+    }
+    Some(user.uid)
 }
 
 // ============================================================================
@@ -551,7 +646,7 @@ pub fn parse_group_line(line: &[u8]) -> Option<Group> {
     let name = parts[0];
     // parts[1] is password placeholder 'x'
     let gid_str = parts[2];
-    // parts[3] is members list (optional)
+    // parts[3] is the members list, read by parse_group_members
 
     // Validate group name
     if name.is_empty() || name.len() > MAX_GROUPNAME_LEN {
@@ -572,6 +667,16 @@ pub fn parse_group_line(line: &[u8]) -> Option<Group> {
     Some(group)
 }
 
+/// Member usernames of a line in /etc/group format
+///
+/// Yields nothing for comments, lines without a members field and empty
+/// names (as in `a,,b`).
+pub fn parse_group_members(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let (parts, count) = split_line(line, b':');
+    let members = if line.first() != Some(&b'#') && count >= 4 { parts[3] } else { &[][..] };
+    members.split(|&b| b == b',').map(|m| m.trim_ascii()).filter(|m| !m.is_empty())
+}
+
 /// Load users from /etc/passwd file contents
 pub fn load_passwd(data: &[u8]) {
     let mut db = USER_DB.lock();
@@ -595,8 +700,11 @@ pub fn load_passwd(data: &[u8]) {
                 let mut found = false;
                 for u in &mut db.users {
                     if u.active && u.uid == user.uid {
-                        // Update existing user
+                        // Update existing user, keeping memberships from /etc/group
+                        let (groups, group_count) = (u.groups, u.group_count);
                         *u = user;
+                        u.groups = groups;
+                        u.group_count = group_count;
                         found = true;
                         break;
                     }
@@ -618,6 +726,11 @@ pub fn load_passwd(data: &[u8]) {
 }
 
 /// Load groups from /etc/group file contents
+///
+/// The members listed for a group become its supplementary members,
+/// replacing any memberships it had; names without an entry in the user
+/// table are skipped, so load /etc/passwd first. A user already in
+/// NGROUPS_MAX groups is not added to more.
 pub fn load_group(data: &[u8]) {
     let mut db = USER_DB.lock();
 
@@ -633,6 +746,15 @@ pub fn load_group(data: &[u8]) {
             };
 
             if let Some(group) = parse_group_line(line) {
+                for user in db.users.iter_mut().filter(|u| u.active) {
+                    user.remove_group(group.gid);
+                }
+                for member in parse_group_members(line) {
+                    if let Some(user) = db.users.iter_mut().find(|u| u.active && u.username_bytes() == member) {
+                        user.add_group(group.gid);
+                    }
+                }
+
                 // Find a free slot or update existing group with same GID
                 let mut found = false;
                 for g in &mut db.groups {
@@ -683,6 +805,17 @@ pub fn list_users() -> [Option<User>; MAX_USERS] {
     result
 }
 
+/// List the members of a group (see `UserDatabase::group_members`),
+/// packed at the front of the array
+pub fn group_members(gid: Gid) -> [Option<User>; MAX_USERS] {
+    let db = USER_DB.lock();
+    let mut result = [None; MAX_USERS];
+    for (slot, user) in result.iter_mut().zip(db.group_members(gid)) {
+        *slot = Some(*user);
+    }
+    result
+}
+
 /// List all active groups
 pub fn list_groups() -> [Option<Group>; MAX_GROUPS] {
     let db = USER_DB.lock();
@@ -696,11 +829,205 @@ pub fn list_groups() -> [Option<Group>; MAX_GROUPS] {
 }
 
 // ============================================================================
-// Credential helpers for permission checking
+// Home directories
 // ============================================================================
 
-/// Maximum supplementary groups (matching VFS NGROUPS_MAX)
-pub const NGROUPS_MAX: usize = 16;
+/// Directory new users' homes are created in
+pub const HOME_BASE: &str = "/home";
+
+/// Files copied into every new home directory
+pub const SKEL_DIR: &str = "/etc/skel";
+
+/// Mode of a new home directory: only the owner may enter it
+const HOME_MODE: u32 = 0o700;
+
+/// Whether a name can be a username, and so a directory under /home
+///
+/// Letters, digits, `_`, `-` and `.`, not starting with `-` or `.`.
+pub fn is_valid_username(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_USERNAME_LEN
+        && !matches!(name[0], b'-' | b'.')
+        && name.iter().all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'))
+}
+
+/// Create a user's home directory, filled from /etc/skel
+///
+/// A missing /etc/skel leaves the home empty. If the directory already
+/// exists (say, left behind by an earlier account of the same name) it is
+/// kept as it is: nothing is copied into it and its owner is not changed.
+pub fn create_home(user: &User) -> VfsResult<()> {
+    let home = core::str::from_utf8(user.home_bytes()).map_err(|_| VfsError::InvalidPath)?;
+
+    if home.starts_with(HOME_BASE) {
+        ignore_exists(watos_vfs::mkdir(HOME_BASE))?;
+    }
+    match watos_vfs::mkdir(home) {
+        Ok(()) => {}
+        Err(VfsError::AlreadyExists) => {
+            return match watos_vfs::stat(home)?.file_type {
+                FileType::Directory => Ok(()),
+                _ => Err(VfsError::NotADirectory),
+            };
+        }
+        Err(e) => return Err(e),
+    }
+
+    if watos_vfs::stat(SKEL_DIR).is_ok() {
+        copy_tree(SKEL_DIR, home, user)?;
+    }
+    set_owner(home, user, Some(HOME_MODE))
+}
+
+/// Copy the contents of directory `from` into existing directory `to`,
+/// giving everything to `user`
+///
+/// Only files and directories are copied; other entries are skipped.
+fn copy_tree(from: &str, to: &str, user: &User) -> VfsResult<()> {
+    for entry in watos_vfs::readdir(from)? {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        let src = format!("{}/{}", from, entry.name);
+        let dst = format!("{}/{}", to, entry.name);
+        let mode = watos_vfs::stat(&src)?.mode & 0o7777;
+
+        match entry.file_type {
+            FileType::Directory => {
+                ignore_exists(watos_vfs::mkdir(&dst))?;
+                copy_tree(&src, &dst, user)?;
+            }
+            FileType::Regular => {
+                watos_vfs::copy(&src, &dst, &mut |_, _| {})?;
+            }
+            _ => continue,
+        }
+        set_owner(&dst, user, Some(mode).filter(|&m| m != 0))?;
+    }
+    Ok(())
+}
+
+/// Give `path` to `user`, and set its mode if given
+///
+/// Filesystems without ownership (FAT) are left as they are.
+fn set_owner(path: &str, user: &User, mode: Option<u32>) -> VfsResult<()> {
+    let supported = |r: VfsResult<()>| match r {
+        Err(VfsError::NotSupported) => Ok(()),
+        r => r,
+    };
+    supported(watos_vfs::chown(path, user.uid, user.gid))?;
+    match mode {
+        Some(mode) => supported(watos_vfs::chmod(path, mode)),
+        None => Ok(()),
+    }
+}
+
+fn ignore_exists(result: VfsResult<()>) -> VfsResult<()> {
+    match result {
+        Err(VfsError::AlreadyExists) => Ok(()),
+        r => r,
+    }
+}
+
+// ============================================================================
+// Enumeration (SYS_GETPWENT, SYS_GETGRENT and friends)
+// ============================================================================
+
+/// Appends to a byte buffer, counting what didn't fit
+struct LineWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> LineWriter<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        LineWriter { out, len: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if let Some(slot) = self.out.get_mut(self.len) {
+                *slot = b;
+            }
+            self.len += 1;
+        }
+    }
+
+    fn push_u32(&mut self, mut n: u32) {
+        let mut digits = [0u8; 10];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        self.push(&digits[i..]);
+    }
+}
+
+impl User {
+    /// Write the /etc/passwd line for this user (no newline)
+    ///
+    /// Returns the length of the whole line; if that is more than `out`
+    /// holds, the line was cut short.
+    pub fn write_passwd_line(&self, out: &mut [u8]) -> usize {
+        let mut w = LineWriter::new(out);
+        w.push(self.username_bytes());
+        w.push(b":x:");
+        w.push_u32(self.uid);
+        w.push(b":");
+        w.push_u32(self.gid);
+        w.push(b":");
+        w.push(self.gecos_bytes());
+        w.push(b":");
+        w.push(self.home_bytes());
+        w.push(b":");
+        w.push(self.shell_bytes());
+        w.len
+    }
+}
+
+impl UserDatabase {
+    /// Write the /etc/group line for a group (no newline); members are the
+    /// users with it as a supplementary group
+    fn write_group_line(&self, group: &Group, out: &mut [u8]) -> usize {
+        let mut w = LineWriter::new(out);
+        w.push(group.name_bytes());
+        w.push(b":x:");
+        w.push_u32(group.gid);
+        w.push(b":");
+        let members = self.users.iter().filter(|u| u.active && u.supplementary_groups().contains(&group.gid));
+        for (i, user) in members.enumerate() {
+            if i > 0 {
+                w.push(b",");
+            }
+            w.push(user.username_bytes());
+        }
+        w.len
+    }
+}
+
+/// The `index`th user account, in table order
+pub fn user_at(index: usize) -> Option<User> {
+    USER_DB.lock().users.iter().filter(|u| u.active).nth(index).copied()
+}
+
+/// The `index`th group, in table order
+pub fn group_at(index: usize) -> Option<Group> {
+    USER_DB.lock().groups.iter().filter(|g| g.active).nth(index).copied()
+}
+
+/// Write the /etc/group line for a group (see `User::write_passwd_line`)
+pub fn write_group_line(group: &Group, out: &mut [u8]) -> usize {
+    USER_DB.lock().write_group_line(group, out)
+}
+
+// ============================================================================
+// Credential helpers for permission checking
+// ============================================================================
 
 /// Credential information for permission checking
 /// This is a standalone struct that can be used by VFS for access control
@@ -783,3 +1110,76 @@ pub fn get_credential_info(uid: Uid, gid: Gid) -> CredentialInfo {
 
     cred
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(line: &[u8]) -> alloc::vec::Vec<&[u8]> {
+        parse_group_members(line).collect()
+    }
+
+    #[test]
+    fn test_group_members() {
+        assert_eq!(members(b"wheel:x:10:root,alice"), [&b"root"[..], b"alice"]);
+        assert_eq!(members(b"wheel:x:10: root , alice "), [&b"root"[..], b"alice"]);
+        assert_eq!(members(b"wheel:x:10:alice"), [&b"alice"[..]]);
+    }
+
+    #[test]
+    fn test_group_members_empty_and_trailing_commas() {
+        assert!(members(b"users:x:100:").is_empty());
+        assert!(members(b"users:x:100").is_empty());
+        assert!(members(b"users:x:100:,,").is_empty());
+        assert_eq!(members(b"users:x:100:alice,"), [&b"alice"[..]]);
+        assert_eq!(members(b"users:x:100:,alice,,bob,"), [&b"alice"[..], b"bob"]);
+    }
+
+    #[test]
+    fn test_group_malformed_lines() {
+        assert!(members(b"# wheel:x:10:root").is_empty());
+        assert!(members(b"").is_empty());
+        assert!(parse_group_line(b"# wheel:x:10:root").is_none());
+        assert!(parse_group_line(b"").is_none());
+        assert!(parse_group_line(b"wheel:x").is_none());
+        assert!(parse_group_line(b"wheel:x:ten:root").is_none());
+        assert!(parse_group_line(b":x:10:root").is_none());
+
+        let group = parse_group_line(b"wheel:x:10:root,alice").unwrap();
+        assert_eq!(group.gid, 10);
+        assert_eq!(&group.name[..group.name_len], b"wheel");
+        assert!(parse_group_line(b"users:x:100:").is_some());
+        assert!(parse_group_line(b"users:x:100").is_some());
+    }
+
+    #[test]
+    fn test_supplementary_group_limit() {
+        let mut user = User::empty();
+        for gid in 0..NGROUPS_MAX as Gid {
+            assert!(user.add_group(1000 + gid));
+        }
+        assert!(!user.add_group(2000));
+        // Already a member: no duplicate, no failure
+        assert!(user.add_group(1000));
+        assert_eq!(user.supplementary_groups().len(), NGROUPS_MAX);
+
+        user.remove_group(1000);
+        assert!(!user.in_group(1000));
+        assert!(user.add_group(2000));
+    }
+
+    #[test]
+    fn test_load_group_sets_memberships() {
+        load_passwd(b"grptest:x:4100:4100::/home/grptest:/bin/sh\n");
+        load_group(b"grpa:x:4200:grptest,nobody-here,\ngrpb:x:4201:\n# grpc:x:4202:grptest\n");
+        let user = get_user_by_name(b"grptest").unwrap();
+        assert_eq!(user.supplementary_groups(), [4200]);
+        assert!(get_group(4201).is_some());
+        assert!(get_group(4202).is_none());
+
+        // Reloading replaces the members of a group
+        load_group(b"grpa:x:4200:\ngrpb:x:4201:grptest\n");
+        let user = get_user_by_name(b"grptest").unwrap();
+        assert_eq!(user.supplementary_groups(), [4201]);
+    }
+}
@@ -2,35 +2,82 @@
 //!
 //! Provides kernel-level virtual terminals (like Linux /dev/tty1-N)
 //! Each VT has its own text buffer and can be switched between.
-//! The kernel VT driver renders the active VT to the framebuffer.
+//! The kernel VT driver renders the active VT to the framebuffer, or to the
+//! VGA text buffer when the bootloader provides no framebuffer.
+//!
+//! Every VT draws with the console palette, one of the built-in themes
+//! (`watos_terminal::color::THEMES`) possibly with its colors changed,
+//! unless it has a palette of its own. The `console.theme` and
+//! `console.vtN.theme` sysctls pick themes at runtime.
+//!
+//! Output can also be mirrored as plain text to a consumer, for
+//! accessibility tools (see [`mirror`]).
 
 #![no_std]
 
+extern crate alloc;
+
 pub mod vt;
 pub mod manager;
 pub mod renderer;
+pub mod vga_text;
+pub mod mirror;
 
 pub use vt::{VirtualTerminal, Color, Cell, VT_WIDTH, VT_HEIGHT};
 pub use manager::{VTManager, MAX_VTS};
-pub use renderer::{VTRenderer, Framebuffer, KernelFramebuffer};
+pub use renderer::{VTRenderer, Framebuffer, KernelFramebuffer, ConsoleBackend, FramebufferConsole};
+pub use vga_text::VgaTextConsole;
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, Ordering};
+pub use watos_terminal::color::{parse_rgb, Palette, THEMES, THEME_NAMES};
+use watos_terminal::font::{BuiltinFont, Font, FontError, Psf2Font};
 
 static VT_INITIALIZED: AtomicBool = AtomicBool::new(false);
 static mut VT_MANAGER: Option<VTManager> = None;
-static mut VT_RENDERER: Option<VTRenderer> = None;
-static mut FRAMEBUFFER: Option<KernelFramebuffer> = None;
+static mut CONSOLE: Option<Box<dyn ConsoleBackend>> = None;
 
-/// Initialize the VT subsystem
+/// Palette of every VT without its own, and the theme it started from
+static mut CONSOLE_PALETTE: (usize, Palette) = (0, Palette::DEFAULT);
+/// VTs' own palettes and their themes, by VT number - 1
+static mut VT_PALETTES: [Option<(usize, Palette)>; MAX_VTS] = [None; MAX_VTS];
+
+/// Initialize the VT subsystem on a framebuffer
 pub fn init(fb_addr: usize, fb_width: u32, fb_height: u32, fb_pitch: u32, fb_bpp: u32, is_bgr: bool) {
+    let fb = KernelFramebuffer::new(fb_addr, fb_width, fb_height, fb_pitch, fb_bpp, is_bgr);
+    init_with(Box::new(FramebufferConsole::new(fb)), false);
+}
+
+/// Initialize the VT subsystem on the VGA text buffer (80x25), for boots
+/// without a framebuffer
+pub fn init_vga_text() {
+    let mut console = unsafe { VgaTextConsole::new(vga_text::VGA_TEXT_ADDR) };
+    console.clear();
+    init_with(Box::new(console), true);
+    unsafe { watos_arch::serial_write(b"[VT] Using VGA text mode console\r\n"); }
+}
+
+/// Install the display; `fit` shrinks every VT to the display's grid
+fn init_with(console: Box<dyn ConsoleBackend>, fit: bool) {
     if VT_INITIALIZED.swap(true, Ordering::SeqCst) {
         return; // Already initialized
     }
 
+    let mut manager = VTManager::new();
+    if fit {
+        let (cols, rows) = console.grid_size();
+        resize_all(&mut manager, cols, rows);
+    }
+    for num in 1..=MAX_VTS {
+        if let (Some(vt), Some(palette)) = (manager.get_vt_mut(num), vt_palette(num)) {
+            vt.set_palette(palette);
+        }
+    }
+
     unsafe {
-        VT_MANAGER = Some(VTManager::new());
-        VT_RENDERER = Some(VTRenderer::new());
-        FRAMEBUFFER = Some(KernelFramebuffer::new(fb_addr, fb_width, fb_height, fb_pitch, fb_bpp, is_bgr));
+        VT_MANAGER = Some(manager);
+        CONSOLE = Some(console);
 
         watos_arch::serial_write(b"[VT] Virtual terminal subsystem initialized (");
         watos_arch::serial_hex(MAX_VTS as u64);
@@ -38,6 +85,17 @@ pub fn init(fb_addr: usize, fb_width: u32, fb_height: u32, fb_pitch: u32, fb_bpp
     }
 }
 
+/// Resize every VT, capped at the buffer size
+fn resize_all(manager: &mut VTManager, cols: usize, rows: usize) -> (usize, usize) {
+    let (cols, rows) = (cols.min(VT_WIDTH), rows.min(VT_HEIGHT));
+    for num in 1..=MAX_VTS {
+        if let Some(vt) = manager.get_vt_mut(num) {
+            vt.resize(cols, rows);
+        }
+    }
+    (cols, rows)
+}
+
 /// Write data to a specific VT (1-based)
 pub fn vt_write(vt_num: usize, data: &[u8]) {
     unsafe {
@@ -62,6 +120,25 @@ pub fn vt_write_active(data: &[u8]) {
     }
 }
 
+/// Whether the active VT was sent a BEL since the last call. Bells on
+/// background VTs are dropped.
+pub fn vt_take_bell() -> bool {
+    unsafe {
+        if let Some(manager) = &mut VT_MANAGER {
+            let active = manager.active_vt_num();
+            let mut rang = false;
+            for num in 1..=MAX_VTS {
+                if let Some(vt) = manager.get_vt_mut(num) {
+                    rang |= vt.take_bell() && num == active;
+                }
+            }
+            rang
+        } else {
+            false
+        }
+    }
+}
+
 /// Switch to a different VT (1-based)
 pub fn vt_switch(vt_num: usize) -> bool {
     unsafe {
@@ -88,13 +165,13 @@ pub fn vt_active() -> usize {
     }
 }
 
-/// Render the active VT to the framebuffer
+/// Render the active VT to the display
 pub fn vt_render() {
     unsafe {
-        if let (Some(manager), Some(renderer), Some(fb)) = (&VT_MANAGER, &VT_RENDERER, &mut FRAMEBUFFER) {
+        if let (Some(manager), Some(console)) = (&VT_MANAGER, &mut CONSOLE) {
             let vt = manager.active_vt();
             if vt.is_dirty() {
-                renderer.render(fb, vt);
+                console.render(vt);
             }
         }
     }
@@ -114,3 +191,230 @@ pub fn vt_clear(vt_num: usize) -> bool {
         }
     }
 }
+
+/// Theme of the console palette, as an index into `THEMES`
+pub fn console_theme() -> usize {
+    unsafe { CONSOLE_PALETTE.0 }
+}
+
+/// The palette of VTs without their own
+pub fn console_palette() -> Palette {
+    unsafe { CONSOLE_PALETTE.1 }
+}
+
+/// Give VTs without their own palette theme `theme`; false if there's no
+/// such theme
+pub fn console_set_theme(theme: usize) -> bool {
+    let Some(&palette) = THEMES.get(theme) else { return false };
+    unsafe { CONSOLE_PALETTE = (theme, palette); }
+    apply_palettes();
+    true
+}
+
+/// Give VTs without their own palette `palette`, counted as a variant of
+/// the current theme
+pub fn console_set_palette(palette: Palette) {
+    unsafe { CONSOLE_PALETTE.1 = palette; }
+    apply_palettes();
+}
+
+/// Theme of VT `vt_num`'s own palette, None if it uses the console's
+pub fn vt_theme(vt_num: usize) -> Option<usize> {
+    let palettes = unsafe { VT_PALETTES };
+    palettes.get(vt_num.wrapping_sub(1)).copied().flatten().map(|(theme, _)| theme)
+}
+
+/// Palette VT `vt_num` draws with, None if there's no such VT
+pub fn vt_palette(vt_num: usize) -> Option<Palette> {
+    let palettes = unsafe { VT_PALETTES };
+    let own = *palettes.get(vt_num.wrapping_sub(1))?;
+    Some(own.map_or(console_palette(), |(_, palette)| palette))
+}
+
+/// Give VT `vt_num` theme `theme` as its own palette, or the console's
+/// again for None; false if there's no such VT or theme
+pub fn vt_set_theme(vt_num: usize, theme: Option<usize>) -> bool {
+    if !(1..=MAX_VTS).contains(&vt_num) {
+        return false;
+    }
+    let own = match theme {
+        Some(theme) => match THEMES.get(theme) {
+            Some(&palette) => Some((theme, palette)),
+            None => return false,
+        },
+        None => None,
+    };
+    unsafe { VT_PALETTES[vt_num - 1] = own; }
+    apply_palettes();
+    true
+}
+
+/// Give VT `vt_num` a palette of its own, counted as a variant of the
+/// theme it has now; false if there's no such VT
+pub fn vt_set_palette(vt_num: usize, palette: Palette) -> bool {
+    if !(1..=MAX_VTS).contains(&vt_num) {
+        return false;
+    }
+    let theme = vt_theme(vt_num).unwrap_or(console_theme());
+    unsafe { VT_PALETTES[vt_num - 1] = Some((theme, palette)); }
+    apply_palettes();
+    true
+}
+
+/// Recolor every VT whose palette changed, and redraw the active one
+fn apply_palettes() {
+    unsafe {
+        if let Some(manager) = &mut *core::ptr::addr_of_mut!(VT_MANAGER) {
+            for num in 1..=MAX_VTS {
+                if let (Some(palette), Some(vt)) = (vt_palette(num), manager.get_vt_mut(num)) {
+                    vt.set_palette(palette);
+                }
+            }
+        }
+    }
+    vt_render();
+}
+
+/// Names for `console.vtN.theme`: "console" to use the console palette,
+/// then `THEME_NAMES`
+const VT_THEME_NAMES: [&str; THEME_NAMES.len() + 1] =
+    ["console", THEME_NAMES[0], THEME_NAMES[1], THEME_NAMES[2], THEME_NAMES[3], THEME_NAMES[4]];
+
+/// The `console.vtN.theme` sysctl for VT `VT`
+fn vt_theme_sysctl<const VT: usize>(name: &'static str) -> watos_sysctl::Tunable {
+    watos_sysctl::Tunable {
+        name,
+        description: "color theme of one VT, or console for the console theme",
+        kind: watos_sysctl::Kind::Choice(&VT_THEME_NAMES),
+        get: || vt_theme(VT).map_or(0, |theme| theme as u64 + 1),
+        set: |value| vt_set_theme(VT, (value as usize).checked_sub(1)),
+    }
+}
+
+/// Register `console.theme` and `console.vtN.theme`
+pub fn register_sysctls() {
+    use watos_sysctl::{Kind, Tunable};
+
+    let _ = watos_sysctl::register(Tunable {
+        name: "console.theme",
+        description: "color theme of VTs without their own",
+        kind: Kind::Choice(&THEME_NAMES),
+        get: || console_theme() as u64,
+        set: |theme| console_set_theme(theme as usize),
+    });
+    let vts: [Tunable; MAX_VTS] = [
+        vt_theme_sysctl::<1>("console.vt1.theme"),
+        vt_theme_sysctl::<2>("console.vt2.theme"),
+        vt_theme_sysctl::<3>("console.vt3.theme"),
+        vt_theme_sysctl::<4>("console.vt4.theme"),
+        vt_theme_sysctl::<5>("console.vt5.theme"),
+        vt_theme_sysctl::<6>("console.vt6.theme"),
+    ];
+    for tunable in vts {
+        let _ = watos_sysctl::register(tunable);
+    }
+}
+
+/// Change the console font: `psf` is a PSF2 file, or None for the built-in
+/// 8x16 font; `scale` multiplies the glyph size. Every VT is resized to fit
+/// the screen. Returns the new (cols, rows). Fails on the VGA text console.
+pub fn vt_set_font(psf: Option<Vec<u8>>, scale: u32) -> Result<(usize, usize), FontError> {
+    let font: Box<dyn Font> = match psf {
+        Some(data) => Box::new(Psf2Font::parse(data)?),
+        None => Box::new(BuiltinFont),
+    };
+
+    unsafe {
+        let (Some(manager), Some(console)) = (&mut VT_MANAGER, &mut CONSOLE) else {
+            return Err(FontError::Unsupported);
+        };
+        let (cols, rows) = console.set_font(font, scale)?;
+        let (cols, rows) = resize_all(manager, cols, rows);
+
+        console.clear();
+        vt_render();
+
+        watos_arch::serial_write(b"[VT] Font changed, grid ");
+        watos_arch::serial_hex(cols as u64);
+        watos_arch::serial_write(b"x");
+        watos_arch::serial_hex(rows as u64);
+        watos_arch::serial_write(b"\r\n");
+        Ok((cols, rows))
+    }
+}
+
+/// Follow a framebuffer mode change: every VT is resized to the new screen.
+/// Returns the new (cols, rows), or None on the VGA text console.
+pub fn vt_set_resolution(width: u32, height: u32, pitch: u32) -> Option<(usize, usize)> {
+    unsafe {
+        let (Some(manager), Some(console)) = (&mut VT_MANAGER, &mut CONSOLE) else {
+            return None;
+        };
+        let (cols, rows) = console.set_resolution(width, height, pitch)?;
+        let (cols, rows) = resize_all(manager, cols, rows);
+
+        console.clear();
+        vt_render();
+
+        watos_arch::serial_write(b"[VT] Resolution changed, grid ");
+        watos_arch::serial_hex(cols as u64);
+        watos_arch::serial_write(b"x");
+        watos_arch::serial_hex(rows as u64);
+        watos_arch::serial_write(b"\r\n");
+        Some((cols, rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One test: the palettes are global
+    #[test]
+    fn test_vt_palette_overrides() {
+        let light = Palette::theme("light").unwrap();
+        let solarized = Palette::theme("solarized").unwrap();
+
+        assert!(console_set_theme(4));
+        assert_eq!(console_theme(), 4);
+        assert_eq!(vt_palette(1), Some(light));
+        assert_eq!(vt_theme(1), None);
+
+        // A VT's own theme wins over the console's, and survives it changing
+        assert!(vt_set_theme(2, Some(2)));
+        assert!(console_set_theme(0));
+        assert_eq!(vt_palette(2), Some(solarized));
+        assert_eq!(vt_theme(2), Some(2));
+        assert_eq!(vt_palette(1), Some(Palette::DEFAULT));
+
+        // Its own palette counts as a variant of its theme
+        let mut custom = solarized;
+        assert!(custom.set_ansi("123456"));
+        assert!(vt_set_palette(2, custom));
+        assert_eq!(vt_palette(2), Some(custom));
+        assert_eq!(vt_theme(2), Some(2));
+
+        // A VT without a theme takes the console's when given a palette
+        assert!(vt_set_palette(3, custom));
+        assert_eq!(vt_theme(3), Some(0));
+
+        // Back to the console palette
+        assert!(vt_set_theme(2, None));
+        assert!(vt_set_theme(3, None));
+        let mut console = Palette::DEFAULT;
+        console.fg = parse_rgb("010203").unwrap();
+        console_set_palette(console);
+        assert_eq!(vt_palette(2), Some(console));
+        assert_eq!(console_theme(), 0);
+
+        // No such VT or theme
+        assert!(!vt_set_theme(0, Some(0)));
+        assert!(!vt_set_theme(MAX_VTS + 1, None));
+        assert!(!vt_set_theme(1, Some(THEMES.len())));
+        assert!(!vt_set_palette(0, light));
+        assert!(!console_set_theme(THEMES.len()));
+        assert_eq!(vt_palette(0), None);
+        assert_eq!(vt_palette(MAX_VTS + 1), None);
+        assert_eq!(vt_theme(1), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Plain-text mirror of console output, for screen readers and braille
+//! displays
+//!
+//! While a consumer is registered, everything written to a VT is also
+//! handed to it as UTF-8 text with the escape sequences and control
+//! characters taken out, in the order it was written. Lines end in `\n`.
+//! Where the cursor moved other than by the text itself, a line of its own
+//! says so before the text that follows:
+//!
+//! ```text
+//! @vt2        what follows was written to VT 2
+//! @clear      the screen was erased
+//! @5,10       the next text goes at row 5, column 10 (1-based)
+//! ```
+//!
+//! A text line that starts with `@` has another `@` put in front of it.
+//! Carriage return and line feed together are a plain newline, and tabs
+//! are kept.
+
+use core::fmt::{self, Write};
+
+use watos_terminal::parser::{csi_param, Event};
+use watos_terminal::terminal::Terminal;
+
+/// Receives mirrored text, in pieces that may split lines
+pub type Consumer = fn(&[u8]);
+
+static mut CONSUMER: Option<Consumer> = None;
+
+/// VT the last mirrored text came from (0 for none yet), and whether
+/// the output is at the start of a line
+static mut STREAM: (usize, bool) = (0, true);
+
+/// Start mirroring console output to `consumer`, or stop with None
+pub fn set_consumer(consumer: Option<Consumer>) {
+    unsafe {
+        CONSUMER = consumer;
+        STREAM = (0, true);
+    }
+}
+
+/// The registered consumer, if any
+pub fn consumer() -> Option<Consumer> {
+    unsafe { CONSUMER }
+}
+
+/// Mirrored bytes on their way to the consumer
+struct Out {
+    buf: [u8; 256],
+    len: usize,
+    consumer: Consumer,
+    /// VT being written
+    vt_num: usize,
+    /// VT the output last came from
+    last_vt: usize,
+    /// Nothing is on the current output line yet
+    line_start: bool,
+}
+
+impl Out {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.len > 0 {
+            (self.consumer)(&self.buf[..self.len]);
+            self.len = 0;
+        }
+    }
+}
+
+impl Write for Out {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Where one VT's text has got to
+pub(crate) struct TextMirror {
+    /// Cursor after the last event
+    cursor: (usize, usize),
+    /// Cursor after the last text or newline
+    flow: (usize, usize),
+    /// The last thing mirrored was a newline, so any column on the
+    /// flow's row continues the text
+    fresh_line: bool,
+    /// The cursor has left the flow; say where before more text
+    jumped: bool,
+}
+
+impl TextMirror {
+    pub(crate) const fn new() -> Self {
+        Self { cursor: (0, 0), flow: (0, 0), fresh_line: false, jumped: false }
+    }
+
+    /// Feed `data` to `terminal`, mirroring it as VT `vt_num`'s output
+    pub(crate) fn write(&mut self, vt_num: usize, terminal: &mut Terminal, data: &[u8], consumer: Consumer) {
+        let (last_vt, line_start) = unsafe { STREAM };
+        let mut out = Out { buf: [0; 256], len: 0, consumer, vt_num, last_vt, line_start };
+        terminal.process_bytes_observed(data, |event, terminal| self.event(&mut out, event, terminal.cursor()));
+        out.flush();
+        unsafe { STREAM = (out.last_vt, out.line_start); }
+    }
+
+    fn event(&mut self, out: &mut Out, event: &Event, cursor: (usize, usize)) {
+        match *event {
+            Event::Print(ch) => {
+                let mut utf8 = [0u8; 4];
+                self.text(out, ch.encode_utf8(&mut utf8).as_bytes());
+                self.follow(cursor, false);
+            }
+            Event::Execute(b'\t') => {
+                self.text(out, b"\t");
+                self.follow(cursor, false);
+            }
+            // LF, VT and FF all start a new line
+            Event::Execute(0x0A..=0x0C) => {
+                mark_vt(out);
+                out.push(b"\n");
+                out.line_start = true;
+                self.follow(cursor, true);
+            }
+            Event::Csi { final_byte: b'J', params, param_count, .. } if csi_param(&params, param_count, 0, 0) >= 2 => {
+                self.clear(out, cursor)
+            }
+            Event::EscDispatch(b'c') => self.clear(out, cursor),
+            _ => {
+                self.jumped = if self.fresh_line { cursor.1 != self.flow.1 } else { cursor != self.flow };
+            }
+        }
+        self.cursor = cursor;
+    }
+
+    /// Mirror text written at the cursor
+    fn text(&mut self, out: &mut Out, text: &[u8]) {
+        if self.jumped {
+            let (col, row) = self.cursor;
+            annotate(out, format_args!("@{},{}", row + 1, col + 1));
+        }
+        mark_vt(out);
+        if out.line_start && text[0] == b'@' {
+            out.push(b"@");
+        }
+        out.push(text);
+        out.line_start = false;
+    }
+
+    /// The screen was erased; text is expected from the top left again
+    fn clear(&mut self, out: &mut Out, cursor: (usize, usize)) {
+        annotate(out, format_args!("@clear"));
+        self.follow((0, 0), false);
+        self.jumped = cursor != (0, 0);
+    }
+
+    /// Text continues from `cursor`
+    fn follow(&mut self, cursor: (usize, usize), fresh_line: bool) {
+        self.flow = cursor;
+        self.fresh_line = fresh_line;
+        self.jumped = false;
+    }
+}
+
+/// Write `line` as an annotation line
+fn annotate(out: &mut Out, line: fmt::Arguments) {
+    mark_vt(out);
+    if !out.line_start {
+        out.push(b"\n");
+    }
+    let _ = out.write_fmt(line);
+    out.push(b"\n");
+    out.line_start = true;
+}
+
+/// Announce the VT if the last output came from another
+fn mark_vt(out: &mut Out) {
+    if out.last_vt != out.vt_num {
+        let vt_num = out.vt_num;
+        out.last_vt = vt_num;
+        annotate(out, format_args!("@vt{}", vt_num));
+    }
+}
@@ -1,7 +1,26 @@
 /// VT Renderer - renders VT text buffer to framebuffer
 
-use crate::vt::{Cell, Color, VirtualTerminal, VT_HEIGHT, VT_WIDTH};
-use watos_terminal::renderer::FONT_8X16;
+use crate::vt::{Cell, Color, VirtualTerminal};
+use alloc::boxed::Box;
+use watos_terminal::font::{BuiltinFont, Font, FontError, REPLACEMENT_CHAR};
+
+/// A display the active VT is drawn on
+///
+/// Implemented by [`FramebufferConsole`] for GOP framebuffers and by
+/// [`VgaTextConsole`](crate::vga_text::VgaTextConsole) for legacy text mode.
+pub trait ConsoleBackend {
+    /// Text grid the display shows, in (cols, rows)
+    fn grid_size(&self) -> (usize, usize);
+    /// Draw a VT
+    fn render(&mut self, vt: &VirtualTerminal);
+    /// Blank the whole display
+    fn clear(&mut self);
+    /// Change the font and scale; returns the new grid size
+    fn set_font(&mut self, font: Box<dyn Font>, scale: u32) -> Result<(usize, usize), FontError>;
+    /// Follow a display mode change; returns the new grid size, or None if
+    /// this display cannot change resolution
+    fn set_resolution(&mut self, width: u32, height: u32, pitch: u32) -> Option<(usize, usize)>;
+}
 
 /// Framebuffer abstraction
 pub trait Framebuffer {
@@ -9,6 +28,10 @@ pub trait Framebuffer {
     fn height(&self) -> u32;
     fn set_pixel(&mut self, x: u32, y: u32, color: Color);
     fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color);
+    /// Adopt a new resolution at the same address; false if unsupported
+    fn resize(&mut self, _width: u32, _height: u32, _pitch: u32) -> bool {
+        false
+    }
 }
 
 /// Simple framebuffer implementation for kernel
@@ -71,29 +94,51 @@ impl Framebuffer for KernelFramebuffer {
             }
         }
     }
-}
 
-const CHAR_WIDTH: u32 = 8;
-const CHAR_HEIGHT: u32 = 16;
+    fn resize(&mut self, width: u32, height: u32, pitch: u32) -> bool {
+        self.width = width;
+        self.height = height;
+        self.pitch = pitch;
+        true
+    }
+}
 
 /// VT Renderer
 pub struct VTRenderer {
-    char_width: u32,
-    char_height: u32,
+    font: Box<dyn Font>,
+    /// Integer scale applied to the font
+    scale: u32,
 }
 
 impl VTRenderer {
     pub fn new() -> Self {
         VTRenderer {
-            char_width: CHAR_WIDTH,
-            char_height: CHAR_HEIGHT,
+            font: Box::new(BuiltinFont),
+            scale: 1,
         }
     }
 
+    /// Replace the font and scale
+    pub fn set_font(&mut self, font: Box<dyn Font>, scale: u32) {
+        self.font = font;
+        self.scale = scale.max(1);
+    }
+
+    /// Cell width in pixels
+    pub fn char_width(&self) -> u32 {
+        self.font.width() * self.scale
+    }
+
+    /// Cell height in pixels
+    pub fn char_height(&self) -> u32 {
+        self.font.height() * self.scale
+    }
+
     /// Render a VT to the framebuffer
     pub fn render<F: Framebuffer>(&self, fb: &mut F, vt: &VirtualTerminal) {
-        for y in 0..VT_HEIGHT {
-            for x in 0..VT_WIDTH {
+        let (cols, rows) = vt.size();
+        for y in 0..rows {
+            for x in 0..cols {
                 if let Some(cell) = vt.get_cell(x, y) {
                     self.render_cell(fb, x as u32, y as u32, &cell);
                 }
@@ -109,25 +154,30 @@ impl VTRenderer {
 
     /// Render a single cell
     fn render_cell<F: Framebuffer>(&self, fb: &mut F, grid_x: u32, grid_y: u32, cell: &Cell) {
-        let pixel_x = grid_x * self.char_width;
-        let pixel_y = grid_y * self.char_height;
+        let pixel_x = grid_x * self.char_width();
+        let pixel_y = grid_y * self.char_height();
 
         // Fill background
-        fb.fill_rect(pixel_x, pixel_y, self.char_width, self.char_height, cell.bg);
+        fb.fill_rect(pixel_x, pixel_y, self.char_width(), self.char_height(), cell.bg);
 
         // Draw character glyph
-        self.draw_glyph(fb, pixel_x, pixel_y, cell.ch, cell.fg);
+        if cell.ch != ' ' {
+            self.draw_glyph(fb, pixel_x, pixel_y, cell.ch, cell.fg);
+        }
     }
 
     /// Draw a character glyph
     fn draw_glyph<F: Framebuffer>(&self, fb: &mut F, x: u32, y: u32, ch: char, fg: Color) {
-        let glyph_idx = (ch as usize) & 0xFF; // Mask to 0-255
-        let glyph = &FONT_8X16[glyph_idx];
-
-        for (row, &bits) in glyph.iter().enumerate() {
-            for col in 0..8 {
-                if (bits >> (7 - col)) & 1 != 0 {
-                    fb.set_pixel(x + col, y + row as u32, fg);
+        let glyph = match self.font.glyph(ch).or_else(|| self.font.glyph(REPLACEMENT_CHAR)) {
+            Some(glyph) => glyph,
+            None => return,
+        };
+        let s = self.scale;
+
+        for row in 0..glyph.height {
+            for col in 0..glyph.width {
+                if glyph.pixel(col, row) {
+                    fb.fill_rect(x + col * s, y + row * s, s, s, fg);
                 }
             }
         }
@@ -135,16 +185,66 @@ impl VTRenderer {
 
     /// Render cursor (simple block cursor)
     fn render_cursor<F: Framebuffer>(&self, fb: &mut F, grid_x: u32, grid_y: u32) {
-        let pixel_x = grid_x * self.char_width;
-        let pixel_y = grid_y * self.char_height;
+        let pixel_x = grid_x * self.char_width();
+        let pixel_y = grid_y * self.char_height();
 
         // Draw cursor as inverted block (white)
         fb.fill_rect(
             pixel_x,
-            pixel_y + self.char_height - 2,
-            self.char_width,
-            2,
+            pixel_y + self.char_height() - 2 * self.scale,
+            self.char_width(),
+            2 * self.scale,
             Color::WHITE,
         );
     }
 }
+
+/// Pixel console: a VT renderer drawing onto a linear framebuffer
+pub struct FramebufferConsole<F: Framebuffer> {
+    fb: F,
+    renderer: VTRenderer,
+}
+
+impl<F: Framebuffer> FramebufferConsole<F> {
+    pub fn new(fb: F) -> Self {
+        FramebufferConsole { fb, renderer: VTRenderer::new() }
+    }
+}
+
+impl<F: Framebuffer> ConsoleBackend for FramebufferConsole<F> {
+    fn grid_size(&self) -> (usize, usize) {
+        (
+            (self.fb.width() / self.renderer.char_width()) as usize,
+            (self.fb.height() / self.renderer.char_height()) as usize,
+        )
+    }
+
+    fn render(&mut self, vt: &VirtualTerminal) {
+        self.renderer.render(&mut self.fb, vt);
+    }
+
+    fn clear(&mut self) {
+        let (width, height) = (self.fb.width(), self.fb.height());
+        self.fb.fill_rect(0, 0, width, height, Color::BLACK);
+    }
+
+    fn set_font(&mut self, font: Box<dyn Font>, scale: u32) -> Result<(usize, usize), FontError> {
+        let scale = scale.max(1);
+        if font.width() * scale > self.fb.width() || font.height() * scale > self.fb.height() {
+            return Err(FontError::Unsupported);
+        }
+        self.renderer.set_font(font, scale);
+        Ok(self.grid_size())
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32, pitch: u32) -> Option<(usize, usize)> {
+        if !self.fb.resize(width, height, pitch) {
+            return None;
+        }
+        // A big scaled font may no longer fit
+        if self.renderer.char_width() > width || self.renderer.char_height() > height {
+            self.renderer.set_font(Box::new(BuiltinFont), 1);
+        }
+        Some(self.grid_size())
+    }
+}
@@ -0,0 +1,155 @@
+//! VGA text mode console - fallback when the bootloader provides no framebuffer
+//!
+//! Draws the active VT into the 80x25 character buffer at 0xB8000 that BIOS
+//! boots leave set up. Cells are 16-bit: code page 437 character in the low
+//! byte, attribute (background << 4 | foreground) in the high byte. The
+//! hardware cursor is driven through the CRT controller.
+
+use crate::renderer::ConsoleBackend;
+use crate::vt::{Color, VirtualTerminal};
+use alloc::boxed::Box;
+use watos_terminal::font::{Font, FontError};
+
+/// Physical (and identity-mapped) address of the text buffer
+pub const VGA_TEXT_ADDR: usize = 0xB8000;
+pub const VGA_TEXT_COLS: usize = 80;
+pub const VGA_TEXT_ROWS: usize = 25;
+
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOW: u8 = 0x0F;
+/// Cursor start register bit that hides the cursor
+const CURSOR_DISABLE: u8 = 0x20;
+
+/// The 16 text mode colors, in attribute order
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0x00, 0x00, 0xAA), (0x00, 0xAA, 0x00), (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00), (0xAA, 0x00, 0xAA), (0xAA, 0x55, 0x00), (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55), (0x55, 0x55, 0xFF), (0x55, 0xFF, 0x55), (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55), (0xFF, 0x55, 0xFF), (0xFF, 0xFF, 0x55), (0xFF, 0xFF, 0xFF),
+];
+
+/// Unicode for code page 437 bytes 0x80-0xFF
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// Code page 437 byte for a character; '?' if it has none
+fn to_cp437(ch: char) -> u8 {
+    match ch {
+        ' '..='~' => ch as u8,
+        _ => CP437_HIGH
+            .iter()
+            .position(|&c| c == ch)
+            .map_or(b'?', |i| 0x80 + i as u8),
+    }
+}
+
+/// Nearest text mode color index
+fn to_attr_color(color: Color) -> u8 {
+    let dist = |&(r, g, b): &(u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, color.r) + d(g, color.g) + d(b, color.b)
+    };
+    PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| dist(entry))
+        .map_or(7, |(i, _)| i as u8)
+}
+
+fn crtc_write(reg: u8, value: u8) {
+    unsafe {
+        watos_arch::port::outb(CRTC_INDEX, reg);
+        watos_arch::port::outb(CRTC_DATA, value);
+    }
+}
+
+fn crtc_read(reg: u8) -> u8 {
+    unsafe {
+        watos_arch::port::outb(CRTC_INDEX, reg);
+        watos_arch::port::inb(CRTC_DATA)
+    }
+}
+
+/// VGA text buffer console
+pub struct VgaTextConsole {
+    addr: usize,
+}
+
+impl VgaTextConsole {
+    /// # Safety
+    /// `addr` must map the 80x25 text buffer for as long as the console lives
+    pub unsafe fn new(addr: usize) -> Self {
+        VgaTextConsole { addr }
+    }
+
+    fn put(&mut self, x: usize, y: usize, ch: u8, attr: u8) {
+        let offset = y * VGA_TEXT_COLS + x;
+        unsafe {
+            core::ptr::write_volatile((self.addr as *mut u16).add(offset), (attr as u16) << 8 | ch as u16);
+        }
+    }
+
+    fn set_cursor(&mut self, pos: Option<(usize, usize)>) {
+        let start = crtc_read(CRTC_CURSOR_START);
+        match pos {
+            Some((x, y)) => {
+                let offset = (y * VGA_TEXT_COLS + x) as u16;
+                crtc_write(CRTC_CURSOR_HIGH, (offset >> 8) as u8);
+                crtc_write(CRTC_CURSOR_LOW, offset as u8);
+                crtc_write(CRTC_CURSOR_START, start & !CURSOR_DISABLE);
+            }
+            None => crtc_write(CRTC_CURSOR_START, start | CURSOR_DISABLE),
+        }
+    }
+}
+
+impl ConsoleBackend for VgaTextConsole {
+    fn grid_size(&self) -> (usize, usize) {
+        (VGA_TEXT_COLS, VGA_TEXT_ROWS)
+    }
+
+    fn render(&mut self, vt: &VirtualTerminal) {
+        for y in 0..VGA_TEXT_ROWS {
+            for x in 0..VGA_TEXT_COLS {
+                let (ch, attr) = match vt.get_cell(x, y) {
+                    Some(cell) => (to_cp437(cell.ch), to_attr_color(cell.bg) << 4 | to_attr_color(cell.fg)),
+                    None => (b' ', 0x07),
+                };
+                self.put(x, y, ch, attr);
+            }
+        }
+
+        // The hardware cursor blinks on its own
+        let (cx, cy) = vt.cursor();
+        let visible = vt.cursor_enabled() && cx < VGA_TEXT_COLS && cy < VGA_TEXT_ROWS;
+        self.set_cursor(visible.then_some((cx, cy)));
+    }
+
+    fn clear(&mut self) {
+        for y in 0..VGA_TEXT_ROWS {
+            for x in 0..VGA_TEXT_COLS {
+                self.put(x, y, b' ', 0x07);
+            }
+        }
+    }
+
+    fn set_font(&mut self, _font: Box<dyn Font>, _scale: u32) -> Result<(usize, usize), FontError> {
+        // Text mode glyphs live in VGA plane 2; loading them is not supported
+        Err(FontError::Unsupported)
+    }
+
+    fn set_resolution(&mut self, _width: u32, _height: u32, _pitch: u32) -> Option<(usize, usize)> {
+        None
+    }
+}
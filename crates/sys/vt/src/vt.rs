@@ -5,6 +5,9 @@
 use watos_terminal::terminal::Terminal;
 use watos_terminal::color::Color as TermColor;
 use watos_terminal::cell::Cell as TermCell;
+use watos_terminal::color::Palette;
+
+use crate::mirror::{self, TextMirror};
 
 // For 1280x800 with 8x16 font: 160 cols x 50 rows
 // This fits common resolutions better than 80x25
@@ -77,6 +80,9 @@ pub struct VirtualTerminal {
 
     /// Tick counter for cursor blinking
     blink_ticks: u32,
+
+    /// Where the accessibility mirror has got to in this VT's output
+    mirror: TextMirror,
 }
 
 impl VirtualTerminal {
@@ -88,29 +94,63 @@ impl VirtualTerminal {
             vt_num,
             cursor_blink_on: true,
             blink_ticks: 0,
+            mirror: TextMirror::new(),
         }
     }
 
     /// Write bytes to the VT (processes ANSI escape sequences)
     pub fn write(&mut self, data: &[u8]) {
-        self.terminal.process_bytes(data);
+        match mirror::consumer() {
+            Some(consumer) => self.mirror.write(self.vt_num, &mut self.terminal, data, consumer),
+            None => self.terminal.process_bytes(data),
+        }
         self.dirty = true;
     }
 
+    /// Whether a BEL was written since the last call
+    pub fn take_bell(&mut self) -> bool {
+        self.terminal.take_bell()
+    }
+
     /// Clear the screen
     pub fn clear(&mut self) {
         // Send ANSI clear screen sequence
         self.write(b"\x1b[2J\x1b[H");
     }
 
+    /// The colors in use
+    pub fn palette(&self) -> Palette {
+        *self.terminal.palette()
+    }
+
+    /// Switch palettes, recoloring what's on screen
+    pub fn set_palette(&mut self, palette: Palette) {
+        if palette != *self.terminal.palette() {
+            self.terminal.set_palette(palette);
+            self.dirty = true;
+        }
+    }
+
     /// Get cursor position
     pub fn cursor(&self) -> (usize, usize) {
         self.terminal.cursor()
     }
 
+    /// Current size in (cols, rows)
+    pub fn size(&self) -> (usize, usize) {
+        self.terminal.size()
+    }
+
+    /// Resize the text grid (e.g. after a font change); at most VT_WIDTH x VT_HEIGHT
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.terminal.resize(cols.min(VT_WIDTH), rows.min(VT_HEIGHT));
+        self.dirty = true;
+    }
+
     /// Get cell at position (converts from terminal Cell to our Cell)
     pub fn get_cell(&self, x: usize, y: usize) -> Option<Cell> {
-        if x < VT_WIDTH && y < VT_HEIGHT {
+        let (cols, rows) = self.size();
+        if x < cols && y < rows {
             let term_cell = self.terminal.grid.get(x, y)?;
             Some(Cell {
                 ch: term_cell.ch,
@@ -127,6 +167,11 @@ impl VirtualTerminal {
         self.terminal.cursor_visible() && self.cursor_blink_on
     }
 
+    /// Is the cursor shown at all (ignoring the blink phase)?
+    pub fn cursor_enabled(&self) -> bool {
+        self.terminal.cursor_visible()
+    }
+
     /// Update cursor blink (call this periodically, e.g., every timer tick)
     pub fn tick_cursor(&mut self) {
         self.blink_ticks += 1;
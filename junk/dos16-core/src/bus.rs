@@ -0,0 +1,149 @@
+//! Memory map
+//!
+//! RAM is the flat `Emulator::memory` vector. On top of it, address ranges
+//! can be mapped as:
+//!
+//! - ROM: reads come from RAM as usual, writes are dropped. Load the image
+//!   with `load_code_at` (or straight into `memory`) before or after mapping.
+//! - MMIO: a [`MmioHandler`] sees every access to the range and can answer
+//!   a read or swallow a write; anything it doesn't handle falls back to
+//!   RAM. Video RAM, the BIOS data area and adapter ROMs are built this way.
+//!
+//! Mappings are checked newest first, so a small region mapped inside a
+//! larger one takes precedence. Instruction fetches go through the map too.
+
+use core::cell::Cell;
+
+#[cfg(feature = "std")]
+use std::boxed::Box;
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+
+use crate::Emulator;
+
+/// Callbacks for a memory-mapped range. Addresses are linear. Handlers
+/// that keep state need interior mutability, since reads take `&self`.
+pub trait MmioHandler {
+    /// The byte at `addr`, or None to read RAM
+    fn read(&self, _addr: usize) -> Option<u8> {
+        None
+    }
+
+    /// Take a write. Return false to store it in RAM as well.
+    fn write(&self, _addr: usize, _val: u8) -> bool {
+        false
+    }
+}
+
+/// What a mapped range does
+pub enum RegionKind {
+    Rom,
+    Mmio(Box<dyn MmioHandler>),
+}
+
+/// A mapped range of linear addresses
+pub struct Region {
+    pub start: usize,
+    pub len: usize,
+    pub kind: RegionKind,
+}
+
+impl Region {
+    fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr - self.start < self.len
+    }
+}
+
+/// The BIOS timer tick count at 0040:006C, driven by a host clock.
+///
+/// `source` returns ticks since midnight at 18.2 Hz (the host's PIT count
+/// works). Programs that write the counter move it relative to the source
+/// rather than stopping it.
+pub struct TickCounter {
+    source: fn() -> u32,
+    adjust: Cell<u32>,
+}
+
+impl TickCounter {
+    /// Linear address of the counter
+    pub const ADDR: usize = 0x46C;
+    /// Ticks per day; the BIOS wraps the count at midnight
+    pub const TICKS_PER_DAY: u32 = 0x1800B0;
+
+    pub fn new(source: fn() -> u32) -> Self {
+        TickCounter { source, adjust: Cell::new(0) }
+    }
+
+    fn ticks(&self) -> u32 {
+        (self.source)().wrapping_add(self.adjust.get()) % Self::TICKS_PER_DAY
+    }
+}
+
+impl MmioHandler for TickCounter {
+    fn read(&self, addr: usize) -> Option<u8> {
+        let shift = (addr - Self::ADDR) * 8;
+        Some((self.ticks() >> shift) as u8)
+    }
+
+    fn write(&self, addr: usize, val: u8) -> bool {
+        let shift = (addr - Self::ADDR) * 8;
+        let ticks = (self.ticks() & !(0xFF << shift)) | ((val as u32) << shift);
+        self.adjust.set(ticks.wrapping_sub((self.source)()));
+        true
+    }
+}
+
+impl Emulator {
+    /// Make `len` bytes at `start` (linear) read-only
+    pub fn map_rom(&mut self, start: usize, len: usize) {
+        self.regions.push(Region { start, len, kind: RegionKind::Rom });
+    }
+
+    /// Send accesses to `len` bytes at `start` (linear) to `handler`
+    pub fn map_mmio(&mut self, start: usize, len: usize, handler: Box<dyn MmioHandler>) {
+        self.regions.push(Region { start, len, kind: RegionKind::Mmio(handler) });
+    }
+
+    /// Drive 0040:006C from a host tick source
+    pub fn map_tick_counter(&mut self, source: fn() -> u32) {
+        self.map_mmio(TickCounter::ADDR, 4, Box::new(TickCounter::new(source)));
+    }
+
+    /// Remove the mappings starting at `start`. Returns false if there were
+    /// none.
+    pub fn unmap(&mut self, start: usize) -> bool {
+        let before = self.regions.len();
+        self.regions.retain(|r| r.start != start);
+        self.regions.len() != before
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    fn region_at(&self, addr: usize) -> Option<&Region> {
+        self.regions.iter().rev().find(|r| r.contains(addr))
+    }
+
+    /// Read a byte through the memory map
+    pub(crate) fn bus_read(&self, addr: usize) -> u8 {
+        if let Some(Region { kind: RegionKind::Mmio(handler), .. }) = self.region_at(addr) {
+            if let Some(val) = handler.read(addr) {
+                return val;
+            }
+        }
+        if addr < self.memory.len() { self.memory[addr] } else { 0 }
+    }
+
+    /// Write a byte through the memory map
+    pub(crate) fn bus_write(&mut self, addr: usize, val: u8) {
+        match self.region_at(addr).map(|r| &r.kind) {
+            Some(RegionKind::Rom) => return,
+            Some(RegionKind::Mmio(handler)) if handler.write(addr, val) => return,
+            _ => {}
+        }
+        if addr < self.memory.len() { self.memory[addr] = val; }
+    }
+}
+
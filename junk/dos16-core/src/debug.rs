@@ -0,0 +1,187 @@
+//! Breakpoints, watchpoints and step-over
+//!
+//! The execution controller behind a debugger front-end. [`Emulator::step`]
+//! checks it around every instruction:
+//!
+//! - A code breakpoint stops *before* the instruction at its CS:IP runs and
+//!   `step` returns [`StepResult::Breakpoint`]. The next `step` runs that
+//!   instruction instead of stopping on it again.
+//! - A watchpoint covers a range of memory. The instruction that touches it
+//!   finishes, then `step` returns [`StepResult::Watchpoint`] with the first
+//!   access it made. Instruction fetches don't count as reads.
+//!
+//! Addresses compare as linear addresses, so a breakpoint at 0010:0000
+//! also fires at 0000:0100.
+
+use core::cell::Cell;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::{Emulator, StepResult};
+
+/// Which accesses a watchpoint reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+/// A watched range of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// Linear address of the first byte
+    pub start: usize,
+    pub len: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: usize, write: bool) -> bool {
+        let kind_ok = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        };
+        kind_ok && addr >= self.start && addr - self.start < self.len
+    }
+}
+
+/// A memory access that tripped a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Linear address accessed
+    pub addr: usize,
+    /// Byte read, or byte written
+    pub value: u8,
+    pub write: bool,
+}
+
+/// Debugger state kept on the emulator
+#[derive(Default)]
+pub(crate) struct Debugger {
+    breakpoints: Vec<(u16, u16)>,
+    watchpoints: Vec<Watchpoint>,
+    /// First watchpoint hit by the current instruction. A Cell because
+    /// reads take &self.
+    hit: Cell<Option<WatchHit>>,
+    /// Linear address of the breakpoint we last stopped on, so the next
+    /// step executes it rather than stopping again
+    resume_at: Option<usize>,
+}
+
+impl Debugger {
+    /// Note an access if a watchpoint covers it
+    pub(crate) fn check_access(&self, addr: usize, value: u8, write: bool) {
+        if self.watchpoints.is_empty() || self.hit.get().is_some() {
+            return;
+        }
+        if self.watchpoints.iter().any(|w| w.matches(addr, write)) {
+            self.hit.set(Some(WatchHit { addr, value, write }));
+        }
+    }
+}
+
+impl Emulator {
+    /// Stop before executing the instruction at seg:off
+    pub fn add_breakpoint(&mut self, seg: u16, off: u16) {
+        if !self.debug.breakpoints.contains(&(seg, off)) {
+            self.debug.breakpoints.push((seg, off));
+        }
+    }
+
+    /// Returns false if there was no breakpoint at seg:off
+    pub fn remove_breakpoint(&mut self, seg: u16, off: u16) -> bool {
+        let before = self.debug.breakpoints.len();
+        self.debug.breakpoints.retain(|&bp| bp != (seg, off));
+        self.debug.breakpoints.len() != before
+    }
+
+    /// Breakpoints as seg:off pairs, in the order they were added
+    pub fn breakpoints(&self) -> &[(u16, u16)] {
+        &self.debug.breakpoints
+    }
+
+    /// Report accesses of `kind` to `len` bytes starting at seg:off
+    pub fn add_watchpoint(&mut self, seg: u16, off: u16, len: usize, kind: WatchKind) {
+        let start = self.lin(seg, off);
+        self.debug.watchpoints.push(Watchpoint { start, len, kind });
+    }
+
+    /// Remove every watchpoint starting at seg:off. Returns false if there
+    /// were none.
+    pub fn remove_watchpoint(&mut self, seg: u16, off: u16) -> bool {
+        let start = self.lin(seg, off);
+        let before = self.debug.watchpoints.len();
+        self.debug.watchpoints.retain(|w| w.start != start);
+        self.debug.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.debug.watchpoints
+    }
+
+    /// Remove all breakpoints and watchpoints
+    pub fn clear_debug(&mut self) {
+        self.debug = Debugger::default();
+    }
+
+    /// Called by step before executing: stop here if there's a breakpoint
+    /// we haven't just stopped on
+    pub(crate) fn check_breakpoint(&mut self) -> Option<StepResult> {
+        if self.debug.breakpoints.is_empty() {
+            return None;
+        }
+        let here = self.lin(self.cpu.cs, self.cpu.ip);
+        if self.debug.resume_at.take() == Some(here) {
+            return None;
+        }
+        if self.debug.breakpoints.iter().any(|&(seg, off)| self.lin(seg, off) == here) {
+            self.debug.resume_at = Some(here);
+            return Some(StepResult::Breakpoint);
+        }
+        None
+    }
+
+    /// Called by step after executing: turn a watchpoint hit into the step
+    /// result, unless the instruction already stopped for another reason
+    pub(crate) fn take_watch_hit(&mut self, result: StepResult) -> StepResult {
+        match (result, self.debug.hit.take()) {
+            (StepResult::Continue, Some(hit)) => StepResult::Watchpoint(hit),
+            (result, _) => result,
+        }
+    }
+
+    /// Execute one instruction, running a CALL through to its return.
+    ///
+    /// Stops when execution gets back to the instruction after the call
+    /// with the stack no deeper than it started (so a recursive call passing
+    /// the same spot doesn't count), or earlier on anything that would stop
+    /// [`run`](Emulator::run), or after `max_steps` instructions. Other
+    /// instructions are a plain [`step`](Emulator::step).
+    pub fn step_over(&mut self, max_steps: usize) -> (StepResult, usize) {
+        let (cs, sp) = (self.cpu.cs, self.cpu.sp);
+        let insn = self.disassemble_one(cs, self.cpu.ip);
+        if !insn.is_call() {
+            return (self.step(), 1);
+        }
+
+        let ret = insn.next_off();
+        let mut steps = 0;
+        while steps < max_steps {
+            let result = self.step();
+            steps += 1;
+            if result != StepResult::Continue {
+                return (result, steps);
+            }
+            if self.cpu.cs == cs && self.cpu.ip == ret && self.cpu.sp >= sp {
+                break;
+            }
+        }
+        (StepResult::Continue, steps)
+    }
+}
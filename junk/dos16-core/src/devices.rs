@@ -0,0 +1,717 @@
+//! PC devices: 8253 timer, 8259 interrupt controllers, 8042 keyboard
+//! controller
+//!
+//! ```text
+//! 0x20, 0x21   master PIC (IRQ 0-7,  vectors 0x08-0x0F after BIOS setup)
+//! 0xA0, 0xA1   slave PIC  (IRQ 8-15, vectors 0x70-0x77), cascaded on IRQ 2
+//! 0x40-0x43    PIT channels 0-2 and control word
+//! 0x60, 0x64   8042 data and status/command
+//! 0x61         system control port B (PIT channel 2 gate, speaker)
+//! ```
+//!
+//! IN and OUT reach these through [`Devices::port_in`] and
+//! [`Devices::port_out`]; other ports read 0xFF and ignore writes.
+//!
+//! Time only moves when the host says so: call [`Devices::advance`] with
+//! PIT input clocks (1.193182 MHz), or set `clocks_per_step` to have every
+//! instruction advance it. PIT channel 0 raises IRQ 0 and a byte arriving
+//! from the keyboard raises IRQ 1. Before each instruction, with IF set,
+//! [`Emulator::step`](crate::Emulator::step) takes the highest pending IRQ
+//! from the PICs and enters its handler through the IVT.
+
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::VecDeque;
+
+/// PIT input clock in Hz
+pub const PIT_HZ: u32 = 1_193_182;
+
+// ============================================================================
+// 8253 PIT
+// ============================================================================
+
+#[derive(Debug, Clone, Default)]
+struct PitChannel {
+    mode: u8,
+    /// 1 = LSB only, 2 = MSB only, 3 = LSB then MSB
+    access: u8,
+    /// Count loaded by the program, 1..=65536
+    reload: u32,
+    /// Current count, 1..=reload while a periodic mode runs
+    count: u32,
+    running: bool,
+    /// One-shot modes: terminal count reached
+    fired: bool,
+    /// LSB written, waiting for the MSB
+    write_lsb: Option<u8>,
+    /// Next read in LSB/MSB access returns the MSB
+    read_msb: bool,
+    latch: Option<u16>,
+}
+
+impl PitChannel {
+    fn periodic(&self) -> bool {
+        self.mode == 2 || self.mode == 3
+    }
+
+    fn load(&mut self, value: u16) {
+        self.reload = if value == 0 { 0x10000 } else { value as u32 };
+        self.count = self.reload;
+        self.running = true;
+        self.fired = false;
+    }
+
+    fn write(&mut self, val: u8) {
+        match self.access {
+            1 => self.load(val as u16),
+            2 => self.load((val as u16) << 8),
+            _ => match self.write_lsb.take() {
+                Some(lsb) => self.load(lsb as u16 | ((val as u16) << 8)),
+                None => {
+                    self.write_lsb = Some(val);
+                    // Mode 0 stops counting while a new count is written
+                    if self.mode == 0 {
+                        self.running = false;
+                    }
+                }
+            },
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let value = self.latch.unwrap_or(self.count as u16);
+        match self.access {
+            1 => {
+                self.latch = None;
+                value as u8
+            }
+            2 => {
+                self.latch = None;
+                (value >> 8) as u8
+            }
+            _ if !self.read_msb => {
+                self.read_msb = true;
+                value as u8
+            }
+            _ => {
+                self.read_msb = false;
+                self.latch = None;
+                (value >> 8) as u8
+            }
+        }
+    }
+
+    /// Count down `clocks`; returns the number of rising output edges
+    fn advance(&mut self, clocks: u32) -> u32 {
+        if !self.running {
+            return 0;
+        }
+        if self.periodic() {
+            if self.count > clocks {
+                self.count -= clocks;
+                return 0;
+            }
+            let past = clocks - self.count;
+            self.count = self.reload - past % self.reload;
+            return 1 + past / self.reload;
+        }
+        // One-shot: the output goes high once at terminal count and the
+        // counter keeps wrapping through 0xFFFF
+        let edge = !self.fired && self.count <= clocks;
+        self.fired |= edge;
+        self.count = (self.count as i64 - clocks as i64).rem_euclid(0x10000) as u32;
+        edge as u32
+    }
+
+    fn output(&self) -> bool {
+        match self.mode {
+            3 => self.count > self.reload / 2,
+            2 => self.count != 1,
+            _ => self.fired,
+        }
+    }
+}
+
+/// 8253 programmable interval timer
+#[derive(Debug, Clone)]
+pub struct Pit {
+    channels: [PitChannel; 3],
+    /// Channel 2 gate, from port 0x61 bit 0
+    pub gate2: bool,
+}
+
+impl Pit {
+    /// As the BIOS leaves it: channel 0 in mode 3 with a count of 65536
+    /// (18.2 Hz), channel 1 running DRAM refresh
+    pub fn new() -> Self {
+        let mut pit = Pit { channels: Default::default(), gate2: false };
+        for (ch, mode, count) in [(0, 3, 0), (1, 2, 18), (2, 3, 0)] {
+            pit.channels[ch].mode = mode;
+            pit.channels[ch].access = 3;
+            pit.channels[ch].load(count);
+        }
+        pit
+    }
+
+    /// Ports 0x40-0x43
+    pub fn write(&mut self, port: u16, val: u8) {
+        match port {
+            0x40..=0x42 => self.channels[(port - 0x40) as usize].write(val),
+            0x43 => self.control(val),
+            _ => {}
+        }
+    }
+
+    fn control(&mut self, val: u8) {
+        let ch = (val >> 6) as usize;
+        // Read-back is an 8254 command
+        if ch == 3 {
+            return;
+        }
+        let channel = &mut self.channels[ch];
+        let access = (val >> 4) & 3;
+        if access == 0 {
+            channel.latch.get_or_insert(channel.count as u16);
+            return;
+        }
+        // Modes 6 and 7 are aliases of 2 and 3. The channel stops until
+        // its new count is written.
+        let mode = (val >> 1) & 7;
+        *channel = PitChannel {
+            mode: if mode > 5 { mode & 3 } else { mode },
+            access,
+            reload: channel.reload,
+            count: channel.count,
+            ..Default::default()
+        };
+    }
+
+    /// Ports 0x40-0x42
+    pub fn read(&mut self, port: u16) -> u8 {
+        match port {
+            0x40..=0x42 => self.channels[(port - 0x40) as usize].read(),
+            _ => 0xFF,
+        }
+    }
+
+    /// Advance all channels by `clocks` input clocks. Returns how many times
+    /// channel 0's output rose (each one is an IRQ 0).
+    pub fn advance(&mut self, clocks: u32) -> u32 {
+        let edges = self.channels[0].advance(clocks);
+        self.channels[1].advance(clocks);
+        if self.gate2 {
+            self.channels[2].advance(clocks);
+        }
+        edges
+    }
+
+    /// A channel's count as loaded, 1..=65536
+    pub fn reload(&self, ch: usize) -> u32 {
+        self.channels[ch].reload
+    }
+
+    /// A channel's mode, 0-5
+    pub fn mode(&self, ch: usize) -> u8 {
+        self.channels[ch].mode
+    }
+
+    /// Level of a channel's output pin
+    pub fn output(&self, ch: usize) -> bool {
+        self.channels[ch].output()
+    }
+}
+
+impl Default for Pit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// 8259 PIC
+// ============================================================================
+
+fn highest(bits: u8) -> Option<u8> {
+    (bits != 0).then(|| bits.trailing_zeros() as u8)
+}
+
+/// One 8259 interrupt controller, fixed priority (IRQ 0 highest)
+#[derive(Debug, Clone)]
+pub struct Pic {
+    /// Requested
+    pub irr: u8,
+    /// In service
+    pub isr: u8,
+    /// Masked
+    pub imr: u8,
+    /// Vector of IRQ 0 (ICW2)
+    pub base: u8,
+    /// Initialization word expected next (2-4), 0 outside initialization
+    init: u8,
+    single: bool,
+    needs_icw4: bool,
+    auto_eoi: bool,
+    read_isr: bool,
+}
+
+impl Pic {
+    pub fn new(base: u8) -> Self {
+        Pic {
+            irr: 0, isr: 0, imr: 0, base,
+            init: 0, single: false, needs_icw4: false, auto_eoi: false, read_isr: false,
+        }
+    }
+
+    fn write_command(&mut self, val: u8) {
+        if val & 0x10 != 0 {
+            // ICW1
+            self.init = 2;
+            self.single = val & 0x02 != 0;
+            self.needs_icw4 = val & 0x01 != 0;
+            self.irr = 0;
+            self.isr = 0;
+            self.imr = 0;
+            self.auto_eoi = false;
+            self.read_isr = false;
+        } else if val & 0x08 != 0 {
+            // OCW3: select which register the command port reads
+            if val & 0x02 != 0 {
+                self.read_isr = val & 0x01 != 0;
+            }
+        } else {
+            // OCW2: non-specific (0x20) or specific (0x60 | irq) EOI, with or
+            // without rotation, which this model doesn't do
+            match val >> 5 {
+                0b001 | 0b101 => {
+                    if let Some(irq) = highest(self.isr) {
+                        self.isr &= !(1 << irq);
+                    }
+                }
+                0b011 | 0b111 => self.isr &= !(1 << (val & 7)),
+                _ => {}
+            }
+        }
+    }
+
+    fn write_data(&mut self, val: u8) {
+        self.init = match self.init {
+            2 => {
+                self.base = val & 0xF8;
+                if !self.single { 3 } else if self.needs_icw4 { 4 } else { 0 }
+            }
+            3 => if self.needs_icw4 { 4 } else { 0 },
+            4 => {
+                self.auto_eoi = val & 0x02 != 0;
+                0
+            }
+            _ => {
+                self.imr = val;
+                0
+            }
+        };
+    }
+
+    fn read_command(&self) -> u8 {
+        if self.read_isr { self.isr } else { self.irr }
+    }
+
+    /// The request that would interrupt now: unmasked, and higher priority
+    /// than anything in service
+    fn pending(&self) -> Option<u8> {
+        let irq = highest(self.irr & !self.imr)?;
+        match highest(self.isr) {
+            Some(busy) if busy <= irq => None,
+            _ => Some(irq),
+        }
+    }
+
+    fn ack(&mut self, irq: u8) {
+        self.irr &= !(1 << irq);
+        if !self.auto_eoi {
+            self.isr |= 1 << irq;
+        }
+    }
+}
+
+/// The master/slave PIC pair of an AT
+#[derive(Debug, Clone)]
+pub struct Pics {
+    pub master: Pic,
+    pub slave: Pic,
+}
+
+impl Pics {
+    /// IRQ line of the slave on the master
+    pub const CASCADE: u8 = 2;
+
+    /// Programmed as the BIOS leaves them: vectors 0x08 and 0x70, nothing
+    /// masked
+    pub fn new() -> Self {
+        Pics { master: Pic::new(0x08), slave: Pic::new(0x70) }
+    }
+
+    /// Request IRQ 0-15. Requests are edge triggered: raising an IRQ that
+    /// is already pending does nothing more.
+    pub fn raise(&mut self, irq: u8) {
+        match irq {
+            0..=7 => self.master.irr |= 1 << irq,
+            8..=15 => self.slave.irr |= 1 << (irq - 8),
+            _ => {}
+        }
+    }
+
+    /// The vector of the IRQ the CPU should take now, marking it in
+    /// service. None if nothing is pending or everything pending is masked
+    /// or outranked.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let cascade = 1 << Self::CASCADE;
+        if self.slave.pending().is_some() {
+            self.master.irr |= cascade;
+        } else {
+            self.master.irr &= !cascade;
+        }
+
+        let irq = self.master.pending()?;
+        self.master.ack(irq);
+        if irq != Self::CASCADE {
+            return Some(self.master.base.wrapping_add(irq));
+        }
+        let irq = self.slave.pending()?;
+        self.slave.ack(irq);
+        Some(self.slave.base.wrapping_add(irq))
+    }
+
+    /// Is anything waiting that `acknowledge` would return?
+    pub fn has_pending(&self) -> bool {
+        self.master.pending().is_some() || self.slave.pending().is_some()
+    }
+
+    pub fn read(&self, port: u16) -> u8 {
+        match port {
+            0x20 => self.master.read_command(),
+            0x21 => self.master.imr,
+            0xA0 => self.slave.read_command(),
+            0xA1 => self.slave.imr,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, port: u16, val: u8) {
+        match port {
+            0x20 => self.master.write_command(val),
+            0x21 => self.master.write_data(val),
+            0xA0 => self.slave.write_command(val),
+            0xA1 => self.slave.write_data(val),
+            _ => {}
+        }
+    }
+}
+
+impl Default for Pics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// 8042 KEYBOARD CONTROLLER
+// ============================================================================
+
+const KBC_STATUS_OUTPUT_FULL: u8 = 0x01;
+const KBC_STATUS_SYSTEM: u8 = 0x04;
+const KBC_STATUS_COMMAND: u8 = 0x08;
+const KBC_STATUS_UNLOCKED: u8 = 0x10;
+
+/// Command byte: raise IRQ 1 when a byte arrives
+const KBC_CMD_IRQ1: u8 = 0x01;
+/// Command byte: keyboard clock disabled
+const KBC_CMD_KBD_DISABLED: u8 = 0x10;
+
+/// Keyboard acknowledge
+pub const KBD_ACK: u8 = 0xFA;
+
+/// 8042 keyboard controller with a keyboard attached
+#[derive(Debug, Clone)]
+pub struct Kbc {
+    /// Replies from the controller and keyboard, delivered first
+    replies: VecDeque<u8>,
+    /// Scancodes typed but not yet delivered
+    keys: VecDeque<u8>,
+    output: Option<u8>,
+    /// Controller command byte (0x20/0x60)
+    pub command_byte: u8,
+    /// Controller output port (0xD0/0xD1); bit 1 is A20
+    pub output_port: u8,
+    /// Keyboard LEDs from the last 0xED
+    pub leds: u8,
+    /// Keyboard scanning (0xF4/0xF5)
+    scanning: bool,
+    /// Controller command waiting for its data byte
+    controller_arg: Option<u8>,
+    /// Keyboard command waiting for its parameter
+    keyboard_arg: Option<u8>,
+    last_was_command: bool,
+    irq: bool,
+    reset_requested: bool,
+}
+
+impl Kbc {
+    pub fn new() -> Self {
+        Kbc {
+            replies: VecDeque::new(),
+            keys: VecDeque::new(),
+            output: None,
+            command_byte: 0x45, // IRQ 1, system flag, scancode translation
+            output_port: 0x03,  // not in reset, A20 on
+            leds: 0,
+            scanning: true,
+            controller_arg: None,
+            keyboard_arg: None,
+            last_was_command: false,
+            irq: false,
+            reset_requested: false,
+        }
+    }
+
+    /// A scancode from the host keyboard
+    pub fn push_scancode(&mut self, code: u8) {
+        self.keys.push_back(code);
+        self.fill();
+    }
+
+    /// Did the program ask the controller to reset the CPU (command 0xFE)?
+    /// Reading clears it.
+    pub fn take_reset(&mut self) -> bool {
+        core::mem::take(&mut self.reset_requested)
+    }
+
+    fn keyboard_enabled(&self) -> bool {
+        self.scanning && self.command_byte & KBC_CMD_KBD_DISABLED == 0
+    }
+
+    /// Move the next byte into the empty output buffer
+    fn fill(&mut self) {
+        if self.output.is_some() {
+            return;
+        }
+        let next = match self.replies.pop_front() {
+            Some(b) => Some(b),
+            None if self.keyboard_enabled() => self.keys.pop_front(),
+            None => None,
+        };
+        if let Some(b) = next {
+            self.output = Some(b);
+            self.irq |= self.command_byte & KBC_CMD_IRQ1 != 0;
+        }
+    }
+
+    fn reply(&mut self, bytes: &[u8]) {
+        self.replies.extend(bytes);
+        self.fill();
+    }
+
+    /// Whether a byte arrived that should raise IRQ 1. Reading clears it.
+    pub fn take_irq(&mut self) -> bool {
+        core::mem::take(&mut self.irq)
+    }
+
+    /// Port 0x64
+    pub fn status(&self) -> u8 {
+        let mut status = KBC_STATUS_UNLOCKED;
+        if self.output.is_some() {
+            status |= KBC_STATUS_OUTPUT_FULL;
+        }
+        if self.command_byte & KBC_STATUS_SYSTEM != 0 {
+            status |= KBC_STATUS_SYSTEM;
+        }
+        if self.last_was_command {
+            status |= KBC_STATUS_COMMAND;
+        }
+        status
+    }
+
+    /// Port 0x60 read
+    pub fn read_data(&mut self) -> u8 {
+        match self.output.take() {
+            Some(b) => {
+                self.fill();
+                b
+            }
+            None => 0,
+        }
+    }
+
+    /// Port 0x64 write
+    pub fn write_command(&mut self, cmd: u8) {
+        self.last_was_command = true;
+        match cmd {
+            0x20 => self.reply(&[self.command_byte]),
+            0x60 | 0xD1 => self.controller_arg = Some(cmd),
+            0xAA => self.reply(&[0x55]), // self test passed
+            0xAB => self.reply(&[0x00]), // keyboard interface OK
+            0xAD => self.command_byte |= KBC_CMD_KBD_DISABLED,
+            0xAE => {
+                self.command_byte &= !KBC_CMD_KBD_DISABLED;
+                self.fill();
+            }
+            0xD0 => self.reply(&[self.output_port]),
+            // Pulse output port bit 0, which is wired to CPU reset
+            0xF0..=0xFF if cmd & 1 == 0 => self.reset_requested = true,
+            _ => {}
+        }
+    }
+
+    /// Port 0x60 write: a controller command's data, or a keyboard command
+    pub fn write_data(&mut self, val: u8) {
+        self.last_was_command = false;
+        match self.controller_arg.take() {
+            Some(0x60) => {
+                self.command_byte = val;
+                self.fill();
+                return;
+            }
+            Some(0xD1) => {
+                self.output_port = val;
+                if val & 1 == 0 {
+                    self.reset_requested = true;
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        if let Some(cmd) = self.keyboard_arg.take() {
+            if cmd == 0xED {
+                self.leds = val & 7;
+            }
+            self.reply(&[KBD_ACK]);
+            return;
+        }
+        match val {
+            0xED | 0xF3 => {
+                self.keyboard_arg = Some(val);
+                self.reply(&[KBD_ACK]);
+            }
+            0xEE => self.reply(&[0xEE]),
+            0xF2 => self.reply(&[KBD_ACK, 0xAB, 0x83]),
+            0xF4 => {
+                self.scanning = true;
+                self.reply(&[KBD_ACK]);
+            }
+            0xF5 => {
+                self.scanning = false;
+                self.reply(&[KBD_ACK]);
+            }
+            0xFF => {
+                self.keys.clear();
+                self.scanning = true;
+                self.reply(&[KBD_ACK, 0xAA]);
+            }
+            _ => self.reply(&[KBD_ACK]),
+        }
+    }
+}
+
+impl Default for Kbc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// PORT DISPATCH
+// ============================================================================
+
+/// The devices on the emulated I/O bus
+#[derive(Debug, Clone)]
+pub struct Devices {
+    pub pit: Pit,
+    pub pics: Pics,
+    pub kbc: Kbc,
+    /// Port 0x61: bit 0 gates PIT channel 2, bit 1 drives the speaker
+    pub port_b: u8,
+    /// PIT clocks each instruction advances time by; 0 leaves it to the
+    /// host
+    pub clocks_per_step: u32,
+    /// Port 0x61 bit 4, which toggles with DRAM refresh and is used for
+    /// delay loops
+    refresh: bool,
+}
+
+impl Devices {
+    pub fn new() -> Self {
+        Devices {
+            pit: Pit::new(),
+            pics: Pics::new(),
+            kbc: Kbc::new(),
+            port_b: 0,
+            clocks_per_step: 0,
+            refresh: false,
+        }
+    }
+
+    /// Advance time by `clocks` PIT input clocks
+    pub fn advance(&mut self, clocks: u32) {
+        if self.pit.advance(clocks) > 0 {
+            self.pics.raise(0);
+        }
+    }
+
+    /// A scancode from the host keyboard
+    pub fn key(&mut self, code: u8) {
+        self.kbc.push_scancode(code);
+        self.sync_irqs();
+    }
+
+    fn sync_irqs(&mut self) {
+        if self.kbc.take_irq() {
+            self.pics.raise(1);
+        }
+    }
+
+    pub fn port_in(&mut self, port: u16) -> u8 {
+        let val = match port {
+            0x20 | 0x21 | 0xA0 | 0xA1 => self.pics.read(port),
+            0x40..=0x43 => self.pit.read(port),
+            0x60 => self.kbc.read_data(),
+            0x61 => {
+                self.refresh = !self.refresh;
+                let mut val = self.port_b & 0x0F;
+                if self.refresh {
+                    val |= 0x10;
+                }
+                if self.pit.output(2) {
+                    val |= 0x20;
+                }
+                val
+            }
+            0x64 => self.kbc.status(),
+            _ => 0xFF,
+        };
+        self.sync_irqs();
+        val
+    }
+
+    pub fn port_out(&mut self, port: u16, val: u8) {
+        match port {
+            0x20 | 0x21 | 0xA0 | 0xA1 => self.pics.write(port, val),
+            0x40..=0x43 => self.pit.write(port, val),
+            0x60 => self.kbc.write_data(val),
+            0x61 => {
+                self.port_b = val;
+                self.pit.gate2 = val & 1 != 0;
+            }
+            0x64 => self.kbc.write_command(val),
+            _ => {}
+        }
+        self.sync_irqs();
+    }
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,499 @@
+//! Intel-syntax disassembler
+//!
+//! Renders 8086 machine code the way NASM prints it, for tracers, the
+//! debugger front-end, and tests that want to see what the emulator was
+//! looking at when it stopped on an opcode it doesn't implement. It knows
+//! the whole 8086 set (plus the 186 PUSHA/POPA/PUSH imm and shift-by-imm
+//! forms), not just the part [`Emulator::step`](crate::Emulator::step)
+//! executes; anything else comes out as a `db` of its first byte.
+//!
+//! ```text
+//! 0000:0100  B8 34 12          mov ax, 0x1234
+//! 0000:0103  26 88 47 FE       mov [es:bx-0x2], al
+//! 0000:0107  F3 A4             rep movsb
+//! ```
+//!
+//! The ModR/M split and the register and addressing-mode tables here are
+//! the ones the executor decodes operands with.
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::{format, string::String, vec::Vec};
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::String, vec::Vec};
+
+/// Longest instruction the decoder will look at (the CPU's limit too)
+pub const MAX_INSN_LEN: usize = 15;
+
+/// 8-bit registers by ModR/M index
+pub const REG8_NAMES: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+
+/// 16-bit registers by ModR/M index
+pub const REG16_NAMES: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+
+/// Segment registers by index (ModR/M reg field, override prefix bits 3-4)
+pub const SEG_NAMES: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+/// Memory operand base by r/m field (r/m 6 with mod 0 is a bare disp16)
+pub const EA_NAMES: [&str; 8] = ["bx+si", "bx+di", "bp+si", "bp+di", "si", "di", "bp", "bx"];
+
+const ALU_NAMES: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFT_NAMES: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+const GROUP3_NAMES: [&str; 8] = ["test", "test", "not", "neg", "mul", "imul", "div", "idiv"];
+const JCC_NAMES: [&str; 16] = [
+    "jo", "jno", "jc", "jnc", "jz", "jnz", "jna", "ja",
+    "js", "jns", "jpe", "jpo", "jl", "jnl", "jng", "jg",
+];
+// 0xA4..=0xAF, with TEST AL/AX, imm at 0xA8/0xA9 handled separately
+const STRING_NAMES: [&str; 12] = [
+    "movsb", "movsw", "cmpsb", "cmpsw", "", "", "stosb", "stosw", "lodsb", "lodsw", "scasb", "scasw",
+];
+
+/// A ModR/M byte split into its fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModRm {
+    pub mode: u8,
+    pub reg: u8,
+    pub rm: u8,
+}
+
+impl ModRm {
+    pub fn new(byte: u8) -> Self {
+        ModRm { mode: (byte >> 6) & 3, reg: (byte >> 3) & 7, rm: byte & 7 }
+    }
+
+    /// Does the r/m field name memory rather than a register?
+    pub fn is_mem(self) -> bool {
+        self.mode != 3
+    }
+
+    /// Displacement bytes following the ModR/M byte
+    pub fn disp_len(self) -> usize {
+        match self.mode {
+            0 if self.rm == 6 => 2,
+            1 => 1,
+            2 => 2,
+            _ => 0,
+        }
+    }
+}
+
+fn reg_name(wide: bool, idx: u8) -> &'static str {
+    if wide { REG16_NAMES[(idx & 7) as usize] } else { REG8_NAMES[(idx & 7) as usize] }
+}
+
+/// One decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub seg: u16,
+    pub off: u16,
+    /// The instruction's bytes, prefixes included
+    pub bytes: Vec<u8>,
+    /// Intel-syntax text, e.g. `mov [es:bx+0x4], al`
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// A near or far CALL, whichever prefixes it has
+    pub fn is_call(&self) -> bool {
+        self.text
+            .split(' ')
+            .find(|w| !SEG_NAMES.contains(w) && *w != "lock")
+            == Some("call")
+    }
+
+    /// Offset of the following instruction
+    pub fn next_off(&self) -> u16 {
+        self.off.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}:{:04X}  ", self.seg, self.off)?;
+        let mut width = 0;
+        for b in &self.bytes {
+            write!(f, "{:02X} ", b)?;
+            width += 3;
+        }
+        // Six bytes covers everything but heavily prefixed instructions
+        for _ in width..18 {
+            f.write_str(" ")?;
+        }
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Decode the instruction at the start of `code`, which was fetched from
+/// `seg:off`. Bytes past the end of `code` are treated as missing, and an
+/// instruction that needs them comes out as `db`.
+pub fn decode(code: &[u8], seg: u16, off: u16) -> Instruction {
+    let code = &code[..code.len().min(MAX_INSN_LEN)];
+    let mut d = Decoder { code, pos: 0, off, seg: None, seg_used: false };
+    let text = match d.instruction() {
+        Some(text) if d.pos <= code.len() => text,
+        _ => {
+            d.pos = 1;
+            format!("db 0x{:02x}", code.first().copied().unwrap_or(0))
+        }
+    };
+    Instruction { seg, off, bytes: Vec::from(&code[..d.pos.min(code.len())]), text }
+}
+
+struct Decoder<'a> {
+    code: &'a [u8],
+    pos: usize,
+    off: u16,
+    /// Segment override prefix, as a SEG_NAMES index
+    seg: Option<u8>,
+    /// Whether a memory operand consumed the override
+    seg_used: bool,
+}
+
+impl Decoder<'_> {
+    fn u8(&mut self) -> u8 {
+        let b = self.code.get(self.pos).copied().unwrap_or(0);
+        self.pos += 1;
+        b
+    }
+
+    fn u16(&mut self) -> u16 {
+        let lo = self.u8() as u16;
+        let hi = self.u8() as u16;
+        lo | (hi << 8)
+    }
+
+    fn modrm(&mut self) -> ModRm {
+        ModRm::new(self.u8())
+    }
+
+    fn imm8(&mut self) -> String {
+        format!("0x{:x}", self.u8())
+    }
+
+    fn imm16(&mut self) -> String {
+        format!("0x{:x}", self.u16())
+    }
+
+    /// A sign-extended imm8, NASM style
+    fn simm8(&mut self) -> String {
+        let v = self.u8() as i8;
+        if v < 0 { format!("byte -0x{:x}", v.unsigned_abs()) } else { format!("byte +0x{:x}", v) }
+    }
+
+    /// Target of a relative branch, which counts from the next instruction
+    fn rel8(&mut self) -> String {
+        let rel = self.u8() as i8 as i16 as u16;
+        format!("0x{:x}", self.off.wrapping_add(self.pos as u16).wrapping_add(rel))
+    }
+
+    fn rel16(&mut self) -> String {
+        let rel = self.u16();
+        format!("0x{:x}", self.off.wrapping_add(self.pos as u16).wrapping_add(rel))
+    }
+
+    fn far_ptr(&mut self) -> String {
+        let off = self.u16();
+        let seg = self.u16();
+        format!("0x{:x}:0x{:x}", seg, off)
+    }
+
+    /// `[seg:addr]` with any override prefix folded in
+    fn mem(&mut self, addr: String, size: &str) -> String {
+        let seg = match self.seg {
+            Some(s) => {
+                self.seg_used = true;
+                format!("{}:", SEG_NAMES[s as usize])
+            }
+            None => String::new(),
+        };
+        format!("{}[{}{}]", size, seg, addr)
+    }
+
+    /// The r/m operand. `sized` adds `byte`/`word` to memory operands when
+    /// no register operand gives the size away.
+    fn ea(&mut self, m: ModRm, wide: bool, sized: bool) -> String {
+        if !m.is_mem() {
+            return String::from(reg_name(wide, m.rm));
+        }
+        let base = EA_NAMES[m.rm as usize];
+        let addr = match (m.mode, m.disp_len()) {
+            (0, 2) => self.imm16(),
+            (_, 1) => {
+                let d = self.u8() as i8;
+                if d < 0 {
+                    format!("{}-0x{:x}", base, d.unsigned_abs())
+                } else {
+                    format!("{}+0x{:x}", base, d)
+                }
+            }
+            (_, 2) => format!("{}+0x{:x}", base, self.u16()),
+            _ => String::from(base),
+        };
+        let size = match (sized, wide) {
+            (false, _) => "",
+            (true, true) => "word ",
+            (true, false) => "byte ",
+        };
+        self.mem(addr, size)
+    }
+
+    /// `op r/m, reg` or `op reg, r/m`: bit 0 of `form` is the width, bit 1
+    /// the direction
+    fn rm_reg(&mut self, name: &str, form: u8) -> String {
+        let wide = form & 1 != 0;
+        let m = self.modrm();
+        let reg = reg_name(wide, m.reg);
+        let rm = self.ea(m, wide, false);
+        if form & 2 != 0 {
+            format!("{} {}, {}", name, reg, rm)
+        } else {
+            format!("{} {}, {}", name, rm, reg)
+        }
+    }
+
+    fn acc_imm(&mut self, name: &str, wide: bool) -> String {
+        if wide {
+            format!("{} ax, {}", name, self.imm16())
+        } else {
+            format!("{} al, {}", name, self.imm8())
+        }
+    }
+
+    /// Prefixes, then the instruction itself. `None` for opcodes the 8086
+    /// doesn't define.
+    fn instruction(&mut self) -> Option<String> {
+        let mut rep = "";
+        let op = loop {
+            match self.u8() {
+                op @ (0x26 | 0x2E | 0x36 | 0x3E) => self.seg = Some((op >> 3) & 3),
+                0xF0 => rep = "lock ",
+                0xF2 => rep = "repne ",
+                0xF3 => rep = "rep ",
+                op => break op,
+            }
+            if self.pos >= self.code.len() {
+                return None;
+            }
+        };
+
+        let text = self.opcode(op)?;
+        if rep == "rep " && (text.starts_with("cmps") || text.starts_with("scas")) {
+            rep = "repe ";
+        }
+        let seg = match self.seg {
+            Some(s) if !self.seg_used => format!("{} ", SEG_NAMES[s as usize]),
+            _ => String::new(),
+        };
+        Some(format!("{}{}{}", seg, rep, text))
+    }
+
+    fn opcode(&mut self, op: u8) -> Option<String> {
+        let text = match op {
+            // ALU ops in their six standard encodings
+            0x00..=0x3F if op & 7 < 4 => self.rm_reg(ALU_NAMES[(op >> 3) as usize], op & 3),
+            0x00..=0x3F if op & 7 < 6 => self.acc_imm(ALU_NAMES[(op >> 3) as usize], op & 1 != 0),
+
+            0x06 | 0x0E | 0x16 | 0x1E => format!("push {}", SEG_NAMES[(op >> 3) as usize]),
+            0x07 | 0x0F | 0x17 | 0x1F => format!("pop {}", SEG_NAMES[(op >> 3) as usize]),
+            0x27 => "daa".into(),
+            0x2F => "das".into(),
+            0x37 => "aaa".into(),
+            0x3F => "aas".into(),
+
+            0x40..=0x5F => {
+                let name = ["inc", "dec", "push", "pop"][((op - 0x40) >> 3) as usize];
+                format!("{} {}", name, REG16_NAMES[(op & 7) as usize])
+            }
+
+            0x60 => "pusha".into(),
+            0x61 => "popa".into(),
+            0x68 => format!("push {}", self.imm16()),
+            0x6A => format!("push {}", self.simm8()),
+
+            0x70..=0x7F => format!("{} {}", JCC_NAMES[(op & 0xF) as usize], self.rel8()),
+
+            // Group 1: ALU op r/m, imm
+            0x80..=0x83 => {
+                let m = self.modrm();
+                let wide = op & 1 != 0;
+                let rm = self.ea(m, wide, true);
+                let imm = match op {
+                    0x81 => self.imm16(),
+                    0x83 => self.simm8(),
+                    _ => self.imm8(),
+                };
+                format!("{} {}, {}", ALU_NAMES[m.reg as usize], rm, imm)
+            }
+
+            0x84 | 0x85 => self.rm_reg("test", op & 1),
+            0x86 | 0x87 => self.rm_reg("xchg", op & 1),
+            0x88..=0x8B => self.rm_reg("mov", op & 3),
+            0x8C | 0x8E => {
+                let m = self.modrm();
+                let sreg = SEG_NAMES[(m.reg & 3) as usize];
+                let rm = self.ea(m, true, false);
+                if op == 0x8C { format!("mov {}, {}", rm, sreg) } else { format!("mov {}, {}", sreg, rm) }
+            }
+            0x8D | 0xC4 | 0xC5 => {
+                let m = self.modrm();
+                if !m.is_mem() {
+                    return None;
+                }
+                let name = match op {
+                    0x8D => "lea",
+                    0xC4 => "les",
+                    _ => "lds",
+                };
+                format!("{} {}, {}", name, REG16_NAMES[m.reg as usize], self.ea(m, true, false))
+            }
+            0x8F => {
+                let m = self.modrm();
+                format!("pop {}", self.ea(m, true, true))
+            }
+
+            0x90 => "nop".into(),
+            0x91..=0x97 => format!("xchg ax, {}", REG16_NAMES[(op & 7) as usize]),
+            0x98 => "cbw".into(),
+            0x99 => "cwd".into(),
+            0x9A => format!("call {}", self.far_ptr()),
+            0x9B => "wait".into(),
+            0x9C => "pushf".into(),
+            0x9D => "popf".into(),
+            0x9E => "sahf".into(),
+            0x9F => "lahf".into(),
+
+            0xA0..=0xA3 => {
+                let addr = self.imm16();
+                let mem = self.mem(addr, "");
+                let acc = if op & 1 != 0 { "ax" } else { "al" };
+                if op & 2 != 0 { format!("mov {}, {}", mem, acc) } else { format!("mov {}, {}", acc, mem) }
+            }
+            0xA8 | 0xA9 => self.acc_imm("test", op & 1 != 0),
+            0xA4..=0xAF => String::from(STRING_NAMES[(op - 0xA4) as usize]),
+
+            0xB0..=0xB7 => format!("mov {}, {}", REG8_NAMES[(op & 7) as usize], self.imm8()),
+            0xB8..=0xBF => format!("mov {}, {}", REG16_NAMES[(op & 7) as usize], self.imm16()),
+
+            // Group 2: shifts and rotates
+            0xC0 | 0xC1 | 0xD0..=0xD3 => {
+                let m = self.modrm();
+                let wide = op & 1 != 0;
+                let rm = self.ea(m, wide, true);
+                let count = match op {
+                    0xC0 | 0xC1 => self.imm8(),
+                    0xD0 | 0xD1 => "1".into(),
+                    _ => "cl".into(),
+                };
+                format!("{} {}, {}", SHIFT_NAMES[m.reg as usize], rm, count)
+            }
+
+            0xC2 => format!("ret {}", self.imm16()),
+            0xC3 => "ret".into(),
+            0xC6 | 0xC7 => {
+                let m = self.modrm();
+                let wide = op & 1 != 0;
+                let rm = self.ea(m, wide, true);
+                let imm = if wide { self.imm16() } else { self.imm8() };
+                format!("mov {}, {}", rm, imm)
+            }
+            0xCA => format!("retf {}", self.imm16()),
+            0xCB => "retf".into(),
+            0xCC => "int3".into(),
+            0xCD => format!("int {}", self.imm8()),
+            0xCE => "into".into(),
+            0xCF => "iret".into(),
+
+            0xD4 | 0xD5 => {
+                let name = if op == 0xD4 { "aam" } else { "aad" };
+                match self.u8() {
+                    0x0A => String::from(name),
+                    base => format!("{} 0x{:x}", name, base),
+                }
+            }
+            0xD6 => "salc".into(),
+            0xD7 => "xlatb".into(),
+            // ESC: coprocessor instructions, shown as the raw escape code
+            0xD8..=0xDF => {
+                let m = self.modrm();
+                let code = ((op & 7) << 3) | m.reg;
+                format!("esc 0x{:x}, {}", code, self.ea(m, true, false))
+            }
+
+            0xE0 => format!("loopne {}", self.rel8()),
+            0xE1 => format!("loope {}", self.rel8()),
+            0xE2 => format!("loop {}", self.rel8()),
+            0xE3 => format!("jcxz {}", self.rel8()),
+            0xE4 | 0xE5 => format!("in {}, {}", reg_name(op & 1 != 0, 0), self.imm8()),
+            0xE6 | 0xE7 => format!("out {}, {}", self.imm8(), reg_name(op & 1 != 0, 0)),
+            0xE8 => format!("call {}", self.rel16()),
+            0xE9 => format!("jmp {}", self.rel16()),
+            0xEA => format!("jmp {}", self.far_ptr()),
+            0xEB => format!("jmp short {}", self.rel8()),
+            0xEC | 0xED => format!("in {}, dx", reg_name(op & 1 != 0, 0)),
+            0xEE | 0xEF => format!("out dx, {}", reg_name(op & 1 != 0, 0)),
+
+            0xF4 => "hlt".into(),
+            0xF5 => "cmc".into(),
+            // Group 3: TEST/NOT/NEG/MUL/IMUL/DIV/IDIV
+            0xF6 | 0xF7 => {
+                let m = self.modrm();
+                let wide = op & 1 != 0;
+                let rm = self.ea(m, wide, true);
+                let name = GROUP3_NAMES[m.reg as usize];
+                if m.reg < 2 {
+                    let imm = if wide { self.imm16() } else { self.imm8() };
+                    format!("{} {}, {}", name, rm, imm)
+                } else {
+                    format!("{} {}", name, rm)
+                }
+            }
+            0xF8 => "clc".into(),
+            0xF9 => "stc".into(),
+            0xFA => "cli".into(),
+            0xFB => "sti".into(),
+            0xFC => "cld".into(),
+            0xFD => "std".into(),
+
+            // Group 4: INC/DEC r/m8
+            0xFE => {
+                let m = self.modrm();
+                let name = match m.reg {
+                    0 => "inc",
+                    1 => "dec",
+                    _ => return None,
+                };
+                format!("{} {}", name, self.ea(m, false, true))
+            }
+            // Group 5: INC/DEC/CALL/JMP/PUSH r/m16
+            0xFF => {
+                let m = self.modrm();
+                match m.reg {
+                    3 | 5 if !m.is_mem() => return None,
+                    3 | 5 => {
+                        let name = if m.reg == 3 { "call" } else { "jmp" };
+                        format!("{} far {}", name, self.ea(m, true, false))
+                    }
+                    7 => return None,
+                    _ => {
+                        let name = ["inc", "dec", "call", "", "jmp", "", "push"][m.reg as usize];
+                        format!("{} {}", name, self.ea(m, true, true))
+                    }
+                }
+            }
+
+            _ => return None,
+        };
+        Some(text)
+    }
+}
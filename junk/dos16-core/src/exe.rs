@@ -0,0 +1,282 @@
+//! MZ executables
+//!
+//! Loading follows DOS's EXEC: the program gets a block of conventional
+//! memory starting with its PSP, sized between what the image plus
+//! `min_alloc` needs and what it plus `max_alloc` asks for, and the image
+//! goes right after the PSP (or at the top of the block, for a header
+//! with both allocation fields zero). Segment fixups add the load segment.
+//! The PSP itself is left to the caller apart from its first two fields,
+//! INT 20h and the segment past the block, which programs read to size
+//! their memory.
+//!
+//! Overlays load the way EXEC function 03h does: the image only, at a
+//! segment the caller picks, fixed up by a caller-supplied factor, with no
+//! PSP and no registers changed. Data appended after the image (the
+//! overlays of Borland-style linkers) isn't loaded;
+//! [`ExeHeader::image_end`] says where it starts.
+
+use core::fmt;
+
+use crate::Emulator;
+
+/// Paragraphs in a PSP
+pub const PSP_PARAS: u32 = 0x10;
+
+/// Size of the fixed part of the header
+const HEADER_LEN: usize = 28;
+
+/// Why an EXE couldn't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExeError {
+    /// Shorter than an MZ header
+    TooSmall,
+    /// No MZ (or ZM) signature
+    BadMagic,
+    /// The header claims more paragraphs than the file has
+    HeaderTruncated,
+    /// The relocation table runs past the end of the file
+    BadRelocTable,
+    /// Relocation `index` points outside the loaded program
+    BadRelocation { index: usize },
+    /// The program needs more paragraphs than are free
+    NotEnoughMemory { needed: u32, available: u32 },
+    /// The initial SS:SP isn't inside the program's memory
+    BadStack { ss: u16, sp: u16 },
+    /// The entry point isn't inside the program's memory
+    BadEntry { cs: u16, ip: u16 },
+}
+
+impl fmt::Display for ExeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExeError::TooSmall => f.write_str("EXE too small"),
+            ExeError::BadMagic => f.write_str("Not an MZ executable"),
+            ExeError::HeaderTruncated => f.write_str("EXE header runs past end of file"),
+            ExeError::BadRelocTable => f.write_str("Relocation table runs past end of file"),
+            ExeError::BadRelocation { index } => write!(f, "Relocation {} outside program", index),
+            ExeError::NotEnoughMemory { needed, available } => write!(
+                f,
+                "Not enough memory: {:#x} paragraphs needed, {:#x} free",
+                needed, available
+            ),
+            ExeError::BadStack { ss, sp } => write!(f, "Stack {:04X}:{:04X} outside program", ss, sp),
+            ExeError::BadEntry { cs, ip } => write!(f, "Entry point {:04X}:{:04X} outside program", cs, ip),
+        }
+    }
+}
+
+/// The fixed part of an MZ header. Segments are relative to the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExeHeader {
+    /// Bytes used in the last 512-byte page, 0 for all of it
+    pub last_page_size: u16,
+    pub page_count: u16,
+    pub reloc_count: u16,
+    pub header_paras: u16,
+    /// Paragraphs needed past the image (.bss and stack)
+    pub min_alloc: u16,
+    /// Paragraphs wanted past the image
+    pub max_alloc: u16,
+    pub init_ss: u16,
+    pub init_sp: u16,
+    pub checksum: u16,
+    pub init_ip: u16,
+    pub init_cs: u16,
+    pub reloc_offset: u16,
+    /// 0 for a main program
+    pub overlay: u16,
+}
+
+impl ExeHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, ExeError> {
+        if data.len() < HEADER_LEN {
+            return Err(ExeError::TooSmall);
+        }
+        // Some early linkers wrote the signature backwards
+        if &data[0..2] != b"MZ" && &data[0..2] != b"ZM" {
+            return Err(ExeError::BadMagic);
+        }
+        let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let header = ExeHeader {
+            last_page_size: word(2),
+            page_count: word(4),
+            reloc_count: word(6),
+            header_paras: word(8),
+            min_alloc: word(10),
+            max_alloc: word(12),
+            init_ss: word(14),
+            init_sp: word(16),
+            checksum: word(18),
+            init_ip: word(20),
+            init_cs: word(22),
+            reloc_offset: word(24),
+            overlay: word(26),
+        };
+        if header.header_size() > data.len() {
+            return Err(ExeError::HeaderTruncated);
+        }
+        let table_end = header.reloc_offset as usize + header.reloc_count as usize * 4;
+        if header.reloc_count != 0 && table_end > data.len() {
+            return Err(ExeError::BadRelocTable);
+        }
+        Ok(header)
+    }
+
+    pub fn header_size(&self) -> usize {
+        self.header_paras as usize * 16
+    }
+
+    /// Offset in the file just past the image, where appended overlay data
+    /// starts
+    pub fn image_end(&self) -> usize {
+        let pages = self.page_count as usize * 512;
+        // A last page size over 512 is nonsense DOS treats as a full page
+        match self.last_page_size {
+            0 | 512.. => pages,
+            n => pages.saturating_sub(512) + n as usize,
+        }
+    }
+
+    /// Bytes of program image: the file from the header to `image_end`
+    pub fn image_size(&self) -> usize {
+        self.image_end().saturating_sub(self.header_size())
+    }
+
+    /// The image, as much of it as `data` holds
+    pub fn image<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        let end = self.image_end().min(data.len());
+        &data[self.header_size().min(end)..end]
+    }
+
+    /// Image paragraphs, rounded up
+    pub fn image_paras(&self) -> u32 {
+        self.image_size().div_ceil(16) as u32
+    }
+
+    /// Relocation entries as (offset, segment) within the image
+    pub fn relocations<'a>(&self, data: &'a [u8]) -> impl Iterator<Item = (u16, u16)> + 'a {
+        let start = self.reloc_offset as usize;
+        let table = data.get(start..start + self.reloc_count as usize * 4).unwrap_or(&[]);
+        table.chunks_exact(4).map(|e| {
+            (u16::from_le_bytes([e[0], e[1]]), u16::from_le_bytes([e[2], e[3]]))
+        })
+    }
+}
+
+/// Where to put a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExeOptions {
+    /// Segment of the PSP, the start of the program's memory
+    pub psp_seg: u16,
+    /// First paragraph past the memory the program may have, None for the
+    /// end of conventional memory (0xA000). Never past the end of `memory`.
+    pub mem_top: Option<u16>,
+}
+
+/// Where a program was put
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExeLoad {
+    pub header: ExeHeader,
+    pub psp_seg: u16,
+    /// Segment of the image, the fixup factor
+    pub load_seg: u16,
+    /// First paragraph past the program's memory
+    pub end_seg: u16,
+}
+
+impl Emulator {
+    /// Load an MZ EXE with its PSP at segment 0
+    pub fn load_exe(&mut self, data: &[u8]) -> Result<ExeLoad, ExeError> {
+        self.load_exe_with(data, &ExeOptions::default())
+    }
+
+    /// Load an MZ EXE and point the registers at its entry point: CS:IP and
+    /// SS:SP from the header, DS and ES at the PSP
+    pub fn load_exe_with(&mut self, data: &[u8], options: &ExeOptions) -> Result<ExeLoad, ExeError> {
+        let header = ExeHeader::parse(data)?;
+        let psp = options.psp_seg as u32;
+        let installed = (self.memory.len() / 16) as u32;
+        let top = installed.min(options.mem_top.map_or(0xA000, |top| top as u32));
+
+        // Negotiate the block: at least image + min_alloc, at most
+        // image + max_alloc, as much of that as there is
+        let available = top.saturating_sub(psp);
+        let base = PSP_PARAS + header.image_paras();
+        let needed = base + header.min_alloc as u32;
+        if needed > available {
+            return Err(ExeError::NotEnoughMemory { needed, available });
+        }
+        let load_high = header.min_alloc == 0 && header.max_alloc == 0;
+        let paras = if load_high { available } else { available.min(base + header.max_alloc as u32) };
+        let end = psp + paras;
+        let load_seg = if load_high { end - header.image_paras() } else { psp + PSP_PARAS };
+
+        let fits = |seg: u16, off: u16, len: u32| {
+            let start = ((load_seg + seg as u32) & 0xFFFF) * 16 + off as u32;
+            start >= (psp + PSP_PARAS) * 16 && start + len <= end * 16
+        };
+        // SP 0 is the top of a full 64K segment
+        let stack_len = if header.init_sp == 0 { 0x10000 } else { 0 };
+        if !fits(header.init_ss, header.init_sp, stack_len) {
+            return Err(ExeError::BadStack { ss: header.init_ss, sp: header.init_sp });
+        }
+        if !fits(header.init_cs, header.init_ip, 1) {
+            return Err(ExeError::BadEntry { cs: header.init_cs, ip: header.init_ip });
+        }
+
+        self.memory[psp as usize * 16..end as usize * 16].fill(0);
+        self.place_image(data, &header, load_seg as u16, load_seg as u16, end)?;
+        // INT 20h, and the segment past the block
+        self.memory[psp as usize * 16..psp as usize * 16 + 4]
+            .copy_from_slice(&[0xCD, 0x20, end as u8, (end >> 8) as u8]);
+
+        let load_seg = load_seg as u16;
+        self.cpu.cs = load_seg.wrapping_add(header.init_cs);
+        self.cpu.ip = header.init_ip;
+        self.cpu.ss = load_seg.wrapping_add(header.init_ss);
+        self.cpu.sp = header.init_sp;
+        self.cpu.ds = psp as u16;
+        self.cpu.es = psp as u16;
+
+        Ok(ExeLoad { header, psp_seg: psp as u16, load_seg, end_seg: end as u16 })
+    }
+
+    /// Load an EXE's image at `seg` as an overlay, fixing segments up by
+    /// `reloc_factor`. Registers are untouched.
+    pub fn load_overlay(&mut self, data: &[u8], seg: u16, reloc_factor: u16) -> Result<ExeHeader, ExeError> {
+        let header = ExeHeader::parse(data)?;
+        let top = (self.memory.len() / 16) as u32;
+        let needed = header.image_paras();
+        let available = top.saturating_sub(seg as u32);
+        if needed > available {
+            return Err(ExeError::NotEnoughMemory { needed, available });
+        }
+        self.place_image(data, &header, seg, reloc_factor, seg as u32 + needed)?;
+        Ok(header)
+    }
+
+    /// Copy the image to `seg`:0 and apply its fixups, none of which may
+    /// reach `end` (a paragraph)
+    fn place_image(
+        &mut self,
+        data: &[u8],
+        header: &ExeHeader,
+        seg: u16,
+        reloc_factor: u16,
+        end: u32,
+    ) -> Result<(), ExeError> {
+        let image = header.image(data);
+        let start = seg as usize * 16;
+        self.memory[start..start + image.len()].copy_from_slice(image);
+
+        for (index, (off, rseg)) in header.relocations(data).enumerate() {
+            let addr = seg as usize * 16 + rseg as usize * 16 + off as usize;
+            if addr + 2 > end as usize * 16 {
+                return Err(ExeError::BadRelocation { index });
+            }
+            let val = u16::from_le_bytes([self.memory[addr], self.memory[addr + 1]]);
+            self.memory[addr..addr + 2].copy_from_slice(&val.wrapping_add(reloc_factor).to_le_bytes());
+        }
+        Ok(())
+    }
+}
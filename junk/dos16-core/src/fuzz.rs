@@ -0,0 +1,755 @@
+//! Differential fuzzing
+//!
+//! Random instruction streams are run through the [`Emulator`] and through
+//! a small reference model side by side, one instruction at a time, and
+//! registers, flags and memory are compared after every step. The model is
+//! written independently of the emulator: it never decodes bytes, it
+//! interprets the description the generator encoded them from, and it
+//! works flags out from wide signed and unsigned arithmetic instead of the
+//! emulator's bit tricks. Where the two disagree, a [`Mismatch`] names the
+//! instruction and the differences.
+//!
+//! The instruction mix is the part of the emulator that only touches
+//! registers and memory: the ALU ops in all their forms, INC/DEC, MOV, LEA,
+//! XCHG, PUSH/POP, CBW/CWD, LAHF/SAHF, CLC/STC and single string moves,
+//! with every addressing mode and segment override. The model follows the
+//! 80286 where the 8086 family differs (PUSH SP pushes the old SP). Flags
+//! the CPU leaves undefined, AF after a logic op, aren't compared, and the
+//! emulator's values are copied into the model so they can't cause a
+//! difference later.
+//!
+//! A failure is reproduced with [`check_case`] and the seed in the report.
+
+use std::boxed::Box;
+use std::fmt;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::{disasm, Cpu16, Emulator, StepResult};
+use crate::{FLAG_AF, FLAG_CF, FLAG_DF, FLAG_OF, FLAG_PF, FLAG_SF, FLAG_ZF};
+
+/// Instructions in each case
+pub const CASE_LEN: usize = 8;
+
+/// Where case code runs, above all data. Data segments are below
+/// `DATA_SEGS`, so no access reaches the code.
+const CODE_SEG: u16 = 0x2000;
+const DATA_SEGS: u16 = 0x1000;
+const MEMORY_SIZE: usize = 0x30000;
+
+/// Segment override prefixes by segment register index (ES, CS, SS, DS).
+/// CS is never generated: a write through it could land on the code.
+const SEG_PREFIXES: [u8; 4] = [0x26, 0x2E, 0x36, 0x3E];
+const OVERRIDES: [u8; 3] = [0, 2, 3];
+
+/// Flags random initial states pick from (IF and TF stay clear)
+const RANDOM_FLAGS: u16 = FLAG_CF | FLAG_PF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_DF | FLAG_OF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AluOp {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+/// ALU ops and their first opcode; the six forms follow it in the order
+/// r/m,reg r/m,reg reg,r/m reg,r/m acc,imm acc,imm (8 then 16 bit)
+const ALU_OPS: [(AluOp, u8); 6] = [
+    (AluOp::Add, 0x00),
+    (AluOp::Or, 0x08),
+    (AluOp::And, 0x20),
+    (AluOp::Sub, 0x28),
+    (AluOp::Xor, 0x30),
+    (AluOp::Cmp, 0x38),
+];
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Alu(AluOp),
+    Mov,
+    Lea,
+    Inc,
+    Dec,
+    /// XCHG AX, r16
+    Xchg,
+    Push,
+    Pop,
+    Cbw,
+    Cwd,
+    Sahf,
+    Lahf,
+    Clc,
+    Stc,
+    /// MOV r/m16, Sreg
+    MovFromSeg(u8),
+    Movs,
+    Stos,
+    Lods,
+}
+
+/// A memory operand: ModR/M mode and r/m fields, and the displacement
+/// (sign extended for mode 1)
+#[derive(Clone, Copy, Debug)]
+struct Ea {
+    mode: u8,
+    rm: u8,
+    disp: u16,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    None,
+    Reg(u8),
+    Mem(Ea),
+    Imm(u16),
+}
+
+/// A generated instruction: what it does, and its encoding
+#[derive(Clone, Debug)]
+struct Insn {
+    op: Op,
+    wide: bool,
+    dst: Operand,
+    src: Operand,
+    /// Segment override, as a segment register index
+    seg: Option<u8>,
+    bytes: Vec<u8>,
+}
+
+/// xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(splitmix(seed) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u32) -> u8 {
+        ((self.next() >> 32) % n as u64) as u8
+    }
+
+    fn chance(&mut self, one_in: u32) -> bool {
+        self.below(one_in) == 0
+    }
+
+    fn u16(&mut self) -> u16 {
+        (self.next() >> 40) as u16
+    }
+
+    /// Mostly uniform, with extra weight on the values flag bugs hide behind
+    fn operand16(&mut self) -> u16 {
+        const EDGES: [u16; 8] = [0, 1, 0x0F, 0x7F, 0x80, 0xFF, 0x7FFF, 0x8000];
+        match self.below(4) {
+            0 => EDGES[self.below(8) as usize],
+            1 => 0xFFFFu16.wrapping_sub(EDGES[self.below(8) as usize]),
+            _ => self.u16(),
+        }
+    }
+}
+
+fn splitmix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+fn gen_ea(rng: &mut Rng) -> Ea {
+    let mode = rng.below(3);
+    let rm = rng.below(8);
+    let disp = match mode {
+        1 => rng.below(256) as i8 as i16 as u16,
+        2 => rng.u16(),
+        _ if rm == 6 => rng.u16(),
+        _ => 0,
+    };
+    Ea { mode, rm, disp }
+}
+
+fn gen_rm(rng: &mut Rng) -> Operand {
+    if rng.chance(2) {
+        Operand::Reg(rng.below(8))
+    } else {
+        Operand::Mem(gen_ea(rng))
+    }
+}
+
+fn gen_imm(rng: &mut Rng, wide: bool) -> Operand {
+    let v = rng.operand16();
+    Operand::Imm(if wide { v } else { v & 0xFF })
+}
+
+/// ModR/M byte and displacement for `reg` and the r/m operand `rm`
+fn encode_modrm(bytes: &mut Vec<u8>, reg: u8, rm: Operand) {
+    match rm {
+        Operand::Reg(r) => bytes.push(0xC0 | reg << 3 | r),
+        Operand::Mem(ea) => {
+            bytes.push(ea.mode << 6 | reg << 3 | ea.rm);
+            match ea.mode {
+                1 => bytes.push(ea.disp as u8),
+                2 => bytes.extend_from_slice(&ea.disp.to_le_bytes()),
+                _ if ea.rm == 6 => bytes.extend_from_slice(&ea.disp.to_le_bytes()),
+                _ => {}
+            }
+        }
+        _ => unreachable!("r/m operand must be a register or memory"),
+    }
+}
+
+fn push_imm(bytes: &mut Vec<u8>, imm: Operand, wide: bool) {
+    if let Operand::Imm(v) = imm {
+        if wide {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        } else {
+            bytes.push(v as u8);
+        }
+    }
+}
+
+fn gen_insn(rng: &mut Rng) -> Insn {
+    let wide = rng.chance(2);
+    let w = wide as u8;
+    let mut bytes = Vec::new();
+    let (op, wide, dst, src) = match rng.below(16) {
+        // The ALU ops get half the stream; they are where flags go wrong
+        0..=7 => {
+            let (op, base) = ALU_OPS[rng.below(6) as usize];
+            match rng.below(3) {
+                0 => {
+                    let (rm, reg) = (gen_rm(rng), rng.below(8));
+                    bytes.push(base + w);
+                    encode_modrm(&mut bytes, reg, rm);
+                    (Op::Alu(op), wide, rm, Operand::Reg(reg))
+                }
+                1 => {
+                    let (rm, reg) = (gen_rm(rng), rng.below(8));
+                    bytes.push(base + 2 + w);
+                    encode_modrm(&mut bytes, reg, rm);
+                    (Op::Alu(op), wide, Operand::Reg(reg), rm)
+                }
+                _ => {
+                    let imm = gen_imm(rng, wide);
+                    bytes.push(base + 4 + w);
+                    push_imm(&mut bytes, imm, wide);
+                    (Op::Alu(op), wide, Operand::Reg(0), imm)
+                }
+            }
+        }
+        8 => match rng.below(5) {
+            0 => {
+                let (rm, reg) = (gen_rm(rng), rng.below(8));
+                bytes.push(0x88 + w);
+                encode_modrm(&mut bytes, reg, rm);
+                (Op::Mov, wide, rm, Operand::Reg(reg))
+            }
+            1 => {
+                let (rm, reg) = (gen_rm(rng), rng.below(8));
+                bytes.push(0x8A + w);
+                encode_modrm(&mut bytes, reg, rm);
+                (Op::Mov, wide, Operand::Reg(reg), rm)
+            }
+            2 => {
+                let (reg, imm) = (rng.below(8), gen_imm(rng, wide));
+                bytes.push(0xB0 + w * 8 + reg);
+                push_imm(&mut bytes, imm, wide);
+                (Op::Mov, wide, Operand::Reg(reg), imm)
+            }
+            3 => {
+                let (rm, imm) = (gen_rm(rng), gen_imm(rng, wide));
+                bytes.push(0xC6 + w);
+                encode_modrm(&mut bytes, 0, rm);
+                push_imm(&mut bytes, imm, wide);
+                (Op::Mov, wide, rm, imm)
+            }
+            _ => {
+                // moffs forms address like [disp16]
+                let mem = Operand::Mem(Ea { mode: 0, rm: 6, disp: rng.u16() });
+                let store = rng.chance(2);
+                bytes.push(0xA0 + (store as u8) * 2 + w);
+                if let Operand::Mem(ea) = mem {
+                    bytes.extend_from_slice(&ea.disp.to_le_bytes());
+                }
+                if store {
+                    (Op::Mov, wide, mem, Operand::Reg(0))
+                } else {
+                    (Op::Mov, wide, Operand::Reg(0), mem)
+                }
+            }
+        },
+        9 => {
+            let (reg, mem) = (rng.below(8), Operand::Mem(gen_ea(rng)));
+            bytes.push(0x8D);
+            encode_modrm(&mut bytes, reg, mem);
+            (Op::Lea, true, Operand::Reg(reg), mem)
+        }
+        10 => {
+            let op = if rng.chance(2) { Op::Inc } else { Op::Dec };
+            let ext = matches!(op, Op::Dec) as u8;
+            if rng.chance(2) {
+                let reg = rng.below(8);
+                bytes.push(0x40 + ext * 8 + reg);
+                (op, true, Operand::Reg(reg), Operand::None)
+            } else {
+                let rm = gen_rm(rng);
+                bytes.push(0xFF);
+                encode_modrm(&mut bytes, ext, rm);
+                (op, true, rm, Operand::None)
+            }
+        }
+        11 => match rng.below(3) {
+            0 => {
+                let reg = rng.below(8);
+                bytes.push(0x50 + reg);
+                (Op::Push, true, Operand::None, Operand::Reg(reg))
+            }
+            1 => {
+                let rm = gen_rm(rng);
+                bytes.push(0xFF);
+                encode_modrm(&mut bytes, 6, rm);
+                (Op::Push, true, Operand::None, rm)
+            }
+            _ => {
+                let reg = rng.below(8);
+                bytes.push(0x58 + reg);
+                (Op::Pop, true, Operand::Reg(reg), Operand::None)
+            }
+        },
+        12 => {
+            let reg = 1 + rng.below(7);
+            bytes.push(0x90 + reg);
+            (Op::Xchg, true, Operand::Reg(reg), Operand::Reg(0))
+        }
+        13 => {
+            let (sreg, rm) = (rng.below(4), gen_rm(rng));
+            bytes.push(0x8C);
+            encode_modrm(&mut bytes, sreg, rm);
+            (Op::MovFromSeg(sreg), true, rm, Operand::None)
+        }
+        14 => {
+            let op = [Op::Movs, Op::Stos, Op::Lods][rng.below(3) as usize];
+            let base = match op {
+                Op::Movs => 0xA4,
+                Op::Stos => 0xAA,
+                _ => 0xAC,
+            };
+            bytes.push(base + w);
+            (op, wide, Operand::None, Operand::None)
+        }
+        _ => {
+            let (op, opcode) = [
+                (Op::Cbw, 0x98),
+                (Op::Cwd, 0x99),
+                (Op::Sahf, 0x9E),
+                (Op::Lahf, 0x9F),
+                (Op::Clc, 0xF8),
+                (Op::Stc, 0xF9),
+            ][rng.below(6) as usize];
+            bytes.push(opcode);
+            (op, true, Operand::None, Operand::None)
+        }
+    };
+
+    let uses_segment = matches!(dst, Operand::Mem(_))
+        || matches!(src, Operand::Mem(_)) && !matches!(op, Op::Lea)
+        || matches!(op, Op::Movs | Op::Lods);
+    let seg = (uses_segment && rng.chance(4)).then(|| OVERRIDES[rng.below(3) as usize]);
+    if let Some(s) = seg {
+        bytes.insert(0, SEG_PREFIXES[s as usize]);
+    }
+    Insn { op, wide, dst, src, seg, bytes }
+}
+
+/// The reference machine
+struct Model {
+    cpu: Cpu16,
+    memory: Vec<u8>,
+}
+
+fn parity_even(v: u16) -> bool {
+    let mut p = v as u8;
+    p ^= p >> 4;
+    p ^= p >> 2;
+    p ^= p >> 1;
+    p & 1 == 0
+}
+
+/// Result and flags of an ALU op, and which of the flags it defines
+struct AluOut {
+    result: u16,
+    flags: u16,
+    defined: u16,
+}
+
+/// `a op b` at 8 or 16 bits
+fn alu(op: AluOp, a: u16, b: u16, wide: bool) -> AluOut {
+    let bits = if wide { 16 } else { 8 };
+    let modulus = 1i32 << bits;
+    let unsigned = |v: u16| v as i32 & (modulus - 1);
+    let signed = |v: u16| {
+        let u = unsigned(v);
+        if u >= modulus / 2 { u - modulus } else { u }
+    };
+    let fits_signed = |v: i32| v >= -modulus / 2 && v < modulus / 2;
+    let (a_u, b_u, a_s, b_s) = (unsigned(a), unsigned(b), signed(a), signed(b));
+
+    let (result, cf, of, af) = match op {
+        AluOp::Add => (
+            a_u + b_u,
+            a_u + b_u >= modulus,
+            !fits_signed(a_s + b_s),
+            (a_u & 0xF) + (b_u & 0xF) > 0xF,
+        ),
+        AluOp::Sub | AluOp::Cmp => (
+            a_u - b_u,
+            a_u < b_u,
+            !fits_signed(a_s - b_s),
+            (a_u & 0xF) < (b_u & 0xF),
+        ),
+        AluOp::Or => (a_u | b_u, false, false, false),
+        AluOp::And => (a_u & b_u, false, false, false),
+        AluOp::Xor => (a_u ^ b_u, false, false, false),
+    };
+    let result = (result & (modulus - 1)) as u16;
+
+    let mut flags = 0;
+    for (flag, on) in [
+        (FLAG_CF, cf),
+        (FLAG_OF, of),
+        (FLAG_AF, af),
+        (FLAG_ZF, result == 0),
+        (FLAG_SF, result as i32 >= modulus / 2),
+        (FLAG_PF, parity_even(result)),
+    ] {
+        if on {
+            flags |= flag;
+        }
+    }
+    let logic = matches!(op, AluOp::Or | AluOp::And | AluOp::Xor);
+    let defined = FLAG_CF | FLAG_OF | FLAG_ZF | FLAG_SF | FLAG_PF | if logic { 0 } else { FLAG_AF };
+    AluOut { result, flags, defined }
+}
+
+impl Model {
+    fn lin(seg: u16, off: u16) -> usize {
+        ((seg as usize) * 16 + off as usize) % 0x10_0000
+    }
+
+    fn read(&self, seg: u16, off: u16, wide: bool) -> u16 {
+        let lo = self.memory[Self::lin(seg, off)] as u16;
+        if !wide {
+            return lo;
+        }
+        // Words wrap within the segment
+        lo | (self.memory[Self::lin(seg, off.wrapping_add(1))] as u16) << 8
+    }
+
+    fn write(&mut self, seg: u16, off: u16, wide: bool, val: u16) {
+        self.memory[Self::lin(seg, off)] = val as u8;
+        if wide {
+            self.memory[Self::lin(seg, off.wrapping_add(1))] = (val >> 8) as u8;
+        }
+    }
+
+    /// Offset of a memory operand, and the segment it uses by default
+    fn address(&self, ea: Ea) -> (u16, u16) {
+        let c = &self.cpu;
+        let (base, ss) = match (ea.rm, ea.mode) {
+            (0, _) => (c.bx as u32 + c.si as u32, false),
+            (1, _) => (c.bx as u32 + c.di as u32, false),
+            (2, _) => (c.bp as u32 + c.si as u32, true),
+            (3, _) => (c.bp as u32 + c.di as u32, true),
+            (4, _) => (c.si as u32, false),
+            (5, _) => (c.di as u32, false),
+            (6, 0) => (0, false),
+            (6, _) => (c.bp as u32, true),
+            _ => (c.bx as u32, false),
+        };
+        let off = ((base + ea.disp as u32) % 0x10000) as u16;
+        (off, if ss { c.ss } else { c.ds })
+    }
+
+    fn segment(&self, insn: &Insn, default: u16) -> u16 {
+        insn.seg.map_or(default, |s| self.cpu.get_seg(s))
+    }
+
+    fn get(&self, insn: &Insn, operand: Operand) -> u16 {
+        match operand {
+            Operand::Reg(r) if insn.wide => self.cpu.get_reg16(r),
+            Operand::Reg(r) => self.cpu.get_reg8(r) as u16,
+            Operand::Mem(ea) => {
+                let (off, seg) = self.address(ea);
+                self.read(self.segment(insn, seg), off, insn.wide)
+            }
+            Operand::Imm(v) => v,
+            Operand::None => 0,
+        }
+    }
+
+    fn set(&mut self, insn: &Insn, operand: Operand, val: u16) {
+        match operand {
+            Operand::Reg(r) if insn.wide => self.cpu.set_reg16(r, val),
+            Operand::Reg(r) => self.cpu.set_reg8(r, val as u8),
+            Operand::Mem(ea) => {
+                let (off, seg) = self.address(ea);
+                let seg = self.segment(insn, seg);
+                self.write(seg, off, insn.wide, val);
+            }
+            _ => unreachable!("not a destination"),
+        }
+    }
+
+    fn set_flags(&mut self, mask: u16, flags: u16) {
+        self.cpu.flags = self.cpu.flags & !mask | flags & mask;
+    }
+
+    /// String op step: +size with DF clear, -size with it set
+    fn string_delta(&self, wide: bool) -> u16 {
+        let size = if wide { 2u16 } else { 1 };
+        if self.cpu.flags & FLAG_DF != 0 { size.wrapping_neg() } else { size }
+    }
+
+    /// Run `insn`; returns the flags it leaves undefined
+    fn exec(&mut self, insn: &Insn) -> u16 {
+        self.cpu.ip = self.cpu.ip.wrapping_add(insn.bytes.len() as u16);
+        let wide = insn.wide;
+        match insn.op {
+            Op::Alu(op) => {
+                let out = alu(op, self.get(insn, insn.dst), self.get(insn, insn.src), wide);
+                if op != AluOp::Cmp {
+                    self.set(insn, insn.dst, out.result);
+                }
+                self.set_flags(FLAG_CF | FLAG_OF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_PF, out.flags);
+                return (FLAG_CF | FLAG_OF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_PF) & !out.defined;
+            }
+            Op::Inc | Op::Dec => {
+                let op = if matches!(insn.op, Op::Inc) { AluOp::Add } else { AluOp::Sub };
+                let out = alu(op, self.get(insn, insn.dst), 1, true);
+                self.set(insn, insn.dst, out.result);
+                // CF is left alone
+                self.set_flags(FLAG_OF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_PF, out.flags);
+            }
+            Op::Mov => {
+                let val = self.get(insn, insn.src);
+                self.set(insn, insn.dst, val);
+            }
+            Op::Lea => {
+                if let Operand::Mem(ea) = insn.src {
+                    let (off, _) = self.address(ea);
+                    self.set(insn, insn.dst, off);
+                }
+            }
+            Op::Xchg => {
+                let (a, b) = (self.get(insn, insn.dst), self.get(insn, insn.src));
+                self.set(insn, insn.dst, b);
+                self.set(insn, insn.src, a);
+            }
+            Op::Push => {
+                let val = self.get(insn, insn.src);
+                self.cpu.sp = self.cpu.sp.wrapping_sub(2);
+                let (ss, sp) = (self.cpu.ss, self.cpu.sp);
+                self.write(ss, sp, true, val);
+            }
+            Op::Pop => {
+                let val = self.read(self.cpu.ss, self.cpu.sp, true);
+                self.cpu.sp = self.cpu.sp.wrapping_add(2);
+                self.set(insn, insn.dst, val);
+            }
+            Op::Cbw => self.cpu.ax = self.cpu.ax as u8 as i8 as i16 as u16,
+            Op::Cwd => self.cpu.dx = if self.cpu.ax >= 0x8000 { 0xFFFF } else { 0 },
+            Op::Sahf => {
+                let ah = self.cpu.ax >> 8;
+                self.set_flags(FLAG_CF | FLAG_PF | FLAG_AF | FLAG_ZF | FLAG_SF, ah);
+            }
+            Op::Lahf => self.cpu.ax = self.cpu.ax & 0x00FF | (self.cpu.flags & 0xFF) << 8,
+            Op::Clc => self.set_flags(FLAG_CF, 0),
+            Op::Stc => self.set_flags(FLAG_CF, FLAG_CF),
+            Op::MovFromSeg(sreg) => {
+                let val = self.cpu.get_seg(sreg);
+                self.set(insn, insn.dst, val);
+            }
+            Op::Movs => {
+                let val = self.read(self.segment(insn, self.cpu.ds), self.cpu.si, wide);
+                let (es, di) = (self.cpu.es, self.cpu.di);
+                self.write(es, di, wide, val);
+                let delta = self.string_delta(wide);
+                self.cpu.si = self.cpu.si.wrapping_add(delta);
+                self.cpu.di = self.cpu.di.wrapping_add(delta);
+            }
+            Op::Stos => {
+                let (es, di, ax) = (self.cpu.es, self.cpu.di, self.cpu.ax);
+                self.write(es, di, wide, ax);
+                self.cpu.di = self.cpu.di.wrapping_add(self.string_delta(wide));
+            }
+            Op::Lods => {
+                let val = self.read(self.segment(insn, self.cpu.ds), self.cpu.si, wide);
+                if wide {
+                    self.cpu.ax = val;
+                } else {
+                    self.cpu.set_reg8(0, val as u8);
+                }
+                self.cpu.si = self.cpu.si.wrapping_add(self.string_delta(wide));
+            }
+        }
+        0
+    }
+}
+
+/// A generated test: a starting state and the instructions to run from it
+pub struct Case {
+    pub seed: u64,
+    /// Registers before the first instruction
+    pub cpu: Cpu16,
+    insns: Vec<Insn>,
+}
+
+impl Case {
+    pub fn generate(seed: u64) -> Case {
+        let mut rng = Rng::new(seed);
+        let mut cpu = Cpu16::new();
+        for r in 0..8 {
+            cpu.set_reg16(r, rng.operand16());
+        }
+        cpu.cs = CODE_SEG;
+        cpu.ip = 0;
+        cpu.ds = rng.u16() % DATA_SEGS;
+        cpu.es = rng.u16() % DATA_SEGS;
+        cpu.ss = rng.u16() % DATA_SEGS;
+        cpu.flags = rng.u16() & RANDOM_FLAGS | 0x0002;
+        let insns = (0..CASE_LEN).map(|_| gen_insn(&mut rng)).collect();
+        Case { seed, cpu, insns }
+    }
+
+    /// The case's instructions, as loaded at CS:0
+    pub fn code(&self) -> Vec<u8> {
+        self.insns.iter().flat_map(|i| i.bytes.iter().copied()).collect()
+    }
+
+    /// An emulator ready to run the case: its registers set, the code
+    /// loaded and data memory filled with noise
+    pub fn emulator(&self) -> Emulator {
+        let mut emu = Emulator::with_memory_size(MEMORY_SIZE);
+        let mut rng = Rng::new(self.seed ^ 0x6D65_6D6F_7279);
+        for chunk in emu.memory[..CODE_SEG as usize * 16].chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next().to_le_bytes()[..chunk.len()]);
+        }
+        emu.load_code_at(CODE_SEG, 0, &self.code());
+        emu.cpu = self.cpu.clone();
+        emu
+    }
+
+    /// Step `emu` through the case beside the model, which starts from
+    /// `self.cpu` and `emu`'s memory. Returns the instructions run.
+    pub fn check(&self, emu: &mut Emulator) -> Result<usize, Box<Mismatch>> {
+        let mut model = Model { cpu: self.cpu.clone(), memory: emu.memory.clone() };
+        for (index, insn) in self.insns.iter().enumerate() {
+            let before = model.cpu.clone();
+            let result = emu.step();
+            let undefined = model.exec(insn);
+            model.cpu.flags = model.cpu.flags & !undefined | emu.cpu.flags & undefined;
+
+            let memory = (model.memory != emu.memory)
+                .then(|| model.memory.iter().zip(&emu.memory).position(|(m, e)| m != e))
+                .flatten()
+                .map(|addr| (addr, model.memory[addr], emu.memory[addr]));
+            if result != StepResult::Continue || model.cpu != emu.cpu || memory.is_some() {
+                return Err(Box::new(Mismatch {
+                    seed: self.seed,
+                    index,
+                    instruction: disasm::decode(&insn.bytes, before.cs, before.ip).text,
+                    bytes: insn.bytes.clone(),
+                    before,
+                    expected: model.cpu,
+                    actual: emu.cpu.clone(),
+                    memory,
+                    result,
+                }));
+            }
+        }
+        Ok(self.insns.len())
+    }
+}
+
+/// Where the emulator and the model parted ways
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Seed of the case, for [`check_case`]
+    pub seed: u64,
+    /// Which instruction of the case
+    pub index: usize,
+    pub bytes: Vec<u8>,
+    /// Disassembly of `bytes`
+    pub instruction: String,
+    /// Registers before the instruction
+    pub before: Cpu16,
+    /// Registers after it, from the model
+    pub expected: Cpu16,
+    /// Registers after it, from the emulator
+    pub actual: Cpu16,
+    /// First byte of memory that differs: linear address, model's value,
+    /// emulator's value
+    pub memory: Option<(usize, u8, u8)>,
+    /// What the emulator's step returned
+    pub result: StepResult,
+}
+
+fn registers(cpu: &Cpu16) -> [(&'static str, u16); 14] {
+    [
+        ("ax", cpu.ax), ("bx", cpu.bx), ("cx", cpu.cx), ("dx", cpu.dx),
+        ("si", cpu.si), ("di", cpu.di), ("bp", cpu.bp), ("sp", cpu.sp),
+        ("ip", cpu.ip), ("cs", cpu.cs), ("ds", cpu.ds), ("es", cpu.es),
+        ("ss", cpu.ss), ("flags", cpu.flags),
+    ]
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seed {:#x}, instruction {}:", self.seed, self.index)?;
+        for b in &self.bytes {
+            write!(f, " {:02X}", b)?;
+        }
+        writeln!(f, "  {}", self.instruction)?;
+        f.write_str("  before:")?;
+        for (name, val) in registers(&self.before) {
+            write!(f, " {}={:04X}", name, val)?;
+        }
+        writeln!(f)?;
+        if self.result != StepResult::Continue {
+            writeln!(f, "  emulator stopped: {:?}", self.result)?;
+        }
+        for ((name, want), (_, got)) in registers(&self.expected).into_iter().zip(registers(&self.actual)) {
+            if want != got {
+                write!(f, "  {}: expected {:04X}, got {:04X}", name, want, got)?;
+                if name == "flags" {
+                    write!(f, " (differ in {:04X})", want ^ got)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        if let Some((addr, want, got)) = self.memory {
+            writeln!(f, "  memory {:05X}: expected {:02X}, got {:02X}", addr, want, got)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generate and check the case for `seed`
+pub fn check_case(seed: u64) -> Result<usize, Box<Mismatch>> {
+    let case = Case::generate(seed);
+    case.check(&mut case.emulator())
+}
+
+/// Check `cases` cases derived from `seed`, stopping at the first
+/// mismatch. Returns the instructions run.
+pub fn run(seed: u64, cases: usize) -> Result<usize, Box<Mismatch>> {
+    (0..cases as u64).try_fold(0, |total, i| Ok(total + check_case(splitmix(seed.wrapping_add(i)))?))
+}
@@ -21,6 +21,19 @@ use alloc::vec::Vec;
 #[cfg(not(feature = "std"))]
 use alloc::vec;
 
+pub mod bus;
+pub mod debug;
+pub mod devices;
+pub mod disasm;
+pub mod exe;
+#[cfg(feature = "std")]
+pub mod fuzz;
+
+use bus::Region;
+use debug::{Debugger, WatchHit};
+use devices::Devices;
+use disasm::{Instruction, ModRm, MAX_INSN_LEN};
+
 // CPU Flags
 pub const FLAG_CF: u16 = 0x0001;  // Carry
 pub const FLAG_PF: u16 = 0x0004;  // Parity
@@ -222,14 +235,25 @@ pub enum StepResult {
     Halt,
     Interrupt(u8),
     UnknownOpcode(u8),
+    /// Stopped before executing an instruction with a breakpoint on it
+    Breakpoint,
+    /// The instruction just executed touched a watched address
+    Watchpoint(WatchHit),
 }
 
 /// Minimal CPU emulator for testing
-/// Contains CPU state plus memory, no I/O or console dependencies
+/// Contains CPU state plus memory (see [`bus`] for mapped ranges), no I/O or console dependencies
 pub struct Emulator {
     pub cpu: Cpu16,
     pub memory: Vec<u8>,
+    /// PIT, PICs and keyboard controller behind IN/OUT
+    pub devices: Devices,
     seg_override: Option<u16>,
+    /// Segment of the last memory operand decoded: the override, or SS
+    /// for BP-based addresses and DS for the rest
+    ea_seg: u16,
+    regions: Vec<Region>,
+    debug: Debugger,
 }
 
 impl Emulator {
@@ -241,7 +265,11 @@ impl Emulator {
         Emulator {
             cpu: Cpu16::new(),
             memory: vec![0u8; size],
+            devices: Devices::new(),
             seg_override: None,
+            ea_seg: 0,
+            regions: Vec::new(),
+            debug: Debugger::default(),
         }
     }
 
@@ -257,74 +285,6 @@ impl Emulator {
         self.cpu.ss = 0;
     }
 
-    /// Load an MZ EXE file
-    pub fn load_exe(&mut self, data: &[u8]) -> Result<(), &'static str> {
-        if data.len() < 28 {
-            return Err("EXE too small");
-        }
-        if data[0] != b'M' || data[1] != b'Z' {
-            return Err("Not an MZ executable");
-        }
-
-        // MZ header parsing
-        let last_page_size = u16::from_le_bytes([data[2], data[3]]);
-        let page_count = u16::from_le_bytes([data[4], data[5]]);
-        let reloc_count = u16::from_le_bytes([data[6], data[7]]) as usize;
-        let header_paras = u16::from_le_bytes([data[8], data[9]]) as usize;
-        let init_ss = u16::from_le_bytes([data[14], data[15]]);
-        let init_sp = u16::from_le_bytes([data[16], data[17]]);
-        let init_ip = u16::from_le_bytes([data[20], data[21]]);
-        let init_cs = u16::from_le_bytes([data[22], data[23]]);
-        let reloc_offset = u16::from_le_bytes([data[24], data[25]]) as usize;
-
-        // Calculate code size
-        let header_size = header_paras * 16;
-        let code_size = if last_page_size == 0 {
-            (page_count as usize) * 512 - header_size
-        } else {
-            ((page_count as usize) - 1) * 512 + (last_page_size as usize) - header_size
-        };
-
-        // Load segment (after PSP)
-        let load_seg: u16 = 0x0010;
-        let load_addr = (load_seg as usize) << 4;
-
-        // Copy code
-        if header_size < data.len() {
-            let code_start = header_size;
-            let code_end = core::cmp::min(code_start + code_size, data.len());
-            let copy_len = core::cmp::min(code_end - code_start, self.memory.len() - load_addr);
-            self.memory[load_addr..load_addr + copy_len]
-                .copy_from_slice(&data[code_start..code_start + copy_len]);
-        }
-
-        // Apply relocations
-        for i in 0..reloc_count {
-            let rel_off = reloc_offset + i * 4;
-            if rel_off + 4 <= data.len() {
-                let off = u16::from_le_bytes([data[rel_off], data[rel_off + 1]]) as usize;
-                let seg = u16::from_le_bytes([data[rel_off + 2], data[rel_off + 3]]) as usize;
-                let addr = load_addr + (seg << 4) + off;
-                if addr + 2 <= self.memory.len() {
-                    let val = u16::from_le_bytes([self.memory[addr], self.memory[addr + 1]]);
-                    let new_val = val.wrapping_add(load_seg);
-                    self.memory[addr] = new_val as u8;
-                    self.memory[addr + 1] = (new_val >> 8) as u8;
-                }
-            }
-        }
-
-        // Set up registers
-        self.cpu.cs = load_seg.wrapping_add(init_cs);
-        self.cpu.ip = init_ip;
-        self.cpu.ss = load_seg.wrapping_add(init_ss);
-        self.cpu.sp = init_sp;
-        self.cpu.ds = load_seg;
-        self.cpu.es = load_seg;
-
-        Ok(())
-    }
-
     /// Load raw code at a specific segment:offset
     pub fn load_code_at(&mut self, seg: u16, off: u16, code: &[u8]) {
         let addr = self.lin(seg, off);
@@ -342,8 +302,14 @@ impl Emulator {
 
     // Memory access
     pub fn read_u8(&self, seg: u16, off: u16) -> u8 {
-        let addr = self.lin(seg, off);
-        if addr < self.memory.len() { self.memory[addr] } else { 0 }
+        let val = self.peek_u8(seg, off);
+        self.debug.check_access(self.lin(seg, off), val, false);
+        val
+    }
+
+    /// Read without tripping watchpoints (instruction fetch, disassembly)
+    pub fn peek_u8(&self, seg: u16, off: u16) -> u8 {
+        self.bus_read(self.lin(seg, off))
     }
 
     pub fn read_u16(&self, seg: u16, off: u16) -> u16 {
@@ -354,7 +320,8 @@ impl Emulator {
 
     pub fn write_u8(&mut self, seg: u16, off: u16, val: u8) {
         let addr = self.lin(seg, off);
-        if addr < self.memory.len() { self.memory[addr] = val; }
+        self.debug.check_access(addr, val, true);
+        self.bus_write(addr, val);
     }
 
     pub fn write_u16(&mut self, seg: u16, off: u16, val: u16) {
@@ -364,7 +331,7 @@ impl Emulator {
 
     // Fetch from CS:IP
     fn fetch_u8(&mut self) -> u8 {
-        let val = self.read_u8(self.cpu.cs, self.cpu.ip);
+        let val = self.peek_u8(self.cpu.cs, self.cpu.ip);
         self.cpu.ip = self.cpu.ip.wrapping_add(1);
         val
     }
@@ -398,18 +365,16 @@ impl Emulator {
 
     // ModR/M decoding - returns (reg field, effective address, rm field for register mode, is_memory)
     fn decode_modrm(&mut self, wide: bool) -> (u8, u16, u8, bool) {
-        let modrm = self.fetch_u8();
-        let mode = (modrm >> 6) & 3;
-        let reg = (modrm >> 3) & 7;
-        let rm = modrm & 7;
+        let modrm = ModRm::new(self.fetch_u8());
+        let (reg, rm) = (modrm.reg, modrm.rm);
 
-        if mode == 3 {
+        if !modrm.is_mem() {
             // Register mode - ea holds register value, rm is register index
             let val = if wide { self.cpu.get_reg16(rm) } else { self.cpu.get_reg8(rm) as u16 };
             return (reg, val, rm, false);
         }
 
-        // Memory mode - calculate effective address
+        // Memory mode - calculate effective address (see disasm::EA_NAMES)
         let mut ea: u16 = match rm {
             0 => self.cpu.bx.wrapping_add(self.cpu.si),
             1 => self.cpu.bx.wrapping_add(self.cpu.di),
@@ -417,18 +382,21 @@ impl Emulator {
             3 => self.cpu.bp.wrapping_add(self.cpu.di),
             4 => self.cpu.si,
             5 => self.cpu.di,
-            6 => if mode == 0 { self.fetch_u16() } else { self.cpu.bp },
+            6 => if modrm.mode == 0 { 0 } else { self.cpu.bp },
             7 => self.cpu.bx,
             _ => 0,
         };
 
         // Add displacement
-        match mode {
+        match modrm.disp_len() {
             1 => ea = ea.wrapping_add(self.fetch_i8() as i16 as u16),
             2 => ea = ea.wrapping_add(self.fetch_u16()),
             _ => {}
         }
 
+        let bp_based = matches!(rm, 2 | 3) || (rm == 6 && modrm.mode != 0);
+        self.ea_seg = self.get_seg(if bp_based { self.cpu.ss } else { self.cpu.ds });
+
         (reg, ea, rm, true)
     }
 
@@ -436,8 +404,7 @@ impl Emulator {
     fn read_rm8(&mut self) -> (u8, u8, u16, u8, bool) {
         let (reg, ea, rm, is_mem) = self.decode_modrm(false);
         let val = if is_mem {
-            let seg = self.get_seg(self.cpu.ds);
-            self.read_u8(seg, ea)
+            self.read_u8(self.ea_seg, ea)
         } else {
             ea as u8
         };
@@ -447,8 +414,7 @@ impl Emulator {
     fn read_rm16(&mut self) -> (u8, u16, u16, u8, bool) {
         let (reg, ea, rm, is_mem) = self.decode_modrm(true);
         let val = if is_mem {
-            let seg = self.get_seg(self.cpu.ds);
-            self.read_u16(seg, ea)
+            self.read_u16(self.ea_seg, ea)
         } else {
             ea
         };
@@ -458,8 +424,7 @@ impl Emulator {
     // Write to ModR/M destination
     fn write_rm8(&mut self, ea: u16, is_mem: bool, rm: u8, val: u8) {
         if is_mem {
-            let seg = self.get_seg(self.cpu.ds);
-            self.write_u8(seg, ea, val);
+            self.write_u8(self.ea_seg, ea, val);
         } else {
             self.cpu.set_reg8(rm, val);
         }
@@ -467,8 +432,7 @@ impl Emulator {
 
     fn write_rm16(&mut self, ea: u16, is_mem: bool, rm: u8, val: u16) {
         if is_mem {
-            let seg = self.get_seg(self.cpu.ds);
-            self.write_u16(seg, ea, val);
+            self.write_u16(self.ea_seg, ea, val);
         } else {
             self.cpu.set_reg16(rm, val);
         }
@@ -476,7 +440,21 @@ impl Emulator {
 
     /// Execute one instruction
     pub fn step(&mut self) -> StepResult {
-        self.step_inner(false)
+        if self.devices.clocks_per_step != 0 {
+            self.devices.advance(self.devices.clocks_per_step);
+        }
+        // Hardware interrupts are taken between instructions
+        if self.cpu.get_flag(FLAG_IF) {
+            if let Some(vector) = self.devices.pics.acknowledge() {
+                self.interrupt(vector);
+                return StepResult::Continue;
+            }
+        }
+        if let Some(stop) = self.check_breakpoint() {
+            return stop;
+        }
+        let result = self.step_inner(false);
+        self.take_watch_hit(result)
     }
 
     fn step_inner(&mut self, has_seg_override: bool) -> StepResult {
@@ -910,7 +888,7 @@ impl Emulator {
             }
             // LEA r16, m
             0x8D => {
-                let (reg, ea, _, _, _) = self.read_rm16();
+                let (reg, ea, _, _) = self.decode_modrm(true);
                 self.cpu.set_reg16(reg, ea);
             }
             // MOV Sreg, r/m16
@@ -962,7 +940,8 @@ impl Emulator {
             // SAHF
             0x9E => {
                 let ah = (self.cpu.ax >> 8) as u8;
-                self.cpu.flags = (self.cpu.flags & 0xFF00) | (ah as u16);
+                // Bits 1, 3 and 5 aren't loaded
+                self.cpu.flags = (self.cpu.flags & 0xFF00) | (ah as u16 & 0xD5) | 0x0002;
             }
             // LAHF
             0x9F => {
@@ -1053,9 +1032,9 @@ impl Emulator {
 
             // LES r16, m16:16
             0xC4 => {
-                let (reg, ea, _, _, is_mem) = self.read_rm16();
+                let (reg, ea, _, is_mem) = self.decode_modrm(true);
                 if is_mem {
-                    let seg = self.get_seg(self.cpu.ds);
+                    let seg = self.ea_seg;
                     let off = self.read_u16(seg, ea);
                     let new_es = self.read_u16(seg, ea.wrapping_add(2));
                     self.cpu.set_reg16(reg, off);
@@ -1064,9 +1043,9 @@ impl Emulator {
             }
             // LDS r16, m16:16
             0xC5 => {
-                let (reg, ea, _, _, is_mem) = self.read_rm16();
+                let (reg, ea, _, is_mem) = self.decode_modrm(true);
                 if is_mem {
-                    let seg = self.get_seg(self.cpu.ds);
+                    let seg = self.ea_seg;
                     let off = self.read_u16(seg, ea);
                     let new_ds = self.read_u16(seg, ea.wrapping_add(2));
                     self.cpu.set_reg16(reg, off);
@@ -1144,6 +1123,39 @@ impl Emulator {
                 self.cpu.ip = self.cpu.ip.wrapping_add(rel as i16 as u16);
             }
 
+            // IN AL, imm8
+            0xE4 => {
+                let port = self.fetch_u8() as u16;
+                let val = self.devices.port_in(port);
+                self.cpu.ax = (self.cpu.ax & 0xFF00) | val as u16;
+            }
+            // IN AX, imm8
+            0xE5 => {
+                let port = self.fetch_u8() as u16;
+                self.cpu.ax = self.port_in16(port);
+            }
+            // OUT imm8, AL
+            0xE6 => {
+                let port = self.fetch_u8() as u16;
+                self.devices.port_out(port, self.cpu.ax as u8);
+            }
+            // OUT imm8, AX
+            0xE7 => {
+                let port = self.fetch_u8() as u16;
+                self.port_out16(port, self.cpu.ax);
+            }
+            // IN AL, DX
+            0xEC => {
+                let val = self.devices.port_in(self.cpu.dx);
+                self.cpu.ax = (self.cpu.ax & 0xFF00) | val as u16;
+            }
+            // IN AX, DX
+            0xED => self.cpu.ax = self.port_in16(self.cpu.dx),
+            // OUT DX, AL
+            0xEE => self.devices.port_out(self.cpu.dx, self.cpu.ax as u8),
+            // OUT DX, AX
+            0xEF => self.port_out16(self.cpu.dx, self.cpu.ax),
+
             // LOCK prefix
             0xF0 => { return self.step_inner(has_seg_override); }
 
@@ -1284,7 +1296,7 @@ impl Emulator {
             // CALL m16:16 (far indirect)
             3 => {
                 if is_mem {
-                    let seg = self.get_seg(self.cpu.ds);
+                    let seg = self.ea_seg;
                     let new_ip = self.read_u16(seg, ea);
                     let new_cs = self.read_u16(seg, ea.wrapping_add(2));
                     self.push16(self.cpu.cs);
@@ -1300,7 +1312,7 @@ impl Emulator {
             // JMP m16:16 (far indirect)
             5 => {
                 if is_mem {
-                    let seg = self.get_seg(self.cpu.ds);
+                    let seg = self.ea_seg;
                     let new_ip = self.read_u16(seg, ea);
                     let new_cs = self.read_u16(seg, ea.wrapping_add(2));
                     self.cpu.cs = new_cs;
@@ -1315,7 +1327,52 @@ impl Emulator {
         }
     }
 
-    /// Run until halt, interrupt, or max_steps reached
+    /// Enter the handler for `vector` through the IVT, as the CPU does for
+    /// a hardware interrupt: push FLAGS, CS and IP, clear IF and TF
+    pub fn interrupt(&mut self, vector: u8) {
+        let ivt = (vector as u16) * 4;
+        self.push16(self.cpu.flags);
+        self.push16(self.cpu.cs);
+        self.push16(self.cpu.ip);
+        self.cpu.set_flag(FLAG_IF, false);
+        self.cpu.set_flag(FLAG_TF, false);
+        self.cpu.ip = self.read_u16(0, ivt);
+        self.cpu.cs = self.read_u16(0, ivt + 2);
+    }
+
+    fn port_in16(&mut self, port: u16) -> u16 {
+        let lo = self.devices.port_in(port) as u16;
+        let hi = self.devices.port_in(port.wrapping_add(1)) as u16;
+        lo | (hi << 8)
+    }
+
+    fn port_out16(&mut self, port: u16, val: u16) {
+        self.devices.port_out(port, val as u8);
+        self.devices.port_out(port.wrapping_add(1), (val >> 8) as u8);
+    }
+
+    /// Disassemble the instruction at seg:off without executing it
+    pub fn disassemble_one(&self, seg: u16, off: u16) -> Instruction {
+        let mut code = [0u8; MAX_INSN_LEN];
+        for (i, b) in code.iter_mut().enumerate() {
+            *b = self.peek_u8(seg, off.wrapping_add(i as u16));
+        }
+        disasm::decode(&code, seg, off)
+    }
+
+    /// Disassemble `count` consecutive instructions starting at seg:off
+    pub fn disassemble(&self, seg: u16, off: u16, count: usize) -> Vec<Instruction> {
+        let mut out = Vec::with_capacity(count);
+        let mut off = off;
+        for _ in 0..count {
+            let insn = self.disassemble_one(seg, off);
+            off = insn.next_off();
+            out.push(insn);
+        }
+        out
+    }
+
+    /// Run until halt, interrupt, breakpoint, watchpoint, or max_steps reached
     pub fn run(&mut self, max_steps: usize) -> (StepResult, usize) {
         let mut steps = 0;
         loop {
@@ -3,6 +3,8 @@
 //! Run with: cargo test --package dos16-core --features std
 
 use super::*;
+use debug::{WatchHit, WatchKind};
+use exe::ExeOptions;
 
 // ============================================================================
 // HELPER MACROS AND FUNCTIONS
@@ -941,6 +943,96 @@ fn test_exe_header_validation() {
     assert!(emu.load_exe(&bad_exe).is_err());
 }
 
+/// Set a header word of an EXE from `build_mz_exe`
+fn set_exe_word(exe: &mut [u8], offset: usize, val: u16) {
+    exe[offset..offset + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+#[test]
+fn test_exe_errors() {
+    use exe::ExeError;
+    let mut emu = Emulator::new();
+    assert_eq!(emu.load_exe(b"MZ"), Err(ExeError::TooSmall));
+
+    let mut exe = build_mz_exe(&[0xF4], 0, 0, &[(0, 0)]);
+    set_exe_word(&mut exe, 24, 0x1000); // relocation table past the end
+    assert_eq!(emu.load_exe(&exe), Err(ExeError::BadRelocTable));
+
+    let top = ExeOptions { psp_seg: 0x100, mem_top: Some(0x180) };
+    let mut small = build_mz_exe(&[0xF4], 0, 0, &[]);
+    set_exe_word(&mut small, 16, 0x100); // SP
+    let mut exe = build_mz_exe(&[0xF4], 0, 0, &[(0, 0x100)]);
+    set_exe_word(&mut exe, 16, 0x100);
+    assert_eq!(emu.load_exe_with(&exe, &top), Err(ExeError::BadRelocation { index: 0 }));
+    let mut exe = small.clone();
+    set_exe_word(&mut exe, 10, 0x80); // min alloc
+    assert_eq!(
+        emu.load_exe_with(&exe, &top),
+        Err(ExeError::NotEnoughMemory { needed: 0x91, available: 0x80 })
+    );
+    let mut exe = small.clone();
+    set_exe_word(&mut exe, 14, 0x70); // SS past the block
+    assert_eq!(emu.load_exe_with(&exe, &top), Err(ExeError::BadStack { ss: 0x70, sp: 0x100 }));
+    let mut exe = small;
+    set_exe_word(&mut exe, 22, 0x70); // CS past the block
+    assert_eq!(emu.load_exe_with(&exe, &top), Err(ExeError::BadEntry { cs: 0x70, ip: 0 }));
+    assert_eq!(ExeError::BadStack { ss: 0x70, sp: 0x100 }.to_string(), "Stack 0070:0100 outside program");
+}
+
+#[test]
+fn test_load_exe_allocation() {
+    let mut exe = build_mz_exe(&[0xF4], 0, 0, &[]);
+    set_exe_word(&mut exe, 10, 0x20); // min alloc
+    set_exe_word(&mut exe, 12, 0x100); // max alloc
+    set_exe_word(&mut exe, 16, 0x100); // SP
+    let mut emu = Emulator::new();
+
+    // Gets max_alloc when there's room: PSP + one image paragraph + 0x100
+    let options = ExeOptions { psp_seg: 0x200, mem_top: None };
+    let load = emu.load_exe_with(&exe, &options).unwrap();
+    assert_eq!((load.psp_seg, load.load_seg, load.end_seg), (0x200, 0x210, 0x311));
+    assert_eq!((emu.cpu.ds, emu.cpu.es, emu.cpu.cs, emu.cpu.ss), (0x200, 0x200, 0x210, 0x210));
+    // PSP starts with INT 20h and the segment past the block
+    assert_eq!(emu.read_u16(0x200, 0), 0x20CD);
+    assert_eq!(emu.read_u16(0x200, 2), 0x311);
+
+    // Makes do with less, down to min_alloc
+    let options = ExeOptions { psp_seg: 0x200, mem_top: Some(0x240) };
+    assert_eq!(emu.load_exe_with(&exe, &options).unwrap().end_seg, 0x240);
+}
+
+#[test]
+fn test_load_exe_high() {
+    // min_alloc and max_alloc both 0 load the image at the top of memory
+    let code = [0x05, 0x00, 0x00, 0xF4]; // ADD AX, seg; HLT
+    let mut exe = build_mz_exe(&code, 0, 0, &[(1, 0)]);
+    set_exe_word(&mut exe, 12, 0); // max alloc
+    set_exe_word(&mut exe, 16, 0x10); // SP
+    let mut emu = Emulator::with_memory_size(0x10000);
+    let load = emu.load_exe(&exe).unwrap();
+    assert_eq!((load.psp_seg, load.load_seg, load.end_seg), (0, 0xFFF, 0x1000));
+    emu.run(100);
+    assert_eq!(emu.cpu.ax, 0xFFF);
+}
+
+#[test]
+fn test_load_overlay() {
+    let code = [0x34, 0x12, 0x00, 0x00]; // a word, and a segment fixup
+    let mut exe = build_mz_exe(&code, 0, 0, &[(2, 0)]);
+    set_exe_word(&mut exe, 26, 1); // overlay number
+    let image_end = exe.len();
+    exe.extend_from_slice(b"appended overlay data");
+
+    let mut emu = Emulator::new();
+    emu.cpu.ax = 0x5555;
+    let header = emu.load_overlay(&exe, 0x3000, 0x1234).unwrap();
+    assert_eq!(header.overlay, 1);
+    assert_eq!(header.image_end(), image_end);
+    assert_eq!(emu.read_u16(0x3000, 0), 0x1234);
+    assert_eq!(emu.read_u16(0x3000, 2), 0x1234);
+    assert_eq!(emu.cpu.ax, 0x5555);
+}
+
 // ============================================================================
 // INTERRUPT TESTS
 // ============================================================================
@@ -1275,3 +1367,633 @@ fn test_load_com_zeroes_rest() {
         assert_eq!(emu.memory[i], 0, "Memory at offset {} should be 0", i);
     }
 }
+
+// ============================================================================
+// DISASSEMBLER TESTS
+// ============================================================================
+
+/// Disassemble `code` loaded at 0000:0100 into its instruction texts
+fn disasm_texts(code: &[u8], count: usize) -> Vec<String> {
+    let emu = emu_with_code(code);
+    emu.disassemble(0, 0x100, count).into_iter().map(|i| i.text).collect()
+}
+
+#[test]
+fn test_disasm_basic() {
+    let code = [
+        0xB8, 0x34, 0x12,       // MOV AX, 0x1234
+        0x88, 0xC3,             // MOV BL, AL
+        0x01, 0xD8,             // ADD AX, BX
+        0x3C, 0x05,             // CMP AL, 5
+        0x74, 0xFE,             // JZ $
+        0xE8, 0x00, 0x01,       // CALL +0x100
+        0xCD, 0x21,             // INT 21h
+        0xF4,                   // HLT
+    ];
+    assert_eq!(
+        disasm_texts(&code, 8),
+        [
+            "mov ax, 0x1234",
+            "mov bl, al",
+            "add ax, bx",
+            "cmp al, 0x5",
+            "jz 0x109",
+            "call 0x20e",
+            "int 0x21",
+            "hlt",
+        ]
+    );
+}
+
+#[test]
+fn test_disasm_memory_operands() {
+    let code = [
+        0x8B, 0x07,                   // MOV AX, [BX]
+        0x26, 0x88, 0x47, 0xFE,       // MOV [ES:BX-2], AL
+        0x8B, 0x16, 0x34, 0x12,       // MOV DX, [0x1234]
+        0xC7, 0x46, 0x04, 0x01, 0x00, // MOV WORD [BP+4], 1
+        0x83, 0x38, 0xFF,             // CMP WORD [BX+SI], -1
+        0xFE, 0x81, 0x00, 0x10,       // INC BYTE [BX+DI+0x1000]
+        0xFF, 0x1F,                   // CALL FAR [BX]
+        0x2E, 0xA1, 0x00, 0x02,       // MOV AX, [CS:0x200]
+    ];
+    assert_eq!(
+        disasm_texts(&code, 8),
+        [
+            "mov ax, [bx]",
+            "mov [es:bx-0x2], al",
+            "mov dx, [0x1234]",
+            "mov word [bp+0x4], 0x1",
+            "cmp word [bx+si], byte -0x1",
+            "inc byte [bx+di+0x1000]",
+            "call far [bx]",
+            "mov ax, [cs:0x200]",
+        ]
+    );
+}
+
+#[test]
+fn test_disasm_prefixes() {
+    let code = [
+        0xF3, 0xA4,       // REP MOVSB
+        0xF3, 0xA6,       // REPE CMPSB
+        0xF2, 0xAE,       // REPNE SCASB
+        0x26, 0xA5,       // ES MOVSW (override with no memory operand)
+    ];
+    assert_eq!(disasm_texts(&code, 4), ["rep movsb", "repe cmpsb", "repne scasb", "es movsw"]);
+}
+
+#[test]
+fn test_disasm_lengths_and_display() {
+    let emu = emu_with_code(&[0x26, 0x88, 0x47, 0xFE, 0x90]);
+    let insns = emu.disassemble(0, 0x100, 2);
+    assert_eq!(insns[0].len(), 4);
+    assert_eq!(insns[0].next_off(), 0x104);
+    assert_eq!(insns[1].off, 0x104);
+    assert_eq!(insns[0].to_string(), "0000:0100  26 88 47 FE       mov [es:bx-0x2], al");
+}
+
+#[test]
+fn test_disasm_undefined_and_truncated() {
+    // 0x63 isn't an 8086 opcode; 0xFF /7 is undefined
+    assert_eq!(disasm_texts(&[0x63, 0xFF, 0xF8], 2), ["db 0x63", "db 0xff"]);
+
+    // An instruction cut off by the end of the input
+    let insn = disasm::decode(&[0xB8, 0x34], 0, 0x100);
+    assert_eq!(insn.text, "db 0xb8");
+    assert_eq!(insn.len(), 1);
+}
+
+#[test]
+fn test_disasm_at_unknown_opcode() {
+    // MOV AX, 1; SHL AX, 1 (not implemented by the executor)
+    let mut emu = emu_with_code(&[0xB8, 0x01, 0x00, 0xD1, 0xE0]);
+    let (ip, result) = loop {
+        let ip = emu.cpu.ip;
+        match emu.step() {
+            StepResult::Continue => continue,
+            other => break (ip, other),
+        }
+    };
+    assert_eq!(result, StepResult::UnknownOpcode(0xD1));
+    assert_eq!(emu.disassemble_one(emu.cpu.cs, ip).text, "shl ax, 1");
+}
+
+// ============================================================================
+// BREAKPOINT AND WATCHPOINT TESTS
+// ============================================================================
+
+#[test]
+fn test_breakpoint_stops_and_resumes() {
+    let code = [
+        0xB8, 0x01, 0x00,   // 0100: MOV AX, 1
+        0x40,               // 0103: INC AX
+        0x40,               // 0104: INC AX
+        0xF4,               // 0105: HLT
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.add_breakpoint(0, 0x104);
+
+    let (result, _) = emu.run(100);
+    assert_eq!(result, StepResult::Breakpoint);
+    assert_eq!(emu.cpu.ip, 0x104);
+    assert_eq!(emu.cpu.ax, 2); // The instruction at the breakpoint hasn't run
+
+    // Running again executes it rather than stopping on it a second time
+    let (result, _) = emu.run(100);
+    assert_eq!(result, StepResult::Halt);
+    assert_eq!(emu.cpu.ax, 3);
+
+    assert!(emu.remove_breakpoint(0, 0x104));
+    assert!(!emu.remove_breakpoint(0, 0x104));
+}
+
+#[test]
+fn test_breakpoint_matches_linear_address() {
+    let mut emu = emu_with_code(&[0x90, 0xF4]);
+    emu.add_breakpoint(0x10, 0x0001); // Same byte as 0000:0101
+    assert_eq!(emu.run(100).0, StepResult::Breakpoint);
+    assert_eq!(emu.cpu.ip, 0x101);
+}
+
+#[test]
+fn test_breakpoint_in_loop_fires_each_pass() {
+    let code = [
+        0xB9, 0x03, 0x00,   // 0100: MOV CX, 3
+        0x49,               // 0103: DEC CX
+        0x75, 0xFD,         // 0104: JNZ 0103
+        0xF4,               // 0106: HLT
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.add_breakpoint(0, 0x103);
+    let mut hits = 0;
+    while emu.run(100).0 == StepResult::Breakpoint {
+        hits += 1;
+    }
+    assert_eq!(hits, 3);
+    assert_eq!(emu.cpu.cx, 0);
+}
+
+#[test]
+fn test_watchpoint_write() {
+    let code = [
+        0xB0, 0x42,             // 0100: MOV AL, 0x42
+        0xA2, 0x00, 0x02,       // 0102: MOV [0x200], AL
+        0xA0, 0x00, 0x02,       // 0105: MOV AL, [0x200]
+        0xF4,                   // 0108: HLT
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.add_watchpoint(0, 0x200, 1, WatchKind::Write);
+
+    let (result, _) = emu.run(100);
+    assert_eq!(result, StepResult::Watchpoint(WatchHit { addr: 0x200, value: 0x42, write: true }));
+    // The write has happened and IP is past the instruction
+    assert_eq!(emu.memory[0x200], 0x42);
+    assert_eq!(emu.cpu.ip, 0x105);
+
+    // The read isn't reported
+    assert_eq!(emu.run(100).0, StepResult::Halt);
+}
+
+#[test]
+fn test_watchpoint_read_range() {
+    let code = [
+        0xBE, 0x00, 0x03,   // 0100: MOV SI, 0x300
+        0xAD,               // 0103: LODSW
+        0xAD,               // 0104: LODSW
+        0xF4,               // 0105: HLT
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.memory[0x302] = 0x34;
+    emu.memory[0x303] = 0x12;
+    emu.add_watchpoint(0, 0x303, 4, WatchKind::Read);
+
+    let (result, _) = emu.run(100);
+    assert_eq!(result, StepResult::Watchpoint(WatchHit { addr: 0x303, value: 0x12, write: false }));
+    assert_eq!(emu.cpu.ax, 0x1234);
+    assert_eq!(emu.cpu.ip, 0x105);
+}
+
+#[test]
+fn test_watchpoint_ignores_fetch() {
+    // Watching the code itself doesn't stop on every instruction
+    let mut emu = emu_with_code(&[0x90, 0x90, 0xF4]);
+    emu.add_watchpoint(0, 0x100, 3, WatchKind::Access);
+    assert_eq!(emu.run(100).0, StepResult::Halt);
+    assert_eq!(emu.disassemble_one(0, 0x100).text, "nop");
+
+    assert!(emu.remove_watchpoint(0, 0x100));
+    assert!(emu.watchpoints().is_empty());
+}
+
+#[test]
+fn test_step_over_call() {
+    let code = [
+        0xE8, 0x02, 0x00,   // 0100: CALL 0105
+        0xF4,               // 0103: HLT
+        0x90,               // 0104: NOP
+        0x40,               // 0105: INC AX
+        0x40,               // 0106: INC AX
+        0xC3,               // 0107: RET
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.cpu.sp = 0x1000;
+
+    let (result, steps) = emu.step_over(100);
+    assert_eq!(result, StepResult::Continue);
+    assert_eq!(steps, 4);
+    assert_eq!(emu.cpu.ip, 0x103);
+    assert_eq!(emu.cpu.ax, 2);
+    assert_eq!(emu.cpu.sp, 0x1000);
+
+    // Not a call: one instruction
+    assert_eq!(emu.step_over(100), (StepResult::Halt, 1));
+}
+
+#[test]
+fn test_step_over_stops_at_breakpoint_inside_call() {
+    let code = [
+        0xE8, 0x01, 0x00,   // 0100: CALL 0104
+        0xF4,               // 0103: HLT
+        0x40,               // 0104: INC AX
+        0xC3,               // 0105: RET
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.add_breakpoint(0, 0x105);
+    assert_eq!(emu.step_over(100).0, StepResult::Breakpoint);
+    assert_eq!(emu.cpu.ip, 0x105);
+
+    emu.clear_debug();
+    assert!(emu.breakpoints().is_empty());
+    assert_eq!(emu.run(100).0, StepResult::Halt);
+}
+
+// ============================================================================
+// MEMORY MAP TESTS
+// ============================================================================
+
+#[test]
+fn test_rom_ignores_writes() {
+    let code = [
+        0xB8, 0x00, 0xF0,       // MOV AX, 0xF000
+        0x8E, 0xD8,             // MOV DS, AX
+        0xC6, 0x06, 0x00, 0x00, 0x99, // MOV BYTE [0], 0x99
+        0xA0, 0x00, 0x00,       // MOV AL, [0]
+        0xF4,                   // HLT
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.load_code_at(0xF000, 0, &[0xEA]);
+    emu.map_rom(0xF0000, 0x10000);
+
+    emu.run(100);
+    assert_eq!(emu.memory[0xF0000], 0xEA);
+    assert_eq!(emu.cpu.ax & 0xFF, 0xEA);
+
+    // Unmapped, it's RAM again
+    assert!(emu.unmap(0xF0000));
+    emu.write_u8(0xF000, 0, 0x99);
+    assert_eq!(emu.read_u8(0xF000, 0), 0x99);
+}
+
+/// Video RAM stand-in that records writes and keeps them in RAM
+struct VideoLog(std::rc::Rc<std::cell::RefCell<Vec<(usize, u8)>>>);
+
+impl bus::MmioHandler for VideoLog {
+    fn write(&self, addr: usize, val: u8) -> bool {
+        self.0.borrow_mut().push((addr, val));
+        false
+    }
+}
+
+#[test]
+fn test_mmio_write_callback_falls_back_to_ram() {
+    let code = [
+        0xB8, 0x00, 0xB8,       // MOV AX, 0xB800
+        0x8E, 0xC0,             // MOV ES, AX
+        0x31, 0xFF,             // XOR DI, DI
+        0xB8, 0x41, 0x07,       // MOV AX, 0x0741 ('A', grey on black)
+        0xAB,                   // STOSW
+        0xF4,                   // HLT
+    ];
+    let log = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut emu = emu_with_code(&code);
+    emu.map_mmio(0xB8000, 0x8000, Box::new(VideoLog(log.clone())));
+
+    emu.run(100);
+    assert_eq!(*log.borrow(), [(0xB8000, 0x41), (0xB8001, 0x07)]);
+    assert_eq!(emu.read_u16(0xB800, 0), 0x0741);
+}
+
+/// Always reads 0x5A and drops writes
+struct Fixed;
+
+impl bus::MmioHandler for Fixed {
+    fn read(&self, _addr: usize) -> Option<u8> {
+        Some(0x5A)
+    }
+
+    fn write(&self, _addr: usize, _val: u8) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_mmio_newest_mapping_wins() {
+    let mut emu = Emulator::new();
+    emu.map_rom(0x1000, 0x100);
+    emu.map_mmio(0x1010, 0x10, Box::new(Fixed));
+    emu.memory[0x1000] = 0x11;
+
+    assert_eq!(emu.read_u8(0x100, 0x00), 0x11);
+    assert_eq!(emu.read_u8(0x100, 0x10), 0x5A);
+    emu.write_u8(0x100, 0x10, 0x77);
+    assert_eq!(emu.memory[0x1010], 0);
+    assert_eq!(emu.regions().len(), 2);
+}
+
+static HOST_TICKS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+fn host_ticks() -> u32 {
+    HOST_TICKS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+#[test]
+fn test_tick_counter() {
+    let code = [
+        0xB8, 0x40, 0x00,       // MOV AX, 0x40
+        0x8E, 0xD8,             // MOV DS, AX
+        0xA1, 0x6C, 0x00,       // MOV AX, [0x6C]
+        0x8B, 0x16, 0x6E, 0x00, // MOV DX, [0x6E]
+        0xF4,                   // HLT
+    ];
+    HOST_TICKS.store(0x0001_2345, std::sync::atomic::Ordering::Relaxed);
+    let mut emu = emu_with_code(&code);
+    emu.map_tick_counter(host_ticks);
+    emu.run(100);
+    assert_eq!((emu.cpu.dx, emu.cpu.ax), (0x0001, 0x2345));
+
+    // Resetting the count moves it relative to the host clock
+    emu.write_u16(0x40, 0x6C, 0);
+    emu.write_u16(0x40, 0x6E, 0);
+    assert_eq!(emu.read_u16(0x40, 0x6C), 0);
+    HOST_TICKS.fetch_add(10, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(emu.read_u16(0x40, 0x6C), 10);
+    assert_eq!(emu.memory[0x46C], 0);
+}
+
+// ============================================================================
+// DEVICE TESTS (PIT, PIC, 8042)
+// ============================================================================
+
+/// Point IVT entry `vector` at 0000:`handler` and load `code` there
+fn install_handler(emu: &mut Emulator, vector: u8, handler: u16, code: &[u8]) {
+    emu.write_u16(0, vector as u16 * 4, handler);
+    emu.write_u16(0, vector as u16 * 4 + 2, 0);
+    emu.load_code_at(0, handler, code);
+}
+
+#[test]
+fn test_pit_programming_and_latch() {
+    let code = [
+        0xB0, 0x34,         // MOV AL, 0x34 (channel 0, LSB/MSB, mode 2)
+        0xE6, 0x43,         // OUT 0x43, AL
+        0xB0, 0x9C,         // MOV AL, 0x9C
+        0xE6, 0x40,         // OUT 0x40, AL
+        0xB0, 0x2E,         // MOV AL, 0x2E
+        0xE6, 0x40,         // OUT 0x40, AL   (count 0x2E9C: 100 Hz)
+        0xF4,               // HLT
+    ];
+    let mut emu = run_code(&code);
+    assert_eq!(emu.devices.pit.mode(0), 2);
+    assert_eq!(emu.devices.pit.reload(0), 0x2E9C);
+
+    // Latch, then read LSB and MSB
+    emu.devices.advance(0x100);
+    emu.devices.port_out(0x43, 0x00);
+    emu.devices.advance(0x100);
+    let lo = emu.devices.port_in(0x40) as u16;
+    let hi = emu.devices.port_in(0x40) as u16;
+    assert_eq!(lo | (hi << 8), 0x2E9C - 0x100);
+}
+
+#[test]
+fn test_pit_periodic_irq_edges() {
+    let mut pit = devices::Pit::new();
+    pit.write(0x43, 0x36); // Channel 0, LSB/MSB, mode 3
+    pit.write(0x40, 100);
+    pit.write(0x40, 0);
+    assert_eq!(pit.advance(99), 0);
+    assert_eq!(pit.advance(1), 1);
+    assert_eq!(pit.advance(250), 2);
+
+    // Mode 0 fires once
+    pit.write(0x43, 0x30);
+    pit.write(0x40, 10);
+    pit.write(0x40, 0);
+    assert_eq!(pit.advance(1000), 1);
+    assert_eq!(pit.advance(0x20000), 0);
+}
+
+#[test]
+fn test_irq0_enters_handler_through_ivt() {
+    // Main program: STI, then spin incrementing BX
+    let code = [
+        0xFB,               // 0100: STI
+        0x43,               // 0101: INC BX
+        0xEB, 0xFD,         // 0102: JMP 0101
+    ];
+    // IRQ 0 handler: INC CX; EOI; IRET
+    let handler = [
+        0x41,               // INC CX
+        0xB0, 0x20,         // MOV AL, 0x20
+        0xE6, 0x20,         // OUT 0x20, AL
+        0xCF,               // IRET
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.cpu.sp = 0x1000;
+    install_handler(&mut emu, 0x08, 0x500, &handler);
+
+    emu.run(10);
+    assert_eq!(emu.cpu.cx, 0);
+
+    // A full 65536-count period raises IRQ 0
+    emu.devices.advance(0x10000);
+    emu.step();
+    assert_eq!((emu.cpu.cs, emu.cpu.ip), (0, 0x500));
+    assert!(!emu.cpu.get_flag(FLAG_IF));
+    assert_eq!(emu.devices.pics.master.isr, 0x01);
+
+    emu.run(4);
+    assert_eq!(emu.cpu.cx, 1);
+    assert_eq!(emu.cpu.sp, 0x1000);
+    assert!(emu.cpu.get_flag(FLAG_IF));
+    assert_eq!(emu.devices.pics.master.isr, 0);
+    assert!((0x101..=0x103).contains(&emu.cpu.ip));
+}
+
+#[test]
+fn test_irq_waits_for_if_and_mask() {
+    let mut emu = emu_with_code(&[0x90, 0x90, 0x90, 0xF4]);
+    emu.cpu.sp = 0x1000;
+    install_handler(&mut emu, 0x08, 0x500, &[0xCF]);
+    emu.devices.pics.raise(0);
+
+    // IF clear: ignored
+    emu.step();
+    assert_eq!(emu.cpu.ip, 0x101);
+
+    // IF set but masked
+    emu.cpu.set_flag(FLAG_IF, true);
+    emu.devices.port_out(0x21, 0x01);
+    emu.step();
+    assert_eq!(emu.cpu.ip, 0x102);
+
+    emu.devices.port_out(0x21, 0x00);
+    emu.step();
+    assert_eq!(emu.cpu.ip, 0x500);
+}
+
+#[test]
+fn test_pic_priority_and_cascade() {
+    let mut pics = devices::Pics::new();
+    pics.raise(12); // Slave IRQ 4 (PS/2 mouse)
+    pics.raise(1);
+    assert_eq!(pics.acknowledge(), Some(0x09));
+    // IRQ 1 is in service, so the lower-priority cascade waits
+    assert_eq!(pics.acknowledge(), None);
+    pics.write(0x20, 0x20);
+    assert_eq!(pics.acknowledge(), Some(0x74));
+    assert_eq!(pics.master.isr, 0x04);
+    assert_eq!(pics.slave.isr, 0x10);
+
+    // Reinitialize the master at vector 0x50
+    for (port, val) in [(0x20, 0x11), (0x21, 0x50), (0x21, 0x04), (0x21, 0x01)] {
+        pics.write(port, val);
+    }
+    pics.raise(0);
+    assert_eq!(pics.acknowledge(), Some(0x50));
+
+    // OCW3 selects ISR for reads of the command port
+    pics.write(0x20, 0x0B);
+    assert_eq!(pics.read(0x20), 0x01);
+}
+
+#[test]
+fn test_keyboard_irq1_delivers_scancodes() {
+    let code = [
+        0xFB,               // 0100: STI
+        0xEB, 0xFE,         // 0101: JMP $
+    ];
+    // IRQ 1 handler: read the scancode into the buffer at DI, EOI, IRET
+    let handler = [
+        0xE4, 0x60,         // IN AL, 0x60
+        0xAA,               // STOSB
+        0xB0, 0x20,         // MOV AL, 0x20
+        0xE6, 0x20,         // OUT 0x20, AL
+        0xCF,               // IRET
+    ];
+    let mut emu = emu_with_code(&code);
+    emu.cpu.sp = 0x1000;
+    emu.cpu.di = 0x600;
+    install_handler(&mut emu, 0x09, 0x500, &handler);
+
+    // 'A' make and break, arriving together
+    emu.devices.key(0x1E);
+    emu.devices.key(0x9E);
+    emu.run(30);
+    assert_eq!(&emu.memory[0x600..0x603], &[0x1E, 0x9E, 0x00]);
+    assert_eq!(emu.devices.port_in(0x64) & 0x01, 0);
+}
+
+#[test]
+fn test_kbc_commands() {
+    let mut dev = devices::Devices::new();
+
+    // Controller self test
+    dev.port_out(0x64, 0xAA);
+    assert_eq!(dev.port_in(0x64) & 0x01, 0x01);
+    assert_eq!(dev.port_in(0x60), 0x55);
+
+    // Keyboard identify
+    dev.port_out(0x60, 0xF2);
+    assert_eq!([dev.port_in(0x60), dev.port_in(0x60), dev.port_in(0x60)], [0xFA, 0xAB, 0x83]);
+
+    // Set LEDs
+    dev.port_out(0x60, 0xED);
+    dev.port_out(0x60, 0x04);
+    assert_eq!(dev.kbc.leds, 0x04);
+
+    // Disabling the keyboard holds scancodes until it's enabled again
+    dev.pics = devices::Pics::new();
+    dev.port_in(0x60);
+    dev.port_in(0x60);
+    dev.port_out(0x64, 0xAD);
+    dev.key(0x1C);
+    assert_eq!(dev.port_in(0x64) & 0x01, 0);
+    dev.port_out(0x64, 0xAE);
+    assert_eq!(dev.port_in(0x60), 0x1C);
+    assert!(dev.pics.master.irr & 0x02 != 0);
+
+    // Reset pulse
+    dev.port_out(0x64, 0xFE);
+    assert!(dev.kbc.take_reset());
+}
+
+#[test]
+fn test_port_b_refresh_toggles() {
+    let mut emu = run_code(&[0xE4, 0x61, 0x88, 0xC3, 0xE4, 0x61, 0xF4]); // IN AL,61; MOV BL,AL; IN AL,61
+    assert_ne!(emu.cpu.ax & 0x10, emu.cpu.bx & 0x10);
+    assert_eq!(emu.devices.port_in(0x99), 0xFF);
+}
+
+// ============================================================================
+// DIFFERENTIAL FUZZING
+// ============================================================================
+
+#[test]
+fn test_fuzz_matches_reference() {
+    if let Err(mismatch) = fuzz::run(0x8086, 2000) {
+        panic!("emulator and reference disagree\n{}", mismatch);
+    }
+}
+
+#[test]
+fn test_fuzz_case_is_reproducible() {
+    let (a, b) = (fuzz::Case::generate(42), fuzz::Case::generate(42));
+    assert_eq!(a.cpu, b.cpu);
+    assert_eq!(a.code(), b.code());
+    assert_eq!(a.emulator().memory, b.emulator().memory);
+    assert_ne!(a.code(), fuzz::Case::generate(43).code());
+}
+
+#[test]
+fn test_fuzz_reports_mismatch() {
+    // An emulator that starts with DF flipped disagrees on the first step
+    let case = fuzz::Case::generate(7);
+    let mut emu = case.emulator();
+    emu.cpu.flags ^= FLAG_DF;
+    let mismatch = case.check(&mut emu).unwrap_err();
+    assert_eq!(mismatch.index, 0);
+    assert_eq!(mismatch.expected.flags ^ mismatch.actual.flags, FLAG_DF);
+    assert!(mismatch.to_string().contains("flags: expected"));
+}
+
+#[test]
+fn test_bp_addressing_defaults_to_ss() {
+    let mut emu = emu_with_code(&[0x8B, 0x46, 0x02, 0x3E, 0x8B, 0x5E, 0x02]); // MOV AX,[BP+2]; MOV BX,[DS:BP+2]
+    emu.cpu.ds = 0x1000;
+    emu.cpu.ss = 0x2000;
+    emu.cpu.bp = 0x10;
+    emu.write_u16(0x2000, 0x12, 0x5555);
+    emu.write_u16(0x1000, 0x12, 0xAAAA);
+    emu.step();
+    emu.step();
+    assert_eq!(emu.cpu.ax, 0x5555);
+    assert_eq!(emu.cpu.bx, 0xAAAA);
+}
+
+#[test]
+fn test_sahf_keeps_reserved_bits() {
+    let emu = run_code(&[0xB4, 0xFF, 0x9E, 0xF4]); // MOV AH,FF; SAHF
+    assert_eq!(emu.cpu.flags & 0xFF, 0xD7);
+}
@@ -51,6 +51,10 @@ usage() {
     echo "  -i             Shortcut for --interactive"
     echo "  -h, --help     Show this help"
     echo ""
+    echo "Environment:"
+    echo "  SHARE_DIR=DIR  Share a host directory over 9P (interactive, as H:)"
+    echo "  BALLOON=1      Add a virtio memory balloon with free page reporting"
+    echo ""
     echo "Examples:"
     echo "  $0 -i                    # Interactive mode"
     echo "  $0 --cmd 'ls' --expect 'TEST.TXT'"
@@ -341,6 +345,18 @@ if [ "$INTERACTIVE" = true ]; then
         QEMU_ARGS+=(-device ide-hd,drive=dosdisk,bus=ide.2)
     fi
 
+    # Share a host directory over virtio 9P (mounted as H:)
+    if [ -n "$SHARE_DIR" ]; then
+        log "Sharing $SHARE_DIR over 9P"
+        QEMU_ARGS+=(-virtfs "local,path=$SHARE_DIR,mount_tag=host,security_model=none")
+    fi
+
+    # Memory balloon: resize from the monitor with `balloon <MB>`
+    if [ -n "$BALLOON" ]; then
+        log "Adding a virtio memory balloon"
+        QEMU_ARGS+=(-device virtio-balloon-pci,free-page-reporting=on)
+    fi
+
     # Launch QEMU with auto VNC viewer if available
     if [ "$USE_VNC" = true ] && [ -n "$VNC_VIEWER" ]; then
         # Start QEMU in background
@@ -98,7 +98,7 @@ echo ""
 if [ "$CLEAN_BUILD" = true ]; then
     log "Cleaning previous build artifacts..."
     rm -rf target/
-    rm -f kernel.bin BOOTX64.EFI
+    rm -f kernel.bin kernel.sym BOOTX64.EFI
     rm -rf uefi_test/
     success "Clean complete"
 fi
@@ -156,6 +156,18 @@ log "Using objcopy: $OBJCOPY"
 $OBJCOPY --binary-architecture=i386:x86-64 "$KERNEL_ELF" -O binary "$PROJECT_ROOT/kernel.bin"
 success "Kernel binary extracted: kernel.bin ($(du -h "$PROJECT_ROOT/kernel.bin" | cut -f1))"
 
+# Kernel symbol map for the sampling profiler (/proc/profile), text symbols only
+NM="$(dirname "$RUST_OBJCOPY")/rust-nm"
+if [ ! -f "$NM" ]; then
+    NM="$(command -v llvm-nm || command -v nm || true)"
+fi
+if [ -n "$NM" ] && "$NM" -n --demangle "$KERNEL_ELF" | grep -i ' [tw] ' > "$PROJECT_ROOT/kernel.sym"; then
+    success "Kernel symbol map: kernel.sym ($(wc -l < "$PROJECT_ROOT/kernel.sym") symbols)"
+else
+    echo -e "${YELLOW}[WARN]${NC} Could not generate kernel.sym (profiler will show raw addresses)"
+    rm -f "$PROJECT_ROOT/kernel.sym"
+fi
+
 # Step 3: Build bootloader
 log "Building UEFI bootloader (target: x86_64-unknown-uefi)..."
 cd "$PROJECT_ROOT"
@@ -186,6 +198,14 @@ if [ "$CREATE_UEFI_STRUCTURE" = true ]; then
     mkdir -p "$PROJECT_ROOT/uefi_test/EFI/BOOT"
     cp "$PROJECT_ROOT/BOOTX64.EFI" "$PROJECT_ROOT/uefi_test/EFI/BOOT/"
     cp "$PROJECT_ROOT/kernel.bin" "$PROJECT_ROOT/uefi_test/"
+    if [ -f "$PROJECT_ROOT/kernel.sym" ]; then
+        cp "$PROJECT_ROOT/kernel.sym" "$PROJECT_ROOT/uefi_test/"
+    fi
+    # Boot configuration (read by the bootloader, passed to the kernel)
+    if [ -f "$PROJECT_ROOT/rootfs/boot/watos.cfg" ]; then
+        mkdir -p "$PROJECT_ROOT/uefi_test/boot"
+        cp "$PROJECT_ROOT/rootfs/boot/watos.cfg" "$PROJECT_ROOT/uefi_test/boot/"
+    fi
     success "UEFI structure created in uefi_test/"
 fi
 
@@ -197,13 +217,29 @@ if [ ! -f "$MKFS_WFS" ]; then
     if rustup run stable cargo build --release 2>&1; then
         mkdir -p "$PROJECT_ROOT/output"
         cp target/x86_64-unknown-linux-gnu/release/mkfs_wfs "$MKFS_WFS"
-        success "mkfs.wfs built"
+        cp target/x86_64-unknown-linux-gnu/release/mkfs_fat "$PROJECT_ROOT/output/mkfs_fat"
+        success "mkfs.wfs built (with mkfs_fat)"
     else
         echo -e "${YELLOW}[WARN]${NC} mkfs.wfs build failed (optional)"
     fi
     cd "$PROJECT_ROOT"
 fi
 
+# Step 6b: Build mkimage (creates the boot disk from images/*.toml)
+MKIMAGE="$PROJECT_ROOT/output/mkimage"
+if [ ! -f "$MKIMAGE" ]; then
+    log "Building mkimage tool..."
+    cd "$PROJECT_ROOT/tools/mkimage"
+    if rustup run stable cargo build --release 2>&1; then
+        mkdir -p "$PROJECT_ROOT/output"
+        cp target/x86_64-unknown-linux-gnu/release/mkimage "$MKIMAGE"
+        success "mkimage built"
+    else
+        echo -e "${YELLOW}[WARN]${NC} mkimage build failed"
+    fi
+    cd "$PROJECT_ROOT"
+fi
+
 # Step 7: Build WATOS native applications
 log "Building WATOS native applications..."
 mkdir -p "$PROJECT_ROOT/rootfs/BIN"
@@ -560,9 +596,38 @@ else
 fi
 cd "$PROJECT_ROOT"
 
+# Build the program interpreter (ld-watos)
+# A static PIE rather than a fixed-address app: SYS_EXEC loads it next to the
+# program it links, and it relocates itself. --export-dynamic keeps dlopen and
+# friends in its dynamic symbol table so programs can link against them.
+log "Building ld-watos for WATOS..."
+cd "$PROJECT_ROOT/crates/apps/ld-watos"
+
+LD_RUSTFLAGS="$COMMON_RUSTFLAGS -C relocation-model=pie -C link-arg=--export-dynamic"
+if RUSTFLAGS="$LD_RUSTFLAGS" CARGO_TARGET_DIR="$PROJECT_ROOT/target/ld-watos" cargo build $CARGO_FLAGS \
+    --target x86_64-unknown-none \
+    --bin ld-watos 2>&1; then
+
+    if [ "$BUILD_TYPE" = "release" ]; then
+        LD_BIN="$PROJECT_ROOT/target/ld-watos/x86_64-unknown-none/release/ld-watos"
+    else
+        LD_BIN="$PROJECT_ROOT/target/ld-watos/x86_64-unknown-none/debug/ld-watos"
+    fi
+
+    if [ -f "$LD_BIN" ]; then
+        mkdir -p "$PROJECT_ROOT/rootfs/lib" "$PROJECT_ROOT/uefi_test/lib"
+        cp "$LD_BIN" "$PROJECT_ROOT/rootfs/lib/ld-watos"
+        cp "$LD_BIN" "$PROJECT_ROOT/uefi_test/lib/ld-watos"
+        success "ld-watos built -> /lib/ld-watos ($(du -h "$LD_BIN" | cut -f1))"
+    fi
+else
+    echo -e "${YELLOW}[WARN]${NC} ld-watos build failed (optional)"
+fi
+cd "$PROJECT_ROOT"
+
 # Build all remaining apps in crates/apps/ automatically
 log "Building additional WATOS applications..."
-BUILT_APPS="gwbasic echo date clear uname uptime ps drives ls pwd cd mkdir console"
+BUILT_APPS="gwbasic echo date clear uname uptime ps drives ls pwd cd mkdir console ld-watos"
 
 for app_dir in "$PROJECT_ROOT/crates/apps"/*; do
     if [ -d "$app_dir" ] && [ -f "$app_dir/Cargo.toml" ]; then
@@ -695,8 +760,9 @@ if [ -f "$MKFS_WFS" ]; then
     [ -f "$PROJECT_ROOT/rootfs/CONFIG.SYS" ] || echo "REM WATOS Configuration" > "$PROJECT_ROOT/rootfs/CONFIG.SYS"
     [ -f "$PROJECT_ROOT/rootfs/AUTOEXEC.BAT" ] || echo "@ECHO WATOS Ready" > "$PROJECT_ROOT/rootfs/AUTOEXEC.BAT"
 
-    # Create WFS disk image (CoW filesystem with B+ trees - supports millions of files)
-    "$MKFS_WFS" -o "$PROJECT_ROOT/output/watos.img" -s 64M -d "$PROJECT_ROOT/rootfs"
+    # Create WFS disk image (CoW filesystem with B+ trees - supports millions of files),
+    # with file data compressed
+    "$MKFS_WFS" -o "$PROJECT_ROOT/output/watos.img" -s 64M -d "$PROJECT_ROOT/rootfs" -c
     success "WFS disk image created: output/watos.img (populated from rootfs)"
 fi
 
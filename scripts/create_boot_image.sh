@@ -1,6 +1,6 @@
 #!/bin/bash
 # Create bootable FAT32 disk image from uefi_test directory
-# Uses mtools (no root/sudo required)
+# Built by mkimage from images/uefi_boot.toml (no root/sudo or mtools required)
 
 set -e
 
@@ -25,84 +25,31 @@ error() {
     echo -e "${RED}[ERROR]${NC} $1"
 }
 
-# Check if mtools is available
-if ! command -v mformat >/dev/null 2>&1; then
-    error "mtools not found. Install with: sudo dnf install mtools"
-    exit 1
-fi
-
 SOURCE_DIR="$PROJECT_ROOT/uefi_test"
+MANIFEST="$PROJECT_ROOT/images/uefi_boot.toml"
 IMAGE_FILE="$PROJECT_ROOT/uefi_boot.img"
+MKIMAGE="$PROJECT_ROOT/output/mkimage"
 
 if [ ! -d "$SOURCE_DIR" ]; then
     error "Source directory not found: $SOURCE_DIR"
     exit 1
 fi
 
-log "Creating bootable disk image from $SOURCE_DIR"
-
-# Create a 64MB disk image (plenty of space for 1.7MB content)
-log "Creating 64MB disk image..."
-dd if=/dev/zero of="$IMAGE_FILE" bs=1M count=64 2>/dev/null
-
-# Format as FAT32 using mtools (no mounting required)
-log "Formatting as FAT32..."
-mformat -i "$IMAGE_FILE" -F -v "WATOS_BOOT" ::
-
-# Copy all files from uefi_test to the image
-log "Copying boot files..."
-
-# Create EFI directory structure
-mmd -i "$IMAGE_FILE" ::/EFI 2>/dev/null || true
-mmd -i "$IMAGE_FILE" ::/EFI/BOOT 2>/dev/null || true
-
-# Copy bootloader
-if [ -f "$SOURCE_DIR/EFI/BOOT/BOOTX64.EFI" ]; then
-    mcopy -i "$IMAGE_FILE" "$SOURCE_DIR/EFI/BOOT/BOOTX64.EFI" ::/EFI/BOOT/
-    log "  - Copied BOOTX64.EFI"
+# The image layout and file list live in the manifest; build.sh builds
+# mkimage, but build it here too so this script works on its own
+if [ ! -x "$MKIMAGE" ]; then
+    log "Building mkimage..."
+    (cd "$PROJECT_ROOT/tools/mkimage" && rustup run stable cargo build --release) || {
+        error "mkimage build failed"
+        exit 1
+    }
+    mkdir -p "$PROJECT_ROOT/output"
+    cp "$PROJECT_ROOT/tools/mkimage/target/x86_64-unknown-linux-gnu/release/mkimage" "$MKIMAGE"
 fi
 
-# Copy kernel
-if [ -f "$SOURCE_DIR/kernel.bin" ]; then
-    mcopy -i "$IMAGE_FILE" "$SOURCE_DIR/kernel.bin" ::/
-    log "  - Copied kernel.bin"
-fi
-
-# Copy apps directory if it exists
-if [ -d "$SOURCE_DIR/apps" ]; then
-    mmd -i "$IMAGE_FILE" ::/apps 2>/dev/null || true
-    mmd -i "$IMAGE_FILE" ::/apps/system 2>/dev/null || true
-
-    # Copy all system apps
-    for app in "$SOURCE_DIR/apps/system"/*; do
-        if [ -f "$app" ]; then
-            mcopy -i "$IMAGE_FILE" "$app" ::/apps/system/
-        fi
-    done
-    log "  - Copied apps/system/ directory"
-fi
-
-# Copy system directory if it exists (contains term)
-if [ -d "$SOURCE_DIR/system" ]; then
-    mmd -i "$IMAGE_FILE" ::/system 2>/dev/null || true
-
-    # Copy all system apps
-    for app in "$SOURCE_DIR/system"/*; do
-        if [ -f "$app" ]; then
-            mcopy -i "$IMAGE_FILE" "$app" ::/system/
-        fi
-    done
-    log "  - Copied system/ directory"
-fi
-
-# Copy AUTOEXEC.CMD if it exists
-if [ -f "$SOURCE_DIR/AUTOEXEC.CMD" ]; then
-    mcopy -i "$IMAGE_FILE" "$SOURCE_DIR/AUTOEXEC.CMD" ::/
-    log "  - Copied AUTOEXEC.CMD"
-fi
+log "Creating bootable disk image from $SOURCE_DIR"
+"$MKIMAGE" "$MANIFEST" -o "$IMAGE_FILE" | sed 's/^/    /'
 
-# Verify the image
-log "Verifying disk image..."
-mdir -i "$IMAGE_FILE" :: >/dev/null 2>&1 && success "Disk image created successfully: uefi_boot.img ($(du -h "$IMAGE_FILE" | cut -f1))"
+success "Disk image created successfully: uefi_boot.img ($(du -h "$IMAGE_FILE" | cut -f1))"
 
 exit 0
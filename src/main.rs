@@ -13,26 +13,42 @@
 extern crate alloc;
 
 use core::panic::PanicInfo;
+#[cfg(not(feature = "heap-debug"))]
 use linked_list_allocator::LockedHeap;
-use spin::Mutex;
+use watos_sync::Mutex;
 
 // Disk and filesystem support
 use watos_driver_traits::{Driver, DriverState};
 use watos_driver_traits::block::{BlockDevice, BlockDeviceExt};
+use watos_driver_traits::cache::CachedDevice;
+use watos_driver_traits::power;
 use watos_driver_ahci::AhciDriver;
 use wfs_common::{Superblock, WFS_MAGIC, BLOCK_SIZE};
 
 // VFS and FAT filesystem
 use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
 use watos_vfs::{FileMode, FileOperations, VfsError};
 use watos_fat::FatFilesystem;
-use watos_procfs::{ProcFs, SystemProvider};
+use watos_procfs::{ProcFs, ProcessProvider, SystemProvider};
+use watos_profiler::{Histogram, SymbolTable};
+use watos_clipboard::Clipboard;
+use watos_bootcfg::{LogLevel, MAX_CMDLINE_SIZE, MAX_CONFIG_SIZE};
 
+#[cfg(not(feature = "heap-debug"))]
 #[global_allocator]
 static ALLOCATOR: LockedHeap = LockedHeap::empty();
 
-const HEAP_START: usize = 0x200000;
-const HEAP_SIZE: usize = 4 * 1024 * 1024;
+/// Debugging heap: checks every free and keeps a live-allocation list (/proc/heap)
+#[cfg(feature = "heap-debug")]
+#[global_allocator]
+static ALLOCATOR: watos_mem::DebugHeap = watos_mem::DebugHeap::empty();
+
+// The 1MB below the heap holds the per-process kernel stacks
+// (watos_mem::paging::KERNEL_STACKS_START)
+const HEAP_START: usize = 0x300000;
+const HEAP_SIZE: usize = 3 * 1024 * 1024;
 
 /// Maximum number of preloaded apps
 const MAX_PRELOADED_APPS: usize = 32;
@@ -62,6 +78,27 @@ pub struct BootInfo {
     pub app_count: u32,       // Number of preloaded apps
     pub _pad: u32,            // Padding for alignment
     pub apps: [PreloadedApp; MAX_PRELOADED_APPS], // Preloaded app table
+    pub config_len: u32,      // Bytes of /boot/watos.cfg in config
+    pub _pad2: u32,
+    pub config: [u8; MAX_CONFIG_SIZE], // Raw boot configuration text
+    pub cmdline_len: u32,     // Bytes of kernel command line
+    pub _pad3: u32,
+    pub cmdline: [u8; MAX_CMDLINE_SIZE], // Kernel command line (ASCII)
+    pub mode_count: u32,      // Number of GOP modes in modes
+    pub _pad4: u32,
+    pub modes: [GopMode; MAX_GOP_MODES], // Resolutions the kernel may switch to
+    pub rsdp_addr: u64,       // ACPI RSDP (0 if the firmware has none)
+}
+
+/// Maximum number of GOP modes recorded by the bootloader
+const MAX_GOP_MODES: usize = 32;
+
+/// A direct-colour mode the firmware's GOP offered
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GopMode {
+    pub width: u32,
+    pub height: u32,
 }
 
 const BOOT_INFO_ADDR: usize = 0x80000;
@@ -70,6 +107,171 @@ const BOOT_MAGIC: u32 = 0x5741544F; // "WATO"
 /// Global boot info (copied from bootloader)
 static mut BOOT_INFO: Option<BootInfo> = None;
 
+/// Publish watos.cfg and the command line from BootInfo to the rest of the
+/// kernel, then apply the kernel-wide options and report what was read
+fn apply_boot_options() {
+    unsafe {
+        if let Some(info) = &*core::ptr::addr_of!(BOOT_INFO) {
+            let config_len = (info.config_len as usize).min(MAX_CONFIG_SIZE);
+            let cmdline_len = (info.cmdline_len as usize).min(MAX_CMDLINE_SIZE);
+            watos_bootcfg::install(&info.config[..config_len], &info.cmdline[..cmdline_len]);
+        }
+    }
+
+    let config = watos_bootcfg::config();
+    let level = watos_bootcfg::log_level();
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] Command line: ");
+        watos_arch::serial_write(watos_bootcfg::cmdline().as_str().as_bytes());
+        watos_arch::serial_write(b"\r\n");
+
+        if let Some(serial) = watos_bootcfg::option("serial") {
+            let ok = serial.parse().is_ok_and(watos_arch::serial_set_baud);
+            if !ok {
+                watos_arch::serial_write(b"[KERNEL] WARNING: unsupported serial speed: ");
+                watos_arch::serial_write(serial.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+        }
+
+        watos_arch::serial_write(b"[KERNEL] Boot config: ");
+        watos_arch::serial_hex(config.entries().count() as u64);
+        watos_arch::serial_write(b" options\r\n");
+
+        if level == LogLevel::Debug {
+            for (key, value) in config.entries() {
+                watos_arch::serial_write(b"[KERNEL]   ");
+                watos_arch::serial_write(key.as_bytes());
+                watos_arch::serial_write(b" = ");
+                watos_arch::serial_write(value.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+        }
+        for line in config.malformed() {
+            watos_arch::serial_write(b"[KERNEL] WARNING: ignoring watos.cfg line: ");
+            watos_arch::serial_write(line.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+        if level == LogLevel::Quiet {
+            watos_arch::serial_write(b"[KERNEL] loglevel=quiet, serial output off\r\n");
+        }
+    }
+    set_log_level(level);
+}
+
+/// Current log level (`kernel.loglevel`), from the boot options until tuned
+static mut LOG_LEVEL: LogLevel = LogLevel::Info;
+
+fn set_log_level(level: LogLevel) {
+    unsafe { LOG_LEVEL = level; }
+    watos_arch::set_serial_echo(level != LogLevel::Quiet);
+}
+
+/// Console colors from watos.cfg: `console.theme`, `console.palette` (up
+/// to 16 comma-separated RRGGBB values for the ANSI colors), `console.fg`
+/// and `console.bg` for every VT, and the same keys under `vtN.` for VT N
+/// alone
+fn configure_console_colors() {
+    use alloc::format;
+
+    for vt in 0..=watos_vt::MAX_VTS {
+        let prefix = if vt == 0 { alloc::string::String::from("console") } else { format!("vt{}", vt) };
+        let option = |key: &str| watos_bootcfg::option(&format!("{}.{}", prefix, key));
+
+        if let Some(name) = option("theme") {
+            let ok = match watos_vt::THEME_NAMES.iter().position(|&n| n == name) {
+                Some(theme) if vt == 0 => watos_vt::console_set_theme(theme),
+                Some(theme) => watos_vt::vt_set_theme(vt, Some(theme)),
+                None => false,
+            };
+            if !ok {
+                unsafe {
+                    watos_arch::serial_write(format!("[KERNEL] WARNING: unknown {}.theme: {}\r\n", prefix, name).as_bytes());
+                }
+            }
+        }
+
+        let (ansi, fg, bg) = (option("palette"), option("fg"), option("bg"));
+        if ansi.is_none() && fg.is_none() && bg.is_none() {
+            continue;
+        }
+        let mut palette = match vt {
+            0 => watos_vt::console_palette(),
+            _ => watos_vt::vt_palette(vt).unwrap_or_default(),
+        };
+        let mut ok = ansi.is_none_or(|list| palette.set_ansi(list));
+        for (value, color) in [(fg, &mut palette.fg), (bg, &mut palette.bg)] {
+            if let Some(value) = value {
+                match watos_vt::parse_rgb(value) {
+                    Some(rgb) => *color = rgb,
+                    None => ok = false,
+                }
+            }
+        }
+        if !ok {
+            unsafe {
+                watos_arch::serial_write(format!("[KERNEL] WARNING: bad {} colors in watos.cfg\r\n", prefix).as_bytes());
+            }
+        } else if vt == 0 {
+            watos_vt::console_set_palette(palette);
+        } else {
+            watos_vt::vt_set_palette(vt, palette);
+        }
+    }
+}
+
+/// Register the kernel's own tunables and those of the subsystems it
+/// links, and make writing any of them root-only
+fn register_sysctls() {
+    use watos_sysctl::{Kind, Tunable};
+
+    watos_sysctl::set_write_check(|| watos_process::get_current_uid() == 0);
+    let _ = watos_sysctl::register(Tunable {
+        name: "kernel.loglevel",
+        description: "serial logging: quiet, info or debug",
+        kind: Kind::Choice(&["quiet", "info", "debug"]),
+        get: || unsafe { LOG_LEVEL as u64 },
+        set: |level| {
+            set_log_level(match level {
+                0 => LogLevel::Quiet,
+                1 => LogLevel::Info,
+                _ => LogLevel::Debug,
+            });
+            true
+        },
+    });
+    let _ = watos_sysctl::register(Tunable {
+        name: "fs.trash",
+        description: "interactive deletes go to the drive's .trash",
+        kind: Kind::Bool,
+        get: || watos_vfs::trash::enabled() as u64,
+        set: |on| {
+            watos_vfs::trash::set_enabled(on != 0);
+            true
+        },
+    });
+    let _ = watos_sysctl::register(Tunable {
+        name: "input.keyboard.repeat_delay",
+        description: "ms a key is held before it repeats",
+        kind: Kind::Int {
+            min: *watos_arch::typematic::DELAY_RANGE.start() as u64,
+            max: *watos_arch::typematic::DELAY_RANGE.end() as u64,
+        },
+        get: || watos_arch::typematic::get().0 as u64,
+        set: |delay| watos_arch::typematic::set(delay as u32, watos_arch::typematic::get().1),
+    });
+    let _ = watos_sysctl::register(Tunable {
+        name: "input.keyboard.repeat_rate",
+        description: "key repeats per second, 0 for none",
+        kind: Kind::Int { min: 0, max: watos_arch::typematic::MAX_RATE as u64 },
+        get: || watos_arch::typematic::get().1 as u64,
+        set: |rate| watos_arch::typematic::set(watos_arch::typematic::get().0, rate as u32),
+    });
+    watos_process::register_sysctls();
+    watos_driver_traits::cache::register_sysctls();
+    watos_vt::register_sysctls();
+}
+
 // ============================================================================
 // Drive Manager - Maps drive names (like "C", "D", "MYDATA") to mount points
 // ============================================================================
@@ -538,7 +740,13 @@ fn init_disk() -> bool {
         }
 
         // Try to create WFS filesystem and mount in VFS
-        match wfs_common::WfsFilesystem::new(driver) {
+        let hook = driver.power_hook();
+        let options = wfs_common::WfsOptions {
+            compress: watos_bootcfg::option_bool("wfs.compress").unwrap_or(false),
+            verify_data: watos_bootcfg::option_bool("wfs.verify").unwrap_or(true),
+        };
+        wfs_common::set_reporter(|msg| unsafe { watos_arch::serial_write(msg.as_bytes()) });
+        match wfs_common::WfsFilesystem::with_options(driver, options) {
             Ok(wfs_fs) => {
                 unsafe {
                     watos_arch::serial_write(b"[KERNEL] WFS filesystem detected on port ");
@@ -546,10 +754,18 @@ fn init_disk() -> bool {
                     watos_arch::serial_write(b"\r\n");
                 }
 
-                // Mount as drive D: in VFS
-                match watos_vfs::mount_drive('D', alloc::boxed::Box::new(wfs_fs)) {
-                    Ok(()) => {
-                        unsafe { watos_arch::serial_write(b"[KERNEL] Mounted WFS as D:\r\n"); }
+                // Mount as drive D: in VFS, unless the drive map says otherwise
+                match watos_vfs::mount_volume('D', alloc::boxed::Box::new(wfs_fs)) {
+                    Ok(letter) => {
+                        unsafe {
+                            watos_arch::serial_write(b"[KERNEL] Mounted WFS as ");
+                            watos_arch::serial_write(&[letter as u8, b':']);
+                            watos_arch::serial_write(b"\r\n");
+                        }
+                        power::register(hook);
+
+                        // Also add to legacy drive table for CURRENT_DRIVE tracking
+                        drive_mount(&[letter as u8], b"/", b"WFS");
                         return true;
                     }
                     Err(_) => {
@@ -571,6 +787,308 @@ fn init_disk() -> bool {
     false
 }
 
+/// Load the drive letters recorded for volumes (`watos_vfs::letters`)
+fn load_drive_map() {
+    match watos_vfs::load_drive_map() {
+        Ok(count) => unsafe {
+            watos_arch::serial_write(b"[KERNEL] Drive map: ");
+            watos_arch::serial_hex(count as u64);
+            watos_arch::serial_write(b" volumes\r\n");
+        },
+        Err(_) => unsafe {
+            watos_arch::serial_write(b"[KERNEL] No drive map, letters are assigned as volumes appear\r\n");
+        },
+    }
+}
+
+/// Rename a drive in the legacy drive table
+fn drive_rename(from: &[u8], to: &[u8]) {
+    if to.is_empty() || to.len() > MAX_DRIVE_NAME {
+        return;
+    }
+    unsafe {
+        for entry in &mut DRIVE_TABLE {
+            if entry.in_use && entry.name[..entry.name_len].eq_ignore_ascii_case(from) {
+                entry.name[..to.len()].copy_from_slice(to);
+                entry.name_len = to.len();
+                return;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Host Directory Sharing (virtio 9P)
+// ============================================================================
+
+/// Carries 9P messages over the virtio 9P device
+struct VirtfsTransport(watos_driver_virtio::p9::Virtio9p);
+
+impl watos_9p::Transport for VirtfsTransport {
+    fn request(&mut self, request: &[u8], reply: &mut [u8]) -> watos_vfs::VfsResult<usize> {
+        self.0.request(request, reply).map_err(|_| VfsError::IoError)
+    }
+}
+
+/// Mount a host directory shared with `-virtfs` as drive H: (or the
+/// `9p.drive` boot option, or the drive map's letter)
+fn init_host_share() -> bool {
+    let mut device = match watos_driver_virtio::p9::Virtio9p::probe() {
+        Some(device) => device,
+        None => return false,
+    };
+    if device.init().is_err() || device.start().is_err() {
+        unsafe { watos_arch::serial_write(b"[KERNEL] virtio 9P init failed\r\n"); }
+        return false;
+    }
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] virtio 9P share: ");
+        watos_arch::serial_write(device.tag().as_bytes());
+        watos_arch::serial_write(b"\r\n");
+    }
+
+    let letter = watos_bootcfg::option("9p.drive")
+        .and_then(|value| value.chars().next())
+        .map(|c| c.to_ascii_uppercase())
+        .unwrap_or('H');
+    let fs = match watos_9p::NinePFs::mount(Box::new(VirtfsTransport(device)), "") {
+        Ok(fs) => fs,
+        Err(_) => {
+            unsafe { watos_arch::serial_write(b"[KERNEL] 9P attach failed\r\n"); }
+            return false;
+        }
+    };
+    match watos_vfs::mount_volume(letter, Box::new(fs)) {
+        Ok(letter) => {
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] Mounted host share as ");
+                watos_arch::serial_write(&[letter as u8, b':']);
+                watos_arch::serial_write(b"\r\n");
+            }
+            drive_mount(&[letter as u8], b"/", b"9P");
+            true
+        }
+        Err(_) => {
+            unsafe { watos_arch::serial_write(b"[KERNEL] Failed to mount host share\r\n"); }
+            false
+        }
+    }
+}
+
+// ============================================================================
+// Memory Balloon - give unused pages back to the host (virtio-balloon)
+// ============================================================================
+
+/// Pages the balloon leaves free however far the host asks it to grow
+const BALLOON_RESERVE_PAGES: usize = 1024;
+
+/// Smallest free run worth reporting (1 MiB), and the most in one range
+const REPORT_MIN_PAGES: usize = 256;
+const REPORT_MAX_PAGES: usize = 1024;
+
+/// Time between free page reports
+const REPORT_INTERVAL_MS: u64 = 2000;
+
+struct Balloon {
+    device: watos_driver_virtio::balloon::VirtioBalloon,
+    /// Physical addresses of the pages the host has
+    pages: alloc::vec::Vec<u64>,
+    /// When free pages were last reported
+    last_report_ms: u64,
+}
+
+static BALLOON: Mutex<Option<Balloon>> = Mutex::new(None);
+
+/// Start the virtio balloon, if QEMU has one
+fn init_balloon() {
+    let mut device = match watos_driver_virtio::balloon::VirtioBalloon::probe() {
+        Some(device) => device,
+        None => return,
+    };
+    if device.init().is_err() || device.start().is_err() {
+        unsafe { watos_arch::serial_write(b"[KERNEL] virtio balloon init failed\r\n"); }
+        return;
+    }
+    // The host polls the first statistics whenever it likes
+    let _ = device.update_stats(&balloon_stats());
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] virtio balloon ready");
+        watos_arch::serial_write(if device.can_report() { b", free page reporting\r\n" } else { b"\r\n" });
+    }
+    *BALLOON.lock() = Some(Balloon { device, pages: alloc::vec::Vec::new(), last_report_ms: 0 });
+}
+
+// ============================================================================
+// Networking - TCP/IP on the e1000 (watos_network::socket)
+// ============================================================================
+
+/// Bring up the e1000, if there is one. The address comes from the
+/// `net.ip`, `net.netmask`, `net.gateway` and `net.dns` boot options, and
+/// defaults to what QEMU's user networking hands out.
+fn init_network() {
+    let mut nic = match watos_driver_e1000::E1000Driver::probe() {
+        Some(nic) => nic,
+        None => return,
+    };
+    if nic.init().is_err() || nic.start().is_err() {
+        unsafe { watos_arch::serial_write(b"[KERNEL] e1000 init failed\r\n"); }
+        return;
+    }
+
+    let mut config = watos_network::NetConfig::default();
+    for (key, addr) in [
+        ("net.ip", &mut config.ip_addr),
+        ("net.netmask", &mut config.netmask),
+        ("net.gateway", &mut config.gateway),
+        ("net.dns", &mut config.dns),
+    ] {
+        if let Some(value) = watos_bootcfg::option(key).and_then(watos_network::parse_ipv4) {
+            *addr = value;
+        }
+    }
+    let seed = watos_arch::cpu::rdtsc() ^ watos_arch::cpu::rdrand().unwrap_or(0);
+    watos_network::socket::init(Box::new(nic), &config, seed, watos_arch::clock::now_ms);
+    unsafe {
+        watos_arch::serial_write(alloc::format!(
+            "[KERNEL] Network up: {} gateway {} dns {}\r\n", config.ip_addr, config.gateway, config.dns).as_bytes());
+    }
+}
+
+/// Memory figures for the host's `guest-stats`
+fn balloon_stats() -> [(u16, u64); 3] {
+    use watos_driver_virtio::balloon::stat;
+
+    let phys = watos_mem::phys::stats();
+    [
+        (stat::MEMORY_FREE, phys.free_bytes() as u64),
+        (stat::MEMORY_TOTAL, phys.total_bytes() as u64),
+        (stat::MEMORY_AVAILABLE, phys.free_bytes() as u64),
+    ]
+}
+
+/// Move the balloon one step toward the host's target, answer a stats
+/// poll, and report free pages now and then. Called when a program idles
+/// or sleeps.
+fn balloon_service() {
+    use watos_driver_virtio::balloon::{MAX_REPORT_RANGES, PAGE_SHIFT, PFNS_PER_REQUEST};
+
+    let Some(mut guard) = BALLOON.try_lock() else { return };
+    let Some(balloon) = guard.as_mut() else { return };
+    if balloon.device.state() != DriverState::Active {
+        return;
+    }
+
+    if balloon.device.stats_requested() {
+        let _ = balloon.device.update_stats(&balloon_stats());
+    }
+
+    let target = balloon.device.target_pages() as usize;
+    let held = balloon.pages.len();
+    if target > held {
+        let free = watos_mem::phys::stats().free_pages;
+        let count = (target - held).min(PFNS_PER_REQUEST).min(free.saturating_sub(BALLOON_RESERVE_PAGES));
+        let pages: alloc::vec::Vec<u64> = (0..count).map_while(|_| watos_mem::phys::alloc_page()).collect();
+        if pages.is_empty() {
+            return;
+        }
+        let pfns: alloc::vec::Vec<u32> = pages.iter().map(|&page| (page >> PAGE_SHIFT) as u32).collect();
+        if balloon.device.inflate(&pfns).is_ok() {
+            balloon.pages.extend_from_slice(&pages);
+        } else {
+            pages.into_iter().for_each(watos_mem::phys::free_page);
+        }
+        balloon.device.set_actual(balloon.pages.len() as u32);
+        return;
+    }
+    if target < held {
+        let count = (held - target).min(PFNS_PER_REQUEST);
+        let pages = balloon.pages.split_off(held - count);
+        let pfns: alloc::vec::Vec<u32> = pages.iter().map(|&page| (page >> PAGE_SHIFT) as u32).collect();
+        // Without the host's acknowledgement the pages can't be trusted
+        if balloon.device.deflate(&pfns).is_ok() {
+            pages.into_iter().for_each(watos_mem::phys::free_page);
+        } else {
+            balloon.pages.extend_from_slice(&pages);
+        }
+        balloon.device.set_actual(balloon.pages.len() as u32);
+        return;
+    }
+
+    let now = watos_arch::clock::now_ms();
+    if !balloon.device.can_report() || now.wrapping_sub(balloon.last_report_ms) < REPORT_INTERVAL_MS {
+        return;
+    }
+    balloon.last_report_ms = now;
+    let mut ranges = alloc::vec::Vec::new();
+    while ranges.len() < MAX_REPORT_RANGES {
+        match watos_mem::phys::take_unreported(REPORT_MIN_PAGES, REPORT_MAX_PAGES) {
+            Some(range) => ranges.push(range),
+            None => break,
+        }
+    }
+    if ranges.is_empty() {
+        return;
+    }
+    let chain: alloc::vec::Vec<(u64, u32)> =
+        ranges.iter().map(|&(addr, count)| (addr, (count << PAGE_SHIFT) as u32)).collect();
+    let reported = balloon.device.report(&chain).is_ok();
+    for (addr, count) in ranges {
+        watos_mem::phys::finish_report(addr, count, reported);
+    }
+}
+
+/// Pages held by the balloon
+fn balloon_pages() -> usize {
+    BALLOON.lock().as_ref().map_or(0, |balloon| balloon.pages.len())
+}
+
+// ============================================================================
+// Swap - page user memory out to a file when physical memory runs out
+// ============================================================================
+
+/// Let the physical allocator swap pages out, and page faults bring them
+/// back (or load a program's pages on first touch); nothing is swapped
+/// until a swap file is switched on
+fn init_swap() {
+    watos_mem::phys::set_reclaimer(watos_process::swap::page_out);
+    watos_arch::exceptions::set_page_fault_resolver(watos_process::resolve_page_fault);
+}
+
+/// Start swapping to a file, formatting it first with `pages` pages if
+/// that is nonzero
+fn swap_on(path: &str, pages: u32) -> Result<(), watos_swap::SwapError> {
+    let file = if pages != 0 {
+        let mode = watos_vfs::FileMode { read: true, ..watos_vfs::FileMode::WRITE };
+        let mut file = watos_vfs::open(path, mode)?;
+        watos_swap::format(file.as_mut(), pages, "")?;
+        file
+    } else {
+        watos_vfs::open(path, watos_vfs::FileMode::READ_WRITE)?
+    };
+    let area = watos_swap::SwapArea::open(file)?;
+    let total = area.stats().total;
+    watos_swap::swapon(area)?;
+    unsafe {
+        watos_arch::serial_write(alloc::format!("[KERNEL] Swap on {}: {} KB\r\n", path, total * 4).as_bytes());
+    }
+    Ok(())
+}
+
+/// Run `f` on the kernel page table, for file access from a syscall
+fn in_kernel_space<T>(f: impl FnOnce() -> T) -> T {
+    let user_cr3 = watos_mem::paging::get_cr3();
+    let kernel_pml4 = watos_process::get_kernel_pml4();
+    let switch = kernel_pml4 != 0 && user_cr3 != kernel_pml4;
+    if switch {
+        unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+    }
+    let result = f();
+    if switch {
+        unsafe { watos_mem::paging::load_cr3(user_cr3); }
+    }
+    result
+}
+
 /// Initialize VFS and mount boot disk (FAT) as drive C:
 /// System provider for procfs that returns real kernel stats
 struct WatosSystemProvider;
@@ -578,14 +1096,43 @@ struct WatosSystemProvider;
 impl SystemProvider for WatosSystemProvider {
     fn cpu_info(&self) -> alloc::string::String {
         use alloc::format;
-        format!(
+        let cpu = watos_arch::cpu::CpuInfo::read();
+        let mut out = format!(
             "processor\t: 0\n\
-             vendor_id\t: WATOS\n\
-             model name\t: x86_64 Virtual CPU\n\
-             cpu family\t: 6\n\
-             model\t\t: 0\n\
-             stepping\t: 0\n"
-        )
+             vendor_id\t: {}\n\
+             cpu family\t: {}\n\
+             model\t\t: {}\n\
+             model name\t: {}\n\
+             stepping\t: {}\n",
+            cpu.vendor(),
+            cpu.family,
+            cpu.model,
+            if cpu.brand().is_empty() { "unknown" } else { cpu.brand() },
+            cpu.stepping
+        );
+        if let Some(mhz) = cpu.current_mhz.or((cpu.base_mhz != 0).then_some(cpu.base_mhz)) {
+            out.push_str(&format!("cpu MHz\t\t: {}.000\n", mhz));
+        }
+        if cpu.base_mhz != 0 {
+            out.push_str(&format!("base MHz\t: {}\n", cpu.base_mhz));
+        }
+        if cpu.max_mhz != 0 {
+            out.push_str(&format!("max MHz\t\t: {}\n", cpu.max_mhz));
+        }
+        if let Some(celsius) = cpu.temperature {
+            out.push_str(&format!("temperature\t: {} C\n", celsius));
+        }
+        if let Some(hv) = watos_arch::hypervisor::detect() {
+            out.push_str(&format!("hypervisor vendor: {}\n", hv.name()));
+        }
+        out.push_str(&format!("clocksource\t: {}\n", watos_arch::clock::source().name()));
+        out.push_str(&format!("siblings\t: {}\ncpu cores\t: {}\nflags\t\t:", cpu.siblings, cpu.cores));
+        for flag in cpu.flags() {
+            out.push(' ');
+            out.push_str(flag);
+        }
+        out.push('\n');
+        out
     }
 
     fn mem_info(&self) -> alloc::string::String {
@@ -599,6 +1146,12 @@ impl SystemProvider for WatosSystemProvider {
         let heap_total_kb = heap_stats.total / 1024;
         let heap_used_kb = heap_stats.used / 1024;
         let heap_free_kb = (heap_stats.total - heap_stats.used) / 1024;
+        let balloon_kb = balloon_pages() * 4;
+        let reported_kb = phys_stats.reported_bytes() / 1024;
+        let swap = watos_swap::stats().unwrap_or(watos_swap::SwapStats { total: 0, used: 0 });
+        let swap_total_kb = swap.total * 4;
+        let swap_free_kb = (swap.total - swap.used) * 4;
+        let exec = watos_process::exec_cache::stats();
 
         format!(
             "MemTotal:       {} kB\n\
@@ -606,15 +1159,28 @@ impl SystemProvider for WatosSystemProvider {
              MemUsed:        {} kB\n\
              HeapTotal:      {} kB\n\
              HeapUsed:       {} kB\n\
-             HeapFree:       {} kB\n",
+             HeapFree:       {} kB\n\
+             Ballooned:      {} kB\n\
+             FreeReported:   {} kB\n\
+             SwapTotal:      {} kB\n\
+             SwapFree:       {} kB\n\
+             ExecCached:     {} kB\n\
+             ExecCacheHits:  {}\n\
+             ExecCacheMiss:  {}\n",
             total_kb, free_kb, used_kb,
-            heap_total_kb, heap_used_kb, heap_free_kb
+            heap_total_kb, heap_used_kb, heap_free_kb,
+            balloon_kb, reported_kb,
+            swap_total_kb, swap_free_kb,
+            exec.bytes / 1024, exec.hits, exec.misses
         )
     }
 
     fn uptime_secs(&self) -> u64 {
-        // Get ticks and convert to seconds (assuming 1000 ticks/sec)
-        watos_arch::idt::get_ticks() / 1000
+        watos_arch::clock::now_ms() / 1000
+    }
+
+    fn idle_secs(&self) -> u64 {
+        watos_process::idle_ms() / 1000
     }
 
     fn mounts_info(&self) -> alloc::string::String {
@@ -653,75 +1219,843 @@ impl SystemProvider for WatosSystemProvider {
 
         result
     }
-}
 
-fn init_vfs() -> bool {
-    unsafe { watos_arch::serial_write(b"[KERNEL] Initializing VFS...\r\n"); }
+    fn cmdline(&self) -> alloc::string::String {
+        alloc::string::String::from(watos_bootcfg::cmdline().as_str())
+    }
 
-    // Initialize the global VFS instance
-    watos_vfs::init();
-    unsafe { watos_arch::serial_write(b"[KERNEL] VFS initialized\r\n"); }
+    fn profile(&self, folded: bool) -> Option<alloc::string::String> {
+        Some(profiler_dump(folded))
+    }
 
-    // Mount procfs at /proc
-    let procfs = ProcFs::new();
-    procfs.set_system_provider(Box::new(WatosSystemProvider));
+    fn profile_control(&self, command: &str) -> bool {
+        profiler_control(command)
+    }
 
-    match watos_vfs::mount("/proc", Box::new(procfs)) {
-        Ok(()) => {
-            unsafe { watos_arch::serial_write(b"[KERNEL] Mounted procfs at /proc\r\n"); }
-        }
-        Err(_) => {
-            unsafe { watos_arch::serial_write(b"[KERNEL] Failed to mount procfs\r\n"); }
-        }
+    fn ksyms(&self) -> Option<alloc::string::String> {
+        Some(ksyms_report())
     }
 
-    // Try all AHCI ports to find a valid FAT filesystem for C:
-    // Port 0 = QEMU virtual FAT (invalid BPB), Port 2 = real FAT disk image
-    for port in 0..4u8 {
-        unsafe {
-            watos_arch::serial_write(b"[KERNEL] Trying AHCI port ");
-            watos_arch::serial_hex(port as u64);
-            watos_arch::serial_write(b" for FAT...\r\n");
+    fn power(&self) -> Option<alloc::string::String> {
+        Some(power_report())
+    }
+
+    fn power_control(&self, command: &str) -> bool {
+        power_control(command)
+    }
+
+    fn block_cache(&self) -> Option<alloc::string::String> {
+        Some(block_cache_report())
+    }
+
+    fn block_cache_control(&self, command: &str) -> bool {
+        block_cache_control(command)
+    }
+
+    #[cfg(feature = "heap-debug")]
+    fn heap_report(&self) -> Option<alloc::string::String> {
+        Some(heap_debug_report())
+    }
+}
+
+/// Outstanding kernel heap allocations, largest first, with symbolized call sites
+#[cfg(feature = "heap-debug")]
+fn heap_debug_report() -> alloc::string::String {
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    let damaged = ALLOCATOR.check();
+    let stats = ALLOCATOR.stats();
+    // Reserve before snapshotting: the heap lock is held while it copies
+    let mut live = Vec::with_capacity(stats.live_allocations + 16);
+    ALLOCATOR.snapshot(&mut live);
+    live.sort_by(|a, b| b.size.cmp(&a.size).then(a.seq.cmp(&b.seq)));
+
+    let mut out = format!(
+        "# {} live allocations, {} bytes; {} damaged now\n\
+         # double frees {}, invalid frees {}, overflows {}, use after free {}\n\
+         #     SEQ       SIZE  ADDRESS     CALLERS\n",
+        stats.live_allocations, stats.live_bytes, damaged,
+        stats.double_frees, stats.invalid_frees, stats.overflows, stats.use_after_free
+    );
+    let symbols = KERNEL_SYMBOLS.lock();
+    for info in &live {
+        out.push_str(&format!("{:>9} {:>10}  {:#010x}", info.seq, info.size, info.addr));
+        for &caller in info.callers.iter().take_while(|&&c| c != 0) {
+            out.push_str(&format!("  {}", symbols.symbolize(caller)));
         }
+        out.push('\n');
+    }
+    out
+}
 
-        let driver = match AhciDriver::probe_port(port) {
-            Some(d) => d,
-            None => continue,
-        };
+// ============================================================================
+// Kernel Symbols - address to function name for panics, /proc/profile,
+// /proc/heap and /proc/ksyms
+// ============================================================================
 
-        let mut driver = driver;
-        if driver.init().is_err() {
-            continue;
+/// Kernel symbol map (nm output) installed next to kernel.bin by the build
+const KERNEL_SYMBOL_MAP: &str = "C:/kernel.sym";
+
+/// Kernel symbols, loaded once C: is mounted
+static KERNEL_SYMBOLS: Mutex<SymbolTable> = Mutex::new(SymbolTable::new());
+
+/// Load the kernel symbol map if it has not been loaded yet
+fn ksyms_load() {
+    let mut symbols = KERNEL_SYMBOLS.lock();
+    if !symbols.is_empty() {
+        return;
+    }
+
+    let mut file = match watos_vfs::open(KERNEL_SYMBOL_MAP, FileMode::READ) {
+        Ok(f) => f,
+        Err(_) => {
+            unsafe { watos_arch::serial_write(b"[KSYMS] No kernel symbol map, addresses stay raw\r\n"); }
+            return;
         }
-        if driver.start().is_err() {
-            continue;
+    };
+    let mut data = alloc::vec::Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = file.read(&mut buf) {
+        if n == 0 {
+            break;
         }
+        data.extend_from_slice(&buf[..n]);
+    }
+    *symbols = SymbolTable::parse(&alloc::string::String::from_utf8_lossy(&data));
 
-        // Try to create FAT filesystem
-        match FatFilesystem::new(driver) {
-            Ok(fat_fs) => {
-                unsafe {
-                    watos_arch::serial_write(b"[KERNEL] FAT filesystem found on port ");
-                    watos_arch::serial_hex(port as u64);
-                    watos_arch::serial_write(b"\r\n");
-                }
+    unsafe {
+        watos_arch::serial_write(b"[KSYMS] Loaded ");
+        watos_arch::serial_hex(symbols.len() as u64);
+        watos_arch::serial_write(b" kernel symbols\r\n");
+    }
+}
 
-                // Mount as drive C:
-                match watos_vfs::mount_drive('C', Box::new(fat_fs)) {
-                    Ok(()) => {
-                        unsafe { watos_arch::serial_write(b"[KERNEL] Mounted FAT as C:\r\n"); }
+/// Frames shown in a panic backtrace
+const PANIC_BACKTRACE_DEPTH: usize = 16;
 
-                        // Also add to legacy drive table so CURRENT_DRIVE works
-                        drive_mount(b"C", b"/", b"FAT");
+/// Only frame pointers inside the identity-mapped kernel region are followed
+const FRAME_MIN: u64 = 0x1000;
+const FRAME_MAX: u64 = 0x80_0000;
 
-                        return true;
-                    }
-                    Err(_) => {
-                        unsafe { watos_arch::serial_write(b"[KERNEL] Failed to mount C:\r\n"); }
-                    }
-                }
+/// Walk the frame-pointer chain from the caller and print each return
+/// address with its function. Allocates nothing, and gives up on the names
+/// if the symbol table is locked by the code that panicked.
+fn ksyms_backtrace() {
+    let symbols = KERNEL_SYMBOLS.try_lock();
+    let mut frame: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags));
+        watos_arch::serial_write(b"Backtrace:\r\n");
+    }
+    for _ in 0..PANIC_BACKTRACE_DEPTH {
+        if !(FRAME_MIN..FRAME_MAX).contains(&frame) || !frame.is_multiple_of(8) {
+            break;
+        }
+        let ret = unsafe { *((frame + 8) as *const u64) };
+        unsafe {
+            watos_arch::serial_write(b"  ");
+            watos_arch::serial_hex(ret);
+            if let Some((name, offset)) = symbols.as_ref().and_then(|s| s.resolve(ret)) {
+                watos_arch::serial_write(b" ");
+                watos_arch::serial_write(name.as_bytes());
+                watos_arch::serial_write(b"+");
+                watos_arch::serial_hex(offset);
             }
-            Err(e) => {
+            watos_arch::serial_write(b"\r\n");
+            frame = *(frame as *const u64);
+        }
+    }
+}
+
+/// Contents of /proc/ksyms: the loaded map in nm format
+fn ksyms_report() -> alloc::string::String {
+    use alloc::format;
+
+    let symbols = KERNEL_SYMBOLS.lock();
+    let mut out = alloc::string::String::with_capacity(symbols.len() * 40);
+    for (addr, name) in symbols.iter() {
+        out.push_str(&format!("{:016x} T {}\n", addr, name));
+    }
+    out
+}
+
+// ============================================================================
+// Sampling Profiler - timer samples (see watos_arch::idt) exposed as /proc/profile
+// ============================================================================
+
+/// Handle a command written to /proc/profile
+fn profiler_control(command: &str) -> bool {
+    match command {
+        "start" => {
+            // In case C: was mounted after boot
+            ksyms_load();
+            watos_arch::idt::profile_enable(true);
+        }
+        "stop" => watos_arch::idt::profile_enable(false),
+        "reset" => watos_arch::idt::profile_reset(),
+        _ => return false,
+    }
+    true
+}
+
+/// Aggregate the buffered samples into a report or folded stacks
+fn profiler_dump(folded: bool) -> alloc::string::String {
+    use alloc::format;
+    use alloc::string::String;
+    use watos_arch::idt::{profile_count, profile_enabled, profile_sample, PROFILE_BUF_LEN};
+
+    let count = profile_count();
+    let held = (count as usize).min(PROFILE_BUF_LEN);
+    let samples = (0..held).filter_map(profile_sample);
+    let symbols = KERNEL_SYMBOLS.lock();
+    let histogram = Histogram::aggregate(samples, &symbols, |pid| match pid {
+        0 => String::from("kernel"),
+        _ => watos_process::process_summary(pid as u32)
+            .map(|p| p.name)
+            .unwrap_or_else(|| format!("pid{}", pid)),
+    });
+
+    if folded {
+        return histogram.folded();
+    }
+    let mut out = format!(
+        "# WATOS sampling profiler: {}, {} samples ({} buffered), one per timer tick (~55 ms)\n\
+         # {} kernel symbols; write start/stop/reset here; profile.folded feeds flamegraph.pl\n",
+        if profile_enabled() { "running" } else { "stopped" },
+        count,
+        histogram.total(),
+        symbols.len()
+    );
+    out.push_str(&histogram.report());
+    out
+}
+
+// ============================================================================
+// Power Management - driver suspend/resume hooks, controlled through /proc/power
+// ============================================================================
+
+/// Where the system is in a suspend/resume cycle
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PowerState {
+    Running,
+    Suspending,
+    Suspended,
+    Resuming,
+}
+
+impl PowerState {
+    fn name(self) -> &'static str {
+        match self {
+            PowerState::Running => "running",
+            PowerState::Suspending => "suspending",
+            PowerState::Suspended => "suspended",
+            PowerState::Resuming => "resuming",
+        }
+    }
+}
+
+/// Power coordinator state
+struct PowerStatus {
+    state: PowerState,
+    /// Completed suspend/resume cycles
+    cycles: u64,
+    /// Device that failed the last cycle, and how
+    last_error: Option<(&'static str, watos_driver_traits::DriverError)>,
+}
+
+static POWER: Mutex<PowerStatus> = Mutex::new(PowerStatus {
+    state: PowerState::Running,
+    cycles: 0,
+    last_error: None,
+});
+
+fn power_set_state(state: PowerState) {
+    POWER.lock().state = state;
+}
+
+/// Suspend every device and bring it straight back. This is everything a
+/// sleep does except the sleep itself (ACPI S3 entry, or the host pausing
+/// the VM), so drivers can be tested on any machine.
+fn power_cycle() -> bool {
+    unsafe { watos_arch::serial_write(b"[POWER] Suspending devices\r\n"); }
+    if watos_vfs::sync_all().is_err() {
+        unsafe { watos_arch::serial_write(b"[POWER] Filesystem sync failed\r\n"); }
+    }
+
+    // No interrupts while devices are half way down
+    unsafe { core::arch::asm!("cli", options(nomem, nostack)); }
+    power_set_state(PowerState::Suspending);
+    let suspended = power::suspend_all();
+    if suspended.is_ok() {
+        power_set_state(PowerState::Suspended);
+        // ACPI S3 entry goes here; the wakeup vector resumes below
+        power_set_state(PowerState::Resuming);
+    }
+    let resumed = suspended.and_then(|()| power::resume_all());
+    power_set_state(PowerState::Running);
+    unsafe { core::arch::asm!("sti", options(nomem, nostack)); }
+
+    let mut status = POWER.lock();
+    match resumed {
+        Ok(()) => {
+            status.cycles += 1;
+            status.last_error = None;
+            unsafe { watos_arch::serial_write(b"[POWER] Devices resumed\r\n"); }
+            true
+        }
+        Err((name, e)) => {
+            status.last_error = Some((name, e));
+            unsafe {
+                watos_arch::serial_write(b"[POWER] Device failed: ");
+                watos_arch::serial_write(name.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+            false
+        }
+    }
+}
+
+/// Handle a command written to /proc/power
+fn power_control(command: &str) -> bool {
+    match command {
+        "test" => power_cycle(),
+        "off" => power_off(false),
+        "reboot" => power_off(true),
+        _ => false,
+    }
+}
+
+/// Set by the ACPI interrupt when the VM's power button is pressed. The
+/// interrupt may have cut into code holding filesystem locks, so the sync
+/// and power off wait for the next syscall (see poll_power_button).
+static POWER_BUTTON: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+fn power_button_sci() {
+    if watos_arch::acpi::take_power_button() {
+        POWER_BUTTON.store(true, core::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Shut down cleanly if the power button was pressed
+fn poll_power_button() {
+    if POWER_BUTTON.swap(false, core::sync::atomic::Ordering::Relaxed) {
+        unsafe { watos_arch::serial_write(b"[POWER] Power button pressed\r\n"); }
+        power_off(false);
+    }
+}
+
+/// Find the ACPI tables and take the power button interrupt
+fn init_acpi() {
+    let rsdp = match unsafe { BOOT_INFO.map(|info| info.rsdp_addr) } {
+        Some(addr) if addr != 0 => Some(addr),
+        _ => watos_arch::acpi::find_rsdp(),
+    };
+    let Some(acpi) = rsdp.filter(|&addr| watos_arch::acpi::init(addr)).and(watos_arch::acpi::info()) else {
+        unsafe { watos_arch::serial_write(b"[KERNEL] ACPI: no usable tables\r\n"); }
+        return;
+    };
+    if acpi.has_power_button() {
+        watos_arch::idt::set_sci_handler(acpi.sci_irq, power_button_sci);
+    }
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] ACPI: SCI on IRQ ");
+        watos_arch::serial_hex(acpi.sci_irq as u64);
+        watos_arch::serial_write(if acpi.can_power_off() { b", soft off\r\n" } else { b", no soft off\r\n" });
+    }
+}
+
+/// Sync every filesystem, flush the drives and power off, or reset when
+/// `reboot`. Halts if the machine can't be switched off.
+fn power_off(reboot: bool) -> ! {
+    // Disk MMIO is only mapped in the kernel's page table
+    let kernel_pml4 = watos_process::get_kernel_pml4();
+    if kernel_pml4 != 0 {
+        unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+    }
+
+    unsafe { watos_arch::serial_write(b"[POWER] Syncing filesystems\r\n"); }
+    if watos_vfs::sync_all().is_err() {
+        unsafe { watos_arch::serial_write(b"[POWER] Filesystem sync failed\r\n"); }
+    }
+    klog_flush();
+
+    // Suspending the drivers flushes their write caches
+    unsafe { core::arch::asm!("cli", options(nomem, nostack)); }
+    power_set_state(PowerState::Suspending);
+    if let Err((name, _)) = power::suspend_all() {
+        unsafe {
+            watos_arch::serial_write(b"[POWER] Device failed: ");
+            watos_arch::serial_write(name.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+    }
+
+    if reboot {
+        unsafe { watos_arch::serial_write(b"[POWER] Rebooting\r\n"); }
+        watos_arch::acpi::reset();
+        watos_arch::reboot();
+    }
+    unsafe { watos_arch::serial_write(b"[POWER] Powering off\r\n"); }
+    watos_arch::acpi::power_off();
+
+    // Devices are down: stay halted with interrupts off
+    unsafe {
+        watos_arch::serial_write(b"[POWER] Power off failed, halting\r\n");
+        loop {
+            core::arch::asm!("cli; hlt", options(nomem, nostack));
+        }
+    }
+}
+
+/// Contents of /proc/power
+fn power_report() -> alloc::string::String {
+    use alloc::format;
+
+    let status = POWER.lock();
+    let mut out = format!("state: {}\ncycles: {}\n", status.state.name(), status.cycles);
+    if let Some((name, e)) = status.last_error {
+        out.push_str(&format!("failed: {} ({:?})\n", name, e));
+    }
+    out.push_str("devices:");
+    for hook in power::hooks() {
+        out.push_str(&format!(" {}@{:#x}", hook.name, hook.ctx));
+    }
+    out.push('\n');
+    out
+}
+
+// ============================================================================
+// Block Cache - tunables, counters and the idle-time writeback
+// ============================================================================
+
+/// Handle a command written to /proc/blockcache: "<tunable> <value>" or "sync"
+fn block_cache_control(command: &str) -> bool {
+    use watos_driver_traits::cache;
+
+    if command == "sync" {
+        return watos_vfs::sync_all().is_ok();
+    }
+    let mut words = command.split_whitespace();
+    let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+        return false;
+    };
+    let Ok(value) = value.parse::<u64>() else { return false };
+    let mut tunables = cache::tunables();
+    if !tunables.set(name, value) {
+        return false;
+    }
+    cache::set_tunables(tunables);
+    true
+}
+
+/// Contents of /proc/blockcache
+fn block_cache_report() -> alloc::string::String {
+    use alloc::format;
+    use watos_driver_traits::cache;
+
+    let t = cache::tunables();
+    let s = cache::stats();
+    format!(
+        "capacity: {}\nreadahead: {}\ndirty_expire: {}\ndirty_max: {}\n\
+         hits: {}\nmisses: {}\nreadahead_sectors: {}\nreadahead_hits: {}\n\
+         writeback_sectors: {}\nwriteback_requests: {}\ndirty: {}\n",
+        t.capacity, t.readahead, t.dirty_expire, t.dirty_max,
+        s.hits, s.misses, s.readahead, s.readahead_hits,
+        s.writeback, s.writeback_requests, s.dirty,
+    )
+}
+
+/// Write back cached sectors that have waited dirty_expire ticks. Called
+/// when a program idles or sleeps, so the flush happens while nothing is
+/// waiting on the disk.
+fn block_cache_writeback() {
+    if watos_driver_traits::cache::writeback_due(watos_arch::idt::get_ticks())
+        && watos_vfs::sync_all().is_err()
+    {
+        unsafe { watos_arch::serial_write(b"[CACHE] Writeback failed\r\n"); }
+    }
+}
+
+// ============================================================================
+// Kernel Monitor - serial escape hatch that runs inside the COM1 interrupt
+// ============================================================================
+
+/// Pressing Ctrl-\ this many times in a row on the serial line opens the
+/// monitor
+const MONITOR_BREAK: u8 = 0x1C;
+const MONITOR_BREAK_COUNT: u8 = 3;
+
+/// Largest `mem` dump
+const MONITOR_MAX_DUMP: u64 = 4096;
+
+static mut MONITOR_BREAKS: u8 = 0;
+
+/// COM1 receive callback: count break characters, drop everything else
+fn monitor_serial_rx(byte: u8, from_user: bool) {
+    unsafe {
+        MONITOR_BREAKS = if byte == MONITOR_BREAK { MONITOR_BREAKS + 1 } else { 0 };
+        if MONITOR_BREAKS < MONITOR_BREAK_COUNT {
+            return;
+        }
+        MONITOR_BREAKS = 0;
+    }
+    monitor(from_user);
+}
+
+/// Formatted output straight to the serial port
+struct MonitorOut;
+
+impl core::fmt::Write for MonitorOut {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for line in s.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(text) => {
+                    watos_arch::serial_write_raw(text.as_bytes());
+                    watos_arch::serial_write_raw(b"\r\n");
+                }
+                None => watos_arch::serial_write_raw(line.as_bytes()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read a line with echo and backspace, polling the UART
+fn monitor_read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let Some(byte) = watos_arch::serial_read() else {
+            core::hint::spin_loop();
+            continue;
+        };
+        match byte {
+            b'\r' | b'\n' => {
+                watos_arch::serial_write_raw(b"\r\n");
+                return len;
+            }
+            0x08 | 0x7F if len > 0 => {
+                len -= 1;
+                watos_arch::serial_write_raw(b"\x08 \x08");
+            }
+            0x20..=0x7E if len < buf.len() => {
+                buf[len] = byte;
+                len += 1;
+                watos_arch::serial_write_raw(&[byte]);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decimal, or hex with a 0x prefix
+fn monitor_number(arg: Option<&str>) -> Option<u64> {
+    let arg = arg?;
+    match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+/// Hex dump of mapped memory; stops at the first unmapped page
+fn monitor_dump(addr: u64, len: u64) {
+    use core::fmt::Write;
+
+    let mut out = MonitorOut;
+    let end = addr.saturating_add(len.min(MONITOR_MAX_DUMP));
+    let mut row = addr & !0xF;
+    while row < end {
+        if watos_mem::paging::translate(row).is_none() {
+            let _ = writeln!(out, "{:016x}: not mapped", row);
+            return;
+        }
+        // User pages too, when the monitor breaks in under a process
+        let bytes = {
+            let _access = watos_mem::UserAccess::open();
+            unsafe { core::ptr::read_volatile(row as *const [u8; 16]) }
+        };
+        let _ = write!(out, "{:016x}:", row);
+        for b in bytes {
+            let _ = write!(out, " {:02x}", b);
+        }
+        let _ = out.write_str("  ");
+        for b in bytes {
+            let _ = out.write_char(if (0x20..0x7F).contains(&b) { b as char } else { '.' });
+        }
+        let _ = out.write_str("\n");
+        row += 16;
+    }
+}
+
+/// Kill `pid`, if the monitor interrupted user mode: interrupting the
+/// kernel, it could leave kernel locks held. A process that isn't running
+/// is at a switch point and goes at once, as does the running one.
+fn monitor_kill(pid: u32, from_user: bool) -> &'static str {
+    if watos_process::process_summary(pid).is_none() {
+        return "no such process";
+    }
+    if pid == watos_process::init_pid() {
+        return "top-level process; use reboot";
+    }
+    if !from_user {
+        return "in the kernel; continue and try again";
+    }
+    if watos_process::current_pid() != Some(pid) {
+        watos_process::sched::send(pid as i64, signals::SIGKILL, 0, None);
+        return "killed";
+    }
+    watos_arch::serial_write_raw(b"killed\r\n");
+    unsafe {
+        watos_arch::serial_write(b"[MONITOR] Killed running process\r\n");
+        watos_mem::paging::load_cr3(watos_process::get_kernel_pml4());
+    }
+    watos_process::record_killed(signals::SIGKILL, false);
+    watos_process::free_current_process();
+    klog_flush();
+    watos_process::sched::schedule(); // Never returns
+}
+
+/// The monitor: commands until `c`. Runs with interrupts off, so time and
+/// the rest of the system stand still meanwhile.
+fn monitor(from_user: bool) {
+    use core::fmt::Write;
+
+    let mut out = MonitorOut;
+    let _ = writeln!(
+        out,
+        "\nWATOS kernel monitor (interrupted {}, pid {}); 'help' lists commands",
+        if from_user { "user mode" } else { "kernel" },
+        watos_process::current_pid().unwrap_or(0)
+    );
+    let mut line = [0u8; 80];
+    loop {
+        let _ = out.write_str("mon> ");
+        let len = monitor_read_line(&mut line);
+        let text = core::str::from_utf8(&line[..len]).unwrap_or("");
+        let mut args = text.split_whitespace();
+        match args.next() {
+            None => {}
+            Some("help") => {
+                let _ = out.write_str(
+                    "  mem ADDR [LEN]  hex dump of mapped memory (0x for hex)\n\
+                     \x20 ps              process table\n\
+                     \x20 mounts          mounted filesystems\n\
+                     \x20 kill PID        kill the running process\n\
+                     \x20 sync            write filesystem caches to disk\n\
+                     \x20 reboot          reset the machine\n\
+                     \x20 c               continue\n",
+                );
+            }
+            Some("mem") => match monitor_number(args.next()) {
+                Some(addr) => monitor_dump(addr, monitor_number(args.next()).unwrap_or(64)),
+                None => {
+                    let _ = out.write_str("usage: mem ADDR [LEN]\n");
+                }
+            },
+            Some("ps") => {
+                let current = watos_process::current_pid();
+                let _ = out.write_str("  PID  PPID STATE       RSS NAME\n");
+                for p in watos_process::list_processes() {
+                    let _ = writeln!(
+                        out,
+                        "{}{:>4} {:>5} {:<10} {:>5}K {}",
+                        if Some(p.pid) == current { '*' } else { ' ' },
+                        p.pid,
+                        p.ppid,
+                        match p.state {
+                            _ if p.stopped => "stopped",
+                            watos_process::ProcessState::Running => "running",
+                            watos_process::ProcessState::Ready => "ready",
+                            watos_process::ProcessState::Waiting(_) => "waiting",
+                            watos_process::ProcessState::Sleeping(_) => "sleeping",
+                            watos_process::ProcessState::Terminated(_) => "zombie",
+                        },
+                        p.memory_kb,
+                        p.name
+                    );
+                }
+            }
+            Some("mounts") => match watos_vfs::vfs() {
+                Some(v) => {
+                    for d in v.list_drives() {
+                        let _ = writeln!(out, "  {}: {} {}", d.letter, d.filesystem.name(),
+                                         d.label.as_deref().unwrap_or(""));
+                    }
+                    for m in v.list_mounts() {
+                        let _ = writeln!(out, "  {} {}", m.path, m.filesystem.name());
+                    }
+                }
+                None => {
+                    let _ = out.write_str("no VFS\n");
+                }
+            },
+            Some("kill") => match monitor_number(args.next()) {
+                Some(pid) => {
+                    let _ = writeln!(out, "{}", monitor_kill(pid as u32, from_user));
+                }
+                None => {
+                    let _ = out.write_str("usage: kill PID\n");
+                }
+            },
+            Some("sync") => {
+                let result = match watos_vfs::sync_all() {
+                    Ok(()) => "synced",
+                    Err(watos_vfs::VfsError::NotInitialized) => "no VFS",
+                    Err(_) => "sync failed",
+                };
+                let _ = writeln!(out, "{}", result);
+            }
+            Some("reboot") => watos_arch::reboot(),
+            Some("c") | Some("continue") => break,
+            Some(cmd) => {
+                let _ = writeln!(out, "{}: unknown command", cmd);
+            }
+        }
+    }
+}
+
+/// Process provider for procfs backed by the kernel process table
+struct WatosProcessProvider;
+
+impl ProcessProvider for WatosProcessProvider {
+    fn current_pid(&self) -> Option<u32> {
+        watos_process::current_pid()
+    }
+
+    fn list_pids(&self) -> alloc::vec::Vec<u32> {
+        watos_process::list_processes().iter().map(|p| p.pid).collect()
+    }
+
+    fn get_process(&self, pid: u32) -> Option<watos_procfs::ProcessInfo> {
+        use watos_procfs::{ProcState, ProcessInfo};
+        use watos_process::ProcessState;
+
+        let p = watos_process::process_summary(pid)?;
+        // The working directory is shared by all processes
+        let mut cwd = [0u8; 256];
+        let cwd_len = get_cwd(&mut cwd);
+        let state = match p.state {
+            _ if p.stopped => ProcState::Stopped,
+            ProcessState::Running | ProcessState::Ready => ProcState::Running,
+            ProcessState::Waiting(_) | ProcessState::Sleeping(_) => ProcState::Sleeping,
+            ProcessState::Terminated(_) => ProcState::Zombie,
+        };
+        Some(ProcessInfo {
+            pid: p.pid,
+            ppid: p.ppid,
+            name: p.name,
+            state,
+            cmdline: p.args,
+            cwd: alloc::string::String::from_utf8_lossy(&cwd[..cwd_len]).into_owned(),
+            uid: p.uid,
+            gid: p.gid,
+            memory_kb: p.vm_size_kb,
+            rss_kb: p.memory_kb,
+            code_kb: p.code_kb,
+            heap_kb: p.heap_kb,
+            stack_kb: p.stack_kb,
+            malloc_bytes: p.malloc_bytes,
+            cpu_time_ms: p.user_time_ms + p.system_time_ms,
+            user_time_ms: p.user_time_ms,
+            system_time_ms: p.system_time_ms,
+            nice: p.nice,
+            io: watos_vfs::IoCounts {
+                reads: p.io.reads,
+                read_bytes: p.io.read_bytes,
+                writes: p.io.writes,
+                write_bytes: p.io.write_bytes,
+            },
+        })
+    }
+}
+
+fn init_vfs() -> bool {
+    unsafe { watos_arch::serial_write(b"[KERNEL] Initializing VFS...\r\n"); }
+
+    // Initialize the global VFS instance
+    watos_vfs::init();
+    unsafe { watos_arch::serial_write(b"[KERNEL] VFS initialized\r\n"); }
+
+    // Mount procfs at /proc
+    let procfs = ProcFs::new();
+    procfs.set_system_provider(Box::new(WatosSystemProvider));
+    procfs.set_process_provider(Box::new(WatosProcessProvider));
+
+    match watos_vfs::mount("/proc", Box::new(procfs)) {
+        Ok(()) => {
+            unsafe { watos_arch::serial_write(b"[KERNEL] Mounted procfs at /proc\r\n"); }
+        }
+        Err(_) => {
+            unsafe { watos_arch::serial_write(b"[KERNEL] Failed to mount procfs\r\n"); }
+        }
+    }
+
+    // Try all AHCI ports to find a valid FAT filesystem for C:, or only the
+    // one named by `root = ahciN` in watos.cfg
+    // Port 0 = QEMU virtual FAT (invalid BPB), Port 2 = real FAT disk image
+    if let Some(root) = watos_bootcfg::option("root") {
+        if watos_bootcfg::root_port().is_none() && !root.eq_ignore_ascii_case("C:") {
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] WARNING: root must be ahciN or C:, got ");
+                watos_arch::serial_write(root.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+        }
+    }
+    let ports = match watos_bootcfg::root_port() {
+        Some(port) => port..port + 1,
+        None => 0..4u8,
+    };
+    for port in ports {
+        unsafe {
+            watos_arch::serial_write(b"[KERNEL] Trying AHCI port ");
+            watos_arch::serial_hex(port as u64);
+            watos_arch::serial_write(b" for FAT...\r\n");
+        }
+
+        let driver = match AhciDriver::probe_port(port) {
+            Some(d) => d,
+            None => continue,
+        };
+
+        let mut driver = driver;
+        if driver.init().is_err() {
+            continue;
+        }
+        if driver.start().is_err() {
+            continue;
+        }
+
+        // Try to create FAT filesystem
+        let hook = driver.power_hook();
+        match FatFilesystem::new(CachedDevice::new(driver, watos_arch::idt::get_ticks)) {
+            Ok(fat_fs) => {
+                unsafe {
+                    watos_arch::serial_write(b"[KERNEL] FAT filesystem found on port ");
+                    watos_arch::serial_hex(port as u64);
+                    watos_arch::serial_write(b"\r\n");
+                }
+
+                // Mount as drive C:
+                match watos_vfs::mount_drive('C', Box::new(fat_fs)) {
+                    Ok(()) => {
+                        unsafe { watos_arch::serial_write(b"[KERNEL] Mounted FAT as C:\r\n"); }
+                        power::register(hook);
+
+                        // Also add to legacy drive table so CURRENT_DRIVE works
+                        drive_mount(b"C", b"/", b"FAT");
+
+                        // Panics, profiles and heap reports name functions
+                        ksyms_load();
+
+                        return true;
+                    }
+                    Err(_) => {
+                        unsafe { watos_arch::serial_write(b"[KERNEL] Failed to mount C:\r\n"); }
+                    }
+                }
+            }
+            Err(e) => {
                 // Log the specific error
                 unsafe {
                     watos_arch::serial_write(b"[KERNEL] FAT probe failed on port ");
@@ -852,15 +2186,182 @@ fn console_read(buf: &mut [u8]) -> usize {
 }
 
 // ============================================================================
-// Keyboard Scancode to ASCII Conversion
+// Console mirror - VT output as plain text for accessibility tools
 // ============================================================================
 
-/// Convert PS/2 scancode to ASCII character (US keyboard layout)
-fn scancode_to_ascii(scancode: u8) -> u8 {
-    // Ignore key release events (scancode >= 0x80)
-    if scancode >= 0x80 {
-        return 0;
-    }
+const MIRROR_BUFFER_SIZE: usize = 4096;
+static mut MIRROR_BUFFER: [u8; MIRROR_BUFFER_SIZE] = [0; MIRROR_BUFFER_SIZE];
+static mut MIRROR_READ_POS: usize = 0;
+static mut MIRROR_WRITE_POS: usize = 0;
+/// Process reading the mirror through SYS_CONSOLE_MIRROR
+static mut MIRROR_READER: Option<u32> = None;
+/// Copy the mirror to the serial port (`console.mirror = serial`)
+static mut MIRROR_SERIAL: bool = false;
+
+/// Start mirroring if watos.cfg asks for it
+fn init_console_mirror() {
+    match watos_bootcfg::option("console.mirror") {
+        Some("serial") => {
+            unsafe { MIRROR_SERIAL = true; }
+            watos_vt::mirror::set_consumer(Some(console_mirror));
+        }
+        Some("off") | None => {}
+        Some(other) => unsafe {
+            watos_arch::serial_write(b"[KERNEL] WARNING: unknown console.mirror: ");
+            watos_arch::serial_write(other.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        },
+    }
+}
+
+/// Mirror consumer: to the serial port and/or the reader's ring buffer,
+/// dropping the oldest text when the reader falls behind
+fn console_mirror(text: &[u8]) {
+    unsafe {
+        if MIRROR_SERIAL {
+            for line in text.split_inclusive(|&b| b == b'\n') {
+                match line.strip_suffix(b"\n") {
+                    Some(line) => {
+                        watos_arch::serial_write_raw(line);
+                        watos_arch::serial_write_raw(b"\r\n");
+                    }
+                    None => watos_arch::serial_write_raw(line),
+                }
+            }
+        }
+        if matches!(MIRROR_READER, Some(_)) {
+            for &byte in text {
+                let next_write = (MIRROR_WRITE_POS + 1) % MIRROR_BUFFER_SIZE;
+                if next_write == MIRROR_READ_POS {
+                    MIRROR_READ_POS = (MIRROR_READ_POS + 1) % MIRROR_BUFFER_SIZE;
+                }
+                MIRROR_BUFFER[MIRROR_WRITE_POS] = byte;
+                MIRROR_WRITE_POS = next_write;
+            }
+        }
+    }
+}
+
+/// Take mirrored text for the reader; returns the bytes copied
+fn console_mirror_read(buf: &mut [u8]) -> usize {
+    unsafe {
+        let mut count = 0;
+        while count < buf.len() && MIRROR_READ_POS != MIRROR_WRITE_POS {
+            buf[count] = MIRROR_BUFFER[MIRROR_READ_POS];
+            MIRROR_READ_POS = (MIRROR_READ_POS + 1) % MIRROR_BUFFER_SIZE;
+            count += 1;
+        }
+        count
+    }
+}
+
+// ============================================================================
+// Clipboard - shared by all programs through SYS_CLIPBOARD_SET/GET
+// ============================================================================
+
+static CLIPBOARD: Mutex<Clipboard> = Mutex::new(Clipboard::new());
+
+// ============================================================================
+// Audio - SYS_AUDIO_* streams on the registered output device
+// ============================================================================
+
+/// Probe PCI for a sound card and make it the audio output
+fn init_audio() {
+    use watos_driver_traits::Driver;
+
+    let Some(mut card) = watos_driver_audio_generic::GenericSoundDriver::probe() else {
+        unsafe { watos_arch::serial_write(b"[KERNEL] No sound card\r\n"); }
+        return;
+    };
+    if card.init().is_err() || Driver::start(&mut card).is_err() {
+        unsafe { watos_arch::serial_write(b"[KERNEL] Sound card init failed\r\n"); }
+        return;
+    }
+    watos_driver_traits::audio::register_output(Box::new(card));
+    unsafe { watos_arch::serial_write(b"[KERNEL] Sound card enabled\r\n"); }
+}
+
+/// Console bell (BEL) tone
+const BELL_HZ: u32 = 750;
+const BELL_MS: u64 = 100;
+
+/// Sound the PC speaker for `ms`, halting until it's done. 0 Hz is a rest.
+fn beep(freq_hz: u32, ms: u64) -> bool {
+    use watos_driver_traits::audio::Beep;
+
+    let mut speaker = watos_driver_pcspeaker::PcSpeaker;
+    if freq_hz != 0 && speaker.tone(freq_hz).is_err() {
+        return false;
+    }
+    sleep_ms(ms);
+    speaker.silence();
+    true
+}
+
+/// Ring the bell if the active VT was sent a BEL
+fn console_bell() {
+    if watos_vt::vt_take_bell() {
+        beep(BELL_HZ, BELL_MS);
+    }
+}
+
+/// SYS_AUDIO_SET_CONFIG format code to sample format
+fn audio_format(code: u8) -> Option<watos_driver_traits::audio::SampleFormat> {
+    use watos_driver_traits::audio::SampleFormat;
+
+    match code {
+        syscall::AUDIO_FORMAT_U8 => Some(SampleFormat::U8),
+        syscall::AUDIO_FORMAT_S16LE => Some(SampleFormat::S16Le),
+        syscall::AUDIO_FORMAT_S16BE => Some(SampleFormat::S16Be),
+        syscall::AUDIO_FORMAT_F32 => Some(SampleFormat::F32),
+        _ => None,
+    }
+}
+
+/// The stream's settings as a device config
+fn audio_stream_config(stream: &watos_process::AudioObject) -> watos_driver_traits::audio::AudioConfig {
+    watos_driver_traits::audio::AudioConfig {
+        sample_rate: stream.sample_rate,
+        channels: stream.channels,
+        format: audio_format(stream.format).unwrap_or(watos_driver_traits::audio::SampleFormat::S16Le),
+    }
+}
+
+/// Switch the device to the stream's format if another stream changed it
+fn audio_apply(dev: &mut dyn watos_driver_traits::audio::AudioDevice, stream: &watos_process::AudioObject) -> bool {
+    let config = audio_stream_config(stream);
+    dev.config() == config || dev.set_config(config).is_ok()
+}
+
+// ============================================================================
+// Keyboard Scancode to ASCII Conversion
+// ============================================================================
+
+/// Either Ctrl key is down (the right one's E0 prefix makes no difference)
+static CTRL_HELD: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Keyboard filter, in the interrupt: Ctrl+Z stops the foreground job
+/// while a shell has handed it the console, and goes no further
+fn job_control_key(scancode: u8) -> bool {
+    use core::sync::atomic::Ordering;
+    match scancode {
+        0x1D => CTRL_HELD.store(true, Ordering::Relaxed),
+        0x9D => CTRL_HELD.store(false, Ordering::Relaxed),
+        0x2C if CTRL_HELD.load(Ordering::Relaxed) && watos_process::console_signals() => {
+            watos_process::sched::post_console_signal(signals::SIGTSTP);
+            return true;
+        }
+        _ => {}
+    }
+    false
+}
+
+/// Convert PS/2 scancode to ASCII character (US keyboard layout)
+fn scancode_to_ascii(scancode: u8) -> u8 {
+    // Ignore key release events (scancode >= 0x80)
+    if scancode >= 0x80 {
+        return 0;
+    }
 
     // TODO: Handle shift, ctrl, alt modifiers properly
     // For now, simple unshifted ASCII mapping
@@ -912,55 +2413,411 @@ impl FileDescriptor {
     }
 }
 
-/// Global file descriptor table
+/// Shared open file - dup/dup2 and pipes hand out several fds for one file
+type FdEntry = Arc<Mutex<Box<dyn FileOperations>>>;
+
+/// One process's file descriptors
 /// fd 0 = console input buffer (special)
 /// fd 1 = stdout (console output)
 /// fd 2 = stderr (console output)
 /// fd 3+ = regular files
-static FD_TABLE: Mutex<[Option<Box<dyn FileOperations>>; MAX_FDS]> =
-    Mutex::new([const { None }; MAX_FDS]);
+///
+/// Slots 0-2 are normally empty, meaning "the console". dup2() can fill them
+/// with a file, pipe or pty to redirect a program's standard streams;
+/// closing the slot restores the console.
+#[derive(Clone)]
+struct FdTable {
+    files: [Option<FdEntry>; MAX_FDS],
+    /// Bit per fd: close it in children (O_CLOEXEC)
+    cloexec: u64,
+}
 
-/// Allocate a new file descriptor for an open file
-/// Returns the fd number, or -1 if table is full
-fn fd_alloc(file: Box<dyn FileOperations>) -> i64 {
-    let mut table = FD_TABLE.lock();
+impl FdTable {
+    const fn new() -> Self {
+        FdTable { files: [const { None }; MAX_FDS], cloexec: 0 }
+    }
+
+    /// Put `entry` (or nothing) in `fd`, which children then inherit
+    fn set(&mut self, fd: usize, entry: Option<FdEntry>) -> Option<FdEntry> {
+        self.cloexec &= !(1 << fd);
+        core::mem::replace(&mut self.files[fd], entry)
+    }
+}
+
+/// File descriptor tables by pid; pid 0 is the kernel itself. A child
+/// starts with a copy of its parent's table, and its table is dropped,
+/// closing what only it still held, when the process is freed.
+static FD_TABLES: Mutex<BTreeMap<u32, FdTable>> = Mutex::new(BTreeMap::new());
+
+/// Run `f` on the calling process's fd table
+fn with_fd_table<T>(f: impl FnOnce(&mut FdTable) -> T) -> T {
+    let pid = watos_process::current_pid().unwrap_or(0);
+    let mut tables = FD_TABLES.lock();
+    f(tables.entry(pid).or_insert_with(FdTable::new))
+}
+
+/// Give a new child a copy of the caller's fds, less the O_CLOEXEC ones
+fn fd_inherit(child: u32) {
+    let mut table = with_fd_table(|table| table.clone());
+    for fd in 0..MAX_FDS {
+        if table.cloexec & (1 << fd) != 0 {
+            table.set(fd, None);
+        }
+    }
+    FD_TABLES.lock().insert(child, table);
+}
+
+/// Drop a freed process's fds (see `watos_process::set_release_hook`)
+fn fd_release(pid: u32) {
+    let table = FD_TABLES.lock().remove(&pid);
+    // Closing the last reference to a file can flush it to disk
+    on_kernel_tables(|| drop(table));
+}
+
+/// Console stream as a file object, so stdin/stdout/stderr can be dup'ed
+struct ConsoleFile;
+
+impl FileOperations for ConsoleFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(console_read(buf))
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, VfsError> {
+        unsafe { watos_arch::serial_write(buf); }
+        watos_vt::vt_write_active(buf);
+        console_bell();
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, _offset: i64, _whence: watos_vfs::SeekFrom) -> Result<u64, VfsError> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn tell(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> Result<(), VfsError> {
+        Ok(())
+    }
+
+    fn stat(&self) -> Result<watos_vfs::FileStat, VfsError> {
+        Ok(watos_vfs::FileStat {
+            file_type: watos_vfs::FileType::CharDevice,
+            ..Default::default()
+        })
+    }
+
+    fn truncate(&mut self, _size: u64) -> Result<(), VfsError> {
+        Err(VfsError::InvalidArgument)
+    }
+}
+
+/// Install an entry in the lowest free fd >= 3
+fn fd_install(table: &mut FdTable, entry: FdEntry) -> i64 {
     // Start from fd 3 (0=console, 1=stdout, 2=stderr are special)
     for fd in 3..MAX_FDS {
-        if table[fd].is_none() {
-            table[fd] = Some(file);
+        if table.files[fd].is_none() {
+            table.set(fd, Some(entry));
             return fd as i64;
         }
     }
     -1 // No free fd
 }
 
+/// Allocate a new file descriptor for an open file
+/// Returns the fd number, or -1 if table is full
+fn fd_alloc(file: Box<dyn FileOperations>) -> i64 {
+    with_fd_table(|table| fd_install(table, Arc::new(Mutex::new(file))))
+}
+
+/// Is this fd backed by a table entry (a file, pipe, or redirected std stream)?
+fn fd_is_open(fd: u64) -> bool {
+    fd < MAX_FDS as u64 && with_fd_table(|table| table.files[fd as usize].is_some())
+}
+
+/// Get the entry behind an fd, treating empty slots 0-2 as the console
+fn fd_entry(table: &FdTable, fd: usize) -> Option<FdEntry> {
+    match &table.files[fd] {
+        Some(entry) => Some(entry.clone()),
+        None if fd < 3 => Some(Arc::new(Mutex::new(Box::new(ConsoleFile)))),
+        None => None,
+    }
+}
+
 /// Close a file descriptor
+/// Closing a redirected std stream (0-2) restores the console
 fn fd_close(fd: i64) -> i64 {
-    if fd < 3 || fd >= MAX_FDS as i64 {
-        return -1; // Invalid fd (can't close 0, 1, 2)
+    if fd < 0 || fd >= MAX_FDS as i64 {
+        return -1; // Invalid fd
     }
-    let mut table = FD_TABLE.lock();
-    if table[fd as usize].take().is_some() {
+    let closed = with_fd_table(|table| table.set(fd as usize, None));
+    if closed.is_some() {
         0
     } else {
         -1 // Was not open
     }
 }
 
+/// The entry behind an open fd; InvalidArgument if it isn't open
+fn fd_file(fd: i64) -> Result<FdEntry, VfsError> {
+    if fd < 0 || fd >= MAX_FDS as i64 {
+        return Err(VfsError::InvalidArgument);
+    }
+    with_fd_table(|table| table.files[fd as usize].clone()).ok_or(VfsError::InvalidArgument)
+}
+
 /// Read from a file descriptor
-fn fd_read(fd: i64, buf: &mut [u8]) -> i64 {
-    if fd < 3 || fd >= MAX_FDS as i64 {
+/// An empty pipe fails with WouldBlock while it still has a writer
+fn fd_read(fd: i64, buf: &mut [u8]) -> Result<usize, VfsError> {
+    let n = fd_file(fd)?.lock().read(buf)?;
+    watos_process::account_io(false, n);
+    Ok(n)
+}
+
+/// Write to a file descriptor
+/// A full pipe fails with WouldBlock
+fn fd_write(fd: i64, buf: &[u8]) -> Result<usize, VfsError> {
+    let n = fd_file(fd)?.lock().write(buf)?;
+    watos_process::account_io(true, n);
+    Ok(n)
+}
+
+/// Duplicate a file descriptor onto the lowest free fd >= 3
+fn fd_dup(fd: i64) -> i64 {
+    if fd < 0 || fd >= MAX_FDS as i64 {
+        return -1;
+    }
+    with_fd_table(|table| match fd_entry(table, fd as usize) {
+        Some(entry) => fd_install(table, entry),
+        None => -1,
+    })
+}
+
+/// Make new_fd refer to the same open file as old_fd, closing new_fd first
+fn fd_dup2(old_fd: i64, new_fd: i64) -> i64 {
+    if old_fd < 0 || old_fd >= MAX_FDS as i64 || new_fd < 0 || new_fd >= MAX_FDS as i64 {
         return -1;
     }
-    let mut table = FD_TABLE.lock();
-    if let Some(ref mut file) = table[fd as usize] {
-        match file.read(buf) {
-            Ok(n) => n as i64,
-            Err(_) => -1,
+    if old_fd == new_fd {
+        return new_fd;
+    }
+    let replaced = with_fd_table(|table| {
+        let entry = fd_entry(table, old_fd as usize)?;
+        Some(table.set(new_fd as usize, Some(entry)))
+    });
+    match replaced {
+        Some(_) => new_fd,
+        None => -1,
+    }
+}
+
+/// Is this fd a terminal (the console or a pty)?
+fn fd_isatty(fd: i64) -> bool {
+    if fd < 0 || fd >= MAX_FDS as i64 {
+        return false;
+    }
+    let entry = with_fd_table(|table| fd_entry(table, fd as usize));
+    match entry {
+        Some(file) => matches!(
+            file.lock().stat(),
+            Ok(watos_vfs::FileStat { file_type: watos_vfs::FileType::CharDevice, .. })
+        ),
+        None => false,
+    }
+}
+
+/// The pty on the caller's stdin, if a terminal app is hosting it
+fn stdin_pty() -> Option<FdEntry> {
+    let entry = with_fd_table(|table| table.files[0].clone())?;
+    let is_pty = matches!(entry.lock().stat(), Ok(stat) if stat.dev == watos_vfs::pty::PTY_DEVICE);
+    is_pty.then_some(entry)
+}
+
+/// The id of the socket behind an fd (see `watos_network::socket`)
+fn fd_socket(fd: i64) -> Option<u64> {
+    let entry = fd_file(fd).ok()?;
+    let stat = on_kernel_tables(|| entry.lock().stat()).ok()?;
+    (stat.dev == watos_network::socket::SOCKET_DEVICE).then_some(stat.inode)
+}
+
+/// Give a new socket an fd; u64::MAX if there is none or no socket
+fn net_install(socket: Result<watos_network::socket::SocketFile, watos_network::socket::NetError>) -> u64 {
+    match socket {
+        Ok(file) => match fd_alloc(Box::new(file)) {
+            fd if fd >= 0 => fd as u64,
+            _ => u64::MAX,
+        },
+        Err(_) => u64::MAX,
+    }
+}
+
+/// Create an anonymous pipe, returns (read_fd, write_fd)
+/// With `cloexec`, children don't inherit either end
+fn fd_pipe(cloexec: bool) -> Option<(i64, i64)> {
+    let (read_fd, write_fd) = fd_install_pair(watos_vfs::create_pipe())?;
+    if cloexec {
+        with_fd_table(|table| table.cloexec |= 1 << read_fd | 1 << write_fd);
+    }
+    Some((read_fd, write_fd))
+}
+
+/// Create a pseudo-terminal pair, returns (master_fd, slave_fd)
+fn fd_openpty() -> Option<(i64, i64)> {
+    fd_install_pair(watos_vfs::create_pty())
+}
+
+/// Install both ends of a pipe or pty, releasing the first if the second won't fit
+fn fd_install_pair(
+    (read_end, write_end): (Box<dyn FileOperations>, Box<dyn FileOperations>),
+) -> Option<(i64, i64)> {
+    with_fd_table(|table| {
+        let read_fd = fd_install(table, Arc::new(Mutex::new(read_end)));
+        if read_fd < 0 {
+            return None;
+        }
+        let write_fd = fd_install(table, Arc::new(Mutex::new(write_end)));
+        if write_fd < 0 {
+            table.set(read_fd as usize, None);
+            return None;
+        }
+        Some((read_fd, write_fd))
+    })
+}
+
+/// Capture the framebuffer and write it to `path` (see SYS_SCREENSHOT)
+fn save_screenshot(path: &str) -> u64 {
+    use watos_driver_video::screenshot::{self, ImageFormat};
+
+    let Some(shot) = screenshot::capture() else { return u64::MAX };
+    let data = shot.encode(ImageFormat::from_path(path));
+
+    let Ok(mut file) = watos_vfs::open(path, FileMode::WRITE) else { return u64::MAX };
+    let mut written = 0;
+    while written < data.len() {
+        match file.write(&data[written..]) {
+            Ok(n) if n > 0 => written += n,
+            _ => return u64::MAX,
         }
+    }
+
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] Screenshot saved to ");
+        watos_arch::serial_write(path.as_bytes());
+        watos_arch::serial_write(b"\r\n");
+    }
+    0
+}
+
+/// Largest font file SYS_SETFONT will read
+const MAX_FONT_FILE: usize = 512 * 1024;
+
+/// Load a PSF2 font (or the built-in one when `path` is empty) into the VTs
+/// (see SYS_SETFONT)
+fn set_console_font(path: &str, scale: u32) -> u64 {
+    let psf = if path.is_empty() {
+        None
     } else {
-        -1 // Not open
+        let Ok(mut file) = watos_vfs::open(path, FileMode::READ) else { return u64::MAX };
+        let mut data = alloc::vec::Vec::new();
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = file.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            if data.len() > MAX_FONT_FILE {
+                return u64::MAX;
+            }
+        }
+        Some(data)
+    };
+
+    match watos_vt::vt_set_font(psf, scale) {
+        Ok((cols, rows)) => ((cols as u64) << 16) | rows as u64,
+        Err(_) => {
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] setfont: cannot use ");
+                watos_arch::serial_write(path.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+            u64::MAX
+        }
+    }
+}
+
+/// Switch the framebuffer to another GOP resolution and resize the VTs
+/// (see SYS_FB_SET_MODE). Returns (width << 32) | (height << 16) | pitch/4.
+fn set_display_mode(width: u32, height: u32) -> u64 {
+    use watos_driver_traits::video::VideoMode;
+
+    let Some(current) = watos_driver_video::get_current_mode() else { return u64::MAX };
+    let mode = VideoMode { width, height, ..current };
+    if watos_driver_video::set_mode(mode).is_err() {
+        return u64::MAX;
+    }
+    let pitch = watos_driver_video::get_pitch().unwrap_or(width as usize * 4) as u32;
+
+    // SYS_FB_* report the kernel copy; new processes map from the original
+    unsafe {
+        if let Some(info) = (*core::ptr::addr_of_mut!(BOOT_INFO)).as_mut() {
+            info.framebuffer_width = width;
+            info.framebuffer_height = height;
+            info.framebuffer_pitch = pitch;
+        }
+        let raw = &mut *(BOOT_INFO_ADDR as *mut BootInfo);
+        if raw.magic == BOOT_MAGIC {
+            raw.framebuffer_width = width;
+            raw.framebuffer_height = height;
+            raw.framebuffer_pitch = pitch;
+        }
+    }
+
+    watos_vt::vt_set_resolution(width, height, pitch);
+
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] Display mode ");
+        watos_arch::serial_hex(width as u64);
+        watos_arch::serial_write(b"x");
+        watos_arch::serial_hex(height as u64);
+        watos_arch::serial_write(b"\r\n");
+    }
+    ((width as u64) << 32) | ((height as u64) << 16) | (pitch / 4) as u64
+}
+
+/// Halt for `ms` milliseconds. The CPU idles between timer ticks (~55 ms
+/// each), so the wait ends on the first tick past the deadline; 0 just
+/// waits for the next interrupt.
+fn sleep_ms(ms: u64) {
+    let deadline = watos_arch::clock::now_ms().saturating_add(ms);
+    loop {
+        watos_process::idle();
+        if watos_arch::clock::now_ms() >= deadline || POWER_BUTTON.load(core::sync::atomic::Ordering::Relaxed) {
+            break;
+        }
+    }
+}
+
+/// Move the cursor by the pending mouse motion (see SYS_MOUSE_POLL).
+/// Returns (buttons << 32) | (y << 16) | x.
+fn mouse_poll() -> u64 {
+    use watos_driver_traits::input::{InputDevice, InputEvent};
+
+    // One move for all pending motion keeps the redraw to two squares
+    let (mut dx, mut dy) = (0i32, 0i32);
+    while let Ok(Some(event)) = watos_driver_ps2::MOUSE.poll_event() {
+        if let InputEvent::MouseMove(x, y) = event {
+            dx += x as i32;
+            dy += y as i32;
+        }
     }
+    let (x, y) = if dx != 0 || dy != 0 {
+        watos_driver_video::cursor_move_by(dx, dy)
+    } else {
+        watos_driver_video::cursor_position()
+    };
+    let buttons = watos_driver_ps2::MOUSE.buttons() as u64;
+    (buttons << 32) | ((y as u64 & 0xFFFF) << 16) | (x as u64 & 0xFFFF)
 }
 
 /// Find a preloaded app by name (case-insensitive)
@@ -995,6 +2852,8 @@ pub extern "C" fn _start() -> ! {
     unsafe {
         ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
     }
+    #[cfg(feature = "heap-debug")]
+    watos_mem::heap_debug::set_reporter(|msg| unsafe { watos_arch::serial_write(msg.as_bytes()) });
 
     // 2. Init architecture (GDT, IDT, PIC)
     let kernel_stack = HEAP_START as u64 + HEAP_SIZE as u64;
@@ -1005,6 +2864,18 @@ pub extern "C" fn _start() -> ! {
 
     unsafe { watos_arch::serial_write(b"WATOS kernel started\r\n"); }
 
+    // 2.5 Keep the kernel out of user pages: SMEP, SMAP, UMIP
+    let protections = watos_arch::cpu::enable_protections();
+    watos_mem::user_access::set_smap(protections.smap);
+    unsafe {
+        watos_arch::serial_write(alloc::format!(
+            "[KERNEL] SMEP: {}, UMIP: {}, SMAP: {}\r\n",
+            if protections.smep { "on" } else { "unsupported" },
+            if protections.umip { "on" } else { "unsupported" },
+            if protections.smap { "on" } else { "unsupported" },
+        ).as_bytes());
+    }
+
     // 3. Copy boot info
     unsafe {
         let boot_info = &*(BOOT_INFO_ADDR as *const BootInfo);
@@ -1020,9 +2891,33 @@ pub extern "C" fn _start() -> ! {
         watos_arch::serial_hex(boot_info.framebuffer_height as u64);
         watos_arch::serial_write(b"\r\n");
     }
+    apply_boot_options();
+
+    // 3.5 Pick the clock source and find the ACPI power button
+    let clock = watos_arch::clock::init();
+    unsafe {
+        if let Some(hv) = watos_arch::hypervisor::detect() {
+            watos_arch::serial_write(b"[KERNEL] Hypervisor: ");
+            watos_arch::serial_write(hv.name().as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+        watos_arch::serial_write(b"[KERNEL] Clock source: ");
+        watos_arch::serial_write(clock.name().as_bytes());
+        watos_arch::serial_write(b"\r\n");
+    }
+    init_acpi();
 
     // 4. Install syscall handler
     watos_arch::idt::install_syscall_handler(syscall_handler);
+    watos_arch::exceptions::set_user_fault_handler(user_fault);
+    watos_arch::exceptions::set_stack_owner_lookup(watos_process::kstack::owner);
+    watos_sync::set_owner_lookup(|| watos_process::current_pid().unwrap_or(0));
+    register_sysctls();
+    watos_arch::idt::set_serial_rx_handler(monitor_serial_rx);
+    watos_arch::idt::set_key_filter(job_control_key);
+    watos_arch::idt::set_user_tick_handler(watos_process::sched::user_tick);
+    watos_process::set_release_hook(fd_release);
+    watos_arch::serial_enable_rx_irq();
     unsafe { watos_arch::serial_write(b"[KERNEL] Syscall handler installed\r\n"); }
 
     // 4.5. Initialize physical page allocator
@@ -1031,6 +2926,17 @@ pub extern "C" fn _start() -> ! {
     watos_mem::phys::init(0x1000000, 128 * 1024 * 1024);
     unsafe { watos_arch::serial_write(b"[KERNEL] Physical allocator initialized (128MB @ 16MB)\r\n"); }
 
+    // 4.6. Take over the firmware's identity map, with 2MB pages wherever
+    // it used a whole page table for one block. Processes inherit the
+    // kernel CR3 captured below.
+    if let Some((pml4, promoted)) = watos_mem::paging::promote_identity_map() {
+        unsafe {
+            watos_mem::paging::load_cr3(pml4);
+            watos_arch::serial_write(alloc::format!(
+                "[KERNEL] Identity map: {} page tables replaced by 2MB pages\r\n", promoted).as_bytes());
+        }
+    }
+
 // 5. Initialize process subsystem
     watos_process::init();
     unsafe { watos_arch::serial_write(b"[KERNEL] Process subsystem initialized\r\n"); }
@@ -1058,6 +2964,24 @@ pub extern "C" fn _start() -> ! {
                 );
                 watos_arch::serial_write(b"[KERNEL] Video driver initialized\r\n");
 
+                // Offer the other GOP modes, and map enough framebuffer into
+                // processes (SYS_FB_MAP) for the largest of them
+                let mode_count = (info.mode_count as usize).min(MAX_GOP_MODES);
+                let mut map_size = info.framebuffer_pitch as u64 * info.framebuffer_height as u64;
+                for mode in &info.modes[..mode_count] {
+                    watos_driver_video::add_boot_mode(mode.width, mode.height);
+                    map_size = map_size.max(mode.width as u64 * mode.height as u64 * 4);
+                }
+                watos_process::set_framebuffer_map_size(map_size);
+
+                // PS/2 mouse for the cursor overlay
+                if watos_driver_ps2::init_mouse() {
+                    watos_arch::serial_write(b"[KERNEL] PS/2 mouse enabled\r\n");
+                } else {
+                    watos_arch::serial_write(b"[KERNEL] No PS/2 mouse\r\n");
+                }
+                power::register(watos_driver_ps2::power_hook());
+
                 // Initialize VT subsystem (kernel virtual terminals)
                 watos_vt::init(
                     info.framebuffer_addr as usize,
@@ -1068,11 +2992,23 @@ pub extern "C" fn _start() -> ! {
                     is_bgr,
                 );
             } else {
+                // Legacy BIOS or -nographic boot: fall back to VGA text mode
                 watos_arch::serial_write(b"[KERNEL] WARNING: No framebuffer from bootloader\r\n");
+                watos_vt::init_vga_text();
             }
+            configure_console_colors();
+            init_console_mirror();
         }
     }
 
+    // 5.45 Sound card for SYS_AUDIO_*
+    init_audio();
+
+    // 5.46 Gameport joystick; USB pads are fed in through watos_driver_gamepad::report
+    if watos_driver_gamepad::init() {
+        unsafe { watos_arch::serial_write(b"[KERNEL] Gameport joystick found\r\n"); }
+    }
+
     // 5.5 Initialize VFS and mount boot disk as C:
     init_cwd();
     let vfs_ok = init_vfs();
@@ -1080,21 +3016,43 @@ pub extern "C" fn _start() -> ! {
         unsafe { watos_arch::serial_write(b"[KERNEL] WARNING: VFS init failed\r\n"); }
     }
 
+    // Letters for the volumes mounted from here on
+    if vfs_ok {
+        load_drive_map();
+    }
+
     // Also check for WFS data disk on other ports
-    let wfs_found = init_disk();
-    if wfs_found {
-        // Also add to legacy drive table for CURRENT_DRIVE tracking
-        drive_mount(b"D", b"/", b"WFS");
+    init_disk();
+
+    // A host directory shared over virtio 9P, if QEMU has one
+    init_host_share();
+
+    // The host's memory balloon, if QEMU has one
+    init_balloon();
+
+    // TCP/IP on the e1000, if there is one
+    init_network();
+    init_swap();
+
+    // 5.6 Save the kernel log now that the root filesystem is writable
+    if vfs_ok {
+        klog_persist_init();
     }
 
-    // 6. Execute init app - try login first, then fall back to TERM.EXE
+    // 6. Execute init app - try `init` from the command line or watos.cfg
+    // (default login) first, then fall back to TERM.EXE
     unsafe {
         if let Some(info) = BOOT_INFO {
-            // First try to find and launch login app from preloaded apps
-            let login_found = find_preloaded_app(b"login");
-            
-            let (name_bytes, app_data_opt): (&[u8], Option<(u64, u64)>) = if login_found.is_some() {
-                (b"login", login_found)
+            let init_name = watos_bootcfg::init().unwrap_or("login").as_bytes();
+            let init_found = find_preloaded_app(init_name);
+            if init_found.is_none() {
+                watos_arch::serial_write(b"[KERNEL] Init program not preloaded: ");
+                watos_arch::serial_write(init_name);
+                watos_arch::serial_write(b"\r\n");
+            }
+
+            let (name_bytes, app_data_opt): (&[u8], Option<(u64, u64)>) = if init_found.is_some() {
+                (init_name, init_found)
             } else if info.init_app_addr != 0 && info.init_app_size != 0 {
                 (b"TERM.EXE", Some((info.init_app_addr, info.init_app_size)))
             } else {
@@ -1116,10 +3074,11 @@ pub extern "C" fn _start() -> ! {
                         addr as *const u8,
                         size as usize,
                     );
+                    let app = watos_process::Executable::from_image(Arc::from(app_data));
 
                     // Execute the app
                     let name_str = core::str::from_utf8(name_bytes).unwrap_or("app");
-                    match watos_process::exec(name_str, app_data, name_str) {
+                    match watos_process::exec(name_str, &app, &[name_str]) {
                         Ok(pid) => {
                             watos_arch::serial_write(b"[KERNEL] ");
                             watos_arch::serial_write(name_bytes);
@@ -1151,31 +3110,270 @@ pub extern "C" fn _start() -> ! {
 }
 
 // ============================================================================
-// Syscall Interface (numbers from watos-syscall crate)
+// Persistent Kernel Log - the watos_arch::klog ring appended to disk
 // ============================================================================
 
-/// Syscall numbers - must match watos_syscall::numbers
-mod syscall {
-    // Console/IO
-    pub const SYS_WRITE: u64 = 1;
-    pub const SYS_READ: u64 = 2;
-    pub const SYS_OPEN: u64 = 3;
-    pub const SYS_CLOSE: u64 = 4;
-    pub const SYS_GETKEY: u64 = 5;
-    pub const SYS_EXIT: u64 = 6;
+/// /var/log on the boot drive (C: is the root filesystem)
+const KERNEL_LOG_DIR: &str = "C:/var/log";
+const KERNEL_LOG_PATH: &str = "C:/var/log/kernel.log";
+/// Previous log after rotation (8.3-safe for FAT)
+const KERNEL_LOG_ROTATED: &str = "C:/var/log/kernel.old";
+/// Rotate once the log would grow past this size (`klog.max_size` in
+/// watos.cfg overrides it)
+const KERNEL_LOG_MAX_SIZE: u64 = 256 * 1024;
+
+/// State of the on-disk log sink
+struct KlogSink {
+    enabled: bool,
+    /// Ring position up to which the log has been saved
+    flushed: u64,
+    /// Unterminated last line, held until it is complete
+    partial: alloc::vec::Vec<u8>,
+    /// Last line written and how many identical lines followed it
+    last_line: alloc::vec::Vec<u8>,
+    repeats: u32,
+}
 
-    // System
-    pub const SYS_MALLOC: u64 = 14;
-    pub const SYS_FREE: u64 = 15;
+static KLOG_SINK: Mutex<KlogSink> = Mutex::new(KlogSink {
+    enabled: false,
+    flushed: 0,
+    partial: alloc::vec::Vec::new(),
+    last_line: alloc::vec::Vec::new(),
+    repeats: 0,
+});
+
+/// Start saving the kernel log, once the root filesystem is writable
+/// Everything logged since boot that is still in the ring goes out first.
+fn klog_persist_init() {
+    let _ = watos_vfs::mkdir("C:/var");
+    let _ = watos_vfs::mkdir(KERNEL_LOG_DIR);
+    if watos_vfs::open(KERNEL_LOG_PATH, FileMode::APPEND).is_err() {
+        unsafe { watos_arch::serial_write(b"[KLOG] Cannot open kernel.log, log stays in memory\r\n"); }
+        return;
+    }
 
-    // Console handle management
-    pub const SYS_CONSOLE_OUT: u64 = 21;   // Get stdout handle (returns 1)
-    pub const SYS_CONSOLE_ERR: u64 = 22;   // Get stderr handle (returns 2)
+    let header = alloc::format!("=== boot {} ===\n", watos_arch::rtc::read_datetime());
+    {
+        let mut sink = KLOG_SINK.lock();
+        sink.enabled = true;
+        klog_append(header.as_bytes());
+    }
+    unsafe { watos_arch::serial_write(b"[KLOG] Saving kernel log to /var/log/kernel.log\r\n"); }
+    klog_flush();
+}
 
-    // Framebuffer
-    pub const SYS_FB_INFO: u64 = 50;
-    pub const SYS_FB_ADDR: u64 = 51;
+/// Append everything logged since the last flush to kernel.log
+///
+/// The text is lines; a run of identical lines is stored once followed by
+/// a "[last message repeated N times]" line, and the whole flush becomes
+/// one compressed record. Logging done by the write itself is picked up by
+/// the next flush.
+fn klog_flush() {
+    // Skip rather than wait if a flush is already running
+    let Some(mut sink) = KLOG_SINK.try_lock() else { return };
+    if !sink.enabled {
+        return;
+    }
+
+    let end = watos_arch::klog::written();
+    let mut out = alloc::vec::Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut pos = sink.flushed;
+    while pos < end {
+        let (n, start) = watos_arch::klog::read(pos, &mut buf);
+        if start > pos {
+            out.extend_from_slice(alloc::format!("[{} bytes of log lost]\n", start - pos).as_bytes());
+        }
+        let n = n.min((end - start) as usize);
+        for &b in &buf[..n] {
+            match b {
+                b'\r' => {}
+                b'\n' => {
+                    let line = core::mem::take(&mut sink.partial);
+                    if line == sink.last_line {
+                        sink.repeats += 1;
+                        continue;
+                    }
+                    if sink.repeats > 0 {
+                        out.extend_from_slice(alloc::format!("[last message repeated {} times]\n", sink.repeats).as_bytes());
+                        sink.repeats = 0;
+                    }
+                    out.extend_from_slice(&line);
+                    out.push(b'\n');
+                    sink.last_line = line;
+                }
+                _ => sink.partial.push(b),
+            }
+        }
+        pos = start + n as u64;
+    }
+    sink.flushed = end;
+
+    if !out.is_empty() {
+        klog_append(&out);
+    }
+}
+
+/// Compress bytes into one record (watos_syscall::klog) at the end of
+/// kernel.log, rotating it first if it would get too big
+fn klog_append(data: &[u8]) {
+    let mut record = alloc::vec::Vec::from(watos_syscall::klog::MAGIC);
+    record.extend_from_slice(&wfs_common::core::compress::pack(data));
+    let data = &record[..];
+
+    if let Ok(stat) = watos_vfs::stat(KERNEL_LOG_PATH) {
+        let max_size = watos_bootcfg::config().get_u64("klog.max_size").unwrap_or(KERNEL_LOG_MAX_SIZE);
+        if stat.size + data.len() as u64 > max_size {
+            let _ = watos_vfs::unlink(KERNEL_LOG_ROTATED);
+            let _ = watos_vfs::rename(KERNEL_LOG_PATH, KERNEL_LOG_ROTATED);
+        }
+    }
+
+    let Ok(mut file) = watos_vfs::open(KERNEL_LOG_PATH, FileMode::APPEND) else { return };
+    let mut written = 0;
+    while written < data.len() {
+        match file.write(&data[written..]) {
+            Ok(n) if n > 0 => written += n,
+            _ => break,
+        }
+    }
+    let _ = file.sync();
+}
+
+// ============================================================================
+// User Process Crashes
+// ============================================================================
+
+/// Signal numbers - must match watos_syscall::signals
+mod signals {
+    pub const SIGILL: u32 = 4;
+    pub const SIGTRAP: u32 = 5;
+    pub const SIGBUS: u32 = 7;
+    pub const SIGFPE: u32 = 8;
+    pub const SIGKILL: u32 = 9;
+    pub const SIGSEGV: u32 = 11;
+    pub const SIGTSTP: u32 = 20;
+}
+
+/// Signal a CPU exception in user code maps to
+fn fault_signal(vector: u64) -> u32 {
+    use watos_arch::exceptions::vector::*;
+    use signals::*;
+    match vector as u8 {
+        DIVIDE_ERROR | OVERFLOW | X87_FPU | SIMD_FPU => SIGFPE,
+        BREAKPOINT => SIGTRAP,
+        INVALID_OPCODE | DEVICE_NOT_AVAILABLE => SIGILL,
+        ALIGNMENT_CHECK => SIGBUS,
+        _ => SIGSEGV,
+    }
+}
+
+/// Write a core file, first in the current directory, then in /tmp
+/// Returns true if one was written
+fn write_core(pid: u32, data: &[u8]) -> bool {
+    let mut cwd = [0u8; 256];
+    let len = get_cwd(&mut cwd);
+    let mut in_cwd = alloc::string::String::from_utf8_lossy(&cwd[..len]).into_owned();
+    if !in_cwd.ends_with('\\') {
+        in_cwd.push('\\');
+    }
+    in_cwd.push_str(&alloc::format!("core.{}", pid));
+    let in_tmp = alloc::format!("/tmp/core.{}", pid);
+
+    for path in [in_cwd.as_str(), in_tmp.as_str()] {
+        let Ok(mut file) = watos_vfs::open(path, FileMode::WRITE) else { continue };
+        let mut written = 0;
+        while written < data.len() {
+            match file.write(&data[written..]) {
+                Ok(n) if n > 0 => written += n,
+                _ => break,
+            }
+        }
+        if written == data.len() {
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] Core dumped to ");
+                watos_arch::serial_write(path.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Terminate a process that faulted in ring 3, leaving a core file behind
+/// if its RLIMIT_CORE allows
+fn user_fault(frame: &watos_arch::exceptions::FaultFrame, cr2: u64) -> ! {
+    // Copy the frame off the kernel stack before anything else touches it
+    let frame = *frame;
+    let pid = watos_process::current_pid().unwrap_or(0);
+    let signal = fault_signal(frame.vector);
+
+    unsafe {
+        watos_arch::serial_write(b"\r\n[KERNEL] PID ");
+        watos_arch::serial_hex(pid as u64);
+        watos_arch::serial_write(b" crashed: vector=");
+        watos_arch::serial_hex(frame.vector);
+        watos_arch::serial_write(b" err=");
+        watos_arch::serial_hex(frame.error_code);
+        watos_arch::serial_write(b" RIP=");
+        watos_arch::serial_hex(frame.rip);
+        watos_arch::serial_write(b" RSP=");
+        watos_arch::serial_hex(frame.rsp);
+        watos_arch::serial_write(b" CR2=");
+        watos_arch::serial_hex(cr2);
+        watos_arch::serial_write(b"\r\n");
+
+        // The core is read from, and the process freed, under the kernel page table
+        watos_mem::paging::load_cr3(watos_process::get_kernel_pml4());
+    }
+
+    let dumped = match watos_process::dump_core(&frame, signal) {
+        Some(core) => write_core(pid, &core),
+        None => false,
+    };
+    klog_flush();
+
+    watos_process::record_killed(signal, dumped);
+    watos_process::free_current_process();
+    watos_process::sched::schedule(); // Never returns
+}
+
+// ============================================================================
+// Syscall Interface (numbers from watos-syscall crate)
+// ============================================================================
+
+/// Syscall numbers - must match watos_syscall::numbers
+mod syscall {
+    // Console/IO
+    pub const SYS_WRITE: u64 = 1;
+    pub const SYS_READ: u64 = 2;
+    pub const SYS_OPEN: u64 = 3;
+    pub const SYS_CLOSE: u64 = 4;
+    pub const SYS_GETKEY: u64 = 5;
+    pub const SYS_EXIT: u64 = 6;
+
+    // System
+    pub const SYS_IDLE: u64 = 10;
+    pub const SYS_SLEEP: u64 = 11;
+    pub const SYS_MALLOC: u64 = 14;
+    pub const SYS_FREE: u64 = 15;
+
+    // Console handle management
+    pub const SYS_CONSOLE_OUT: u64 = 21;   // Get stdout handle (returns 1)
+    pub const SYS_CONSOLE_ERR: u64 = 22;   // Get stderr handle (returns 2)
+
+    // Framebuffer
+    pub const SYS_FB_INFO: u64 = 50;
+    pub const SYS_FB_ADDR: u64 = 51;
     pub const SYS_FB_DIMENSIONS: u64 = 52;
+    pub const SYS_SCREENSHOT: u64 = 53;
+    pub const SYS_SETFONT: u64 = 54;
+    pub const SYS_FB_SET_MODE: u64 = 55;
+    pub const SYS_MOUSE_POLL: u64 = 56;
+    pub const SYS_CURSOR_SHOW: u64 = 57;
+    pub const SYS_CURSOR_SPRITE: u64 = 58;
+    pub const SYS_FB_MAP: u64 = 59;
 
     // Raw keyboard
     pub const SYS_READ_SCANCODE: u64 = 60;
@@ -1199,7 +3397,136 @@ mod syscall {
 
     // Process execution
     pub const SYS_EXEC: u64 = 80;
+    pub const SYS_SPAWN: u64 = 81;
+    pub const SYS_WAIT: u64 = 82;
     pub const SYS_GETARGS: u64 = 83;
+    pub const SYS_GETRUSAGE: u64 = 84;
+    pub const SYS_GETMEMUSAGE: u64 = 155;
+    pub const SYS_SETRLIMIT: u64 = 156;
+    pub const SYS_GETRLIMIT: u64 = 157;
+    pub const RLIMIT_CORE: u64 = 4; // watos_syscall::rlimit
+    pub const SYS_SETPRIORITY: u64 = 158;
+    pub const SYS_GETPRIORITY: u64 = 159;
+
+    // Process groups and job control
+    pub const SYS_SETPGID: u64 = 150;
+    pub const SYS_GETPGID: u64 = 151;
+    pub const SYS_KILL: u64 = 152;
+    pub const SYS_TCSETPGRP: u64 = 153;
+    pub const SYS_TCGETPGRP: u64 = 154;
+
+    // File descriptor operations
+    pub const SYS_PIPE: u64 = 160;
+    pub const SYS_DUP: u64 = 161;
+    pub const SYS_DUP2: u64 = 162;
+    pub const SYS_OPENPTY: u64 = 163;
+
+    // Clipboard
+    pub const SYS_CLIPBOARD_SET: u64 = 170;
+    pub const SYS_CLIPBOARD_GET: u64 = 171;
+
+    // Audio
+    pub const SYS_AUDIO_OPEN: u64 = 180;
+    pub const SYS_AUDIO_SET_CONFIG: u64 = 181;
+    pub const SYS_AUDIO_WRITE: u64 = 182;
+    pub const SYS_AUDIO_AVAILABLE: u64 = 183;
+    pub const SYS_AUDIO_CLOSE: u64 = 184;
+    pub const SYS_BEEP: u64 = 185;
+    pub const SYS_GAMEPAD_LIST: u64 = 186;
+    pub const SYS_GAMEPAD_STATE: u64 = 187;
+
+    // Filesystem snapshots
+    pub const SYS_SNAPSHOT: u64 = 188;
+    pub const SYS_SNAPSHOT_LIST: u64 = 189;
+    pub const SYS_SNAPSHOT_MOUNT: u64 = 190;
+
+    // Hard links
+    pub const SYS_LINK: u64 = 191;
+
+    // Terminals
+    pub const SYS_ISATTY: u64 = 192;
+
+    // Argument vectors (watos_syscall::argv)
+    pub const SYS_EXECV: u64 = 193;
+    pub const SYS_GETARGV: u64 = 194;
+
+    // Account enumeration (watos_syscall::pwd)
+    pub const SYS_GETPWENT: u64 = 195;
+    pub const SYS_GETPWNAM: u64 = 196;
+    pub const SYS_GETGRENT: u64 = 197;
+    pub const SYS_GETGRGID: u64 = 198;
+    pub const SYS_GETGROUPS: u64 = 199;
+
+    // Keyring (watos_keyring)
+    pub const SYS_KEY_ADD: u64 = 200;
+    pub const SYS_KEY_READ: u64 = 201;
+    pub const SYS_KEY_DELETE: u64 = 202;
+
+    // Swap (watos_swap)
+    pub const SYS_SWAPON: u64 = 203;
+    pub const SYS_SWAPOFF: u64 = 204;
+    pub const SYS_SWAPINFO: u64 = 205;
+
+    // Descriptor to descriptor copy
+    pub const SYS_SENDFILE: u64 = 206;
+
+    // Kernel tunables (watos_sysctl)
+    pub const SYS_SYSCTL: u64 = 207;
+
+    // System statistics (watos_syscall::sysinfo)
+    pub const SYS_SYSINFO: u64 = 208;
+
+    // Volume label and serial number (watos_syscall::volume)
+    pub const SYS_VOLINFO: u64 = 209;
+
+    // Drive letter reassignment (watos_vfs::letters)
+    pub const SYS_DRIVE_ASSIGN: u64 = 210;
+
+    // Path to path file copy (watos_syscall::copyfile)
+    pub const SYS_COPYFILE: u64 = 211;
+
+    // Per-drive recycle bin (watos_vfs::trash)
+    pub const SYS_TRASH: u64 = 212;
+    pub const SYS_TRASH_LIST: u64 = 213;
+    pub const SYS_TRASH_RESTORE: u64 = 214;
+    pub const SYS_TRASH_PURGE: u64 = 215;
+
+    // Timestamp and performance counters (watos_syscall::perfctr)
+    pub const SYS_PERFCTR: u64 = 216;
+
+    // Keyboard
+    pub const SYS_KBD_REPEAT: u64 = 217;
+
+    // Plain-text copy of console output (watos_vt::mirror)
+    pub const SYS_CONSOLE_MIRROR: u64 = 218;
+
+    // TCP/IP (watos_network::socket)
+    pub const SYS_NET_CONNECT: u64 = 219;
+    pub const SYS_NET_LISTEN: u64 = 220;
+    pub const SYS_NET_ACCEPT: u64 = 221;
+    pub const SYS_NET_STATE: u64 = 222;
+    pub const SYS_NET_PEER: u64 = 223;
+    pub const SYS_NET_RESOLVE: u64 = 224;
+    pub const SYS_NET_RESOLVED: u64 = 225;
+
+    // Random bytes (watos_keyring::random_bytes)
+    pub const SYS_GETRANDOM: u64 = 226;
+
+    // SYS_OPEN flags - must match watos_syscall::open
+    pub const O_ACCMODE: u64 = 0x003;
+    pub const O_RDONLY: u64 = 0x000;
+    pub const O_WRONLY: u64 = 0x001;
+    pub const O_CREAT: u64 = 0x040;
+    pub const O_EXCL: u64 = 0x080;
+    pub const O_TRUNC: u64 = 0x200;
+    pub const O_APPEND: u64 = 0x400;
+    pub const O_CLOEXEC: u64 = 0x80000;
+
+    // SYS_AUDIO_SET_CONFIG formats - must match watos_syscall::audio
+    pub const AUDIO_FORMAT_U8: u8 = 0;
+    pub const AUDIO_FORMAT_S16LE: u8 = 1;
+    pub const AUDIO_FORMAT_S16BE: u8 = 2;
+    pub const AUDIO_FORMAT_F32: u8 = 3;
 
     // Date/Time
     pub const SYS_GETDATE: u64 = 90;
@@ -1216,7 +3543,13 @@ mod syscall {
     // Filesystem operations
     pub const SYS_READDIR: u64 = 71;
     pub const SYS_MKDIR: u64 = 72;
+    pub const SYS_UNLINK: u64 = 73;
+    pub const SYS_RMDIR: u64 = 74;
+    pub const SYS_RENAME: u64 = 75;
     pub const SYS_STAT: u64 = 70;
+    pub const SYS_STATFS: u64 = 89;
+    pub const SYS_REALPATH: u64 = 93;
+    pub const SYS_WATCH: u64 = 94;
 
     // User authentication and session management
     pub const SYS_AUTHENTICATE: u64 = 120;
@@ -1224,6 +3557,7 @@ mod syscall {
     pub const SYS_GETUID: u64 = 122;
     pub const SYS_GETGID: u64 = 123;
     pub const SYS_SETGID: u64 = 124;
+    pub const SYS_GET_USER_INFO: u64 = 125;
     pub const SYS_GETEUID: u64 = 126;
     pub const SYS_GETEGID: u64 = 127;
 
@@ -1240,6 +3574,10 @@ mod syscall {
     // Memory info
     pub const SYS_MEMINFO: u64 = 135;
 
+    // Power management
+    pub const SYS_SHUTDOWN: u64 = 100;
+    pub const SYS_REBOOT: u64 = 101;
+
     // Environment variables
     pub const SYS_SETENV: u64 = 136;
     pub const SYS_GETENV: u64 = 137;
@@ -1256,6 +3594,12 @@ mod syscall {
 #[no_mangle]
 pub unsafe extern "C" fn syscall_handler() {
     core::arch::naked_asm!(
+        // Ring 3 can set RFLAGS.AC, and the interrupt gate keeps it: clear
+        // it so SMAP holds until a user_access window opens
+        "pushfq",
+        "and qword ptr [rsp], -262145",                  // ~(1 << 18)
+        "popfq",
+
         // Save original register state to global (for parent context saving during exec)
         // This must be done BEFORE we modify any registers
         "mov qword ptr [rip + {saved_regs} + 0], rbx",   // Save RBX
@@ -1331,8 +3675,13 @@ pub unsafe extern "C" fn syscall_handler() {
 static mut SYSCALL_PATH_BUF: [u8; 256] = [0u8; 256];
 /// Static buffer for file read operations
 static mut SYSCALL_READ_BUF: [u8; 4096] = [0u8; 4096];
+/// SYS_SENDFILE staging buffer: kernel memory only, never mapped to a user
+static mut SENDFILE_BUF: [u8; SENDFILE_CHUNK] = [0u8; SENDFILE_CHUNK];
+/// Bytes SYS_SENDFILE moves per read, a few clusters of the block cache
+const SENDFILE_CHUNK: usize = 16384;
 
-/// Saved register state from syscall entry (for parent context saving)
+/// Saved register state from syscall entry, for the caller's context when
+/// the syscall blocks it
 #[repr(C)]
 struct SavedSyscallRegs {
     rbx: u64,
@@ -1358,8 +3707,81 @@ static mut SAVED_SYSCALL_REGS: SavedSyscallRegs = SavedSyscallRegs {
 
 /// Inner syscall handler - called from naked handler
 /// return_rip and return_rsp are from the interrupt frame for saving parent context
+///
+/// Checks the caller's kernel stack canary on the way out.
 #[inline(never)]
 extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, return_rsp: u64) -> u64 {
+    let result = dispatch_syscall(num, arg1, arg2, arg3, return_rip, return_rsp);
+    if let Err(pid) = watos_process::kstack::check() {
+        kernel_stack_overflow(pid);
+    }
+    // Ctrl+Z may have stopped the caller's group meanwhile
+    if watos_process::sched::console_signal_pending() {
+        watos_process::sched::deliver_console_signal(Some(&syscall_context(return_rip, return_rsp, result)));
+    }
+    result
+}
+
+/// The caller's registers as they are when its syscall returns `rax` to
+/// `rip`, for resuming it after another process has run
+fn syscall_context(rip: u64, rsp: u64, rax: u64) -> watos_process::SavedContext {
+    let regs = unsafe { &*core::ptr::addr_of!(SAVED_SYSCALL_REGS) };
+    watos_process::SavedContext {
+        rax,
+        rbx: regs.rbx,
+        rcx: regs.rcx,
+        rdx: regs.rdx,
+        rsi: regs.rsi,
+        rdi: regs.rdi,
+        rbp: regs.rbp,
+        r8: regs.r8,
+        r9: regs.r9,
+        r10: regs.r10,
+        r11: regs.r11,
+        r12: regs.r12,
+        r13: regs.r13,
+        r14: regs.r14,
+        r15: regs.r15,
+        ..watos_process::SavedContext::new(rip, rsp)
+    }
+}
+
+/// Block the caller in syscall `num` until something wakes it, then run
+/// the syscall again with the same arguments
+fn restart_syscall(num: u64, return_rip: u64, return_rsp: u64, state: watos_process::ProcessState) -> ! {
+    // `int 0x80` is two bytes
+    watos_process::sched::block(syscall_context(return_rip - 2, return_rsp, num), state)
+}
+
+/// Block the caller on an empty or full pipe, running whatever else can run
+/// (or halting until an interrupt if nothing can), then retry the syscall
+fn block_on_pipe(num: u64, return_rip: u64, return_rsp: u64) -> ! {
+    if !watos_process::sched::others_runnable() {
+        watos_process::idle();
+    }
+    restart_syscall(num, return_rip, return_rsp, watos_process::ProcessState::Ready)
+}
+
+/// Terminate a process whose syscall ran its kernel stack into the guard
+/// page's canary
+fn kernel_stack_overflow(pid: u32) -> ! {
+    unsafe {
+        watos_arch::serial_write(b"\r\n[KERNEL] Kernel stack overflow in PID ");
+        watos_arch::serial_hex(pid as u64);
+        watos_arch::serial_write(b", terminating\r\n");
+        watos_mem::paging::load_cr3(watos_process::get_kernel_pml4());
+    }
+    klog_flush();
+
+    watos_process::record_killed(signals::SIGSEGV, false);
+    watos_process::free_current_process();
+    watos_process::sched::schedule(); // Never returns
+}
+
+#[inline(never)]
+fn dispatch_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, return_rsp: u64) -> u64 {
+    poll_power_button();
+
     // For file I/O syscalls that access disk, we need to switch to kernel page table
     // to access AHCI MMIO. But we must copy user data first since user pointers
     // become invalid after CR3 switch.
@@ -1376,9 +3798,11 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
 
             // Copy path from user memory while still in user page table
             let path_copy: &[u8] = unsafe {
-                let user_path = core::slice::from_raw_parts(path_ptr, path_len);
-                SYSCALL_PATH_BUF[..path_len].copy_from_slice(user_path);
-                &SYSCALL_PATH_BUF[..path_len]
+                let buf = &mut *core::ptr::addr_of_mut!(SYSCALL_PATH_BUF);
+                if watos_mem::copy_from_user(path_ptr as u64, &mut buf[..path_len]).is_err() {
+                    return u64::MAX;
+                }
+                &buf[..path_len]
             };
 
             // Now switch to kernel page table for disk access
@@ -1400,8 +3824,9 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
             result
         }
 
-        syscall::SYS_CLOSE => {
-            // SYS_CLOSE only needs fd number, no user pointers
+        syscall::SYS_CLOSE | syscall::SYS_DUP2 => {
+            // SYS_CLOSE/SYS_DUP2 only need fd numbers, no user pointers.
+            // Either may drop the last reference to a file, which can flush to disk.
             let user_cr3 = watos_mem::paging::get_cr3();
             let kernel_pml4 = watos_process::get_kernel_pml4();
 
@@ -1418,7 +3843,57 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
             result
         }
 
-        syscall::SYS_READ if arg1 >= 3 => {
+        syscall::SYS_WRITE if fd_is_open(arg1) => {
+            // Write to a file, pipe, or redirected stdout/stderr:
+            // copy user data into a kernel buffer, then switch to kernel CR3
+            let fd = arg1 as i64;
+            let user_buf_ptr = arg2 as *const u8;
+            let total = arg3 as usize;
+
+            if user_buf_ptr.is_null() || total == 0 {
+                return 0;
+            }
+
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            let mut written = 0usize;
+
+            while written < total {
+                let chunk = (total - written).min(4096);
+                let copied = unsafe {
+                    let buf = &mut *core::ptr::addr_of_mut!(SYSCALL_READ_BUF);
+                    watos_mem::copy_from_user(user_buf_ptr as u64 + written as u64, &mut buf[..chunk])
+                };
+                if copied.is_err() {
+                    break;
+                }
+
+                if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                    unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+                }
+
+                let result = unsafe {
+                    use core::ptr::addr_of;
+                    let buf = &*addr_of!(SYSCALL_READ_BUF);
+                    fd_write(fd, &buf[..chunk])
+                };
+
+                if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                    unsafe { watos_mem::paging::load_cr3(user_cr3); }
+                }
+
+                match result {
+                    Ok(n) if n > 0 => written += n,
+                    // A full pipe: wait for the reader unless this is a short write
+                    Err(VfsError::WouldBlock) if written == 0 => block_on_pipe(num, return_rip, return_rsp),
+                    _ => break,
+                }
+            }
+
+            written as u64
+        }
+
+        syscall::SYS_READ if arg1 >= 3 || fd_is_open(arg1) => {
             // File read needs special handling:
             // 1. Switch to kernel CR3 to read from disk
             // 2. Switch back to user CR3 to copy to user buffer
@@ -1439,12 +3914,10 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
             }
 
             // Read into kernel buffer
-            let bytes_read = unsafe {
+            let result = unsafe {
                 use core::ptr::addr_of_mut;
                 let buf = &mut *addr_of_mut!(SYSCALL_READ_BUF);
-                let kernel_buf = &mut buf[..user_buf_len];
-                let result = fd_read(fd, kernel_buf);
-                if result < 0 { 0usize } else { result as usize }
+                fd_read(fd, &mut buf[..user_buf_len])
             };
 
             // Switch back to user page table
@@ -1452,17 +3925,47 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
                 unsafe { watos_mem::paging::load_cr3(user_cr3); }
             }
 
+            // An empty pipe: wait for its writer to write or close
+            let bytes_read = match result {
+                Ok(n) => n,
+                Err(VfsError::WouldBlock) => block_on_pipe(num, return_rip, return_rsp),
+                Err(_) => 0,
+            };
+
             // Copy from kernel buffer to user buffer
             if bytes_read > 0 {
-                unsafe {
-                    let user_buf = core::slice::from_raw_parts_mut(user_buf_ptr, bytes_read);
-                    user_buf.copy_from_slice(&SYSCALL_READ_BUF[..bytes_read]);
+                let buf = unsafe { &*core::ptr::addr_of!(SYSCALL_READ_BUF) };
+                if watos_mem::copy_to_user(&buf[..bytes_read], user_buf_ptr as u64).is_err() {
+                    return u64::MAX;
                 }
             }
 
             bytes_read as u64
         }
 
+        syscall::SYS_SENDFILE => {
+            // Both ends are kernel objects, so the whole copy runs under
+            // the kernel CR3 and no byte passes through user memory
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = sendfile(arg1 as i64, arg2 as i64, arg3);
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                Ok(copied) => copied,
+                Err(VfsError::WouldBlock) => block_on_pipe(num, return_rip, return_rsp),
+                Err(_) => u64::MAX,
+            }
+        }
+
         _ => {
             // All other syscalls run in user page table
             handle_syscall(num, arg1, arg2, arg3, return_rip, return_rsp)
@@ -1470,6 +3973,291 @@ extern "C" fn handle_syscall_inner(num: u64, arg1: u64, arg2: u64, arg3: u64, re
     }
 }
 
+/// Copy up to `count` bytes from `in_fd`, at its current position, to
+/// `out_fd`. Files are read through the block cache into a kernel buffer and
+/// written straight from it. Returns the bytes copied, which is short at end
+/// of input or when the output stops taking data, or the error that stopped
+/// it before any byte was copied (WouldBlock on an empty or full pipe).
+fn sendfile(out_fd: i64, in_fd: i64, count: u64) -> Result<u64, VfsError> {
+    let valid = |fd: i64| (0..MAX_FDS as i64).contains(&fd);
+    if !valid(out_fd) || !valid(in_fd) {
+        return Err(VfsError::InvalidArgument);
+    }
+    let entries = with_fd_table(|table| (fd_entry(table, in_fd as usize), fd_entry(table, out_fd as usize)));
+    let (input, output) = match entries {
+        (Some(input), Some(output)) => (input, output),
+        _ => return Err(VfsError::InvalidArgument),
+    };
+
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(SENDFILE_BUF) };
+    let mut copied = 0u64;
+    let mut failed = None;
+
+    'copy: while copied < count {
+        let chunk = (count - copied).min(SENDFILE_CHUNK as u64) as usize;
+        let n = match input.lock().read(&mut buf[..chunk]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                failed = Some(e);
+                break;
+            }
+        };
+        watos_process::account_io(false, n);
+        let mut done = 0usize;
+        while done < n {
+            match output.lock().write(&buf[done..n]) {
+                Ok(written) if written > 0 => {
+                    watos_process::account_io(true, written);
+                    done += written;
+                }
+                result => {
+                    // Give a seekable input back what wasn't written; from
+                    // a pipe those bytes are lost, as with a short write
+                    let unwritten = (n - done) as i64;
+                    let _ = input.lock().seek(-unwritten, watos_vfs::SeekFrom::Current);
+                    failed = result.err();
+                    copied += done as u64;
+                    break 'copy;
+                }
+            }
+        }
+        copied += n as u64;
+    }
+
+    match failed {
+        Some(e) if copied == 0 => Err(e),
+        _ => Ok(copied),
+    }
+}
+
+/// FileMode for SYS_OPEN flags (watos_syscall::open), None if invalid
+fn open_mode(flags: u64) -> Option<FileMode> {
+    let access = flags & syscall::O_ACCMODE;
+    if access == syscall::O_ACCMODE {
+        return None;
+    }
+
+    let write = access != syscall::O_RDONLY;
+    Some(FileMode {
+        read: access != syscall::O_WRONLY,
+        write,
+        append: write && flags & syscall::O_APPEND != 0,
+        create: flags & syscall::O_CREAT != 0,
+        truncate: write && flags & syscall::O_TRUNC != 0,
+        exclusive: flags & syscall::O_EXCL != 0,
+    })
+}
+
+/// Executables up to this size are read whole; larger ones are mapped
+/// from their file (see `ExecFile`)
+const EXEC_READ_WHOLE: usize = 1024 * 1024;
+
+/// Bytes read up front from an executable mapped from its file: enough for
+/// the ELF and program headers, and the interpreter's path
+const EXEC_HEAD_SIZE: usize = 4096;
+
+/// An executable too large to read whole, whose pages are read from the
+/// file (and so through the block cache) as its process first touches them
+///
+/// The file stays open for as long as a process runs the program.
+struct ExecFile {
+    file: Mutex<Box<dyn FileOperations>>,
+    size: u64,
+}
+
+impl ExecFile {
+    fn open(path: &str, size: u64) -> Option<watos_process::Executable> {
+        let file = watos_vfs::open(path, FileMode::READ).ok()?;
+        let source = ExecFile { file: Mutex::new(file), size };
+        let mut head = alloc::vec![0u8; EXEC_HEAD_SIZE.min(size as usize)];
+        if !watos_process::PageSource::read_at(&source, 0, &mut head) {
+            return None;
+        }
+        unsafe {
+            watos_arch::serial_write(b"[KERNEL] Mapping ");
+            watos_arch::serial_hex(size);
+            watos_arch::serial_write(b" bytes from ");
+            watos_arch::serial_write(path.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+        Some(watos_process::Executable { head: Arc::from(head), source: Arc::new(source) })
+    }
+}
+
+impl watos_process::PageSource for ExecFile {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> bool {
+        let mut file = self.file.lock();
+        if file.seek(offset as i64, watos_vfs::SeekFrom::Start).is_err() {
+            return false;
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            match file.read(&mut buf[done..]) {
+                Ok(0) | Err(_) => return false,
+                Ok(n) => done += n,
+            }
+        }
+        true
+    }
+}
+
+/// Read an executable (or its interpreter) from the VFS
+///
+/// Call with the kernel page table loaded. Returns None if the file can't
+/// be opened or is empty. Files up to `EXEC_READ_WHOLE` are read whole,
+/// their images coming from `watos_process::exec_cache` while the file is
+/// unchanged; larger ones are mapped from the file.
+fn read_executable(path: &str) -> Option<watos_process::Executable> {
+    let stat = watos_vfs::stat(path).ok();
+    let key = stat.map(|stat| watos_process::exec_cache::ExecKey {
+        path: alloc::string::String::from(path),
+        dev: stat.dev,
+        inode: stat.inode,
+        size: stat.size,
+        mtime: stat.mtime,
+    });
+    if let Some(image) = key.as_ref().and_then(watos_process::exec_cache::lookup) {
+        unsafe {
+            watos_arch::serial_write(b"[KERNEL] Cached ");
+            watos_arch::serial_hex(image.len() as u64);
+            watos_arch::serial_write(b" bytes for ");
+            watos_arch::serial_write(path.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+        return Some(watos_process::Executable::from_image(image));
+    }
+    if let Some(stat) = stat.filter(|stat| stat.size > EXEC_READ_WHOLE as u64) {
+        return ExecFile::open(path, stat.size);
+    }
+
+    let fd = handle_sys_open(path.as_bytes(), syscall::O_RDONLY);
+    if fd == u64::MAX {
+        return None;
+    }
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] File opened, fd=");
+        watos_arch::serial_hex(fd);
+        watos_arch::serial_write(b"\r\n");
+    }
+
+    // Read file using Vec
+    let mut file_contents = alloc::vec::Vec::new();
+    const CHUNK_SIZE: usize = 4096;
+    let mut read_buf = [0u8; CHUNK_SIZE];
+
+    loop {
+        let chunk_read = match fd_read(fd as i64, &mut read_buf) {
+            Ok(n) if n > 0 => n,
+            _ => break,
+        };
+
+        file_contents.extend_from_slice(&read_buf[..chunk_read]);
+
+        // It grew since it was looked at, and won't match its key
+        if file_contents.len() > EXEC_READ_WHOLE {
+            break;
+        }
+    }
+
+    fd_close(fd as i64);
+
+    if file_contents.is_empty() {
+        return None;
+    }
+    unsafe {
+        watos_arch::serial_write(b"[KERNEL] Loaded ");
+        watos_arch::serial_hex(file_contents.len() as u64);
+        watos_arch::serial_write(b" bytes from ");
+        watos_arch::serial_write(path.as_bytes());
+        watos_arch::serial_write(b"\r\n");
+    }
+    let image: Arc<[u8]> = Arc::from(file_contents);
+    // A file that changed while being read doesn't match its key
+    if let Some(key) = key.filter(|key| key.size == image.len() as u64) {
+        watos_process::exec_cache::insert(key, image.clone());
+    }
+    Some(watos_process::Executable::from_image(image))
+}
+
+/// Copy a path of `len` bytes out of user memory; None if it is longer
+/// than any drive-qualified path or not readable
+fn user_path(ptr: u64, len: usize) -> Option<alloc::vec::Vec<u8>> {
+    if len > MAX_PATH_LEN + MAX_DRIVE_NAME + 1 {
+        return None;
+    }
+    watos_mem::read_user_bytes(ptr, len).ok()
+}
+
+/// Copy `len` bytes of user memory as text, replacing invalid UTF-8
+fn user_string_lossy(ptr: u64, len: usize) -> Option<alloc::string::String> {
+    let bytes = watos_mem::read_user_bytes(ptr, len).ok()?;
+    Some(alloc::string::String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Run a VFS query on a user path, relative paths starting from the
+/// working directory
+///
+/// The query runs on the kernel page table, so it gets a copy of the path
+/// and its result must be written to user memory afterwards.
+fn vfs_query<T>(
+    path_ptr: u64,
+    path_len: u64,
+    query: impl FnOnce(&str) -> watos_vfs::VfsResult<T>,
+) -> Option<T> {
+    let path_len = path_len as usize;
+    if path_ptr == 0 || path_len == 0 || path_len > 256 {
+        return None;
+    }
+    let mut path_buf = [0u8; 256];
+    watos_mem::copy_from_user(path_ptr, &mut path_buf[..path_len]).ok()?;
+    let path = core::str::from_utf8(&path_buf[..path_len]).ok()?;
+
+    let mut cwd_buf = [0u8; MAX_PATH_LEN + MAX_DRIVE_NAME + 1];
+    let cwd_len = get_cwd(&mut cwd_buf);
+    let cwd = core::str::from_utf8(&cwd_buf[..cwd_len]).unwrap_or("");
+
+    let user_cr3 = watos_mem::paging::get_cr3();
+    let kernel_pml4 = watos_process::get_kernel_pml4();
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+    }
+
+    let result = watos_vfs::canonicalize(path, cwd).and_then(|path| query(&path));
+
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(user_cr3); }
+    }
+    result.ok()
+}
+
+/// Run `f` on the kernel page table, for VFS calls whose arguments are
+/// already out of user memory
+fn on_kernel_tables<T>(f: impl FnOnce() -> T) -> T {
+    let user_cr3 = watos_mem::paging::get_cr3();
+    let kernel_pml4 = watos_process::get_kernel_pml4();
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+    }
+
+    let result = f();
+
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(user_cr3); }
+    }
+    result
+}
+
+/// The drive letter in a trash syscall's argument
+fn trash_drive(arg: u64) -> Option<char> {
+    let drive = (arg as u8 as char).to_ascii_uppercase();
+    drive.is_ascii_uppercase().then_some(drive)
+}
+
 /// Handle SYS_OPEN with path already copied to kernel buffer
 fn handle_sys_open(path: &[u8], mode_flags: u64) -> u64 {
     let path_str = match core::str::from_utf8(path) {
@@ -1483,15 +4271,15 @@ fn handle_sys_open(path: &[u8], mode_flags: u64) -> u64 {
         watos_arch::serial_write(b"\r\n");
     }
 
-    // Build FileMode based on flags
-    let mode = if mode_flags == 0 {
-        FileMode::READ
-    } else if mode_flags == 1 {
-        FileMode::WRITE
-    } else {
-        FileMode::READ_WRITE
+    let mode = match open_mode(mode_flags) {
+        Some(mode) => mode,
+        None => return u64::MAX,
     };
 
+    if mode.write {
+        watos_process::exec_cache::forget(path_str);
+    }
+
     // Open via VFS
     match watos_vfs::open(path_str, mode) {
         Ok(file) => {
@@ -1509,6 +4297,7 @@ fn handle_sys_open(path: &[u8], mode_flags: u64) -> u64 {
                 match e {
                     VfsError::NotFound => watos_arch::serial_write(b"NotFound"),
                     VfsError::IoError => watos_arch::serial_write(b"IoError"),
+                    VfsError::AlreadyExists => watos_arch::serial_write(b"AlreadyExists"),
                     _ => watos_arch::serial_write(b"Other"),
                 }
                 watos_arch::serial_write(b"\r\n");
@@ -1518,47 +4307,232 @@ fn handle_sys_open(path: &[u8], mode_flags: u64) -> u64 {
     }
 }
 
-fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, return_rsp: u64) -> u64 {
-    match num {
-        syscall::SYS_EXIT => {
-            // Check if there's a parent process to return to
-            if watos_process::has_parent_context() {
-                // CRITICAL: Switch to kernel page table BEFORE freeing child process
-                // The child's page table will be deallocated when we free the process,
-                // so we must not be using it (CR3) at that point!
-                let kernel_pml4 = watos_process::get_kernel_pml4();
-                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+/// The dos16 emulator, which runs DOS programs: its argv is the program's
+/// path followed by the program's arguments
+const DOS_RUNNER: &str = "C:/apps/system/dos16";
 
-                // Now safe to free the current child process
-                watos_process::free_current_process();
+/// The Windows program runner, which takes the same argv as `DOS_RUNNER`
+const WIN_RUNNER: &str = "C:/apps/system/winrun";
 
-                // Resume parent (this will switch to parent's page table)
-                watos_process::resume_parent(); // Never returns
-            }
-            // No parent - top-level process exiting, halt
-            loop { watos_arch::halt(); }
-        }
+// winrun switches to 32-bit code with the selectors user space is told of
+const _: () = {
+    use watos_arch::gdt::selectors;
+    assert!(watos_syscall::segments::USER_CODE == selectors::USER_CODE);
+    assert!(watos_syscall::segments::USER_CODE32 == selectors::USER_CODE32);
+    assert!(watos_syscall::segments::USER_DATA == selectors::USER_DATA);
+};
 
-        syscall::SYS_WRITE => {
-            // arg1 = fd (0=serial only, 1=stdout/console, 2=stderr/console)
-            // arg2 = pointer to string
-            // arg3 = length
-            let fd = arg1;
-            let ptr = arg2 as *const u8;
-            let len = arg3 as usize;
+/// Largest .COM image: a segment less the PSP
+const DOS_COM_MAX: usize = 0xFF00;
 
-            unsafe {
-                let slice = core::slice::from_raw_parts(ptr, len);
+/// Is `data`, loaded from `path`, a DOS program rather than a native one?
+///
+/// EXE files start with an MZ header. COM files are raw code with no header,
+/// so they are known by their name and size.
+fn is_dos_program(path: &str, data: &[u8]) -> bool {
+    if data.starts_with(b"\x7fELF") {
+        return false;
+    }
+    let com = path.rsplit_once('.').is_some_and(|(_, ext)| ext.eq_ignore_ascii_case("com"));
+    data.starts_with(b"MZ") || data.starts_with(b"ZM") || (com && !data.is_empty() && data.len() <= DOS_COM_MAX)
+}
+
+/// The runner for a foreign program: Windows programs are also MZ files,
+/// told apart from DOS ones by their PE header
+fn runner_for(path: &str, data: &[u8]) -> Option<&'static str> {
+    if watos_pe::is_pe(data) {
+        Some(WIN_RUNNER)
+    } else if is_dos_program(path, data) {
+        Some(DOS_RUNNER)
+    } else {
+        None
+    }
+}
+
+/// Load argv[0] as a new child process with `argv`, ready to run
+/// (SYS_EXEC, SYS_EXECV, SYS_SPAWN)
+///
+/// The program is looked up as given, then in C:/apps/system. DOS and
+/// Windows programs are run by `DOS_RUNNER` and `WIN_RUNNER` under their
+/// own name. Returns the child's pid, or Err(1) if it failed to load and
+/// Err(2) if it (or the runner it needs) wasn't found.
+fn spawn_argv(argv: &[&str]) -> Result<u32, u64> {
+    let program_str = argv[0];
+    let program_name = program_str.as_bytes();
+
+    // Build full path: C:/apps/system/<name>
+    let mut full_path_buf = [0u8; 128];
+    let prefix = b"C:/apps/system/";
+    let mut pos = 0;
+    for &b in prefix {
+        if pos < full_path_buf.len() {
+            full_path_buf[pos] = b;
+            pos += 1;
+        }
+    }
+    for &b in program_name {
+        if pos < full_path_buf.len() {
+            full_path_buf[pos] = b;
+            pos += 1;
+        }
+    }
+    let full_path_str = core::str::from_utf8(&full_path_buf[..pos]).unwrap_or("");
+
+    // Try multiple paths to find the executable
+    let paths = [
+        program_str,      // Try as-is (e.g., "/apps/system/shell")
+        full_path_str,    // Try C:/apps/system/<name>
+    ];
+
+    // Switch to kernel page table for disk access
+    let user_cr3 = watos_mem::paging::get_cr3();
+    let kernel_pml4 = watos_process::get_kernel_pml4();
+
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+    }
+
+    let mut app_data: Option<(&str, watos_process::Executable)> = None;
+
+    for path in &paths {
+        if path.is_empty() {
+            continue;
+        }
+
+        unsafe {
+            watos_arch::serial_write(b"[KERNEL] Trying to load: ");
+            watos_arch::serial_write(path.as_bytes());
+            watos_arch::serial_write(b"\r\n");
+        }
+
+        if let Some(file_contents) = read_executable(path) {
+            app_data = Some((path, file_contents));
+            break;
+        }
+    }
+
+    // Start a runner in place of a DOS or Windows program, passing it the
+    // path the program was found at ahead of the program's arguments
+    let mut runner_argv = alloc::vec::Vec::new();
+    let (app_data, argv) = match app_data {
+        Some((path, data)) => match runner_for(path, &data.head) {
+            Some(runner_path) => {
+                runner_argv.push(runner_path);
+                runner_argv.push(path);
+                runner_argv.extend_from_slice(&argv[1..]);
+                let runner = read_executable(runner_path);
+                if runner.is_none() {
+                    unsafe {
+                        watos_arch::serial_write(b"[KERNEL] Runner not installed: ");
+                        watos_arch::serial_write(runner_path.as_bytes());
+                        watos_arch::serial_write(b"\r\n");
+                    }
+                }
+                (runner, &runner_argv[..])
+            }
+            None => (Some(data), argv),
+        },
+        None => (None, argv),
+    };
+
+    let result = if let Some(data) = app_data {
+        // Dynamically linked programs name their loader in PT_INTERP;
+        // it is mapped alongside the program and started instead
+        let interp = watos_process::elf::Elf64::parse(&data.head)
+            .ok()
+            .and_then(|elf| elf.interp(&data.head));
+        let interp_data = interp.map(|path| (path, read_executable(path)));
+
+        let spawn_result = match interp_data {
+            None => watos_process::spawn(program_str, &data, None, argv),
+            Some((_, Some(ld))) => watos_process::spawn(program_str, &data, Some(&ld), argv),
+            Some((path, None)) => {
+                unsafe {
+                    watos_arch::serial_write(b"[KERNEL] Interpreter not found: ");
+                    watos_arch::serial_write(path.as_bytes());
+                    watos_arch::serial_write(b"\r\n");
+                }
+                Err("Interpreter not found")
+            }
+        };
+
+        spawn_result.map_err(|e| {
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] exec failed: ");
+                watos_arch::serial_write(e.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+            1 // Error
+        })
+    } else {
+        unsafe {
+            watos_arch::serial_write(b"[KERNEL] App not found: ");
+            watos_arch::serial_write(program_name);
+            watos_arch::serial_write(b"\r\n");
+        }
+        Err(2) // Not found
+    };
+
+    // Restore user page table
+    if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+        unsafe { watos_mem::paging::load_cr3(user_cr3); }
+    }
+
+    if let Ok(pid) = result {
+        fd_inherit(pid);
+    }
+    result
+}
+
+/// Run argv[0] as a child of the caller and wait for it to exit
+/// (SYS_EXEC, SYS_EXECV): the caller resumes with 0 once it has. Returns
+/// at once with spawn_argv's error if it can't start.
+fn exec_argv(argv: &[&str], return_rip: u64, return_rsp: u64) -> u64 {
+    match spawn_argv(argv) {
+        Ok(pid) => watos_process::run_child(pid, syscall_context(return_rip, return_rsp, 0)),
+        Err(code) => code,
+    }
+}
+
+fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, return_rsp: u64) -> u64 {
+    match num {
+        syscall::SYS_EXIT => {
+            // CRITICAL: Switch to kernel page table BEFORE freeing the process
+            // Its page table will be deallocated when we free the process,
+            // so we must not be using it (CR3) at that point!
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+
+            // Keep the exit code for the parent's SYS_WAIT, then free the process
+            watos_process::record_exit(arg1 as i32);
+            watos_process::free_current_process();
+            klog_flush();
+
+            // Run whatever is next (this switches to its page table)
+            watos_process::sched::schedule(); // Never returns
+        }
+
+        syscall::SYS_WRITE => {
+            // arg1 = fd (0=serial only, 1=stdout/console, 2=stderr/console)
+            // arg2 = pointer to string
+            // arg3 = length
+            let fd = arg1;
+            let len = arg3 as usize;
+            let Ok(data) = watos_mem::read_user_bytes(arg2, len) else { return u64::MAX };
 
+            // Rendered on the kernel page table, where the framebuffer is
+            // mapped for the kernel only
+            on_kernel_tables(|| unsafe {
                 // Always write to serial for debugging
-                watos_arch::serial_write(slice);
+                watos_arch::serial_write(&data);
 
                 // If fd is stdout (1) or stderr (2), write to active VT
                 // The kernel VT driver will render it to the framebuffer
                 if fd == 1 || fd == 2 {
-                    watos_vt::vt_write_active(slice);
+                    watos_vt::vt_write_active(&data);
+                    console_bell();
                 }
-            }
+            });
             len as u64
         }
 
@@ -1579,10 +4553,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 let mut temp = [0u8; 256];
                 let read_size = buf_size.min(temp.len());
                 let count = console_read(&mut temp[..read_size]);
-                if count > 0 {
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(temp.as_ptr(), buf_ptr, count);
-                    }
+                if count > 0 && watos_mem::copy_to_user(&temp[..count], buf_ptr as u64).is_err() {
+                    return u64::MAX;
                 }
                 count as u64
             } else if fd >= 3 {
@@ -1600,8 +4572,7 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                     let buf_ref = &*addr_of!(SYSCALL_READ_BUF);
                     let max_chunk = buf_size.min(buf_ref.len());
                     let buf = &mut *addr_of_mut!(SYSCALL_READ_BUF);
-                    let result = fd_read(fd, &mut buf[..max_chunk]);
-                    result
+                    fd_read(fd, &mut buf[..max_chunk]).unwrap_or(0)
                 };
 
                 // Restore user page table
@@ -1611,9 +4582,10 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
                 // Copy data to user buffer (now in user page table)
                 if result > 0 {
-                    unsafe {
-                        let copy_len = (result as usize).min(buf_size);
-                        core::ptr::copy_nonoverlapping(SYSCALL_READ_BUF.as_ptr(), buf_ptr, copy_len);
+                    let copy_len = result.min(buf_size);
+                    let buf = unsafe { &*core::ptr::addr_of!(SYSCALL_READ_BUF) };
+                    if watos_mem::copy_to_user(&buf[..copy_len], buf_ptr as u64).is_err() {
+                        return u64::MAX;
                     }
                     result as u64
                 } else {
@@ -1638,6 +4610,216 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             }
         }
 
+        syscall::SYS_DUP => {
+            // arg1 = fd to duplicate
+            // Returns the new fd, u64::MAX on error
+            let fd = fd_dup(arg1 as i64);
+            if fd < 0 { u64::MAX } else { fd as u64 }
+        }
+
+        syscall::SYS_DUP2 => {
+            // arg1 = old fd, arg2 = new fd
+            // Returns the new fd, u64::MAX on error
+            let fd = fd_dup2(arg1 as i64, arg2 as i64);
+            if fd < 0 { u64::MAX } else { fd as u64 }
+        }
+
+        syscall::SYS_ISATTY => {
+            // arg1 = fd
+            // Returns 1 if the fd is a terminal, 0 otherwise
+            fd_isatty(arg1 as i64) as u64
+        }
+
+        syscall::SYS_PIPE => {
+            // arg1 = pointer to i32[2], receives [read_fd, write_fd]
+            // arg2 = flags (O_CLOEXEC)
+            // Returns 0 on success, u64::MAX on error
+            let fds_ptr = arg1 as *mut i32;
+            if fds_ptr.is_null() {
+                return u64::MAX;
+            }
+            match fd_pipe(arg2 & syscall::O_CLOEXEC != 0) {
+                Some((read_fd, write_fd)) => {
+                    match watos_mem::write_user(fds_ptr as u64, [read_fd as i32, write_fd as i32]) {
+                        Ok(()) => 0,
+                        Err(_) => u64::MAX,
+                    }
+                }
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_OPENPTY => {
+            // arg1 = pointer to i32[2], receives [master_fd, slave_fd]
+            // Returns 0 on success, u64::MAX on error
+            let fds_ptr = arg1 as *mut i32;
+            if fds_ptr.is_null() {
+                return u64::MAX;
+            }
+            match fd_openpty() {
+                Some((master_fd, slave_fd)) => {
+                    match watos_mem::write_user(fds_ptr as u64, [master_fd as i32, slave_fd as i32]) {
+                        Ok(()) => 0,
+                        Err(_) => u64::MAX,
+                    }
+                }
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_CLIPBOARD_SET => {
+            // arg1 = type pointer, arg2 = type length, arg3 = data pointer, r10 = data length
+            // Returns 0 on success, u64::MAX on a bad type or oversized data
+            let type_ptr = arg1 as *const u8;
+            let type_len = arg2 as usize;
+            let data_ptr = arg3 as *const u8;
+            let data_len = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
+            if type_ptr.is_null() || type_len > watos_clipboard::MAX_TYPE_LEN
+                || (data_ptr.is_null() && data_len != 0) || data_len > watos_clipboard::MAX_CLIPBOARD_SIZE {
+                return u64::MAX;
+            }
+            let Ok(kind) = watos_mem::read_user_bytes(type_ptr as u64, type_len) else { return u64::MAX };
+            let data = if data_len == 0 {
+                alloc::vec::Vec::new()
+            } else {
+                let Ok(data) = watos_mem::read_user_bytes(data_ptr as u64, data_len) else { return u64::MAX };
+                data
+            };
+            match core::str::from_utf8(&kind) {
+                Ok(kind) if CLIPBOARD.lock().set(kind, &data).is_ok() => 0,
+                _ => u64::MAX,
+            }
+        }
+
+        syscall::SYS_CLIPBOARD_GET => {
+            // arg1 = wanted type pointer, arg2 = type length, arg3 = buffer pointer, r10 = buffer length
+            // Copies as much as fits and returns the full length, or u64::MAX if
+            // the clipboard is empty or holds another type
+            let type_ptr = arg1 as *const u8;
+            let type_len = arg2 as usize;
+            let buf_ptr = arg3 as *mut u8;
+            let buf_len = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
+            if (type_ptr.is_null() && type_len != 0) || type_len > watos_clipboard::MAX_TYPE_LEN
+                || (buf_ptr.is_null() && buf_len != 0) {
+                return u64::MAX;
+            }
+            let kind = if type_len == 0 {
+                alloc::vec::Vec::new()
+            } else {
+                let Ok(kind) = watos_mem::read_user_bytes(type_ptr as u64, type_len) else { return u64::MAX };
+                kind
+            };
+            let Ok(kind) = core::str::from_utf8(&kind) else { return u64::MAX };
+            let clipboard = CLIPBOARD.lock();
+            match clipboard.get(kind) {
+                Some(data) => {
+                    let copy_len = core::cmp::min(data.len(), buf_len);
+                    if copy_len > 0 && watos_mem::copy_to_user(&data[..copy_len], buf_ptr as u64).is_err() {
+                        return u64::MAX;
+                    }
+                    data.len() as u64
+                }
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_AUDIO_OPEN => {
+            // Returns a stream handle in the device's current format, or
+            // u64::MAX if there is no sound card
+            use watos_driver_traits::audio::AudioDevice;
+
+            let Some(table) = watos_process::current_handle_table() else { return u64::MAX };
+            let opened = watos_driver_traits::audio::with_output(|dev| {
+                let config = dev.config();
+                let format = match config.format {
+                    watos_driver_traits::audio::SampleFormat::U8 => syscall::AUDIO_FORMAT_U8,
+                    watos_driver_traits::audio::SampleFormat::S16Le => syscall::AUDIO_FORMAT_S16LE,
+                    watos_driver_traits::audio::SampleFormat::S16Be => syscall::AUDIO_FORMAT_S16BE,
+                    watos_driver_traits::audio::SampleFormat::F32 => syscall::AUDIO_FORMAT_F32,
+                };
+                // Already playing is fine when another stream started it
+                let _ = AudioDevice::start(dev);
+                watos_process::AudioObject {
+                    sample_rate: config.sample_rate,
+                    channels: config.channels,
+                    format,
+                }
+            });
+            match opened {
+                Some(stream) => table.open_audio(stream) as u64,
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_AUDIO_SET_CONFIG => {
+            // arg1 = handle, arg2 = sample rate, arg3 = channels | format << 8
+            // Returns 0, or u64::MAX if the device can't play that format
+            let Some(table) = watos_process::current_handle_table() else { return u64::MAX };
+            let Some(stream) = table.get_audio_mut(arg1 as u32) else { return u64::MAX };
+            let format = (arg3 >> 8) as u8;
+            if arg2 > u32::MAX as u64 || arg3 >> 16 != 0 || audio_format(format).is_none() {
+                return u64::MAX;
+            }
+            let wanted = watos_process::AudioObject {
+                sample_rate: arg2 as u32,
+                channels: arg3 as u8,
+                format,
+            };
+            match watos_driver_traits::audio::with_output(|dev| audio_apply(dev, &wanted)) {
+                Some(true) => {
+                    *stream = wanted;
+                    0
+                }
+                _ => u64::MAX,
+            }
+        }
+
+        syscall::SYS_AUDIO_WRITE => {
+            // arg1 = handle, arg2 = buffer pointer, arg3 = length
+            // Returns the bytes taken, which is short when the device buffer fills
+            let Some(table) = watos_process::current_handle_table() else { return u64::MAX };
+            let Some(stream) = table.get_audio_mut(arg1 as u32) else { return u64::MAX };
+            let buf_ptr = arg2 as *const u8;
+            let len = arg3 as usize;
+            if buf_ptr.is_null() && len != 0 {
+                return u64::MAX;
+            }
+            let samples = if len == 0 {
+                alloc::vec::Vec::new()
+            } else {
+                let Ok(samples) = watos_mem::read_user_bytes(buf_ptr as u64, len) else { return u64::MAX };
+                samples
+            };
+            let written = watos_driver_traits::audio::with_output(|dev| {
+                if !audio_apply(dev, stream) {
+                    return None;
+                }
+                dev.write(&samples).ok()
+            });
+            match written.flatten() {
+                Some(n) => n as u64,
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_AUDIO_AVAILABLE => {
+            // arg1 = handle; returns the bytes SYS_AUDIO_WRITE would take now
+            let Some(table) = watos_process::current_handle_table() else { return u64::MAX };
+            if table.get_audio_mut(arg1 as u32).is_none() {
+                return u64::MAX;
+            }
+            watos_driver_traits::audio::with_output(|dev| dev.available() as u64).unwrap_or(u64::MAX)
+        }
+
+        syscall::SYS_AUDIO_CLOSE => {
+            // arg1 = handle. Queued samples keep playing.
+            let Some(table) = watos_process::current_handle_table() else { return u64::MAX };
+            if table.get_audio_mut(arg1 as u32).is_none() || !table.close(arg1 as u32) {
+                return u64::MAX;
+            }
+            0
+        }
+
         syscall::SYS_CONSOLE_OUT => {
             // Return stdout file descriptor
             1
@@ -1650,37 +4832,39 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
         syscall::SYS_GETKEY => {
             // Returns ASCII key or 0 if no key
+            // A program hosted on a pty reads its keys from the pty
+            if let Some(pty) = stdin_pty() {
+                let mut key = [0u8; 1];
+                return match pty.lock().read(&mut key) {
+                    Ok(1) => key[0] as u64,
+                    _ => 0,
+                };
+            }
+            // Keys go to the console's foreground group; others see none
+            if !watos_process::owns_console() {
+                return 0;
+            }
             watos_arch::idt::get_scancode().map(|scancode| {
                 scancode_to_ascii(scancode) as u64
             }).unwrap_or(0)
         }
 
         syscall::SYS_MALLOC => {
-            // arg1 = size, returns pointer
-            use alloc::alloc::{alloc, Layout};
-            let size = arg1 as usize;
-            if size == 0 {
+            // arg1 = size, returns pointer (16-byte aligned) or 0
+            // The memory comes from the process's own heap, never the kernel's
+            if arg1 == 0 {
                 return 0;
             }
-            unsafe {
-                let layout = Layout::from_size_align(size, 8).unwrap();
-                alloc(layout) as u64
-            }
+            watos_process::user_malloc(arg1)
         }
 
         syscall::SYS_FREE => {
-            // arg1 = pointer, arg2 = size
-            use alloc::alloc::{dealloc, Layout};
-            let ptr = arg1 as *mut u8;
-            let size = arg2 as usize;
-            if ptr.is_null() || size == 0 {
+            // arg1 = pointer; the heap knows the block's size, so arg2 (the
+            // size, as callers pass it) is not needed
+            if arg1 == 0 {
                 return 0;
             }
-            unsafe {
-                let layout = Layout::from_size_align(size, 8).unwrap();
-                dealloc(ptr, layout);
-            }
-            0
+            if watos_process::user_free(arg1) { 0 } else { u64::MAX }
         }
 
         syscall::SYS_FB_INFO => {
@@ -1697,6 +4881,19 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             }
         }
 
+        syscall::SYS_FB_MAP => {
+            // Maps the framebuffer into the caller, for root and the video
+            // group only. Returns its address, or u64::MAX if not allowed
+            let cred = watos_users::get_credential_info(
+                watos_process::get_current_uid(),
+                watos_process::get_current_gid(),
+            );
+            if !cred.is_root() && !cred.in_group(watos_users::GID_VIDEO) {
+                return u64::MAX;
+            }
+            watos_process::map_framebuffer().unwrap_or(u64::MAX)
+        }
+
         syscall::SYS_FB_DIMENSIONS => {
             // Returns width/height/pitch packed
             // Format: high 32 bits = width | mid 16 bits = height | low 16 bits = pitch/4
@@ -1710,8 +4907,114 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             }
         }
 
+        syscall::SYS_SCREENSHOT => {
+            // arg1 = path pointer, arg2 = path length
+            // Writes the framebuffer as PNG (.png) or BMP; returns 0 or u64::MAX
+            let path_ptr = arg1 as *const u8;
+            let path_len = (arg2 as usize).min(255);
+            if path_ptr.is_null() || path_len == 0 {
+                return u64::MAX;
+            }
+
+            let Some(path) = user_string_lossy(path_ptr as u64, path_len) else { return u64::MAX };
+
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = save_screenshot(&path);
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+            result
+        }
+
+        syscall::SYS_SETFONT => {
+            // arg1 = PSF2 path pointer, arg2 = path length (0 = built-in font),
+            // arg3 = integer scale. Returns (cols << 16) | rows or u64::MAX
+            let path_ptr = arg1 as *const u8;
+            let path_len = (arg2 as usize).min(255);
+            let path = if path_ptr.is_null() || path_len == 0 {
+                alloc::string::String::new()
+            } else {
+                let Some(path) = user_string_lossy(path_ptr as u64, path_len) else { return u64::MAX };
+                path
+            };
+            let scale = (arg3 as u32).clamp(1, 8);
+
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = set_console_font(&path, scale);
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+            result
+        }
+
+        syscall::SYS_FB_SET_MODE => {
+            // arg1 = width, arg2 = height: one of the GOP modes from boot.
+            // Returns the new dimensions packed like SYS_FB_DIMENSIONS or u64::MAX
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = set_display_mode(arg1 as u32, arg2 as u32);
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+            result
+        }
+
+        syscall::SYS_MOUSE_POLL => {
+            // Applies pending mouse motion to the cursor.
+            // Returns (buttons << 32) | (y << 16) | x
+            mouse_poll()
+        }
+
+        syscall::SYS_CURSOR_SHOW => {
+            // arg1 = 1 to show the cursor, 0 to hide it; arg2 = 1 to redraw it
+            // after drawing under it. Returns 0 or u64::MAX without a framebuffer
+            let ok = watos_driver_video::cursor_show(arg1 != 0);
+            if arg2 != 0 {
+                watos_driver_video::cursor_refresh();
+            }
+            if ok { 0 } else { u64::MAX }
+        }
+
+        syscall::SYS_CURSOR_SPRITE => {
+            // arg1 = pointer to 32x32 ARGB pixels, arg2 = pixel count,
+            // arg3 = hotspot (x << 16) | y. Returns 0 or u64::MAX
+            let ptr = arg1 as *const u32;
+            let count = arg2 as usize;
+            let size = watos_driver_video::cursor::CURSOR_SIZE;
+            if ptr.is_null() || count != size * size {
+                return u64::MAX;
+            }
+            let mut pixels = alloc::vec![0u32; count];
+            let Ok(()) = watos_mem::copy_from_user(ptr as u64, unsafe {
+                core::slice::from_raw_parts_mut(pixels.as_mut_ptr() as *mut u8, count * 4)
+            }) else { return u64::MAX };
+            let (hot_x, hot_y) = ((arg3 >> 16) as u32 & 0xFFFF, arg3 as u32 & 0xFFFF);
+            if watos_driver_video::cursor_set_sprite(&pixels, hot_x, hot_y) { 0 } else { u64::MAX }
+        }
+
         syscall::SYS_READ_SCANCODE => {
-            // Returns raw PS/2 scancode or 0 if no key
+            // Returns raw PS/2 scancode or 0 if no key (as SYS_GETKEY, only
+            // for the console's foreground group)
+            if !watos_process::owns_console() {
+                return 0;
+            }
             watos_arch::idt::get_scancode().map(|s| s as u64).unwrap_or(0)
         }
 
@@ -1719,6 +5022,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             // arg1 = pointer to full command line string
             // arg2 = length of command line
             // Returns: 0 on success, non-zero on error
+            // The command line is split at whitespace; SYS_EXECV takes an
+            // argv for arguments that hold spaces or quotes
             let cmdline_ptr = arg1 as *const u8;
             let cmdline_len = arg2 as usize;
 
@@ -1728,168 +5033,63 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
             // Copy cmdline from user memory while still in user page table
             let mut cmdline_buf = [0u8; 256];
-            let cmdline_copy = unsafe {
-                let user_cmdline = core::slice::from_raw_parts(cmdline_ptr, cmdline_len);
-                cmdline_buf[..cmdline_len].copy_from_slice(user_cmdline);
-                &cmdline_buf[..cmdline_len]
-            };
+            if watos_mem::copy_from_user(cmdline_ptr as u64, &mut cmdline_buf[..cmdline_len]).is_err() {
+                return u64::MAX;
+            }
+            let cmdline_copy = &cmdline_buf[..cmdline_len];
 
-            // Extract program name (first word before space)
-            let program_name = {
-                let space_pos = cmdline_copy.iter().position(|&c| c == b' ').unwrap_or(cmdline_len);
-                &cmdline_copy[..space_pos]
+            let cmdline_str = match core::str::from_utf8(cmdline_copy) {
+                Ok(s) => s,
+                Err(_) => return u64::MAX,
             };
+            let argv: alloc::vec::Vec<&str> = cmdline_str.split_whitespace().collect();
+            if argv.is_empty() {
+                return u64::MAX;
+            }
+            exec_argv(&argv, return_rip, return_rsp)
+        }
 
-            // Convert program name to string and build path
-            let program_str = core::str::from_utf8(program_name).unwrap_or("app");
+        syscall::SYS_EXECV => {
+            // arg1 = argv blocks pointer, arg2 = length (see watos_syscall::argv)
+            // Returns: as SYS_EXEC
+            let blocks_ptr = arg1 as *const u8;
+            let blocks_len = arg2 as usize;
 
-            // Build full path: C:/apps/system/<name>
-            let mut full_path_buf = [0u8; 128];
-            let prefix = b"C:/apps/system/";
-            let mut pos = 0;
-            for &b in prefix {
-                if pos < full_path_buf.len() {
-                    full_path_buf[pos] = b;
-                    pos += 1;
-                }
-            }
-            for &b in program_name {
-                if pos < full_path_buf.len() {
-                    full_path_buf[pos] = b;
-                    pos += 1;
-                }
+            if blocks_ptr.is_null() || blocks_len == 0 || blocks_len > watos_syscall::argv::MAX_BYTES {
+                return u64::MAX;
             }
-            let full_path_str = core::str::from_utf8(&full_path_buf[..pos]).unwrap_or("");
 
-            // Try multiple paths to find the executable
-            let paths = [
-                program_str,      // Try as-is (e.g., "/apps/system/shell")
-                full_path_str,    // Try C:/apps/system/<name>
-            ];
+            // Copy the blocks from user memory while still in user page table
+            let Ok(blocks) = watos_mem::read_user_bytes(blocks_ptr as u64, blocks_len) else { return u64::MAX };
+            if !watos_syscall::argv::is_valid(&blocks) {
+                return u64::MAX;
+            }
+            let argv: alloc::vec::Vec<&str> = watos_syscall::argv::decode(&blocks).collect();
+            if argv.first().map_or(true, |name| name.is_empty()) {
+                return u64::MAX;
+            }
+            exec_argv(&argv, return_rip, return_rsp)
+        }
 
-            // Switch to kernel page table for disk access
-            let user_cr3 = watos_mem::paging::get_cr3();
-            let kernel_pml4 = watos_process::get_kernel_pml4();
+        syscall::SYS_SPAWN => {
+            // arg1 = argv blocks pointer, arg2 = length (see watos_syscall::argv)
+            // Starts the program as a child that runs alongside the caller
+            // Returns the child's pid, or u64::MAX if it can't be started
+            let blocks_ptr = arg1 as *const u8;
+            let blocks_len = arg2 as usize;
 
-            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
-                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            if blocks_ptr.is_null() || blocks_len == 0 || blocks_len > watos_syscall::argv::MAX_BYTES {
+                return u64::MAX;
             }
-
-            // Now we can safely use heap allocation
-            extern crate alloc;
-            use alloc::vec::Vec;
-
-            let mut app_data: Option<Vec<u8>> = None;
-
-            for path in &paths {
-                if path.is_empty() {
-                    continue;
-                }
-
-                unsafe {
-                    watos_arch::serial_write(b"[KERNEL] Trying to load: ");
-                    watos_arch::serial_write(path.as_bytes());
-                    watos_arch::serial_write(b"\r\n");
-                }
-
-                // Try to open and read the file from VFS
-                let fd = handle_sys_open(path.as_bytes(), 0); // 0 = read mode
-                if fd != u64::MAX {
-                    unsafe {
-                        watos_arch::serial_write(b"[KERNEL] File opened, fd=");
-                        watos_arch::serial_hex(fd);
-                        watos_arch::serial_write(b"\r\n");
-                    }
-
-                    // Read file using Vec
-                    let mut file_contents = Vec::new();
-                    const CHUNK_SIZE: usize = 4096;
-                    let mut read_buf = [0u8; CHUNK_SIZE];
-
-                    loop {
-                        let chunk_read = fd_read(fd as i64, &mut read_buf);
-                        if chunk_read <= 0 {
-                            break;
-                        }
-
-                        file_contents.extend_from_slice(&read_buf[..chunk_read as usize]);
-
-                        // Safety limit: max 1MB per executable
-                        if file_contents.len() >= 1024 * 1024 {
-                            unsafe {
-                                watos_arch::serial_write(b"[KERNEL] File too large\r\n");
-                            }
-                            break;
-                        }
-                    }
-
-                    fd_close(fd as i64);
-
-                    if !file_contents.is_empty() {
-                        unsafe {
-                            watos_arch::serial_write(b"[KERNEL] Loaded ");
-                            watos_arch::serial_hex(file_contents.len() as u64);
-                            watos_arch::serial_write(b" bytes from ");
-                            watos_arch::serial_write(path.as_bytes());
-                            watos_arch::serial_write(b"\r\n");
-                        }
-                        app_data = Some(file_contents);
-                        break;
-                    }
-                }
+            let Ok(blocks) = watos_mem::read_user_bytes(blocks_ptr as u64, blocks_len) else { return u64::MAX };
+            if !watos_syscall::argv::is_valid(&blocks) {
+                return u64::MAX;
             }
-
-            let result = if let Some(data) = app_data {
-                // Save parent context with all register state
-                let regs = unsafe { &SAVED_SYSCALL_REGS };
-                watos_process::save_parent_context_with_frame(
-                    return_rip,
-                    return_rsp,
-                    regs.rbx,
-                    regs.rcx,
-                    regs.rdx,
-                    regs.rsi,
-                    regs.rdi,
-                    regs.rbp,
-                    regs.r8,
-                    regs.r9,
-                    regs.r10,
-                    regs.r11,
-                    regs.r12,
-                    regs.r13,
-                    regs.r14,
-                    regs.r15,
-                );
-
-                // Execute the app with the full command line as args
-                let cmdline_str = core::str::from_utf8(cmdline_copy).unwrap_or("");
-
-                match watos_process::exec(program_str, &data, cmdline_str) {
-                    Ok(_pid) => 0, // Success
-                    Err(e) => {
-                        unsafe {
-                            watos_arch::serial_write(b"[KERNEL] exec failed: ");
-                            watos_arch::serial_write(e.as_bytes());
-                            watos_arch::serial_write(b"\r\n");
-                        }
-                        1 // Error
-                    }
-                }
-            } else {
-                unsafe {
-                    watos_arch::serial_write(b"[KERNEL] App not found: ");
-                    watos_arch::serial_write(program_name);
-                    watos_arch::serial_write(b"\r\n");
-                }
-                2 // Not found
-            };
-
-            // Restore user page table
-            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
-                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            let argv: alloc::vec::Vec<&str> = watos_syscall::argv::decode(&blocks).collect();
+            if argv.first().map_or(true, |name| name.is_empty()) {
+                return u64::MAX;
             }
-
-            result
+            spawn_argv(&argv).map_or(u64::MAX, |pid| pid as u64)
         }
 
         syscall::SYS_GETARGS => {
@@ -1912,8 +5112,11 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 0;
             }
 
-            let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr, buf_size) };
-            let copied = watos_process::get_current_args(buf);
+            let mut buf = alloc::vec![0u8; buf_size.min(watos_syscall::argv::MAX_BYTES)];
+            let copied = watos_process::get_current_args(&mut buf);
+            if watos_mem::copy_to_user(&buf[..copied], buf_ptr as u64).is_err() {
+                return 0;
+            }
             unsafe {
                 watos_arch::serial_write(b"[KERNEL] SYS_GETARGS: copied ");
                 watos_arch::serial_hex(copied as u64);
@@ -1922,11 +5125,244 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             copied as u64
         }
 
+        syscall::SYS_GETARGV => {
+            // arg1 = buffer pointer, arg2 = buffer size
+            // Returns the length of the argv blocks; copied only if they fit
+            let buf_ptr = arg1 as *mut u8;
+            let buf_size = arg2 as usize;
+            if buf_ptr.is_null() {
+                return watos_process::get_current_argv(&mut []) as u64;
+            }
+            let mut buf = alloc::vec![0u8; buf_size.min(watos_syscall::argv::MAX_BYTES)];
+            let len = watos_process::get_current_argv(&mut buf);
+            if len <= buf.len() && watos_mem::copy_to_user(&buf[..len], buf_ptr as u64).is_err() {
+                return u64::MAX;
+            }
+            len as u64
+        }
+
+        syscall::SYS_IDLE => {
+            // Give the CPU to another process, or halt until the next
+            // interrupt if none can run; for polling loops with nothing to do
+            block_cache_writeback();
+            balloon_service();
+            on_kernel_tables(watos_network::socket::poll);
+            if watos_process::sched::others_runnable() {
+                watos_process::sched::block(syscall_context(return_rip, return_rsp, 0), watos_process::ProcessState::Ready);
+            }
+            watos_process::idle();
+            0
+        }
+
+        syscall::SYS_SLEEP => {
+            // arg1 = milliseconds, rounded up to the next timer tick
+            // Other processes run meanwhile
+            block_cache_writeback();
+            balloon_service();
+            let deadline = watos_arch::clock::now_ms().saturating_add(arg1);
+            watos_process::sched::block(
+                syscall_context(return_rip, return_rsp, 0),
+                watos_process::ProcessState::Sleeping(deadline),
+            )
+        }
+
+        syscall::SYS_GAMEPAD_LIST => {
+            // Returns a mask with bit n set for each connected pad n
+            watos_driver_gamepad::GAMEPADS.with(|g| g.connected()) as u64
+        }
+
+        syscall::SYS_GAMEPAD_STATE => {
+            // arg1 = pad, arg2 = GamepadInfo pointer (see watos_syscall::gamepad)
+            // Returns 0, or u64::MAX if the pad isn't connected
+            let info_ptr = arg2 as *mut watos_driver_gamepad::GamepadState;
+            if info_ptr.is_null() || arg1 >= watos_driver_gamepad::MAX_PADS as u64 {
+                return u64::MAX;
+            }
+            watos_driver_gamepad::poll();
+            match watos_driver_gamepad::GAMEPADS.with(|g| g.state(arg1 as u8)) {
+                Some(state) => match watos_mem::write_user(info_ptr as u64, state) {
+                    Ok(()) => 0,
+                    Err(_) => u64::MAX,
+                },
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_BEEP => {
+            // arg1 = frequency in Hz (0 = rest), arg2 = milliseconds
+            // Returns once the tone ends; u64::MAX if the PIT can't make it
+            if arg1 > u32::MAX as u64 {
+                return u64::MAX;
+            }
+            if beep(arg1 as u32, arg2) { 0 } else { u64::MAX }
+        }
+
         syscall::SYS_GETPID => {
             // Returns current process ID
             watos_process::current_pid().unwrap_or(0) as u64
         }
 
+        syscall::SYS_SETPGID => {
+            // arg1 = pid (0 = current), arg2 = pgid (0 = same as pid)
+            // Returns 0 on success, u64::MAX on error
+            let pid = match arg1 as u32 {
+                0 => watos_process::current_pid().unwrap_or(0),
+                p => p,
+            };
+            if watos_process::set_pgid(pid, arg2 as u32) {
+                0
+            } else {
+                u64::MAX
+            }
+        }
+
+        syscall::SYS_GETPGID => {
+            // arg1 = pid (0 = current)
+            // Returns process group ID, u64::MAX if no such process
+            let pid = match arg1 as u32 {
+                0 => watos_process::current_pid().unwrap_or(0),
+                p => p,
+            };
+            watos_process::get_pgid(pid).map(|g| g as u64).unwrap_or(u64::MAX)
+        }
+
+        syscall::SYS_GETRUSAGE => {
+            // arg1 = pid (0 = current), arg2 = pointer to u64[2] buffer
+            // Fills [user_ms, system_ms]; returns 0, or u64::MAX if no such process
+            let pid = match arg1 as u32 {
+                0 => watos_process::current_pid().unwrap_or(0),
+                p => p,
+            };
+            let buf_ptr = arg2 as *mut u64;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            match watos_process::cpu_usage(pid) {
+                Some((user_ms, system_ms)) => match watos_mem::write_user(buf_ptr as u64, [user_ms, system_ms]) {
+                    Ok(()) => 0,
+                    Err(_) => u64::MAX,
+                },
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_GETMEMUSAGE => {
+            // arg1 = pid (0 = current), arg2 = pointer to u64[6] buffer
+            // Fills [vm_size_kb, rss_kb, code_kb, heap_kb, stack_kb, malloc_bytes]
+            // Returns 0, or u64::MAX if no such process
+            let pid = match arg1 as u32 {
+                0 => watos_process::current_pid().unwrap_or(0),
+                p => p,
+            };
+            let buf_ptr = arg2 as *mut u64;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            match watos_process::process_summary(pid) {
+                Some(p) => {
+                    let values = [p.vm_size_kb, p.memory_kb, p.code_kb, p.heap_kb, p.stack_kb, p.malloc_bytes];
+                    match watos_mem::write_user(buf_ptr as u64, values) {
+                        Ok(()) => 0,
+                        Err(_) => u64::MAX,
+                    }
+                }
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_SETRLIMIT => {
+            // arg1 = resource, arg2 = new limit (u64::MAX = unlimited)
+            match arg1 {
+                syscall::RLIMIT_CORE if watos_process::set_core_limit(arg2) => 0,
+                _ => u64::MAX,
+            }
+        }
+
+        syscall::SYS_GETRLIMIT => {
+            // arg1 = resource, arg2 = pointer to u64 for the limit
+            let buf_ptr = arg2 as *mut u64;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            match arg1 {
+                syscall::RLIMIT_CORE => match watos_mem::write_user(buf_ptr as u64, watos_process::core_limit()) {
+                    Ok(()) => 0,
+                    Err(_) => u64::MAX,
+                },
+                _ => u64::MAX,
+            }
+        }
+
+        syscall::SYS_SETPRIORITY => {
+            // arg1 = pid (0 = self), arg2 = nice value as i32 (-20..19, clamped)
+            // Returns 0, or u64::MAX if no such process or not permitted
+            let pid = if arg1 == 0 { watos_process::current_pid().unwrap_or(0) } else { arg1 as u32 };
+            if watos_process::set_nice(pid, arg2 as i32) { 0 } else { u64::MAX }
+        }
+
+        syscall::SYS_GETPRIORITY => {
+            // arg1 = pid (0 = self)
+            // Returns 20 - nice (1..40, higher is more favoured), or u64::MAX
+            let pid = if arg1 == 0 { watos_process::current_pid().unwrap_or(0) } else { arg1 as u32 };
+            match watos_process::get_nice(pid) {
+                Some(nice) => (20 - nice) as u64,
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_KILL => {
+            // arg1 = pid, or -pgid for a process group (0 = the caller's
+            // group), arg2 = signal (see watos_process::sched for what each does)
+            // Returns 0 if any process was signalled, u64::MAX if none could be
+            let target = match arg1 as i64 {
+                0 => -(watos_process::current_pid().and_then(watos_process::get_pgid).unwrap_or(0) as i64),
+                target => target,
+            };
+            if arg2 > 64 {
+                return u64::MAX;
+            }
+            let uid = watos_process::get_current_uid();
+            let context = syscall_context(return_rip, return_rsp, 0);
+            if watos_process::sched::send(target, arg2 as u32, uid, Some(&context)) { 0 } else { u64::MAX }
+        }
+
+        syscall::SYS_TCSETPGRP => {
+            // arg1 = process group to move to the foreground
+            // A pty has no foreground group, so a program hosted on one
+            // leaves the console's alone
+            if stdin_pty().is_none() {
+                watos_process::set_foreground_pgrp(arg1 as u32);
+            }
+            0
+        }
+
+        syscall::SYS_TCGETPGRP => {
+            // Returns the console's foreground process group
+            watos_process::foreground_pgrp() as u64
+        }
+
+        syscall::SYS_WAIT => {
+            // arg1 = pid (0 = any child), arg2 = status pointer, arg3 = options
+            // Reports an exit, a crash or (with WUNTRACED) a stop that hasn't
+            // been collected yet, blocking until there is one unless WNOHANG.
+            // A bad status pointer fails before the status is taken.
+            if arg2 != 0 && watos_mem::validate_user_ptr(arg2, 4).is_err() {
+                return u64::MAX;
+            }
+            let untraced = arg3 & watos_syscall::wait::WUNTRACED as u64 != 0;
+            match watos_process::take_child_event(arg1 as u32, untraced) {
+                Some((pid, status)) => {
+                    if arg2 != 0 {
+                        let _ = watos_mem::copy_to_user(&status.to_ne_bytes(), arg2);
+                    }
+                    pid as u64
+                }
+                None if !watos_process::has_child(arg1 as u32) => u64::MAX, // no children to wait for
+                None if arg3 & watos_syscall::wait::WNOHANG as u64 != 0 => 0, // nothing to report
+                None => restart_syscall(num, return_rip, return_rsp, watos_process::ProcessState::Waiting(0)),
+            }
+        }
+
         syscall::SYS_GETDATE => {
             // Returns packed date: year << 16 | month << 8 | day
             watos_arch::rtc::get_packed_date() as u64
@@ -1950,17 +5386,19 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 1;
             }
 
-            unsafe {
-                let phys_stats = watos_mem::phys::stats();
-                let heap_stats = watos_mem::heap::stats();
-
-                *buf_ptr.offset(0) = phys_stats.total_bytes() as u64;
-                *buf_ptr.offset(1) = phys_stats.free_bytes() as u64;
-                *buf_ptr.offset(2) = phys_stats.used_bytes() as u64;
-                *buf_ptr.offset(3) = heap_stats.total as u64;
-                *buf_ptr.offset(4) = heap_stats.used as u64;
+            let phys_stats = watos_mem::phys::stats();
+            let heap_stats = watos_mem::heap::stats();
+            let values = [
+                phys_stats.total_bytes() as u64,
+                phys_stats.free_bytes() as u64,
+                phys_stats.used_bytes() as u64,
+                heap_stats.total as u64,
+                heap_stats.used as u64,
+            ];
+            match watos_mem::write_user(buf_ptr as u64, values) {
+                Ok(()) => 0,
+                Err(_) => 1,
             }
-            0
         }
 
         syscall::SYS_MOUNT => {
@@ -1977,29 +5415,68 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
-            unsafe {
-                let name = core::slice::from_raw_parts(name_ptr, name_len);
+            let Ok(name) = watos_mem::read_user_bytes(name_ptr as u64, name_len) else { return u64::MAX };
 
-                // Find null-terminated mount path length
-                let mut mount_len = 0usize;
-                if !mount_ptr.is_null() {
-                    while mount_len < 64 && *mount_ptr.add(mount_len) != 0 {
-                        mount_len += 1;
+            // Null-terminated mount path, up to 64 bytes
+            let mut mount_path = alloc::vec::Vec::new();
+            if !mount_ptr.is_null() {
+                while mount_path.len() < 64 {
+                    match watos_mem::read_user::<u8>(mount_ptr as u64 + mount_path.len() as u64) {
+                        Ok(0) => break,
+                        Ok(byte) => mount_path.push(byte),
+                        Err(_) => return u64::MAX,
                     }
                 }
-                let mount_path = if mount_len > 0 {
-                    core::slice::from_raw_parts(mount_ptr, mount_len)
-                } else {
-                    b"/"
-                };
+            }
+            if mount_path.is_empty() {
+                mount_path.push(b'/');
+            }
 
+            unsafe {
                 watos_arch::serial_write(b"[KERNEL] SYS_MOUNT: ");
-                watos_arch::serial_write(name);
+                watos_arch::serial_write(&name);
                 watos_arch::serial_write(b" -> ");
-                watos_arch::serial_write(mount_path);
+                watos_arch::serial_write(&mount_path);
                 watos_arch::serial_write(b"\r\n");
+            }
+
+            drive_mount(&name, &mount_path, b"WFS")
+        }
+
+        syscall::SYS_DRIVE_ASSIGN => {
+            // arg1 = current letter, arg2 = new letter (root only)
+            // Returns 0; 1 bad letter or C:, 2 not mounted, 3 new letter
+            // taken, 4 permission denied
+            if watos_process::get_current_uid() != 0 {
+                return 4;
+            }
+            let from = (arg1 as u8 as char).to_ascii_uppercase();
+            let to = (arg2 as u8 as char).to_ascii_uppercase();
+            // The system lives on C:, by name
+            if !from.is_ascii_uppercase() || !to.is_ascii_uppercase() || from == 'C' || to == 'C' {
+                return 1;
+            }
+
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = watos_vfs::reassign_drive(from, to);
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
 
-                drive_mount(name, mount_path, b"WFS")
+            match result {
+                Ok(()) => {
+                    drive_rename(&[from as u8], &[to as u8]);
+                    0
+                }
+                Err(watos_vfs::VfsError::NotMounted) => 2,
+                Err(watos_vfs::VfsError::AlreadyMounted) => 3,
+                Err(_) => 1,
             }
         }
 
@@ -2013,13 +5490,13 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
+            let Ok(name) = watos_mem::read_user_bytes(name_ptr as u64, name_len) else { return u64::MAX };
             unsafe {
-                let name = core::slice::from_raw_parts(name_ptr, name_len);
                 watos_arch::serial_write(b"[KERNEL] SYS_UNMOUNT: ");
-                watos_arch::serial_write(name);
+                watos_arch::serial_write(&name);
                 watos_arch::serial_write(b"\r\n");
-                drive_unmount(name)
             }
+            drive_unmount(&name)
         }
 
         syscall::SYS_LISTDRIVES => {
@@ -2033,9 +5510,11 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 0;
             }
 
-            unsafe {
-                let buf = core::slice::from_raw_parts_mut(buf_ptr, buf_size);
-                drive_list(buf) as u64
+            let mut buf = alloc::vec![0u8; buf_size.min(4096)];
+            let len = drive_list(&mut buf);
+            match watos_mem::copy_to_user(&buf[..len], buf_ptr as u64) {
+                Ok(()) => len as u64,
+                Err(_) => 0,
             }
         }
 
@@ -2050,13 +5529,13 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
+            let Some(path) = user_path(path_ptr as u64, path_len) else { return u64::MAX };
             unsafe {
-                let path = core::slice::from_raw_parts(path_ptr, path_len);
                 watos_arch::serial_write(b"[KERNEL] SYS_CHDIR: ");
-                watos_arch::serial_write(path);
+                watos_arch::serial_write(&path);
                 watos_arch::serial_write(b"\r\n");
-                change_dir(path)
             }
+            change_dir(&path)
         }
 
         syscall::SYS_READDIR => {
@@ -2065,6 +5544,7 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             // arg3 = buffer pointer for output
             // Returns bytes written: entries as "TYPE NAME SIZE\n"
             // TYPE: D=directory, F=file
+            // Entries come back in collated (display) order, not disk order
             let path_ptr = arg1 as *const u8;
             let path_len = arg2 as usize;
             let buf_ptr = arg3 as *mut u8;
@@ -2083,7 +5563,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
             unsafe {
                 let buf_size = 4096usize;
-                let buf = core::slice::from_raw_parts_mut(buf_ptr, buf_size);
+                let mut kernel_buf = alloc::vec![0u8; buf_size];
+                let buf = &mut kernel_buf[..];
 
                 // Get path (construct full path with drive letter if not specified)
                 static mut FULL_PATH_BUF: [u8; 260] = [0u8; 260];
@@ -2107,7 +5588,12 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
                     &FULL_PATH_BUF[..pos]
                 } else {
-                    core::slice::from_raw_parts(path_ptr, path_len)
+                    let path_len = path_len.min(FULL_PATH_BUF.len());
+                    let full = &mut *core::ptr::addr_of_mut!(FULL_PATH_BUF);
+                    if watos_mem::copy_from_user(path_ptr as u64, &mut full[..path_len]).is_err() {
+                        return 0;
+                    }
+                    &full[..path_len]
                 };
 
                 // Convert to string for VFS
@@ -2129,7 +5615,7 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 }
 
                 // Use VFS to read directory (with kernel page table)
-                let vfs_result = watos_vfs::readdir(path_str);
+                let vfs_result = watos_vfs::readdir_sorted(path_str);
 
                 // Restore user page table before writing to user buffer
                 if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
@@ -2180,7 +5666,10 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                             buf[pos] = b'\n';
                             pos += 1;
                         }
-                        pos as u64
+                        match watos_mem::copy_to_user(&buf[..pos], buf_ptr as u64) {
+                            Ok(()) => pos as u64,
+                            Err(_) => 0,
+                        }
                     }
                     Err(e) => {
                         watos_arch::serial_write(b"[KERNEL] VFS readdir error: ");
@@ -2208,6 +5697,7 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                             VfsError::Busy => b"Busy",
                             VfsError::InvalidName => b"InvalidName",
                             VfsError::Corrupted => b"Corrupted",
+                            VfsError::WouldBlock => b"WouldBlock",
                             VfsError::FsError(_) => b"FsError",
                         };
                         watos_arch::serial_write(err_msg);
@@ -2229,15 +5719,15 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
+            let Some(path) = user_path(path_ptr as u64, path_len) else { return u64::MAX };
             unsafe {
-                let path = core::slice::from_raw_parts(path_ptr, path_len);
                 watos_arch::serial_write(b"[KERNEL] SYS_MKDIR: ");
-                watos_arch::serial_write(path);
+                watos_arch::serial_write(&path);
                 watos_arch::serial_write(b"\r\n");
 
                 // Try WFS first
                 if WFS_SUPERBLOCK.is_some() {
-                    if wfs_mkdir(path) {
+                    if wfs_mkdir(&path) {
                         return 0;
                     }
                     watos_arch::serial_write(b"[KERNEL] WFS mkdir failed\r\n");
@@ -2263,16 +5753,19 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
-            unsafe {
-                let path = core::slice::from_raw_parts(path_ptr, path_len);
+            let Some(path) = user_path(path_ptr as u64, path_len) else { return u64::MAX };
+            let path = &path[..];
+            let stat = |kind: u64, size: u64| match watos_mem::write_user(stat_ptr as u64, [kind, size]) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            };
 
+            unsafe {
                 // Check for directories
                 if path == b"." || path == b".." || path == b"\\" || path == b"/" ||
                    path == b"SYSTEM" || path == b"apps" {
                     // Directory: type=1, size=0
-                    *stat_ptr = 1; // type: directory
-                    *stat_ptr.add(1) = 0; // size
-                    return 0;
+                    return stat(1, 0);
                 }
 
                 // Check preloaded apps
@@ -2291,9 +5784,7 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                             });
                             if matches {
                                 // File: type=0, size=app.size
-                                *stat_ptr = 0; // type: file
-                                *stat_ptr.add(1) = app.size;
-                                return 0;
+                                return stat(0, app.size);
                             }
                         }
                     }
@@ -2304,6 +5795,65 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             }
         }
 
+        syscall::SYS_STATFS => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // arg3 = pointer to u64[6]: total blocks, free blocks, block size,
+            //        total inodes, free inodes, max name length
+            // Returns 0 on success, u64::MAX on error
+            let buf_ptr = arg3 as *mut u64;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            let Some(stats) = vfs_query(arg1, arg2, watos_vfs::statfs) else { return u64::MAX };
+            let values = [
+                stats.total_blocks,
+                stats.free_blocks,
+                stats.block_size as u64,
+                stats.total_inodes,
+                stats.free_inodes,
+                stats.max_name_len as u64,
+            ];
+            match watos_mem::write_user(buf_ptr as u64, values) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_VOLINFO => {
+            // arg1 = path pointer, arg2 = path length
+            // arg3 = VolInfo pointer, arg4 (r10) = its size in the caller's version
+            // Returns the bytes filled, or u64::MAX on error
+            use watos_syscall::volume::{VolInfo, LABEL_MAX};
+            let buf_ptr = arg3 as *mut u8;
+            let buf_len = unsafe { SAVED_SYSCALL_REGS.r10 } as usize;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            let Some(volume) = vfs_query(arg1, arg2, watos_vfs::volume) else { return u64::MAX };
+            let mut info = VolInfo {
+                serial: volume.serial.unwrap_or(0),
+                has_serial: volume.serial.is_some() as u8,
+                ..VolInfo::default()
+            };
+            if let Some(label) = volume.label {
+                let mut len = label.len().min(LABEL_MAX);
+                while !label.is_char_boundary(len) {
+                    len -= 1;
+                }
+                info.label[..len].copy_from_slice(&label.as_bytes()[..len]);
+                info.label_len = len as u8;
+            }
+            let len = buf_len.min(core::mem::size_of::<VolInfo>());
+            let bytes = unsafe {
+                core::slice::from_raw_parts(&info as *const VolInfo as *const u8, len)
+            };
+            match watos_mem::copy_to_user(bytes, buf_ptr as u64) {
+                Ok(()) => len as u64,
+                Err(_) => u64::MAX,
+            }
+        }
+
         syscall::SYS_GETCWD => {
             // arg1 = buffer pointer
             // arg2 = buffer size
@@ -2315,9 +5865,11 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 0;
             }
 
-            unsafe {
-                let buf = core::slice::from_raw_parts_mut(buf_ptr, buf_size);
-                get_cwd(buf) as u64
+            let mut buf = [0u8; MAX_PATH_LEN + MAX_DRIVE_NAME + 1];
+            let len = get_cwd(&mut buf[..buf_size.min(MAX_PATH_LEN + MAX_DRIVE_NAME + 1)]);
+            match watos_mem::copy_to_user(&buf[..len], buf_ptr as u64) {
+                Ok(()) => len as u64,
+                Err(_) => 0,
             }
         }
 
@@ -2355,11 +5907,12 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
-            unsafe {
-                let data = core::slice::from_raw_parts(buf_ptr, stride * height);
+            let Some(size) = stride.checked_mul(height) else { return u64::MAX };
+            let Ok(data) = watos_mem::read_user_bytes(buf_ptr as u64, size) else { return u64::MAX };
+            {
                 // Blit to active session if exists, otherwise to physical framebuffer
                 if let Some(session_id) = watos_driver_video::get_active_session() {
-                    watos_driver_video::session_blit(session_id, data, width, height, stride);
+                    watos_driver_video::session_blit(session_id, &data, width, height, stride);
                 } else {
                     // Direct blit to physical framebuffer
                     for y in 0..height {
@@ -2447,150 +6000,970 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             let session_id = arg1 as u32;
             let buf_ptr = arg2 as *mut u32;
 
-            if buf_ptr.is_null() {
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+
+            if let Some(mode) = watos_driver_video::get_session_info(session_id) {
+                // Write VideoMode: width, height, bpp, format
+                let values = [mode.width, mode.height, mode.bpp as u32, mode.format as u32];
+                match watos_mem::write_user(buf_ptr as u64, values) {
+                    Ok(()) => 0,
+                    Err(_) => u64::MAX,
+                }
+            } else {
+                u64::MAX
+            }
+        }
+
+        syscall::SYS_VGA_ENUMERATE_MODES => {
+            // arg1 = buffer pointer, arg2 = buffer size (in VideoMode structs)
+            // Returns number of modes written
+            let buf_ptr = arg1 as *mut u32;
+            let max_modes = arg2 as usize;
+
+            if buf_ptr.is_null() || max_modes == 0 {
+                return 0;
+            }
+
+            let modes = watos_driver_video::get_available_modes();
+            let count = modes.len().min(max_modes);
+
+            for (i, mode) in modes.iter().take(count).enumerate() {
+                let offset = i as u64 * 16; // 4 u32s per VideoMode
+                let values = [mode.width, mode.height, mode.bpp as u32, mode.format as u32];
+                if watos_mem::write_user(buf_ptr as u64 + offset, values).is_err() {
+                    return i as u64;
+                }
+            }
+
+            count as u64
+        }
+
+        syscall::SYS_AUTHENTICATE => {
+            // arg1 = username pointer
+            // arg2 = username length
+            // arg3 = password pointer
+            // Returns UID on success, u64::MAX on failure
+            // Note: Password length is determined by null terminator (max 64 bytes)
+            let username_ptr = arg1 as *const u8;
+            let username_len = arg2 as usize;
+            let password_ptr = arg3 as *const u8;
+
+            if username_ptr.is_null() || username_len == 0 || password_ptr.is_null() {
+                return u64::MAX;
+            }
+
+            let Ok(username) = watos_mem::read_user_bytes(username_ptr as u64, username_len.min(256)) else {
+                return u64::MAX;
+            };
+            let username = &username[..];
+
+            // Safely find password length (null-terminated, max 64 bytes)
+            let mut password_buf = [0u8; 64];
+            let mut password_len = 0usize;
+            while password_len < 64 {
+                match watos_mem::read_user::<u8>(password_ptr as u64 + password_len as u64) {
+                    Ok(0) => break,
+                    Ok(byte) => password_buf[password_len] = byte,
+                    Err(_) => return u64::MAX,
+                }
+                password_len += 1;
+            }
+
+            unsafe {
+                // Validate we found a null terminator
+                if password_len == 64 {
+                    watos_arch::serial_write(b"[KERNEL] Password too long or not null-terminated\r\n");
+                    return u64::MAX;
+                }
+                
+                let password = &password_buf[..password_len];
+
+                watos_arch::serial_write(b"[KERNEL] SYS_AUTHENTICATE: user=");
+                watos_arch::serial_write(username);
+                watos_arch::serial_write(b"\r\n");
+
+                // Authenticate via users subsystem
+                match watos_users::authenticate(username, password) {
+                    Some(uid) => {
+                        watos_arch::serial_write(b"[KERNEL] Authentication successful, UID=");
+                        watos_arch::serial_hex(uid as u64);
+                        watos_arch::serial_write(b"\r\n");
+                        // The password is only here now: unlock the user's keyring
+                        if let Err(e) = watos_keyring::unlock(uid, password) {
+                            watos_arch::serial_write(b"[KERNEL] Keyring not unlocked: ");
+                            watos_arch::serial_write(alloc::format!("{:?}\r\n", e).as_bytes());
+                        }
+                        uid as u64
+                    }
+                    None => {
+                        watos_arch::serial_write(b"[KERNEL] Authentication failed\r\n");
+                        u64::MAX
+                    }
+                }
+            }
+        }
+
+        syscall::SYS_SETUID => {
+            // arg1 = UID to set
+            let uid = arg1 as u32;
+            
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] SYS_SETUID: ");
+                watos_arch::serial_hex(uid as u64);
+                watos_arch::serial_write(b"\r\n");
+            }
+
+            if watos_process::set_current_uid(uid) {
+                0
+            } else {
+                u64::MAX
+            }
+        }
+
+        syscall::SYS_SHUTDOWN | syscall::SYS_REBOOT => {
+            // Root only; does not return when allowed
+            if watos_process::get_current_uid() != 0 {
+                return u64::MAX;
+            }
+            power_off(num == syscall::SYS_REBOOT)
+        }
+
+        syscall::SYS_GETUID => {
+            // Returns current process UID
+            watos_process::get_current_uid() as u64
+        }
+
+        syscall::SYS_GETGID => {
+            // Returns current process GID
+            watos_process::get_current_gid() as u64
+        }
+
+        syscall::SYS_SETGID => {
+            // arg1 = GID to set
+            let gid = arg1 as u32;
+
+            if watos_process::set_current_gid(gid) {
+                0
+            } else {
+                u64::MAX
+            }
+        }
+
+        syscall::SYS_GETEUID => {
+            // Returns effective UID (same as UID for now - no setuid support)
+            watos_process::get_current_uid() as u64
+        }
+
+        syscall::SYS_GETEGID => {
+            // Returns effective GID (same as GID for now - no setgid support)
+            watos_process::get_current_gid() as u64
+        }
+
+        syscall::SYS_GETPWENT | syscall::SYS_GET_USER_INFO | syscall::SYS_GETPWNAM => {
+            // SYS_GETPWENT:      arg1 = index
+            // SYS_GET_USER_INFO: arg1 = UID
+            // SYS_GETPWNAM:      arg1 = name pointer, arg2 = name length
+            // Buffer in arg2/arg3 (arg3/R10 for SYS_GETPWNAM)
+            // Returns the /etc/passwd line's length (cut short if larger
+            // than the buffer), 0 if there is no such user
+            let (buf_ptr, buf_len) = if num == syscall::SYS_GETPWNAM {
+                (arg3 as *mut u8, unsafe { SAVED_SYSCALL_REGS.r10 } as usize)
+            } else {
+                (arg2 as *mut u8, arg3 as usize)
+            };
+            if buf_ptr.is_null() {
+                return 0;
+            }
+
+            let user = match num {
+                syscall::SYS_GETPWENT => watos_users::user_at(arg1 as usize),
+                syscall::SYS_GET_USER_INFO => watos_users::get_user(arg1 as u32),
+                _ => {
+                    let name_ptr = arg1 as *const u8;
+                    let name_len = arg2 as usize;
+                    if name_ptr.is_null() || name_len == 0 || name_len > watos_users::MAX_USERNAME_LEN {
+                        return 0;
+                    }
+                    let Ok(name) = watos_mem::read_user_bytes(name_ptr as u64, name_len) else { return 0 };
+                    watos_users::get_user_by_name(&name)
+                }
+            };
+            match user {
+                Some(user) => {
+                    let mut buf = alloc::vec![0u8; buf_len.min(4096)];
+                    let len = user.write_passwd_line(&mut buf);
+                    match watos_mem::copy_to_user(&buf[..len], buf_ptr as u64) {
+                        Ok(()) => len as u64,
+                        Err(_) => 0,
+                    }
+                }
+                None => 0,
+            }
+        }
+
+        syscall::SYS_GETGRENT | syscall::SYS_GETGRGID => {
+            // arg1 = index (SYS_GETGRENT) or GID (SYS_GETGRGID)
+            // arg2 = buffer pointer, arg3 = buffer length
+            // Returns the /etc/group line's length (cut short if larger than
+            // the buffer), 0 if there is no such group
+            let buf_ptr = arg2 as *mut u8;
+            if buf_ptr.is_null() {
+                return 0;
+            }
+            let group = if num == syscall::SYS_GETGRENT {
+                watos_users::group_at(arg1 as usize)
+            } else {
+                watos_users::get_group(arg1 as u32)
+            };
+            match group {
+                Some(group) => {
+                    let mut buf = alloc::vec![0u8; (arg3 as usize).min(4096)];
+                    let len = watos_users::write_group_line(&group, &mut buf);
+                    match watos_mem::copy_to_user(&buf[..len], buf_ptr as u64) {
+                        Ok(()) => len as u64,
+                        Err(_) => 0,
+                    }
+                }
+                None => 0,
+            }
+        }
+
+        syscall::SYS_GETGROUPS => {
+            // arg1 = u32 buffer pointer, arg2 = how many it holds
+            // Returns the number of supplementary groups, copying what fits
+            let cred = watos_users::get_credential_info(
+                watos_process::get_current_uid(),
+                watos_process::get_current_gid(),
+            );
+            let gids_ptr = arg1 as *mut u32;
+            if !gids_ptr.is_null() {
+                let count = (arg2 as usize).min(cred.ngroups);
+                for (i, &gid) in cred.groups[..count].iter().enumerate() {
+                    if watos_mem::write_user(gids_ptr as u64 + i as u64 * 4, gid).is_err() {
+                        return u64::MAX;
+                    }
+                }
+            }
+            cred.ngroups as u64
+        }
+
+        syscall::SYS_KEY_ADD | syscall::SYS_KEY_READ | syscall::SYS_KEY_DELETE => {
+            // arg1 = name pointer, arg2 = name length
+            // SYS_KEY_ADD:    arg3 = data pointer, arg4 (R10) = data length
+            // SYS_KEY_READ:   arg3 = buffer pointer, arg4 (R10) = buffer length;
+            //                 returns the key's length, copying only if it fits
+            // SYS_KEY_DELETE: arg3 = owner UID, u64::MAX for the caller's own
+            // Returns u64::MAX on error
+            let name_ptr = arg1 as *const u8;
+            let name_len = arg2 as usize;
+            if name_ptr.is_null() || name_len == 0 || name_len > watos_keyring::MAX_NAME_LEN {
+                return u64::MAX;
+            }
+            let Ok(name_bytes) = watos_mem::read_user_bytes(name_ptr as u64, name_len) else { return u64::MAX };
+            let name = match core::str::from_utf8(&name_bytes) {
+                Ok(n) => n,
+                Err(_) => return u64::MAX,
+            };
+            let uid = watos_process::get_current_uid();
+            let len = unsafe { SAVED_SYSCALL_REGS.r10 } as usize;
+
+            let result = match num {
+                syscall::SYS_KEY_ADD => {
+                    if arg3 == 0 || len > watos_keyring::MAX_KEY_SIZE {
+                        return u64::MAX;
+                    }
+                    let Ok(data) = watos_mem::read_user_bytes(arg3, len) else { return u64::MAX };
+                    watos_keyring::add(uid, name, &data).map(|()| 0)
+                }
+                syscall::SYS_KEY_READ => {
+                    if arg3 == 0 {
+                        return u64::MAX;
+                    }
+                    // Secrets are decrypted into kernel memory, copied out and wiped
+                    let mut buf = alloc::vec![0u8; len.min(watos_keyring::MAX_KEY_SIZE)];
+                    let result = watos_keyring::read(uid, name, &mut buf);
+                    let copied = match result {
+                        Ok(n) if n <= buf.len() => watos_mem::copy_to_user(&buf[..n], arg3).is_ok(),
+                        _ => true,
+                    };
+                    buf.fill(0);
+                    if !copied {
+                        return u64::MAX;
+                    }
+                    result.map(|n| n as u64)
+                }
+                _ => {
+                    let owner = if arg3 == u64::MAX { uid } else { arg3 as u32 };
+                    watos_keyring::delete(uid, owner, name).map(|()| 0)
+                }
+            };
+            match result {
+                Ok(v) => v,
+                Err(e) => {
+                    unsafe {
+                        watos_arch::serial_write(alloc::format!("[KERNEL] keyring: {:?}\r\n", e).as_bytes());
+                    }
+                    u64::MAX
+                }
+            }
+        }
+
+        syscall::SYS_SWAPON => {
+            // arg1 = path pointer, arg2 = path length
+            // arg3 = pages to format the file with first, 0 to use it as is
+            // Root only. Returns 0, or u64::MAX on error
+            let path_len = arg2 as usize;
+            if watos_process::get_current_uid() != 0 || arg1 == 0 || path_len == 0 || path_len > 256 {
+                return u64::MAX;
+            }
+            let mut path_buf = [0u8; 256];
+            if watos_mem::copy_from_user(arg1, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
+            }
+            let path = match core::str::from_utf8(&path_buf[..path_len]) {
+                Ok(p) => p,
+                Err(_) => return u64::MAX,
+            };
+            let pages = match u32::try_from(arg3) {
+                Ok(p) => p,
+                Err(_) => return u64::MAX,
+            };
+            match in_kernel_space(|| swap_on(path, pages)) {
+                Ok(()) => 0,
+                Err(e) => {
+                    unsafe {
+                        watos_arch::serial_write(alloc::format!("[KERNEL] swapon {}: {:?}\r\n", path, e).as_bytes());
+                    }
+                    u64::MAX
+                }
+            }
+        }
+
+        syscall::SYS_SWAPOFF => {
+            // Root only. Returns 0, or u64::MAX on error
+            if watos_process::get_current_uid() != 0 {
+                return u64::MAX;
+            }
+            match in_kernel_space(watos_process::swap::swapoff) {
+                Ok(()) => {
+                    unsafe { watos_arch::serial_write(b"[KERNEL] Swap off\r\n"); }
+                    0
+                }
+                Err(e) => {
+                    unsafe {
+                        watos_arch::serial_write(alloc::format!("[KERNEL] swapoff: {:?}\r\n", e).as_bytes());
+                    }
+                    u64::MAX
+                }
+            }
+        }
+
+        syscall::SYS_SYSCTL => {
+            // arg1 = name pointer, arg2 = name length (e.g. "kernel.loglevel")
+            // arg3 = 0 to read, 1 to write arg4 (R10); writes are root only
+            // Returns the value (after a write), or u64::MAX on error
+            let name_len = arg2 as usize;
+            if arg1 == 0 || name_len == 0 || name_len > 128 || arg3 > 1 {
+                return u64::MAX;
+            }
+            let Ok(name_bytes) = watos_mem::read_user_bytes(arg1, name_len) else { return u64::MAX };
+            let Ok(name) = core::str::from_utf8(&name_bytes) else { return u64::MAX };
+            if arg3 == 1 {
+                let value = unsafe { SAVED_SYSCALL_REGS.r10 };
+                if watos_sysctl::set(name, value).is_err() {
+                    return u64::MAX;
+                }
+            }
+            watos_sysctl::get(name).unwrap_or(u64::MAX)
+        }
+
+        syscall::SYS_SYSINFO => {
+            // arg1 = SysInfo pointer, arg2 = its size in the caller's version
+            // Returns the bytes filled, or u64::MAX on error
+            use watos_syscall::sysinfo::SysInfo;
+            if arg1 == 0 {
+                return u64::MAX;
+            }
+            let phys = watos_mem::phys::stats();
+            let heap = watos_mem::heap::stats();
+            let swap = watos_swap::stats().unwrap_or(watos_swap::SwapStats { total: 0, used: 0 });
+            let info = SysInfo {
+                uptime_secs: watos_arch::clock::now_ms() / 1000,
+                idle_secs: watos_process::idle_ms() / 1000,
+                mem_total: phys.total_bytes() as u64,
+                mem_free: phys.free_bytes() as u64,
+                heap_total: heap.total as u64,
+                heap_used: heap.used as u64,
+                swap_total: (swap.total * watos_swap::PAGE_SIZE) as u64,
+                swap_used: (swap.used * watos_swap::PAGE_SIZE) as u64,
+                procs: watos_process::process_count() as u32,
+                loads: watos_process::load_average(),
+            };
+            let len = (arg2 as usize).min(core::mem::size_of::<SysInfo>());
+            let bytes = unsafe {
+                core::slice::from_raw_parts(&info as *const SysInfo as *const u8, len)
+            };
+            match watos_mem::copy_to_user(bytes, arg1) {
+                Ok(()) => len as u64,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_SWAPINFO => {
+            // arg1 = pointer to u64[2]: swap size, bytes in use
+            // Returns 0, or u64::MAX if swap is off
+            let buf_ptr = arg1 as *mut u64;
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            match watos_swap::stats() {
+                Some(stats) => {
+                    let values = [
+                        (stats.total * watos_swap::PAGE_SIZE) as u64,
+                        (stats.used * watos_swap::PAGE_SIZE) as u64,
+                    ];
+                    match watos_mem::write_user(buf_ptr as u64, values) {
+                        Ok(()) => 0,
+                        Err(_) => u64::MAX,
+                    }
+                }
+                None => u64::MAX,
+            }
+        }
+
+        syscall::SYS_UNLINK | syscall::SYS_RMDIR => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // Returns 0 on success, u64::MAX on error
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+
+            if path_ptr.is_null() || path_len == 0 || path_len > 256 {
+                return u64::MAX;
+            }
+
+            // Copy path from user memory
+            let mut path_buf = [0u8; 256];
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
+            }
+
+            let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
+                Ok(s) => s,
+                Err(_) => return u64::MAX,
+            };
+
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = if num == syscall::SYS_UNLINK {
+                watos_vfs::unlink(path_str)
+            } else {
+                watos_vfs::rmdir(path_str)
+            };
+
+            // Restore user page table
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_RENAME | syscall::SYS_LINK => {
+            // arg1 = old path pointer
+            // arg2 = old path length
+            // arg3 = new path pointer
+            // r10 = new path length
+            // SYS_LINK gives the old path's file the new path as a second name
+            // Returns 0 on success, u64::MAX on error
+            let old_len = arg2 as usize;
+            let new_ptr = arg3 as *const u8;
+            let new_len = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
+
+            if arg1 == 0 || new_ptr.is_null() || old_len == 0 || new_len == 0
+                || old_len > 256 || new_len > 256 {
+                return u64::MAX;
+            }
+
+            // Copy both paths from user memory
+            let mut old_buf = [0u8; 256];
+            let mut new_buf = [0u8; 256];
+            if watos_mem::copy_from_user(arg1, &mut old_buf[..old_len]).is_err() {
+                return u64::MAX;
+            }
+            if watos_mem::copy_from_user(new_ptr as u64, &mut new_buf[..new_len]).is_err() {
+                return u64::MAX;
+            }
+
+            let (old_str, new_str) = match (
+                core::str::from_utf8(&old_buf[..old_len]),
+                core::str::from_utf8(&new_buf[..new_len]),
+            ) {
+                (Ok(o), Ok(n)) => (o, n),
+                _ => return u64::MAX,
+            };
+
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = if num == syscall::SYS_RENAME {
+                watos_vfs::rename(old_str, new_str)
+            } else {
+                watos_vfs::link(old_str, new_str)
+            };
+
+            // Restore user page table
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_COPYFILE => {
+            // arg1 = CopyFile pointer
+            // Copies up to copyfile::STEP bytes from its `done` and updates
+            // `done` and `total`. Returns 0 when the copy is finished, 1
+            // when there is more, u64::MAX on error (the copy is removed)
+            use watos_syscall::copyfile::{CopyFile, STEP};
+            let req_ptr = arg1 as *mut CopyFile;
+            if req_ptr.is_null() {
+                return u64::MAX;
+            }
+            let Ok(mut req) = watos_mem::read_user::<CopyFile>(req_ptr as u64) else { return u64::MAX };
+            let (src_len, dst_len) = (req.src_len as usize, req.dst_len as usize);
+            if req.src_ptr == 0 || req.dst_ptr == 0 || src_len == 0 || dst_len == 0
+                || src_len > 256 || dst_len > 256 {
+                return u64::MAX;
+            }
+
+            // Copy both paths from user memory
+            let mut src_buf = [0u8; 256];
+            let mut dst_buf = [0u8; 256];
+            if watos_mem::copy_from_user(req.src_ptr, &mut src_buf[..src_len]).is_err() {
+                return u64::MAX;
+            }
+            if watos_mem::copy_from_user(req.dst_ptr, &mut dst_buf[..dst_len]).is_err() {
+                return u64::MAX;
+            }
+            let (src, dst) = match (
+                core::str::from_utf8(&src_buf[..src_len]),
+                core::str::from_utf8(&dst_buf[..dst_len]),
+            ) {
+                (Ok(s), Ok(d)) => (s, d),
+                _ => return u64::MAX,
+            };
+
+            let mut cwd_buf = [0u8; MAX_PATH_LEN + MAX_DRIVE_NAME + 1];
+            let cwd_len = get_cwd(&mut cwd_buf);
+            let cwd = core::str::from_utf8(&cwd_buf[..cwd_len]).unwrap_or("");
+
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = watos_vfs::canonicalize(src, cwd)
+                .and_then(|src| Ok((src, watos_vfs::canonicalize(dst, cwd)?)))
+                .and_then(|(src, dst)| watos_vfs::copy_part(&src, &dst, req.done, STEP, &mut |_, _| {}));
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                Ok(state) => {
+                    req.done = state.done;
+                    req.total = state.total;
+                    if watos_mem::write_user(req_ptr as u64, req).is_err() {
+                        return u64::MAX;
+                    }
+                    if state.finished { 0 } else { 1 }
+                }
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_TRASH => {
+            // arg1 = path pointer, arg2 = path length
+            // Returns 0 when trashed, 1 when there is no trash for the path
+            // (the caller deletes it instead), u64::MAX on error
+            let now = watos_arch::rtc::now().secs().max(0) as u64;
+            let result = vfs_query(arg1, arg2, |path| match watos_vfs::trash(path, now) {
+                Ok(_) => Ok(0),
+                Err(watos_vfs::VfsError::NotSupported) => Ok(1),
+                Err(e) => Err(e),
+            });
+            result.unwrap_or(u64::MAX)
+        }
+
+        syscall::SYS_TRASH_LIST => {
+            // arg1 = drive letter, arg2 = buffer pointer, arg3 = buffer size
+            // Returns bytes written as "ID\tDELETED\tPATH\n" lines, oldest
+            // first and cut at the end of the buffer; u64::MAX on error
+            let buf_ptr = arg2 as *mut u8;
+            let buf_len = arg3 as usize;
+            let Some(drive) = trash_drive(arg1) else { return u64::MAX };
+            if buf_ptr.is_null() {
+                return u64::MAX;
+            }
+            let entries = match on_kernel_tables(|| watos_vfs::trash_list(drive)) {
+                Ok(entries) => entries,
+                Err(_) => return u64::MAX,
+            };
+            let mut written = 0;
+            for entry in &entries {
+                let line = alloc::format!("{}\t{}\t{}\n", entry.id, entry.deleted, entry.path);
+                if written + line.len() > buf_len {
+                    break;
+                }
+                if watos_mem::copy_to_user(line.as_bytes(), buf_ptr as u64 + written as u64).is_err() {
+                    return u64::MAX;
+                }
+                written += line.len();
+            }
+            written as u64
+        }
+
+        syscall::SYS_TRASH_RESTORE | syscall::SYS_TRASH_PURGE => {
+            // arg1 = drive letter, arg2 = id pointer, arg3 = id length
+            // SYS_TRASH_RESTORE returns 0, or 1 if the original path is
+            // taken; SYS_TRASH_PURGE purges everything when the length is
+            // 0 and returns how many went. u64::MAX on error
+            let id_len = arg3 as usize;
+            let Some(drive) = trash_drive(arg1) else { return u64::MAX };
+            if id_len > 256 || (id_len > 0 && arg2 == 0) || (id_len == 0 && num == syscall::SYS_TRASH_RESTORE) {
+                return u64::MAX;
+            }
+            let mut id_buf = [0u8; 256];
+            if id_len > 0 && watos_mem::copy_from_user(arg2, &mut id_buf[..id_len]).is_err() {
+                return u64::MAX;
+            }
+            let Ok(id) = core::str::from_utf8(&id_buf[..id_len]) else { return u64::MAX };
+
+            if num == syscall::SYS_TRASH_RESTORE {
+                match on_kernel_tables(|| watos_vfs::trash_restore(drive, id)) {
+                    Ok(_) => 0,
+                    Err(watos_vfs::VfsError::AlreadyExists) => 1,
+                    Err(_) => u64::MAX,
+                }
+            } else {
+                let id = Some(id).filter(|id| !id.is_empty());
+                match on_kernel_tables(|| watos_vfs::trash_purge(drive, id)) {
+                    Ok(count) => count as u64,
+                    Err(_) => u64::MAX,
+                }
+            }
+        }
+
+        syscall::SYS_PERFCTR => {
+            // arg1 = operation (watos_syscall::perfctr::OP_*)
+            // arg2 = counter, arg3 = event (OP_START)
+            // Returns what the operation gives, u64::MAX on error
+            use watos_syscall::perfctr;
+            match arg1 {
+                perfctr::OP_INFO => watos_arch::pmu::info().map_or(0, |pmu| pmu.to_bits()),
+                perfctr::OP_TSC_KHZ => watos_arch::clock::tsc_khz().or_else(watos_arch::cpu::tsc_khz).unwrap_or(0),
+                perfctr::OP_START => match watos_arch::pmu::Event::from_index(arg3) {
+                    Some(event) if arg2 < watos_arch::pmu::MAX_COUNTERS as u64
+                        && watos_arch::pmu::start(arg2 as u32, event) => 0,
+                    _ => u64::MAX,
+                },
+                perfctr::OP_READ => watos_arch::pmu::read(arg2 as u32).unwrap_or(u64::MAX),
+                perfctr::OP_STOP => watos_arch::pmu::stop(arg2 as u32).unwrap_or(u64::MAX),
+                _ => u64::MAX,
+            }
+        }
+
+        syscall::SYS_KBD_REPEAT => {
+            // arg1 = delay in ms, arg2 = repeats per second (0 = off)
+            // arg3 = 1 to set them, 0 to only read
+            // Returns delay << 16 | rate afterwards, u64::MAX if out of range
+            if arg3 == 1 {
+                let (Ok(delay), Ok(rate)) = (u32::try_from(arg1), u32::try_from(arg2)) else { return u64::MAX };
+                if !watos_arch::typematic::set(delay, rate) {
+                    return u64::MAX;
+                }
+            }
+            let (delay, rate) = watos_arch::typematic::get();
+            (delay as u64) << 16 | rate as u64
+        }
+
+        syscall::SYS_CONSOLE_MIRROR => {
+            // arg1 = buffer pointer, arg2 = buffer length
+            // The first caller becomes the mirror's reader until it exits
+            // Returns bytes of mirrored text read, u64::MAX if another
+            // process is the reader
+            let Some(pid) = watos_process::current_pid() else { return u64::MAX };
+            unsafe {
+                match MIRROR_READER {
+                    Some(reader) if reader == pid => {}
+                    Some(reader) if watos_process::process_summary(reader).is_some() => return u64::MAX,
+                    _ => {
+                        MIRROR_READER = Some(pid);
+                        MIRROR_READ_POS = MIRROR_WRITE_POS;
+                        watos_vt::mirror::set_consumer(Some(console_mirror));
+                    }
+                }
+            }
+            if arg1 == 0 || arg2 == 0 {
+                return 0;
+            }
+            let mut buf = alloc::vec![0u8; (arg2 as usize).min(MIRROR_BUFFER_SIZE)];
+            let len = console_mirror_read(&mut buf);
+            match watos_mem::copy_to_user(&buf[..len], arg1) {
+                Ok(()) => len as u64,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_NET_CONNECT => {
+            // arg1 = IPv4 address (a.b.c.d as a << 24 | b << 16 | c << 8 | d)
+            // arg2 = port
+            // Returns an fd for the connection, which opens in the
+            // background (see SYS_NET_STATE), or u64::MAX without a network
+            let addr = watos_network::Ipv4Address::from_bytes(&(arg1 as u32).to_be_bytes());
+            on_kernel_tables(|| net_install(watos_network::socket::connect(addr, arg2 as u16)))
+        }
+
+        syscall::SYS_NET_LISTEN => {
+            // arg1 = port
+            // Returns an fd for SYS_NET_ACCEPT, or u64::MAX if the port is
+            // taken or there is no network
+            on_kernel_tables(|| net_install(watos_network::socket::listen(arg1 as u16)))
+        }
+
+        syscall::SYS_NET_ACCEPT => {
+            // arg1 = listening fd
+            // Returns an fd for a new connection, 0 if none has come in,
+            // or u64::MAX if arg1 is not a listener
+            let Some(id) = fd_socket(arg1 as i64) else { return u64::MAX };
+            on_kernel_tables(|| match watos_network::socket::accept(id) {
+                Ok(Some(file)) => net_install(Ok(file)),
+                Ok(None) => 0,
+                Err(_) => u64::MAX,
+            })
+        }
+
+        syscall::SYS_NET_STATE => {
+            // arg1 = socket fd
+            // Returns 0 connecting, 1 open, 2 closed (the peer has finished
+            // and everything it sent has been read), u64::MAX not a socket
+            use watos_network::socket::SocketState;
+            let Some(id) = fd_socket(arg1 as i64) else { return u64::MAX };
+            match on_kernel_tables(|| watos_network::socket::state(id)) {
+                Ok(SocketState::Connecting) => 0,
+                Ok(SocketState::Open) => 1,
+                Ok(SocketState::Closed) => 2,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_NET_PEER => {
+            // arg1 = connection fd
+            // Returns the peer's address << 16 | port, u64::MAX if unknown
+            let Some(id) = fd_socket(arg1 as i64) else { return u64::MAX };
+            match on_kernel_tables(|| watos_network::socket::peer(id)) {
+                Ok((addr, port)) => (u32::from_be_bytes(addr.0) as u64) << 16 | port as u64,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_NET_RESOLVE => {
+            // arg1 = host name pointer, arg2 = host name length
+            // Returns a query id for SYS_NET_RESOLVED, u64::MAX on error
+            let name_len = arg2 as usize;
+            if arg1 == 0 || name_len == 0 || name_len > 255 {
+                return u64::MAX;
+            }
+            let Ok(name_bytes) = watos_mem::read_user_bytes(arg1, name_len) else { return u64::MAX };
+            let Ok(name) = core::str::from_utf8(&name_bytes) else { return u64::MAX };
+            on_kernel_tables(|| watos_network::socket::resolve(name)).unwrap_or(u64::MAX)
+        }
+
+        syscall::SYS_NET_RESOLVED => {
+            // arg1 = query id
+            // Returns 0 while the query is out, 1 << 32 | IPv4 address
+            // once answered, u64::MAX if the name wasn't found
+            match on_kernel_tables(|| watos_network::socket::resolved(arg1)) {
+                Ok(Some(addr)) => 1 << 32 | u32::from_be_bytes(addr.0) as u64,
+                Ok(None) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        syscall::SYS_GETRANDOM => {
+            // arg1 = buffer pointer, arg2 = length (up to 256)
+            // Returns the bytes filled, u64::MAX on error
+            let len = arg2 as usize;
+            if arg1 == 0 || len > 256 {
                 return u64::MAX;
             }
-
-            if let Some(mode) = watos_driver_video::get_session_info(session_id) {
-                unsafe {
-                    // Write VideoMode: width, height, bpp, format
-                    *buf_ptr = mode.width;
-                    *buf_ptr.add(1) = mode.height;
-                    *buf_ptr.add(2) = mode.bpp as u32;
-                    *buf_ptr.add(3) = mode.format as u32;
-                }
-                0
-            } else {
-                u64::MAX
+            let mut buf = [0u8; 256];
+            watos_keyring::random_bytes(&mut buf[..len]);
+            let result = watos_mem::copy_to_user(&buf[..len], arg1);
+            buf.fill(0);
+            match result {
+                Ok(()) => len as u64,
+                Err(_) => u64::MAX,
             }
         }
 
-        syscall::SYS_VGA_ENUMERATE_MODES => {
-            // arg1 = buffer pointer, arg2 = buffer size (in VideoMode structs)
-            // Returns number of modes written
-            let buf_ptr = arg1 as *mut u32;
-            let max_modes = arg2 as usize;
+        syscall::SYS_SNAPSHOT | syscall::SYS_SNAPSHOT_MOUNT => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // arg3 = snapshot name pointer
+            // r10 = snapshot name length
+            // SYS_SNAPSHOT takes a snapshot of the path and returns 0;
+            // SYS_SNAPSHOT_MOUNT mounts one of the snapshots on the path's
+            // filesystem read-only and returns its drive letter (ASCII).
+            // Returns u64::MAX on error
+            let path_len = arg2 as usize;
+            let name_ptr = arg3 as *const u8;
+            let name_len = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
 
-            if buf_ptr.is_null() || max_modes == 0 {
-                return 0;
+            if arg1 == 0 || name_ptr.is_null() || path_len == 0 || name_len == 0
+                || path_len > 256 || name_len > 256 {
+                return u64::MAX;
             }
 
-            let modes = watos_driver_video::get_available_modes();
-            let count = modes.len().min(max_modes);
-
-            unsafe {
-                for (i, mode) in modes.iter().take(count).enumerate() {
-                    let offset = i * 4; // 4 u32s per VideoMode
-                    *buf_ptr.add(offset) = mode.width;
-                    *buf_ptr.add(offset + 1) = mode.height;
-                    *buf_ptr.add(offset + 2) = mode.bpp as u32;
-                    *buf_ptr.add(offset + 3) = mode.format as u32;
-                }
+            // Copy path and name from user memory
+            let mut path_buf = [0u8; 256];
+            let mut name_buf = [0u8; 256];
+            if watos_mem::copy_from_user(arg1, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
+            }
+            if watos_mem::copy_from_user(name_ptr as u64, &mut name_buf[..name_len]).is_err() {
+                return u64::MAX;
             }
 
-            count as u64
-        }
+            let (path_str, name_str) = match (
+                core::str::from_utf8(&path_buf[..path_len]),
+                core::str::from_utf8(&name_buf[..name_len]),
+            ) {
+                (Ok(p), Ok(n)) => (p, n),
+                _ => return u64::MAX,
+            };
 
-        syscall::SYS_AUTHENTICATE => {
-            // arg1 = username pointer
-            // arg2 = username length
-            // arg3 = password pointer
-            // Returns UID on success, u64::MAX on failure
-            // Note: Password length is determined by null terminator (max 64 bytes)
-            let username_ptr = arg1 as *const u8;
-            let username_len = arg2 as usize;
-            let password_ptr = arg3 as *const u8;
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
 
-            if username_ptr.is_null() || username_len == 0 || password_ptr.is_null() {
-                return u64::MAX;
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
             }
 
-            unsafe {
-                let username = core::slice::from_raw_parts(username_ptr, username_len);
-                
-                // Safely find password length (null-terminated, max 64 bytes)
-                let mut password_len = 0usize;
-                for i in 0..64 {
-                    if *password_ptr.add(i) == 0 {
-                        break;
-                    }
-                    password_len += 1;
-                }
-                
-                // Validate we found a null terminator
-                if password_len == 64 && *password_ptr.add(63) != 0 {
-                    watos_arch::serial_write(b"[KERNEL] Password too long or not null-terminated\r\n");
-                    return u64::MAX;
-                }
-                
-                let password = core::slice::from_raw_parts(password_ptr, password_len);
+            let result = if num == syscall::SYS_SNAPSHOT {
+                watos_vfs::snapshot(path_str, name_str).map(|()| 0)
+            } else {
+                watos_vfs::update(|v| match (b'E'..=b'Z').map(|c| c as char).find(|&c| v.get_drive(c).is_none()) {
+                    Some(letter) => v.mount_snapshot(path_str, name_str, letter).map(|()| letter as u64),
+                    None => Err(watos_vfs::VfsError::NoSpace),
+                })
+            };
 
-                watos_arch::serial_write(b"[KERNEL] SYS_AUTHENTICATE: user=");
-                watos_arch::serial_write(username);
-                watos_arch::serial_write(b"\r\n");
+            // Restore user page table
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
 
-                // Authenticate via users subsystem
-                match watos_users::authenticate(username, password) {
-                    Some(uid) => {
-                        watos_arch::serial_write(b"[KERNEL] Authentication successful, UID=");
-                        watos_arch::serial_hex(uid as u64);
+            match result {
+                Ok(value) => {
+                    unsafe {
+                        watos_arch::serial_write(if num == syscall::SYS_SNAPSHOT {
+                            b"[KERNEL] Snapshot taken: "
+                        } else {
+                            b"[KERNEL] Snapshot mounted: "
+                        });
+                        watos_arch::serial_write(name_str.as_bytes());
                         watos_arch::serial_write(b"\r\n");
-                        uid as u64
-                    }
-                    None => {
-                        watos_arch::serial_write(b"[KERNEL] Authentication failed\r\n");
-                        u64::MAX
                     }
+                    value
                 }
+                Err(_) => u64::MAX,
             }
         }
 
-        syscall::SYS_SETUID => {
-            // arg1 = UID to set
-            let uid = arg1 as u32;
-            
-            unsafe {
-                watos_arch::serial_write(b"[KERNEL] SYS_SETUID: ");
-                watos_arch::serial_hex(uid as u64);
-                watos_arch::serial_write(b"\r\n");
+        syscall::SYS_SNAPSHOT_LIST => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // arg3 = buffer pointer
+            // r10 = buffer size
+            // Fills the buffer with the names of the snapshots on the
+            // path's filesystem, one per line, oldest first.
+            // Returns bytes written, or u64::MAX on error
+            let path_len = arg2 as usize;
+            let buf_ptr = arg3 as *mut u8;
+            let buf_size = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
+
+            if arg1 == 0 || buf_ptr.is_null() || path_len == 0 || path_len > 256 {
+                return u64::MAX;
             }
 
-            if watos_process::set_current_uid(uid) {
-                0
-            } else {
-                u64::MAX
+            let mut path_buf = [0u8; 256];
+            if watos_mem::copy_from_user(arg1, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
             }
-        }
 
-        syscall::SYS_GETUID => {
-            // Returns current process UID
-            watos_process::get_current_uid() as u64
-        }
+            let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
+                Ok(s) => s,
+                Err(_) => return u64::MAX,
+            };
 
-        syscall::SYS_GETGID => {
-            // Returns current process GID
-            watos_process::get_current_gid() as u64
-        }
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
 
-        syscall::SYS_SETGID => {
-            // arg1 = GID to set
-            let gid = arg1 as u32;
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
 
-            if watos_process::set_current_gid(gid) {
-                0
-            } else {
-                u64::MAX
+            let result = watos_vfs::snapshots(path_str);
+
+            // Restore user page table
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
             }
-        }
 
-        syscall::SYS_GETEUID => {
-            // Returns effective UID (same as UID for now - no setuid support)
-            watos_process::get_current_uid() as u64
-        }
+            let names = match result {
+                Ok(names) => names,
+                Err(_) => return u64::MAX,
+            };
 
-        syscall::SYS_GETEGID => {
-            // Returns effective GID (same as GID for now - no setgid support)
-            watos_process::get_current_gid() as u64
+            // Whole lines only, so a short buffer never ends in half a name
+            let mut buf = alloc::vec::Vec::new();
+            for name in &names {
+                if buf.len() + name.len() + 1 > buf_size {
+                    break;
+                }
+                buf.extend_from_slice(name.as_bytes());
+                buf.push(b'\n');
+            }
+            match watos_mem::copy_to_user(&buf, buf_ptr as u64) {
+                Ok(()) => buf.len() as u64,
+                Err(_) => u64::MAX,
+            }
         }
 
         syscall::SYS_CHMOD => {
@@ -2608,8 +6981,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
             // Copy path from user memory
             let mut path_buf = [0u8; 256];
-            unsafe {
-                core::ptr::copy_nonoverlapping(path_ptr, path_buf.as_mut_ptr(), path_len);
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
             }
 
             let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
@@ -2655,8 +7028,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
             // Copy path from user memory
             let mut path_buf = [0u8; 256];
-            unsafe {
-                core::ptr::copy_nonoverlapping(path_ptr, path_buf.as_mut_ptr(), path_len);
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
             }
 
             let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
@@ -2685,6 +7058,115 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
             }
         }
 
+        syscall::SYS_REALPATH => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // arg3 = buffer pointer
+            // r10 = buffer size
+            // Returns bytes written (e.g., "C:/apps/ls"), 0 on error
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+            let buf_ptr = arg3 as *mut u8;
+            let buf_size = unsafe { SAVED_SYSCALL_REGS.r10 as usize };
+
+            if path_ptr.is_null() || path_len == 0 || path_len > 256 || buf_ptr.is_null() {
+                return 0;
+            }
+
+            // Copy path from user memory
+            let mut path_buf = [0u8; 256];
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
+            }
+
+            let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
+                Ok(s) => s,
+                Err(_) => return 0,
+            };
+
+            // Relative paths start from the working directory (shared by all processes)
+            let mut cwd_buf = [0u8; MAX_PATH_LEN + MAX_DRIVE_NAME + 1];
+            let cwd_len = get_cwd(&mut cwd_buf);
+            let cwd_str = core::str::from_utf8(&cwd_buf[..cwd_len]).unwrap_or("");
+
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = watos_vfs::canonicalize(path_str, cwd_str);
+
+            // Restore user page table before writing to user buffer
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                Ok(canonical) if canonical.len() <= buf_size => {
+                    match watos_mem::copy_to_user(canonical.as_bytes(), buf_ptr as u64) {
+                        Ok(()) => canonical.len() as u64,
+                        Err(_) => 0,
+                    }
+                }
+                _ => 0,
+            }
+        }
+
+        syscall::SYS_WATCH => {
+            // arg1 = path pointer
+            // arg2 = path length
+            // arg3 = event mask (watos_syscall::watch)
+            // Returns an fd that reads as event lines, u64::MAX on error
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+            let mask = arg3 as u32;
+
+            if path_ptr.is_null() || path_len == 0 || path_len > 256 || mask == 0 {
+                return u64::MAX;
+            }
+
+            // Copy path from user memory
+            let mut path_buf = [0u8; 256];
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
+            }
+
+            let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
+                Ok(s) => s,
+                Err(_) => return u64::MAX,
+            };
+
+            unsafe {
+                watos_arch::serial_write(b"[KERNEL] SYS_WATCH: ");
+                watos_arch::serial_write(path_str.as_bytes());
+                watos_arch::serial_write(b"\r\n");
+            }
+
+            // Switch to kernel page table for disk access
+            let user_cr3 = watos_mem::paging::get_cr3();
+            let kernel_pml4 = watos_process::get_kernel_pml4();
+
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(kernel_pml4); }
+            }
+
+            let result = watos_vfs::watch(path_str, mask);
+
+            // Restore user page table
+            if kernel_pml4 != 0 && user_cr3 != kernel_pml4 {
+                unsafe { watos_mem::paging::load_cr3(user_cr3); }
+            }
+
+            match result {
+                // A full fd table gives -1, which is u64::MAX here too
+                Ok(handle) => fd_alloc(handle) as u64,
+                Err(_) => u64::MAX,
+            }
+        }
+
         syscall::SYS_ACCESS => {
             // arg1 = path pointer
             // arg2 = path length
@@ -2700,8 +7182,8 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
 
             // Copy path from user memory
             let mut path_buf = [0u8; 256];
-            unsafe {
-                core::ptr::copy_nonoverlapping(path_ptr, path_buf.as_mut_ptr(), path_len);
+            if watos_mem::copy_from_user(path_ptr as u64, &mut path_buf[..path_len]).is_err() {
+                return u64::MAX;
             }
 
             let path_str = match core::str::from_utf8(&path_buf[..path_len]) {
@@ -2745,14 +7227,12 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return u64::MAX;
             }
 
-            unsafe {
-                let name_bytes = core::slice::from_raw_parts(name_ptr, name_len);
-                let name_str = core::str::from_utf8(name_bytes).unwrap_or("session");
-                
-                match watos_console::manager().create_console(name_str, task_id) {
-                    Some(id) => id as u64,
-                    None => u64::MAX,
-                }
+            let Ok(name_bytes) = watos_mem::read_user_bytes(name_ptr as u64, name_len.min(256)) else { return u64::MAX };
+            let name_str = core::str::from_utf8(&name_bytes).unwrap_or("session");
+
+            match watos_console::manager().create_console(name_str, task_id) {
+                Some(id) => id as u64,
+                None => u64::MAX,
             }
         }
 
@@ -2780,19 +7260,22 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 1; // Error
             }
 
-            unsafe {
-                let key_slice = core::slice::from_raw_parts(key_ptr, key_len);
-                let val_slice = core::slice::from_raw_parts(val_ptr, val_len);
-
-                if let (Ok(key), Ok(val)) = (core::str::from_utf8(key_slice), core::str::from_utf8(val_slice)) {
-                    if watos_process::setenv(key, val) {
-                        0 // Success
-                    } else {
-                        1 // Error
-                    }
+            let Ok(key_slice) = watos_mem::read_user_bytes(key_ptr as u64, key_len) else { return 1 };
+            let val_slice = if val_len == 0 {
+                alloc::vec::Vec::new()
+            } else {
+                let Ok(val_slice) = watos_mem::read_user_bytes(val_ptr as u64, val_len) else { return 1 };
+                val_slice
+            };
+
+            if let (Ok(key), Ok(val)) = (core::str::from_utf8(&key_slice), core::str::from_utf8(&val_slice)) {
+                if watos_process::setenv(key, val) {
+                    0 // Success
                 } else {
-                    1 // Invalid UTF-8
+                    1 // Error
                 }
+            } else {
+                1 // Invalid UTF-8
             }
         }
 
@@ -2808,21 +7291,21 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 0; // Not found
             }
 
-            unsafe {
-                let key_slice = core::slice::from_raw_parts(key_ptr, key_len);
-
-                if let Ok(key) = core::str::from_utf8(key_slice) {
-                    if let Some(value) = watos_process::getenv(key) {
-                        let value_bytes = value.as_bytes();
-                        let copy_len = core::cmp::min(value_bytes.len(), buf_len);
-                        core::ptr::copy_nonoverlapping(value_bytes.as_ptr(), buf_ptr, copy_len);
-                        value_bytes.len() as u64 // Return actual length
-                    } else {
-                        0 // Not found
+            let Ok(key_slice) = watos_mem::read_user_bytes(key_ptr as u64, key_len) else { return 0 };
+
+            if let Ok(key) = core::str::from_utf8(&key_slice) {
+                if let Some(value) = watos_process::getenv(key) {
+                    let value_bytes = value.as_bytes();
+                    let copy_len = core::cmp::min(value_bytes.len(), buf_len);
+                    if copy_len > 0 && watos_mem::copy_to_user(&value_bytes[..copy_len], buf_ptr as u64).is_err() {
+                        return 0;
                     }
+                    value_bytes.len() as u64 // Return actual length
                 } else {
-                    0 // Invalid UTF-8
+                    0 // Not found
                 }
+            } else {
+                0 // Invalid UTF-8
             }
         }
 
@@ -2835,18 +7318,16 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return 1; // Error
             }
 
-            unsafe {
-                let key_slice = core::slice::from_raw_parts(key_ptr, key_len);
+            let Ok(key_slice) = watos_mem::read_user_bytes(key_ptr as u64, key_len) else { return 1 };
 
-                if let Ok(key) = core::str::from_utf8(key_slice) {
-                    if watos_process::unsetenv(key) {
-                        0 // Success
-                    } else {
-                        1 // Error
-                    }
+            if let Ok(key) = core::str::from_utf8(&key_slice) {
+                if watos_process::unsetenv(key) {
+                    0 // Success
                 } else {
-                    1 // Invalid UTF-8
+                    1 // Error
                 }
+            } else {
+                1 // Invalid UTF-8
             }
         }
 
@@ -2862,23 +7343,22 @@ fn handle_syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, return_rip: u64, re
                 return watos_process::listenv().len() as u64;
             }
 
-            unsafe {
-                let env_list = watos_process::listenv();
-                let mut offset = 0;
+            let env_list = watos_process::listenv();
+            let mut buf = alloc::vec::Vec::new();
 
-                for entry in env_list.iter() {
-                    let entry_bytes = entry.as_bytes();
-                    if offset + entry_bytes.len() + 1 > buf_len {
-                        break; // Buffer full
-                    }
-                    core::ptr::copy_nonoverlapping(entry_bytes.as_ptr(), buf_ptr.add(offset), entry_bytes.len());
-                    offset += entry_bytes.len();
-                    *buf_ptr.add(offset) = 0; // Null terminator
-                    offset += 1;
+            for entry in env_list.iter() {
+                let entry_bytes = entry.as_bytes();
+                if buf.len() + entry_bytes.len() + 1 > buf_len {
+                    break; // Buffer full
                 }
-
-                env_list.len() as u64
+                buf.extend_from_slice(entry_bytes);
+                buf.push(0); // Null terminator
+            }
+            if !buf.is_empty() && watos_mem::copy_to_user(&buf, buf_ptr as u64).is_err() {
+                return 0;
             }
+
+            env_list.len() as u64
         }
 
         _ => {
@@ -2920,6 +7400,7 @@ fn panic(info: &PanicInfo) -> ! {
             watos_arch::serial_write(b"\r\n");
         }
     }
+    ksyms_backtrace();
     loop {
         watos_arch::halt();
     }
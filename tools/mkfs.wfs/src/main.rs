@@ -6,10 +6,12 @@
 //! - Extent-based file storage
 //! - Crash-safe without journal
 //! - Checksums on all metadata
+//! - Optional LZ4 compression of file data
 //!
 //! Usage:
 //!   mkfs.wfs -o disk.img -s 64M          # Create 64MB v1 disk image
 //!   mkfs.wfs -o disk.img -s 1G           # Create 1GB v1 disk image
+//!   mkfs.wfs -o disk.img -s 64M -d rootfs -c   # Copy rootfs in, compressed
 
 use clap::Parser;
 use std::cell::UnsafeCell;
@@ -21,9 +23,8 @@ use std::path::{Path, PathBuf};
 use wfs_common::*;
 use wfs_common::core::{
     Superblock, TreeNode, NodeType, Inode, BPlusTree, BlockDevice, BlockAllocator, TreeError,
-    TreeOps, BLOCK_SIZE, ROOT_INODE, InodeOps, DirOps, FilesystemState,
+    TreeOps, BLOCK_SIZE, ROOT_INODE, InodeOps, DirOps, FileOps, FilesystemState,
 };
-use wfs_common::core::inode::INODE_INLINE_SIZE;
 use wfs_common::core::dir::{DirEntry, EntryType};
 
 #[derive(Parser)]
@@ -42,6 +43,10 @@ struct Args {
     #[arg(short, long)]
     dir: Option<PathBuf>,
 
+    /// Compress file data; files keep compressing when rewritten
+    #[arg(short, long)]
+    compress: bool,
+
     /// Verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -65,6 +70,7 @@ fn parse_size(s: &str) -> Option<u64> {
 struct FileBlockDevice {
     file: UnsafeCell<File>,
     next_block: UnsafeCell<u64>,
+    total_blocks: u64,
 }
 
 impl FileBlockDevice {
@@ -81,6 +87,7 @@ impl FileBlockDevice {
         Ok(Self {
             file: UnsafeCell::new(file),
             next_block: UnsafeCell::new(5),
+            total_blocks: size / BLOCK_SIZE as u64,
         })
     }
 
@@ -133,6 +140,9 @@ impl BlockAllocator for FileBlockDevice {
     fn allocate_block(&mut self) -> Result<u64, TreeError> {
         let next_block = unsafe { &mut *self.next_block.get() };
         let block = *next_block;
+        if block >= self.total_blocks {
+            return Err(TreeError::NodeFull);
+        }
         *next_block += 1;
 
         // Zero out the new block
@@ -155,6 +165,7 @@ fn populate_filesystem(
     device: &mut FileBlockDevice,
     state: &mut FilesystemState,
     source_dir: &Path,
+    compress: bool,
     verbose: bool,
 ) -> std::io::Result<()> {
     let mut file_count = 0;
@@ -170,6 +181,7 @@ fn populate_filesystem(
         file_count: &mut usize,
         dir_count: &mut usize,
         skipped_count: &mut usize,
+        compress: bool,
         verbose: bool,
     ) -> std::io::Result<()> {
         for entry in fs::read_dir(source_path)? {
@@ -203,35 +215,30 @@ fn populate_filesystem(
 
                 // Recursively populate subdirectory
                 populate_recursive(device, state, &path, dir_inode_num,
-                                 file_count, dir_count, skipped_count, verbose)?;
+                                 file_count, dir_count, skipped_count, compress, verbose)?;
 
             } else if metadata.is_file() {
                 let file_size = metadata.len();
 
-                // Only support small files that fit in inline data
-                if file_size <= INODE_INLINE_SIZE as u64 {
-                    // Read file contents
-                    let mut file_data = Vec::new();
-                    let mut file = File::open(&path)?;
-                    file.read_to_end(&mut file_data)?;
+                // Read file contents
+                let mut file_data = Vec::new();
+                let mut file = File::open(&path)?;
+                file.read_to_end(&mut file_data)?;
 
-                    // Create file in WFS
-                    if let Err(e) = create_file(device, state, wfs_parent_inode_num,
-                                                &name_str, &file_data, verbose) {
+                // Create file in WFS
+                let blocks = match create_file(device, state, wfs_parent_inode_num,
+                                               &name_str, &file_data, compress) {
+                    Ok(blocks) => blocks,
+                    Err(e) => {
                         eprintln!("Failed to create file {}: {:?}", name_str, e);
                         *skipped_count += 1;
                         continue;
                     }
+                };
 
-                    *file_count += 1;
-                    if verbose {
-                        println!("  FILE: {} ({} bytes)", name_str, file_size);
-                    }
-                } else {
-                    if verbose {
-                        println!("  SKIP: {} ({} bytes, too large)", name_str, file_size);
-                    }
-                    *skipped_count += 1;
+                *file_count += 1;
+                if verbose {
+                    println!("  FILE: {} ({} bytes, {} blocks)", name_str, file_size, blocks);
                 }
             }
         }
@@ -240,7 +247,7 @@ fn populate_filesystem(
     }
 
     populate_recursive(device, state, source_dir, ROOT_INODE,
-                      &mut file_count, &mut dir_count, &mut skipped_count, verbose)?;
+                      &mut file_count, &mut dir_count, &mut skipped_count, compress, verbose)?;
 
     println!("\nPopulation complete:");
     println!("  Files:   {}", file_count);
@@ -338,21 +345,19 @@ fn create_directory(
     Ok(())
 }
 
-/// Create a file in WFS with inline data
+/// Create a file in WFS, inline if small enough
+///
+/// Returns the number of data blocks it took.
 fn create_file(
     device: &mut FileBlockDevice,
     state: &mut FilesystemState,
     parent_inode_num: u64,
     name: &str,
     data: &[u8],
-    verbose: bool,
-) -> std::io::Result<()> {
-    if data.len() > INODE_INLINE_SIZE {
-        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "File too large"));
-    }
-
+    compress: bool,
+) -> std::io::Result<u64> {
     let dev_ptr = device as *mut FileBlockDevice;
-    let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+    let (dev_ref, alloc_ref, data_dev) = unsafe { (&mut *dev_ptr, &mut *dev_ptr, &mut *dev_ptr) };
     let mut ops = TreeOps::new(dev_ref, alloc_ref);
 
     // Get parent inode
@@ -363,16 +368,17 @@ fn create_file(
     // Allocate new inode
     let inode_num = InodeOps::allocate_inode_num(state);
 
-    // Create file inode with inline data
+    // Create file inode and write its data (inline if it fits)
     let mut file_inode = Inode::new(inode_num, S_IFREG | 0o644);
-    file_inode.size = data.len() as u64;
-    file_inode.inline_size = data.len() as u16;
-    file_inode.inline_data[..data.len()].copy_from_slice(data);
     file_inode.nlink = 1;
+    if compress {
+        file_inode.flags |= INODE_COMPRESS;
+    }
 
-    // Insert file inode
     InodeOps::insert(&mut ops, state, file_inode)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
+    FileOps::write(&mut ops, data_dev, state, &mut file_inode, 0, data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
 
     // Add entry to parent directory
     let entry = DirEntry::new(name, inode_num, EntryType::File)
@@ -392,7 +398,7 @@ fn create_file(
     InodeOps::insert(&mut ops, state, parent_inode)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
 
-    Ok(())
+    Ok(file_inode.blocks)
 }
 
 fn create_wfs_v1(path: &PathBuf, size: u64, verbose: bool) -> std::io::Result<(FileBlockDevice, FilesystemState)> {
@@ -463,15 +469,7 @@ fn create_wfs_v1(path: &PathBuf, size: u64, verbose: bool) -> std::io::Result<(F
         .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to write dir tree"))?;
 
     // Write superblocks (primary and backup)
-    let mut sb_block = [0u8; BLOCK_SIZE as usize];
-    let sb_bytes = unsafe {
-        std::slice::from_raw_parts(&superblock as *const _ as *const u8,
-            std::mem::size_of::<Superblock>())
-    };
-    sb_block[..sb_bytes.len()].copy_from_slice(sb_bytes);
-
-    device.write_block_raw(0, &sb_block)?;
-    device.write_block_raw(1, &sb_block)?;
+    write_superblocks(&mut device, &mut superblock)?;
 
     // Sync to ensure all writes are flushed before we start using TreeOps
     device.sync().map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Initial sync failed"))?;
@@ -495,6 +493,27 @@ fn create_wfs_v1(path: &PathBuf, size: u64, verbose: bool) -> std::io::Result<(F
     Ok((device, state))
 }
 
+/// Write the primary and backup superblocks
+///
+/// The superblock lives in the payload of a tree node, the layout
+/// `FilesystemOps::read_superblock` (and so the kernel) expects.
+fn write_superblocks(device: &mut FileBlockDevice, superblock: &mut Superblock) -> std::io::Result<()> {
+    superblock.update_crc();
+
+    let mut node = TreeNode::default();
+    let sb_bytes = unsafe {
+        std::slice::from_raw_parts(superblock as *const _ as *const u8,
+            std::mem::size_of::<Superblock>())
+    };
+    node.data[..sb_bytes.len()].copy_from_slice(sb_bytes);
+
+    for block in [0, 1] {
+        device.write_node(block, &node)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to write superblock"))?;
+    }
+    Ok(())
+}
+
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
@@ -521,22 +540,18 @@ fn main() -> std::io::Result<()> {
         }
 
         println!("\nPopulating filesystem from: {}", dir.display());
-        println!("Note: Only small files (<160 bytes) will be copied.");
-        println!("      Larger files will be skipped.\n");
-
-        populate_filesystem(&mut device, &mut state, dir, args.verbose)?;
+        if args.compress {
+            println!("  Compressing file data\n");
+        }
 
-        // Write updated superblock
-        let mut sb_block = [0u8; BLOCK_SIZE as usize];
-        let sb_bytes = unsafe {
-            std::slice::from_raw_parts(&state.superblock as *const _ as *const u8,
-                std::mem::size_of::<Superblock>())
-        };
-        sb_block[..sb_bytes.len()].copy_from_slice(sb_bytes);
-        device.write_block_raw(0, &sb_block)?;
-        device.write_block_raw(1, &sb_block)?;
+        populate_filesystem(&mut device, &mut state, dir, args.compress, args.verbose)?;
     }
 
+    // Write updated superblock. Inserts are copy-on-write and mkfs runs no
+    // transaction, so point it at the current inode tree root by hand.
+    state.superblock.root_tree_block = state.inode_tree.root_block;
+    write_superblocks(&mut device, &mut state.superblock)?;
+
     // Final sync
     device.sync().map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Final sync failed"))?;
 
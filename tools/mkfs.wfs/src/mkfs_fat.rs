@@ -0,0 +1,187 @@
+//! mkfs_fat - Create FAT12/16/32 disk images
+//!
+//! Uses the FAT driver's own formatter (watos_fat::format), so the images
+//! match what WATOS reads. Populate the result with mtools (mcopy, mmd).
+//!
+//! Usage:
+//!   mkfs_fat -o esp.img -s 64M -F 32 -n WATOS_BOOT   # EFI System Partition
+//!   mkfs_fat -o floppy.img -s 1440K                  # FAT12 floppy
+//!   mkfs_fat -o disk.img -s 256M -c 4K               # FAT16, 4 KiB clusters
+
+use clap::Parser;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use watos_driver_traits::block::{BlockDevice, BlockGeometry};
+use watos_driver_traits::DriverError;
+use watos_fat::{FatType, FormatOptions};
+
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Parser)]
+#[command(name = "mkfs_fat")]
+#[command(about = "Create FAT12/16/32 disk images")]
+struct Args {
+    /// Output disk image file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Disk size (e.g., 1440K, 64M, 1G)
+    #[arg(short, long)]
+    size: String,
+
+    /// FAT type: 12, 16 or 32 (default: chosen from the size)
+    #[arg(short = 'F', long = "fat")]
+    fat: Option<u8>,
+
+    /// Cluster size in bytes (e.g., 512, 4K); power of two up to 64K
+    #[arg(short, long)]
+    cluster_size: Option<String>,
+
+    /// Volume label (up to 11 characters)
+    #[arg(short = 'n', long)]
+    label: Option<String>,
+
+    /// Keep a metadata journal in the reserved sectors (crash-safe writes
+    /// under WATOS; other systems see plain FAT)
+    #[arg(short, long)]
+    journal: bool,
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim().to_uppercase();
+    let (num_str, mult) = if s.ends_with("G") || s.ends_with("GB") {
+        (s.trim_end_matches("GB").trim_end_matches("G"), 1024 * 1024 * 1024)
+    } else if s.ends_with("M") || s.ends_with("MB") {
+        (s.trim_end_matches("MB").trim_end_matches("M"), 1024 * 1024)
+    } else if s.ends_with("K") || s.ends_with("KB") {
+        (s.trim_end_matches("KB").trim_end_matches("K"), 1024)
+    } else {
+        (s.as_str(), 1)
+    };
+
+    num_str.parse::<u64>().ok().map(|n| n * mult)
+}
+
+/// Image file as a 512-byte sector device
+struct ImageFile {
+    file: File,
+    total_sectors: u64,
+}
+
+impl BlockDevice for ImageFile {
+    fn geometry(&self) -> BlockGeometry {
+        BlockGeometry {
+            sector_size: SECTOR_SIZE as u32,
+            total_sectors: self.total_sectors,
+            optimal_transfer: 64,
+        }
+    }
+
+    fn read_sectors(&mut self, start: u64, buffer: &mut [u8]) -> Result<usize, DriverError> {
+        self.file
+            .read_exact_at(buffer, start * SECTOR_SIZE)
+            .map_err(|_| DriverError::IoError)?;
+        Ok(buffer.len())
+    }
+
+    fn write_sectors(&mut self, start: u64, buffer: &[u8]) -> Result<usize, DriverError> {
+        self.file
+            .write_all_at(buffer, start * SECTOR_SIZE)
+            .map_err(|_| DriverError::IoError)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), DriverError> {
+        self.file.sync_all().map_err(|_| DriverError::IoError)
+    }
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let size = parse_size(&args.size).ok_or_else(|| invalid("Invalid size format"))?;
+    let total_sectors = size / SECTOR_SIZE;
+
+    let fat_type = match args.fat {
+        None => None,
+        Some(12) => Some(FatType::Fat12),
+        Some(16) => Some(FatType::Fat16),
+        Some(32) => Some(FatType::Fat32),
+        Some(_) => return Err(invalid("FAT type must be 12, 16 or 32")),
+    };
+
+    let sectors_per_cluster = match args.cluster_size {
+        None => None,
+        Some(ref s) => {
+            let bytes = parse_size(s).ok_or_else(|| invalid("Invalid cluster size"))?;
+            let sectors = bytes / SECTOR_SIZE;
+            if bytes % SECTOR_SIZE != 0 || !(1..=128).contains(&sectors) || !sectors.is_power_of_two() {
+                return Err(invalid("Cluster size must be a power of two from 512 to 64K"));
+            }
+            Some(sectors as u8)
+        }
+    };
+
+    // Serial number from the creation time, as DOS FORMAT does
+    let volume_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32 ^ d.subsec_nanos())
+        .unwrap_or(0);
+
+    let options = FormatOptions {
+        fat_type,
+        sectors_per_cluster,
+        volume_label: args.label.clone(),
+        volume_id,
+        journal: args.journal,
+    };
+
+    println!("Creating FAT disk image: {}", args.output.display());
+    println!("  Size: {} bytes ({} sectors)", total_sectors * SECTOR_SIZE, total_sectors);
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&args.output)?;
+    file.set_len(total_sectors * SECTOR_SIZE)?;
+
+    let mut device = ImageFile { file, total_sectors };
+    let bpb = match watos_fat::format(&mut device, &options) {
+        Ok(bpb) => bpb,
+        Err(e) => {
+            // Don't leave a half-written image behind
+            let _ = std::fs::remove_file(&args.output);
+            return Err(std::io::Error::other(format!(
+                "Format failed: {:?} (check size, FAT type and cluster size)",
+                e
+            )));
+        }
+    };
+
+    let fat_type = match bpb.fat_type() {
+        FatType::Fat12 => "FAT12",
+        FatType::Fat16 => "FAT16",
+        FatType::Fat32 => "FAT32",
+    };
+    println!("  Type:     {}", fat_type);
+    println!("  Label:    {}", bpb.volume_label_str());
+    println!("  Serial:   {:04X}-{:04X}", bpb.volume_id >> 16, bpb.volume_id & 0xFFFF);
+    println!("  Cluster:  {} bytes", bpb.sectors_per_cluster as u64 * SECTOR_SIZE);
+    println!("  Clusters: {}", bpb.cluster_count());
+    println!("  FAT size: {} sectors (x{})", bpb.fat_size(), bpb.num_fats);
+    if args.journal {
+        println!("  Journal:  {} reserved sectors", watos_fat::JOURNAL_SECTORS);
+    }
+
+    println!("\nDone! {} filesystem created.", fat_type);
+    Ok(())
+}
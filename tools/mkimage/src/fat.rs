@@ -0,0 +1,465 @@
+//! FAT partitions
+//!
+//! The volume is formatted with the driver's own `watos_fat::format`, then
+//! filled directly: files and directories get contiguous cluster runs in
+//! tree order, the FAT is kept in memory and written to every copy at the
+//! end, and FSInfo is updated on FAT32.
+//!
+//! Names that fit 8.3 get a single short entry (lower-case names use the
+//! NT case bits, as Windows and mtools do); anything else gets long name
+//! entries plus a `~N` short alias. All timestamps are 1980-01-01 00:00 so
+//! images are reproducible.
+
+use std::collections::BTreeSet;
+
+use wfs_common::crc32;
+use watos_driver_traits::block::{BlockDevice, BlockGeometry};
+use watos_driver_traits::DriverError;
+use watos_fat::{BiosParameterBlock, FatType, FormatOptions, FsInfo};
+
+use crate::manifest::{self, PartitionSpec};
+use crate::tree::{Dir, Node};
+use crate::{error, Window, SECTOR_SIZE};
+
+const ENTRY_SIZE: usize = 32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LFN: u8 = 0x0F;
+
+/// NT case bits: base / extension stored upper-case but shown lower-case
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+/// 1980-01-01, the FAT epoch
+const FIXED_DATE: u16 = 0x0021;
+
+/// UTF-16 units per long name entry
+const LFN_CHARS: usize = 13;
+const LFN_MAX: usize = 255;
+
+impl BlockDevice for Window {
+    fn geometry(&self) -> BlockGeometry {
+        BlockGeometry {
+            sector_size: SECTOR_SIZE as u32,
+            total_sectors: self.sectors(),
+            optimal_transfer: 64,
+        }
+    }
+
+    fn read_sectors(&mut self, start: u64, buffer: &mut [u8]) -> Result<usize, DriverError> {
+        self.read_at(buffer, start * SECTOR_SIZE)
+            .map_err(|_| DriverError::IoError)?;
+        Ok(buffer.len())
+    }
+
+    fn write_sectors(&mut self, start: u64, buffer: &[u8]) -> Result<usize, DriverError> {
+        self.write_at(buffer, start * SECTOR_SIZE)
+            .map_err(|_| DriverError::IoError)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+/// One name in a directory, with its on-disk short name
+struct Name<'a> {
+    long: &'a str,
+    short: [u8; 11],
+    case: u8,
+    /// Needs long name entries
+    lfn: bool,
+}
+
+impl Name<'_> {
+    /// Directory entries this name takes
+    fn slots(&self) -> usize {
+        if self.lfn {
+            self.long.encode_utf16().count().div_ceil(LFN_CHARS) + 1
+        } else {
+            1
+        }
+    }
+}
+
+struct FatWriter {
+    window: Window,
+    bpb: BiosParameterBlock,
+    fat_type: FatType,
+    /// First copy of the FAT, written to every copy at the end
+    fat: Vec<u8>,
+    /// Next unallocated cluster
+    next_cluster: u32,
+    verbose: bool,
+    files: usize,
+    dirs: usize,
+}
+
+/// Format the partition and copy `files` into it
+pub fn build(mut window: Window, part: &PartitionSpec, files: &Dir, verbose: bool) -> std::io::Result<()> {
+    let sectors_per_cluster = match part.cluster_size {
+        None => None,
+        Some(ref s) => {
+            let sectors = manifest::size_sectors(s, &part.name)?;
+            if !(1..=128).contains(&sectors) || !sectors.is_power_of_two() {
+                return Err(error(format!("partition '{}': cluster size must be a power of two from 512 to 64K", part.name)));
+            }
+            Some(sectors as u8)
+        }
+    };
+
+    let options = FormatOptions {
+        fat_type: part.fat_type(),
+        sectors_per_cluster,
+        volume_label: part.label.clone(),
+        // Reproducible serial number
+        volume_id: crc32(part.name.as_bytes()),
+        journal: false,
+    };
+    let bpb = watos_fat::format(&mut window, &options).map_err(|e| {
+        std::io::Error::other(format!(
+            "partition '{}': format failed: {:?} (check size, FAT type and cluster size)",
+            part.name, e
+        ))
+    })?;
+
+    let fat_type = bpb.fat_type();
+    println!("  Type:     {}", match fat_type {
+        FatType::Fat12 => "FAT12",
+        FatType::Fat16 => "FAT16",
+        FatType::Fat32 => "FAT32",
+    });
+    println!("  Label:    {}", bpb.volume_label_str());
+    println!("  Cluster:  {} bytes", bpb.sectors_per_cluster as u64 * SECTOR_SIZE);
+
+    let mut fat = vec![0u8; bpb.fat_size() as usize * SECTOR_SIZE as usize];
+    window.read_at(&mut fat, bpb.reserved_sector_count as u64 * SECTOR_SIZE)?;
+
+    let mut writer = FatWriter {
+        window,
+        next_cluster: if fat_type == FatType::Fat32 { bpb.root_cluster + 1 } else { 2 },
+        bpb,
+        fat_type,
+        fat,
+        verbose,
+        files: 0,
+        dirs: 0,
+    };
+    writer.write_root(files, part.label.is_some())?;
+    writer.finish()?;
+
+    println!("  Files:    {}", writer.files);
+    println!("  Dirs:     {}", writer.dirs);
+    println!("  Free:     {} of {} clusters",
+        writer.bpb.cluster_count() + 2 - writer.next_cluster, writer.bpb.cluster_count());
+    Ok(())
+}
+
+impl FatWriter {
+    fn cluster_bytes(&self) -> u64 {
+        self.bpb.sectors_per_cluster as u64 * SECTOR_SIZE
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn set_entry(&mut self, cluster: u32, value: u32) {
+        let n = cluster as usize;
+        match self.fat_type {
+            FatType::Fat12 => {
+                let at = n + n / 2;
+                let old = u16::from_le_bytes([self.fat[at], self.fat[at + 1]]);
+                let new = if n.is_multiple_of(2) {
+                    (old & 0xF000) | (value as u16 & 0x0FFF)
+                } else {
+                    (old & 0x000F) | ((value as u16) << 4)
+                };
+                self.fat[at..at + 2].copy_from_slice(&new.to_le_bytes());
+            }
+            FatType::Fat16 => self.fat[n * 2..n * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+            FatType::Fat32 => {
+                // The top four bits are reserved and preserved
+                let old = u32::from_le_bytes(self.fat[n * 4..n * 4 + 4].try_into().unwrap());
+                let new = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.fat[n * 4..n * 4 + 4].copy_from_slice(&new.to_le_bytes());
+            }
+        }
+    }
+
+    /// Allocate a contiguous chain for `bytes` (none for 0)
+    fn allocate(&mut self, bytes: u64) -> std::io::Result<u32> {
+        let count = bytes.div_ceil(self.cluster_bytes()) as u32;
+        if count == 0 {
+            return Ok(0);
+        }
+        let first = self.next_cluster;
+        if first as u64 + count as u64 > self.bpb.cluster_count() as u64 + 2 {
+            return Err(std::io::Error::other("partition is full"));
+        }
+        for cluster in first..first + count - 1 {
+            self.set_entry(cluster, cluster + 1);
+        }
+        self.set_entry(first + count - 1, self.end_of_chain());
+        self.next_cluster += count;
+        Ok(first)
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        let data_start = self.bpb.reserved_sector_count as u64
+            + self.bpb.num_fats as u64 * self.bpb.fat_size() as u64
+            + self.bpb.root_dir_sectors() as u64;
+        (data_start + (cluster as u64 - 2) * self.bpb.sectors_per_cluster as u64) * SECTOR_SIZE
+    }
+
+    fn write_root(&mut self, dir: &Dir, has_label: bool) -> std::io::Result<()> {
+        let names = dir_names(dir)?;
+        let slots = has_label as usize + names.iter().map(Name::slots).sum::<usize>();
+        let bytes = (slots * ENTRY_SIZE) as u64;
+
+        let offset = if self.fat_type == FatType::Fat32 {
+            // The formatter gave the root one cluster; chain on more if needed
+            let root = self.bpb.root_cluster;
+            let extra = bytes.saturating_sub(self.cluster_bytes());
+            let next = self.allocate(extra)?;
+            if next != 0 {
+                self.set_entry(root, next);
+            }
+            self.cluster_offset(root)
+        } else {
+            if slots > self.bpb.root_entry_count as usize {
+                return Err(error(format!(
+                    "root directory needs {} entries; FAT12/16 roots hold {}",
+                    slots, self.bpb.root_entry_count
+                )));
+            }
+            (self.bpb.reserved_sector_count as u64 + self.bpb.num_fats as u64 * self.bpb.fat_size() as u64)
+                * SECTOR_SIZE
+        };
+
+        let mut entries = Vec::with_capacity(slots * ENTRY_SIZE);
+        if has_label {
+            entries.extend(short_entry(&self.bpb.volume_label, ATTR_VOLUME_ID, 0, 0, 0));
+        }
+        self.write_children(dir, &names, 0, "", &mut entries)?;
+        self.window.write_at(&entries, offset)
+    }
+
+    /// Write the contents of `dir`, appending its entries to `entries`
+    ///
+    /// `self_cluster` is what children's ".." entries point at (0 for root).
+    fn write_children(
+        &mut self,
+        dir: &Dir,
+        names: &[Name],
+        self_cluster: u32,
+        path: &str,
+        entries: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        for name in names {
+            let child_path = format!("{}/{}", path, name.long);
+            let (cluster, attr, size) = match &dir[name.long] {
+                Node::File(src) => {
+                    let data = std::fs::read(src)?;
+                    let size = u32::try_from(data.len())
+                        .map_err(|_| error(format!("{}: too large for FAT", src.display())))?;
+                    let cluster = self.allocate(data.len() as u64)?;
+                    if cluster != 0 {
+                        self.window.write_at(&data, self.cluster_offset(cluster))?;
+                    }
+                    if self.verbose {
+                        println!("    FILE: {} ({} bytes)", child_path, size);
+                    }
+                    self.files += 1;
+                    (cluster, ATTR_ARCHIVE, size)
+                }
+                Node::Dir(sub) => {
+                    if self.verbose {
+                        println!("    DIR:  {}", child_path);
+                    }
+                    let sub_names = dir_names(sub)?;
+                    let slots = 2 + sub_names.iter().map(Name::slots).sum::<usize>();
+                    let cluster = self.allocate((slots * ENTRY_SIZE) as u64)?;
+
+                    let mut sub_entries = Vec::with_capacity(slots * ENTRY_SIZE);
+                    sub_entries.extend(short_entry(b".          ", ATTR_DIRECTORY, 0, cluster, 0));
+                    sub_entries.extend(short_entry(b"..         ", ATTR_DIRECTORY, 0, self_cluster, 0));
+                    self.write_children(sub, &sub_names, cluster, &child_path, &mut sub_entries)?;
+                    self.window.write_at(&sub_entries, self.cluster_offset(cluster))?;
+                    self.dirs += 1;
+                    (cluster, ATTR_DIRECTORY, 0)
+                }
+            };
+
+            if name.lfn {
+                entries.extend(lfn_entries(name.long, &name.short));
+            }
+            entries.extend(short_entry(&name.short, attr, name.case, cluster, size));
+        }
+        Ok(())
+    }
+
+    /// Write every FAT copy and, on FAT32, the FSInfo hints
+    fn finish(&mut self) -> std::io::Result<()> {
+        let fat_start = self.bpb.reserved_sector_count as u64;
+        for copy in 0..self.bpb.num_fats as u64 {
+            let sector = fat_start + copy * self.bpb.fat_size() as u64;
+            self.window.write_at(&self.fat, sector * SECTOR_SIZE)?;
+        }
+
+        if self.fat_type == FatType::Fat32 {
+            let free_count = self.bpb.cluster_count() + 2 - self.next_cluster;
+            let offset = self.bpb.fs_info_sector as u64 * SECTOR_SIZE;
+            let mut sector = [0u8; SECTOR_SIZE as usize];
+            self.window.read_at(&mut sector, offset)?;
+            FsInfo { free_count, next_free: self.next_cluster }.write(&mut sector);
+            self.window.write_at(&sector, offset)?;
+        }
+        Ok(())
+    }
+}
+
+fn short_entry(name: &[u8; 11], attr: u8, case: u8, cluster: u32, size: u32) -> [u8; ENTRY_SIZE] {
+    let mut e = [0u8; ENTRY_SIZE];
+    e[0..11].copy_from_slice(name);
+    e[11] = attr;
+    e[12] = case;
+    if attr & ATTR_VOLUME_ID == 0 {
+        // Creation, access and write dates; times stay 00:00:00
+        for at in [16, 18, 24] {
+            e[at..at + 2].copy_from_slice(&FIXED_DATE.to_le_bytes());
+        }
+    }
+    e[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    e[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    e[28..32].copy_from_slice(&size.to_le_bytes());
+    e
+}
+
+/// Long name entries for `long`, in on-disk order (last part first)
+fn lfn_entries(long: &str, short: &[u8; 11]) -> Vec<u8> {
+    let checksum = short.iter().fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c));
+
+    let mut units: Vec<u16> = long.encode_utf16().collect();
+    let count = units.len().div_ceil(LFN_CHARS);
+    // NUL-terminate unless the name exactly fills the last entry, pad with 0xFFFF
+    if !units.len().is_multiple_of(LFN_CHARS) {
+        units.push(0);
+    }
+    units.resize(count * LFN_CHARS, 0xFFFF);
+
+    let mut out = Vec::with_capacity(count * ENTRY_SIZE);
+    for seq in (1..=count).rev() {
+        let mut e = [0u8; ENTRY_SIZE];
+        e[0] = seq as u8 | if seq == count { 0x40 } else { 0 };
+        e[11] = ATTR_LFN;
+        e[13] = checksum;
+        let part = &units[(seq - 1) * LFN_CHARS..seq * LFN_CHARS];
+        let offsets = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (at, unit) in offsets.zip(part) {
+            e[at..at + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        out.extend(e);
+    }
+    out
+}
+
+fn is_short_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&c)
+}
+
+/// The short entry for a name that fits 8.3 as-is, with its case bits
+fn exact_short(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    if !base.bytes().chain(ext.bytes()).all(is_short_char) {
+        return None;
+    }
+
+    // Each part must be all one case to be shown correctly without LFN
+    let case_of = |part: &str, bit: u8| {
+        let lower = part.bytes().any(|c| c.is_ascii_lowercase());
+        let upper = part.bytes().any(|c| c.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => None,
+            (true, false) => Some(bit),
+            _ => Some(0),
+        }
+    };
+    let case = case_of(base, CASE_LOWER_BASE)? | case_of(ext, CASE_LOWER_EXT)?;
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    Some((short, case))
+}
+
+/// Upper-cased, filtered characters for a `~N` alias
+fn alias_part(s: &str) -> Vec<u8> {
+    s.bytes()
+        .filter(|&c| c != b' ' && c != b'.')
+        .map(|c| if is_short_char(c) { c.to_ascii_uppercase() } else { b'_' })
+        .collect()
+}
+
+/// Short names for every entry of `dir`, in tree (sorted) order
+fn dir_names(dir: &Dir) -> std::io::Result<Vec<Name<'_>>> {
+    let mut names = Vec::with_capacity(dir.len());
+    let mut taken = BTreeSet::new();
+
+    // Names that fit 8.3 keep their short name; aliases come second so
+    // they never steal one
+    for long in dir.keys() {
+        if long.encode_utf16().count() > LFN_MAX {
+            return Err(error(format!("'{}': name longer than {} characters", long, LFN_MAX)));
+        }
+        let (short, case, lfn) = match exact_short(long) {
+            Some((short, case)) => {
+                if !taken.insert(short) {
+                    return Err(error(format!("'{}' differs from another name only in case", long)));
+                }
+                (short, case, false)
+            }
+            None => ([0; 11], 0, true),
+        };
+        names.push(Name { long, short, case, lfn });
+    }
+
+    for name in names.iter_mut().filter(|n| n.lfn) {
+        let (base, ext) = match name.long.trim_start_matches('.').rsplit_once('.') {
+            Some((base, ext)) => (alias_part(base), alias_part(ext)),
+            None => (alias_part(name.long), Vec::new()),
+        };
+
+        let mut short = [b' '; 11];
+        let ext = &ext[..ext.len().min(3)];
+        short[8..8 + ext.len()].copy_from_slice(ext);
+        let found = (1..=999_999u32).find(|n| {
+            let tail = format!("~{}", n);
+            let keep = base.len().min(8 - tail.len());
+            short[..8].fill(b' ');
+            short[..keep].copy_from_slice(&base[..keep]);
+            short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+            !taken.contains(&short)
+        });
+        if found.is_none() {
+            return Err(error(format!("'{}': no free short name", name.long)));
+        }
+        taken.insert(short);
+        name.short = short;
+    }
+
+    Ok(names)
+}
@@ -0,0 +1,189 @@
+//! GUID Partition Table
+//!
+//! Layout (UEFI spec, chapter 5):
+//! - LBA 0: protective MBR, one type 0xEE partition covering the disk
+//! - LBA 1: primary header, LBA 2-33: 128 entries of 128 bytes
+//! - last 33 LBAs: backup entries, then the backup header in the last LBA
+//!
+//! The table is written after the partitions are formatted, so nothing a
+//! formatter does can clobber it.
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+use wfs_common::crc32;
+
+use crate::manifest::{Manifest, PartitionSpec};
+use crate::{error, SECTOR_SIZE};
+
+const ENTRY_COUNT: usize = 128;
+const ENTRY_SIZE: usize = 128;
+const ENTRY_SECTORS: u64 = (ENTRY_COUNT * ENTRY_SIZE) as u64 / SECTOR_SIZE;
+const HEADER_SIZE: usize = 92;
+
+/// Sectors at the end of the disk taken by the backup table and header
+pub const BACKUP_SECTORS: u64 = ENTRY_SECTORS + 1;
+
+/// EFI System Partition
+const ESP_TYPE: &str = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B";
+/// Microsoft basic data, what other FAT volumes use
+const BASIC_DATA_TYPE: &str = "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7";
+/// WATOS File System (project-defined)
+const WFS_TYPE: &str = "8B3F6E52-57A7-4F53-9D2E-5741544F5357";
+
+/// A GUID in its textual (big-endian) byte order
+#[derive(Clone, Copy)]
+struct Guid([u8; 16]);
+
+impl Guid {
+    fn parse(s: &str) -> Option<Self> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let lens: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        if lens != [8, 4, 4, 4, 12] {
+            return None;
+        }
+        let hex: String = groups.concat();
+        let mut bytes = [0u8; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+        Some(Guid(bytes))
+    }
+
+    /// A version-4-shaped GUID derived from `seed`, so rebuilding the same
+    /// manifest gives the same GUIDs
+    fn derive(seed: &str) -> Self {
+        let mut bytes = [0u8; 16];
+        for (i, chunk) in bytes.chunks_mut(4).enumerate() {
+            let crc = crc32(format!("{}#{}", seed, i).as_bytes());
+            chunk.copy_from_slice(&crc.to_be_bytes());
+        }
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+        Guid(bytes)
+    }
+
+    /// On-disk form: the first three fields are little-endian
+    fn to_disk(self) -> [u8; 16] {
+        let b = self.0;
+        let mut out = b;
+        out[0..4].copy_from_slice(&[b[3], b[2], b[1], b[0]]);
+        out[4..6].copy_from_slice(&[b[5], b[4]]);
+        out[6..8].copy_from_slice(&[b[7], b[6]]);
+        out
+    }
+}
+
+fn guid_or(given: Option<&str>, what: &str, seed: &str) -> std::io::Result<Guid> {
+    match given {
+        Some(s) => Guid::parse(s).ok_or_else(|| error(format!("{}: invalid GUID '{}'", what, s))),
+        None => Ok(Guid::derive(seed)),
+    }
+}
+
+fn type_guid(part: &PartitionSpec) -> std::io::Result<Guid> {
+    let default = if part.esp {
+        ESP_TYPE
+    } else if part.fat_type().is_some() {
+        BASIC_DATA_TYPE
+    } else {
+        WFS_TYPE
+    };
+    guid_or(Some(part.type_guid.as_deref().unwrap_or(default)), &part.name, "")
+}
+
+fn put_u32(buf: &mut [u8], at: usize, v: u32) {
+    buf[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], at: usize, v: u64) {
+    buf[at..at + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+/// Write the protective MBR and both GPT copies
+pub fn write(
+    file: &File,
+    manifest: &Manifest,
+    layout: &[(u64, u64)],
+    total_sectors: u64,
+) -> std::io::Result<()> {
+    if manifest.partitions.len() > ENTRY_COUNT {
+        return Err(error("too many partitions for GPT"));
+    }
+
+    // Derived GUIDs depend on the image file name, not where it is written
+    let image_name = manifest.image.output.file_name().unwrap_or_default().to_string_lossy();
+
+    let first_usable = 2 + ENTRY_SECTORS;
+    let last_usable = total_sectors - BACKUP_SECTORS - 1;
+
+    // Partition entry array, shared by both copies
+    let mut entries = vec![0u8; ENTRY_COUNT * ENTRY_SIZE];
+    for (i, (part, &(start, sectors))) in manifest.partitions.iter().zip(layout).enumerate() {
+        let entry = &mut entries[i * ENTRY_SIZE..(i + 1) * ENTRY_SIZE];
+        let seed = format!("{}/partition/{}/{}", image_name, i, part.name);
+        entry[0..16].copy_from_slice(&type_guid(part)?.to_disk());
+        entry[16..32].copy_from_slice(&guid_or(part.guid.as_deref(), &part.name, &seed)?.to_disk());
+        put_u64(entry, 32, start);
+        put_u64(entry, 40, start + sectors - 1);
+
+        let name: Vec<u16> = part.name.encode_utf16().collect();
+        if name.len() > 36 {
+            return Err(error(format!("partition '{}': GPT names are at most 36 characters", part.name)));
+        }
+        for (j, unit) in name.iter().enumerate() {
+            entry[56 + j * 2..58 + j * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+    let entries_crc = crc32(&entries);
+
+    let seed = format!("{}/disk", image_name);
+    let disk_guid = guid_or(manifest.image.disk_guid.as_deref(), "disk_guid", &seed)?;
+
+    let header = |my_lba: u64, alternate_lba: u64, entries_lba: u64| {
+        let mut sector = [0u8; SECTOR_SIZE as usize];
+        sector[0..8].copy_from_slice(b"EFI PART");
+        put_u32(&mut sector, 8, 0x0001_0000);
+        put_u32(&mut sector, 12, HEADER_SIZE as u32);
+        put_u64(&mut sector, 24, my_lba);
+        put_u64(&mut sector, 32, alternate_lba);
+        put_u64(&mut sector, 40, first_usable);
+        put_u64(&mut sector, 48, last_usable);
+        sector[56..72].copy_from_slice(&disk_guid.to_disk());
+        put_u64(&mut sector, 72, entries_lba);
+        put_u32(&mut sector, 80, ENTRY_COUNT as u32);
+        put_u32(&mut sector, 84, ENTRY_SIZE as u32);
+        put_u32(&mut sector, 88, entries_crc);
+        let crc = crc32(&sector[..HEADER_SIZE]);
+        put_u32(&mut sector, 16, crc);
+        sector
+    };
+
+    let last = total_sectors - 1;
+    let backup_entries = last - ENTRY_SECTORS;
+
+    // Protective MBR: one 0xEE partition from LBA 1 to the end (capped)
+    let mut mbr = [0u8; SECTOR_SIZE as usize];
+    let pte = &mut mbr[446..462];
+    pte[1..4].copy_from_slice(&[0x00, 0x02, 0x00]);
+    pte[4] = 0xEE;
+    pte[5..8].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+    put_u32(pte, 8, 1);
+    put_u32(pte, 12, (total_sectors - 1).min(u32::MAX as u64) as u32);
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+
+    file.write_all_at(&mbr, 0)?;
+    file.write_all_at(&header(1, last, 2), SECTOR_SIZE)?;
+    file.write_all_at(&entries, 2 * SECTOR_SIZE)?;
+    file.write_all_at(&entries, backup_entries * SECTOR_SIZE)?;
+    file.write_all_at(&header(last, 1, backup_entries), last * SECTOR_SIZE)?;
+
+    println!("\nGPT: {} partition(s), disk GUID {}", manifest.partitions.len(), format_guid(disk_guid));
+    Ok(())
+}
+
+fn format_guid(guid: Guid) -> String {
+    let hex: String = guid.0.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
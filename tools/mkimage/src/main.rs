@@ -0,0 +1,222 @@
+//! mkimage - Build WATOS disk images from a manifest
+//!
+//! Replaces the mtools steps in the build scripts with one reproducible
+//! step: the same manifest and input files always produce a byte-identical
+//! image (fixed timestamps, sorted directories, GUIDs derived from names).
+//!
+//! Supported layouts:
+//! - `table = "gpt"`: protective MBR + GPT, partitions 1 MiB aligned, each
+//!   formatted as FAT12/16/32 (optionally the EFI System Partition) or WFS
+//! - `table = "none"`: a single filesystem at sector 0, which is what the
+//!   kernel mounts today (it does not read partition tables yet)
+//!
+//! See `manifest.rs` for the manifest format and `images/` for examples.
+//!
+//! Usage:
+//!   mkimage images/uefi_boot.toml              # Boot disk for QEMU
+//!   mkimage images/release.toml -o disk.img    # Override the output path
+
+mod fat;
+mod gpt;
+mod manifest;
+mod tree;
+mod wfs;
+
+use clap::Parser;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+
+use manifest::{FsKind, Manifest, TableKind};
+
+pub const SECTOR_SIZE: u64 = 512;
+
+/// Partition alignment (1 MiB), as fdisk and parted use
+const ALIGN_SECTORS: u64 = 2048;
+
+#[derive(Parser)]
+#[command(name = "mkimage")]
+#[command(about = "Build a WATOS disk image (GPT, FAT, WFS) from a manifest")]
+struct Args {
+    /// Image manifest (TOML)
+    manifest: PathBuf,
+
+    /// Output image file (overrides the manifest)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// List every file as it is added
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim().to_uppercase();
+    let (num_str, mult) = if s.ends_with("G") || s.ends_with("GB") {
+        (s.trim_end_matches("GB").trim_end_matches("G"), 1024 * 1024 * 1024)
+    } else if s.ends_with("M") || s.ends_with("MB") {
+        (s.trim_end_matches("MB").trim_end_matches("M"), 1024 * 1024)
+    } else if s.ends_with("K") || s.ends_with("KB") {
+        (s.trim_end_matches("KB").trim_end_matches("K"), 1024)
+    } else {
+        (s.as_str(), 1)
+    };
+
+    num_str.parse::<u64>().ok().map(|n| n * mult)
+}
+
+pub fn error(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg.into())
+}
+
+/// A byte range of the output image
+///
+/// Each filesystem is written through its own window, so formatters see a
+/// device starting at sector 0 wherever the partition actually lives.
+pub struct Window {
+    file: File,
+    /// First sector of the window on the image
+    start: u64,
+    /// Length in sectors
+    sectors: u64,
+}
+
+impl Window {
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    fn check(&self, offset: u64, len: usize) -> std::io::Result<u64> {
+        if offset + len as u64 > self.sectors * SECTOR_SIZE {
+            return Err(std::io::Error::other("write past end of partition"));
+        }
+        Ok(self.start * SECTOR_SIZE + offset)
+    }
+
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let pos = self.check(offset, buf.len())?;
+        self.file.read_exact_at(buf, pos)
+    }
+
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        let pos = self.check(offset, buf.len())?;
+        self.file.write_all_at(buf, pos)
+    }
+}
+
+/// Where each partition goes, in sectors
+struct Layout {
+    total_sectors: u64,
+    /// (start, length) per partition, in manifest order
+    partitions: Vec<(u64, u64)>,
+}
+
+fn plan_layout(manifest: &Manifest) -> std::io::Result<Layout> {
+    let image_sectors = manifest
+        .image
+        .size
+        .as_deref()
+        .map(|s| manifest::size_sectors(s, "image"))
+        .transpose()?;
+
+    if manifest.image.table == TableKind::None {
+        let part = &manifest.partitions[0];
+        let sectors = match (image_sectors, part.sectors()?) {
+            (Some(image), Some(part)) if image != part => {
+                return Err(error("table = \"none\": image and partition sizes differ"))
+            }
+            (Some(n), _) | (None, Some(n)) => n,
+            (None, None) => return Err(error("table = \"none\" needs a size")),
+        };
+        return Ok(Layout { total_sectors: sectors, partitions: vec![(0, sectors)] });
+    }
+
+    // Sized partitions back to back from 1 MiB; an unsized last partition
+    // stops short of the backup GPT
+    let mut next = ALIGN_SECTORS;
+    let mut partitions = Vec::new();
+    for part in &manifest.partitions {
+        let start = next.div_ceil(ALIGN_SECTORS) * ALIGN_SECTORS;
+        let sectors = match part.sectors()? {
+            Some(n) => n,
+            None => {
+                let total = image_sectors.ok_or_else(|| {
+                    error(format!("partition '{}' has no size, so [image] needs one", part.name))
+                })?;
+                let last_usable = total.saturating_sub(gpt::BACKUP_SECTORS + 1);
+                if last_usable < start {
+                    return Err(error(format!("partition '{}' does not fit", part.name)));
+                }
+                last_usable + 1 - start
+            }
+        };
+        partitions.push((start, sectors));
+        next = start + sectors;
+    }
+
+    // Default size leaves room for the backup GPT, rounded up to 1 MiB
+    let needed = next + gpt::BACKUP_SECTORS;
+    let total_sectors = match image_sectors {
+        Some(total) if total < needed => {
+            return Err(error(format!("partitions need {} sectors; image has {}", needed, total)))
+        }
+        Some(total) => total,
+        None => needed.div_ceil(ALIGN_SECTORS) * ALIGN_SECTORS,
+    };
+
+    Ok(Layout { total_sectors, partitions })
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let mut manifest = Manifest::load(&args.manifest)?;
+    if let Some(output) = args.output {
+        manifest.image.output = output;
+    }
+    let layout = plan_layout(&manifest)?;
+
+    println!("Creating disk image: {}", manifest.image.output.display());
+    println!("  Size:  {} bytes ({} sectors)", layout.total_sectors * SECTOR_SIZE, layout.total_sectors);
+    println!("  Table: {}", match manifest.image.table {
+        TableKind::Gpt => "GPT",
+        TableKind::None => "none",
+    });
+
+    // Start from an all-zero (sparse) file so untouched space reads as zero
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&manifest.image.output)?;
+    file.set_len(layout.total_sectors * SECTOR_SIZE)?;
+
+    let result = (|| {
+        for (part, &(start, sectors)) in manifest.partitions.iter().zip(&layout.partitions) {
+            println!("\nPartition '{}': sectors {}..{} ({} KiB)",
+                part.name, start, start + sectors, sectors * SECTOR_SIZE / 1024);
+
+            let files = tree::collect(&part.files)?;
+            let window = Window { file: file.try_clone()?, start, sectors };
+            match part.filesystem {
+                FsKind::Wfs => wfs::build(window, &files, args.verbose)?,
+                _ => fat::build(window, part, &files, args.verbose)?,
+            }
+        }
+
+        if manifest.image.table == TableKind::Gpt {
+            gpt::write(&file, &manifest, &layout.partitions, layout.total_sectors)?;
+        }
+        file.sync_all()
+    })();
+
+    if let Err(e) = result {
+        // Don't leave a half-written image behind
+        let _ = std::fs::remove_file(&manifest.image.output);
+        return Err(e);
+    }
+
+    println!("\nDone! {} created.", manifest.image.output.display());
+    Ok(())
+}
@@ -0,0 +1,184 @@
+//! Image manifest
+//!
+//! A manifest is a TOML file with one `[image]` table and one or more
+//! `[[partition]]` tables:
+//!
+//! ```toml
+//! [image]
+//! output = "../output/watos-disk.img"   # relative to the manifest
+//! table = "gpt"                         # "gpt" or "none"
+//! size = "256M"                         # optional with sized partitions
+//!
+//! [[partition]]
+//! name = "EFI System"
+//! filesystem = "fat32"                  # fat12, fat16, fat32 or wfs
+//! esp = true                            # EFI System Partition type GUID
+//! size = "64M"
+//! label = "WATOS_BOOT"
+//! files = [
+//!     { src = "../uefi_test", dst = "/" },
+//!     { src = "../extra/NOTES.TXT", dst = "/NOTES.TXT", optional = true },
+//! ]
+//!
+//! [[partition]]
+//! name = "WATOS root"
+//! filesystem = "wfs"                    # no size: fills the rest
+//! files = [{ src = "../rootfs", dst = "/" }]
+//! ```
+//!
+//! A directory `src` is merged into `dst` recursively; a file `src` is
+//! copied to exactly `dst`. With `table = "none"` the image holds a single
+//! filesystem starting at sector 0 (what the kernel currently mounts).
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::{error, parse_size, SECTOR_SIZE};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub image: ImageSpec,
+    #[serde(rename = "partition", default)]
+    pub partitions: Vec<PartitionSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageSpec {
+    /// Output image file
+    pub output: PathBuf,
+    /// Partition table
+    #[serde(default)]
+    pub table: TableKind,
+    /// Total image size; defaults to the sum of the partitions
+    pub size: Option<String>,
+    /// GPT disk GUID; derived from the output file name if omitted
+    pub disk_guid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableKind {
+    #[default]
+    Gpt,
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartitionSpec {
+    /// GPT partition name
+    pub name: String,
+    pub filesystem: FsKind,
+    /// Partition size; the last partition may omit it to fill the image
+    pub size: Option<String>,
+    /// Mark as EFI System Partition (FAT only)
+    #[serde(default)]
+    pub esp: bool,
+    /// Volume label (FAT only)
+    pub label: Option<String>,
+    /// FAT cluster size in bytes (e.g. "4K"); chosen from the size if omitted
+    pub cluster_size: Option<String>,
+    /// Override the GPT partition type GUID
+    pub type_guid: Option<String>,
+    /// GPT unique partition GUID; derived from the name if omitted
+    pub guid: Option<String>,
+    #[serde(default)]
+    pub files: Vec<FileSpec>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsKind {
+    Fat12,
+    Fat16,
+    Fat32,
+    Wfs,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileSpec {
+    /// Host file or directory (relative to the manifest)
+    pub src: PathBuf,
+    /// Absolute path inside the filesystem
+    pub dst: String,
+    /// Skip silently if `src` does not exist
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl Manifest {
+    /// Read a manifest, resolving relative paths against its directory
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut manifest: Manifest = toml::from_str(&text)
+            .map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        manifest.image.output = base.join(&manifest.image.output);
+        for part in &mut manifest.partitions {
+            for file in &mut part.files {
+                file.src = base.join(&file.src);
+            }
+        }
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> std::io::Result<()> {
+        if self.partitions.is_empty() {
+            return Err(error("manifest has no [[partition]]"));
+        }
+        if self.image.table == TableKind::None && self.partitions.len() != 1 {
+            return Err(error("table = \"none\" takes exactly one partition"));
+        }
+
+        for (i, part) in self.partitions.iter().enumerate() {
+            let last = i + 1 == self.partitions.len();
+            if part.size.is_none() && !last {
+                return Err(error(format!("partition '{}': only the last partition may omit size", part.name)));
+            }
+            if part.fat_type().is_none() && (part.esp || part.label.is_some() || part.cluster_size.is_some()) {
+                return Err(error(format!("partition '{}': esp, label and cluster_size are FAT options", part.name)));
+            }
+            for file in &part.files {
+                if !file.dst.starts_with('/') {
+                    return Err(error(format!("partition '{}': dst '{}' must be absolute", part.name, file.dst)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PartitionSpec {
+    /// Size in sectors, if given
+    pub fn sectors(&self) -> std::io::Result<Option<u64>> {
+        self.size
+            .as_deref()
+            .map(|s| size_sectors(s, &self.name))
+            .transpose()
+    }
+
+    /// FAT variant, or `None` for WFS
+    pub fn fat_type(&self) -> Option<watos_fat::FatType> {
+        match self.filesystem {
+            FsKind::Fat12 => Some(watos_fat::FatType::Fat12),
+            FsKind::Fat16 => Some(watos_fat::FatType::Fat16),
+            FsKind::Fat32 => Some(watos_fat::FatType::Fat32),
+            FsKind::Wfs => None,
+        }
+    }
+}
+
+/// Parse a size string into whole sectors
+pub fn size_sectors(size: &str, what: &str) -> std::io::Result<u64> {
+    let bytes = parse_size(size).ok_or_else(|| error(format!("{}: invalid size '{}'", what, size)))?;
+    if bytes % SECTOR_SIZE != 0 {
+        return Err(error(format!("{}: size '{}' is not a whole number of sectors", what, size)));
+    }
+    Ok(bytes / SECTOR_SIZE)
+}
@@ -0,0 +1,95 @@
+//! Host file tree
+//!
+//! The `files` entries of a partition are resolved into one sorted tree
+//! before anything is written, so every filesystem sees the same, ordered
+//! input regardless of host directory order.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error;
+use crate::manifest::FileSpec;
+
+pub enum Node {
+    /// Regular file, read from the host when written
+    File(PathBuf),
+    Dir(Dir),
+}
+
+pub type Dir = BTreeMap<String, Node>;
+
+/// Resolve a partition's file list into a tree rooted at `/`
+pub fn collect(specs: &[FileSpec]) -> std::io::Result<Dir> {
+    let mut root = Dir::new();
+
+    for spec in specs {
+        let meta = match fs::metadata(&spec.src) {
+            Ok(meta) => meta,
+            Err(_) if spec.optional => continue,
+            Err(e) => return Err(error(format!("{}: {}", spec.src.display(), e))),
+        };
+
+        let mut parts: Vec<&str> = spec.dst.split('/').filter(|p| !p.is_empty()).collect();
+        if meta.is_dir() {
+            let dir = make_dirs(&mut root, &parts, &spec.dst)?;
+            add_dir(dir, &spec.src)?;
+        } else {
+            // "/EFI/BOOT/" keeps the host file name
+            let name = match parts.pop() {
+                Some(name) if !spec.dst.ends_with('/') => name.to_string(),
+                popped => {
+                    parts.extend(popped);
+                    host_name(&spec.src)?
+                }
+            };
+            let dir = make_dirs(&mut root, &parts, &spec.dst)?;
+            insert(dir, name, Node::File(spec.src.clone()), &spec.dst)?;
+        }
+    }
+    Ok(root)
+}
+
+/// Walk to (creating as needed) the directory at `parts`
+fn make_dirs<'a>(mut dir: &'a mut Dir, parts: &[&str], dst: &str) -> std::io::Result<&'a mut Dir> {
+    for part in parts {
+        let node = dir.entry(part.to_string()).or_insert_with(|| Node::Dir(Dir::new()));
+        dir = match node {
+            Node::Dir(d) => d,
+            Node::File(_) => return Err(error(format!("{}: '{}' is a file", dst, part))),
+        };
+    }
+    Ok(dir)
+}
+
+fn insert(dir: &mut Dir, name: String, node: Node, dst: &str) -> std::io::Result<()> {
+    if dir.contains_key(&name) {
+        return Err(error(format!("{}: '{}' is listed twice", dst, name)));
+    }
+    dir.insert(name, node);
+    Ok(())
+}
+
+/// Merge a host directory into `dir`, recursively
+fn add_dir(dir: &mut Dir, src: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        let name = host_name(&path)?;
+        // Follow symlinks; skip sockets, devices and the like
+        let meta = fs::metadata(&path)?;
+        if meta.is_dir() {
+            let sub = make_dirs(dir, &[name.as_str()], &path.to_string_lossy())?;
+            add_dir(sub, &path)?;
+        } else if meta.is_file() {
+            insert(dir, name, Node::File(path.clone()), &path.to_string_lossy())?;
+        }
+    }
+    Ok(())
+}
+
+fn host_name(path: &Path) -> std::io::Result<String> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(String::from)
+        .ok_or_else(|| error(format!("{}: file name is not UTF-8", path.display())))
+}
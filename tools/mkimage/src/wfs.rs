@@ -0,0 +1,216 @@
+//! WFS partitions
+//!
+//! The partition is initialized with `wfs_common::core::init_filesystem`
+//! and filled in a single CoW transaction, committed once at the end.
+//!
+//! Like mkfs.wfs, only inline files (up to 160 bytes) are supported for
+//! now; larger files are skipped and counted. Timestamps are zero so
+//! images are reproducible.
+
+use wfs_common::core::dir::{dot_entry, dotdot_entry, DirEntry, EntryType};
+use wfs_common::core::inode::INODE_INLINE_SIZE;
+use wfs_common::core::transaction::TransactionError;
+use wfs_common::core::{
+    init_filesystem, BPlusTree, BlockAllocator, BlockDevice, DirOps, FilesystemOps,
+    FilesystemState, Inode, InodeOps, NodeType, TreeError, TreeNode, TreeOps, BLOCK_SIZE,
+    ROOT_INODE,
+};
+
+use crate::tree::{Dir, Node};
+use crate::{error, Window, SECTOR_SIZE};
+
+/// Partition as a WFS block device with a bump allocator
+///
+/// The partition starts out zeroed and nothing is freed while building,
+/// so blocks are simply handed out in order.
+struct WfsDevice {
+    window: Window,
+    total_blocks: u64,
+    next_block: u64,
+}
+
+impl BlockDevice for WfsDevice {
+    fn read_node(&self, block: u64) -> Result<TreeNode, TreeError> {
+        let mut node = TreeNode::default();
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let node_bytes = unsafe {
+            std::slice::from_raw_parts_mut(&mut node as *mut TreeNode as *mut u8, BLOCK_SIZE as usize)
+        };
+        self.window
+            .read_at(node_bytes, block * BLOCK_SIZE as u64)
+            .map_err(|_| TreeError::IoError)?;
+        Ok(node)
+    }
+
+    fn write_node(&mut self, block: u64, node: &TreeNode) -> Result<(), TreeError> {
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let node_bytes = unsafe {
+            std::slice::from_raw_parts(node as *const TreeNode as *const u8, BLOCK_SIZE as usize)
+        };
+        self.window
+            .write_at(node_bytes, block * BLOCK_SIZE as u64)
+            .map_err(|_| TreeError::IoError)
+    }
+
+    fn sync(&mut self) -> Result<(), TreeError> {
+        // The image is synced once, after the partition table is written
+        Ok(())
+    }
+}
+
+impl BlockAllocator for WfsDevice {
+    fn allocate_block(&mut self) -> Result<u64, TreeError> {
+        if self.next_block >= self.total_blocks {
+            return Err(TreeError::NodeFull);
+        }
+        let block = self.next_block;
+        self.next_block += 1;
+        Ok(block)
+    }
+
+    fn free_block(&mut self, _block: u64) -> Result<(), TreeError> {
+        Ok(())
+    }
+}
+
+impl FilesystemOps for WfsDevice {
+    fn allocate_blocks(&mut self, _state: &mut FilesystemState, count: u64) -> Result<u64, TransactionError> {
+        if self.next_block + count > self.total_blocks {
+            return Err(TransactionError::NoSpace);
+        }
+        let start = self.next_block;
+        self.next_block += count;
+        Ok(start)
+    }
+
+    fn free_blocks(&mut self, _state: &mut FilesystemState, _start: u64, _count: u64) -> Result<(), TransactionError> {
+        Ok(())
+    }
+}
+
+/// TreeOps over the device, which serves as both block device and allocator
+fn tree_ops(dev: &mut WfsDevice) -> TreeOps<'_, WfsDevice, WfsDevice> {
+    let dev_ptr = dev as *mut WfsDevice;
+    // SAFETY: TreeOps never holds on to both references across a call
+    let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+    TreeOps::new(dev_ref, alloc_ref)
+}
+
+fn tree_error(e: TreeError) -> std::io::Error {
+    std::io::Error::other(format!("WFS: {:?}", e))
+}
+
+struct WfsWriter {
+    dev: WfsDevice,
+    state: FilesystemState,
+    verbose: bool,
+    files: usize,
+    dirs: usize,
+    skipped: usize,
+}
+
+/// Create a WFS filesystem on the partition and copy `files` into it
+pub fn build(window: Window, files: &Dir, verbose: bool) -> std::io::Result<()> {
+    let total_blocks = window.sectors() * SECTOR_SIZE / BLOCK_SIZE as u64;
+    if total_blocks < 16 {
+        return Err(error("WFS partition is too small"));
+    }
+
+    let mut dev = WfsDevice { window, total_blocks, next_block: 0 };
+    let state = init_filesystem(&mut dev, total_blocks).map_err(tree_error)?;
+    dev.next_block = state.superblock.data_start_block;
+    println!("  Type:     WFS v1 ({} blocks of {} bytes)", total_blocks, BLOCK_SIZE);
+
+    let mut writer = WfsWriter { dev, state, verbose, files: 0, dirs: 0, skipped: 0 };
+    writer.dev.begin_transaction(&mut writer.state).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+
+    let root = InodeOps::lookup(&mut tree_ops(&mut writer.dev), &writer.state, ROOT_INODE)
+        .map_err(tree_error)?
+        .ok_or_else(|| std::io::Error::other("WFS: root inode missing"))?;
+    writer.fill_dir(root, files, "")?;
+
+    writer.state.superblock.free_blocks = total_blocks - writer.dev.next_block;
+    writer.dev.commit_transaction(&mut writer.state).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+
+    println!("  Files:    {}", writer.files);
+    println!("  Dirs:     {}", writer.dirs);
+    if writer.skipped > 0 {
+        println!("  Skipped:  {} (larger than {} bytes)", writer.skipped, INODE_INLINE_SIZE);
+    }
+    println!("  Free:     {} of {} blocks", writer.state.superblock.free_blocks, total_blocks);
+    Ok(())
+}
+
+impl WfsWriter {
+    /// Add the children of `dir` to the directory inode, then store it
+    fn fill_dir(&mut self, mut inode: Inode, dir: &Dir, path: &str) -> std::io::Result<()> {
+        let mut tree = BPlusTree::new(inode.extent_root, NodeType::Directory, self.state.superblock.root_generation);
+
+        for (name, node) in dir {
+            let child_path = format!("{}/{}", path, name);
+            let (inode_num, entry_type) = match node {
+                Node::File(src) => {
+                    let data = std::fs::read(src)?;
+                    if data.len() > INODE_INLINE_SIZE {
+                        if self.verbose {
+                            println!("    SKIP: {} ({} bytes, too large)", child_path, data.len());
+                        }
+                        self.skipped += 1;
+                        continue;
+                    }
+                    let mut file = Inode::new_file(InodeOps::allocate_inode_num(&mut self.state));
+                    file.set_inline_data(&data);
+                    file.update_crc();
+                    InodeOps::insert(&mut tree_ops(&mut self.dev), &mut self.state, file).map_err(tree_error)?;
+                    if self.verbose {
+                        println!("    FILE: {} ({} bytes)", child_path, data.len());
+                    }
+                    self.files += 1;
+                    (file.inode_num, EntryType::File)
+                }
+                Node::Dir(sub) => {
+                    if self.verbose {
+                        println!("    DIR:  {}", child_path);
+                    }
+                    let num = self.new_dir(inode.inode_num)?;
+                    let child = InodeOps::lookup(&mut tree_ops(&mut self.dev), &self.state, num)
+                        .map_err(tree_error)?
+                        .ok_or_else(|| std::io::Error::other("WFS: new directory missing"))?;
+                    self.fill_dir(child, sub, &child_path)?;
+                    self.dirs += 1;
+                    inode.nlink += 1;
+                    (num, EntryType::Directory)
+                }
+            };
+
+            let entry = DirEntry::new(name, inode_num, entry_type)
+                .ok_or_else(|| error(format!("{}: name too long for WFS", child_path)))?;
+            DirOps::insert(&mut tree_ops(&mut self.dev), &mut tree, entry).map_err(tree_error)?;
+        }
+
+        // Inserts are CoW, so the tree root may have moved
+        inode.extent_root = tree.root_block;
+        inode.update_crc();
+        InodeOps::insert(&mut tree_ops(&mut self.dev), &mut self.state, inode).map_err(tree_error)
+    }
+
+    /// Create an empty directory (just "." and "..") and return its inode
+    fn new_dir(&mut self, parent: u64) -> std::io::Result<u64> {
+        let num = InodeOps::allocate_inode_num(&mut self.state);
+        let block = self.dev.allocate_block().map_err(tree_error)?;
+        let mut node = TreeNode::new(NodeType::Directory, 0, self.state.superblock.root_generation);
+        node.update_crc();
+        self.dev.write_node(block, &node).map_err(tree_error)?;
+
+        let mut tree = BPlusTree::new(block, NodeType::Directory, self.state.superblock.root_generation);
+        let mut ops = tree_ops(&mut self.dev);
+        DirOps::insert(&mut ops, &mut tree, dot_entry(num)).map_err(tree_error)?;
+        DirOps::insert(&mut ops, &mut tree, dotdot_entry(parent)).map_err(tree_error)?;
+
+        let mut inode = Inode::new_directory(num);
+        inode.extent_root = tree.root_block;
+        inode.update_crc();
+        InodeOps::insert(&mut ops, &mut self.state, inode).map_err(tree_error)?;
+        Ok(num)
+    }
+}
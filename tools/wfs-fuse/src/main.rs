@@ -0,0 +1,910 @@
+//! wfs_fuse - Mount WFS (WATOS File System) disk images on the host
+//!
+//! Serves a WFS image through FUSE so disk images can be inspected and
+//! populated with ordinary tools (ls, cp, editors) without booting WATOS.
+//!
+//! Every modifying request runs in its own CoW transaction and is committed
+//! (primary, then backup superblock) before the reply is sent, so stopping
+//! the process at any point leaves a consistent image.
+//!
+//! Limitations (shared with mkfs.wfs and the kernel driver):
+//! - File data is stored inline in the inode, so files are limited to 160
+//!   bytes; larger writes fail with EFBIG
+//! - Freed blocks are not reused; old CoW copies stay on disk
+//! - Two names whose hashes collide cannot live in the same directory
+//!
+//! Usage:
+//!   wfs_fuse disk.img /mnt/wfs              # Mount read-write
+//!   wfs_fuse --read-only disk.img /mnt/wfs  # Mount read-only
+//!   fusermount -u /mnt/wfs                  # Unmount
+
+use clap::Parser;
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyStatfs, ReplyWrite, Request, TimeOrNow,
+};
+use libc::{
+    c_int, EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENAMETOOLONG, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY,
+    EPERM, EROFS,
+};
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use wfs_common::core::dir::{DirEntry, EntryType};
+use wfs_common::core::fs::DirEntryValue;
+use wfs_common::core::inode::INODE_INLINE_SIZE;
+use wfs_common::core::transaction::TransactionError;
+use wfs_common::core::tree::{InternalNode, LeafNode};
+use wfs_common::core::{
+    BPlusTree, BlockAllocator, BlockDevice, DirOps, FileOps, FilesystemOps, FilesystemState,
+    Inode, InodeOps, NodeType, TreeError, TreeNode, TreeOps, BLOCK_SIZE, MAX_FILENAME, S_IFDIR,
+    S_IFLNK, S_IFMT, S_IFREG,
+};
+
+/// How long the kernel may cache attributes and lookups
+const TTL: Duration = Duration::from_secs(1);
+
+/// Deepest directory B+tree readdir will walk
+const MAX_TREE_DEPTH: u32 = 16;
+
+#[derive(Parser)]
+#[command(name = "wfs_fuse")]
+#[command(about = "Mount a WFS (WATOS File System) disk image via FUSE")]
+struct Args {
+    /// WFS disk image
+    image: PathBuf,
+
+    /// Directory to mount on
+    mountpoint: PathBuf,
+
+    /// Mount read-only (the image is never written)
+    #[arg(short, long)]
+    read_only: bool,
+
+    /// Allow other users to access the mount (needs user_allow_other in /etc/fuse.conf)
+    #[arg(long)]
+    allow_other: bool,
+
+    /// Log every modifying request
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+// ============================================================================
+// IMAGE FILE DEVICE
+// ============================================================================
+
+/// Block device backed by an image file
+struct ImageDevice {
+    file: File,
+    total_blocks: u64,
+    next_block: u64,
+}
+
+impl ImageDevice {
+    fn open(path: &Path, read_only: bool) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(!read_only).open(path)?;
+        let total_blocks = file.metadata()?.len() / BLOCK_SIZE as u64;
+        Ok(Self {
+            file,
+            total_blocks,
+            next_block: total_blocks,
+        })
+    }
+
+    /// Start allocating after the last block holding a valid tree node
+    ///
+    /// Neither mkfs.wfs nor the kernel maintain the free space tree yet, so
+    /// the image itself is the only record of which blocks are in use.
+    fn find_next_free(&mut self, data_start: u64) {
+        let mut next = data_start;
+        for block in data_start..self.total_blocks {
+            if matches!(self.read_node(block), Ok(node) if node.is_valid()) {
+                next = block + 1;
+            }
+        }
+        self.next_block = next;
+    }
+}
+
+impl BlockDevice for ImageDevice {
+    fn read_node(&self, block: u64) -> Result<TreeNode, TreeError> {
+        if block >= self.total_blocks {
+            return Err(TreeError::IoError);
+        }
+
+        let mut node = TreeNode::default();
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let node_bytes = unsafe {
+            std::slice::from_raw_parts_mut(&mut node as *mut TreeNode as *mut u8, BLOCK_SIZE as usize)
+        };
+        self.file
+            .read_exact_at(node_bytes, block * BLOCK_SIZE as u64)
+            .map_err(|_| TreeError::IoError)?;
+        Ok(node)
+    }
+
+    fn write_node(&mut self, block: u64, node: &TreeNode) -> Result<(), TreeError> {
+        if block >= self.total_blocks {
+            return Err(TreeError::IoError);
+        }
+
+        // SAFETY: TreeNode is repr(C) and exactly BLOCK_SIZE bytes
+        let node_bytes = unsafe {
+            std::slice::from_raw_parts(node as *const TreeNode as *const u8, BLOCK_SIZE as usize)
+        };
+        self.file
+            .write_all_at(node_bytes, block * BLOCK_SIZE as u64)
+            .map_err(|_| TreeError::IoError)
+    }
+
+    fn sync(&mut self) -> Result<(), TreeError> {
+        self.file.sync_data().map_err(|_| TreeError::IoError)
+    }
+}
+
+impl BlockAllocator for ImageDevice {
+    fn allocate_block(&mut self) -> Result<u64, TreeError> {
+        if self.next_block >= self.total_blocks {
+            return Err(TreeError::NodeFull);
+        }
+        let block = self.next_block;
+        self.next_block += 1;
+        Ok(block)
+    }
+
+    fn free_block(&mut self, _block: u64) -> Result<(), TreeError> {
+        // Old CoW copies are left in place (see find_next_free)
+        Ok(())
+    }
+}
+
+impl FilesystemOps for ImageDevice {
+    fn allocate_blocks(&mut self, _state: &mut FilesystemState, count: u64) -> Result<u64, TransactionError> {
+        if self.next_block + count > self.total_blocks {
+            return Err(TransactionError::NoSpace);
+        }
+        let start = self.next_block;
+        self.next_block += count;
+        Ok(start)
+    }
+
+    fn free_blocks(&mut self, _state: &mut FilesystemState, _start: u64, _count: u64) -> Result<(), TransactionError> {
+        Ok(())
+    }
+}
+
+/// TreeOps over the device, which serves as both block device and allocator
+fn tree_ops(dev: &mut ImageDevice) -> TreeOps<'_, ImageDevice, ImageDevice> {
+    let dev_ptr = dev as *mut ImageDevice;
+    // SAFETY: TreeOps never holds on to both references across a call
+    let (dev_ref, alloc_ref) = unsafe { (&mut *dev_ptr, &mut *dev_ptr) };
+    TreeOps::new(dev_ref, alloc_ref)
+}
+
+// ============================================================================
+// FUSE FILESYSTEM
+// ============================================================================
+
+struct WfsFuse {
+    dev: ImageDevice,
+    state: FilesystemState,
+    read_only: bool,
+    verbose: bool,
+}
+
+impl WfsFuse {
+    fn inode(&mut self, ino: u64) -> Result<Inode, c_int> {
+        InodeOps::lookup(&mut tree_ops(&mut self.dev), &self.state, ino)
+            .map_err(errno)?
+            .ok_or(ENOENT)
+    }
+
+    fn dir_inode(&mut self, ino: u64) -> Result<Inode, c_int> {
+        let inode = self.inode(ino)?;
+        if !inode.is_directory() {
+            return Err(ENOTDIR);
+        }
+        Ok(inode)
+    }
+
+    fn find(&mut self, dir: &Inode, name: &str) -> Result<Option<DirEntry>, c_int> {
+        let tree = dir_tree(&self.state, dir);
+        DirOps::lookup(&mut tree_ops(&mut self.dev), &tree, name).map_err(errno)
+    }
+
+    /// All entries of a directory, in hash order
+    fn list(&self, dir: &Inode) -> Result<Vec<DirEntry>, c_int> {
+        let mut entries = Vec::new();
+        if dir.extent_root != 0 {
+            self.collect(dir.extent_root, 0, &mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    fn collect(&self, block: u64, depth: u32, out: &mut Vec<DirEntry>) -> Result<(), c_int> {
+        if depth > MAX_TREE_DEPTH {
+            return Err(EIO);
+        }
+
+        let mut node = self.dev.read_node(block).map_err(errno)?;
+        if !node.is_valid() {
+            return Err(EIO);
+        }
+
+        if node.is_leaf() {
+            let leaf = LeafNode::<u64, DirEntryValue>::new(&mut node);
+            out.extend((0..).map_while(|i| leaf.get_entry(i)).map(|(_, value)| value.0));
+        } else {
+            let children: Vec<u64> = {
+                let internal = InternalNode::new(&mut node);
+                (0..).map_while(|i| internal.get_child(i)).collect()
+            };
+            for child in children {
+                self.collect(child, depth + 1, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a modifying request as one committed transaction
+    ///
+    /// On failure the in-memory state is rolled back; any blocks the request
+    /// already wrote are unreferenced and simply left behind.
+    fn modify<T>(&mut self, what: &str, f: impl FnOnce(&mut Self) -> Result<T, c_int>) -> Result<T, c_int> {
+        if self.read_only {
+            return Err(EROFS);
+        }
+
+        let saved = self.state.clone();
+        self.dev.begin_transaction(&mut self.state).map_err(|_| EIO)?;
+        let result = f(self).and_then(|value| {
+            self.dev.commit_transaction(&mut self.state).map_err(|_| EIO)?;
+            Ok(value)
+        });
+
+        match result {
+            Ok(_) if self.verbose => println!("{}", what),
+            Ok(_) => {}
+            Err(e) => {
+                self.state = saved;
+                if self.verbose {
+                    println!("{} failed: errno {}", what, e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Create an inode and link it into `parent` as `name`
+    fn create_node(&mut self, parent: u64, name: &str, mode: u32, uid: u32, gid: u32) -> Result<Inode, c_int> {
+        let mut dir = self.dir_inode(parent)?;
+        if self.find(&dir, name)?.is_some() {
+            return Err(EEXIST);
+        }
+
+        let now = now();
+        let inode_num = InodeOps::allocate_inode_num(&mut self.state);
+        let mut inode = Inode::new(inode_num, mode);
+        inode.uid = uid;
+        inode.gid = gid;
+        inode.atime = now;
+        inode.mtime = now;
+        inode.ctime = now;
+
+        let entry_type = if mode & S_IFMT == S_IFDIR {
+            // Each directory owns a B+tree; start it with an empty leaf
+            let block = self.dev.allocate_block().map_err(errno)?;
+            let mut node = TreeNode::new(NodeType::Directory, 0, self.state.superblock.root_generation);
+            node.update_crc();
+            self.dev.write_node(block, &node).map_err(errno)?;
+            inode.extent_root = block;
+            inode.nlink = 2;
+            dir.nlink += 1;
+            EntryType::Directory
+        } else {
+            inode.set_inline_data(&[]);
+            EntryType::File
+        };
+        inode.update_crc();
+
+        let entry = DirEntry::new(name, inode_num, entry_type).ok_or(ENAMETOOLONG)?;
+        let mut ops = tree_ops(&mut self.dev);
+        InodeOps::insert(&mut ops, &mut self.state, inode).map_err(errno)?;
+
+        let mut tree = dir_tree(&self.state, &dir);
+        DirOps::insert(&mut ops, &mut tree, entry).map_err(errno)?;
+        dir.extent_root = tree.root_block;
+        dir.mtime = now;
+        dir.ctime = now;
+        dir.update_crc();
+        InodeOps::insert(&mut ops, &mut self.state, dir).map_err(errno)?;
+
+        Ok(inode)
+    }
+
+    /// Remove `name` from `parent` (rmdir semantics if `is_dir`)
+    fn remove(&mut self, parent: u64, name: &str, is_dir: bool) -> Result<(), c_int> {
+        let mut dir = self.dir_inode(parent)?;
+        let entry = self.find(&dir, name)?.ok_or(ENOENT)?;
+        let mut target = self.inode(entry.inode_num)?;
+
+        if is_dir {
+            if !target.is_directory() {
+                return Err(ENOTDIR);
+            }
+            if self.list(&target)?.iter().any(|e| !is_dot(e)) {
+                return Err(ENOTEMPTY);
+            }
+        } else if target.is_directory() {
+            return Err(EISDIR);
+        }
+
+        let now = now();
+        let mut ops = tree_ops(&mut self.dev);
+
+        let mut tree = dir_tree(&self.state, &dir);
+        DirOps::delete(&mut ops, &mut tree, name).map_err(errno)?;
+        dir.extent_root = tree.root_block;
+        if is_dir {
+            dir.nlink = dir.nlink.saturating_sub(1);
+        }
+        dir.mtime = now;
+        dir.ctime = now;
+        dir.update_crc();
+        InodeOps::insert(&mut ops, &mut self.state, dir).map_err(errno)?;
+
+        // A directory holds links from its parent and from its own "."
+        target.nlink = target.nlink.saturating_sub(if is_dir { 2 } else { 1 });
+        if target.nlink == 0 {
+            InodeOps::delete(&mut ops, &mut self.state, target.inode_num).map_err(errno)?;
+        } else {
+            target.ctime = now;
+            target.update_crc();
+            InodeOps::insert(&mut ops, &mut self.state, target).map_err(errno)?;
+        }
+        Ok(())
+    }
+
+    /// Add `new_name` in `new_parent` as another name for inode `ino`
+    fn link_entry(&mut self, ino: u64, new_parent: u64, new_name: &str) -> Result<Inode, c_int> {
+        let mut target = self.inode(ino)?;
+        if target.is_directory() {
+            return Err(EPERM);
+        }
+
+        let mut dir = self.dir_inode(new_parent)?;
+        if self.find(&dir, new_name)?.is_some() {
+            return Err(EEXIST);
+        }
+
+        let now = now();
+        dir.mtime = now;
+        dir.ctime = now;
+        target.ctime = now;
+        let mut ops = tree_ops(&mut self.dev);
+        DirOps::link(&mut ops, &mut self.state, &mut dir, new_name, &mut target).map_err(errno)?;
+        Ok(target)
+    }
+
+    fn rename_entry(&mut self, parent: u64, name: &str, new_parent: u64, new_name: &str) -> Result<(), c_int> {
+        let src_dir = self.dir_inode(parent)?;
+        let entry = self.find(&src_dir, name)?.ok_or(ENOENT)?;
+
+        let dst_dir = self.dir_inode(new_parent)?;
+        if let Some(existing) = self.find(&dst_dir, new_name)? {
+            if existing.inode_num == entry.inode_num {
+                return Ok(());
+            }
+            // Replace the target, as rename(2) does, if it's the same kind
+            match (entry.is_directory(), existing.is_directory()) {
+                (true, false) => return Err(ENOTDIR),
+                (false, true) => return Err(EISDIR),
+                (true, true) => {
+                    let target = self.inode(existing.inode_num)?;
+                    if self.list(&target)?.iter().any(|e| !is_dot(e)) {
+                        return Err(ENOTEMPTY);
+                    }
+                }
+                (false, false) => {}
+            }
+        }
+
+        let mut ops = tree_ops(&mut self.dev);
+        DirOps::rename(&mut ops, &mut self.state, parent, name, new_parent, new_name).map_err(errno)?;
+
+        let now = now();
+        for ino in [parent, new_parent] {
+            let mut dir = self.dir_inode(ino)?;
+            dir.mtime = now;
+            dir.ctime = now;
+            dir.update_crc();
+            InodeOps::insert(&mut tree_ops(&mut self.dev), &mut self.state, dir).map_err(errno)?;
+        }
+        Ok(())
+    }
+
+    fn read_data(&mut self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>, c_int> {
+        let inode = self.inode(ino)?;
+        if inode.is_directory() {
+            return Err(EISDIR);
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        let dev_ptr = &mut self.dev as *mut ImageDevice;
+        // SAFETY: FileOps::read only reads through both references
+        let read = unsafe {
+            FileOps::read(&mut tree_ops(&mut *dev_ptr), &*dev_ptr, &self.state, &inode, offset, &mut buf)
+        }
+        .map_err(errno)?;
+        buf.truncate(read);
+        Ok(buf)
+    }
+
+    fn write_data(&mut self, ino: u64, offset: u64, data: &[u8]) -> Result<u32, c_int> {
+        let mut inode = self.inode(ino)?;
+        if inode.is_directory() {
+            return Err(EISDIR);
+        }
+
+        let end = offset + data.len() as u64;
+        if end > INODE_INLINE_SIZE as u64 || !has_inline_data(&inode) {
+            return Err(EFBIG);
+        }
+
+        let (offset, end) = (offset as usize, end as usize);
+        let mut contents = inode.get_inline_data().to_vec();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[offset..end].copy_from_slice(data);
+
+        let now = now();
+        inode.set_inline_data(&contents);
+        inode.mtime = now;
+        inode.ctime = now;
+        inode.update_crc();
+        InodeOps::insert(&mut tree_ops(&mut self.dev), &mut self.state, inode).map_err(errno)?;
+        Ok(data.len() as u32)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn set_attr(
+        &mut self,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+    ) -> Result<Inode, c_int> {
+        let mut inode = self.inode(ino)?;
+        let now = now();
+
+        if let Some(mode) = mode {
+            inode.mode = (inode.mode & S_IFMT) | (mode & 0o7777);
+        }
+        if let Some(uid) = uid {
+            inode.uid = uid;
+        }
+        if let Some(gid) = gid {
+            inode.gid = gid;
+        }
+        if let Some(size) = size {
+            if inode.is_directory() {
+                return Err(EISDIR);
+            }
+            let mut contents = if has_inline_data(&inode) {
+                inode.get_inline_data().to_vec()
+            } else if size == 0 {
+                // Extent data cannot be rewritten here, but it can be dropped
+                inode.extent_root = 0;
+                inode.blocks = 0;
+                Vec::new()
+            } else {
+                return Err(EFBIG);
+            };
+            if size > INODE_INLINE_SIZE as u64 {
+                return Err(EFBIG);
+            }
+            contents.resize(size as usize, 0);
+            inode.set_inline_data(&contents);
+            inode.mtime = now;
+        }
+        if let Some(atime) = atime {
+            inode.atime = time_secs(atime);
+        }
+        if let Some(mtime) = mtime {
+            inode.mtime = time_secs(mtime);
+        }
+
+        inode.ctime = now;
+        inode.update_crc();
+        InodeOps::insert(&mut tree_ops(&mut self.dev), &mut self.state, inode).map_err(errno)?;
+        Ok(inode)
+    }
+}
+
+impl Filesystem for WfsFuse {
+    fn destroy(&mut self) {
+        if !self.read_only {
+            let _ = BlockDevice::sync(&mut self.dev);
+        }
+    }
+
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let result = name_str(name).and_then(|name| {
+            let dir = self.dir_inode(parent)?;
+            let entry = self.find(&dir, name)?.ok_or(ENOENT)?;
+            self.inode(entry.inode_num)
+        });
+        match result {
+            Ok(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.inode(ino) {
+            Ok(inode) => reply.attr(&TTL, &attr(&inode)),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let what = format!("setattr inode {}", ino);
+        match self.modify(&what, |fs| fs.set_attr(ino, mode, uid, gid, size, atime, mtime)) {
+            Ok(inode) => reply.attr(&TTL, &attr(&inode)),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let result = name_str(name).and_then(|name| {
+            let mode = S_IFDIR | (mode & !umask & 0o7777);
+            let what = format!("mkdir {}", name);
+            self.modify(&what, |fs| fs.create_node(parent, name, mode, req.uid(), req.gid()))
+        });
+        match result {
+            Ok(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let result = name_str(name).and_then(|name| {
+            self.modify(&format!("unlink {}", name), |fs| fs.remove(parent, name, false))
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn link(&mut self, _req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
+        let result = name_str(newname).and_then(|name| {
+            let what = format!("link inode {} -> {}", ino, name);
+            self.modify(&what, |fs| fs.link_entry(ino, newparent, name))
+        });
+        match result {
+            Ok(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let result = name_str(name).and_then(|name| {
+            self.modify(&format!("rmdir {}", name), |fs| fs.remove(parent, name, true))
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        // RENAME_NOREPLACE / RENAME_EXCHANGE are not supported
+        if flags != 0 {
+            reply.error(EINVAL);
+            return;
+        }
+        let result = name_str(name).and_then(|name| {
+            let new_name = name_str(newname)?;
+            let what = format!("rename {} -> {}", name, new_name);
+            self.modify(&what, |fs| fs.rename_entry(parent, name, newparent, new_name))
+        });
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.read_data(ino, offset.max(0) as u64, size) {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let what = format!("write inode {} ({} bytes at {})", ino, data.len(), offset);
+        match self.modify(&what, |fs| fs.write_data(ino, offset.max(0) as u64, data)) {
+            Ok(written) => reply.written(written),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn flush(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        // Every write is committed before it is acknowledged
+        reply.ok();
+    }
+
+    fn fsync(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        if self.read_only {
+            reply.ok();
+            return;
+        }
+        match BlockDevice::sync(&mut self.dev) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let entries = match self.dir_inode(ino).and_then(|dir| self.list(&dir)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                reply.error(e);
+                return;
+            }
+        };
+
+        // "." and ".." are synthesized; the on-disk copies (if any) are skipped
+        let mut listing = vec![
+            (ino, FileType::Directory, String::from(".")),
+            (ino, FileType::Directory, String::from("..")),
+        ];
+        listing.extend(
+            entries
+                .iter()
+                .filter(|e| !is_dot(e))
+                .map(|e| (e.inode_num, entry_kind(e.entry_type()), String::from(e.name_str()))),
+        );
+
+        for (i, (ino, kind, name)) in listing.into_iter().enumerate().skip(offset.max(0) as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let free = self.dev.total_blocks.saturating_sub(self.dev.next_block);
+        reply.statfs(
+            self.dev.total_blocks,
+            free,
+            free,
+            self.state.superblock.inode_count,
+            free,
+            BLOCK_SIZE,
+            MAX_FILENAME as u32,
+            BLOCK_SIZE,
+        );
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let result = name_str(name).and_then(|name| {
+            let mode = S_IFREG | (mode & !umask & 0o7777);
+            let what = format!("create {}", name);
+            self.modify(&what, |fs| fs.create_node(parent, name, mode, req.uid(), req.gid()))
+        });
+        match result {
+            Ok(inode) => reply.created(&TTL, &attr(&inode), 0, 0, 0),
+            Err(e) => reply.error(e),
+        }
+    }
+}
+
+// ============================================================================
+// HELPERS
+// ============================================================================
+
+fn dir_tree(state: &FilesystemState, dir: &Inode) -> BPlusTree {
+    BPlusTree::new(dir.extent_root, NodeType::Directory, state.superblock.root_generation)
+}
+
+/// Whether the file's data (if any) lives in the inode
+fn has_inline_data(inode: &Inode) -> bool {
+    inode.is_inline() || inode.size == 0
+}
+
+fn is_dot(entry: &DirEntry) -> bool {
+    matches!(entry.name_str(), "." | "..")
+}
+
+fn name_str(name: &OsStr) -> Result<&str, c_int> {
+    let name = name.to_str().ok_or(EINVAL)?;
+    if name.len() > MAX_FILENAME {
+        return Err(ENAMETOOLONG);
+    }
+    Ok(name)
+}
+
+fn errno(err: TreeError) -> c_int {
+    match err {
+        TreeError::KeyNotFound | TreeError::NodeNotFound => ENOENT,
+        TreeError::NodeFull => ENOSPC,
+        _ => EIO,
+    }
+}
+
+fn entry_kind(entry_type: EntryType) -> FileType {
+    match entry_type {
+        EntryType::Directory => FileType::Directory,
+        EntryType::Symlink => FileType::Symlink,
+        EntryType::BlockDevice => FileType::BlockDevice,
+        EntryType::CharDevice => FileType::CharDevice,
+        EntryType::Fifo => FileType::NamedPipe,
+        EntryType::Socket => FileType::Socket,
+        EntryType::File | EntryType::Unknown => FileType::RegularFile,
+    }
+}
+
+fn attr(inode: &Inode) -> FileAttr {
+    let kind = match inode.mode & S_IFMT {
+        S_IFDIR => FileType::Directory,
+        S_IFLNK => FileType::Symlink,
+        _ => FileType::RegularFile,
+    };
+    let time = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+
+    FileAttr {
+        ino: inode.inode_num,
+        size: inode.size,
+        // Allocated blocks only, so sparse files show their real usage
+        blocks: inode.blocks * (BLOCK_SIZE as u64 / 512),
+        atime: time(inode.atime),
+        mtime: time(inode.mtime),
+        ctime: time(inode.ctime),
+        crtime: time(inode.ctime),
+        kind,
+        perm: (inode.mode & 0o7777) as u16,
+        nlink: inode.nlink,
+        uid: inode.uid,
+        gid: inode.gid,
+        rdev: 0,
+        blksize: BLOCK_SIZE,
+        flags: 0,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn time_secs(time: TimeOrNow) -> u64 {
+    match time {
+        TimeOrNow::SpecificTime(t) => t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        TimeOrNow::Now => now(),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let mut dev = ImageDevice::open(&args.image, args.read_only)?;
+
+    // Fall back to the backup superblock if the primary is damaged
+    let superblock = dev
+        .read_superblock(0)
+        .or_else(|_| dev.read_superblock(1))
+        .map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "No valid WFS superblock found")
+        })?;
+
+    if superblock.total_blocks < dev.total_blocks {
+        dev.total_blocks = superblock.total_blocks;
+    }
+    if !args.read_only {
+        dev.find_next_free(superblock.data_start_block);
+    }
+
+    println!("Mounting WFS image: {}", args.image.display());
+    println!("  Blocks:     {} ({} bytes each)", dev.total_blocks, BLOCK_SIZE);
+    println!("  Inodes:     {}", superblock.inode_count);
+    println!("  Generation: {}", superblock.root_generation);
+    println!("  Mode:       {}", if args.read_only { "read-only" } else { "read-write" });
+    if !args.read_only {
+        println!("  Free:       {} blocks", dev.total_blocks - dev.next_block);
+    }
+    println!("Unmount with: fusermount -u {}", args.mountpoint.display());
+
+    let mut options = vec![
+        MountOption::FSName(args.image.display().to_string()),
+        MountOption::Subtype(String::from("wfs")),
+        if args.read_only { MountOption::RO } else { MountOption::RW },
+    ];
+    if args.allow_other {
+        options.push(MountOption::AllowOther);
+    }
+
+    let fs = WfsFuse {
+        dev,
+        state: FilesystemState::new(superblock),
+        read_only: args.read_only,
+        verbose: args.verbose,
+    };
+    fuser::mount2(fs, &args.mountpoint, &options)
+}